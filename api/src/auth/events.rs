@@ -0,0 +1,140 @@
+//! Authentication events for anomaly detection
+//!
+//! Portal sign-ins and requests refused with 401 or 403 are written to
+//! `audit.auth_events`, where the jobs worker looks for suspicious patterns
+//! (see `emr_core::services::security_events`). The caller's location comes
+//! from the edge proxy's geolocation headers; the proxy must overwrite any
+//! the client sends, as Cloudflare does.
+
+use actix_web::http::header::HeaderMap;
+use chrono::Utc;
+use emr_core::services::security_events::{AuthEvent, AuthEventKind, GeoLocation, SecurityEvent};
+use std::net::IpAddr;
+use crate::database::Pool;
+use crate::repositories::AuditRepository;
+
+/// ISO country code of the caller, set by the edge proxy
+pub const COUNTRY_HEADER: &str = "CF-IPCountry";
+/// City of the caller, set by the edge proxy
+pub const CITY_HEADER: &str = "CF-IPCity";
+/// Latitude of the caller, set by the edge proxy
+pub const LATITUDE_HEADER: &str = "CF-IPLatitude";
+/// Longitude of the caller, set by the edge proxy
+pub const LONGITUDE_HEADER: &str = "CF-IPLongitude";
+
+/// Location of the caller from the edge proxy's headers; `XX` (unknown) and
+/// `T1` (Tor) are not locations
+pub fn client_location(headers: &HeaderMap) -> Option<GeoLocation> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let country = header(COUNTRY_HEADER)
+        .map(str::to_ascii_uppercase)
+        .filter(|country| country.len() == 2 && country != "XX" && country != "T1")?;
+    let coordinate = |name: &str, limit: f64| {
+        header(name)
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| value.abs() <= limit)
+    };
+
+    Some(GeoLocation {
+        country,
+        city: header(CITY_HEADER).map(str::to_string),
+        latitude: coordinate(LATITUDE_HEADER, 90.0),
+        longitude: coordinate(LONGITUDE_HEADER, 180.0),
+    })
+}
+
+/// Subject for a caller without a valid token
+pub fn anonymous_subject(ip_address: Option<&str>) -> String {
+    format!("ip:{}", ip_address.unwrap_or("unknown"))
+}
+
+/// An event for a request happening now; `ip_address` is the client
+/// address from `networks::client_ip`
+pub fn auth_event(
+    kind: AuthEventKind,
+    subject: String,
+    headers: &HeaderMap,
+    ip_address: Option<&str>,
+    path: Option<&str>,
+) -> AuthEvent {
+    AuthEvent {
+        occurred_at: Utc::now(),
+        subject,
+        kind,
+        // Forwarded headers are client input; keep only addresses
+        ip_address: ip_address
+            .and_then(|address| address.parse::<IpAddr>().ok())
+            .map(|address| address.to_string()),
+        location: client_location(headers),
+        path: path.map(|path| path.chars().take(255).collect()),
+    }
+}
+
+/// Store an event; a failure is logged rather than failing the request
+pub async fn record(pool: &Pool, event: &AuthEvent) {
+    if let Err(e) = AuditRepository::new().record_auth_event(pool, event).await {
+        tracing::warn!(error = %e, kind = event.kind.as_str(), "Failed to record authentication event");
+    }
+}
+
+/// Store a security event noticed by the API; a failure is logged rather
+/// than failing the request
+pub async fn record_security_event(pool: &Pool, event: &SecurityEvent) {
+    if let Err(e) = AuditRepository::new().record_security_event(pool, event).await {
+        tracing::warn!(error = %e, kind = %event.kind, "Failed to record security event");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(values: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_client_location() {
+        let located = headers(&[
+            ("cf-ipcountry", "us"),
+            ("cf-ipcity", "Portland"),
+            ("cf-iplatitude", "45.52"),
+            ("cf-iplongitude", "-122.68"),
+        ]);
+        let location = client_location(&located).unwrap();
+        assert_eq!(location.label(), "Portland, US");
+        assert_eq!(location.longitude, Some(-122.68));
+
+        assert_eq!(client_location(&headers(&[("cf-ipcountry", "XX")])), None);
+        assert_eq!(client_location(&HeaderMap::new()), None);
+        let bad_latitude = client_location(&headers(&[("cf-ipcountry", "GB"), ("cf-iplatitude", "451")])).unwrap();
+        assert_eq!(bad_latitude.latitude, None);
+    }
+
+    #[test]
+    fn test_auth_event() {
+        let event = auth_event(
+            AuthEventKind::AccessDenied,
+            anonymous_subject(Some("203.0.113.7")),
+            &HeaderMap::new(),
+            Some("203.0.113.7"),
+            Some("/api/v1/patients"),
+        );
+        assert_eq!(event.subject, "ip:203.0.113.7");
+        assert_eq!(event.ip_address.as_deref(), Some("203.0.113.7"));
+
+        let spoofed = auth_event(AuthEventKind::LoginFailed, "jdoe".into(), &HeaderMap::new(), Some("<script>"), None);
+        assert_eq!(spoofed.ip_address, None);
+    }
+}
@@ -1,21 +1,101 @@
 //! JWT token handling
+//!
+//! Tokens are signed with HS256 under `auth.jwt_secret` and name this API as
+//! their issuer. Validation checks the signature, the issuer and the expiry,
+//! and refuses tokens issued in the future; both allow [`LEEWAY_SECONDS`] of
+//! clock skew.
 
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use crate::error::{ApiError, Result};
 use crate::auth::Claims;
 
-/// Create JWT token
-pub fn create_token(_claims: &Claims) -> Result<String> {
-    // TODO: Implement JWT creation
-    Ok("dummy.jwt.token".to_string())
+/// Issuer of the tokens this API signs
+pub const TOKEN_ISSUER: &str = "nexus-api";
+
+/// Clock skew allowed when checking `exp` and `iat`
+const LEEWAY_SECONDS: u64 = 60;
+
+/// Sign a token carrying `claims`
+pub fn create_token(secret: &str, claims: &Claims) -> Result<String> {
+    if secret.is_empty() {
+        return Err(ApiError::internal_error("auth.jwt_secret is not set"));
+    }
+    jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &EncodingKey::from_secret(secret.as_bytes()))
+        .map_err(|e| ApiError::internal_error(&format!("Failed to sign token: {}", e)))
 }
 
-/// Validate JWT token
-pub fn validate_token(_token: &str) -> Result<Claims> {
-    // TODO: Implement JWT validation
-    Ok(Claims {
-        sub: "test_user".to_string(),
-        exp: (chrono::Utc::now().timestamp() + 3600) as usize,
-        iat: chrono::Utc::now().timestamp() as usize,
-        scope: Some("patient/*.read".to_string()),
-    })
-} 
\ No newline at end of file
+/// Validate a token and return its claims
+pub fn validate_token(secret: &str, token: &str) -> Result<Claims> {
+    if secret.is_empty() {
+        return Err(ApiError::authentication_error("Token validation is not configured"));
+    }
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_issuer(&[TOKEN_ISSUER]);
+    validation.set_required_spec_claims(&["exp", "iat", "iss", "sub"]);
+    validation.leeway = LEEWAY_SECONDS;
+
+    let claims = jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &validation)
+        .map_err(|e| match e.kind() {
+            ErrorKind::ExpiredSignature => ApiError::authentication_error("Token has expired"),
+            ErrorKind::InvalidIssuer => ApiError::authentication_error("Token was not issued by this API"),
+            _ => ApiError::authentication_error("Invalid token"),
+        })?
+        .claims;
+
+    let now = chrono::Utc::now().timestamp() as usize;
+    if claims.iat > now + LEEWAY_SECONDS as usize {
+        return Err(ApiError::authentication_error("Token was issued in the future"));
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn claims(issued_ago: i64, lifetime: i64) -> Claims {
+        let now = chrono::Utc::now().timestamp();
+        Claims {
+            sub: "portal:1".to_string(),
+            iss: TOKEN_ISSUER.to_string(),
+            exp: (now - issued_ago + lifetime) as usize,
+            iat: (now - issued_ago) as usize,
+            scope: Some("patient/*.read".to_string()),
+            patient: Some(uuid::Uuid::new_v4()),
+            clearance: None,
+            tenant: None,
+            roles: vec!["clinician".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        let issued = claims(0, 900);
+        let token = create_token(SECRET, &issued).unwrap();
+        let validated = validate_token(SECRET, &token).unwrap();
+        assert_eq!(validated.sub, issued.sub);
+        assert_eq!(validated.patient, issued.patient);
+        assert_eq!(validated.roles, issued.roles);
+    }
+
+    #[test]
+    fn test_rejected_tokens() {
+        let token = create_token(SECRET, &claims(0, 900)).unwrap();
+        assert!(validate_token("another-secret", &token).is_err());
+        assert!(validate_token(SECRET, "dummy.jwt.token").is_err());
+        assert!(validate_token("", &token).is_err());
+
+        let expired = create_token(SECRET, &claims(3600, 900)).unwrap();
+        assert!(validate_token(SECRET, &expired).is_err());
+
+        let future = create_token(SECRET, &claims(-3600, 7200)).unwrap();
+        assert!(validate_token(SECRET, &future).is_err());
+
+        let mut foreign = claims(0, 900);
+        foreign.iss = "someone-else".to_string();
+        assert!(validate_token(SECRET, &create_token(SECRET, &foreign).unwrap()).is_err());
+    }
+}
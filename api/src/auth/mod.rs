@@ -1,25 +1,121 @@
-//! Authentication and authorization scaffolding for Nexus.
+//! Authentication and authorization for Nexus.
 //!
-//! These modules define intended extension points; they are not complete auth
-//! or RBAC implementations yet.
+//! Session tokens are signed and validated in [`jwt`]. OAuth2 is an intended
+//! extension point, not a complete implementation yet.
 
+// Not wired up yet
+#[allow(dead_code)]
 pub mod oauth2;
 pub mod jwt;
+pub mod scopes;
+pub mod events;
+pub mod networks;
 
 use crate::error::{ApiError, Result};
+use emr_core::domain::Confidentiality;
+use emr_core::services::consent::PurposeOfUse;
+use emr_core::types::Id;
+use scopes::{ScopeAccess, Scopes};
 
 /// JWT claims
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Claims {
     pub sub: String,
+    /// Issuer, [`jwt::TOKEN_ISSUER`] for tokens this API signs
+    pub iss: String,
     pub exp: usize,
     pub iat: usize,
     pub scope: Option<String>,
+    /// Patient in the SMART launch context, set for portal sessions
+    #[serde(default)]
+    pub patient: Option<Id>,
+    /// Most confidential data the user may see; normal when absent
+    #[serde(default)]
+    pub clearance: Option<Confidentiality>,
+    /// Organization the user works for; database rows are limited to its
+    /// patients
+    #[serde(default)]
+    pub tenant: Option<Id>,
+    /// Roles of the user, e.g. `billing`; some may be limited to network
+    /// zones
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
-/// Validate JWT token
-pub fn validate_token(token: &str) -> Result<Claims> {
-    let _ = token;
-    // TODO(nexus-phase2): Implement JWT signature + claim validation.
-    Err(ApiError::authentication_error("JWT validation not implemented"))
+/// Caller of a request, added to the request extensions by `AuthMiddleware`
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub subject: String,
+    pub patient_id: Option<Id>,
+    /// Organization the user works for
+    pub tenant_id: Option<Id>,
+    pub scopes: Scopes,
+    /// Most confidential data the user may see
+    pub clearance: Confidentiality,
+    /// Purpose of use the user selected for this request
+    pub purpose_of_use: Option<PurposeOfUse>,
+    /// Roles of the user
+    pub roles: Vec<String>,
+}
+
+impl AuthContext {
+    /// Build the context of a validated token
+    pub fn from_claims(claims: &Claims) -> Self {
+        Self {
+            subject: claims.sub.clone(),
+            patient_id: claims.patient,
+            tenant_id: claims.tenant,
+            scopes: Scopes::parse(claims.scope.as_deref().unwrap_or_default()),
+            clearance: claims.clearance.unwrap_or_default(),
+            purpose_of_use: None,
+            roles: claims.roles.clone(),
+        }
+    }
+
+    /// Whether this is a patient signed in to the portal: every scope is
+    /// limited to the launch patient
+    pub fn is_patient_session(&self) -> bool {
+        self.scopes.is_patient_only()
+    }
+
+    /// The practitioner signed in, for staff sessions whose subject is a
+    /// practitioner id
+    pub fn practitioner_id(&self) -> Option<Id> {
+        if self.is_patient_session() {
+            return None;
+        }
+        self.subject.parse().ok()
+    }
+
+    /// The session's patient, if its scopes allow reading a resource type
+    pub fn readable_patient(&self, resource_type: &str) -> Result<Id> {
+        self.patient_with(resource_type, ScopeAccess::Read)
+    }
+
+    /// The session's patient, if its scopes allow writing a resource type
+    pub fn writable_patient(&self, resource_type: &str) -> Result<Id> {
+        self.patient_with(resource_type, ScopeAccess::Write)
+    }
+
+    fn patient_with(&self, resource_type: &str, access: ScopeAccess) -> Result<Id> {
+        let patient_id = self
+            .patient_id
+            .ok_or_else(|| ApiError::authorization_error("Token has no patient context"))?;
+        if !self.scopes.allows(resource_type, access) {
+            let verb = match access {
+                ScopeAccess::Read => "reading",
+                ScopeAccess::Write => "writing",
+            };
+            return Err(ApiError::authorization_error(&format!(
+                "Token scopes do not allow {} {}",
+                verb, resource_type
+            )));
+        }
+        Ok(patient_id)
+    }
+}
+
+/// Validate a bearer token signed with `secret`
+pub fn validate_token(secret: &str, token: &str) -> Result<Claims> {
+    jwt::validate_token(secret, token)
 } 
\ No newline at end of file
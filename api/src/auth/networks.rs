@@ -0,0 +1,214 @@
+//! Network zones
+//!
+//! Roles such as billing or system administration can be limited to named
+//! network zones, each a list of address ranges (`auth.network_zones`). A
+//! caller holding a limited role must connect from an address in one of the
+//! role's zones; `AuthMiddleware` refuses other requests with 403 and
+//! records a network zone violation security event.
+//!
+//! Behind reverse proxies the client address comes from `X-Forwarded-For`,
+//! but only the hops appended by `trusted_proxies` are believed: anything
+//! further left was written by the client.
+
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use crate::config::NetworkZoneConfig;
+
+/// A CIDR range such as `10.20.0.0/16`; a bare address is a range of one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// Whether `address` is in the range; IPv4-mapped IPv6 addresses match
+    /// IPv4 ranges
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| format!("'{}' is not an IP address or CIDR range", value))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| format!("'{}' has a prefix length outside 0-{}", value, max))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<IpRange> for String {
+    fn from(range: IpRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Address of the client: the peer, or while the peer is a trusted proxy,
+/// the hop it reported in `X-Forwarded-For` (all of the request's values,
+/// joined with commas)
+pub fn client_ip(peer: Option<IpAddr>, forwarded_for: Option<&str>, trusted_proxies: &[IpRange]) -> Option<IpAddr> {
+    let mut client = peer?.to_canonical();
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        if !trusted_proxies.iter().any(|proxy| proxy.contains(client)) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(address) => client = address.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+/// Client address of a request, see [`client_ip`]
+pub fn request_ip(req: &HttpRequest, trusted_proxies: &[IpRange]) -> Option<IpAddr> {
+    let forwarded_for: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    client_ip(req.peer_addr().map(|address| address.ip()), Some(&forwarded_for.join(",")), trusted_proxies)
+}
+
+/// The first of `roles` that may not be used from `client`; an unknown
+/// address is outside every zone
+pub fn restricted_role<'a>(config: &NetworkZoneConfig, roles: &'a [String], client: Option<IpAddr>) -> Option<&'a str> {
+    roles
+        .iter()
+        .find(|role| {
+            config.roles.get(role.as_str()).is_some_and(|zones| {
+                let inside = |address: IpAddr| {
+                    zones
+                        .iter()
+                        .filter_map(|zone| config.zones.get(zone))
+                        .flatten()
+                        .any(|range| range.contains(address))
+                };
+                !client.is_some_and(inside)
+            })
+        })
+        .map(String::as_str)
+}
+
+/// Problems with the zone configuration: roles limited to no zones or to
+/// zones that are not defined
+pub fn check_zones(config: &NetworkZoneConfig) -> Result<(), String> {
+    for (role, zones) in &config.roles {
+        if zones.is_empty() {
+            return Err(format!("Role {} is limited to no network zones", role));
+        }
+        if let Some(zone) = zones.iter().find(|zone| !config.zones.contains_key(zone.as_str())) {
+            return Err(format!("Role {} is limited to undefined network zone {}", role, zone));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn ranges(values: &[&str]) -> Vec<IpRange> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ip_range() {
+        let corporate: IpRange = "10.20.0.0/16".parse().unwrap();
+        assert!(corporate.contains(ip("10.20.3.4")));
+        assert!(corporate.contains(ip("::ffff:10.20.3.4")));
+        assert!(!corporate.contains(ip("10.21.0.1")));
+        assert!(!corporate.contains(ip("2001:db8::1")));
+
+        let everything: IpRange = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(ip("203.0.113.7")));
+        let host: IpRange = "2001:db8::1".parse().unwrap();
+        assert_eq!(host.to_string(), "2001:db8::1/128");
+        assert!(host.contains(ip("2001:db8::1")) && !host.contains(ip("2001:db8::2")));
+
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("corporate".parse::<IpRange>().is_err());
+        assert_eq!(serde_json::to_value(corporate).unwrap(), "10.20.0.0/16");
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxies = ranges(&["10.0.0.0/8"]);
+        let forwarded = Some("198.51.100.9, 203.0.113.7, 10.1.1.1");
+
+        // Direct clients cannot claim another address
+        assert_eq!(client_ip(Some(ip("203.0.113.7")), forwarded, &proxies), Some(ip("203.0.113.7")));
+        // Hops appended by trusted proxies are followed, the client's own are not
+        assert_eq!(client_ip(Some(ip("10.0.0.2")), forwarded, &proxies), Some(ip("203.0.113.7")));
+        assert_eq!(client_ip(Some(ip("10.0.0.2")), None, &proxies), Some(ip("10.0.0.2")));
+        assert_eq!(client_ip(None, forwarded, &proxies), None);
+    }
+
+    #[test]
+    fn test_restricted_role() {
+        let mut config = NetworkZoneConfig::default();
+        config.zones.insert("corporate".to_string(), ranges(&["10.20.0.0/16"]));
+        config.zones.insert("vpn".to_string(), ranges(&["100.64.0.0/10"]));
+        config.roles.insert("billing".to_string(), vec!["corporate".to_string()]);
+        config.roles.insert("system_admin".to_string(), vec!["corporate".to_string(), "vpn".to_string()]);
+        assert!(check_zones(&config).is_ok());
+
+        let roles = vec!["clinician".to_string(), "system_admin".to_string()];
+        assert_eq!(restricted_role(&config, &roles, Some(ip("100.64.1.1"))), None);
+        assert_eq!(restricted_role(&config, &roles, Some(ip("203.0.113.7"))), Some("system_admin"));
+        assert_eq!(restricted_role(&config, &roles, None), Some("system_admin"));
+        assert_eq!(restricted_role(&config, &["billing".to_string()], Some(ip("100.64.1.1"))), Some("billing"));
+        assert_eq!(restricted_role(&config, &["clinician".to_string()], None), None);
+
+        config.roles.insert("auditor".to_string(), vec!["head-office".to_string()]);
+        assert!(check_zones(&config).is_err());
+    }
+}
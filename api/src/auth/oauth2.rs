@@ -1,6 +1,6 @@
 //! OAuth2 implementation
 
-use crate::error::{ApiError, Result};
+use crate::error::Result;
 
 /// OAuth2 client
 pub struct OAuth2Client {
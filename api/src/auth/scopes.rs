@@ -0,0 +1,132 @@
+//! SMART on FHIR scopes
+//!
+//! Access tokens carry space-separated scopes such as `patient/*.read` or
+//! `user/Observation.write`. Only `context/Resource.access` scopes grant
+//! access to data; `openid`, `launch/patient` and the like are ignored here.
+
+/// Whose data a scope covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeContext {
+    /// The patient in the token's launch context
+    Patient,
+    /// Whatever the signed-in user may see
+    User,
+    /// Backend services
+    System,
+}
+
+/// What a scope allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeAccess {
+    Read,
+    Write,
+}
+
+/// One resource scope
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmartScope {
+    pub context: ScopeContext,
+    /// Resource type, or `*` for all of them
+    pub resource_type: String,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl SmartScope {
+    /// Parse a `context/Resource.access` scope; `access` is `read`, `write` or `*`
+    pub fn parse(scope: &str) -> Option<Self> {
+        let (context, rest) = scope.split_once('/')?;
+        let (resource_type, access) = rest.rsplit_once('.')?;
+
+        let context = match context {
+            "patient" => ScopeContext::Patient,
+            "user" => ScopeContext::User,
+            "system" => ScopeContext::System,
+            _ => return None,
+        };
+        let valid_type = resource_type == "*"
+            || (resource_type.starts_with(|c: char| c.is_ascii_uppercase())
+                && resource_type.chars().all(|c| c.is_ascii_alphanumeric()));
+        if !valid_type {
+            return None;
+        }
+        let (read, write) = match access {
+            "read" => (true, false),
+            "write" => (false, true),
+            "*" => (true, true),
+            _ => return None,
+        };
+
+        Some(Self {
+            context,
+            resource_type: resource_type.to_string(),
+            read,
+            write,
+        })
+    }
+
+    /// Whether the scope covers an access to a resource type
+    pub fn allows(&self, resource_type: &str, access: ScopeAccess) -> bool {
+        let covers_type = self.resource_type == "*" || self.resource_type == resource_type;
+        let covers_access = match access {
+            ScopeAccess::Read => self.read,
+            ScopeAccess::Write => self.write,
+        };
+        covers_type && covers_access
+    }
+}
+
+/// Resource scopes granted to a token
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scopes(Vec<SmartScope>);
+
+impl Scopes {
+    /// Parse a space-separated scope string, keeping resource scopes only
+    pub fn parse(scope: &str) -> Self {
+        Self(scope.split_whitespace().filter_map(SmartScope::parse).collect())
+    }
+
+    /// Whether any scope covers an access to a resource type
+    pub fn allows(&self, resource_type: &str, access: ScopeAccess) -> bool {
+        self.0.iter().any(|scope| scope.allows(resource_type, access))
+    }
+
+    /// Whether every resource scope is limited to the launch patient
+    pub fn is_patient_only(&self) -> bool {
+        !self.0.is_empty() && self.0.iter().all(|scope| scope.context == ScopeContext::Patient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scope() {
+        let scope = SmartScope::parse("patient/Observation.read").unwrap();
+        assert_eq!(scope.context, ScopeContext::Patient);
+        assert!(scope.allows("Observation", ScopeAccess::Read));
+        assert!(!scope.allows("Observation", ScopeAccess::Write));
+        assert!(!scope.allows("Encounter", ScopeAccess::Read));
+
+        let scope = SmartScope::parse("user/*.*").unwrap();
+        assert!(scope.allows("Encounter", ScopeAccess::Write));
+
+        assert!(SmartScope::parse("openid").is_none());
+        assert!(SmartScope::parse("launch/patient").is_none());
+        assert!(SmartScope::parse("patient/observation.read").is_none());
+        assert!(SmartScope::parse("clinic/*.read").is_none());
+        assert!(SmartScope::parse("patient/*.delete").is_none());
+    }
+
+    #[test]
+    fn test_patient_only_scopes() {
+        let scopes = Scopes::parse("openid launch/patient patient/*.read");
+        assert!(scopes.is_patient_only());
+        assert!(scopes.allows("DocumentReference", ScopeAccess::Read));
+        assert!(!scopes.allows("Patient", ScopeAccess::Write));
+
+        assert!(!Scopes::parse("patient/*.read user/Practitioner.read").is_patient_only());
+        assert!(!Scopes::parse("openid profile").is_patient_only());
+    }
+}
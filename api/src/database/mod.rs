@@ -1,12 +1,244 @@
 //! Database connection scaffolding for Nexus.
+//!
+//! Every connection handed out carries a statement timeout for its
+//! [`QueryClass`]: short for queries answering a waiting caller, long for
+//! reports, exports and panel evaluations. A [`Connection`] cancels its
+//! running statement when the caller stops waiting for it, e.g. when the
+//! client disconnects and Actix drops the handler, and logs statements
+//! slower than `database.slow_query_ms` with the request's route and id.
+
+pub mod session;
+
+pub use session::DbSession;
 
 use crate::config::DatabaseConfig;
 use crate::error::{ApiError, Result};
-use deadpool_diesel::postgres::{Manager, Pool as DeadPool, Runtime};
-use diesel_async::{AsyncConnection, AsyncPgConnection};
+use deadpool_diesel::postgres::{Manager, Object, Pool as DeadPool, Runtime};
+use deadpool_diesel::InteractError;
+use diesel::connection::SimpleConnection;
+use diesel::{PgConnection, RunQueryDsl};
+use serde::Serialize;
+use std::fmt::Write;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Kind of work a query does, which sets its statement timeout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueryClass {
+    /// Queries answering a request while the caller waits
+    #[default]
+    Interactive,
+    /// Reports, exports and panel evaluations, which may scan whole tables
+    Batch,
+}
+
+impl QueryClass {
+    /// Name used in logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryClass::Interactive => "interactive",
+            QueryClass::Batch => "batch",
+        }
+    }
+}
+
+/// Statement timeouts by query class and the slow query threshold
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryLimits {
+    pub interactive_timeout: Duration,
+    pub batch_timeout: Duration,
+    pub slow_query: Duration,
+}
+
+impl QueryLimits {
+    /// Limits from the database configuration
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        Self {
+            interactive_timeout: Duration::from_millis(config.statement_timeout_ms),
+            batch_timeout: Duration::from_millis(config.batch_statement_timeout_ms),
+            slow_query: Duration::from_millis(config.slow_query_ms),
+        }
+    }
+
+    /// Statement timeout for a class of queries
+    pub fn timeout(&self, class: QueryClass) -> Duration {
+        match class {
+            QueryClass::Interactive => self.interactive_timeout,
+            QueryClass::Batch => self.batch_timeout,
+        }
+    }
+
+    /// Whether a statement that ran for `elapsed` should be logged as slow
+    pub fn is_slow(&self, elapsed: Duration) -> bool {
+        !self.slow_query.is_zero() && elapsed >= self.slow_query
+    }
+}
+
+/// Database connection pool
+///
+/// Connections from [`Pool::get`] run as `database.row_security_role`, when
+/// one is configured, with the current request's [`DbSession`] in their
+/// session variables, so row-level security applies to every query, and
+/// with the statement timeout of the pool's [`QueryClass`]. Status and
+/// resizing are those of the underlying deadpool pool.
+#[derive(Clone)]
+pub struct Pool {
+    inner: DeadPool,
+    row_security_role: Option<String>,
+    limits: QueryLimits,
+    class: QueryClass,
+}
+
+impl Pool {
+    /// The same connections, for batch queries with the longer timeout
+    pub fn batch(&self) -> Pool {
+        Pool {
+            class: QueryClass::Batch,
+            ..self.clone()
+        }
+    }
+
+    /// Acquire a connection prepared for the current request's session
+    pub async fn get(&self) -> Result<Connection> {
+        let conn = self.inner.get().await?;
+        let session = DbSession::current();
+        let applied = session.clone();
+        let role = self.row_security_role.clone();
+        let timeout = self.limits.timeout(self.class);
+
+        let backend_pid = conn
+            .interact(move |conn| applied.apply(conn, role.as_deref(), timeout))
+            .await??;
+        Ok(Connection {
+            inner: Some(conn),
+            pool: self.inner.clone(),
+            backend_pid,
+            class: self.class,
+            limits: self.limits,
+            session,
+            in_flight: AtomicBool::new(false),
+        })
+    }
+}
+
+impl Deref for Pool {
+    type Target = DeadPool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// Pooled connection from [`Pool::get`]
+///
+/// Statements run through [`Connection::interact`] are timed, and cancelled
+/// if the future waiting for them is dropped. A connection dropped with a
+/// statement running is closed rather than returned to the pool, so the
+/// cancellation cannot reach a later query on the same backend.
+pub struct Connection {
+    inner: Option<Object>,
+    pool: DeadPool,
+    backend_pid: i32,
+    class: QueryClass,
+    limits: QueryLimits,
+    session: DbSession,
+    in_flight: AtomicBool,
+}
+
+impl Connection {
+    /// Run `f` on the connection, like [`Object::interact`]
+    pub async fn interact<F, R>(&self, f: F) -> std::result::Result<R, InteractError>
+    where
+        F: FnOnce(&mut PgConnection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let start = Instant::now();
+        self.in_flight.store(true, Ordering::Release);
+        let result = self.object().interact(f).await;
+        self.in_flight.store(false, Ordering::Release);
+
+        let elapsed = start.elapsed();
+        if self.limits.is_slow(elapsed) {
+            tracing::warn!(
+                elapsed_ms = elapsed.as_millis() as u64,
+                threshold_ms = self.limits.slow_query.as_millis() as u64,
+                class = self.class.as_str(),
+                route = self.session.route.as_deref().unwrap_or("-"),
+                request_id = self.session.request_id.as_deref().unwrap_or("-"),
+                "Slow database query"
+            );
+        }
+        result
+    }
+
+    /// Close the connection instead of returning it to the pool, e.g. to
+    /// roll back an open transaction
+    pub fn detach(mut self) {
+        if let Some(object) = self.inner.take() {
+            drop(Object::take(object));
+        }
+    }
+
+    fn object(&self) -> &Object {
+        self.inner.as_ref().expect("connection is only taken when dropped")
+    }
+}
+
+impl Deref for Connection {
+    type Target = Object;
+
+    fn deref(&self) -> &Self::Target {
+        self.object()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if !self.in_flight.load(Ordering::Acquire) {
+            return;
+        }
+        let Some(object) = self.inner.take() else {
+            return;
+        };
+        drop(Object::take(object));
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let pool = self.pool.clone();
+        let backend_pid = self.backend_pid;
+        tracing::info!(
+            backend_pid,
+            route = self.session.route.as_deref().unwrap_or("-"),
+            request_id = self.session.request_id.as_deref().unwrap_or("-"),
+            "Caller went away, cancelling its database query"
+        );
+        runtime.spawn(async move {
+            let Ok(conn) = pool.get().await else {
+                return;
+            };
+            let cancelled = conn
+                .interact(move |conn| {
+                    // Signalling a backend takes its login role, not the
+                    // row security role a previous user may have left
+                    conn.batch_execute("RESET ROLE")?;
+                    diesel::sql_query("SELECT pg_cancel_backend($1)")
+                        .bind::<diesel::sql_types::Integer, _>(backend_pid)
+                        .execute(conn)
+                })
+                .await
+                .map_err(ApiError::from)
+                .and_then(|result| result.map_err(ApiError::from));
+            if let Err(e) = cancelled {
+                tracing::warn!(backend_pid, error = %e, "Failed to cancel database query");
+            }
+        });
+    }
+}
 
-/// Database connection pool type
-pub type Pool = DeadPool<Manager<AsyncPgConnection>>;
+/// Upper bound for runtime pool resizing
+pub const MAX_POOL_SIZE: usize = 512;
 
 /// Create database connection pool
 pub async fn create_pool(config: &DatabaseConfig) -> Result<Pool> {
@@ -16,7 +248,12 @@ pub async fn create_pool(config: &DatabaseConfig) -> Result<Pool> {
         .build()
         .map_err(|e| ApiError::database_error(&format!("Failed to create pool: {}", e)))?;
 
-    Ok(pool)
+    Ok(Pool {
+        inner: pool,
+        row_security_role: config.row_security_role.clone(),
+        limits: QueryLimits::from_config(config),
+        class: QueryClass::Interactive,
+    })
 }
 
 /// Run database migrations
@@ -28,4 +265,225 @@ pub async fn run_migrations(pool: &Pool) -> Result<()> {
     
     tracing::info!("Database migrations completed");
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Point-in-time pool statistics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PoolStats {
+    /// Configured maximum number of connections
+    pub max_size: usize,
+    /// Connections currently open
+    pub size: usize,
+    /// Idle connections ready for use
+    pub available: usize,
+    /// Callers waiting for a connection
+    pub waiting: usize,
+    /// Successful acquisitions since startup
+    pub acquired_total: u64,
+    /// Acquisitions slower than the warning threshold
+    pub slow_acquires_total: u64,
+    /// Failed acquisitions (timeouts, connection errors)
+    pub acquire_errors_total: u64,
+    /// Cumulative time spent waiting for connections, in milliseconds
+    pub acquire_wait_ms_total: u64,
+}
+
+impl PoolStats {
+    /// Render as Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let metrics: [(&str, &str, &str, u64); 8] = [
+            ("emr_db_pool_max_size", "gauge", "Configured maximum pool size", self.max_size as u64),
+            ("emr_db_pool_size", "gauge", "Open database connections", self.size as u64),
+            ("emr_db_pool_available", "gauge", "Idle database connections", self.available as u64),
+            ("emr_db_pool_waiting", "gauge", "Requests waiting for a connection", self.waiting as u64),
+            ("emr_db_pool_acquired_total", "counter", "Connections acquired", self.acquired_total),
+            ("emr_db_pool_slow_acquires_total", "counter", "Acquisitions over the slow threshold", self.slow_acquires_total),
+            ("emr_db_pool_acquire_errors_total", "counter", "Failed acquisitions", self.acquire_errors_total),
+            ("emr_db_pool_acquire_wait_ms_total", "counter", "Time spent waiting for connections", self.acquire_wait_ms_total),
+        ];
+
+        let mut output = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value);
+        }
+        output
+    }
+}
+
+/// Tracks connection acquisition for metrics and slow-acquire warnings
+#[derive(Debug)]
+pub struct PoolMonitor {
+    slow_threshold: Duration,
+    acquired: AtomicU64,
+    slow_acquires: AtomicU64,
+    acquire_errors: AtomicU64,
+    acquire_wait_ms: AtomicU64,
+}
+
+impl PoolMonitor {
+    /// Create a monitor that warns when acquiring takes longer than `slow_threshold`
+    pub fn new(slow_threshold: Duration) -> Self {
+        Self {
+            slow_threshold,
+            acquired: AtomicU64::new(0),
+            slow_acquires: AtomicU64::new(0),
+            acquire_errors: AtomicU64::new(0),
+            acquire_wait_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a monitor from the database configuration
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        Self::new(Duration::from_millis(config.slow_acquire_ms))
+    }
+
+    /// Acquire a pooled connection, recording wait time
+    pub async fn get(&self, pool: &Pool) -> Result<Connection> {
+        let start = Instant::now();
+        let result = pool.get().await;
+        let waited = start.elapsed();
+        self.record(waited, result.is_ok());
+
+        if waited >= self.slow_threshold {
+            let status = pool.status();
+            tracing::warn!(
+                waited_ms = waited.as_millis() as u64,
+                threshold_ms = self.slow_threshold.as_millis() as u64,
+                size = status.size,
+                available = status.available,
+                waiting = status.waiting,
+                "Slow database connection acquire"
+            );
+        }
+
+        result.map_err(|e| {
+            ApiError::service_unavailable(&format!("Database connection unavailable: {}", e))
+        })
+    }
+
+    fn record(&self, waited: Duration, ok: bool) {
+        if ok {
+            self.acquired.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.acquire_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if waited >= self.slow_threshold {
+            self.slow_acquires.fetch_add(1, Ordering::Relaxed);
+        }
+        self.acquire_wait_ms
+            .fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Combine pool status with acquisition counters
+    pub fn stats(&self, pool: &Pool) -> PoolStats {
+        let status = pool.status();
+        PoolStats {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+            waiting: status.waiting,
+            ..self.counters()
+        }
+    }
+
+    fn counters(&self) -> PoolStats {
+        PoolStats {
+            acquired_total: self.acquired.load(Ordering::Relaxed),
+            slow_acquires_total: self.slow_acquires.load(Ordering::Relaxed),
+            acquire_errors_total: self.acquire_errors.load(Ordering::Relaxed),
+            acquire_wait_ms_total: self.acquire_wait_ms.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+/// Check a requested pool size against the configured minimum and the hard cap
+pub fn validate_pool_size(max_size: usize, config: &DatabaseConfig) -> Result<()> {
+    let min = (config.min_connections as usize).max(1);
+    if max_size < min || max_size > MAX_POOL_SIZE {
+        return Err(ApiError::validation_error(&format!(
+            "Pool size must be between {} and {}",
+            min, MAX_POOL_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Change the pool's maximum size without a restart
+///
+/// Shrinking closes idle connections first; connections in use are closed
+/// when they are returned. The change is not persisted, so the configured
+/// `max_connections` applies again after a restart.
+pub fn resize_pool(pool: &Pool, max_size: usize, config: &DatabaseConfig) -> Result<()> {
+    validate_pool_size(max_size, config)?;
+
+    let previous = pool.status().max_size;
+    pool.resize(max_size);
+    tracing::info!(previous, max_size, "Database pool resized");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DatabaseConfig {
+        crate::config::Config::default().database
+    }
+
+    #[test]
+    fn test_validate_pool_size() {
+        let config = config();
+        assert!(validate_pool_size(1, &config).is_ok());
+        assert!(validate_pool_size(64, &config).is_ok());
+        assert!(validate_pool_size(0, &config).is_err());
+        assert!(validate_pool_size(MAX_POOL_SIZE + 1, &config).is_err());
+
+        let config = DatabaseConfig { min_connections: 4, ..config };
+        assert!(validate_pool_size(3, &config).is_err());
+    }
+
+    #[test]
+    fn test_query_limits() {
+        let mut config = config();
+        let limits = QueryLimits::from_config(&config);
+        assert_eq!(limits.timeout(QueryClass::Interactive), Duration::from_secs(5));
+        assert_eq!(limits.timeout(QueryClass::Batch), Duration::from_secs(300));
+        assert!(limits.is_slow(Duration::from_millis(1_000)));
+        assert!(!limits.is_slow(Duration::from_millis(999)));
+
+        config.slow_query_ms = 0;
+        assert!(!QueryLimits::from_config(&config).is_slow(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_monitor_counts_slow_and_failed_acquires() {
+        let monitor = PoolMonitor::new(Duration::from_millis(100));
+        monitor.record(Duration::from_millis(5), true);
+        monitor.record(Duration::from_millis(150), true);
+        monitor.record(Duration::from_millis(200), false);
+
+        let stats = monitor.counters();
+        assert_eq!(stats.acquired_total, 2);
+        assert_eq!(stats.slow_acquires_total, 2);
+        assert_eq!(stats.acquire_errors_total, 1);
+        assert_eq!(stats.acquire_wait_ms_total, 355);
+    }
+
+    #[test]
+    fn test_prometheus_output() {
+        let stats = PoolStats {
+            max_size: 32,
+            size: 10,
+            available: 3,
+            waiting: 2,
+            ..Default::default()
+        };
+        let text = stats.to_prometheus();
+        assert!(text.contains("# TYPE emr_db_pool_size gauge\nemr_db_pool_size 10\n"));
+        assert!(text.contains("emr_db_pool_waiting 2\n"));
+        assert!(text.contains("# TYPE emr_db_pool_acquired_total counter"));
+    }
+}
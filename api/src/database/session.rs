@@ -0,0 +1,157 @@
+//! Row-level security session
+//!
+//! Postgres row-level security policies (see `infra/init-db.sql`) limit the
+//! patient-scoped tables to the patients of the tenant in the
+//! `application.tenant_id` session variable, or to the patient in
+//! `application.patient_id` for portal sessions. [`DbSession`] holds those
+//! values and the user for the request being served: the auth middleware
+//! runs each request inside [`DbSession::scope`], and
+//! [`Pool::get`](super::Pool::get) writes the current session to every
+//! connection it hands out. Queries outside a request run with an empty
+//! session, which the policies answer with no rows.
+//!
+//! The session also carries the request's route and `X-Request-ID`: the id
+//! goes to `application.request_id` for the audit triggers, and both label
+//! slow query logs.
+
+use crate::auth::AuthContext;
+use diesel::{PgConnection, QueryResult, RunQueryDsl};
+use emr_core::types::Id;
+use std::future::Future;
+use std::time::Duration;
+
+tokio::task_local! {
+    static DB_SESSION: DbSession;
+}
+
+/// Session variables a pooled connection is given for the request it serves
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbSession {
+    /// Organization whose patients the request may see
+    pub tenant_id: Option<Id>,
+    /// Signed-in user, recorded by the audit triggers
+    pub user_id: Option<String>,
+    /// Patient of a portal session
+    pub patient_id: Option<Id>,
+    /// `X-Request-ID` of the request
+    pub request_id: Option<String>,
+    /// Route pattern of the request, e.g. `/patients/{id}`
+    pub route: Option<String>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct BackendRow {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    backend_pid: i32,
+}
+
+impl DbSession {
+    /// Session of an authenticated caller
+    pub fn from_context(context: &AuthContext) -> Self {
+        Self {
+            tenant_id: context.tenant_id,
+            user_id: Some(context.subject.clone()),
+            patient_id: context.patient_id.filter(|_| context.is_patient_session()),
+            ..Self::default()
+        }
+    }
+
+    /// The session for one request, labelled with its route and id
+    pub fn for_request(self, route: String, request_id: Option<String>) -> Self {
+        Self {
+            route: Some(route),
+            request_id,
+            ..self
+        }
+    }
+
+    /// Run `future` with this session for the connections it acquires
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        DB_SESSION.scope(self, future).await
+    }
+
+    /// Session of the request being served; empty outside one
+    pub fn current() -> Self {
+        DB_SESSION.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// `(name, value)` of each session variable; unset values are empty
+    fn settings(&self) -> [(&'static str, String); 4] {
+        let text = |id: Option<Id>| id.map(|id| id.to_string()).unwrap_or_default();
+        [
+            ("application.tenant_id", text(self.tenant_id)),
+            ("application.user_id", self.user_id.clone().unwrap_or_default()),
+            ("application.patient_id", text(self.patient_id)),
+            ("application.request_id", self.request_id.clone().unwrap_or_default()),
+        ]
+    }
+
+    /// Switch a connection to `role` (the login role when `None`), set the
+    /// session variables and the statement timeout (none when zero),
+    /// replacing whatever its last user left. Returns the connection's
+    /// backend process id, for cancelling its queries.
+    pub(crate) fn apply(&self, conn: &mut PgConnection, role: Option<&str>, timeout: Duration) -> QueryResult<i32> {
+        let [(tenant, tenant_id), (user, user_id), (patient, patient_id), (request, request_id)] = self.settings();
+        let row = diesel::sql_query(
+            "SELECT pg_backend_pid() AS backend_pid, set_config('role', $1, false), \
+             set_config('statement_timeout', $2, false), set_config($3, $4, false), \
+             set_config($5, $6, false), set_config($7, $8, false), set_config($9, $10, false)",
+        )
+        .bind::<diesel::sql_types::Text, _>(role.unwrap_or("none"))
+        .bind::<diesel::sql_types::Text, _>(timeout.as_millis().to_string())
+        .bind::<diesel::sql_types::Text, _>(tenant)
+        .bind::<diesel::sql_types::Text, _>(tenant_id)
+        .bind::<diesel::sql_types::Text, _>(user)
+        .bind::<diesel::sql_types::Text, _>(user_id)
+        .bind::<diesel::sql_types::Text, _>(patient)
+        .bind::<diesel::sql_types::Text, _>(patient_id)
+        .bind::<diesel::sql_types::Text, _>(request)
+        .bind::<diesel::sql_types::Text, _>(request_id)
+        .get_result::<BackendRow>(conn)?;
+        Ok(row.backend_pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::scopes::Scopes;
+
+    fn context(subject: &str, scope: &str) -> AuthContext {
+        AuthContext {
+            subject: subject.to_string(),
+            patient_id: Some(uuid::Uuid::new_v4()),
+            tenant_id: Some(uuid::Uuid::new_v4()),
+            scopes: Scopes::parse(scope),
+            clearance: Default::default(),
+            purpose_of_use: None,
+            roles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_session_from_context() {
+        let staff = context("practitioner-1", "user/*.read");
+        let session = DbSession::from_context(&staff);
+        assert_eq!(session.tenant_id, staff.tenant_id);
+        assert_eq!(session.user_id.as_deref(), Some("practitioner-1"));
+        assert_eq!(session.patient_id, None);
+
+        let portal = context("portal:1", "patient/*.read");
+        assert_eq!(DbSession::from_context(&portal).patient_id, portal.patient_id);
+    }
+
+    #[tokio::test]
+    async fn test_session_scope() {
+        assert_eq!(DbSession::current(), DbSession::default());
+        assert_eq!(DbSession::default().settings()[0], ("application.tenant_id", String::new()));
+
+        let session = DbSession::from_context(&context("practitioner-1", "user/*.read"));
+        let current = session.clone().scope(async { DbSession::current() }).await;
+        assert_eq!(current, session);
+
+        let labelled = session.for_request("/patients/{id}".to_string(), Some("req-123".to_string()));
+        assert_eq!(labelled.settings()[3], ("application.request_id", "req-123".to_string()));
+        assert_eq!(labelled.tenant_id, current.tenant_id);
+    }
+}
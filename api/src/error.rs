@@ -1,9 +1,17 @@
 //! Error handling for the EMR API
-
+//!
+//! Errors are rendered as RFC 7807 problem details
+//! (`application/problem+json`), or as a FHIR OperationOutcome for clients
+//! that accept `application/fhir+json`. The [`ErrorNegotiation`] middleware
+//! picks the format from the request's `Accept` header.
+//!
+//! [`ErrorNegotiation`]: crate::middleware::errors::ErrorNegotiation
+
+use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use emr_core::Error as CoreError;
+use emr_fhir::{FhirFormat, OperationOutcome, OperationOutcomeIssue};
 use serde::{Deserialize, Serialize};
-use std::fmt;
 use thiserror::Error;
 
 /// Result type alias for API operations
@@ -32,10 +40,19 @@ pub enum ApiError {
     #[error("Authorization error: {message}")]
     Authorization { message: String },
 
+    /// A sensitive access needs a purpose of use; reported in `details` as
+    /// a `required` error on `purpose_of_use`
+    #[error("Purpose of use required: {message}")]
+    PurposeOfUseRequired { message: String },
+
     /// Validation error
     #[error("Validation error: {message}")]
     Validation { message: String },
 
+    /// Request fields that failed validation, reported in `details`
+    #[error("Validation error: {}", describe_fields(.fields))]
+    InvalidFields { fields: Vec<FieldError> },
+
     /// External service error
     #[error("External service error: {service} - {message}")]
     ExternalService { service: String, message: String },
@@ -60,6 +77,14 @@ pub enum ApiError {
     #[error("Conflict: {message}")]
     Conflict { message: String },
 
+    /// No acceptable representation, e.g. an unsupported API version
+    #[error("Not acceptable: {message}")]
+    NotAcceptable { message: String },
+
+    /// Request body over the limit of its route
+    #[error("Payload too large: {message}")]
+    PayloadTooLarge { message: String },
+
     /// Too many requests error
     #[error("Too many requests: {message}")]
     TooManyRequests { message: String },
@@ -80,7 +105,130 @@ pub struct ErrorResponse {
     pub request_id: Option<String>,
 }
 
+/// Media type of RFC 7807 problem details
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Media type of FHIR resources in JSON
+pub const FHIR_JSON: &str = "application/fhir+json";
+
+/// Media type of FHIR resources in XML
+pub const FHIR_XML: &str = "application/fhir+xml";
+
+/// RFC 7807 problem details
+///
+/// `type` is always `about:blank`, so `title` is the status's reason phrase.
+/// `error`, `details`, `timestamp` and `request_id` are extension members
+/// carrying what [`ErrorResponse`] does.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<FieldError>>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Format an error is rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// RFC 7807 problem details
+    Problem,
+    /// FHIR OperationOutcome
+    OperationOutcome,
+    /// FHIR OperationOutcome in XML
+    OperationOutcomeXml,
+}
+
+impl ErrorFormat {
+    /// The format for a request's `Accept` header: an OperationOutcome when
+    /// FHIR JSON or XML is acceptable, in whichever the client prefers, and
+    /// problem details otherwise
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let accepts_fhir = accept.unwrap_or_default().split(',').any(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            // `application/json+fhir` and `application/xml+fhir` are the
+            // pre-R4 names
+            let fhir = [FHIR_JSON, FHIR_XML, "application/json+fhir", "application/xml+fhir"]
+                .contains(&media_type.as_str());
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q <= 0.0)
+            });
+            fhir && !refused
+        });
+        if !accepts_fhir {
+            ErrorFormat::Problem
+        } else if FhirFormat::from_accept(accept) == FhirFormat::Xml {
+            ErrorFormat::OperationOutcomeXml
+        } else {
+            ErrorFormat::OperationOutcome
+        }
+    }
+}
+
+/// A request field that failed validation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Path of the field, e.g. `name` or `criteria[0].code`
+    pub field: String,
+    /// Machine-readable reason, e.g. `length`, `range` or `required`
+    pub code: String,
+    /// Human-readable reason
+    pub message: String,
+}
+
+impl FieldError {
+    /// Create a field error
+    pub fn new(field: &str, code: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Summary of invalid fields for the error message
+fn describe_fields(fields: &[FieldError]) -> String {
+    fields
+        .iter()
+        .map(|field| format!("{} {}", field.field, field.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 impl ApiError {
+    /// Create an error for request fields that failed validation
+    pub fn invalid_fields(fields: Vec<FieldError>) -> Self {
+        Self::InvalidFields { fields }
+    }
+
+    /// Field-level details of a validation error, if it has any
+    pub fn field_errors(&self) -> Option<Vec<FieldError>> {
+        match self {
+            ApiError::InvalidFields { fields } => Some(fields.clone()),
+            ApiError::Core(CoreError::ValidationError {
+                message,
+                field: Some(field),
+            }) => Some(vec![FieldError::new(field, "invalid", message)]),
+            ApiError::PurposeOfUseRequired { message } => {
+                Some(vec![FieldError::new("purpose_of_use", "required", message)])
+            }
+            _ => None,
+        }
+    }
+
     /// Create a configuration error
     pub fn configuration_error(message: &str) -> Self {
         Self::Configuration {
@@ -109,6 +257,13 @@ impl ApiError {
         }
     }
 
+    /// Create an error asking for a purpose of use
+    pub fn purpose_of_use_required(message: &str) -> Self {
+        Self::PurposeOfUseRequired {
+            message: message.to_string(),
+        }
+    }
+
     /// Create a validation error
     pub fn validation_error(message: &str) -> Self {
         Self::Validation {
@@ -159,6 +314,20 @@ impl ApiError {
         }
     }
 
+    /// Create a not acceptable error
+    pub fn not_acceptable(message: &str) -> Self {
+        Self::NotAcceptable {
+            message: message.to_string(),
+        }
+    }
+
+    /// Create an error for a request body over its limit
+    pub fn payload_too_large(message: &str) -> Self {
+        Self::PayloadTooLarge {
+            message: message.to_string(),
+        }
+    }
+
     /// Create a too many requests error
     pub fn too_many_requests(message: &str) -> Self {
         Self::TooManyRequests {
@@ -181,13 +350,16 @@ impl ApiError {
             ApiError::Database { .. } => "database",
             ApiError::Authentication { .. } => "authentication",
             ApiError::Authorization { .. } => "authorization",
-            ApiError::Validation { .. } => "validation",
+            ApiError::PurposeOfUseRequired { .. } => "purpose_of_use_required",
+            ApiError::Validation { .. } | ApiError::InvalidFields { .. } => "validation",
             ApiError::ExternalService { .. } => "external_service",
             ApiError::Fhir { .. } => "fhir",
             ApiError::Internal { .. } => "internal",
             ApiError::BadRequest { .. } => "bad_request",
             ApiError::NotFound { .. } => "not_found",
             ApiError::Conflict { .. } => "conflict",
+            ApiError::NotAcceptable { .. } => "not_acceptable",
+            ApiError::PayloadTooLarge { .. } => "payload_too_large",
             ApiError::TooManyRequests { .. } => "too_many_requests",
             ApiError::ServiceUnavailable { .. } => "service_unavailable",
         }
@@ -204,9 +376,7 @@ impl ApiError {
     }
 
     /// Get the HTTP status code for this error
-    pub fn status_code(&self) -> actix_web::http::StatusCode {
-        use actix_web::http::StatusCode;
-
+    pub fn status_code(&self) -> StatusCode {
         match self {
             ApiError::Core(core_error) => match core_error {
                 CoreError::EntityNotFound { .. } => StatusCode::NOT_FOUND,
@@ -222,14 +392,16 @@ impl ApiError {
             ApiError::Configuration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::Database { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::Authentication { .. } => StatusCode::UNAUTHORIZED,
-            ApiError::Authorization { .. } => StatusCode::FORBIDDEN,
-            ApiError::Validation { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Authorization { .. } | ApiError::PurposeOfUseRequired { .. } => StatusCode::FORBIDDEN,
+            ApiError::Validation { .. } | ApiError::InvalidFields { .. } => StatusCode::BAD_REQUEST,
             ApiError::ExternalService { .. } => StatusCode::BAD_GATEWAY,
             ApiError::Fhir { .. } => StatusCode::BAD_REQUEST,
             ApiError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::BadRequest { .. } => StatusCode::BAD_REQUEST,
             ApiError::NotFound { .. } => StatusCode::NOT_FOUND,
             ApiError::Conflict { .. } => StatusCode::CONFLICT,
+            ApiError::NotAcceptable { .. } => StatusCode::NOT_ACCEPTABLE,
+            ApiError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
             ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             ApiError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
         }
@@ -240,24 +412,118 @@ impl ApiError {
         ErrorResponse {
             error: self.category().to_string(),
             message: self.to_string(),
-            details: None,
+            details: self
+                .field_errors()
+                .and_then(|fields| serde_json::to_value(fields).ok()),
             timestamp: chrono::Utc::now(),
             path,
             request_id,
         }
     }
+
+    /// Render the error as RFC 7807 problem details
+    pub fn problem_details(&self, instance: Option<String>, request_id: Option<String>) -> ProblemDetails {
+        let status = self.status_code();
+        ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            instance,
+            error: self.category().to_string(),
+            details: self.field_errors(),
+            timestamp: chrono::Utc::now(),
+            request_id,
+        }
+    }
+
+    /// FHIR issue type of the error
+    fn issue_code(&self) -> &'static str {
+        match self {
+            ApiError::Core(core_error) => match core_error {
+                CoreError::EntityNotFound { .. } => "not-found",
+                CoreError::ValidationError { .. } => "invalid",
+                CoreError::BusinessRuleViolation { .. } => "business-rule",
+                CoreError::AuthorizationError { .. } => "forbidden",
+                CoreError::FhirError { .. } => "processing",
+                CoreError::DataIntegrityError { .. } => "conflict",
+                CoreError::ExternalServiceError { .. } => "transient",
+                CoreError::ConfigurationError { .. } | CoreError::InternalError { .. } => "exception",
+            },
+            ApiError::Configuration { .. } | ApiError::Database { .. } | ApiError::Internal { .. } => "exception",
+            ApiError::Authentication { .. } => "login",
+            ApiError::Authorization { .. } | ApiError::PurposeOfUseRequired { .. } => "forbidden",
+            ApiError::Validation { .. } | ApiError::InvalidFields { .. } | ApiError::BadRequest { .. } => "invalid",
+            ApiError::ExternalService { .. } | ApiError::ServiceUnavailable { .. } => "transient",
+            ApiError::Fhir { .. } => "processing",
+            ApiError::NotFound { .. } => "not-found",
+            ApiError::Conflict { .. } => "conflict",
+            ApiError::NotAcceptable { .. } => "not-supported",
+            ApiError::PayloadTooLarge { .. } => "too-long",
+            ApiError::TooManyRequests { .. } => "throttled",
+        }
+    }
+
+    /// Render the error as a FHIR OperationOutcome, with one issue per
+    /// invalid field
+    pub fn operation_outcome(&self) -> OperationOutcome {
+        let code = self.issue_code();
+        let issue = match self.field_errors() {
+            Some(fields) => fields
+                .into_iter()
+                .map(|field| OperationOutcomeIssue {
+                    severity: "error".to_string(),
+                    code: code.to_string(),
+                    details: Some(serde_json::json!({"text": field.code})),
+                    diagnostics: Some(field.message),
+                    expression: Some(vec![field.field]),
+                })
+                .collect(),
+            None => vec![OperationOutcomeIssue {
+                severity: "error".to_string(),
+                code: code.to_string(),
+                details: None,
+                diagnostics: Some(self.to_string()),
+                expression: None,
+            }],
+        };
+        OperationOutcome {
+            resource_type: "OperationOutcome".to_string(),
+            issue,
+        }
+    }
+
+    /// Build the HTTP response for the error in `format`
+    pub fn render(&self, format: ErrorFormat, path: Option<String>, request_id: Option<String>) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match format {
+            ErrorFormat::Problem => response
+                .content_type(PROBLEM_JSON)
+                .json(self.problem_details(path, request_id)),
+            ErrorFormat::OperationOutcome => response.content_type(FHIR_JSON).json(self.operation_outcome()),
+            ErrorFormat::OperationOutcomeXml => {
+                let xml = serde_json::to_value(self.operation_outcome())
+                    .ok()
+                    .and_then(|outcome| FhirFormat::Xml.encode(&outcome).ok());
+                match xml {
+                    Some(xml) => response.content_type(FHIR_XML).body(xml),
+                    None => response.content_type(FHIR_JSON).json(self.operation_outcome()),
+                }
+            }
+        }
+    }
 }
 
 impl ResponseError for ApiError {
+    /// Problem details without the request; [`ErrorNegotiation`] renders
+    /// the error again for the request it failed
+    ///
+    /// [`ErrorNegotiation`]: crate::middleware::errors::ErrorNegotiation
     fn error_response(&self) -> HttpResponse {
-        let error_response = self.error_response(None, None);
-        
-        HttpResponse::build(self.status_code())
-            .insert_header(("Content-Type", "application/json"))
-            .json(error_response)
+        self.render(ErrorFormat::Problem, None, None)
     }
 
-    fn status_code(&self) -> actix_web::http::StatusCode {
+    fn status_code(&self) -> StatusCode {
         self.status_code()
     }
 }
@@ -269,9 +535,17 @@ impl From<config::ConfigError> for ApiError {
     }
 }
 
+/// Postgres message for a statement stopped by `statement_timeout`
+const STATEMENT_TIMEOUT_MESSAGE: &str = "canceling statement due to statement timeout";
+
 impl From<diesel::result::Error> for ApiError {
     fn from(err: diesel::result::Error) -> Self {
-        ApiError::database_error(&err.to_string())
+        match err {
+            diesel::result::Error::DatabaseError(_, ref info) if info.message() == STATEMENT_TIMEOUT_MESSAGE => {
+                ApiError::service_unavailable("The query took too long; narrow it down or try again later")
+            }
+            err => ApiError::database_error(&err.to_string()),
+        }
     }
 }
 
@@ -281,6 +555,27 @@ impl From<deadpool_diesel::PoolError> for ApiError {
     }
 }
 
+impl From<deadpool_diesel::InteractError> for ApiError {
+    fn from(err: deadpool_diesel::InteractError) -> Self {
+        ApiError::database_error(&err.to_string())
+    }
+}
+
+impl From<emr_fhir::FhirClientError> for ApiError {
+    fn from(err: emr_fhir::FhirClientError) -> Self {
+        use emr_fhir::FhirClientError;
+
+        match err {
+            FhirClientError::NotFound { resource } => ApiError::not_found(&resource),
+            FhirClientError::Rejected { message, .. } => ApiError::fhir_error(&message),
+            FhirClientError::Unavailable { message, .. } => ApiError::external_service_error("FHIR", &message),
+            FhirClientError::Authentication(message) => ApiError::external_service_error("FHIR", &message),
+            FhirClientError::InvalidResponse(message) => ApiError::external_service_error("FHIR", &message),
+            FhirClientError::Configuration(message) => ApiError::configuration_error(&message),
+        }
+    }
+}
+
 impl From<reqwest::Error> for ApiError {
     fn from(err: reqwest::Error) -> Self {
         ApiError::external_service_error("HTTP", &err.to_string())
@@ -293,6 +588,93 @@ impl From<serde_json::Error> for ApiError {
     }
 }
 
+impl From<validator::ValidationErrors> for ApiError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut fields = Vec::new();
+        collect_field_errors(&errors, "", &mut fields);
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+        ApiError::invalid_fields(fields)
+    }
+}
+
+/// Flatten nested validation errors into fields with dotted paths
+fn collect_field_errors(errors: &validator::ValidationErrors, prefix: &str, fields: &mut Vec<FieldError>) {
+    use validator::ValidationErrorsKind;
+
+    for (name, kind) in errors.errors() {
+        let path = if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                for error in errors {
+                    let message = match &error.message {
+                        Some(message) => message.to_string(),
+                        None => default_message(error),
+                    };
+                    fields.push(FieldError::new(&path, &error.code, &message));
+                }
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, &path, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(errors, &format!("{}[{}]", path, index), fields);
+                }
+            }
+        }
+    }
+}
+
+/// Message for a validation rule that did not set one
+fn default_message(error: &validator::ValidationError) -> String {
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+    match error.code.as_ref() {
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must have a length between {} and {}", min, max),
+            (Some(min), None) => format!("must have a length of at least {}", min),
+            (None, Some(max)) => format!("must have a length of at most {}", max),
+            (None, None) => "has an invalid length".to_string(),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must be between {} and {}", min, max),
+            (Some(min), None) => format!("must be at least {}", min),
+            (None, Some(max)) => format!("must be at most {}", max),
+            (None, None) => "is out of range".to_string(),
+        },
+        "email" => "must be an email address".to_string(),
+        "url" => "must be a URL".to_string(),
+        "required" => "is required".to_string(),
+        code => format!("is invalid ({})", code),
+    }
+}
+
+/// Turn a malformed JSON body into a 400 naming the offending field where
+/// serde reports one, and an oversized one into a 413; install with
+/// `web::JsonConfig::default().error_handler`
+pub fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    use actix_web::error::JsonPayloadError;
+
+    let error = match &err {
+        JsonPayloadError::Deserialize(e) if e.is_data() => {
+            let message = e.to_string();
+            // serde names the field in backticks, e.g. "missing field `name` at line 1 column 9"
+            let named = |prefix: &str| message.starts_with(prefix).then(|| message.split('`').nth(1)).flatten();
+            let field = match (named("missing field"), named("unknown field")) {
+                (Some(field), _) => FieldError::new(field, "required", "is required"),
+                (_, Some(field)) => FieldError::new(field, "unknown", "is not a known field"),
+                _ => FieldError::new("body", "invalid", &message),
+            };
+            ApiError::invalid_fields(vec![field])
+        }
+        JsonPayloadError::OverflowKnownLength { limit, .. } | JsonPayloadError::Overflow { limit } => {
+            ApiError::payload_too_large(&format!("JSON bodies are limited to {} bytes", limit))
+        }
+        _ => ApiError::bad_request(&err.to_string()),
+    };
+    error.into()
+}
+
 impl From<std::io::Error> for ApiError {
     fn from(err: std::io::Error) -> Self {
         ApiError::internal_error(&err.to_string())
@@ -331,6 +713,18 @@ mod tests {
         assert_eq!(response.request_id, Some("req-123".to_string()));
     }
 
+    #[test]
+    fn test_fhir_client_error_mapping() {
+        use emr_fhir::FhirClientError;
+
+        let not_found = ApiError::from(FhirClientError::NotFound { resource: "Patient/1".to_string() });
+        assert_eq!(not_found.status_code(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let unavailable = ApiError::from(FhirClientError::Unavailable { status: Some(503), message: "down".to_string() });
+        assert_eq!(unavailable.status_code(), actix_web::http::StatusCode::BAD_GATEWAY);
+        assert!(unavailable.is_retryable());
+    }
+
     #[test]
     fn test_retryable_errors() {
         let external_error = ApiError::external_service_error("TestService", "Connection failed");
@@ -343,6 +737,71 @@ mod tests {
         assert!(internal_error.is_retryable());
     }
 
+    #[test]
+    fn test_field_error_details() {
+        use validator::Validate;
+
+        #[derive(Validate)]
+        struct Request {
+            #[validate(length(min = 1, max = 10))]
+            name: String,
+            #[validate(range(min = 0, max = 100))]
+            percentage: u8,
+        }
+
+        let errors = Request { name: String::new(), percentage: 150 }.validate().unwrap_err();
+        let error = ApiError::from(errors);
+        assert_eq!(error.status_code(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let response = error.error_response(None, None);
+        assert_eq!(response.error, "validation");
+        let details: Vec<FieldError> = serde_json::from_value(response.details.unwrap()).unwrap();
+        assert_eq!(details.iter().map(|d| d.field.as_str()).collect::<Vec<_>>(), vec!["name", "percentage"]);
+        assert_eq!(details[0].code, "length");
+        assert_eq!(details[1].message, "must be between 0 and 100");
+
+        let core = ApiError::from(CoreError::validation_error_with_field("Too long", "name"));
+        assert_eq!(core.field_errors(), Some(vec![FieldError::new("name", "invalid", "Too long")]));
+        assert!(ApiError::not_found("Patient").error_response(None, None).details.is_none());
+
+        let purpose = ApiError::purpose_of_use_required("Select a purpose of use");
+        assert_eq!(purpose.status_code(), actix_web::http::StatusCode::FORBIDDEN);
+        assert_eq!(purpose.category(), "purpose_of_use_required");
+        assert_eq!(purpose.field_errors().unwrap()[0].field, "purpose_of_use");
+    }
+
+    #[test]
+    fn test_error_format_negotiation() {
+        assert_eq!(ErrorFormat::from_accept(None), ErrorFormat::Problem);
+        assert_eq!(ErrorFormat::from_accept(Some("application/json")), ErrorFormat::Problem);
+        assert_eq!(
+            ErrorFormat::from_accept(Some("text/html, application/fhir+json;q=0.9")),
+            ErrorFormat::OperationOutcome
+        );
+        assert_eq!(ErrorFormat::from_accept(Some("application/fhir+json; q=0")), ErrorFormat::Problem);
+        assert_eq!(
+            ErrorFormat::from_accept(Some("application/fhir+json;q=0.5, application/fhir+xml")),
+            ErrorFormat::OperationOutcomeXml
+        );
+
+        let error = ApiError::not_found("Patient 123 not found");
+        let problem = error.problem_details(Some("/api/patients/123".to_string()), None);
+        assert_eq!((problem.status, problem.title.as_str()), (404, "Not Found"));
+        assert_eq!(problem.instance.as_deref(), Some("/api/patients/123"));
+
+        let outcome = error.operation_outcome();
+        assert_eq!(outcome.resource_type, "OperationOutcome");
+        assert_eq!(outcome.issue[0].code, "not-found");
+
+        let invalid = ApiError::invalid_fields(vec![
+            FieldError::new("name", "length", "must not be empty"),
+            FieldError::new("gender", "one_of", "must be male, female, other or unknown"),
+        ]);
+        let outcome = invalid.operation_outcome();
+        assert_eq!(outcome.issue.len(), 2);
+        assert_eq!(outcome.issue[1].expression, Some(vec!["gender".to_string()]));
+    }
+
     #[test]
     fn test_core_error_conversion() {
         let core_error = CoreError::entity_not_found("Patient", uuid::Uuid::new_v4());
@@ -351,4 +810,16 @@ mod tests {
         assert_eq!(api_error.category(), "core");
         assert_eq!(api_error.status_code(), actix_web::http::StatusCode::NOT_FOUND);
     }
+
+    #[test]
+    fn test_statement_timeout_conversion() {
+        use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+        let timeout = DieselError::DatabaseError(
+            DatabaseErrorKind::Unknown,
+            Box::new(STATEMENT_TIMEOUT_MESSAGE.to_string()),
+        );
+        assert_eq!(ApiError::from(timeout).status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ApiError::from(DieselError::NotFound).category(), "database");
+    }
 } 
\ No newline at end of file
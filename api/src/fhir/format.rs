@@ -0,0 +1,102 @@
+//! FHIR content negotiation
+//!
+//! FHIR endpoints answer in JSON or XML as the client asks: the `_format`
+//! query parameter wins, then the `Accept` header, then JSON. Request bodies
+//! are read as their `Content-Type` says.
+//!
+//! Bundles built by the API are streamed: their JSON is serialized a chunk
+//! of entries at a time as the client reads it (see
+//! [`fhir_bundle_response`]).
+
+use actix_web::dev::Payload;
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use emr_fhir::{Bundle, FhirFormat};
+use futures_util::future::LocalBoxFuture;
+use futures_util::stream;
+use serde_json::Value;
+use crate::error::{ApiError, Result};
+
+/// Bytes of Bundle JSON serialized per streamed chunk
+pub const BUNDLE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The representation a request asks FHIR resources to be returned in
+pub fn response_format(req: &HttpRequest) -> FhirFormat {
+    let requested = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| {
+            query
+                .iter()
+                .find(|(key, _)| key == "_format")
+                .and_then(|(_, value)| FhirFormat::from_media_type(value))
+        });
+    requested.unwrap_or_else(|| {
+        FhirFormat::from_accept(req.headers().get(ACCEPT).and_then(|value| value.to_str().ok()))
+    })
+}
+
+/// Finish a response with a FHIR resource in the representation the request
+/// asks for
+pub fn fhir_response(req: &HttpRequest, mut response: HttpResponseBuilder, resource: &Value) -> Result<HttpResponse> {
+    let format = response_format(req);
+    let body = format
+        .encode(resource)
+        .map_err(|e| ApiError::internal_error(&format!("Failed to serialize FHIR resource: {}", e)))?;
+    Ok(response.content_type(format.content_type()).body(body))
+}
+
+/// Finish a response with a Bundle in the representation the request asks
+/// for. JSON is streamed in chunks of [`BUNDLE_CHUNK_SIZE`]; XML is
+/// rendered whole.
+pub fn fhir_bundle_response(
+    req: &HttpRequest,
+    mut response: HttpResponseBuilder,
+    bundle: Bundle,
+) -> Result<HttpResponse> {
+    match response_format(req) {
+        FhirFormat::Json => {
+            let chunks = bundle
+                .json_chunks(BUNDLE_CHUNK_SIZE)
+                .map(|chunk| chunk.map(web::Bytes::from).map_err(ApiError::from));
+            Ok(response
+                .content_type(FhirFormat::Json.content_type())
+                .streaming(stream::iter(chunks)))
+        }
+        FhirFormat::Xml => fhir_response(req, response, &bundle.into_value()),
+    }
+}
+
+/// A FHIR resource request body, in JSON or XML
+#[derive(Debug)]
+pub struct FhirBody(pub Value);
+
+impl std::ops::Deref for FhirBody {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl FromRequest for FhirBody {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, std::result::Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let format = if req.headers().contains_key(CONTENT_TYPE) {
+            FhirFormat::from_media_type(req.content_type())
+        } else {
+            Some(FhirFormat::Json)
+        };
+        let body = web::Bytes::from_request(req, payload);
+
+        Box::pin(async move {
+            let format = format.ok_or_else(|| {
+                ApiError::bad_request("FHIR resources are sent as application/fhir+json or application/fhir+xml")
+            })?;
+            let body = body.await?;
+            let resource = format.decode(&body).map_err(|e| ApiError::bad_request(&e.to_string()))?;
+            Ok(FhirBody(resource))
+        })
+    }
+}
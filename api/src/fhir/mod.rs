@@ -1,77 +1,118 @@
 //! FHIR client module
+//!
+//! The API talks to the FHIR server only through [`FhirGateway`], implemented
+//! by the fhir crate's [`KodjinClient`]. Handlers hold an
+//! `Arc<dyn FhirGateway>` so tests can substitute their own gateway.
+//! [`format`] negotiates JSON or XML with the API's own clients.
 
+pub mod format;
+
+use crate::config::{FhirAuthConfig, FhirConfig};
 use crate::error::{ApiError, Result};
-use reqwest::Client;
-use serde_json::Value;
-
-/// FHIR client for interacting with Kodjin FHIR server
-#[derive(Clone)]
-pub struct FhirClient {
-    client: Client,
-    base_url: String,
+use emr_fhir::ClientCredentials;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use emr_fhir::{FhirGateway, KodjinClient};
+pub use format::{fhir_bundle_response, fhir_response, FhirBody};
+
+/// Build the FHIR gateway from configuration
+pub fn gateway_from_config(config: &FhirConfig) -> Result<Arc<dyn FhirGateway>> {
+    let mut client = KodjinClient::new(&config.base_url)
+        .map_err(ApiError::from)?
+        .with_timeout(Duration::from_secs(config.timeout))
+        .with_retries(config.max_retries, Duration::from_millis(config.retry_delay))
+        .with_format(config.format);
+
+    let auth = &config.auth;
+    if let Some((identity, ca)) = read_mtls_files(auth)? {
+        client = client.with_mtls(&identity, ca.as_deref())?;
+    }
+    if let Some(credentials) = client_credentials(auth)? {
+        client = client.with_client_credentials(credentials);
+    }
+
+    Ok(Arc::new(client))
 }
 
-impl FhirClient {
-    /// Create a new FHIR client
-    pub fn new(base_url: &str) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .map_err(|e| ApiError::fhir_error(&format!("Failed to create HTTP client: {}", e)))?;
-
-        Ok(Self {
-            client,
-            base_url: base_url.to_string(),
-        })
+/// Client-credentials settings, when a token URL is configured
+fn client_credentials(auth: &FhirAuthConfig) -> Result<Option<ClientCredentials>> {
+    let Some(token_url) = auth.token_url.as_ref().filter(|url| !url.is_empty()) else {
+        return Ok(None);
+    };
+    if auth.client_id.is_empty() || auth.client_secret.is_empty() {
+        return Err(ApiError::configuration_error(
+            "fhir.auth.client_id and fhir.auth.client_secret are required with fhir.auth.token_url",
+        ));
     }
 
-    /// Get a patient by ID
-    pub async fn get_patient(&self, id: &str) -> Result<Value> {
-        let url = format!("{}/Patient/{}", self.base_url, id);
-        
-        let response = self.client
-            .get(&url)
-            .header("Accept", "application/fhir+json")
-            .send()
-            .await
-            .map_err(|e| ApiError::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            let json: Value = response.json().await
-                .map_err(|e| ApiError::fhir_error(&format!("Failed to parse FHIR response: {}", e)))?;
-            Ok(json)
-        } else {
-            Err(ApiError::fhir_error(&format!("FHIR request failed with status: {}", response.status())))
+    Ok(Some(ClientCredentials {
+        token_url: token_url.clone(),
+        client_id: auth.client_id.clone(),
+        client_secret: auth.client_secret.clone(),
+        scope: auth.scope.clone(),
+    }))
+}
+
+/// Client identity PEM (certificate chain + key) and optional CA PEM
+type MtlsFiles = (Vec<u8>, Option<Vec<u8>>);
+
+/// Read the client identity and CA files, if mutual TLS is configured
+fn read_mtls_files(auth: &FhirAuthConfig) -> Result<Option<MtlsFiles>> {
+    let (cert_path, key_path) = match (&auth.client_cert_path, &auth.client_key_path) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(ApiError::configuration_error(
+                "fhir.auth.client_cert_path and fhir.auth.client_key_path must be set together",
+            ))
         }
+    };
+
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|e| ApiError::configuration_error(&format!("Failed to read {}: {}", path, e)))
+    };
+    let mut identity = read(cert_path)?;
+    identity.push(b'\n');
+    identity.extend(read(key_path)?);
+    let ca = auth.ca_cert_path.as_deref().map(read).transpose()?;
+
+    Ok(Some((identity, ca)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gateway_from_config() {
+        let config = crate::config::Config::default().fhir;
+        assert!(gateway_from_config(&config).is_ok());
     }
 
-    /// Search for FHIR resources
-    pub async fn search(&self, resource_type: &str, params: &[(&str, &str)]) -> Result<Value> {
-        let mut url = format!("{}/{}", self.base_url, resource_type);
-        
-        if !params.is_empty() {
-            url.push('?');
-            for (i, (key, value)) in params.iter().enumerate() {
-                if i > 0 {
-                    url.push('&');
-                }
-                url.push_str(&format!("{}={}", key, value));
-            }
-        }
+    #[test]
+    fn test_client_credentials_config() {
+        assert!(client_credentials(&FhirAuthConfig::default()).unwrap().is_none());
 
-        let response = self.client
-            .get(&url)
-            .header("Accept", "application/fhir+json")
-            .send()
-            .await
-            .map_err(|e| ApiError::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            let json: Value = response.json().await
-                .map_err(|e| ApiError::fhir_error(&format!("Failed to parse FHIR response: {}", e)))?;
-            Ok(json)
-        } else {
-            Err(ApiError::fhir_error(&format!("FHIR search failed with status: {}", response.status())))
-        }
+        let mut auth = FhirAuthConfig {
+            token_url: Some("https://auth.example.com/oauth2/token".to_string()),
+            ..Default::default()
+        };
+        assert!(client_credentials(&auth).is_err());
+
+        auth.client_id = "emr-api".to_string();
+        auth.client_secret = "secret".to_string();
+        let credentials = client_credentials(&auth).unwrap().unwrap();
+        assert_eq!(credentials.client_id, "emr-api");
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_mtls_paths_must_be_paired() {
+        let auth = FhirAuthConfig {
+            client_cert_path: Some("certs/fhir-client.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(read_mtls_files(&auth).is_err());
+        assert!(read_mtls_files(&FhirAuthConfig::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,54 @@
+//! Internal gRPC patient service
+//!
+//! The jobs worker looks patients up here rather than through the HTTP
+//! API. Callers are other services authenticated by their client
+//! certificate (see `emr_proto::GrpcConfig`), so no user token is checked.
+
+use crate::repositories::PatientRepository;
+use emr_proto::v1::patient_service_server::{PatientService, PatientServiceServer};
+use emr_proto::v1::{GetPatientRequest, Patient};
+use emr_proto::GrpcConfig;
+use tonic::{Request, Response, Status};
+
+/// [`PatientService`] backed by the patient repository
+#[derive(Default)]
+pub struct RepositoryPatientService;
+
+#[tonic::async_trait]
+impl PatientService for RepositoryPatientService {
+    async fn get_patient(&self, request: Request<GetPatientRequest>) -> Result<Response<Patient>, Status> {
+        let patient_id = uuid::Uuid::parse_str(&request.get_ref().patient_id)
+            .map_err(|_| Status::invalid_argument(format!("'{}' is not a patient id", request.get_ref().patient_id)))?;
+
+        let patient = PatientRepository::new()
+            .find_by_id(patient_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::not_found(format!("Patient {} not found", patient_id)))?;
+        tracing::info!(
+            patient_id = %patient_id,
+            request_id = emr_proto::interceptors::request_id(&request).unwrap_or_default(),
+            "Patient looked up over the internal API"
+        );
+
+        Ok(Response::new(Patient {
+            id: patient.id.to_string(),
+            name: patient.name,
+            gender: patient.gender,
+            birth_date: patient.birth_date.map(|date| date.to_string()),
+            active: patient.active,
+        }))
+    }
+}
+
+/// Serve the patient service on `grpc.listen_addr` until it fails
+pub async fn serve(config: &GrpcConfig) -> anyhow::Result<()> {
+    let addr = config.listen_addr.parse()?;
+    tracing::info!(%addr, mtls = config.mtls(), "Serving the internal patient API");
+
+    emr_proto::server(config)?
+        .add_service(PatientServiceServer::new(RepositoryPatientService))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
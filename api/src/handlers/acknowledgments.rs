@@ -0,0 +1,258 @@
+//! Result acknowledgment endpoints
+//!
+//! An abnormal result recorded against an order opens an acknowledgment task
+//! for the practitioner who placed the order, and a Notification job tells
+//! them it is waiting; critical results are sent at critical priority so
+//! quiet hours do not hold them. Practitioners work through
+//! `/practitioners/{id}/acknowledgments` and acknowledge each result.
+//! Critical results left unacknowledged past the SLA are escalated by the
+//! jobs worker. `/admin/compliance/acknowledgment-latency` reports how long
+//! acknowledgment took. Tasks are also available as FHIR `Task`s.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use emr_core::domain::{AcknowledgmentTask, Observation, ServiceRequest, DEFAULT_CRITICAL_ACK_SLA_MINUTES};
+use emr_core::notifications::{TemplateRef, ABNORMAL_RESULT_TEMPLATE, CRITICAL_RESULT_TEMPLATE};
+use emr_core::types::Id;
+use emr_fhir::acknowledgment_task_to_fhir;
+use emr_jobs::types::{JobSubmission, JobType, NotificationChannel, NotificationJob, NotificationType, Priority};
+use serde::Deserialize;
+use serde_json::json;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::repositories::{AcknowledgmentRepository, ServiceRequestRepository};
+use crate::AppState;
+
+/// Days the latency report covers when no range is given
+const DEFAULT_REPORT_DAYS: i64 = 30;
+
+/// Practitioner's acknowledgment list
+#[derive(Debug, Deserialize)]
+pub struct AcknowledgmentListQuery {
+    /// Only results still waiting for acknowledgment
+    #[serde(default)]
+    pub pending: bool,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Acknowledgment of a result
+#[derive(Debug, Deserialize)]
+pub struct AcknowledgeRequest {
+    /// Practitioner acknowledging, the orderer or someone covering for them
+    pub practitioner_id: Id,
+    /// Follow-up planned for the result
+    pub note: Option<String>,
+}
+
+/// Latency report range, defaulting to the last 30 days
+#[derive(Debug, Deserialize)]
+pub struct LatencyReportQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// SLA critical results are measured against
+    pub critical_sla_minutes: Option<i64>,
+}
+
+/// Notification job telling the orderer a result waits for acknowledgment
+fn acknowledgment_job(task: &AcknowledgmentTask) -> JobSubmission {
+    let (template, priority) = if task.critical {
+        (CRITICAL_RESULT_TEMPLATE, Priority::Critical)
+    } else {
+        (ABNORMAL_RESULT_TEMPLATE, Priority::High)
+    };
+
+    let mut variables = serde_json::Map::new();
+    variables.insert("test_name".to_string(), task.test_name.clone().into());
+
+    JobSubmission {
+        job_id: None,
+        idempotency_key: Some(format!("acknowledgment:{}", task.id)),
+        job: JobType::Notification(NotificationJob {
+            recipient_id: task.owner_id,
+            address: None,
+            notification_type: NotificationType::Alert,
+            message: String::new(),
+            template: Some(TemplateRef {
+                name: template.to_string(),
+                locale: None,
+                variables,
+            }),
+            channel: NotificationChannel::InApp,
+            priority,
+            scheduled_for: None,
+            digest: false,
+        }),
+    }
+}
+
+/// Order a result is based on, checked to be for the result's patient
+pub(crate) async fn result_order(data: &AppState, observation: &Observation) -> Result<Option<ServiceRequest>> {
+    let Some(order_id) = observation.based_on.first() else {
+        return Ok(None);
+    };
+
+    let order = ServiceRequestRepository::new()
+        .find(&data.db_pool, *order_id)
+        .await?
+        .ok_or_else(|| ApiError::validation_error(&format!("Order {} not found", order_id)))?;
+    if order.patient_id != observation.subject {
+        return Err(ApiError::validation_error(&format!(
+            "Order {} is for a different patient than the result",
+            order_id
+        )));
+    }
+    Ok(Some(order))
+}
+
+/// Open an acknowledgment task for an abnormal result and notify the orderer
+///
+/// Results without an order have nobody to acknowledge them and are
+/// skipped, as are results that already have a task.
+pub(crate) async fn open_acknowledgment(
+    data: &AppState,
+    observation: &Observation,
+    order: Option<&ServiceRequest>,
+) -> Result<Option<AcknowledgmentTask>> {
+    let Some(order) = order else {
+        return Ok(None);
+    };
+    let test_name = order.display.as_deref().unwrap_or(&observation.code);
+    let Some(task) =
+        AcknowledgmentTask::for_result(observation, order.requester_id, Some(order.metadata.id), test_name, Utc::now())
+    else {
+        return Ok(None);
+    };
+
+    if !AcknowledgmentRepository::new().insert(&data.db_pool, &task).await? {
+        return Ok(None);
+    }
+
+    // The task is stored and listed; critical ones are escalated regardless
+    if let Err(error) = submit_job(data, &acknowledgment_job(&task)).await {
+        tracing::warn!(task_id = %task.id, %error, "Failed to submit result notification");
+    }
+
+    Ok(Some(task))
+}
+
+/// A practitioner's results to acknowledge; pending ones come critical and oldest first
+#[get("/practitioners/{id}/acknowledgments")]
+pub async fn list_acknowledgments(
+    path: web::Path<Id>,
+    query: web::Query<AcknowledgmentListQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+
+    let tasks = AcknowledgmentRepository::new()
+        .for_owner(&data.db_pool, path.into_inner(), query.pending, pagination.limit(), pagination.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        tasks,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+async fn find_task(data: &AppState, id: Id) -> Result<AcknowledgmentTask> {
+    AcknowledgmentRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Acknowledgment task {} not found", id)))
+}
+
+/// An acknowledgment task as a FHIR `Task`
+#[get("/acknowledgments/{id}/fhir")]
+pub async fn get_acknowledgment_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let task = find_task(&data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &acknowledgment_task_to_fhir(&task))
+}
+
+/// Acknowledge a result
+#[post("/acknowledgments/{id}/acknowledge")]
+pub async fn acknowledge_result(
+    path: web::Path<Id>,
+    request: web::Json<AcknowledgeRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut task = find_task(&data, path.into_inner()).await?;
+    task.acknowledge(request.practitioner_id, request.note, Utc::now())?;
+
+    if !AcknowledgmentRepository::new().acknowledge(&data.db_pool, &task).await? {
+        return Err(ApiError::conflict("The result was acknowledged by another request"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(task)))
+}
+
+/// Acknowledgment latency of critical and other abnormal results
+#[get("/admin/compliance/acknowledgment-latency")]
+pub async fn acknowledgment_latency(
+    query: web::Query<LatencyReportQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - Duration::days(DEFAULT_REPORT_DAYS));
+    if from >= to {
+        return Err(ApiError::validation_error("from must be before to"));
+    }
+    let sla_minutes = query.critical_sla_minutes.unwrap_or(DEFAULT_CRITICAL_ACK_SLA_MINUTES);
+    if sla_minutes <= 0 {
+        return Err(ApiError::validation_error("critical_sla_minutes must be positive"));
+    }
+
+    let rows = AcknowledgmentRepository::new()
+        .latency(&data.db_pool, from, to, sla_minutes)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        rows,
+        json!({ "from": from, "to": to, "critical_sla_minutes": sla_minutes }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::domain::{ObservationStatus, ObservationValue};
+
+    fn task(interpretation: &str) -> AcknowledgmentTask {
+        let mut observation = Observation::new(ObservationStatus::Final, "2823-3".to_string(), uuid::Uuid::new_v4());
+        observation.value = Some(ObservationValue::Quantity {
+            value: 6.8,
+            unit: "mmol/L".to_string(),
+            system: None,
+            code: None,
+        });
+        observation.interpretation.push(interpretation.to_string());
+        AcknowledgmentTask::for_result(&observation, uuid::Uuid::new_v4(), None, "Potassium", Utc::now()).unwrap()
+    }
+
+    #[test]
+    fn test_acknowledgment_job() {
+        let critical = serde_json::to_value(acknowledgment_job(&task("HH"))).unwrap();
+        assert_eq!(critical["template"]["name"], CRITICAL_RESULT_TEMPLATE);
+        assert_eq!(critical["priority"], "Critical");
+        assert_eq!(critical["template"]["variables"]["test_name"], "Potassium");
+
+        let abnormal = serde_json::to_value(acknowledgment_job(&task("H"))).unwrap();
+        assert_eq!(abnormal["template"]["name"], ABNORMAL_RESULT_TEMPLATE);
+        assert_eq!(abnormal["priority"], "High");
+        assert_eq!(abnormal["channel"], "InApp");
+    }
+}
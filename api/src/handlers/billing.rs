@@ -0,0 +1,169 @@
+//! Billing reconciliation endpoints
+//!
+//! The jobs worker posts the payer's 835 remittances against exported
+//! claims. `/admin/billing/reconciliation` compares what was billed for each
+//! claim with what was paid, written off and passed to the patient, so
+//! billing staff can see outstanding balances. Denials, reversals, unmatched
+//! payments and charge mismatches open follow-up tasks, worked through
+//! `/admin/billing/tasks`.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDate, Utc};
+use emr_core::domain::TaskStatus;
+use emr_core::types::Id;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::models::ClaimReconciliationModel;
+use crate::repositories::BillingRepository;
+use crate::AppState;
+
+/// Days of service the reconciliation report covers when no range is given
+const DEFAULT_REPORT_DAYS: i64 = 30;
+
+/// Reconciliation report range, by date of service
+#[derive(Debug, Deserialize)]
+pub struct ReconciliationQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    /// Only claims billed to this payer
+    pub payer_id: Option<String>,
+}
+
+/// Follow-up task list
+#[derive(Debug, Deserialize)]
+pub struct BillingTaskListQuery {
+    /// Completed tasks instead of open ones
+    #[serde(default)]
+    pub completed: bool,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Completion of a follow-up task
+#[derive(Debug, Deserialize)]
+pub struct CompleteBillingTaskRequest {
+    pub completed_by: Id,
+}
+
+/// Report totals, in cents, and claim counts by status
+fn totals(claims: &[ClaimReconciliationModel]) -> Value {
+    let sum = |amount: fn(&ClaimReconciliationModel) -> i64| claims.iter().map(amount).sum::<i64>();
+    let count = |status: &str| claims.iter().filter(|claim| claim.status == status).count();
+
+    json!({
+        "claims": claims.len(),
+        "billed_cents": sum(|claim| claim.billed_cents),
+        "paid_cents": sum(|claim| claim.paid_cents),
+        "written_off_cents": sum(|claim| claim.written_off_cents),
+        "patient_responsibility_cents": sum(|claim| claim.patient_responsibility_cents),
+        "balance_cents": sum(|claim| claim.balance_cents),
+        "unpaid": count("unpaid"),
+        "denied": count("denied"),
+        "partial": count("partial"),
+        "paid": count("paid"),
+    })
+}
+
+/// Billed, paid and outstanding amounts of exported claims
+#[get("/admin/billing/reconciliation")]
+pub async fn claim_reconciliation(
+    query: web::Query<ReconciliationQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - Duration::days(DEFAULT_REPORT_DAYS));
+    if from > to {
+        return Err(ApiError::validation_error("from must not be after to"));
+    }
+
+    let claims = BillingRepository::new()
+        .reconciliation(&data.db_pool, from, to, query.payer_id.clone())
+        .await?;
+    let totals = totals(&claims);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        claims,
+        json!({ "from": from, "to": to, "payer_id": query.payer_id, "totals": totals }),
+    )))
+}
+
+/// Follow-up tasks for billing staff, oldest first
+#[get("/admin/billing/tasks")]
+pub async fn get_billing_tasks(
+    query: web::Query<BillingTaskListQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+    let status = if query.completed { TaskStatus::Completed } else { TaskStatus::Requested };
+
+    let tasks = BillingRepository::new()
+        .tasks(&data.db_pool, status, pagination.limit() as i64, pagination.offset() as i64)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        tasks,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Close a follow-up task once the claim has been worked
+#[post("/admin/billing/tasks/{id}/complete")]
+pub async fn complete_billing_task(
+    path: web::Path<Id>,
+    request: web::Json<CompleteBillingTaskRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let repository = BillingRepository::new();
+    let mut task = repository
+        .find_task(&data.db_pool, path.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::not_found("Billing task not found"))?;
+
+    task.complete(request.completed_by, Utc::now())?;
+    if !repository.complete_task(&data.db_pool, &task).await? {
+        return Err(ApiError::conflict("The task was completed by another request"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(task)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(status: &str, billed_cents: i64, paid_cents: i64) -> ClaimReconciliationModel {
+        ClaimReconciliationModel {
+            claim_id: "ABC123".to_string(),
+            encounter_id: uuid::Uuid::new_v4(),
+            payer_id: "60054".to_string(),
+            service_date: NaiveDate::from_ymd_opt(2026, 3, 4).unwrap(),
+            billed_cents,
+            paid_cents,
+            written_off_cents: 0,
+            patient_responsibility_cents: 0,
+            balance_cents: billed_cents - paid_cents,
+            status: status.to_string(),
+            payment_count: 1,
+            open_tasks: 0,
+        }
+    }
+
+    #[test]
+    fn test_totals() {
+        let totals = totals(&[claim("paid", 10000, 10000), claim("partial", 5000, 2000), claim("denied", 900, 0)]);
+        assert_eq!(totals["claims"], 3);
+        assert_eq!(totals["billed_cents"], 15900);
+        assert_eq!(totals["balance_cents"], 3900);
+        assert_eq!((totals["paid"].clone(), totals["denied"].clone()), (json!(1), json!(1)));
+    }
+}
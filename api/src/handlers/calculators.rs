@@ -0,0 +1,110 @@
+//! Clinical calculator endpoints
+//!
+//! `GET /calculators` lists the calculators this deployment offers, and
+//! `POST /patients/{id}/calculators/{calculator}` runs one against the
+//! patient's demographics, stored observations and active conditions from the
+//! FHIR server. The result is stored as a calculated observation unless the
+//! request only asks for a preview.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::values::AdministrativeGender;
+use emr_core::services::calculators::CalculatorInput;
+use emr_core::services::ObservationService;
+use emr_core::types::Id;
+use serde::Deserialize;
+use crate::error::{ApiError, Result};
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::ApiResponse;
+use crate::repositories::PortalRepository;
+use crate::services::{active_conditions, calculator_registry};
+use crate::AppState;
+
+/// Calculator run options
+#[derive(Debug, Deserialize)]
+pub struct RunCalculatorQuery {
+    /// Calculate without storing the result
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// Stored gender as the domain value
+pub(crate) fn parse_gender(gender: &str) -> Option<AdministrativeGender> {
+    match gender.trim().to_ascii_lowercase().as_str() {
+        "male" => Some(AdministrativeGender::Male),
+        "female" => Some(AdministrativeGender::Female),
+        "other" => Some(AdministrativeGender::Other),
+        "unknown" => Some(AdministrativeGender::Unknown),
+        _ => None,
+    }
+}
+
+/// The available calculators
+#[get("/calculators")]
+pub async fn list_calculators(
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let registry = calculator_registry(&data.config.growth)?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(registry.list())))
+}
+
+/// Run a calculator for a patient
+#[post("/patients/{id}/calculators/{calculator}")]
+pub async fn run_calculator(
+    path: web::Path<(Id, String)>,
+    query: web::Query<RunCalculatorQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (patient_id, calculator) = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let registry = calculator_registry(&data.config.growth)?;
+    if registry.find(&calculator).is_none() {
+        return Err(ApiError::not_found(&format!("Calculator '{}' not found", calculator)));
+    }
+
+    let demographics = PortalRepository::new()
+        .demographics(&data.db_pool, patient_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Patient {} not found", patient_id)))?;
+    let input = CalculatorInput {
+        patient_id,
+        birth_date: demographics.birth_date,
+        gender: demographics.gender.as_deref().and_then(parse_gender),
+        observations: data.observations.get_patient_observations(patient_id).await?,
+        conditions: active_conditions(data.fhir_client.as_ref(), patient_id).await?,
+    };
+
+    let observation = registry.run(&calculator, &input, Utc::now())?;
+    if query.preview {
+        return Ok(HttpResponse::Ok().json(ApiResponse::new(observation)));
+    }
+    let observation = data.observations.create_observation(observation).await?;
+    tracing::info!(
+        patient_id = %patient_id,
+        calculator = %calculator,
+        observation_id = %observation.metadata.id,
+        "Calculated observation recorded"
+    );
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(observation)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gender() {
+        assert!(matches!(parse_gender("Female"), Some(AdministrativeGender::Female)));
+        assert!(matches!(parse_gender(" male "), Some(AdministrativeGender::Male)));
+        assert!(parse_gender("f").is_none());
+    }
+
+    #[test]
+    fn test_run_query_defaults_to_storing() {
+        let query: RunCalculatorQuery = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(!query.preview);
+    }
+}
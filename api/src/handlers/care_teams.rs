@@ -0,0 +1,515 @@
+//! Care team endpoints
+//!
+//! A patient's care teams (FHIR `CareTeam`) list the practitioners caring for
+//! them, each in a role for a period. Current participation in an active team
+//! is the treating relationship that [`authorize_patient_access`] requires
+//! before a practitioner reads patient data. Without one a practitioner can
+//! break the glass at `POST /patients/{id}/emergency-access`: the reason is
+//! recorded, the grant expires after a few hours, and every read made under
+//! it is written to the audit log.
+//!
+//! Sensitive data (psychiatric notes, HIV results, and the record of a
+//! patient outside the practitioner's panel, i.e. reached by breaking the
+//! glass) also needs a purpose of use that the consent rules permit; see
+//! [`authorize_sensitive_access`].
+
+use actix_web::{get, http::Method, post, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{CareTeam, CareTeamRole, Confidentiality};
+use emr_core::services::consent::{ConsentDecision, ConsentRules, PurposeOfUse, SensitiveData};
+use emr_core::services::{PatientAccess, SecurityService};
+use emr_core::types::Id;
+use emr_fhir::care_team_to_fhir;
+use serde::Deserialize;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::ApiResponse;
+use crate::repositories::{AuditRepository, AuditedAccess, CareTeamRepository};
+use crate::services::CareTeamSecurityService;
+use crate::AppState;
+
+/// Participant to add to a team
+#[derive(Debug, Deserialize)]
+pub struct ParticipantRequest {
+    pub practitioner_id: Id,
+    pub role: CareTeamRole,
+    /// Defaults to now
+    pub start: Option<DateTime<Utc>>,
+    /// Open-ended when absent
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// New care team
+#[derive(Debug, Deserialize)]
+pub struct CreateCareTeamRequest {
+    pub name: String,
+    #[serde(default)]
+    pub participants: Vec<ParticipantRequest>,
+}
+
+/// Participant added to an existing team
+#[derive(Debug, Deserialize)]
+pub struct AddParticipantRequest {
+    #[serde(flatten)]
+    pub participant: ParticipantRequest,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// End of a practitioner's participation in a role
+#[derive(Debug, Deserialize)]
+pub struct EndParticipationRequest {
+    pub role: CareTeamRole,
+    /// Defaults to now
+    pub end: Option<DateTime<Utc>>,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// Break-the-glass request
+#[derive(Debug, Deserialize)]
+pub struct EmergencyAccessRequest {
+    /// Practitioner asking for access; must be the signed-in practitioner
+    pub practitioner_id: Id,
+    /// Why access is needed, reviewed afterwards
+    pub reason: String,
+}
+
+/// Access check
+#[derive(Debug, Deserialize)]
+pub struct AccessQuery {
+    pub practitioner_id: Id,
+}
+
+/// The caller's session, if the request was authenticated
+fn auth_context(req: &HttpRequest) -> Option<AuthContext> {
+    req.extensions().get::<AuthContext>().cloned()
+}
+
+/// The practitioner signed in to make a change the record attributes to
+/// them, such as finalized codes or captured charges; `action` completes
+/// "Only practitioners can …"
+pub(crate) fn acting_practitioner(context: Option<&AuthContext>, action: &str) -> Result<Id> {
+    let context = context.ok_or_else(|| ApiError::authentication_error(&format!("Sign in to {}", action)))?;
+    context
+        .practitioner_id()
+        .ok_or_else(|| ApiError::authorization_error(&format!("Only practitioners can {}", action)))
+}
+
+/// How an access to patient data is audited
+fn access_action(method: &Method) -> &'static str {
+    if method == Method::GET || method == Method::HEAD {
+        "READ"
+    } else {
+        "WRITE"
+    }
+}
+
+/// Describe purposes of use for an error message
+fn purpose_list(purposes: &[PurposeOfUse]) -> String {
+    purposes.iter().map(|purpose| purpose.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+/// Check the caller's purpose of use against the consent rules for `sensitive`
+fn check_consent(sensitive: &[SensitiveData], purpose: Option<PurposeOfUse>) -> Result<()> {
+    let described = sensitive.iter().map(|data| data.description()).collect::<Vec<_>>().join(" and ");
+    match ConsentRules::default().evaluate(sensitive, purpose) {
+        ConsentDecision::Permit => Ok(()),
+        ConsentDecision::PurposeRequired { allowed } => Err(ApiError::purpose_of_use_required(&format!(
+            "Select a purpose of use to open {}: {}",
+            described,
+            purpose_list(&allowed)
+        ))),
+        ConsentDecision::Deny { allowed } => Err(ApiError::authorization_error(&format!(
+            "Consent rules do not allow opening {} for {}; allowed purposes: {}",
+            described,
+            purpose.map_or("", |purpose| purpose.as_str()),
+            purpose_list(&allowed)
+        ))),
+    }
+}
+
+/// Whether the caller's purpose of use permits seeing `sensitive` data, for
+/// lists that withhold it otherwise
+///
+/// Patients always see their own data. Unauthenticated requests, which have
+/// no purpose of use, see only what the consent rules permit without one.
+pub(crate) fn consent_permits(req: &HttpRequest, sensitive: &[SensitiveData]) -> bool {
+    match auth_context(req) {
+        Some(context) if context.is_patient_session() => true,
+        context => ConsentRules::default()
+            .evaluate(sensitive, context.and_then(|context| context.purpose_of_use))
+            .is_permitted(),
+    }
+}
+
+/// Most confidential data the caller may see; normal for unauthenticated
+/// requests
+pub(crate) fn clearance(req: &HttpRequest) -> Confidentiality {
+    auth_context(req).map_or(Confidentiality::Normal, |context| context.clearance)
+}
+
+/// Organization the caller works for, which new patients belong to
+pub(crate) fn tenant(req: &HttpRequest) -> Option<Id> {
+    auth_context(req).and_then(|context| context.tenant_id)
+}
+
+/// Require the caller to have a basis for reading a patient's data
+///
+/// Portal sessions may only read their own patient. Practitioners need a
+/// treating relationship or an active emergency access grant. Their accesses
+/// are audited under the patient for the accounting of disclosures.
+/// Unauthenticated requests still pass until the auth middleware rejects
+/// them (TODO(nexus-phase2)), but are audited without a user and refused
+/// sensitive data.
+pub(crate) async fn authorize_patient_access(req: &HttpRequest, data: &AppState, patient_id: Id) -> Result<()> {
+    authorize_sensitive_access(req, data, patient_id, &[]).await
+}
+
+/// Require the caller to have a basis for reading a patient's `sensitive`
+/// data, and a purpose of use the consent rules permit
+///
+/// As [`authorize_patient_access`]; a record reached by breaking the glass is
+/// also sensitive. The purpose and the sensitive data are audited with the
+/// access.
+pub(crate) async fn authorize_sensitive_access(
+    req: &HttpRequest,
+    data: &AppState,
+    patient_id: Id,
+    sensitive: &[SensitiveData],
+) -> Result<()> {
+    let Some(context) = auth_context(req) else {
+        check_consent(sensitive, None)?;
+        let audited = AuditedAccess {
+            patient_id,
+            user_id: None,
+            action: access_action(req.method()),
+            path: req.path(),
+            purpose_of_use: None,
+            sensitive,
+        };
+        return AuditRepository::new()
+            .record_patient_access(&data.db_pool, &audited, "unauthenticated")
+            .await;
+    };
+    if context.is_patient_session() {
+        if context.patient_id != Some(patient_id) {
+            return Err(ApiError::authorization_error("Patients can only access their own record"));
+        }
+        return Ok(());
+    }
+    let practitioner_id = context
+        .practitioner_id()
+        .ok_or_else(|| ApiError::authorization_error("Only practitioners can access patient records"))?;
+
+    let access = CareTeamSecurityService::new(data.db_pool.clone())
+        .check_patient_access(practitioner_id, patient_id)
+        .await?;
+    let mut sensitive = sensitive.to_vec();
+    if matches!(access, PatientAccess::EmergencyAccess { .. }) {
+        sensitive.push(SensitiveData::OffPanelPatient);
+    }
+    if access.is_allowed() {
+        check_consent(&sensitive, context.purpose_of_use)?;
+    }
+
+    let audited = AuditedAccess {
+        patient_id,
+        user_id: Some(practitioner_id),
+        action: access_action(req.method()),
+        path: req.path(),
+        purpose_of_use: context.purpose_of_use,
+        sensitive: &sensitive,
+    };
+    match access {
+        PatientAccess::TreatingRelationship => {
+            AuditRepository::new()
+                .record_patient_access(&data.db_pool, &audited, "treating_relationship")
+                .await
+        }
+        PatientAccess::EmergencyAccess { grant_id, .. } => {
+            CareTeamRepository::new()
+                .record_emergency_read(&data.db_pool, grant_id, &audited)
+                .await
+        }
+        PatientAccess::Denied => Err(ApiError::authorization_error(
+            "You are not on this patient's care team; request emergency access to open the record",
+        )),
+    }
+}
+
+async fn find_team(data: &AppState, id: Id) -> Result<CareTeam> {
+    CareTeamRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Care team {} not found", id)))
+}
+
+/// Load a team for a change based on `version`
+async fn team_at_version(data: &AppState, id: Id, version: u64) -> Result<CareTeam> {
+    let team = find_team(data, id).await?;
+    if team.metadata.version != version {
+        return Err(ApiError::conflict("The care team changed since it was loaded; reload it and try again"));
+    }
+    Ok(team)
+}
+
+/// Write a changed team unless it was changed concurrently
+async fn store_change(data: &AppState, team: &CareTeam, previous_version: u64) -> Result<()> {
+    let stored = CareTeamRepository::new()
+        .update(&data.db_pool, team, previous_version)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict("The care team was changed by another request; reload it and try again"));
+    }
+    Ok(())
+}
+
+/// Add a participant starting now unless a start is given
+fn add_participant(team: &mut CareTeam, participant: ParticipantRequest) -> Result<()> {
+    let start = participant.start.unwrap_or_else(Utc::now);
+    team.add_participant(participant.practitioner_id, participant.role, start, participant.end)?;
+    Ok(())
+}
+
+/// Create a care team for a patient
+#[post("/patients/{id}/care-teams")]
+pub async fn create_care_team(
+    path: web::Path<Id>,
+    request: web::Json<CreateCareTeamRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut team = CareTeam::new(path.into_inner(), &request.name);
+    for participant in request.participants {
+        add_participant(&mut team, participant)?;
+    }
+    team.validate()?;
+
+    CareTeamRepository::new().insert(&data.db_pool, &team).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(team)))
+}
+
+/// A patient's care teams, active ones first
+#[get("/patients/{id}/care-teams")]
+pub async fn list_care_teams(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let teams = CareTeamRepository::new()
+        .for_patient(&data.db_pool, path.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(teams)))
+}
+
+/// A care team
+#[get("/care-teams/{id}")]
+pub async fn get_care_team(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let team = find_team(&data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(team)))
+}
+
+/// A care team as a FHIR `CareTeam`
+#[get("/care-teams/{id}/fhir")]
+pub async fn get_care_team_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let team = find_team(&data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &care_team_to_fhir(&team))
+}
+
+/// Add a practitioner to a team
+#[post("/care-teams/{id}/participants")]
+pub async fn add_care_team_participant(
+    path: web::Path<Id>,
+    request: web::Json<AddParticipantRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut team = team_at_version(&data, path.into_inner(), request.version).await?;
+    let previous_version = team.metadata.version;
+
+    add_participant(&mut team, request.participant)?;
+    store_change(&data, &team, previous_version).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(team)))
+}
+
+/// End a practitioner's participation in a role
+#[post("/care-teams/{id}/participants/{practitioner_id}/end")]
+pub async fn end_care_team_participation(
+    path: web::Path<(Id, Id)>,
+    request: web::Json<EndParticipationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (team_id, practitioner_id) = path.into_inner();
+    let mut team = team_at_version(&data, team_id, request.version).await?;
+    let previous_version = team.metadata.version;
+
+    team.end_participation(practitioner_id, request.role, request.end.unwrap_or_else(Utc::now))?;
+    store_change(&data, &team, previous_version).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(team)))
+}
+
+/// Break the glass: grant a practitioner time-limited emergency access
+#[post("/patients/{id}/emergency-access")]
+pub async fn request_emergency_access(
+    path: web::Path<Id>,
+    request: web::Json<EmergencyAccessRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    if let Some(context) = auth_context(&req) {
+        if context.practitioner_id() != Some(request.practitioner_id) {
+            return Err(ApiError::authorization_error(
+                "Emergency access can only be requested for yourself",
+            ));
+        }
+    }
+
+    let grant = CareTeamSecurityService::new(data.db_pool.clone())
+        .break_the_glass(request.practitioner_id, path.into_inner(), &request.reason)
+        .await?;
+    tracing::warn!(
+        grant_id = %grant.id,
+        practitioner_id = %grant.practitioner_id,
+        patient_id = %grant.patient_id,
+        "Emergency access granted"
+    );
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(grant)))
+}
+
+/// How, if at all, a practitioner may access a patient's data
+#[get("/patients/{id}/access")]
+pub async fn check_patient_access(
+    path: web::Path<Id>,
+    query: web::Query<AccessQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let access = CareTeamSecurityService::new(data.db_pool.clone())
+        .check_patient_access(query.practitioner_id, path.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(access)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::scopes::Scopes;
+    use serde_json::json;
+
+    #[test]
+    fn test_access_action() {
+        assert_eq!(access_action(&Method::GET), "READ");
+        assert_eq!(access_action(&Method::HEAD), "READ");
+        assert_eq!(access_action(&Method::POST), "WRITE");
+        assert_eq!(access_action(&Method::DELETE), "WRITE");
+    }
+
+    #[test]
+    fn test_acting_practitioner() {
+        let practitioner_id = uuid::Uuid::new_v4();
+        let mut context = AuthContext {
+            subject: practitioner_id.to_string(),
+            patient_id: None,
+            tenant_id: None,
+            scopes: Scopes::parse("user/*.*"),
+            clearance: Confidentiality::Normal,
+            purpose_of_use: None,
+            roles: Vec::new(),
+        };
+        assert_eq!(acting_practitioner(Some(&context), "code encounters").unwrap(), practitioner_id);
+
+        assert_eq!(acting_practitioner(None, "code encounters").unwrap_err().category(), "authentication");
+
+        context.patient_id = Some(uuid::Uuid::new_v4());
+        context.scopes = Scopes::parse("patient/*.read");
+        assert_eq!(acting_practitioner(Some(&context), "code encounters").unwrap_err().category(), "authorization");
+    }
+
+    #[test]
+    fn test_anonymous_callers() {
+        let req = actix_web::test::TestRequest::get().uri("/api/v1/patients").to_http_request();
+        assert_eq!(clearance(&req), Confidentiality::Normal);
+        assert!(consent_permits(&req, &[]));
+        assert!(!consent_permits(&req, &[SensitiveData::PsychiatricNotes]));
+        assert!(!consent_permits(&req, &[SensitiveData::HivResults]));
+
+        req.extensions_mut().insert(AuthContext {
+            subject: uuid::Uuid::new_v4().to_string(),
+            patient_id: None,
+            tenant_id: None,
+            scopes: Scopes::parse("user/*.*"),
+            clearance: Confidentiality::Restricted,
+            purpose_of_use: Some(PurposeOfUse::Treatment),
+            roles: Vec::new(),
+        });
+        assert_eq!(clearance(&req), Confidentiality::Restricted);
+        assert!(consent_permits(&req, &[SensitiveData::HivResults]));
+    }
+
+    #[test]
+    fn test_check_consent() {
+        assert!(check_consent(&[], None).is_ok());
+        assert!(check_consent(&[SensitiveData::HivResults], Some(PurposeOfUse::Treatment)).is_ok());
+
+        let missing = check_consent(&[SensitiveData::OffPanelPatient], None).unwrap_err();
+        assert_eq!(missing.category(), "purpose_of_use_required");
+        let denied = check_consent(&[SensitiveData::PsychiatricNotes], Some(PurposeOfUse::Payment)).unwrap_err();
+        assert_eq!(denied.category(), "authorization");
+        assert!(denied.to_string().contains("allowed purposes: treatment, emergency"));
+    }
+
+    #[test]
+    fn test_add_participant_request() {
+        let practitioner = uuid::Uuid::new_v4();
+        let request: AddParticipantRequest = serde_json::from_value(json!({
+            "practitioner_id": practitioner,
+            "role": "attending_physician",
+            "version": 3
+        }))
+        .unwrap();
+
+        assert_eq!(request.version, 3);
+        assert_eq!(request.participant.role, CareTeamRole::AttendingPhysician);
+
+        let mut team = CareTeam::new(uuid::Uuid::new_v4(), "Inpatient team");
+        add_participant(&mut team, request.participant).unwrap();
+        assert!(team.is_treating(practitioner, Utc::now()));
+    }
+
+    #[test]
+    fn test_patient_access_serialization() {
+        assert_eq!(
+            serde_json::to_value(PatientAccess::TreatingRelationship).unwrap(),
+            json!({ "basis": "treating_relationship" })
+        );
+        let access = serde_json::to_value(PatientAccess::EmergencyAccess {
+            grant_id: uuid::Uuid::new_v4(),
+            expires_at: Utc::now(),
+        })
+        .unwrap();
+        assert_eq!(access["basis"], "emergency_access");
+        assert!(access.get("grant_id").is_some());
+    }
+}
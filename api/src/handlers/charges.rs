@@ -0,0 +1,253 @@
+//! Charge capture and superbill endpoints
+//!
+//! The clinician signed in captures an encounter's charges with
+//! `PUT /encounters/{id}/charges`: the ICD-10-CM diagnoses addressed and the
+//! CPT lines with their units, modifiers and diagnosis pointers. Every
+//! diagnosis must code a condition recorded for the encounter on the FHIR
+//! server, and every pointer must reference one of those diagnoses; each
+//! capture replaces the previous one. `GET /encounters/{id}/superbill`
+//! prints the captured charges as an HTML page or, with `?format=pdf`, a PDF
+//! document.
+
+use actix_web::{get, put, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::billing::{ClaimPerson, FeeSchedule, PostalAddress, Superbill};
+use emr_core::domain::{ChargeLine, Encounter, EncounterCharges, EncounterStatus};
+use emr_core::services::coding::charge_diagnoses;
+use emr_core::services::EncounterService;
+use emr_core::types::Id;
+use serde::Deserialize;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::calculators::parse_gender;
+use crate::handlers::care_teams::{acting_practitioner, authorize_patient_access};
+use crate::handlers::ApiResponse;
+use crate::models::PortalDemographicsModel;
+use crate::repositories::{CoverageRepository, EncounterChargeRepository, PortalRepository};
+use crate::services::encounter_conditions;
+use crate::AppState;
+
+/// A charge line as the clinician entered it
+#[derive(Debug, Deserialize)]
+pub struct CapturedLine {
+    pub code: String,
+    pub display: Option<String>,
+    /// Units of service; defaults to one
+    pub units: Option<u32>,
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    /// Positions of the diagnoses the service treats among the diagnoses in
+    /// this capture, from 1; defaults to the principal diagnosis
+    #[serde(default)]
+    pub diagnosis_pointers: Vec<u32>,
+}
+
+/// An encounter's charges; the signed-in clinician captures them
+#[derive(Debug, Deserialize)]
+pub struct CaptureChargesRequest {
+    /// ICD-10-CM codes of recorded conditions, the principal one first
+    pub diagnoses: Vec<String>,
+    pub lines: Vec<CapturedLine>,
+}
+
+/// Superbill format
+#[derive(Debug, Deserialize)]
+pub struct SuperbillQuery {
+    /// `html` (the default) or `pdf`
+    pub format: Option<String>,
+}
+
+impl CapturedLine {
+    fn into_line(self) -> ChargeLine {
+        let mut line = ChargeLine::new(&self.code, self.units.unwrap_or(1), self.modifiers, self.diagnosis_pointers);
+        line.display = self.display;
+        line
+    }
+}
+
+/// The patient as printed on the superbill
+fn superbill_patient(demographics: PortalDemographicsModel) -> ClaimPerson {
+    let address = match (demographics.address_line1, demographics.city, demographics.state, demographics.postal_code) {
+        (Some(line1), Some(city), Some(state), Some(postal_code)) => Some(PostalAddress {
+            line1,
+            line2: demographics.address_line2,
+            city,
+            state,
+            postal_code,
+        }),
+        _ => None,
+    };
+
+    ClaimPerson {
+        family_name: demographics.family_name.unwrap_or_default(),
+        given_name: demographics.given_names.into_iter().next(),
+        birth_date: demographics.birth_date,
+        gender: demographics.gender.as_deref().and_then(parse_gender),
+        address,
+    }
+}
+
+async fn find_encounter(data: &AppState, id: Id) -> Result<Encounter> {
+    data.encounters
+        .get_encounter(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Encounter {} not found", id)))
+}
+
+async fn find_charges(data: &AppState, encounter: &Encounter) -> Result<EncounterCharges> {
+    EncounterChargeRepository::new()
+        .for_encounter(&data.db_pool, encounter.metadata.id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("No charges captured for encounter {}", encounter.metadata.id)))
+}
+
+/// The configured fee schedule, if any
+async fn fee_schedule(data: &AppState) -> Result<Option<FeeSchedule>> {
+    let Some(path) = &data.config.billing.fee_schedule_path else {
+        return Ok(None);
+    };
+    let text = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| ApiError::configuration_error(&format!("Cannot read fee schedule {}: {}", path, e)))?;
+    Ok(Some(FeeSchedule::from_csv(&text)?))
+}
+
+/// An encounter's captured charges
+#[get("/encounters/{id}/charges")]
+pub async fn get_encounter_charges(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+
+    let charges = find_charges(&data, &encounter).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(charges)))
+}
+
+/// Capture an encounter's charges
+#[put("/encounters/{id}/charges")]
+pub async fn capture_encounter_charges(
+    path: web::Path<Id>,
+    request: web::Json<CaptureChargesRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let captured_by = acting_practitioner(req.extensions().get::<AuthContext>(), "capture charges")?;
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+    if matches!(encounter.status, EncounterStatus::Cancelled | EncounterStatus::EnteredInError) {
+        return Err(ApiError::bad_request("Charges cannot be captured for a cancelled encounter"));
+    }
+
+    let request = request.into_inner();
+    let conditions = encounter_conditions(data.fhir_client.as_ref(), &encounter).await?;
+    let diagnoses = charge_diagnoses(&request.diagnoses, &conditions)?;
+    let lines = request.lines.into_iter().map(CapturedLine::into_line).collect();
+    let charges = EncounterCharges::new(
+        encounter.metadata.id,
+        encounter.subject,
+        diagnoses,
+        lines,
+        captured_by,
+        Utc::now(),
+    )?;
+    EncounterChargeRepository::new().replace(&data.db_pool, &charges).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(charges)))
+}
+
+/// An encounter's superbill, as HTML or PDF
+#[get("/encounters/{id}/superbill")]
+pub async fn get_superbill(
+    path: web::Path<Id>,
+    query: web::Query<SuperbillQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let pdf = match query.format.as_deref() {
+        None | Some("html") => false,
+        Some("pdf") => true,
+        Some(other) => return Err(ApiError::validation_error(&format!("Unknown superbill format '{}'", other))),
+    };
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+
+    let charges = find_charges(&data, &encounter).await?;
+    let demographics = PortalRepository::new()
+        .demographics(&data.db_pool, encounter.subject)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Patient {} not found", encounter.subject)))?;
+    let fees = fee_schedule(&data).await?;
+    let mut superbill = Superbill::new(
+        &encounter,
+        &charges,
+        superbill_patient(demographics),
+        None,
+        data.config.billing.billing_provider.clone(),
+        fees.as_ref(),
+    );
+    superbill.coverage = CoverageRepository::new()
+        .for_patient(&data.db_pool, encounter.subject)
+        .await?
+        .into_iter()
+        .find(|coverage| coverage.is_in_force(superbill.date_of_service));
+
+    if pdf {
+        Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header((
+                "Content-Disposition",
+                format!("inline; filename=\"superbill-{}.pdf\"", encounter.metadata.id),
+            ))
+            .body(superbill.render_pdf()))
+    } else {
+        Ok(HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(superbill.render_html()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_captured_line() {
+        let request: CaptureChargesRequest = serde_json::from_value(json!({
+            "diagnoses": ["I10"],
+            "lines": [
+                {"code": "99213", "modifiers": ["25"]},
+                {"code": "36415", "units": 2, "diagnosis_pointers": [1]}
+            ]
+        }))
+        .unwrap();
+
+        let lines: Vec<ChargeLine> = request.lines.into_iter().map(CapturedLine::into_line).collect();
+        assert_eq!((lines[0].units, lines[0].modifiers.clone()), (1, vec!["25".to_string()]));
+        assert_eq!((lines[1].units, lines[1].diagnosis_pointers.clone()), (2, vec![1]));
+    }
+
+    #[test]
+    fn test_superbill_patient() {
+        let patient = superbill_patient(PortalDemographicsModel {
+            id: uuid::Uuid::new_v4(),
+            family_name: Some("Smith".to_string()),
+            given_names: vec!["John".to_string(), "Q".to_string()],
+            birth_date: None,
+            gender: Some("male".to_string()),
+            phone: None,
+            email: None,
+            address_line1: Some("1 Main St".to_string()),
+            address_line2: None,
+            city: Some("Springfield".to_string()),
+            state: None,
+            postal_code: Some("62701".to_string()),
+            country: None,
+        });
+        assert_eq!(patient.given_name.as_deref(), Some("John"));
+        assert!(patient.gender.is_some() && patient.address.is_none());
+    }
+}
@@ -0,0 +1,342 @@
+//! Clinical note endpoints
+//!
+//! Authors create a note as a draft and save it as often as they like; each
+//! save sends the `version` it was based on and becomes the next version, so
+//! a save from a stale editor is rejected with 409 instead of overwriting
+//! newer text. Signing makes the note final and stores the author's
+//! attestation signature over its content. After that it only changes
+//! through addenda, each with a reason and its own amendment signature.
+//! `/notes/{id}/signatures` verifies them all. `/notes/{id}/fhir` renders
+//! the note as a FHIR `Composition`.
+//!
+//! Psychiatric notes need a purpose of use the consent rules permit: reading
+//! one is refused without it, and patient note lists leave them out.
+
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{ClinicalNote, NoteSection, NoteType, CLINICAL_NOTE_SIGNATURE_TARGET};
+use emr_core::services::consent::SensitiveData;
+use emr_core::signing::{Signature, SignatureKind};
+use emr_core::types::Id;
+use emr_fhir::clinical_note_to_fhir;
+use serde::Deserialize;
+use serde_json::json;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::care_teams::{authorize_patient_access, authorize_sensitive_access, consent_permits};
+use crate::handlers::signatures::{signing_key, verify_signatures};
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::ClinicalNoteRepository;
+use crate::AppState;
+
+/// New draft note
+#[derive(Debug, Deserialize)]
+pub struct CreateNoteRequest {
+    pub patient_id: Id,
+    pub encounter_id: Option<Id>,
+    /// Practitioner writing the note
+    pub author_id: Id,
+    pub note_type: NoteType,
+    pub title: String,
+    #[serde(default)]
+    pub sections: Vec<NoteSection>,
+}
+
+/// Draft save
+#[derive(Debug, Deserialize)]
+pub struct SaveDraftRequest {
+    pub author_id: Id,
+    /// Version the edits are based on
+    pub version: u64,
+    pub title: String,
+    pub sections: Vec<NoteSection>,
+}
+
+/// Signature by the note's author
+#[derive(Debug, Deserialize)]
+pub struct SignNoteRequest {
+    pub author_id: Id,
+    /// Version the author reviewed before signing
+    pub version: u64,
+}
+
+/// Addendum to a signed note
+#[derive(Debug, Deserialize)]
+pub struct AddendumRequest {
+    pub author_id: Id,
+    pub text: String,
+    /// Why the signed note needs amending
+    pub reason: String,
+}
+
+/// Who is checking a note's signatures
+#[derive(Debug, Deserialize)]
+pub struct VerifyQuery {
+    pub practitioner_id: Option<Id>,
+}
+
+async fn find_note(data: &AppState, id: Id) -> Result<ClinicalNote> {
+    ClinicalNoteRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Clinical note {} not found", id)))
+}
+
+/// Load a note to read, checking the purpose of use for psychiatric notes
+async fn read_note(req: &HttpRequest, data: &AppState, id: Id) -> Result<ClinicalNote> {
+    let note = find_note(data, id).await?;
+    if note.note_type == NoteType::PsychiatricNote {
+        authorize_sensitive_access(req, data, note.patient_id, &[SensitiveData::PsychiatricNotes]).await?;
+    }
+    Ok(note)
+}
+
+/// Write a changed note, with the signature made for it, unless it was changed concurrently
+async fn store_change(
+    data: &AppState,
+    note: &ClinicalNote,
+    previous_version: u64,
+    signature: Option<Signature>,
+) -> Result<()> {
+    let stored = ClinicalNoteRepository::new()
+        .update(&data.db_pool, note, previous_version, signature)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict("The note was changed by another request; reload it and try again"));
+    }
+    Ok(())
+}
+
+/// Start a draft note
+#[post("/notes")]
+pub async fn create_note(
+    request: web::Json<CreateNoteRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut note = ClinicalNote::new(request.patient_id, request.author_id, request.note_type, &request.title);
+    note.encounter_id = request.encounter_id;
+    note.sections = request.sections;
+    note.validate()?;
+
+    ClinicalNoteRepository::new().insert(&data.db_pool, &note).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(note)))
+}
+
+/// A note with its addenda
+#[get("/notes/{id}")]
+pub async fn get_note(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let note = read_note(&req, &data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(note)))
+}
+
+/// A patient's notes, newest first
+#[get("/patients/{id}/notes")]
+pub async fn list_patient_notes(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let (page, per_page) = query.normalize();
+    let psychiatric = consent_permits(&req, &[SensitiveData::PsychiatricNotes]);
+
+    let notes = ClinicalNoteRepository::new()
+        .for_patient(&data.db_pool, patient_id, psychiatric, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        notes,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Save a new version of a draft
+#[put("/notes/{id}")]
+pub async fn save_draft(
+    path: web::Path<Id>,
+    request: web::Json<SaveDraftRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut note = find_note(&data, path.into_inner()).await?;
+    let previous_version = note.metadata.version;
+
+    note.save_draft(request.author_id, request.version, &request.title, request.sections)?;
+    note.validate()?;
+    store_change(&data, &note, previous_version, None).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(note)))
+}
+
+/// Saved versions of a draft, oldest first
+#[get("/notes/{id}/versions")]
+pub async fn list_note_versions(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let note = read_note(&req, &data, path.into_inner()).await?;
+    let versions = ClinicalNoteRepository::new()
+        .versions(&data.db_pool, note.metadata.id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(versions)))
+}
+
+/// Sign a draft, attesting its content
+#[post("/notes/{id}/sign")]
+pub async fn sign_note(
+    path: web::Path<Id>,
+    request: web::Json<SignNoteRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut note = find_note(&data, path.into_inner()).await?;
+    let previous_version = note.metadata.version;
+    if request.version != previous_version {
+        return Err(ApiError::conflict("The note changed after it was reviewed; reload it before signing"));
+    }
+
+    let signature = signing_key(&data)?.attest(
+        CLINICAL_NOTE_SIGNATURE_TARGET,
+        note.metadata.id,
+        &note.attested_content(),
+        request.author_id,
+        Utc::now(),
+    );
+    note.sign(request.author_id, signature.signed_at)?;
+    store_change(&data, &note, previous_version, Some(signature.clone())).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(note, json!({ "signature": signature }))))
+}
+
+/// Add an addendum to a signed note, signed as an amendment
+#[post("/notes/{id}/addenda")]
+pub async fn add_addendum(
+    path: web::Path<Id>,
+    request: web::Json<AddendumRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut note = find_note(&data, path.into_inner()).await?;
+    let previous_version = note.metadata.version;
+
+    let addendum = note.add_addendum(request.author_id, &request.text, &request.reason)?.clone();
+    let signature = signing_key(&data)?.amend(
+        CLINICAL_NOTE_SIGNATURE_TARGET,
+        note.metadata.id,
+        &addendum.signed_content(),
+        request.author_id,
+        &addendum.reason,
+        addendum.created_at,
+    )?;
+    store_change(&data, &note, previous_version, Some(signature.clone())).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::with_meta(note, json!({ "signature": signature }))))
+}
+
+/// A note's signatures, each verified against the note as stored now
+///
+/// The attestation covers the note body and each amendment one addendum.
+#[get("/notes/{id}/signatures")]
+pub async fn verify_note_signatures(
+    path: web::Path<Id>,
+    query: web::Query<VerifyQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let note = read_note(&req, &data, path.into_inner()).await?;
+    let attested = note.attested_content();
+    let addenda: Vec<Vec<u8>> = note.addenda.iter().map(|addendum| addendum.signed_content()).collect();
+
+    let signatures = verify_signatures(
+        &data,
+        CLINICAL_NOTE_SIGNATURE_TARGET,
+        note.metadata.id,
+        query.practitioner_id,
+        |signature| signed_note_content(signature, &attested, &addenda),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(signatures)))
+}
+
+/// What a note signature should cover: the body, or the addendum with the signed hash
+fn signed_note_content(signature: &Signature, attested: &[u8], addenda: &[Vec<u8>]) -> Option<Vec<u8>> {
+    match signature.kind {
+        SignatureKind::Attestation => Some(attested.to_vec()),
+        SignatureKind::Amendment => addenda
+            .iter()
+            .find(|addendum| emr_core::signing::content_hash(addendum) == signature.content_hash)
+            .cloned(),
+    }
+}
+
+/// A note as a FHIR `Composition`
+#[get("/notes/{id}/fhir")]
+pub async fn get_note_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let note = read_note(&req, &data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &clinical_note_to_fhir(&note))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_request_defaults() {
+        let request: CreateNoteRequest = serde_json::from_value(json!({
+            "patient_id": uuid::Uuid::new_v4(),
+            "author_id": uuid::Uuid::new_v4(),
+            "note_type": "history_and_physical",
+            "title": "Admission H&P"
+        }))
+        .unwrap();
+
+        assert_eq!(request.note_type, NoteType::HistoryAndPhysical);
+        assert!(request.encounter_id.is_none());
+        assert!(request.sections.is_empty());
+    }
+
+    #[test]
+    fn test_signed_note_content() {
+        let key = emr_core::signing::SigningKey::new(&[3; 32]).unwrap();
+        let (attested, addenda) = (b"body".to_vec(), vec![b"first".to_vec(), b"second".to_vec()]);
+        let note_id = uuid::Uuid::new_v4();
+
+        let attestation = key.attest("ClinicalNote", note_id, &attested, note_id, Utc::now());
+        assert_eq!(signed_note_content(&attestation, &attested, &addenda), Some(attested.clone()));
+
+        let amendment = key
+            .amend("ClinicalNote", note_id, b"second", note_id, "Correction", Utc::now())
+            .unwrap();
+        assert_eq!(signed_note_content(&amendment, &attested, &addenda), Some(b"second".to_vec()));
+        assert_eq!(signed_note_content(&amendment, &attested, &addenda[..1]), None);
+    }
+
+    #[test]
+    fn test_save_request_requires_version() {
+        let missing_version = serde_json::from_value::<SaveDraftRequest>(json!({
+            "author_id": uuid::Uuid::new_v4(),
+            "title": "Follow-up",
+            "sections": []
+        }));
+        assert!(missing_version.is_err());
+    }
+}
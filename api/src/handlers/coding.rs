@@ -0,0 +1,180 @@
+//! Encounter coding endpoints
+//!
+//! `GET /encounters/{id}/coding/suggestions` suggests ICD-10-CM diagnoses
+//! from the conditions recorded on the FHIR server and the E/M visit code
+//! from the encounter's class and length. The signed-in coder reviews the
+//! suggestions and finalizes the encounter's codes with
+//! `PUT /encounters/{id}/coding`, accepting, overriding (with a reason) or
+//! adding codes; each review replaces the previous coding. `GET /encounters/{id}/coding` is the finalized coding
+//! the claims export bills. Only finished encounters are coded. Diagnoses
+//! reviewed without a display get the ICD-10-CM one from the FHIR server's
+//! code system, which warm-up caches.
+
+use actix_web::{get, put, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::{CodeKind, Encounter, EncounterCode, EncounterCoding, EncounterStatus, ICD10CM_SYSTEM};
+use emr_core::services::coding::{
+    encounter_minutes, is_established_patient, suggest_diagnoses, suggest_evaluation_and_management,
+    CodingSuggestions,
+};
+use emr_core::services::EncounterService;
+use emr_core::types::Id;
+use serde::Deserialize;
+use serde_json::json;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::care_teams::{acting_practitioner, authorize_patient_access};
+use crate::handlers::ApiResponse;
+use crate::repositories::EncounterCodeRepository;
+use crate::services::encounter_conditions;
+use crate::AppState;
+
+/// A code as the coder finalized it
+#[derive(Debug, Deserialize)]
+pub struct ReviewedCode {
+    pub kind: CodeKind,
+    pub code: String,
+    pub display: Option<String>,
+    /// The suggestion this code was reviewed from; leave out for added codes
+    pub suggested_code: Option<String>,
+    /// Units of service (procedures)
+    pub units: Option<u32>,
+    /// CPT modifiers (procedures)
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    /// Positions of the diagnoses the procedure treats among the diagnoses
+    /// in this review, from 1; defaults to the principal diagnosis
+    #[serde(default)]
+    pub diagnosis_pointers: Vec<u32>,
+    /// Required when the code replaces a different suggested code
+    pub override_reason: Option<String>,
+}
+
+/// A coder's review of an encounter; the signed-in coder finalizes it
+#[derive(Debug, Deserialize)]
+pub struct CodingReviewRequest {
+    /// Diagnoses in sequence, the principal one first, and procedures
+    pub codes: Vec<ReviewedCode>,
+}
+
+impl ReviewedCode {
+    fn into_code(self) -> EncounterCode {
+        let mut code = EncounterCode::new(self.kind, &self.code, self.suggested_code.as_deref());
+        code.display = self.display;
+        code.units = self.units.unwrap_or(1);
+        code.modifiers = self.modifiers;
+        code.diagnosis_pointers = self.diagnosis_pointers;
+        code.override_reason = self.override_reason;
+        code
+    }
+}
+
+async fn find_encounter(data: &AppState, id: Id) -> Result<Encounter> {
+    data.encounters
+        .get_encounter(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Encounter {} not found", id)))
+}
+
+/// Coding suggestions for an encounter
+#[get("/encounters/{id}/coding/suggestions")]
+pub async fn get_coding_suggestions(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+
+    let conditions = encounter_conditions(data.fhir_client.as_ref(), &encounter).await?;
+    let (diagnoses, uncoded_conditions) = suggest_diagnoses(&conditions);
+    let history = data.encounters.get_patient_encounters(encounter.subject).await?;
+    let established = is_established_patient(&encounter, &history);
+    let suggestions = CodingSuggestions {
+        diagnoses,
+        procedures: suggest_evaluation_and_management(&encounter, established),
+        uncoded_conditions,
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        suggestions,
+        json!({
+            "established_patient": established,
+            "minutes": encounter_minutes(&encounter),
+        }),
+    )))
+}
+
+/// An encounter's finalized codes
+#[get("/encounters/{id}/coding")]
+pub async fn get_encounter_coding(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+
+    let coding = EncounterCodeRepository::new()
+        .for_encounter(&data.db_pool, encounter.metadata.id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Encounter {} has not been coded", encounter.metadata.id)))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(coding)))
+}
+
+/// Finalize an encounter's codes
+#[put("/encounters/{id}/coding")]
+pub async fn review_encounter_coding(
+    path: web::Path<Id>,
+    request: web::Json<CodingReviewRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let coder_id = acting_practitioner(req.extensions().get::<AuthContext>(), "code encounters")?;
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+    if encounter.status != EncounterStatus::Finished {
+        return Err(ApiError::bad_request("Only finished encounters can be coded"));
+    }
+
+    let request = request.into_inner();
+    let mut codes: Vec<EncounterCode> = request.codes.into_iter().map(ReviewedCode::into_code).collect();
+    for code in codes.iter_mut().filter(|code| code.kind == CodeKind::Diagnosis && code.display.is_none()) {
+        // The display is a convenience; a terminology outage does not block coding
+        code.display = data.terminology.display(ICD10CM_SYSTEM, &code.code).await.unwrap_or_else(|e| {
+            tracing::warn!(code = %code.code, error = %e, "Cannot look up the diagnosis display");
+            None
+        });
+    }
+    let coding = EncounterCoding::new(
+        encounter.metadata.id,
+        encounter.subject,
+        codes,
+        coder_id,
+        Utc::now(),
+    )?;
+    EncounterCodeRepository::new().replace(&data.db_pool, &coding).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(coding)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::domain::CodeOrigin;
+
+    #[test]
+    fn test_reviewed_code() {
+        let request: CodingReviewRequest = serde_json::from_value(json!({
+            "codes": [
+                {"kind": "diagnosis", "code": "i10", "suggested_code": "I10"},
+                {"kind": "procedure", "code": "99214", "suggested_code": "99213", "override_reason": "Time in note"}
+            ]
+        }))
+        .unwrap();
+
+        let codes: Vec<EncounterCode> = request.codes.into_iter().map(ReviewedCode::into_code).collect();
+        assert_eq!((codes[0].code.as_str(), codes[0].origin), ("I10", CodeOrigin::Accepted));
+        assert_eq!((codes[1].units, codes[1].origin), (1, CodeOrigin::Overridden));
+    }
+}
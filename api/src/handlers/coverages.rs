@@ -0,0 +1,108 @@
+//! Patient insurance coverage
+//!
+//! `POST /patients/{id}/coverages` records a health plan from the patient's
+//! insurance card; `GET /patients/{id}/coverages` lists them, primary first.
+//! The claims export bills each coded encounter to the primary coverage in
+//! force on the date of service.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::NaiveDate;
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{Coverage, PlanType, Subscriber, SubscriberRelationship};
+use emr_core::types::Id;
+use serde::Deserialize;
+use crate::error::Result;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::ApiResponse;
+use crate::repositories::CoverageRepository;
+use crate::AppState;
+
+/// A health plan as read from the insurance card
+#[derive(Debug, Deserialize)]
+pub struct CreateCoverageRequest {
+    pub plan_type: PlanType,
+    pub payer_name: String,
+    /// Payer id assigned by the clearinghouse
+    pub payer_id: String,
+    pub member_id: String,
+    pub group_number: Option<String>,
+    pub plan_name: Option<String>,
+    /// Order in which plans pay; defaults to 1 (primary)
+    pub priority: Option<u32>,
+    /// The patient's relationship to the subscriber; defaults to `self`
+    pub relationship: Option<SubscriberRelationship>,
+    /// The subscriber, when the patient is not the subscriber
+    pub subscriber: Option<Subscriber>,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+impl CreateCoverageRequest {
+    fn into_coverage(self, patient_id: Id) -> Coverage {
+        let mut coverage = Coverage::new(patient_id, self.plan_type, &self.payer_name, &self.payer_id, &self.member_id);
+        coverage.group_number = self.group_number;
+        coverage.plan_name = self.plan_name;
+        coverage.priority = self.priority.unwrap_or(1);
+        coverage.relationship = self.relationship.unwrap_or(SubscriberRelationship::Own);
+        coverage.subscriber = self.subscriber;
+        coverage.start = self.start;
+        coverage.end = self.end;
+        coverage
+    }
+}
+
+/// Record a patient's health plan
+#[post("/patients/{id}/coverages")]
+pub async fn create_coverage(
+    path: web::Path<Id>,
+    request: web::Json<CreateCoverageRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let coverage = request.into_inner().into_coverage(patient_id);
+    coverage.validate()?;
+    CoverageRepository::new().insert(&data.db_pool, &coverage).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(coverage)))
+}
+
+/// A patient's health plans, primary first
+#[get("/patients/{id}/coverages")]
+pub async fn get_patient_coverages(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let coverages = CoverageRepository::new().for_patient(&data.db_pool, patient_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(coverages)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_into_coverage() {
+        let request: CreateCoverageRequest = serde_json::from_value(json!({
+            "plan_type": "commercial",
+            "payer_name": " Acme Health ",
+            "payer_id": "60054",
+            "member_id": "W123456789",
+            "relationship": "child",
+            "subscriber": {"family_name": "Doe", "given_name": "Jane", "birth_date": "1985-03-02", "gender": "Female"}
+        }))
+        .unwrap();
+
+        let coverage = request.into_coverage(uuid::Uuid::new_v4());
+        assert_eq!((coverage.payer_name.as_str(), coverage.priority), ("Acme Health", 1));
+        assert_eq!(coverage.relationship, SubscriberRelationship::Child);
+        assert!(coverage.validate().is_ok());
+    }
+}
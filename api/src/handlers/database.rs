@@ -0,0 +1,66 @@
+//! Database pool instrumentation endpoints
+//!
+//! Publishes connection pool statistics for Prometheus, beside the HTTPS
+//! listener's connection metrics (see `server`), and lets operators resize
+//! the pool under load without a restart.
+
+use actix_web::{get, put, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use crate::database;
+use crate::error::Result;
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Pool resize request
+#[derive(Debug, Deserialize)]
+pub struct ResizePoolRequest {
+    /// New maximum number of connections
+    pub max_size: usize,
+}
+
+/// Pool and connection metrics in Prometheus text format
+#[get("/metrics")]
+pub async fn metrics(
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let stats = data.pool_monitor.stats(&data.db_pool);
+    let connections = data.connection_monitor.stats();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(stats.to_prometheus() + &connections.to_prometheus()))
+}
+
+/// Current pool size, utilization and acquisition counters
+#[get("/admin/database/pool")]
+pub async fn get_pool_stats(
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ApiResponse::new(data.pool_monitor.stats(&data.db_pool))))
+}
+
+/// Change the maximum pool size until the next restart
+#[put("/admin/database/pool")]
+pub async fn resize_pool(
+    request: web::Json<ResizePoolRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    database::resize_pool(&data.db_pool, request.max_size, &data.config.database)?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(data.pool_monitor.stats(&data.db_pool))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_request_deserialization() {
+        let request: ResizePoolRequest = serde_json::from_str(r#"{"max_size": 48}"#).unwrap();
+        assert_eq!(request.max_size, 48);
+        assert!(serde_json::from_str::<ResizePoolRequest>(r#"{"max_size": -1}"#).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Accounting of disclosures
+//!
+//! Patients may ask who accessed their record, what was accessed and why.
+//! Every access that [`authorize_patient_access`] allows is audited under the
+//! patient, and `GET /patients/{id}/disclosures` lists them for a date range.
+//! To hand the report to the patient, `POST /patients/{id}/disclosures/exports`
+//! submits an AccessLog audit report job, which renders it as CSV or PDF
+//! into `uploads.report_dir`; `GET /patients/{id}/disclosures/exports/{job_id}`
+//! downloads it once the job has finished.
+//!
+//! [`authorize_patient_access`]: crate::handlers::care_teams::authorize_patient_access
+
+use actix_web::{get, post, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use emr_core::domain::ReportFormat;
+use emr_core::types::Id;
+use emr_jobs::types::{AuditReportJob, AuditReportType, DateRange, JobSubmission, JobType, OutputFormat};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::PathBuf;
+use crate::auth::AuthContext;
+use crate::config::UploadConfig;
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::repositories::AuditRepository;
+use crate::AppState;
+
+/// Longest period a report covers; HIPAA accounts for disclosures over the
+/// six years before a request
+const MAX_ACCOUNTING_DAYS: i64 = 6 * 366;
+
+/// Period of a disclosure report
+#[derive(Debug, Deserialize)]
+pub struct DisclosureRange {
+    pub start: DateTime<Utc>,
+    /// Exclusive
+    pub end: DateTime<Utc>,
+}
+
+/// Disclosure report to render for the patient
+#[derive(Debug, Deserialize)]
+pub struct DisclosureExportRequest {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub format: ReportFormat,
+}
+
+/// Reject reports for a caller who is not a practitioner; unauthenticated
+/// requests still pass until the auth middleware rejects them
+/// (TODO(nexus-phase2))
+fn check_practitioner(context: Option<&AuthContext>) -> Result<()> {
+    match context {
+        Some(context) if context.practitioner_id().is_none() => Err(ApiError::authorization_error(
+            "Only practitioners can report on disclosures",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check that a report covers a valid period
+fn check_range(start: DateTime<Utc>, end: DateTime<Utc>) -> Result<()> {
+    if start >= end {
+        return Err(ApiError::validation_error("Disclosure reports must end after they start"));
+    }
+    if end - start > Duration::days(MAX_ACCOUNTING_DAYS) {
+        return Err(ApiError::validation_error("Disclosure reports cover at most six years"));
+    }
+    Ok(())
+}
+
+/// AccessLog audit report job for one patient
+fn disclosure_job(job_id: Id, patient_id: Id, request: &DisclosureExportRequest) -> JobSubmission {
+    JobSubmission {
+        job_id: Some(job_id),
+        idempotency_key: None,
+        job: JobType::AuditReport(AuditReportJob {
+            report_type: AuditReportType::AccessLog,
+            date_range: DateRange {
+                start: request.start,
+                end: request.end,
+            },
+            patient_ids: Some(vec![patient_id]),
+            practitioner_ids: None,
+            output_format: match request.format {
+                ReportFormat::Csv => OutputFormat::Csv,
+                ReportFormat::Pdf => OutputFormat::Pdf,
+            },
+        }),
+    }
+}
+
+/// Where an export job stores its file (`emr_jobs::disclosures::export_path`)
+fn export_path(config: &UploadConfig, patient_id: Id, job_id: Id, format: ReportFormat) -> PathBuf {
+    PathBuf::from(&config.report_dir)
+        .join("disclosures")
+        .join(patient_id.to_string())
+        .join(format!("{}.{}", job_id, format.as_str()))
+}
+
+/// Accesses to a patient's data over a period, oldest first
+#[get("/patients/{id}/disclosures")]
+pub async fn list_disclosures(
+    path: web::Path<Id>,
+    range: web::Query<DisclosureRange>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    check_practitioner(req.extensions().get::<AuthContext>())?;
+    let patient_id = path.into_inner();
+    check_range(range.start, range.end)?;
+    let (page, per_page) = query.normalize();
+
+    let records = AuditRepository::new()
+        .disclosures(
+            &data.db_pool,
+            patient_id,
+            range.start,
+            range.end,
+            i64::from(query.limit()),
+            i64::from(query.offset()),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        records,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Render a patient's disclosure report as CSV or PDF in the background
+#[post("/patients/{id}/disclosures/exports")]
+pub async fn export_disclosures(
+    path: web::Path<Id>,
+    request: web::Json<DisclosureExportRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    check_practitioner(req.extensions().get::<AuthContext>())?;
+    let patient_id = path.into_inner();
+    let request = request.into_inner();
+    check_range(request.start, request.end)?;
+
+    let job_id = uuid::Uuid::new_v4();
+    submit_job(&data, &disclosure_job(job_id, patient_id, &request)).await?;
+    tracing::info!(job_id = %job_id, %patient_id, "Disclosure report job submitted");
+
+    Ok(HttpResponse::Accepted().json(ApiResponse::new(json!({
+        "job_id": job_id,
+        "download": format!("/api/patients/{}/disclosures/exports/{}", patient_id, job_id),
+    }))))
+}
+
+/// Download a rendered disclosure report
+#[get("/patients/{id}/disclosures/exports/{job_id}")]
+pub async fn download_disclosures(
+    path: web::Path<(Id, Id)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    check_practitioner(req.extensions().get::<AuthContext>())?;
+    let (patient_id, job_id) = path.into_inner();
+
+    for format in [ReportFormat::Pdf, ReportFormat::Csv] {
+        let content = match tokio::fs::read(export_path(&data.config.uploads, patient_id, job_id, format)).await {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+        return Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"disclosures-{}.{}\"", patient_id, format.as_str()),
+            ))
+            .body(content));
+    }
+    Err(ApiError::not_found(&format!(
+        "Disclosure report {} is not ready; follow /api/jobs/{}/events",
+        job_id, job_id
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_check_range() {
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        assert!(check_range(start, start + Duration::days(365)).is_ok());
+        assert!(check_range(start, start).is_err());
+        assert!(check_range(start, start + Duration::days(7 * 365)).is_err());
+    }
+
+    #[test]
+    fn test_disclosure_job() {
+        let (job_id, patient_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let request = DisclosureExportRequest {
+            start: Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2026, 7, 1, 0, 0, 0).unwrap(),
+            format: ReportFormat::Pdf,
+        };
+
+        let job = serde_json::to_value(disclosure_job(job_id, patient_id, &request)).unwrap();
+        assert_eq!(job["type"], "AuditReport");
+        assert_eq!(job["report_type"], "AccessLog");
+        assert_eq!(job["patient_ids"], json!([patient_id]));
+        assert_eq!(job["output_format"], "Pdf");
+        assert_eq!(job["date_range"]["start"], "2026-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_export_path() {
+        let config = UploadConfig::default();
+        let (patient_id, job_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let path = export_path(&config, patient_id, job_id, ReportFormat::Csv);
+        assert_eq!(
+            path,
+            PathBuf::from(&config.report_dir)
+                .join("disclosures")
+                .join(patient_id.to_string())
+                .join(format!("{}.csv", job_id))
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Patient document upload and download
+//!
+//! Documents are accepted as multipart uploads, checked against the
+//! configured content-type allowlist and size limit, and written to
+//! `uploads.document_dir`. The new `DocumentReference` is quarantined
+//! (`scan_status` `pending`) until the malware scanner passes it: clean
+//! documents are released, infected ones are deleted, marked
+//! `entered-in-error`, audited, and the upload rejected. When the scanner
+//! cannot be reached the document stays quarantined and the upload is
+//! accepted for a later `POST /admin/documents/rescan`. Content is only
+//! served for clean documents.
+//!
+//! Each clean document is handed to the jobs worker as a DocumentOcr job,
+//! which stores its text so `GET /patients/{id}/documents?q=` finds
+//! documents by what they say as well as by title.
+//!
+//! Documents carry a confidentiality label; those labelled above the
+//! caller's clearance are left out of lists and read as not found.
+
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::Confidentiality;
+use emr_core::services::malware::{ScanStatus, ScanVerdict};
+use emr_core::types::Id;
+use emr_jobs::types::{DocumentOcrJob, JobSubmission, JobType};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::PathBuf;
+use crate::auth::AuthContext;
+use crate::config::UploadConfig;
+use crate::error::{ApiError, Result};
+use crate::handlers::care_teams::{authorize_patient_access, clearance};
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::models::DocumentReferenceModel;
+use crate::repositories::DocumentRepository;
+use crate::services::malware_scanner;
+use crate::AppState;
+
+/// Multipart field carrying the document
+const FILE_FIELD: &str = "file";
+
+/// Quarantined documents rescanned per request
+const RESCAN_BATCH_SIZE: i64 = 100;
+
+/// Patient document list; `q` searches titles and document text
+#[derive(Debug, Deserialize)]
+pub struct DocumentListQuery {
+    pub q: Option<String>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Outcome of a rescan of quarantined documents
+#[derive(Debug, Default, Serialize)]
+pub struct RescanSummary {
+    pub clean: usize,
+    pub infected: usize,
+    /// Scans that failed; those documents stay quarantined
+    pub failed: usize,
+    /// Quarantined documents whose content is no longer stored
+    pub missing: usize,
+}
+
+/// Uploaded document before it is stored
+#[derive(Debug, Default)]
+struct DocumentUpload {
+    file: Option<(Vec<u8>, String)>,
+    title: Option<String>,
+    type_code: Option<String>,
+    type_display: Option<String>,
+    encounter_id: Option<Id>,
+    confidentiality: Confidentiality,
+}
+
+impl DocumentUpload {
+    /// The document's record, quarantined; its content lives at `document_path`
+    fn into_document(self, patient_id: Id, config: &UploadConfig) -> Result<(DocumentReferenceModel, Vec<u8>)> {
+        let (bytes, content_type) =
+            self.file.ok_or_else(|| ApiError::validation_error("Multipart body must include a file field"))?;
+        if !config.allowed_document_types.iter().any(|allowed| allowed == &content_type) {
+            return Err(ApiError::validation_error(&format!(
+                "Unsupported document content type: {}",
+                content_type
+            )));
+        }
+        if bytes.is_empty() {
+            return Err(ApiError::validation_error("Document is empty"));
+        }
+
+        let id = uuid::Uuid::new_v4();
+        let document = DocumentReferenceModel {
+            id,
+            patient_id,
+            encounter_id: self.encounter_id,
+            status: "current".to_string(),
+            type_code: self.type_code,
+            type_display: self.type_display,
+            title: self.title,
+            content_type,
+            url: format!("/api/documents/{}/content", id),
+            size_bytes: Some(bytes.len() as i64),
+            scan_status: ScanStatus::Pending.as_str().to_string(),
+            scanned_at: None,
+            confidentiality: self.confidentiality.as_str().to_string(),
+            created_at: Utc::now(),
+        };
+        Ok((document, bytes))
+    }
+}
+
+/// A document's confidentiality label; unknown labels are treated as the
+/// most confidential
+fn document_label(document: &DocumentReferenceModel) -> Confidentiality {
+    Confidentiality::parse(&document.confidentiality).unwrap_or(Confidentiality::VeryRestricted)
+}
+
+/// Where a document's content is stored
+fn document_path(config: &UploadConfig, document_id: Id) -> PathBuf {
+    PathBuf::from(&config.document_dir).join(document_id.to_string())
+}
+
+/// DocumentOcr job extracting a clean document's text
+fn ocr_job(document_id: Id) -> JobSubmission {
+    JobSubmission {
+        job_id: None,
+        idempotency_key: Some(format!("document-ocr:{}", document_id)),
+        job: JobType::DocumentOcr(DocumentOcrJob { document_id }),
+    }
+}
+
+/// The practitioner uploading, for the audit trail
+fn uploader(req: &HttpRequest) -> Option<Id> {
+    req.extensions().get::<AuthContext>().and_then(AuthContext::practitioner_id)
+}
+
+/// Scan a quarantined document and record the result; infected content is
+/// deleted and clean documents are sent for text extraction. `None` when the
+/// scan failed and the document stays quarantined.
+async fn scan_document(
+    data: &AppState,
+    document: &mut DocumentReferenceModel,
+    content: &[u8],
+    uploaded_by: Option<Id>,
+) -> Result<Option<ScanVerdict>> {
+    let scanner = malware_scanner(&data.config.scanning)?;
+    let verdict = match scanner.scan(content).await {
+        Ok(verdict) => verdict,
+        Err(error) => {
+            tracing::warn!(document_id = %document.id, %error, "Malware scan failed; document stays quarantined");
+            return Ok(None);
+        }
+    };
+
+    DocumentRepository::new()
+        .record_scan(&data.db_pool, document.id, &verdict, scanner.name(), uploaded_by)
+        .await?;
+    document.scan_status = ScanStatus::from(&verdict).as_str().to_string();
+    document.scanned_at = Some(Utc::now());
+    match &verdict {
+        ScanVerdict::Infected { signature } => {
+            tracing::warn!(
+                document_id = %document.id,
+                patient_id = %document.patient_id,
+                %signature,
+                "Malware detected"
+            );
+            document.status = "entered-in-error".to_string();
+            tokio::fs::remove_file(document_path(&data.config.uploads, document.id)).await?;
+        }
+        ScanVerdict::Clean => {
+            // The document is available; it is only missing from content search
+            if let Err(error) = submit_job(data, &ocr_job(document.id)).await {
+                tracing::warn!(document_id = %document.id, %error, "Failed to submit document OCR");
+            }
+        }
+    }
+    Ok(Some(verdict))
+}
+
+/// Upload a patient document
+///
+/// Expects a multipart body with a `file` field and optional `title`,
+/// `type_code`, `type_display`, `encounter_id` and `confidentiality` text
+/// fields.
+#[post("/patients/{id}/documents")]
+pub async fn upload_document(
+    path: web::Path<Id>,
+    mut payload: Multipart,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let config = &data.config.uploads;
+
+    let mut upload = DocumentUpload::default();
+    while let Some(mut field) = payload
+        .try_next()
+        .await
+        .map_err(|e| ApiError::validation_error(&format!("Invalid multipart body: {}", e)))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let content_type = field.content_type().map(|mime| mime.essence_str().to_string());
+
+        // Read in chunks so oversized uploads are rejected without buffering
+        // the whole body
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field
+            .try_next()
+            .await
+            .map_err(|e| ApiError::validation_error(&format!("Invalid multipart body: {}", e)))?
+        {
+            if bytes.len() + chunk.len() > config.max_document_bytes {
+                return Err(ApiError::payload_too_large(&format!(
+                    "Document exceeds the {} byte limit",
+                    config.max_document_bytes
+                )));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let text = || Some(String::from_utf8_lossy(&bytes).trim().to_string()).filter(|text| !text.is_empty());
+        match name.as_str() {
+            FILE_FIELD => {
+                let content_type = content_type
+                    .ok_or_else(|| ApiError::validation_error("File field requires a content type"))?;
+                upload.file = Some((bytes, content_type));
+            }
+            "title" => upload.title = text(),
+            "type_code" => upload.type_code = text(),
+            "type_display" => upload.type_display = text(),
+            "encounter_id" => {
+                upload.encounter_id = text()
+                    .map(|id| id.parse())
+                    .transpose()
+                    .map_err(|_| ApiError::validation_error("encounter_id must be a UUID"))?;
+            }
+            "confidentiality" => {
+                let label = text().unwrap_or_default();
+                upload.confidentiality = Confidentiality::parse(&label).ok_or_else(|| {
+                    ApiError::validation_error("confidentiality must be normal, restricted or very_restricted")
+                })?;
+            }
+            _ => {}
+        }
+    }
+
+    let (mut document, content) = upload.into_document(patient_id, config)?;
+    let path = document_path(config, document.id);
+    if let Some(directory) = path.parent() {
+        tokio::fs::create_dir_all(directory).await?;
+    }
+    tokio::fs::write(&path, &content).await?;
+    DocumentRepository::new().insert(&data.db_pool, &document).await?;
+
+    match scan_document(&data, &mut document, &content, uploader(&req)).await? {
+        Some(ScanVerdict::Clean) => Ok(HttpResponse::Created().json(ApiResponse::new(document))),
+        Some(ScanVerdict::Infected { .. }) => Err(ApiError::validation_error(
+            "The document was rejected because malware was detected in it",
+        )),
+        None => Ok(HttpResponse::Accepted().json(ApiResponse::new(document))),
+    }
+}
+
+/// A patient's documents, newest first or best match first when searching
+#[get("/patients/{id}/documents")]
+pub async fn list_documents(
+    path: web::Path<Id>,
+    query: web::Query<DocumentListQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()).map(str::to_string);
+
+    let documents = DocumentRepository::new()
+        .search(&data.db_pool, patient_id, search, clearance(&req), pagination.limit(), pagination.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        documents,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// A document's content, once scanned clean
+#[get("/documents/{id}/content")]
+pub async fn get_document_content(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let document_id = path.into_inner();
+    let document = DocumentRepository::new()
+        .find(&data.db_pool, document_id)
+        .await?
+        .filter(|document| document.status == "current" && clearance(&req).clears(document_label(document)))
+        .ok_or_else(|| ApiError::not_found(&format!("Document {} not found", document_id)))?;
+    authorize_patient_access(&req, &data, document.patient_id).await?;
+
+    match ScanStatus::parse(&document.scan_status) {
+        Some(ScanStatus::Clean) => {}
+        Some(ScanStatus::Pending) => {
+            return Err(ApiError::conflict("The document is quarantined until it has been scanned for malware"))
+        }
+        _ => return Err(ApiError::not_found(&format!("Document {} not found", document_id))),
+    }
+
+    let content = tokio::fs::read(document_path(&data.config.uploads, document.id)).await?;
+    Ok(HttpResponse::Ok().content_type(document.content_type).body(content))
+}
+
+/// Scan quarantined documents again, oldest first
+#[post("/admin/documents/rescan")]
+pub async fn rescan_documents(_req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let mut summary = RescanSummary::default();
+    let pending = DocumentRepository::new()
+        .pending_scans(&data.db_pool, RESCAN_BATCH_SIZE)
+        .await?;
+
+    for mut document in pending {
+        let content = match tokio::fs::read(document_path(&data.config.uploads, document.id)).await {
+            Ok(content) => content,
+            Err(error) => {
+                tracing::warn!(document_id = %document.id, %error, "Quarantined document has no stored content");
+                summary.missing += 1;
+                continue;
+            }
+        };
+        match scan_document(&data, &mut document, &content, None).await? {
+            Some(ScanVerdict::Clean) => summary.clean += 1,
+            Some(ScanVerdict::Infected { .. }) => summary.infected += 1,
+            None => summary.failed += 1,
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(summary)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_document() {
+        let config = UploadConfig::default();
+        let upload = |content_type: &str, bytes: &[u8]| DocumentUpload {
+            file: Some((bytes.to_vec(), content_type.to_string())),
+            title: Some("Outside records".to_string()),
+            ..DocumentUpload::default()
+        };
+        let patient_id = uuid::Uuid::new_v4();
+
+        let (document, content) = upload("application/pdf", b"%PDF-1.4").into_document(patient_id, &config).unwrap();
+        assert_eq!((document.scan_status.as_str(), document.size_bytes), ("pending", Some(8)));
+        assert_eq!(document.url, format!("/api/documents/{}/content", document.id));
+        assert_eq!(document_label(&document), Confidentiality::Normal);
+        assert_eq!(content, b"%PDF-1.4");
+        assert!(document_path(&config, document.id).starts_with("data/documents"));
+
+        assert!(upload("application/x-msdownload", b"MZ").into_document(patient_id, &config).is_err());
+        assert!(upload("application/pdf", b"").into_document(patient_id, &config).is_err());
+        assert!(DocumentUpload::default().into_document(patient_id, &config).is_err());
+    }
+
+    #[test]
+    fn test_ocr_job() {
+        let document_id = uuid::Uuid::new_v4();
+        let job = serde_json::to_value(ocr_job(document_id)).unwrap();
+        assert_eq!(job["type"], "DocumentOcr");
+        assert_eq!(job["document_id"], document_id.to_string());
+        assert_eq!(job["idempotency_key"], format!("document-ocr:{}", document_id));
+    }
+}
@@ -0,0 +1,371 @@
+//! Encounter endpoints for the Nexus API.
+//!
+//! Encounters are stored in `emr.encounters` through the `EncounterService`,
+//! which keeps the patient summary up to date with each write.
+//!
+//! Status changes go through the check-in/start/end workflow endpoints, which
+//! enforce the transitions defined on the `core` Encounter entity.
+//!
+//! `GET /encounters/{id}/view` returns the encounter together with its
+//! patient, practitioners, conditions, locations and observations, fetched in
+//! parallel by the prefetch service.
+//!
+//! Creating an encounter and moving it through the workflow publish
+//! `encounter.*` webhook events to the service provider's endpoints.
+//!
+//! Creates and updates are checked against the service provider's
+//! validation profile, and rejected when the profile is strict.
+//!
+//! A planned encounter given a `start` is an appointment; it shows on the
+//! patient summary until the patient arrives or the time passes.
+
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse};
+use emr_core::domain::{
+    Encounter, EncounterClass, EncounterLocation, EncounterParticipant, EncounterStatus, Period,
+};
+use emr_core::services::EncounterService;
+use emr_fhir::encounter_to_fhir;
+use serde::{Deserialize, Serialize};
+use crate::error::{ApiError, Result};
+use crate::handlers::care_teams::clearance;
+use crate::handlers::{ApiResponse, PaginationParams, PaginatedResponse, PaginationMeta};
+use crate::handlers::validation_profiles::enforce_profile;
+use crate::handlers::webhooks::publish_event;
+use crate::AppState;
+
+/// Encounter response DTO
+#[derive(Debug, Serialize)]
+pub struct EncounterResponse {
+    pub id: String,
+    pub patient_id: String,
+    pub status: EncounterStatus,
+    pub class: EncounterClass,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub reason: Vec<String>,
+    pub participants: Vec<EncounterParticipant>,
+    pub locations: Vec<EncounterLocation>,
+    pub period: Option<Period>,
+    /// Length of the encounter in minutes
+    pub length: Option<u32>,
+    pub service_provider: Option<String>,
+}
+
+/// Encounter creation/update request
+#[derive(Debug, Deserialize)]
+pub struct CreateEncounterRequest {
+    pub patient_id: uuid::Uuid,
+    pub class: EncounterClass,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub reason: Vec<String>,
+    pub service_provider: Option<uuid::Uuid>,
+    /// Scheduled start; only planned encounters can be rescheduled
+    pub start: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Encounter list filters
+#[derive(Debug, Deserialize)]
+pub struct EncounterFilter {
+    pub patient_id: Option<uuid::Uuid>,
+    pub status: Option<EncounterStatus>,
+}
+
+/// Participant assignment request
+#[derive(Debug, Deserialize)]
+pub struct AssignParticipantRequest {
+    pub practitioner_id: uuid::Uuid,
+    /// Participation type code (e.g. ATND, ADM, CON)
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+}
+
+/// Location assignment request
+#[derive(Debug, Deserialize)]
+pub struct AssignLocationRequest {
+    pub location_id: uuid::Uuid,
+}
+
+impl CreateEncounterRequest {
+    /// Build a planned domain encounter from the request
+    fn to_domain(&self) -> Encounter {
+        let mut encounter = Encounter::new(EncounterStatus::Planned, self.class.clone(), self.patient_id);
+        encounter.type_ = self.type_.clone();
+        encounter.priority = self.priority.clone();
+        encounter.reason = self.reason.clone();
+        encounter.service_provider = self.service_provider;
+        encounter.period = self.start.map(|start| Period {
+            start: Some(start),
+            end: None,
+        });
+        encounter
+    }
+}
+
+impl From<&Encounter> for EncounterResponse {
+    fn from(encounter: &Encounter) -> Self {
+        Self {
+            id: encounter.metadata.id.to_string(),
+            patient_id: encounter.subject.to_string(),
+            status: encounter.status.clone(),
+            class: encounter.class.clone(),
+            type_: encounter.type_.clone(),
+            reason: encounter.reason.clone(),
+            participants: encounter.participants.clone(),
+            locations: encounter.location.clone(),
+            period: encounter.period.clone(),
+            length: encounter.length,
+            service_provider: encounter.service_provider.map(|id| id.to_string()),
+        }
+    }
+}
+
+/// Look up an encounter or return 404
+async fn find_encounter(data: &AppState, id: uuid::Uuid) -> Result<Encounter> {
+    data.encounters
+        .get_encounter(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Encounter {} not found", id)))
+}
+
+/// List encounters with pagination
+#[get("/encounters")]
+pub async fn list_encounters(
+    query: web::Query<PaginationParams>,
+    filter: web::Query<EncounterFilter>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+
+    let (encounters, total) = data
+        .encounters
+        .list_encounters(filter.status.as_ref(), filter.patient_id, query.limit(), query.offset())
+        .await?;
+
+    let response = PaginatedResponse {
+        data: encounters.iter().map(EncounterResponse::from).collect(),
+        pagination: PaginationMeta::new(page, per_page, total),
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Get encounter by ID
+#[get("/encounters/{id}")]
+pub async fn get_encounter(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = find_encounter(&data, path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// Get an encounter with the resources its screen displays
+#[get("/encounters/{id}/view")]
+pub async fn get_encounter_view(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    let mut view = data
+        .encounter_views
+        .encounter_view(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Encounter {} not found", id)))?;
+    view.withhold_uncleared(clearance(&req));
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(view)))
+}
+
+/// Publish an encounter webhook event to its service provider, if it has one
+async fn publish_encounter_event(data: &AppState, event_type: &str, encounter: &Encounter) {
+    if let Some(tenant_id) = encounter.service_provider {
+        let payload = serde_json::to_value(EncounterResponse::from(encounter)).unwrap_or_default();
+        publish_event(data, tenant_id, event_type, payload).await;
+    }
+}
+
+/// Create a planned encounter
+#[post("/encounters")]
+pub async fn create_encounter(
+    request: web::Json<CreateEncounterRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = request.to_domain();
+    enforce_profile(&data, encounter.service_provider, "Encounter", &encounter_to_fhir(&encounter)).await?;
+
+    let encounter = data.encounters.create_encounter(encounter).await?;
+    publish_encounter_event(&data, "encounter.created", &encounter).await;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// Update encounter details
+#[put("/encounters/{id}")]
+pub async fn update_encounter(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<CreateEncounterRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let existing = find_encounter(&data, path.into_inner()).await?;
+
+    let mut encounter = request.to_domain();
+    encounter.metadata = existing.metadata;
+    encounter.participants = existing.participants;
+    encounter.location = existing.location;
+    if existing.status != EncounterStatus::Planned || encounter.period.is_none() {
+        encounter.period = existing.period;
+    }
+    encounter.length = existing.length;
+    enforce_profile(&data, encounter.service_provider, "Encounter", &encounter_to_fhir(&encounter)).await?;
+
+    let encounter = data.encounters.update_encounter(encounter).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// Check the patient in for a planned encounter
+#[post("/encounters/{id}/check-in")]
+pub async fn check_in_encounter(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = data.encounters.check_in_encounter(path.into_inner()).await?;
+    publish_encounter_event(&data, "encounter.arrived", &encounter).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// Start an encounter
+#[post("/encounters/{id}/start")]
+pub async fn start_encounter(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter_id = path.into_inner();
+    data.encounters.start_encounter(encounter_id).await?;
+
+    let encounter = find_encounter(&data, encounter_id).await?;
+    publish_encounter_event(&data, "encounter.started", &encounter).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// End an encounter
+#[post("/encounters/{id}/end")]
+pub async fn end_encounter(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter_id = path.into_inner();
+    data.encounters.end_encounter(encounter_id).await?;
+
+    let encounter = find_encounter(&data, encounter_id).await?;
+    publish_encounter_event(&data, "encounter.finished", &encounter).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// Assign a practitioner to an encounter
+#[post("/encounters/{id}/participants")]
+pub async fn assign_participant(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<AssignParticipantRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let participant = EncounterParticipant {
+        type_: request.type_,
+        period: None,
+        individual: Some(request.practitioner_id),
+    };
+
+    let encounter = data
+        .encounters
+        .add_participant(path.into_inner(), participant)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+/// Move an encounter to a new location
+#[put("/encounters/{id}/location")]
+pub async fn assign_location(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<AssignLocationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter = data
+        .encounters
+        .assign_location(path.into_inner(), request.location_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(EncounterResponse::from(&encounter))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_creates_planned_encounter() {
+        let request = CreateEncounterRequest {
+            patient_id: uuid::Uuid::new_v4(),
+            class: EncounterClass::Ambulatory,
+            type_: Some("checkup".to_string()),
+            priority: None,
+            reason: vec!["Annual physical".to_string()],
+            service_provider: None,
+            start: None,
+        };
+
+        let encounter = request.to_domain();
+        assert_eq!(encounter.status, EncounterStatus::Planned);
+        assert_eq!(encounter.subject, request.patient_id);
+
+        let response = EncounterResponse::from(&encounter);
+        assert_eq!(response.patient_id, request.patient_id.to_string());
+        assert_eq!(response.reason, vec!["Annual physical".to_string()]);
+    }
+
+    #[test]
+    fn test_filter_deserialization() {
+        let filter: EncounterFilter = serde_json::from_value(serde_json::json!({
+            "status": "InProgress"
+        }))
+        .unwrap();
+
+        assert_eq!(filter.status, Some(EncounterStatus::InProgress));
+        assert!(filter.patient_id.is_none());
+    }
+
+    #[test]
+    fn test_events_match_published_schema() {
+        let request = CreateEncounterRequest {
+            patient_id: uuid::Uuid::new_v4(),
+            class: EncounterClass::Ambulatory,
+            type_: None,
+            priority: None,
+            reason: Vec::new(),
+            service_provider: Some(uuid::Uuid::new_v4()),
+            start: None,
+        };
+        let payload = serde_json::to_value(EncounterResponse::from(&request.to_domain())).unwrap();
+
+        for event_type in emr_core::events::ENCOUNTER_EVENTS {
+            let schema = emr_core::events::schema(event_type, 1).unwrap();
+            assert_eq!(schema.validate(&payload), Vec::<String>::new(), "{}", event_type);
+        }
+    }
+}
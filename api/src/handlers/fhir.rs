@@ -1,47 +1,101 @@
 //! FHIR proxy handlers
+//!
+//! Resources whose `meta.security` confidentiality label is above the
+//! caller's clearance are withheld: reads answer not found and searches
+//! leave them out of the Bundle.
 
-use actix_web::{get, web, HttpRequest, HttpResponse};
-use crate::error::Result;
-use crate::handlers::{ApiResponse, PaginationParams};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use emr_fhir::{resource_confidentiality, withhold_uncleared, SearchParameters, Subscription};
+use crate::error::{ApiError, Result};
+use crate::fhir::{fhir_response, FhirBody};
+use crate::handlers::care_teams::clearance;
 use crate::AppState;
 
 /// Get FHIR patient by ID (proxy to Kodjin)
 #[get("/fhir/Patient/{id}")]
 pub async fn get_fhir_patient(
     path: web::Path<String>,
-    _req: HttpRequest,
+    req: HttpRequest,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
     let patient_id = path.into_inner();
     
     // Proxy request to FHIR server
-    let fhir_response = data.fhir_client.get_patient(&patient_id).await?;
+    let patient = data.fhir_client.read("Patient", &patient_id).await?;
+    if !clearance(&req).clears(resource_confidentiality(&patient)) {
+        return Err(ApiError::not_found(&format!("Patient {} not found", patient_id)));
+    }
     
-    Ok(HttpResponse::Ok()
-        .content_type("application/fhir+json")
-        .json(fhir_response))
+    fhir_response(&req, HttpResponse::Ok(), &patient)
 }
 
 /// Search FHIR resources
 #[get("/fhir/{resource_type}")]
 pub async fn search_fhir_resources(
     path: web::Path<String>,
-    query: web::Query<std::collections::HashMap<String, String>>,
-    _req: HttpRequest,
+    query: web::Query<Vec<(String, String)>>,
+    req: HttpRequest,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
     let resource_type = path.into_inner();
     
-    // Convert query parameters
-    let params: Vec<(&str, &str)> = query
-        .iter()
-        .map(|(k, v)| (k.as_str(), v.as_str()))
-        .collect();
+    // Keep repeated parameters (e.g. a date range) and re-encode them
+    let params = SearchParameters::from_pairs(&resource_type, query.into_inner())?;
     
     // Proxy search to FHIR server
-    let fhir_response = data.fhir_client.search(&resource_type, &params).await?;
+    let mut bundle = data.fhir_client.search(&params).await?;
+    withhold_uncleared(&mut bundle, clearance(&req));
     
-    Ok(HttpResponse::Ok()
-        .content_type("application/fhir+json")
-        .json(fhir_response))
-} 
\ No newline at end of file
+    fhir_response(&req, HttpResponse::Ok(), &bundle)
+} 
+
+/// Register a FHIR Subscription (rest-hook channels only)
+#[post("/fhir/Subscription")]
+pub async fn create_subscription(
+    resource: FhirBody,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let subscription = Subscription::from_resource(&resource)?;
+    let registered = data.subscriptions.register(subscription).await?;
+    let location = format!("/fhir/Subscription/{}", registered.id.as_deref().unwrap_or_default());
+
+    let mut response = HttpResponse::Created();
+    response.insert_header(("Location", location));
+    fhir_response(&req, response, &registered.to_resource()?)
+}
+
+/// Get a registered FHIR Subscription, including its delivery status
+#[get("/fhir/Subscription/{id}")]
+pub async fn get_subscription(
+    path: web::Path<String>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let subscription_id = path.into_inner();
+
+    let subscription = data
+        .subscriptions
+        .get(&subscription_id)
+        .await
+        .ok_or_else(|| ApiError::not_found(&format!("Subscription {} not found", subscription_id)))?;
+
+    fhir_response(&req, HttpResponse::Ok(), &subscription.to_resource()?)
+}
+
+/// Remove a FHIR Subscription
+#[delete("/fhir/Subscription/{id}")]
+pub async fn delete_subscription(
+    path: web::Path<String>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let subscription_id = path.into_inner();
+
+    data.subscriptions
+        .remove(&subscription_id)
+        .await
+        .ok_or_else(|| ApiError::not_found(&format!("Subscription {} not found", subscription_id)))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
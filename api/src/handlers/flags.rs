@@ -0,0 +1,141 @@
+//! Feature flag endpoints
+//!
+//! Operators list and toggle runtime feature flags at `/admin/flags`; a
+//! change takes effect on this instance at once and on the others within
+//! `flags.cache_ttl` seconds. `GET /flags` evaluates every flag for the
+//! caller so the UI can hide features that are off for them.
+
+use actix_web::{delete, get, put, web, HttpMessage, HttpRequest, HttpResponse};
+use emr_core::flags::FeatureFlag;
+use emr_core::types::Id;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use validator::Validate;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, ValidatedJson};
+use crate::repositories::FeatureFlagRepository;
+use crate::AppState;
+
+/// New or changed flag
+#[derive(Debug, Deserialize, Validate)]
+pub struct SaveFlagRequest {
+    #[validate(length(max = 500))]
+    pub description: Option<String>,
+    pub enabled: bool,
+    #[serde(default = "default_rollout_percentage")]
+    #[validate(range(max = 100))]
+    pub rollout_percentage: u8,
+    /// Tenants the flag is forced on or off for
+    #[serde(default)]
+    pub tenants: BTreeMap<Id, bool>,
+    /// Version the change is based on; unchecked when missing
+    pub version: Option<u64>,
+}
+
+fn default_rollout_percentage() -> u8 {
+    100
+}
+
+/// Tenant flags are evaluated for
+#[derive(Debug, Deserialize)]
+pub struct FlagQuery {
+    pub organization_id: Option<Id>,
+}
+
+/// Every flag
+#[get("/admin/flags")]
+pub async fn list_flags(_req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let flags = FeatureFlagRepository::new().list(&data.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(flags)))
+}
+
+/// Create or change a flag
+#[put("/admin/flags/{key}")]
+pub async fn save_flag(
+    path: web::Path<String>,
+    request: ValidatedJson<SaveFlagRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let key = path.into_inner();
+    let request = request.into_inner();
+    let updated_by = req.extensions().get::<AuthContext>().map(|context| context.subject.clone());
+    let repository = FeatureFlagRepository::new();
+
+    let existing = repository.find(&data.db_pool, &key).await?;
+    if let (Some(flag), Some(version)) = (&existing, request.version) {
+        if flag.version != version {
+            return Err(ApiError::conflict(&format!("Flag {} was changed by someone else", key)));
+        }
+    }
+
+    let mut flag = existing.clone().unwrap_or_else(|| FeatureFlag::new(&key));
+    flag.description = request.description;
+    flag.enabled = request.enabled;
+    flag.rollout_percentage = request.rollout_percentage;
+    flag.tenants = request.tenants;
+    flag.validate()?;
+
+    let saved = match &existing {
+        Some(previous) => {
+            flag.touch(updated_by);
+            repository.update(&data.db_pool, &flag, previous.version).await?
+        }
+        None => {
+            flag.updated_by = updated_by;
+            repository.insert(&data.db_pool, &flag).await?
+        }
+    };
+    if !saved {
+        return Err(ApiError::conflict(&format!("Flag {} was changed by someone else", key)));
+    }
+    data.flags().invalidate().await;
+
+    let mut response = if existing.is_some() { HttpResponse::Ok() } else { HttpResponse::Created() };
+    Ok(response.json(ApiResponse::new(flag)))
+}
+
+/// Delete a flag; code checking it sees it as off
+#[delete("/admin/flags/{key}")]
+pub async fn delete_flag(path: web::Path<String>, _req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let key = path.into_inner();
+    if !FeatureFlagRepository::new().delete(&data.db_pool, &key).await? {
+        return Err(ApiError::not_found(&format!("Flag {} not found", key)));
+    }
+    data.flags().invalidate().await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Every flag evaluated for the caller, by key
+#[get("/flags")]
+pub async fn evaluate_flags(
+    query: web::Query<FlagQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    // Unauthenticated requests still pass until the auth middleware rejects
+    // them (TODO(nexus-phase2)); they are bucketed as one anonymous subject
+    let subject = req
+        .extensions()
+        .get::<AuthContext>()
+        .map(|context| context.subject.clone())
+        .unwrap_or_default();
+    let flags = data.flags().evaluate(query.organization_id, &subject).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(flags)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_request_defaults() {
+        let request: SaveFlagRequest = serde_json::from_str(r#"{"enabled": true}"#).unwrap();
+        assert_eq!(request.rollout_percentage, 100);
+        assert!(request.tenants.is_empty());
+        assert!(request.version.is_none());
+    }
+}
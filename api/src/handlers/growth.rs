@@ -0,0 +1,125 @@
+//! Pediatric growth chart endpoint
+//!
+//! `GET /patients/{id}/growth?measure=weight|height|bmi` places the patient's
+//! measurements on the WHO (under 24 months) and CDC (24 months on) growth
+//! references, with a z-score and percentile for each, and returns the
+//! reference percentile curves for the vitals chart to draw behind them.
+
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::values::AdministrativeGender;
+use emr_core::services::ObservationService;
+use emr_core::services::growth::{
+    age_in_months, growth_points, GrowthMeasure, GrowthPoint, PercentileCurve, CHART_PERCENTILES,
+};
+use emr_core::types::Id;
+use serde::{Deserialize, Serialize};
+use crate::error::{ApiError, Result};
+use crate::handlers::calculators::parse_gender;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::ApiResponse;
+use crate::repositories::PortalRepository;
+use crate::services::growth_references;
+use crate::AppState;
+
+/// Oldest age the pediatric references cover, in months
+const MAX_CHART_AGE_MONTHS: f64 = 240.0;
+
+/// Months of curve drawn past the patient's current age by default
+const CHART_LOOKAHEAD_MONTHS: f64 = 12.0;
+
+/// Growth chart options
+#[derive(Debug, Deserialize)]
+pub struct GrowthChartQuery {
+    /// Measurement to chart
+    pub measure: GrowthMeasure,
+    /// Youngest age of the curves, in months; defaults to birth
+    pub from_months: Option<f64>,
+    /// Oldest age of the curves, in months; defaults to a year past the
+    /// patient's current age
+    pub to_months: Option<f64>,
+}
+
+/// A patient's growth chart for one measure
+#[derive(Debug, Serialize)]
+pub struct GrowthChart {
+    pub measure: GrowthMeasure,
+    pub unit: &'static str,
+    pub age_months: Option<f64>,
+    pub points: Vec<GrowthPoint>,
+    pub curves: Vec<PercentileCurve>,
+}
+
+/// Default age span of the curves: birth to a year past the patient's age
+fn chart_span(age_months: f64, query: &GrowthChartQuery) -> Result<(f64, f64)> {
+    let from = query.from_months.unwrap_or(0.0);
+    let to = query
+        .to_months
+        .unwrap_or_else(|| (age_months + CHART_LOOKAHEAD_MONTHS).min(MAX_CHART_AGE_MONTHS));
+    if !(from.is_finite() && to.is_finite() && from >= 0.0 && from <= to) {
+        return Err(ApiError::bad_request("from_months must be at least 0 and no more than to_months"));
+    }
+    Ok((from, to))
+}
+
+/// Growth chart for a patient
+#[get("/patients/{id}/growth")]
+pub async fn get_growth_chart(
+    path: web::Path<Id>,
+    query: web::Query<GrowthChartQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let demographics = PortalRepository::new()
+        .demographics(&data.db_pool, patient_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Patient {} not found", patient_id)))?;
+    let birth_date = demographics
+        .birth_date
+        .ok_or_else(|| ApiError::bad_request("Growth charts need the patient's date of birth"))?;
+    let gender = demographics
+        .gender
+        .as_deref()
+        .and_then(parse_gender)
+        .unwrap_or(AdministrativeGender::Unknown);
+
+    let references = growth_references(&data.config.growth)?;
+    let observations = data.observations.get_patient_observations(patient_id).await?;
+    let points = growth_points(&references, query.measure, birth_date, &gender, &observations);
+
+    let age_months = age_in_months(birth_date, Utc::now().date_naive());
+    let (from, to) = chart_span(age_months.unwrap_or(0.0), &query)?;
+    let curves = references.curves(query.measure, &gender, &CHART_PERCENTILES, from, to);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(GrowthChart {
+        measure: query.measure,
+        unit: query.measure.unit(),
+        age_months,
+        points,
+        curves,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(value: serde_json::Value) -> GrowthChartQuery {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_chart_span() {
+        let default = query(serde_json::json!({"measure": "bmi"}));
+        assert_eq!(default.measure, GrowthMeasure::Bmi);
+        assert_eq!(chart_span(30.0, &default).unwrap(), (0.0, 42.0));
+        assert_eq!(chart_span(235.0, &default).unwrap(), (0.0, MAX_CHART_AGE_MONTHS));
+
+        let reversed = query(serde_json::json!({"measure": "weight", "from_months": 24.0, "to_months": 12.0}));
+        assert!(chart_span(30.0, &reversed).is_err());
+        assert!(serde_json::from_value::<GrowthChartQuery>(serde_json::json!({"measure": "head"})).is_err());
+    }
+}
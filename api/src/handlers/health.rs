@@ -3,6 +3,7 @@
 use actix_web::{get, web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use crate::error::Result;
+use crate::services::warmup::WarmupReport;
 use crate::AppState;
 
 /// Health check response
@@ -54,6 +55,15 @@ pub struct ServiceStatus {
     pub error: Option<String>,
 }
 
+/// Readiness response
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    /// `warming_up` until the caches are warm, then `ready`
+    pub status: String,
+    /// How the caches warmed up, once they have
+    pub warmup: Option<WarmupReport>,
+}
+
 /// Application start time (set at startup)
 static START_TIME: std::sync::OnceLock<chrono::DateTime<chrono::Utc>> = std::sync::OnceLock::new();
 
@@ -105,8 +115,8 @@ pub async fn health_check(
         build: BuildInfo {
             commit: option_env!("GIT_COMMIT").unwrap_or("unknown").to_string(),
             timestamp: option_env!("BUILD_TIMESTAMP").unwrap_or("unknown").to_string(),
-            rust_version: env!("RUSTC_VERSION").to_string(),
-            target: env!("TARGET").to_string(),
+            rust_version: option_env!("RUSTC_VERSION").unwrap_or("unknown").to_string(),
+            target: option_env!("TARGET").unwrap_or("unknown").to_string(),
             profile: if cfg!(debug_assertions) { "debug" } else { "release" }.to_string(),
         },
         uptime: get_uptime(),
@@ -119,6 +129,27 @@ pub async fn health_check(
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Readiness probe
+///
+/// Answers 503 while the caches warm up after boot, then 200 (see
+/// `services::warmup`).
+#[get("/readyz")]
+pub async fn readiness_check(data: web::Data<AppState>) -> Result<HttpResponse> {
+    let response = match data.readiness.report().await {
+        Some(report) => HttpResponse::Ok().json(ReadinessResponse {
+            status: "ready".to_string(),
+            warmup: Some(report),
+        }),
+        None => HttpResponse::ServiceUnavailable()
+            .insert_header(("Retry-After", "1"))
+            .json(ReadinessResponse {
+                status: "warming_up".to_string(),
+                warmup: None,
+            }),
+    };
+    Ok(response)
+}
+
 /// Check database connection health
 async fn check_database_health(data: &AppState) -> ServiceStatus {
     let start = std::time::Instant::now();
@@ -140,7 +171,7 @@ async fn check_database_health(data: &AppState) -> ServiceStatus {
 }
 
 /// Check FHIR server health
-async fn check_fhir_health(data: &AppState) -> ServiceStatus {
+async fn check_fhir_health(_data: &AppState) -> ServiceStatus {
     let start = std::time::Instant::now();
     
     // TODO(nexus-phase6): Implement real FHIR integration health checks.
@@ -156,7 +187,7 @@ async fn check_fhir_health(data: &AppState) -> ServiceStatus {
 }
 
 /// Check NATS connection health
-async fn check_nats_health(data: &AppState) -> ServiceStatus {
+async fn check_nats_health(_data: &AppState) -> ServiceStatus {
     let start = std::time::Instant::now();
     
     // TODO(nexus-phase6): Implement real NATS connectivity checks.
@@ -243,11 +274,23 @@ mod tests {
         assert!(status.error.is_none());
     }
 
+    #[test]
+    fn test_readiness_response_serialization() {
+        let response = ReadinessResponse {
+            status: "warming_up".to_string(),
+            warmup: None,
+        };
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["status"], "warming_up");
+        assert!(json["warmup"].is_null());
+    }
+
     #[test]
     fn test_uptime_calculation() {
         init_start_time();
         std::thread::sleep(std::time::Duration::from_millis(10));
         let uptime = get_uptime();
-        assert!(uptime >= 0);
+        assert!(uptime < 60);
     }
 } 
\ No newline at end of file
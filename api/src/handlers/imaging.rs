@@ -0,0 +1,153 @@
+//! Imaging study endpoints
+//!
+//! Studies are looked up in the PACS on each request, under the patient's
+//! identifier in `imaging.patient_identifier_system` (the MRN by default).
+//! `GET /patients/{id}/imaging-studies` lists them most recent first, and
+//! `GET /patients/{id}/imaging-studies/{study_uid}` adds the study's series.
+//! Each study carries a deep link into the image viewer when
+//! `imaging.viewer_url` is configured; the images themselves never pass
+//! through the API.
+
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use emr_core::domain::imaging::is_valid_uid;
+use emr_core::domain::ImagingStudy;
+use emr_core::types::Id;
+use emr_fhir::imaging_study_to_fhir;
+use serde::Serialize;
+use serde_json::json;
+use crate::config::ImagingConfig;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::ApiResponse;
+use crate::repositories::PatientRepository;
+use crate::services::imaging_archive;
+use crate::AppState;
+
+/// A study with its viewer link
+#[derive(Debug, Serialize)]
+pub struct ImagingStudyView {
+    #[serde(flatten)]
+    pub study: ImagingStudy,
+    pub viewer_url: Option<String>,
+}
+
+impl ImagingStudyView {
+    fn new(study: ImagingStudy, config: &ImagingConfig, pacs_patient_id: &str) -> Self {
+        let viewer_url = config
+            .viewer_url
+            .as_deref()
+            .map(|template| study.viewer_url(template, pacs_patient_id));
+        Self { study, viewer_url }
+    }
+}
+
+/// The identifier the PACS files the patient's studies under
+async fn pacs_patient_id(data: &AppState, patient_id: Id) -> Result<Option<String>> {
+    PatientRepository::new()
+        .identifier_value(&data.db_pool, patient_id, &data.config.imaging.patient_identifier_system)
+        .await
+}
+
+/// One of the patient's studies, with its series
+async fn find_study(data: &AppState, patient_id: Id, study_uid: &str) -> Result<(ImagingStudy, String)> {
+    if !is_valid_uid(study_uid) {
+        return Err(ApiError::validation_error(&format!("'{}' is not a Study Instance UID", study_uid)));
+    }
+    let not_found = || ApiError::not_found(&format!("Imaging study {} not found", study_uid));
+    let pacs_patient_id = pacs_patient_id(data, patient_id).await?.ok_or_else(not_found)?;
+
+    let study = imaging_archive(&data.config.imaging)?
+        .find_study(patient_id, &pacs_patient_id, study_uid)
+        .await?
+        .ok_or_else(not_found)?;
+    Ok((study, pacs_patient_id))
+}
+
+/// A patient's imaging studies, most recent first
+#[get("/patients/{id}/imaging-studies")]
+pub async fn list_imaging_studies(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    // A patient without the identifier has nothing filed in the PACS
+    let Some(pacs_patient_id) = pacs_patient_id(&data, patient_id).await? else {
+        return Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+            Vec::<ImagingStudyView>::new(),
+            json!({ "pacs_patient_id": null }),
+        )));
+    };
+
+    let mut studies = imaging_archive(&data.config.imaging)?
+        .search_studies(patient_id, &pacs_patient_id)
+        .await?;
+    studies.sort_by_key(|study| std::cmp::Reverse(study.started));
+    let studies: Vec<ImagingStudyView> = studies
+        .into_iter()
+        .map(|study| ImagingStudyView::new(study, &data.config.imaging, &pacs_patient_id))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        studies,
+        json!({ "pacs_patient_id": pacs_patient_id }),
+    )))
+}
+
+/// One of a patient's imaging studies, with its series
+#[get("/patients/{id}/imaging-studies/{study_uid}")]
+pub async fn get_imaging_study(
+    path: web::Path<(Id, String)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (patient_id, study_uid) = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let (study, pacs_patient_id) = find_study(&data, patient_id, &study_uid).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(ImagingStudyView::new(
+        study,
+        &data.config.imaging,
+        &pacs_patient_id,
+    ))))
+}
+
+/// One of a patient's imaging studies as a FHIR `ImagingStudy`
+#[get("/patients/{id}/imaging-studies/{study_uid}/fhir")]
+pub async fn get_imaging_study_fhir(
+    path: web::Path<(Id, String)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (patient_id, study_uid) = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let (study, _) = find_study(&data, patient_id, &study_uid).await?;
+    fhir_response(&req, HttpResponse::Ok(), &imaging_study_to_fhir(&study))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_study_view() {
+        let study = ImagingStudy::from_dicom_json(
+            uuid::Uuid::new_v4(),
+            &json!({"0020000D": {"vr": "UI", "Value": ["1.2.840.1"]}}),
+        )
+        .unwrap();
+        let config = ImagingConfig {
+            viewer_url: Some("https://viewer.example.com/?StudyInstanceUIDs={study_uid}&mrn={patient_id}".to_string()),
+            ..ImagingConfig::default()
+        };
+
+        let view = serde_json::to_value(ImagingStudyView::new(study.clone(), &config, "MRN 1")).unwrap();
+        assert_eq!(view["study_instance_uid"], "1.2.840.1");
+        assert_eq!(view["viewer_url"], "https://viewer.example.com/?StudyInstanceUIDs=1.2.840.1&mrn=MRN%201");
+        assert!(ImagingStudyView::new(study, &ImagingConfig::default(), "MRN 1").viewer_url.is_none());
+    }
+}
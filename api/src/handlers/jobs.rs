@@ -0,0 +1,548 @@
+//! Background job handlers
+//!
+//! Job progress is published by the jobs worker on NATS and relayed to clients
+//! as server-sent events. Cancellation requests are published on NATS too;
+//! the worker drops queued jobs and running handlers stop at their next
+//! cancellation check, after which the event stream reports `Cancelled`.
+//!
+//! `GET /jobs/stats` reports throughput, failure rates and duration
+//! percentiles from the run history the worker records in `jobs.job_runs`.
+//!
+//! Jobs that fail for good are kept in `jobs.dead_letters` by the worker.
+//! The admin endpoints list and inspect them and replay selected jobs under
+//! their original id once the cause has been fixed.
+
+use actix_web::{get, post, web, web::Bytes, HttpRequest, HttpResponse};
+use emr_core::events::{Event, EventEnvelope};
+use emr_jobs::progress::{events_subject, JobEvent};
+use emr_jobs::types::JobSubmission;
+use emr_jobs::worker::CANCEL_SUBJECT;
+use futures_util::{future::ready, stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_stream::wrappers::IntervalStream;
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::models::{DeadLetterModel, JobRunStatsModel};
+use crate::repositories::{DeadLetterRepository, JobRunRepository};
+use crate::AppState;
+
+/// Interval between keep-alive comments on idle event streams
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Job statuses that end an event stream
+const TERMINAL_STATUSES: [&str; 3] = ["Completed", "Failed", "Cancelled"];
+
+/// Maximum number of dead letters replayed per request
+const MAX_REPLAY_JOBS: usize = 100;
+
+/// Stats window used when none is given
+const DEFAULT_STATS_WINDOW: &str = "24h";
+
+/// Longest stats window, in seconds (90 days)
+const MAX_STATS_WINDOW_SECS: i64 = 90 * 24 * 3600;
+
+/// Cancellation request acknowledgement
+#[derive(Debug, Serialize)]
+pub struct CancelJobResponse {
+    pub job_id: uuid::Uuid,
+    pub status: &'static str,
+}
+
+/// Job stats query
+#[derive(Debug, Deserialize)]
+pub struct JobStatsQuery {
+    /// Window such as `30m`, `24h` or `7d`
+    pub window: Option<String>,
+}
+
+/// Job run statistics over a window
+#[derive(Debug, Serialize)]
+pub struct JobStatsResponse {
+    pub window: String,
+    pub from: chrono::DateTime<chrono::Utc>,
+    pub to: chrono::DateTime<chrono::Utc>,
+    pub overall: JobTypeStats,
+    pub by_type: Vec<JobTypeStats>,
+}
+
+/// Statistics for one job type, or all of them
+#[derive(Debug, Serialize)]
+pub struct JobTypeStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_type: Option<String>,
+    pub total: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    pub retries: i64,
+    /// Finished runs per hour
+    pub throughput_per_hour: f64,
+    /// Failed share of runs that succeeded or failed; cancellations are
+    /// not failures
+    pub failure_rate: f64,
+    pub duration_ms: DurationPercentiles,
+}
+
+/// Run duration percentiles; absent when nothing ran
+#[derive(Debug, Serialize)]
+pub struct DurationPercentiles {
+    pub p50: Option<f64>,
+    pub p95: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+impl JobTypeStats {
+    fn from_model(model: JobRunStatsModel, window_secs: i64) -> Self {
+        let completed = model.succeeded + model.failed;
+        Self {
+            throughput_per_hour: model.total as f64 * 3600.0 / window_secs as f64,
+            failure_rate: if completed == 0 {
+                0.0
+            } else {
+                model.failed as f64 / completed as f64
+            },
+            job_type: model.job_type,
+            total: model.total,
+            succeeded: model.succeeded,
+            failed: model.failed,
+            cancelled: model.cancelled,
+            retries: model.retries,
+            duration_ms: DurationPercentiles {
+                p50: model.p50_ms,
+                p95: model.p95_ms,
+                p99: model.p99_ms,
+            },
+        }
+    }
+}
+
+/// Parse a stats window such as `30m`, `24h` or `7d` into seconds
+fn parse_window(window: &str) -> Result<i64> {
+    let invalid = || ApiError::validation_error(&format!("Invalid window '{}', expected e.g. 30m, 24h or 7d", window));
+
+    let window = window.trim();
+    let unit_len = window.chars().last().map(char::len_utf8).unwrap_or(0);
+    let (amount, unit) = window.split_at(window.len() - unit_len);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        _ => return Err(invalid()),
+    };
+
+    let secs = amount.checked_mul(unit_secs).ok_or_else(invalid)?;
+    if secs <= 0 || secs > MAX_STATS_WINDOW_SECS {
+        return Err(ApiError::validation_error("Window must be between 1m and 90d"));
+    }
+    Ok(secs)
+}
+
+/// Dead letter list filter
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterFilter {
+    pub job_type: Option<String>,
+}
+
+/// Jobs to replay from the dead-letter store
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    pub job_ids: Vec<uuid::Uuid>,
+}
+
+/// Outcome of replaying one dead letter
+#[derive(Debug, Serialize)]
+pub struct ReplayOutcome {
+    pub job_id: uuid::Uuid,
+    /// `replayed`, `not_found` or `failed`
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ReplayOutcome {
+    fn new(job_id: uuid::Uuid, status: &'static str) -> Self {
+        Self { job_id, status, error: None }
+    }
+
+    fn failed(job_id: uuid::Uuid, error: &ApiError) -> Self {
+        Self {
+            job_id,
+            status: "failed",
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Job submission re-enqueuing a dead letter under its original id
+fn replay_submission(letter: &DeadLetterModel) -> Result<JobSubmission> {
+    let mut submission: JobSubmission = serde_json::from_value(letter.payload.clone()).map_err(|e| {
+        ApiError::internal_error(&format!("Dead letter {} has no valid job payload: {}", letter.job_id, e))
+    })?;
+    submission.job_id = Some(letter.job_id);
+    Ok(submission)
+}
+
+/// A single frame written to the SSE stream
+#[derive(Debug)]
+enum SseFrame {
+    /// Job event relayed from the worker
+    Event { name: String, data: String, is_terminal: bool },
+    /// Comment line keeping idle connections open through proxies
+    KeepAlive,
+}
+
+impl SseFrame {
+    /// Build a frame from a published job event
+    ///
+    /// Clients receive the event without its envelope.
+    fn from_payload(payload: &[u8]) -> Self {
+        let (data, event) = match EventEnvelope::read(payload, JobEvent::EVENT_TYPE) {
+            Ok(envelope) => (envelope.payload.to_string(), envelope.payload),
+            Err(_) => (String::from_utf8_lossy(payload).into_owned(), Value::Null),
+        };
+
+        let name = event
+            .get("event")
+            .and_then(Value::as_str)
+            .unwrap_or("message")
+            .to_string();
+        let is_terminal = name == "status"
+            && event
+                .get("status")
+                .and_then(Value::as_str)
+                .map(|status| TERMINAL_STATUSES.contains(&status))
+                .unwrap_or(false);
+
+        Self::Event { name, data, is_terminal }
+    }
+
+    /// Check if this frame ends the stream
+    fn is_terminal(&self) -> bool {
+        matches!(self, Self::Event { is_terminal: true, .. })
+    }
+
+    /// Encode the frame in the text/event-stream wire format
+    fn to_bytes(&self) -> Bytes {
+        match self {
+            Self::Event { name, data, .. } => {
+                Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+            }
+            Self::KeepAlive => Bytes::from_static(b": keep-alive\n\n"),
+        }
+    }
+}
+
+/// Job throughput, failure rate and duration percentiles over a window,
+/// overall and per job type
+#[get("/jobs/stats")]
+pub async fn job_stats(
+    query: web::Query<JobStatsQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let window = query.into_inner().window.unwrap_or_else(|| DEFAULT_STATS_WINDOW.to_string());
+    let window_secs = parse_window(&window)?;
+
+    let mut rows = JobRunRepository::new()
+        .window_stats(&data.db_pool, window_secs)
+        .await?
+        .into_iter()
+        .map(|model| JobTypeStats::from_model(model, window_secs));
+    let overall = rows
+        .next()
+        .ok_or_else(|| ApiError::internal_error("Job stats query returned no overall row"))?;
+
+    let to = chrono::Utc::now();
+    Ok(HttpResponse::Ok().json(ApiResponse::new(JobStatsResponse {
+        window,
+        from: to - chrono::Duration::seconds(window_secs),
+        to,
+        overall,
+        by_type: rows.collect(),
+    })))
+}
+
+/// Stream job progress, status transitions and log lines as server-sent events
+///
+/// The stream closes after the job reaches a terminal status.
+#[get("/jobs/{id}/events")]
+pub async fn job_events(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let job_id = path.into_inner();
+
+    let subscriber = data
+        .nats_client
+        .subscribe(events_subject(job_id))
+        .await
+        .map_err(|e| ApiError::external_service_error("NATS", &e.to_string()))?;
+
+    let events = subscriber.map(|message| SseFrame::from_payload(&message.payload));
+    let keep_alive = IntervalStream::new(tokio::time::interval(KEEP_ALIVE_INTERVAL))
+        .map(|_| SseFrame::KeepAlive);
+
+    let body = stream::select(events, keep_alive)
+        .scan(false, |finished, frame| {
+            if *finished {
+                return ready(None);
+            }
+            *finished = frame.is_terminal();
+            ready(Some(frame))
+        })
+        .map(|frame| Ok::<_, actix_web::Error>(frame.to_bytes()));
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body))
+}
+
+/// Request cancellation of a queued or running job
+///
+/// Cancellation is asynchronous; follow `GET /jobs/{id}/events` for the final
+/// status. Jobs that finish before the request arrives are unaffected.
+#[post("/jobs/{id}/cancel")]
+pub async fn cancel_job(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let job_id = path.into_inner();
+
+    let payload = serde_json::to_vec(&json!({ "job_id": job_id }))?;
+    data.nats_client
+        .publish(CANCEL_SUBJECT, payload.into())
+        .await
+        .map_err(|e| ApiError::external_service_error("NATS", &e.to_string()))?;
+
+    Ok(HttpResponse::Accepted().json(ApiResponse::new(CancelJobResponse {
+        job_id,
+        status: "cancellation_requested",
+    })))
+}
+
+/// List dead-lettered jobs not replayed since they last failed, newest first
+#[get("/admin/jobs/dead-letters")]
+pub async fn list_dead_letters(
+    query: web::Query<PaginationParams>,
+    filter: web::Query<DeadLetterFilter>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+
+    let letters = DeadLetterRepository::new()
+        .list_open(&data.db_pool, filter.into_inner().job_type, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        letters,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Get a dead-lettered job with its payload and last error
+#[get("/admin/jobs/dead-letters/{id}")]
+pub async fn get_dead_letter(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let job_id = path.into_inner();
+
+    let letter = DeadLetterRepository::new()
+        .find_by_id(&data.db_pool, job_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Dead letter {} not found", job_id)))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(letter)))
+}
+
+/// Re-enqueue dead-lettered jobs
+///
+/// Each job is submitted again under its original id, so its progress can be
+/// followed on `GET /jobs/{id}/events` and a repeat failure updates the same
+/// dead letter. Returns one outcome per requested id, in order.
+#[post("/admin/jobs/dead-letters/replay")]
+pub async fn replay_dead_letters(
+    request: web::Json<ReplayRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut job_ids = request.into_inner().job_ids;
+    if job_ids.is_empty() {
+        return Err(ApiError::validation_error("No jobs to replay"));
+    }
+    if job_ids.len() > MAX_REPLAY_JOBS {
+        return Err(ApiError::validation_error(&format!(
+            "Replays are limited to {} jobs per request",
+            MAX_REPLAY_JOBS
+        )));
+    }
+    let mut seen = std::collections::HashSet::new();
+    job_ids.retain(|id| seen.insert(*id));
+
+    let repository = DeadLetterRepository::new();
+    let mut outcomes = Vec::with_capacity(job_ids.len());
+    for job_id in job_ids {
+        let outcome = match replay(&repository, &data, job_id).await {
+            Ok(true) => ReplayOutcome::new(job_id, "replayed"),
+            Ok(false) => ReplayOutcome::new(job_id, "not_found"),
+            Err(e) => {
+                tracing::error!(job_id = %job_id, error = %e, "Dead letter replay failed");
+                ReplayOutcome::failed(job_id, &e)
+            }
+        };
+        outcomes.push(outcome);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(outcomes)))
+}
+
+/// Submit one dead letter again; false if there is none for the id
+async fn replay(repository: &DeadLetterRepository, data: &AppState, job_id: uuid::Uuid) -> Result<bool> {
+    let Some(letter) = repository.find_by_id(&data.db_pool, job_id).await? else {
+        return Ok(false);
+    };
+
+    submit_job(data, &replay_submission(&letter)?).await?;
+    repository.mark_replayed(&data.db_pool, job_id).await?;
+
+    tracing::info!(job_id = %job_id, job_type = %letter.job_type, "Dead letter replayed");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("30m").unwrap(), 1800);
+        assert_eq!(parse_window("24h").unwrap(), 86_400);
+        assert_eq!(parse_window("7d").unwrap(), 604_800);
+        assert!(parse_window("0h").is_err());
+        assert!(parse_window("91d").is_err());
+        assert!(parse_window("24").is_err());
+        assert!(parse_window("h").is_err());
+        assert!(parse_window("1w").is_err());
+        assert!(parse_window("24é").is_err());
+    }
+
+    #[test]
+    fn test_job_type_stats() {
+        let model = JobRunStatsModel {
+            job_type: Some("Notification".to_string()),
+            total: 48,
+            succeeded: 36,
+            failed: 4,
+            cancelled: 8,
+            retries: 6,
+            p50_ms: Some(120.0),
+            p95_ms: Some(900.0),
+            p99_ms: Some(1500.0),
+        };
+
+        let stats = JobTypeStats::from_model(model, 86_400);
+        assert_eq!(stats.throughput_per_hour, 2.0);
+        assert_eq!(stats.failure_rate, 0.1);
+        assert_eq!(stats.duration_ms.p95, Some(900.0));
+
+        let idle = JobRunStatsModel {
+            job_type: None,
+            total: 0,
+            succeeded: 0,
+            failed: 0,
+            cancelled: 0,
+            retries: 0,
+            p50_ms: None,
+            p95_ms: None,
+            p99_ms: None,
+        };
+        let stats = JobTypeStats::from_model(idle, 3600);
+        assert_eq!(stats.failure_rate, 0.0);
+        assert!(serde_json::to_value(&stats).unwrap().get("job_type").is_none());
+    }
+
+    fn dead_letter(payload: Value) -> DeadLetterModel {
+        DeadLetterModel {
+            job_id: uuid::Uuid::new_v4(),
+            job_type: "Backup".to_string(),
+            queue: "long_running".to_string(),
+            payload,
+            attempts: 4,
+            last_error: "External service error: S3 unavailable".to_string(),
+            failed_at: chrono::Utc::now(),
+            replay_count: 0,
+            replayed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_submission() {
+        let letter = dead_letter(json!({"type": "Backup", "backup_id": uuid::Uuid::nil(), "schemas": ["emr"]}));
+
+        let submission = serde_json::to_value(replay_submission(&letter).unwrap()).unwrap();
+        assert_eq!(submission["job_id"], json!(letter.job_id));
+        assert_eq!(submission["type"], "Backup");
+        assert_eq!(submission["schemas"], json!(["emr"]));
+
+        assert!(replay_submission(&dead_letter(Value::Null)).is_err());
+    }
+
+    #[test]
+    fn test_replay_outcome_serialization() {
+        let job_id = uuid::Uuid::nil();
+        let replayed = serde_json::to_value(ReplayOutcome::new(job_id, "replayed")).unwrap();
+        assert!(replayed.get("error").is_none());
+
+        let failed = serde_json::to_value(ReplayOutcome::failed(job_id, &ApiError::internal_error("boom"))).unwrap();
+        assert_eq!(failed["status"], "failed");
+        assert!(failed["error"].as_str().unwrap().contains("boom"));
+    }
+
+    #[test]
+    fn test_progress_frame() {
+        let frame = SseFrame::from_payload(br#"{"event":"progress","progress":42.0}"#);
+        assert!(!frame.is_terminal());
+
+        let bytes = frame.to_bytes();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        assert!(text.starts_with("event: progress\n"));
+        assert!(text.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn test_enveloped_frame() {
+        let envelope = EventEnvelope::with_type(JobEvent::EVENT_TYPE, 1, json!({"event": "status", "status": "Failed"}));
+        let frame = SseFrame::from_payload(&serde_json::to_vec(&envelope).unwrap());
+        assert!(frame.is_terminal());
+
+        let bytes = frame.to_bytes();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        assert!(text.starts_with("event: status\n"));
+        assert!(!text.contains("payload"));
+    }
+
+    #[test]
+    fn test_terminal_status_frame() {
+        let frame = SseFrame::from_payload(br#"{"event":"status","status":"Completed"}"#);
+        assert!(frame.is_terminal());
+
+        let frame = SseFrame::from_payload(br#"{"event":"status","status":"Running"}"#);
+        assert!(!frame.is_terminal());
+    }
+
+    #[test]
+    fn test_malformed_payload_frame() {
+        let frame = SseFrame::from_payload(b"not json");
+        assert!(!frame.is_terminal());
+        assert!(std::str::from_utf8(&frame.to_bytes()).unwrap().starts_with("event: message\n"));
+    }
+}
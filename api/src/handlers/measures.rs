@@ -0,0 +1,322 @@
+//! Clinical quality measure endpoints
+//!
+//! Measures (see [`QualityMeasure`]) are defined from panel criteria and
+//! computed per reporting period by the jobs worker:
+//! `POST /measures/reports` submits a quality Analytics job and answers with
+//! its id, which `/jobs/{id}/events` follows. Reports keep each patient's
+//! populations; `GET /measure-reports/{id}/patients` drills down into one
+//! population, or with `gaps=true` into the denominator patients that did
+//! not meet the measure, showing only patients the caller has a treating
+//! relationship with.
+
+use actix_web::{get, post, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{ImprovementNotation, MeasurePopulation, MeasureReport, PanelCriterion, QualityMeasure};
+use emr_core::types::Id;
+use emr_jobs::types::{AnalyticsJob, AnalyticsType, DateRange, JobSubmission, JobType};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::repositories::{MeasureRepository, SearchPatientFilter};
+use crate::AppState;
+
+/// Most measures computed by one request
+const MAX_REPORT_MEASURES: usize = 50;
+
+/// New measure
+#[derive(Debug, Deserialize)]
+pub struct CreateMeasureRequest {
+    pub identifier: String,
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(default = "default_improvement")]
+    pub improvement: ImprovementNotation,
+    pub initial_population: Vec<PanelCriterion>,
+    #[serde(default)]
+    pub denominator: Vec<PanelCriterion>,
+    #[serde(default)]
+    pub denominator_exclusions: Vec<PanelCriterion>,
+    pub numerator: Vec<PanelCriterion>,
+}
+
+fn default_improvement() -> ImprovementNotation {
+    ImprovementNotation::Increase
+}
+
+/// Measures to compute for a reporting period; all active measures when
+/// `measure_ids` is empty
+#[derive(Debug, Deserialize)]
+pub struct MeasureReportRequest {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    #[serde(default)]
+    pub measure_ids: Vec<Id>,
+}
+
+/// Report with its performance rate
+#[derive(Debug, Serialize)]
+pub struct MeasureReportView {
+    #[serde(flatten)]
+    pub report: MeasureReport,
+    pub performance_rate: Option<f64>,
+}
+
+impl From<MeasureReport> for MeasureReportView {
+    fn from(report: MeasureReport) -> Self {
+        Self {
+            performance_rate: report.performance_rate(),
+            report,
+        }
+    }
+}
+
+/// Report drill-down
+#[derive(Debug, Deserialize)]
+pub struct MeasurePatientsQuery {
+    /// Population listed; defaults to the numerator
+    pub population: Option<String>,
+    /// List the denominator patients who did not meet the measure instead
+    #[serde(default)]
+    pub gaps: bool,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Practitioner a request acts for; `None` for unauthenticated requests,
+/// which still pass until the auth middleware rejects them
+/// (TODO(nexus-phase2))
+fn measure_practitioner(context: Option<&AuthContext>) -> Result<Option<Id>> {
+    let Some(context) = context else {
+        return Ok(None);
+    };
+    if context.is_patient_session() {
+        return Err(ApiError::authorization_error("Quality measures are not available in the portal"));
+    }
+    context
+        .practitioner_id()
+        .map(Some)
+        .ok_or_else(|| ApiError::authorization_error("Only practitioners can use quality measures"))
+}
+
+/// Quality Analytics job computing measures for a reporting period
+fn measure_job(job_id: Id, request: &MeasureReportRequest) -> JobSubmission {
+    JobSubmission {
+        job_id: Some(job_id),
+        idempotency_key: None,
+        job: JobType::Analytics(AnalyticsJob {
+            analytics_type: AnalyticsType::Quality,
+            date_range: DateRange {
+                start: request.period_start,
+                end: request.period_end,
+            },
+            dimensions: Vec::new(),
+            metrics: Vec::new(),
+            output_location: String::new(),
+            measure_ids: request.measure_ids.clone(),
+        }),
+    }
+}
+
+/// Define a measure
+#[post("/measures")]
+pub async fn create_measure(
+    request: web::Json<CreateMeasureRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    measure_practitioner(req.extensions().get::<AuthContext>())?;
+    let request = request.into_inner();
+    let mut measure = QualityMeasure::new(
+        &request.identifier,
+        &request.title,
+        request.initial_population,
+        request.numerator,
+    );
+    measure.description = request.description;
+    measure.improvement = request.improvement;
+    measure.denominator = request.denominator;
+    measure.denominator_exclusions = request.denominator_exclusions;
+    measure.validate()?;
+
+    if !MeasureRepository::new().insert(&data.db_pool, &measure).await? {
+        return Err(ApiError::conflict(&format!("Measure {} already exists", measure.identifier)));
+    }
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(measure)))
+}
+
+/// Measures, by identifier
+#[get("/measures")]
+pub async fn list_measures(
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    measure_practitioner(req.extensions().get::<AuthContext>())?;
+    let (page, per_page) = query.normalize();
+
+    let measures = MeasureRepository::new()
+        .list(&data.db_pool, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        measures,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// A measure
+#[get("/measures/{id}")]
+pub async fn get_measure(path: web::Path<Id>, req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    measure_practitioner(req.extensions().get::<AuthContext>())?;
+    let id = path.into_inner();
+
+    let measure = MeasureRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Measure {} not found", id)))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(measure)))
+}
+
+/// Compute measures for a reporting period in the background
+#[post("/measures/reports")]
+pub async fn compute_measure_reports(
+    request: web::Json<MeasureReportRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    measure_practitioner(req.extensions().get::<AuthContext>())?;
+    let request = request.into_inner();
+    if request.period_start >= request.period_end {
+        return Err(ApiError::validation_error("Reporting periods must end after they start"));
+    }
+    if request.measure_ids.len() > MAX_REPORT_MEASURES {
+        return Err(ApiError::validation_error(&format!(
+            "At most {} measures are computed per request",
+            MAX_REPORT_MEASURES
+        )));
+    }
+
+    let job_id = uuid::Uuid::new_v4();
+    submit_job(&data, &measure_job(job_id, &request)).await?;
+    tracing::info!(job_id = %job_id, measures = request.measure_ids.len(), "Quality measure job submitted");
+
+    Ok(HttpResponse::Accepted().json(ApiResponse::new(json!({ "job_id": job_id }))))
+}
+
+/// A measure's reports, latest reporting period first
+#[get("/measures/{id}/reports")]
+pub async fn measure_reports(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    measure_practitioner(req.extensions().get::<AuthContext>())?;
+    let (page, per_page) = query.normalize();
+
+    let reports: Vec<MeasureReportView> = MeasureRepository::new()
+        .reports(&data.db_pool, path.into_inner(), query.limit(), query.offset())
+        .await?
+        .into_iter()
+        .map(MeasureReportView::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        reports,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Patients of a report in a population, or its care gaps, by name
+#[get("/measure-reports/{id}/patients")]
+pub async fn measure_report_patients(
+    path: web::Path<Id>,
+    query: web::Query<MeasurePatientsQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = measure_practitioner(req.extensions().get::<AuthContext>())?;
+    let query = query.into_inner();
+    let population = match query.population.as_deref() {
+        None => MeasurePopulation::Numerator,
+        Some(population) => MeasurePopulation::parse(population)
+            .ok_or_else(|| ApiError::validation_error(&format!("Unknown population '{}'", population)))?,
+    };
+    let id = path.into_inner();
+    let repository = MeasureRepository::new();
+    let report = repository
+        .find_report(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Measure report {} not found", id)))?;
+
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+    let patients = practitioner_id.map_or(SearchPatientFilter::All, SearchPatientFilter::TreatedBy);
+    let found = repository
+        .patients(
+            &data.db_pool,
+            report.id,
+            population,
+            query.gaps,
+            patients,
+            pagination.limit(),
+            pagination.offset(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        found,
+        json!({
+            "page": page,
+            "per_page": per_page,
+            "population": if query.gaps { "gaps" } else { population.as_str() },
+            "report": MeasureReportView::from(report),
+        }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_measure_job() {
+        let request = MeasureReportRequest {
+            period_start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            period_end: Utc.with_ymd_and_hms(2024, 12, 31, 23, 59, 59).unwrap(),
+            measure_ids: vec![uuid::Uuid::nil()],
+        };
+        let job = serde_json::to_value(measure_job(uuid::Uuid::nil(), &request)).unwrap();
+        assert_eq!(job["type"], "Analytics");
+        assert_eq!(job["analytics_type"], "Quality");
+        assert_eq!(job["date_range"]["end"], "2024-12-31T23:59:59Z");
+        assert_eq!(job["measure_ids"][0], "00000000-0000-0000-0000-000000000000");
+    }
+
+    #[test]
+    fn test_report_view_includes_rate() {
+        let report = MeasureReport {
+            id: uuid::Uuid::new_v4(),
+            measure_id: uuid::Uuid::new_v4(),
+            period_start: Utc::now(),
+            period_end: Utc::now(),
+            initial_population: 12,
+            denominator: 10,
+            denominator_exclusions: 2,
+            numerator: 2,
+            computed_at: Utc::now(),
+        };
+        let view = serde_json::to_value(MeasureReportView::from(report)).unwrap();
+        assert_eq!(view["performance_rate"], 0.25);
+        assert_eq!(view["numerator"], 2);
+    }
+}
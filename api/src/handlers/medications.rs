@@ -0,0 +1,382 @@
+//! Medication orders and the medication administration record (MAR)
+//!
+//! Prescribers place `MedicationRequest`s; nurses document each dose given,
+//! held or refused against its order at
+//! `POST /medication-requests/{id}/administrations`. A scheduled dose must be
+//! given within the configured window (`mar.window_minutes`) of its
+//! scheduled time and is documented once. When the nurse scans the patient's
+//! wristband and the medication package, the scan is checked against the
+//! patient's identifiers and the order's products; without a scan a given
+//! dose needs a documented override while `mar.require_barcode` is set.
+//! `GET /encounters/{id}/mar` is the MAR grid for an encounter.
+//!
+//! Placing an order also looks the medication up in the formulary of the
+//! patient's drug plan and returns the coverage tier, prior authorization
+//! requirement and covered alternatives alongside the order. The lookup only
+//! informs the prescriber: when the formulary cannot be reached the order is
+//! still placed, with `formulary` left null.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{
+    mar, AdministrationOutcome, BarcodeScan, MedicationAdministration, MedicationRequest, MedicationRequestStatus,
+};
+use emr_core::services::barcode::{BarcodeVerifier, IdentifierBarcodeVerifier, PatientIdentity};
+use emr_core::services::EncounterService;
+use emr_core::types::Id;
+use emr_fhir::{medication_administration_to_fhir, medication_request_to_fhir};
+use serde::Deserialize;
+use serde_json::json;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::{MedicationAdministrationRepository, MedicationRequestRepository, PatientRepository};
+use crate::services::formulary_provider;
+use crate::AppState;
+
+/// Longest period one MAR request covers
+const MAX_MAR_DAYS: i64 = 7;
+
+/// New medication order
+#[derive(Debug, Deserialize)]
+pub struct CreateMedicationRequest {
+    pub patient_id: Id,
+    pub encounter_id: Option<Id>,
+    /// RxNorm code
+    pub medication_code: String,
+    pub medication_display: Option<String>,
+    /// NDC or GTIN of the products that fill the order
+    #[serde(default)]
+    pub product_identifiers: Vec<String>,
+    pub dose_quantity: f64,
+    pub dose_unit: String,
+    pub route: Option<String>,
+    /// Hours between doses; the minimum interval for as-needed orders
+    pub frequency_hours: Option<u32>,
+    #[serde(default)]
+    pub as_needed: bool,
+    /// First dose; defaults to now
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// Practitioner placing the order
+    pub requester_id: Id,
+    pub reason: Option<String>,
+    /// Save as a draft instead of placing the order
+    #[serde(default)]
+    pub draft: bool,
+    /// The patient's drug plan, for the formulary lookup
+    pub plan_id: Option<String>,
+}
+
+/// Status change
+#[derive(Debug, Deserialize)]
+pub struct MedicationRequestStatusRequest {
+    pub status: MedicationRequestStatus,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// A dose to document
+#[derive(Debug, Deserialize)]
+pub struct AdministrationRequest {
+    /// Clinician documenting the dose
+    pub performer_id: Id,
+    pub outcome: AdministrationOutcome,
+    /// Scheduled time of the dose; leave out for as-needed doses
+    pub scheduled_for: Option<DateTime<Utc>>,
+    /// When the dose was given, held or refused; defaults to now
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub dose_quantity: Option<f64>,
+    pub dose_unit: Option<String>,
+    pub reason: Option<String>,
+    pub note: Option<String>,
+    /// Wristband and package barcodes scanned at the bedside
+    pub scan: Option<BarcodeScan>,
+    /// Why the dose was given without a scan
+    pub barcode_override: Option<String>,
+}
+
+/// MAR period; defaults to the 12 hours either side of now
+#[derive(Debug, Deserialize)]
+pub struct MarQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+async fn find_medication_request(data: &AppState, id: Id) -> Result<MedicationRequest> {
+    MedicationRequestRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Medication request {} not found", id)))
+}
+
+/// MAR period from the query
+fn mar_period(query: &MarQuery, now: DateTime<Utc>) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let from = query.from.unwrap_or(now - Duration::hours(12));
+    let to = query.to.unwrap_or(from + Duration::hours(24));
+    if to <= from || to - from > Duration::days(MAX_MAR_DAYS) {
+        return Err(ApiError::bad_request(&format!(
+            "The MAR period must end after it starts and span at most {} days",
+            MAX_MAR_DAYS
+        )));
+    }
+    Ok((from, to))
+}
+
+/// Place (or draft) a medication order
+#[post("/medication-requests")]
+pub async fn create_medication_request(
+    request: web::Json<CreateMedicationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut order = MedicationRequest::new(
+        request.patient_id,
+        request.requester_id,
+        &request.medication_code,
+        request.dose_quantity,
+        &request.dose_unit,
+        request.frequency_hours.unwrap_or_default(),
+        request.start.unwrap_or_else(Utc::now),
+    );
+    order.encounter_id = request.encounter_id;
+    order.medication_display = request.medication_display;
+    order.product_identifiers = request.product_identifiers;
+    order.route = request.route;
+    order.frequency_hours = request.frequency_hours;
+    order.as_needed = request.as_needed;
+    order.end = request.end;
+    order.reason = request.reason;
+    if request.draft {
+        order.status = MedicationRequestStatus::Draft;
+    }
+    order.validate()?;
+
+    MedicationRequestRepository::new().insert(&data.db_pool, &order).await?;
+
+    let formulary = match formulary_provider(&data.config.formulary)?
+        .lookup(request.plan_id.as_deref(), &order.medication_code)
+        .await
+    {
+        Ok(status) => status,
+        Err(error) => {
+            tracing::warn!(order_id = %order.metadata.id, %error, "Formulary lookup failed");
+            None
+        }
+    };
+
+    Ok(HttpResponse::Created().json(ApiResponse::with_meta(order, json!({ "formulary": formulary }))))
+}
+
+/// A medication order
+#[get("/medication-requests/{id}")]
+pub async fn get_medication_request(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let order = find_medication_request(&data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(order)))
+}
+
+/// A medication order as a FHIR `MedicationRequest`
+#[get("/medication-requests/{id}/fhir")]
+pub async fn get_medication_request_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let order = find_medication_request(&data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &medication_request_to_fhir(&order))
+}
+
+/// Change a medication order's status
+#[post("/medication-requests/{id}/status")]
+pub async fn update_medication_request_status(
+    path: web::Path<Id>,
+    request: web::Json<MedicationRequestStatusRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut order = find_medication_request(&data, path.into_inner()).await?;
+    let previous_version = order.metadata.version;
+    if request.version != previous_version {
+        return Err(ApiError::conflict(
+            "The medication order changed since it was loaded; reload it and try again",
+        ));
+    }
+
+    order.transition(request.status)?;
+    let stored = MedicationRequestRepository::new()
+        .update(&data.db_pool, &order, previous_version)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict(
+            "The medication order was changed by another request; reload it and try again",
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(order)))
+}
+
+/// A patient's medication orders, newest first
+#[get("/patients/{id}/medication-requests")]
+pub async fn list_patient_medication_requests(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let (page, per_page) = query.normalize();
+
+    let orders = MedicationRequestRepository::new()
+        .for_patient(&data.db_pool, patient_id, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        orders,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Document a dose given, held or refused
+#[post("/medication-requests/{id}/administrations")]
+pub async fn record_administration(
+    path: web::Path<Id>,
+    request: web::Json<AdministrationRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let order = find_medication_request(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, order.patient_id).await?;
+    let request = request.into_inner();
+    let now = Utc::now();
+
+    let mut administration = MedicationAdministration::new(
+        &order,
+        request.performer_id,
+        request.outcome,
+        request.scheduled_for,
+        request.occurred_at.unwrap_or(now),
+    );
+    administration.dose_quantity = request.dose_quantity;
+    administration.dose_unit = request.dose_unit;
+    administration.reason = request.reason;
+    administration.note = request.note;
+    administration.barcode_override = request.barcode_override;
+    if let Some(scan) = &request.scan {
+        let patient = PatientIdentity {
+            patient_id: order.patient_id,
+            identifiers: PatientRepository::new()
+                .identifier_values(&data.db_pool, order.patient_id)
+                .await?,
+        };
+        administration.barcode = Some(IdentifierBarcodeVerifier.verify(scan, &patient, &order, now)?);
+    }
+
+    let repository = MedicationAdministrationRepository::new();
+    let previous = repository.for_request(&data.db_pool, order.metadata.id).await?;
+    administration.check(&order, &previous, &data.config.mar.policy(), now)?;
+    if !repository.insert(&data.db_pool, &administration).await? {
+        return Err(ApiError::conflict("This dose has already been documented"));
+    }
+    tracing::info!(
+        medication_request_id = %order.metadata.id,
+        administration_id = %administration.metadata.id,
+        outcome = administration.outcome.as_str(),
+        scanned = administration.barcode.is_some(),
+        "Medication administration recorded"
+    );
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(administration)))
+}
+
+/// A documented dose as a FHIR `MedicationAdministration`
+#[get("/medication-administrations/{id}/fhir")]
+pub async fn get_administration_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    let administration = MedicationAdministrationRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Medication administration {} not found", id)))?;
+    authorize_patient_access(&req, &data, administration.patient_id).await?;
+    let order = find_medication_request(&data, administration.request_id).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &medication_administration_to_fhir(&administration, &order))
+}
+
+/// The MAR for an encounter: each order with its doses in the period
+#[get("/encounters/{id}/mar")]
+pub async fn get_encounter_mar(
+    path: web::Path<Id>,
+    query: web::Query<MarQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let encounter_id = path.into_inner();
+    let encounter = data
+        .encounters
+        .get_encounter(encounter_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Encounter {} not found", encounter_id)))?;
+    authorize_patient_access(&req, &data, encounter.subject).await?;
+    let now = Utc::now();
+    let (from, to) = mar_period(&query, now)?;
+
+    let orders = MedicationRequestRepository::new()
+        .for_encounter(&data.db_pool, encounter_id)
+        .await?;
+    let administrations = MedicationAdministrationRepository::new()
+        .for_encounter_requests(&data.db_pool, encounter_id)
+        .await?;
+    let rows = mar(orders, &administrations, from, to, &data.config.mar.policy(), now);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        rows,
+        json!({ "from": from, "to": to }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mar_period() {
+        let now = Utc::now();
+        let (from, to) = mar_period(&MarQuery { from: None, to: None }, now).unwrap();
+        assert_eq!((from, to), (now - Duration::hours(12), now + Duration::hours(12)));
+
+        let too_long = MarQuery {
+            from: Some(now),
+            to: Some(now + Duration::days(8)),
+        };
+        assert!(mar_period(&too_long, now).is_err());
+        assert!(mar_period(&MarQuery { from: Some(now), to: Some(now) }, now).is_err());
+    }
+
+    #[test]
+    fn test_administration_request() {
+        let request: AdministrationRequest = serde_json::from_value(json!({
+            "performer_id": uuid::Uuid::new_v4(),
+            "outcome": "refused",
+            "reason": "Patient declined",
+            "scan": {"patient": "MRN-0042", "medication": "00350580413045"}
+        }))
+        .unwrap();
+
+        assert_eq!(request.outcome, AdministrationOutcome::Refused);
+        assert!(request.scheduled_for.is_none());
+        assert_eq!(request.scan.unwrap().patient, "MRN-0042");
+    }
+}
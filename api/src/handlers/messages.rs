@@ -0,0 +1,274 @@
+//! Secure messaging endpoints
+//!
+//! Practitioners and patients exchange `Communication` messages in threads
+//! about one patient. Clinicians use the `/messages/threads` routes below;
+//! patients use the `/portal/messages` routes, which share the helpers here.
+//! Every recipient gets a Notification job that says a message is waiting
+//! without including its content, and a thread is marked read for its
+//! sender when they post to it.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{Communication, CommunicationParty};
+use emr_core::notifications::{TemplateRef, SECURE_MESSAGE_TEMPLATE};
+use emr_core::types::Id;
+use emr_jobs::types::{JobSubmission, JobType, NotificationChannel, NotificationJob, NotificationType, Priority};
+use serde::Deserialize;
+use serde_json::json;
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::repositories::CommunicationRepository;
+use crate::AppState;
+
+/// New thread started by a practitioner
+#[derive(Debug, Deserialize)]
+pub struct StartThreadRequest {
+    /// Sending practitioner
+    pub practitioner_id: Id,
+    pub patient_id: Id,
+    /// Other practitioners to include
+    #[serde(default)]
+    pub cc: Vec<Id>,
+    pub topic: Option<String>,
+    pub body: String,
+    /// DocumentReference ids of the patient's documents
+    #[serde(default)]
+    pub attachment_ids: Vec<Id>,
+}
+
+/// Reply from a practitioner
+#[derive(Debug, Deserialize)]
+pub struct ReplyRequest {
+    pub practitioner_id: Id,
+    pub body: String,
+    #[serde(default)]
+    pub attachment_ids: Vec<Id>,
+}
+
+/// Practitioner reading their threads
+#[derive(Debug, Deserialize)]
+pub struct PractitionerQuery {
+    pub practitioner_id: Id,
+    pub patient_id: Option<Id>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Notification job telling a recipient a message is waiting
+///
+/// Patients are emailed; practitioners see it in the app. The template never
+/// includes the message.
+fn notification_job(message: &Communication, recipient: CommunicationParty) -> JobSubmission {
+    let channel = match recipient {
+        CommunicationParty::Patient(_) => NotificationChannel::Email,
+        CommunicationParty::Practitioner(_) => NotificationChannel::InApp,
+    };
+
+    JobSubmission {
+        job_id: None,
+        idempotency_key: Some(format!("communication:{}:{}", message.id, recipient.id())),
+        job: JobType::Notification(NotificationJob {
+            recipient_id: recipient.id(),
+            address: None,
+            notification_type: NotificationType::Update,
+            message: String::new(),
+            template: Some(TemplateRef {
+                name: SECURE_MESSAGE_TEMPLATE.to_string(),
+                locale: None,
+                variables: Default::default(),
+            }),
+            channel,
+            priority: Priority::Normal,
+            scheduled_for: None,
+            digest: false,
+        }),
+    }
+}
+
+/// Validate, store and announce a message
+pub(crate) async fn send_message(data: &AppState, message: Communication) -> Result<Communication> {
+    message.validate()?;
+
+    let repository = CommunicationRepository::new();
+    if !message.attachments.is_empty() {
+        let found = repository
+            .count_patient_documents(&data.db_pool, message.subject, message.attachments.clone())
+            .await?;
+        if found != message.attachments.len() as i64 {
+            return Err(ApiError::validation_error(
+                "Attachments must be current documents of the thread's patient",
+            ));
+        }
+    }
+
+    repository.insert(&data.db_pool, &message).await?;
+    repository
+        .mark_read(&data.db_pool, message.thread_id, message.sender, message.sent)
+        .await?;
+
+    for recipient in &message.recipients {
+        // The message is stored; a missed notification only delays reading it
+        if let Err(error) = submit_job(data, &notification_job(&message, *recipient)).await {
+            tracing::warn!(message_id = %message.id, %error, "Failed to submit message notification");
+        }
+    }
+
+    Ok(message)
+}
+
+/// Messages of a thread the party takes part in, oldest first
+pub(crate) async fn load_thread(data: &AppState, thread_id: Id, party: CommunicationParty) -> Result<Vec<Communication>> {
+    let messages = CommunicationRepository::new().thread(&data.db_pool, thread_id).await?;
+
+    match messages.first() {
+        Some(first) if first.participants().contains(&party) => Ok(messages),
+        _ => Err(ApiError::not_found(&format!("Message thread {} not found", thread_id))),
+    }
+}
+
+/// Reply to the latest message of a thread
+pub(crate) async fn reply_to_thread(
+    data: &AppState,
+    thread_id: Id,
+    sender: CommunicationParty,
+    body: &str,
+    attachment_ids: Vec<Id>,
+) -> Result<Communication> {
+    let thread = load_thread(data, thread_id, sender).await?;
+    let latest = thread
+        .last()
+        .ok_or_else(|| ApiError::not_found(&format!("Message thread {} not found", thread_id)))?;
+
+    let message = latest.reply(sender, body)?.with_attachments(attachment_ids);
+    send_message(data, message).await
+}
+
+/// Mark a thread read up to its latest message
+pub(crate) async fn mark_thread_read(data: &AppState, thread_id: Id, party: CommunicationParty) -> Result<()> {
+    let thread = load_thread(data, thread_id, party).await?;
+    if let Some(latest) = thread.last() {
+        CommunicationRepository::new()
+            .mark_read(&data.db_pool, thread_id, party, latest.sent)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Start a thread with a patient
+#[post("/messages/threads")]
+pub async fn start_thread(
+    request: web::Json<StartThreadRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+
+    let mut recipients = vec![CommunicationParty::Patient(request.patient_id)];
+    recipients.extend(request.cc.into_iter().map(CommunicationParty::Practitioner));
+    let message = Communication::new_thread(
+        request.patient_id,
+        CommunicationParty::Practitioner(request.practitioner_id),
+        recipients,
+        request.topic,
+        &request.body,
+    )
+    .with_attachments(request.attachment_ids);
+
+    let message = send_message(&data, message).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::new(message)))
+}
+
+/// A practitioner's threads, most recently active first, with unread counts
+#[get("/messages/threads")]
+pub async fn list_threads(
+    query: web::Query<PractitionerQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+
+    let threads = CommunicationRepository::new()
+        .threads(
+            &data.db_pool,
+            CommunicationParty::Practitioner(query.practitioner_id),
+            query.patient_id,
+            pagination.limit(),
+            pagination.offset(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        threads,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Messages of a thread, oldest first
+#[get("/messages/threads/{id}")]
+pub async fn get_thread(
+    path: web::Path<Id>,
+    query: web::Query<PractitionerQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let party = CommunicationParty::Practitioner(query.practitioner_id);
+    let messages = load_thread(&data, path.into_inner(), party).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(messages)))
+}
+
+/// Reply in a thread
+#[post("/messages/threads/{id}/replies")]
+pub async fn reply(
+    path: web::Path<Id>,
+    request: web::Json<ReplyRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let sender = CommunicationParty::Practitioner(request.practitioner_id);
+
+    let message = reply_to_thread(&data, path.into_inner(), sender, &request.body, request.attachment_ids).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::new(message)))
+}
+
+/// Mark a thread read
+#[post("/messages/threads/{id}/read")]
+pub async fn mark_read(
+    path: web::Path<Id>,
+    query: web::Query<PractitionerQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let party = CommunicationParty::Practitioner(query.practitioner_id);
+    mark_thread_read(&data, path.into_inner(), party).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_job_hides_content() {
+        let patient = CommunicationParty::Patient(uuid::Uuid::new_v4());
+        let doctor = CommunicationParty::Practitioner(uuid::Uuid::new_v4());
+        let message = Communication::new_thread(patient.id(), doctor, vec![patient], None, "Your biopsy results");
+
+        let job = serde_json::to_value(notification_job(&message, patient)).unwrap();
+        assert_eq!(job["type"], "Notification");
+        assert_eq!(job["channel"], "Email");
+        assert_eq!(job["recipient_id"], json!(patient.id()));
+        assert_eq!(job["template"]["name"], SECURE_MESSAGE_TEMPLATE);
+        assert_eq!(job["template"]["variables"], json!({}));
+        assert!(!job.to_string().contains("biopsy"));
+        let job = serde_json::to_value(notification_job(&message, doctor)).unwrap();
+        assert_eq!(job["channel"], "InApp");
+    }
+}
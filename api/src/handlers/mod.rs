@@ -4,10 +4,359 @@ pub mod health;
 pub mod patients;
 pub mod fhir;
 pub mod auth;
+pub mod jobs;
+pub mod practitioners;
+pub mod organizations;
+pub mod encounters;
+pub mod observations;
+pub mod photos;
+pub mod verification;
+pub mod retention;
+pub mod database;
+pub mod webhooks;
+pub mod provenance;
+pub mod validation_profiles;
+pub mod portal;
+pub mod messages;
+pub mod notification_templates;
+pub mod notification_preferences;
+pub mod notifications;
+pub mod clinical_notes;
+pub mod signatures;
+pub mod orders;
+pub mod acknowledgments;
+pub mod care_teams;
+pub mod referrals;
+pub mod questionnaires;
+pub mod calculators;
+pub mod growth;
+pub mod medications;
+pub mod coding;
+pub mod coverages;
+pub mod billing;
+pub mod charges;
+pub mod imaging;
+pub mod documents;
+pub mod search;
+pub mod panels;
+pub mod measures;
+pub mod reports;
+pub mod report_schedules;
+pub mod flags;
+pub mod disclosures;
 
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use validator::Validate;
+use emr_jobs::types::JobSubmission;
+use emr_jobs::worker::SUBMIT_SUBJECT;
 use crate::error::{ApiError, Result};
+use crate::AppState;
+
+/// Register the API routes, mounted under `/api/v1` and, deprecated, under
+/// `/api` (see `middleware::versioning`)
+///
+/// Each route is its own resource and resources are tried in the order they
+/// are registered, so a static segment comes before a parameter that would
+/// match it (`/patients/export` before `/patients/{id}`).
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    // Patients
+    cfg.service(patients::bulk_create_patients)
+        .service(patients::export_patients)
+        .service(patients::get_patient)
+        .service(patients::patient_everything)
+        .service(patients::get_patient_summary)
+        .service(patients::list_patients)
+        .service(patients::create_patient)
+        .service(patients::update_patient)
+        .service(patients::delete_patient);
+
+    // Authentication
+    cfg.service(auth::authorize)
+        .service(auth::token);
+
+    // Jobs
+    cfg.service(jobs::job_stats)
+        .service(jobs::job_events)
+        .service(jobs::cancel_job)
+        .service(jobs::list_dead_letters)
+        .service(jobs::get_dead_letter)
+        .service(jobs::replay_dead_letters);
+
+    // Practitioners
+    cfg.service(practitioners::list_practitioners)
+        .service(practitioners::get_practitioner)
+        .service(practitioners::create_practitioner)
+        .service(practitioners::update_practitioner)
+        .service(practitioners::delete_practitioner)
+        .service(practitioners::add_qualification)
+        .service(practitioners::remove_qualification);
+
+    // Organizations
+    cfg.service(organizations::list_organizations)
+        .service(organizations::get_organization)
+        .service(organizations::get_organization_hierarchy)
+        .service(organizations::create_organization)
+        .service(organizations::update_organization)
+        .service(organizations::delete_organization);
+
+    // Encounters
+    cfg.service(encounters::list_encounters)
+        .service(encounters::get_encounter)
+        .service(encounters::get_encounter_view)
+        .service(encounters::create_encounter)
+        .service(encounters::update_encounter)
+        .service(encounters::check_in_encounter)
+        .service(encounters::start_encounter)
+        .service(encounters::end_encounter)
+        .service(encounters::assign_participant)
+        .service(encounters::assign_location);
+
+    // Observations
+    cfg.service(observations::list_observations)
+        .service(observations::get_observation)
+        .service(observations::stream_waveform)
+        .service(observations::create_observation)
+        .service(observations::bulk_create_observations)
+        .service(observations::list_stored_observations)
+        .service(observations::update_observation)
+        .service(observations::record_vitals);
+
+    // Photos
+    cfg.service(photos::upload_photo);
+
+    // Contact verification
+    cfg.service(verification::send_verification)
+        .service(verification::confirm_verification);
+
+    // Retention
+    cfg.service(retention::list_retention_policies)
+        .service(retention::retention_report);
+
+    // Database pool
+    cfg.service(database::get_pool_stats)
+        .service(database::resize_pool);
+
+    // Webhooks
+    cfg.service(webhooks::create_webhook)
+        .service(webhooks::list_webhooks)
+        .service(webhooks::delete_webhook)
+        .service(webhooks::list_webhook_deliveries)
+        .service(webhooks::redeliver_webhook);
+
+    // Validation profiles
+    cfg.service(validation_profiles::list_validation_profiles)
+        .service(validation_profiles::list_validation_profile_versions)
+        .service(validation_profiles::save_validation_profile)
+        .service(validation_profiles::dry_run_validation_profile)
+        .service(validation_profiles::run_validation_profile);
+
+    // Patient portal
+    cfg.service(portal::portal_login)
+        .service(portal::portal_demographics)
+        .service(portal::portal_appointments)
+        .service(portal::portal_observations)
+        .service(portal::portal_documents)
+        .service(portal::portal_threads)
+        .service(portal::portal_thread)
+        .service(portal::portal_send_message)
+        .service(portal::portal_reply)
+        .service(portal::portal_mark_read)
+        .service(portal::portal_notification_preferences)
+        .service(portal::portal_update_notification_preferences);
+
+    // Messaging
+    cfg.service(messages::start_thread)
+        .service(messages::list_threads)
+        .service(messages::get_thread)
+        .service(messages::reply)
+        .service(messages::mark_read);
+
+    // Notification templates
+    cfg.service(notification_templates::list_notification_templates)
+        .service(notification_templates::preview_notification_template);
+
+    // Notification preferences
+    cfg.service(notification_preferences::get_notification_preferences)
+        .service(notification_preferences::update_notification_preferences);
+
+    // Notifications
+    cfg.service(notifications::list_notifications)
+        .service(notifications::unread_count)
+        .service(notifications::mark_notification_read)
+        .service(notifications::mark_all_notifications_read)
+        .service(notifications::notification_stream);
+
+    // Clinical notes
+    cfg.service(clinical_notes::create_note)
+        .service(clinical_notes::get_note)
+        .service(clinical_notes::list_patient_notes)
+        .service(clinical_notes::save_draft)
+        .service(clinical_notes::list_note_versions)
+        .service(clinical_notes::sign_note)
+        .service(clinical_notes::add_addendum)
+        .service(clinical_notes::verify_note_signatures)
+        .service(clinical_notes::get_note_fhir);
+
+    // Orders
+    cfg.service(orders::create_order)
+        .service(orders::get_order)
+        .service(orders::get_order_fhir)
+        .service(orders::list_patient_orders)
+        .service(orders::pending_orders)
+        .service(orders::update_order_status)
+        .service(orders::fulfil_orders);
+
+    // Result acknowledgment
+    cfg.service(acknowledgments::list_acknowledgments)
+        .service(acknowledgments::get_acknowledgment_fhir)
+        .service(acknowledgments::acknowledge_result)
+        .service(acknowledgments::acknowledgment_latency);
+
+    // Care teams
+    cfg.service(care_teams::create_care_team)
+        .service(care_teams::list_care_teams)
+        .service(care_teams::get_care_team)
+        .service(care_teams::get_care_team_fhir)
+        .service(care_teams::add_care_team_participant)
+        .service(care_teams::end_care_team_participation)
+        .service(care_teams::request_emergency_access)
+        .service(care_teams::check_patient_access);
+
+    // Referrals
+    cfg.service(referrals::create_referral)
+        .service(referrals::get_referral)
+        .service(referrals::get_referral_fhir)
+        .service(referrals::attach_referral_document)
+        .service(referrals::send_referral)
+        .service(referrals::update_referral_status)
+        .service(referrals::sync_referral)
+        .service(referrals::list_patient_referrals)
+        .service(referrals::referral_worklist);
+
+    // Questionnaires
+    cfg.service(questionnaires::create_questionnaire)
+        .service(questionnaires::list_questionnaires)
+        .service(questionnaires::get_questionnaire)
+        .service(questionnaires::get_questionnaire_fhir)
+        .service(questionnaires::update_questionnaire_status)
+        .service(questionnaires::enabled_questionnaire_items)
+        .service(questionnaires::create_questionnaire_response)
+        .service(questionnaires::get_questionnaire_response)
+        .service(questionnaires::revise_questionnaire_response)
+        .service(questionnaires::get_questionnaire_response_fhir)
+        .service(questionnaires::list_patient_questionnaire_responses);
+
+    // Calculators
+    cfg.service(calculators::list_calculators)
+        .service(calculators::run_calculator);
+
+    // Growth charts
+    cfg.service(growth::get_growth_chart);
+
+    // Medications
+    cfg.service(medications::create_medication_request)
+        .service(medications::get_medication_request)
+        .service(medications::get_medication_request_fhir)
+        .service(medications::update_medication_request_status)
+        .service(medications::list_patient_medication_requests)
+        .service(medications::record_administration)
+        .service(medications::get_administration_fhir)
+        .service(medications::get_encounter_mar);
+
+    // Coding
+    cfg.service(coding::get_coding_suggestions)
+        .service(coding::get_encounter_coding)
+        .service(coding::review_encounter_coding);
+
+    // Coverage
+    cfg.service(coverages::create_coverage)
+        .service(coverages::get_patient_coverages);
+
+    // Billing
+    cfg.service(billing::claim_reconciliation)
+        .service(billing::get_billing_tasks)
+        .service(billing::complete_billing_task);
+
+    // Charges
+    cfg.service(charges::get_encounter_charges)
+        .service(charges::capture_encounter_charges)
+        .service(charges::get_superbill);
+
+    // Imaging
+    cfg.service(imaging::list_imaging_studies)
+        .service(imaging::get_imaging_study)
+        .service(imaging::get_imaging_study_fhir);
+
+    // Documents
+    cfg.service(documents::upload_document)
+        .service(documents::list_documents)
+        .service(documents::get_document_content)
+        .service(documents::rescan_documents);
+
+    // Search
+    cfg.service(search::global_search);
+
+    // Panels
+    cfg.service(panels::create_panel)
+        .service(panels::get_panel)
+        .service(panels::update_panel)
+        .service(panels::delete_panel)
+        .service(panels::organization_panels)
+        .service(panels::evaluate_panel)
+        .service(panels::panel_members);
+
+    // Quality measures
+    cfg.service(measures::create_measure)
+        .service(measures::list_measures)
+        .service(measures::get_measure)
+        .service(measures::compute_measure_reports)
+        .service(measures::measure_reports)
+        .service(measures::measure_report_patients);
+
+    // Reports
+    cfg.service(reports::report_fields)
+        .service(reports::run_report);
+
+    // Report schedules
+    cfg.service(report_schedules::create_report_schedule)
+        .service(report_schedules::list_report_schedules)
+        .service(report_schedules::get_report_schedule)
+        .service(report_schedules::update_report_schedule)
+        .service(report_schedules::delete_report_schedule)
+        .service(report_schedules::report_schedule_runs)
+        .service(report_schedules::download_report_run);
+
+    // Feature flags
+    cfg.service(flags::list_flags)
+        .service(flags::save_flag)
+        .service(flags::delete_flag)
+        .service(flags::evaluate_flags);
+
+    // Disclosures
+    cfg.service(disclosures::list_disclosures)
+        .service(disclosures::export_disclosures)
+        .service(disclosures::download_disclosures);
+
+    // Matches `/{type}/{id}/provenance` for every type, so it goes last
+    cfg.service(provenance::get_provenance);
+}
+
+/// Register the probes, the metrics and the FHIR facade, served at the root
+pub fn configure_root(cfg: &mut web::ServiceConfig) {
+    cfg.service(health::health_check)
+        .service(health::readiness_check)
+        .service(database::metrics);
+
+    cfg.service(fhir::get_fhir_patient)
+        .service(fhir::search_fhir_resources)
+        .service(fhir::create_subscription)
+        .service(fhir::get_subscription)
+        .service(fhir::delete_subscription);
+}
 
 /// Common pagination parameters
 #[derive(Debug, Deserialize)]
@@ -34,6 +383,41 @@ impl PaginationParams {
     }
 }
 
+/// JSON request body checked against its `#[validate(...)]` rules
+///
+/// Bodies that fail are rejected with a 400 listing every invalid field in
+/// `details`, before the handler runs.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, std::result::Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await?.into_inner();
+            body.validate().map_err(ApiError::from)?;
+            Ok(ValidatedJson(body))
+        })
+    }
+}
+
 /// Common response wrapper
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
@@ -86,6 +470,15 @@ impl PaginationMeta {
     }
 }
 
+/// Publish a job for the jobs worker on [`SUBMIT_SUBJECT`]
+pub async fn submit_job(data: &AppState, submission: &JobSubmission) -> Result<()> {
+    let payload = serde_json::to_vec(submission)?;
+    data.nats_client
+        .publish(SUBMIT_SUBJECT, payload.into())
+        .await
+        .map_err(|e| ApiError::external_service_error("NATS", &e.to_string()))
+}
+
 /// Extract request ID from headers
 pub fn extract_request_id(req: &HttpRequest) -> Option<String> {
     req.headers()
@@ -94,13 +487,6 @@ pub fn extract_request_id(req: &HttpRequest) -> Option<String> {
         .map(|s| s.to_string())
 }
 
-/// Extract user ID from request (after authentication)
-pub fn extract_user_id(req: &HttpRequest) -> Option<uuid::Uuid> {
-    req.extensions()
-        .get::<uuid::Uuid>()
-        .copied()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
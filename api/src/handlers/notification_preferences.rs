@@ -0,0 +1,107 @@
+//! Notification preference endpoints
+//!
+//! Users (patients and practitioners) choose the channels each notification
+//! type may use, quiet hours, and which types wait for a daily digest. The
+//! jobs worker applies the preferences when it delivers a notification;
+//! critical notifications ignore them. Patients manage their own through
+//! `/portal/notification-preferences`, which shares the helpers here.
+
+use actix_web::{get, put, web, HttpRequest, HttpResponse};
+use chrono::NaiveTime;
+use emr_core::notifications::{NotificationPreferences, QuietHours, TypePreference};
+use emr_core::types::Id;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use crate::error::Result;
+use crate::handlers::ApiResponse;
+use crate::repositories::NotificationPreferenceRepository;
+use crate::AppState;
+
+/// Preferences to save; omitted fields take their defaults
+#[derive(Debug, Deserialize)]
+pub struct PreferencesRequest {
+    #[serde(default)]
+    pub types: BTreeMap<String, TypePreference>,
+    pub quiet_hours: Option<QuietHours>,
+    pub digest_time: Option<NaiveTime>,
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+impl PreferencesRequest {
+    /// Preferences of a user from the request
+    pub fn into_preferences(self, user_id: Id) -> NotificationPreferences {
+        let mut preferences = NotificationPreferences::new(user_id);
+        preferences.types = self.types;
+        preferences.quiet_hours = self.quiet_hours;
+        if let Some(digest_time) = self.digest_time {
+            preferences.digest_time = digest_time;
+        }
+        preferences.utc_offset_minutes = self.utc_offset_minutes;
+        preferences
+    }
+}
+
+/// A user's saved preferences, or the defaults
+pub(crate) async fn load_preferences(data: &AppState, user_id: Id) -> Result<NotificationPreferences> {
+    let saved = NotificationPreferenceRepository::new().find(&data.db_pool, user_id).await?;
+    Ok(saved.unwrap_or_else(|| NotificationPreferences::new(user_id)))
+}
+
+/// Validate and save a user's preferences
+pub(crate) async fn save_preferences(
+    data: &AppState,
+    user_id: Id,
+    request: PreferencesRequest,
+) -> Result<NotificationPreferences> {
+    let preferences = request.into_preferences(user_id);
+    preferences.validate()?;
+
+    NotificationPreferenceRepository::new().save(&data.db_pool, &preferences).await?;
+    Ok(preferences)
+}
+
+/// A user's notification preferences
+#[get("/users/{id}/notification-preferences")]
+pub async fn get_notification_preferences(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let preferences = load_preferences(&data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(preferences)))
+}
+
+/// Replace a user's notification preferences
+#[put("/users/{id}/notification-preferences")]
+pub async fn update_notification_preferences(
+    path: web::Path<Id>,
+    request: web::Json<PreferencesRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let preferences = save_preferences(&data, path.into_inner(), request.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(preferences)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_defaults() {
+        let user_id = uuid::Uuid::new_v4();
+        let request: PreferencesRequest = serde_json::from_value(json!({
+            "types": {"Update": {"channels": ["Email"], "delivery": "digest"}},
+            "quiet_hours": {"start": "22:00:00", "end": "07:00:00"}
+        }))
+        .unwrap();
+
+        let preferences = request.into_preferences(user_id);
+        assert_eq!(preferences.user_id, user_id);
+        assert_eq!(preferences.digest_time, NaiveTime::from_hms_opt(8, 0, 0).unwrap());
+        assert!(preferences.quiet_hours.is_some());
+        assert!(preferences.validate().is_ok());
+    }
+}
@@ -0,0 +1,100 @@
+//! Notification template handlers
+//!
+//! Notification jobs are generated from the named, localized templates of
+//! the built-in catalog (`emr_core::notifications`). These endpoints list the
+//! templates with their variables and preview one with sample data, using
+//! the same locale fallback and variable checks as delivery.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use emr_core::notifications::{NotificationTemplate, TemplateCatalog, TemplateRef};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::error::{ApiError, Result};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Sample data to preview a template with
+#[derive(Debug, Deserialize)]
+pub struct PreviewRequest {
+    /// Recipient locale; the default locale when absent
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+/// A template and its locale variants
+#[derive(Debug, Serialize)]
+pub struct TemplateSummary<'a> {
+    pub name: &'a str,
+    pub variants: Vec<&'a NotificationTemplate>,
+}
+
+/// Group template variants by name, in catalog order
+fn summaries(templates: &[NotificationTemplate]) -> Vec<TemplateSummary<'_>> {
+    let mut summaries: Vec<TemplateSummary> = Vec::new();
+    for template in templates {
+        match summaries.iter_mut().find(|summary| summary.name == template.name) {
+            Some(summary) => summary.variants.push(template),
+            None => summaries.push(TemplateSummary {
+                name: &template.name,
+                variants: vec![template],
+            }),
+        }
+    }
+    summaries
+}
+
+/// List notification templates with their locales and variables
+#[get("/admin/notification-templates")]
+pub async fn list_notification_templates(
+    _req: HttpRequest,
+    _data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let catalog = TemplateCatalog::builtin();
+    Ok(HttpResponse::Ok().json(ApiResponse::new(summaries(catalog.templates()))))
+}
+
+/// Render a template with sample data
+#[post("/admin/notification-templates/{name}/preview")]
+pub async fn preview_notification_template(
+    path: web::Path<String>,
+    request: web::Json<PreviewRequest>,
+    _req: HttpRequest,
+    _data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let name = path.into_inner();
+    let request = request.into_inner();
+    let catalog = TemplateCatalog::builtin();
+
+    if catalog.resolve(&name, None).is_none() {
+        return Err(ApiError::not_found(&format!("Notification template {} not found", name)));
+    }
+    let rendered = catalog.render(&TemplateRef {
+        name,
+        locale: request.locale,
+        variables: request.variables,
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(rendered)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summaries_group_variants() {
+        let summaries = summaries(TemplateCatalog::builtin().templates());
+        let messages = summaries
+            .iter()
+            .find(|summary| summary.name == emr_core::notifications::SECURE_MESSAGE_TEMPLATE)
+            .unwrap();
+
+        let locales: Vec<&str> = messages.variants.iter().map(|v| v.locale.as_str()).collect();
+        assert_eq!(locales, vec!["en", "es"]);
+        assert_eq!(
+            summaries.len(),
+            summaries.iter().map(|s| s.name).collect::<std::collections::HashSet<_>>().len()
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! In-app notification center
+//!
+//! The jobs worker delivers `InApp` notifications to the recipient's inbox in
+//! `emr.notifications` and announces each one on NATS. These endpoints list
+//! the inbox with its unread count, mark notifications read, and relay new
+//! ones as server-sent events so open sessions update without polling.
+
+use actix_web::{get, post, web, web::Bytes, HttpRequest, HttpResponse};
+use emr_core::events::{Event, EventEnvelope};
+use emr_core::types::Id;
+use emr_jobs::notifications::{inbox_subject, InAppNotification};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio_stream::wrappers::IntervalStream;
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::NotificationRepository;
+use crate::AppState;
+
+/// Interval between keep-alive comments on idle notification streams
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Inbox listing
+#[derive(Debug, Deserialize)]
+pub struct InboxQuery {
+    pub recipient_id: Id,
+    #[serde(default)]
+    pub unread_only: bool,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Whose inbox a request is about
+#[derive(Debug, Deserialize)]
+pub struct RecipientQuery {
+    pub recipient_id: Id,
+}
+
+/// Encode an announced notification as a server-sent event, without its
+/// envelope
+fn notification_frame(payload: &[u8]) -> Bytes {
+    let data = match EventEnvelope::read(payload, InAppNotification::EVENT_TYPE) {
+        Ok(envelope) => envelope.payload.to_string(),
+        Err(_) => String::from_utf8_lossy(payload).into_owned(),
+    };
+    Bytes::from(format!("event: notification\ndata: {}\n\n", data))
+}
+
+/// A recipient's notifications, newest first, with their unread count
+#[get("/notifications")]
+pub async fn list_notifications(
+    query: web::Query<InboxQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+    let repository = NotificationRepository::new();
+
+    let notifications = repository
+        .list(
+            &data.db_pool,
+            query.recipient_id,
+            query.unread_only,
+            pagination.limit(),
+            pagination.offset(),
+        )
+        .await?;
+    let unread = repository.unread_count(&data.db_pool, query.recipient_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        notifications,
+        json!({ "page": page, "per_page": per_page, "unread_count": unread }),
+    )))
+}
+
+/// Number of a recipient's unread notifications
+#[get("/notifications/unread-count")]
+pub async fn unread_count(
+    query: web::Query<RecipientQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let count = NotificationRepository::new()
+        .unread_count(&data.db_pool, query.recipient_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(json!({ "unread_count": count }))))
+}
+
+/// Mark a notification read
+#[post("/notifications/{id}/read")]
+pub async fn mark_notification_read(
+    path: web::Path<Id>,
+    query: web::Query<RecipientQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+
+    let found = NotificationRepository::new()
+        .mark_read(&data.db_pool, query.recipient_id, id)
+        .await?;
+    if !found {
+        return Err(ApiError::not_found(&format!("Notification {} not found", id)));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Mark all of a recipient's notifications read
+#[post("/notifications/read-all")]
+pub async fn mark_all_notifications_read(
+    query: web::Query<RecipientQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let marked = NotificationRepository::new()
+        .mark_all_read(&data.db_pool, query.recipient_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(json!({ "marked_read": marked }))))
+}
+
+/// Stream a recipient's new notifications as server-sent events
+///
+/// Each `notification` event carries the stored notification. The stream
+/// stays open until the client disconnects.
+#[get("/notifications/stream")]
+pub async fn notification_stream(
+    query: web::Query<RecipientQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let subscriber = data
+        .nats_client
+        .subscribe(inbox_subject(query.recipient_id))
+        .await
+        .map_err(|e| ApiError::external_service_error("NATS", &e.to_string()))?;
+
+    let notifications = subscriber.map(|message| notification_frame(&message.payload));
+    let keep_alive = IntervalStream::new(tokio::time::interval(KEEP_ALIVE_INTERVAL))
+        .map(|_| Bytes::from_static(b": keep-alive\n\n"));
+    let body = stream::select(notifications, keep_alive).map(Ok::<_, actix_web::Error>);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_frame() {
+        let frame = notification_frame(br#"{"message":"Lab results are ready"}"#);
+        assert_eq!(
+            frame,
+            Bytes::from_static(b"event: notification\ndata: {\"message\":\"Lab results are ready\"}\n\n")
+        );
+
+        let envelope = EventEnvelope::with_type(InAppNotification::EVENT_TYPE, 1, json!({"message": "Lab results are ready"}));
+        assert_eq!(notification_frame(&envelope.encode().unwrap()), frame);
+    }
+}
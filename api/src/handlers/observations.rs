@@ -0,0 +1,722 @@
+//! Observation endpoints for the Nexus API.
+//!
+//! Besides generic CRUD, `POST /patients/{id}/vitals` accepts a vitals panel
+//! and records LOINC-coded, unit-checked observations grouped under the
+//! vital signs panel, and `GET /observations/{id}/waveform` streams
+//! SampledData waveforms in time-windowed segments.
+//!
+//! `POST /observations/_bulk` stores batches of device readings, such as a
+//! bedside monitor's, without the per-observation checks below: each
+//! reading is validated on its own, rejected readings are reported by index
+//! and the rest are inserted in bulk. Batches beyond the `device_ingest`
+//! limits are refused (see [`DeviceIngestGate`](crate::services::DeviceIngestGate)).
+//! `GET /patients/{id}/observations` reads them back from the database,
+//! within a window of at most 400 days (the last 30 by default).
+//!
+//! Creates, updates and bulk readings are checked against the validation
+//! profile of the encounter's service provider. A strict profile rejects the
+//! observation, or the reading by its index.
+//! Abnormal results of an order open an acknowledgment task for the
+//! ordering practitioner. Observations are stored in `emr.observations`, and
+//! vital signs, from any of these endpoints, update the patient's summary in
+//! the same transaction.
+//!
+//! HIV results need a purpose of use the consent rules permit: reading one is
+//! refused without it, and lists leave them out. Observations labelled above
+//! the caller's clearance are left out of lists and read as not found.
+
+use actix_web::{get, post, put, web, web::Bytes, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use emr_core::domain::{
+    panel_observation, Confidentiality, EncounterStatus, Observation, ObservationStatus, ObservationValue,
+    SampledDataEncoding, VitalSign, Waveform, BLOOD_PRESSURE_PANEL_CODE, VITAL_SIGNS_PANEL_CODE,
+};
+use futures_util::stream;
+use emr_core::partitions::ObservationQuery;
+use emr_core::services::consent::{is_hiv_result, SensitiveData};
+use emr_core::services::device_observations::ValidatedBatch;
+use emr_core::services::{EncounterService, ObservationService};
+use emr_fhir::observation_to_fhir;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use crate::error::{ApiError, Result};
+use crate::handlers::acknowledgments::{open_acknowledgment, result_order};
+use crate::handlers::care_teams::{authorize_patient_access, authorize_sensitive_access, clearance, consent_permits};
+use crate::handlers::{ApiResponse, PaginationParams, PaginatedResponse, PaginationMeta};
+use crate::handlers::validation_profiles::{active_profile, check_profile, enforce_profile};
+use crate::repositories::{ObservationRepository, ObservationSearch};
+use crate::AppState;
+
+/// Observation response DTO
+#[derive(Debug, Serialize)]
+pub struct ObservationResponse {
+    pub id: String,
+    pub patient_id: String,
+    pub encounter_id: Option<String>,
+    pub status: ObservationStatus,
+    pub category: Vec<String>,
+    pub code: String,
+    pub effective: Option<DateTime<Utc>>,
+    pub value: Option<ObservationValue>,
+    pub interpretation: Vec<String>,
+    pub note: Vec<String>,
+    pub has_member: Vec<String>,
+    pub confidentiality: Confidentiality,
+}
+
+/// Observation creation/update request
+#[derive(Debug, Deserialize)]
+pub struct CreateObservationRequest {
+    pub patient_id: uuid::Uuid,
+    pub encounter_id: Option<uuid::Uuid>,
+    pub status: ObservationStatus,
+    #[serde(default)]
+    pub category: Vec<String>,
+    pub code: String,
+    pub effective: Option<DateTime<Utc>>,
+    pub value: Option<ObservationValue>,
+    /// HL7 v3 ObservationInterpretation codes, e.g. `H` or `LL`
+    #[serde(default)]
+    pub interpretation: Vec<String>,
+    #[serde(default)]
+    pub note: Vec<String>,
+    /// Orders the result fulfils
+    #[serde(default)]
+    pub based_on: Vec<uuid::Uuid>,
+    /// Confidentiality label; defaults to normal
+    #[serde(default)]
+    pub confidentiality: Confidentiality,
+}
+
+/// Bulk device observation upload
+#[derive(Debug, Deserialize)]
+pub struct BulkObservationRequest {
+    /// Device readings (`emr_core::services::device_observations::DeviceReading`);
+    /// malformed ones are reported by index rather than failing the batch
+    pub observations: Vec<serde_json::Value>,
+}
+
+/// Observation list filters
+#[derive(Debug, Deserialize)]
+pub struct ObservationFilter {
+    pub patient_id: Option<uuid::Uuid>,
+    pub category: Option<String>,
+    pub code: Option<String>,
+}
+
+/// Window and filters of a patient's stored observations
+#[derive(Debug, Deserialize)]
+pub struct StoredObservationParams {
+    /// Start of the window; 30 days before `to` when omitted
+    pub from: Option<DateTime<Utc>>,
+    /// End of the window; now when omitted
+    pub to: Option<DateTime<Utc>>,
+    pub category: Option<String>,
+    pub code: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl StoredObservationParams {
+    fn to_query(&self, patient_id: uuid::Uuid, now: DateTime<Utc>) -> ObservationQuery {
+        let to = self.to.unwrap_or(now);
+        let from = self.from.unwrap_or(to - chrono::Duration::days(STORED_OBSERVATION_DAYS));
+
+        let mut query = ObservationQuery::between(from, to).for_patient(patient_id);
+        query.category = self.category.clone();
+        query.code = self.code.clone();
+        if let Some(limit) = self.limit {
+            query = query.limit(limit);
+        }
+        query
+    }
+}
+
+/// Single vital sign measurement
+#[derive(Debug, Deserialize)]
+pub struct Measurement {
+    pub value: f64,
+    pub unit: String,
+}
+
+/// Blood pressure reading
+#[derive(Debug, Deserialize)]
+pub struct BloodPressureReading {
+    pub systolic: f64,
+    pub diastolic: f64,
+    #[serde(default = "default_pressure_unit")]
+    pub unit: String,
+}
+
+fn default_pressure_unit() -> String {
+    VitalSign::SystolicBloodPressure.ucum_unit().to_string()
+}
+
+/// Vitals panel request
+#[derive(Debug, Deserialize)]
+pub struct VitalsRequest {
+    /// When the vitals were taken; defaults to now
+    pub effective: Option<DateTime<Utc>>,
+    /// Encounter to link to; defaults to the patient's active encounter
+    pub encounter_id: Option<uuid::Uuid>,
+    pub blood_pressure: Option<BloodPressureReading>,
+    pub heart_rate: Option<Measurement>,
+    pub respiratory_rate: Option<Measurement>,
+    pub temperature: Option<Measurement>,
+    pub oxygen_saturation: Option<Measurement>,
+}
+
+/// Vitals panel response
+#[derive(Debug, Serialize)]
+pub struct VitalsResponse {
+    pub panel: ObservationResponse,
+    pub members: Vec<ObservationResponse>,
+}
+
+/// Days of stored observations returned when no window is given
+const STORED_OBSERVATION_DAYS: i64 = 30;
+
+/// Default waveform segment length in milliseconds
+const DEFAULT_SEGMENT_MS: f64 = 1000.0;
+
+/// Waveform streaming parameters
+#[derive(Debug, Deserialize)]
+pub struct WaveformQuery {
+    /// Window start, in milliseconds from the start of the waveform
+    pub start_ms: Option<f64>,
+    /// Window end, in milliseconds from the start of the waveform
+    pub end_ms: Option<f64>,
+    /// Length of each streamed segment in milliseconds
+    pub segment_ms: Option<f64>,
+    /// Encoding of segment data; omit to receive decoded points
+    pub encoding: Option<SampledDataEncoding>,
+}
+
+/// Waveform segment, streamed as one NDJSON line
+#[derive(Debug, Serialize)]
+pub struct WaveformSegment {
+    /// Segment start, in milliseconds from the start of the waveform
+    pub offset_ms: f64,
+    /// Encoded SampledData value when an encoding was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<ObservationValue>,
+    /// Decoded `[offset_ms, [values...]]` points for charting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<Vec<(f64, Vec<f64>)>>,
+}
+
+impl WaveformQuery {
+    /// Split the requested window of a waveform into segments
+    fn segments(&self, waveform: &Waveform) -> Result<Vec<WaveformSegment>> {
+        let start_ms = self.start_ms.unwrap_or(0.0);
+        let end_ms = self.end_ms.unwrap_or_else(|| waveform.duration_ms());
+        if end_ms < start_ms {
+            return Err(ApiError::validation_error("end_ms must not be before start_ms"));
+        }
+
+        let window = waveform.window(start_ms, end_ms);
+        window
+            .segments(self.segment_ms.unwrap_or(DEFAULT_SEGMENT_MS))
+            .into_iter()
+            .map(|(offset_ms, segment)| {
+                let (value, points) = match self.encoding {
+                    Some(encoding) => (Some(segment.to_value(encoding)?), None),
+                    None => (None, Some(segment.points())),
+                };
+                Ok(WaveformSegment {
+                    offset_ms: start_ms + offset_ms,
+                    value,
+                    points,
+                })
+            })
+            .collect()
+    }
+}
+
+impl CreateObservationRequest {
+    /// Build a domain observation from the request
+    fn to_domain(&self) -> Observation {
+        let mut observation = Observation::new(self.status.clone(), self.code.trim().to_string(), self.patient_id);
+        observation.encounter = self.encounter_id;
+        observation.category = self.category.clone();
+        observation.effective = self.effective;
+        observation.value = self.value.clone();
+        observation.interpretation = self.interpretation.clone();
+        observation.note = self.note.clone();
+        observation.based_on = self.based_on.clone();
+        observation.confidentiality = self.confidentiality;
+        observation
+    }
+}
+
+impl VitalsRequest {
+    /// Build the vital signs panel followed by its member observations
+    fn to_domain(&self, patient_id: uuid::Uuid, encounter_id: Option<uuid::Uuid>) -> Result<Vec<Observation>> {
+        let effective = self.effective.unwrap_or_else(Utc::now);
+        let mut members = Vec::new();
+
+        if let Some(bp) = &self.blood_pressure {
+            if bp.systolic <= bp.diastolic {
+                return Err(ApiError::validation_error(
+                    "Systolic pressure must be greater than diastolic pressure",
+                ));
+            }
+
+            let systolic = VitalSign::SystolicBloodPressure.observation(bp.systolic, &bp.unit, patient_id, effective)?;
+            let diastolic = VitalSign::DiastolicBloodPressure.observation(bp.diastolic, &bp.unit, patient_id, effective)?;
+
+            let mut bp_panel = panel_observation(BLOOD_PRESSURE_PANEL_CODE, patient_id, effective);
+            bp_panel.add_member(systolic.metadata.id);
+            bp_panel.add_member(diastolic.metadata.id);
+            members.extend([bp_panel, systolic, diastolic]);
+        }
+
+        let measurements = [
+            (VitalSign::HeartRate, &self.heart_rate),
+            (VitalSign::RespiratoryRate, &self.respiratory_rate),
+            (VitalSign::BodyTemperature, &self.temperature),
+            (VitalSign::OxygenSaturation, &self.oxygen_saturation),
+        ];
+        for (vital_sign, measurement) in measurements {
+            if let Some(measurement) = measurement {
+                members.push(vital_sign.observation(measurement.value, &measurement.unit, patient_id, effective)?);
+            }
+        }
+
+        if members.is_empty() {
+            return Err(ApiError::validation_error("Vitals panel must contain at least one measurement"));
+        }
+
+        let mut panel = panel_observation(VITAL_SIGNS_PANEL_CODE, patient_id, effective);
+        for member in &members {
+            // Blood pressure components are grouped under their own panel
+            if !matches!(member.code.as_str(), "8480-6" | "8462-4") {
+                panel.add_member(member.metadata.id);
+            }
+        }
+
+        let mut observations = vec![panel];
+        observations.extend(members);
+
+        if let Some(encounter_id) = encounter_id {
+            for observation in &mut observations {
+                observation.set_encounter(encounter_id);
+            }
+        }
+
+        Ok(observations)
+    }
+}
+
+impl From<&Observation> for ObservationResponse {
+    fn from(observation: &Observation) -> Self {
+        Self {
+            id: observation.metadata.id.to_string(),
+            patient_id: observation.subject.to_string(),
+            encounter_id: observation.encounter.map(|id| id.to_string()),
+            status: observation.status.clone(),
+            category: observation.category.clone(),
+            code: observation.code.clone(),
+            effective: observation.effective,
+            value: observation.value.clone(),
+            interpretation: observation.interpretation.clone(),
+            note: observation.note.clone(),
+            has_member: observation.has_member.iter().map(|id| id.to_string()).collect(),
+            confidentiality: observation.confidentiality,
+        }
+    }
+}
+
+/// Find the encounter a patient is currently checked in to
+async fn active_encounter(data: &AppState, patient_id: uuid::Uuid) -> Result<Option<uuid::Uuid>> {
+    let encounters = data.encounters.get_patient_encounters(patient_id).await?;
+
+    Ok(encounters
+        .iter()
+        .find(|encounter| {
+            matches!(
+                encounter.status,
+                EncounterStatus::InProgress | EncounterStatus::Arrived | EncounterStatus::Triaged
+            )
+        })
+        .map(|encounter| encounter.metadata.id))
+}
+
+/// Find an observation the caller is cleared for; others read as not found
+async fn find_cleared(req: &HttpRequest, data: &AppState, observation_id: uuid::Uuid) -> Result<Observation> {
+    data.observations
+        .get_observation(observation_id)
+        .await?
+        .filter(|observation| clearance(req).clears(observation.confidentiality))
+        .ok_or_else(|| ApiError::not_found(&format!("Observation {} not found", observation_id)))
+}
+
+/// List observations with pagination
+#[get("/observations")]
+pub async fn list_observations(
+    query: web::Query<PaginationParams>,
+    filter: web::Query<ObservationFilter>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+    let filter = filter.into_inner();
+    let search = ObservationSearch {
+        patient_id: filter.patient_id,
+        encounter_id: None,
+        category: filter.category,
+        code: filter.code,
+        clearance: Some(clearance(&req)),
+        without_hiv_results: !consent_permits(&req, &[SensitiveData::HivResults]),
+    };
+
+    let (observations, total) = data
+        .observations
+        .list_observations(search, query.limit(), query.offset())
+        .await?;
+
+    let response = PaginatedResponse {
+        data: observations.iter().map(ObservationResponse::from).collect(),
+        pagination: PaginationMeta::new(page, per_page, total),
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Get observation by ID
+#[get("/observations/{id}")]
+pub async fn get_observation(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let observation = find_cleared(&req, &data, path.into_inner()).await?;
+    if is_hiv_result(&observation.code) {
+        authorize_sensitive_access(&req, &data, observation.subject, &[SensitiveData::HivResults]).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(ObservationResponse::from(&observation))))
+}
+
+/// A patient's observations as stored in `emr.observations`, most recent
+/// first, device readings included
+#[get("/patients/{id}/observations")]
+pub async fn list_stored_observations(
+    path: web::Path<uuid::Uuid>,
+    query: web::Query<StoredObservationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let hiv_results = consent_permits(&req, &[SensitiveData::HivResults]);
+
+    let mut observations = ObservationRepository::new()
+        .list(&data.db_pool, query.to_query(patient_id, Utc::now()))
+        .await?;
+    observations.retain(|observation| hiv_results || !observation.code.as_deref().is_some_and(is_hiv_result));
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(observations)))
+}
+
+/// Stream a SampledData waveform as NDJSON segments
+#[get("/observations/{id}/waveform")]
+pub async fn stream_waveform(
+    path: web::Path<uuid::Uuid>,
+    query: web::Query<WaveformQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let observation = find_cleared(&req, &data, path.into_inner()).await?;
+    let value = observation
+        .value
+        .as_ref()
+        .ok_or_else(|| ApiError::validation_error("Observation has no value"))?;
+
+    let segments = query.segments(&Waveform::from_value(value)?)?;
+    let body = stream::iter(segments.into_iter().map(|segment| {
+        let mut line = serde_json::to_vec(&segment)?;
+        line.push(b'\n');
+        Ok::<_, ApiError>(Bytes::from(line))
+    }));
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body))
+}
+
+/// Create new observation
+#[post("/observations")]
+pub async fn create_observation(
+    request: web::Json<CreateObservationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let observation = request.to_domain();
+    enforce_observation_profile(&data, &observation).await?;
+    let order = result_order(&data, &observation).await?;
+
+    let observation = data.observations.create_observation(observation).await?;
+    open_acknowledgment(&data, &observation, order.as_ref()).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(ObservationResponse::from(&observation))))
+}
+
+/// Store a batch of device readings, reporting rejected readings by index
+#[post("/observations/_bulk")]
+pub async fn bulk_create_observations(
+    request: web::Json<BulkObservationRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let _permit = data.device_ingest.admit(request.observations.len())?;
+    let mut batch = ValidatedBatch::from_values(request.into_inner().observations, Utc::now());
+    enforce_reading_profiles(&data, &mut batch).await?;
+
+    let result = ObservationRepository::new()
+        .insert_device_batch(&data.db_pool, batch, data.device_ingest.limits().chunk_size)
+        .await?;
+    tracing::info!(
+        received = result.received,
+        inserted = result.inserted,
+        duplicates = result.duplicates,
+        rejected = result.errors.len(),
+        "Stored device observations"
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(result)))
+}
+
+/// Check an observation against the validation profile of its encounter's
+/// service provider; observations without one get the default profile
+async fn enforce_observation_profile(data: &AppState, observation: &Observation) -> Result<()> {
+    let tenant_id = match observation.encounter {
+        Some(encounter_id) => data
+            .encounters
+            .get_encounter(encounter_id)
+            .await?
+            .and_then(|encounter| encounter.service_provider),
+        None => None,
+    };
+
+    enforce_profile(data, tenant_id, "Observation", &observation_to_fhir(observation)).await
+}
+
+/// Reject device readings a strict validation profile fails, loading the
+/// profile of each tenant once per batch (see [`enforce_observation_profile`])
+async fn enforce_reading_profiles(data: &AppState, batch: &mut ValidatedBatch) -> Result<()> {
+    let mut tenants = HashMap::new();
+    for encounter_id in batch.encounter_ids() {
+        let encounter = data.encounters.get_encounter(encounter_id).await?;
+        tenants.insert(encounter_id, encounter.and_then(|encounter| encounter.service_provider));
+    }
+    let mut profiles = HashMap::new();
+    for tenant_id in tenants.values().copied().chain([None]).collect::<HashSet<_>>() {
+        profiles.insert(tenant_id, active_profile(data, tenant_id, "Observation").await?);
+    }
+
+    batch.reject(|reading| {
+        let tenant_id = reading.encounter_id.and_then(|encounter_id| tenants.get(&encounter_id).copied().flatten());
+        let profile = profiles.get(&tenant_id)?.as_ref()?;
+        check_profile(profile, &observation_to_fhir(&Observation::from(reading)))
+            .err()
+            .map(|e| e.to_string())
+    });
+    Ok(())
+}
+
+/// Update observation
+#[put("/observations/{id}")]
+pub async fn update_observation(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<CreateObservationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut observation = request.to_domain();
+    observation.metadata.id = path.into_inner();
+    enforce_observation_profile(&data, &observation).await?;
+    let order = result_order(&data, &observation).await?;
+
+    let observation = data.observations.update_observation(observation).await?;
+    open_acknowledgment(&data, &observation, order.as_ref()).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(ObservationResponse::from(&observation))))
+}
+
+/// Record a vitals panel for a patient
+#[post("/patients/{id}/vitals")]
+pub async fn record_vitals(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<VitalsRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+
+    let encounter_id = match request.encounter_id {
+        Some(encounter_id) => Some(encounter_id),
+        None => active_encounter(&data, patient_id).await?,
+    };
+
+    let observations = data
+        .observations
+        .create_observations(request.to_domain(patient_id, encounter_id)?)
+        .await?;
+    let mut observations = observations.into_iter();
+
+    let panel = observations
+        .next()
+        .ok_or_else(|| ApiError::internal_error("Vitals panel was not created"))?;
+    let response = VitalsResponse {
+        panel: ObservationResponse::from(&panel),
+        members: observations.map(|obs| ObservationResponse::from(&obs)).collect(),
+    };
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(value: f64, unit: &str) -> Option<Measurement> {
+        Some(Measurement {
+            value,
+            unit: unit.to_string(),
+        })
+    }
+
+    fn vitals_request() -> VitalsRequest {
+        VitalsRequest {
+            effective: None,
+            encounter_id: None,
+            blood_pressure: Some(BloodPressureReading {
+                systolic: 120.0,
+                diastolic: 80.0,
+                unit: default_pressure_unit(),
+            }),
+            heart_rate: measurement(72.0, "/min"),
+            respiratory_rate: measurement(16.0, "/min"),
+            temperature: measurement(37.0, "Cel"),
+            oxygen_saturation: measurement(98.0, "%"),
+        }
+    }
+
+    #[test]
+    fn test_vitals_panel() {
+        let patient_id = uuid::Uuid::new_v4();
+        let encounter_id = uuid::Uuid::new_v4();
+        let observations = vitals_request().to_domain(patient_id, Some(encounter_id)).unwrap();
+
+        // Vitals panel, BP panel, systolic, diastolic, HR, RR, temp, SpO2
+        assert_eq!(observations.len(), 8);
+        assert_eq!(observations[0].code, VITAL_SIGNS_PANEL_CODE);
+        assert_eq!(observations[0].has_member.len(), 5);
+        assert_eq!(observations[1].code, BLOOD_PRESSURE_PANEL_CODE);
+        assert_eq!(observations[1].has_member.len(), 2);
+        assert!(observations.iter().all(|obs| obs.encounter == Some(encounter_id)));
+    }
+
+    #[test]
+    fn test_vitals_panel_converts_units() {
+        let mut request = vitals_request();
+        request.temperature = measurement(98.6, "[degF]");
+
+        let observations = request.to_domain(uuid::Uuid::new_v4(), None).unwrap();
+        let temperature = observations
+            .iter()
+            .find(|obs| obs.code == VitalSign::BodyTemperature.loinc_code())
+            .unwrap();
+        assert!(matches!(
+            &temperature.value,
+            Some(ObservationValue::Quantity { unit, .. }) if unit == "Cel"
+        ));
+    }
+
+    #[test]
+    fn test_waveform_segments() {
+        let waveform = Waveform::from_value(&ObservationValue::SampledData {
+            origin: 0.0,
+            period: 100.0,
+            factor: None,
+            lower_limit: None,
+            upper_limit: None,
+            dimensions: 1,
+            data: "1 2 3 4 5 6 7 8 9 10".to_string(),
+            encoding: SampledDataEncoding::Text,
+        })
+        .unwrap();
+
+        let query = WaveformQuery {
+            start_ms: Some(200.0),
+            end_ms: Some(800.0),
+            segment_ms: Some(300.0),
+            encoding: None,
+        };
+        let segments = query.segments(&waveform).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].offset_ms, 500.0);
+        assert_eq!(segments[0].points.as_ref().unwrap()[0].1, vec![3.0]);
+
+        let query = WaveformQuery {
+            encoding: Some(SampledDataEncoding::DeltaVarint),
+            ..query
+        };
+        let segments = query.segments(&waveform).unwrap();
+        assert!(segments.iter().all(|segment| segment.value.is_some() && segment.points.is_none()));
+    }
+
+    #[test]
+    fn test_vitals_panel_validation() {
+        let mut request = vitals_request();
+        request.temperature = measurement(37.0, "kg");
+        assert!(request.to_domain(uuid::Uuid::new_v4(), None).is_err());
+
+        let mut request = vitals_request();
+        request.blood_pressure = Some(BloodPressureReading {
+            systolic: 70.0,
+            diastolic: 90.0,
+            unit: default_pressure_unit(),
+        });
+        assert!(request.to_domain(uuid::Uuid::new_v4(), None).is_err());
+
+        let empty = VitalsRequest {
+            effective: None,
+            encounter_id: None,
+            blood_pressure: None,
+            heart_rate: None,
+            respiratory_rate: None,
+            temperature: None,
+            oxygen_saturation: None,
+        };
+        assert!(empty.to_domain(uuid::Uuid::new_v4(), None).is_err());
+    }
+
+    #[test]
+    fn test_stored_observation_window() {
+        let now = Utc::now();
+        let patient_id = uuid::Uuid::new_v4();
+        let params = StoredObservationParams {
+            from: None,
+            to: None,
+            category: Some("vital-signs".to_string()),
+            code: None,
+            limit: None,
+        };
+        let query = params.to_query(patient_id, now);
+        assert_eq!((query.from, query.to), (now - chrono::Duration::days(30), now));
+        assert_eq!(query.patient_id, Some(patient_id));
+        assert_eq!(query.category.as_deref(), Some("vital-signs"));
+        assert!(query.check().is_ok());
+
+        let params = StoredObservationParams {
+            from: Some(now - chrono::Duration::days(500)),
+            to: None,
+            category: None,
+            code: None,
+            limit: Some(50),
+        };
+        let query = params.to_query(patient_id, now);
+        assert_eq!(query.limit, 50);
+        assert!(query.check().is_err());
+    }
+}
@@ -0,0 +1,283 @@
+//! Order entry endpoints
+//!
+//! Practitioners place lab, imaging and referral orders as FHIR
+//! `ServiceRequest`s and move them through their statuses. Results come back
+//! as `DiagnosticReport`s posted to `/orders/fulfilments`; each order named
+//! in the report's `basedOn` is linked to it, and a final report completes
+//! the order. `/practitioners/{id}/pending-orders` is the worklist of orders
+//! a practitioner placed or must perform that are still open.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{RequestCategory, RequestPriority, RequestStatus, ServiceRequest};
+use emr_core::types::Id;
+use emr_fhir::{report_based_on, service_request_to_fhir};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::ServiceRequestRepository;
+use crate::AppState;
+
+/// New order
+#[derive(Debug, Deserialize)]
+pub struct CreateOrderRequest {
+    pub patient_id: Id,
+    pub encounter_id: Option<Id>,
+    pub category: RequestCategory,
+    pub code_system: String,
+    pub code: String,
+    pub display: Option<String>,
+    #[serde(default = "default_priority")]
+    pub priority: RequestPriority,
+    /// Practitioner placing the order
+    pub requester_id: Id,
+    pub performer_id: Option<Id>,
+    pub reason: Option<String>,
+    /// Save as a draft instead of placing the order
+    #[serde(default)]
+    pub draft: bool,
+}
+
+fn default_priority() -> RequestPriority {
+    RequestPriority::Routine
+}
+
+/// Status change
+#[derive(Debug, Deserialize)]
+pub struct OrderStatusRequest {
+    pub status: RequestStatus,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+async fn find_order(data: &AppState, id: Id) -> Result<ServiceRequest> {
+    ServiceRequestRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Order {} not found", id)))
+}
+
+/// Write a changed order unless it was changed concurrently
+async fn store_change(data: &AppState, order: &ServiceRequest, previous_version: u64) -> Result<()> {
+    let stored = ServiceRequestRepository::new()
+        .update(&data.db_pool, order, previous_version)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict("The order was changed by another request; reload it and try again"));
+    }
+    Ok(())
+}
+
+/// Patient a FHIR resource's `subject` refers to, if it names a local patient
+fn subject_patient(resource: &Value) -> Option<Id> {
+    resource
+        .get("subject")?
+        .get("reference")?
+        .as_str()?
+        .strip_prefix("Patient/")?
+        .parse()
+        .ok()
+}
+
+/// Place (or draft) an order
+#[post("/orders")]
+pub async fn create_order(
+    request: web::Json<CreateOrderRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut order = ServiceRequest::new(
+        request.patient_id,
+        request.requester_id,
+        request.category,
+        &request.code_system,
+        &request.code,
+        request.priority,
+    );
+    order.encounter_id = request.encounter_id;
+    order.display = request.display;
+    order.performer_id = request.performer_id;
+    order.reason = request.reason;
+    if request.draft {
+        order.status = RequestStatus::Draft;
+    }
+    order.validate()?;
+
+    ServiceRequestRepository::new().insert(&data.db_pool, &order).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(order)))
+}
+
+/// An order
+#[get("/orders/{id}")]
+pub async fn get_order(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let order = find_order(&data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(order)))
+}
+
+/// An order as a FHIR `ServiceRequest`
+#[get("/orders/{id}/fhir")]
+pub async fn get_order_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let order = find_order(&data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &service_request_to_fhir(&order))
+}
+
+/// A patient's orders, newest first
+#[get("/patients/{id}/orders")]
+pub async fn list_patient_orders(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let (page, per_page) = query.normalize();
+
+    let orders = ServiceRequestRepository::new()
+        .for_patient(&data.db_pool, patient_id, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        orders,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Open orders a practitioner placed or must perform, most urgent first
+#[get("/practitioners/{id}/pending-orders")]
+pub async fn pending_orders(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+
+    let orders = ServiceRequestRepository::new()
+        .pending_for_practitioner(&data.db_pool, path.into_inner(), query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        orders,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Change an order's status
+#[post("/orders/{id}/status")]
+pub async fn update_order_status(
+    path: web::Path<Id>,
+    request: web::Json<OrderStatusRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut order = find_order(&data, path.into_inner()).await?;
+    let previous_version = order.metadata.version;
+    if request.version != previous_version {
+        return Err(ApiError::conflict("The order changed since it was loaded; reload it and try again"));
+    }
+
+    order.transition(request.status)?;
+    store_change(&data, &order, previous_version).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(order)))
+}
+
+/// Link an incoming `DiagnosticReport` to the orders it is `basedOn`
+///
+/// Responds with the orders that were fulfilled and the referenced order
+/// ids that are not known here.
+#[post("/orders/fulfilments")]
+pub async fn fulfil_orders(
+    report: web::Json<Value>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let report = report.into_inner();
+    if report.get("resourceType").and_then(Value::as_str) != Some("DiagnosticReport") {
+        return Err(ApiError::validation_error("Expected a DiagnosticReport"));
+    }
+    let report_id = report
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or_else(|| ApiError::validation_error("The DiagnosticReport needs an id"))?;
+    let status = report
+        .get("status")
+        .and_then(Value::as_str)
+        .ok_or_else(|| ApiError::validation_error("The DiagnosticReport needs a status"))?;
+    let reference = format!("DiagnosticReport/{}", report_id);
+    let subject = subject_patient(&report);
+
+    let repository = ServiceRequestRepository::new();
+    let mut fulfilled = Vec::new();
+    let mut unmatched = Vec::new();
+    for order_id in report_based_on(&report) {
+        let Some(mut order) = repository.find(&data.db_pool, order_id).await? else {
+            unmatched.push(order_id);
+            continue;
+        };
+        if subject.is_some_and(|patient_id| patient_id != order.patient_id) {
+            return Err(ApiError::validation_error(&format!(
+                "Order {} is for a different patient than the report",
+                order_id
+            )));
+        }
+
+        let previous_version = order.metadata.version;
+        if order.fulfil(&reference, status)? {
+            store_change(&data, &order, previous_version).await?;
+        }
+        fulfilled.push(order);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(json!({
+        "fulfilled": fulfilled,
+        "unmatched": unmatched,
+    }))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_request_defaults() {
+        let request: CreateOrderRequest = serde_json::from_value(json!({
+            "patient_id": uuid::Uuid::new_v4(),
+            "category": "laboratory",
+            "code_system": "http://loinc.org",
+            "code": "4548-4",
+            "requester_id": uuid::Uuid::new_v4()
+        }))
+        .unwrap();
+
+        assert_eq!(request.priority, RequestPriority::Routine);
+        assert!(!request.draft);
+        assert!(request.performer_id.is_none());
+    }
+
+    #[test]
+    fn test_subject_patient() {
+        let patient = uuid::Uuid::new_v4();
+        assert_eq!(
+            subject_patient(&json!({"subject": {"reference": format!("Patient/{}", patient)}})),
+            Some(patient)
+        );
+        assert_eq!(subject_patient(&json!({"subject": {"reference": "Group/1"}})), None);
+        assert_eq!(subject_patient(&json!({})), None);
+    }
+}
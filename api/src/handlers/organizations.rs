@@ -0,0 +1,228 @@
+//! Organization endpoints for the Nexus API.
+//!
+//! Organizations are stored in `emr.organizations` through the
+//! `OrganizationService`; the hierarchy endpoint returns the `part_of` tree
+//! rooted at the requested organization.
+
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use emr_core::domain::{Organization, OrganizationNode, OrganizationType};
+use emr_core::services::OrganizationService;
+use serde::{Deserialize, Serialize};
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, PaginationParams, PaginatedResponse, PaginationMeta};
+use crate::AppState;
+
+/// Organization response DTO
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationResponse {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub part_of: Option<String>,
+    pub aliases: Vec<String>,
+    pub active: bool,
+}
+
+/// Organization hierarchy node DTO
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationTreeResponse {
+    #[serde(flatten)]
+    pub organization: OrganizationResponse,
+    pub children: Vec<OrganizationTreeResponse>,
+}
+
+/// Organization creation/update request
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    pub part_of: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl CreateOrganizationRequest {
+    /// Build a domain organization from the request
+    fn to_domain(&self) -> Result<Organization> {
+        let mut organization = Organization::new(self.name.trim().to_string())?;
+
+        if let Some(code) = &self.type_ {
+            let type_ = OrganizationType::from_code(code).ok_or_else(|| {
+                ApiError::validation_error(&format!("Unknown organization type: {}", code))
+            })?;
+            organization.set_type(type_);
+        }
+
+        if let Some(part_of) = self.part_of {
+            organization.set_part_of(part_of);
+        }
+
+        for alias in &self.aliases {
+            organization.add_alias(alias.clone());
+        }
+
+        Ok(organization)
+    }
+}
+
+impl From<&Organization> for OrganizationResponse {
+    fn from(organization: &Organization) -> Self {
+        Self {
+            id: organization.metadata.id.to_string(),
+            name: organization.name.clone(),
+            type_: organization.type_.as_ref().map(|t| t.code().to_string()),
+            part_of: organization.part_of.map(|id| id.to_string()),
+            aliases: organization.aliases.clone(),
+            active: organization.active,
+        }
+    }
+}
+
+impl From<&OrganizationNode> for OrganizationTreeResponse {
+    fn from(node: &OrganizationNode) -> Self {
+        Self {
+            organization: OrganizationResponse::from(&node.organization),
+            children: node.children.iter().map(OrganizationTreeResponse::from).collect(),
+        }
+    }
+}
+
+/// List organizations with pagination
+#[get("/organizations")]
+pub async fn list_organizations(
+    query: web::Query<PaginationParams>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+
+    let (organizations, total) = data.organizations.list_organizations(query.limit(), query.offset()).await?;
+
+    let response = PaginatedResponse {
+        data: organizations.iter().map(OrganizationResponse::from).collect(),
+        pagination: PaginationMeta::new(page, per_page, total),
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Get organization by ID
+#[get("/organizations/{id}")]
+pub async fn get_organization(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let organization_id = path.into_inner();
+
+    let organization = data
+        .organizations
+        .get_organization(organization_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Organization {} not found", organization_id)))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(OrganizationResponse::from(&organization))))
+}
+
+/// Get the organization hierarchy rooted at an organization
+#[get("/organizations/{id}/hierarchy")]
+pub async fn get_organization_hierarchy(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tree = data.organizations.get_organization_tree(path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        OrganizationTreeResponse::from(&tree),
+        serde_json::json!({ "total": tree.size() }),
+    )))
+}
+
+/// Create new organization
+#[post("/organizations")]
+pub async fn create_organization(
+    request: web::Json<CreateOrganizationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let organization = data
+        .organizations
+        .create_organization(request.to_domain()?)
+        .await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(OrganizationResponse::from(&organization))))
+}
+
+/// Update organization
+#[put("/organizations/{id}")]
+pub async fn update_organization(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<CreateOrganizationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut organization = request.to_domain()?;
+    organization.metadata.id = path.into_inner();
+
+    let organization = data.organizations.update_organization(organization).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(OrganizationResponse::from(&organization))))
+}
+
+/// Delete organization
+#[delete("/organizations/{id}")]
+pub async fn delete_organization(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.organizations.delete_organization(path.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_request(type_: Option<&str>) -> CreateOrganizationRequest {
+        CreateOrganizationRequest {
+            name: "General Hospital".to_string(),
+            type_: type_.map(str::to_string),
+            part_of: None,
+            aliases: vec!["GH".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_request_to_response() {
+        let organization = create_request(Some("prov")).to_domain().unwrap();
+        let response = OrganizationResponse::from(&organization);
+
+        assert_eq!(response.name, "General Hospital");
+        assert_eq!(response.type_.as_deref(), Some("prov"));
+        assert_eq!(response.aliases, vec!["GH".to_string()]);
+    }
+
+    #[test]
+    fn test_request_with_unknown_type() {
+        assert!(create_request(Some("spaceship")).to_domain().is_err());
+    }
+
+    #[test]
+    fn test_tree_response() {
+        let root = create_request(None).to_domain().unwrap();
+        let mut child = Organization::new("Cardiology".to_string()).unwrap();
+        child.set_type(OrganizationType::Dept);
+        child.set_part_of(root.metadata.id);
+
+        let tree = OrganizationNode::build(root.metadata.id, &[root.clone(), child]).unwrap();
+        let response = OrganizationTreeResponse::from(&tree);
+
+        assert_eq!(response.children.len(), 1);
+        assert_eq!(response.children[0].organization.type_.as_deref(), Some("dept"));
+    }
+}
@@ -0,0 +1,274 @@
+//! Patient panel endpoints
+//!
+//! A panel is a saved, named patient query built from structured criteria
+//! (see [`PatientPanel`]). Practitioners save panels for an organization,
+//! optionally sharing them with its other practitioners, and list them at
+//! `/organizations/{id}/panels`. `POST /panels/{id}/evaluate` finds the
+//! members again; nightly panels are also refreshed by the jobs worker.
+//! Member lists only show patients the caller has a treating relationship
+//! with, like search results do.
+
+use actix_web::{delete, get, post, put, web, HttpMessage, HttpRequest, HttpResponse};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{PanelCriterion, PanelRefresh, PatientPanel};
+use emr_core::types::Id;
+use serde::Deserialize;
+use serde_json::json;
+use validator::Validate;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, PaginationParams, ValidatedJson};
+use crate::repositories::{PanelRepository, SearchPatientFilter};
+use crate::AppState;
+
+/// New panel
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePanelRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(max = 1000))]
+    pub description: Option<String>,
+    pub organization_id: Id,
+    /// Practitioner saving the panel
+    pub owner_id: Id,
+    #[serde(default)]
+    pub shared: bool,
+    pub criteria: Vec<PanelCriterion>,
+    #[serde(default = "default_refresh")]
+    pub refresh: PanelRefresh,
+}
+
+fn default_refresh() -> PanelRefresh {
+    PanelRefresh::OnDemand
+}
+
+/// Changed panel definition
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdatePanelRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(max = 1000))]
+    pub description: Option<String>,
+    pub shared: bool,
+    pub criteria: Vec<PanelCriterion>,
+    pub refresh: PanelRefresh,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// Practitioner a request acts for; `None` for unauthenticated requests,
+/// which still pass until the auth middleware rejects them
+/// (TODO(nexus-phase2))
+fn panel_practitioner(context: Option<&AuthContext>) -> Result<Option<Id>> {
+    let Some(context) = context else {
+        return Ok(None);
+    };
+    if context.is_patient_session() {
+        return Err(ApiError::authorization_error("Patient panels are not available in the portal"));
+    }
+    context
+        .practitioner_id()
+        .map(Some)
+        .ok_or_else(|| ApiError::authorization_error("Only practitioners can use patient panels"))
+}
+
+/// Load a panel the caller can see; panels others have not shared are
+/// reported as missing
+async fn visible_panel(data: &AppState, id: Id, practitioner_id: Option<Id>) -> Result<PatientPanel> {
+    PanelRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .filter(|panel| practitioner_id.map_or(true, |practitioner_id| panel.visible_to(practitioner_id)))
+        .ok_or_else(|| ApiError::not_found(&format!("Panel {} not found", id)))
+}
+
+/// Check that only the panel's owner changes it
+fn check_owner(panel: &PatientPanel, practitioner_id: Option<Id>) -> Result<()> {
+    match practitioner_id {
+        Some(practitioner_id) if practitioner_id != panel.owner_id => {
+            Err(ApiError::authorization_error("Only the practitioner who saved a panel can change it"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Save a panel
+#[post("/panels")]
+pub async fn create_panel(
+    request: ValidatedJson<CreatePanelRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let request = request.into_inner();
+    let mut panel = PatientPanel::new(&request.name, request.organization_id, request.owner_id, request.criteria);
+    check_owner(&panel, practitioner_id)?;
+    panel.description = request.description;
+    panel.shared = request.shared;
+    panel.refresh = request.refresh;
+    panel.validate()?;
+
+    PanelRepository::new().insert(&data.db_pool, &panel).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(panel)))
+}
+
+/// A panel
+#[get("/panels/{id}")]
+pub async fn get_panel(path: web::Path<Id>, req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let panel = visible_panel(&data, path.into_inner(), practitioner_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(panel)))
+}
+
+/// Change a panel's definition; its members stay as they are until it is
+/// evaluated again
+#[put("/panels/{id}")]
+pub async fn update_panel(
+    path: web::Path<Id>,
+    request: ValidatedJson<UpdatePanelRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let request = request.into_inner();
+    let mut panel = visible_panel(&data, path.into_inner(), practitioner_id).await?;
+    check_owner(&panel, practitioner_id)?;
+    if panel.metadata.version != request.version {
+        return Err(ApiError::conflict("The panel changed since it was loaded; reload it and try again"));
+    }
+
+    let previous_version = panel.metadata.version;
+    panel.name = request.name.trim().to_string();
+    panel.description = request.description;
+    panel.shared = request.shared;
+    panel.criteria = request.criteria;
+    panel.refresh = request.refresh;
+    panel.validate()?;
+    panel.metadata.update();
+
+    if !PanelRepository::new().update(&data.db_pool, &panel, previous_version).await? {
+        return Err(ApiError::conflict("The panel was changed by another request; reload it and try again"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(panel)))
+}
+
+/// Delete a panel
+#[delete("/panels/{id}")]
+pub async fn delete_panel(path: web::Path<Id>, req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let panel = visible_panel(&data, path.into_inner(), practitioner_id).await?;
+    check_owner(&panel, practitioner_id)?;
+
+    PanelRepository::new().delete(&data.db_pool, panel.metadata.id).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An organization's shared panels and the caller's own, by name
+#[get("/organizations/{id}/panels")]
+pub async fn organization_panels(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let (page, per_page) = query.normalize();
+
+    let panels = PanelRepository::new()
+        .for_organization(&data.db_pool, path.into_inner(), practitioner_id, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        panels,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Find a panel's members now
+#[post("/panels/{id}/evaluate")]
+pub async fn evaluate_panel(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let mut panel = visible_panel(&data, path.into_inner(), practitioner_id).await?;
+
+    let (member_count, evaluated_at) = PanelRepository::new().evaluate(&data.db_pool, &panel).await?;
+    panel.member_count = Some(member_count);
+    panel.evaluated_at = Some(evaluated_at);
+    tracing::info!(panel_id = %panel.metadata.id, member_count, "Panel evaluated");
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(panel)))
+}
+
+/// Patients found by a panel's last evaluation, by name
+#[get("/panels/{id}/members")]
+pub async fn panel_members(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = panel_practitioner(req.extensions().get::<AuthContext>())?;
+    let panel = visible_panel(&data, path.into_inner(), practitioner_id).await?;
+    let (page, per_page) = query.normalize();
+    let patients = practitioner_id.map_or(SearchPatientFilter::All, SearchPatientFilter::TreatedBy);
+
+    let members = PanelRepository::new()
+        .members(&data.db_pool, panel.metadata.id, patients, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        members,
+        json!({
+            "page": page,
+            "per_page": per_page,
+            "evaluated_at": panel.evaluated_at,
+            "member_count": panel.member_count,
+        }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::scopes::Scopes;
+
+    fn context(subject: &str, patient_id: Option<Id>) -> AuthContext {
+        AuthContext {
+            subject: subject.to_string(),
+            patient_id,
+            tenant_id: None,
+            scopes: Scopes::parse("user/*.read"),
+            clearance: Default::default(),
+            purpose_of_use: None,
+            roles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_panel_practitioner() {
+        let practitioner_id = uuid::Uuid::new_v4();
+        assert_eq!(panel_practitioner(None).unwrap(), None);
+        assert_eq!(
+            panel_practitioner(Some(&context(&practitioner_id.to_string(), None))).unwrap(),
+            Some(practitioner_id)
+        );
+        assert!(panel_practitioner(Some(&context("jane", Some(uuid::Uuid::new_v4())))).is_err());
+        assert!(panel_practitioner(Some(&context("service", None))).is_err());
+    }
+
+    #[test]
+    fn test_only_the_owner_changes_a_panel() {
+        let owner = uuid::Uuid::new_v4();
+        let mut panel = PatientPanel::new("Diabetics", uuid::Uuid::new_v4(), owner, vec![]);
+        panel.shared = true;
+        assert!(check_owner(&panel, Some(owner)).is_ok());
+        assert!(check_owner(&panel, None).is_ok());
+        assert!(check_owner(&panel, Some(uuid::Uuid::new_v4())).is_err());
+    }
+}
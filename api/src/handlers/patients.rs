@@ -1,14 +1,73 @@
 //! Patient endpoints for the Nexus API.
 //!
-//! These handlers currently return scaffold-level data while persistence and
-//! role-based access controls are still in progress.
+//! Reading, updating and deleting single patients still return scaffold-level
+//! data while persistence is in progress. Each needs a treating relationship
+//! or emergency access first (see `care_teams`), so writes and deletes are
+//! audited like reads.
+//!
+//! `GET /patients/export` streams NDJSON from a server-side cursor. Batches are
+//! fetched only when actix polls the body stream, so a slow client throttles
+//! the database reads instead of buffering the export in memory. Register it
+//! before `GET /patients/{id}`, which would otherwise match `export` as an id.
+//!
+//! `POST /patients` stores one patient in `emr.patients` and returns the id
+//! the database assigned.
+//!
+//! `POST /patients/_bulk` loads rosters from a JSON array or NDJSON body,
+//! inserting in chunked transactions and reporting an outcome per item.
+//!
+//! Both check new patients against the validation profile of the caller's
+//! tenant; a strict profile rejects the patient, or the bulk item.
+//!
+//! `GET /patients/{id}/everything` merges the FHIR server's `$everything`
+//! result with locally held encounters and observations into one
+//! `collection` Bundle, the input for the patient summary page and C-CDA
+//! documents. If the FHIR server is unavailable the local resources are
+//! still returned, with an OperationOutcome entry saying the result is partial.
+//! Like other patient data it needs a treating relationship or emergency
+//! access (see `care_teams`).
+//!
+//! `GET /patients/{id}/summary` returns what the detail page opens with
+//! (latest vitals, active problems and medications, next appointment) from
+//! one stored row, kept current as those records change (see
+//! `emr_core::services::patient_summary`).
 
-use actix_web::{get, post, put, delete, web, HttpRequest, HttpResponse};
+use actix_web::{get, post, put, delete, web, web::Bytes, HttpRequest, HttpResponse};
+use futures_util::{stream, TryStreamExt};
 use serde::{Deserialize, Serialize};
-use crate::error::Result;
-use crate::handlers::{ApiResponse, PaginationParams, PaginatedResponse, PaginationMeta};
+use emr_core::services::patient_summary::PatientSummary;
+use emr_core::services::{EncounterService, ObservationService};
+use emr_core::validation::ValidationProfile;
+use emr_fhir::{
+    encounter_to_fhir, observation_to_fhir, withhold_uncleared_entries, Bundle, BundleBuilder, FhirClientError,
+};
+use serde_json::{json, Value};
+use validator::{Validate, ValidationError};
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_bundle_response;
+use crate::handlers::care_teams::{authorize_patient_access, clearance, tenant};
+use crate::handlers::validation_profiles::{active_profile, check_profile, enforce_profile};
+use crate::handlers::{ApiResponse, PaginatedResponse, PaginationMeta, PaginationParams, ValidatedJson};
+use crate::middleware::versioning::{versioned_json, ApiVersion};
+use crate::models::NewPatientModel;
+use crate::repositories::{parse_export_fields, PatientExportCursor, PatientRepository, PatientSummaryRepository};
 use crate::AppState;
 
+/// Rows fetched from the export cursor per chunk
+const DEFAULT_EXPORT_BATCH: usize = 500;
+
+/// Largest accepted export batch size
+const MAX_EXPORT_BATCH: usize = 5000;
+
+/// Most patients accepted by one bulk request
+const MAX_BULK_ITEMS: usize = 10_000;
+
+/// Patients inserted per transaction
+const BULK_CHUNK_SIZE: usize = 500;
+
+/// Administrative genders accepted on create
+const GENDERS: &[&str] = &["male", "female", "other", "unknown"];
+
 /// Patient response DTO
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PatientResponse {
@@ -20,32 +79,406 @@ pub struct PatientResponse {
 }
 
 /// Patient creation request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreatePatientRequest {
+    #[validate(length(min = 1, max = 200))]
     pub name: String,
+    #[validate(custom(function = "validate_gender"))]
     pub gender: Option<String>,
+    #[validate(custom(function = "validate_birth_date"))]
     pub birth_date: Option<String>,
 }
 
+fn validate_gender(gender: &str) -> std::result::Result<(), ValidationError> {
+    let gender = gender.trim().to_lowercase();
+    if gender.is_empty() || GENDERS.contains(&gender.as_str()) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("one_of");
+    error.message = Some(format!("must be one of {}", GENDERS.join(", ")).into());
+    Err(error)
+}
+
+fn validate_birth_date(date: &str) -> std::result::Result<(), ValidationError> {
+    let date = date.trim();
+    if date.is_empty() || chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("date");
+    error.message = Some("must be YYYY-MM-DD".into());
+    Err(error)
+}
+
+impl CreatePatientRequest {
+    /// Validate the request and split the name into family and given names
+    fn to_model(&self) -> std::result::Result<NewPatientModel, String> {
+        let mut names: Vec<String> = self.name.split_whitespace().map(str::to_string).collect();
+        let family_name = names.pop().ok_or_else(|| "name is required".to_string())?;
+
+        let gender = match self.gender.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(gender) => {
+                let gender = gender.to_lowercase();
+                if !GENDERS.contains(&gender.as_str()) {
+                    return Err(format!("gender must be one of {}", GENDERS.join(", ")));
+                }
+                Some(gender)
+            }
+        };
+
+        let birth_date = match self.birth_date.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(date) => Some(
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|_| "birth_date must be YYYY-MM-DD".to_string())?,
+            ),
+        };
+
+        Ok(NewPatientModel {
+            family_name,
+            given_names: names,
+            gender,
+            birth_date,
+        })
+    }
+}
+
+/// Outcome of one bulk item
+#[derive(Debug, Serialize)]
+pub struct BulkItemOutcome {
+    /// Position of the item in the request
+    pub index: usize,
+    /// HTTP status the item would have received on its own
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BulkItemOutcome {
+    fn created(index: usize, id: uuid::Uuid) -> Self {
+        Self {
+            index,
+            status: 201,
+            id: Some(id.to_string()),
+            error: None,
+        }
+    }
+
+    fn failed(index: usize, status: u16, error: String) -> Self {
+        Self {
+            index,
+            status,
+            id: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// FHIR `Patient` JSON of a new patient, for validation profiles
+fn new_patient_fhir(model: &NewPatientModel) -> Value {
+    let mut name = json!({"family": model.family_name});
+    if !model.given_names.is_empty() {
+        name["given"] = json!(model.given_names);
+    }
+    let mut resource = json!({"resourceType": "Patient", "active": true, "name": [name]});
+    if let Some(gender) = &model.gender {
+        resource["gender"] = json!(gender);
+    }
+    if let Some(birth_date) = model.birth_date {
+        resource["birthDate"] = json!(birth_date.format("%Y-%m-%d").to_string());
+    }
+    resource
+}
+
+/// Keep the bulk items the profile accepts, recording an outcome for the
+/// others
+fn check_bulk_profile(
+    profile: &ValidationProfile,
+    pending: Vec<(usize, NewPatientModel)>,
+    outcomes: &mut Vec<BulkItemOutcome>,
+) -> Vec<(usize, NewPatientModel)> {
+    pending
+        .into_iter()
+        .filter_map(|(index, model)| match check_profile(profile, &new_patient_fhir(&model)) {
+            Ok(()) => Some((index, model)),
+            Err(e) => {
+                outcomes.push(BulkItemOutcome::failed(index, e.status_code().as_u16(), e.to_string()));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Bulk create summary
+#[derive(Debug, Serialize)]
+pub struct BulkCreateResponse {
+    pub created: usize,
+    pub failed: usize,
+    pub items: Vec<BulkItemOutcome>,
+}
+
+/// Parse a bulk body into per-item requests.
+///
+/// Items that are not valid patient objects become per-item errors; only a
+/// body that is not an array (or not NDJSON) fails the whole request.
+fn parse_bulk_items(body: &[u8], ndjson: bool) -> Result<Vec<std::result::Result<CreatePatientRequest, String>>> {
+    let values: Vec<std::result::Result<serde_json::Value, String>> = if ndjson {
+        let text = std::str::from_utf8(body).map_err(|_| ApiError::validation_error("NDJSON body must be UTF-8"))?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("Invalid JSON: {}", e)))
+            .collect()
+    } else {
+        let values: Vec<serde_json::Value> = serde_json::from_slice(body)
+            .map_err(|e| ApiError::validation_error(&format!("Body must be a JSON array of patients: {}", e)))?;
+        values.into_iter().map(Ok).collect()
+    };
+
+    Ok(values
+        .into_iter()
+        .map(|value| value.and_then(|value| serde_json::from_value(value).map_err(|e| format!("Invalid patient: {}", e))))
+        .collect())
+}
+
+/// Create many patients in one request
+#[post("/patients/_bulk")]
+pub async fn bulk_create_patients(
+    mut payload: web::Payload,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let ndjson = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("application/x-ndjson"))
+        .unwrap_or(false);
+
+    let max_bytes = data.config.body_limits.bulk_bytes;
+    let mut body = Vec::new();
+    while let Some(chunk) = payload
+        .try_next()
+        .await
+        .map_err(|e| ApiError::validation_error(&format!("Invalid request body: {}", e)))?
+    {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(ApiError::payload_too_large(&format!("Bulk body exceeds the {} byte limit", max_bytes)));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let items = parse_bulk_items(&body, ndjson)?;
+    if items.is_empty() {
+        return Err(ApiError::validation_error("Bulk request contains no patients"));
+    }
+    if items.len() > MAX_BULK_ITEMS {
+        return Err(ApiError::validation_error(&format!(
+            "Bulk requests are limited to {} patients",
+            MAX_BULK_ITEMS
+        )));
+    }
+
+    let mut outcomes = Vec::with_capacity(items.len());
+    let mut pending = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        match item.and_then(|request| request.to_model()) {
+            Ok(model) => pending.push((index, model)),
+            Err(error) => outcomes.push(BulkItemOutcome::failed(index, 400, error)),
+        }
+    }
+    let pending = match active_profile(&data, tenant(&req), "Patient").await? {
+        Some(profile) => check_bulk_profile(&profile, pending, &mut outcomes),
+        None => pending,
+    };
+
+    let repository = PatientRepository::new();
+    for chunk in pending.chunks(BULK_CHUNK_SIZE) {
+        let (indexes, models): (Vec<usize>, Vec<NewPatientModel>) = chunk.iter().cloned().unzip();
+
+        match repository.insert_batch(&data.db_pool, models).await {
+            Ok(results) => {
+                for (index, result) in indexes.into_iter().zip(results) {
+                    outcomes.push(match result {
+                        Ok(id) => BulkItemOutcome::created(index, id),
+                        Err(e) => BulkItemOutcome::failed(index, e.status_code().as_u16(), e.to_string()),
+                    });
+                }
+            }
+            // The chunk was rolled back; earlier chunks stay committed
+            Err(e) => {
+                tracing::error!(error = %e, "Bulk patient chunk failed");
+                let status = e.status_code().as_u16();
+                outcomes.extend(
+                    indexes
+                        .into_iter()
+                        .map(|index| BulkItemOutcome::failed(index, status, e.to_string())),
+                );
+            }
+        }
+    }
+
+    outcomes.sort_by_key(|outcome| outcome.index);
+    let created = outcomes.iter().filter(|outcome| outcome.id.is_some()).count();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(BulkCreateResponse {
+        created,
+        failed: outcomes.len() - created,
+        items: outcomes,
+    })))
+}
+
+/// Patient export parameters
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// Comma-separated columns to include; all columns when omitted
+    pub fields: Option<String>,
+    /// Rows per cursor fetch
+    pub batch_size: Option<usize>,
+}
+
+/// Join JSON rows into one NDJSON chunk
+fn ndjson_chunk(rows: Vec<String>) -> Bytes {
+    let mut chunk = Vec::with_capacity(rows.iter().map(|row| row.len() + 1).sum());
+    for row in rows {
+        chunk.extend_from_slice(row.as_bytes());
+        chunk.push(b'\n');
+    }
+    Bytes::from(chunk)
+}
+
+/// Stream all patients as NDJSON
+#[get("/patients/export")]
+pub async fn export_patients(
+    query: web::Query<ExportParams>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let fields = parse_export_fields(query.fields.as_deref())?;
+    let batch_size = query
+        .batch_size
+        .unwrap_or(DEFAULT_EXPORT_BATCH)
+        .clamp(1, MAX_EXPORT_BATCH);
+
+    let cursor = PatientExportCursor::open(&data.db_pool, &fields, batch_size).await?;
+    tracing::info!(fields = fields.len(), batch_size, "Patient export started");
+
+    let body = stream::try_unfold(cursor, |mut cursor| async move {
+        let rows = cursor.next_batch().await?;
+        if rows.is_empty() {
+            return Ok::<_, ApiError>(None);
+        }
+        Ok(Some((ndjson_chunk(rows), cursor)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body))
+}
+
 /// Get patient by ID
 #[get("/patients/{id}")]
 pub async fn get_patient(
-    path: web::Path<String>,
-    _req: HttpRequest,
-    _data: web::Data<AppState>,
+    path: web::Path<uuid::Uuid>,
+    version: ApiVersion,
+    req: HttpRequest,
+    data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
     let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
     
     // TODO(nexus-phase1): Fetch from repository-backed storage.
     let patient = PatientResponse {
-        id: patient_id,
+        id: patient_id.to_string(),
         name: "John Doe".to_string(),
         gender: Some("male".to_string()),
         birth_date: Some("1990-01-01".to_string()),
         active: true,
     };
     
-    Ok(HttpResponse::Ok().json(ApiResponse::new(patient)))
+    versioned_json(version, "patient", &patient)
+}
+
+/// Everything known about a patient as a FHIR `collection` Bundle
+#[get("/patients/{id}/everything")]
+pub async fn patient_everything(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let remote = data.fhir_client.patient_everything(&patient_id.to_string()).await;
+
+    let mut local: Vec<Value> = data
+        .encounters
+        .get_patient_encounters(patient_id)
+        .await?
+        .iter()
+        .map(encounter_to_fhir)
+        .collect();
+    local.extend(
+        data.observations
+            .get_patient_observations(patient_id)
+            .await?
+            .iter()
+            .map(observation_to_fhir),
+    );
+
+    let mut bundle = everything_bundle(remote, local)?;
+    withhold_uncleared_entries(&mut bundle, clearance(&req));
+
+    fhir_bundle_response(&req, HttpResponse::Ok(), bundle)
+}
+
+/// What the patient detail page opens with, from the stored summary
+#[get("/patients/{id}/summary")]
+pub async fn get_patient_summary(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+
+    let summary = PatientSummaryRepository::new()
+        .find(&data.db_pool, patient_id)
+        .await?
+        .unwrap_or_else(|| PatientSummary::new(patient_id));
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(summary.view(chrono::Utc::now()))))
+}
+
+/// Merge the server's `$everything` result with local resources. Server
+/// entries win when both hold the same resource.
+fn everything_bundle(remote: std::result::Result<Bundle, FhirClientError>, local: Vec<Value>) -> Result<Bundle> {
+    let mut builder = BundleBuilder::new("collection");
+
+    match remote {
+        Ok(bundle) => builder.append(bundle),
+        // Not registered on the FHIR server yet; local data is all there is
+        Err(FhirClientError::NotFound { .. }) => {}
+        Err(err @ FhirClientError::Unavailable { .. }) => {
+            tracing::warn!(error = %err, "FHIR server unavailable; returning local resources only");
+            builder.add_resource(json!({
+                "resourceType": "OperationOutcome",
+                "issue": [{
+                    "severity": "warning",
+                    "code": "incomplete",
+                    "diagnostics": format!("Resources from the FHIR server are missing: {}", err),
+                }]
+            }));
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    for resource in local {
+        builder.add_resource(resource);
+    }
+
+    Ok(builder.finish())
 }
 
 /// List patients with pagination
@@ -89,13 +522,16 @@ pub async fn list_patients(
 /// Create new patient
 #[post("/patients")]
 pub async fn create_patient(
-    request: web::Json<CreatePatientRequest>,
-    _req: HttpRequest,
-    _data: web::Data<AppState>,
+    request: ValidatedJson<CreatePatientRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
-    // TODO(nexus-phase1): Persist through service/repository layers.
+    let model = request.to_model().map_err(|e| ApiError::validation_error(&e))?;
+    enforce_profile(&data, tenant(&req), "Patient", &new_patient_fhir(&model)).await?;
+    let id = PatientRepository::new().create(&data.db_pool, model).await?;
+
     let patient = PatientResponse {
-        id: uuid::Uuid::new_v4().to_string(),
+        id: id.to_string(),
         name: request.name.clone(),
         gender: request.gender.clone(),
         birth_date: request.birth_date.clone(),
@@ -108,16 +544,17 @@ pub async fn create_patient(
 /// Update patient
 #[put("/patients/{id}")]
 pub async fn update_patient(
-    path: web::Path<String>,
-    request: web::Json<CreatePatientRequest>,
-    _req: HttpRequest,
-    _data: web::Data<AppState>,
+    path: web::Path<uuid::Uuid>,
+    request: ValidatedJson<CreatePatientRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
     let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
     
     // TODO(nexus-phase1): Persist through service/repository layers.
     let patient = PatientResponse {
-        id: patient_id,
+        id: patient_id.to_string(),
         name: request.name.clone(),
         gender: request.gender.clone(),
         birth_date: request.birth_date.clone(),
@@ -130,13 +567,162 @@ pub async fn update_patient(
 /// Delete patient
 #[delete("/patients/{id}")]
 pub async fn delete_patient(
-    path: web::Path<String>,
-    _req: HttpRequest,
-    _data: web::Data<AppState>,
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
-    let _patient_id = path.into_inner();
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
     
     // TODO(nexus-phase1): Persist through service/repository layers.
     
     Ok(HttpResponse::NoContent().finish())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_request_validation() {
+        let request: CreatePatientRequest =
+            serde_json::from_value(json!({"name": "Ada Lovelace", "gender": "Female", "birth_date": "1815-12-10"}))
+                .unwrap();
+        assert!(request.validate().is_ok());
+
+        let request: CreatePatientRequest =
+            serde_json::from_value(json!({"name": "", "gender": "robot", "birth_date": "12/10/1815"})).unwrap();
+        let error = ApiError::from(request.validate().unwrap_err());
+        let fields: Vec<String> = error.field_errors().unwrap().into_iter().map(|field| field.field).collect();
+        assert_eq!(fields, vec!["birth_date", "gender", "name"]);
+    }
+
+    #[test]
+    fn test_strict_profile_rejects_bulk_item() {
+        use emr_core::validation::{ProfileRule, RuleCheck, RuleSeverity};
+
+        let mut profile = ValidationProfile {
+            id: uuid::Uuid::new_v4(),
+            tenant_id: None,
+            entity_type: "Patient".to_string(),
+            version: 2,
+            strict: true,
+            rules: vec![ProfileRule {
+                name: "birth-date".to_string(),
+                description: "birthDate is required".to_string(),
+                field: "birthDate".to_string(),
+                check: RuleCheck::Required,
+                severity: RuleSeverity::Error,
+            }],
+            created_at: chrono::Utc::now(),
+        };
+        let request = |body: Value| serde_json::from_value::<CreatePatientRequest>(body).unwrap().to_model().unwrap();
+        let pending = vec![
+            (0, request(json!({"name": "Ada Lovelace", "birth_date": "1815-12-10"}))),
+            (1, request(json!({"name": "Charles Babbage"}))),
+        ];
+
+        let mut outcomes = Vec::new();
+        let accepted = check_bulk_profile(&profile, pending.clone(), &mut outcomes);
+        assert_eq!(accepted.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![0]);
+        assert_eq!((outcomes[0].index, outcomes[0].status), (1, 400));
+        assert!(outcomes[0].error.as_ref().unwrap().contains("Rejected by validation profile v2"));
+
+        profile.strict = false;
+        let mut outcomes = Vec::new();
+        assert_eq!(check_bulk_profile(&profile, pending, &mut outcomes).len(), 2);
+        assert!(outcomes.is_empty());
+    }
+
+    #[test]
+    fn test_everything_bundle_merges_local_resources() {
+        let remote = json!({
+            "resourceType": "Bundle",
+            "type": "searchset",
+            "entry": [
+                {"resource": {"resourceType": "Patient", "id": "p1"}},
+                {"resource": {"resourceType": "Encounter", "id": "e1", "status": "finished"}}
+            ]
+        });
+        let local = vec![
+            json!({"resourceType": "Encounter", "id": "e1", "status": "in-progress"}),
+            json!({"resourceType": "Observation", "id": "o1"}),
+        ];
+
+        let remote = serde_json::from_value(remote).unwrap();
+        let bundle = everything_bundle(Ok(remote), local).unwrap().into_value();
+        assert_eq!(bundle["type"], "collection");
+        assert_eq!(bundle["total"], 3);
+        assert_eq!(bundle["entry"][1]["resource"]["status"], "finished");
+        assert_eq!(bundle["entry"][2]["resource"]["id"], "o1");
+    }
+
+    #[test]
+    fn test_everything_bundle_without_fhir_server() {
+        let local = vec![json!({"resourceType": "Observation", "id": "o1"})];
+
+        let not_found = FhirClientError::NotFound { resource: "Patient/p1".to_string() };
+        let bundle = everything_bundle(Err(not_found), local.clone()).unwrap().into_value();
+        assert_eq!(bundle["total"], 1);
+
+        let unavailable = FhirClientError::Unavailable { status: Some(503), message: "maintenance".to_string() };
+        let bundle = everything_bundle(Err(unavailable), local.clone()).unwrap().into_value();
+        assert_eq!(bundle["total"], 2);
+        assert_eq!(bundle["entry"][0]["resource"]["resourceType"], "OperationOutcome");
+
+        let rejected = FhirClientError::Rejected { status: 403, message: "forbidden".to_string(), outcome: None };
+        assert!(everything_bundle(Err(rejected), local).is_err());
+    }
+
+    #[test]
+    fn test_ndjson_chunk() {
+        let chunk = ndjson_chunk(vec![r#"{"id":"1"}"#.to_string(), r#"{"id":"2"}"#.to_string()]);
+        assert_eq!(&chunk[..], b"{\"id\":\"1\"}\n{\"id\":\"2\"}\n");
+        assert!(ndjson_chunk(Vec::new()).is_empty());
+    }
+
+    fn request(name: &str, gender: Option<&str>, birth_date: Option<&str>) -> CreatePatientRequest {
+        CreatePatientRequest {
+            name: name.to_string(),
+            gender: gender.map(str::to_string),
+            birth_date: birth_date.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_create_request_to_model() {
+        let model = request("Mary Ann Smith", Some("Female"), Some("1985-05-15")).to_model().unwrap();
+        assert_eq!(model.family_name, "Smith");
+        assert_eq!(model.given_names, vec!["Mary", "Ann"]);
+        assert_eq!(model.gender.as_deref(), Some("female"));
+        assert_eq!(model.birth_date, chrono::NaiveDate::from_ymd_opt(1985, 5, 15));
+
+        assert!(request("  ", None, None).to_model().is_err());
+        assert!(request("Smith", Some("x"), None).to_model().is_err());
+        assert!(request("Smith", None, Some("05/15/1985")).to_model().is_err());
+    }
+
+    #[test]
+    fn test_parse_bulk_items() {
+        let array = br#"[{"name": "John Doe"}, {"gender": "male"}]"#;
+        let items = parse_bulk_items(array, false).unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+
+        let ndjson = b"{\"name\": \"John Doe\"}\n\nnot json\n{\"name\": \"Jane Roe\"}\n";
+        let items = parse_bulk_items(ndjson, true).unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items[1].is_err());
+        assert!(items[2].is_ok());
+
+        assert!(parse_bulk_items(br#"{"name": "John Doe"}"#, false).is_err());
+    }
+
+    #[test]
+    fn test_export_params() {
+        let params: ExportParams = serde_json::from_str(r#"{"fields": "id,gender"}"#).unwrap();
+        assert_eq!(params.fields.as_deref(), Some("id,gender"));
+        assert!(params.batch_size.is_none());
+    }
+}
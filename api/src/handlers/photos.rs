@@ -0,0 +1,325 @@
+//! Patient photo upload
+//!
+//! Photos are accepted as multipart uploads, checked against the configured
+//! content-type allowlist and size limit, scanned for malware, and stored as
+//! FHIR Attachments together with a server-generated PNG thumbnail. Photos
+//! are not kept until scanned, so an upload is refused when the scanner
+//! cannot be reached; one found to be malware is audited and rejected.
+//! Decoding runs on the blocking thread pool under `image::Limits`, so a
+//! small file claiming a huge canvas is refused instead of exhausting memory.
+
+use actix_multipart::Multipart;
+use actix_web::{post, web, HttpMessage, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use emr_core::domain::Attachment;
+use emr_core::services::malware::ScanVerdict;
+use futures_util::TryStreamExt;
+use image::{imageops::FilterType, ImageError, ImageFormat, ImageReader, Limits};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::io::Cursor;
+use crate::auth::AuthContext;
+use crate::config::UploadConfig;
+use crate::error::{ApiError, Result};
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::ApiResponse;
+use crate::repositories::{DocumentRepository, PatientRepository};
+use crate::services::malware_scanner;
+use crate::AppState;
+
+/// Multipart field carrying the image
+const PHOTO_FIELD: &str = "photo";
+
+/// Multipart field carrying an optional caption
+const TITLE_FIELD: &str = "title";
+
+/// Processed photo ready to attach to a patient
+#[derive(Debug)]
+pub struct ProcessedPhoto {
+    pub original: Attachment,
+    pub thumbnail: Attachment,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Photo upload response DTO
+#[derive(Debug, Serialize)]
+pub struct PhotoResponse {
+    pub content_type: String,
+    pub size: Option<u64>,
+    pub hash: Option<String>,
+    pub title: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Base64-encoded PNG thumbnail for avatars
+    pub thumbnail: Option<String>,
+}
+
+impl From<&ProcessedPhoto> for PhotoResponse {
+    fn from(photo: &ProcessedPhoto) -> Self {
+        Self {
+            content_type: photo.original.content_type.clone(),
+            size: photo.original.size,
+            hash: photo.original.hash.clone(),
+            title: photo.original.title.clone(),
+            width: photo.width,
+            height: photo.height,
+            thumbnail: photo.thumbnail.data.as_ref().map(|data| STANDARD.encode(data)),
+        }
+    }
+}
+
+/// FHIR Attachment hash: base64-encoded SHA-1 of the data
+fn attachment_hash(data: &[u8]) -> String {
+    STANDARD.encode(Sha1::digest(data))
+}
+
+/// Decoder limits for uploaded photos
+fn photo_limits(config: &UploadConfig) -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(config.max_photo_dimension);
+    limits.max_image_height = Some(config.max_photo_dimension);
+    limits.max_alloc = Some(config.max_photo_decode_bytes);
+    limits
+}
+
+/// Validate an uploaded image and generate its thumbnail
+///
+/// Decoding is CPU-bound; call it off the async workers.
+pub fn process_photo(
+    data: Vec<u8>,
+    declared_type: &str,
+    title: Option<String>,
+    config: &UploadConfig,
+) -> Result<ProcessedPhoto> {
+    if !config.allowed_image_types.iter().any(|allowed| allowed == declared_type) {
+        return Err(ApiError::validation_error(&format!(
+            "Unsupported photo content type: {}",
+            declared_type
+        )));
+    }
+
+    if data.len() > config.max_photo_bytes {
+        return Err(ApiError::payload_too_large(&format!(
+            "Photo exceeds the {} byte limit",
+            config.max_photo_bytes
+        )));
+    }
+
+    // Trust the bytes, not the declared content type
+    let format = image::guess_format(&data)
+        .map_err(|_| ApiError::validation_error("Photo is not a recognized image"))?;
+    if format.to_mime_type() != declared_type {
+        return Err(ApiError::validation_error(&format!(
+            "Photo content does not match declared type {}",
+            declared_type
+        )));
+    }
+
+    let mut reader = ImageReader::with_format(Cursor::new(&data), format);
+    reader.limits(photo_limits(config));
+    let image = reader.decode().map_err(|e| match e {
+        ImageError::Limits(_) => ApiError::payload_too_large(&format!(
+            "Photo is larger than {0}x{0} pixels or needs too much memory to decode",
+            config.max_photo_dimension
+        )),
+        e => ApiError::validation_error(&format!("Photo could not be decoded: {}", e)),
+    })?;
+
+    let thumbnail = image.resize(config.thumbnail_size, config.thumbnail_size, FilterType::Triangle);
+    let mut thumbnail_data = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut thumbnail_data), ImageFormat::Png)
+        .map_err(|e| ApiError::internal_error(&format!("Thumbnail encoding failed: {}", e)))?;
+
+    let now = Utc::now();
+    Ok(ProcessedPhoto {
+        width: image.width(),
+        height: image.height(),
+        thumbnail: Attachment {
+            content_type: "image/png".to_string(),
+            language: None,
+            size: Some(thumbnail_data.len() as u64),
+            hash: Some(attachment_hash(&thumbnail_data)),
+            data: Some(thumbnail_data),
+            url: None,
+            title: Some("thumbnail".to_string()),
+            creation: Some(now),
+        },
+        original: Attachment {
+            content_type: declared_type.to_string(),
+            language: None,
+            size: Some(data.len() as u64),
+            hash: Some(attachment_hash(&data)),
+            data: Some(data),
+            url: None,
+            title,
+            creation: Some(now),
+        },
+    })
+}
+
+/// Upload a patient photo
+///
+/// Expects a multipart body with a `photo` file field and an optional
+/// `title` text field.
+#[post("/patients/{id}/photos")]
+pub async fn upload_photo(
+    path: web::Path<uuid::Uuid>,
+    mut payload: Multipart,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let config = &data.config.uploads;
+
+    let mut photo: Option<(Vec<u8>, String)> = None;
+    let mut title = None;
+
+    while let Some(mut field) = payload
+        .try_next()
+        .await
+        .map_err(|e| ApiError::validation_error(&format!("Invalid multipart body: {}", e)))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let content_type = field.content_type().map(|mime| mime.essence_str().to_string());
+
+        // Read in chunks so oversized uploads are rejected without buffering
+        // the whole body
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field
+            .try_next()
+            .await
+            .map_err(|e| ApiError::validation_error(&format!("Invalid multipart body: {}", e)))?
+        {
+            if bytes.len() + chunk.len() > config.max_photo_bytes {
+                return Err(ApiError::payload_too_large(&format!(
+                    "Photo exceeds the {} byte limit",
+                    config.max_photo_bytes
+                )));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        match name.as_str() {
+            PHOTO_FIELD => {
+                let content_type = content_type
+                    .ok_or_else(|| ApiError::validation_error("Photo field requires a content type"))?;
+                photo = Some((bytes, content_type));
+            }
+            TITLE_FIELD => title = Some(String::from_utf8_lossy(&bytes).trim().to_string()),
+            _ => {}
+        }
+    }
+
+    let (bytes, content_type) =
+        photo.ok_or_else(|| ApiError::validation_error("Multipart body must include a photo field"))?;
+
+    let scanner = malware_scanner(&data.config.scanning)?;
+    let verdict = scanner.scan(&bytes).await.map_err(|error| {
+        tracing::warn!(%patient_id, %error, "Malware scan failed; photo refused");
+        ApiError::service_unavailable("The photo could not be scanned for malware; try again later")
+    })?;
+    if let ScanVerdict::Infected { signature } = verdict {
+        tracing::warn!(%patient_id, %signature, "Malware detected");
+        let uploaded_by = req.extensions().get::<AuthContext>().and_then(AuthContext::practitioner_id);
+        DocumentRepository::new()
+            .record_rejected_upload(
+                &data.db_pool,
+                "patients",
+                serde_json::json!({
+                    "patient_id": patient_id,
+                    "scanner": scanner.name(),
+                    "signature": signature,
+                    "content_type": content_type,
+                    "hash": attachment_hash(&bytes),
+                }),
+                uploaded_by,
+            )
+            .await?;
+        return Err(ApiError::validation_error("The photo was rejected because malware was detected in it"));
+    }
+
+    let config = config.clone();
+    let processed = web::block(move || process_photo(bytes, &content_type, title, &config)).await??;
+    let response = PhotoResponse::from(&processed);
+
+    let stored = PatientRepository::new()
+        .add_photos(&data.db_pool, patient_id, vec![processed.original, processed.thumbnail])
+        .await?;
+    if !stored {
+        return Err(ApiError::not_found(&format!("Patient {} not found", patient_id)));
+    }
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let image = image::DynamicImage::new_rgb8(width, height);
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_process_photo() {
+        let config = UploadConfig::default();
+        let data = png_bytes(400, 200);
+        let size = data.len() as u64;
+
+        let photo = process_photo(data, "image/png", Some("Intake".to_string()), &config).unwrap();
+
+        assert_eq!((photo.width, photo.height), (400, 200));
+        assert_eq!(photo.original.size, Some(size));
+        assert_eq!(photo.original.title.as_deref(), Some("Intake"));
+        // SHA-1 digests are 20 bytes, 28 characters in base64
+        assert_eq!(photo.original.hash.as_ref().unwrap().len(), 28);
+
+        let thumbnail = image::load_from_memory(photo.thumbnail.data.as_ref().unwrap()).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 64));
+    }
+
+    #[test]
+    fn test_process_photo_rejects_disallowed_type() {
+        let config = UploadConfig::default();
+        assert!(process_photo(png_bytes(10, 10), "image/gif", None, &config).is_err());
+    }
+
+    #[test]
+    fn test_process_photo_rejects_mismatched_content() {
+        let config = UploadConfig::default();
+        assert!(process_photo(png_bytes(10, 10), "image/jpeg", None, &config).is_err());
+        assert!(process_photo(b"not an image".to_vec(), "image/png", None, &config).is_err());
+    }
+
+    #[test]
+    fn test_process_photo_rejects_oversized_canvas() {
+        let config = UploadConfig {
+            max_photo_dimension: 100,
+            ..UploadConfig::default()
+        };
+        let error = process_photo(png_bytes(200, 10), "image/png", None, &config).unwrap_err();
+        assert_eq!(error.status_code(), actix_web::http::StatusCode::PAYLOAD_TOO_LARGE);
+
+        let config = UploadConfig {
+            max_photo_decode_bytes: 64,
+            ..UploadConfig::default()
+        };
+        assert!(process_photo(png_bytes(10, 10), "image/png", None, &config).is_err());
+    }
+
+    #[test]
+    fn test_process_photo_rejects_oversized_upload() {
+        let config = UploadConfig {
+            max_photo_bytes: 16,
+            ..UploadConfig::default()
+        };
+        assert!(process_photo(png_bytes(10, 10), "image/png", None, &config).is_err());
+    }
+}
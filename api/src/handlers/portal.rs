@@ -0,0 +1,387 @@
+//! Patient portal endpoints
+//!
+//! Patients sign in with a SMART standalone launch (`/auth/authorize` with
+//! `launch/patient` and `patient/*.read`) or with local credentials on
+//! `POST /portal/login`. Either way the token names the patient and carries
+//! only `patient/` scopes; `AuthMiddleware` keeps such tokens on the routes
+//! below, and each route serves the token's own patient after checking that
+//! its scopes allow the access to the resource type. Only secure messaging
+//! (`/portal/messages`) and notification preferences accept writes.
+
+use actix_web::{get, post, put, web, HttpMessage, HttpRequest, HttpResponse};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use chrono::{DateTime, Utc};
+use emr_core::domain::{
+    Communication, CommunicationParty, Encounter, EncounterStatus, Observation, ObservationStatus,
+};
+use emr_core::services::security_events::AuthEventKind;
+use emr_core::services::throttle::ThrottledAction;
+use emr_core::services::{EncounterService, ObservationService};
+use serde::Deserialize;
+use serde_json::json;
+use crate::auth::{events, jwt, networks, AuthContext, Claims};
+use crate::error::{ApiError, Result};
+use crate::handlers::care_teams::clearance;
+use crate::handlers::auth::TokenResponse;
+use crate::handlers::encounters::EncounterResponse;
+use crate::handlers::observations::ObservationResponse;
+use crate::handlers::messages::{load_thread, mark_thread_read, reply_to_thread, send_message};
+use crate::handlers::notification_preferences::{load_preferences, save_preferences, PreferencesRequest};
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::{CommunicationRepository, PortalRepository};
+use crate::AppState;
+
+/// Scopes of a session started with local credentials
+const PORTAL_SCOPE: &str = "openid patient/*.read patient/Communication.write";
+
+/// Lifetime of a portal session token
+const PORTAL_TOKEN_SECONDS: u64 = 900;
+
+/// Local credentials sign-in
+#[derive(Debug, Deserialize)]
+pub struct PortalLoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// New message from the patient to a practitioner
+#[derive(Debug, Deserialize)]
+pub struct PortalMessageRequest {
+    pub practitioner_id: uuid::Uuid,
+    pub topic: Option<String>,
+    pub body: String,
+    #[serde(default)]
+    pub attachment_ids: Vec<uuid::Uuid>,
+}
+
+/// Reply from the patient
+#[derive(Debug, Deserialize)]
+pub struct PortalReplyRequest {
+    pub body: String,
+    #[serde(default)]
+    pub attachment_ids: Vec<uuid::Uuid>,
+}
+
+/// The portal session of a request
+fn session(req: &HttpRequest) -> Result<AuthContext> {
+    req.extensions()
+        .get::<AuthContext>()
+        .cloned()
+        .ok_or_else(|| ApiError::authentication_error("Sign in to the portal"))
+}
+
+/// The signed-in patient, if the token's scopes allow reading a resource type
+fn session_patient(req: &HttpRequest, resource_type: &str) -> Result<uuid::Uuid> {
+    session(req)?.readable_patient(resource_type)
+}
+
+/// Check a password against an Argon2 PHC string
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
+
+/// Planned encounters that have not started, soonest first; those without
+/// a start time come last
+fn upcoming(encounters: Vec<Encounter>, now: DateTime<Utc>) -> Vec<Encounter> {
+    let start = |encounter: &Encounter| encounter.period.as_ref().and_then(|period| period.start);
+    let mut upcoming: Vec<Encounter> = encounters
+        .into_iter()
+        .filter(|encounter| encounter.status == EncounterStatus::Planned)
+        .filter(|encounter| start(encounter).map_or(true, |start| start >= now))
+        .collect();
+    upcoming.sort_by_key(|encounter| (start(encounter).is_none(), start(encounter)));
+    upcoming
+}
+
+/// Results a patient may see: final, amended or corrected
+fn released(observation: &Observation) -> bool {
+    matches!(
+        observation.status,
+        ObservationStatus::Final | ObservationStatus::Amended | ObservationStatus::Corrected
+    )
+}
+
+/// Sign in with local credentials
+///
+/// Unknown usernames, inactive accounts and wrong passwords get the same
+/// error. Too many failures for a username lock it out for a while
+/// (`auth.throttle.portal_login`), whoever is trying.
+#[post("/portal/login")]
+pub async fn portal_login(
+    request: web::Json<PortalLoginRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let repository = PortalRepository::new();
+    let ip_address = networks::request_ip(&req, &data.config.auth.network_zones.trusted_proxies)
+        .map(|address| address.to_string());
+    let ip_address = ip_address.as_deref();
+    let username = request.username.trim();
+    let throttle_key = username.to_lowercase();
+    data.throttle.check(ThrottledAction::PortalLogin, &throttle_key).await?;
+
+    let account = repository.find_account(&data.db_pool, username).await?;
+    let verified = account
+        .as_ref()
+        .filter(|account| account.active && verify_password(&request.password, &account.password_hash));
+    let Some(account) = verified else {
+        // Failures against a known account count towards that account,
+        // guessed usernames towards the caller's address
+        let subject = match &account {
+            Some(account) => format!("portal:{}", account.id),
+            None => events::anonymous_subject(ip_address),
+        };
+        let event = events::auth_event(AuthEventKind::LoginFailed, subject, req.headers(), ip_address, None);
+        events::record(&data.db_pool, &event).await;
+        let lockout = data.throttle.record_failure(ThrottledAction::PortalLogin, &throttle_key, ip_address).await;
+        if let Some(lockout) = lockout {
+            events::record_security_event(&data.db_pool, &lockout).await;
+        }
+        return Err(ApiError::authentication_error("Invalid username or password"));
+    };
+    data.throttle.reset(ThrottledAction::PortalLogin, &throttle_key).await;
+    repository.record_login(&data.db_pool, account.id).await?;
+    let subject = format!("portal:{}", account.id);
+    let event = events::auth_event(AuthEventKind::LoginSucceeded, subject.clone(), req.headers(), ip_address, None);
+    events::record(&data.db_pool, &event).await;
+
+    let now = Utc::now().timestamp() as usize;
+    let claims = Claims {
+        sub: subject,
+        iss: jwt::TOKEN_ISSUER.to_string(),
+        exp: now + PORTAL_TOKEN_SECONDS as usize,
+        iat: now,
+        scope: Some(PORTAL_SCOPE.to_string()),
+        patient: Some(account.patient_id),
+        clearance: None,
+        tenant: None,
+        roles: Vec::new(),
+    };
+
+    Ok(HttpResponse::Ok().json(TokenResponse {
+        access_token: jwt::create_token(&data.config.auth.jwt_secret, &claims)?,
+        token_type: "Bearer".to_string(),
+        expires_in: PORTAL_TOKEN_SECONDS,
+        scope: claims.scope,
+        patient: Some(account.patient_id.to_string()),
+    }))
+}
+
+/// The signed-in patient's demographics
+#[get("/portal/me")]
+pub async fn portal_demographics(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "Patient")?;
+
+    let demographics = PortalRepository::new()
+        .demographics(&data.db_pool, patient_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Patient record not found"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(demographics)))
+}
+
+/// The signed-in patient's upcoming appointments (planned encounters)
+#[get("/portal/appointments")]
+pub async fn portal_appointments(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "Encounter")?;
+
+    let encounters = data.encounters.get_patient_encounters(patient_id).await?;
+    let appointments: Vec<EncounterResponse> = upcoming(encounters, Utc::now())
+        .iter()
+        .map(EncounterResponse::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(appointments)))
+}
+
+/// The signed-in patient's released results, newest first
+#[get("/portal/observations")]
+pub async fn portal_observations(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "Observation")?;
+
+    let mut observations: Vec<Observation> = data
+        .observations
+        .get_patient_observations(patient_id)
+        .await?
+        .into_iter()
+        .filter(|observation| released(observation) && clearance(&req).clears(observation.confidentiality))
+        .collect();
+    observations.sort_by_key(|observation| std::cmp::Reverse(observation.effective));
+    let observations: Vec<ObservationResponse> = observations.iter().map(ObservationResponse::from).collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(observations)))
+}
+
+/// The signed-in patient's current documents, newest first
+#[get("/portal/documents")]
+pub async fn portal_documents(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "DocumentReference")?;
+
+    let documents = PortalRepository::new()
+        .documents(&data.db_pool, patient_id, clearance(&req))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(documents)))
+}
+
+/// The signed-in patient's message threads, most recently active first
+#[get("/portal/messages")]
+pub async fn portal_threads(
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "Communication")?;
+    let (page, per_page) = query.normalize();
+
+    let threads = CommunicationRepository::new()
+        .threads(
+            &data.db_pool,
+            CommunicationParty::Patient(patient_id),
+            None,
+            query.limit(),
+            query.offset(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        threads,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Messages of one of the signed-in patient's threads, oldest first
+#[get("/portal/messages/{id}")]
+pub async fn portal_thread(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "Communication")?;
+
+    let messages = load_thread(&data, path.into_inner(), CommunicationParty::Patient(patient_id)).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(messages)))
+}
+
+/// Send a practitioner a message in a new thread
+#[post("/portal/messages")]
+pub async fn portal_send_message(
+    request: web::Json<PortalMessageRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = session(&req)?.writable_patient("Communication")?;
+    let request = request.into_inner();
+
+    // TODO(nexus-phase2): Limit recipients to the patient's care team.
+    let message = Communication::new_thread(
+        patient_id,
+        CommunicationParty::Patient(patient_id),
+        vec![CommunicationParty::Practitioner(request.practitioner_id)],
+        request.topic,
+        &request.body,
+    )
+    .with_attachments(request.attachment_ids);
+
+    let message = send_message(&data, message).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::new(message)))
+}
+
+/// Reply in one of the signed-in patient's threads
+#[post("/portal/messages/{id}/replies")]
+pub async fn portal_reply(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<PortalReplyRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = session(&req)?.writable_patient("Communication")?;
+    let request = request.into_inner();
+
+    let message = reply_to_thread(
+        &data,
+        path.into_inner(),
+        CommunicationParty::Patient(patient_id),
+        &request.body,
+        request.attachment_ids,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(ApiResponse::new(message)))
+}
+
+/// Mark one of the signed-in patient's threads read
+#[post("/portal/messages/{id}/read")]
+pub async fn portal_mark_read(
+    path: web::Path<uuid::Uuid>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = session(&req)?.writable_patient("Communication")?;
+
+    mark_thread_read(&data, path.into_inner(), CommunicationParty::Patient(patient_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The signed-in patient's notification preferences
+#[get("/portal/notification-preferences")]
+pub async fn portal_notification_preferences(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let patient_id = session_patient(&req, "Patient")?;
+
+    let preferences = load_preferences(&data, patient_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(preferences)))
+}
+
+/// Replace the signed-in patient's notification preferences
+#[put("/portal/notification-preferences")]
+pub async fn portal_update_notification_preferences(
+    request: web::Json<PreferencesRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    // Preferences are the patient's own settings rather than FHIR data, so
+    // no write scope is needed
+    let patient_id = session_patient(&req, "Patient")?;
+
+    let preferences = save_preferences(&data, patient_id, request.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(preferences)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use emr_core::domain::Period;
+    use emr_core::domain::EncounterClass;
+
+    fn planned(start: Option<DateTime<Utc>>) -> Encounter {
+        let mut encounter = Encounter::new(EncounterStatus::Planned, EncounterClass::Ambulatory, uuid::Uuid::new_v4());
+        encounter.period = Some(Period { start, end: None });
+        encounter
+    }
+
+    #[test]
+    fn test_upcoming_appointments() {
+        let now = Utc::now();
+        let later = planned(Some(now + Duration::days(7)));
+        let sooner = planned(Some(now + Duration::days(1)));
+        let unscheduled = planned(None);
+        let missed = planned(Some(now - Duration::days(1)));
+        let mut finished = planned(Some(now + Duration::days(2)));
+        finished.status = EncounterStatus::Finished;
+
+        let ids = |encounters: &[Encounter]| encounters.iter().map(|e| e.metadata.id).collect::<Vec<_>>();
+        let expected = ids(&[sooner.clone(), later.clone(), unscheduled.clone()]);
+
+        let upcoming = upcoming(vec![later, missed, unscheduled, finished, sooner], now);
+        assert_eq!(ids(&upcoming), expected);
+    }
+
+    #[test]
+    fn test_verify_password_rejects_malformed_hash() {
+        assert!(!verify_password("secret", "not-a-phc-string"));
+        assert!(!verify_password("secret", ""));
+    }
+}
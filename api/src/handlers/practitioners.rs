@@ -0,0 +1,311 @@
+//! Practitioner endpoints for the Nexus API.
+//!
+//! Requests are validated against the `core` Practitioner entity (including
+//! NPI check digits) and stored in `emr.practitioners`. Deleting a
+//! practitioner deactivates the record, which encounters and claims still
+//! refer to.
+
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::values::{HumanName, Identifier, IdentifierUse, NameUse};
+use emr_core::domain::{Practitioner, PractitionerQualification};
+use emr_core::types::Id;
+use serde::{Deserialize, Serialize};
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, PaginationParams, PaginatedResponse, PaginationMeta};
+use crate::repositories::PractitionerRepository;
+use crate::AppState;
+
+/// Identifier system used for NPIs created through the API
+const NPI_SYSTEM: &str = "http://hl7.org/fhir/sid/us-npi";
+
+/// Practitioner response DTO
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PractitionerResponse {
+    pub id: String,
+    pub name: String,
+    pub npi: Option<String>,
+    pub qualifications: Vec<QualificationResponse>,
+    pub organization_ids: Vec<String>,
+    pub active: bool,
+}
+
+/// Practitioner qualification DTO
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QualificationResponse {
+    pub code: String,
+    pub issuer: Option<String>,
+}
+
+/// Practitioner creation/update request
+#[derive(Debug, Deserialize)]
+pub struct CreatePractitionerRequest {
+    pub given: Vec<String>,
+    pub family: String,
+    pub prefix: Option<String>,
+    pub npi: Option<String>,
+    #[serde(default)]
+    pub qualifications: Vec<QualificationRequest>,
+    #[serde(default)]
+    pub organization_ids: Vec<uuid::Uuid>,
+}
+
+/// Qualification request
+#[derive(Debug, Deserialize)]
+pub struct QualificationRequest {
+    pub code: String,
+    pub issuer: Option<uuid::Uuid>,
+}
+
+impl From<&QualificationRequest> for PractitionerQualification {
+    fn from(request: &QualificationRequest) -> Self {
+        PractitionerQualification {
+            identifiers: Vec::new(),
+            code: request.code.trim().to_string(),
+            period: None,
+            issuer: request.issuer,
+        }
+    }
+}
+
+impl CreatePractitionerRequest {
+    /// Build and validate a domain practitioner from the request
+    fn to_domain(&self) -> Result<Practitioner> {
+        let mut practitioner = Practitioner::new(vec![HumanName {
+            given: self.given.clone(),
+            family: self.family.clone(),
+            prefix: self.prefix.clone(),
+            suffix: None,
+            use_: Some(NameUse::Official),
+        }])?;
+
+        if let Some(npi) = &self.npi {
+            practitioner.add_identifier(Identifier {
+                use_: Some(IdentifierUse::Official),
+                system: Some(NPI_SYSTEM.to_string()),
+                value: npi.trim().to_string(),
+            });
+        }
+
+        for qualification in &self.qualifications {
+            practitioner.add_qualification(qualification.into());
+        }
+
+        for organization_id in &self.organization_ids {
+            practitioner.add_organization(*organization_id);
+        }
+
+        Validatable::validate(&practitioner)?;
+        Ok(practitioner)
+    }
+}
+
+impl From<&Practitioner> for PractitionerResponse {
+    fn from(practitioner: &Practitioner) -> Self {
+        let name = practitioner
+            .primary_name()
+            .map(|name| {
+                let mut parts: Vec<&str> = name.prefix.iter().map(String::as_str).collect();
+                parts.extend(name.given.iter().map(String::as_str));
+                parts.push(&name.family);
+                parts.join(" ")
+            })
+            .unwrap_or_default();
+
+        Self {
+            id: practitioner.metadata.id.to_string(),
+            name,
+            npi: practitioner.npi().map(str::to_string),
+            qualifications: practitioner
+                .qualifications
+                .iter()
+                .map(|qualification| QualificationResponse {
+                    code: qualification.code.clone(),
+                    issuer: qualification.issuer.map(|id| id.to_string()),
+                })
+                .collect(),
+            organization_ids: practitioner.organizations.iter().map(|id| id.to_string()).collect(),
+            active: practitioner.active,
+        }
+    }
+}
+
+async fn find_practitioner(data: &AppState, id: Id) -> Result<Practitioner> {
+    PractitionerRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Practitioner {} not found", id)))
+}
+
+/// Write a changed practitioner unless it was changed concurrently
+async fn store_change(data: &AppState, practitioner: &Practitioner, previous_version: u64) -> Result<()> {
+    let stored = PractitionerRepository::new()
+        .update(&data.db_pool, practitioner, previous_version)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict("The practitioner was changed by another request; reload it and try again"));
+    }
+    Ok(())
+}
+
+/// List practitioners with pagination
+#[get("/practitioners")]
+pub async fn list_practitioners(
+    query: web::Query<PaginationParams>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+
+    let (practitioners, total) = PractitionerRepository::new()
+        .list(&data.db_pool, query.limit(), query.offset())
+        .await?;
+
+    let response = PaginatedResponse {
+        data: practitioners.iter().map(PractitionerResponse::from).collect(),
+        pagination: PaginationMeta::new(page, per_page, total),
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Get practitioner by ID
+#[get("/practitioners/{id}")]
+pub async fn get_practitioner(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner = find_practitioner(&data, path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(PractitionerResponse::from(&practitioner))))
+}
+
+/// Create new practitioner
+#[post("/practitioners")]
+pub async fn create_practitioner(
+    request: web::Json<CreatePractitionerRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner = request.to_domain()?;
+    PractitionerRepository::new().insert(&data.db_pool, &practitioner).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(PractitionerResponse::from(&practitioner))))
+}
+
+/// Update practitioner
+#[put("/practitioners/{id}")]
+pub async fn update_practitioner(
+    path: web::Path<Id>,
+    request: web::Json<CreatePractitionerRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let existing = find_practitioner(&data, path.into_inner()).await?;
+
+    let mut practitioner = request.to_domain()?;
+    practitioner.metadata = existing.metadata.clone();
+    practitioner.metadata.update();
+    practitioner.telecom = existing.telecom;
+    practitioner.active = existing.active;
+    store_change(&data, &practitioner, existing.metadata.version).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(PractitionerResponse::from(&practitioner))))
+}
+
+/// Delete practitioner
+#[delete("/practitioners/{id}")]
+pub async fn delete_practitioner(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut practitioner = find_practitioner(&data, path.into_inner()).await?;
+    if practitioner.active {
+        let previous_version = practitioner.metadata.version;
+        practitioner.deactivate();
+        store_change(&data, &practitioner, previous_version).await?;
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Add a qualification to a practitioner
+#[post("/practitioners/{id}/qualifications")]
+pub async fn add_qualification(
+    path: web::Path<Id>,
+    request: web::Json<QualificationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if request.code.trim().is_empty() {
+        return Err(ApiError::validation_error("Qualification code cannot be empty"));
+    }
+
+    let mut practitioner = find_practitioner(&data, path.into_inner()).await?;
+    let previous_version = practitioner.metadata.version;
+    practitioner.add_qualification((&*request).into());
+    store_change(&data, &practitioner, previous_version).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(PractitionerResponse::from(&practitioner))))
+}
+
+/// Remove a qualification from a practitioner
+#[delete("/practitioners/{id}/qualifications/{code}")]
+pub async fn remove_qualification(
+    path: web::Path<(Id, String)>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (practitioner_id, code) = path.into_inner();
+
+    let mut practitioner = find_practitioner(&data, practitioner_id).await?;
+    let previous_version = practitioner.metadata.version;
+    if !practitioner.remove_qualification(&code) {
+        return Err(ApiError::not_found(&format!("Practitioner {} has no qualification {}", practitioner_id, code)));
+    }
+    store_change(&data, &practitioner, previous_version).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_request(npi: Option<&str>) -> CreatePractitionerRequest {
+        CreatePractitionerRequest {
+            given: vec!["Emily".to_string()],
+            family: "Brown".to_string(),
+            prefix: Some("Dr.".to_string()),
+            npi: npi.map(str::to_string),
+            qualifications: vec![QualificationRequest {
+                code: "MD".to_string(),
+                issuer: None,
+            }],
+            organization_ids: vec![uuid::Uuid::new_v4()],
+        }
+    }
+
+    #[test]
+    fn test_request_to_response() {
+        let practitioner = create_request(Some("1234567893")).to_domain().unwrap();
+        let response = PractitionerResponse::from(&practitioner);
+
+        assert_eq!(response.name, "Dr. Emily Brown");
+        assert_eq!(response.npi.as_deref(), Some("1234567893"));
+        assert_eq!(response.qualifications.len(), 1);
+        assert_eq!(response.organization_ids.len(), 1);
+    }
+
+    #[test]
+    fn test_request_with_invalid_npi() {
+        assert!(create_request(Some("1234567890")).to_domain().is_err());
+    }
+
+    #[test]
+    fn test_request_without_npi() {
+        assert!(create_request(None).to_domain().is_ok());
+    }
+}
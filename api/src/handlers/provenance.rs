@@ -0,0 +1,85 @@
+//! Provenance endpoints
+//!
+//! Entities created by batch imports or FHIR sync carry provenance records
+//! written by the jobs worker: the source system, the job that created the
+//! entity, its identifiers in the source system and the transformation
+//! version. `GET /{type}/{id}/provenance` returns them newest first, or as a
+//! FHIR `searchset` Bundle of `Provenance` resources with `?format=fhir`.
+
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use emr_fhir::{provenance_to_fhir, BundleBuilder};
+use serde::Deserialize;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_bundle_response;
+use crate::handlers::ApiResponse;
+use crate::repositories::ProvenanceRepository;
+use crate::AppState;
+
+/// Path segments with provenance and the entity type they hold
+const PROVENANCE_TARGETS: &[(&str, &str)] = &[
+    ("patients", "Patient"),
+    ("encounters", "Encounter"),
+    ("observations", "Observation"),
+    ("organizations", "Organization"),
+    ("practitioners", "Practitioner"),
+];
+
+/// Provenance response format
+#[derive(Debug, Deserialize)]
+pub struct ProvenanceQuery {
+    /// `fhir` for a Bundle of FHIR `Provenance` resources
+    pub format: Option<String>,
+}
+
+/// Entity type for a path segment such as `patients`
+fn target_type(segment: &str) -> Result<&'static str> {
+    PROVENANCE_TARGETS
+        .iter()
+        .find(|(path, _)| *path == segment)
+        .map(|(_, target)| *target)
+        .ok_or_else(|| ApiError::not_found(&format!("No provenance is kept for '{}'", segment)))
+}
+
+/// Where an imported or synchronized entity came from
+#[get("/{type}/{id}/provenance")]
+pub async fn get_provenance(
+    path: web::Path<(String, uuid::Uuid)>,
+    query: web::Query<ProvenanceQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (segment, id) = path.into_inner();
+    let target_type = target_type(&segment)?;
+
+    let records = ProvenanceRepository::new()
+        .for_target(&data.db_pool, target_type, id)
+        .await?;
+
+    match query.into_inner().format.as_deref() {
+        None | Some("json") => Ok(HttpResponse::Ok().json(ApiResponse::new(records))),
+        Some("fhir") => {
+            let mut bundle = BundleBuilder::new("searchset");
+            for record in &records {
+                bundle.add_resource(provenance_to_fhir(record));
+            }
+            fhir_bundle_response(&req, HttpResponse::Ok(), bundle.finish())
+        }
+        Some(other) => Err(ApiError::validation_error(&format!(
+            "Unsupported format '{}', expected json or fhir",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_type() {
+        assert_eq!(target_type("patients").unwrap(), "Patient");
+        assert_eq!(target_type("observations").unwrap(), "Observation");
+        assert!(target_type("photos").is_err());
+        assert!(target_type("Patient").is_err());
+    }
+}
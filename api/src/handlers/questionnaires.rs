@@ -0,0 +1,371 @@
+//! Questionnaire endpoints
+//!
+//! Questionnaires (FHIR `Questionnaire`) are designed as drafts, activated to
+//! take responses and retired when replaced; their items never change once
+//! stored, so old responses keep matching the form they answered. Forms are
+//! rendered by the web client, which asks `POST /questionnaires/{id}/enabled-items`
+//! which items to show as answers change. Responses are checked against the
+//! form when saved: answers to hidden questions are dropped, completed
+//! responses must answer every shown required question, and scored forms get
+//! their total.
+
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{Questionnaire, QuestionnaireItem, QuestionnaireResponse, QuestionnaireStatus, ResponseAnswer};
+use emr_core::types::Id;
+use emr_fhir::{questionnaire_response_to_fhir, questionnaire_to_fhir};
+use serde::Deserialize;
+use serde_json::json;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::QuestionnaireRepository;
+use crate::AppState;
+
+/// New draft questionnaire
+#[derive(Debug, Deserialize)]
+pub struct CreateQuestionnaireRequest {
+    pub title: String,
+    /// Canonical URL, when the form is published under one
+    pub url: Option<String>,
+    pub items: Vec<QuestionnaireItem>,
+}
+
+/// Questionnaire list filter
+#[derive(Debug, Deserialize)]
+pub struct QuestionnaireListQuery {
+    pub status: Option<QuestionnaireStatus>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+/// Activation or retirement
+#[derive(Debug, Deserialize)]
+pub struct QuestionnaireStatusRequest {
+    pub status: QuestionnaireStatus,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// Answers given so far in a form being filled in
+#[derive(Debug, Deserialize)]
+pub struct EnabledItemsRequest {
+    #[serde(default)]
+    pub answers: Vec<ResponseAnswer>,
+}
+
+/// New response
+#[derive(Debug, Deserialize)]
+pub struct CreateResponseRequest {
+    pub patient_id: Id,
+    /// Practitioner entering the answers; absent when the patient does
+    pub author_id: Option<Id>,
+    pub answers: Vec<ResponseAnswer>,
+    /// Submit the response rather than save it in progress
+    #[serde(default)]
+    pub complete: bool,
+}
+
+/// Revised answers
+#[derive(Debug, Deserialize)]
+pub struct ReviseResponseRequest {
+    pub answers: Vec<ResponseAnswer>,
+    #[serde(default)]
+    pub complete: bool,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// A patient's responses, optionally to one questionnaire
+#[derive(Debug, Deserialize)]
+pub struct PatientResponsesQuery {
+    pub questionnaire_id: Option<Id>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+async fn find_questionnaire(data: &AppState, id: Id) -> Result<Questionnaire> {
+    QuestionnaireRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Questionnaire {} not found", id)))
+}
+
+async fn find_response(data: &AppState, id: Id) -> Result<QuestionnaireResponse> {
+    QuestionnaireRepository::new()
+        .find_response(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Questionnaire response {} not found", id)))
+}
+
+/// Create a draft questionnaire
+#[post("/questionnaires")]
+pub async fn create_questionnaire(
+    request: web::Json<CreateQuestionnaireRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut questionnaire = Questionnaire::new(&request.title, request.items);
+    questionnaire.url = request.url.map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+    questionnaire.validate()?;
+
+    QuestionnaireRepository::new().insert(&data.db_pool, &questionnaire).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(questionnaire)))
+}
+
+/// Questionnaires by title
+#[get("/questionnaires")]
+pub async fn list_questionnaires(
+    query: web::Query<QuestionnaireListQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+
+    let questionnaires = QuestionnaireRepository::new()
+        .list(&data.db_pool, query.status, pagination.limit(), pagination.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        questionnaires,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// A questionnaire
+#[get("/questionnaires/{id}")]
+pub async fn get_questionnaire(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let questionnaire = find_questionnaire(&data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(questionnaire)))
+}
+
+/// A questionnaire as a FHIR `Questionnaire`
+#[get("/questionnaires/{id}/fhir")]
+pub async fn get_questionnaire_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let questionnaire = find_questionnaire(&data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &questionnaire_to_fhir(&questionnaire))
+}
+
+/// Activate or retire a questionnaire
+#[post("/questionnaires/{id}/status")]
+pub async fn update_questionnaire_status(
+    path: web::Path<Id>,
+    request: web::Json<QuestionnaireStatusRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut questionnaire = find_questionnaire(&data, path.into_inner()).await?;
+    if questionnaire.metadata.version != request.version {
+        return Err(ApiError::conflict("The questionnaire changed since it was loaded; reload it and try again"));
+    }
+    let previous_version = questionnaire.metadata.version;
+
+    if questionnaire.transition(request.status)? {
+        let stored = QuestionnaireRepository::new()
+            .update_status(&data.db_pool, &questionnaire, previous_version)
+            .await?;
+        if !stored {
+            return Err(ApiError::conflict(
+                "The questionnaire was changed by another request; reload it and try again",
+            ));
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(questionnaire)))
+}
+
+/// Items to show for the answers given so far, in form order, with the
+/// score those answers add up to
+#[post("/questionnaires/{id}/enabled-items")]
+pub async fn enabled_questionnaire_items(
+    path: web::Path<Id>,
+    request: web::Json<EnabledItemsRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let questionnaire = find_questionnaire(&data, path.into_inner()).await?;
+    let enabled = questionnaire.enabled_items(&request.answers);
+    let score = questionnaire.score(&request.answers);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(json!({ "enabled": enabled, "score": score }))))
+}
+
+/// Save a patient's answers, in progress or completed
+#[post("/questionnaires/{id}/responses")]
+pub async fn create_questionnaire_response(
+    path: web::Path<Id>,
+    request: web::Json<CreateResponseRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    authorize_patient_access(&req, &data, request.patient_id).await?;
+    let questionnaire = find_questionnaire(&data, path.into_inner()).await?;
+
+    let response = QuestionnaireResponse::record(
+        &questionnaire,
+        request.patient_id,
+        request.author_id,
+        request.answers,
+        request.complete,
+        Utc::now(),
+    )?;
+    QuestionnaireRepository::new()
+        .insert_response(&data.db_pool, &response)
+        .await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(response)))
+}
+
+/// A response
+#[get("/questionnaire-responses/{id}")]
+pub async fn get_questionnaire_response(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let response = find_response(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, response.patient_id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(response)))
+}
+
+/// Replace a response's answers; changing a submitted response amends it
+#[put("/questionnaire-responses/{id}")]
+pub async fn revise_questionnaire_response(
+    path: web::Path<Id>,
+    request: web::Json<ReviseResponseRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut response = find_response(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, response.patient_id).await?;
+    if response.metadata.version != request.version {
+        return Err(ApiError::conflict("The response changed since it was loaded; reload it and try again"));
+    }
+    let previous_version = response.metadata.version;
+    let questionnaire = find_questionnaire(&data, response.questionnaire_id).await?;
+
+    response.revise(&questionnaire, request.answers, request.complete, Utc::now())?;
+    let stored = QuestionnaireRepository::new()
+        .update_response(&data.db_pool, &response, previous_version)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict("The response was changed by another request; reload it and try again"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(response)))
+}
+
+/// A response as a FHIR `QuestionnaireResponse`
+#[get("/questionnaire-responses/{id}/fhir")]
+pub async fn get_questionnaire_response_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let response = find_response(&data, path.into_inner()).await?;
+    authorize_patient_access(&req, &data, response.patient_id).await?;
+    let questionnaire = find_questionnaire(&data, response.questionnaire_id).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &questionnaire_response_to_fhir(&response, &questionnaire))
+}
+
+/// A patient's responses, most recently answered first
+#[get("/patients/{id}/questionnaire-responses")]
+pub async fn list_patient_questionnaire_responses(
+    path: web::Path<Id>,
+    query: web::Query<PatientResponsesQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+
+    let responses = QuestionnaireRepository::new()
+        .responses_for_patient(
+            &data.db_pool,
+            patient_id,
+            query.questionnaire_id,
+            pagination.limit(),
+            pagination.offset(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        responses,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::domain::{AnswerValue, ItemType};
+
+    #[test]
+    fn test_create_questionnaire_request() {
+        let request: CreateQuestionnaireRequest = serde_json::from_value(json!({
+            "title": "Fall risk",
+            "items": [
+                { "link_id": "fell", "text": "Fallen in the last year?", "type": "boolean", "required": true },
+                {
+                    "link_id": "falls",
+                    "text": "How many times?",
+                    "type": "integer",
+                    "enable_when": [
+                        { "question": "fell", "operator": "=", "answer": { "type": "boolean", "value": true } }
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let questionnaire = Questionnaire::new(&request.title, request.items);
+        assert!(questionnaire.validate().is_ok());
+        assert_eq!(questionnaire.items[1].item_type, ItemType::Integer);
+
+        let answers = vec![ResponseAnswer {
+            link_id: "fell".to_string(),
+            values: vec![AnswerValue::Boolean(false)],
+        }];
+        assert_eq!(questionnaire.enabled_items(&answers), vec!["fell"]);
+    }
+
+    #[test]
+    fn test_response_requests() {
+        let request: CreateResponseRequest = serde_json::from_value(json!({
+            "patient_id": uuid::Uuid::new_v4(),
+            "answers": [{ "link_id": "dob", "values": [{ "type": "date", "value": "1980-02-29" }] }]
+        }))
+        .unwrap();
+        assert!(!request.complete);
+        assert!(request.author_id.is_none());
+        assert!(matches!(request.answers[0].values[0], AnswerValue::Date(_)));
+
+        assert!(serde_json::from_value::<ReviseResponseRequest>(json!({ "answers": [] })).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+//! Referral endpoints
+//!
+//! A practitioner drafts a referral to another organization with a reason
+//! and the patient documents it needs, then sends it. Sending publishes the
+//! referral to the FHIR server as a `ServiceRequest` plus a `Task` owned by
+//! the target organization (see [`ReferralExchange`]). The target responds by
+//! updating that `Task`, picked up with `POST /referrals/{id}/sync`, or, when
+//! it works in this system, through `POST /referrals/{id}/status`; responses
+//! recorded here are published back to the `Task`.
+//! `/organizations/{id}/referrals` is the incoming and outgoing worklist.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{Referral, ReferralStatus, RequestPriority};
+use emr_core::types::Id;
+use emr_fhir::referral_to_fhir;
+use serde::Deserialize;
+use serde_json::json;
+use crate::error::{ApiError, Result};
+use crate::fhir::fhir_response;
+use crate::handlers::care_teams::authorize_patient_access;
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::{CommunicationRepository, ReferralRepository};
+use crate::services::ReferralExchange;
+use crate::AppState;
+
+/// New referral
+#[derive(Debug, Deserialize)]
+pub struct CreateReferralRequest {
+    pub patient_id: Id,
+    /// Referring practitioner
+    pub requester_id: Id,
+    pub source_organization_id: Id,
+    pub target_organization_id: Id,
+    pub reason: String,
+    #[serde(default = "default_priority")]
+    pub priority: RequestPriority,
+    /// Patient documents to send along
+    #[serde(default)]
+    pub document_ids: Vec<Id>,
+}
+
+fn default_priority() -> RequestPriority {
+    RequestPriority::Routine
+}
+
+/// Document attached to a draft
+#[derive(Debug, Deserialize)]
+pub struct AttachDocumentRequest {
+    pub document_id: Id,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// Change based on a loaded version, e.g. sending
+#[derive(Debug, Deserialize)]
+pub struct ReferralVersionRequest {
+    pub version: u64,
+}
+
+/// Response or cancellation
+#[derive(Debug, Deserialize)]
+pub struct ReferralStatusRequest {
+    pub status: ReferralStatus,
+    /// Required when declining
+    pub reason: Option<String>,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// Which side of the worklist to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReferralDirection {
+    /// Referrals other organizations sent to this one
+    Incoming,
+    /// Referrals this organization sent
+    Outgoing,
+}
+
+/// Organization worklist
+#[derive(Debug, Deserialize)]
+pub struct ReferralWorklistQuery {
+    pub direction: ReferralDirection,
+    /// Only referrals that still need work
+    #[serde(default)]
+    pub open: bool,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+async fn find_referral(data: &AppState, id: Id) -> Result<Referral> {
+    ReferralRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Referral {} not found", id)))
+}
+
+/// Load a referral for a change based on `version`
+async fn referral_at_version(data: &AppState, id: Id, version: u64) -> Result<Referral> {
+    let referral = find_referral(data, id).await?;
+    if referral.metadata.version != version {
+        return Err(ApiError::conflict("The referral changed since it was loaded; reload it and try again"));
+    }
+    Ok(referral)
+}
+
+/// Write a changed referral unless it was changed concurrently
+async fn store_change(data: &AppState, referral: &Referral, previous_version: u64) -> Result<()> {
+    let stored = ReferralRepository::new()
+        .update(&data.db_pool, referral, previous_version)
+        .await?;
+    if !stored {
+        return Err(ApiError::conflict("The referral was changed by another request; reload it and try again"));
+    }
+    Ok(())
+}
+
+/// Check that documents are current documents of the patient
+async fn check_documents(data: &AppState, patient_id: Id, document_ids: &[Id]) -> Result<()> {
+    if document_ids.is_empty() {
+        return Ok(());
+    }
+    let found = CommunicationRepository::new()
+        .count_patient_documents(&data.db_pool, patient_id, document_ids.to_vec())
+        .await?;
+    if found != document_ids.len() as i64 {
+        return Err(ApiError::validation_error("Documents must be current documents of the referred patient"));
+    }
+    Ok(())
+}
+
+/// Publish a changed referral to the FHIR server, then store it
+///
+/// Publishing first means a failed exchange leaves the stored referral
+/// unchanged, and publishing again is harmless.
+async fn publish_and_store(data: &AppState, referral: &Referral, previous_version: u64) -> Result<()> {
+    if referral.sent_at.is_some() {
+        ReferralExchange::new(data.fhir_client.clone()).publish(referral).await?;
+    }
+    store_change(data, referral, previous_version).await
+}
+
+/// Draft a referral
+#[post("/referrals")]
+pub async fn create_referral(
+    request: web::Json<CreateReferralRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let mut referral = Referral::new(
+        request.patient_id,
+        request.requester_id,
+        request.source_organization_id,
+        request.target_organization_id,
+        &request.reason,
+        request.priority,
+    );
+    for document_id in request.document_ids {
+        referral.attach_document(document_id)?;
+    }
+    referral.validate()?;
+    check_documents(&data, referral.patient_id, &referral.document_ids).await?;
+
+    ReferralRepository::new().insert(&data.db_pool, &referral).await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(referral)))
+}
+
+/// A referral
+#[get("/referrals/{id}")]
+pub async fn get_referral(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let referral = find_referral(&data, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(referral)))
+}
+
+/// A referral as the FHIR `ServiceRequest` sent to the target organization
+#[get("/referrals/{id}/fhir")]
+pub async fn get_referral_fhir(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let referral = find_referral(&data, path.into_inner()).await?;
+
+    fhir_response(&req, HttpResponse::Ok(), &referral_to_fhir(&referral))
+}
+
+/// Attach a patient document to a draft referral
+#[post("/referrals/{id}/documents")]
+pub async fn attach_referral_document(
+    path: web::Path<Id>,
+    request: web::Json<AttachDocumentRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut referral = referral_at_version(&data, path.into_inner(), request.version).await?;
+    let previous_version = referral.metadata.version;
+
+    referral.attach_document(request.document_id)?;
+    check_documents(&data, referral.patient_id, &[request.document_id]).await?;
+    store_change(&data, &referral, previous_version).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(referral)))
+}
+
+/// Send a draft referral to the target organization
+#[post("/referrals/{id}/send")]
+pub async fn send_referral(
+    path: web::Path<Id>,
+    request: web::Json<ReferralVersionRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut referral = referral_at_version(&data, path.into_inner(), request.version).await?;
+    let previous_version = referral.metadata.version;
+
+    if !referral.transition(ReferralStatus::Sent, None, Utc::now())? {
+        return Err(ApiError::conflict("The referral has already been sent"));
+    }
+    publish_and_store(&data, &referral, previous_version).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(referral)))
+}
+
+/// Record the target's response, completion, or a cancellation
+#[post("/referrals/{id}/status")]
+pub async fn update_referral_status(
+    path: web::Path<Id>,
+    request: web::Json<ReferralStatusRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    if request.status == ReferralStatus::Sent {
+        return Err(ApiError::validation_error("Send referrals with POST /referrals/{id}/send"));
+    }
+    let mut referral = referral_at_version(&data, path.into_inner(), request.version).await?;
+    let previous_version = referral.metadata.version;
+
+    if referral.transition(request.status, request.reason.as_deref(), Utc::now())? {
+        publish_and_store(&data, &referral, previous_version).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(referral)))
+}
+
+/// Pick up the target organization's response from the FHIR `Task`
+#[post("/referrals/{id}/sync")]
+pub async fn sync_referral(
+    path: web::Path<Id>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut referral = find_referral(&data, path.into_inner()).await?;
+    if referral.sent_at.is_none() {
+        return Err(ApiError::validation_error("Only sent referrals can be synchronized"));
+    }
+    let previous_version = referral.metadata.version;
+
+    let remote = ReferralExchange::new(data.fhir_client.clone())
+        .remote_status(&referral)
+        .await?;
+    let changed = match remote {
+        // The target has not picked it up yet
+        Some((ReferralStatus::Draft | ReferralStatus::Sent, _)) | None => false,
+        Some((status, reason)) => referral.transition(status, reason.as_deref(), Utc::now())?,
+    };
+    if changed {
+        store_change(&data, &referral, previous_version).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(referral, json!({ "changed": changed }))))
+}
+
+/// A patient's referrals, newest first
+#[get("/patients/{id}/referrals")]
+pub async fn list_patient_referrals(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    authorize_patient_access(&req, &data, patient_id).await?;
+    let (page, per_page) = query.normalize();
+
+    let referrals = ReferralRepository::new()
+        .for_patient(&data.db_pool, patient_id, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        referrals,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// An organization's incoming or outgoing referrals; open ones come first,
+/// most urgent and longest waiting first
+#[get("/organizations/{id}/referrals")]
+pub async fn referral_worklist(
+    path: web::Path<Id>,
+    query: web::Query<ReferralWorklistQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let pagination = PaginationParams {
+        page: query.page,
+        per_page: query.per_page,
+    };
+    let (page, per_page) = pagination.normalize();
+
+    let referrals = ReferralRepository::new()
+        .worklist(
+            &data.db_pool,
+            path.into_inner(),
+            query.direction == ReferralDirection::Incoming,
+            query.open,
+            pagination.limit(),
+            pagination.offset(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        referrals,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_request_defaults() {
+        let request: CreateReferralRequest = serde_json::from_value(json!({
+            "patient_id": uuid::Uuid::new_v4(),
+            "requester_id": uuid::Uuid::new_v4(),
+            "source_organization_id": uuid::Uuid::new_v4(),
+            "target_organization_id": uuid::Uuid::new_v4(),
+            "reason": "Second opinion on MRI findings"
+        }))
+        .unwrap();
+
+        assert_eq!(request.priority, RequestPriority::Routine);
+        assert!(request.document_ids.is_empty());
+    }
+
+    #[test]
+    fn test_worklist_query() {
+        let query: ReferralWorklistQuery =
+            serde_json::from_value(json!({ "direction": "incoming", "open": true })).unwrap();
+        assert_eq!(query.direction, ReferralDirection::Incoming);
+        assert!(query.open);
+        assert!(serde_json::from_value::<ReferralWorklistQuery>(json!({ "direction": "sideways" })).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Scheduled report endpoints
+//!
+//! A report schedule (see [`ReportSchedule`]) runs an ad-hoc report
+//! definition on a daily, weekly or monthly cadence. The jobs worker renders
+//! each run as CSV or PDF into `uploads.report_dir` and notifies the
+//! recipients in-app with a link to `GET /report-runs/{id}/download`;
+//! `GET /report-schedules/{id}/runs` is the schedule's history. Schedules
+//! are visible to their owner and recipients and changed only by the owner.
+
+use actix_web::{delete, get, post, put, web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::{ReportCadence, ReportFormat, ReportPeriod, ReportRunStatus, ReportSchedule};
+use emr_core::services::reporting::ReportDefinition;
+use emr_core::types::Id;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::PathBuf;
+use crate::auth::AuthContext;
+use crate::config::UploadConfig;
+use crate::error::{ApiError, Result};
+use crate::handlers::{ApiResponse, PaginationParams};
+use crate::repositories::ReportScheduleRepository;
+use crate::AppState;
+
+/// New report schedule
+#[derive(Debug, Deserialize)]
+pub struct CreateReportScheduleRequest {
+    pub name: String,
+    /// Practitioner scheduling the report
+    pub owner_id: Id,
+    pub definition: ReportDefinition,
+    pub period: Option<ReportPeriod>,
+    pub format: ReportFormat,
+    pub cadence: ReportCadence,
+    /// Practitioners notified of each run; the owner when empty
+    #[serde(default)]
+    pub recipients: Vec<Id>,
+}
+
+/// Changed report schedule
+#[derive(Debug, Deserialize)]
+pub struct UpdateReportScheduleRequest {
+    pub name: String,
+    pub definition: ReportDefinition,
+    pub period: Option<ReportPeriod>,
+    pub format: ReportFormat,
+    pub cadence: ReportCadence,
+    pub recipients: Vec<Id>,
+    pub active: bool,
+    /// Version the change is based on
+    pub version: u64,
+}
+
+/// Practitioner a request acts for; `None` for unauthenticated requests,
+/// which still pass until the auth middleware rejects them
+/// (TODO(nexus-phase2))
+fn schedule_practitioner(context: Option<&AuthContext>) -> Result<Option<Id>> {
+    let Some(context) = context else {
+        return Ok(None);
+    };
+    if context.is_patient_session() {
+        return Err(ApiError::authorization_error("Scheduled reports are not available in the portal"));
+    }
+    context
+        .practitioner_id()
+        .map(Some)
+        .ok_or_else(|| ApiError::authorization_error("Only practitioners can schedule reports"))
+}
+
+/// Whether a practitioner owns or receives a schedule
+fn can_see(schedule: &ReportSchedule, practitioner_id: Option<Id>) -> bool {
+    practitioner_id.map_or(true, |practitioner_id| {
+        schedule.owner_id == practitioner_id || schedule.recipients.contains(&practitioner_id)
+    })
+}
+
+/// Check that only the schedule's owner changes it
+fn check_owner(schedule: &ReportSchedule, practitioner_id: Option<Id>) -> Result<()> {
+    match practitioner_id {
+        Some(practitioner_id) if practitioner_id != schedule.owner_id => {
+            Err(ApiError::authorization_error("Only the practitioner who scheduled a report can change it"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Load a schedule the caller can see; others' schedules are reported as
+/// missing
+async fn visible_schedule(data: &AppState, id: Id, practitioner_id: Option<Id>) -> Result<ReportSchedule> {
+    ReportScheduleRepository::new()
+        .find(&data.db_pool, id)
+        .await?
+        .filter(|schedule| can_see(schedule, practitioner_id))
+        .ok_or_else(|| ApiError::not_found(&format!("Report schedule {} not found", id)))
+}
+
+/// Where a run's file is stored (`reports.output_dir` in the jobs worker)
+fn run_path(config: &UploadConfig, run_id: Id) -> PathBuf {
+    PathBuf::from(&config.report_dir).join(run_id.to_string())
+}
+
+/// Recipients without duplicates, in the order given
+fn distinct(recipients: Vec<Id>) -> Vec<Id> {
+    let mut distinct = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        if !distinct.contains(&recipient) {
+            distinct.push(recipient);
+        }
+    }
+    distinct
+}
+
+/// Schedule a report
+#[post("/report-schedules")]
+pub async fn create_report_schedule(
+    request: web::Json<CreateReportScheduleRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let request = request.into_inner();
+    let mut schedule = ReportSchedule::new(
+        &request.name,
+        request.owner_id,
+        request.definition,
+        request.format,
+        request.cadence,
+    );
+    check_owner(&schedule, practitioner_id)?;
+    schedule.period = request.period;
+    if !request.recipients.is_empty() {
+        schedule.recipients = distinct(request.recipients);
+    }
+    schedule.validate()?;
+
+    ReportScheduleRepository::new().insert(&data.db_pool, &schedule).await?;
+    tracing::info!(schedule_id = %schedule.metadata.id, next_run_at = %schedule.next_run_at, "Report scheduled");
+
+    Ok(HttpResponse::Created().json(ApiResponse::new(schedule)))
+}
+
+/// Schedules the caller owns or receives, by name
+#[get("/report-schedules")]
+pub async fn list_report_schedules(
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let (page, per_page) = query.normalize();
+
+    let schedules = ReportScheduleRepository::new()
+        .list(&data.db_pool, practitioner_id, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        schedules,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// A report schedule
+#[get("/report-schedules/{id}")]
+pub async fn get_report_schedule(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let schedule = visible_schedule(&data, path.into_inner(), practitioner_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(schedule)))
+}
+
+/// Change a report schedule; a changed cadence or resumed schedule runs
+/// next at the cadence's next time
+#[put("/report-schedules/{id}")]
+pub async fn update_report_schedule(
+    path: web::Path<Id>,
+    request: web::Json<UpdateReportScheduleRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let request = request.into_inner();
+    let mut schedule = visible_schedule(&data, path.into_inner(), practitioner_id).await?;
+    check_owner(&schedule, practitioner_id)?;
+    if schedule.metadata.version != request.version {
+        return Err(ApiError::conflict("The report schedule changed since it was loaded; reload it and try again"));
+    }
+
+    let previous_version = schedule.metadata.version;
+    if request.cadence != schedule.cadence || (request.active && !schedule.active) {
+        schedule.next_run_at = request.cadence.next_run(Utc::now());
+    }
+    schedule.name = request.name.trim().to_string();
+    schedule.definition = request.definition;
+    schedule.period = request.period;
+    schedule.format = request.format;
+    schedule.cadence = request.cadence;
+    schedule.recipients = distinct(request.recipients);
+    schedule.active = request.active;
+    schedule.validate()?;
+    schedule.metadata.update();
+
+    if !ReportScheduleRepository::new()
+        .update(&data.db_pool, &schedule, previous_version)
+        .await?
+    {
+        return Err(ApiError::conflict(
+            "The report schedule was changed by another request; reload it and try again",
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(schedule)))
+}
+
+/// Delete a report schedule with its history and generated files
+#[delete("/report-schedules/{id}")]
+pub async fn delete_report_schedule(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let schedule = visible_schedule(&data, path.into_inner(), practitioner_id).await?;
+    check_owner(&schedule, practitioner_id)?;
+
+    let runs = ReportScheduleRepository::new()
+        .delete(&data.db_pool, schedule.metadata.id)
+        .await?;
+    for run_id in runs {
+        // Failed runs have no file
+        if let Err(error) = tokio::fs::remove_file(run_path(&data.config.uploads, run_id)).await {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(run_id = %run_id, %error, "Failed to remove scheduled report file");
+            }
+        }
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// A schedule's generated reports, latest first
+#[get("/report-schedules/{id}/runs")]
+pub async fn report_schedule_runs(
+    path: web::Path<Id>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let schedule = visible_schedule(&data, path.into_inner(), practitioner_id).await?;
+    let (page, per_page) = query.normalize();
+
+    let runs = ReportScheduleRepository::new()
+        .runs(&data.db_pool, schedule.metadata.id, query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        runs,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Download a generated report
+#[get("/report-runs/{id}/download")]
+pub async fn download_report_run(
+    path: web::Path<Id>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let practitioner_id = schedule_practitioner(req.extensions().get::<AuthContext>())?;
+    let run_id = path.into_inner();
+    let repository = ReportScheduleRepository::new();
+    let run = repository
+        .find_run(&data.db_pool, run_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Report run {} not found", run_id)))?;
+    let schedule = visible_schedule(&data, run.schedule_id, practitioner_id)
+        .await
+        .map_err(|_| ApiError::not_found(&format!("Report run {} not found", run_id)))?;
+    if run.status != ReportRunStatus::Completed {
+        return Err(ApiError::not_found(&format!("Report run {} has no file", run_id)));
+    }
+
+    let content = tokio::fs::read(run_path(&data.config.uploads, run.id)).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(run.format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", run.file_name(&schedule.name)),
+        ))
+        .body(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(owner_id: Id, recipients: Vec<Id>) -> ReportSchedule {
+        let definition: ReportDefinition =
+            serde_json::from_value(json!({"entity": "patients", "metrics": [{"function": "count"}]})).unwrap();
+        let mut schedule = ReportSchedule::new(
+            "Active patients",
+            owner_id,
+            definition,
+            ReportFormat::Csv,
+            ReportCadence::Day { hour: 6 },
+        );
+        schedule.recipients = recipients;
+        schedule
+    }
+
+    #[test]
+    fn test_schedule_access() {
+        let (owner, recipient, other) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let schedule = schedule(owner, vec![owner, recipient]);
+
+        assert!(can_see(&schedule, None));
+        assert!(can_see(&schedule, Some(owner)));
+        assert!(can_see(&schedule, Some(recipient)));
+        assert!(!can_see(&schedule, Some(other)));
+
+        assert!(check_owner(&schedule, Some(owner)).is_ok());
+        assert!(check_owner(&schedule, Some(recipient)).is_err());
+    }
+
+    #[test]
+    fn test_distinct_recipients() {
+        let (a, b) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        assert_eq!(distinct(vec![a, b, a]), vec![a, b]);
+    }
+
+    #[test]
+    fn test_run_path() {
+        let run_id = uuid::Uuid::new_v4();
+        assert!(run_path(&UploadConfig::default(), run_id).starts_with("data/reports"));
+        assert!(run_path(&UploadConfig::default(), run_id).ends_with(run_id.to_string()));
+    }
+}
@@ -0,0 +1,149 @@
+//! Ad-hoc reporting endpoints
+//!
+//! `POST /reports/query` runs a declarative [`ReportDefinition`]: the
+//! entity, filters, group-by dimensions and metrics are checked against the
+//! reporting allowlist (see `emr_core::services::reporting`), compiled into
+//! one aggregate query and returned as a table, in JSON or with
+//! `?format=csv` as CSV. Dashboard widgets use it instead of an endpoint per
+//! report; `GET /reports/fields` lists what definitions may use. Reports
+//! only return aggregates, never patient identifiers, and are limited to
+//! practitioners.
+
+use actix_web::{get, post, web, HttpMessage, HttpRequest, HttpResponse};
+use emr_core::services::reporting::{compile, FieldKind, ReportDefinition, ReportEntity};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::ApiResponse;
+use crate::repositories::ReportRepository;
+use crate::AppState;
+
+/// Report output format
+#[derive(Debug, Deserialize)]
+pub struct ReportFormatQuery {
+    /// `json` (default) or `csv`
+    pub format: Option<String>,
+}
+
+/// Check the caller may run reports; unauthenticated requests still pass
+/// until the auth middleware rejects them (TODO(nexus-phase2))
+fn authorize_reports(context: Option<&AuthContext>) -> Result<()> {
+    match context {
+        Some(context) if context.is_patient_session() => {
+            Err(ApiError::authorization_error("Reports are not available in the portal"))
+        }
+        Some(context) if context.practitioner_id().is_none() => {
+            Err(ApiError::authorization_error("Only practitioners can run reports"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn kind_name(kind: FieldKind) -> &'static str {
+    match kind {
+        FieldKind::Text => "text",
+        FieldKind::Number => "number",
+        FieldKind::Boolean => "boolean",
+        FieldKind::Date => "date",
+        FieldKind::Timestamp => "timestamp",
+    }
+}
+
+/// Entities and fields report definitions may use
+fn report_catalog() -> Value {
+    let entities = [
+        ReportEntity::Patients,
+        ReportEntity::Encounters,
+        ReportEntity::Observations,
+        ReportEntity::MedicationRequests,
+    ];
+    let catalog: Vec<Value> = entities
+        .iter()
+        .map(|entity| {
+            let fields: Vec<Value> = entity
+                .fields()
+                .iter()
+                .map(|field| {
+                    json!({
+                        "name": field.name,
+                        "kind": kind_name(field.kind),
+                        "groupable": field.groupable,
+                    })
+                })
+                .collect();
+            json!({ "entity": entity, "fields": fields })
+        })
+        .collect();
+    Value::Array(catalog)
+}
+
+/// Fields reports may group, filter and aggregate by
+#[get("/reports/fields")]
+pub async fn report_fields(req: HttpRequest) -> Result<HttpResponse> {
+    authorize_reports(req.extensions().get::<AuthContext>())?;
+    Ok(HttpResponse::Ok().json(ApiResponse::new(report_catalog())))
+}
+
+/// Run an ad-hoc report
+#[post("/reports/query")]
+pub async fn run_report(
+    definition: web::Json<ReportDefinition>,
+    query: web::Query<ReportFormatQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_reports(req.extensions().get::<AuthContext>())?;
+    let csv = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(other) => return Err(ApiError::validation_error(&format!("Unknown report format '{}'", other))),
+    };
+    let compiled = compile(&definition)?;
+
+    let table = ReportRepository::new().run(&data.db_pool, compiled).await?;
+    tracing::info!(entity = ?definition.entity, rows = table.rows.len(), "Report run");
+
+    if csv {
+        Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(("Content-Disposition", "attachment; filename=\"report.csv\""))
+            .body(table.to_csv()))
+    } else {
+        Ok(HttpResponse::Ok().json(ApiResponse::new(table)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::scopes::Scopes;
+
+    #[test]
+    fn test_authorize_reports() {
+        let context = |subject: &str, patient_id| AuthContext {
+            subject: subject.to_string(),
+            patient_id,
+            tenant_id: None,
+            scopes: Scopes::parse("user/*.read"),
+            clearance: Default::default(),
+            purpose_of_use: None,
+            roles: Vec::new(),
+        };
+        assert!(authorize_reports(None).is_ok());
+        assert!(authorize_reports(Some(&context(&uuid::Uuid::new_v4().to_string(), None))).is_ok());
+        assert!(authorize_reports(Some(&context("service", None))).is_err());
+        assert!(authorize_reports(Some(&context("jane", Some(uuid::Uuid::new_v4())))).is_err());
+    }
+
+    #[test]
+    fn test_report_catalog() {
+        let catalog = report_catalog();
+        assert_eq!(catalog[1]["entity"], "encounters");
+        assert!(catalog[0]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|field| field["name"] == "age" && field["kind"] == "number"));
+    }
+}
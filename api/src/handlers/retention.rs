@@ -0,0 +1,80 @@
+//! Data retention endpoints
+//!
+//! Exposes the configured retention policies and a dry-run report for
+//! compliance review. The report loads records through the DataCleanup job's
+//! retention store (`emr_jobs::retention`) and evaluates them against the
+//! same policies, without changing anything.
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use emr_core::retention::RetentionReport;
+use serde::Deserialize;
+use crate::error::{ApiError, Result};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Dry-run report request
+#[derive(Debug, Default, Deserialize)]
+pub struct RetentionReportRequest {
+    /// Jurisdiction whose policies apply to the records, instead of that of
+    /// each patient's address
+    pub jurisdiction: Option<String>,
+    /// Evaluate as of this time instead of now, to preview upcoming actions
+    pub as_of: Option<DateTime<Utc>>,
+    /// Entity types to include; empty means all
+    #[serde(default)]
+    pub entity_types: Vec<String>,
+}
+
+/// List the configured retention policies
+#[get("/admin/retention/policies")]
+pub async fn list_retention_policies(
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ApiResponse::new(data.retention.policies())))
+}
+
+/// Report which records the retention policies would archive, anonymize or purge
+#[post("/admin/retention/report")]
+pub async fn retention_report(
+    request: web::Json<RetentionReportRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+
+    let mut candidates = data
+        .retention_store
+        .candidates(&request.entity_types)
+        .await
+        .map_err(|e| ApiError::internal_error(&format!("Failed to load retention candidates: {}", e)))?;
+    if let Some(jurisdiction) = &request.jurisdiction {
+        for candidate in &mut candidates {
+            candidate.jurisdiction = Some(jurisdiction.clone());
+        }
+    }
+
+    let report: RetentionReport = data
+        .retention
+        .evaluate(&candidates, request.as_of.unwrap_or_else(Utc::now));
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_request() {
+        let request: RetentionReportRequest = serde_json::from_str("{}").unwrap();
+        assert!(request.entity_types.is_empty());
+        assert!(request.jurisdiction.is_none());
+
+        let request: RetentionReportRequest =
+            serde_json::from_str(r#"{"jurisdiction": "US-CA", "entity_types": ["Observation"]}"#).unwrap();
+        assert_eq!(request.entity_types, vec!["Observation".to_string()]);
+        assert_eq!(request.jurisdiction.as_deref(), Some("US-CA"));
+    }
+}
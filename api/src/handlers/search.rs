@@ -0,0 +1,196 @@
+//! Global search
+//!
+//! `GET /search?q=` looks for patients, practitioners, organizations and
+//! documents at once and returns one ranked list, each hit tagged with its
+//! entity type and badge; the web search bar calls it as the user types.
+//! Hits are limited to what the caller could open: portal sessions find only
+//! their own record and documents, within their token's scopes, and
+//! practitioners find only patients they have a treating relationship with
+//! and those patients' documents. Emergency access is not applied to
+//! searches; it is requested for a patient whose id is already known.
+//! Documents labelled above the caller's clearance are not found. Searching
+//! needs a signed-in caller.
+
+use actix_web::{get, web, HttpMessage, HttpRequest, HttpResponse};
+use emr_core::domain::Confidentiality;
+use emr_core::services::search::{normalize_query, rank_hits, SearchEntity};
+use serde::Deserialize;
+use serde_json::json;
+use crate::auth::scopes::ScopeAccess;
+use crate::auth::AuthContext;
+use crate::error::{ApiError, Result};
+use crate::handlers::ApiResponse;
+use crate::repositories::{SearchPatientFilter, SearchRepository};
+use crate::AppState;
+
+/// Hits returned when no limit is given
+const DEFAULT_LIMIT: usize = 10;
+
+/// Most hits returned
+const MAX_LIMIT: usize = 25;
+
+/// Search request
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Comma-separated entity types to search, e.g. `patient,document`;
+    /// all of them when absent
+    pub types: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// What a caller's search may return
+#[derive(Debug, Clone)]
+struct SearchAccess {
+    patients: SearchPatientFilter,
+    entities: Vec<SearchEntity>,
+    clearance: Confidentiality,
+}
+
+/// Decide which hits a caller may see; unauthenticated callers may not
+/// search at all
+fn search_access(context: Option<&AuthContext>) -> Result<SearchAccess> {
+    let Some(context) = context else {
+        return Err(ApiError::authentication_error("Sign in to search records"));
+    };
+    if context.is_patient_session() {
+        let patient_id = context
+            .patient_id
+            .ok_or_else(|| ApiError::authorization_error("Token has no patient context"))?;
+        let entities = SearchEntity::ALL
+            .into_iter()
+            .filter(|entity| context.scopes.allows(entity.resource_type(), ScopeAccess::Read))
+            .collect();
+        return Ok(SearchAccess {
+            patients: SearchPatientFilter::Only(patient_id),
+            entities,
+            clearance: context.clearance,
+        });
+    }
+    let practitioner_id = context
+        .practitioner_id()
+        .ok_or_else(|| ApiError::authorization_error("Only practitioners can search records"))?;
+    Ok(SearchAccess {
+        patients: SearchPatientFilter::TreatedBy(practitioner_id),
+        entities: SearchEntity::ALL.to_vec(),
+        clearance: context.clearance,
+    })
+}
+
+/// Entity types requested, all of them when none are named
+fn requested_entities(types: Option<&str>) -> Result<Vec<SearchEntity>> {
+    let Some(types) = types.filter(|types| !types.trim().is_empty()) else {
+        return Ok(SearchEntity::ALL.to_vec());
+    };
+    types
+        .split(',')
+        .map(|name| {
+            SearchEntity::parse(name.trim())
+                .ok_or_else(|| ApiError::validation_error(&format!("Unknown search type: {}", name.trim())))
+        })
+        .collect()
+}
+
+/// Search patients, practitioners, organizations and documents
+#[get("/search")]
+pub async fn global_search(
+    query: web::Query<SearchQuery>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let q = normalize_query(&query.q)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let access = search_access(req.extensions().get::<AuthContext>())?;
+    let entities: Vec<SearchEntity> = requested_entities(query.types.as_deref())?
+        .into_iter()
+        .filter(|entity| access.entities.contains(entity))
+        .collect();
+    let wants = |entity| entities.contains(&entity);
+
+    let repository = SearchRepository::new();
+    let pool = &data.db_pool;
+    let (patients, practitioners, organizations, documents) = futures_util::try_join!(
+        async {
+            if wants(SearchEntity::Patient) {
+                repository.patients(pool, &q, access.patients, limit).await
+            } else {
+                Ok(Vec::new())
+            }
+        },
+        async {
+            if wants(SearchEntity::Practitioner) {
+                repository.practitioners(pool, &q, limit).await
+            } else {
+                Ok(Vec::new())
+            }
+        },
+        async {
+            if wants(SearchEntity::Organization) {
+                repository.organizations(pool, &q, limit).await
+            } else {
+                Ok(Vec::new())
+            }
+        },
+        async {
+            if wants(SearchEntity::Document) {
+                repository.documents(pool, &q, access.patients, access.clearance, limit).await
+            } else {
+                Ok(Vec::new())
+            }
+        },
+    )?;
+
+    let hits = rank_hits([patients, practitioners, organizations, documents].concat(), limit);
+    let searched: Vec<&str> = entities.iter().map(SearchEntity::as_str).collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        hits,
+        json!({ "query": q, "types": searched, "limit": limit }),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::scopes::Scopes;
+
+    fn context(subject: &str, patient_id: Option<uuid::Uuid>, scope: &str) -> AuthContext {
+        AuthContext {
+            subject: subject.to_string(),
+            patient_id,
+            tenant_id: None,
+            scopes: Scopes::parse(scope),
+            clearance: Default::default(),
+            purpose_of_use: None,
+            roles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_search_access() {
+        let patient_id = uuid::Uuid::new_v4();
+        let scope = "patient/Patient.read patient/DocumentReference.read";
+        let portal = search_access(Some(&context("jane", Some(patient_id), scope))).unwrap();
+        assert!(matches!(portal.patients, SearchPatientFilter::Only(id) if id == patient_id));
+        assert_eq!(portal.entities, vec![SearchEntity::Patient, SearchEntity::Document]);
+
+        let practitioner_id = uuid::Uuid::new_v4();
+        let staff = search_access(Some(&context(&practitioner_id.to_string(), None, "user/*.read"))).unwrap();
+        assert!(matches!(staff.patients, SearchPatientFilter::TreatedBy(id) if id == practitioner_id));
+        assert_eq!(staff.entities.len(), 4);
+        assert_eq!(staff.clearance, Confidentiality::Normal);
+
+        assert!(search_access(Some(&context("service", None, "user/*.read"))).is_err());
+        assert_eq!(search_access(None).unwrap_err().category(), "authentication");
+    }
+
+    #[test]
+    fn test_requested_entities() {
+        assert_eq!(requested_entities(None).unwrap().len(), 4);
+        assert_eq!(
+            requested_entities(Some("patient, document")).unwrap(),
+            vec![SearchEntity::Patient, SearchEntity::Document]
+        );
+        assert!(requested_entities(Some("encounter")).is_err());
+    }
+}
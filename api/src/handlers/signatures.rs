@@ -0,0 +1,89 @@
+//! Electronic signature helpers
+//!
+//! Resources that practitioners sign (clinical notes, orders) share these
+//! helpers: the server signing key from `auth.signing_key`, and verification
+//! of a resource's stored signatures against its current content. Every
+//! verification is written to the audit trail as a `VERIFY` event, next to
+//! the `SIGN` and `AMEND` events stored with the signatures themselves.
+
+use emr_core::signing::{Signature, SigningKey, Verification};
+use emr_core::types::Id;
+use serde::Serialize;
+use crate::error::{ApiError, Result};
+use crate::repositories::SignatureRepository;
+use crate::AppState;
+
+/// A stored signature with the outcome of checking it
+#[derive(Debug, Serialize)]
+pub struct VerifiedSignature {
+    #[serde(flatten)]
+    pub signature: Signature,
+    pub verification: Verification,
+}
+
+/// The key sealing and verifying signatures
+pub(crate) fn signing_key(data: &AppState) -> Result<SigningKey> {
+    SigningKey::new(data.config.auth.signing_key.as_bytes())
+        .map_err(|e| ApiError::internal_error(&format!("Signing is not configured: {}", e)))
+}
+
+/// Check every signature on a resource and audit the check
+///
+/// `content` returns what a signature should cover; `None` means the signed
+/// content no longer exists, which counts as changed.
+pub(crate) async fn verify_signatures<F>(
+    data: &AppState,
+    target_type: &str,
+    target_id: Id,
+    verified_by: Option<Id>,
+    content: F,
+) -> Result<Vec<VerifiedSignature>>
+where
+    F: Fn(&Signature) -> Option<Vec<u8>>,
+{
+    let key = signing_key(data)?;
+    let repository = SignatureRepository::new();
+
+    let verified: Vec<VerifiedSignature> = repository
+        .for_target(&data.db_pool, target_type, target_id)
+        .await?
+        .into_iter()
+        .map(|signature| {
+            let verification = match content(&signature) {
+                Some(content) => key.verify(&signature, &content),
+                None => Verification::ContentChanged,
+            };
+            VerifiedSignature { signature, verification }
+        })
+        .collect();
+
+    let outcomes: Vec<(Id, Verification)> = verified
+        .iter()
+        .map(|verified| (verified.signature.id, verified.verification))
+        .collect();
+    repository
+        .record_verification(&data.db_pool, target_type, target_id, &outcomes, verified_by)
+        .await?;
+
+    Ok(verified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_verified_signature_is_flat() {
+        let key = SigningKey::new(&[1; 32]).unwrap();
+        let signature = key.attest("ClinicalNote", uuid::Uuid::new_v4(), b"note", uuid::Uuid::new_v4(), Utc::now());
+
+        let value = serde_json::to_value(VerifiedSignature {
+            signature,
+            verification: Verification::Valid,
+        })
+        .unwrap();
+        assert_eq!(value["kind"], "attestation");
+        assert_eq!(value["verification"], "valid");
+    }
+}
@@ -0,0 +1,331 @@
+//! Validation profile administration handlers
+//!
+//! Each tenant (organization) can keep its own rule set per entity type;
+//! tenants without one fall back to the default profile, saved without a
+//! tenant. Saving rules adds a version, so earlier versions stay readable and
+//! batch results can name the version they were checked against.
+//!
+//! In strict mode, creates and updates that violate an error or critical rule
+//! are rejected by [`enforce_profile`]. `POST .../run` checks the stored
+//! entities in batch with a DataValidation job carrying the active profile.
+
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse};
+use emr_core::types::Id;
+use emr_core::validation::{validate_rules, ProfileRule, ValidationProfile, ValidationReport};
+use emr_jobs::types::{DataValidationJob, JobSubmission, JobType, ValidationType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse};
+use crate::repositories::ValidationProfileRepository;
+use crate::AppState;
+
+/// Most rules per profile
+const MAX_RULES: usize = 200;
+
+/// Profile list filter
+#[derive(Debug, Deserialize)]
+pub struct ProfileFilter {
+    pub tenant_id: Option<Id>,
+    pub entity_type: Option<String>,
+}
+
+/// Tenant of a profile; omitted for the default profile
+#[derive(Debug, Deserialize)]
+pub struct TenantQuery {
+    pub tenant_id: Option<Id>,
+}
+
+/// New profile version
+#[derive(Debug, Deserialize)]
+pub struct SaveProfileRequest {
+    pub tenant_id: Option<Id>,
+    #[serde(default)]
+    pub strict: bool,
+    pub rules: Vec<ProfileRule>,
+}
+
+/// Dry run of a profile against an entity's FHIR JSON
+#[derive(Debug, Deserialize)]
+pub struct DryRunRequest {
+    pub tenant_id: Option<Id>,
+    /// Draft rules to try instead of the active profile
+    pub rules: Option<Vec<ProfileRule>>,
+    pub entity: Value,
+}
+
+/// Batch run request
+#[derive(Debug, Deserialize)]
+pub struct RunProfileRequest {
+    pub tenant_id: Option<Id>,
+}
+
+/// Batch run acknowledgement
+#[derive(Debug, Serialize)]
+pub struct RunProfileResponse {
+    pub job_id: uuid::Uuid,
+    pub profile_id: Id,
+    pub profile_version: i32,
+    pub status: &'static str,
+}
+
+/// Check a rule set before saving or trying it
+fn check_rules(entity_type: &str, rules: &[ProfileRule]) -> Result<()> {
+    if rules.len() > MAX_RULES {
+        return Err(ApiError::validation_error(&format!(
+            "Profiles are limited to {} rules",
+            MAX_RULES
+        )));
+    }
+    validate_rules(entity_type, rules).map_err(|e| ApiError::validation_error(&e.to_string()))
+}
+
+/// Job submission checking stored entities against a profile version
+fn validation_submission(profile: &ValidationProfile, job_id: uuid::Uuid) -> JobSubmission {
+    JobSubmission {
+        job_id: Some(job_id),
+        idempotency_key: None,
+        job: JobType::DataValidation(DataValidationJob {
+            patient_id: None,
+            validation_type: ValidationType::BusinessRules,
+            rules: Vec::new(),
+            auto_fix: false,
+            profile: Some(profile.clone()),
+        }),
+    }
+}
+
+/// Error for a write rejected in strict mode, listing what failed
+fn strict_rejection(report: &ValidationReport) -> ApiError {
+    ApiError::validation_error(&report.rejection())
+}
+
+/// Evaluate the profile in force for a tenant against an entity's FHIR JSON
+/// before it is written
+///
+/// Strict profiles reject the write when an error or critical rule fails;
+/// other violations are logged and the write goes ahead.
+pub(crate) async fn enforce_profile(
+    data: &AppState,
+    tenant_id: Option<Id>,
+    entity_type: &str,
+    entity: &Value,
+) -> Result<()> {
+    match active_profile(data, tenant_id, entity_type).await? {
+        Some(profile) => check_profile(&profile, entity),
+        None => Ok(()),
+    }
+}
+
+/// The profile in force for a tenant's entities of a type, for batch writes
+/// that check many entities with [`check_profile`]
+pub(crate) async fn active_profile(
+    data: &AppState,
+    tenant_id: Option<Id>,
+    entity_type: &str,
+) -> Result<Option<ValidationProfile>> {
+    ValidationProfileRepository::new()
+        .active(&data.db_pool, tenant_id, entity_type)
+        .await
+}
+
+/// Evaluate a profile against an entity's FHIR JSON as [`enforce_profile`] does
+pub(crate) fn check_profile(profile: &ValidationProfile, entity: &Value) -> Result<()> {
+    let report = profile.evaluate(entity);
+    if profile.strict && report.has_errors() {
+        return Err(strict_rejection(&report));
+    }
+    if !report.violations.is_empty() {
+        tracing::info!(
+            entity_type = %profile.entity_type,
+            profile_id = %profile.id,
+            version = profile.version,
+            violations = report.violations.len(),
+            "Entity saved with validation profile violations"
+        );
+    }
+    Ok(())
+}
+
+/// Latest version of each profile, optionally of one tenant or entity type
+#[get("/admin/validation-profiles")]
+pub async fn list_validation_profiles(
+    filter: web::Query<ProfileFilter>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let filter = filter.into_inner();
+    let profiles = ValidationProfileRepository::new()
+        .list_latest(&data.db_pool, filter.tenant_id, filter.entity_type)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(profiles)))
+}
+
+/// Version history of a tenant's profile for an entity type, newest first
+#[get("/admin/validation-profiles/{entity_type}/versions")]
+pub async fn list_validation_profile_versions(
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let versions = ValidationProfileRepository::new()
+        .versions(&data.db_pool, query.into_inner().tenant_id, &path.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(versions)))
+}
+
+/// Save rules as the next version of a tenant's profile
+#[put("/admin/validation-profiles/{entity_type}")]
+pub async fn save_validation_profile(
+    path: web::Path<String>,
+    request: web::Json<SaveProfileRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let entity_type = path.into_inner();
+    let request = request.into_inner();
+    check_rules(&entity_type, &request.rules)?;
+
+    let profile = ValidationProfileRepository::new()
+        .create_version(&data.db_pool, request.tenant_id, &entity_type, request.strict, &request.rules)
+        .await?;
+
+    tracing::info!(
+        profile_id = %profile.id,
+        tenant_id = ?profile.tenant_id,
+        entity_type = %profile.entity_type,
+        version = profile.version,
+        "Validation profile saved"
+    );
+    Ok(HttpResponse::Created().json(ApiResponse::new(profile)))
+}
+
+/// Evaluate the active profile, or draft rules, against an entity without
+/// saving anything
+#[post("/admin/validation-profiles/{entity_type}/validate")]
+pub async fn dry_run_validation_profile(
+    path: web::Path<String>,
+    request: web::Json<DryRunRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let entity_type = path.into_inner();
+    let request = request.into_inner();
+
+    let profile = match request.rules {
+        Some(rules) => {
+            check_rules(&entity_type, &rules)?;
+            ValidationProfile {
+                id: uuid::Uuid::nil(),
+                tenant_id: request.tenant_id,
+                entity_type,
+                version: 0,
+                strict: false,
+                rules,
+                created_at: chrono::Utc::now(),
+            }
+        }
+        None => ValidationProfileRepository::new()
+            .active(&data.db_pool, request.tenant_id, &entity_type)
+            .await?
+            .ok_or_else(|| ApiError::not_found(&format!("No validation profile for '{}'", entity_type)))?,
+    };
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(profile.evaluate(&request.entity))))
+}
+
+/// Check stored entities against the active profile in a background job
+///
+/// Follow the job on `GET /jobs/{id}/events`; its result counts violations
+/// per rule and lists the first failing entities.
+#[post("/admin/validation-profiles/{entity_type}/run")]
+pub async fn run_validation_profile(
+    path: web::Path<String>,
+    request: web::Json<RunProfileRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let entity_type = path.into_inner();
+    let profile = ValidationProfileRepository::new()
+        .active(&data.db_pool, request.into_inner().tenant_id, &entity_type)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("No validation profile for '{}'", entity_type)))?;
+
+    let job_id = uuid::Uuid::new_v4();
+    submit_job(&data, &validation_submission(&profile, job_id)).await?;
+
+    tracing::info!(job_id = %job_id, profile_id = %profile.id, version = profile.version, "Profile validation submitted");
+    Ok(HttpResponse::Accepted().json(ApiResponse::new(RunProfileResponse {
+        job_id,
+        profile_id: profile.id,
+        profile_version: profile.version,
+        status: "submitted",
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::validation::{RuleCheck, RuleSeverity, RuleViolation};
+
+    fn required(name: &str) -> ProfileRule {
+        ProfileRule {
+            name: name.to_string(),
+            description: format!("{} is required", name),
+            field: name.to_string(),
+            check: RuleCheck::Required,
+            severity: RuleSeverity::Error,
+        }
+    }
+
+    #[test]
+    fn test_check_rules() {
+        assert!(check_rules("Encounter", &[required("reasonCode")]).is_ok());
+        assert!(check_rules("Encounter", &[required("reasonCode"), required("reasonCode")]).is_err());
+        assert!(check_rules("Medication", &[required("code")]).is_err());
+
+        let too_many: Vec<_> = (0..=MAX_RULES).map(|i| required(&format!("field{}", i))).collect();
+        assert!(check_rules("Encounter", &too_many).is_err());
+    }
+
+    #[test]
+    fn test_validation_submission() {
+        let profile = ValidationProfile {
+            id: uuid::Uuid::new_v4(),
+            tenant_id: Some(uuid::Uuid::new_v4()),
+            entity_type: "Observation".to_string(),
+            version: 4,
+            strict: true,
+            rules: vec![required("valueQuantity")],
+            created_at: chrono::Utc::now(),
+        };
+        let job_id = uuid::Uuid::new_v4();
+
+        let submission = serde_json::to_value(validation_submission(&profile, job_id)).unwrap();
+        assert_eq!(submission["type"], "DataValidation");
+        assert_eq!(submission["job_id"], serde_json::json!(job_id));
+        assert_eq!(submission["profile"]["version"], 4);
+        assert_eq!(submission["profile"]["rules"][0]["check"], "required");
+    }
+
+    #[test]
+    fn test_strict_rejection_lists_violations() {
+        let report = ValidationReport {
+            profile_id: uuid::Uuid::new_v4(),
+            profile_version: 2,
+            violations: vec![RuleViolation {
+                rule: "reason".to_string(),
+                field: "reasonCode".to_string(),
+                severity: RuleSeverity::Error,
+                message: "Reason is required: missing".to_string(),
+            }],
+        };
+
+        let error = strict_rejection(&report).to_string();
+        assert!(error.contains("v2"));
+        assert!(error.contains("Reason is required: missing"));
+    }
+}
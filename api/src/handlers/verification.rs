@@ -0,0 +1,247 @@
+//! Contact point verification endpoints
+//!
+//! `POST /patients/{id}/telecom/verify` sends a one-time code to one of the
+//! patient's phone numbers or email addresses through a Notification job, and
+//! `POST /patients/{id}/telecom/verify/confirm` checks the code and records
+//! `verified_at` on the patient's stored contact point. Codes are only sent to
+//! values already among the patient's telecom entries.
+//! Wrong codes are counted per patient across reissued codes, and too many
+//! lock the patient's confirmations out (`auth.throttle.verification_confirm`).
+
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use emr_core::domain::values::{ContactPoint, ContactSystem};
+use emr_core::domain::{ContactVerification, VerificationStatus};
+use emr_core::notifications::{TemplateRef, CONTACT_VERIFICATION_TEMPLATE};
+use emr_core::services::throttle::ThrottledAction;
+use serde::{Deserialize, Serialize};
+use emr_jobs::types::{JobSubmission, JobType, NotificationChannel, NotificationJob, NotificationType, Priority};
+use crate::auth::{events, networks};
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse};
+use crate::repositories::PatientRepository;
+use crate::AppState;
+
+/// Request to send a verification code
+#[derive(Debug, Deserialize)]
+pub struct SendVerificationRequest {
+    pub system: ContactSystem,
+    pub value: String,
+}
+
+/// Request to confirm a verification code
+#[derive(Debug, Deserialize)]
+pub struct ConfirmVerificationRequest {
+    pub value: String,
+    pub code: String,
+}
+
+/// Verification response DTO; never includes the code
+#[derive(Debug, Serialize)]
+pub struct VerificationResponse {
+    pub id: String,
+    pub patient_id: String,
+    pub value: String,
+    pub status: VerificationStatus,
+    pub expires_at: DateTime<Utc>,
+    pub attempts_remaining: u32,
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+impl From<&ContactVerification> for VerificationResponse {
+    fn from(verification: &ContactVerification) -> Self {
+        Self {
+            id: verification.id.to_string(),
+            patient_id: verification.patient_id.to_string(),
+            value: verification.value.clone(),
+            status: verification.status(Utc::now()),
+            expires_at: verification.expires_at,
+            attempts_remaining: verification.max_attempts.saturating_sub(verification.attempts),
+            verified_at: verification.verified_at,
+        }
+    }
+}
+
+/// Whether `value` is one of the patient's contact points that a code for
+/// `system` can be delivered to; SMS codes go to phone numbers too
+fn is_patient_contact(telecom: &[ContactPoint], system: &ContactSystem, value: &str) -> bool {
+    let texts = |system: &ContactSystem| matches!(system, ContactSystem::Phone | ContactSystem::Sms);
+    telecom.iter().any(|telecom| {
+        telecom.value == value
+            && telecom.is_verifiable()
+            && (telecom.system == *system || (texts(&telecom.system) && texts(system)))
+    })
+}
+
+/// Build the Notification job that delivers a verification code
+fn notification_job(verification: &ContactVerification, code: &str) -> JobSubmission {
+    let channel = match verification.system {
+        ContactSystem::Email => NotificationChannel::Email,
+        _ => NotificationChannel::Sms,
+    };
+
+    let mut variables = serde_json::Map::new();
+    variables.insert("code".to_string(), code.into());
+    variables.insert("expires_at".to_string(), verification.expires_at.format("%H:%M").to_string().into());
+
+    JobSubmission {
+        job_id: None,
+        // One message per issued code, even if the request is retried
+        idempotency_key: Some(format!("contact-verification:{}", verification.id)),
+        job: JobType::Notification(NotificationJob {
+            recipient_id: verification.patient_id,
+            address: Some(verification.value.clone()),
+            notification_type: NotificationType::Alert,
+            message: String::new(),
+            template: Some(TemplateRef {
+                name: CONTACT_VERIFICATION_TEMPLATE.to_string(),
+                locale: None,
+                variables,
+            }),
+            channel,
+            priority: Priority::High,
+            scheduled_for: None,
+            digest: false,
+        }),
+    }
+}
+
+/// Send a verification code to a patient contact point
+#[post("/patients/{id}/telecom/verify")]
+pub async fn send_verification(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<SendVerificationRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    let request = request.into_inner();
+    let value = request.value.trim();
+
+    let telecom = PatientRepository::new()
+        .telecom(&data.db_pool, patient_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Patient {} not found", patient_id)))?;
+    if !is_patient_contact(&telecom, &request.system, value) {
+        return Err(ApiError::validation_error("Patient has no such contact point"));
+    }
+
+    let (verification, code) = data.verifications.issue(patient_id, request.system, value).await?;
+
+    submit_job(&data, &notification_job(&verification, &code)).await?;
+
+    Ok(HttpResponse::Accepted().json(ApiResponse::new(VerificationResponse::from(&verification))))
+}
+
+/// Confirm a verification code
+#[post("/patients/{id}/telecom/verify/confirm")]
+pub async fn confirm_verification(
+    path: web::Path<uuid::Uuid>,
+    request: web::Json<ConfirmVerificationRequest>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let patient_id = path.into_inner();
+    let throttle_key = patient_id.to_string();
+    data.throttle.check(ThrottledAction::VerificationConfirm, &throttle_key).await?;
+
+    let confirmed = data
+        .verifications
+        .confirm(patient_id, request.value.trim(), &request.code)
+        .await;
+    let verification = match confirmed {
+        Ok(verification) => verification,
+        Err(e) => {
+            let ip_address = networks::request_ip(&req, &data.config.auth.network_zones.trusted_proxies)
+                .map(|address| address.to_string());
+            let lockout = data
+                .throttle
+                .record_failure(ThrottledAction::VerificationConfirm, &throttle_key, ip_address.as_deref())
+                .await;
+            if let Some(lockout) = lockout {
+                events::record_security_event(&data.db_pool, &lockout).await;
+            }
+            return Err(e);
+        }
+    };
+    data.throttle.reset(ThrottledAction::VerificationConfirm, &throttle_key).await;
+
+    let verified_at = verification.verified_at.unwrap_or_else(Utc::now);
+    let recorded = PatientRepository::new()
+        .record_verified_telecom(&data.db_pool, patient_id, &verification.value, verified_at)
+        .await?;
+    if !recorded {
+        return Err(ApiError::validation_error("Patient no longer has this contact point"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(VerificationResponse::from(&verification))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::domain::VerificationPolicy;
+    use emr_core::notifications::TemplateCatalog;
+
+    #[test]
+    fn test_notification_job() {
+        let (verification, code) = ContactVerification::issue(
+            uuid::Uuid::new_v4(),
+            ContactSystem::Email,
+            "jane@example.com",
+            &VerificationPolicy::default(),
+            Utc::now(),
+        )
+        .unwrap();
+
+        let job = serde_json::to_value(notification_job(&verification, &code)).unwrap();
+        assert_eq!(job["type"], "Notification");
+        assert_eq!(job["channel"], "Email");
+        assert_eq!(job["address"], "jane@example.com");
+        assert_eq!(job["idempotency_key"], format!("contact-verification:{}", verification.id));
+        assert_eq!(job["template"]["name"], CONTACT_VERIFICATION_TEMPLATE);
+        assert_eq!(job["template"]["variables"]["code"], code);
+
+        let template: TemplateRef = serde_json::from_value(job["template"].clone()).unwrap();
+        let rendered = TemplateCatalog::builtin().render(&template).unwrap();
+        assert!(rendered.body.contains(&code));
+    }
+
+    #[test]
+    fn test_is_patient_contact() {
+        let contact = |system, value: &str| ContactPoint {
+            system,
+            value: value.to_string(),
+            use_: None,
+            rank: None,
+            verified_at: None,
+        };
+        let telecom = vec![
+            contact(ContactSystem::Phone, "+15555550100"),
+            contact(ContactSystem::Email, "jane@example.com"),
+            contact(ContactSystem::Fax, "+15555550199"),
+        ];
+
+        assert!(is_patient_contact(&telecom, &ContactSystem::Sms, "+15555550100"));
+        assert!(is_patient_contact(&telecom, &ContactSystem::Email, "jane@example.com"));
+        assert!(!is_patient_contact(&telecom, &ContactSystem::Email, "+15555550100"));
+        assert!(!is_patient_contact(&telecom, &ContactSystem::Sms, "+15555550199"));
+        assert!(!is_patient_contact(&telecom, &ContactSystem::Email, "someone@example.com"));
+    }
+
+    #[test]
+    fn test_response_omits_code() {
+        let (verification, code) = ContactVerification::issue(
+            uuid::Uuid::new_v4(),
+            ContactSystem::Sms,
+            "+15555550100",
+            &VerificationPolicy::default(),
+            Utc::now(),
+        )
+        .unwrap();
+
+        let response = serde_json::to_string(&VerificationResponse::from(&verification)).unwrap();
+        assert!(!response.contains(&code));
+        assert!(response.contains("\"attempts_remaining\":5"));
+    }
+}
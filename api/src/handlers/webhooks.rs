@@ -0,0 +1,345 @@
+//! Webhook administration handlers
+//!
+//! Tenants (organizations) register endpoints that receive domain events.
+//! Deliveries are made by the jobs worker (`emr_jobs::webhooks`), which
+//! signs each payload with the endpoint secret and logs every attempt in
+//! `jobs.webhook_deliveries`. The secret is returned once, when the endpoint
+//! is registered.
+//!
+//! Handlers emit domain events with [`publish_event`]; the worker matches
+//! them against the tenant's endpoints. Redelivering an event submits a new
+//! delivery job for the same endpoint and event, with a new delivery id.
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use emr_core::events::{self, EventEnvelope};
+use emr_jobs::types::{JobSubmission, JobType, WebhookDeliveryJob, WebhookEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::error::{ApiError, Result};
+use crate::handlers::{submit_job, ApiResponse, PaginationParams};
+use crate::models::{NewWebhookEndpointModel, WebhookDeliveryModel, WebhookEndpointModel};
+use crate::repositories::WebhookRepository;
+use crate::AppState;
+
+/// NATS subject the jobs worker consumes domain events from
+/// (`emr_jobs::webhooks::EVENTS_SUBJECT`)
+const WEBHOOK_EVENTS_SUBJECT: &str = "webhooks.events";
+
+/// Delivery attempts for a redelivery
+const REDELIVERY_MAX_ATTEMPTS: u32 = 5;
+
+/// Most event filters per endpoint
+const MAX_EVENT_FILTERS: usize = 50;
+
+/// Endpoint registration request
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub tenant_id: uuid::Uuid,
+    pub url: String,
+    /// Event filters such as `encounter.*`, `encounter.finished` or `*`
+    pub events: Vec<String>,
+}
+
+/// Registered endpoint with its signing secret, returned only on creation
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpointModel,
+    pub secret: String,
+}
+
+/// Endpoint list filter
+#[derive(Debug, Deserialize)]
+pub struct WebhookFilter {
+    pub tenant_id: Option<uuid::Uuid>,
+}
+
+/// Redelivery acknowledgement
+#[derive(Debug, Serialize)]
+pub struct RedeliveryResponse {
+    pub delivery_id: uuid::Uuid,
+    pub redelivery_of: uuid::Uuid,
+    pub status: &'static str,
+}
+
+/// Check an endpoint URL: HTTPS, or HTTP to a local receiver
+fn validate_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| ApiError::validation_error(&format!("Invalid webhook URL: {}", e)))?;
+
+    let local = matches!(parsed.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    match parsed.scheme() {
+        "https" => Ok(()),
+        "http" if local => Ok(()),
+        _ => Err(ApiError::validation_error("Webhook URLs must use https")),
+    }
+}
+
+/// Check event filters: non-empty names of letters, digits, `_`, `-` and
+/// `.`, optionally ending in `.*`, or `*` alone
+fn validate_events(events: &[String]) -> Result<()> {
+    if events.is_empty() {
+        return Err(ApiError::validation_error("At least one event filter is required"));
+    }
+    if events.len() > MAX_EVENT_FILTERS {
+        return Err(ApiError::validation_error(&format!(
+            "Endpoints are limited to {} event filters",
+            MAX_EVENT_FILTERS
+        )));
+    }
+
+    for filter in events {
+        let name = filter.strip_suffix(".*").unwrap_or(filter);
+        let valid = filter == "*"
+            || (!name.is_empty()
+                && !name.starts_with('.')
+                && !name.ends_with('.')
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')));
+        if !valid {
+            return Err(ApiError::validation_error(&format!("Invalid event filter '{}'", filter)));
+        }
+    }
+    Ok(())
+}
+
+/// Random signing secret
+fn generate_secret() -> String {
+    format!(
+        "whsec_{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Job submission delivering a logged event to its endpoint again
+fn redelivery_submission(delivery: &WebhookDeliveryModel, delivery_id: uuid::Uuid) -> Result<JobSubmission> {
+    let event: WebhookEvent = serde_json::from_value(delivery.event.clone()).map_err(|e| {
+        ApiError::internal_error(&format!("Webhook delivery {} has no valid event: {}", delivery.delivery_id, e))
+    })?;
+    Ok(JobSubmission {
+        job_id: Some(delivery_id),
+        idempotency_key: None,
+        job: JobType::WebhookDelivery(WebhookDeliveryJob {
+            endpoint_id: delivery.endpoint_id,
+            event,
+            max_attempts: REDELIVERY_MAX_ATTEMPTS,
+        }),
+    })
+}
+
+/// Domain event in the envelope the worker reads into
+/// `emr_jobs::types::WebhookEvent`, at the newest registered version
+fn webhook_event(tenant_id: uuid::Uuid, event_type: &str, payload: Value) -> EventEnvelope {
+    let version = events::latest_version(event_type).unwrap_or(1);
+    EventEnvelope::with_type(event_type, version, payload).with_tenant(tenant_id)
+}
+
+/// Publish a domain event for delivery to the tenant's webhooks
+///
+/// Failures are logged rather than returned; the change that raised the
+/// event has already been made.
+pub(crate) async fn publish_event(data: &AppState, tenant_id: uuid::Uuid, event_type: &str, payload: Value) {
+    let event = webhook_event(tenant_id, event_type, payload);
+    let published = match event.encode() {
+        Ok(body) => data
+            .nats_client
+            .publish(WEBHOOK_EVENTS_SUBJECT, body.into())
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+
+    if let Err(error) = published {
+        tracing::warn!(%tenant_id, event_type, %error, "Failed to publish webhook event");
+    }
+}
+
+/// Register a webhook endpoint
+///
+/// The response includes the signing secret; it cannot be read again.
+#[post("/admin/webhooks")]
+pub async fn create_webhook(
+    request: web::Json<CreateWebhookRequest>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    validate_url(&request.url)?;
+    validate_events(&request.events)?;
+
+    let secret = generate_secret();
+    let endpoint = WebhookRepository::new()
+        .create_endpoint(
+            &data.db_pool,
+            NewWebhookEndpointModel {
+                tenant_id: request.tenant_id,
+                url: request.url,
+                secret: secret.clone(),
+                events: request.events,
+            },
+        )
+        .await?;
+
+    tracing::info!(endpoint_id = %endpoint.id, tenant_id = %endpoint.tenant_id, "Webhook endpoint registered");
+    Ok(HttpResponse::Created().json(ApiResponse::new(CreatedWebhook { endpoint, secret })))
+}
+
+/// List webhook endpoints, optionally of one tenant
+#[get("/admin/webhooks")]
+pub async fn list_webhooks(
+    filter: web::Query<WebhookFilter>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let endpoints = WebhookRepository::new()
+        .list_endpoints(&data.db_pool, filter.into_inner().tenant_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::new(endpoints)))
+}
+
+/// Remove a webhook endpoint; its delivery log is kept
+#[delete("/admin/webhooks/{id}")]
+pub async fn delete_webhook(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+
+    if !WebhookRepository::new().delete_endpoint(&data.db_pool, id).await? {
+        return Err(ApiError::not_found(&format!("Webhook endpoint {} not found", id)));
+    }
+
+    tracing::info!(endpoint_id = %id, "Webhook endpoint removed");
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Delivery log of an endpoint, newest first, with every attempt
+#[get("/admin/webhooks/{id}/deliveries")]
+pub async fn list_webhook_deliveries(
+    path: web::Path<uuid::Uuid>,
+    query: web::Query<PaginationParams>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (page, per_page) = query.normalize();
+
+    let deliveries = WebhookRepository::new()
+        .list_deliveries(&data.db_pool, path.into_inner(), query.limit(), query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::with_meta(
+        deliveries,
+        json!({ "page": page, "per_page": per_page }),
+    )))
+}
+
+/// Deliver a logged event to its endpoint again
+///
+/// The redelivery runs as a new job whose id is the new delivery id; follow
+/// it on `GET /jobs/{id}/events` or in the endpoint's delivery log.
+#[post("/admin/webhooks/deliveries/{id}/redeliver")]
+pub async fn redeliver_webhook(
+    path: web::Path<uuid::Uuid>,
+    _req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let original = path.into_inner();
+
+    let delivery = WebhookRepository::new()
+        .find_delivery(&data.db_pool, original)
+        .await?
+        .ok_or_else(|| ApiError::not_found(&format!("Webhook delivery {} not found", original)))?;
+
+    let delivery_id = uuid::Uuid::new_v4();
+    submit_job(&data, &redelivery_submission(&delivery, delivery_id)?).await?;
+
+    tracing::info!(delivery_id = %delivery_id, redelivery_of = %original, "Webhook redelivery submitted");
+    Ok(HttpResponse::Accepted().json(ApiResponse::new(RedeliveryResponse {
+        delivery_id,
+        redelivery_of: original,
+        status: "submitted",
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("https://hooks.example.com/emr").is_ok());
+        assert!(validate_url("http://localhost:9000/hook").is_ok());
+        assert!(validate_url("http://hooks.example.com/emr").is_err());
+        assert!(validate_url("ftp://hooks.example.com").is_err());
+        assert!(validate_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_validate_events() {
+        let events = |filters: &[&str]| filters.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+
+        assert!(validate_events(&events(&["*"])).is_ok());
+        assert!(validate_events(&events(&["encounter.*", "patient.created"])).is_ok());
+        assert!(validate_events(&[]).is_err());
+        assert!(validate_events(&events(&[".*"])).is_err());
+        assert!(validate_events(&events(&["encounter."])).is_err());
+        assert!(validate_events(&events(&["encounter.**"])).is_err());
+        assert!(validate_events(&events(&["patient created"])).is_err());
+    }
+
+    #[test]
+    fn test_generate_secret() {
+        let secret = generate_secret();
+        assert!(secret.starts_with("whsec_"));
+        assert_eq!(secret.len(), 6 + 64);
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn test_webhook_event() {
+        let tenant_id = uuid::Uuid::new_v4();
+        let event = webhook_event(tenant_id, "encounter.started", json!({"id": "e1"}));
+        assert_eq!(event.tenant_id, Some(tenant_id));
+        assert_eq!((event.event_type.as_str(), event.version), ("encounter.started", 1));
+        assert_eq!(event.payload["id"], "e1");
+
+        let published: Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(published["payload"]["id"], "e1");
+        assert!(published["occurred_at"].is_string());
+    }
+
+    #[test]
+    fn test_redelivery_submission() {
+        let delivery = WebhookDeliveryModel {
+            delivery_id: uuid::Uuid::new_v4(),
+            endpoint_id: uuid::Uuid::new_v4(),
+            event_id: uuid::Uuid::new_v4(),
+            event_type: "encounter.finished".to_string(),
+            event: json!({
+                "id": uuid::Uuid::nil(),
+                "tenant_id": uuid::Uuid::nil(),
+                "event_type": "encounter.finished",
+                "occurred_at": "2026-01-05T10:00:00Z",
+                "data": {},
+            }),
+            status: "failed".to_string(),
+            attempts: 5,
+            last_status_code: Some(503),
+            last_error: Some("Endpoint responded with 503".to_string()),
+            attempt_log: json!([]),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let delivery_id = uuid::Uuid::new_v4();
+
+        let submission = serde_json::to_value(redelivery_submission(&delivery, delivery_id).unwrap()).unwrap();
+        assert_eq!(submission["type"], "WebhookDelivery");
+        assert_eq!(submission["job_id"], json!(delivery_id));
+        assert_eq!(submission["endpoint_id"], json!(delivery.endpoint_id));
+        assert_eq!(submission["event"], delivery.event);
+    }
+}
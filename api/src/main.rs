@@ -1,139 +1,128 @@
-//! Prototype Actix API entrypoint for Nexus.
+//! Actix API entrypoint for Nexus.
 //!
-//! This binary intentionally keeps a small in-memory surface while the
-//! repository is in an architecture reset. API routes return mock data in a
-//! predictable format so backend wiring, error handling, and route structure can
-//! evolve before database-backed workflows are introduced.
-
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Result};
-use serde::Serialize;
+//! The API routes are served under `/api/v1` and, deprecated, under `/api`;
+//! health probes, metrics and the FHIR facade live at the root. Plain HTTP is
+//! served on `PORT`, and HTTPS on `server.host:server.port` once the TLS
+//! certificate and key exist. `--check-config` reports every configuration
+//! problem and exits instead of starting the server.
+
+use actix_web::{
+    middleware::{Compress, Logger},
+    web, App, HttpServer,
+};
 use std::env;
-use std::sync::OnceLock;
-use std::time::Instant;
-
-static START_TIME: OnceLock<Instant> = OnceLock::new();
-
-#[derive(Serialize)]
-struct HealthResponse {
-    status: &'static str,
-    timestamp: chrono::DateTime<chrono::Utc>,
-    version: &'static str,
-    uptime: u64,
-    rust_version: &'static str,
-}
-
-#[derive(Serialize)]
-struct PatientResponse {
-    id: String,
-    name: String,
-    email: String,
-    phone: String,
-    birth_date: String,
-    status: String,
-}
-
-#[derive(Serialize)]
-struct PatientsListResponse {
-    patients: Vec<PatientResponse>,
-    total: usize,
-    page: usize,
-    per_page: usize,
-}
-
-async fn health_check() -> Result<HttpResponse> {
-    let uptime = START_TIME
-        .get()
-        .map(|start| start.elapsed().as_secs())
-        .unwrap_or_default();
-
-    let response = HealthResponse {
-        status: "healthy",
-        timestamp: chrono::Utc::now(),
-        version: env!("CARGO_PKG_VERSION"),
-        uptime,
-        rust_version: match option_env!("CARGO_PKG_RUST_VERSION") {
-            Some(v) => v,
-            None => "unknown",
-        },
-    };
-
-    Ok(HttpResponse::Ok().json(response))
-}
-
-fn mock_patients() -> Vec<PatientResponse> {
-    vec![
-        PatientResponse {
-            id: "patient-001".to_string(),
-            name: "John Doe".to_string(),
-            email: "john.doe@example.com".to_string(),
-            phone: "+1-555-0123".to_string(),
-            birth_date: "1985-06-15".to_string(),
-            status: "active".to_string(),
-        },
-        PatientResponse {
-            id: "patient-002".to_string(),
-            name: "Jane Smith".to_string(),
-            email: "jane.smith@example.com".to_string(),
-            phone: "+1-555-0456".to_string(),
-            birth_date: "1990-03-22".to_string(),
-            status: "active".to_string(),
-        },
-        PatientResponse {
-            id: "patient-003".to_string(),
-            name: "Bob Johnson".to_string(),
-            email: "bob.johnson@example.com".to_string(),
-            phone: "+1-555-0789".to_string(),
-            birth_date: "1978-11-03".to_string(),
-            status: "active".to_string(),
-        },
-    ]
-}
-
-async fn get_patients() -> Result<HttpResponse> {
-    // Prototype-only data while persistent storage is being wired.
-    let patients = mock_patients();
-
-    let response = PatientsListResponse {
-        total: patients.len(),
-        patients,
-        page: 1,
-        per_page: 10,
-    };
-
-    Ok(HttpResponse::Ok().json(response))
-}
-
-async fn get_patient(path: web::Path<String>) -> Result<HttpResponse> {
-    let patient_id = path.into_inner();
-
-    // Prototype-only data while persistent storage is being wired.
-    let patient = PatientResponse {
-        id: patient_id,
-        name: "John Doe".to_string(),
-        email: "john.doe@example.com".to_string(),
-        phone: "+1-555-0123".to_string(),
-        birth_date: "1985-06-15".to_string(),
-        status: "active".to_string(),
-    };
-
-    Ok(HttpResponse::Ok().json(patient))
-}
-
-#[derive(Serialize)]
-struct RootResponse {
-    service: &'static str,
-    status_endpoint: &'static str,
-    patients_endpoint: &'static str,
-    note: &'static str,
+use std::sync::Arc;
+use std::time::Duration;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+
+mod auth;
+mod config;
+mod database;
+mod error;
+mod fhir;
+mod grpc;
+mod handlers;
+mod middleware;
+mod models;
+mod repositories;
+mod server;
+mod services;
+
+/// State shared by every handler
+pub struct AppState {
+    pub config: config::Config,
+    pub db_pool: database::Pool,
+    pub pool_monitor: database::PoolMonitor,
+    pub connection_monitor: Arc<server::ConnectionMonitor>,
+    pub nats_client: async_nats::Client,
+    pub fhir_client: Arc<dyn emr_fhir::gateway::FhirGateway>,
+    pub subscriptions: emr_fhir::subscription::SubscriptionRegistry,
+    pub organizations: services::OrganizationService,
+    pub encounters: services::EncounterService,
+    pub observations: services::ObservationService,
+    pub encounter_views: services::EncounterPrefetchService,
+    pub verifications: services::ContactVerificationService,
+    pub throttle: services::ThrottleService,
+    pub device_ingest: services::DeviceIngestGate,
+    pub retention: emr_core::retention::RetentionPolicySet,
+    pub retention_store: Arc<dyn emr_jobs::handlers::RetentionStore>,
+    pub readiness: services::Readiness,
+    pub terminology: services::Terminology,
+    feature_flags: services::FeatureFlags,
 }
 
-async fn root() -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().json(RootResponse {
-        service: "Nexus API prototype backend",
-        status_endpoint: "/healthz",
-        patients_endpoint: "/api/patients",
-        note: "Responses are scaffold-level and include mock data.",
-    }))
+impl AppState {
+    /// Connect to the database, NATS and the FHIR server and create the
+    /// services handlers share
+    async fn connect(config: config::Config) -> anyhow::Result<Self> {
+        let db_pool = database::create_pool(&config.database).await?;
+        database::run_migrations(&db_pool).await?;
+        let nats_client = async_nats::ConnectOptions::new()
+            .name(&config.nats.client_id)
+            .connection_timeout(Duration::from_secs(config.nats.connection_timeout))
+            .retry_on_initial_connect()
+            .connect(config.nats.url.as_str())
+            .await?;
+        let fhir_client = fhir::gateway_from_config(&config.fhir)?;
+
+        let encounters = services::EncounterService::new(db_pool.clone());
+        let observations = services::ObservationService::new(db_pool.clone());
+        let encounter_views = services::EncounterPrefetchService::new(
+            fhir_client.clone(),
+            encounters.clone(),
+            observations.clone(),
+            services::DEFAULT_PREFETCH_CONCURRENCY,
+        );
+
+        Ok(Self {
+            pool_monitor: database::PoolMonitor::from_config(&config.database),
+            connection_monitor: Arc::new(server::ConnectionMonitor::new()),
+            subscriptions: emr_fhir::subscription::SubscriptionRegistry::new(),
+            organizations: services::OrganizationService::new(db_pool.clone()),
+            verifications: services::ContactVerificationService::new(Default::default()),
+            throttle: services::ThrottleService::new(config.auth.throttle.clone()),
+            device_ingest: services::DeviceIngestGate::new(config.device_ingest.clone()),
+            retention: emr_core::retention::RetentionPolicySet::from_config(&config.retention)?,
+            // The dry-run report reads what the DataCleanup job would, across
+            // tenants, so it bypasses the row-security role like the worker
+            retention_store: Arc::new(emr_jobs::retention::DatabaseRetentionStore::new((*db_pool).clone())),
+            readiness: services::Readiness::new(),
+            terminology: services::Terminology::new(fhir_client.clone()),
+            feature_flags: services::FeatureFlags::new(db_pool.clone(), &config.flags),
+            encounters,
+            observations,
+            encounter_views,
+            fhir_client,
+            nats_client,
+            db_pool,
+            config,
+        })
+    }
+
+    /// Start the background tasks: cache warm-up, configuration reloads and,
+    /// when enabled, the internal gRPC API
+    fn spawn_background(&self, log_level: services::reload::LogLevelHandle) {
+        services::CacheWarmer::new(
+            self.config.warmup.clone(),
+            self.terminology.clone(),
+            self.feature_flags.clone(),
+            self.fhir_client.clone(),
+        )
+        .spawn(self.readiness.clone());
+
+        services::ConfigReloader::new(self.config.clone(), self.db_pool.clone(), self.feature_flags.clone())
+            .with_log_level(log_level)
+            .spawn(self.config.reload.watch_interval);
+
+        if self.config.grpc.enabled {
+            let grpc = self.config.grpc.clone();
+            tokio::spawn(async move {
+                if let Err(e) = grpc::serve(&grpc).await {
+                    tracing::error!(error = %e, "The internal patient API stopped");
+                }
+            });
+        }
+    }
 }
 
 fn configure_cors() -> actix_cors::Cors {
@@ -147,31 +136,79 @@ fn configure_cors() -> actix_cors::Cors {
 }
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init();
-    let _ = START_TIME.set(Instant::now());
+async fn main() -> anyhow::Result<()> {
+    // `RUST_LOG` wins over `logging.level`, which applies once loaded
+    let from_env = EnvFilter::try_from_default_env().ok();
+    let (filter, filter_handle) = reload::Layer::new(from_env.clone().unwrap_or_else(|| EnvFilter::new("info")));
+    tracing_subscriber::registry().with(filter).with(tracing_subscriber::fmt::layer()).init();
+    let log_level: services::reload::LogLevelHandle = Arc::new(move |level: &str| {
+        let filter = EnvFilter::try_new(level).map_err(|e| e.to_string())?;
+        filter_handle.reload(filter).map_err(|e| e.to_string())
+    });
+
+    if env::args().any(|arg| arg == "--check-config") {
+        let report = match config::Config::load_checked(true).await {
+            Ok((_, report)) => report,
+            Err(report) => report,
+        };
+        println!("{}", report);
+        std::process::exit(if report.has_errors() { 1 } else { 0 });
+    }
+
+    let config = match config::Config::load_checked(false).await {
+        Ok((config, report)) => {
+            for line in report.to_string().lines() {
+                tracing::warn!("{}", line);
+            }
+            config
+        }
+        Err(report) => anyhow::bail!("Invalid configuration:\n{}", report),
+    };
+    if from_env.is_none() {
+        log_level(&config.logging.level).map_err(|e| anyhow::anyhow!("Invalid logging.level: {}", e))?;
+    }
+
+    handlers::health::init_start_time();
+    let https = config.server.tls_files_exist();
+    let state = web::Data::new(AppState::connect(config).await?);
+    state.spawn_background(log_level);
+
+    let app = {
+        let state = state.clone();
+        move || {
+            let config = &state.config;
+            App::new()
+                .app_data(state.clone())
+                .app_data(middleware::limits::json_config(&config.body_limits))
+                .wrap(middleware::auth::AuthMiddleware)
+                .wrap(middleware::versioning::ApiVersioning::new(&config.versioning))
+                .wrap(middleware::limits::BodyLimit::new(&config.body_limits))
+                .wrap(Compress::default())
+                .wrap(middleware::compression::CompressionPolicy::new(&config.compression))
+                .wrap(middleware::security::SecurityHeaders)
+                .wrap(Logger::default())
+                .wrap(configure_cors())
+                .wrap(middleware::errors::ErrorNegotiation)
+                .configure(handlers::configure_root)
+                // `/api/v1` first: the `/api` scope would otherwise claim its requests
+                .service(web::scope("/api/v1").configure(handlers::configure))
+                .service(web::scope("/api").configure(handlers::configure))
+        }
+    };
 
+    let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8090".to_string());
-    let bind_address = format!("127.0.0.1:{}", port);
-
-    println!("Nexus API prototype starting on http://{}", bind_address);
-    println!("📋 Health Check: http://{}/healthz", bind_address);
-    println!("👥 Patients API: http://{}/api/patients", bind_address);
-
-    HttpServer::new(move || {
-        App::new()
-            .wrap(Logger::default())
-            .wrap(configure_cors())
-            .service(
-                web::scope("/api")
-                    .route("/patients", web::get().to(get_patients))
-                    .route("/patients/{id}", web::get().to(get_patient)),
-            )
-            .route("/healthz", web::get().to(health_check))
-            .route("/health", web::get().to(health_check))
-            .route("/", web::get().to(root))
-    })
-    .bind(&bind_address)?
-    .run()
-    .await
+    let bind_address = format!("{}:{}", host, port);
+    tracing::info!("Nexus API listening on http://{}", bind_address);
+    let http = HttpServer::new(app.clone()).bind(&bind_address)?.run();
+
+    if https {
+        let server = &state.config.server;
+        tracing::info!("Nexus API listening on https://{}:{}", server.host, server.port);
+        let https = server::serve(server, state.connection_monitor.clone(), app)?;
+        tokio::try_join!(http, https)?;
+    } else {
+        http.await?;
+    }
+    Ok(())
 }
@@ -1,14 +1,119 @@
 //! Authentication middleware
+//!
+//! A bearer token is validated and its [`AuthContext`] added to the request
+//! extensions. Tokens whose scopes are all `patient/...` belong to patients
+//! signed in to the portal: they may only use `/portal/` routes, which
+//! serve the token's own patient, and may only write to portal messaging
+//! and notification preferences.
+//! Portal routes other than the login need such a token.
+//!
+//! Staff select a purpose of use for sensitive reads, sent in the
+//! `X-Purpose-Of-Use` header as `treatment`, `payment`, `operations` or
+//! `emergency`; it is added to the [`AuthContext`] for the consent rules
+//! and the audit log.
+//!
+//! Each request is served inside the [`DbSession`] of its caller, so the
+//! database connections it uses carry the caller's tenant, user and portal
+//! patient for row-level security, and the route and request id for the
+//! audit trail and slow query logs.
+//!
+//! Requests refused with 401 or 403, here or by the handler, are recorded
+//! as authentication events for the security event detector.
+//!
+//! Callers holding a role limited to network zones are refused with 403
+//! outside those zones, and the attempt is recorded as a security event
+//! (see [`networks`]).
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
+    http::{header, Method, StatusCode},
+    web, Error, HttpMessage,
 };
 use futures_util::future::{ready, Ready};
 use std::{
     future::Future,
     pin::Pin,
 };
+use emr_core::services::consent::PurposeOfUse;
+use chrono::Utc;
+use emr_core::services::security_events::{AuthEvent, AuthEventKind, SecurityEvent};
+use crate::auth::{events, networks, validate_token, AuthContext};
+use crate::database::DbSession;
+use crate::error::ApiError;
+use crate::handlers::extract_request_id;
+use crate::middleware::versioning::ApiVersion;
+use crate::AppState;
+
+/// Portal sign-in with local credentials, open to anyone
+const PORTAL_LOGIN_PATH: &str = "/portal/login";
+
+/// Portal routes patient sessions may write to
+const PORTAL_WRITE_PATHS: [&str; 2] = ["/portal/messages", "/portal/notification-preferences"];
+
+/// Header carrying the purpose of use selected for a request
+pub const PURPOSE_OF_USE_HEADER: &str = "X-Purpose-Of-Use";
+
+/// Path without the `/api` or `/api/v<n>` mount point
+fn route_path(path: &str) -> &str {
+    match ApiVersion::from_path(path) {
+        Some((_, route)) => route,
+        None => path.strip_prefix("/api").unwrap_or(path),
+    }
+}
+
+/// Whether a request may go ahead for the caller
+fn authorize(method: &Method, path: &str, context: Option<&AuthContext>) -> std::result::Result<(), ApiError> {
+    let path = route_path(path);
+    let portal = path.starts_with("/portal/") && path != PORTAL_LOGIN_PATH;
+
+    match context {
+        Some(context) if context.is_patient_session() => {
+            if !portal {
+                return Err(ApiError::authorization_error("Patient sessions are limited to the portal"));
+            }
+            let writable = PORTAL_WRITE_PATHS.iter().any(|prefix| path.starts_with(prefix));
+            if method != Method::GET && !writable {
+                return Err(ApiError::authorization_error(
+                    "Patient sessions are read-only outside messaging and notification preferences",
+                ));
+            }
+            Ok(())
+        }
+        Some(_) if portal => Err(ApiError::authorization_error("The portal is for patient sessions")),
+        None if portal => Err(ApiError::authentication_error("Sign in to the portal")),
+        // TODO(nexus-phase2): Require tokens and check roles outside the portal.
+        _ => Ok(()),
+    }
+}
+
+/// Validate the request's bearer token, if it has one, against the
+/// `auth.jwt_secret` it was signed with
+fn bearer_context(req: &ServiceRequest, secret: &str) -> std::result::Result<Option<AuthContext>, ApiError> {
+    let Some(value) = req.headers().get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let token = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::authentication_error("Expected a bearer token"))?;
+
+    let claims = validate_token(secret, token.trim())?;
+    Ok(Some(AuthContext::from_claims(&claims)))
+}
+
+/// Parse the purpose of use a request was made for, if it names one
+fn purpose_of_use(value: Option<&str>) -> std::result::Result<Option<PurposeOfUse>, ApiError> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+    PurposeOfUse::parse(&value.to_ascii_lowercase()).map(Some).ok_or_else(|| {
+        ApiError::validation_error(&format!(
+            "{} must be treatment, payment, operations or emergency",
+            PURPOSE_OF_USE_HEADER
+        ))
+    })
+}
 
 /// Authentication middleware
 pub struct AuthMiddleware;
@@ -23,7 +128,7 @@ where
     type Error = Error;
     type Transform = AuthMiddlewareService<S>;
     type InitError = ();
-    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+    type Future = Ready<std::result::Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(AuthMiddlewareService { service }))
@@ -42,14 +147,157 @@ where
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>>>>;
 
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        // TODO(nexus-phase2): Enforce token validation + role checks.
-        // Current behavior intentionally passes through during scaffold phase.
-        let fut = self.service.call(req);
-        Box::pin(async move { fut.await })
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+        let pool = state.as_ref().map(|state| state.db_pool.clone());
+        let zones = state.as_ref().map(|state| &state.config.auth.network_zones);
+        let trusted_proxies = zones.map(|zones| zones.trusted_proxies.as_slice()).unwrap_or_default();
+        let client = networks::request_ip(req.request(), trusted_proxies);
+        let ip_address = client.map(|address| address.to_string());
+        let route = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+
+        let secret = state.as_ref().map(|state| state.config.auth.jwt_secret.as_str()).unwrap_or_default();
+        let context = bearer_context(&req, secret);
+        let denial = denial_event(&req, ip_address.as_deref(), context.as_ref().ok().and_then(Option::as_ref));
+        let checked = context.and_then(|context| {
+            authorize(req.method(), req.path(), context.as_ref())?;
+            let header = req.headers().get(PURPOSE_OF_USE_HEADER);
+            let purpose = purpose_of_use(header.and_then(|value| value.to_str().ok()))?;
+            Ok(context.map(|context| AuthContext {
+                purpose_of_use: purpose,
+                ..context
+            }))
+        });
+        let violation = match (&checked, zones) {
+            (Ok(Some(context)), Some(zones)) => networks::restricted_role(zones, &context.roles, client).map(|role| {
+                SecurityEvent::network_zone_violation(
+                    &context.subject,
+                    role,
+                    ip_address.as_deref(),
+                    req.path(),
+                    Utc::now(),
+                )
+            }),
+            _ => None,
+        };
+        let checked = match violation {
+            Some(_) => Err(ApiError::authorization_error("One of your roles may not be used from this network")),
+            None => checked,
+        };
+
+        match checked {
+            Ok(context) => {
+                let session = context
+                    .as_ref()
+                    .map(DbSession::from_context)
+                    .unwrap_or_default()
+                    .for_request(route, extract_request_id(req.request()));
+                if let Some(context) = context {
+                    req.extensions_mut().insert(context);
+                }
+                let fut = session.scope(self.service.call(req));
+                Box::pin(async move {
+                    let response = fut.await?;
+                    if let Some(pool) = pool {
+                        if let Some(event) = denial.filter(|_| is_denied(response.status())) {
+                            events::record(&pool, &event).await;
+                        }
+                    }
+                    Ok(response)
+                })
+            }
+            Err(error) => Box::pin(async move {
+                if let Some(pool) = pool {
+                    if let Some(event) = violation {
+                        events::record_security_event(&pool, &event).await;
+                    }
+                    if let Some(event) = denial.filter(|_| is_denied(error.status_code())) {
+                        events::record(&pool, &event).await;
+                    }
+                }
+                Err(error.into())
+            }),
+        }
     }
-} 
\ No newline at end of file
+}
+
+/// Whether a response refuses the caller
+fn is_denied(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+/// Event to record if the request is refused; portal sign-ins record their
+/// own
+fn denial_event(req: &ServiceRequest, ip_address: Option<&str>, context: Option<&AuthContext>) -> Option<AuthEvent> {
+    if route_path(req.path()) == PORTAL_LOGIN_PATH {
+        return None;
+    }
+    let subject = match context {
+        Some(context) => context.subject.clone(),
+        None => events::anonymous_subject(ip_address),
+    };
+    Some(events::auth_event(
+        AuthEventKind::AccessDenied,
+        subject,
+        req.headers(),
+        ip_address,
+        Some(req.path()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::scopes::Scopes;
+
+    fn context(scope: &str) -> AuthContext {
+        AuthContext {
+            subject: "portal-user".to_string(),
+            patient_id: Some(uuid::Uuid::new_v4()),
+            tenant_id: None,
+            scopes: Scopes::parse(scope),
+            clearance: Default::default(),
+            purpose_of_use: None,
+            roles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_patient_sessions_limited_to_portal() {
+        let patient = context("openid patient/*.read");
+
+        assert!(authorize(&Method::GET, "/api/portal/observations", Some(&patient)).is_ok());
+        assert!(authorize(&Method::GET, "/api/v1/portal/observations", Some(&patient)).is_ok());
+        assert!(authorize(&Method::GET, "/portal/me", Some(&patient)).is_ok());
+        assert!(authorize(&Method::POST, "/api/portal/observations", Some(&patient)).is_err());
+        assert!(authorize(&Method::POST, "/api/portal/messages", Some(&patient)).is_ok());
+        assert!(authorize(&Method::PUT, "/api/portal/notification-preferences", Some(&patient)).is_ok());
+        assert!(authorize(&Method::POST, "/api/messages/threads", Some(&patient)).is_err());
+        assert!(authorize(&Method::GET, "/api/patients", Some(&patient)).is_err());
+        assert!(authorize(&Method::GET, "/api/v1/patients", Some(&patient)).is_err());
+        assert!(authorize(&Method::GET, "/api/admin/webhooks", Some(&patient)).is_err());
+    }
+
+    #[test]
+    fn test_purpose_of_use_header() {
+        assert_eq!(purpose_of_use(None).unwrap(), None);
+        assert_eq!(purpose_of_use(Some(" ")).unwrap(), None);
+        assert_eq!(purpose_of_use(Some("Treatment")).unwrap(), Some(PurposeOfUse::Treatment));
+        assert_eq!(purpose_of_use(Some("emergency")).unwrap(), Some(PurposeOfUse::Emergency));
+        assert!(purpose_of_use(Some("research")).is_err());
+    }
+
+    #[test]
+    fn test_portal_requires_patient_session() {
+        assert!(authorize(&Method::GET, "/api/portal/me", None).is_err());
+        assert!(authorize(&Method::POST, "/api/portal/login", None).is_ok());
+
+        let clinician = context("user/*.*");
+        assert!(authorize(&Method::GET, "/api/portal/me", Some(&clinician)).is_err());
+        assert!(authorize(&Method::GET, "/api/patients", Some(&clinician)).is_ok());
+    }
+}
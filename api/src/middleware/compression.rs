@@ -0,0 +1,190 @@
+//! Response compression policy
+//!
+//! actix-web's [`Compress`] middleware encodes responses in whichever coding
+//! the request's `Accept-Encoding` prefers among those it was built with.
+//! [`CompressionPolicy`] wraps it and narrows `Accept-Encoding` to the
+//! configured codings before it looks, expanding `*` into them and
+//! dropping the header when compression is disabled, so responses are only
+//! ever compressed the way `compression` allows:
+//!
+//! ```ignore
+//! App::new()
+//!     .wrap(Compress::default())
+//!     .wrap(CompressionPolicy::new(&config.compression))
+//! ```
+//!
+//! [`Compress`]: actix_web::middleware::Compress
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+    Error,
+};
+use futures_util::future::{ready, Ready};
+use crate::config::CompressionConfig;
+
+/// The codings of `accept_encoding` compression may use: allowed ones and
+/// `identity` as the client weighted them, and `*` as every allowed coding
+/// the client did not name. `None` when none is left.
+pub fn narrow_accept_encoding(accept_encoding: &str, allowed: &[String]) -> Option<String> {
+    let entries: Vec<(String, &str)> = accept_encoding
+        .split(',')
+        .filter_map(|entry| {
+            let (coding, params) = entry.split_once(';').unwrap_or((entry, ""));
+            let coding = coding.trim().to_ascii_lowercase();
+            (!coding.is_empty()).then_some((coding, params.trim()))
+        })
+        .collect();
+    let named = |coding: &str| entries.iter().any(|(named, _)| named == coding);
+
+    let mut narrowed = Vec::new();
+    for (coding, params) in &entries {
+        let codings: Vec<&str> = if coding == "*" {
+            allowed.iter().map(String::as_str).filter(|allowed| !named(allowed)).collect()
+        } else if coding == "identity" || allowed.contains(coding) {
+            vec![coding.as_str()]
+        } else {
+            Vec::new()
+        };
+        for coding in codings {
+            if params.is_empty() {
+                narrowed.push(coding.to_string());
+            } else {
+                narrowed.push(format!("{};{}", coding, params));
+            }
+        }
+    }
+    (!narrowed.is_empty()).then(|| narrowed.join(", "))
+}
+
+/// Response compression policy middleware
+pub struct CompressionPolicy {
+    enabled: bool,
+    encodings: Vec<String>,
+}
+
+impl CompressionPolicy {
+    /// Create the middleware for the configured codings
+    pub fn new(config: &CompressionConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            encodings: config.encodings.iter().map(|encoding| encoding.to_ascii_lowercase()).collect(),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CompressionPolicy
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = CompressionPolicyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<std::result::Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CompressionPolicyMiddleware {
+            service,
+            enabled: self.enabled,
+            encodings: self.encodings.clone(),
+        }))
+    }
+}
+
+pub struct CompressionPolicyMiddleware<S> {
+    service: S,
+    enabled: bool,
+    encodings: Vec<String>,
+}
+
+impl<S, B> Service<ServiceRequest> for CompressionPolicyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let narrowed = match req.headers().get(header::ACCEPT_ENCODING) {
+            Some(value) if self.enabled => value
+                .to_str()
+                .ok()
+                .and_then(|value| narrow_accept_encoding(value, &self.encodings))
+                .and_then(|value| HeaderValue::from_str(&value).ok()),
+            _ => None,
+        };
+        match narrowed {
+            Some(value) => req.headers_mut().insert(header::ACCEPT_ENCODING, value),
+            None => req.headers_mut().remove(header::ACCEPT_ENCODING),
+        };
+        self.service.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{middleware::Compress, web, App, HttpResponse};
+
+    fn allowed(codings: &[&str]) -> Vec<String> {
+        codings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_narrow_accept_encoding() {
+        let gzip = allowed(&["gzip"]);
+        assert_eq!(narrow_accept_encoding("gzip, deflate, br", &gzip).as_deref(), Some("gzip"));
+        assert_eq!(narrow_accept_encoding("br;q=1.0, GZIP;q=0.5", &gzip).as_deref(), Some("gzip;q=0.5"));
+        assert_eq!(narrow_accept_encoding("br, identity;q=0", &gzip).as_deref(), Some("identity;q=0"));
+        assert_eq!(narrow_accept_encoding("deflate", &gzip), None);
+
+        let both = allowed(&["br", "gzip"]);
+        assert_eq!(narrow_accept_encoding("gzip, *;q=0.1", &both).as_deref(), Some("gzip, br;q=0.1"));
+        assert_eq!(narrow_accept_encoding("*", &both).as_deref(), Some("br, gzip"));
+    }
+
+    #[actix_web::test]
+    async fn test_compression_follows_policy() {
+        let body = "x".repeat(4096);
+        let encoding_for = |config: CompressionConfig, accept: &'static str| {
+            let body = body.clone();
+            async move {
+                let app = init_service(
+                    App::new()
+                        .wrap(Compress::default())
+                        .wrap(CompressionPolicy::new(&config))
+                        .route("/", web::get().to(move || {
+                            let body = body.clone();
+                            async move { HttpResponse::Ok().content_type("application/json").body(body) }
+                        })),
+                )
+                .await;
+                let req = TestRequest::get().uri("/").insert_header((header::ACCEPT_ENCODING, accept));
+                let res = call_service(&app, req.to_request()).await;
+                res.headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|value| value.to_str().unwrap().to_string())
+            }
+        };
+
+        let config = CompressionConfig::default();
+        assert_eq!(encoding_for(config.clone(), "gzip, br").await.as_deref(), Some("br"));
+        assert_eq!(encoding_for(config.clone(), "gzip, zstd").await.as_deref(), Some("gzip"));
+        assert_eq!(encoding_for(config.clone(), "zstd").await, None);
+
+        let disabled = CompressionConfig {
+            enabled: false,
+            ..config
+        };
+        assert_eq!(encoding_for(disabled, "gzip, br").await, None);
+    }
+}
@@ -0,0 +1,92 @@
+//! Error format negotiation
+//!
+//! Handlers and inner middleware fail with an [`ApiError`], which renders
+//! itself as problem details without knowing the request. This middleware
+//! renders it again for the request: as an OperationOutcome when the client
+//! accepts `application/fhir+json` or `+xml`, and with the request path as the
+//! problem's `instance` and the `X-Request-ID` header echoed back. It should
+//! wrap every other middleware so their errors are negotiated too.
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    Error, HttpRequest,
+};
+use futures_util::future::{ready, Ready};
+use std::{future::Future, pin::Pin};
+use crate::error::{ApiError, ErrorFormat};
+use crate::handlers::extract_request_id;
+
+/// Render the error for the request it failed
+fn negotiate(req: &HttpRequest, error: &ApiError) -> actix_web::HttpResponse {
+    let accept = req.headers().get(header::ACCEPT).and_then(|value| value.to_str().ok());
+    error.render(ErrorFormat::from_accept(accept), Some(req.path().to_string()), extract_request_id(req))
+}
+
+/// Error format negotiation middleware
+pub struct ErrorNegotiation;
+
+impl<S, B> Transform<S, ServiceRequest> for ErrorNegotiation
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ErrorNegotiationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<std::result::Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ErrorNegotiationMiddleware { service }))
+    }
+}
+
+pub struct ErrorNegotiationMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for ErrorNegotiationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request = req.request().clone();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            match fut.await {
+                Ok(res) => {
+                    let rendered = res
+                        .response()
+                        .error()
+                        .and_then(|error| error.as_error::<ApiError>())
+                        .map(|error| negotiate(res.request(), error));
+                    match rendered {
+                        Some(response) => Ok(res.into_response(response).map_into_right_body()),
+                        None => Ok(res.map_into_left_body()),
+                    }
+                }
+                // Middleware errors never became a response; render them here
+                Err(error) => match error.as_error::<ApiError>() {
+                    Some(api_error) => {
+                        let response = negotiate(&request, api_error);
+                        Ok(ServiceResponse::new(request, response).map_into_right_body())
+                    }
+                    None => Err(error),
+                },
+            }
+        })
+    }
+}
+
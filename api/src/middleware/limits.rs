@@ -0,0 +1,274 @@
+//! Request body size limits
+//!
+//! Every route belongs to a [`RouteClass`] with its own limit in
+//! `body_limits`: small for JSON CRUD requests, larger for `_bulk` routes and
+//! photo and document uploads. [`BodyLimit`] answers a request whose
+//! `Content-Length` is over its limit with a 413 before the handler runs.
+//! Bodies without a length are cut off at the limit as they stream in, and
+//! the handler's response is replaced with the 413, however the handler
+//! reported the truncated body.
+//!
+//! `web::Json` has a limit of its own; [`json_config`] sets it to the largest
+//! JSON class so this middleware decides, and reports overflows as a 413 too.
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    http::header,
+    web::{self, Bytes},
+    Error, HttpMessage, HttpResponse,
+};
+use futures_util::future::{ready, Ready};
+use futures_util::Stream;
+use std::{
+    cell::Cell,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+use crate::config::BodyLimitConfig;
+use crate::error::{json_error_handler, ApiError};
+use crate::middleware::versioning::ApiVersion;
+
+/// Kind of route, for its body limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// JSON CRUD requests
+    Json,
+    /// `_bulk` routes
+    Bulk,
+    /// Photo and document uploads
+    Upload,
+}
+
+impl RouteClass {
+    /// Class of the route a path is served by, under `/api/v1` or `/api`
+    pub fn of(path: &str) -> Self {
+        let route = ApiVersion::from_path(path)
+            .map(|(_, route)| route)
+            .or_else(|| path.strip_prefix("/api"))
+            .unwrap_or(path);
+        let segments: Vec<&str> = route.trim_matches('/').split('/').collect();
+        match segments.as_slice() {
+            [.., "_bulk"] => RouteClass::Bulk,
+            ["patients", _, "photos" | "documents"] => RouteClass::Upload,
+            _ => RouteClass::Json,
+        }
+    }
+
+    /// Largest body the class accepts
+    pub fn limit(self, config: &BodyLimitConfig) -> usize {
+        match self {
+            RouteClass::Json => config.json_bytes,
+            RouteClass::Bulk => config.bulk_bytes,
+            RouteClass::Upload => config.upload_bytes,
+        }
+    }
+}
+
+impl fmt::Display for RouteClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteClass::Json => write!(f, "JSON"),
+            RouteClass::Bulk => write!(f, "bulk"),
+            RouteClass::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+/// The 413 for a body over the limit of its route
+fn too_large(class: RouteClass, limit: usize) -> ApiError {
+    ApiError::payload_too_large(&format!("Request bodies of {} routes are limited to {} bytes", class, limit))
+}
+
+/// `web::Json` settings leaving body limits to [`BodyLimit`]
+pub fn json_config(config: &BodyLimitConfig) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(config.json_bytes.max(config.bulk_bytes))
+        .error_handler(json_error_handler)
+}
+
+/// A request body that fails once more than `remaining` bytes arrive
+struct LimitedPayload {
+    payload: Payload,
+    remaining: usize,
+    exceeded: Rc<Cell<bool>>,
+}
+
+impl Stream for LimitedPayload {
+    type Item = std::result::Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.exceeded.get() {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut this.payload).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) if chunk.len() > this.remaining => {
+                this.exceeded.set(true);
+                Poll::Ready(Some(Err(PayloadError::Overflow)))
+            }
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.remaining -= chunk.len();
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            other => other,
+        }
+    }
+}
+
+/// Request body size limit middleware
+pub struct BodyLimit {
+    config: BodyLimitConfig,
+}
+
+impl BodyLimit {
+    /// Create the middleware for the configured limits
+    pub fn new(config: &BodyLimitConfig) -> Self {
+        Self { config: config.clone() }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BodyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = BodyLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<std::result::Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BodyLimitMiddleware {
+            service,
+            config: self.config.clone(),
+        }))
+    }
+}
+
+pub struct BodyLimitMiddleware<S> {
+    service: S,
+    config: BodyLimitConfig,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let class = RouteClass::of(req.path());
+        let limit = class.limit(&self.config);
+
+        let length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if length.is_some_and(|length| length > limit) {
+            let response = req.error_response(too_large(class, limit)).map_into_right_body();
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let exceeded = Rc::new(Cell::new(false));
+        let payload = LimitedPayload {
+            payload: req.take_payload(),
+            remaining: limit,
+            exceeded: exceeded.clone(),
+        };
+        req.set_payload(Payload::from(Box::pin(payload) as Pin<Box<dyn Stream<Item = _>>>));
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            if !exceeded.get() {
+                return Ok(res.map_into_left_body());
+            }
+            let response = HttpResponse::from_error(too_large(class, limit));
+            Ok(res.into_response(response).map_into_right_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{http::StatusCode, App};
+    use futures_util::{stream, TryStreamExt};
+
+    #[test]
+    fn test_route_classes() {
+        assert_eq!(RouteClass::of("/api/v1/patients"), RouteClass::Json);
+        assert_eq!(RouteClass::of("/api/v1/patients/_bulk"), RouteClass::Bulk);
+        assert_eq!(RouteClass::of("/api/observations/_bulk"), RouteClass::Bulk);
+        assert_eq!(RouteClass::of("/api/v1/patients/123/documents"), RouteClass::Upload);
+        assert_eq!(RouteClass::of("/api/patients/123/photos"), RouteClass::Upload);
+        assert_eq!(RouteClass::of("/api/v1/referrals/123/documents"), RouteClass::Json);
+        assert_eq!(RouteClass::of("/healthz"), RouteClass::Json);
+    }
+
+    async fn read_body(mut payload: web::Payload) -> std::result::Result<HttpResponse, Error> {
+        let mut body = Vec::new();
+        while let Some(chunk) = payload.try_next().await.map_err(|e| ApiError::bad_request(&e.to_string()))? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(HttpResponse::Ok().body(body))
+    }
+
+    #[actix_web::test]
+    async fn test_body_limits() {
+        let config = BodyLimitConfig {
+            json_bytes: 16,
+            bulk_bytes: 64,
+            upload_bytes: 32,
+        };
+        let app = init_service(
+            App::new()
+                .wrap(BodyLimit::new(&config))
+                .app_data(json_config(&config))
+                .route("/api/v1/notes", web::post().to(|body: web::Json<serde_json::Value>| async move {
+                    HttpResponse::Ok().json(body.into_inner())
+                }))
+                .route("/api/v1/patients/_bulk", web::post().to(read_body))
+                .route("/api/v1/patients/{id}/documents", web::post().to(read_body)),
+        )
+        .await;
+        let post = |uri: &str, body: &str| {
+            TestRequest::post().uri(uri).insert_header(header::ContentType::json()).set_payload(body.to_string())
+        };
+
+        let small = post("/api/v1/notes", r#"{"text":"hi"}"#).to_request();
+        assert_eq!(call_service(&app, small).await.status(), StatusCode::OK);
+
+        // Fits the bulk limit, not the JSON one
+        let body = format!(r#"{{"text":"{}"}}"#, "x".repeat(32));
+        let res = call_service(&app, post("/api/v1/notes", &body).to_request()).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let problem: serde_json::Value = read_body_json(res).await;
+        assert_eq!(problem["error"], "payload_too_large");
+        assert!(problem["detail"].as_str().unwrap().contains("limited to 16 bytes"));
+        let res = call_service(&app, post("/api/v1/patients/_bulk", &body).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Bodies without a length are cut off as they arrive
+        let chunks = (0..2).map(|_| Ok::<_, PayloadError>(Bytes::from(vec![b'x'; 24])));
+        let streamed = Payload::from(Box::pin(stream::iter(chunks)) as Pin<Box<dyn Stream<Item = _>>>);
+        let (req, _) = TestRequest::post().uri("/api/v1/patients/1/documents").to_request().replace_payload(streamed);
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
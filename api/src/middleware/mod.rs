@@ -1,4 +1,8 @@
 //! Middleware modules
 
 pub mod security;
-pub mod auth; 
\ No newline at end of file
+pub mod auth;
+pub mod errors;
+pub mod versioning;
+pub mod compression;
+pub mod limits;
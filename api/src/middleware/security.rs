@@ -2,14 +2,10 @@
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpMessage,
+    Error,
 };
 use futures_util::future::{ready, Ready};
-use std::{
-    future::{ready as fut_ready, Future, Ready as FutReady},
-    pin::Pin,
-    task::{Context, Poll},
-};
+use std::{future::Future, pin::Pin};
 
 /// Security headers middleware
 pub struct SecurityHeaders;
@@ -56,19 +52,19 @@ where
             // Add security headers
             res.headers_mut().insert(
                 actix_web::http::header::HeaderName::from_static("x-content-type-options"),
-                actix_web::http::HeaderValue::from_static("nosniff"),
+                actix_web::http::header::HeaderValue::from_static("nosniff"),
             );
             res.headers_mut().insert(
                 actix_web::http::header::HeaderName::from_static("x-frame-options"),
-                actix_web::http::HeaderValue::from_static("DENY"),
+                actix_web::http::header::HeaderValue::from_static("DENY"),
             );
             res.headers_mut().insert(
                 actix_web::http::header::HeaderName::from_static("x-xss-protection"),
-                actix_web::http::HeaderValue::from_static("1; mode=block"),
+                actix_web::http::header::HeaderValue::from_static("1; mode=block"),
             );
             res.headers_mut().insert(
                 actix_web::http::header::HeaderName::from_static("strict-transport-security"),
-                actix_web::http::HeaderValue::from_static("max-age=31536000; includeSubDomains"),
+                actix_web::http::header::HeaderValue::from_static("max-age=31536000; includeSubDomains"),
             );
 
             Ok(res)
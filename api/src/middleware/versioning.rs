@@ -0,0 +1,317 @@
+//! API versioning
+//!
+//! Routes are mounted twice: under `/api/v1`, which pins the version, and
+//! under the unversioned `/api` they were first served at. Unversioned
+//! requests get the version their `Accept` header names
+//! (`application/vnd.emr.v1+json` or `application/json; version=1`), or the
+//! current one, and are answered with `Deprecation`, `Sunset` (once
+//! `versioning.legacy_sunset` is set) and a `Link` to the versioned route.
+//! Asking for a version that is not served is a 406.
+//!
+//! The negotiated [`ApiVersion`] is added to the request extensions and
+//! echoed in the `API-Version` header. When a response body changes shape,
+//! the change gets a [`Shim`] that turns the new shape back into the old one,
+//! and the handler answers through [`versioned_json`], so clients pinned to
+//! an older version keep getting what they integrated against.
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderName, HeaderValue},
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use chrono::{NaiveDate, NaiveTime};
+use futures_util::future::{ready, Ready};
+use serde::Serialize;
+use serde_json::Value;
+use std::{fmt, future::Future, pin::Pin};
+use crate::config::VersioningConfig;
+use crate::error::ApiError;
+use crate::handlers::ApiResponse;
+
+/// Mount point of the unversioned routes
+const LEGACY_PREFIX: &str = "/api";
+
+/// Day the unversioned routes were deprecated in favour of `/api/v1`
+const LEGACY_DEPRECATED: (i32, u32, u32) = (2026, 10, 17);
+
+/// A version of the API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion(pub u16);
+
+impl ApiVersion {
+    /// Version new integrations get
+    pub const CURRENT: ApiVersion = ApiVersion(1);
+
+    /// Versions served, oldest first
+    pub const SUPPORTED: [ApiVersion; 1] = [ApiVersion(1)];
+
+    /// Whether the version is served
+    pub fn is_supported(self) -> bool {
+        Self::SUPPORTED.contains(&self)
+    }
+
+    /// Mount point of the version's routes, e.g. `/api/v1`
+    pub fn prefix(self) -> String {
+        format!("{}/v{}", LEGACY_PREFIX, self.0)
+    }
+
+    /// The version a path is mounted under, and the path below it
+    pub fn from_path(path: &str) -> Option<(ApiVersion, &str)> {
+        let rest = path.strip_prefix(LEGACY_PREFIX)?.strip_prefix("/v")?;
+        let (number, route) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        Some((ApiVersion(number.parse().ok()?), route))
+    }
+
+    /// The version an `Accept` header names, if it names one
+    pub fn from_accept(accept: &str) -> Option<ApiVersion> {
+        accept.split(',').find_map(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next()?.to_ascii_lowercase();
+            let vendor = media_type
+                .strip_prefix("application/vnd.emr.v")
+                .and_then(|rest| rest.strip_suffix("+json"))
+                .and_then(|number| number.parse().ok());
+            let parameter = || params.find_map(|param| param.strip_prefix("version=")?.parse().ok());
+            vendor.or_else(parameter).map(ApiVersion)
+        })
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The version negotiated for the request; the current one outside the
+/// versioning middleware
+impl FromRequest for ApiVersion {
+    type Error = Error;
+    type Future = Ready<std::result::Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(req.extensions().get::<ApiVersion>().copied().unwrap_or(ApiVersion::CURRENT)))
+    }
+}
+
+/// A change to a response body, undone for clients of older versions
+pub struct Shim {
+    /// Kind of body the change applies to, e.g. `patient`
+    pub kind: &'static str,
+    /// First version with the new shape
+    pub since: ApiVersion,
+    /// Turn the new shape into the one before `since`
+    pub downgrade: fn(&mut Value),
+}
+
+/// Every response body change since v1, oldest first
+const SHIMS: &[Shim] = &[];
+
+/// Turn the current shape of a body into the one `version` clients expect
+pub fn downgrade(kind: &str, version: ApiVersion, body: &mut Value) {
+    apply_shims(SHIMS, kind, version, body);
+}
+
+fn apply_shims(shims: &[Shim], kind: &str, version: ApiVersion, body: &mut Value) {
+    // Newest first, so each shim sees the shape its own version produced
+    for shim in shims.iter().rev().filter(|shim| shim.kind == kind && version < shim.since) {
+        (shim.downgrade)(body);
+    }
+}
+
+/// Respond with `data`, in the shape the request's API version expects, as
+/// an [`ApiResponse`]
+pub fn versioned_json<T: Serialize>(version: ApiVersion, kind: &str, data: &T) -> crate::error::Result<HttpResponse> {
+    let mut data = serde_json::to_value(data)?;
+    downgrade(kind, version, &mut data);
+    Ok(HttpResponse::Ok().json(ApiResponse::new(data)))
+}
+
+/// HTTP date of midnight UTC on a day, as `Sunset` takes
+fn http_date(day: NaiveDate) -> String {
+    day.and_time(NaiveTime::MIN).and_utc().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// How a request was routed
+struct Negotiated {
+    version: ApiVersion,
+    /// Versioned path of an unversioned request
+    successor: Option<String>,
+}
+
+/// The version a request gets
+fn negotiate(path: &str, accept: Option<&str>) -> crate::error::Result<Negotiated> {
+    if let Some((version, _)) = ApiVersion::from_path(path) {
+        if !version.is_supported() {
+            return Err(ApiError::not_found(&format!("API version {} does not exist", version)));
+        }
+        return Ok(Negotiated { version, successor: None });
+    }
+
+    let version = accept.and_then(ApiVersion::from_accept).unwrap_or(ApiVersion::CURRENT);
+    if !version.is_supported() {
+        let supported: Vec<String> = ApiVersion::SUPPORTED.iter().map(ToString::to_string).collect();
+        return Err(ApiError::not_acceptable(&format!(
+            "API version {} is not served; use one of {}",
+            version,
+            supported.join(", ")
+        )));
+    }
+    let successor = path
+        .strip_prefix(LEGACY_PREFIX)
+        .filter(|route| route.is_empty() || route.starts_with('/'))
+        .map(|route| format!("{}{}", version.prefix(), route));
+    Ok(Negotiated { version, successor })
+}
+
+/// API versioning middleware
+pub struct ApiVersioning {
+    sunset: Option<HeaderValue>,
+}
+
+impl ApiVersioning {
+    /// Create the middleware for the configured sunset of unversioned routes
+    pub fn new(config: &VersioningConfig) -> Self {
+        Self {
+            sunset: config.legacy_sunset.and_then(|day| HeaderValue::from_str(&http_date(day)).ok()),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiVersioning
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiVersioningMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<std::result::Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiVersioningMiddleware {
+            service,
+            sunset: self.sunset.clone(),
+        }))
+    }
+}
+
+pub struct ApiVersioningMiddleware<S> {
+    service: S,
+    sunset: Option<HeaderValue>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiVersioningMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Health checks and the like live outside `/api` and are not versioned
+        if !req.path().starts_with(LEGACY_PREFIX) {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        let accept = req.headers().get(header::ACCEPT).and_then(|value| value.to_str().ok());
+        let negotiated = match negotiate(req.path(), accept) {
+            Ok(negotiated) => negotiated,
+            Err(error) => {
+                let response = req.error_response(error).map_into_right_body();
+                return Box::pin(async move { Ok(response) });
+            }
+        };
+        req.extensions_mut().insert(negotiated.version);
+
+        let sunset = self.sunset.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            let headers = res.headers_mut();
+            if let Ok(version) = HeaderValue::from_str(&negotiated.version.to_string()) {
+                headers.insert(HeaderName::from_static("api-version"), version);
+            }
+
+            if let Some(successor) = negotiated.successor {
+                let (year, month, day) = LEGACY_DEPRECATED;
+                let deprecated = NaiveDate::from_ymd_opt(year, month, day)
+                    .map(|day| day.and_time(NaiveTime::MIN).and_utc().timestamp())
+                    .and_then(|timestamp| HeaderValue::from_str(&format!("@{}", timestamp)).ok());
+                if let Some(deprecated) = deprecated {
+                    headers.insert(HeaderName::from_static("deprecation"), deprecated);
+                }
+                if let Some(sunset) = sunset {
+                    headers.insert(HeaderName::from_static("sunset"), sunset);
+                }
+                if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor)) {
+                    headers.append(header::LINK, link);
+                }
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_version_negotiation() {
+        assert_eq!(ApiVersion::from_path("/api/v1/patients/123"), Some((ApiVersion(1), "/patients/123")));
+        assert_eq!(ApiVersion::from_path("/api/patients"), None);
+        assert_eq!(ApiVersion::from_accept("application/vnd.emr.v2+json"), Some(ApiVersion(2)));
+        assert_eq!(ApiVersion::from_accept("text/html, application/json; version=1"), Some(ApiVersion(1)));
+        assert_eq!(ApiVersion::from_accept("application/json"), None);
+
+        let legacy = negotiate("/api/patients/123", Some("application/json")).unwrap();
+        assert_eq!(legacy.version, ApiVersion::CURRENT);
+        assert_eq!(legacy.successor.as_deref(), Some("/api/v1/patients/123"));
+        assert!(negotiate("/api/v1/patients", None).unwrap().successor.is_none());
+        assert!(negotiate("/api/patients", Some("application/vnd.emr.v9+json")).is_err());
+        assert!(negotiate("/api/v9/patients", None).is_err());
+
+        assert_eq!(http_date(NaiveDate::from_ymd_opt(2027, 6, 30).unwrap()), "Wed, 30 Jun 2027 00:00:00 GMT");
+    }
+
+    #[test]
+    fn test_shims_downgrade_newest_first() {
+        fn split_name(body: &mut Value) {
+            let name = format!("{} {}", body["given"].as_str().unwrap_or(""), body["family"].as_str().unwrap_or(""));
+            body["name"] = json!(name.trim());
+        }
+        fn rename_dob(body: &mut Value) {
+            body["dob"] = body["birth_date"].take();
+        }
+        let shims = [
+            Shim { kind: "patient", since: ApiVersion(2), downgrade: split_name },
+            Shim { kind: "patient", since: ApiVersion(3), downgrade: rename_dob },
+        ];
+
+        let current = json!({"given": "Ada", "family": "Lovelace", "birth_date": "1815-12-10"});
+        let mut v1 = current.clone();
+        apply_shims(&shims, "patient", ApiVersion(1), &mut v1);
+        assert_eq!(v1["name"], "Ada Lovelace");
+        assert_eq!(v1["dob"], "1815-12-10");
+
+        let mut v2 = current.clone();
+        apply_shims(&shims, "patient", ApiVersion(2), &mut v2);
+        assert!(v2.get("name").is_none());
+        assert_eq!(v2["dob"], "1815-12-10");
+
+        let mut other = current.clone();
+        apply_shims(&shims, "practitioner", ApiVersion(1), &mut other);
+        assert_eq!(other, current);
+    }
+}
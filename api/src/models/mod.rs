@@ -12,4 +12,246 @@ pub struct PatientModel {
     pub active: bool,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
-} 
\ No newline at end of file
+}
+
+/// Patient row to insert; the database assigns id and timestamps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewPatientModel {
+    pub family_name: String,
+    pub given_names: Vec<String>,
+    pub gender: Option<String>,
+    pub birth_date: Option<chrono::NaiveDate>,
+}
+
+/// Job in the `jobs.dead_letters` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterModel {
+    pub job_id: uuid::Uuid,
+    pub job_type: String,
+    pub queue: String,
+    /// The job as submitted, including its `type` tag
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub last_error: String,
+    pub failed_at: chrono::DateTime<chrono::Utc>,
+    pub replay_count: i32,
+    pub replayed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Job run totals and duration percentiles over a window, for one job type
+/// or (`job_type` of `None`) for all of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRunStatsModel {
+    pub job_type: Option<String>,
+    pub total: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    /// Attempts beyond the first
+    pub retries: i64,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+
+/// Tenant webhook endpoint; the signing secret is only returned on creation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpointModel {
+    pub id: uuid::Uuid,
+    pub tenant_id: uuid::Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub active: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Webhook endpoint row to insert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewWebhookEndpointModel {
+    pub tenant_id: uuid::Uuid,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+/// Delivery of one event to one webhook endpoint, with its attempt log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryModel {
+    pub delivery_id: uuid::Uuid,
+    pub endpoint_id: uuid::Uuid,
+    pub event_id: uuid::Uuid,
+    pub event_type: String,
+    pub event: serde_json::Value,
+    /// `pending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub attempt_log: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Patient portal sign-in account
+#[derive(Debug, Clone)]
+pub struct PortalAccountModel {
+    pub id: uuid::Uuid,
+    pub patient_id: uuid::Uuid,
+    /// Argon2 PHC string
+    pub password_hash: String,
+    pub active: bool,
+}
+
+/// Demographics shown to a patient in the portal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalDemographicsModel {
+    pub id: uuid::Uuid,
+    pub family_name: Option<String>,
+    pub given_names: Vec<String>,
+    pub birth_date: Option<chrono::NaiveDate>,
+    pub gender: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub address_line1: Option<String>,
+    pub address_line2: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Document or file in the `emr.document_references` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentReferenceModel {
+    pub id: uuid::Uuid,
+    pub patient_id: uuid::Uuid,
+    pub encounter_id: Option<uuid::Uuid>,
+    /// `current`, `superseded` or `entered-in-error`
+    pub status: String,
+    pub type_code: Option<String>,
+    pub type_display: Option<String>,
+    pub title: Option<String>,
+    pub content_type: String,
+    pub url: String,
+    pub size_bytes: Option<i64>,
+    /// `pending` (quarantined), `clean` or `infected`
+    pub scan_status: String,
+    pub scanned_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// `normal`, `restricted` or `very_restricted`
+    pub confidentiality: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Document found by a search, with the passage of its text that matched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSearchModel {
+    #[serde(flatten)]
+    pub document: DocumentReferenceModel,
+    /// Matching passages of the extracted text, terms wrapped in `<b>`
+    pub snippet: Option<String>,
+}
+
+/// Patient found by a panel's last evaluation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanelMemberModel {
+    pub patient_id: uuid::Uuid,
+    pub name: String,
+    pub gender: Option<String>,
+    pub birth_date: Option<chrono::NaiveDate>,
+}
+
+/// Patient counted in a quality measure report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeasurePatientModel {
+    pub patient_id: uuid::Uuid,
+    pub name: String,
+    pub gender: Option<String>,
+    pub birth_date: Option<chrono::NaiveDate>,
+}
+
+/// Message thread as seen by one participant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageThreadModel {
+    pub thread_id: uuid::Uuid,
+    pub patient_id: uuid::Uuid,
+    pub topic: Option<String>,
+    pub message_count: i64,
+    /// Messages from others sent after the participant last read the thread
+    pub unread_count: i64,
+    pub last_sent_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// In-app notification in the `emr.notifications` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationModel {
+    pub id: uuid::Uuid,
+    pub recipient_id: uuid::Uuid,
+    pub notification_type: String,
+    pub priority: String,
+    pub subject: Option<String>,
+    pub message: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub read_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Saved version of a clinical note draft
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClinicalNoteVersionModel {
+    pub note_id: uuid::Uuid,
+    pub version: i64,
+    pub title: String,
+    pub sections: Vec<emr_core::domain::NoteSection>,
+    pub saved_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Result acknowledgment latency over a reporting window, for critical or
+/// other abnormal results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcknowledgmentLatencyModel {
+    pub critical: bool,
+    pub total: i64,
+    pub acknowledged: i64,
+    pub pending: i64,
+    /// Critical results acknowledged after the SLA, or still pending past it
+    pub breached_sla: i64,
+    pub p50_minutes: Option<f64>,
+    pub p90_minutes: Option<f64>,
+    pub max_minutes: Option<f64>,
+}
+
+/// What was billed for a claim and what has been posted against it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimReconciliationModel {
+    pub claim_id: String,
+    pub encounter_id: uuid::Uuid,
+    pub payer_id: String,
+    pub service_date: chrono::NaiveDate,
+    pub billed_cents: i64,
+    pub paid_cents: i64,
+    /// Contractual and other adjustments not assigned to the patient
+    pub written_off_cents: i64,
+    pub patient_responsibility_cents: i64,
+    /// Billed amount not yet paid, written off or passed to the patient
+    pub balance_cents: i64,
+    /// `unpaid`, `denied`, `partial` or `paid`
+    pub status: String,
+    pub payment_count: i64,
+    pub open_tasks: i64,
+}
+
+/// Observation row of `emr.observations`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObservationModel {
+    pub id: uuid::Uuid,
+    pub status: String,
+    pub category: Option<String>,
+    pub code: Option<String>,
+    pub display: Option<String>,
+    pub patient_id: Option<uuid::Uuid>,
+    pub encounter_id: Option<uuid::Uuid>,
+    /// Reporting device of readings stored in bulk
+    pub device_id: Option<String>,
+    pub effective_date: chrono::DateTime<chrono::Utc>,
+    pub value: Option<f64>,
+    pub unit: Option<String>,
+}
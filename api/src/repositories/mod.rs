@@ -3,9 +3,55 @@
 //! Database access should be centralized in this layer so handlers and services
 //! remain testable. Current implementations are placeholders.
 
-use crate::error::Result;
-use crate::models::PatientModel;
-use emr_core::types::Id;
+use crate::database::{Connection, Pool};
+use crate::error::{ApiError, Result};
+use crate::models::{
+    AcknowledgmentLatencyModel, ClaimReconciliationModel, ClinicalNoteVersionModel, DeadLetterModel,
+    DocumentReferenceModel, DocumentSearchModel, JobRunStatsModel, MeasurePatientModel, MessageThreadModel,
+    NewPatientModel, NewWebhookEndpointModel, NotificationModel, ObservationModel, PanelMemberModel, PatientModel,
+    PortalAccountModel, PortalDemographicsModel, WebhookDeliveryModel, WebhookEndpointModel,
+};
+use diesel::connection::SimpleConnection;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::{Connection as _, PgConnection, QueryableByName, RunQueryDsl};
+use emr_core::billing::{BillingTask, FollowUpReason};
+use emr_core::domain::{
+    attachment_from_fhir, attachment_to_fhir, AcknowledgmentTask, AdministrationOutcome, Attachment, BarcodeScan,
+    BarcodeVerification, CareTeam, CareTeamParticipant, CareTeamRole, CareTeamStatus, ClinicalNote, CodeKind,
+    CodeOrigin, Communication, CommunicationParty, CommunicationStatus, Confidentiality, Coverage, CoverageStatus,
+    EmergencyAccess, Encounter, EncounterCharges, EncounterClass, EncounterCode, EncounterCoding, EncounterDiagnosis,
+    EncounterLocation, EncounterParticipant, EncounterStatus, ImprovementNotation, MeasurePopulation, MeasureReport,
+    MedicationAdministration, MedicationRequest, MedicationRequestStatus, NoteStatus, NoteType, Observation,
+    ObservationReferenceRange, ObservationStatus, ObservationValue, Organization, OrganizationType, PanelRefresh,
+    Patient, PatientPanel, Period, PlanType, Practitioner, PractitionerQualification, Provenance, ProvenanceActivity,
+    QualityMeasure, Questionnaire, QuestionnaireResponse, QuestionnaireStatus, Referral, ReferralStatus, ReportFormat,
+    ReportRun, ReportRunStatus, ReportSchedule, RequestCategory, RequestPriority, RequestStatus, ResponseStatus,
+    ServiceRequest, SubscriberRelationship, TaskStatus,
+};
+use emr_core::domain::units::UCUM_SYSTEM;
+use emr_core::domain::values::{ContactPoint, ContactSystem, HumanName, Identifier, NameUse};
+use emr_core::flags::FeatureFlag;
+use emr_core::notifications::NotificationPreferences;
+use emr_core::partitions::{ObservationQuery, OBSERVATION_QUERY};
+use emr_core::services::consent::{PurposeOfUse, SensitiveData, HIV_RESULT_CODES};
+use emr_core::services::device_observations::{
+    IngestResult, ValidatedBatch, INSERT_DEVICE_OBSERVATIONS_QUERY, KNOWN_ENCOUNTERS_QUERY, KNOWN_PATIENTS_QUERY,
+};
+use emr_core::services::disclosures::{AccessRecord, DISCLOSURES_QUERY};
+use emr_core::services::malware::{ScanStatus, ScanVerdict};
+use emr_core::services::panels::{member_query, PanelParam, PanelQuery};
+use emr_core::services::patient_summary::{
+    device_events, PatientSummary, SummaryEvent, CREATE_SUMMARY_QUERY, LOCK_SUMMARY_QUERY, SAVE_SUMMARY_QUERY,
+    SUMMARY_QUERY,
+};
+use emr_core::services::reporting::{ReportParam, ReportQuery, ReportTable};
+use emr_core::services::search::{score_document, score_name, SearchEntity, SearchHit};
+use emr_core::services::security_events::{AuthEvent, SecurityEvent};
+use emr_core::signing::{Signature, SignatureKind, Verification};
+use emr_core::types::{EntityMetadata, Id, Timestamp};
+use emr_core::validation::{ProfileRule, ValidationProfile};
+use emr_fhir::{encounter_to_fhir, observation_to_fhir};
+use std::collections::BTreeMap;
 
 /// Patient repository
 pub struct PatientRepository;
@@ -24,11 +70,7968 @@ impl PatientRepository {
         Ok(None)
     }
 
-    /// Persist a new patient.
+    /// Persist a new patient, returning the id the database assigned.
+    pub async fn create(&self, pool: &Pool, patient: NewPatientModel) -> Result<Id> {
+        let conn = pool.get().await?;
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(INSERT_PATIENT_QUERY)
+                    .bind::<diesel::sql_types::Text, _>(&patient.family_name)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&patient.given_names)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&patient.gender)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(patient.birth_date)
+                    .get_result::<InsertedId>(conn)
+            })
+            .await?
+            .map_err(insert_error)?;
+
+        Ok(row.id)
+    }
+
+    /// Insert patients in one transaction, each under its own savepoint.
+    ///
+    /// A failing row is rolled back to its savepoint without aborting the
+    /// others, so the result has one outcome per input row, in order. An
+    /// `Err` for the whole call means the transaction itself failed and
+    /// nothing was inserted.
+    pub async fn insert_batch(
+        &self,
+        pool: &Pool,
+        patients: Vec<NewPatientModel>,
+    ) -> Result<Vec<Result<Id>>> {
+        let conn = pool.get().await?;
+
+        let outcomes = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let outcomes = patients
+                        .iter()
+                        .map(|patient| {
+                            conn.transaction(|conn| {
+                                diesel::sql_query(INSERT_PATIENT_QUERY)
+                                    .bind::<diesel::sql_types::Text, _>(&patient.family_name)
+                                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&patient.given_names)
+                                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&patient.gender)
+                                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(patient.birth_date)
+                                    .get_result::<InsertedId>(conn)
+                            })
+                            .map(|row| row.id)
+                            .map_err(insert_error)
+                        })
+                        .collect::<Vec<_>>();
+                    Ok::<_, DieselError>(outcomes)
+                })
+            })
+            .await??;
+
+        Ok(outcomes)
+    }
+
+    /// Values of a patient's identifiers, such as the MRN.
+    pub async fn identifier_values(&self, pool: &Pool, patient_id: Id) -> Result<Vec<String>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT identifier->>'value' AS value \
+                     FROM emr.patients p \
+                     CROSS JOIN LATERAL jsonb_array_elements(COALESCE(p.identifiers, '[]'::jsonb)) AS identifier \
+                     WHERE p.id = $1 AND identifier->>'value' IS NOT NULL",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .load::<IdentifierValueRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(|row| row.value).collect())
+    }
+
+    /// Value of a patient's identifier in `system`, such as the MRN the PACS
+    /// files studies under.
+    pub async fn identifier_value(&self, pool: &Pool, patient_id: Id, system: &str) -> Result<Option<String>> {
+        let conn = pool.get().await?;
+        let system = system.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT identifier->>'value' AS value \
+                     FROM emr.patients p \
+                     CROSS JOIN LATERAL jsonb_array_elements(COALESCE(p.identifiers, '[]'::jsonb)) AS identifier \
+                     WHERE p.id = $1 AND identifier->>'system' = $2 AND identifier->>'value' IS NOT NULL \
+                     LIMIT 1",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .bind::<diesel::sql_types::Text, _>(system)
+                .load::<IdentifierValueRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().next().map(|row| row.value))
+    }
+
+    /// A patient's contact points, or `None` when the patient does not exist.
+    pub async fn telecom(&self, pool: &Pool, patient_id: Id) -> Result<Option<Vec<ContactPoint>>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(PATIENT_TELECOM_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .load::<TelecomRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(stored_telecom).transpose()
+    }
+
+    /// Record that the patient proved control of the contact point `value`.
+    ///
+    /// Returns `false` when the patient does not exist or has no such contact
+    /// point.
+    pub async fn record_verified_telecom(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        value: &str,
+        at: Timestamp,
+    ) -> Result<bool> {
+        let conn = pool.get().await?;
+        let value = value.to_string();
+
+        let recorded = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let rows = diesel::sql_query(format!("{PATIENT_TELECOM_QUERY} FOR UPDATE"))
+                        .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                        .load::<TelecomRow>(conn)?;
+                    let Some(row) = rows.into_iter().next() else { return Ok(false) };
+
+                    let mut telecom = stored_telecom(row)?;
+                    if !mark_verified(&mut telecom, &value, at) {
+                        return Ok(false);
+                    }
+
+                    diesel::sql_query(
+                        "UPDATE emr.patients SET contact_points = $2::jsonb, updated_at = NOW() WHERE id = $1",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::Text, _>(serde_json::to_string(&telecom)?)
+                    .execute(conn)?;
+                    Ok::<_, ApiError>(true)
+                })
+            })
+            .await??;
+
+        Ok(recorded)
+    }
+
+    /// Add photos to a patient through [`Patient::add_photo`], which skips
+    /// photos already stored with the same hash.
+    ///
+    /// Returns `false` when the patient does not exist.
+    pub async fn add_photos(&self, pool: &Pool, patient_id: Id, photos: Vec<Attachment>) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let added = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let rows = diesel::sql_query(PATIENT_PHOTOS_QUERY)
+                        .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                        .load::<PhotosRow>(conn)?;
+                    let Some(row) = rows.into_iter().next() else { return Ok(false) };
+
+                    let mut patient = Patient::try_from(row)?;
+                    for photo in photos {
+                        patient.add_photo(photo)?;
+                    }
+
+                    let stored = serde_json::Value::Array(patient.photos.iter().map(attachment_to_fhir).collect());
+                    diesel::sql_query("UPDATE emr.patients SET photos = $2::jsonb, updated_at = NOW() WHERE id = $1")
+                        .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                        .bind::<diesel::sql_types::Text, _>(stored.to_string())
+                        .execute(conn)?;
+                    Ok::<_, ApiError>(true)
+                })
+            })
+            .await??;
+
+        Ok(added)
+    }
+}
+
+/// Locks the row, so concurrent uploads do not drop each other's photos
+const PATIENT_PHOTOS_QUERY: &str =
+    "SELECT family_name, given_names, photos::text AS photos FROM emr.patients WHERE id = $1 FOR UPDATE";
+
+#[derive(diesel::QueryableByName)]
+struct PhotosRow {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    family_name: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Array<diesel::sql_types::Text>>)]
+    given_names: Option<Vec<String>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    photos: Option<String>,
+}
+
+/// The patient as far as its photos need: the name the domain requires and
+/// the stored photos, kept as FHIR `Attachment` elements
+impl TryFrom<PhotosRow> for Patient {
+    type Error = ApiError;
+
+    fn try_from(row: PhotosRow) -> Result<Self> {
+        let name = HumanName {
+            given: row.given_names.unwrap_or_default(),
+            family: row.family_name.unwrap_or_default(),
+            prefix: None,
+            suffix: None,
+            use_: None,
+        };
+        let mut patient = Patient::new(vec![name])?;
+        let photos: Vec<serde_json::Value> = json_or_default(row.photos)?;
+        patient.photos = photos
+            .iter()
+            .map(attachment_from_fhir)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(patient)
+    }
+}
+
+const PATIENT_TELECOM_QUERY: &str =
+    "SELECT contact_points::text AS contact_points, phone, email FROM emr.patients WHERE id = $1";
+
+#[derive(diesel::QueryableByName)]
+struct TelecomRow {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    contact_points: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    phone: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    email: Option<String>,
+}
+
+/// Contact points of a patient row; rows loaded outside the API (the CLI
+/// seed) only have the flat `phone` and `email` columns.
+fn stored_telecom(row: TelecomRow) -> Result<Vec<ContactPoint>> {
+    let telecom: Vec<ContactPoint> = json_or_default(row.contact_points)?;
+    if !telecom.is_empty() {
+        return Ok(telecom);
+    }
+
+    let flat = [(ContactSystem::Phone, row.phone), (ContactSystem::Email, row.email)];
+    Ok(flat
+        .into_iter()
+        .filter_map(|(system, value)| value.map(|value| (system, value)))
+        .map(|(system, value)| ContactPoint { system, value, use_: None, rank: None, verified_at: None })
+        .collect())
+}
+
+/// Mark the contact point `value` verified, returning whether there was one
+fn mark_verified(telecom: &mut [ContactPoint], value: &str, at: Timestamp) -> bool {
+    match telecom.iter_mut().find(|telecom| telecom.value == value) {
+        Some(telecom) => {
+            telecom.mark_verified(at);
+            true
+        }
+        None => false,
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct IdentifierValueRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    value: String,
+}
+
+/// Insert statement for one patient row
+pub const INSERT_PATIENT_QUERY: &str =
+    "INSERT INTO emr.patients (family_name, given_names, gender, birth_date) VALUES ($1, $2, $3, $4) RETURNING id";
+
+#[derive(diesel::QueryableByName)]
+struct InsertedId {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+}
+
+fn insert_error(err: DieselError) -> ApiError {
+    match err {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::conflict("Patient already exists")
+        }
+        DieselError::DatabaseError(DatabaseErrorKind::CheckViolation | DatabaseErrorKind::NotNullViolation, info) => {
+            ApiError::validation_error(info.message())
+        }
+        err => ApiError::from(err),
+    }
+} 
+
+const PRACTITIONER_COLUMNS: &str = "id, active, family_name, given_names, qualification, \
+     identifiers::text AS identifiers, contact_points::text AS contact_points, names::text AS names, \
+     qualifications::text AS qualifications, organization_ids, version, \
+     COALESCE(created_at, NOW()) AS created_at, COALESCE(updated_at, NOW()) AS updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct PractitionerRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    family_name: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Array<diesel::sql_types::Text>>)]
+    given_names: Option<Vec<String>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    qualification: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    identifiers: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    contact_points: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    names: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    qualifications: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Uuid>)]
+    organization_ids: Vec<Id>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Parse an optional JSON column, treating NULL as empty
+fn json_or_default<T: serde::de::DeserializeOwned + Default>(column: Option<String>) -> Result<T> {
+    Ok(column.map(|json| serde_json::from_str(&json)).transpose()?.unwrap_or_default())
+}
+
+impl TryFrom<PractitionerRow> for Practitioner {
+    type Error = ApiError;
+
+    fn try_from(row: PractitionerRow) -> Result<Self> {
+        // Rows loaded outside the API (the CLI seed) only have the flat columns
+        let mut names: Vec<HumanName> = json_or_default(row.names)?;
+        if names.is_empty() {
+            names.push(HumanName {
+                given: row.given_names.unwrap_or_default(),
+                family: row.family_name.unwrap_or_default(),
+                prefix: None,
+                suffix: None,
+                use_: Some(NameUse::Official),
+            });
+        }
+        let mut qualifications: Vec<PractitionerQualification> = json_or_default(row.qualifications)?;
+        if qualifications.is_empty() {
+            qualifications.extend(row.qualification.map(|code| PractitionerQualification {
+                identifiers: Vec::new(),
+                code,
+                period: None,
+                issuer: None,
+            }));
+        }
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            identifiers: json_or_default(row.identifiers)?,
+            names,
+            telecom: json_or_default(row.contact_points)?,
+            addresses: Vec::new(),
+            gender: None,
+            qualifications,
+            communications: Vec::new(),
+            organizations: row.organization_ids,
+            active: row.active,
+        })
+    }
+}
+
+/// Practitioners in `emr.practitioners`
+///
+/// The first name and qualification are also written to the flat columns the
+/// directory search and claim export read. Addresses, gender and languages
+/// are not stored.
+pub struct PractitionerRepository;
+
+impl PractitionerRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A page of practitioners sorted by family name, and how many there are in all.
+    pub async fn list(&self, pool: &Pool, limit: u32, offset: u32) -> Result<(Vec<Practitioner>, u64)> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.practitioners ORDER BY family_name, id LIMIT $1 OFFSET $2",
+            PRACTITIONER_COLUMNS
+        );
+
+        let (rows, total) = conn
+            .interact(move |conn| {
+                let rows = diesel::sql_query(query)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<PractitionerRow>(conn)?;
+                let total = diesel::sql_query("SELECT COUNT(*) AS count FROM emr.practitioners")
+                    .get_result::<CountRow>(conn)?;
+                Ok::<_, DieselError>((rows, total.count))
+            })
+            .await??;
+
+        let practitioners = rows.into_iter().map(Practitioner::try_from).collect::<Result<_>>()?;
+        Ok((practitioners, total as u64))
+    }
+
+    /// A practitioner by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<Practitioner>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.practitioners WHERE id = $1", PRACTITIONER_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<PractitionerRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(Practitioner::try_from).transpose()
+    }
+
+    /// Store a new practitioner.
+    pub async fn insert(&self, pool: &Pool, practitioner: &Practitioner) -> Result<()> {
+        let conn = pool.get().await?;
+        let columns = PractitionerColumns::new(practitioner)?;
+        let practitioner = practitioner.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.practitioners \
+                 (id, active, family_name, given_names, qualification, identifiers, contact_points, names, \
+                 qualifications, organization_ids, version, created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7::jsonb, $8::jsonb, $9::jsonb, $10, $11, $12, $13)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(practitioner.metadata.id)
+            .bind::<diesel::sql_types::Bool, _>(practitioner.active)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(columns.family_name)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(columns.given_names)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(columns.qualification)
+            .bind::<diesel::sql_types::Text, _>(columns.identifiers)
+            .bind::<diesel::sql_types::Text, _>(columns.contact_points)
+            .bind::<diesel::sql_types::Text, _>(columns.names)
+            .bind::<diesel::sql_types::Text, _>(columns.qualifications)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&practitioner.organizations)
+            .bind::<diesel::sql_types::BigInt, _>(practitioner.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(practitioner.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(practitioner.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// Write a changed practitioner, provided nobody else changed it since it was read at `previous_version`.
+    ///
+    /// Returns `false` when the stored practitioner has moved past `previous_version`.
+    pub async fn update(&self, pool: &Pool, practitioner: &Practitioner, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let columns = PractitionerColumns::new(practitioner)?;
+        let practitioner = practitioner.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.practitioners \
+                     SET active = $3, family_name = $4, given_names = $5, qualification = $6, identifiers = $7::jsonb, \
+                         contact_points = $8::jsonb, names = $9::jsonb, qualifications = $10::jsonb, \
+                         organization_ids = $11, version = $12, updated_at = $13 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(practitioner.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Bool, _>(practitioner.active)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(columns.family_name)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(columns.given_names)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(columns.qualification)
+                .bind::<diesel::sql_types::Text, _>(columns.identifiers)
+                .bind::<diesel::sql_types::Text, _>(columns.contact_points)
+                .bind::<diesel::sql_types::Text, _>(columns.names)
+                .bind::<diesel::sql_types::Text, _>(columns.qualifications)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&practitioner.organizations)
+                .bind::<diesel::sql_types::BigInt, _>(practitioner.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(practitioner.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+}
+
+/// A practitioner's values as `emr.practitioners` stores them
+struct PractitionerColumns {
+    family_name: Option<String>,
+    given_names: Vec<String>,
+    qualification: Option<String>,
+    identifiers: String,
+    contact_points: String,
+    names: String,
+    qualifications: String,
+}
+
+impl PractitionerColumns {
+    fn new(practitioner: &Practitioner) -> Result<Self> {
+        let name = practitioner.primary_name();
+        Ok(Self {
+            family_name: name.map(|name| name.family.clone()),
+            given_names: name.map(|name| name.given.clone()).unwrap_or_default(),
+            qualification: practitioner.qualifications.first().map(|qualification| qualification.code.clone()),
+            identifiers: serde_json::to_string(&practitioner.identifiers)?,
+            contact_points: serde_json::to_string(&practitioner.telecom)?,
+            names: serde_json::to_string(&practitioner.names)?,
+            qualifications: serde_json::to_string(&practitioner.qualifications)?,
+        })
+    }
+}
+
+const ORGANIZATION_COLUMNS: &str = "id, active, name, type AS organization_type, part_of, \
+     identifiers::text AS identifiers, contact_points::text AS contact_points, aliases, version, \
+     COALESCE(created_at, NOW()) AS created_at, COALESCE(updated_at, NOW()) AS updated_at";
+
+/// Recursive query returning an organization and all of its descendants
+pub const ORGANIZATION_HIERARCHY_QUERY: &str = r#"
+WITH RECURSIVE hierarchy AS (
+    SELECT o.*, 0 AS depth
+    FROM emr.organizations o
+    WHERE o.id = $1
+    UNION ALL
+    SELECT child.*, hierarchy.depth + 1
+    FROM emr.organizations child
+    JOIN hierarchy ON child.part_of = hierarchy.id
+    WHERE hierarchy.depth < 32
+)
+SELECT id, active, name, type AS organization_type, part_of, identifiers::text AS identifiers,
+       contact_points::text AS contact_points, aliases, version, COALESCE(created_at, NOW()) AS created_at,
+       COALESCE(updated_at, NOW()) AS updated_at
+FROM hierarchy ORDER BY depth, name
+"#;
+
+/// Recursive query returning an organization's id and those of its ancestors, nearest first
+pub const ORGANIZATION_ANCESTORS_QUERY: &str = r#"
+WITH RECURSIVE ancestors AS (
+    SELECT o.id, o.part_of, 0 AS depth
+    FROM emr.organizations o
+    WHERE o.id = $1
+    UNION ALL
+    SELECT parent.id, parent.part_of, ancestors.depth + 1
+    FROM emr.organizations parent
+    JOIN ancestors ON parent.id = ancestors.part_of
+    WHERE ancestors.depth < 32
+)
+SELECT id FROM ancestors ORDER BY depth
+"#;
+
+#[derive(diesel::QueryableByName)]
+struct OrganizationRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    organization_type: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    part_of: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    identifiers: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    contact_points: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+    aliases: Vec<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<OrganizationRow> for Organization {
+    type Error = ApiError;
+
+    fn try_from(row: OrganizationRow) -> Result<Self> {
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            identifiers: json_or_default(row.identifiers)?,
+            name: row.name,
+            aliases: row.aliases,
+            telecom: json_or_default(row.contact_points)?,
+            addresses: Vec::new(),
+            // Types loaded outside the API may not be FHIR codes
+            type_: row.organization_type.as_deref().and_then(OrganizationType::from_code),
+            part_of: row.part_of,
+            contacts: Vec::new(),
+            endpoints: Vec::new(),
+            active: row.active,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct OrganizationIdRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+}
+
+fn organization_write_error(err: DieselError) -> ApiError {
+    match err {
+        DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+            ApiError::conflict("The organization is still referenced by other records")
+        }
+        err => ApiError::from(err),
+    }
+}
+
+/// Organizations in `emr.organizations`
+///
+/// Addresses, contacts and endpoints are not stored.
+pub struct OrganizationRepository;
+
+impl OrganizationRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A page of organizations sorted by name, and how many there are in all.
+    pub async fn list(&self, pool: &Pool, limit: u32, offset: u32) -> Result<(Vec<Organization>, u64)> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.organizations ORDER BY name, id LIMIT $1 OFFSET $2",
+            ORGANIZATION_COLUMNS
+        );
+
+        let (rows, total) = conn
+            .interact(move |conn| {
+                let rows = diesel::sql_query(query)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<OrganizationRow>(conn)?;
+                let total = diesel::sql_query("SELECT COUNT(*) AS count FROM emr.organizations")
+                    .get_result::<CountRow>(conn)?;
+                Ok::<_, DieselError>((rows, total.count))
+            })
+            .await??;
+
+        let organizations = rows.into_iter().map(Organization::try_from).collect::<Result<_>>()?;
+        Ok((organizations, total as u64))
+    }
+
+    /// An organization by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<Organization>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.organizations WHERE id = $1", ORGANIZATION_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<OrganizationRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(Organization::try_from).transpose()
+    }
+
+    /// An organization and all of its descendants, level by level; empty when it does not exist.
+    pub async fn hierarchy(&self, pool: &Pool, id: Id) -> Result<Vec<Organization>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(ORGANIZATION_HIERARCHY_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<OrganizationRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Organization::try_from).collect()
+    }
+
+    /// Ids of an organization and its ancestors, nearest first; empty when it does not exist.
+    pub async fn ancestors(&self, pool: &Pool, id: Id) -> Result<Vec<Id>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(ORGANIZATION_ANCESTORS_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<OrganizationIdRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(|row| row.id).collect())
+    }
+
+    /// Whether any organization is part of this one.
+    pub async fn has_children(&self, pool: &Pool, id: Id) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query("SELECT id FROM emr.organizations WHERE part_of = $1 LIMIT 1")
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<OrganizationIdRow>(conn)
+            })
+            .await??;
+
+        Ok(!rows.is_empty())
+    }
+
+    /// Store a new organization.
+    pub async fn insert(&self, pool: &Pool, organization: &Organization) -> Result<()> {
+        let conn = pool.get().await?;
+        let identifiers = serde_json::to_string(&organization.identifiers)?;
+        let telecom = serde_json::to_string(&organization.telecom)?;
+        let organization = organization.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.organizations \
+                 (id, active, name, type, part_of, identifiers, contact_points, aliases, version, created_at, \
+                 updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7::jsonb, $8, $9, $10, $11)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(organization.metadata.id)
+            .bind::<diesel::sql_types::Bool, _>(organization.active)
+            .bind::<diesel::sql_types::Text, _>(&organization.name)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(
+                organization.type_.as_ref().map(OrganizationType::code),
+            )
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(organization.part_of)
+            .bind::<diesel::sql_types::Text, _>(&identifiers)
+            .bind::<diesel::sql_types::Text, _>(&telecom)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&organization.aliases)
+            .bind::<diesel::sql_types::BigInt, _>(organization.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(organization.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(organization.metadata.updated_at)
+            .execute(conn)
+        })
+        .await?
+        .map_err(organization_write_error)?;
+
+        Ok(())
+    }
+
+    /// Write a changed organization, provided nobody else changed it since it was read at `previous_version`.
+    ///
+    /// Returns `false` when the stored organization has moved past `previous_version`.
+    pub async fn update(&self, pool: &Pool, organization: &Organization, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let identifiers = serde_json::to_string(&organization.identifiers)?;
+        let telecom = serde_json::to_string(&organization.telecom)?;
+        let organization = organization.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.organizations \
+                     SET active = $3, name = $4, type = $5, part_of = $6, identifiers = $7::jsonb, \
+                         contact_points = $8::jsonb, aliases = $9, version = $10, updated_at = $11 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(organization.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Bool, _>(organization.active)
+                .bind::<diesel::sql_types::Text, _>(&organization.name)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(
+                    organization.type_.as_ref().map(OrganizationType::code),
+                )
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(organization.part_of)
+                .bind::<diesel::sql_types::Text, _>(&identifiers)
+                .bind::<diesel::sql_types::Text, _>(&telecom)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&organization.aliases)
+                .bind::<diesel::sql_types::BigInt, _>(organization.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(organization.metadata.updated_at)
+                .execute(conn)
+            })
+            .await?
+            .map_err(organization_write_error)?;
+
+        Ok(updated > 0)
+    }
+
+    /// Delete an organization, returning whether it existed.
+    ///
+    /// Organizations that patients, encounters or other organizations still
+    /// refer to are kept and reported as a conflict.
+    pub async fn delete(&self, pool: &Pool, id: Id) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let deleted = conn
+            .interact(move |conn| {
+                diesel::sql_query("DELETE FROM emr.organizations WHERE id = $1")
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .execute(conn)
+            })
+            .await?
+            .map_err(organization_write_error)?;
+
+        Ok(deleted > 0)
+    }
+}
+const ENCOUNTER_COLUMNS: &str =
+    "id, status, class, type AS encounter_type, patient_id, organization_id, start_date, end_date, \
+     details::text AS details, version, COALESCE(created_at, NOW()) AS created_at, \
+     COALESCE(updated_at, NOW()) AS updated_at";
+
+/// Filters shared by the encounter list and its count; encounters anonymized
+/// under a retention policy no longer belong to a patient and are left out
+const ENCOUNTER_FILTER: &str =
+    "patient_id IS NOT NULL AND ($1::text IS NULL OR status = $1) AND ($2::uuid IS NULL OR patient_id = $2)";
+
+#[derive(diesel::QueryableByName)]
+struct EncounterRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    class: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    encounter_type: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    patient_id: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    organization_id: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    start_date: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    end_date: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    details: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The parts of an encounter without a column of their own, stored in `details`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct EncounterDetails {
+    identifiers: Vec<Identifier>,
+    /// Kept so outpatient visits do not read back as ambulatory ones
+    class: Option<EncounterClass>,
+    priority: Option<String>,
+    participants: Vec<EncounterParticipant>,
+    appointment: Vec<Id>,
+    length: Option<u32>,
+    reason: Vec<String>,
+    diagnosis: Vec<EncounterDiagnosis>,
+    location: Vec<EncounterLocation>,
+}
+
+impl TryFrom<EncounterRow> for Encounter {
+    type Error = ApiError;
+
+    fn try_from(row: EncounterRow) -> Result<Self> {
+        let details: EncounterDetails = json_or_default(row.details)?;
+        let status = EncounterStatus::from_code(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown encounter status '{}'", row.status)))?;
+        let class = match details.class {
+            Some(class) => class,
+            None => {
+                let code = row.class.unwrap_or_default();
+                EncounterClass::from_code(&code)
+                    .ok_or_else(|| ApiError::internal_error(&format!("Unknown encounter class '{}'", code)))?
+            }
+        };
+        let patient_id = row
+            .patient_id
+            .ok_or_else(|| ApiError::internal_error(&format!("Encounter {} has no patient", row.id)))?;
+        let period = match (row.start_date, row.end_date) {
+            (None, None) => None,
+            (start, end) => Some(Period { start, end }),
+        };
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            identifiers: details.identifiers,
+            status,
+            class,
+            type_: row.encounter_type,
+            priority: details.priority,
+            subject: patient_id,
+            participants: details.participants,
+            appointment: details.appointment,
+            period,
+            length: details.length,
+            reason: details.reason,
+            diagnosis: details.diagnosis,
+            location: details.location,
+            service_provider: row.organization_id,
+        })
+    }
+}
+
+/// Column values of an encounter being written
+struct EncounterColumns {
+    encounter: Encounter,
+    details: String,
+    fhir_data: String,
+    /// First participant, who the billing and reporting jobs attribute the encounter to
+    practitioner_id: Option<Id>,
+    reason_description: Option<String>,
+}
+
+impl EncounterColumns {
+    fn new(encounter: &Encounter) -> Result<Self> {
+        let details = EncounterDetails {
+            identifiers: encounter.identifiers.clone(),
+            class: Some(encounter.class.clone()),
+            priority: encounter.priority.clone(),
+            participants: encounter.participants.clone(),
+            appointment: encounter.appointment.clone(),
+            length: encounter.length,
+            reason: encounter.reason.clone(),
+            diagnosis: encounter.diagnosis.clone(),
+            location: encounter.location.clone(),
+        };
+        Ok(Self {
+            encounter: encounter.clone(),
+            details: serde_json::to_string(&details)?,
+            fhir_data: encounter_to_fhir(encounter).to_string(),
+            practitioner_id: encounter.participants.iter().find_map(|participant| participant.individual),
+            reason_description: (!encounter.reason.is_empty()).then(|| encounter.reason.join("; ")),
+        })
+    }
+
+    fn start(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.encounter.period.as_ref().and_then(|period| period.start)
+    }
+
+    fn end(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.encounter.period.as_ref().and_then(|period| period.end)
+    }
+
+    /// The patient summary change the write brings
+    fn summary_events(&self) -> BTreeMap<Id, Vec<SummaryEvent>> {
+        BTreeMap::from([(self.encounter.subject, vec![SummaryEvent::from_encounter(&self.encounter)])])
+    }
+}
+
+fn encounter_write_error(err: DieselError) -> ApiError {
+    match err {
+        DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => ApiError::validation_error(
+            "The encounter refers to a patient, practitioner or organization that does not exist",
+        ),
+        err => ApiError::from(err),
+    }
+}
+
+/// Encounters in `emr.encounters`
+///
+/// Writes bring the patient's summary up to date in the same transaction.
+pub struct EncounterRepository;
+
+impl EncounterRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A page of encounters, most recently updated first, and how many match
+    /// in all.
+    pub async fn list(
+        &self,
+        pool: &Pool,
+        status: Option<&EncounterStatus>,
+        patient_id: Option<Id>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Encounter>, u64)> {
+        let conn = pool.get().await?;
+        let status = status.map(EncounterStatus::code);
+        let query = format!(
+            "SELECT {} FROM emr.encounters WHERE {} ORDER BY updated_at DESC, id LIMIT $3 OFFSET $4",
+            ENCOUNTER_COLUMNS, ENCOUNTER_FILTER
+        );
+
+        let (rows, total) = conn
+            .interact(move |conn| {
+                let rows = diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(status)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(patient_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<EncounterRow>(conn)?;
+                let total = diesel::sql_query(format!(
+                    "SELECT COUNT(*) AS count FROM emr.encounters WHERE {}",
+                    ENCOUNTER_FILTER
+                ))
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(status)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(patient_id)
+                .get_result::<CountRow>(conn)?;
+                Ok::<_, DieselError>((rows, total.count))
+            })
+            .await??;
+
+        let encounters = rows.into_iter().map(Encounter::try_from).collect::<Result<_>>()?;
+        Ok((encounters, total as u64))
+    }
+
+    /// An encounter by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<Encounter>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.encounters WHERE id = $1 AND patient_id IS NOT NULL",
+            ENCOUNTER_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<EncounterRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(Encounter::try_from).transpose()
+    }
+
+    /// Store a new encounter.
+    pub async fn insert(&self, pool: &Pool, encounter: &Encounter) -> Result<()> {
+        let conn = pool.get().await?;
+        let columns = EncounterColumns::new(encounter)?;
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                let encounter = &columns.encounter;
+                diesel::sql_query(
+                    "INSERT INTO emr.encounters \
+                     (id, status, class, type, patient_id, organization_id, practitioner_id, start_date, end_date, \
+                     reason_description, fhir_data, details, version, created_at, updated_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11::jsonb, $12::jsonb, $13, $14, $15)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(encounter.metadata.id)
+                .bind::<diesel::sql_types::Text, _>(encounter.status.code())
+                .bind::<diesel::sql_types::Text, _>(encounter.class.code())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&encounter.type_)
+                .bind::<diesel::sql_types::Uuid, _>(encounter.subject)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(encounter.service_provider)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(columns.practitioner_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(columns.start())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(columns.end())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&columns.reason_description)
+                .bind::<diesel::sql_types::Text, _>(&columns.fhir_data)
+                .bind::<diesel::sql_types::Text, _>(&columns.details)
+                .bind::<diesel::sql_types::BigInt, _>(encounter.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(encounter.metadata.created_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(encounter.metadata.updated_at)
+                .execute(conn)?;
+                apply_summary_events(conn, columns.summary_events())
+            })
+        })
+        .await?
+        .map_err(encounter_write_error)?;
+
+        Ok(())
+    }
+
+    /// Write a changed encounter, provided nobody else changed it since it was read at `previous_version`.
     ///
-    /// Current status: prototype stub, returns a not-implemented API error.
-    pub async fn create(&self, _patient: &PatientModel) -> Result<PatientModel> {
-        // TODO(nexus-phase1): Implement SQLx-backed insert.
-        Err(crate::error::ApiError::internal_error("Not implemented"))
+    /// Returns `false` when the stored encounter has moved past `previous_version`.
+    pub async fn update(&self, pool: &Pool, encounter: &Encounter, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let columns = EncounterColumns::new(encounter)?;
+
+        let updated = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let encounter = &columns.encounter;
+                    let updated = diesel::sql_query(
+                        "UPDATE emr.encounters \
+                         SET status = $3, class = $4, type = $5, organization_id = $6, practitioner_id = $7, \
+                             start_date = $8, end_date = $9, reason_description = $10, fhir_data = $11::jsonb, \
+                             details = $12::jsonb, version = $13, updated_at = $14 \
+                         WHERE id = $1 AND version = $2",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(encounter.metadata.id)
+                    .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                    .bind::<diesel::sql_types::Text, _>(encounter.status.code())
+                    .bind::<diesel::sql_types::Text, _>(encounter.class.code())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&encounter.type_)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(encounter.service_provider)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(columns.practitioner_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(columns.start())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(columns.end())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&columns.reason_description)
+                    .bind::<diesel::sql_types::Text, _>(&columns.fhir_data)
+                    .bind::<diesel::sql_types::Text, _>(&columns.details)
+                    .bind::<diesel::sql_types::BigInt, _>(encounter.metadata.version as i64)
+                    .bind::<diesel::sql_types::Timestamptz, _>(encounter.metadata.updated_at)
+                    .execute(conn)?;
+                    if updated > 0 {
+                        apply_summary_events(conn, columns.summary_events())?;
+                    }
+                    Ok::<_, DieselError>(updated)
+                })
+            })
+            .await?
+            .map_err(encounter_write_error)?;
+
+        Ok(updated > 0)
+    }
+}
+
+/// Columns of `emr.patients` that can be selected for export
+pub const PATIENT_EXPORT_FIELDS: &[&str] = &[
+    "id",
+    "fhir_id",
+    "active",
+    "family_name",
+    "given_names",
+    "birth_date",
+    "gender",
+    "phone",
+    "email",
+    "address_line1",
+    "address_line2",
+    "city",
+    "state",
+    "postal_code",
+    "country",
+    "identifiers",
+    "contact_points",
+    "created_at",
+    "updated_at",
+];
+
+/// Name of the server-side cursor used by patient exports
+const PATIENT_EXPORT_CURSOR: &str = "patient_export";
+
+/// Resolve a comma-separated `fields` projection against
+/// [`PATIENT_EXPORT_FIELDS`]; `None` selects every field.
+pub fn parse_export_fields(fields: Option<&str>) -> Result<Vec<&'static str>> {
+    let Some(fields) = fields else {
+        return Ok(PATIENT_EXPORT_FIELDS.to_vec());
+    };
+
+    let mut selected = Vec::new();
+    for field in fields.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+        let known = PATIENT_EXPORT_FIELDS
+            .iter()
+            .find(|known| **known == field)
+            .ok_or_else(|| ApiError::validation_error(&format!("Unknown export field: {}", field)))?;
+        if !selected.contains(known) {
+            selected.push(*known);
+        }
+    }
+
+    if selected.is_empty() {
+        return Err(ApiError::validation_error("fields must name at least one field"));
+    }
+    Ok(selected)
+}
+
+/// Cursor declaration selecting each patient as one JSON object.
+///
+/// Field names must come from [`parse_export_fields`]; they are interpolated
+/// into the statement.
+pub fn patient_export_cursor_query(fields: &[&str]) -> String {
+    let columns = fields
+        .iter()
+        .map(|field| format!("'{0}', p.{0}", field))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "DECLARE {} NO SCROLL CURSOR FOR \
+         SELECT jsonb_build_object({})::text AS row FROM emr.patients p ORDER BY p.created_at, p.id",
+        PATIENT_EXPORT_CURSOR, columns
+    )
+}
+
+#[derive(diesel::QueryableByName)]
+struct ExportRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    row: String,
+}
+
+/// Server-side cursor over `emr.patients` for streaming exports.
+///
+/// The cursor holds a pooled connection inside a read-only transaction until
+/// the last batch is fetched. If it is dropped early (e.g. the client
+/// disconnected) the connection is detached from the pool instead of being
+/// returned mid-transaction. Exports run with the batch statement timeout.
+pub struct PatientExportCursor {
+    conn: Option<Connection>,
+    batch_size: usize,
+    finished: bool,
+}
+
+impl PatientExportCursor {
+    /// Open a transaction and declare the export cursor.
+    pub async fn open(pool: &Pool, fields: &[&'static str], batch_size: usize) -> Result<Self> {
+        let conn = pool.batch().get().await?;
+        let declare = patient_export_cursor_query(fields);
+
+        conn.interact(move |conn| {
+            conn.batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")?;
+            conn.batch_execute(&declare)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Some(conn),
+            batch_size: batch_size.max(1),
+            finished: false,
+        })
+    }
+
+    /// Fetch the next batch of rows as JSON text; empty once exhausted.
+    pub async fn next_batch(&mut self) -> Result<Vec<String>> {
+        let Some(conn) = self.conn.as_ref().filter(|_| !self.finished) else {
+            return Ok(Vec::new());
+        };
+
+        let fetch = format!("FETCH FORWARD {} FROM {}", self.batch_size, PATIENT_EXPORT_CURSOR);
+        let rows: Vec<ExportRow> = conn
+            .interact(move |conn| diesel::sql_query(fetch).load::<ExportRow>(conn))
+            .await??;
+
+        if rows.len() < self.batch_size {
+            conn.interact(|conn| conn.batch_execute("COMMIT")).await??;
+            self.finished = true;
+        }
+
+        Ok(rows.into_iter().map(|row| row.row).collect())
+    }
+}
+
+impl Drop for PatientExportCursor {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Some(conn) = self.conn.take() {
+                // Closing the connection rolls back the open transaction
+                conn.detach();
+            }
+        }
+    }
+}
+
+/// Columns selected for a dead letter, with the payload as text
+const DEAD_LETTER_COLUMNS: &str =
+    "job_id, job_type, queue, payload::text AS payload, attempts, last_error, failed_at, replay_count, replayed_at";
+
+/// Dead letters not replayed since they last failed, newest first.
+///
+/// `$1` optionally filters by job type.
+pub fn dead_letter_list_query() -> String {
+    format!(
+        "SELECT {} FROM jobs.dead_letters \
+         WHERE (replayed_at IS NULL OR failed_at > replayed_at) AND ($1::text IS NULL OR job_type = $1) \
+         ORDER BY failed_at DESC LIMIT $2 OFFSET $3",
+        DEAD_LETTER_COLUMNS
+    )
+}
+
+/// Record a replay of a dead letter
+pub const MARK_DEAD_LETTER_REPLAYED_QUERY: &str =
+    "UPDATE jobs.dead_letters SET replay_count = replay_count + 1, replayed_at = NOW() WHERE job_id = $1";
+
+#[derive(diesel::QueryableByName)]
+struct DeadLetterRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    job_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    job_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    queue: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    payload: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    attempts: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    last_error: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    failed_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    replay_count: i32,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    replayed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl TryFrom<DeadLetterRow> for DeadLetterModel {
+    type Error = ApiError;
+
+    fn try_from(row: DeadLetterRow) -> Result<Self> {
+        Ok(Self {
+            job_id: row.job_id,
+            job_type: row.job_type,
+            queue: row.queue,
+            payload: serde_json::from_str(&row.payload)?,
+            attempts: row.attempts,
+            last_error: row.last_error,
+            failed_at: row.failed_at,
+            replay_count: row.replay_count,
+            replayed_at: row.replayed_at,
+        })
+    }
+}
+
+/// Dead-lettered jobs written by the jobs worker
+pub struct DeadLetterRepository;
+
+impl DeadLetterRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Dead letters awaiting attention, optionally of one job type.
+    pub async fn list_open(
+        &self,
+        pool: &Pool,
+        job_type: Option<String>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DeadLetterModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(dead_letter_list_query())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(job_type)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<DeadLetterRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(DeadLetterModel::try_from).collect()
+    }
+
+    /// Find a dead letter by job ID, replayed or not.
+    pub async fn find_by_id(&self, pool: &Pool, job_id: uuid::Uuid) -> Result<Option<DeadLetterModel>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM jobs.dead_letters WHERE job_id = $1", DEAD_LETTER_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(job_id)
+                    .load::<DeadLetterRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(DeadLetterModel::try_from).transpose()
+    }
+
+    /// Mark a dead letter as replayed; it leaves the open list until the
+    /// job fails again.
+    pub async fn mark_replayed(&self, pool: &Pool, job_id: uuid::Uuid) -> Result<()> {
+        let conn = pool.get().await?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query(MARK_DEAD_LETTER_REPLAYED_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(job_id)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+/// Job runs finished in the last `$1` seconds, per job type plus an overall
+/// row with a NULL `job_type`
+pub const JOB_RUN_STATS_QUERY: &str = r#"
+SELECT job_type,
+       COUNT(*) AS total,
+       COUNT(*) FILTER (WHERE status = 'succeeded') AS succeeded,
+       COUNT(*) FILTER (WHERE status = 'failed') AS failed,
+       COUNT(*) FILTER (WHERE status = 'cancelled') AS cancelled,
+       COALESCE(SUM(attempts - 1), 0)::bigint AS retries,
+       percentile_cont(0.5) WITHIN GROUP (ORDER BY duration_ms) AS p50_ms,
+       percentile_cont(0.95) WITHIN GROUP (ORDER BY duration_ms) AS p95_ms,
+       percentile_cont(0.99) WITHIN GROUP (ORDER BY duration_ms) AS p99_ms
+FROM jobs.job_runs
+WHERE finished_at >= NOW() - make_interval(secs => $1)
+GROUP BY GROUPING SETS ((job_type), ())
+ORDER BY job_type NULLS FIRST
+"#;
+
+#[derive(diesel::QueryableByName)]
+struct JobRunStatsRow {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    job_type: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    succeeded: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    failed: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    cancelled: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    retries: i64,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p50_ms: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p95_ms: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p99_ms: Option<f64>,
+}
+
+impl From<JobRunStatsRow> for JobRunStatsModel {
+    fn from(row: JobRunStatsRow) -> Self {
+        Self {
+            job_type: row.job_type,
+            total: row.total,
+            succeeded: row.succeeded,
+            failed: row.failed,
+            cancelled: row.cancelled,
+            retries: row.retries,
+            p50_ms: row.p50_ms,
+            p95_ms: row.p95_ms,
+            p99_ms: row.p99_ms,
+        }
+    }
+}
+
+/// Job run history recorded by the jobs worker
+pub struct JobRunRepository;
+
+impl JobRunRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Statistics for runs finished within the last `window_secs`; the
+    /// overall row comes first and is present even when nothing ran.
+    pub async fn window_stats(&self, pool: &Pool, window_secs: i64) -> Result<Vec<JobRunStatsModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(JOB_RUN_STATS_QUERY)
+                    .bind::<diesel::sql_types::Double, _>(window_secs as f64)
+                    .load::<JobRunStatsRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(JobRunStatsModel::from).collect())
+    }
+}
+
+/// Columns returned for a webhook endpoint; the secret is never selected
+const WEBHOOK_ENDPOINT_COLUMNS: &str = "id, tenant_id, url, events, active, created_at";
+
+/// Columns selected for a webhook delivery, with JSON as text
+const WEBHOOK_DELIVERY_COLUMNS: &str = "delivery_id, endpoint_id, event_id, event_type, event::text AS event, status, \
+     attempts, last_status_code, last_error, attempt_log::text AS attempt_log, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct WebhookEndpointRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    tenant_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    url: String,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+    events: Vec<String>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<WebhookEndpointRow> for WebhookEndpointModel {
+    fn from(row: WebhookEndpointRow) -> Self {
+        Self {
+            id: row.id,
+            tenant_id: row.tenant_id,
+            url: row.url,
+            events: row.events,
+            active: row.active,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct WebhookDeliveryRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    delivery_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    endpoint_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    event_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    event_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    event: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    attempts: i32,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Integer>)]
+    last_status_code: Option<i32>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    last_error: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    attempt_log: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<WebhookDeliveryRow> for WebhookDeliveryModel {
+    type Error = ApiError;
+
+    fn try_from(row: WebhookDeliveryRow) -> Result<Self> {
+        Ok(Self {
+            delivery_id: row.delivery_id,
+            endpoint_id: row.endpoint_id,
+            event_id: row.event_id,
+            event_type: row.event_type,
+            event: serde_json::from_str(&row.event)?,
+            status: row.status,
+            attempts: row.attempts,
+            last_status_code: row.last_status_code,
+            last_error: row.last_error,
+            attempt_log: serde_json::from_str(&row.attempt_log)?,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// Tenant webhook endpoints and their delivery log
+pub struct WebhookRepository;
+
+impl WebhookRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Register an endpoint.
+    pub async fn create_endpoint(&self, pool: &Pool, endpoint: NewWebhookEndpointModel) -> Result<WebhookEndpointModel> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "INSERT INTO emr.webhook_endpoints (tenant_id, url, secret, events) VALUES ($1, $2, $3, $4) RETURNING {}",
+            WEBHOOK_ENDPOINT_COLUMNS
+        );
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(endpoint.tenant_id)
+                    .bind::<diesel::sql_types::Text, _>(&endpoint.url)
+                    .bind::<diesel::sql_types::Text, _>(&endpoint.secret)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&endpoint.events)
+                    .get_result::<WebhookEndpointRow>(conn)
+            })
+            .await??;
+
+        Ok(row.into())
+    }
+
+    /// Endpoints, optionally of one tenant, oldest first.
+    pub async fn list_endpoints(&self, pool: &Pool, tenant_id: Option<uuid::Uuid>) -> Result<Vec<WebhookEndpointModel>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.webhook_endpoints WHERE ($1::uuid IS NULL OR tenant_id = $1) ORDER BY created_at",
+            WEBHOOK_ENDPOINT_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(tenant_id)
+                    .load::<WebhookEndpointRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(WebhookEndpointModel::from).collect())
+    }
+
+    /// Remove an endpoint; false if it did not exist. Its delivery log is kept.
+    pub async fn delete_endpoint(&self, pool: &Pool, id: uuid::Uuid) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let deleted = conn
+            .interact(move |conn| {
+                diesel::sql_query("DELETE FROM emr.webhook_endpoints WHERE id = $1")
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .execute(conn)
+            })
+            .await??;
+
+        Ok(deleted > 0)
+    }
+
+    /// Deliveries to an endpoint, newest first.
+    pub async fn list_deliveries(
+        &self,
+        pool: &Pool,
+        endpoint_id: uuid::Uuid,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<WebhookDeliveryModel>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM jobs.webhook_deliveries WHERE endpoint_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+            WEBHOOK_DELIVERY_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(endpoint_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<WebhookDeliveryRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(WebhookDeliveryModel::try_from).collect()
+    }
+
+    /// Find a delivery by ID.
+    pub async fn find_delivery(&self, pool: &Pool, delivery_id: uuid::Uuid) -> Result<Option<WebhookDeliveryModel>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM jobs.webhook_deliveries WHERE delivery_id = $1", WEBHOOK_DELIVERY_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(delivery_id)
+                    .load::<WebhookDeliveryRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(WebhookDeliveryModel::try_from).transpose()
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ProvenanceRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    target_type: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    target_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    activity: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    source_system: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    job_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    original_identifiers: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    transformation_version: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    source_reference: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    recorded_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ProvenanceRow> for Provenance {
+    type Error = ApiError;
+
+    fn try_from(row: ProvenanceRow) -> Result<Self> {
+        let activity = ProvenanceActivity::parse(&row.activity)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown provenance activity '{}'", row.activity)))?;
+
+        Ok(Self {
+            id: row.id,
+            target_type: row.target_type,
+            target_id: row.target_id,
+            activity,
+            source_system: row.source_system,
+            job_id: row.job_id,
+            original_identifiers: serde_json::from_str(&row.original_identifiers)?,
+            transformation_version: row.transformation_version,
+            source_reference: row.source_reference,
+            recorded_at: row.recorded_at,
+        })
+    }
+}
+
+/// Provenance recorded by imports and FHIR sync
+pub struct ProvenanceRepository;
+
+impl ProvenanceRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Provenance of an entity, most recent first.
+    pub async fn for_target(&self, pool: &Pool, target_type: &str, target_id: Id) -> Result<Vec<Provenance>> {
+        let conn = pool.get().await?;
+        let target_type = target_type.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, target_type, target_id, activity, source_system, job_id, \
+                     original_identifiers::text AS original_identifiers, transformation_version, \
+                     source_reference, recorded_at \
+                     FROM emr.provenance WHERE target_type = $1 AND target_id = $2 ORDER BY recorded_at DESC",
+                )
+                .bind::<diesel::sql_types::Text, _>(&target_type)
+                .bind::<diesel::sql_types::Uuid, _>(target_id)
+                .load::<ProvenanceRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Provenance::try_from).collect()
+    }
+}
+
+const VALIDATION_PROFILE_COLUMNS: &str = "id, tenant_id, entity_type, version, strict, rules::text AS rules, created_at";
+
+#[derive(diesel::QueryableByName)]
+struct ValidationProfileRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    tenant_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    entity_type: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    version: i32,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    strict: bool,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    rules: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ValidationProfileRow> for ValidationProfile {
+    type Error = ApiError;
+
+    fn try_from(row: ValidationProfileRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            tenant_id: row.tenant_id,
+            entity_type: row.entity_type,
+            version: row.version,
+            strict: row.strict,
+            rules: serde_json::from_str(&row.rules)?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// Versioned validation profiles per tenant and entity type
+///
+/// Profiles are never edited in place: saving rules adds a version, and the
+/// latest version is the one in force.
+pub struct ValidationProfileRepository;
+
+impl ValidationProfileRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Save rules as the next version of a tenant's profile (`None` for the default profile).
+    pub async fn create_version(
+        &self,
+        pool: &Pool,
+        tenant_id: Option<Id>,
+        entity_type: &str,
+        strict: bool,
+        rules: &[ProfileRule],
+    ) -> Result<ValidationProfile> {
+        let conn = pool.get().await?;
+        let entity_type = entity_type.to_string();
+        let rules = serde_json::to_string(rules)?;
+        let query = format!(
+            "INSERT INTO emr.validation_profiles (id, tenant_id, entity_type, version, strict, rules) \
+             SELECT $1, $2, $3, COALESCE(MAX(version), 0) + 1, $4, $5::jsonb FROM emr.validation_profiles \
+             WHERE tenant_id IS NOT DISTINCT FROM $2 AND entity_type = $3 RETURNING {}",
+            VALIDATION_PROFILE_COLUMNS
+        );
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(uuid::Uuid::new_v4())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(tenant_id)
+                    .bind::<diesel::sql_types::Text, _>(&entity_type)
+                    .bind::<diesel::sql_types::Bool, _>(strict)
+                    .bind::<diesel::sql_types::Text, _>(&rules)
+                    .get_result::<ValidationProfileRow>(conn)
+            })
+            .await??;
+
+        ValidationProfile::try_from(row)
+    }
+
+    /// Latest version of every profile, optionally for one tenant or entity type.
+    pub async fn list_latest(
+        &self,
+        pool: &Pool,
+        tenant_id: Option<Id>,
+        entity_type: Option<String>,
+    ) -> Result<Vec<ValidationProfile>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT DISTINCT ON (tenant_id, entity_type) {} FROM emr.validation_profiles \
+             WHERE ($1::uuid IS NULL OR tenant_id = $1) AND ($2::text IS NULL OR entity_type = $2) \
+             ORDER BY tenant_id NULLS FIRST, entity_type, version DESC",
+            VALIDATION_PROFILE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(tenant_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(entity_type)
+                    .load::<ValidationProfileRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ValidationProfile::try_from).collect()
+    }
+
+    /// Every version of a tenant's profile for an entity type, newest first.
+    pub async fn versions(&self, pool: &Pool, tenant_id: Option<Id>, entity_type: &str) -> Result<Vec<ValidationProfile>> {
+        let conn = pool.get().await?;
+        let entity_type = entity_type.to_string();
+        let query = format!(
+            "SELECT {} FROM emr.validation_profiles \
+             WHERE tenant_id IS NOT DISTINCT FROM $1 AND entity_type = $2 ORDER BY version DESC",
+            VALIDATION_PROFILE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(tenant_id)
+                    .bind::<diesel::sql_types::Text, _>(&entity_type)
+                    .load::<ValidationProfileRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ValidationProfile::try_from).collect()
+    }
+
+    /// Profile in force for a tenant: its own latest version, else the
+    /// default profile's latest version.
+    pub async fn active(&self, pool: &Pool, tenant_id: Option<Id>, entity_type: &str) -> Result<Option<ValidationProfile>> {
+        let conn = pool.get().await?;
+        let entity_type = entity_type.to_string();
+        let query = format!(
+            "SELECT {} FROM emr.validation_profiles \
+             WHERE entity_type = $2 AND (tenant_id = $1 OR tenant_id IS NULL) \
+             ORDER BY tenant_id NULLS LAST, version DESC LIMIT 1",
+            VALIDATION_PROFILE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(tenant_id)
+                    .bind::<diesel::sql_types::Text, _>(&entity_type)
+                    .load::<ValidationProfileRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(ValidationProfile::try_from).transpose()
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PortalAccountRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    password_hash: String,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+}
+
+impl From<PortalAccountRow> for PortalAccountModel {
+    fn from(row: PortalAccountRow) -> Self {
+        Self {
+            id: row.id,
+            patient_id: row.patient_id,
+            password_hash: row.password_hash,
+            active: row.active,
+        }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PortalDemographicsRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    family_name: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Array<diesel::sql_types::Text>>)]
+    given_names: Option<Vec<String>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Date>)]
+    birth_date: Option<chrono::NaiveDate>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    gender: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    phone: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    email: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    address_line1: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    address_line2: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    city: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    state: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    postal_code: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    country: Option<String>,
+}
+
+impl From<PortalDemographicsRow> for PortalDemographicsModel {
+    fn from(row: PortalDemographicsRow) -> Self {
+        Self {
+            id: row.id,
+            family_name: row.family_name,
+            given_names: row.given_names.unwrap_or_default(),
+            birth_date: row.birth_date,
+            gender: row.gender,
+            phone: row.phone,
+            email: row.email,
+            address_line1: row.address_line1,
+            address_line2: row.address_line2,
+            city: row.city,
+            state: row.state,
+            postal_code: row.postal_code,
+            country: row.country,
+        }
+    }
+}
+
+const DOCUMENT_REFERENCE_COLUMNS: &str = "id, patient_id, encounter_id, status, type_code, type_display, title, \
+     content_type, url, size_bytes, scan_status, scanned_at, confidentiality, created_at";
+
+#[derive(diesel::QueryableByName)]
+struct DocumentReferenceRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    type_code: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    type_display: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    title: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    content_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    url: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::BigInt>)]
+    size_bytes: Option<i64>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    scan_status: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    scanned_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    confidentiality: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct DocumentSearchRow {
+    #[diesel(embed)]
+    document: DocumentReferenceRow,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    snippet: Option<String>,
+}
+
+impl From<DocumentReferenceRow> for DocumentReferenceModel {
+    fn from(row: DocumentReferenceRow) -> Self {
+        Self {
+            id: row.id,
+            patient_id: row.patient_id,
+            encounter_id: row.encounter_id,
+            status: row.status,
+            type_code: row.type_code,
+            type_display: row.type_display,
+            title: row.title,
+            content_type: row.content_type,
+            url: row.url,
+            size_bytes: row.size_bytes,
+            scan_status: row.scan_status,
+            scanned_at: row.scanned_at,
+            confidentiality: row.confidentiality,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Patient portal accounts and the patient-facing views of their data
+pub struct PortalRepository;
+
+impl PortalRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Find a sign-in account by username.
+    pub async fn find_account(&self, pool: &Pool, username: &str) -> Result<Option<PortalAccountModel>> {
+        let conn = pool.get().await?;
+        let username = username.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, patient_id, password_hash, active FROM emr.portal_account($1)",
+                )
+                .bind::<diesel::sql_types::Text, _>(&username)
+                .load::<PortalAccountRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().next().map(PortalAccountModel::from))
+    }
+
+    /// Record a successful sign-in.
+    pub async fn record_login(&self, pool: &Pool, account_id: Id) -> Result<()> {
+        let conn = pool.get().await?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query("SELECT emr.record_portal_login($1)")
+                .bind::<diesel::sql_types::Uuid, _>(account_id)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// Demographics of an active patient.
+    pub async fn demographics(&self, pool: &Pool, patient_id: Id) -> Result<Option<PortalDemographicsModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, family_name, given_names, birth_date, gender, phone, email, address_line1, \
+                     address_line2, city, state, postal_code, country \
+                     FROM emr.patients WHERE id = $1 AND active",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .load::<PortalDemographicsRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().next().map(PortalDemographicsModel::from))
+    }
+
+    /// Current documents of a patient that were scanned clean and that
+    /// `clearance` clears, newest first.
+    pub async fn documents(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        clearance: Confidentiality,
+    ) -> Result<Vec<DocumentReferenceModel>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.document_references \
+             WHERE patient_id = $1 AND status = 'current' AND scan_status = 'clean' \
+             AND confidentiality = ANY($2) \
+             ORDER BY created_at DESC",
+            DOCUMENT_REFERENCE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(clearance.cleared_labels())
+                    .load::<DocumentReferenceRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(DocumentReferenceModel::from).collect())
+    }
+}
+
+const COMMUNICATION_COLUMNS: &str = "id, thread_id, in_response_to, patient_id, sender_type, sender_id, \
+     recipients::text AS recipients, topic, payload, attachments, status, sent_at";
+
+#[derive(diesel::QueryableByName)]
+struct CommunicationRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    thread_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    in_response_to: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    sender_type: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    sender_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    recipients: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    topic: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    payload: String,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Uuid>)]
+    attachments: Vec<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    sent_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<CommunicationRow> for Communication {
+    type Error = ApiError;
+
+    fn try_from(row: CommunicationRow) -> Result<Self> {
+        let sender = CommunicationParty::parse(&row.sender_type, row.sender_id)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown message sender type '{}'", row.sender_type)))?;
+        let status = CommunicationStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown message status '{}'", row.status)))?;
+
+        Ok(Self {
+            id: row.id,
+            thread_id: row.thread_id,
+            in_response_to: row.in_response_to,
+            subject: row.patient_id,
+            sender,
+            recipients: serde_json::from_str(&row.recipients)?,
+            topic: row.topic,
+            payload: row.payload,
+            attachments: row.attachments,
+            status,
+            sent: row.sent_at,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct MessageThreadRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    thread_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    topic: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    message_count: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    unread_count: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    last_sent_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<MessageThreadRow> for MessageThreadModel {
+    fn from(row: MessageThreadRow) -> Self {
+        Self {
+            thread_id: row.thread_id,
+            patient_id: row.patient_id,
+            topic: row.topic,
+            message_count: row.message_count,
+            unread_count: row.unread_count,
+            last_sent_at: row.last_sent_at,
+        }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    count: i64,
+}
+
+/// Threads a party takes part in, with unread counts for that party
+pub const MESSAGE_THREADS_QUERY: &str = r#"
+SELECT c.thread_id, c.patient_id,
+       (ARRAY_AGG(c.topic ORDER BY c.sent_at))[1] AS topic,
+       COUNT(*) AS message_count,
+       COUNT(*) FILTER (
+           WHERE NOT (c.sender_type = $1 AND c.sender_id = $2)
+             AND c.sent_at > COALESCE(r.read_at, '-infinity'::timestamptz)
+       ) AS unread_count,
+       MAX(c.sent_at) AS last_sent_at
+FROM emr.communications c
+LEFT JOIN emr.communication_reads r
+       ON r.thread_id = c.thread_id AND r.reader_type = $1 AND r.reader_id = $2
+WHERE c.thread_id IN (
+        SELECT thread_id FROM emr.communications
+        WHERE (sender_type = $1 AND sender_id = $2) OR recipients @> $3::jsonb)
+  AND ($4::uuid IS NULL OR c.patient_id = $4)
+GROUP BY c.thread_id, c.patient_id, r.read_at
+ORDER BY last_sent_at DESC
+LIMIT $5 OFFSET $6
+"#;
+
+/// Secure message threads
+pub struct CommunicationRepository;
+
+impl CommunicationRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a sent message.
+    pub async fn insert(&self, pool: &Pool, message: &Communication) -> Result<()> {
+        let conn = pool.get().await?;
+        let recipients = serde_json::to_string(&message.recipients)?;
+        let message = message.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.communications \
+                 (id, thread_id, in_response_to, patient_id, sender_type, sender_id, recipients, topic, payload, \
+                 attachments, status, sent_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, $8, $9, $10, $11, $12)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(message.id)
+            .bind::<diesel::sql_types::Uuid, _>(message.thread_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(message.in_response_to)
+            .bind::<diesel::sql_types::Uuid, _>(message.subject)
+            .bind::<diesel::sql_types::Text, _>(message.sender.type_name())
+            .bind::<diesel::sql_types::Uuid, _>(message.sender.id())
+            .bind::<diesel::sql_types::Text, _>(&recipients)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(message.topic.as_deref())
+            .bind::<diesel::sql_types::Text, _>(&message.payload)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&message.attachments)
+            .bind::<diesel::sql_types::Text, _>(message.status.as_str())
+            .bind::<diesel::sql_types::Timestamptz, _>(message.sent)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// Threads a party takes part in, most recently active first, optionally about one patient.
+    pub async fn threads(
+        &self,
+        pool: &Pool,
+        party: CommunicationParty,
+        patient_id: Option<Id>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<MessageThreadModel>> {
+        let conn = pool.get().await?;
+        let as_recipient = serde_json::to_string(&[party])?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(MESSAGE_THREADS_QUERY)
+                    .bind::<diesel::sql_types::Text, _>(party.type_name())
+                    .bind::<diesel::sql_types::Uuid, _>(party.id())
+                    .bind::<diesel::sql_types::Text, _>(&as_recipient)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(patient_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<MessageThreadRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(MessageThreadModel::from).collect())
+    }
+
+    /// Messages of a thread, oldest first.
+    pub async fn thread(&self, pool: &Pool, thread_id: Id) -> Result<Vec<Communication>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.communications WHERE thread_id = $1 ORDER BY sent_at",
+            COMMUNICATION_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(thread_id)
+                    .load::<CommunicationRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Communication::try_from).collect()
+    }
+
+    /// Record that a party has read a thread up to `read_at`; read marks never move back.
+    pub async fn mark_read(
+        &self,
+        pool: &Pool,
+        thread_id: Id,
+        party: CommunicationParty,
+        read_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        let conn = pool.get().await?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.communication_reads (thread_id, reader_type, reader_id, read_at) \
+                 VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (thread_id, reader_type, reader_id) \
+                 DO UPDATE SET read_at = GREATEST(emr.communication_reads.read_at, EXCLUDED.read_at)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(thread_id)
+            .bind::<diesel::sql_types::Text, _>(party.type_name())
+            .bind::<diesel::sql_types::Uuid, _>(party.id())
+            .bind::<diesel::sql_types::Timestamptz, _>(read_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// How many of the given documents are current documents of a patient
+    /// that were scanned clean.
+    pub async fn count_patient_documents(&self, pool: &Pool, patient_id: Id, document_ids: Vec<Id>) -> Result<i64> {
+        let conn = pool.get().await?;
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT COUNT(*) AS count FROM emr.document_references \
+                     WHERE patient_id = $1 AND status = 'current' AND scan_status = 'clean' AND id = ANY($2)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&document_ids)
+                .get_result::<CountRow>(conn)
+            })
+            .await??;
+
+        Ok(row.count)
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct NotificationPreferencesRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    preferences: String,
+}
+
+/// Notification preferences per user, stored as JSON
+pub struct NotificationPreferenceRepository;
+
+impl NotificationPreferenceRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A user's preferences, if they have saved any.
+    pub async fn find(&self, pool: &Pool, user_id: Id) -> Result<Option<NotificationPreferences>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT preferences::text AS preferences FROM emr.notification_preferences WHERE user_id = $1",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(user_id)
+                .load::<NotificationPreferencesRow>(conn)
+            })
+            .await??;
+
+        match rows.into_iter().next() {
+            Some(row) => Ok(Some(serde_json::from_str(&row.preferences)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace a user's preferences.
+    pub async fn save(&self, pool: &Pool, preferences: &NotificationPreferences) -> Result<()> {
+        let conn = pool.get().await?;
+        let user_id = preferences.user_id;
+        let preferences = serde_json::to_string(preferences)?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.notification_preferences (user_id, preferences, updated_at) \
+                 VALUES ($1, $2::jsonb, NOW()) \
+                 ON CONFLICT (user_id) DO UPDATE SET preferences = EXCLUDED.preferences, updated_at = NOW()",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(user_id)
+            .bind::<diesel::sql_types::Text, _>(&preferences)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct NotificationRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    recipient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    notification_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    priority: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    subject: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    message: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    read_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<NotificationRow> for NotificationModel {
+    fn from(row: NotificationRow) -> Self {
+        Self {
+            id: row.id,
+            recipient_id: row.recipient_id,
+            notification_type: row.notification_type,
+            priority: row.priority,
+            subject: row.subject,
+            message: row.message,
+            created_at: row.created_at,
+            read_at: row.read_at,
+        }
+    }
+}
+
+/// In-app notification inboxes, filled by the jobs worker
+pub struct NotificationRepository;
+
+impl NotificationRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A recipient's notifications, newest first, optionally unread only.
+    pub async fn list(
+        &self,
+        pool: &Pool,
+        recipient_id: Id,
+        unread_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<NotificationModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, recipient_id, notification_type, priority, subject, message, created_at, read_at \
+                     FROM emr.notifications WHERE recipient_id = $1 AND (NOT $2 OR read_at IS NULL) \
+                     ORDER BY created_at DESC LIMIT $3 OFFSET $4",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(recipient_id)
+                .bind::<diesel::sql_types::Bool, _>(unread_only)
+                .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                .load::<NotificationRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(NotificationModel::from).collect())
+    }
+
+    /// Number of a recipient's unread notifications.
+    pub async fn unread_count(&self, pool: &Pool, recipient_id: Id) -> Result<i64> {
+        let conn = pool.get().await?;
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT COUNT(*) AS count FROM emr.notifications WHERE recipient_id = $1 AND read_at IS NULL",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(recipient_id)
+                .get_result::<CountRow>(conn)
+            })
+            .await??;
+
+        Ok(row.count)
+    }
+
+    /// Mark one of a recipient's notifications read; false if they have no such notification.
+    pub async fn mark_read(&self, pool: &Pool, recipient_id: Id, id: Id) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.notifications SET read_at = COALESCE(read_at, NOW()) \
+                     WHERE id = $1 AND recipient_id = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(id)
+                .bind::<diesel::sql_types::Uuid, _>(recipient_id)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+
+    /// Mark all of a recipient's notifications read, returning how many were unread.
+    pub async fn mark_all_read(&self, pool: &Pool, recipient_id: Id) -> Result<usize> {
+        let conn = pool.get().await?;
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.notifications SET read_at = NOW() WHERE recipient_id = $1 AND read_at IS NULL",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(recipient_id)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated)
+    }
+}
+
+const CLINICAL_NOTE_COLUMNS: &str = "id, patient_id, encounter_id, author_id, note_type, title, status, \
+     sections::text AS sections, addenda::text AS addenda, signed_at, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct ClinicalNoteRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    author_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    note_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    title: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    sections: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    addenda: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    signed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ClinicalNoteRow> for ClinicalNote {
+    type Error = ApiError;
+
+    fn try_from(row: ClinicalNoteRow) -> Result<Self> {
+        let note_type = NoteType::parse(&row.note_type)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown note type '{}'", row.note_type)))?;
+        let status = NoteStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown note status '{}'", row.status)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            patient_id: row.patient_id,
+            encounter_id: row.encounter_id,
+            author_id: row.author_id,
+            note_type,
+            title: row.title,
+            status,
+            sections: serde_json::from_str(&row.sections)?,
+            signed_at: row.signed_at,
+            addenda: serde_json::from_str(&row.addenda)?,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ClinicalNoteVersionRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    note_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    title: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    sections: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    saved_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ClinicalNoteVersionRow> for ClinicalNoteVersionModel {
+    type Error = ApiError;
+
+    fn try_from(row: ClinicalNoteVersionRow) -> Result<Self> {
+        Ok(Self {
+            note_id: row.note_id,
+            version: row.version,
+            title: row.title,
+            sections: serde_json::from_str(&row.sections)?,
+            saved_at: row.saved_at,
+        })
+    }
+}
+
+/// Records a draft's title and sections as one saved version
+const INSERT_NOTE_VERSION_QUERY: &str = "INSERT INTO emr.clinical_note_versions \
+     (note_id, version, title, sections, saved_at) VALUES ($1, $2, $3, $4::jsonb, $5)";
+
+/// Clinical notes and their draft history
+pub struct ClinicalNoteRepository;
+
+impl ClinicalNoteRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new draft as its first version.
+    pub async fn insert(&self, pool: &Pool, note: &ClinicalNote) -> Result<()> {
+        let conn = pool.get().await?;
+        let sections = serde_json::to_string(&note.sections)?;
+        let addenda = serde_json::to_string(&note.addenda)?;
+        let note = note.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.clinical_notes \
+                     (id, patient_id, encounter_id, author_id, note_type, title, status, sections, addenda, \
+                     signed_at, version, created_at, updated_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8::jsonb, $9::jsonb, $10, $11, $12, $13)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(note.metadata.id)
+                .bind::<diesel::sql_types::Uuid, _>(note.patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(note.encounter_id)
+                .bind::<diesel::sql_types::Uuid, _>(note.author_id)
+                .bind::<diesel::sql_types::Text, _>(note.note_type.as_str())
+                .bind::<diesel::sql_types::Text, _>(&note.title)
+                .bind::<diesel::sql_types::Text, _>(note.status.as_str())
+                .bind::<diesel::sql_types::Text, _>(&sections)
+                .bind::<diesel::sql_types::Text, _>(&addenda)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(note.signed_at)
+                .bind::<diesel::sql_types::BigInt, _>(note.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(note.metadata.created_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(note.metadata.updated_at)
+                .execute(conn)?;
+
+                diesel::sql_query(INSERT_NOTE_VERSION_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(note.metadata.id)
+                    .bind::<diesel::sql_types::BigInt, _>(note.metadata.version as i64)
+                    .bind::<diesel::sql_types::Text, _>(&note.title)
+                    .bind::<diesel::sql_types::Text, _>(&sections)
+                    .bind::<diesel::sql_types::Timestamptz, _>(note.metadata.updated_at)
+                    .execute(conn)
+            })
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A note by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<ClinicalNote>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.clinical_notes WHERE id = $1", CLINICAL_NOTE_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<ClinicalNoteRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(ClinicalNote::try_from).transpose()
+    }
+
+    /// A patient's notes, newest first, leaving out psychiatric notes unless `psychiatric`.
+    pub async fn for_patient(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        psychiatric: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<ClinicalNote>> {
+        let conn = pool.get().await?;
+        let withheld = if psychiatric {
+            String::new()
+        } else {
+            format!(" AND note_type <> '{}'", NoteType::PsychiatricNote.as_str())
+        };
+        let query = format!(
+            "SELECT {} FROM emr.clinical_notes WHERE patient_id = $1{} ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+            CLINICAL_NOTE_COLUMNS, withheld
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ClinicalNoteRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ClinicalNote::try_from).collect()
+    }
+
+    /// Write a changed note, provided nobody else changed it since it was read at `previous_version`.
+    ///
+    /// Drafts also get a row in the version history, and a signature made for
+    /// the change is stored and audited with it. Returns `false` when the
+    /// stored note has moved past `previous_version`.
+    pub async fn update(
+        &self,
+        pool: &Pool,
+        note: &ClinicalNote,
+        previous_version: u64,
+        signature: Option<Signature>,
+    ) -> Result<bool> {
+        let conn = pool.get().await?;
+        let sections = serde_json::to_string(&note.sections)?;
+        let addenda = serde_json::to_string(&note.addenda)?;
+        let note = note.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let updated = diesel::sql_query(
+                        "UPDATE emr.clinical_notes \
+                         SET title = $3, status = $4, sections = $5::jsonb, addenda = $6::jsonb, signed_at = $7, \
+                             version = $8, updated_at = $9 \
+                         WHERE id = $1 AND version = $2",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(note.metadata.id)
+                    .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                    .bind::<diesel::sql_types::Text, _>(&note.title)
+                    .bind::<diesel::sql_types::Text, _>(note.status.as_str())
+                    .bind::<diesel::sql_types::Text, _>(&sections)
+                    .bind::<diesel::sql_types::Text, _>(&addenda)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(note.signed_at)
+                    .bind::<diesel::sql_types::BigInt, _>(note.metadata.version as i64)
+                    .bind::<diesel::sql_types::Timestamptz, _>(note.metadata.updated_at)
+                    .execute(conn)?;
+
+                    if updated > 0 && note.is_draft() {
+                        diesel::sql_query(INSERT_NOTE_VERSION_QUERY)
+                            .bind::<diesel::sql_types::Uuid, _>(note.metadata.id)
+                            .bind::<diesel::sql_types::BigInt, _>(note.metadata.version as i64)
+                            .bind::<diesel::sql_types::Text, _>(&note.title)
+                            .bind::<diesel::sql_types::Text, _>(&sections)
+                            .bind::<diesel::sql_types::Timestamptz, _>(note.metadata.updated_at)
+                            .execute(conn)?;
+                    }
+                    if let (true, Some(signature)) = (updated > 0, &signature) {
+                        insert_signature(conn, signature)?;
+                    }
+                    Ok::<_, DieselError>(updated > 0)
+                })
+            })
+            .await??;
+
+        Ok(updated)
+    }
+
+    /// Saved draft versions of a note, oldest first.
+    pub async fn versions(&self, pool: &Pool, note_id: Id) -> Result<Vec<ClinicalNoteVersionModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT note_id, version, title, sections::text AS sections, saved_at \
+                     FROM emr.clinical_note_versions WHERE note_id = $1 ORDER BY version",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(note_id)
+                .load::<ClinicalNoteVersionRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ClinicalNoteVersionModel::try_from).collect()
+    }
+}
+
+/// Audit trail entry for a signing event; `request_id` comes from the session like the table triggers
+const INSERT_SIGNING_AUDIT_QUERY: &str = "INSERT INTO audit.audit_log \
+     (table_name, operation, new_values, changed_by, request_id, user_id) \
+     VALUES ('signatures', $1, $2::jsonb, current_user, audit.current_request_id(), $3)";
+
+/// Store a signature and audit it as a `SIGN` or `AMEND` event
+fn insert_signature(conn: &mut PgConnection, signature: &Signature) -> std::result::Result<(), DieselError> {
+    let operation = match signature.kind {
+        SignatureKind::Attestation => "SIGN",
+        SignatureKind::Amendment => "AMEND",
+    };
+    let audited = serde_json::to_string(signature).map_err(|e| DieselError::SerializationError(Box::new(e)))?;
+
+    diesel::sql_query(
+        "INSERT INTO emr.signatures \
+         (id, target_type, target_id, kind, signer_id, signed_at, reason, content_hash, seal) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind::<diesel::sql_types::Uuid, _>(signature.id)
+    .bind::<diesel::sql_types::Text, _>(&signature.target_type)
+    .bind::<diesel::sql_types::Uuid, _>(signature.target_id)
+    .bind::<diesel::sql_types::Text, _>(signature.kind.as_str())
+    .bind::<diesel::sql_types::Uuid, _>(signature.signer_id)
+    .bind::<diesel::sql_types::Timestamptz, _>(signature.signed_at)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(signature.reason.as_deref())
+    .bind::<diesel::sql_types::Text, _>(&signature.content_hash)
+    .bind::<diesel::sql_types::Text, _>(&signature.seal)
+    .execute(conn)?;
+
+    diesel::sql_query(INSERT_SIGNING_AUDIT_QUERY)
+        .bind::<diesel::sql_types::Text, _>(operation)
+        .bind::<diesel::sql_types::Text, _>(&audited)
+        .bind::<diesel::sql_types::Uuid, _>(signature.signer_id)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+#[derive(diesel::QueryableByName)]
+struct SignatureRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    target_type: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    target_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    kind: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    signer_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    signed_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    content_hash: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    seal: String,
+}
+
+impl TryFrom<SignatureRow> for Signature {
+    type Error = ApiError;
+
+    fn try_from(row: SignatureRow) -> Result<Self> {
+        let kind = SignatureKind::parse(&row.kind)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown signature kind '{}'", row.kind)))?;
+
+        Ok(Self {
+            id: row.id,
+            target_type: row.target_type,
+            target_id: row.target_id,
+            kind,
+            signer_id: row.signer_id,
+            signed_at: row.signed_at,
+            reason: row.reason,
+            content_hash: row.content_hash,
+            seal: row.seal,
+        })
+    }
+}
+
+/// Electronic signatures and their audit trail
+pub struct SignatureRepository;
+
+impl SignatureRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Signatures on a resource, oldest first.
+    pub async fn for_target(&self, pool: &Pool, target_type: &str, target_id: Id) -> Result<Vec<Signature>> {
+        let conn = pool.get().await?;
+        let target_type = target_type.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, target_type, target_id, kind, signer_id, signed_at, reason, content_hash, seal \
+                     FROM emr.signatures WHERE target_type = $1 AND target_id = $2 ORDER BY signed_at, id",
+                )
+                .bind::<diesel::sql_types::Text, _>(&target_type)
+                .bind::<diesel::sql_types::Uuid, _>(target_id)
+                .load::<SignatureRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Signature::try_from).collect()
+    }
+
+    /// Audit a verification of a resource's signatures as a `VERIFY` event.
+    pub async fn record_verification(
+        &self,
+        pool: &Pool,
+        target_type: &str,
+        target_id: Id,
+        outcomes: &[(Id, Verification)],
+        verified_by: Option<Id>,
+    ) -> Result<()> {
+        let conn = pool.get().await?;
+        let audited = serde_json::to_string(&serde_json::json!({
+            "target_type": target_type,
+            "target_id": target_id,
+            "outcomes": outcomes
+                .iter()
+                .map(|(id, outcome)| serde_json::json!({"signature_id": id, "outcome": outcome}))
+                .collect::<Vec<_>>(),
+        }))?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_SIGNING_AUDIT_QUERY)
+                .bind::<diesel::sql_types::Text, _>("VERIFY")
+                .bind::<diesel::sql_types::Text, _>(&audited)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(verified_by)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+const SERVICE_REQUEST_COLUMNS: &str = "id, patient_id, encounter_id, category, code, code_system, display, \
+     priority, status, requester_id, performer_id, reason, authored_on, fulfilled_by, completed_at, version, \
+     created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct ServiceRequestRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    category: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    code: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    code_system: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    display: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    priority: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    requester_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    performer_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    authored_on: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+    fulfilled_by: Vec<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ServiceRequestRow> for ServiceRequest {
+    type Error = ApiError;
+
+    fn try_from(row: ServiceRequestRow) -> Result<Self> {
+        let category = RequestCategory::parse(&row.category)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown order category '{}'", row.category)))?;
+        let priority = RequestPriority::parse(&row.priority)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown order priority '{}'", row.priority)))?;
+        let status = RequestStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown order status '{}'", row.status)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            patient_id: row.patient_id,
+            encounter_id: row.encounter_id,
+            category,
+            code: row.code,
+            code_system: row.code_system,
+            display: row.display,
+            priority,
+            status,
+            requester_id: row.requester_id,
+            performer_id: row.performer_id,
+            reason: row.reason,
+            authored_on: row.authored_on,
+            fulfilled_by: row.fulfilled_by,
+            completed_at: row.completed_at,
+        })
+    }
+}
+
+/// Pending orders a practitioner placed or must perform, most urgent first
+pub const PENDING_ORDERS_QUERY: &str = r#"
+SELECT id, patient_id, encounter_id, category, code, code_system, display, priority, status, requester_id,
+       performer_id, reason, authored_on, fulfilled_by, completed_at, version, created_at, updated_at
+FROM emr.service_requests
+WHERE (requester_id = $1 OR performer_id = $1)
+  AND status IN ('draft', 'active', 'on-hold')
+ORDER BY CASE priority WHEN 'stat' THEN 0 WHEN 'asap' THEN 1 WHEN 'urgent' THEN 2 ELSE 3 END,
+         authored_on
+LIMIT $2 OFFSET $3
+"#;
+
+/// Orders (FHIR ServiceRequest)
+pub struct ServiceRequestRepository;
+
+impl ServiceRequestRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new order.
+    pub async fn insert(&self, pool: &Pool, request: &ServiceRequest) -> Result<()> {
+        let conn = pool.get().await?;
+        let request = request.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.service_requests \
+                 (id, patient_id, encounter_id, category, code, code_system, display, priority, status, \
+                 requester_id, performer_id, reason, authored_on, fulfilled_by, completed_at, version, created_at, \
+                 updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(request.metadata.id)
+            .bind::<diesel::sql_types::Uuid, _>(request.patient_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(request.encounter_id)
+            .bind::<diesel::sql_types::Text, _>(request.category.as_str())
+            .bind::<diesel::sql_types::Text, _>(&request.code)
+            .bind::<diesel::sql_types::Text, _>(&request.code_system)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(request.display.as_deref())
+            .bind::<diesel::sql_types::Text, _>(request.priority.as_str())
+            .bind::<diesel::sql_types::Text, _>(request.status.as_str())
+            .bind::<diesel::sql_types::Uuid, _>(request.requester_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(request.performer_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(request.reason.as_deref())
+            .bind::<diesel::sql_types::Timestamptz, _>(request.authored_on)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&request.fulfilled_by)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(request.completed_at)
+            .bind::<diesel::sql_types::BigInt, _>(request.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(request.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(request.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// An order by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<ServiceRequest>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.service_requests WHERE id = $1", SERVICE_REQUEST_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<ServiceRequestRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(ServiceRequest::try_from).transpose()
+    }
+
+    /// A patient's orders, newest first.
+    pub async fn for_patient(&self, pool: &Pool, patient_id: Id, limit: u32, offset: u32) -> Result<Vec<ServiceRequest>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.service_requests WHERE patient_id = $1 ORDER BY authored_on DESC LIMIT $2 OFFSET $3",
+            SERVICE_REQUEST_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ServiceRequestRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ServiceRequest::try_from).collect()
+    }
+
+    /// Pending orders a practitioner placed or must perform, most urgent first.
+    pub async fn pending_for_practitioner(
+        &self,
+        pool: &Pool,
+        practitioner_id: Id,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<ServiceRequest>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(PENDING_ORDERS_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(practitioner_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ServiceRequestRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ServiceRequest::try_from).collect()
+    }
+
+    /// Write a changed order, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, request: &ServiceRequest, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let request = request.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.service_requests \
+                     SET status = $3, performer_id = $4, fulfilled_by = $5, completed_at = $6, version = $7, \
+                         updated_at = $8 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(request.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Text, _>(request.status.as_str())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(request.performer_id)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&request.fulfilled_by)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(request.completed_at)
+                .bind::<diesel::sql_types::BigInt, _>(request.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(request.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct KnownPatientRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+}
+
+#[derive(diesel::QueryableByName)]
+struct KnownEncounterRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    patient_id: Option<Id>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct ObservationRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    category: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    code: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    display: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    patient_id: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    device_id: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    effective_date: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    value: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    unit: Option<String>,
+}
+
+impl From<ObservationRow> for ObservationModel {
+    fn from(row: ObservationRow) -> Self {
+        Self {
+            id: row.id,
+            status: row.status,
+            category: row.category,
+            code: row.code,
+            display: row.display,
+            patient_id: row.patient_id,
+            encounter_id: row.encounter_id,
+            device_id: row.device_id,
+            effective_date: row.effective_date,
+            value: row.value,
+            unit: row.unit,
+        }
+    }
+}
+
+const OBSERVATION_RECORD_COLUMNS: &str = "id, status, category, code, patient_id, encounter_id, effective_date, \
+     value_quantity_value::float8 AS value, value_quantity_unit AS unit, confidentiality, details::text AS details, \
+     version, COALESCE(created_at, NOW()) AS created_at, COALESCE(updated_at, NOW()) AS updated_at";
+
+/// Filters shared by the observation list and its count; observations
+/// anonymized under a retention policy no longer belong to a patient and are
+/// left out
+const OBSERVATION_RECORD_FILTER: &str = "patient_id IS NOT NULL \
+     AND ($1::uuid IS NULL OR patient_id = $1) AND ($2::uuid IS NULL OR encounter_id = $2) \
+     AND ($3::text IS NULL OR category = $3 OR COALESCE(details->'category', '[]'::jsonb) ? $3) \
+     AND ($4::text IS NULL OR code = $4) AND confidentiality = ANY($5) AND COALESCE(code, '') <> ALL($6)";
+
+/// Which observations a listing includes
+#[derive(Debug, Clone, Default)]
+pub struct ObservationSearch {
+    pub patient_id: Option<Id>,
+    pub encounter_id: Option<Id>,
+    pub category: Option<String>,
+    pub code: Option<String>,
+    /// Leave out observations labelled above this clearance
+    pub clearance: Option<Confidentiality>,
+    /// Leave out HIV results, for callers the consent rules do not permit them
+    pub without_hiv_results: bool,
+}
+
+impl ObservationSearch {
+    /// Every observation of a patient
+    pub fn patient(patient_id: Id) -> Self {
+        Self {
+            patient_id: Some(patient_id),
+            ..Default::default()
+        }
+    }
+
+    /// Every observation made during an encounter
+    pub fn encounter(encounter_id: Id) -> Self {
+        Self {
+            encounter_id: Some(encounter_id),
+            ..Default::default()
+        }
+    }
+
+    fn cleared_labels(&self) -> Vec<&'static str> {
+        self.clearance.unwrap_or(Confidentiality::VeryRestricted).cleared_labels()
+    }
+
+    fn excluded_codes(&self) -> Vec<&'static str> {
+        if self.without_hiv_results {
+            HIV_RESULT_CODES.to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ObservationRecordRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    category: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    code: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    patient_id: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<Id>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    effective_date: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    value: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    unit: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    confidentiality: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    details: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The parts of an observation without a column of their own, stored in
+/// `details`. Device readings stored in bulk have none.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct ObservationDetails {
+    identifiers: Vec<Identifier>,
+    category: Vec<String>,
+    /// Kept so observations without an effective time read back without one
+    effective: Option<chrono::DateTime<chrono::Utc>>,
+    issued: Option<chrono::DateTime<chrono::Utc>>,
+    performer: Vec<Id>,
+    value: Option<ObservationValue>,
+    interpretation: Vec<String>,
+    note: Vec<String>,
+    method: Option<String>,
+    specimen: Option<Id>,
+    device: Option<Id>,
+    reference_range: Vec<ObservationReferenceRange>,
+    has_member: Vec<Id>,
+    derived_from: Vec<Id>,
+    based_on: Vec<Id>,
+}
+
+impl TryFrom<ObservationRecordRow> for Observation {
+    type Error = ApiError;
+
+    fn try_from(row: ObservationRecordRow) -> Result<Self> {
+        let status = ObservationStatus::from_code(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown observation status '{}'", row.status)))?;
+        let patient_id = row
+            .patient_id
+            .ok_or_else(|| ApiError::internal_error(&format!("Observation {} has no patient", row.id)))?;
+        let details = match row.details {
+            Some(details) => serde_json::from_str(&details)?,
+            None => ObservationDetails {
+                category: row.category.into_iter().collect(),
+                effective: Some(row.effective_date),
+                value: row.value.map(|value| ObservationValue::Quantity {
+                    value,
+                    unit: row.unit.clone().unwrap_or_default(),
+                    system: row.unit.as_ref().map(|_| UCUM_SYSTEM.to_string()),
+                    code: row.unit,
+                }),
+                ..Default::default()
+            },
+        };
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            identifiers: details.identifiers,
+            status,
+            category: details.category,
+            code: row.code.unwrap_or_default(),
+            subject: patient_id,
+            encounter: row.encounter_id,
+            effective: details.effective,
+            issued: details.issued,
+            performer: details.performer,
+            value: details.value,
+            interpretation: details.interpretation,
+            note: details.note,
+            method: details.method,
+            specimen: details.specimen,
+            device: details.device,
+            reference_range: details.reference_range,
+            has_member: details.has_member,
+            derived_from: details.derived_from,
+            based_on: details.based_on,
+            confidentiality: Confidentiality::parse(&row.confidentiality).unwrap_or_default(),
+        })
+    }
+}
+
+/// Column values of an observation being written
+struct ObservationColumns {
+    observation: Observation,
+    details: String,
+    fhir_data: String,
+}
+
+impl ObservationColumns {
+    fn new(observation: &Observation) -> Result<Self> {
+        let details = ObservationDetails {
+            identifiers: observation.identifiers.clone(),
+            category: observation.category.clone(),
+            effective: observation.effective,
+            issued: observation.issued,
+            performer: observation.performer.clone(),
+            value: observation.value.clone(),
+            interpretation: observation.interpretation.clone(),
+            note: observation.note.clone(),
+            method: observation.method.clone(),
+            specimen: observation.specimen,
+            device: observation.device,
+            reference_range: observation.reference_range.clone(),
+            has_member: observation.has_member.clone(),
+            derived_from: observation.derived_from.clone(),
+            based_on: observation.based_on.clone(),
+        };
+        Ok(Self {
+            observation: observation.clone(),
+            details: serde_json::to_string(&details)?,
+            fhir_data: observation_to_fhir(observation).to_string(),
+        })
+    }
+
+    /// Partition key; observations without an effective time are filed when recorded
+    fn effective_date(&self) -> chrono::DateTime<chrono::Utc> {
+        self.observation.effective.unwrap_or(self.observation.metadata.created_at)
+    }
+
+    fn quantity(&self) -> (Option<f64>, Option<&str>) {
+        match &self.observation.value {
+            Some(ObservationValue::Quantity { value, unit, .. }) => (Some(*value), Some(unit.as_str())),
+            Some(ObservationValue::Integer(value)) => (Some(*value as f64), None),
+            _ => (None, None),
+        }
+    }
+
+    fn value_string(&self) -> Option<&str> {
+        match &self.observation.value {
+            Some(ObservationValue::String(value)) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    fn value_boolean(&self) -> Option<bool> {
+        match &self.observation.value {
+            Some(ObservationValue::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn reference_range(&self) -> (Option<f64>, Option<f64>) {
+        self.observation
+            .reference_range
+            .first()
+            .map_or((None, None), |range| (range.low, range.high))
+    }
+}
+
+/// Patient summary changes brought by writing observations
+fn observation_events(columns: &[ObservationColumns]) -> BTreeMap<Id, Vec<SummaryEvent>> {
+    let mut events: BTreeMap<Id, Vec<SummaryEvent>> = BTreeMap::new();
+    for observation in columns.iter().map(|columns| &columns.observation) {
+        if let Some(event) = SummaryEvent::from_observation(observation) {
+            events.entry(observation.subject).or_default().push(event);
+        }
+    }
+    events
+}
+
+fn observation_write_error(err: DieselError) -> ApiError {
+    match err {
+        DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+            ApiError::validation_error("The observation refers to a patient or encounter that does not exist")
+        }
+        err => ApiError::from(err),
+    }
+}
+
+/// Observations in the partitioned `emr.observations` table
+pub struct ObservationRepository;
+
+impl ObservationRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Observations effective in the query's window, most recent first.
+    ///
+    /// The window bounds the partition key, so only the monthly partitions
+    /// it overlaps are scanned.
+    pub async fn list(&self, pool: &Pool, query: ObservationQuery) -> Result<Vec<ObservationModel>> {
+        query.check()?;
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(OBSERVATION_QUERY)
+                    .bind::<diesel::sql_types::Timestamptz, _>(query.from)
+                    .bind::<diesel::sql_types::Timestamptz, _>(query.to)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(query.patient_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(query.category)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(query.code)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(query.limit))
+                    .load::<ObservationRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(ObservationModel::from).collect())
+    }
+
+    /// A page of observations, most recent effective time first, and how
+    /// many match in all.
+    pub async fn list_records(
+        &self,
+        pool: &Pool,
+        search: ObservationSearch,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Observation>, u64)> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.observations WHERE {} ORDER BY effective_date DESC, id LIMIT $7 OFFSET $8",
+            OBSERVATION_RECORD_COLUMNS, OBSERVATION_RECORD_FILTER
+        );
+        let labels = search.cleared_labels();
+        let excluded = search.excluded_codes();
+
+        let (rows, total) = conn
+            .interact(move |conn| {
+                let rows = diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(search.patient_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(search.encounter_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&search.category)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&search.code)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&labels)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&excluded)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ObservationRecordRow>(conn)?;
+                let total = diesel::sql_query(format!(
+                    "SELECT COUNT(*) AS count FROM emr.observations WHERE {}",
+                    OBSERVATION_RECORD_FILTER
+                ))
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(search.patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(search.encounter_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&search.category)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&search.code)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&labels)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&excluded)
+                .get_result::<CountRow>(conn)?;
+                Ok::<_, DieselError>((rows, total.count))
+            })
+            .await??;
+
+        let observations = rows.into_iter().map(Observation::try_from).collect::<Result<_>>()?;
+        Ok((observations, total as u64))
+    }
+
+    /// An observation by id, in any partition.
+    pub async fn find_record(&self, pool: &Pool, id: Id) -> Result<Option<Observation>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.observations WHERE id = $1 AND patient_id IS NOT NULL LIMIT 1",
+            OBSERVATION_RECORD_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<ObservationRecordRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(Observation::try_from).transpose()
+    }
+
+    /// Store new observations together, such as a vitals panel and its
+    /// members; nothing is stored if any fails.
+    pub async fn insert_records(&self, pool: &Pool, observations: &[Observation]) -> Result<()> {
+        let conn = pool.get().await?;
+        let columns = observations.iter().map(ObservationColumns::new).collect::<Result<Vec<_>>>()?;
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                for columns in &columns {
+                    let observation = &columns.observation;
+                    let (value, unit) = columns.quantity();
+                    let (low, high) = columns.reference_range();
+                    diesel::sql_query(
+                        "INSERT INTO emr.observations \
+                         (id, status, category, code, patient_id, encounter_id, effective_date, value_quantity_value, \
+                         value_quantity_unit, value_string, value_boolean, interpretation, reference_range_low, \
+                         reference_range_high, confidentiality, fhir_data, details, version, created_at, updated_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16::jsonb, \
+                         $17::jsonb, $18, $19, $20)",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(observation.metadata.id)
+                    .bind::<diesel::sql_types::Text, _>(observation.status.code())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(observation.category.first())
+                    .bind::<diesel::sql_types::Text, _>(&observation.code)
+                    .bind::<diesel::sql_types::Uuid, _>(observation.subject)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(observation.encounter)
+                    .bind::<diesel::sql_types::Timestamptz, _>(columns.effective_date())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(value)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(unit)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(columns.value_string())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Bool>, _>(columns.value_boolean())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(observation.interpretation.first())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(low)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(high)
+                    .bind::<diesel::sql_types::Text, _>(observation.confidentiality.as_str())
+                    .bind::<diesel::sql_types::Text, _>(&columns.fhir_data)
+                    .bind::<diesel::sql_types::Text, _>(&columns.details)
+                    .bind::<diesel::sql_types::BigInt, _>(observation.metadata.version as i64)
+                    .bind::<diesel::sql_types::Timestamptz, _>(observation.metadata.created_at)
+                    .bind::<diesel::sql_types::Timestamptz, _>(observation.metadata.updated_at)
+                    .execute(conn)?;
+                }
+                apply_summary_events(conn, observation_events(&columns))
+            })
+        })
+        .await?
+        .map_err(observation_write_error)?;
+
+        Ok(())
+    }
+
+    /// Write a changed observation, provided nobody else changed it since it was read at `previous_version`.
+    ///
+    /// Returns `false` when the stored observation has moved past `previous_version`.
+    pub async fn update_record(&self, pool: &Pool, observation: &Observation, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let columns = ObservationColumns::new(observation)?;
+
+        let updated = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let observation = &columns.observation;
+                    let (value, unit) = columns.quantity();
+                    let (low, high) = columns.reference_range();
+                    let updated = diesel::sql_query(
+                        "UPDATE emr.observations \
+                         SET status = $3, category = $4, code = $5, patient_id = $6, encounter_id = $7, \
+                             effective_date = $8, value_quantity_value = $9, value_quantity_unit = $10, \
+                             value_string = $11, value_boolean = $12, interpretation = $13, \
+                             reference_range_low = $14, reference_range_high = $15, confidentiality = $16, \
+                             fhir_data = $17::jsonb, details = $18::jsonb, version = $19, updated_at = $20 \
+                         WHERE id = $1 AND version = $2",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(observation.metadata.id)
+                    .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                    .bind::<diesel::sql_types::Text, _>(observation.status.code())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(observation.category.first())
+                    .bind::<diesel::sql_types::Text, _>(&observation.code)
+                    .bind::<diesel::sql_types::Uuid, _>(observation.subject)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(observation.encounter)
+                    .bind::<diesel::sql_types::Timestamptz, _>(columns.effective_date())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(value)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(unit)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(columns.value_string())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Bool>, _>(columns.value_boolean())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(observation.interpretation.first())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(low)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(high)
+                    .bind::<diesel::sql_types::Text, _>(observation.confidentiality.as_str())
+                    .bind::<diesel::sql_types::Text, _>(&columns.fhir_data)
+                    .bind::<diesel::sql_types::Text, _>(&columns.details)
+                    .bind::<diesel::sql_types::BigInt, _>(observation.metadata.version as i64)
+                    .bind::<diesel::sql_types::Timestamptz, _>(observation.metadata.updated_at)
+                    .execute(conn)?;
+                    if updated > 0 {
+                        apply_summary_events(conn, observation_events(std::slice::from_ref(&columns)))?;
+                    }
+                    Ok::<_, DieselError>(updated)
+                })
+            })
+            .await?
+            .map_err(observation_write_error)?;
+
+        Ok(updated > 0)
+    }
+
+    /// Store a validated device batch in one transaction, `chunk_size` rows
+    /// per insert.
+    ///
+    /// Observations of patients or encounters the caller cannot see are
+    /// rejected first. The rest are stored together, or not at all when the
+    /// call fails; observations whose id and effective time are already
+    /// stored are skipped.
+    pub async fn insert_device_batch(
+        &self,
+        pool: &Pool,
+        mut batch: ValidatedBatch,
+        chunk_size: usize,
+    ) -> Result<IngestResult> {
+        let conn = pool.batch().get().await?;
+
+        let (batch, inserted) = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let patients = diesel::sql_query(KNOWN_PATIENTS_QUERY)
+                        .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(batch.patient_ids())
+                        .load::<KnownPatientRow>(conn)?;
+                    let encounters = diesel::sql_query(KNOWN_ENCOUNTERS_QUERY)
+                        .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(batch.encounter_ids())
+                        .load::<KnownEncounterRow>(conn)?;
+                    batch.reject_unknown(
+                        &patients.into_iter().map(|row| row.id).collect(),
+                        &encounters.into_iter().map(|row| (row.id, row.patient_id)).collect(),
+                    );
+
+                    let mut inserted = 0;
+                    for columns in batch.chunks(chunk_size) {
+                        inserted += diesel::sql_query(INSERT_DEVICE_OBSERVATIONS_QUERY)
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(columns.ids)
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Nullable<diesel::sql_types::Text>>, _>(
+                                columns.categories,
+                            )
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(columns.codes)
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Nullable<diesel::sql_types::Text>>, _>(
+                                columns.displays,
+                            )
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(columns.patient_ids)
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Nullable<diesel::sql_types::Uuid>>, _>(
+                                columns.encounter_ids,
+                            )
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Nullable<diesel::sql_types::Text>>, _>(
+                                columns.device_ids,
+                            )
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Timestamptz>, _>(columns.effective)
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Double>, _>(columns.values)
+                            .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(columns.units)
+                            .execute(conn)?;
+                    }
+                    apply_summary_events(conn, device_events(batch.accepted()))?;
+                    Ok::<_, DieselError>((batch, inserted))
+                })
+            })
+            .await??;
+
+        Ok(batch.into_result(inserted))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PatientSummaryRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    summary: String,
+}
+
+/// Fold events into the patients' stored summaries, creating missing ones.
+///
+/// Summaries are locked in patient id order until the caller's transaction
+/// ends, so the change and its summary are committed together.
+fn apply_summary_events(
+    conn: &mut PgConnection,
+    events: BTreeMap<Id, Vec<SummaryEvent>>,
+) -> std::result::Result<(), DieselError> {
+    let now = chrono::Utc::now();
+    for (patient_id, events) in events {
+        let empty = serde_json::to_string(&PatientSummary::new(patient_id))
+            .map_err(|e| DieselError::SerializationError(Box::new(e)))?;
+        diesel::sql_query(CREATE_SUMMARY_QUERY)
+            .bind::<diesel::sql_types::Uuid, _>(patient_id)
+            .bind::<diesel::sql_types::Text, _>(&empty)
+            .execute(conn)?;
+        let row = diesel::sql_query(LOCK_SUMMARY_QUERY)
+            .bind::<diesel::sql_types::Uuid, _>(patient_id)
+            .get_result::<PatientSummaryRow>(conn)?;
+
+        let mut summary: PatientSummary =
+            serde_json::from_str(&row.summary).map_err(|e| DieselError::DeserializationError(Box::new(e)))?;
+        for event in events {
+            summary.apply(event, now);
+        }
+        let summary = serde_json::to_string(&summary).map_err(|e| DieselError::SerializationError(Box::new(e)))?;
+        diesel::sql_query(SAVE_SUMMARY_QUERY)
+            .bind::<diesel::sql_types::Uuid, _>(patient_id)
+            .bind::<diesel::sql_types::Text, _>(&summary)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Patient summaries, stored as JSON and kept up to date from record changes
+pub struct PatientSummaryRepository;
+
+impl PatientSummaryRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A patient's summary, if any summarized record has changed.
+    pub async fn find(&self, pool: &Pool, patient_id: Id) -> Result<Option<PatientSummary>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(SUMMARY_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .load::<PatientSummaryRow>(conn)
+            })
+            .await??;
+
+        match rows.into_iter().next() {
+            Some(row) => Ok(Some(serde_json::from_str(&row.summary)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+const ACKNOWLEDGMENT_TASK_COLUMNS: &str = "id, observation_id, patient_id, order_id, owner_id, test_name, critical, \
+     status, created_at, acknowledged_at, acknowledged_by, note, escalation_count, last_escalated_at";
+
+#[derive(diesel::QueryableByName)]
+struct AcknowledgmentTaskRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    observation_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    order_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    owner_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    test_name: String,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    critical: bool,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    acknowledged_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    acknowledged_by: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    note: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    escalation_count: i32,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    last_escalated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl TryFrom<AcknowledgmentTaskRow> for AcknowledgmentTask {
+    type Error = ApiError;
+
+    fn try_from(row: AcknowledgmentTaskRow) -> Result<Self> {
+        let status = TaskStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown task status '{}'", row.status)))?;
+
+        Ok(Self {
+            id: row.id,
+            observation_id: row.observation_id,
+            patient_id: row.patient_id,
+            order_id: row.order_id,
+            owner_id: row.owner_id,
+            test_name: row.test_name,
+            critical: row.critical,
+            status,
+            created_at: row.created_at,
+            acknowledged_at: row.acknowledged_at,
+            acknowledged_by: row.acknowledged_by,
+            note: row.note,
+            escalation_count: row.escalation_count.max(0) as u32,
+            last_escalated_at: row.last_escalated_at,
+        })
+    }
+}
+
+/// Acknowledgment latency of results opened in `[$1, $2)`, split into
+/// critical and other abnormal results; `$3` is the critical SLA in minutes
+pub const ACKNOWLEDGMENT_LATENCY_QUERY: &str = r#"
+WITH tasks AS (
+    SELECT critical,
+           acknowledged_at,
+           (EXTRACT(EPOCH FROM (COALESCE(acknowledged_at, NOW()) - created_at)) / 60.0)::double precision AS minutes
+    FROM emr.acknowledgment_tasks
+    WHERE created_at >= $1 AND created_at < $2
+)
+SELECT critical,
+       COUNT(*) AS total,
+       COUNT(acknowledged_at) AS acknowledged,
+       COUNT(*) - COUNT(acknowledged_at) AS pending,
+       COUNT(*) FILTER (WHERE critical AND minutes > $3) AS breached_sla,
+       percentile_cont(0.5) WITHIN GROUP (ORDER BY minutes) FILTER (WHERE acknowledged_at IS NOT NULL) AS p50_minutes,
+       percentile_cont(0.9) WITHIN GROUP (ORDER BY minutes) FILTER (WHERE acknowledged_at IS NOT NULL) AS p90_minutes,
+       MAX(minutes) FILTER (WHERE acknowledged_at IS NOT NULL) AS max_minutes
+FROM tasks
+GROUP BY critical
+ORDER BY critical DESC
+"#;
+
+#[derive(diesel::QueryableByName)]
+struct AcknowledgmentLatencyRow {
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    critical: bool,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    acknowledged: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pending: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    breached_sla: i64,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p50_minutes: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p90_minutes: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    max_minutes: Option<f64>,
+}
+
+impl From<AcknowledgmentLatencyRow> for AcknowledgmentLatencyModel {
+    fn from(row: AcknowledgmentLatencyRow) -> Self {
+        Self {
+            critical: row.critical,
+            total: row.total,
+            acknowledged: row.acknowledged,
+            pending: row.pending,
+            breached_sla: row.breached_sla,
+            p50_minutes: row.p50_minutes,
+            p90_minutes: row.p90_minutes,
+            max_minutes: row.max_minutes,
+        }
+    }
+}
+
+/// Result acknowledgment tasks (FHIR Task)
+pub struct AcknowledgmentRepository;
+
+impl AcknowledgmentRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new task; returns `false` when the result already has one.
+    pub async fn insert(&self, pool: &Pool, task: &AcknowledgmentTask) -> Result<bool> {
+        let conn = pool.get().await?;
+        let task = task.clone();
+
+        let inserted = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.acknowledgment_tasks \
+                     (id, observation_id, patient_id, order_id, owner_id, test_name, critical, status, created_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+                     ON CONFLICT (observation_id) DO NOTHING",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(task.id)
+                .bind::<diesel::sql_types::Uuid, _>(task.observation_id)
+                .bind::<diesel::sql_types::Uuid, _>(task.patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(task.order_id)
+                .bind::<diesel::sql_types::Uuid, _>(task.owner_id)
+                .bind::<diesel::sql_types::Text, _>(&task.test_name)
+                .bind::<diesel::sql_types::Bool, _>(task.critical)
+                .bind::<diesel::sql_types::Text, _>(task.status.as_str())
+                .bind::<diesel::sql_types::Timestamptz, _>(task.created_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(inserted > 0)
+    }
+
+    /// A task by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<AcknowledgmentTask>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.acknowledgment_tasks WHERE id = $1", ACKNOWLEDGMENT_TASK_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<AcknowledgmentTaskRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(AcknowledgmentTask::try_from).transpose()
+    }
+
+    /// A practitioner's tasks, critical and oldest first when only pending ones are listed.
+    pub async fn for_owner(
+        &self,
+        pool: &Pool,
+        owner_id: Id,
+        pending_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<AcknowledgmentTask>> {
+        let conn = pool.get().await?;
+        let query = if pending_only {
+            format!(
+                "SELECT {} FROM emr.acknowledgment_tasks WHERE owner_id = $1 AND status = 'requested' \
+                 ORDER BY critical DESC, created_at LIMIT $2 OFFSET $3",
+                ACKNOWLEDGMENT_TASK_COLUMNS
+            )
+        } else {
+            format!(
+                "SELECT {} FROM emr.acknowledgment_tasks WHERE owner_id = $1 \
+                 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+                ACKNOWLEDGMENT_TASK_COLUMNS
+            )
+        };
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(owner_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<AcknowledgmentTaskRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(AcknowledgmentTask::try_from).collect()
+    }
+
+    /// Record an acknowledgment, unless the task was acknowledged concurrently.
+    pub async fn acknowledge(&self, pool: &Pool, task: &AcknowledgmentTask) -> Result<bool> {
+        let conn = pool.get().await?;
+        let task = task.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.acknowledgment_tasks \
+                     SET status = $2, acknowledged_at = $3, acknowledged_by = $4, note = $5 \
+                     WHERE id = $1 AND status = 'requested'",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(task.id)
+                .bind::<diesel::sql_types::Text, _>(task.status.as_str())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(task.acknowledged_at)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(task.acknowledged_by)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(task.note.as_deref())
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+
+    /// Acknowledgment latency of results that arrived in `[from, to)`.
+    pub async fn latency(
+        &self,
+        pool: &Pool,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        critical_sla_minutes: i64,
+    ) -> Result<Vec<AcknowledgmentLatencyModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(ACKNOWLEDGMENT_LATENCY_QUERY)
+                    .bind::<diesel::sql_types::Timestamptz, _>(from)
+                    .bind::<diesel::sql_types::Timestamptz, _>(to)
+                    .bind::<diesel::sql_types::Double, _>(critical_sla_minutes as f64)
+                    .load::<AcknowledgmentLatencyRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(AcknowledgmentLatencyModel::from).collect())
+    }
+}
+
+const CARE_TEAM_COLUMNS: &str = "id, patient_id, name, status, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct CareTeamRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct CareTeamParticipantRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    care_team_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    practitioner_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    role: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    period_start: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    period_end: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl TryFrom<CareTeamParticipantRow> for CareTeamParticipant {
+    type Error = ApiError;
+
+    fn try_from(row: CareTeamParticipantRow) -> Result<Self> {
+        let role = CareTeamRole::parse(&row.role)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown care team role '{}'", row.role)))?;
+
+        Ok(Self {
+            practitioner_id: row.practitioner_id,
+            role,
+            start: row.period_start,
+            end: row.period_end,
+        })
+    }
+}
+
+/// Build teams from their rows and all of their participant rows
+fn care_teams(rows: Vec<CareTeamRow>, participants: Vec<CareTeamParticipantRow>) -> Result<Vec<CareTeam>> {
+    let mut teams = rows
+        .into_iter()
+        .map(|row| {
+            let status = CareTeamStatus::parse(&row.status)
+                .ok_or_else(|| ApiError::internal_error(&format!("Unknown care team status '{}'", row.status)))?;
+            Ok(CareTeam {
+                metadata: EntityMetadata {
+                    id: row.id,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                    version: row.version as u64,
+                },
+                patient_id: row.patient_id,
+                name: row.name,
+                status,
+                participants: Vec::new(),
+            })
+        })
+        .collect::<Result<Vec<CareTeam>>>()?;
+
+    for row in participants {
+        let care_team_id = row.care_team_id;
+        if let Some(team) = teams.iter_mut().find(|team| team.metadata.id == care_team_id) {
+            team.participants.push(CareTeamParticipant::try_from(row)?);
+        }
+    }
+    Ok(teams)
+}
+
+/// Write a team's participants, replacing any stored ones
+fn replace_participants(conn: &mut PgConnection, team: &CareTeam) -> std::result::Result<(), DieselError> {
+    diesel::sql_query("DELETE FROM emr.care_team_participants WHERE care_team_id = $1")
+        .bind::<diesel::sql_types::Uuid, _>(team.metadata.id)
+        .execute(conn)?;
+
+    for participant in &team.participants {
+        diesel::sql_query(
+            "INSERT INTO emr.care_team_participants \
+             (care_team_id, practitioner_id, role, period_start, period_end) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind::<diesel::sql_types::Uuid, _>(team.metadata.id)
+        .bind::<diesel::sql_types::Uuid, _>(participant.practitioner_id)
+        .bind::<diesel::sql_types::Text, _>(participant.role.as_str())
+        .bind::<diesel::sql_types::Timestamptz, _>(participant.start)
+        .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(participant.end)
+        .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Whether practitioner `$1` is a current participant of one of patient `$2`'s active care teams
+pub const TREATING_RELATIONSHIP_QUERY: &str = r#"
+SELECT COUNT(*) AS count
+FROM emr.care_team_participants p
+JOIN emr.care_teams t ON t.id = p.care_team_id
+WHERE p.practitioner_id = $1 AND t.patient_id = $2 AND t.status = 'active'
+  AND p.period_start <= NOW() AND (p.period_end IS NULL OR p.period_end > NOW())
+"#;
+
+const EMERGENCY_ACCESS_COLUMNS: &str = "id, practitioner_id, patient_id, reason, granted_at, expires_at";
+
+#[derive(diesel::QueryableByName)]
+struct EmergencyAccessRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    practitioner_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    reason: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    granted_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<EmergencyAccessRow> for EmergencyAccess {
+    fn from(row: EmergencyAccessRow) -> Self {
+        Self {
+            id: row.id,
+            practitioner_id: row.practitioner_id,
+            patient_id: row.patient_id,
+            reason: row.reason,
+            granted_at: row.granted_at,
+            expires_at: row.expires_at,
+        }
+    }
+}
+
+/// Audit trail entry for emergency access; `request_id` comes from the session like the table triggers
+const INSERT_EMERGENCY_AUDIT_QUERY: &str = "INSERT INTO audit.audit_log \
+     (table_name, operation, new_values, changed_by, request_id, user_id, patient_id, purpose_of_use) \
+     VALUES ('emergency_access', $1, $2::jsonb, current_user, audit.current_request_id(), \
+     $3, $4, $5)";
+
+/// Care teams and the emergency access that stands in for them
+pub struct CareTeamRepository;
+
+impl CareTeamRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new team with its participants.
+    pub async fn insert(&self, pool: &Pool, team: &CareTeam) -> Result<()> {
+        let conn = pool.get().await?;
+        let team = team.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.care_teams (id, patient_id, name, status, version, created_at, updated_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(team.metadata.id)
+                .bind::<diesel::sql_types::Uuid, _>(team.patient_id)
+                .bind::<diesel::sql_types::Text, _>(&team.name)
+                .bind::<diesel::sql_types::Text, _>(team.status.as_str())
+                .bind::<diesel::sql_types::BigInt, _>(team.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(team.metadata.created_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(team.metadata.updated_at)
+                .execute(conn)?;
+
+                replace_participants(conn, &team)
+            })
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A team by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<CareTeam>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.care_teams WHERE id = $1", CARE_TEAM_COLUMNS);
+
+        let (rows, participants) = conn
+            .interact(move |conn| {
+                let rows = diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<CareTeamRow>(conn)?;
+                let participants = diesel::sql_query(
+                    "SELECT care_team_id, practitioner_id, role, period_start, period_end \
+                     FROM emr.care_team_participants WHERE care_team_id = $1 ORDER BY period_start",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(id)
+                .load::<CareTeamParticipantRow>(conn)?;
+                Ok::<_, DieselError>((rows, participants))
+            })
+            .await??;
+
+        Ok(care_teams(rows, participants)?.into_iter().next())
+    }
+
+    /// A patient's teams, active ones first.
+    pub async fn for_patient(&self, pool: &Pool, patient_id: Id) -> Result<Vec<CareTeam>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.care_teams WHERE patient_id = $1 ORDER BY status = 'active' DESC, created_at",
+            CARE_TEAM_COLUMNS
+        );
+
+        let (rows, participants) = conn
+            .interact(move |conn| {
+                let rows = diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .load::<CareTeamRow>(conn)?;
+                let participants = diesel::sql_query(
+                    "SELECT p.care_team_id, p.practitioner_id, p.role, p.period_start, p.period_end \
+                     FROM emr.care_team_participants p JOIN emr.care_teams t ON t.id = p.care_team_id \
+                     WHERE t.patient_id = $1 ORDER BY p.period_start",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .load::<CareTeamParticipantRow>(conn)?;
+                Ok::<_, DieselError>((rows, participants))
+            })
+            .await??;
+
+        care_teams(rows, participants)
+    }
+
+    /// Write a changed team, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, team: &CareTeam, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let team = team.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let updated = diesel::sql_query(
+                        "UPDATE emr.care_teams SET name = $3, status = $4, version = $5, updated_at = $6 \
+                         WHERE id = $1 AND version = $2",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(team.metadata.id)
+                    .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                    .bind::<diesel::sql_types::Text, _>(&team.name)
+                    .bind::<diesel::sql_types::Text, _>(team.status.as_str())
+                    .bind::<diesel::sql_types::BigInt, _>(team.metadata.version as i64)
+                    .bind::<diesel::sql_types::Timestamptz, _>(team.metadata.updated_at)
+                    .execute(conn)?;
+
+                    if updated > 0 {
+                        replace_participants(conn, &team)?;
+                    }
+                    Ok::<_, DieselError>(updated > 0)
+                })
+            })
+            .await??;
+
+        Ok(updated)
+    }
+
+    /// Whether a practitioner currently cares for a patient through an active team.
+    pub async fn has_treating_relationship(&self, pool: &Pool, practitioner_id: Id, patient_id: Id) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(TREATING_RELATIONSHIP_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(practitioner_id)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .get_result::<CountRow>(conn)
+            })
+            .await??;
+
+        Ok(row.count > 0)
+    }
+
+    /// Store an emergency access grant and audit it as a `BREAK_GLASS` event.
+    pub async fn insert_emergency_access(&self, pool: &Pool, grant: &EmergencyAccess) -> Result<()> {
+        let conn = pool.get().await?;
+        let audited = serde_json::to_string(grant)?;
+        let grant = grant.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.emergency_access \
+                     (id, practitioner_id, patient_id, reason, granted_at, expires_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(grant.id)
+                .bind::<diesel::sql_types::Uuid, _>(grant.practitioner_id)
+                .bind::<diesel::sql_types::Uuid, _>(grant.patient_id)
+                .bind::<diesel::sql_types::Text, _>(&grant.reason)
+                .bind::<diesel::sql_types::Timestamptz, _>(grant.granted_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(grant.expires_at)
+                .execute(conn)?;
+
+                diesel::sql_query(INSERT_EMERGENCY_AUDIT_QUERY)
+                    .bind::<diesel::sql_types::Text, _>("BREAK_GLASS")
+                    .bind::<diesel::sql_types::Text, _>(&audited)
+                    .bind::<diesel::sql_types::Uuid, _>(grant.practitioner_id)
+                    .bind::<diesel::sql_types::Uuid, _>(grant.patient_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(None::<String>)
+                    .execute(conn)
+            })
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// The practitioner's latest unexpired emergency access grant for a patient.
+    pub async fn active_emergency_access(
+        &self,
+        pool: &Pool,
+        practitioner_id: Id,
+        patient_id: Id,
+    ) -> Result<Option<EmergencyAccess>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.emergency_access \
+             WHERE practitioner_id = $1 AND patient_id = $2 AND granted_at <= NOW() AND expires_at > NOW() \
+             ORDER BY expires_at DESC LIMIT 1",
+            EMERGENCY_ACCESS_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(practitioner_id)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .load::<EmergencyAccessRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().next().map(EmergencyAccess::from))
+    }
+
+    /// Audit an access to patient data made under an emergency access grant.
+    pub async fn record_emergency_read(&self, pool: &Pool, grant_id: Id, access: &AuditedAccess<'_>) -> Result<()> {
+        let conn = pool.get().await?;
+        let audited = serde_json::json!({
+            "grant_id": grant_id,
+            "path": access.path,
+            "sensitive": access.sensitive,
+        })
+        .to_string();
+        let (action, user_id, patient_id) = (access.action, access.user_id, access.patient_id);
+        let purpose = access.purpose_of_use.map(|purpose| purpose.as_str());
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_EMERGENCY_AUDIT_QUERY)
+                .bind::<diesel::sql_types::Text, _>(action)
+                .bind::<diesel::sql_types::Text, _>(&audited)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(user_id)
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(purpose)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+const REFERRAL_COLUMNS: &str = "id, patient_id, requester_id, source_organization_id, target_organization_id, reason, \
+     priority, status, document_ids, sent_at, responded_at, decline_reason, completed_at, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct ReferralRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    requester_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    source_organization_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    target_organization_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    reason: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    priority: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Uuid>)]
+    document_ids: Vec<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    sent_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    responded_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    decline_reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ReferralRow> for Referral {
+    type Error = ApiError;
+
+    fn try_from(row: ReferralRow) -> Result<Self> {
+        let priority = RequestPriority::parse(&row.priority)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown referral priority '{}'", row.priority)))?;
+        let status = ReferralStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown referral status '{}'", row.status)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            patient_id: row.patient_id,
+            requester_id: row.requester_id,
+            source_organization_id: row.source_organization_id,
+            target_organization_id: row.target_organization_id,
+            reason: row.reason,
+            priority,
+            status,
+            document_ids: row.document_ids,
+            sent_at: row.sent_at,
+            responded_at: row.responded_at,
+            decline_reason: row.decline_reason,
+            completed_at: row.completed_at,
+        })
+    }
+}
+
+/// Referrals sent (`{direction}` = `source`) or received (`target`) by organization `$1`,
+/// open ones first, most urgent and oldest first; `$2` limits them to open referrals
+const REFERRAL_WORKLIST_QUERY: &str = r#"
+SELECT {columns}
+FROM emr.referrals
+WHERE {direction}_organization_id = $1
+  AND (NOT $2 OR status IN ('draft', 'sent', 'accepted'))
+ORDER BY status IN ('draft', 'sent', 'accepted') DESC,
+         CASE priority WHEN 'stat' THEN 0 WHEN 'asap' THEN 1 WHEN 'urgent' THEN 2 ELSE 3 END,
+         COALESCE(sent_at, created_at)
+LIMIT $3 OFFSET $4
+"#;
+
+/// Worklist of referrals an organization received or sent
+fn referral_worklist_query(incoming: bool) -> String {
+    REFERRAL_WORKLIST_QUERY
+        .replace("{columns}", REFERRAL_COLUMNS)
+        .replace("{direction}", if incoming { "target" } else { "source" })
+}
+
+/// Referrals between organizations
+pub struct ReferralRepository;
+
+impl ReferralRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new referral.
+    pub async fn insert(&self, pool: &Pool, referral: &Referral) -> Result<()> {
+        let conn = pool.get().await?;
+        let referral = referral.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.referrals \
+                 (id, patient_id, requester_id, source_organization_id, target_organization_id, reason, priority, \
+                 status, document_ids, sent_at, responded_at, decline_reason, completed_at, version, created_at, \
+                 updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(referral.metadata.id)
+            .bind::<diesel::sql_types::Uuid, _>(referral.patient_id)
+            .bind::<diesel::sql_types::Uuid, _>(referral.requester_id)
+            .bind::<diesel::sql_types::Uuid, _>(referral.source_organization_id)
+            .bind::<diesel::sql_types::Uuid, _>(referral.target_organization_id)
+            .bind::<diesel::sql_types::Text, _>(&referral.reason)
+            .bind::<diesel::sql_types::Text, _>(referral.priority.as_str())
+            .bind::<diesel::sql_types::Text, _>(referral.status.as_str())
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&referral.document_ids)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(referral.sent_at)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(referral.responded_at)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(referral.decline_reason.as_deref())
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(referral.completed_at)
+            .bind::<diesel::sql_types::BigInt, _>(referral.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(referral.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(referral.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A referral by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<Referral>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.referrals WHERE id = $1", REFERRAL_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<ReferralRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(Referral::try_from).transpose()
+    }
+
+    /// A patient's referrals, newest first.
+    pub async fn for_patient(&self, pool: &Pool, patient_id: Id, limit: u32, offset: u32) -> Result<Vec<Referral>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.referrals WHERE patient_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+            REFERRAL_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ReferralRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Referral::try_from).collect()
+    }
+
+    /// An organization's incoming or outgoing referrals, open ones first.
+    pub async fn worklist(
+        &self,
+        pool: &Pool,
+        organization_id: Id,
+        incoming: bool,
+        open_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Referral>> {
+        let conn = pool.get().await?;
+        let query = referral_worklist_query(incoming);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(organization_id)
+                    .bind::<diesel::sql_types::Bool, _>(open_only)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ReferralRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Referral::try_from).collect()
+    }
+
+    /// Write a changed referral, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, referral: &Referral, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let referral = referral.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.referrals \
+                     SET status = $3, document_ids = $4, sent_at = $5, responded_at = $6, decline_reason = $7, \
+                         completed_at = $8, version = $9, updated_at = $10 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(referral.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Text, _>(referral.status.as_str())
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&referral.document_ids)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(referral.sent_at)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(referral.responded_at)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(referral.decline_reason.as_deref())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(referral.completed_at)
+                .bind::<diesel::sql_types::BigInt, _>(referral.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(referral.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+}
+
+const QUESTIONNAIRE_COLUMNS: &str = "id, url, title, status, items::text AS items, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct QuestionnaireRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    url: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    title: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    items: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<QuestionnaireRow> for Questionnaire {
+    type Error = ApiError;
+
+    fn try_from(row: QuestionnaireRow) -> Result<Self> {
+        let status = QuestionnaireStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown questionnaire status '{}'", row.status)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            url: row.url,
+            title: row.title,
+            status,
+            items: serde_json::from_str(&row.items)?,
+        })
+    }
+}
+
+const QUESTIONNAIRE_RESPONSE_COLUMNS: &str = "id, questionnaire_id, patient_id, author_id, status, \
+     answers::text AS answers, authored, score, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct QuestionnaireResponseRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    questionnaire_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    author_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    answers: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    authored: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Integer>)]
+    score: Option<i32>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<QuestionnaireResponseRow> for QuestionnaireResponse {
+    type Error = ApiError;
+
+    fn try_from(row: QuestionnaireResponseRow) -> Result<Self> {
+        let status = ResponseStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown response status '{}'", row.status)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            questionnaire_id: row.questionnaire_id,
+            patient_id: row.patient_id,
+            author_id: row.author_id,
+            status,
+            answers: serde_json::from_str(&row.answers)?,
+            authored: row.authored,
+            score: row.score,
+        })
+    }
+}
+
+/// Questionnaires and the responses to them
+pub struct QuestionnaireRepository;
+
+impl QuestionnaireRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new questionnaire.
+    pub async fn insert(&self, pool: &Pool, questionnaire: &Questionnaire) -> Result<()> {
+        let conn = pool.get().await?;
+        let items = serde_json::to_string(&questionnaire.items)?;
+        let questionnaire = questionnaire.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.questionnaires (id, url, title, status, items, version, created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, $5::jsonb, $6, $7, $8)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(questionnaire.metadata.id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(questionnaire.url.as_deref())
+            .bind::<diesel::sql_types::Text, _>(&questionnaire.title)
+            .bind::<diesel::sql_types::Text, _>(questionnaire.status.as_str())
+            .bind::<diesel::sql_types::Text, _>(&items)
+            .bind::<diesel::sql_types::BigInt, _>(questionnaire.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(questionnaire.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(questionnaire.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A questionnaire by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<Questionnaire>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.questionnaires WHERE id = $1", QUESTIONNAIRE_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<QuestionnaireRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(Questionnaire::try_from).transpose()
+    }
+
+    /// Questionnaires by title, optionally only those with a status.
+    pub async fn list(
+        &self,
+        pool: &Pool,
+        status: Option<QuestionnaireStatus>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Questionnaire>> {
+        let conn = pool.get().await?;
+        let status = status.map(|status| status.as_str());
+        let query = format!(
+            "SELECT {} FROM emr.questionnaires WHERE ($1::text IS NULL OR status = $1) \
+             ORDER BY title, created_at LIMIT $2 OFFSET $3",
+            QUESTIONNAIRE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(status)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<QuestionnaireRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Questionnaire::try_from).collect()
+    }
+
+    /// Write a questionnaire's new status, provided nobody else changed it since it was read at `previous_version`.
+    ///
+    /// The form itself is never rewritten, so stored responses keep matching it.
+    pub async fn update_status(
+        &self,
+        pool: &Pool,
+        questionnaire: &Questionnaire,
+        previous_version: u64,
+    ) -> Result<bool> {
+        let conn = pool.get().await?;
+        let questionnaire = questionnaire.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.questionnaires SET status = $3, version = $4, updated_at = $5 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(questionnaire.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Text, _>(questionnaire.status.as_str())
+                .bind::<diesel::sql_types::BigInt, _>(questionnaire.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(questionnaire.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+
+    /// Store a new response.
+    pub async fn insert_response(&self, pool: &Pool, response: &QuestionnaireResponse) -> Result<()> {
+        let conn = pool.get().await?;
+        let answers = serde_json::to_string(&response.answers)?;
+        let response = response.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.questionnaire_responses \
+                 (id, questionnaire_id, patient_id, author_id, status, answers, authored, score, version, created_at, \
+                 updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7, $8, $9, $10, $11)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(response.metadata.id)
+            .bind::<diesel::sql_types::Uuid, _>(response.questionnaire_id)
+            .bind::<diesel::sql_types::Uuid, _>(response.patient_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(response.author_id)
+            .bind::<diesel::sql_types::Text, _>(response.status.as_str())
+            .bind::<diesel::sql_types::Text, _>(&answers)
+            .bind::<diesel::sql_types::Timestamptz, _>(response.authored)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Integer>, _>(response.score)
+            .bind::<diesel::sql_types::BigInt, _>(response.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(response.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(response.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A response by id.
+    pub async fn find_response(&self, pool: &Pool, id: Id) -> Result<Option<QuestionnaireResponse>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.questionnaire_responses WHERE id = $1",
+            QUESTIONNAIRE_RESPONSE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<QuestionnaireResponseRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(QuestionnaireResponse::try_from).transpose()
+    }
+
+    /// A patient's responses, most recently answered first, optionally to one questionnaire.
+    pub async fn responses_for_patient(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        questionnaire_id: Option<Id>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<QuestionnaireResponse>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.questionnaire_responses \
+             WHERE patient_id = $1 AND ($2::uuid IS NULL OR questionnaire_id = $2) \
+             ORDER BY authored DESC LIMIT $3 OFFSET $4",
+            QUESTIONNAIRE_RESPONSE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(questionnaire_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<QuestionnaireResponseRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(QuestionnaireResponse::try_from).collect()
+    }
+
+    /// Write revised answers, provided nobody else changed the response since it was read at `previous_version`.
+    pub async fn update_response(
+        &self,
+        pool: &Pool,
+        response: &QuestionnaireResponse,
+        previous_version: u64,
+    ) -> Result<bool> {
+        let conn = pool.get().await?;
+        let answers = serde_json::to_string(&response.answers)?;
+        let response = response.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.questionnaire_responses \
+                     SET status = $3, answers = $4::jsonb, authored = $5, score = $6, version = $7, updated_at = $8 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(response.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Text, _>(response.status.as_str())
+                .bind::<diesel::sql_types::Text, _>(&answers)
+                .bind::<diesel::sql_types::Timestamptz, _>(response.authored)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Integer>, _>(response.score)
+                .bind::<diesel::sql_types::BigInt, _>(response.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(response.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+}
+
+const MEDICATION_REQUEST_COLUMNS: &str = "id, patient_id, encounter_id, medication_code, medication_display, \
+     product_identifiers, dose_quantity, dose_unit, route, frequency_hours, as_needed, start_at, end_at, status, \
+     requester_id, reason, authored_on, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct MedicationRequestRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    medication_code: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    medication_display: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+    product_identifiers: Vec<String>,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    dose_quantity: f64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    dose_unit: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    route: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Integer>)]
+    frequency_hours: Option<i32>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    as_needed: bool,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    start_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    end_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    requester_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    authored_on: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<MedicationRequestRow> for MedicationRequest {
+    type Error = ApiError;
+
+    fn try_from(row: MedicationRequestRow) -> Result<Self> {
+        let status = MedicationRequestStatus::parse(&row.status).ok_or_else(|| {
+            ApiError::internal_error(&format!("Unknown medication request status '{}'", row.status))
+        })?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            patient_id: row.patient_id,
+            encounter_id: row.encounter_id,
+            medication_code: row.medication_code,
+            medication_display: row.medication_display,
+            product_identifiers: row.product_identifiers,
+            dose_quantity: row.dose_quantity,
+            dose_unit: row.dose_unit,
+            route: row.route,
+            frequency_hours: row.frequency_hours.map(|hours| hours as u32),
+            as_needed: row.as_needed,
+            start: row.start_at,
+            end: row.end_at,
+            status,
+            requester_id: row.requester_id,
+            reason: row.reason,
+            authored_on: row.authored_on,
+        })
+    }
+}
+
+/// Medication orders (FHIR MedicationRequest)
+pub struct MedicationRequestRepository;
+
+impl MedicationRequestRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new medication order.
+    pub async fn insert(&self, pool: &Pool, request: &MedicationRequest) -> Result<()> {
+        let conn = pool.get().await?;
+        let request = request.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.medication_requests \
+                     (id, patient_id, encounter_id, medication_code, medication_display, product_identifiers, \
+                     dose_quantity, dose_unit, route, frequency_hours, as_needed, start_at, end_at, status, \
+                     requester_id, reason, authored_on, version, created_at, updated_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, \
+                     $20)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(request.metadata.id)
+                .bind::<diesel::sql_types::Uuid, _>(request.patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(request.encounter_id)
+                .bind::<diesel::sql_types::Text, _>(&request.medication_code)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(request.medication_display.as_deref())
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&request.product_identifiers)
+                .bind::<diesel::sql_types::Double, _>(request.dose_quantity)
+                .bind::<diesel::sql_types::Text, _>(&request.dose_unit)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(request.route.as_deref())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Integer>, _>(
+                    request.frequency_hours.map(|hours| hours as i32),
+                )
+                .bind::<diesel::sql_types::Bool, _>(request.as_needed)
+                .bind::<diesel::sql_types::Timestamptz, _>(request.start)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(request.end)
+                .bind::<diesel::sql_types::Text, _>(request.status.as_str())
+                .bind::<diesel::sql_types::Uuid, _>(request.requester_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(request.reason.as_deref())
+                .bind::<diesel::sql_types::Timestamptz, _>(request.authored_on)
+                .bind::<diesel::sql_types::BigInt, _>(request.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(request.metadata.created_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(request.metadata.updated_at)
+                .execute(conn)?;
+                let event = SummaryEvent::from_medication_request(&request);
+                apply_summary_events(conn, BTreeMap::from([(request.patient_id, vec![event])]))
+            })
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A medication order by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<MedicationRequest>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.medication_requests WHERE id = $1", MEDICATION_REQUEST_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<MedicationRequestRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(MedicationRequest::try_from).transpose()
+    }
+
+    /// A patient's medication orders, newest first.
+    pub async fn for_patient(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<MedicationRequest>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.medication_requests WHERE patient_id = $1 \
+             ORDER BY authored_on DESC LIMIT $2 OFFSET $3",
+            MEDICATION_REQUEST_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<MedicationRequestRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(MedicationRequest::try_from).collect()
+    }
+
+    /// Every medication order placed in an encounter, by start.
+    pub async fn for_encounter(&self, pool: &Pool, encounter_id: Id) -> Result<Vec<MedicationRequest>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.medication_requests WHERE encounter_id = $1 ORDER BY start_at, medication_code",
+            MEDICATION_REQUEST_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(encounter_id)
+                    .load::<MedicationRequestRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(MedicationRequest::try_from).collect()
+    }
+
+    /// Write a changed order, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, request: &MedicationRequest, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let request = request.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let updated = diesel::sql_query(
+                        "UPDATE emr.medication_requests \
+                         SET status = $3, end_at = $4, version = $5, updated_at = $6 \
+                         WHERE id = $1 AND version = $2",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(request.metadata.id)
+                    .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                    .bind::<diesel::sql_types::Text, _>(request.status.as_str())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(request.end)
+                    .bind::<diesel::sql_types::BigInt, _>(request.metadata.version as i64)
+                    .bind::<diesel::sql_types::Timestamptz, _>(request.metadata.updated_at)
+                    .execute(conn)?;
+                    if updated > 0 {
+                        let event = SummaryEvent::from_medication_request(&request);
+                        apply_summary_events(conn, BTreeMap::from([(request.patient_id, vec![event])]))?;
+                    }
+                    Ok::<_, DieselError>(updated)
+                })
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+}
+
+const MEDICATION_ADMINISTRATION_COLUMNS: &str = "id, request_id, patient_id, encounter_id, outcome, scheduled_for, \
+     occurred_at, performer_id, dose_quantity, dose_unit, reason, note, barcode_patient, barcode_medication, \
+     barcode_verified_at, barcode_override, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct MedicationAdministrationRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    request_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    encounter_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    outcome: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    scheduled_for: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    occurred_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    performer_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    dose_quantity: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    dose_unit: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    note: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    barcode_patient: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    barcode_medication: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    barcode_verified_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    barcode_override: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<MedicationAdministrationRow> for MedicationAdministration {
+    type Error = ApiError;
+
+    fn try_from(row: MedicationAdministrationRow) -> Result<Self> {
+        let outcome = AdministrationOutcome::parse(&row.outcome).ok_or_else(|| {
+            ApiError::internal_error(&format!("Unknown administration outcome '{}'", row.outcome))
+        })?;
+        let barcode = match (row.barcode_patient, row.barcode_medication, row.barcode_verified_at) {
+            (Some(patient), Some(medication), Some(verified_at)) => Some(BarcodeVerification {
+                scan: BarcodeScan { patient, medication },
+                verified_at,
+            }),
+            _ => None,
+        };
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            request_id: row.request_id,
+            patient_id: row.patient_id,
+            encounter_id: row.encounter_id,
+            outcome,
+            scheduled_for: row.scheduled_for,
+            occurred_at: row.occurred_at,
+            performer_id: row.performer_id,
+            dose_quantity: row.dose_quantity,
+            dose_unit: row.dose_unit,
+            reason: row.reason,
+            note: row.note,
+            barcode,
+            barcode_override: row.barcode_override,
+        })
+    }
+}
+
+/// Documented doses (FHIR MedicationAdministration)
+pub struct MedicationAdministrationRepository;
+
+impl MedicationAdministrationRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a documented dose. Returns `false` when the scheduled dose was
+    /// already documented.
+    pub async fn insert(&self, pool: &Pool, administration: &MedicationAdministration) -> Result<bool> {
+        let conn = pool.get().await?;
+        let administration = administration.clone();
+
+        let inserted = conn
+            .interact(move |conn| {
+                let barcode = administration.barcode.as_ref();
+                diesel::sql_query(
+                    "INSERT INTO emr.medication_administrations \
+                     (id, request_id, patient_id, encounter_id, outcome, scheduled_for, occurred_at, performer_id, \
+                     dose_quantity, dose_unit, reason, note, barcode_patient, barcode_medication, \
+                     barcode_verified_at, barcode_override, version, created_at, updated_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19) \
+                     ON CONFLICT DO NOTHING",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(administration.metadata.id)
+                .bind::<diesel::sql_types::Uuid, _>(administration.request_id)
+                .bind::<diesel::sql_types::Uuid, _>(administration.patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(administration.encounter_id)
+                .bind::<diesel::sql_types::Text, _>(administration.outcome.as_str())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(administration.scheduled_for)
+                .bind::<diesel::sql_types::Timestamptz, _>(administration.occurred_at)
+                .bind::<diesel::sql_types::Uuid, _>(administration.performer_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(administration.dose_quantity)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(administration.dose_unit.as_deref())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(administration.reason.as_deref())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(administration.note.as_deref())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(
+                    barcode.map(|barcode| barcode.scan.patient.as_str()),
+                )
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(
+                    barcode.map(|barcode| barcode.scan.medication.as_str()),
+                )
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(
+                    barcode.map(|barcode| barcode.verified_at),
+                )
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(
+                    administration.barcode_override.as_deref(),
+                )
+                .bind::<diesel::sql_types::BigInt, _>(administration.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(administration.metadata.created_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(administration.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(inserted > 0)
+    }
+
+    /// A documented dose by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<MedicationAdministration>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.medication_administrations WHERE id = $1",
+            MEDICATION_ADMINISTRATION_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<MedicationAdministrationRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(MedicationAdministration::try_from).transpose()
+    }
+
+    /// Every dose documented against an order, oldest first.
+    pub async fn for_request(&self, pool: &Pool, request_id: Id) -> Result<Vec<MedicationAdministration>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.medication_administrations WHERE request_id = $1 ORDER BY occurred_at",
+            MEDICATION_ADMINISTRATION_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(request_id)
+                    .load::<MedicationAdministrationRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(MedicationAdministration::try_from).collect()
+    }
+
+    /// Doses documented against an encounter's orders, oldest first.
+    pub async fn for_encounter_requests(&self, pool: &Pool, encounter_id: Id) -> Result<Vec<MedicationAdministration>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.medication_administrations WHERE request_id IN \
+             (SELECT id FROM emr.medication_requests WHERE encounter_id = $1) ORDER BY occurred_at",
+            MEDICATION_ADMINISTRATION_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(encounter_id)
+                    .load::<MedicationAdministrationRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(MedicationAdministration::try_from).collect()
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct EncounterCodeRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    kind: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    sequence: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    code: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    display: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    units: i32,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+    modifiers: Vec<String>,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Integer>)]
+    diagnosis_pointers: Vec<i32>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    origin: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    suggested_code: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    override_reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    coded_by: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    coded_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<EncounterCodeRow> for EncounterCode {
+    type Error = ApiError;
+
+    fn try_from(row: EncounterCodeRow) -> Result<Self> {
+        let kind = CodeKind::parse(&row.kind)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown code kind '{}'", row.kind)))?;
+        let origin = CodeOrigin::parse(&row.origin)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown code origin '{}'", row.origin)))?;
+
+        Ok(Self {
+            kind,
+            code: row.code,
+            display: row.display,
+            sequence: row.sequence as u32,
+            units: row.units as u32,
+            modifiers: row.modifiers,
+            diagnosis_pointers: row.diagnosis_pointers.into_iter().map(|pointer| pointer as u32).collect(),
+            origin,
+            suggested_code: row.suggested_code,
+            override_reason: row.override_reason,
+        })
+    }
+}
+
+/// Finalized billing codes of encounters
+pub struct EncounterCodeRepository;
+
+impl EncounterCodeRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Replace an encounter's codes with a newly finalized coding.
+    pub async fn replace(&self, pool: &Pool, coding: &EncounterCoding) -> Result<()> {
+        let conn = pool.get().await?;
+        let coding = coding.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query("DELETE FROM emr.encounter_codes WHERE encounter_id = $1")
+                    .bind::<diesel::sql_types::Uuid, _>(coding.encounter_id)
+                    .execute(conn)?;
+
+                for code in &coding.codes {
+                    let pointers: Vec<i32> = code.diagnosis_pointers.iter().map(|&pointer| pointer as i32).collect();
+                    diesel::sql_query(
+                        "INSERT INTO emr.encounter_codes \
+                         (encounter_id, kind, sequence, patient_id, code, display, units, modifiers, \
+                         diagnosis_pointers, origin, suggested_code, override_reason, coded_by, coded_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(coding.encounter_id)
+                    .bind::<diesel::sql_types::Text, _>(code.kind.as_str())
+                    .bind::<diesel::sql_types::Integer, _>(code.sequence as i32)
+                    .bind::<diesel::sql_types::Uuid, _>(coding.patient_id)
+                    .bind::<diesel::sql_types::Text, _>(&code.code)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(code.display.as_deref())
+                    .bind::<diesel::sql_types::Integer, _>(code.units as i32)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&code.modifiers)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Integer>, _>(&pointers)
+                    .bind::<diesel::sql_types::Text, _>(code.origin.as_str())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(code.suggested_code.as_deref())
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(code.override_reason.as_deref())
+                    .bind::<diesel::sql_types::Uuid, _>(coding.coded_by)
+                    .bind::<diesel::sql_types::Timestamptz, _>(coding.coded_at)
+                    .execute(conn)?;
+                }
+                let event = SummaryEvent::from_coding(&coding);
+                apply_summary_events(conn, BTreeMap::from([(coding.patient_id, vec![event])]))
+            })
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// An encounter's finalized coding, diagnoses then procedures in sequence.
+    pub async fn for_encounter(&self, pool: &Pool, encounter_id: Id) -> Result<Option<EncounterCoding>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT patient_id, kind, sequence, code, display, units, modifiers, diagnosis_pointers, origin, \
+                     suggested_code, override_reason, coded_by, coded_at \
+                     FROM emr.encounter_codes WHERE encounter_id = $1 ORDER BY kind, sequence",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(encounter_id)
+                .load::<EncounterCodeRow>(conn)
+            })
+            .await??;
+
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        let (patient_id, coded_by, coded_at) = (first.patient_id, first.coded_by, first.coded_at);
+        Ok(Some(EncounterCoding {
+            encounter_id,
+            patient_id,
+            codes: rows.into_iter().map(EncounterCode::try_from).collect::<Result<_>>()?,
+            coded_by,
+            coded_at,
+        }))
+    }
+}
+
+const COVERAGE_COLUMNS: &str = "id, patient_id, status, priority, plan_type, payer_name, payer_id, member_id, \
+     group_number, plan_name, relationship, subscriber::text AS subscriber, start_date, end_date, version, \
+     created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct CoverageRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    priority: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    plan_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    payer_name: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    payer_id: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    member_id: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    group_number: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    plan_name: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    relationship: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    subscriber: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Date>)]
+    start_date: Option<chrono::NaiveDate>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Date>)]
+    end_date: Option<chrono::NaiveDate>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<CoverageRow> for Coverage {
+    type Error = ApiError;
+
+    fn try_from(row: CoverageRow) -> Result<Self> {
+        let status = CoverageStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown coverage status '{}'", row.status)))?;
+        let plan_type = PlanType::parse(&row.plan_type)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown plan type '{}'", row.plan_type)))?;
+        let relationship = SubscriberRelationship::parse(&row.relationship).ok_or_else(|| {
+            ApiError::internal_error(&format!("Unknown subscriber relationship '{}'", row.relationship))
+        })?;
+
+        Ok(Coverage {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            patient_id: row.patient_id,
+            status,
+            priority: row.priority as u32,
+            plan_type,
+            payer_name: row.payer_name,
+            payer_id: row.payer_id,
+            member_id: row.member_id,
+            group_number: row.group_number,
+            plan_name: row.plan_name,
+            relationship,
+            subscriber: row.subscriber.as_deref().map(serde_json::from_str).transpose()?,
+            start: row.start_date,
+            end: row.end_date,
+        })
+    }
+}
+
+/// Patients' health plans
+pub struct CoverageRepository;
+
+impl CoverageRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new coverage.
+    pub async fn insert(&self, pool: &Pool, coverage: &Coverage) -> Result<()> {
+        let conn = pool.get().await?;
+        let coverage = coverage.clone();
+        let subscriber = coverage.subscriber.as_ref().map(serde_json::to_string).transpose()?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.coverages \
+                 (id, patient_id, status, priority, plan_type, payer_name, payer_id, member_id, group_number, \
+                 plan_name, relationship, subscriber, start_date, end_date, version, created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::jsonb, $13, $14, $15, $16, $17)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(coverage.metadata.id)
+            .bind::<diesel::sql_types::Uuid, _>(coverage.patient_id)
+            .bind::<diesel::sql_types::Text, _>(coverage.status.as_str())
+            .bind::<diesel::sql_types::Integer, _>(coverage.priority as i32)
+            .bind::<diesel::sql_types::Text, _>(coverage.plan_type.as_str())
+            .bind::<diesel::sql_types::Text, _>(&coverage.payer_name)
+            .bind::<diesel::sql_types::Text, _>(&coverage.payer_id)
+            .bind::<diesel::sql_types::Text, _>(&coverage.member_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(coverage.group_number.as_deref())
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(coverage.plan_name.as_deref())
+            .bind::<diesel::sql_types::Text, _>(coverage.relationship.as_str())
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(subscriber)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(coverage.start)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Date>, _>(coverage.end)
+            .bind::<diesel::sql_types::BigInt, _>(coverage.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(coverage.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(coverage.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A patient's coverages, primary first.
+    pub async fn for_patient(&self, pool: &Pool, patient_id: Id) -> Result<Vec<Coverage>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.coverages WHERE patient_id = $1 ORDER BY priority, created_at DESC",
+            COVERAGE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .load::<CoverageRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(Coverage::try_from).collect()
+    }
+}
+
+/// Exported claims with a date of service in `[$1, $2]`, optionally for one
+/// payer (`$3`), with the payments posted against them
+pub const CLAIM_RECONCILIATION_QUERY: &str = r#"
+SELECT c.claim_id, c.encounter_id, c.payer_id, (c.claim->>'service_date')::date AS service_date,
+       c.total_charge_cents AS billed_cents,
+       COUNT(p.id) AS payment_count,
+       COALESCE(SUM(p.paid_cents), 0)::bigint AS paid_cents,
+       COALESCE(SUM(p.written_off_cents), 0)::bigint AS written_off_cents,
+       COALESCE(SUM(p.patient_responsibility_cents), 0)::bigint AS patient_responsibility_cents,
+       COALESCE((ARRAY_AGG(p.denied ORDER BY p.posted_at DESC))[1], false) AS denied,
+       (SELECT COUNT(*) FROM emr.billing_tasks t WHERE t.claim_id = c.claim_id AND t.status = 'requested') AS open_tasks
+FROM emr.claims c
+LEFT JOIN emr.claim_payments p ON p.claim_id = c.claim_id
+WHERE (c.claim->>'service_date')::date BETWEEN $1 AND $2
+  AND ($3::text IS NULL OR c.payer_id = $3)
+GROUP BY c.claim_id
+ORDER BY service_date, c.claim_id
+"#;
+
+#[derive(diesel::QueryableByName)]
+struct ClaimReconciliationRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    claim_id: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    encounter_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    payer_id: String,
+    #[diesel(sql_type = diesel::sql_types::Date)]
+    service_date: chrono::NaiveDate,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    billed_cents: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    payment_count: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    paid_cents: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    written_off_cents: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    patient_responsibility_cents: i64,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    denied: bool,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    open_tasks: i64,
+}
+
+impl From<ClaimReconciliationRow> for ClaimReconciliationModel {
+    fn from(row: ClaimReconciliationRow) -> Self {
+        let balance_cents =
+            row.billed_cents - row.paid_cents - row.written_off_cents - row.patient_responsibility_cents;
+        // The latest payment decides a denial; a corrected claim paid later is not denied
+        let status = if row.payment_count == 0 {
+            "unpaid"
+        } else if row.denied {
+            "denied"
+        } else if balance_cents > 0 {
+            "partial"
+        } else {
+            "paid"
+        };
+
+        Self {
+            claim_id: row.claim_id,
+            encounter_id: row.encounter_id,
+            payer_id: row.payer_id,
+            service_date: row.service_date,
+            billed_cents: row.billed_cents,
+            paid_cents: row.paid_cents,
+            written_off_cents: row.written_off_cents,
+            patient_responsibility_cents: row.patient_responsibility_cents,
+            balance_cents,
+            status: status.to_string(),
+            payment_count: row.payment_count,
+            open_tasks: row.open_tasks,
+        }
+    }
+}
+
+const BILLING_TASK_COLUMNS: &str =
+    "id, remittance_id, claim_id, reason, detail, status, created_at, completed_at, completed_by";
+
+#[derive(diesel::QueryableByName)]
+struct BillingTaskRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    remittance_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    claim_id: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    reason: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    detail: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    completed_by: Option<uuid::Uuid>,
+}
+
+impl TryFrom<BillingTaskRow> for BillingTask {
+    type Error = ApiError;
+
+    fn try_from(row: BillingTaskRow) -> Result<Self> {
+        let reason = FollowUpReason::parse(&row.reason)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown follow-up reason '{}'", row.reason)))?;
+        let status = TaskStatus::parse(&row.status)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown task status '{}'", row.status)))?;
+
+        Ok(Self {
+            id: row.id,
+            remittance_id: row.remittance_id,
+            claim_id: row.claim_id,
+            reason,
+            detail: row.detail,
+            status,
+            created_at: row.created_at,
+            completed_at: row.completed_at,
+            completed_by: row.completed_by,
+        })
+    }
+}
+
+/// Claim payments and billing follow-up tasks
+pub struct BillingRepository;
+
+impl BillingRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Claims with a date of service in `[from, to]` and what was posted
+    /// against them.
+    pub async fn reconciliation(
+        &self,
+        pool: &Pool,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+        payer_id: Option<String>,
+    ) -> Result<Vec<ClaimReconciliationModel>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(CLAIM_RECONCILIATION_QUERY)
+                    .bind::<diesel::sql_types::Date, _>(from)
+                    .bind::<diesel::sql_types::Date, _>(to)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(payer_id)
+                    .load::<ClaimReconciliationRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(ClaimReconciliationModel::from).collect())
+    }
+
+    /// Follow-up tasks with a status, oldest first.
+    pub async fn tasks(&self, pool: &Pool, status: TaskStatus, limit: i64, offset: i64) -> Result<Vec<BillingTask>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.billing_tasks WHERE status = $1 ORDER BY created_at, id LIMIT $2 OFFSET $3",
+            BILLING_TASK_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Text, _>(status.as_str())
+                    .bind::<diesel::sql_types::BigInt, _>(limit)
+                    .bind::<diesel::sql_types::BigInt, _>(offset)
+                    .load::<BillingTaskRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(BillingTask::try_from).collect()
+    }
+
+    /// Find a follow-up task.
+    pub async fn find_task(&self, pool: &Pool, id: Id) -> Result<Option<BillingTask>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.billing_tasks WHERE id = $1", BILLING_TASK_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<BillingTaskRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(BillingTask::try_from).transpose()
+    }
+
+    /// Record a completed task; false when another request completed it first.
+    pub async fn complete_task(&self, pool: &Pool, task: &BillingTask) -> Result<bool> {
+        let conn = pool.get().await?;
+        let task = task.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.billing_tasks SET status = $2, completed_at = $3, completed_by = $4 \
+                     WHERE id = $1 AND status = 'requested'",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(task.id)
+                .bind::<diesel::sql_types::Text, _>(task.status.as_str())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>, _>(task.completed_at)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(task.completed_by)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct EncounterChargesRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    encounter_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    diagnoses: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    lines: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    captured_by: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    captured_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<EncounterChargesRow> for EncounterCharges {
+    type Error = ApiError;
+
+    fn try_from(row: EncounterChargesRow) -> Result<Self> {
+        Ok(Self {
+            encounter_id: row.encounter_id,
+            patient_id: row.patient_id,
+            diagnoses: serde_json::from_str(&row.diagnoses)?,
+            lines: serde_json::from_str(&row.lines)?,
+            captured_by: row.captured_by,
+            captured_at: row.captured_at,
+        })
+    }
+}
+
+/// Charges captured at encounters
+pub struct EncounterChargeRepository;
+
+impl EncounterChargeRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Replace an encounter's captured charges.
+    pub async fn replace(&self, pool: &Pool, charges: &EncounterCharges) -> Result<()> {
+        let conn = pool.get().await?;
+        let diagnoses = serde_json::to_string(&charges.diagnoses)?;
+        let lines = serde_json::to_string(&charges.lines)?;
+        let charges = charges.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.encounter_charges \
+                 (encounter_id, patient_id, diagnoses, lines, captured_by, captured_at) \
+                 VALUES ($1, $2, $3::jsonb, $4::jsonb, $5, $6) \
+                 ON CONFLICT (encounter_id) DO UPDATE SET diagnoses = EXCLUDED.diagnoses, lines = EXCLUDED.lines, \
+                 captured_by = EXCLUDED.captured_by, captured_at = EXCLUDED.captured_at",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(charges.encounter_id)
+            .bind::<diesel::sql_types::Uuid, _>(charges.patient_id)
+            .bind::<diesel::sql_types::Text, _>(diagnoses)
+            .bind::<diesel::sql_types::Text, _>(lines)
+            .bind::<diesel::sql_types::Uuid, _>(charges.captured_by)
+            .bind::<diesel::sql_types::Timestamptz, _>(charges.captured_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// An encounter's captured charges, if any.
+    pub async fn for_encounter(&self, pool: &Pool, encounter_id: Id) -> Result<Option<EncounterCharges>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT encounter_id, patient_id, diagnoses::text AS diagnoses, lines::text AS lines, \
+                     captured_by, captured_at FROM emr.encounter_charges WHERE encounter_id = $1",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(encounter_id)
+                .load::<EncounterChargesRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(EncounterCharges::try_from).transpose()
+    }
+}
+
+/// Audit trail entry for an upload found to be malware; `request_id` comes from the session like the table triggers
+const INSERT_MALWARE_AUDIT_QUERY: &str = "INSERT INTO audit.audit_log \
+     (table_name, operation, new_values, changed_by, request_id, user_id) \
+     VALUES ($1, 'MALWARE', $2::jsonb, current_user, audit.current_request_id(), $3)";
+
+/// Uploaded documents and their malware scans
+pub struct DocumentRepository;
+
+impl DocumentRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store an uploaded document, quarantined until it is scanned.
+    pub async fn insert(&self, pool: &Pool, document: &DocumentReferenceModel) -> Result<()> {
+        let conn = pool.get().await?;
+        let document = document.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.document_references \
+                 (id, patient_id, encounter_id, status, type_code, type_display, title, content_type, url, \
+                 size_bytes, scan_status, confidentiality, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 'pending', $11, $12)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(document.id)
+            .bind::<diesel::sql_types::Uuid, _>(document.patient_id)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(document.encounter_id)
+            .bind::<diesel::sql_types::Text, _>(&document.status)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&document.type_code)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&document.type_display)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&document.title)
+            .bind::<diesel::sql_types::Text, _>(&document.content_type)
+            .bind::<diesel::sql_types::Text, _>(&document.url)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::BigInt>, _>(document.size_bytes)
+            .bind::<diesel::sql_types::Text, _>(&document.confidentiality)
+            .bind::<diesel::sql_types::Timestamptz, _>(document.created_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A document, whatever its scan status.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<DocumentReferenceModel>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.document_references WHERE id = $1", DOCUMENT_REFERENCE_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<DocumentReferenceRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().next().map(DocumentReferenceModel::from))
+    }
+
+    /// A patient's current, clean documents that `clearance` clears, newest
+    /// first. With a query, only documents whose title, type or extracted
+    /// text match it, best match first; `query` uses web search syntax
+    /// (`"quoted phrase"`, `or`, `-excluded`).
+    pub async fn search(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        query: Option<String>,
+        clearance: Confidentiality,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DocumentSearchModel>> {
+        let conn = pool.get().await?;
+        let sql = format!(
+            "SELECT {}, \
+             ts_headline('english', t.content, q, 'MaxFragments=2, MinWords=5, MaxWords=20') AS snippet \
+             FROM emr.document_references d \
+             LEFT JOIN emr.document_texts t ON t.document_id = d.id \
+             LEFT JOIN websearch_to_tsquery('english', $2) q ON TRUE \
+             WHERE d.patient_id = $1 AND d.status = 'current' AND d.scan_status = 'clean' \
+             AND d.confidentiality = ANY($5) \
+             AND ($2::text IS NULL OR t.search_vector @@ q \
+             OR to_tsvector('english', COALESCE(d.title, '') || ' ' || COALESCE(d.type_display, '')) @@ q) \
+             ORDER BY ts_rank(t.search_vector, q) DESC NULLS LAST, d.created_at DESC \
+             LIMIT $3 OFFSET $4",
+            DOCUMENT_REFERENCE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(sql)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(query)
+                    .bind::<diesel::sql_types::BigInt, _>(limit as i64)
+                    .bind::<diesel::sql_types::BigInt, _>(offset as i64)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(clearance.cleared_labels())
+                    .load::<DocumentSearchRow>(conn)
+            })
+            .await??;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DocumentSearchModel {
+                document: DocumentReferenceModel::from(row.document),
+                snippet: row.snippet,
+            })
+            .collect())
+    }
+
+    /// Documents still in quarantine, oldest first.
+    pub async fn pending_scans(&self, pool: &Pool, limit: i64) -> Result<Vec<DocumentReferenceModel>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.document_references WHERE scan_status = 'pending' ORDER BY created_at LIMIT $1",
+            DOCUMENT_REFERENCE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::BigInt, _>(limit)
+                    .load::<DocumentReferenceRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(DocumentReferenceModel::from).collect())
+    }
+
+    /// Record the scan of a quarantined document. An infected document is
+    /// marked `entered-in-error` and the detection audited in the same
+    /// transaction. Returns `false` when the document was no longer pending.
+    pub async fn record_scan(
+        &self,
+        pool: &Pool,
+        document_id: Id,
+        verdict: &ScanVerdict,
+        scanner: &str,
+        uploaded_by: Option<Id>,
+    ) -> Result<bool> {
+        let conn = pool.get().await?;
+        let status = ScanStatus::from(verdict).as_str();
+        let signature = match verdict {
+            ScanVerdict::Infected { signature } => Some(signature.clone()),
+            ScanVerdict::Clean => None,
+        };
+        let audited = serde_json::json!({
+            "document_id": document_id,
+            "scanner": scanner,
+            "signature": signature,
+        })
+        .to_string();
+        let scanner = scanner.to_string();
+
+        let updated = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let updated = diesel::sql_query(
+                        "UPDATE emr.document_references SET scan_status = $2, scanned_at = NOW(), scanner = $3, \
+                         scan_signature = $4, \
+                         status = CASE WHEN $2 = 'infected' THEN 'entered-in-error' ELSE status END \
+                         WHERE id = $1 AND scan_status = 'pending'",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(document_id)
+                    .bind::<diesel::sql_types::Text, _>(status)
+                    .bind::<diesel::sql_types::Text, _>(&scanner)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&signature)
+                    .execute(conn)?;
+
+                    if updated > 0 && signature.is_some() {
+                        diesel::sql_query(INSERT_MALWARE_AUDIT_QUERY)
+                            .bind::<diesel::sql_types::Text, _>("document_references")
+                            .bind::<diesel::sql_types::Text, _>(&audited)
+                            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(uploaded_by)
+                            .execute(conn)?;
+                    }
+                    Ok::<_, DieselError>(updated > 0)
+                })
+            })
+            .await??;
+
+        Ok(updated)
+    }
+
+    /// Audit an upload that was rejected as malware before it was stored,
+    /// such as a patient photo.
+    pub async fn record_rejected_upload(
+        &self,
+        pool: &Pool,
+        table_name: &str,
+        details: serde_json::Value,
+        uploaded_by: Option<Id>,
+    ) -> Result<()> {
+        let conn = pool.get().await?;
+        let table_name = table_name.to_string();
+        let audited = details.to_string();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_MALWARE_AUDIT_QUERY)
+                .bind::<diesel::sql_types::Text, _>(&table_name)
+                .bind::<diesel::sql_types::Text, _>(&audited)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(uploaded_by)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
+
+/// Restricts search hits to patients the caller may see; `{patient}` is the
+/// patient id column. `$2` is a practitioner whose treating relationships
+/// bound the hits and `$3` the only patient allowed; either may be NULL.
+const SEARCH_PATIENT_FILTER: &str = "($2::uuid IS NULL OR EXISTS (\
+     SELECT 1 FROM emr.care_team_participants cp JOIN emr.care_teams ct ON ct.id = cp.care_team_id \
+     WHERE cp.practitioner_id = $2 AND ct.patient_id = {patient} AND ct.status = 'active' \
+     AND cp.period_start <= NOW() AND (cp.period_end IS NULL OR cp.period_end > NOW()))) \
+     AND ($3::uuid IS NULL OR {patient} = $3)";
+
+/// Patients a search may return
+#[derive(Debug, Clone, Copy)]
+pub enum SearchPatientFilter {
+    /// Every patient
+    All,
+    /// Patients the practitioner has a treating relationship with
+    TreatedBy(Id),
+    /// Only this patient, for portal sessions
+    Only(Id),
+}
+
+impl SearchPatientFilter {
+    fn binds(self) -> (Option<Id>, Option<Id>) {
+        match self {
+            SearchPatientFilter::All => (None, None),
+            SearchPatientFilter::TreatedBy(practitioner_id) => (Some(practitioner_id), None),
+            SearchPatientFilter::Only(patient_id) => (None, Some(patient_id)),
+        }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PatientSearchRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Date>)]
+    birth_date: Option<chrono::NaiveDate>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    gender: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    similarity: f64,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    identifier_match: bool,
+}
+
+#[derive(diesel::QueryableByName)]
+struct DirectorySearchRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    detail: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    similarity: f64,
+    /// Matched on something other than the name, e.g. a specialty
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    detail_match: bool,
+}
+
+#[derive(diesel::QueryableByName)]
+struct DocumentHitRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    title: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    type_display: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    text_rank: Option<f64>,
+}
+
+/// Candidates of each entity type for global search, scored against the query
+pub struct SearchRepository;
+
+impl SearchRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Active patients whose name contains or resembles the query, or with
+    /// an identifier equal to it.
+    pub async fn patients(
+        &self,
+        pool: &Pool,
+        query: &str,
+        filter: SearchPatientFilter,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let conn = pool.get().await?;
+        let (treated_by, only_patient) = filter.binds();
+        let sql = format!(
+            "SELECT * FROM (\
+             SELECT p.id, concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name) AS name, \
+             p.birth_date, p.gender, \
+             similarity(concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name), $1)::float8 \
+             AS similarity, \
+             EXISTS (SELECT 1 FROM jsonb_array_elements(COALESCE(p.identifiers, '[]'::jsonb)) AS identifier \
+             WHERE identifier->>'value' = $1) AS identifier_match \
+             FROM emr.patients p WHERE p.active AND {}) candidates \
+             WHERE identifier_match OR strpos(lower(name), lower($1)) > 0 OR name % $1 \
+             ORDER BY identifier_match DESC, similarity DESC LIMIT $4",
+            SEARCH_PATIENT_FILTER.replace("{patient}", "p.id")
+        );
+        let query_text = query.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(sql)
+                    .bind::<diesel::sql_types::Text, _>(query_text)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(treated_by)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(only_patient)
+                    .bind::<diesel::sql_types::BigInt, _>(limit as i64)
+                    .load::<PatientSearchRow>(conn)
+            })
+            .await??;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let score = if row.identifier_match {
+                    1.0
+                } else {
+                    score_name(query, &row.name, row.similarity)
+                };
+                let subtitle = [
+                    row.birth_date.map(|date| format!("Born {}", date)),
+                    row.gender,
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" · ");
+                let subtitle = Some(subtitle).filter(|subtitle| !subtitle.is_empty());
+                SearchHit::new(SearchEntity::Patient, row.id, row.name, subtitle, score).for_patient(row.id)
+            })
+            .collect())
+    }
+
+    /// Active practitioners whose name or a specialty matches the query.
+    pub async fn practitioners(&self, pool: &Pool, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let sql = "SELECT * FROM (\
+             SELECT p.id, concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name) AS name, \
+             NULLIF(concat_ws(' · ', p.qualification, array_to_string(p.specialties, ', ')), '') AS detail, \
+             similarity(concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name), $1)::float8 \
+             AS similarity, \
+             EXISTS (SELECT 1 FROM unnest(p.specialties) AS specialty \
+             WHERE strpos(lower(specialty), lower($1)) > 0) AS detail_match \
+             FROM emr.practitioners p WHERE p.active) candidates \
+             WHERE detail_match OR strpos(lower(name), lower($1)) > 0 OR name % $1 \
+             ORDER BY similarity DESC LIMIT $2";
+        self.directory(pool, sql, SearchEntity::Practitioner, query, limit).await
+    }
+
+    /// Active organizations whose name matches the query.
+    pub async fn organizations(&self, pool: &Pool, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let sql = "SELECT * FROM (\
+             SELECT o.id, o.name, NULLIF(concat_ws(' · ', o.type, o.city), '') AS detail, \
+             similarity(o.name, $1)::float8 AS similarity, false AS detail_match \
+             FROM emr.organizations o WHERE o.active) candidates \
+             WHERE strpos(lower(name), lower($1)) > 0 OR name % $1 \
+             ORDER BY similarity DESC LIMIT $2";
+        self.directory(pool, sql, SearchEntity::Organization, query, limit).await
+    }
+
+    async fn directory(
+        &self,
+        pool: &Pool,
+        sql: &'static str,
+        entity: SearchEntity,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let conn = pool.get().await?;
+        let query_text = query.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(sql)
+                    .bind::<diesel::sql_types::Text, _>(query_text)
+                    .bind::<diesel::sql_types::BigInt, _>(limit as i64)
+                    .load::<DirectorySearchRow>(conn)
+            })
+            .await??;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let mut score = score_name(query, &row.name, row.similarity);
+                if row.detail_match {
+                    // A specialty match is worth as much as a name containing the query
+                    score = score.max(0.6);
+                }
+                SearchHit::new(entity, row.id, row.name, row.detail, score)
+            })
+            .collect())
+    }
+
+    /// Current, clean documents that `clearance` clears, whose title or type
+    /// contains the query or whose extracted text matches it.
+    pub async fn documents(
+        &self,
+        pool: &Pool,
+        query: &str,
+        filter: SearchPatientFilter,
+        clearance: Confidentiality,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let conn = pool.get().await?;
+        let (treated_by, only_patient) = filter.binds();
+        let sql = format!(
+            "SELECT d.id, d.patient_id, d.title, d.type_display, d.created_at, \
+             CASE WHEN t.search_vector @@ q THEN ts_rank(t.search_vector, q)::float8 END AS text_rank \
+             FROM emr.document_references d \
+             LEFT JOIN emr.document_texts t ON t.document_id = d.id \
+             CROSS JOIN websearch_to_tsquery('english', $1) q \
+             WHERE d.status = 'current' AND d.scan_status = 'clean' AND d.confidentiality = ANY($5) AND {} \
+             AND (strpos(lower(COALESCE(d.title, '') || ' ' || COALESCE(d.type_display, '')), lower($1)) > 0 \
+             OR t.search_vector @@ q) \
+             ORDER BY text_rank DESC NULLS LAST, d.created_at DESC LIMIT $4",
+            SEARCH_PATIENT_FILTER.replace("{patient}", "d.patient_id")
+        );
+        let query_text = query.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(sql)
+                    .bind::<diesel::sql_types::Text, _>(query_text)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(treated_by)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(only_patient)
+                    .bind::<diesel::sql_types::BigInt, _>(limit as i64)
+                    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(clearance.cleared_labels())
+                    .load::<DocumentHitRow>(conn)
+            })
+            .await??;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let score = [row.title.as_deref(), row.type_display.as_deref()]
+                    .into_iter()
+                    .map(|name| score_document(query, name, row.text_rank))
+                    .fold(0.0, f64::max);
+                let title = row
+                    .title
+                    .clone()
+                    .or_else(|| row.type_display.clone())
+                    .unwrap_or_else(|| "Untitled document".to_string());
+                let subtitle = [row.type_display, Some(row.created_at.date_naive().to_string())]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" · ");
+                SearchHit::new(SearchEntity::Document, row.id, title, Some(subtitle), score)
+                    .for_patient(row.patient_id)
+            })
+            .collect())
+    }
+}
+
+const PANEL_COLUMNS: &str = "id, name, description, organization_id, owner_id, shared, criteria::text AS criteria, \
+     refresh, evaluated_at, member_count, version, created_at, updated_at";
+
+#[derive(diesel::QueryableByName)]
+struct PanelRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    description: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    organization_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    owner_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    shared: bool,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    criteria: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    refresh: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>)]
+    evaluated_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::BigInt>)]
+    member_count: Option<i64>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<PanelRow> for PatientPanel {
+    type Error = ApiError;
+
+    fn try_from(row: PanelRow) -> Result<Self> {
+        let refresh = PanelRefresh::parse(&row.refresh)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown panel refresh '{}'", row.refresh)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            name: row.name,
+            description: row.description,
+            organization_id: row.organization_id,
+            owner_id: row.owner_id,
+            shared: row.shared,
+            criteria: serde_json::from_str(&row.criteria)?,
+            refresh,
+            evaluated_at: row.evaluated_at,
+            member_count: row.member_count,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PanelMemberRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    gender: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Date>)]
+    birth_date: Option<chrono::NaiveDate>,
+}
+
+/// Bind a compiled panel query's parameters, in order
+fn bind_panel_params(
+    mut query: diesel::query_builder::BoxedSqlQuery<'static, diesel::pg::Pg, diesel::query_builder::SqlQuery>,
+    params: Vec<PanelParam>,
+) -> diesel::query_builder::BoxedSqlQuery<'static, diesel::pg::Pg, diesel::query_builder::SqlQuery> {
+    for param in params {
+        query = match param {
+            PanelParam::Text(value) => query.bind::<diesel::sql_types::Text, _>(value),
+            PanelParam::Integer(value) => query.bind::<diesel::sql_types::Integer, _>(value),
+            PanelParam::Double(value) => query.bind::<diesel::sql_types::Double, _>(value),
+            PanelParam::Timestamp(value) => query.bind::<diesel::sql_types::Timestamptz, _>(value),
+        };
+    }
+    query
+}
+
+/// Statement replacing a panel's members with the patients its query finds;
+/// the panel id is bound after the query's own parameters
+fn panel_members_insert(query: &PanelQuery) -> String {
+    format!(
+        "INSERT INTO emr.patient_panel_members (panel_id, patient_id) SELECT ${}, id FROM ({}) members",
+        query.params.len() + 1,
+        query.sql
+    )
+}
+
+/// Saved patient panels and their members
+pub struct PanelRepository;
+
+impl PanelRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new panel.
+    pub async fn insert(&self, pool: &Pool, panel: &PatientPanel) -> Result<()> {
+        let conn = pool.get().await?;
+        let criteria = serde_json::to_string(&panel.criteria)?;
+        let panel = panel.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.patient_panels \
+                 (id, name, description, organization_id, owner_id, shared, criteria, refresh, version, created_at, \
+                 updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, $8, $9, $10, $11)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(panel.metadata.id)
+            .bind::<diesel::sql_types::Text, _>(&panel.name)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(panel.description.as_deref())
+            .bind::<diesel::sql_types::Uuid, _>(panel.organization_id)
+            .bind::<diesel::sql_types::Uuid, _>(panel.owner_id)
+            .bind::<diesel::sql_types::Bool, _>(panel.shared)
+            .bind::<diesel::sql_types::Text, _>(&criteria)
+            .bind::<diesel::sql_types::Text, _>(panel.refresh.as_str())
+            .bind::<diesel::sql_types::BigInt, _>(panel.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(panel.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(panel.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A panel by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<PatientPanel>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.patient_panels WHERE id = $1", PANEL_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<PanelRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(PatientPanel::try_from).transpose()
+    }
+
+    /// An organization's shared panels and those the practitioner saved, by name.
+    pub async fn for_organization(
+        &self,
+        pool: &Pool,
+        organization_id: Id,
+        practitioner_id: Option<Id>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<PatientPanel>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.patient_panels WHERE organization_id = $1 AND (shared OR owner_id = $2) \
+             ORDER BY name, created_at LIMIT $3 OFFSET $4",
+            PANEL_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(organization_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(practitioner_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<PanelRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(PatientPanel::try_from).collect()
+    }
+
+    /// Write a changed panel definition, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, panel: &PatientPanel, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let criteria = serde_json::to_string(&panel.criteria)?;
+        let panel = panel.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.patient_panels \
+                     SET name = $3, description = $4, shared = $5, criteria = $6::jsonb, refresh = $7, \
+                         version = $8, updated_at = $9 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(panel.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Text, _>(&panel.name)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(panel.description.as_deref())
+                .bind::<diesel::sql_types::Bool, _>(panel.shared)
+                .bind::<diesel::sql_types::Text, _>(&criteria)
+                .bind::<diesel::sql_types::Text, _>(panel.refresh.as_str())
+                .bind::<diesel::sql_types::BigInt, _>(panel.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(panel.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+
+    /// Delete a panel and its members.
+    pub async fn delete(&self, pool: &Pool, id: Id) -> Result<bool> {
+        let conn = pool.get().await?;
+
+        let deleted = conn
+            .interact(move |conn| {
+                diesel::sql_query("DELETE FROM emr.patient_panels WHERE id = $1")
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .execute(conn)
+            })
+            .await??;
+
+        Ok(deleted > 0)
+    }
+
+    /// Find a panel's members again and record when, returning how many were found.
+    pub async fn evaluate(&self, pool: &Pool, panel: &PatientPanel) -> Result<(i64, chrono::DateTime<chrono::Utc>)> {
+        let conn = pool.batch().get().await?;
+        let query = member_query(&panel.criteria);
+        let panel_id = panel.metadata.id;
+
+        let outcome = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    diesel::sql_query("DELETE FROM emr.patient_panel_members WHERE panel_id = $1")
+                        .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                        .execute(conn)?;
+                    let insert = diesel::sql_query(panel_members_insert(&query)).into_boxed();
+                    let found = bind_panel_params(insert, query.params)
+                        .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                        .execute(conn)? as i64;
+                    let evaluated_at = chrono::Utc::now();
+                    diesel::sql_query(
+                        "UPDATE emr.patient_panels SET evaluated_at = $2, member_count = $3 WHERE id = $1",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                    .bind::<diesel::sql_types::Timestamptz, _>(evaluated_at)
+                    .bind::<diesel::sql_types::BigInt, _>(found)
+                    .execute(conn)?;
+                    Ok::<_, DieselError>((found, evaluated_at))
+                })
+            })
+            .await??;
+
+        Ok(outcome)
+    }
+
+    /// Members found by a panel's last evaluation that the caller may see, by name.
+    pub async fn members(
+        &self,
+        pool: &Pool,
+        panel_id: Id,
+        patients: SearchPatientFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<PanelMemberModel>> {
+        let conn = pool.get().await?;
+        let (treated_by, only_patient) = patients.binds();
+        let query = format!(
+            "SELECT p.id AS patient_id, concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name) AS name, \
+             p.gender, p.birth_date \
+             FROM emr.patient_panel_members m JOIN emr.patients p ON p.id = m.patient_id \
+             WHERE m.panel_id = $1 AND {} \
+             ORDER BY p.family_name, p.given_names, p.id LIMIT $4 OFFSET $5",
+            SEARCH_PATIENT_FILTER.replace("{patient}", "p.id")
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(treated_by)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(only_patient)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<PanelMemberRow>(conn)
+            })
+            .await??;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PanelMemberModel {
+                patient_id: row.patient_id,
+                name: row.name,
+                gender: row.gender,
+                birth_date: row.birth_date,
+            })
+            .collect())
+    }
+}
+
+/// Columns of `emr.quality_measures` read into a [`MeasureRow`]
+const MEASURE_COLUMNS: &str = "id, identifier, title, description, improvement, \
+    initial_population::text AS initial_population, denominator::text AS denominator, \
+    denominator_exclusions::text AS denominator_exclusions, numerator::text AS numerator, active, version, \
+    created_at, updated_at";
+
+/// Columns of `emr.measure_reports` read into a [`MeasureReportRow`]
+const MEASURE_REPORT_COLUMNS: &str = "id, measure_id, period_start, period_end, initial_population, denominator, \
+    denominator_exclusions, numerator, computed_at";
+
+#[derive(diesel::QueryableByName)]
+struct MeasureRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    identifier: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    title: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    description: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    improvement: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    initial_population: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    denominator: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    denominator_exclusions: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    numerator: String,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<MeasureRow> for QualityMeasure {
+    type Error = ApiError;
+
+    fn try_from(row: MeasureRow) -> Result<Self> {
+        let improvement = ImprovementNotation::parse(&row.improvement).ok_or_else(|| {
+            ApiError::internal_error(&format!("Unknown improvement notation '{}'", row.improvement))
+        })?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            identifier: row.identifier,
+            title: row.title,
+            description: row.description,
+            improvement,
+            initial_population: serde_json::from_str(&row.initial_population)?,
+            denominator: serde_json::from_str(&row.denominator)?,
+            denominator_exclusions: serde_json::from_str(&row.denominator_exclusions)?,
+            numerator: serde_json::from_str(&row.numerator)?,
+            active: row.active,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct MeasureReportRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    measure_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    period_start: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    period_end: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    initial_population: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    denominator: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    denominator_exclusions: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    numerator: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    computed_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<MeasureReportRow> for MeasureReport {
+    fn from(row: MeasureReportRow) -> Self {
+        Self {
+            id: row.id,
+            measure_id: row.measure_id,
+            period_start: row.period_start,
+            period_end: row.period_end,
+            initial_population: row.initial_population,
+            denominator: row.denominator,
+            denominator_exclusions: row.denominator_exclusions,
+            numerator: row.numerator,
+            computed_at: row.computed_at,
+        }
+    }
+}
+
+/// Condition selecting a report's patients: those in `population`, or with
+/// `gaps` the denominator patients neither excluded nor in the numerator
+fn measure_patients_condition(gaps: bool) -> &'static str {
+    if gaps {
+        "r.population = 'denominator' AND NOT EXISTS (SELECT 1 FROM emr.measure_report_patients o \
+         WHERE o.report_id = r.report_id AND o.patient_id = r.patient_id \
+         AND o.population IN ('denominator_exclusion', 'numerator'))"
+    } else {
+        "r.population = $6"
+    }
+}
+
+/// Clinical quality measures and their reports
+pub struct MeasureRepository;
+
+impl MeasureRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new measure; false if its identifier is taken.
+    pub async fn insert(&self, pool: &Pool, measure: &QualityMeasure) -> Result<bool> {
+        let conn = pool.get().await?;
+        let populations = [
+            serde_json::to_string(&measure.initial_population)?,
+            serde_json::to_string(&measure.denominator)?,
+            serde_json::to_string(&measure.denominator_exclusions)?,
+            serde_json::to_string(&measure.numerator)?,
+        ];
+        let measure = measure.clone();
+
+        let inserted = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.quality_measures \
+                     (id, identifier, title, description, improvement, initial_population, denominator, \
+                     denominator_exclusions, numerator, active, version, created_at, updated_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7::jsonb, $8::jsonb, $9::jsonb, $10, $11, $12, $13) \
+                     ON CONFLICT (identifier) DO NOTHING",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(measure.metadata.id)
+                .bind::<diesel::sql_types::Text, _>(&measure.identifier)
+                .bind::<diesel::sql_types::Text, _>(&measure.title)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(measure.description.as_deref())
+                .bind::<diesel::sql_types::Text, _>(measure.improvement.as_str())
+                .bind::<diesel::sql_types::Text, _>(&populations[0])
+                .bind::<diesel::sql_types::Text, _>(&populations[1])
+                .bind::<diesel::sql_types::Text, _>(&populations[2])
+                .bind::<diesel::sql_types::Text, _>(&populations[3])
+                .bind::<diesel::sql_types::Bool, _>(measure.active)
+                .bind::<diesel::sql_types::BigInt, _>(measure.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(measure.metadata.created_at)
+                .bind::<diesel::sql_types::Timestamptz, _>(measure.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(inserted > 0)
+    }
+
+    /// A measure by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<QualityMeasure>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.quality_measures WHERE id = $1", MEASURE_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<MeasureRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(QualityMeasure::try_from).transpose()
+    }
+
+    /// Measures by identifier.
+    pub async fn list(&self, pool: &Pool, limit: u32, offset: u32) -> Result<Vec<QualityMeasure>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.quality_measures ORDER BY identifier LIMIT $1 OFFSET $2",
+            MEASURE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<MeasureRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(QualityMeasure::try_from).collect()
+    }
+
+    /// A measure's reports, latest reporting period first.
+    pub async fn reports(&self, pool: &Pool, measure_id: Id, limit: u32, offset: u32) -> Result<Vec<MeasureReport>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.measure_reports WHERE measure_id = $1 \
+             ORDER BY period_end DESC, period_start DESC LIMIT $2 OFFSET $3",
+            MEASURE_REPORT_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(measure_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<MeasureReportRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(MeasureReport::from).collect())
+    }
+
+    /// A report by id.
+    pub async fn find_report(&self, pool: &Pool, id: Id) -> Result<Option<MeasureReport>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.measure_reports WHERE id = $1", MEASURE_REPORT_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<MeasureReportRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().next().map(MeasureReport::from))
+    }
+
+    /// Patients of a report in a population, or its care gaps, that the caller may see, by name.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn patients(
+        &self,
+        pool: &Pool,
+        report_id: Id,
+        population: MeasurePopulation,
+        gaps: bool,
+        patients: SearchPatientFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<MeasurePatientModel>> {
+        let conn = pool.get().await?;
+        let (treated_by, only_patient) = patients.binds();
+        let query = format!(
+            "SELECT p.id AS patient_id, concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name) AS name, \
+             p.gender, p.birth_date \
+             FROM emr.measure_report_patients r JOIN emr.patients p ON p.id = r.patient_id \
+             WHERE r.report_id = $1 AND {} AND {} \
+             ORDER BY p.family_name, p.given_names, p.id LIMIT $4 OFFSET $5",
+            measure_patients_condition(gaps),
+            SEARCH_PATIENT_FILTER.replace("{patient}", "p.id")
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                let query = diesel::sql_query(query)
+                    .into_boxed()
+                    .bind::<diesel::sql_types::Uuid, _>(report_id)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(treated_by)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(only_patient)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset));
+                let query = if gaps {
+                    query
+                } else {
+                    query.bind::<diesel::sql_types::Text, _>(population.as_str())
+                };
+                query.load::<PanelMemberRow>(conn)
+            })
+            .await??;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| MeasurePatientModel {
+                patient_id: row.patient_id,
+                name: row.name,
+                gender: row.gender,
+                birth_date: row.birth_date,
+            })
+            .collect())
+    }
+}
+
+/// Bind a compiled report query's parameters, in order
+fn bind_report_params(
+    mut query: diesel::query_builder::BoxedSqlQuery<'static, diesel::pg::Pg, diesel::query_builder::SqlQuery>,
+    params: Vec<ReportParam>,
+) -> diesel::query_builder::BoxedSqlQuery<'static, diesel::pg::Pg, diesel::query_builder::SqlQuery> {
+    for param in params {
+        query = match param {
+            ReportParam::Text(value) => query.bind::<diesel::sql_types::Text, _>(value),
+            ReportParam::TextList(values) => {
+                query.bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(values)
+            }
+            ReportParam::Number(value) => query.bind::<diesel::sql_types::Double, _>(value),
+            ReportParam::Boolean(value) => query.bind::<diesel::sql_types::Bool, _>(value),
+            ReportParam::Timestamp(value) => query.bind::<diesel::sql_types::Timestamptz, _>(value),
+            ReportParam::Limit(value) => query.bind::<diesel::sql_types::BigInt, _>(value),
+        };
+    }
+    query
+}
+
+/// Ad-hoc aggregate reports
+pub struct ReportRepository;
+
+impl ReportRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Run a compiled report in a read-only transaction.
+    pub async fn run(&self, pool: &Pool, query: ReportQuery) -> Result<ReportTable> {
+        let conn = pool.batch().get().await?;
+        let ReportQuery { sql, params, columns } = query;
+
+        let rows = conn
+            .interact(move |conn| {
+                conn.build_transaction()
+                    .read_only()
+                    .run(|conn| {
+                        bind_report_params(diesel::sql_query(sql).into_boxed(), params).load::<ExportRow>(conn)
+                    })
+            })
+            .await??;
+
+        let rows = rows
+            .into_iter()
+            .map(|row| serde_json::from_str(&row.row))
+            .collect::<std::result::Result<Vec<Vec<serde_json::Value>>, _>>()?;
+        Ok(ReportTable { columns, rows })
+    }
+}
+
+/// Columns of `emr.report_schedules` read into a [`ReportScheduleRow`]
+const REPORT_SCHEDULE_COLUMNS: &str = "id, name, owner_id, definition::text AS definition, period::text AS period, \
+    format, cadence::text AS cadence, recipients, active, next_run_at, version, created_at, updated_at";
+
+/// Columns of `emr.report_runs` read into a [`ReportRunRow`]
+const REPORT_RUN_COLUMNS: &str =
+    "id, schedule_id, scheduled_for, status, format, row_count, byte_count, error, generated_at";
+
+#[derive(diesel::QueryableByName)]
+struct ReportScheduleRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    owner_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    definition: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    period: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    format: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    cadence: String,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Uuid>)]
+    recipients: Vec<Id>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    next_run_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ReportScheduleRow> for ReportSchedule {
+    type Error = ApiError;
+
+    fn try_from(row: ReportScheduleRow) -> Result<Self> {
+        let format = ReportFormat::parse(&row.format)
+            .ok_or_else(|| ApiError::internal_error(&format!("Unknown report format '{}'", row.format)))?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            name: row.name,
+            owner_id: row.owner_id,
+            definition: serde_json::from_str(&row.definition)?,
+            period: row.period.as_deref().map(serde_json::from_str).transpose()?,
+            format,
+            cadence: serde_json::from_str(&row.cadence)?,
+            recipients: row.recipients,
+            active: row.active,
+            next_run_at: row.next_run_at,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ReportRunRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    schedule_id: Id,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    scheduled_for: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    status: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    format: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::BigInt>)]
+    row_count: Option<i64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::BigInt>)]
+    byte_count: Option<i64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    error: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    generated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TryFrom<ReportRunRow> for ReportRun {
+    type Error = ApiError;
+
+    fn try_from(row: ReportRunRow) -> Result<Self> {
+        Ok(Self {
+            id: row.id,
+            schedule_id: row.schedule_id,
+            scheduled_for: row.scheduled_for,
+            status: ReportRunStatus::parse(&row.status)
+                .ok_or_else(|| ApiError::internal_error(&format!("Unknown report run status '{}'", row.status)))?,
+            format: ReportFormat::parse(&row.format)
+                .ok_or_else(|| ApiError::internal_error(&format!("Unknown report format '{}'", row.format)))?,
+            row_count: row.row_count,
+            byte_count: row.byte_count,
+            error: row.error,
+            generated_at: row.generated_at,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ReportRunIdRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Id,
+}
+
+/// Scheduled reports and the history of their runs
+pub struct ReportScheduleRepository;
+
+impl ReportScheduleRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Store a new schedule.
+    pub async fn insert(&self, pool: &Pool, schedule: &ReportSchedule) -> Result<()> {
+        let conn = pool.get().await?;
+        let definition = serde_json::to_string(&schedule.definition)?;
+        let period = schedule.period.as_ref().map(serde_json::to_string).transpose()?;
+        let cadence = serde_json::to_string(&schedule.cadence)?;
+        let schedule = schedule.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.report_schedules \
+                 (id, name, owner_id, definition, period, format, cadence, recipients, active, next_run_at, version, \
+                 created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4::jsonb, $5::jsonb, $6, $7::jsonb, $8, $9, $10, $11, $12, $13)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(schedule.metadata.id)
+            .bind::<diesel::sql_types::Text, _>(&schedule.name)
+            .bind::<diesel::sql_types::Uuid, _>(schedule.owner_id)
+            .bind::<diesel::sql_types::Text, _>(&definition)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(period.as_deref())
+            .bind::<diesel::sql_types::Text, _>(schedule.format.as_str())
+            .bind::<diesel::sql_types::Text, _>(&cadence)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&schedule.recipients)
+            .bind::<diesel::sql_types::Bool, _>(schedule.active)
+            .bind::<diesel::sql_types::Timestamptz, _>(schedule.next_run_at)
+            .bind::<diesel::sql_types::BigInt, _>(schedule.metadata.version as i64)
+            .bind::<diesel::sql_types::Timestamptz, _>(schedule.metadata.created_at)
+            .bind::<diesel::sql_types::Timestamptz, _>(schedule.metadata.updated_at)
+            .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A schedule by id.
+    pub async fn find(&self, pool: &Pool, id: Id) -> Result<Option<ReportSchedule>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.report_schedules WHERE id = $1", REPORT_SCHEDULE_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<ReportScheduleRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(ReportSchedule::try_from).transpose()
+    }
+
+    /// Schedules the practitioner owns or receives, or all schedules without one, by name.
+    pub async fn list(
+        &self,
+        pool: &Pool,
+        practitioner_id: Option<Id>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<ReportSchedule>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.report_schedules \
+             WHERE $1::uuid IS NULL OR owner_id = $1 OR $1 = ANY(recipients) \
+             ORDER BY name, created_at LIMIT $2 OFFSET $3",
+            REPORT_SCHEDULE_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(practitioner_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ReportScheduleRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ReportSchedule::try_from).collect()
+    }
+
+    /// Write a changed schedule, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, schedule: &ReportSchedule, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let definition = serde_json::to_string(&schedule.definition)?;
+        let period = schedule.period.as_ref().map(serde_json::to_string).transpose()?;
+        let cadence = serde_json::to_string(&schedule.cadence)?;
+        let schedule = schedule.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.report_schedules \
+                     SET name = $3, definition = $4::jsonb, period = $5::jsonb, format = $6, cadence = $7::jsonb, \
+                         recipients = $8, active = $9, next_run_at = $10, version = $11, updated_at = $12 \
+                     WHERE id = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(schedule.metadata.id)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Text, _>(&schedule.name)
+                .bind::<diesel::sql_types::Text, _>(&definition)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(period.as_deref())
+                .bind::<diesel::sql_types::Text, _>(schedule.format.as_str())
+                .bind::<diesel::sql_types::Text, _>(&cadence)
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&schedule.recipients)
+                .bind::<diesel::sql_types::Bool, _>(schedule.active)
+                .bind::<diesel::sql_types::Timestamptz, _>(schedule.next_run_at)
+                .bind::<diesel::sql_types::BigInt, _>(schedule.metadata.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(schedule.metadata.updated_at)
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+
+    /// Delete a schedule and its run history, returning the ids of the deleted runs.
+    pub async fn delete(&self, pool: &Pool, id: Id) -> Result<Vec<Id>> {
+        let conn = pool.get().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let runs = diesel::sql_query("DELETE FROM emr.report_runs WHERE schedule_id = $1 RETURNING id")
+                        .bind::<diesel::sql_types::Uuid, _>(id)
+                        .load::<ReportRunIdRow>(conn)?;
+                    diesel::sql_query("DELETE FROM emr.report_schedules WHERE id = $1")
+                        .bind::<diesel::sql_types::Uuid, _>(id)
+                        .execute(conn)?;
+                    Ok::<_, DieselError>(runs)
+                })
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(|row| row.id).collect())
+    }
+
+    /// A schedule's runs, latest first.
+    pub async fn runs(&self, pool: &Pool, schedule_id: Id, limit: u32, offset: u32) -> Result<Vec<ReportRun>> {
+        let conn = pool.get().await?;
+        let query = format!(
+            "SELECT {} FROM emr.report_runs WHERE schedule_id = $1 \
+             ORDER BY scheduled_for DESC, generated_at DESC LIMIT $2 OFFSET $3",
+            REPORT_RUN_COLUMNS
+        );
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(schedule_id)
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(limit))
+                    .bind::<diesel::sql_types::BigInt, _>(i64::from(offset))
+                    .load::<ReportRunRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().map(ReportRun::try_from).collect()
+    }
+
+    /// A run by id.
+    pub async fn find_run(&self, pool: &Pool, id: Id) -> Result<Option<ReportRun>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.report_runs WHERE id = $1", REPORT_RUN_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<ReportRunRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(ReportRun::try_from).transpose()
+    }
+}
+
+/// Columns of `emr.feature_flags` read into a [`FeatureFlagRow`]
+const FEATURE_FLAG_COLUMNS: &str =
+    "key, description, enabled, rollout_percentage, tenants::text AS tenants, version, updated_at, updated_by";
+
+#[derive(diesel::QueryableByName)]
+struct FeatureFlagRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    key: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    description: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    enabled: bool,
+    #[diesel(sql_type = diesel::sql_types::SmallInt)]
+    rollout_percentage: i16,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    tenants: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    version: i64,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    updated_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    updated_by: Option<String>,
+}
+
+impl TryFrom<FeatureFlagRow> for FeatureFlag {
+    type Error = ApiError;
+
+    fn try_from(row: FeatureFlagRow) -> Result<Self> {
+        Ok(Self {
+            key: row.key,
+            description: row.description,
+            enabled: row.enabled,
+            rollout_percentage: row.rollout_percentage.clamp(0, 100) as u8,
+            tenants: serde_json::from_str(&row.tenants)?,
+            version: row.version as u64,
+            updated_at: row.updated_at,
+            updated_by: row.updated_by,
+        })
+    }
+}
+
+/// Runtime feature flags
+pub struct FeatureFlagRepository;
+
+impl FeatureFlagRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// All flags, by key.
+    pub async fn list(&self, pool: &Pool) -> Result<Vec<FeatureFlag>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.feature_flags ORDER BY key", FEATURE_FLAG_COLUMNS);
+
+        let rows = conn
+            .interact(move |conn| diesel::sql_query(query).load::<FeatureFlagRow>(conn))
+            .await??;
+
+        rows.into_iter().map(FeatureFlag::try_from).collect()
+    }
+
+    /// A flag by key.
+    pub async fn find(&self, pool: &Pool, key: &str) -> Result<Option<FeatureFlag>> {
+        let conn = pool.get().await?;
+        let query = format!("SELECT {} FROM emr.feature_flags WHERE key = $1", FEATURE_FLAG_COLUMNS);
+        let key = key.to_string();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Text, _>(key)
+                    .load::<FeatureFlagRow>(conn)
+            })
+            .await??;
+
+        rows.into_iter().next().map(FeatureFlag::try_from).transpose()
+    }
+
+    /// Store a new flag; false if one with the key already exists.
+    pub async fn insert(&self, pool: &Pool, flag: &FeatureFlag) -> Result<bool> {
+        let conn = pool.get().await?;
+        let tenants = serde_json::to_string(&flag.tenants)?;
+        let flag = flag.clone();
+
+        let inserted = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.feature_flags \
+                     (key, description, enabled, rollout_percentage, tenants, version, updated_at, updated_by) \
+                     VALUES ($1, $2, $3, $4, $5::jsonb, $6, $7, $8) \
+                     ON CONFLICT (key) DO NOTHING",
+                )
+                .bind::<diesel::sql_types::Text, _>(&flag.key)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(flag.description.as_deref())
+                .bind::<diesel::sql_types::Bool, _>(flag.enabled)
+                .bind::<diesel::sql_types::SmallInt, _>(i16::from(flag.rollout_percentage))
+                .bind::<diesel::sql_types::Text, _>(&tenants)
+                .bind::<diesel::sql_types::BigInt, _>(flag.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(flag.updated_at)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(flag.updated_by.as_deref())
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(inserted > 0)
+    }
+
+    /// Write a changed flag, provided nobody else changed it since it was read at `previous_version`.
+    pub async fn update(&self, pool: &Pool, flag: &FeatureFlag, previous_version: u64) -> Result<bool> {
+        let conn = pool.get().await?;
+        let tenants = serde_json::to_string(&flag.tenants)?;
+        let flag = flag.clone();
+
+        let updated = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.feature_flags \
+                     SET description = $3, enabled = $4, rollout_percentage = $5, tenants = $6::jsonb, \
+                         version = $7, updated_at = $8, updated_by = $9 \
+                     WHERE key = $1 AND version = $2",
+                )
+                .bind::<diesel::sql_types::Text, _>(&flag.key)
+                .bind::<diesel::sql_types::BigInt, _>(previous_version as i64)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(flag.description.as_deref())
+                .bind::<diesel::sql_types::Bool, _>(flag.enabled)
+                .bind::<diesel::sql_types::SmallInt, _>(i16::from(flag.rollout_percentage))
+                .bind::<diesel::sql_types::Text, _>(&tenants)
+                .bind::<diesel::sql_types::BigInt, _>(flag.version as i64)
+                .bind::<diesel::sql_types::Timestamptz, _>(flag.updated_at)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(flag.updated_by.as_deref())
+                .execute(conn)
+            })
+            .await??;
+
+        Ok(updated > 0)
+    }
+
+    /// Delete a flag; code checking it sees it as off.
+    pub async fn delete(&self, pool: &Pool, key: &str) -> Result<bool> {
+        let conn = pool.get().await?;
+        let key = key.to_string();
+
+        let deleted = conn
+            .interact(move |conn| {
+                diesel::sql_query("DELETE FROM emr.feature_flags WHERE key = $1")
+                    .bind::<diesel::sql_types::Text, _>(key)
+                    .execute(conn)
+            })
+            .await??;
+
+        Ok(deleted > 0)
+    }
+}
+
+/// Audit trail entry for an access to a patient's data; `request_id` comes from the session like the table triggers
+const INSERT_PATIENT_ACCESS_QUERY: &str = "INSERT INTO audit.audit_log \
+     (table_name, operation, new_values, changed_by, request_id, user_id, patient_id, purpose_of_use) \
+     VALUES ('patient_access', $1, $2::jsonb, current_user, audit.current_request_id(), \
+     $3, $4, $5)";
+
+/// Sign-in or denied request for the security event detector
+const INSERT_AUTH_EVENT_QUERY: &str = "INSERT INTO audit.auth_events \
+     (occurred_at, subject, kind, ip_address, country, city, latitude, longitude, path) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+
+/// Security event noticed while serving a request; the jobs worker alerts it
+const INSERT_SECURITY_EVENT_QUERY: &str = "INSERT INTO audit.security_events \
+     (kind, subject, occurred_at, description, details) VALUES ($1, $2, $3, $4, $5::jsonb)";
+
+/// An access to a patient's data to audit
+#[derive(Debug, Clone)]
+pub struct AuditedAccess<'a> {
+    pub patient_id: Id,
+    /// Practitioner accessing the data; `None` for unauthenticated requests
+    pub user_id: Option<Id>,
+    /// `READ` or `WRITE`
+    pub action: &'static str,
+    pub path: &'a str,
+    /// Purpose of use the user selected
+    pub purpose_of_use: Option<PurposeOfUse>,
+    /// Sensitive data the consent rules were evaluated for
+    pub sensitive: &'a [SensitiveData],
+}
+
+#[derive(diesel::QueryableByName)]
+struct AccessRecordRow {
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    accessed_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    user_id: Option<uuid::Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    user_name: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    action: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    resource: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    basis: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    purpose_of_use: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    request_id: Option<String>,
+}
+
+impl From<AccessRecordRow> for AccessRecord {
+    fn from(row: AccessRecordRow) -> Self {
+        Self {
+            accessed_at: row.accessed_at,
+            user_id: row.user_id,
+            user_name: row.user_name,
+            action: row.action,
+            resource: row.resource,
+            basis: row.basis,
+            purpose_of_use: row.purpose_of_use,
+            request_id: row.request_id,
+        }
+    }
+}
+
+/// Accesses to patients' data in the audit trail
+pub struct AuditRepository;
+
+impl AuditRepository {
+    /// Create a new repository instance.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Audit an access to a patient's data, with the basis it was allowed on.
+    pub async fn record_patient_access(&self, pool: &Pool, access: &AuditedAccess<'_>, basis: &str) -> Result<()> {
+        let conn = pool.get().await?;
+        let audited = serde_json::json!({
+            "path": access.path,
+            "basis": basis,
+            "sensitive": access.sensitive,
+        })
+        .to_string();
+        let (action, user_id, patient_id) = (access.action, access.user_id, access.patient_id);
+        let purpose = access.purpose_of_use.map(|purpose| purpose.as_str());
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_PATIENT_ACCESS_QUERY)
+                .bind::<diesel::sql_types::Text, _>(action)
+                .bind::<diesel::sql_types::Text, _>(&audited)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(user_id)
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(purpose)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// Record a sign-in or denied request.
+    pub async fn record_auth_event(&self, pool: &Pool, event: &AuthEvent) -> Result<()> {
+        let conn = pool.get().await?;
+        let event = event.clone();
+        let (country, city, latitude, longitude) = match &event.location {
+            Some(location) => (
+                Some(location.country.clone()),
+                location.city.clone(),
+                location.latitude,
+                location.longitude,
+            ),
+            None => (None, None, None, None),
+        };
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_AUTH_EVENT_QUERY)
+                .bind::<diesel::sql_types::Timestamptz, _>(event.occurred_at)
+                .bind::<diesel::sql_types::Text, _>(&event.subject)
+                .bind::<diesel::sql_types::Text, _>(event.kind.as_str())
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&event.ip_address)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&country)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&city)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(latitude)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Double>, _>(longitude)
+                .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(&event.path)
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// Record a security event; its alert goes out with the worker's next
+    /// detection run
+    pub async fn record_security_event(&self, pool: &Pool, event: &SecurityEvent) -> Result<()> {
+        let conn = pool.get().await?;
+        let event = event.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_SECURITY_EVENT_QUERY)
+                .bind::<diesel::sql_types::Text, _>(&event.kind)
+                .bind::<diesel::sql_types::Text, _>(&event.subject)
+                .bind::<diesel::sql_types::Timestamptz, _>(event.occurred_at)
+                .bind::<diesel::sql_types::Text, _>(&event.description)
+                .bind::<diesel::sql_types::Text, _>(event.details.to_string())
+                .execute(conn)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// A page of the accesses to a patient's data in `[start, end)`, oldest first.
+    pub async fn disclosures(
+        &self,
+        pool: &Pool,
+        patient_id: Id,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AccessRecord>> {
+        let conn = pool.get().await?;
+        let query = format!("{} LIMIT $4 OFFSET $5", DISCLOSURES_QUERY);
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<diesel::sql_types::Timestamptz, _>(start)
+                    .bind::<diesel::sql_types::Timestamptz, _>(end)
+                    .bind::<diesel::sql_types::BigInt, _>(limit)
+                    .bind::<diesel::sql_types::BigInt, _>(offset)
+                    .load::<AccessRecordRow>(conn)
+            })
+            .await??;
+
+        Ok(rows.into_iter().map(AccessRecord::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export_fields() {
+        assert_eq!(parse_export_fields(None).unwrap().len(), PATIENT_EXPORT_FIELDS.len());
+        assert_eq!(
+            parse_export_fields(Some("id, family_name,id,")).unwrap(),
+            vec!["id", "family_name"]
+        );
+        assert!(parse_export_fields(Some("id,password_hash")).is_err());
+        assert!(parse_export_fields(Some("p.id); DROP TABLE emr.patients; --")).is_err());
+        assert!(parse_export_fields(Some(" , ")).is_err());
+    }
+
+    #[test]
+    fn test_panel_members_insert() {
+        let query = member_query(&[emr_core::domain::PanelCriterion::SeenWithin { days: 365 }]);
+        let insert = panel_members_insert(&query);
+        assert!(insert.starts_with("INSERT INTO emr.patient_panel_members (panel_id, patient_id) SELECT $2, id FROM"));
+        assert!(insert.ends_with("make_interval(days => $1))) members"));
+    }
+
+    #[test]
+    fn test_measure_patients_condition() {
+        assert_eq!(measure_patients_condition(false), "r.population = $6");
+        assert!(measure_patients_condition(true).contains("o.population IN ('denominator_exclusion', 'numerator')"));
+    }
+
+    #[test]
+    fn test_dead_letter_list_query() {
+        let query = dead_letter_list_query();
+        assert!(query.contains("payload::text AS payload"));
+        assert!(query.contains("(replayed_at IS NULL OR failed_at > replayed_at)"));
+        assert!(query.ends_with("LIMIT $2 OFFSET $3"));
+    }
+
+    #[test]
+    fn test_patient_export_cursor_query() {
+        let query = patient_export_cursor_query(&["id", "birth_date"]);
+        assert!(query.starts_with("DECLARE patient_export NO SCROLL CURSOR FOR SELECT"));
+        assert!(query.contains("jsonb_build_object('id', p.id, 'birth_date', p.birth_date)::text AS row"));
+    }
+
+    #[test]
+    fn test_stored_telecom() {
+        let flat = TelecomRow {
+            contact_points: None,
+            phone: Some("+15555550100".to_string()),
+            email: Some("jane@example.com".to_string()),
+        };
+        let mut telecom = stored_telecom(flat).unwrap();
+        assert_eq!(telecom.len(), 2);
+        assert_eq!(telecom[0].system, ContactSystem::Phone);
+
+        let at = chrono::Utc::now();
+        assert!(mark_verified(&mut telecom, "jane@example.com", at));
+        assert!(!mark_verified(&mut telecom, "john@example.com", at));
+        assert_eq!(telecom[1].verified_at, Some(at));
+        assert!(!telecom[0].is_verified());
+
+        let stored = TelecomRow {
+            contact_points: Some(serde_json::to_string(&telecom).unwrap()),
+            phone: Some("+15555550100".to_string()),
+            email: None,
+        };
+        let telecom = stored_telecom(stored).unwrap();
+        assert_eq!(telecom.len(), 2);
+        assert!(telecom[1].is_verified());
+    }
+
+    #[test]
+    fn test_photos_row() {
+        let photo = Attachment {
+            content_type: "image/png".to_string(),
+            language: None,
+            data: Some(vec![1, 2, 3]),
+            url: None,
+            size: Some(3),
+            hash: Some("hash".to_string()),
+            title: Some("Intake".to_string()),
+            creation: None,
+        };
+        let row = PhotosRow {
+            family_name: None,
+            given_names: None,
+            photos: Some(serde_json::json!([attachment_to_fhir(&photo)]).to_string()),
+        };
+
+        let mut patient = Patient::try_from(row).unwrap();
+        assert_eq!(patient.photos[0].data, Some(vec![1, 2, 3]));
+
+        patient.add_photo(photo).unwrap();
+        assert_eq!(patient.photos.len(), 1, "same hash is stored once");
+    }
+
+    #[test]
+    fn test_encounter_row() {
+        let row = |class: &str, details: Option<String>| EncounterRow {
+            id: uuid::Uuid::new_v4(),
+            status: "finished".to_string(),
+            class: Some(class.to_string()),
+            encounter_type: None,
+            patient_id: Some(uuid::Uuid::new_v4()),
+            organization_id: None,
+            start_date: Some(chrono::Utc::now()),
+            end_date: None,
+            details,
+            version: 1,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+
+        // Rows seeded outside the API have no details
+        let seeded = Encounter::try_from(row("AMB", None)).unwrap();
+        assert_eq!(seeded.status, EncounterStatus::Finished);
+        assert!(matches!(seeded.class, EncounterClass::Ambulatory));
+        assert!(seeded.period.unwrap().start.is_some());
+
+        let mut encounter = Encounter::new(EncounterStatus::Finished, EncounterClass::Outpatient, uuid::Uuid::new_v4());
+        encounter.reason.push("Follow-up".to_string());
+        let columns = EncounterColumns::new(&encounter).unwrap();
+        assert_eq!(columns.reason_description.as_deref(), Some("Follow-up"));
+        let stored = Encounter::try_from(row("AMB", Some(columns.details))).unwrap();
+        assert!(matches!(stored.class, EncounterClass::Outpatient));
+        assert_eq!(stored.reason, encounter.reason);
+
+        assert!(Encounter::try_from(row("OUTP", None)).is_err());
+    }
+
+    #[test]
+    fn test_referral_worklist_query() {
+        let incoming = referral_worklist_query(true);
+        assert!(incoming.contains("WHERE target_organization_id = $1"));
+        assert!(incoming.contains("SELECT id, patient_id, requester_id"));
+        assert!(referral_worklist_query(false).contains("WHERE source_organization_id = $1"));
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,286 @@
+//! HTTPS listener and connection metrics
+//!
+//! The listener negotiates HTTP/2 over ALPN and falls back to HTTP/1.1. It
+//! is tuned by `server`: worker count, listen backlog, connections per
+//! worker, concurrent TLS handshakes, keep-alive and the client timeouts.
+//!
+//! It is assembled from actix-server and actix-http rather than
+//! `HttpServer`, which has no hook around the TLS handshake. The
+//! [`ConnectionMonitor`] times every handshake, counts open connections
+//! and the ones on HTTP/2, and measures how late each worker's event loop
+//! runs ready work. A saturated worker leaves requests waiting there, so
+//! the lag is the queueing time requests see before their handlers run.
+//! Everything is published on `/metrics` beside the database pool's
+//! statistics.
+//!
+//! The app sees a default `AppConfig`, so `ConnectionInfo` reports the
+//! `Host` header and `X-Forwarded-*` headers rather than the listener's own
+//! address and scheme.
+
+use actix_http::{HttpService, Protocol, Request, Response};
+use actix_server::Server;
+use actix_service::{
+    apply_fn_factory, fn_service, map_config, IntoServiceFactory, Service, ServiceFactory, ServiceFactoryExt,
+};
+use actix_tls::accept::rustls_0_21::{Acceptor, TlsStream};
+use actix_tls::accept::TlsError;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{AppConfig, Extensions};
+use actix_web::rt::net::TcpStream;
+use tokio::time::{interval, MissedTickBehavior};
+use serde::Serialize;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::config::ServerConfig;
+
+/// How often each worker checks its event loop for lag
+const LAG_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Event loop lag counted as a stall
+const STALL_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// Point-in-time connection statistics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConnectionStats {
+    /// Connections currently open
+    pub active: u64,
+    /// Connections opened since startup
+    pub opened_total: u64,
+    /// Connections that negotiated HTTP/2
+    pub http2_total: u64,
+    /// Completed TLS handshakes
+    pub handshakes_total: u64,
+    /// Handshakes that failed or timed out
+    pub handshake_errors_total: u64,
+    /// Cumulative handshake time, in milliseconds
+    pub handshake_ms_total: u64,
+    /// Event loop lag samples taken
+    pub lag_samples_total: u64,
+    /// Cumulative event loop lag, in milliseconds
+    pub lag_ms_total: u64,
+    /// Samples lagging by more than the stall threshold
+    pub stalls_total: u64,
+}
+
+impl ConnectionStats {
+    /// Render as Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let metrics: [(&str, &str, &str, u64); 9] = [
+            ("emr_http_connections_active", "gauge", "Open client connections", self.active),
+            ("emr_http_connections_total", "counter", "Client connections opened", self.opened_total),
+            ("emr_http2_connections_total", "counter", "Connections that negotiated HTTP/2", self.http2_total),
+            ("emr_tls_handshakes_total", "counter", "Completed TLS handshakes", self.handshakes_total),
+            ("emr_tls_handshake_errors_total", "counter", "Failed TLS handshakes", self.handshake_errors_total),
+            ("emr_tls_handshake_ms_total", "counter", "Time spent in TLS handshakes", self.handshake_ms_total),
+            ("emr_http_worker_lag_samples_total", "counter", "Worker event loop lag samples", self.lag_samples_total),
+            ("emr_http_worker_lag_ms_total", "counter", "Time ready work waited for a worker", self.lag_ms_total),
+            ("emr_http_worker_stalls_total", "counter", "Lag samples over the stall threshold", self.stalls_total),
+        ];
+
+        let mut output = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value);
+        }
+        output
+    }
+}
+
+/// Tracks connections, TLS handshakes and worker lag for metrics
+#[derive(Debug, Default)]
+pub struct ConnectionMonitor {
+    active: AtomicU64,
+    opened: AtomicU64,
+    http2: AtomicU64,
+    handshakes: AtomicU64,
+    handshake_errors: AtomicU64,
+    handshake_ms: AtomicU64,
+    lag_samples: AtomicU64,
+    lag_ms: AtomicU64,
+    stalls: AtomicU64,
+}
+
+/// Marks a connection open until the connection is dropped
+struct OpenConnection(Arc<ConnectionMonitor>);
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ConnectionMonitor {
+    /// Create a monitor with every counter at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record_handshake(&self, took: Duration, ok: bool) {
+        if ok {
+            self.handshakes.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.handshake_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.handshake_ms.fetch_add(took.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Count a new connection, open until the returned guard is dropped
+    fn open(self: &Arc<Self>, http2: bool) -> OpenConnection {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        self.opened.fetch_add(1, Ordering::Relaxed);
+        if http2 {
+            self.http2.fetch_add(1, Ordering::Relaxed);
+        }
+        OpenConnection(self.clone())
+    }
+
+    fn record_lag(&self, lag: Duration) {
+        self.lag_samples.fetch_add(1, Ordering::Relaxed);
+        self.lag_ms.fetch_add(lag.as_millis() as u64, Ordering::Relaxed);
+        if lag >= STALL_THRESHOLD {
+            self.stalls.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            active: self.active.load(Ordering::Relaxed),
+            opened_total: self.opened.load(Ordering::Relaxed),
+            http2_total: self.http2.load(Ordering::Relaxed),
+            handshakes_total: self.handshakes.load(Ordering::Relaxed),
+            handshake_errors_total: self.handshake_errors.load(Ordering::Relaxed),
+            handshake_ms_total: self.handshake_ms.load(Ordering::Relaxed),
+            lag_samples_total: self.lag_samples.load(Ordering::Relaxed),
+            lag_ms_total: self.lag_ms.load(Ordering::Relaxed),
+            stalls_total: self.stalls.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Sample how late the current worker's event loop runs a timer, for as
+/// long as the worker runs
+async fn probe_worker_lag(monitor: Arc<ConnectionMonitor>) {
+    let mut ticks = interval(LAG_PROBE_INTERVAL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let scheduled = ticks.tick().await;
+        monitor.record_lag(scheduled.elapsed());
+    }
+}
+
+/// Whether the client picked HTTP/2 during the handshake
+fn negotiated_http2(io: &TlsStream<TcpStream>) -> bool {
+    io.get_ref().1.alpn_protocol() == Some(&b"h2"[..])
+}
+
+/// Serve the app over HTTPS on `server.host:server.port`
+///
+/// `factory` builds the app once per worker, as `HttpServer::new` does.
+pub fn serve<F, I, S, B>(config: &ServerConfig, monitor: Arc<ConnectionMonitor>, factory: F) -> anyhow::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<Response<BoxBody>> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let mut tls = config.tls_config().map_err(|e| anyhow::anyhow!("Cannot load TLS settings: {}", e))?;
+    tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    actix_tls::accept::max_concurrent_tls_connect(config.max_connection_rate);
+
+    let keep_alive = Duration::from_secs(config.keep_alive);
+    let request_timeout = Duration::from_millis(config.client_timeout);
+    let disconnect_timeout = Duration::from_millis(config.client_shutdown);
+    let handshake_timeout = Duration::from_millis(config.tls_handshake_timeout);
+    let mut builder = Server::build()
+        .backlog(config.backlog)
+        .max_concurrent_connections(config.max_connections)
+        .shutdown_timeout(config.shutdown_timeout);
+    if config.workers > 0 {
+        builder = builder.workers(config.workers);
+    }
+
+    let server = builder
+        .bind("emr-https", (config.host.as_str(), config.port), move || {
+            actix_web::rt::spawn(probe_worker_lag(monitor.clone()));
+
+            let mut acceptor = Acceptor::new(tls.clone());
+            acceptor.set_handshake_timeout(handshake_timeout);
+            let handshake_monitor = monitor.clone();
+            let timed_acceptor = apply_fn_factory::<_, _, _, _, _, TcpStream, _, _>(acceptor, move |io: TcpStream, acceptor| {
+                let started = Instant::now();
+                let handshake = acceptor.call(io);
+                let monitor = handshake_monitor.clone();
+                async move {
+                    let result = handshake.await;
+                    monitor.record_handshake(started.elapsed(), result.is_ok());
+                    result
+                }
+            });
+
+            let connection_monitor = monitor.clone();
+            let http = HttpService::build()
+                .keep_alive(keep_alive)
+                .client_request_timeout(request_timeout)
+                .client_disconnect_timeout(disconnect_timeout)
+                .secure()
+                .on_connect_ext(move |io: &TlsStream<TcpStream>, extensions: &mut Extensions| {
+                    extensions.insert(connection_monitor.open(negotiated_http2(io)));
+                })
+                .finish(map_config(factory(), |_| AppConfig::default()));
+
+            timed_acceptor
+                .map_init_err(|_| ())
+                .map_err(TlsError::into_service_error)
+                .and_then(fn_service(|io: TlsStream<TcpStream>| async move {
+                    let protocol = if negotiated_http2(&io) { Protocol::Http2 } else { Protocol::Http1 };
+                    let peer_addr = io.get_ref().0.peer_addr().ok();
+                    Ok((io, protocol, peer_addr))
+                }))
+                .and_then(http.map_err(TlsError::Service))
+        })?
+        .run();
+    Ok(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor_counts_connections_and_handshakes() {
+        let monitor = Arc::new(ConnectionMonitor::new());
+        monitor.record_handshake(Duration::from_millis(12), true);
+        monitor.record_handshake(Duration::from_millis(3_000), false);
+        let first = monitor.open(true);
+        let second = monitor.open(false);
+        drop(first);
+
+        let stats = monitor.stats();
+        assert_eq!((stats.active, stats.opened_total, stats.http2_total), (1, 2, 1));
+        assert_eq!((stats.handshakes_total, stats.handshake_errors_total), (1, 1));
+        assert_eq!(stats.handshake_ms_total, 3_012);
+        drop(second);
+        assert_eq!(monitor.stats().active, 0);
+    }
+
+    #[test]
+    fn test_worker_lag_and_prometheus_output() {
+        let monitor = ConnectionMonitor::new();
+        monitor.record_lag(Duration::from_millis(2));
+        monitor.record_lag(Duration::from_millis(250));
+
+        let stats = monitor.stats();
+        assert_eq!((stats.lag_samples_total, stats.lag_ms_total, stats.stalls_total), (2, 252, 1));
+        let text = stats.to_prometheus();
+        assert!(text.contains("# TYPE emr_http_connections_active gauge\nemr_http_connections_active 0\n"));
+        assert!(text.contains("emr_http_worker_lag_ms_total 252\n"));
+    }
+}
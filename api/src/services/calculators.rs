@@ -0,0 +1,95 @@
+//! Inputs and registry for the clinical calculators.
+//!
+//! Observations and demographics are stored locally, but conditions live on
+//! the FHIR server, so [`active_conditions`] reads the patient's active
+//! `Condition` resources from there. The BMI percentile calculator needs the
+//! CDC BMI-for-age table from the growth references
+//! ([`crate::services::growth_references`]).
+
+use crate::config::GrowthConfig;
+use crate::error::Result;
+use crate::services::growth_references;
+use emr_core::services::calculators::{
+    BmiPercentileCalculator, CalculatorRegistry, Cha2ds2VascCalculator, ConditionCode,
+};
+use emr_core::services::growth::{GrowthMeasure, GrowthReference};
+use emr_core::types::Id;
+use emr_fhir::{FhirGateway, SearchParameters};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Most conditions read per patient
+const MAX_CONDITIONS: u32 = 200;
+
+/// The calculators available with this configuration
+pub fn calculator_registry(config: &GrowthConfig) -> Result<CalculatorRegistry> {
+    let mut registry = CalculatorRegistry::new();
+    registry.register(Arc::new(Cha2ds2VascCalculator));
+    let references = growth_references(config)?;
+    if let Some(table) = references.table(GrowthReference::Cdc, GrowthMeasure::Bmi) {
+        registry.register(Arc::new(BmiPercentileCalculator::new(table.clone())));
+    }
+    Ok(registry)
+}
+
+/// Codes of a patient's active conditions on the FHIR server
+pub async fn active_conditions(gateway: &dyn FhirGateway, patient_id: Id) -> Result<Vec<ConditionCode>> {
+    let params = SearchParameters::new("Condition")
+        .add_parameter("patient", &patient_id.to_string())
+        .add_parameter("clinical-status", "active")
+        .with_count(MAX_CONDITIONS);
+    let bundle = gateway.search(&params).await?;
+    Ok(condition_codes(&bundle))
+}
+
+/// Every coding of the `Condition` resources in a search Bundle
+fn condition_codes(bundle: &Value) -> Vec<ConditionCode> {
+    let entries = bundle.get("entry").and_then(Value::as_array);
+    entries
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.get("resource"))
+        .filter(|resource| resource.get("resourceType").and_then(Value::as_str) == Some("Condition"))
+        .filter_map(|condition| condition.pointer("/code/coding").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|coding| {
+            Some(ConditionCode {
+                system: coding.get("system")?.as_str()?.to_string(),
+                code: coding.get("code")?.as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_condition_codes() {
+        let bundle = json!({
+            "resourceType": "Bundle",
+            "entry": [
+                {"resource": {"resourceType": "Condition", "code": {"coding": [
+                    {"system": "http://snomed.info/sct", "code": "38341003"},
+                    {"system": "http://hl7.org/fhir/sid/icd-10-cm", "code": "I10"}
+                ]}}},
+                {"resource": {"resourceType": "Condition", "code": {"text": "uncoded"}}},
+                {"resource": {"resourceType": "OperationOutcome"}}
+            ]
+        });
+
+        let codes = condition_codes(&bundle);
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[1].code, "I10");
+        assert!(condition_codes(&json!({"resourceType": "Bundle"})).is_empty());
+    }
+
+    #[test]
+    fn test_registry_without_growth_reference() {
+        let registry = calculator_registry(&GrowthConfig::default()).unwrap();
+        let ids: Vec<&str> = registry.list().iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec!["cha2ds2-vasc"]);
+    }
+}
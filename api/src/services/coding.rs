@@ -0,0 +1,134 @@
+//! Conditions for coding an encounter.
+//!
+//! Conditions live on the FHIR server. [`encounter_conditions`] reads those
+//! recorded against the encounter and the patient's active ones, and marks a
+//! condition addressed when it references the encounter or is one of the
+//! encounter's diagnoses. Addressed conditions come first, the encounter's
+//! diagnoses in rank order.
+
+use crate::error::Result;
+use emr_core::domain::Encounter;
+use emr_core::services::coding::{ConditionCoding, RecordedCondition};
+use emr_fhir::{FhirGateway, SearchParameters};
+use serde_json::Value;
+
+/// Most conditions read per search
+const MAX_CONDITIONS: u32 = 200;
+
+/// The encounter's conditions and the patient's active ones
+pub async fn encounter_conditions(gateway: &dyn FhirGateway, encounter: &Encounter) -> Result<Vec<RecordedCondition>> {
+    let encounter_reference = format!("Encounter/{}", encounter.metadata.id);
+    let recorded = SearchParameters::new("Condition")
+        .add_parameter("encounter", &encounter_reference)
+        .with_count(MAX_CONDITIONS);
+    let active = SearchParameters::new("Condition")
+        .add_parameter("patient", &encounter.subject.to_string())
+        .add_parameter("clinical-status", "active,recurrence,relapse")
+        .with_count(MAX_CONDITIONS);
+
+    let mut resources = conditions(&gateway.search(&recorded).await?);
+    for condition in conditions(&gateway.search(&active).await?) {
+        if !resources.iter().any(|existing| existing.get("id") == condition.get("id")) {
+            resources.push(condition);
+        }
+    }
+    Ok(recorded_conditions(&resources, encounter))
+}
+
+/// The `Condition` resources of a search Bundle
+fn conditions(bundle: &Value) -> Vec<Value> {
+    let entries = bundle.get("entry").and_then(Value::as_array);
+    entries
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.get("resource"))
+        .filter(|resource| resource.get("resourceType").and_then(Value::as_str) == Some("Condition"))
+        .cloned()
+        .collect()
+}
+
+/// Conditions as coding input, addressed ones first
+fn recorded_conditions(resources: &[Value], encounter: &Encounter) -> Vec<RecordedCondition> {
+    let encounter_reference = format!("Encounter/{}", encounter.metadata.id);
+    let diagnosis_rank = |id: &str| {
+        encounter
+            .diagnosis
+            .iter()
+            .find(|diagnosis| diagnosis.condition.to_string() == id)
+            .map(|diagnosis| diagnosis.rank.unwrap_or(u32::MAX))
+    };
+
+    let mut recorded: Vec<(Option<u32>, RecordedCondition)> = resources
+        .iter()
+        .filter_map(|resource| {
+            let id = resource.get("id")?.as_str()?.to_string();
+            let codings = resource
+                .pointer("/code/coding")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|coding| {
+                    Some(ConditionCoding {
+                        system: coding.get("system")?.as_str()?.to_string(),
+                        code: coding.get("code")?.as_str()?.to_string(),
+                        display: coding.get("display").and_then(Value::as_str).map(str::to_string),
+                    })
+                })
+                .collect();
+            let rank = diagnosis_rank(&id);
+            let references_encounter =
+                resource.pointer("/encounter/reference").and_then(Value::as_str) == Some(encounter_reference.as_str());
+            Some((
+                rank,
+                RecordedCondition {
+                    id,
+                    codings,
+                    addressed: rank.is_some() || references_encounter,
+                },
+            ))
+        })
+        .collect();
+
+    // Diagnoses by rank, then other addressed conditions, then the rest
+    recorded.sort_by_key(|(rank, condition)| (rank.is_none(), *rank, !condition.addressed));
+    recorded.into_iter().map(|(_, condition)| condition).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::domain::{EncounterClass, EncounterDiagnosis, EncounterStatus};
+    use serde_json::json;
+
+    #[test]
+    fn test_recorded_conditions() {
+        let mut encounter = Encounter::new(EncounterStatus::Finished, EncounterClass::Ambulatory, uuid::Uuid::new_v4());
+        let diagnosis_id = uuid::Uuid::new_v4();
+        encounter.diagnosis.push(EncounterDiagnosis {
+            condition: diagnosis_id,
+            use_: None,
+            rank: Some(1),
+        });
+        let condition = |id: String, code: &str, encounter: Option<String>| {
+            json!({
+                "resourceType": "Condition",
+                "id": id,
+                "code": {"coding": [{"system": "http://hl7.org/fhir/sid/icd-10-cm", "code": code}]},
+                "encounter": encounter.map(|reference| json!({"reference": reference}))
+            })
+        };
+        let bundle = json!({"resourceType": "Bundle", "entry": [
+            {"resource": condition("old".to_string(), "E11.9", None)},
+            {"resource": condition("seen".to_string(), "J06.9", Some(format!("Encounter/{}", encounter.metadata.id)))},
+            {"resource": condition(diagnosis_id.to_string(), "I10", None)},
+            {"resource": {"resourceType": "OperationOutcome"}}
+        ]});
+
+        let recorded = recorded_conditions(&conditions(&bundle), &encounter);
+        let order: Vec<(&str, bool)> = recorded
+            .iter()
+            .map(|condition| (condition.codings[0].code.as_str(), condition.addressed))
+            .collect();
+        assert_eq!(order, vec![("I10", true), ("J06.9", true), ("E11.9", false)]);
+    }
+}
@@ -0,0 +1,75 @@
+//! Backpressure for bulk device observation uploads.
+//!
+//! [`DeviceIngestGate`] admits `POST /observations/_bulk` batches within the
+//! `device_ingest` limits (see `emr_core::services::device_observations`):
+//! oversized batches are refused with 400, and while `max_concurrent`
+//! batches are being stored further ones get 429 so devices back off
+//! instead of queueing on the database pool.
+//!
+//! Current status: each API instance admits `max_concurrent` batches on its
+//! own.
+
+use crate::error::{ApiError, Result};
+use emr_core::services::device_observations::IngestLimits;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Batches being stored, up to the configured limits
+#[derive(Debug, Clone)]
+pub struct DeviceIngestGate {
+    limits: IngestLimits,
+    permits: Arc<Semaphore>,
+}
+
+impl DeviceIngestGate {
+    /// Create a gate with the given limits.
+    pub fn new(limits: IngestLimits) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limits.max_concurrent)),
+            limits,
+        }
+    }
+
+    /// Limits the gate applies
+    pub fn limits(&self) -> &IngestLimits {
+        &self.limits
+    }
+
+    /// Admit a batch of `readings`; it counts against `max_concurrent`
+    /// until the permit is dropped.
+    pub fn admit(&self, readings: usize) -> Result<OwnedSemaphorePermit> {
+        self.limits
+            .check_batch(readings)
+            .map_err(|message| ApiError::validation_error(&message))?;
+        self.permits
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| ApiError::too_many_requests("Too many observation batches are being stored; retry shortly"))
+    }
+}
+
+impl Default for DeviceIngestGate {
+    fn default() -> Self {
+        Self::new(IngestLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admit() {
+        let gate = DeviceIngestGate::new(IngestLimits {
+            max_readings: 10,
+            max_concurrent: 1,
+            ..IngestLimits::default()
+        });
+
+        assert!(matches!(gate.admit(11), Err(ApiError::Validation { .. })));
+        let permit = gate.admit(10).unwrap();
+        assert!(matches!(gate.admit(1), Err(ApiError::TooManyRequests { .. })));
+        drop(permit);
+        assert!(gate.admit(1).is_ok());
+    }
+}
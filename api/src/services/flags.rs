@@ -0,0 +1,123 @@
+//! Runtime feature flags.
+//!
+//! [`FeatureFlags`] answers "is this feature on for this user?" from the
+//! flags in `emr.feature_flags`. Flags are read in one query and cached for
+//! `flags.cache_ttl` seconds, so a toggle made through the admin API reaches
+//! other API instances within that time; the instance that made it drops its
+//! cache straight away. Flags nobody defined are off.
+
+use crate::config::FlagConfig;
+use crate::database::Pool;
+use crate::error::Result;
+use crate::repositories::FeatureFlagRepository;
+use crate::AppState;
+use emr_core::flags::FeatureFlag;
+use emr_core::types::Id;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Flags as last read and when
+type FlagCache = Option<(Instant, HashMap<String, FeatureFlag>)>;
+
+/// Cached, database-backed feature flags
+#[derive(Clone)]
+pub struct FeatureFlags {
+    pool: Pool,
+    /// Cache TTL in seconds; changed when the configuration is reloaded
+    ttl: Arc<AtomicU64>,
+    cache: Arc<RwLock<FlagCache>>,
+}
+
+impl FeatureFlags {
+    /// Create a flag service over the API database pool.
+    pub fn new(pool: Pool, config: &FlagConfig) -> Self {
+        Self {
+            pool,
+            ttl: Arc::new(AtomicU64::new(config.cache_ttl)),
+            cache: Arc::default(),
+        }
+    }
+
+    /// Whether a flag is on for a subject (usually the user) of a tenant.
+    pub async fn is_enabled(&self, key: &str, tenant_id: Option<Id>, subject: &str) -> bool {
+        self.flags()
+            .await
+            .get(key)
+            .is_some_and(|flag| flag.is_enabled(tenant_id, subject))
+    }
+
+    /// Every flag evaluated for a subject of a tenant, by key.
+    pub async fn evaluate(&self, tenant_id: Option<Id>, subject: &str) -> HashMap<String, bool> {
+        self.flags()
+            .await
+            .iter()
+            .map(|(key, flag)| (key.clone(), flag.is_enabled(tenant_id, subject)))
+            .collect()
+    }
+
+    /// Change how long flags are cached.
+    pub fn set_cache_ttl(&self, seconds: u64) {
+        self.ttl.store(seconds, Ordering::Relaxed);
+    }
+
+    /// Drop the cache so the next check reads the flags again, e.g. after a toggle.
+    pub async fn invalidate(&self) {
+        *self.cache.write().await = None;
+    }
+
+    /// Read the flags into the cache now, e.g. while warming up after boot.
+    /// Returns how many there are.
+    pub async fn warm(&self) -> Result<usize> {
+        let flags = self.load().await?;
+        let count = flags.len();
+        *self.cache.write().await = Some((Instant::now(), flags));
+        Ok(count)
+    }
+
+    /// The cached flags, read again once older than the TTL.
+    ///
+    /// A failed read keeps serving the flags read last, or none at all, so a
+    /// database outage turns features off rather than failing requests.
+    async fn flags(&self) -> HashMap<String, FeatureFlag> {
+        let ttl = Duration::from_secs(self.ttl.load(Ordering::Relaxed));
+        if let Some((read_at, flags)) = self.cache.read().await.as_ref() {
+            if read_at.elapsed() < ttl {
+                return flags.clone();
+            }
+        }
+
+        let mut cache = self.cache.write().await;
+        // Another request may have read them while this one waited
+        if let Some((read_at, flags)) = cache.as_ref() {
+            if read_at.elapsed() < ttl {
+                return flags.clone();
+            }
+        }
+
+        match self.load().await {
+            Ok(flags) => {
+                *cache = Some((Instant::now(), flags.clone()));
+                flags
+            }
+            Err(e) => {
+                tracing::warn!("Failed to read feature flags: {}", e);
+                cache.as_ref().map(|(_, flags)| flags.clone()).unwrap_or_default()
+            }
+        }
+    }
+
+    async fn load(&self) -> Result<HashMap<String, FeatureFlag>> {
+        let flags = FeatureFlagRepository::new().list(&self.pool).await?;
+        Ok(flags.into_iter().map(|flag| (flag.key.clone(), flag)).collect())
+    }
+}
+
+impl AppState {
+    /// Runtime feature flags.
+    pub fn flags(&self) -> &FeatureFlags {
+        &self.feature_flags
+    }
+}
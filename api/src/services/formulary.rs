@@ -0,0 +1,145 @@
+//! Formulary lookups for prescribing.
+//!
+//! With `formulary.base_url` set, lookups go to the pharmacy benefit
+//! manager's formulary API as `GET {base_url}/formulary/{rxnorm}?plan_id=`,
+//! which answers with a [`FormularyStatus`] or 404 for an unlisted
+//! medication. Without it, the entries in `formulary.mock_path` answer, and
+//! with neither every medication is unlisted. Either way answers are cached
+//! for `formulary.cache_ttl_secs`; the provider is built on first use and kept
+//! for the life of the process so the cache is shared by all requests.
+
+use crate::config::FormularyConfig;
+use crate::error::{ApiError, Result};
+use async_trait::async_trait;
+use emr_core::services::formulary::{
+    CachedFormularyProvider, FormularyProvider, FormularyStatus, MockFormularyProvider,
+};
+use emr_core::{Error as CoreError, Result as CoreResult};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+static FORMULARY_PROVIDER: OnceLock<Arc<dyn FormularyProvider>> = OnceLock::new();
+
+/// The configured formulary provider, built on first use
+pub fn formulary_provider(config: &FormularyConfig) -> Result<Arc<dyn FormularyProvider>> {
+    if let Some(provider) = FORMULARY_PROVIDER.get() {
+        return Ok(provider.clone());
+    }
+    let provider = build(config)?;
+    Ok(FORMULARY_PROVIDER.get_or_init(|| provider).clone())
+}
+
+/// Provider for the configuration, behind the cache
+fn build(config: &FormularyConfig) -> Result<Arc<dyn FormularyProvider>> {
+    let inner: Arc<dyn FormularyProvider> = match config.base_url.as_deref().filter(|url| !url.is_empty()) {
+        Some(base_url) => Arc::new(HttpFormularyProvider::new(
+            base_url,
+            config.api_key.clone(),
+            Duration::from_secs(config.timeout),
+        )?),
+        None => Arc::new(mock_formulary(config.mock_path.as_deref())?),
+    };
+    let ttl = chrono::Duration::seconds(i64::try_from(config.cache_ttl_secs).unwrap_or(i64::MAX / 1000));
+    Ok(Arc::new(CachedFormularyProvider::new(inner, ttl)))
+}
+
+/// An entry of the development formulary file
+#[derive(Debug, Deserialize)]
+struct MockEntry {
+    plan_id: Option<String>,
+    #[serde(flatten)]
+    status: FormularyStatus,
+}
+
+/// Formulary from a JSON array of entries, or an empty one without a file
+fn mock_formulary(path: Option<&str>) -> Result<MockFormularyProvider> {
+    let Some(path) = path else {
+        return Ok(MockFormularyProvider::new());
+    };
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| ApiError::configuration_error(&format!("Cannot read the formulary at {}: {}", path, e)))?;
+    let entries: Vec<MockEntry> = serde_json::from_str(&contents)
+        .map_err(|e| ApiError::configuration_error(&format!("Invalid formulary at {}: {}", path, e)))?;
+
+    Ok(entries.into_iter().fold(MockFormularyProvider::new(), |formulary, entry| {
+        formulary.with_entry(entry.plan_id.as_deref(), entry.status)
+    }))
+}
+
+/// Formulary API of a pharmacy benefit manager
+pub struct HttpFormularyProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl HttpFormularyProvider {
+    /// Create a provider for the API at `base_url`
+    pub fn new(base_url: &str, api_key: Option<String>, timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        })
+    }
+
+    fn url(&self, medication_code: &str) -> String {
+        format!("{}/formulary/{}", self.base_url, medication_code)
+    }
+}
+
+/// Report a formulary API failure through the core error type
+fn unavailable(message: impl std::fmt::Display) -> CoreError {
+    CoreError::external_service_error("Formulary", &message.to_string())
+}
+
+#[async_trait]
+impl FormularyProvider for HttpFormularyProvider {
+    async fn lookup(&self, plan_id: Option<&str>, medication_code: &str) -> CoreResult<Option<FormularyStatus>> {
+        let mut request = self.client.get(self.url(medication_code));
+        if let Some(plan_id) = plan_id {
+            request = request.query(&[("plan_id", plan_id)]);
+        }
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await.map_err(unavailable)?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => response.json().await.map(Some).map_err(unavailable),
+            status => Err(unavailable(format!("formulary lookup failed with {}", status))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_formulary_file() {
+        let path = std::env::temp_dir().join(format!("formulary-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"[{"plan_id": "PLAN-A", "medication_code": "617314", "covered": true, "tier": 3,
+                 "prior_authorization_required": true, "quantity_limit": "30 per 30 days",
+                 "alternatives": [{"medication_code": "259255", "display": null, "tier": 1}]}]"#,
+        )
+        .unwrap();
+
+        let formulary = mock_formulary(path.to_str()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let status = formulary.lookup(Some("PLAN-A"), "617314").await.unwrap().unwrap();
+        assert!(status.prior_authorization_required);
+        assert_eq!(status.alternatives[0].tier, Some(1));
+        assert!(formulary.lookup(None, "617314").await.unwrap().is_none());
+
+        assert!(mock_formulary(Some("/nonexistent/formulary.json")).is_err());
+        let provider = HttpFormularyProvider::new("https://pbm.example.com/", None, Duration::from_secs(5)).unwrap();
+        assert_eq!(provider.url("617314"), "https://pbm.example.com/formulary/617314");
+    }
+}
@@ -0,0 +1,76 @@
+//! Growth references for pediatric charts and calculators.
+//!
+//! The WHO and CDC tables named in the `growth` configuration are read on
+//! first use and kept for the life of the process. A table that is not
+//! configured is simply absent: charts then show the measurements at the ages
+//! it covers without percentiles.
+
+use crate::config::GrowthConfig;
+use crate::error::{ApiError, Result};
+use emr_core::services::growth::{GrowthMeasure, GrowthReference, GrowthReferences, LmsTable};
+use std::sync::{Arc, OnceLock};
+
+static GROWTH_REFERENCES: OnceLock<Arc<GrowthReferences>> = OnceLock::new();
+
+/// The configured growth references, read on first use
+pub fn growth_references(config: &GrowthConfig) -> Result<Arc<GrowthReferences>> {
+    if let Some(references) = GROWTH_REFERENCES.get() {
+        return Ok(references.clone());
+    }
+    let references = load(config)?;
+    Ok(GROWTH_REFERENCES.get_or_init(|| Arc::new(references)).clone())
+}
+
+/// Read every configured table
+fn load(config: &GrowthConfig) -> Result<GrowthReferences> {
+    let mut references = GrowthReferences::new();
+
+    let cdc = [
+        (GrowthMeasure::Weight, &config.cdc_weight_for_age_path),
+        (GrowthMeasure::Height, &config.cdc_stature_for_age_path),
+        (GrowthMeasure::Bmi, &config.cdc_bmi_for_age_path),
+    ];
+    for (measure, path) in cdc {
+        if let Some(path) = path {
+            let table = LmsTable::from_cdc_csv(&read(path)?)?;
+            references.insert(GrowthReference::Cdc, measure, Arc::new(table));
+        }
+    }
+
+    let who = [
+        (GrowthMeasure::Weight, &config.who_weight_for_age),
+        (GrowthMeasure::Height, &config.who_length_for_age),
+        (GrowthMeasure::Bmi, &config.who_bmi_for_age),
+    ];
+    for (measure, paths) in who {
+        if let Some(paths) = paths {
+            let table = LmsTable::from_who_tables(&read(&paths.boys)?, &read(&paths.girls)?)?;
+            references.insert(GrowthReference::Who, measure, Arc::new(table));
+        }
+    }
+
+    Ok(references)
+}
+
+/// A reference file's contents
+fn read(path: &str) -> Result<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| ApiError::internal_error(&format!("Cannot read the growth reference at {}: {}", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_skips_unconfigured_tables() {
+        let references = load(&GrowthConfig::default()).unwrap();
+        assert!(references.table(GrowthReference::Cdc, GrowthMeasure::Bmi).is_none());
+
+        let config = GrowthConfig {
+            cdc_weight_for_age_path: Some("/nonexistent/wtage.csv".to_string()),
+            ..GrowthConfig::default()
+        };
+        assert!(load(&config).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Imaging study lookups in the PACS.
+//!
+//! With `imaging.dicomweb_url` set, studies are searched with QIDO-RS
+//! (`GET {dicomweb_url}/studies?PatientID=`) and a study's series are read
+//! from its WADO-RS instance metadata (`GET {dicomweb_url}/studies/{uid}/metadata`),
+//! both as DICOM JSON. Without it, the instances in `imaging.mock_path`
+//! answer, and with neither no patient has studies. The archive is built on
+//! first use and kept for the life of the process.
+
+use crate::config::ImagingConfig;
+use crate::error::{ApiError, Result};
+use async_trait::async_trait;
+use emr_core::domain::imaging::tags;
+use emr_core::domain::ImagingStudy;
+use emr_core::services::imaging::{ImagingArchive, MockImagingArchive};
+use emr_core::types::Id;
+use emr_core::{Error as CoreError, Result as CoreResult};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+static IMAGING_ARCHIVE: OnceLock<Arc<dyn ImagingArchive>> = OnceLock::new();
+
+/// Study attributes QIDO-RS leaves out unless asked for
+const STUDY_INCLUDE_FIELDS: [&str; 5] = [
+    tags::STUDY_DESCRIPTION,
+    tags::MODALITIES_IN_STUDY,
+    tags::REFERRING_PHYSICIAN_NAME,
+    tags::NUMBER_OF_STUDY_RELATED_SERIES,
+    tags::NUMBER_OF_STUDY_RELATED_INSTANCES,
+];
+
+/// The configured imaging archive, built on first use
+pub fn imaging_archive(config: &ImagingConfig) -> Result<Arc<dyn ImagingArchive>> {
+    if let Some(archive) = IMAGING_ARCHIVE.get() {
+        return Ok(archive.clone());
+    }
+    let archive: Arc<dyn ImagingArchive> = match config.dicomweb_url.as_deref().filter(|url| !url.is_empty()) {
+        Some(base_url) => Arc::new(DicomWebArchive::new(
+            base_url,
+            config.api_key.clone(),
+            Duration::from_secs(config.timeout),
+        )?),
+        None => Arc::new(mock_archive(config.mock_path.as_deref())?),
+    };
+    Ok(IMAGING_ARCHIVE.get_or_init(|| archive).clone())
+}
+
+/// Archive of a JSON array of instance metadata, or an empty one without a file
+fn mock_archive(path: Option<&str>) -> Result<MockImagingArchive> {
+    let Some(path) = path else {
+        return Ok(MockImagingArchive::new());
+    };
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| ApiError::configuration_error(&format!("Cannot read the imaging archive at {}: {}", path, e)))?;
+    let instances: Vec<Value> = serde_json::from_str(&contents)
+        .map_err(|e| ApiError::configuration_error(&format!("Invalid imaging archive at {}: {}", path, e)))?;
+
+    Ok(instances.into_iter().fold(MockImagingArchive::new(), MockImagingArchive::with_instance))
+}
+
+/// DICOMweb API of a PACS
+pub struct DicomWebArchive {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl DicomWebArchive {
+    /// Create an archive for the DICOMweb root at `base_url`
+    pub fn new(base_url: &str, api_key: Option<String>, timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        })
+    }
+
+    fn studies_url(&self) -> String {
+        format!("{}/studies", self.base_url)
+    }
+
+    fn metadata_url(&self, study_uid: &str) -> String {
+        format!("{}/studies/{}/metadata", self.base_url, study_uid)
+    }
+
+    /// GET a DICOM JSON array; `None` when the resource does not exist
+    async fn get(&self, url: &str, query: &[(&str, &str)]) -> CoreResult<Option<Vec<Value>>> {
+        let mut request = self
+            .client
+            .get(url)
+            .query(query)
+            .header(reqwest::header::ACCEPT, "application/dicom+json");
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await.map_err(unavailable)?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            // QIDO-RS answers a search without matches with 204
+            StatusCode::NO_CONTENT => Ok(Some(Vec::new())),
+            status if status.is_success() => response.json().await.map(Some).map_err(unavailable),
+            status => Err(unavailable(format!("DICOMweb request failed with {}", status))),
+        }
+    }
+
+    /// QIDO-RS study search
+    async fn search(&self, pacs_patient_id: &str, study_uid: Option<&str>) -> CoreResult<Vec<Value>> {
+        let mut query = vec![(tags::PATIENT_ID, pacs_patient_id)];
+        if let Some(study_uid) = study_uid {
+            query.push((tags::STUDY_INSTANCE_UID, study_uid));
+        }
+        query.extend(STUDY_INCLUDE_FIELDS.iter().map(|tag| ("includefield", *tag)));
+        Ok(self.get(&self.studies_url(), &query).await?.unwrap_or_default())
+    }
+}
+
+/// Report a PACS failure through the core error type
+fn unavailable(message: impl std::fmt::Display) -> CoreError {
+    CoreError::external_service_error("PACS", &message.to_string())
+}
+
+#[async_trait]
+impl ImagingArchive for DicomWebArchive {
+    async fn search_studies(&self, patient_id: Id, pacs_patient_id: &str) -> CoreResult<Vec<ImagingStudy>> {
+        self.search(pacs_patient_id, None)
+            .await?
+            .iter()
+            .map(|dataset| ImagingStudy::from_dicom_json(patient_id, dataset))
+            .collect()
+    }
+
+    async fn find_study(
+        &self,
+        patient_id: Id,
+        pacs_patient_id: &str,
+        study_uid: &str,
+    ) -> CoreResult<Option<ImagingStudy>> {
+        // Searching by patient as well keeps another patient's study out
+        let Some(dataset) = self.search(pacs_patient_id, Some(study_uid)).await?.into_iter().next() else {
+            return Ok(None);
+        };
+        let study = ImagingStudy::from_dicom_json(patient_id, &dataset)?;
+        match self.get(&self.metadata_url(study_uid), &[]).await? {
+            Some(instances) => study.with_instances(&instances).map(Some),
+            None => Ok(Some(study)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_archive_file() {
+        let path = std::env::temp_dir().join(format!("imaging-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"[{"00100020": {"vr": "LO", "Value": ["MRN-0042"]},
+                 "0020000D": {"vr": "UI", "Value": ["1.2.840.1"]},
+                 "0020000E": {"vr": "UI", "Value": ["1.2.840.1.1"]},
+                 "00080060": {"vr": "CS", "Value": ["CR"]}}]"#,
+        )
+        .unwrap();
+
+        let archive = mock_archive(path.to_str()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let studies = archive.search_studies(uuid::Uuid::new_v4(), "MRN-0042").await.unwrap();
+        assert_eq!(studies[0].modalities, vec!["CR"]);
+
+        assert!(mock_archive(Some("/nonexistent/imaging.json")).is_err());
+        let pacs = DicomWebArchive::new("https://pacs.example.com/dicom-web/", None, Duration::from_secs(5)).unwrap();
+        assert_eq!(pacs.metadata_url("1.2.840.1"), "https://pacs.example.com/dicom-web/studies/1.2.840.1/metadata");
+    }
+}
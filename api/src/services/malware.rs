@@ -0,0 +1,141 @@
+//! Malware scanning of uploads.
+//!
+//! With `scanning.clamav_address` set, uploads are streamed to ClamAV's
+//! `clamd` with the `zINSTREAM` command: the content in length-prefixed
+//! chunks of `scanning.chunk_size` bytes, then a zero-length chunk, after
+//! which `clamd` answers with one NUL-terminated reply. Without it nothing is
+//! scanned. The scanner is built on first use and kept for the life of the
+//! process.
+
+use crate::config::ScanningConfig;
+use crate::error::Result;
+use async_trait::async_trait;
+use emr_core::services::malware::{parse_clamd_reply, MalwareScanner, NoopScanner, ScanVerdict};
+use emr_core::{Error as CoreError, Result as CoreResult};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+static MALWARE_SCANNER: OnceLock<Arc<dyn MalwareScanner>> = OnceLock::new();
+
+/// Longest reply read from `clamd`
+const MAX_REPLY_BYTES: u64 = 4096;
+
+/// The configured malware scanner, built on first use
+pub fn malware_scanner(config: &ScanningConfig) -> Result<Arc<dyn MalwareScanner>> {
+    if let Some(scanner) = MALWARE_SCANNER.get() {
+        return Ok(scanner.clone());
+    }
+    let scanner: Arc<dyn MalwareScanner> = match config.clamav_address.as_deref().filter(|address| !address.is_empty()) {
+        Some(address) => Arc::new(ClamAvScanner::new(
+            address,
+            Duration::from_secs(config.timeout),
+            config.chunk_size,
+        )),
+        None => {
+            tracing::warn!("No malware scanner is configured; uploads are not scanned");
+            Arc::new(NoopScanner)
+        }
+    };
+    Ok(MALWARE_SCANNER.get_or_init(|| scanner).clone())
+}
+
+/// ClamAV daemon reached over TCP
+pub struct ClamAvScanner {
+    address: String,
+    timeout: Duration,
+    chunk_size: usize,
+}
+
+impl ClamAvScanner {
+    /// Create a scanner for the `clamd` at `address`
+    pub fn new(address: &str, timeout: Duration, chunk_size: usize) -> Self {
+        Self {
+            address: address.to_string(),
+            timeout,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    async fn instream(&self, data: &[u8]) -> std::io::Result<String> {
+        let mut stream = TcpStream::connect(&self.address).await?;
+        stream.write_all(b"zINSTREAM\0").await?;
+        for chunk in data.chunks(self.chunk_size) {
+            stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+            stream.write_all(chunk).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+        stream.flush().await?;
+
+        let mut reply = Vec::new();
+        stream.take(MAX_REPLY_BYTES).read_to_end(&mut reply).await?;
+        Ok(String::from_utf8_lossy(&reply).into_owned())
+    }
+}
+
+#[async_trait]
+impl MalwareScanner for ClamAvScanner {
+    fn name(&self) -> &str {
+        "clamav"
+    }
+
+    async fn scan(&self, data: &[u8]) -> CoreResult<ScanVerdict> {
+        let reply = tokio::time::timeout(self.timeout, self.instream(data))
+            .await
+            .map_err(|_| CoreError::external_service_error("ClamAV", "Scan timed out"))?
+            .map_err(|e| CoreError::external_service_error("ClamAV", &e.to_string()))?;
+        parse_clamd_reply(&reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// A `clamd` that reads one stream and flags content containing `EICAR`
+    async fn fake_clamd() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut command = [0u8; 10];
+            socket.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+
+            let mut content = Vec::new();
+            loop {
+                let length = socket.read_u32().await.unwrap() as usize;
+                if length == 0 {
+                    break;
+                }
+                let mut chunk = vec![0u8; length];
+                socket.read_exact(&mut chunk).await.unwrap();
+                content.extend(chunk);
+            }
+            let reply: &[u8] = if content.windows(5).any(|window| window == b"EICAR") {
+                b"stream: Eicar-Signature FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            socket.write_all(reply).await.unwrap();
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_clamav_scanner() {
+        let scanner = ClamAvScanner::new(&fake_clamd().await, Duration::from_secs(5), 4);
+        let verdict = scanner.scan(b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD").await.unwrap();
+        assert_eq!(
+            verdict,
+            ScanVerdict::Infected {
+                signature: "Eicar-Signature".to_string()
+            }
+        );
+
+        let scanner = ClamAvScanner::new(&fake_clamd().await, Duration::from_secs(5), 4);
+        assert_eq!(scanner.scan(b"%PDF-1.4 harmless").await.unwrap(), ScanVerdict::Clean);
+    }
+}
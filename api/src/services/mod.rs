@@ -3,32 +3,608 @@
 //! During the architecture reset, this module documents where business rules
 //! should live once handlers are split from domain logic and persistence.
 
-use crate::error::Result;
-use crate::models::PatientModel;
+use crate::auth::scopes::{ScopeAccess, Scopes};
+use crate::database::Pool;
+use crate::error::{ApiError, Result};
+use crate::repositories::{
+    CareTeamRepository, EncounterRepository, ObservationRepository, ObservationSearch, OrganizationRepository,
+};
+use async_trait::async_trait;
+use emr_core::domain::traits::Validatable;
+use emr_core::domain::values::ContactSystem;
+use emr_core::domain::{
+    ContactVerification, EmergencyAccess, Encounter, EncounterParticipant, EncounterStatus, Observation,
+    Organization, OrganizationNode, VerificationPolicy,
+};
+use emr_core::services::{
+    EncounterService as CoreEncounterService, ObservationService as CoreObservationService,
+    OrganizationService as CoreOrganizationService, PatientAccess, Permission,
+    SecurityService as CoreSecurityService,
+};
 use emr_core::types::Id;
+use emr_core::{Error as CoreError, Result as CoreResult};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 
-/// Patient service
-pub struct PatientService;
+pub mod calculators;
+pub mod coding;
+pub mod device_ingest;
+pub mod flags;
+pub mod formulary;
+pub mod growth;
+pub mod imaging;
+pub mod malware;
+pub mod prefetch;
+pub mod referrals;
+pub mod reload;
+pub mod secrets;
+pub mod terminology;
+pub mod throttle;
+pub mod warmup;
 
-impl PatientService {
-    /// Create a new patient service.
-    pub fn new() -> Self {
-        Self
+pub use calculators::{active_conditions, calculator_registry};
+pub use coding::encounter_conditions;
+pub use device_ingest::DeviceIngestGate;
+pub use flags::FeatureFlags;
+pub use formulary::formulary_provider;
+pub use growth::growth_references;
+pub use imaging::imaging_archive;
+pub use malware::malware_scanner;
+pub use prefetch::{EncounterPrefetchService, DEFAULT_PREFETCH_CONCURRENCY};
+pub use referrals::ReferralExchange;
+pub use reload::ConfigReloader;
+pub use secrets::secret_resolver;
+pub use terminology::Terminology;
+pub use throttle::ThrottleService;
+pub use warmup::{CacheWarmer, Readiness};
+
+/// Organization service
+///
+/// Organizations are stored in `emr.organizations`; the hierarchy is read
+/// with [`crate::repositories::ORGANIZATION_HIERARCHY_QUERY`].
+#[derive(Clone)]
+pub struct OrganizationService {
+    pool: Pool,
+}
+
+impl OrganizationService {
+    /// Create an organization service over the API database pool.
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    /// A page of organizations sorted by name, and how many there are in all.
+    pub async fn list_organizations(&self, limit: u32, offset: u32) -> Result<(Vec<Organization>, u64)> {
+        OrganizationRepository::new().list(&self.pool, limit, offset).await
+    }
+
+    /// Get an organization and its descendants as a tree.
+    pub async fn get_organization_tree(&self, id: Id) -> Result<OrganizationNode> {
+        let organizations = self.get_organization_hierarchy(id).await?;
+        Ok(OrganizationNode::build(id, &organizations)?)
+    }
+
+    /// Check that `parent_id` exists and that linking to it keeps the
+    /// hierarchy acyclic.
+    async fn check_parent(&self, id: Id, parent_id: Id) -> CoreResult<()> {
+        let ancestors = OrganizationRepository::new()
+            .ancestors(&self.pool, parent_id)
+            .await
+            .map_err(core_error)?;
+        if ancestors.is_empty() {
+            return Err(CoreError::validation_error_with_field("Parent organization does not exist", "part_of"));
+        }
+        if ancestors.contains(&id) {
+            return Err(CoreError::validation_error_with_field(
+                "Organization cannot be part of one of its descendants",
+                "part_of",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Carry a repository error through a `core` service trait
+fn core_error(error: ApiError) -> CoreError {
+    match error {
+        ApiError::Core(error) => error,
+        ApiError::Validation { message } => CoreError::validation_error(&message),
+        ApiError::Conflict { message } => CoreError::data_integrity_error(&message),
+        error => CoreError::InternalError {
+            message: error.to_string(),
+        },
+    }
+}
+
+#[async_trait]
+impl CoreOrganizationService for OrganizationService {
+    async fn create_organization(&self, organization: Organization) -> CoreResult<Organization> {
+        Validatable::validate(&organization)?;
+        if let Some(parent_id) = organization.part_of {
+            self.check_parent(organization.metadata.id, parent_id).await?;
+        }
+
+        OrganizationRepository::new()
+            .insert(&self.pool, &organization)
+            .await
+            .map_err(core_error)?;
+        Ok(organization)
+    }
+
+    async fn get_organization(&self, id: Id) -> CoreResult<Option<Organization>> {
+        OrganizationRepository::new().find(&self.pool, id).await.map_err(core_error)
+    }
+
+    async fn update_organization(&self, mut organization: Organization) -> CoreResult<Organization> {
+        Validatable::validate(&organization)?;
+
+        let repository = OrganizationRepository::new();
+        let existing = repository
+            .find(&self.pool, organization.metadata.id)
+            .await
+            .map_err(core_error)?
+            .ok_or_else(|| CoreError::entity_not_found("Organization", organization.metadata.id))?;
+
+        if let Some(parent_id) = organization.part_of {
+            self.check_parent(organization.metadata.id, parent_id).await?;
+        }
+
+        organization.metadata.created_at = existing.metadata.created_at;
+        organization.metadata.version = existing.metadata.version;
+        organization.metadata.update();
+
+        let stored = repository
+            .update(&self.pool, &organization, existing.metadata.version)
+            .await
+            .map_err(core_error)?;
+        if !stored {
+            return Err(CoreError::data_integrity_error(
+                "The organization was changed by another request; reload it and try again",
+            ));
+        }
+        Ok(organization)
+    }
+
+    async fn delete_organization(&self, id: Id) -> CoreResult<()> {
+        let repository = OrganizationRepository::new();
+        if repository.has_children(&self.pool, id).await.map_err(core_error)? {
+            return Err(CoreError::business_rule_violation(
+                "organization_has_children",
+                "Reassign or delete child organizations first",
+            ));
+        }
+
+        if !repository.delete(&self.pool, id).await.map_err(core_error)? {
+            return Err(CoreError::entity_not_found("Organization", id));
+        }
+        Ok(())
+    }
+
+    async fn get_organization_hierarchy(&self, id: Id) -> CoreResult<Vec<Organization>> {
+        let hierarchy = OrganizationRepository::new()
+            .hierarchy(&self.pool, id)
+            .await
+            .map_err(core_error)?;
+        if hierarchy.is_empty() {
+            return Err(CoreError::entity_not_found("Organization", id));
+        }
+        Ok(hierarchy)
+    }
+}
+
+/// Encounter service
+///
+/// Encounters are stored in `emr.encounters`; every write brings the
+/// patient's summary up to date in the same transaction.
+#[derive(Clone)]
+pub struct EncounterService {
+    pool: Pool,
+}
+
+impl EncounterService {
+    /// Create an encounter service over the API database pool.
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    /// A page of encounters, most recently updated first, optionally filtered
+    /// by status and patient, and how many match in all.
+    pub async fn list_encounters(
+        &self,
+        status: Option<&EncounterStatus>,
+        patient_id: Option<Id>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Encounter>, u64)> {
+        EncounterRepository::new().list(&self.pool, status, patient_id, limit, offset).await
+    }
+
+    /// Record the patient's arrival for a planned encounter.
+    pub async fn check_in_encounter(&self, id: Id) -> CoreResult<Encounter> {
+        self.modify(id, Encounter::check_in).await
+    }
+
+    /// Add a participant to an encounter.
+    pub async fn add_participant(&self, id: Id, participant: EncounterParticipant) -> CoreResult<Encounter> {
+        self.modify(id, |encounter| encounter.add_participant(participant)).await
+    }
+
+    /// Move an encounter to a new location.
+    pub async fn assign_location(&self, id: Id, location: Id) -> CoreResult<Encounter> {
+        self.modify(id, |encounter| encounter.assign_location(location)).await
+    }
+
+    /// Apply a domain operation to a stored encounter.
+    async fn modify<F>(&self, id: Id, operation: F) -> CoreResult<Encounter>
+    where
+        F: FnOnce(&mut Encounter) -> CoreResult<()> + Send,
+    {
+        let mut encounter = self
+            .get_encounter(id)
+            .await?
+            .ok_or_else(|| CoreError::entity_not_found("Encounter", id))?;
+
+        let previous_version = encounter.metadata.version;
+        operation(&mut encounter)?;
+        if encounter.metadata.version != previous_version {
+            self.store(&encounter, previous_version).await?;
+        }
+        Ok(encounter)
+    }
+
+    /// Write a changed encounter, refusing lost updates
+    async fn store(&self, encounter: &Encounter, previous_version: u64) -> CoreResult<()> {
+        let stored = EncounterRepository::new()
+            .update(&self.pool, encounter, previous_version)
+            .await
+            .map_err(core_error)?;
+        if !stored {
+            return Err(CoreError::data_integrity_error(
+                "The encounter was changed by another request; reload it and try again",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Keep what an update must not change from the stored encounter
+fn carry_over(existing: &Encounter, encounter: &mut Encounter) {
+    // Status changes go through the workflow endpoints, not updates
+    encounter.status = existing.status.clone();
+    encounter.metadata.created_at = existing.metadata.created_at;
+    encounter.metadata.version = existing.metadata.version;
+    encounter.metadata.update();
+}
+
+#[async_trait]
+impl CoreEncounterService for EncounterService {
+    async fn create_encounter(&self, encounter: Encounter) -> CoreResult<Encounter> {
+        Validatable::validate(&encounter)?;
+
+        EncounterRepository::new()
+            .insert(&self.pool, &encounter)
+            .await
+            .map_err(core_error)?;
+        Ok(encounter)
+    }
+
+    async fn get_encounter(&self, id: Id) -> CoreResult<Option<Encounter>> {
+        EncounterRepository::new().find(&self.pool, id).await.map_err(core_error)
+    }
+
+    async fn update_encounter(&self, mut encounter: Encounter) -> CoreResult<Encounter> {
+        Validatable::validate(&encounter)?;
+
+        let existing = self
+            .get_encounter(encounter.metadata.id)
+            .await?
+            .ok_or_else(|| CoreError::entity_not_found("Encounter", encounter.metadata.id))?;
+
+        carry_over(&existing, &mut encounter);
+        self.store(&encounter, existing.metadata.version).await?;
+        Ok(encounter)
+    }
+
+    async fn get_patient_encounters(&self, patient_id: Id) -> CoreResult<Vec<Encounter>> {
+        let (encounters, _) = self
+            .list_encounters(None, Some(patient_id), u32::MAX, 0)
+            .await
+            .map_err(core_error)?;
+        Ok(encounters)
+    }
+
+    async fn start_encounter(&self, id: Id) -> CoreResult<()> {
+        self.modify(id, Encounter::start).await.map(|_| ())
+    }
+
+    async fn end_encounter(&self, id: Id) -> CoreResult<()> {
+        self.modify(id, Encounter::end).await.map(|_| ())
+    }
+}
+
+/// Observation service
+///
+/// Observations are stored in `emr.observations`; every write brings the
+/// patient's summary up to date in the same transaction.
+#[derive(Clone)]
+pub struct ObservationService {
+    pool: Pool,
+}
+
+impl ObservationService {
+    /// Create an observation service over the API database pool.
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    /// A page of observations, most recent effective time first, and how
+    /// many match in all.
+    pub async fn list_observations(
+        &self,
+        search: ObservationSearch,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<Observation>, u64)> {
+        ObservationRepository::new().list_records(&self.pool, search, limit, offset).await
+    }
+
+    /// Validate and store a group of observations together, such as a
+    /// vitals panel and its members. Nothing is stored if any fails.
+    pub async fn create_observations(&self, observations: Vec<Observation>) -> CoreResult<Vec<Observation>> {
+        let observations = prepare_observations(observations)?;
+
+        ObservationRepository::new()
+            .insert_records(&self.pool, &observations)
+            .await
+            .map_err(core_error)?;
+        Ok(observations)
+    }
+
+    async fn search(&self, search: ObservationSearch) -> CoreResult<Vec<Observation>> {
+        let (observations, _) = self.list_observations(search, u32::MAX, 0).await.map_err(core_error)?;
+        Ok(observations)
+    }
+}
+
+/// Normalize units, compress waveforms and validate observations before
+/// they are stored
+fn prepare_observations(mut observations: Vec<Observation>) -> CoreResult<Vec<Observation>> {
+    for observation in &mut observations {
+        observation.normalize_units()?;
+        observation.compress_waveform()?;
+        Validatable::validate(observation)?;
+    }
+    Ok(observations)
+}
+
+#[async_trait]
+impl CoreObservationService for ObservationService {
+    async fn create_observation(&self, observation: Observation) -> CoreResult<Observation> {
+        let mut created = self.create_observations(vec![observation]).await?;
+        created
+            .pop()
+            .ok_or_else(|| CoreError::internal_error("Observation was not created"))
+    }
+
+    async fn get_observation(&self, id: Id) -> CoreResult<Option<Observation>> {
+        ObservationRepository::new().find_record(&self.pool, id).await.map_err(core_error)
+    }
+
+    async fn update_observation(&self, observation: Observation) -> CoreResult<Observation> {
+        let mut observation = prepare_observations(vec![observation])?
+            .pop()
+            .ok_or_else(|| CoreError::internal_error("Observation was not updated"))?;
+        let existing = self
+            .get_observation(observation.metadata.id)
+            .await?
+            .ok_or_else(|| CoreError::entity_not_found("Observation", observation.metadata.id))?;
+
+        observation.metadata.created_at = existing.metadata.created_at;
+        observation.metadata.version = existing.metadata.version;
+        observation.metadata.update();
+
+        let stored = ObservationRepository::new()
+            .update_record(&self.pool, &observation, existing.metadata.version)
+            .await
+            .map_err(core_error)?;
+        if !stored {
+            return Err(CoreError::data_integrity_error(
+                "The observation was changed by another request; reload it and try again",
+            ));
+        }
+        Ok(observation)
+    }
+
+    async fn get_patient_observations(&self, patient_id: Id) -> CoreResult<Vec<Observation>> {
+        self.search(ObservationSearch::patient(patient_id)).await
+    }
+
+    async fn get_encounter_observations(&self, encounter_id: Id) -> CoreResult<Vec<Observation>> {
+        self.search(ObservationSearch::encounter(encounter_id)).await
+    }
+}
+
+/// Verifications issued by patient and contact value
+type VerificationStore = HashMap<(Id, String), Vec<ContactVerification>>;
+
+/// Contact verification service
+///
+/// Current status: prototype storage; issued codes are kept in memory and
+/// lost on restart.
+#[derive(Debug, Clone, Default)]
+pub struct ContactVerificationService {
+    policy: VerificationPolicy,
+    verifications: Arc<RwLock<VerificationStore>>,
+}
+
+impl ContactVerificationService {
+    /// Create a verification service with the given limits.
+    pub fn new(policy: VerificationPolicy) -> Self {
+        Self {
+            policy,
+            verifications: Arc::default(),
+        }
+    }
+
+    /// Issue a code for a patient's contact point, enforcing the resend
+    /// interval and send limit. Returns the plain code for delivery.
+    pub async fn issue(
+        &self,
+        patient_id: Id,
+        system: ContactSystem,
+        value: &str,
+    ) -> Result<(ContactVerification, String)> {
+        let now = chrono::Utc::now();
+        let mut verifications = self.verifications.write().await;
+        let history = verifications.entry((patient_id, value.to_string())).or_default();
+
+        // Forget sends that no longer count towards the limit
+        history.retain(|verification| now - verification.sent_at < self.policy.send_window);
+
+        let sent: Vec<_> = history.iter().map(|verification| verification.sent_at).collect();
+        self.policy
+            .check_send_rate(&sent, now)
+            .map_err(|e| ApiError::too_many_requests(&e.to_string()))?;
+
+        let (verification, code) = ContactVerification::issue(patient_id, system, value, &self.policy, now)?;
+        history.push(verification.clone());
+        Ok((verification, code))
+    }
+
+    /// Check a code against the latest verification for a contact point.
+    pub async fn confirm(&self, patient_id: Id, value: &str, code: &str) -> Result<ContactVerification> {
+        let mut verifications = self.verifications.write().await;
+        let latest = verifications
+            .get_mut(&(patient_id, value.to_string()))
+            .and_then(|history| history.last_mut())
+            .ok_or_else(|| ApiError::not_found(&format!("No verification pending for {}", value)))?;
+
+        latest.confirm(code, chrono::Utc::now())?;
+        Ok(latest.clone())
+    }
+}
+
+/// Security service backed by care teams
+///
+/// Access to a patient's data rests on a treating relationship, i.e. being a
+/// current participant of one of the patient's active care teams, with an
+/// unexpired break-the-glass grant as the fallback.
+///
+/// Current status: only the patient access checks are implemented; role and
+/// permission lookups are prototype stubs until RBAC lands.
+#[derive(Clone)]
+pub struct CareTeamSecurityService {
+    pool: Pool,
+}
+
+impl CareTeamSecurityService {
+    /// Create a security service over the API database pool.
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CoreSecurityService for CareTeamSecurityService {
+    async fn check_permission(&self, _user_id: Id, _resource_type: &str, _resource_id: Id, _action: &str) -> CoreResult<bool> {
+        // TODO(nexus-phase2): Resolve permissions from RBAC role assignments.
+        Ok(false)
+    }
+
+    async fn check_role(&self, _user_id: Id, _role: &str) -> CoreResult<bool> {
+        // TODO(nexus-phase2): Resolve roles from RBAC role assignments.
+        Ok(false)
+    }
+
+    async fn get_user_permissions(&self, _user_id: Id) -> CoreResult<Vec<Permission>> {
+        // TODO(nexus-phase2): Resolve permissions from RBAC role assignments.
+        Ok(Vec::new())
+    }
+
+    async fn validate_smart_scope(&self, scope: &str, resource_type: &str, _resource_id: Id) -> CoreResult<bool> {
+        Ok(Scopes::parse(scope).allows(resource_type, ScopeAccess::Read))
     }
 
-    /// Resolve a patient by domain identifier.
-    ///
-    /// Current status: prototype stub, always returns `None`.
-    pub async fn get_patient(&self, _id: Id) -> Result<Option<PatientModel>> {
-        // TODO(nexus-phase1): Implement business rules and repository lookup.
-        Ok(None)
+    async fn has_treating_relationship(&self, practitioner_id: Id, patient_id: Id) -> CoreResult<bool> {
+        CareTeamRepository::new()
+            .has_treating_relationship(&self.pool, practitioner_id, patient_id)
+            .await
+            .map_err(core_error)
     }
 
-    /// Validate and create a patient.
-    ///
-    /// Current status: prototype stub, returns a not-implemented API error.
-    pub async fn create_patient(&self, _patient: PatientModel) -> Result<PatientModel> {
-        // TODO(nexus-phase1): Add validation and persistence orchestration.
-        Err(crate::error::ApiError::internal_error("Not implemented"))
+    async fn check_patient_access(&self, practitioner_id: Id, patient_id: Id) -> CoreResult<PatientAccess> {
+        if self.has_treating_relationship(practitioner_id, patient_id).await? {
+            return Ok(PatientAccess::TreatingRelationship);
+        }
+
+        let grant = CareTeamRepository::new()
+            .active_emergency_access(&self.pool, practitioner_id, patient_id)
+            .await
+            .map_err(core_error)?;
+        Ok(match grant {
+            Some(grant) => PatientAccess::EmergencyAccess {
+                grant_id: grant.id,
+                expires_at: grant.expires_at,
+            },
+            None => PatientAccess::Denied,
+        })
+    }
+
+    async fn break_the_glass(&self, practitioner_id: Id, patient_id: Id, reason: &str) -> CoreResult<EmergencyAccess> {
+        let grant = EmergencyAccess::grant(practitioner_id, patient_id, reason, chrono::Utc::now())?;
+        CareTeamRepository::new()
+            .insert_emergency_access(&self.pool, &grant)
+            .await
+            .map_err(core_error)?;
+        Ok(grant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::test_support::{EncounterBuilder, ObservationBuilder};
+
+    #[test]
+    fn test_update_keeps_status() {
+        let existing = EncounterBuilder::new(uuid::Uuid::new_v4()).build();
+        let mut changed = existing.clone();
+        changed.status = EncounterStatus::Finished;
+        changed.reason.push("Follow-up".to_string());
+
+        carry_over(&existing, &mut changed);
+        assert_eq!(changed.status, EncounterStatus::Planned);
+        assert_eq!(changed.reason, vec!["Follow-up".to_string()]);
+        assert_eq!(changed.metadata.version, existing.metadata.version + 1);
+    }
+
+    #[test]
+    fn test_prepare_observations_is_all_or_nothing() {
+        let patient_id = uuid::Uuid::new_v4();
+        let valid = ObservationBuilder::new(patient_id).build();
+        let invalid = ObservationBuilder::new(patient_id).with_code(" ").build();
+
+        assert!(prepare_observations(vec![valid.clone(), invalid]).is_err());
+        assert_eq!(prepare_observations(vec![valid]).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_contact_verification() {
+        let service = ContactVerificationService::default();
+        let patient_id = uuid::Uuid::new_v4();
+        let phone = "+15555550100";
+
+        let (_, code) = service.issue(patient_id, ContactSystem::Sms, phone).await.unwrap();
+        // A second code inside the resend interval is rate limited
+        let resend = service.issue(patient_id, ContactSystem::Sms, phone).await;
+        assert!(matches!(resend, Err(ApiError::TooManyRequests { .. })));
+
+        assert!(service.confirm(patient_id, phone, "wrong").await.is_err());
+        let verified = service.confirm(patient_id, phone, &code).await.unwrap();
+        assert!(verified.verified_at.is_some());
+        assert_eq!(verified.attempts, 1);
+
+        assert!(service.confirm(patient_id, "other@example.com", &code).await.is_err());
     }
-} 
\ No newline at end of file
+}
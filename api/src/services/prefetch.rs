@@ -0,0 +1,249 @@
+//! Encounter view prefetch.
+//!
+//! An encounter screen needs the patient, the participating practitioners,
+//! the diagnosed conditions, the locations and the encounter's observations.
+//! Rather than have the UI make those calls one after another,
+//! [`EncounterPrefetchService`] follows the references described by
+//! [`ENCOUNTER_VIEW_GRAPH`] (the links of a FHIR GraphDefinition starting at
+//! Encounter) and reads the targets from the FHIR server in parallel, with at
+//! most `concurrency` requests in flight.
+//!
+//! References the server cannot resolve (deleted, or the server is down) are
+//! reported in [`EncounterView::unresolved`] instead of failing the view.
+//! Conditions and observations the viewer is not cleared for are withheld
+//! with [`EncounterView::withhold_uncleared`].
+
+use crate::error::{ApiError, Result};
+use crate::services::{EncounterService, ObservationService};
+use emr_core::services::{EncounterService as CoreEncounterService, ObservationService as CoreObservationService};
+use emr_core::domain::Confidentiality;
+use emr_core::types::Id;
+use emr_fhir::{encounter_to_fhir, observation_to_fhir, resource_confidentiality, FhirClientError, FhirGateway};
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Default number of FHIR reads in flight per view
+pub const DEFAULT_PREFETCH_CONCURRENCY: usize = 8;
+
+/// A GraphDefinition link: references at `path` in the source resource point
+/// to resources of type `target`
+#[derive(Debug, Clone, Copy)]
+pub struct GraphLink {
+    pub path: &'static str,
+    pub target: &'static str,
+}
+
+/// Links followed from an Encounter to build its view
+pub const ENCOUNTER_VIEW_GRAPH: &[GraphLink] = &[
+    GraphLink { path: "subject", target: "Patient" },
+    GraphLink { path: "participant.individual", target: "Practitioner" },
+    GraphLink { path: "diagnosis.condition", target: "Condition" },
+    GraphLink { path: "location.location", target: "Location" },
+];
+
+/// Encounter with everything its screen displays
+#[derive(Debug, Default, Serialize)]
+pub struct EncounterView {
+    pub encounter: Value,
+    pub patient: Option<Value>,
+    pub participants: Vec<Value>,
+    pub conditions: Vec<Value>,
+    pub locations: Vec<Value>,
+    pub observations: Vec<Value>,
+    pub unresolved: Vec<UnresolvedReference>,
+}
+
+impl EncounterView {
+    /// Remove the conditions and observations `clearance` does not clear
+    pub fn withhold_uncleared(&mut self, clearance: Confidentiality) {
+        let cleared = |resource: &Value| clearance.clears(resource_confidentiality(resource));
+        self.conditions.retain(cleared);
+        self.observations.retain(cleared);
+    }
+}
+
+/// A reference that could not be read
+#[derive(Debug, Serialize)]
+pub struct UnresolvedReference {
+    pub reference: String,
+    pub reason: String,
+}
+
+/// Builds [`EncounterView`]s
+#[derive(Clone)]
+pub struct EncounterPrefetchService {
+    gateway: Arc<dyn FhirGateway>,
+    encounters: EncounterService,
+    observations: ObservationService,
+    concurrency: usize,
+}
+
+impl EncounterPrefetchService {
+    /// Create a prefetch service reading at most `concurrency` resources at once
+    pub fn new(
+        gateway: Arc<dyn FhirGateway>,
+        encounters: EncounterService,
+        observations: ObservationService,
+        concurrency: usize,
+    ) -> Self {
+        Self {
+            gateway,
+            encounters,
+            observations,
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Prefetch the view of an encounter; `None` if the encounter does not exist
+    pub async fn encounter_view(&self, id: Id) -> Result<Option<EncounterView>> {
+        let Some(encounter) = self.encounters.get_encounter(id).await? else {
+            return Ok(None);
+        };
+        let encounter = encounter_to_fhir(&encounter);
+        let links = graph_references(&encounter, ENCOUNTER_VIEW_GRAPH);
+
+        // Observations are held locally; read them while the FHIR reads run
+        let (resolved, observations) = futures_util::join!(
+            self.read_all(&links),
+            self.observations.get_encounter_observations(id)
+        );
+
+        let mut view = EncounterView {
+            encounter,
+            observations: observations?.iter().map(observation_to_fhir).collect(),
+            ..Default::default()
+        };
+
+        for ((target, reference), result) in links.into_iter().zip(resolved) {
+            match result {
+                Ok(resource) => match target {
+                    "Patient" => view.patient = Some(resource),
+                    "Practitioner" => view.participants.push(resource),
+                    "Condition" => view.conditions.push(resource),
+                    "Location" => view.locations.push(resource),
+                    _ => {}
+                },
+                Err(err @ (FhirClientError::NotFound { .. } | FhirClientError::Unavailable { .. })) => {
+                    tracing::warn!(%reference, error = %err, "Encounter view reference unresolved");
+                    view.unresolved.push(UnresolvedReference {
+                        reference,
+                        reason: err.to_string(),
+                    });
+                }
+                Err(err) => return Err(ApiError::from(err)),
+            }
+        }
+
+        Ok(Some(view))
+    }
+
+    /// Read every reference, in order, with bounded concurrency
+    async fn read_all(&self, links: &[(&'static str, String)]) -> Vec<std::result::Result<Value, FhirClientError>> {
+        stream::iter(links)
+            .map(|(target, reference)| {
+                let id = reference.rsplit('/').next().unwrap_or_default();
+                self.gateway.read(target, id)
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+}
+
+/// `(target type, reference)` pairs for each link, without duplicates
+fn graph_references(resource: &Value, graph: &[GraphLink]) -> Vec<(&'static str, String)> {
+    let mut seen = HashSet::new();
+    let mut references = Vec::new();
+
+    for link in graph {
+        for reference in references_at(resource, link.path) {
+            if reference.starts_with(&format!("{}/", link.target)) && seen.insert(reference.clone()) {
+                references.push((link.target, reference));
+            }
+        }
+    }
+
+    references
+}
+
+/// `reference` values found by following a dotted path through objects
+/// and arrays
+fn references_at(resource: &Value, path: &str) -> Vec<String> {
+    let mut nodes = vec![resource];
+    for element in path.split('.') {
+        nodes = nodes
+            .into_iter()
+            .filter_map(|node| node.get(element))
+            .flat_map(|node| match node {
+                Value::Array(items) => items.iter().collect::<Vec<_>>(),
+                node => vec![node],
+            })
+            .collect();
+    }
+
+    nodes
+        .into_iter()
+        .filter_map(|node| node["reference"].as_str().map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_graph_references() {
+        let encounter = json!({
+            "resourceType": "Encounter",
+            "subject": {"reference": "Patient/p1"},
+            "participant": [
+                {"individual": {"reference": "Practitioner/a"}},
+                {"individual": {"reference": "Practitioner/b"}},
+                {"individual": {"reference": "Practitioner/a"}}
+            ],
+            "diagnosis": [{"condition": {"reference": "Condition/c1"}, "rank": 1}],
+            "location": [{"location": {"reference": "Location/l1"}}]
+        });
+
+        let references = graph_references(&encounter, ENCOUNTER_VIEW_GRAPH);
+        assert_eq!(
+            references,
+            vec![
+                ("Patient", "Patient/p1".to_string()),
+                ("Practitioner", "Practitioner/a".to_string()),
+                ("Practitioner", "Practitioner/b".to_string()),
+                ("Condition", "Condition/c1".to_string()),
+                ("Location", "Location/l1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_withhold_uncleared() {
+        let labelled = |code: &str| {
+            json!({"meta": {"security": [{"system": emr_core::domain::CONFIDENTIALITY_SYSTEM, "code": code}]}})
+        };
+        let mut view = EncounterView {
+            conditions: vec![labelled("N"), labelled("V")],
+            observations: vec![json!({"resourceType": "Observation"}), labelled("R")],
+            ..Default::default()
+        };
+
+        view.withhold_uncleared(Confidentiality::Restricted);
+        assert_eq!(view.conditions, vec![labelled("N")]);
+        assert_eq!(view.observations.len(), 2);
+        view.withhold_uncleared(Confidentiality::Normal);
+        assert_eq!(view.observations.len(), 1);
+    }
+
+    #[test]
+    fn test_references_ignore_other_targets() {
+        let encounter = json!({"subject": {"reference": "Group/g1"}});
+        assert!(graph_references(&encounter, ENCOUNTER_VIEW_GRAPH).is_empty());
+        assert!(references_at(&encounter, "participant.individual").is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+//! Outbound referral exchange.
+//!
+//! A sent referral is written to the FHIR server as a `ServiceRequest` and a
+//! `Task` whose owner is the target organization, both under the referral's
+//! own id so that resending after a failure overwrites instead of duplicating.
+//! The target organization responds by updating the `Task`, which
+//! [`ReferralExchange::remote_status`] reads back. Cancelling a sent referral
+//! publishes both resources again.
+
+use emr_core::domain::{Referral, ReferralStatus};
+use emr_fhir::{referral_status_from_task, referral_task_to_fhir, referral_to_fhir, FhirClientError, FhirGateway};
+use std::sync::Arc;
+
+/// Exchanges referrals with other organizations through the FHIR server
+#[derive(Clone)]
+pub struct ReferralExchange {
+    gateway: Arc<dyn FhirGateway>,
+}
+
+impl ReferralExchange {
+    /// Create an exchange over the API's FHIR gateway
+    pub fn new(gateway: Arc<dyn FhirGateway>) -> Self {
+        Self { gateway }
+    }
+
+    /// Publish the referral's current state: its `ServiceRequest`, then the
+    /// `Task` that refers to it
+    pub async fn publish(&self, referral: &Referral) -> Result<(), FhirClientError> {
+        let id = referral.metadata.id.to_string();
+        self.gateway
+            .update("ServiceRequest", &id, &referral_to_fhir(referral))
+            .await?;
+        self.gateway
+            .update("Task", &id, &referral_task_to_fhir(referral))
+            .await?;
+        Ok(())
+    }
+
+    /// Status and reason the target organization set on the referral's `Task`
+    ///
+    /// `None` when the task holds a status that has no referral equivalent.
+    pub async fn remote_status(
+        &self,
+        referral: &Referral,
+    ) -> Result<Option<(ReferralStatus, Option<String>)>, FhirClientError> {
+        let task = self.gateway.read("Task", &referral.metadata.id.to_string()).await?;
+        Ok(referral_status_from_task(&task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use emr_core::domain::RequestPriority;
+    use emr_fhir::{Bundle, FhirResult, OperationOutcome, SearchParameters};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Gateway keeping updated resources in memory
+    #[derive(Default)]
+    struct MemoryGateway {
+        resources: Mutex<HashMap<String, Value>>,
+    }
+
+    #[async_trait]
+    impl FhirGateway for MemoryGateway {
+        async fn capability_statement(&self) -> FhirResult<Value> {
+            Ok(Value::Null)
+        }
+
+        async fn read(&self, resource_type: &str, id: &str) -> FhirResult<Value> {
+            let key = format!("{}/{}", resource_type, id);
+            self.resources
+                .lock()
+                .unwrap()
+                .get(&key)
+                .cloned()
+                .ok_or(FhirClientError::NotFound { resource: key })
+        }
+
+        async fn search(&self, _params: &SearchParameters) -> FhirResult<Value> {
+            Ok(Value::Null)
+        }
+
+        async fn create(&self, _resource_type: &str, resource: &Value) -> FhirResult<Value> {
+            Ok(resource.clone())
+        }
+
+        async fn update(&self, resource_type: &str, id: &str, resource: &Value) -> FhirResult<Value> {
+            let key = format!("{}/{}", resource_type, id);
+            self.resources.lock().unwrap().insert(key, resource.clone());
+            Ok(resource.clone())
+        }
+
+        async fn delete(&self, _resource_type: &str, _id: &str) -> FhirResult<()> {
+            Ok(())
+        }
+
+        async fn validate(&self, _resource_type: &str, _resource: &Value) -> FhirResult<OperationOutcome> {
+            Err(FhirClientError::Configuration("not supported".to_string()))
+        }
+
+        async fn patient_everything(&self, _id: &str) -> FhirResult<Bundle> {
+            Ok(Bundle::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_read_back_response() {
+        let gateway = Arc::new(MemoryGateway::default());
+        let exchange = ReferralExchange::new(gateway.clone());
+        let mut referral = Referral::new(
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            "Knee pain, orthopaedic opinion",
+            RequestPriority::Routine,
+        );
+        referral.transition(ReferralStatus::Sent, None, chrono::Utc::now()).unwrap();
+
+        exchange.publish(&referral).await.unwrap();
+        exchange.publish(&referral).await.unwrap();
+        assert_eq!(gateway.resources.lock().unwrap().len(), 2, "republishing overwrites");
+        assert_eq!(exchange.remote_status(&referral).await.unwrap(), Some((ReferralStatus::Sent, None)));
+
+        let key = format!("Task/{}", referral.metadata.id);
+        gateway.resources.lock().unwrap().get_mut(&key).unwrap()["status"] = "accepted".into();
+        assert_eq!(
+            exchange.remote_status(&referral).await.unwrap(),
+            Some((ReferralStatus::Accepted, None))
+        );
+    }
+}
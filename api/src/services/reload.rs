@@ -0,0 +1,152 @@
+//! Configuration reloading.
+//!
+//! [`ConfigReloader`] loads the configuration again on `SIGHUP` and, every
+//! `reload.watch_interval` seconds, when one of the configuration files
+//! changed. Only the settings [`Config::apply_reload`] takes are applied: the
+//! log level, through the handle the logger was set up with, the feature
+//! flag cache TTL and the database pool size. A configuration that fails to
+//! load is logged and the running settings are kept.
+
+use crate::config::Config;
+use crate::database::{self, Pool};
+use crate::error::Result;
+use crate::services::FeatureFlags;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+/// Sets the log filter, e.g. through a `tracing_subscriber::reload::Handle`
+pub type LogLevelHandle = Arc<dyn Fn(&str) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Applies reloaded settings to the running server
+#[derive(Clone)]
+pub struct ConfigReloader {
+    config: Arc<Mutex<Config>>,
+    pool: Pool,
+    flags: FeatureFlags,
+    log_level: Option<LogLevelHandle>,
+}
+
+impl ConfigReloader {
+    /// Create a reloader starting from the configuration the server started with.
+    pub fn new(config: Config, pool: Pool, flags: FeatureFlags) -> Self {
+        Self {
+            config: Arc::new(Mutex::new(config)),
+            pool,
+            flags,
+            log_level: None,
+        }
+    }
+
+    /// Change the log level through `handle` on reload.
+    pub fn with_log_level(mut self, handle: LogLevelHandle) -> Self {
+        self.log_level = Some(handle);
+        self
+    }
+
+    /// Load the configuration again and apply what can change without a
+    /// restart, returning the names of the settings that changed.
+    pub fn reload(&self) -> Result<Vec<&'static str>> {
+        let reloaded = Config::from_env()?;
+        let mut config = self.config.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let changed = config.apply_reload(&reloaded);
+
+        for setting in &changed {
+            let applied = match *setting {
+                "logging.level" => match &self.log_level {
+                    Some(handle) => handle(&config.logging.level),
+                    None => Err("no log level handle is installed".to_string()),
+                },
+                "flags.cache_ttl" => {
+                    self.flags.set_cache_ttl(config.flags.cache_ttl);
+                    Ok(())
+                }
+                "database.max_connections" => {
+                    database::resize_pool(&self.pool, config.database.max_connections as usize, &config.database)
+                        .map_err(|e| e.to_string())
+                }
+                _ => Ok(()),
+            };
+            match applied {
+                Ok(()) => tracing::info!(setting, "Configuration setting reloaded"),
+                Err(error) => tracing::warn!(setting, %error, "Configuration setting could not be reloaded"),
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Reload on `SIGHUP` and whenever a configuration file changes, until the
+    /// process exits.
+    pub fn spawn(self, watch_interval: u64) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(signal) => Some(signal),
+                Err(e) => {
+                    tracing::warn!("Failed to install SIGHUP handler: {}", e);
+                    None
+                }
+            };
+
+            let mut watch = (watch_interval > 0).then(|| tokio::time::interval(Duration::from_secs(watch_interval)));
+            let mut fingerprint = config_fingerprint();
+
+            loop {
+                let hangup_received = async {
+                    #[cfg(unix)]
+                    if let Some(signal) = hangup.as_mut() {
+                        signal.recv().await;
+                        return;
+                    }
+                    std::future::pending::<()>().await
+                };
+                let watch_tick = async {
+                    match watch.as_mut() {
+                        Some(interval) => {
+                            interval.tick().await;
+                        }
+                        None => std::future::pending::<()>().await,
+                    }
+                };
+
+                tokio::select! {
+                    _ = hangup_received => tracing::info!("SIGHUP received, reloading configuration"),
+                    _ = watch_tick => {
+                        let current = config_fingerprint();
+                        if current == fingerprint {
+                            continue;
+                        }
+                        fingerprint = current;
+                        tracing::info!("Configuration files changed, reloading configuration");
+                    }
+                }
+
+                if let Err(e) = self.reload() {
+                    tracing::warn!("Failed to reload configuration, keeping the running settings: {}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Modification times of the configuration files [`Config::from_env`] reads
+fn config_fingerprint() -> Vec<(PathBuf, SystemTime)> {
+    let mut fingerprint: Vec<(PathBuf, SystemTime)> = Config::files()
+        .iter()
+        .flat_map(|file| {
+            let path = PathBuf::from(file);
+            let stem = path.file_name().map(|name| name.to_os_string());
+            let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+            // `config/default` matches `config/default.toml`, `.yaml` and so on
+            std::fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(move |entry| entry.path().file_stem().map(|s| s.to_os_string()) == stem)
+                .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?.modified().ok()?)))
+        })
+        .collect();
+    fingerprint.sort();
+    fingerprint
+}
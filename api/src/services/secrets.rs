@@ -0,0 +1,110 @@
+//! Secret backends.
+//!
+//! Secret settings can be `env:` and `file:` references (see
+//! [`emr_core::secrets`]) and, with `secrets.vault_address` set,
+//! `vault:<path>#<key>` references read from HashiCorp Vault over its HTTP
+//! API, e.g. `vault:secret/data/emr/api#jwt_secret`. Both KV version 2
+//! (`secret/data/...`) and version 1 paths work. The Vault token is itself a
+//! setting and may be a `file:` reference, such as the sink of a Vault Agent.
+//! A cloud KMS plugs in the same way, as another [`SecretBackend`].
+
+use crate::config::SecretsConfig;
+use crate::error::Result;
+use async_trait::async_trait;
+use emr_core::secrets::{SecretBackend, SecretResolver};
+use emr_core::{Error as CoreError, Result as CoreResult};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The resolver for the configured secret backends
+pub async fn secret_resolver(config: &SecretsConfig) -> Result<SecretResolver> {
+    let resolver = SecretResolver::new();
+    match config.vault_address.as_deref().filter(|address| !address.is_empty()) {
+        Some(address) => {
+            let token = resolver.resolve(config.vault_token.as_deref().unwrap_or("env:VAULT_TOKEN")).await?;
+            let vault = VaultSecrets::new(address, &token, Duration::from_secs(config.timeout))?;
+            Ok(resolver.with_backend(Arc::new(vault)))
+        }
+        None => Ok(resolver),
+    }
+}
+
+/// HashiCorp Vault key/value secrets
+pub struct VaultSecrets {
+    client: reqwest::Client,
+    address: String,
+    token: String,
+}
+
+impl VaultSecrets {
+    /// Create a backend for the Vault at `address`
+    pub fn new(address: &str, token: &str, timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            address: address.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    /// Secret path and key of a `path#key` reference
+    fn split(reference: &str) -> CoreResult<(&str, &str)> {
+        reference
+            .split_once('#')
+            .filter(|(path, key)| !path.is_empty() && !key.is_empty())
+            .ok_or_else(|| {
+                let message = format!("Vault references look like vault:<path>#<key>, not {}", reference);
+                CoreError::configuration_error(&message)
+            })
+    }
+}
+
+/// Report a Vault failure through the core error type
+fn unavailable(message: impl std::fmt::Display) -> CoreError {
+    CoreError::external_service_error("Vault", &message.to_string())
+}
+
+#[async_trait]
+impl SecretBackend for VaultSecrets {
+    fn scheme(&self) -> &'static str {
+        "vault"
+    }
+
+    async fn fetch(&self, path: &str) -> CoreResult<String> {
+        let (secret_path, key) = Self::split(path)?;
+        let response = self
+            .client
+            .get(format!("{}/v1/{}", self.address, secret_path.trim_start_matches('/')))
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await
+            .map_err(unavailable)?;
+        if !response.status().is_success() {
+            return Err(unavailable(format!("reading {} failed with {}", secret_path, response.status())));
+        }
+
+        let body: Value = response.json().await.map_err(unavailable)?;
+        // KV version 2 nests the secret's keys one level deeper than version 1
+        let data = body["data"].get("data").filter(|data| data.is_object()).unwrap_or(&body["data"]);
+        data[key]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| CoreError::configuration_error(&format!("Vault secret {} has no key {}", secret_path, key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_references() {
+        assert_eq!(
+            VaultSecrets::split("secret/data/emr/api#jwt_secret").unwrap(),
+            ("secret/data/emr/api", "jwt_secret")
+        );
+        assert!(VaultSecrets::split("secret/data/emr/api").is_err());
+        assert!(VaultSecrets::split("#jwt_secret").is_err());
+    }
+}
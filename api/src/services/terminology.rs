@@ -0,0 +1,147 @@
+//! Code systems from the FHIR server's terminology.
+//!
+//! [`Terminology`] reads a `CodeSystem` by its canonical URL with
+//! `GET CodeSystem?url=` and keeps it for the life of the process, so code
+//! displays are looked up in memory. A URL the server has no code system for
+//! is remembered too, rather than searched for on every lookup. The
+//! configured `warmup.code_systems` are read at boot (see
+//! [`crate::services::warmup`]); others are read on first use.
+
+use crate::error::Result;
+use emr_fhir::{FhirGateway, SearchParameters};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// A code system's concepts, by code
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeSystem {
+    /// Canonical URL
+    pub url: String,
+    pub version: Option<String>,
+    /// Display of every concept, nested ones included, by code
+    pub concepts: HashMap<String, Option<String>>,
+}
+
+impl CodeSystem {
+    /// Read a `CodeSystem` resource
+    pub fn from_resource(resource: &Value) -> Self {
+        let mut concepts = HashMap::new();
+        collect_concepts(&resource["concept"], &mut concepts);
+        Self {
+            url: resource["url"].as_str().unwrap_or_default().to_string(),
+            version: resource["version"].as_str().map(ToString::to_string),
+            concepts,
+        }
+    }
+
+    /// Display of a code, if the code is defined and has one
+    pub fn display(&self, code: &str) -> Option<&str> {
+        self.concepts.get(code).and_then(|display| display.as_deref())
+    }
+}
+
+/// Add `concepts` and the concepts nested under them to `into`
+fn collect_concepts(concepts: &Value, into: &mut HashMap<String, Option<String>>) {
+    for concept in concepts.as_array().into_iter().flatten() {
+        if let Some(code) = concept["code"].as_str() {
+            into.insert(code.to_string(), concept["display"].as_str().map(ToString::to_string));
+        }
+        collect_concepts(&concept["concept"], into);
+    }
+}
+
+/// Code systems by URL; `None` for URLs the server has no code system for
+type CodeSystemCache = HashMap<String, Option<Arc<CodeSystem>>>;
+
+/// Cached code systems from the FHIR server
+#[derive(Clone)]
+pub struct Terminology {
+    gateway: Arc<dyn FhirGateway>,
+    code_systems: Arc<RwLock<CodeSystemCache>>,
+}
+
+impl Terminology {
+    /// Create a terminology cache over the FHIR server
+    pub fn new(gateway: Arc<dyn FhirGateway>) -> Self {
+        Self {
+            gateway,
+            code_systems: Arc::default(),
+        }
+    }
+
+    /// The code system with a canonical URL, read from the server on first use
+    pub async fn code_system(&self, url: &str) -> Result<Option<Arc<CodeSystem>>> {
+        if let Some(code_system) = self.code_systems.read().await.get(url) {
+            return Ok(code_system.clone());
+        }
+
+        let code_system = self.fetch(url).await?;
+        self.code_systems
+            .write()
+            .await
+            .insert(url.to_string(), code_system.clone());
+        Ok(code_system)
+    }
+
+    /// Display of a code in a code system
+    pub async fn display(&self, system: &str, code: &str) -> Result<Option<String>> {
+        let code_system = self.code_system(system).await?;
+        Ok(code_system.and_then(|code_system| code_system.display(code).map(ToString::to_string)))
+    }
+
+    /// Read code systems into the cache now, e.g. while warming up after
+    /// boot. Returns how many concepts they define.
+    pub async fn warm(&self, urls: &[String]) -> Result<usize> {
+        let mut concepts = 0;
+        for url in urls {
+            match self.code_system(url).await? {
+                Some(code_system) => concepts += code_system.concepts.len(),
+                None => tracing::warn!(url = %url, "FHIR server has no code system to preload"),
+            }
+        }
+        Ok(concepts)
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Option<Arc<CodeSystem>>> {
+        let params = SearchParameters::new("CodeSystem").add_parameter("url", url);
+        let bundle = self.gateway.search(&params).await?;
+        let resource = bundle["entry"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|entry| &entry["resource"])
+            .find(|resource| resource["resourceType"] == "CodeSystem");
+        Ok(resource.map(|resource| Arc::new(CodeSystem::from_resource(resource))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_code_system_from_resource() {
+        let resource = json!({
+            "resourceType": "CodeSystem",
+            "url": "http://terminology.hl7.org/CodeSystem/v3-Confidentiality",
+            "version": "3.0.0",
+            "concept": [
+                { "code": "N", "display": "normal" },
+                {
+                    "code": "_Confidentiality",
+                    "concept": [{ "code": "R", "display": "restricted" }]
+                }
+            ]
+        });
+
+        let code_system = CodeSystem::from_resource(&resource);
+        assert_eq!(code_system.version.as_deref(), Some("3.0.0"));
+        assert_eq!(code_system.concepts.len(), 3);
+        assert_eq!(code_system.display("R"), Some("restricted"));
+        assert_eq!(code_system.display("_Confidentiality"), None);
+        assert_eq!(code_system.display("V"), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Brute-force throttling for sign-in and verification codes.
+//!
+//! [`ThrottleService`] counts failed attempts per action and key with the
+//! `auth.throttle` rules (see `emr_core::services::throttle`). A locked out
+//! key gets 429 until the lockout ends, and the failure that locked it out
+//! returns a `throttle_lockout` security event for the caller to record.
+//!
+//! Current status: counts are kept in memory and lost on restart; each API
+//! instance applies the limits on its own.
+
+use crate::error::{ApiError, Result};
+use chrono::Utc;
+use emr_core::services::security_events::SecurityEvent;
+use emr_core::services::throttle::{Attempts, ThrottleRules, ThrottledAction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Failed attempts by action and key
+#[derive(Debug, Clone, Default)]
+pub struct ThrottleService {
+    rules: ThrottleRules,
+    attempts: Arc<RwLock<HashMap<(ThrottledAction, String), Attempts>>>,
+}
+
+impl ThrottleService {
+    /// Create a throttle with the given rules.
+    pub fn new(rules: ThrottleRules) -> Self {
+        Self {
+            rules,
+            attempts: Arc::default(),
+        }
+    }
+
+    /// Refuse an attempt while its key is locked out.
+    pub async fn check(&self, action: ThrottledAction, key: &str) -> Result<()> {
+        let now = Utc::now();
+        let attempts = self.attempts.read().await;
+        match attempts.get(&(action, key.to_string())).and_then(|attempts| attempts.locked_until(now)) {
+            Some(until) => Err(ApiError::too_many_requests(&format!(
+                "Too many failed attempts; try again after {}",
+                until.format("%H:%M UTC")
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Count a failed attempt from `ip_address`. Returns the lockout event
+    /// when this failure locks the key out.
+    pub async fn record_failure(
+        &self,
+        action: ThrottledAction,
+        key: &str,
+        ip_address: Option<&str>,
+    ) -> Option<SecurityEvent> {
+        let now = Utc::now();
+        let rule = self.rules.rule(action);
+        let mut attempts = self.attempts.write().await;
+
+        // Forget keys whose failures no longer count
+        attempts.retain(|(action, _), attempts| !attempts.is_idle(self.rules.rule(*action), now));
+
+        let until = attempts.entry((action, key.to_string())).or_default().record_failure(rule, now)?;
+        tracing::warn!(action = action.as_str(), key, %until, "Locked out after repeated failures");
+        Some(SecurityEvent::throttle_lockout(action, key, rule, ip_address, until, now))
+    }
+
+    /// Forget the failures of a key after a successful attempt.
+    pub async fn reset(&self, action: ThrottledAction, key: &str) {
+        self.attempts.write().await.remove(&(action, key.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_throttle() {
+        let service = ThrottleService::default();
+        let action = ThrottledAction::PortalLogin;
+
+        for _ in 0..4 {
+            assert!(service.record_failure(action, "jdoe", None).await.is_none());
+        }
+        service.reset(action, "jdoe").await;
+        for _ in 0..4 {
+            assert!(service.record_failure(action, "jdoe", None).await.is_none());
+        }
+        assert!(service.check(action, "jdoe").await.is_ok());
+
+        let lockout = service.record_failure(action, "jdoe", Some("203.0.113.7")).await.unwrap();
+        assert_eq!(lockout.subject, "jdoe");
+        let refused = service.check(action, "jdoe").await;
+        assert!(matches!(refused, Err(ApiError::TooManyRequests { .. })));
+
+        // Other keys and actions are not affected
+        assert!(service.check(action, "asmith").await.is_ok());
+        assert!(service.check(ThrottledAction::VerificationConfirm, "jdoe").await.is_ok());
+    }
+}
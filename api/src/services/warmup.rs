@@ -0,0 +1,202 @@
+//! Cache warming after boot.
+//!
+//! [`CacheWarmer`] reads the configured terminology code systems, the
+//! feature flags and the FHIR server's capability statement into their
+//! caches side by side, then marks the instance ready, so `/readyz` only
+//! sends traffic once the first clinical requests will not pay for cold
+//! caches. Each cache gets `warmup.timeout` seconds; one that fails or takes
+//! longer is logged and left to fill on first use, so an unreachable FHIR
+//! server slows the first requests down instead of keeping the instance out
+//! of rotation.
+
+use crate::config::WarmupConfig;
+use crate::error::{ApiError, Result};
+use crate::services::terminology::Terminology;
+use crate::services::FeatureFlags;
+use emr_fhir::FhirGateway;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// How one cache warmed up
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmupStep {
+    /// Cache warmed
+    pub name: &'static str,
+    /// Entries read into the cache
+    pub loaded: usize,
+    pub duration_ms: u64,
+    /// Why the cache was left cold
+    pub error: Option<String>,
+}
+
+/// How the caches warmed up
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmupReport {
+    /// One step per cache; none when warming up is disabled
+    pub steps: Vec<WarmupStep>,
+    pub duration_ms: u64,
+}
+
+impl WarmupReport {
+    /// Whether every cache warmed up
+    pub fn is_complete(&self) -> bool {
+        self.steps.iter().all(|step| step.error.is_none())
+    }
+}
+
+/// Whether the instance has finished warming up, and how it went
+#[derive(Debug, Clone, Default)]
+pub struct Readiness {
+    report: Arc<RwLock<Option<WarmupReport>>>,
+}
+
+impl Readiness {
+    /// Not ready until [`Readiness::mark_ready`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report warming up as finished
+    pub async fn mark_ready(&self, report: WarmupReport) {
+        *self.report.write().await = Some(report);
+    }
+
+    /// How warming up went, once it has finished
+    pub async fn report(&self) -> Option<WarmupReport> {
+        self.report.read().await.clone()
+    }
+}
+
+/// Run a warm-up step, giving up after `timeout`
+async fn step<F>(name: &'static str, timeout: Duration, warm: F) -> WarmupStep
+where
+    F: Future<Output = Result<usize>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, warm).await {
+        Ok(result) => result,
+        Err(_) => Err(ApiError::service_unavailable(&format!(
+            "Timed out after {} seconds",
+            timeout.as_secs()
+        ))),
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(loaded) => {
+            tracing::info!(cache = name, loaded, duration_ms, "Cache warmed up");
+            WarmupStep {
+                name,
+                loaded,
+                duration_ms,
+                error: None,
+            }
+        }
+        Err(e) => {
+            tracing::warn!(cache = name, duration_ms, error = %e, "Cache left cold");
+            WarmupStep {
+                name,
+                loaded: 0,
+                duration_ms,
+                error: Some(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Fills the caches the first clinical requests need
+#[derive(Clone)]
+pub struct CacheWarmer {
+    config: WarmupConfig,
+    terminology: Terminology,
+    flags: FeatureFlags,
+    gateway: Arc<dyn FhirGateway>,
+}
+
+impl CacheWarmer {
+    /// Create a warmer for the server's caches
+    pub fn new(
+        config: WarmupConfig,
+        terminology: Terminology,
+        flags: FeatureFlags,
+        gateway: Arc<dyn FhirGateway>,
+    ) -> Self {
+        Self {
+            config,
+            terminology,
+            flags,
+            gateway,
+        }
+    }
+
+    /// Warm every cache, side by side
+    pub async fn warm(&self) -> WarmupReport {
+        if !self.config.enabled {
+            return WarmupReport::default();
+        }
+
+        let started = Instant::now();
+        let timeout = Duration::from_secs(self.config.timeout);
+        let (code_systems, flags, capabilities) = tokio::join!(
+            step("code_systems", timeout, self.terminology.warm(&self.config.code_systems)),
+            step("feature_flags", timeout, self.flags.warm()),
+            step("capability_statement", timeout, async {
+                self.gateway.capability_statement().await?;
+                Ok(1)
+            }),
+        );
+        WarmupReport {
+            steps: vec![code_systems, flags, capabilities],
+            duration_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Warm the caches in the background, then mark the instance ready.
+    pub fn spawn(self, readiness: Readiness) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let report = self.warm().await;
+            if report.is_complete() {
+                tracing::info!(duration_ms = report.duration_ms, "Warm-up finished, ready for traffic");
+            } else {
+                tracing::warn!(duration_ms = report.duration_ms, "Warm-up left caches cold, ready for traffic");
+            }
+            readiness.mark_ready(report).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_steps_report_failures_and_timeouts() {
+        let timeout = Duration::from_millis(20);
+        let warmed = step("flags", timeout, async { Ok(3) }).await;
+        assert_eq!((warmed.loaded, warmed.error.is_none()), (3, true));
+
+        let failed = step("flags", timeout, async { Err(ApiError::service_unavailable("FHIR server down")) }).await;
+        assert!(failed.error.unwrap().contains("FHIR server down"));
+
+        let slow = step("flags", timeout, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(1)
+        })
+        .await;
+        assert!(slow.error.unwrap().contains("Timed out"));
+        assert!(slow.duration_ms < 5_000);
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let readiness = Readiness::new();
+        assert!(readiness.report().await.is_none());
+
+        readiness.clone().mark_ready(WarmupReport::default()).await;
+        let report = readiness.report().await.unwrap();
+        assert!(report.is_complete());
+    }
+}
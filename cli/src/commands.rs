@@ -0,0 +1,326 @@
+//! Subcommand implementations
+
+use crate::config::Config;
+use crate::seed;
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use emr_jobs::worker::SUBMIT_SUBJECT;
+use rand::RngCore;
+use serde_json::Value;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+/// Bytes of entropy in a generated JWT secret
+const JWT_SECRET_BYTES: usize = 64;
+
+/// Account details for `create-user`
+pub struct NewUser {
+    pub username: String,
+    pub email: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub role: String,
+    pub password: String,
+}
+
+async fn connect(config: &Config) -> Result<PgPool> {
+    PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(Duration::from_secs(config.database.connection_timeout))
+        .connect(&config.database.url)
+        .await
+        .context("Failed to connect to the database")
+}
+
+/// Create a user account with a bcrypt password hash
+pub async fn create_user(config: &Config, user: NewUser) -> Result<()> {
+    if user.password.len() < 12 {
+        bail!("Passwords must be at least 12 characters");
+    }
+
+    let password_hash = bcrypt::hash(&user.password, config.auth.password_hash_cost)?;
+    let pool = connect(config).await?;
+
+    let id: uuid::Uuid = sqlx::query_scalar(
+        "INSERT INTO emr.users (username, email, password_hash, first_name, last_name, role)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING id",
+    )
+    .bind(&user.username)
+    .bind(&user.email)
+    .bind(&password_hash)
+    .bind(&user.first_name)
+    .bind(&user.last_name)
+    .bind(&user.role)
+    .fetch_one(&pool)
+    .await
+    .with_context(|| format!("Failed to create user {}", user.username))?;
+
+    println!("Created user {} ({}) with role {}", user.username, id, user.role);
+    Ok(())
+}
+
+/// Change the role of an existing user
+pub async fn assign_role(config: &Config, username: &str, role: &str) -> Result<()> {
+    let pool = connect(config).await?;
+
+    let updated = sqlx::query("UPDATE emr.users SET role = $1 WHERE username = $2")
+        .bind(role)
+        .bind(username)
+        .execute(&pool)
+        .await?
+        .rows_affected();
+
+    if updated == 0 {
+        bail!("No user named {}", username);
+    }
+
+    println!("Assigned role {} to {}", role, username);
+    Ok(())
+}
+
+/// Generate a random base64 secret suitable for HS512 signing
+pub fn generate_jwt_secret() -> String {
+    let mut secret = [0u8; JWT_SECRET_BYTES];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    STANDARD.encode(secret)
+}
+
+/// Print a new JWT secret and revoke sessions signed with the old one
+pub async fn rotate_jwt_secret(config: &Config, keep_sessions: bool) -> Result<()> {
+    let secret = generate_jwt_secret();
+
+    if !keep_sessions {
+        let pool = connect(config).await?;
+        let revoked = sqlx::query("DELETE FROM emr.sessions").execute(&pool).await?.rows_affected();
+        eprintln!("Revoked {} sessions", revoked);
+    }
+
+    eprintln!("Set the new secret as auth.jwt_secret (e.g. in config/local.toml) and restart the API:");
+    println!("{}", secret);
+    Ok(())
+}
+
+/// Apply, or list, pending migrations from a directory
+pub async fn run_migration(config: &Config, path: &Path, dry_run: bool) -> Result<()> {
+    let migrator = sqlx::migrate::Migrator::new(path)
+        .await
+        .with_context(|| format!("Failed to read migrations from {}", path.display()))?;
+    let pool = connect(config).await?;
+
+    if dry_run {
+        // The bookkeeping table does not exist before the first run
+        let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations")
+            .fetch_all(&pool)
+            .await
+            .unwrap_or_default();
+
+        for migration in migrator.iter().filter(|m| !applied.contains(&m.version)) {
+            println!("pending {} {}", migration.version, migration.description);
+        }
+        return Ok(());
+    }
+
+    migrator.run(&pool).await?;
+    println!("Migrations applied");
+    Ok(())
+}
+
+/// Check that a job definition is a tagged `JobType`
+pub fn validate_job(job: &Value) -> Result<()> {
+    match job.get("type").and_then(Value::as_str) {
+        Some(job_type) if !job_type.is_empty() => Ok(()),
+        _ => bail!("Job definitions need a \"type\" field, e.g. {{\"type\": \"DataCleanup\", ...}}"),
+    }
+}
+
+/// Publish a job definition for the jobs worker
+pub async fn enqueue_job(config: &Config, file: &Path, idempotency_key: Option<&str>) -> Result<()> {
+    let mut definition = String::new();
+    if file == Path::new("-") {
+        std::io::stdin().read_to_string(&mut definition)?;
+    } else {
+        definition = std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    }
+
+    let mut job: Value = serde_json::from_str(&definition).context("Job definition is not valid JSON")?;
+    validate_job(&job)?;
+    if let Some(key) = idempotency_key {
+        job["idempotency_key"] = Value::String(key.to_string());
+    }
+
+    let client = async_nats::connect(&config.nats.url)
+        .await
+        .with_context(|| format!("Failed to connect to NATS at {}", config.nats.url))?;
+    client
+        .publish(SUBMIT_SUBJECT, serde_json::to_vec(&job)?.into())
+        .await?;
+    client.flush().await?;
+
+    println!("Submitted {} job", job["type"].as_str().unwrap_or_default());
+    Ok(())
+}
+
+/// Write a patient with their encounters and observations as JSON
+pub async fn export_patient(config: &Config, id: uuid::Uuid, output: Option<&Path>) -> Result<()> {
+    let pool = connect(config).await?;
+
+    let patient: Option<Value> = sqlx::query_scalar("SELECT row_to_json(p) FROM emr.patients p WHERE p.id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+    let Some(patient) = patient else {
+        bail!("Patient {} not found", id);
+    };
+
+    let encounters: Vec<Value> =
+        sqlx::query_scalar("SELECT row_to_json(e) FROM emr.encounters e WHERE e.patient_id = $1 ORDER BY e.created_at")
+            .bind(id)
+            .fetch_all(&pool)
+            .await?;
+    let observations: Vec<Value> = sqlx::query_scalar(
+        "SELECT row_to_json(o) FROM emr.observations o WHERE o.patient_id = $1 ORDER BY o.effective_date",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let export = serde_json::json!({
+        "exported_at": chrono::Utc::now(),
+        "patient": patient,
+        "encounters": encounters,
+        "observations": observations,
+    });
+    let json = serde_json::to_string_pretty(&export)?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!("Exported patient {} to {}", id, path.display());
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Rebuild the GIN indexes backing search in the emr schema
+pub async fn reindex_search(config: &Config, table: Option<&str>) -> Result<()> {
+    let pool = connect(config).await?;
+
+    let rows = sqlx::query(
+        "SELECT indexname FROM pg_indexes
+         WHERE schemaname = 'emr' AND indexdef ILIKE '%USING gin%'
+           AND ($1::text IS NULL OR tablename = $1)
+         ORDER BY indexname",
+    )
+    .bind(table)
+    .fetch_all(&pool)
+    .await?;
+
+    if rows.is_empty() {
+        bail!("No search indexes found");
+    }
+
+    for row in rows {
+        let index: String = row.get("indexname");
+        // Index names come from the catalog, not user input
+        sqlx::query(&format!("REINDEX INDEX CONCURRENTLY emr.\"{}\"", index))
+            .execute(&pool)
+            .await
+            .with_context(|| format!("Failed to reindex {}", index))?;
+        println!("reindexed {}", index);
+    }
+    Ok(())
+}
+
+/// Generate and insert synthetic demo data
+pub async fn seed(config: &Config, options: &seed::SeedOptions, clear: bool) -> Result<()> {
+    let pool = connect(config).await?;
+    if clear {
+        seed::clear(&pool).await?;
+    }
+
+    let data = seed::generate(options);
+    seed::insert(&pool, &data)
+        .await
+        .context("Failed to insert seed data; use --clear to replace an earlier run")?;
+
+    println!(
+        "Seeded {} patients, {} practitioners, {} encounters, {} observations (seed {})",
+        data.patients.len(),
+        data.practitioners.len(),
+        data.encounters.len(),
+        data.observations.len(),
+        options.seed
+    );
+    Ok(())
+}
+
+/// Check each dependency and fail if any is unhealthy
+pub async fn healthcheck(config: &Config) -> Result<()> {
+    let mut healthy = true;
+    let mut report = |name: &str, result: Result<()>| match result {
+        Ok(()) => println!("{:<10} ok", name),
+        Err(e) => {
+            healthy = false;
+            println!("{:<10} FAILED: {:#}", name, e);
+        }
+    };
+
+    let database = async {
+        let pool = connect(config).await?;
+        sqlx::query("SELECT 1").execute(&pool).await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    report("database", database.await);
+
+    let nats = async {
+        async_nats::connect(&config.nats.url).await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    report("nats", nats.await);
+
+    let api = async {
+        let host = &config.server.host;
+        let local = host == "127.0.0.1" || host == "localhost";
+        let client = reqwest::Client::builder()
+            // Local development certificates are self-signed
+            .danger_accept_invalid_certs(local)
+            .timeout(Duration::from_secs(5))
+            .build()?;
+        client
+            .get(format!("https://{}:{}/healthz", host, config.server.port))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok::<_, anyhow::Error>(())
+    };
+    report("api", api.await);
+
+    if !healthy {
+        bail!("Healthcheck failed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_jwt_secret() {
+        let secret = generate_jwt_secret();
+        assert_eq!(STANDARD.decode(&secret).unwrap().len(), JWT_SECRET_BYTES);
+        assert_ne!(secret, generate_jwt_secret());
+    }
+
+    #[test]
+    fn test_validate_job() {
+        assert!(validate_job(&serde_json::json!({"type": "DataCleanup", "dry_run": true})).is_ok());
+        assert!(validate_job(&serde_json::json!({"dry_run": true})).is_err());
+        assert!(validate_job(&serde_json::json!({"type": ""})).is_err());
+    }
+}
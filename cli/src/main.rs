@@ -0,0 +1,212 @@
+#![deny(unsafe_code)]
+
+//! Administration CLI for the EMR platform
+//!
+//! `emr-admin` loads the same configuration as the API server and performs
+//! operator tasks (user management, secret rotation, migrations, job
+//! submission, exports) without direct SQL access.
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+// Shared with the API so both read the same config files and EMR_* variables
+#[path = "../../api/src/config.rs"]
+#[allow(dead_code)]
+mod config;
+
+// The API modules the configuration checks call into, at the paths it
+// expects them
+#[path = "../../api/src/auth/networks.rs"]
+#[allow(dead_code)]
+mod networks;
+#[path = "../../api/src/services/secrets.rs"]
+#[allow(dead_code)]
+mod secrets;
+
+mod auth {
+    pub(crate) use crate::networks;
+}
+
+mod services {
+    pub use crate::secrets::secret_resolver;
+}
+
+mod error {
+    pub use anyhow::Result;
+}
+
+mod commands;
+mod seed;
+
+/// Roles a user can be assigned
+pub const ROLES: &[&str] = &["admin", "clinician", "billing", "executive", "user"];
+
+/// EMR administration tool
+#[derive(Debug, Parser)]
+#[command(name = "emr-admin", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a user account
+    CreateUser {
+        #[arg(long)]
+        username: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        first_name: Option<String>,
+        #[arg(long)]
+        last_name: Option<String>,
+        #[arg(long, default_value = "user", value_parser = clap::builder::PossibleValuesParser::new(ROLES))]
+        role: String,
+        /// Initial password; prefer the environment variable over the flag
+        #[arg(long, env = "EMR_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+
+    /// Change a user's role
+    AssignRole {
+        #[arg(long)]
+        username: String,
+        #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(ROLES))]
+        role: String,
+    },
+
+    /// Generate a new JWT signing secret and revoke existing sessions
+    RotateJwtSecret {
+        /// Keep existing sessions instead of forcing users to sign in again
+        #[arg(long)]
+        keep_sessions: bool,
+    },
+
+    /// Apply pending database migrations
+    RunMigration {
+        #[arg(long, default_value = "migrations")]
+        path: PathBuf,
+        /// List pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Submit a job to the jobs worker
+    EnqueueJob {
+        /// JSON job definition (`{"type": "DataCleanup", ...}`); `-` reads stdin
+        file: PathBuf,
+        /// Key naming the operation; the worker drops later submissions with the same key
+        #[arg(long)]
+        idempotency_key: Option<String>,
+    },
+
+    /// Export a patient and their encounters and observations as JSON
+    ExportPatient {
+        #[arg(long)]
+        id: uuid::Uuid,
+        /// Output file; stdout when omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Rebuild search indexes
+    ReindexSearch {
+        /// Restrict to one table, e.g. `patients`
+        #[arg(long)]
+        table: Option<String>,
+    },
+
+    /// Check the database, NATS and API health
+    Healthcheck,
+
+    /// Generate synthetic demo data (no PHI)
+    Seed {
+        /// Random seed; the same seed produces the same data
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        #[arg(long, default_value_t = 100)]
+        patients: usize,
+        #[arg(long, default_value_t = 10)]
+        practitioners: usize,
+        #[arg(long, default_value_t = 3)]
+        encounters_per_patient: usize,
+        /// Delete previously seeded rows first
+        #[arg(long)]
+        clear: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = config::Config::from_env()?;
+
+    match cli.command {
+        Command::CreateUser {
+            username,
+            email,
+            first_name,
+            last_name,
+            role,
+            password,
+        } => {
+            commands::create_user(
+                &config,
+                commands::NewUser {
+                    username,
+                    email,
+                    first_name,
+                    last_name,
+                    role,
+                    password,
+                },
+            )
+            .await
+        }
+        Command::AssignRole { username, role } => commands::assign_role(&config, &username, &role).await,
+        Command::RotateJwtSecret { keep_sessions } => commands::rotate_jwt_secret(&config, keep_sessions).await,
+        Command::RunMigration { path, dry_run } => commands::run_migration(&config, &path, dry_run).await,
+        Command::EnqueueJob { file, idempotency_key } => commands::enqueue_job(&config, &file, idempotency_key.as_deref()).await,
+        Command::ExportPatient { id, output } => commands::export_patient(&config, id, output.as_deref()).await,
+        Command::ReindexSearch { table } => commands::reindex_search(&config, table.as_deref()).await,
+        Command::Healthcheck => commands::healthcheck(&config).await,
+        Command::Seed {
+            seed,
+            patients,
+            practitioners,
+            encounters_per_patient,
+            clear,
+        } => {
+            let options = seed::SeedOptions {
+                seed,
+                patients,
+                practitioners,
+                encounters_per_patient,
+                as_of: chrono::Utc::now(),
+            };
+            commands::seed(&config, &options, clear).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_assign_role() {
+        let cli = Cli::try_parse_from(["emr-admin", "assign-role", "--username", "jdoe", "--role", "billing"]).unwrap();
+        assert!(matches!(cli.command, Command::AssignRole { ref role, .. } if role == "billing"));
+
+        let result = Cli::try_parse_from(["emr-admin", "assign-role", "--username", "jdoe", "--role", "root"]);
+        assert!(result.is_err());
+    }
+}
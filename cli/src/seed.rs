@@ -0,0 +1,362 @@
+//! Synthetic demo data
+//!
+//! Generates fictional patients, practitioners, encounters and vital-sign
+//! observations. Output depends only on the seed and the reference time, so
+//! UI demos and load tests can recreate the same data set. Names come from
+//! fixed lists, phone numbers from the reserved 555-01xx range and emails from
+//! `example.com`; nothing is derived from real records.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use emr_core::domain::{VitalSign, VITAL_SIGNS_CATEGORY};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sqlx::postgres::PgPool;
+use uuid::Uuid;
+
+/// Prefix on every generated `fhir_id`, used to find and clear seeded rows
+pub const SEED_PREFIX: &str = "seed-";
+
+const GIVEN_NAMES: &[&str] = &[
+    "Avery", "Jordan", "Riley", "Morgan", "Casey", "Quinn", "Harper", "Rowan", "Emerson", "Sage",
+    "Elliot", "Reese", "Dakota", "Finley", "Hayden", "Kendall", "Logan", "Parker", "Skyler", "Tatum",
+];
+
+const FAMILY_NAMES: &[&str] = &[
+    "Alder", "Birch", "Calloway", "Dunmore", "Ellery", "Fairbanks", "Garrow", "Hollis", "Ingram", "Jessup",
+    "Kinsley", "Lowell", "Merritt", "Norwood", "Oakley", "Pember", "Quimby", "Radley", "Stroud", "Thorne",
+];
+
+const SPECIALTIES: &[&str] = &["family-medicine", "internal-medicine", "psychiatry", "addiction-medicine", "nursing"];
+
+const QUALIFICATIONS: &[&str] = &["MD", "DO", "NP", "PA", "RN"];
+
+const ENCOUNTER_REASONS: &[(&str, &str)] = &[
+    ("185349003", "Encounter for check up"),
+    ("390906007", "Follow-up encounter"),
+    ("308335008", "Patient encounter procedure"),
+    ("702927004", "Urgent care clinic visit"),
+];
+
+/// Vital signs recorded at every seeded encounter
+const SEEDED_VITALS: &[VitalSign] = &[
+    VitalSign::SystolicBloodPressure,
+    VitalSign::DiastolicBloodPressure,
+    VitalSign::HeartRate,
+    VitalSign::RespiratoryRate,
+    VitalSign::BodyTemperature,
+    VitalSign::OxygenSaturation,
+];
+
+/// How much data to generate
+#[derive(Debug, Clone)]
+pub struct SeedOptions {
+    pub seed: u64,
+    pub patients: usize,
+    pub practitioners: usize,
+    pub encounters_per_patient: usize,
+    /// Encounters fall within the year before this time
+    pub as_of: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeedPatient {
+    pub id: Uuid,
+    pub fhir_id: String,
+    pub given_names: Vec<String>,
+    pub family_name: String,
+    pub birth_date: NaiveDate,
+    pub gender: &'static str,
+    pub phone: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeedPractitioner {
+    pub id: Uuid,
+    pub fhir_id: String,
+    pub given_names: Vec<String>,
+    pub family_name: String,
+    pub qualification: &'static str,
+    pub specialty: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeedEncounter {
+    pub id: Uuid,
+    pub fhir_id: String,
+    pub patient_id: Uuid,
+    pub practitioner_id: Uuid,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub reason_code: &'static str,
+    pub reason_description: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeedObservation {
+    pub id: Uuid,
+    pub fhir_id: String,
+    pub patient_id: Uuid,
+    pub encounter_id: Uuid,
+    pub vital: VitalSign,
+    pub effective: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// A complete generated data set
+#[derive(Debug, Clone, Default)]
+pub struct SeedData {
+    pub patients: Vec<SeedPatient>,
+    pub practitioners: Vec<SeedPractitioner>,
+    pub encounters: Vec<SeedEncounter>,
+    pub observations: Vec<SeedObservation>,
+}
+
+/// Typical adult values, narrower than the plausibility limits used for validation
+fn typical_range(vital: VitalSign) -> (f64, f64) {
+    match vital {
+        VitalSign::SystolicBloodPressure => (105.0, 145.0),
+        VitalSign::DiastolicBloodPressure => (60.0, 92.0),
+        VitalSign::HeartRate => (55.0, 100.0),
+        VitalSign::RespiratoryRate => (12.0, 20.0),
+        VitalSign::BodyTemperature => (36.2, 37.8),
+        VitalSign::OxygenSaturation => (94.0, 100.0),
+    }
+}
+
+fn uuid(rng: &mut StdRng) -> Uuid {
+    uuid::Builder::from_random_bytes(rng.gen()).into_uuid()
+}
+
+fn name(rng: &mut StdRng) -> (Vec<String>, String) {
+    let given = GIVEN_NAMES.choose(rng).unwrap().to_string();
+    let family = FAMILY_NAMES.choose(rng).unwrap().to_string();
+    (vec![given], family)
+}
+
+/// Generate a data set; the same options always produce the same data
+pub fn generate(options: &SeedOptions) -> SeedData {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut data = SeedData::default();
+
+    for n in 0..options.practitioners {
+        let (given_names, family_name) = name(&mut rng);
+        data.practitioners.push(SeedPractitioner {
+            id: uuid(&mut rng),
+            fhir_id: format!("{}practitioner-{}", SEED_PREFIX, n),
+            given_names,
+            family_name,
+            qualification: QUALIFICATIONS.choose(&mut rng).unwrap(),
+            specialty: SPECIALTIES.choose(&mut rng).unwrap(),
+        });
+    }
+
+    let today = options.as_of.date_naive();
+    for n in 0..options.patients {
+        let (given_names, family_name) = name(&mut rng);
+        let patient = SeedPatient {
+            id: uuid(&mut rng),
+            fhir_id: format!("{}patient-{}", SEED_PREFIX, n),
+            email: format!(
+                "{}.{}{}@example.com",
+                given_names[0].to_lowercase(),
+                family_name.to_lowercase(),
+                n
+            ),
+            given_names,
+            family_name,
+            birth_date: today - Duration::days(rng.gen_range(18 * 365..90 * 365)),
+            gender: ["female", "male", "other", "unknown"][rng.gen_range(0..4)],
+            phone: format!("+1-555-01{:02}", rng.gen_range(0..100)),
+        };
+
+        for e in 0..options.encounters_per_patient {
+            let Some(practitioner) = data.practitioners.choose(&mut rng) else {
+                break;
+            };
+            let start = options.as_of - Duration::minutes(rng.gen_range(60..365 * 24 * 60));
+            let (reason_code, reason_description) = *ENCOUNTER_REASONS.choose(&mut rng).unwrap();
+            let encounter = SeedEncounter {
+                id: uuid(&mut rng),
+                fhir_id: format!("{}encounter-{}-{}", SEED_PREFIX, n, e),
+                patient_id: patient.id,
+                practitioner_id: practitioner.id,
+                start,
+                end: start + Duration::minutes(rng.gen_range(15..60)),
+                reason_code,
+                reason_description,
+            };
+
+            for vital in SEEDED_VITALS {
+                let (low, high) = typical_range(*vital);
+                let value: f64 = rng.gen_range(low..=high);
+                data.observations.push(SeedObservation {
+                    id: uuid(&mut rng),
+                    fhir_id: format!("{}observation-{}-{}-{}", SEED_PREFIX, n, e, vital.loinc_code()),
+                    patient_id: patient.id,
+                    encounter_id: encounter.id,
+                    vital: *vital,
+                    effective: encounter.start + Duration::minutes(5),
+                    value: (value * 10.0).round() / 10.0,
+                });
+            }
+            data.encounters.push(encounter);
+        }
+
+        data.patients.push(patient);
+    }
+
+    data
+}
+
+/// Delete previously seeded rows
+pub async fn clear(pool: &PgPool) -> Result<()> {
+    let pattern = format!("{}%", SEED_PREFIX);
+    let mut tx = pool.begin().await?;
+    for table in ["observations", "encounters", "patients", "practitioners"] {
+        sqlx::query(&format!("DELETE FROM emr.{} WHERE fhir_id LIKE $1", table))
+            .bind(&pattern)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Insert a generated data set in one transaction
+pub async fn insert(pool: &PgPool, data: &SeedData) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for practitioner in &data.practitioners {
+        sqlx::query(
+            "INSERT INTO emr.practitioners (id, fhir_id, family_name, given_names, qualification, specialties)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(practitioner.id)
+        .bind(&practitioner.fhir_id)
+        .bind(&practitioner.family_name)
+        .bind(&practitioner.given_names)
+        .bind(practitioner.qualification)
+        .bind(vec![practitioner.specialty])
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for patient in &data.patients {
+        sqlx::query(
+            "INSERT INTO emr.patients (id, fhir_id, family_name, given_names, birth_date, gender, phone, email)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(patient.id)
+        .bind(&patient.fhir_id)
+        .bind(&patient.family_name)
+        .bind(&patient.given_names)
+        .bind(patient.birth_date)
+        .bind(patient.gender)
+        .bind(&patient.phone)
+        .bind(&patient.email)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for encounter in &data.encounters {
+        sqlx::query(
+            "INSERT INTO emr.encounters
+                (id, fhir_id, status, class, patient_id, practitioner_id, start_date, end_date, reason_code, reason_description)
+             VALUES ($1, $2, 'finished', 'AMB', $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(encounter.id)
+        .bind(&encounter.fhir_id)
+        .bind(encounter.patient_id)
+        .bind(encounter.practitioner_id)
+        .bind(encounter.start)
+        .bind(encounter.end)
+        .bind(encounter.reason_code)
+        .bind(encounter.reason_description)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for observation in &data.observations {
+        sqlx::query(
+            "INSERT INTO emr.observations
+                (id, fhir_id, status, category, code, display, patient_id, encounter_id,
+                 effective_date, value_quantity_value, value_quantity_unit)
+             VALUES ($1, $2, 'final', $3, $4, $5, $6, $7, $8, $9::float8::numeric, $10)",
+        )
+        .bind(observation.id)
+        .bind(&observation.fhir_id)
+        .bind(VITAL_SIGNS_CATEGORY)
+        .bind(observation.vital.loinc_code())
+        .bind(observation.vital.display())
+        .bind(observation.patient_id)
+        .bind(observation.encounter_id)
+        .bind(observation.effective)
+        .bind(observation.value)
+        .bind(observation.vital.ucum_unit())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(seed: u64) -> SeedOptions {
+        SeedOptions {
+            seed,
+            patients: 20,
+            practitioners: 3,
+            encounters_per_patient: 2,
+            as_of: DateTime::parse_from_rfc3339("2024-06-01T12:00:00Z").unwrap().with_timezone(&Utc),
+        }
+    }
+
+    #[test]
+    fn test_generate_is_deterministic() {
+        let first = generate(&options(42));
+        let second = generate(&options(42));
+        let other = generate(&options(7));
+
+        let ids = |data: &SeedData| data.patients.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids(&first), ids(&second));
+        assert_ne!(ids(&first), ids(&other));
+        assert_eq!(first.observations[0].value, second.observations[0].value);
+    }
+
+    #[test]
+    fn test_generate_counts_and_ranges() {
+        let options = options(1);
+        let data = generate(&options);
+
+        assert_eq!(data.patients.len(), 20);
+        assert_eq!(data.practitioners.len(), 3);
+        assert_eq!(data.encounters.len(), 40);
+        assert_eq!(data.observations.len(), 40 * SEEDED_VITALS.len());
+
+        for encounter in &data.encounters {
+            assert!(encounter.start < options.as_of && encounter.end > encounter.start);
+        }
+        for observation in &data.observations {
+            let (low, high) = observation.vital.plausible_range();
+            assert!(observation.value >= low && observation.value <= high);
+        }
+        assert!(data.patients.iter().all(|p| p.email.ends_with("@example.com")));
+    }
+
+    #[test]
+    fn test_generate_without_practitioners_skips_encounters() {
+        let data = generate(&SeedOptions {
+            practitioners: 0,
+            ..options(1)
+        });
+        assert_eq!(data.patients.len(), 20);
+        assert!(data.encounters.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! Benchmarks for core hot paths: patient (de)serialization and SampledData
+//! waveform conversion
+//!
+//! Run with `cargo bench -p emr-core`.
+
+use chrono::NaiveDate;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use emr_core::domain::values::*;
+use emr_core::domain::{Patient, SampledDataEncoding, Waveform};
+
+fn sample_patient() -> Patient {
+    let mut patient = Patient::new(vec![HumanName {
+        given: vec!["Alex".to_string(), "Jordan".to_string()],
+        family: "Rivera".to_string(),
+        prefix: None,
+        suffix: None,
+        use_: Some(NameUse::Official),
+    }])
+    .unwrap();
+
+    patient.birth_date = NaiveDate::from_ymd_opt(1984, 3, 14);
+    patient.add_identifier(Identifier {
+        use_: Some(IdentifierUse::Official),
+        system: Some("MRN".to_string()),
+        value: "seed-000042".to_string(),
+    });
+    patient.add_telecom(ContactPoint {
+        system: ContactSystem::Email,
+        value: "alex.rivera@example.org".to_string(),
+        use_: Some(ContactUse::Home),
+        rank: Some(1),
+        verified_at: None,
+    });
+    patient.add_address(Address {
+        use_: Some(AddressUse::Home),
+        type_: Some(AddressType::Physical),
+        text: None,
+        line: vec!["1 Example Way".to_string()],
+        city: Some("Springfield".to_string()),
+        district: None,
+        state: Some("IL".to_string()),
+        postal_code: Some("62701".to_string()),
+        country: Some("US".to_string()),
+    });
+    patient
+}
+
+fn bench_patient_serialization(c: &mut Criterion) {
+    let patient = sample_patient();
+    let json = serde_json::to_string(&patient).unwrap();
+
+    let mut group = c.benchmark_group("patient");
+    group.throughput(Throughput::Bytes(json.len() as u64));
+    group.bench_function("serialize", |b| b.iter(|| serde_json::to_string(black_box(&patient)).unwrap()));
+    group.bench_function("deserialize", |b| {
+        b.iter(|| serde_json::from_str::<Patient>(black_box(&json)).unwrap())
+    });
+    group.finish();
+}
+
+/// One minute of a 250 Hz single-lead ECG-like signal
+fn sample_waveform() -> Waveform {
+    let samples = (0..15_000)
+        .map(|i| {
+            let t = i as f64 / 250.0;
+            ((t * std::f64::consts::TAU * 1.2).sin() * 400.0).round() * 0.005
+        })
+        .collect();
+
+    Waveform {
+        origin: 0.0,
+        period: 4.0,
+        factor: 0.005,
+        lower_limit: None,
+        upper_limit: None,
+        dimensions: 1,
+        samples,
+    }
+}
+
+fn bench_sampled_data(c: &mut Criterion) {
+    let waveform = sample_waveform();
+
+    let mut group = c.benchmark_group("sampled_data");
+    group.throughput(Throughput::Elements(waveform.samples.len() as u64));
+    for encoding in [
+        SampledDataEncoding::Text,
+        SampledDataEncoding::DeltaVarint,
+        SampledDataEncoding::Float32,
+    ] {
+        let value = waveform.to_value(encoding).unwrap();
+        let name = format!("{:?}", encoding);
+
+        group.bench_with_input(BenchmarkId::new("encode", &name), &encoding, |b, encoding| {
+            b.iter(|| waveform.to_value(black_box(*encoding)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("decode", &name), &value, |b, value| {
+            b.iter(|| Waveform::from_value(black_box(value)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_patient_serialization, bench_sampled_data);
+criterion_main!(benches);
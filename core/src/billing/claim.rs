@@ -0,0 +1,414 @@
+//! Professional claims
+//!
+//! A [`ProfessionalClaim`] is one finished, coded encounter priced and ready
+//! to bill: the claim as JSON is what the claims export records, and the
+//! 837P writer serializes it. [`assemble_claim`] builds it from a
+//! [`ClaimSource`] (the finalized coding, the patient's demographics and
+//! coverage, and the rendering practitioner), pricing procedures from the
+//! [`FeeSchedule`]. Anything a payer would reject the claim for is reported
+//! as a [`ClaimIssue`] instead, so one bad encounter does not hold up the
+//! rest of a batch.
+
+use crate::domain::practitioner::is_valid_npi;
+use crate::domain::values::AdministrativeGender;
+use crate::domain::{CodeKind, Coverage, EncounterClass, EncounterCoding, PlanType, SubscriberRelationship};
+use crate::types::Id;
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A postal address as claims carry it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostalAddress {
+    /// Street address
+    pub line1: String,
+    /// Second address line
+    pub line2: Option<String>,
+    /// City
+    pub city: String,
+    /// State code
+    pub state: String,
+    /// ZIP code
+    pub postal_code: String,
+}
+
+/// The practice billing the claims
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingProvider {
+    /// Organization name
+    pub name: String,
+    /// Organization NPI
+    pub npi: String,
+    /// Employer identification number
+    pub tax_id: String,
+    /// Provider taxonomy code
+    pub taxonomy: Option<String>,
+    /// Pay-to address
+    pub address: PostalAddress,
+}
+
+/// A patient or subscriber as named on a claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimPerson {
+    /// Family name
+    pub family_name: String,
+    /// Given name
+    pub given_name: Option<String>,
+    /// Date of birth
+    pub birth_date: Option<NaiveDate>,
+    /// Administrative gender
+    pub gender: Option<AdministrativeGender>,
+    /// Home address
+    pub address: Option<PostalAddress>,
+}
+
+/// The practitioner who saw the patient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderingProvider {
+    /// Practitioner NPI
+    pub npi: String,
+    /// Family name
+    pub family_name: String,
+    /// Given name
+    pub given_name: Option<String>,
+}
+
+/// Everything a claim is assembled from
+#[derive(Debug, Clone)]
+pub struct ClaimSource {
+    /// Encounter billed
+    pub encounter_id: Id,
+    /// Class of the encounter, which decides the place of service
+    pub encounter_class: EncounterClass,
+    /// Date of service
+    pub service_date: NaiveDate,
+    /// The encounter's finalized coding
+    pub coding: EncounterCoding,
+    /// The patient
+    pub patient: ClaimPerson,
+    /// The patient's primary coverage on the date of service
+    pub coverage: Option<Coverage>,
+    /// The practitioner who saw the patient
+    pub rendering_provider: Option<RenderingProvider>,
+}
+
+/// Charges per unit of each procedure code, in cents
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    fees: HashMap<String, u64>,
+}
+
+impl FeeSchedule {
+    /// Create an empty fee schedule
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the charge per unit of `code`
+    pub fn with_fee(mut self, code: &str, cents: u64) -> Self {
+        self.fees.insert(code.trim().to_uppercase(), cents);
+        self
+    }
+
+    /// Parse `code,charge` lines with the charge in dollars; a header line
+    /// and blank lines are skipped
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut schedule = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (index == 0 && line.to_lowercase().starts_with("code")) {
+                continue;
+            }
+            let (code, charge) = line.split_once(',').ok_or_else(|| {
+                Error::configuration_error(&format!("Fee schedule line {} is not `code,charge`", index + 1))
+            })?;
+            let cents = parse_dollars(charge.trim()).ok_or_else(|| {
+                Error::configuration_error(&format!("Fee schedule line {} has an invalid charge", index + 1))
+            })?;
+            schedule = schedule.with_fee(code, cents);
+        }
+        Ok(schedule)
+    }
+
+    /// Charge per unit of `code`, in cents
+    pub fn charge(&self, code: &str) -> Option<u64> {
+        self.fees.get(code).copied()
+    }
+}
+
+/// Parse a dollar amount with at most two decimals into cents
+fn parse_dollars(value: &str) -> Option<u64> {
+    let (dollars, cents) = value.split_once('.').unwrap_or((value, ""));
+    if dollars.is_empty() || cents.len() > 2 || !cents.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let dollars: u64 = dollars.parse().ok()?;
+    let cents: u64 = if cents.is_empty() { 0 } else { format!("{:0<2}", cents).parse().ok()? };
+    dollars.checked_mul(100)?.checked_add(cents)
+}
+
+/// A billed procedure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimLine {
+    /// Line number, from 1
+    pub line_number: u32,
+    /// CPT code
+    pub code: String,
+    /// CPT modifiers
+    pub modifiers: Vec<String>,
+    /// Units of service
+    pub units: u32,
+    /// Line charge (per-unit charge times units), in cents
+    pub charge_cents: u64,
+    /// Positions of the diagnoses the line treats among the claim's
+    /// diagnoses, from 1
+    pub diagnosis_pointers: Vec<u32>,
+    /// Date of service
+    pub service_date: NaiveDate,
+}
+
+/// A professional claim, ready to serialize
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfessionalClaim {
+    /// Patient control number, echoed back on the remittance
+    pub claim_id: String,
+    /// Encounter billed
+    pub encounter_id: Id,
+    /// CMS place of service code
+    pub place_of_service: String,
+    /// Date of service
+    pub service_date: NaiveDate,
+    /// ICD-10-CM diagnoses without dots, the principal one first
+    pub diagnoses: Vec<String>,
+    /// Procedures billed
+    pub lines: Vec<ClaimLine>,
+    /// Sum of the line charges, in cents
+    pub total_charge_cents: u64,
+    /// Payer name
+    pub payer_name: String,
+    /// Payer id assigned by the clearinghouse
+    pub payer_id: String,
+    /// Kind of plan billed
+    pub plan_type: PlanType,
+    /// Subscriber's member id
+    pub member_id: String,
+    /// Group number
+    pub group_number: Option<String>,
+    /// The patient's relationship to the subscriber
+    pub relationship: SubscriberRelationship,
+    /// The policy holder; the patient when the relationship is `self`
+    pub subscriber: ClaimPerson,
+    /// The patient
+    pub patient: ClaimPerson,
+    /// The practitioner who saw the patient, when not the billing provider
+    pub rendering_provider: Option<RenderingProvider>,
+}
+
+/// Why a claim could not be assembled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClaimIssue {
+    /// Part of the claim at fault
+    pub field: String,
+    /// What is wrong
+    pub message: String,
+}
+
+impl ClaimIssue {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// CMS place of service code of an encounter class
+pub fn place_of_service(class: &EncounterClass) -> &'static str {
+    match class {
+        EncounterClass::Ambulatory | EncounterClass::Outpatient => "11",
+        EncounterClass::Virtual => "02",
+        EncounterClass::Home => "12",
+        EncounterClass::Field => "15",
+        EncounterClass::Inpatient => "21",
+        EncounterClass::Daytime => "22",
+        EncounterClass::Emergency => "23",
+    }
+}
+
+/// Assemble the claim of a coded encounter, or every reason it cannot be
+/// billed
+pub fn assemble_claim(
+    source: &ClaimSource,
+    fees: &FeeSchedule,
+) -> std::result::Result<ProfessionalClaim, Vec<ClaimIssue>> {
+    let mut issues = Vec::new();
+
+    let coverage = match &source.coverage {
+        Some(coverage) if coverage.is_in_force(source.service_date) => Some(coverage),
+        Some(_) => {
+            issues.push(ClaimIssue::new("coverage", format!("Coverage is not in force on {}", source.service_date)));
+            None
+        }
+        None => {
+            issues.push(ClaimIssue::new("coverage", "The patient has no coverage on file"));
+            None
+        }
+    };
+
+    let patient = &source.patient;
+    if patient.birth_date.is_none() {
+        issues.push(ClaimIssue::new("patient.birth_date", "The patient's date of birth is required"));
+    }
+    if patient.gender.is_none() {
+        issues.push(ClaimIssue::new("patient.gender", "The patient's gender is required"));
+    }
+    if patient.address.is_none() {
+        issues.push(ClaimIssue::new("patient.address", "The patient's address is required"));
+    }
+    if let Some(provider) = &source.rendering_provider {
+        if !is_valid_npi(&provider.npi) {
+            issues.push(ClaimIssue::new(
+                "rendering_provider.npi",
+                format!("{} is not a valid NPI", provider.npi),
+            ));
+        }
+    }
+
+    let diagnoses: Vec<String> = source
+        .coding
+        .of_kind(CodeKind::Diagnosis)
+        .map(|code| code.code.replace('.', ""))
+        .collect();
+    if diagnoses.is_empty() {
+        issues.push(ClaimIssue::new("diagnoses", "The encounter has no diagnoses"));
+    }
+
+    let mut lines = Vec::new();
+    for (index, code) in source.coding.of_kind(CodeKind::Procedure).enumerate() {
+        let Some(charge) = fees.charge(&code.code) else {
+            issues.push(ClaimIssue::new(
+                &format!("lines[{}].charge", index),
+                format!("No fee is scheduled for {}", code.code),
+            ));
+            continue;
+        };
+        lines.push(ClaimLine {
+            line_number: index as u32 + 1,
+            code: code.code.clone(),
+            modifiers: code.modifiers.clone(),
+            units: code.units,
+            charge_cents: charge * u64::from(code.units),
+            diagnosis_pointers: code.diagnosis_pointers.clone(),
+            service_date: source.service_date,
+        });
+    }
+    if source.coding.of_kind(CodeKind::Procedure).next().is_none() {
+        issues.push(ClaimIssue::new("lines", "The encounter has no procedures to bill"));
+    }
+
+    let Some(coverage) = coverage else {
+        return Err(issues);
+    };
+    let subscriber = match (&coverage.relationship, &coverage.subscriber) {
+        (SubscriberRelationship::Own, _) => patient.clone(),
+        (_, Some(subscriber)) => ClaimPerson {
+            family_name: subscriber.family_name.clone(),
+            given_name: subscriber.given_name.clone(),
+            birth_date: subscriber.birth_date,
+            gender: subscriber.gender.clone(),
+            address: patient.address.clone(),
+        },
+        (_, None) => {
+            issues.push(ClaimIssue::new("coverage.subscriber", "Coverage through someone else needs the subscriber"));
+            return Err(issues);
+        }
+    };
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+
+    Ok(ProfessionalClaim {
+        claim_id: source.encounter_id.simple().to_string().to_uppercase(),
+        encounter_id: source.encounter_id,
+        place_of_service: place_of_service(&source.encounter_class).to_string(),
+        service_date: source.service_date,
+        diagnoses,
+        total_charge_cents: lines.iter().map(|line| line.charge_cents).sum(),
+        lines,
+        payer_name: coverage.payer_name.clone(),
+        payer_id: coverage.payer_id.clone(),
+        plan_type: coverage.plan_type,
+        member_id: coverage.member_id.clone(),
+        group_number: coverage.group_number.clone(),
+        relationship: coverage.relationship,
+        subscriber,
+        patient: patient.clone(),
+        rendering_provider: source.rendering_provider.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::EncounterCode;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn claim_source() -> ClaimSource {
+        let patient_id = Uuid::new_v4();
+        let encounter_id = Uuid::new_v4();
+        let mut visit = EncounterCode::new(CodeKind::Procedure, "99213", Some("99213"));
+        visit.modifiers = vec!["25".to_string()];
+        let codes = vec![
+            EncounterCode::new(CodeKind::Diagnosis, "E11.9", None),
+            EncounterCode::new(CodeKind::Diagnosis, "I10", None),
+            visit,
+        ];
+        let coding = EncounterCoding::new(encounter_id, patient_id, codes, Uuid::new_v4(), Utc::now()).unwrap();
+        ClaimSource {
+            encounter_id,
+            encounter_class: EncounterClass::Ambulatory,
+            service_date: NaiveDate::from_ymd_opt(2026, 3, 4).unwrap(),
+            coding,
+            patient: ClaimPerson {
+                family_name: "Smith".to_string(),
+                given_name: Some("John".to_string()),
+                birth_date: NaiveDate::from_ymd_opt(1970, 5, 6),
+                gender: Some(AdministrativeGender::Male),
+                address: Some(PostalAddress {
+                    line1: "1 Main St".to_string(),
+                    line2: None,
+                    city: "Springfield".to_string(),
+                    state: "IL".to_string(),
+                    postal_code: "62701".to_string(),
+                }),
+            },
+            coverage: Some(Coverage::new(patient_id, PlanType::Commercial, "Acme Health", "60054", "W123456789")),
+            rendering_provider: Some(RenderingProvider {
+                npi: "1234567893".to_string(),
+                family_name: "Jones".to_string(),
+                given_name: Some("Ann".to_string()),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_assemble_claim() {
+        let fees = FeeSchedule::from_csv("code,charge\n99213,125.5\n").unwrap();
+        assert_eq!(fees.charge("99213"), Some(12550));
+
+        let claim = assemble_claim(&claim_source(), &fees).unwrap();
+        assert_eq!(claim.diagnoses, vec!["E119".to_string(), "I10".to_string()]);
+        assert_eq!(claim.place_of_service, "11");
+        assert_eq!((claim.lines.len(), claim.total_charge_cents), (1, 12550));
+        assert_eq!(claim.subscriber.family_name, "Smith");
+
+        let mut source = claim_source();
+        source.coverage = None;
+        source.patient.gender = None;
+        let issues = assemble_claim(&source, &FeeSchedule::new()).unwrap_err();
+        let fields: Vec<&str> = issues.iter().map(|issue| issue.field.as_str()).collect();
+        assert_eq!(fields, vec!["coverage", "patient.gender", "lines[0].charge"]);
+    }
+}
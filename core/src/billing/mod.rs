@@ -0,0 +1,20 @@
+//! Billing
+//!
+//! Finished, coded encounters are billed to the patient's coverage as
+//! professional claims. [`claim`] assembles the structured intermediate form
+//! of each claim and reports what would get it rejected; [`x12`] writes a
+//! batch of assembled claims as an X12 837P (005010X222A1) file for the
+//! clearinghouse. [`remittance`] reads the payer's 835 back and decides which
+//! payments need follow-up by billing staff. [`superbill`] prints an
+//! encounter's captured charges for the patient.
+
+pub mod claim;
+pub(crate) mod pdf;
+pub mod remittance;
+pub mod superbill;
+pub mod x12;
+
+pub use claim::*;
+pub use remittance::*;
+pub use superbill::{Superbill, SuperbillLine};
+pub use x12::{write_837p, InterchangeSettings};
@@ -0,0 +1,156 @@
+//! Minimal PDF writer for printable text documents
+//!
+//! Lays out lines of text cells in Helvetica on US Letter pages, starting a
+//! new page when one fills up. Only what billing forms need: no images, no
+//! wrapping, and text outside WinAnsi printed as `?`.
+
+use std::fmt::Write;
+
+/// Page width and height in points (US Letter)
+const PAGE_SIZE: (f32, f32) = (612.0, 792.0);
+
+/// Page margin in points
+const MARGIN: f32 = 54.0;
+
+/// A line of text cells
+#[derive(Debug, Clone)]
+pub struct PdfLine {
+    /// Font size in points
+    pub size: f32,
+    /// Whether the line is set in bold
+    pub bold: bool,
+    /// Text cells and their offset from the left margin, in points
+    pub cells: Vec<(f32, String)>,
+}
+
+impl PdfLine {
+    /// A line of one cell at the margin
+    pub fn text(size: f32, bold: bool, text: impl Into<String>) -> Self {
+        Self {
+            size,
+            bold,
+            cells: vec![(0.0, text.into())],
+        }
+    }
+
+    /// An empty line
+    pub fn blank() -> Self {
+        Self {
+            size: 6.0,
+            bold: false,
+            cells: Vec::new(),
+        }
+    }
+}
+
+/// Text as a PDF literal string
+fn literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('(');
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped.push(')');
+    escaped
+}
+
+/// Content streams of the pages the lines fill
+fn page_contents(lines: &[PdfLine]) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut content = String::new();
+    let mut y = PAGE_SIZE.1 - MARGIN;
+
+    for line in lines {
+        let height = line.size * 1.4;
+        if y - height < MARGIN && !content.is_empty() {
+            pages.push(std::mem::take(&mut content));
+            y = PAGE_SIZE.1 - MARGIN;
+        }
+        y -= height;
+        let font = if line.bold { "F2" } else { "F1" };
+        for (x, text) in &line.cells {
+            let _ = writeln!(
+                content,
+                "BT /{} {:.1} Tf {:.1} {:.1} Td {} Tj ET",
+                font,
+                line.size,
+                MARGIN + x,
+                y,
+                literal(text)
+            );
+        }
+    }
+    pages.push(content);
+    pages
+}
+
+/// Render lines as a PDF document
+pub fn render(lines: &[PdfLine]) -> Vec<u8> {
+    let pages = page_contents(lines);
+    // Objects 1-4 are the catalog, page tree and fonts; each page then has a
+    // page object and a content stream
+    let page_ids: Vec<usize> = (0..pages.len()).map(|index| 5 + index * 2).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string(),
+    ];
+    for (content, page_id) in pages.iter().zip(&page_ids) {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_SIZE.0,
+            PAGE_SIZE.1,
+            page_id + 1
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+    }
+
+    let mut document = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(document.len());
+        let _ = write!(document, "{} 0 obj\n{}\nendobj\n", index + 1, object);
+    }
+    let xref = document.len();
+    let _ = write!(document, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(document, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        document,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    );
+    document.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_pdf() {
+        let lines: Vec<PdfLine> = (0..80).map(|i| PdfLine::text(10.0, i == 0, format!("Line (#{}) é", i))).collect();
+        let pdf = String::from_utf8(render(&lines)).unwrap();
+
+        assert!(pdf.starts_with("%PDF-1.4\n") && pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("/Count 2"));
+        assert!(pdf.contains("(Line \\(#0\\) ?) Tj"));
+        let xref: usize = pdf.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(pdf[xref..].starts_with("xref\n0 9\n"));
+    }
+}
@@ -0,0 +1,601 @@
+//! Remittance advice (X12 835)
+//!
+//! An 835 reports what a payer did with the claims it received: one
+//! [`Remittance`] per transaction (a check or EFT), with a [`ClaimPayment`]
+//! per claim matched to the claim's patient control number (CLP01, the
+//! `claim_id` of the exported claim). Adjustments (CAS) explain every cent
+//! between the charge and the payment, by group (contractual, patient
+//! responsibility, ...) and CARC reason code. Delimiters are read from the
+//! ISA header rather than assumed.
+//!
+//! Payments that need a person are turned into a [`BillingTask`]: denials,
+//! reversals, payments for claims we never exported and charges that do not
+//! match what was billed.
+
+use crate::domain::TaskStatus;
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// What the payer did with a claim (CLP02)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimPaymentStatus {
+    /// Processed as primary, secondary or tertiary
+    Processed,
+    /// Processed and forwarded to another payer
+    Forwarded,
+    /// Denied
+    Denied,
+    /// Reversal of an earlier payment
+    Reversed,
+    /// Any other status, e.g. a predetermination
+    Other,
+}
+
+impl ClaimPaymentStatus {
+    /// Status of an X12 claim status code
+    pub fn from_x12(code: &str) -> Self {
+        match code {
+            "1" | "2" | "3" => ClaimPaymentStatus::Processed,
+            "19" | "20" | "21" | "23" => ClaimPaymentStatus::Forwarded,
+            "4" => ClaimPaymentStatus::Denied,
+            "22" => ClaimPaymentStatus::Reversed,
+            _ => ClaimPaymentStatus::Other,
+        }
+    }
+
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClaimPaymentStatus::Processed => "processed",
+            ClaimPaymentStatus::Forwarded => "forwarded",
+            ClaimPaymentStatus::Denied => "denied",
+            ClaimPaymentStatus::Reversed => "reversed",
+            ClaimPaymentStatus::Other => "other",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "processed" => Some(ClaimPaymentStatus::Processed),
+            "forwarded" => Some(ClaimPaymentStatus::Forwarded),
+            "denied" => Some(ClaimPaymentStatus::Denied),
+            "reversed" => Some(ClaimPaymentStatus::Reversed),
+            "other" => Some(ClaimPaymentStatus::Other),
+            _ => None,
+        }
+    }
+}
+
+/// Who an adjustment is assigned to (CAS01)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdjustmentGroup {
+    /// Contractual obligation, written off
+    #[serde(rename = "CO")]
+    Contractual,
+    /// Patient responsibility
+    #[serde(rename = "PR")]
+    PatientResponsibility,
+    /// Other adjustment
+    #[serde(rename = "OA")]
+    Other,
+    /// Payer initiated reduction
+    #[serde(rename = "PI")]
+    PayerInitiated,
+    /// Correction or reversal
+    #[serde(rename = "CR")]
+    Correction,
+}
+
+impl AdjustmentGroup {
+    /// Parse the X12 group code
+    pub fn parse(code: &str) -> Option<Self> {
+        match code {
+            "CO" => Some(AdjustmentGroup::Contractual),
+            "PR" => Some(AdjustmentGroup::PatientResponsibility),
+            "OA" => Some(AdjustmentGroup::Other),
+            "PI" => Some(AdjustmentGroup::PayerInitiated),
+            "CR" => Some(AdjustmentGroup::Correction),
+            _ => None,
+        }
+    }
+
+    /// X12 group code
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdjustmentGroup::Contractual => "CO",
+            AdjustmentGroup::PatientResponsibility => "PR",
+            AdjustmentGroup::Other => "OA",
+            AdjustmentGroup::PayerInitiated => "PI",
+            AdjustmentGroup::Correction => "CR",
+        }
+    }
+}
+
+/// An amount the payer did not pay, and why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Adjustment {
+    /// Who the amount is assigned to
+    pub group: AdjustmentGroup,
+    /// Claim adjustment reason code (CARC)
+    pub reason_code: String,
+    /// Amount in cents; negative amounts increase the payment
+    pub amount_cents: i64,
+    /// Units adjusted
+    pub quantity: Option<f64>,
+}
+
+/// Payment of one service line (SVC)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServicePayment {
+    /// Procedure code as billed, without the qualifier
+    pub code: String,
+    /// Modifiers as billed
+    pub modifiers: Vec<String>,
+    /// Line charge, in cents
+    pub charge_cents: i64,
+    /// Line payment, in cents
+    pub paid_cents: i64,
+    /// Units paid
+    pub units: Option<f64>,
+    /// Line adjustments
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// Payment of one claim (CLP)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimPayment {
+    /// Patient control number, the `claim_id` of the exported claim
+    pub claim_id: String,
+    /// X12 claim status code
+    pub status_code: String,
+    /// What the payer did with the claim
+    pub status: ClaimPaymentStatus,
+    /// Charge as the payer received it, in cents
+    pub charge_cents: i64,
+    /// Payment, in cents
+    pub paid_cents: i64,
+    /// Patient responsibility, in cents
+    pub patient_responsibility_cents: i64,
+    /// Payer's claim control number
+    pub payer_claim_number: Option<String>,
+    /// Claim-level adjustments
+    pub adjustments: Vec<Adjustment>,
+    /// Service line payments
+    pub lines: Vec<ServicePayment>,
+}
+
+impl ClaimPayment {
+    /// Claim and line adjustments together
+    pub fn all_adjustments(&self) -> impl Iterator<Item = &Adjustment> {
+        self.adjustments
+            .iter()
+            .chain(self.lines.iter().flat_map(|line| line.adjustments.iter()))
+    }
+
+    /// Sum of adjustments not assigned to the patient, in cents
+    pub fn written_off_cents(&self) -> i64 {
+        self.all_adjustments()
+            .filter(|adjustment| adjustment.group != AdjustmentGroup::PatientResponsibility)
+            .map(|adjustment| adjustment.amount_cents)
+            .sum()
+    }
+
+    /// Whether the payer refused the claim: denied outright, or processed
+    /// with nothing paid and nothing left to the patient
+    pub fn is_denied(&self) -> bool {
+        self.status == ClaimPaymentStatus::Denied
+            || (self.status == ClaimPaymentStatus::Processed
+                && self.charge_cents > 0
+                && self.paid_cents == 0
+                && self.patient_responsibility_cents == 0)
+    }
+}
+
+/// One 835 transaction: a payment and the claims it covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Remittance {
+    /// Check or EFT trace number (TRN02)
+    pub trace_number: String,
+    /// Payer name (N1*PR)
+    pub payer_name: String,
+    /// Payer id (N1*PR identifier, or the TRN originator)
+    pub payer_id: String,
+    /// Total payment, in cents (BPR02)
+    pub payment_cents: i64,
+    /// Payment method: CHK, ACH, BOP, FWT or NON (BPR04)
+    pub payment_method: String,
+    /// Date the payment was issued (BPR16)
+    pub paid_on: Option<NaiveDate>,
+    /// Claims paid, denied or adjusted
+    pub claims: Vec<ClaimPayment>,
+}
+
+/// Parse an amount into cents
+fn cents(value: &str) -> Option<i64> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let valid = !whole.is_empty()
+        && fraction.len() <= 2
+        && whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit());
+    if !valid {
+        return None;
+    }
+    let amount = whole.parse::<i64>().ok()?.checked_mul(100)? + format!("{:0<2}", fraction).parse::<i64>().ok()?;
+    Some(if negative { -amount } else { amount })
+}
+
+/// Elements of a segment; missing elements read as empty
+struct Segment<'a> {
+    elements: Vec<&'a str>,
+}
+
+impl<'a> Segment<'a> {
+    fn id(&self) -> &'a str {
+        self.elements[0]
+    }
+
+    fn get(&self, position: usize) -> &'a str {
+        self.elements.get(position).copied().unwrap_or("")
+    }
+
+    fn optional(&self, position: usize) -> Option<String> {
+        Some(self.get(position).trim()).filter(|value| !value.is_empty()).map(str::to_string)
+    }
+
+    fn amount(&self, position: usize) -> Result<i64> {
+        match self.get(position) {
+            "" => Ok(0),
+            value => cents(value.trim()).ok_or_else(|| {
+                Error::validation_error(&format!("{}{:02} '{}' is not an amount", self.id(), position, value))
+            }),
+        }
+    }
+}
+
+/// Adjustments of a CAS segment: a group and up to six reason, amount and
+/// quantity triplets
+fn adjustments(segment: &Segment) -> Result<Vec<Adjustment>> {
+    let group = AdjustmentGroup::parse(segment.get(1))
+        .ok_or_else(|| Error::validation_error(&format!("Unknown adjustment group '{}'", segment.get(1))))?;
+    let mut adjustments = Vec::new();
+    for position in (2..=17).step_by(3) {
+        let reason_code = segment.get(position);
+        if reason_code.is_empty() {
+            continue;
+        }
+        adjustments.push(Adjustment {
+            group,
+            reason_code: reason_code.to_string(),
+            amount_cents: segment.amount(position + 1)?,
+            quantity: segment.get(position + 2).parse().ok(),
+        });
+    }
+    Ok(adjustments)
+}
+
+/// Parse every 835 transaction of an interchange
+pub fn parse_835(text: &str) -> Result<Vec<Remittance>> {
+    let text = text.trim_start();
+    let header: Vec<char> = text.chars().take(106).collect();
+    if header.len() < 106 || !text.starts_with("ISA") {
+        return Err(Error::validation_error("An 835 file starts with a 106-character ISA segment"));
+    }
+    let (element_separator, component_separator, segment_terminator) = (header[3], header[104], header[105]);
+
+    let mut remittances = Vec::new();
+    let mut current: Option<Remittance> = None;
+    // Whether CAS segments belong to the last service line or to the claim
+    let mut in_service_line = false;
+
+    for raw in text.split(segment_terminator) {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        let segment = Segment {
+            elements: raw.split(element_separator).collect(),
+        };
+        match segment.id() {
+            "ST" if segment.get(1) == "835" => {
+                current = Some(Remittance {
+                    trace_number: String::new(),
+                    payer_name: String::new(),
+                    payer_id: String::new(),
+                    payment_cents: 0,
+                    payment_method: String::new(),
+                    paid_on: None,
+                    claims: Vec::new(),
+                });
+            }
+            "ST" => return Err(Error::validation_error(&format!("Transaction set {} is not an 835", segment.get(1)))),
+            "SE" => {
+                let remittance = current
+                    .take()
+                    .ok_or_else(|| Error::validation_error("SE segment without a transaction"))?;
+                remittances.push(remittance);
+            }
+            id => {
+                let Some(remittance) = current.as_mut() else {
+                    continue;
+                };
+                match id {
+                    "BPR" => {
+                        remittance.payment_cents = segment.amount(2)?;
+                        remittance.payment_method = segment.get(4).to_string();
+                        remittance.paid_on = NaiveDate::parse_from_str(segment.get(16), "%Y%m%d").ok();
+                    }
+                    "TRN" => {
+                        remittance.trace_number = segment.get(2).to_string();
+                        if remittance.payer_id.is_empty() {
+                            remittance.payer_id = segment.get(3).to_string();
+                        }
+                    }
+                    "N1" if segment.get(1) == "PR" => {
+                        remittance.payer_name = segment.get(2).to_string();
+                        if let Some(payer_id) = segment.optional(4) {
+                            remittance.payer_id = payer_id;
+                        }
+                    }
+                    "CLP" => {
+                        in_service_line = false;
+                        remittance.claims.push(ClaimPayment {
+                            claim_id: segment.get(1).to_string(),
+                            status_code: segment.get(2).to_string(),
+                            status: ClaimPaymentStatus::from_x12(segment.get(2)),
+                            charge_cents: segment.amount(3)?,
+                            paid_cents: segment.amount(4)?,
+                            patient_responsibility_cents: segment.amount(5)?,
+                            payer_claim_number: segment.optional(7),
+                            adjustments: Vec::new(),
+                            lines: Vec::new(),
+                        });
+                    }
+                    "SVC" => {
+                        let claim = remittance
+                            .claims
+                            .last_mut()
+                            .ok_or_else(|| Error::validation_error("SVC segment outside a claim"))?;
+                        let mut procedure = segment.get(1).split(component_separator).skip(1);
+                        in_service_line = true;
+                        claim.lines.push(ServicePayment {
+                            code: procedure.next().unwrap_or("").to_string(),
+                            modifiers: procedure.map(str::to_string).collect(),
+                            charge_cents: segment.amount(2)?,
+                            paid_cents: segment.amount(3)?,
+                            units: segment.get(5).parse().ok(),
+                            adjustments: Vec::new(),
+                        });
+                    }
+                    "CAS" => {
+                        let claim = remittance
+                            .claims
+                            .last_mut()
+                            .ok_or_else(|| Error::validation_error("CAS segment outside a claim"))?;
+                        let parsed = adjustments(&segment)?;
+                        match claim.lines.last_mut() {
+                            Some(line) if in_service_line => line.adjustments.extend(parsed),
+                            _ => claim.adjustments.extend(parsed),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if current.is_some() {
+        return Err(Error::validation_error("The 835 transaction has no SE segment"));
+    }
+    Ok(remittances)
+}
+
+/// Why a payment needs billing staff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowUpReason {
+    /// The payer denied the claim; correct and resubmit, or appeal
+    Denied,
+    /// The payer took back an earlier payment
+    Reversed,
+    /// The remittance names a claim that was never exported
+    Unmatched,
+    /// The payer received a different charge than was billed
+    ChargeMismatch,
+}
+
+impl FollowUpReason {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FollowUpReason::Denied => "denied",
+            FollowUpReason::Reversed => "reversed",
+            FollowUpReason::Unmatched => "unmatched",
+            FollowUpReason::ChargeMismatch => "charge_mismatch",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "denied" => Some(FollowUpReason::Denied),
+            "reversed" => Some(FollowUpReason::Reversed),
+            "unmatched" => Some(FollowUpReason::Unmatched),
+            "charge_mismatch" => Some(FollowUpReason::ChargeMismatch),
+            _ => None,
+        }
+    }
+}
+
+/// Follow-up task for billing staff (a FHIR `Task`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingTask {
+    /// Task id
+    pub id: Id,
+    /// Remittance the payment was reported in
+    pub remittance_id: Id,
+    /// Patient control number of the claim
+    pub claim_id: String,
+    /// Why the payment needs attention
+    pub reason: FollowUpReason,
+    /// What the payer reported, for the worklist
+    pub detail: String,
+    /// Task status
+    pub status: TaskStatus,
+    /// When the task was opened
+    pub created_at: Timestamp,
+    /// When billing staff closed the task
+    pub completed_at: Option<Timestamp>,
+    /// Who closed it
+    pub completed_by: Option<Id>,
+}
+
+impl BillingTask {
+    /// Close the task once the claim has been worked
+    pub fn complete(&mut self, by: Id, at: Timestamp) -> Result<()> {
+        if self.status == TaskStatus::Completed {
+            return Err(Error::business_rule_violation(
+                "already_completed",
+                "The billing task has already been completed",
+            ));
+        }
+
+        self.status = TaskStatus::Completed;
+        self.completed_at = Some(at);
+        self.completed_by = Some(by);
+        Ok(())
+    }
+}
+
+/// Adjustments as `CO-45 12.50` for task details
+fn describe_adjustments(payment: &ClaimPayment) -> String {
+    let adjustments: Vec<String> = payment
+        .all_adjustments()
+        .map(|adjustment| {
+            format!(
+                "{}-{} {:.2}",
+                adjustment.group.as_str(),
+                adjustment.reason_code,
+                adjustment.amount_cents as f64 / 100.0
+            )
+        })
+        .collect();
+    if adjustments.is_empty() {
+        "no adjustment reasons given".to_string()
+    } else {
+        adjustments.join(", ")
+    }
+}
+
+/// The follow-up a payment needs, given the charge of the exported claim it
+/// pays (`None` when no exported claim has its id)
+pub fn follow_up(
+    payment: &ClaimPayment,
+    billed_charge_cents: Option<i64>,
+    remittance_id: Id,
+    now: Timestamp,
+) -> Option<BillingTask> {
+    let (reason, detail) = match billed_charge_cents {
+        None => (
+            FollowUpReason::Unmatched,
+            format!("No exported claim has control number {}", payment.claim_id),
+        ),
+        Some(_) if payment.status == ClaimPaymentStatus::Reversed => (
+            FollowUpReason::Reversed,
+            format!("Payment of {:.2} reversed", payment.paid_cents as f64 / 100.0),
+        ),
+        Some(_) if payment.is_denied() => {
+            (FollowUpReason::Denied, format!("Denied: {}", describe_adjustments(payment)))
+        }
+        Some(billed) if billed != payment.charge_cents => (
+            FollowUpReason::ChargeMismatch,
+            format!(
+                "Billed {:.2}, payer received {:.2}",
+                billed as f64 / 100.0,
+                payment.charge_cents as f64 / 100.0
+            ),
+        ),
+        Some(_) => return None,
+    };
+
+    Some(BillingTask {
+        id: Uuid::new_v4(),
+        remittance_id,
+        claim_id: payment.claim_id.clone(),
+        reason,
+        detail,
+        status: TaskStatus::Requested,
+        created_at: now,
+        completed_at: None,
+        completed_by: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    const REMITTANCE: &str = "ISA*00*          *00*          *ZZ*PAYER          *ZZ*EMRSENDER      \
+        *260320*1200*^*00501*000000101*0*P*:~\n\
+        GS*HP*PAYER*EMRSENDER*20260320*1200*101*X*005010X221A1~\n\
+        ST*835*0001~\n\
+        BPR*I*80*C*ACH*CCP*01*999999999*DA*123456*1512345678**01*999988880*DA*98765*20260320~\n\
+        TRN*1*EFT12345*1512345678~\n\
+        N1*PR*ACME HEALTH*XV*60054~\n\
+        N1*PE*SPRINGFIELD CLINIC*XX*1234567893~\n\
+        CLP*ABC123*1*125.5*80*20*12*PCN1~\n\
+        SVC*HC:99213:25*125.5*80**1~\n\
+        CAS*CO*45*25.5~\n\
+        CAS*PR*2*20~\n\
+        CLP*DEF456*4*90*0*0*12*PCN2~\n\
+        CAS*CO*50*90~\n\
+        SE*12*0001~\n\
+        GE*1*101~\n\
+        IEA*1*000000101~\n";
+
+    #[test]
+    fn test_parse_835() {
+        let remittances = parse_835(REMITTANCE).unwrap();
+        assert_eq!(remittances.len(), 1);
+        let remittance = &remittances[0];
+        assert_eq!((remittance.trace_number.as_str(), remittance.payer_id.as_str()), ("EFT12345", "60054"));
+        assert_eq!(remittance.payment_cents, 8000);
+        assert_eq!(remittance.paid_on, NaiveDate::from_ymd_opt(2026, 3, 20));
+
+        let paid = &remittance.claims[0];
+        assert_eq!((paid.charge_cents, paid.paid_cents, paid.patient_responsibility_cents), (12550, 8000, 2000));
+        assert_eq!(paid.lines[0].modifiers, vec!["25".to_string()]);
+        assert_eq!(paid.lines[0].adjustments.len(), 2);
+        assert_eq!(paid.written_off_cents(), 2550);
+        assert!(!paid.is_denied());
+
+        let denied = &remittance.claims[1];
+        assert!(denied.is_denied());
+        assert_eq!(denied.adjustments[0].reason_code, "50");
+        assert_eq!(cents("-20.05"), Some(-2005));
+        assert!(parse_835("ST*835*0001~").is_err());
+    }
+
+    #[test]
+    fn test_follow_up() {
+        let remittance = &parse_835(REMITTANCE).unwrap()[0];
+        let (paid, denied) = (&remittance.claims[0], &remittance.claims[1]);
+        let id = Uuid::new_v4();
+
+        assert!(follow_up(paid, Some(12550), id, Utc::now()).is_none());
+        assert_eq!(follow_up(paid, Some(13000), id, Utc::now()).unwrap().reason, FollowUpReason::ChargeMismatch);
+        assert_eq!(follow_up(paid, None, id, Utc::now()).unwrap().reason, FollowUpReason::Unmatched);
+        let mut task = follow_up(denied, Some(9000), id, Utc::now()).unwrap();
+        assert_eq!((task.reason, task.detail.as_str()), (FollowUpReason::Denied, "Denied: CO-50 90.00"));
+        task.complete(Uuid::new_v4(), Utc::now()).unwrap();
+        assert!(task.complete(Uuid::new_v4(), Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,409 @@
+//! Superbills
+//!
+//! A [`Superbill`] is the printable summary of an encounter's captured
+//! charges that the patient or the front desk takes away: the practice, the
+//! patient and their insurance, the diagnoses and the charge lines with
+//! their fees. It renders as HTML for the browser and as PDF for printing
+//! and for patients who submit claims themselves.
+
+use crate::billing::claim::{place_of_service, BillingProvider, ClaimPerson, FeeSchedule, PostalAddress};
+use crate::billing::pdf::{self, PdfLine};
+use crate::domain::values::AdministrativeGender;
+use crate::domain::{ChargeDiagnosis, Coverage, Encounter, EncounterCharges};
+use crate::types::Id;
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// HTML superbill; names ending in `.html` are auto-escaped
+const SUPERBILL_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Superbill {{ date_of_service }}</title>
+<style>
+body { font-family: Helvetica, Arial, sans-serif; font-size: 12px; margin: 32px; }
+h1 { font-size: 20px; margin-bottom: 4px; }
+h2 { font-size: 14px; border-bottom: 1px solid #999; margin-top: 20px; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 4px 6px; border-bottom: 1px solid #ddd; }
+td.amount, th.amount { text-align: right; }
+.signature { margin-top: 48px; }
+@media print { body { margin: 0; } }
+</style>
+</head>
+<body>
+<h1>Superbill</h1>
+{% if provider %}
+<p><strong>{{ provider.name }}</strong><br>{{ provider.address }}<br>
+NPI {{ provider.npi }} &middot; TIN {{ provider.tax_id }}</p>
+{% endif %}
+<h2>Patient</h2>
+<p>{{ patient.name }}<br>Date of birth: {{ patient.birth_date }} &middot; Sex: {{ patient.gender }}
+{% if patient.address %}<br>{{ patient.address }}{% endif %}</p>
+<h2>Insurance</h2>
+{% if coverage %}
+<p>{{ coverage.payer_name }} (payer {{ coverage.payer_id }})<br>Member {{ coverage.member_id }}
+{% if coverage.group_number %} &middot; Group {{ coverage.group_number }}{% endif %}</p>
+{% else %}
+<p>Self-pay</p>
+{% endif %}
+<h2>Encounter</h2>
+<p>Date of service: {{ date_of_service }} &middot; Place of service: {{ place_of_service }}<br>
+Encounter {{ encounter_id }}</p>
+<h2>Diagnoses</h2>
+<table>
+<tr><th>#</th><th>ICD-10-CM</th><th>Description</th></tr>
+{% for diagnosis in diagnoses %}
+<tr><td>{{ diagnosis.sequence }}</td><td>{{ diagnosis.code }}</td><td>{{ diagnosis.display }}</td></tr>
+{% endfor %}
+</table>
+<h2>Services</h2>
+<table>
+<tr><th>CPT</th><th>Modifiers</th><th>Units</th><th>Diagnoses</th><th>Description</th><th class="amount">Fee</th></tr>
+{% for line in lines %}
+<tr><td>{{ line.code }}</td><td>{{ line.modifiers }}</td><td>{{ line.units }}</td><td>{{ line.pointers }}</td>
+<td>{{ line.display }}</td><td class="amount">{{ line.fee }}</td></tr>
+{% endfor %}
+<tr><th colspan="5">Total</th><th class="amount">{{ total }}</th></tr>
+</table>
+<p class="signature">Provider signature ______________________________ Date ______________</p>
+</body>
+</html>
+"#;
+
+/// A charge line as billed on the superbill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuperbillLine {
+    /// CPT code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// CPT modifiers
+    pub modifiers: Vec<String>,
+    /// Units of service
+    pub units: u32,
+    /// Sequences of the diagnoses the service treats
+    pub diagnosis_pointers: Vec<u32>,
+    /// Per-unit charge times units, in cents; `None` when the fee schedule
+    /// has no charge for the code
+    pub fee_cents: Option<u64>,
+}
+
+/// The printable summary of an encounter's charges
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Superbill {
+    /// Encounter billed
+    pub encounter_id: Id,
+    /// Date of service
+    pub date_of_service: NaiveDate,
+    /// CMS place of service code
+    pub place_of_service: String,
+    /// The practice, when configured
+    pub provider: Option<BillingProvider>,
+    /// The patient
+    pub patient: ClaimPerson,
+    /// The patient's primary coverage in force on the date of service
+    pub coverage: Option<Coverage>,
+    /// Diagnoses in sequence
+    pub diagnoses: Vec<ChargeDiagnosis>,
+    /// Charge lines in sequence
+    pub lines: Vec<SuperbillLine>,
+    /// Sum of the known fees, in cents
+    pub total_cents: u64,
+}
+
+impl Superbill {
+    /// A superbill of an encounter's captured charges, priced from the fee
+    /// schedule when there is one
+    pub fn new(
+        encounter: &Encounter,
+        charges: &EncounterCharges,
+        patient: ClaimPerson,
+        coverage: Option<Coverage>,
+        provider: Option<BillingProvider>,
+        fees: Option<&FeeSchedule>,
+    ) -> Self {
+        let date_of_service = encounter
+            .period
+            .as_ref()
+            .and_then(|period| period.start)
+            .unwrap_or(charges.captured_at)
+            .date_naive();
+        let lines: Vec<SuperbillLine> = charges
+            .lines
+            .iter()
+            .map(|line| SuperbillLine {
+                code: line.code.clone(),
+                display: line.display.clone(),
+                modifiers: line.modifiers.clone(),
+                units: line.units,
+                diagnosis_pointers: line.diagnosis_pointers.clone(),
+                fee_cents: fees
+                    .and_then(|fees| fees.charge(&line.code))
+                    .map(|charge| charge * u64::from(line.units)),
+            })
+            .collect();
+
+        Self {
+            encounter_id: encounter.metadata.id,
+            date_of_service,
+            place_of_service: place_of_service(&encounter.class).to_string(),
+            provider,
+            patient,
+            coverage,
+            diagnoses: charges.diagnoses.clone(),
+            total_cents: lines.iter().filter_map(|line| line.fee_cents).sum(),
+            lines,
+        }
+    }
+
+    /// The superbill as an HTML page
+    pub fn render_html(&self) -> Result<String> {
+        let mut env = Environment::new();
+        env.add_template("superbill.html", SUPERBILL_TEMPLATE)
+            .map_err(|e| Error::internal_error(&format!("Invalid superbill template: {}", e)))?;
+        let template = env
+            .get_template("superbill.html")
+            .map_err(|e| Error::internal_error(&format!("Invalid superbill template: {}", e)))?;
+
+        let context = json!({
+            "encounter_id": self.encounter_id.to_string(),
+            "date_of_service": self.date_of_service.to_string(),
+            "place_of_service": self.place_of_service,
+            "provider": self.provider.as_ref().map(|provider| json!({
+                "name": provider.name,
+                "address": address(&provider.address),
+                "npi": provider.npi,
+                "tax_id": provider.tax_id,
+            })),
+            "patient": {
+                "name": person_name(&self.patient),
+                "birth_date": self.patient.birth_date.map(|date| date.to_string()).unwrap_or_default(),
+                "gender": self.patient.gender.as_ref().map(gender_label).unwrap_or("Unknown"),
+                "address": self.patient.address.as_ref().map(address),
+            },
+            "coverage": self.coverage.as_ref().map(|coverage| json!({
+                "payer_name": coverage.payer_name,
+                "payer_id": coverage.payer_id,
+                "member_id": coverage.member_id,
+                "group_number": coverage.group_number,
+            })),
+            "diagnoses": self.diagnoses.iter().map(|diagnosis| json!({
+                "sequence": diagnosis.sequence,
+                "code": diagnosis.code,
+                "display": diagnosis.display.clone().unwrap_or_default(),
+            })).collect::<Vec<_>>(),
+            "lines": self.lines.iter().map(|line| json!({
+                "code": line.code,
+                "modifiers": line.modifiers.join(" "),
+                "units": line.units,
+                "pointers": pointers(line),
+                "display": line.display.clone().unwrap_or_default(),
+                "fee": line.fee_cents.map(dollars).unwrap_or_default(),
+            })).collect::<Vec<_>>(),
+            "total": dollars(self.total_cents),
+        });
+
+        template
+            .render(context)
+            .map_err(|e| Error::internal_error(&format!("Superbill failed to render: {}", e)))
+    }
+
+    /// The superbill as a PDF document
+    pub fn render_pdf(&self) -> Vec<u8> {
+        let mut lines = vec![PdfLine::text(18.0, true, "Superbill"), PdfLine::blank()];
+        if let Some(provider) = &self.provider {
+            lines.push(PdfLine::text(12.0, true, provider.name.clone()));
+            lines.push(PdfLine::text(10.0, false, address(&provider.address)));
+            lines.push(PdfLine::text(10.0, false, format!("NPI {}   TIN {}", provider.npi, provider.tax_id)));
+            lines.push(PdfLine::blank());
+        }
+
+        lines.push(PdfLine::text(12.0, true, "Patient"));
+        lines.push(PdfLine::text(10.0, false, person_name(&self.patient)));
+        lines.push(PdfLine::text(
+            10.0,
+            false,
+            format!(
+                "Date of birth: {}   Sex: {}",
+                self.patient.birth_date.map(|date| date.to_string()).unwrap_or_default(),
+                self.patient.gender.as_ref().map(gender_label).unwrap_or("Unknown")
+            ),
+        ));
+        if let Some(patient_address) = &self.patient.address {
+            lines.push(PdfLine::text(10.0, false, address(patient_address)));
+        }
+        lines.push(PdfLine::blank());
+
+        lines.push(PdfLine::text(12.0, true, "Insurance"));
+        match &self.coverage {
+            Some(coverage) => {
+                let payer = format!("{} (payer {})", coverage.payer_name, coverage.payer_id);
+                lines.push(PdfLine::text(10.0, false, payer));
+                let group = coverage.group_number.as_deref().map(|group| format!("   Group {}", group));
+                lines.push(PdfLine::text(
+                    10.0,
+                    false,
+                    format!("Member {}{}", coverage.member_id, group.unwrap_or_default()),
+                ));
+            }
+            None => lines.push(PdfLine::text(10.0, false, "Self-pay")),
+        }
+        lines.push(PdfLine::blank());
+
+        lines.push(PdfLine::text(12.0, true, "Encounter"));
+        lines.push(PdfLine::text(
+            10.0,
+            false,
+            format!(
+                "Date of service: {}   Place of service: {}",
+                self.date_of_service, self.place_of_service
+            ),
+        ));
+        lines.push(PdfLine::text(10.0, false, format!("Encounter {}", self.encounter_id)));
+        lines.push(PdfLine::blank());
+
+        lines.push(PdfLine::text(12.0, true, "Diagnoses"));
+        for diagnosis in &self.diagnoses {
+            lines.push(PdfLine {
+                size: 10.0,
+                bold: false,
+                cells: vec![
+                    (0.0, diagnosis.sequence.to_string()),
+                    (24.0, diagnosis.code.clone()),
+                    (96.0, diagnosis.display.clone().unwrap_or_default()),
+                ],
+            });
+        }
+        lines.push(PdfLine::blank());
+
+        let columns = [0.0, 56.0, 130.0, 170.0, 230.0, 440.0];
+        let row = |bold: bool, values: [String; 6]| PdfLine {
+            size: 10.0,
+            bold,
+            cells: columns.iter().copied().zip(values).collect(),
+        };
+        lines.push(PdfLine::text(12.0, true, "Services"));
+        lines.push(row(
+            true,
+            ["CPT", "Modifiers", "Units", "Diagnoses", "Description", "Fee"].map(str::to_string),
+        ));
+        for line in &self.lines {
+            lines.push(row(
+                false,
+                [
+                    line.code.clone(),
+                    line.modifiers.join(" "),
+                    line.units.to_string(),
+                    pointers(line),
+                    line.display.clone().unwrap_or_default(),
+                    line.fee_cents.map(dollars).unwrap_or_default(),
+                ],
+            ));
+        }
+        lines.push(row(
+            true,
+            ["Total", "", "", "", "", &dollars(self.total_cents)].map(str::to_string),
+        ));
+        lines.push(PdfLine::blank());
+        lines.push(PdfLine::blank());
+        lines.push(PdfLine::text(10.0, false, "Provider signature ______________________________   Date ____________"));
+
+        pdf::render(&lines)
+    }
+}
+
+/// Cents as dollars, e.g. `125.50`
+fn dollars(cents: u64) -> String {
+    format!("{}.{:02}", cents / 100, cents % 100)
+}
+
+fn person_name(person: &ClaimPerson) -> String {
+    match &person.given_name {
+        Some(given) => format!("{}, {}", person.family_name, given),
+        None => person.family_name.clone(),
+    }
+}
+
+fn gender_label(gender: &AdministrativeGender) -> &'static str {
+    match gender {
+        AdministrativeGender::Male => "Male",
+        AdministrativeGender::Female => "Female",
+        AdministrativeGender::Other => "Other",
+        AdministrativeGender::Unknown => "Unknown",
+    }
+}
+
+fn address(address: &PostalAddress) -> String {
+    let street = match &address.line2 {
+        Some(line2) => format!("{}, {}", address.line1, line2),
+        None => address.line1.clone(),
+    };
+    format!("{}, {}, {} {}", street, address.city, address.state, address.postal_code)
+}
+
+/// Diagnosis pointers as numbers of the diagnosis list
+fn pointers(line: &SuperbillLine) -> String {
+    line.diagnosis_pointers.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{ChargeLine, EncounterClass, EncounterStatus};
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn superbill() -> Superbill {
+        let patient_id = Uuid::new_v4();
+        let encounter = Encounter::new(EncounterStatus::Finished, EncounterClass::Ambulatory, patient_id);
+        let diagnosis = ChargeDiagnosis {
+            sequence: 0,
+            code: "I10".to_string(),
+            display: Some("Essential <primary> hypertension".to_string()),
+            condition_id: "c1".to_string(),
+        };
+        let lines = vec![
+            ChargeLine::new("99213", 1, vec!["25".to_string()], Vec::new()),
+            ChargeLine::new("36415", 2, Vec::new(), Vec::new()),
+        ];
+        let charges = EncounterCharges::new(
+            encounter.metadata.id,
+            patient_id,
+            vec![diagnosis],
+            lines,
+            Uuid::new_v4(),
+            Utc::now(),
+        )
+        .unwrap();
+        let patient = ClaimPerson {
+            family_name: "Smith".to_string(),
+            given_name: Some("John".to_string()),
+            birth_date: NaiveDate::from_ymd_opt(1970, 5, 6),
+            gender: Some(AdministrativeGender::Male),
+            address: None,
+        };
+        let fees = FeeSchedule::new().with_fee("99213", 12550);
+
+        Superbill::new(&encounter, &charges, patient, None, None, Some(&fees))
+    }
+
+    #[test]
+    fn test_superbill() {
+        let superbill = superbill();
+        assert_eq!((superbill.place_of_service.as_str(), superbill.total_cents), ("11", 12550));
+        assert_eq!(superbill.lines[1].fee_cents, None);
+
+        let html = superbill.render_html().unwrap();
+        assert!(html.contains("Essential &lt;primary&gt; hypertension"));
+        assert!(html.contains("<tr><td>99213</td><td>25</td>"));
+        assert!(html.contains("Self-pay"));
+
+        let pdf = String::from_utf8(superbill.render_pdf()).unwrap();
+        assert!(pdf.contains("(Smith, John) Tj"));
+        assert!(pdf.contains("(125.50) Tj"));
+    }
+}
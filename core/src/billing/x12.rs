@@ -0,0 +1,404 @@
+//! X12 837P writer
+//!
+//! Writes assembled claims as one 837 professional (005010X222A1)
+//! interchange: a billing provider loop (2000A) with a subscriber loop
+//! (2000B) per claim, a patient loop (2000C) when the patient is a dependent,
+//! and the claim (2300) with its service lines (2400). Segments end with `~`,
+//! elements are separated by `*`, components by `:` and repetitions by `^`;
+//! those characters are dropped from free text. Values are upper-cased and
+//! trailing empty elements are left out.
+
+use super::claim::{BillingProvider, ClaimPerson, PostalAddress, ProfessionalClaim};
+use crate::domain::values::AdministrativeGender;
+use crate::domain::{PlanType, SubscriberRelationship};
+use crate::types::Timestamp;
+use serde::{Deserialize, Serialize};
+
+const SEGMENT_TERMINATOR: char = '~';
+const ELEMENT_SEPARATOR: char = '*';
+const COMPONENT_SEPARATOR: char = ':';
+const REPETITION_SEPARATOR: char = '^';
+
+/// Implementation guide the files follow
+pub const IMPLEMENTATION_GUIDE: &str = "005010X222A1";
+
+/// Who sends and receives the interchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterchangeSettings {
+    /// Sender id qualifier (ISA05)
+    pub sender_qualifier: String,
+    /// Sender id (ISA06, GS02)
+    pub sender_id: String,
+    /// Receiver id qualifier (ISA07)
+    pub receiver_qualifier: String,
+    /// Receiver id (ISA08, GS03)
+    pub receiver_id: String,
+    /// Submitter name (loop 1000A)
+    pub submitter_name: String,
+    /// Submitter id assigned by the receiver
+    pub submitter_id: String,
+    /// Submitter contact name
+    pub contact_name: String,
+    /// Submitter contact phone, digits only
+    pub contact_phone: String,
+    /// Receiver name (loop 1000B)
+    pub receiver_name: String,
+    /// Whether the file is production (`P`) rather than test (`T`) data
+    pub production: bool,
+}
+
+/// Segments of one interchange
+struct Segments {
+    segments: Vec<String>,
+}
+
+impl Segments {
+    fn new() -> Self {
+        Self { segments: Vec::new() }
+    }
+
+    /// Add a segment, leaving out trailing empty elements
+    fn push(&mut self, id: &str, elements: &[&str]) {
+        let used = elements.iter().rposition(|element| !element.is_empty()).map_or(0, |last| last + 1);
+        let mut segment = id.to_string();
+        for element in &elements[..used] {
+            segment.push(ELEMENT_SEPARATOR);
+            segment.push_str(element);
+        }
+        self.segments.push(segment);
+    }
+
+    fn len(&self) -> usize {
+        self.segments.len()
+    }
+}
+
+/// Free text as an element value: upper-cased, without delimiters
+fn text(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| ![SEGMENT_TERMINATOR, ELEMENT_SEPARATOR, COMPONENT_SEPARATOR, REPETITION_SEPARATOR].contains(c))
+        .collect::<String>()
+        .trim()
+        .to_uppercase()
+}
+
+/// An amount in cents as dollars, without trailing zeros
+fn amount(cents: u64) -> String {
+    match cents % 100 {
+        0 => format!("{}", cents / 100),
+        remainder if remainder % 10 == 0 => format!("{}.{}", cents / 100, remainder / 10),
+        remainder => format!("{}.{:02}", cents / 100, remainder),
+    }
+}
+
+/// Claim filing indicator (SBR09) of a plan type
+fn filing_indicator(plan_type: PlanType) -> &'static str {
+    match plan_type {
+        PlanType::Commercial => "CI",
+        PlanType::Medicare => "MB",
+        PlanType::Medicaid => "MC",
+        PlanType::Other => "ZZ",
+    }
+}
+
+/// Individual relationship code (SBR02, PAT01) of the patient
+fn relationship_code(relationship: SubscriberRelationship) -> &'static str {
+    match relationship {
+        SubscriberRelationship::Own => "18",
+        SubscriberRelationship::Spouse => "01",
+        SubscriberRelationship::Child => "19",
+        SubscriberRelationship::Other => "G8",
+    }
+}
+
+fn gender_code(gender: Option<&AdministrativeGender>) -> &'static str {
+    match gender {
+        Some(AdministrativeGender::Male) => "M",
+        Some(AdministrativeGender::Female) => "F",
+        _ => "U",
+    }
+}
+
+fn push_address(segments: &mut Segments, address: &PostalAddress) {
+    let line2 = address.line2.as_deref().map(text).unwrap_or_default();
+    segments.push("N3", &[&text(&address.line1), &line2]);
+    segments.push(
+        "N4",
+        &[&text(&address.city), &text(&address.state), &text(&address.postal_code)],
+    );
+}
+
+/// NM1 of an organization
+fn push_organization(segments: &mut Segments, entity: &str, name: &str, qualifier: &str, id: &str) {
+    segments.push("NM1", &[entity, "2", &text(name), "", "", "", "", qualifier, &text(id)]);
+}
+
+/// NM1, N3, N4 and DMG of a subscriber or patient
+fn push_person(segments: &mut Segments, entity: &str, person: &ClaimPerson, member_id: Option<&str>) {
+    let given = person.given_name.as_deref().map(text).unwrap_or_default();
+    let (qualifier, id) = match member_id {
+        Some(member_id) => ("MI", text(member_id)),
+        None => ("", String::new()),
+    };
+    segments.push(
+        "NM1",
+        &[entity, "1", &text(&person.family_name), &given, "", "", "", qualifier, &id],
+    );
+    if let Some(address) = &person.address {
+        push_address(segments, address);
+    }
+    if let Some(birth_date) = person.birth_date {
+        let birth_date = birth_date.format("%Y%m%d").to_string();
+        segments.push("DMG", &["D8", &birth_date, gender_code(person.gender.as_ref())]);
+    }
+}
+
+/// Write `claims` as one 837P interchange numbered `control_number`
+pub fn write_837p(
+    settings: &InterchangeSettings,
+    provider: &BillingProvider,
+    claims: &[ProfessionalClaim],
+    control_number: u32,
+    at: Timestamp,
+) -> String {
+    let control = format!("{:09}", control_number);
+    let date = at.format("%Y%m%d").to_string();
+    let time = at.format("%H%M").to_string();
+    let mut envelope = Segments::new();
+    envelope.push(
+        "ISA",
+        &[
+            "00",
+            &" ".repeat(10),
+            "00",
+            &" ".repeat(10),
+            &format!("{:<2}", text(&settings.sender_qualifier)),
+            &format!("{:<15}", text(&settings.sender_id)),
+            &format!("{:<2}", text(&settings.receiver_qualifier)),
+            &format!("{:<15}", text(&settings.receiver_id)),
+            &at.format("%y%m%d").to_string(),
+            &time,
+            &REPETITION_SEPARATOR.to_string(),
+            "00501",
+            &control,
+            "0",
+            if settings.production { "P" } else { "T" },
+            &COMPONENT_SEPARATOR.to_string(),
+        ],
+    );
+    envelope.push(
+        "GS",
+        &[
+            "HC",
+            &text(&settings.sender_id),
+            &text(&settings.receiver_id),
+            &date,
+            &time,
+            &control_number.to_string(),
+            "X",
+            IMPLEMENTATION_GUIDE,
+        ],
+    );
+
+    let mut transaction = Segments::new();
+    let set_control = format!("{:04}", control_number % 10_000);
+    transaction.push("ST", &["837", &set_control, IMPLEMENTATION_GUIDE]);
+    transaction.push("BHT", &["0019", "00", &control, &date, &time, "CH"]);
+    push_organization(&mut transaction, "41", &settings.submitter_name, "46", &settings.submitter_id);
+    transaction.push("PER", &["IC", &text(&settings.contact_name), "TE", &text(&settings.contact_phone)]);
+    push_organization(&mut transaction, "40", &settings.receiver_name, "46", &settings.receiver_id);
+
+    transaction.push("HL", &["1", "", "20", "1"]);
+    if let Some(taxonomy) = &provider.taxonomy {
+        transaction.push("PRV", &["BI", "PXC", &text(taxonomy)]);
+    }
+    push_organization(&mut transaction, "85", &provider.name, "XX", &provider.npi);
+    push_address(&mut transaction, &provider.address);
+    transaction.push("REF", &["EI", &provider.tax_id.replace('-', "")]);
+
+    let mut hl = 1;
+    for claim in claims {
+        hl += 1;
+        let subscriber_hl = hl;
+        let dependent = claim.relationship != SubscriberRelationship::Own;
+        let group_number = claim.group_number.as_deref().map(text).unwrap_or_default();
+        transaction.push("HL", &[&subscriber_hl.to_string(), "1", "22", if dependent { "1" } else { "0" }]);
+        transaction.push(
+            "SBR",
+            &[
+                "P",
+                if dependent { "" } else { relationship_code(claim.relationship) },
+                &group_number,
+                "",
+                "",
+                "",
+                "",
+                "",
+                filing_indicator(claim.plan_type),
+            ],
+        );
+        push_person(&mut transaction, "IL", &claim.subscriber, Some(&claim.member_id));
+        push_organization(&mut transaction, "PR", &claim.payer_name, "PI", &claim.payer_id);
+        if dependent {
+            hl += 1;
+            transaction.push("HL", &[&hl.to_string(), &subscriber_hl.to_string(), "23", "0"]);
+            transaction.push("PAT", &[relationship_code(claim.relationship)]);
+            push_person(&mut transaction, "QC", &claim.patient, None);
+        }
+
+        let facility = format!("{}{}B{}1", claim.place_of_service, COMPONENT_SEPARATOR, COMPONENT_SEPARATOR);
+        transaction.push(
+            "CLM",
+            &[&text(&claim.claim_id), &amount(claim.total_charge_cents), "", "", &facility, "Y", "A", "Y", "Y"],
+        );
+        let diagnoses: Vec<String> = claim
+            .diagnoses
+            .iter()
+            .enumerate()
+            .map(|(index, code)| {
+                let qualifier = if index == 0 { "ABK" } else { "ABF" };
+                format!("{}{}{}", qualifier, COMPONENT_SEPARATOR, text(code))
+            })
+            .collect();
+        let diagnoses: Vec<&str> = diagnoses.iter().map(String::as_str).collect();
+        transaction.push("HI", &diagnoses);
+        if let Some(rendering) = &claim.rendering_provider {
+            let given = rendering.given_name.as_deref().map(text).unwrap_or_default();
+            transaction.push(
+                "NM1",
+                &["82", "1", &text(&rendering.family_name), &given, "", "", "", "XX", &text(&rendering.npi)],
+            );
+        }
+
+        for line in &claim.lines {
+            let mut procedure = format!("HC{}{}", COMPONENT_SEPARATOR, text(&line.code));
+            for modifier in line.modifiers.iter().take(4) {
+                procedure.push(COMPONENT_SEPARATOR);
+                procedure.push_str(&text(modifier));
+            }
+            let pointers = line
+                .diagnosis_pointers
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(&COMPONENT_SEPARATOR.to_string());
+            transaction.push("LX", &[&line.line_number.to_string()]);
+            transaction.push(
+                "SV1",
+                &[&procedure, &amount(line.charge_cents), "UN", &line.units.to_string(), "", "", &pointers],
+            );
+            transaction.push("DTP", &["472", "D8", &line.service_date.format("%Y%m%d").to_string()]);
+        }
+    }
+    let segment_count = transaction.len() + 1;
+    transaction.push("SE", &[&segment_count.to_string(), &set_control]);
+
+    let mut trailer = Segments::new();
+    trailer.push("GE", &["1", &control_number.to_string()]);
+    trailer.push("IEA", &["1", &control]);
+
+    envelope
+        .segments
+        .into_iter()
+        .chain(transaction.segments)
+        .chain(trailer.segments)
+        .map(|segment| format!("{}{}\n", segment, SEGMENT_TERMINATOR))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::claim::{ClaimLine, RenderingProvider};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use uuid::Uuid;
+
+    fn person(family_name: &str, birth_year: i32) -> ClaimPerson {
+        ClaimPerson {
+            family_name: family_name.to_string(),
+            given_name: Some("Sam".to_string()),
+            birth_date: NaiveDate::from_ymd_opt(birth_year, 1, 2),
+            gender: Some(AdministrativeGender::Female),
+            address: Some(PostalAddress {
+                line1: "1 Main St*Apt 2".to_string(),
+                line2: None,
+                city: "Springfield".to_string(),
+                state: "IL".to_string(),
+                postal_code: "62701".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_write_837p() {
+        let settings = InterchangeSettings {
+            sender_qualifier: "ZZ".to_string(),
+            sender_id: "EMRSENDER".to_string(),
+            receiver_qualifier: "ZZ".to_string(),
+            receiver_id: "CLEARINGHOUSE".to_string(),
+            submitter_name: "Springfield Clinic".to_string(),
+            submitter_id: "S12345".to_string(),
+            contact_name: "Billing Office".to_string(),
+            contact_phone: "2175550100".to_string(),
+            receiver_name: "Clearinghouse".to_string(),
+            production: false,
+        };
+        let provider = BillingProvider {
+            name: "Springfield Clinic".to_string(),
+            npi: "1234567893".to_string(),
+            tax_id: "12-3456789".to_string(),
+            taxonomy: Some("207Q00000X".to_string()),
+            address: person("Clinic", 2000).address.unwrap(),
+        };
+        let service_date = NaiveDate::from_ymd_opt(2026, 3, 4).unwrap();
+        let claim = ProfessionalClaim {
+            claim_id: "ABC123".to_string(),
+            encounter_id: Uuid::new_v4(),
+            place_of_service: "11".to_string(),
+            service_date,
+            diagnoses: vec!["E119".to_string(), "I10".to_string()],
+            lines: vec![ClaimLine {
+                line_number: 1,
+                code: "99213".to_string(),
+                modifiers: vec!["25".to_string()],
+                units: 1,
+                charge_cents: 12550,
+                diagnosis_pointers: vec![1, 2],
+                service_date,
+            }],
+            total_charge_cents: 12550,
+            payer_name: "Acme Health".to_string(),
+            payer_id: "60054".to_string(),
+            plan_type: PlanType::Commercial,
+            member_id: "W123456789".to_string(),
+            group_number: None,
+            relationship: SubscriberRelationship::Child,
+            subscriber: person("Doe", 1980),
+            patient: person("Doe", 2015),
+            rendering_provider: Some(RenderingProvider {
+                npi: "1234567893".to_string(),
+                family_name: "Jones".to_string(),
+                given_name: None,
+            }),
+        };
+
+        let file = write_837p(&settings, &provider, &[claim], 42, Utc.with_ymd_and_hms(2026, 3, 5, 9, 30, 0).unwrap());
+        let segments: Vec<&str> = file.lines().map(|line| line.trim_end_matches('~')).collect();
+        assert_eq!(segments[0].len(), 105);
+        assert!(segments[0].ends_with("*000000042*0*T*:"));
+        assert!(segments.contains(&"SBR*P********CI"));
+        assert!(segments.contains(&"HL*3*2*23*0"));
+        assert!(segments.contains(&"PAT*19"));
+        assert!(segments.contains(&"N3*1 MAIN STAPT 2"));
+        assert!(segments.contains(&"CLM*ABC123*125.5***11:B:1*Y*A*Y*Y"));
+        assert!(segments.contains(&"HI*ABK:E119*ABF:I10"));
+        assert!(segments.contains(&"SV1*HC:99213:25*125.5*UN*1***1:2"));
+        assert!(segments.contains(&"REF*EI*123456789"));
+
+        let st = segments.iter().position(|segment| segment.starts_with("ST*")).unwrap();
+        let se = segments.iter().position(|segment| segment.starts_with("SE*")).unwrap();
+        assert_eq!(segments[se], format!("SE*{}*0042", se - st + 1));
+        assert_eq!(segments.last(), Some(&"IEA*1*000000042"));
+    }
+}
@@ -0,0 +1,193 @@
+//! Result acknowledgment tasks
+//!
+//! Every abnormal result of an order opens an [`AcknowledgmentTask`] (a FHIR
+//! `Task`) for the practitioner who placed the order. The task stays open
+//! until someone acknowledges the result. Critical results that are not
+//! acknowledged within the SLA are escalated by the jobs worker, once per
+//! SLA period. The compliance report measures the time from result to
+//! acknowledgment.
+
+use crate::domain::observation::{Observation, ResultFlag};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Minutes a critical result may wait for acknowledgment before escalation
+pub const DEFAULT_CRITICAL_ACK_SLA_MINUTES: i64 = 60;
+
+/// Task status (subset of FHIR `Task.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    /// Waiting for acknowledgment
+    Requested,
+    /// Acknowledged
+    Completed,
+}
+
+impl TaskStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStatus::Requested => "requested",
+            TaskStatus::Completed => "completed",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "requested" => Some(TaskStatus::Requested),
+            "completed" => Some(TaskStatus::Completed),
+            _ => None,
+        }
+    }
+}
+
+/// Task asking the ordering practitioner to acknowledge an abnormal result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcknowledgmentTask {
+    /// Task id
+    pub id: Id,
+    /// Result to acknowledge
+    pub observation_id: Id,
+    /// Patient the result is about
+    pub patient_id: Id,
+    /// Order the result fulfils
+    pub order_id: Option<Id>,
+    /// Practitioner who must acknowledge (the orderer)
+    pub owner_id: Id,
+    /// Test name shown in notifications
+    pub test_name: String,
+    /// Whether the result is critical
+    pub critical: bool,
+    /// Task status
+    pub status: TaskStatus,
+    /// When the task was opened
+    pub created_at: Timestamp,
+    /// When the result was acknowledged
+    pub acknowledged_at: Option<Timestamp>,
+    /// Who acknowledged it, the owner or someone covering
+    pub acknowledged_by: Option<Id>,
+    /// Follow-up noted on acknowledgment
+    pub note: Option<String>,
+    /// Escalations sent so far
+    pub escalation_count: u32,
+    /// When the last escalation was sent
+    pub last_escalated_at: Option<Timestamp>,
+}
+
+impl AcknowledgmentTask {
+    /// Task for an abnormal result, or `None` for a normal one
+    pub fn for_result(
+        observation: &Observation,
+        owner_id: Id,
+        order_id: Option<Id>,
+        test_name: &str,
+        now: Timestamp,
+    ) -> Option<Self> {
+        let flag = observation.result_flag();
+        if flag == ResultFlag::Normal {
+            return None;
+        }
+
+        Some(Self {
+            id: Uuid::new_v4(),
+            observation_id: observation.metadata.id,
+            patient_id: observation.subject,
+            order_id,
+            owner_id,
+            test_name: test_name.to_string(),
+            critical: flag == ResultFlag::Critical,
+            status: TaskStatus::Requested,
+            created_at: now,
+            acknowledged_at: None,
+            acknowledged_by: None,
+            note: None,
+            escalation_count: 0,
+            last_escalated_at: None,
+        })
+    }
+
+    /// Acknowledge the result
+    pub fn acknowledge(&mut self, by: Id, note: Option<String>, at: Timestamp) -> Result<()> {
+        if self.status == TaskStatus::Completed {
+            return Err(Error::business_rule_violation(
+                "already_acknowledged",
+                "The result has already been acknowledged",
+            ));
+        }
+
+        self.status = TaskStatus::Completed;
+        self.acknowledged_at = Some(at);
+        self.acknowledged_by = Some(by);
+        self.note = note.filter(|note| !note.trim().is_empty());
+        Ok(())
+    }
+
+    /// Time from result to acknowledgment
+    pub fn latency(&self) -> Option<Duration> {
+        self.acknowledged_at.map(|at| at - self.created_at)
+    }
+
+    /// Whether an unacknowledged critical result is due for (another) escalation
+    pub fn needs_escalation(&self, sla: Duration, now: Timestamp) -> bool {
+        self.critical
+            && self.status == TaskStatus::Requested
+            && now - self.last_escalated_at.unwrap_or(self.created_at) >= sla
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::observation::{ObservationStatus, ObservationValue};
+    use chrono::Utc;
+
+    fn result(interpretation: &str) -> Observation {
+        let mut observation = Observation::new(ObservationStatus::Final, "2823-3".to_string(), Uuid::new_v4());
+        observation.value = Some(ObservationValue::Quantity {
+            value: 6.8,
+            unit: "mmol/L".to_string(),
+            system: None,
+            code: None,
+        });
+        observation.interpretation.push(interpretation.to_string());
+        observation
+    }
+
+    #[test]
+    fn test_tasks_only_for_abnormal_results() {
+        let owner = Uuid::new_v4();
+        assert!(AcknowledgmentTask::for_result(&result("N"), owner, None, "Potassium", Utc::now()).is_none());
+
+        let task = AcknowledgmentTask::for_result(&result("H"), owner, None, "Potassium", Utc::now()).unwrap();
+        assert!(!task.critical);
+        assert_eq!(task.status, TaskStatus::Requested);
+
+        let task = AcknowledgmentTask::for_result(&result("HH"), owner, None, "Potassium", Utc::now()).unwrap();
+        assert!(task.critical);
+    }
+
+    #[test]
+    fn test_escalation_and_acknowledgment() {
+        let opened = Utc::now() - Duration::minutes(90);
+        let mut task =
+            AcknowledgmentTask::for_result(&result("LL"), Uuid::new_v4(), None, "Potassium", opened).unwrap();
+        let sla = Duration::minutes(DEFAULT_CRITICAL_ACK_SLA_MINUTES);
+
+        assert!(task.needs_escalation(sla, Utc::now()));
+        task.escalation_count = 1;
+        task.last_escalated_at = Some(Utc::now() - Duration::minutes(10));
+        assert!(!task.needs_escalation(sla, Utc::now()), "escalated within the last SLA period");
+
+        let covering = Uuid::new_v4();
+        task.acknowledge(covering, Some("Repeat K+ ordered".to_string()), Utc::now()).unwrap();
+        assert_eq!(task.acknowledged_by, Some(covering));
+        assert!(task.latency().unwrap() >= Duration::minutes(90));
+        assert!(!task.needs_escalation(sla, Utc::now() + Duration::days(1)));
+        assert!(task.acknowledge(covering, None, Utc::now()).is_err());
+    }
+}
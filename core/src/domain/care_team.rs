@@ -0,0 +1,348 @@
+//! Care teams and emergency access
+//!
+//! A [`CareTeam`] links a patient to the practitioners caring for them, each
+//! with a role and a period of participation. Being an active participant of
+//! an active team is the patient's "treating relationship", which gates
+//! access to their record. A practitioner without one can still open the
+//! record in an emergency by "breaking the glass": an [`EmergencyAccess`]
+//! grant records their reason and expires after [`EMERGENCY_ACCESS_HOURS`].
+
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Hours an emergency access grant lasts
+pub const EMERGENCY_ACCESS_HOURS: i64 = 4;
+
+/// Longest accepted emergency access reason, in characters
+pub const MAX_EMERGENCY_REASON_CHARS: usize = 1000;
+
+/// Role of a care team participant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CareTeamRole {
+    /// The patient's primary care physician
+    PrimaryCarePhysician,
+    /// Physician responsible for the current episode
+    AttendingPhysician,
+    /// Physician consulted on the patient
+    ConsultingPhysician,
+    /// Nurse
+    Nurse,
+    /// Coordinates care across the team
+    CareCoordinator,
+}
+
+impl CareTeamRole {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CareTeamRole::PrimaryCarePhysician => "primary_care_physician",
+            CareTeamRole::AttendingPhysician => "attending_physician",
+            CareTeamRole::ConsultingPhysician => "consulting_physician",
+            CareTeamRole::Nurse => "nurse",
+            CareTeamRole::CareCoordinator => "care_coordinator",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "primary_care_physician" => Some(CareTeamRole::PrimaryCarePhysician),
+            "attending_physician" => Some(CareTeamRole::AttendingPhysician),
+            "consulting_physician" => Some(CareTeamRole::ConsultingPhysician),
+            "nurse" => Some(CareTeamRole::Nurse),
+            "care_coordinator" => Some(CareTeamRole::CareCoordinator),
+            _ => None,
+        }
+    }
+
+    /// SNOMED CT code and display of the role
+    pub fn snomed(&self) -> (&'static str, &'static str) {
+        match self {
+            CareTeamRole::PrimaryCarePhysician => ("446050000", "Primary care physician"),
+            CareTeamRole::AttendingPhysician => ("405279007", "Attending physician"),
+            CareTeamRole::ConsultingPhysician => ("158967008", "Consultant physician"),
+            CareTeamRole::Nurse => ("224535009", "Registered nurse"),
+            CareTeamRole::CareCoordinator => ("768730001", "Care coordinator"),
+        }
+    }
+}
+
+/// Care team status (subset of FHIR `CareTeam.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CareTeamStatus {
+    /// Currently caring for the patient
+    Active,
+    /// No longer caring for the patient
+    Inactive,
+}
+
+impl CareTeamStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CareTeamStatus::Active => "active",
+            CareTeamStatus::Inactive => "inactive",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(CareTeamStatus::Active),
+            "inactive" => Some(CareTeamStatus::Inactive),
+            _ => None,
+        }
+    }
+}
+
+/// A practitioner's participation in a care team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CareTeamParticipant {
+    /// Participating practitioner
+    pub practitioner_id: Id,
+    /// Role on the team
+    pub role: CareTeamRole,
+    /// When participation starts
+    pub start: Timestamp,
+    /// When participation ends; open-ended when absent
+    pub end: Option<Timestamp>,
+}
+
+impl CareTeamParticipant {
+    /// Whether the participation covers `at`
+    pub fn is_active_at(&self, at: Timestamp) -> bool {
+        self.start <= at && self.end.map_or(true, |end| at < end)
+    }
+
+    /// Whether the participation overlaps another period
+    fn overlaps(&self, start: Timestamp, end: Option<Timestamp>) -> bool {
+        let starts_before_other_ends = end.map_or(true, |end| self.start < end);
+        let ends_after_other_starts = self.end.map_or(true, |own_end| start < own_end);
+        starts_before_other_ends && ends_after_other_starts
+    }
+}
+
+/// Practitioners caring for a patient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CareTeam {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Patient the team cares for
+    pub patient_id: Id,
+    /// Team name, e.g. "Cardiology inpatient team"
+    pub name: String,
+    /// Team status
+    pub status: CareTeamStatus,
+    /// Current and past participants
+    pub participants: Vec<CareTeamParticipant>,
+}
+
+impl CareTeam {
+    /// New active team without participants
+    pub fn new(patient_id: Id, name: &str) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            patient_id,
+            name: name.trim().to_string(),
+            status: CareTeamStatus::Active,
+            participants: Vec::new(),
+        }
+    }
+
+    /// Add a practitioner in a role for a period
+    ///
+    /// A practitioner cannot hold the same role twice in overlapping periods.
+    pub fn add_participant(
+        &mut self,
+        practitioner_id: Id,
+        role: CareTeamRole,
+        start: Timestamp,
+        end: Option<Timestamp>,
+    ) -> Result<()> {
+        if end.is_some_and(|end| end <= start) {
+            return Err(Error::validation_error_with_field(
+                "Participation must end after it starts",
+                "end",
+            ));
+        }
+        let duplicate = self.participants.iter().any(|participant| {
+            participant.practitioner_id == practitioner_id && participant.role == role && participant.overlaps(start, end)
+        });
+        if duplicate {
+            return Err(Error::business_rule_violation(
+                "duplicate_care_team_participant",
+                &format!("The practitioner is already a {} on this team for that period", role.as_str()),
+            ));
+        }
+
+        self.participants.push(CareTeamParticipant {
+            practitioner_id,
+            role,
+            start,
+            end,
+        });
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// End a practitioner's current participation in a role
+    pub fn end_participation(&mut self, practitioner_id: Id, role: CareTeamRole, at: Timestamp) -> Result<()> {
+        let participant = self
+            .participants
+            .iter_mut()
+            .find(|participant| {
+                participant.practitioner_id == practitioner_id && participant.role == role && participant.is_active_at(at)
+            })
+            .ok_or_else(|| {
+                Error::business_rule_violation(
+                    "not_a_care_team_participant",
+                    &format!("The practitioner is not a current {} on this team", role.as_str()),
+                )
+            })?;
+
+        participant.end = Some(at);
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Participants active at `at`
+    pub fn active_participants(&self, at: Timestamp) -> impl Iterator<Item = &CareTeamParticipant> {
+        self.participants.iter().filter(move |participant| participant.is_active_at(at))
+    }
+
+    /// Whether the practitioner is treating the patient through this team at `at`
+    pub fn is_treating(&self, practitioner_id: Id, at: Timestamp) -> bool {
+        self.status == CareTeamStatus::Active
+            && self
+                .active_participants(at)
+                .any(|participant| participant.practitioner_id == practitioner_id)
+    }
+}
+
+impl Validatable for CareTeam {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(Error::validation_error_with_field("Care teams need a name", "name"));
+        }
+        Ok(())
+    }
+}
+
+/// Emergency ("break-the-glass") access to a patient's record by a
+/// practitioner without a treating relationship
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergencyAccess {
+    /// Grant id
+    pub id: Id,
+    /// Practitioner granted access
+    pub practitioner_id: Id,
+    /// Patient whose record is opened
+    pub patient_id: Id,
+    /// Why access is needed, reviewed afterwards
+    pub reason: String,
+    /// When the glass was broken
+    pub granted_at: Timestamp,
+    /// When the grant lapses
+    pub expires_at: Timestamp,
+}
+
+impl EmergencyAccess {
+    /// Grant access for [`EMERGENCY_ACCESS_HOURS`]; a reason is required
+    pub fn grant(practitioner_id: Id, patient_id: Id, reason: &str, now: Timestamp) -> Result<Self> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(Error::validation_error_with_field(
+                "Emergency access needs a reason",
+                "reason",
+            ));
+        }
+        if reason.chars().count() > MAX_EMERGENCY_REASON_CHARS {
+            return Err(Error::validation_error_with_field(
+                &format!("Reasons are limited to {} characters", MAX_EMERGENCY_REASON_CHARS),
+                "reason",
+            ));
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            practitioner_id,
+            patient_id,
+            reason: reason.to_string(),
+            granted_at: now,
+            expires_at: now + Duration::hours(EMERGENCY_ACCESS_HOURS),
+        })
+    }
+
+    /// Whether the grant still allows access
+    pub fn is_active(&self) -> bool {
+        self.is_active_at(Utc::now())
+    }
+
+    /// Whether the grant allows access at `at`
+    pub fn is_active_at(&self, at: Timestamp) -> bool {
+        self.granted_at <= at && at < self.expires_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_treating_relationship_follows_participation_periods() {
+        let now = Utc::now();
+        let (nurse, attending) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut team = CareTeam::new(Uuid::new_v4(), "Ward 4 team");
+        team.add_participant(nurse, CareTeamRole::Nurse, now - Duration::days(2), None).unwrap();
+        team.add_participant(attending, CareTeamRole::AttendingPhysician, now + Duration::days(1), None)
+            .unwrap();
+
+        assert!(team.is_treating(nurse, now));
+        assert!(!team.is_treating(attending, now), "participation has not started");
+        assert!(!team.is_treating(Uuid::new_v4(), now));
+
+        team.end_participation(nurse, CareTeamRole::Nurse, now).unwrap();
+        assert!(!team.is_treating(nurse, now));
+        assert!(team.is_treating(nurse, now - Duration::hours(1)));
+
+        team.add_participant(nurse, CareTeamRole::Nurse, now, None).unwrap();
+        team.status = CareTeamStatus::Inactive;
+        assert!(!team.is_treating(nurse, now));
+    }
+
+    #[test]
+    fn test_participant_periods_are_checked() {
+        let now = Utc::now();
+        let practitioner = Uuid::new_v4();
+        let mut team = CareTeam::new(Uuid::new_v4(), "Primary care");
+        team.add_participant(practitioner, CareTeamRole::PrimaryCarePhysician, now, None)
+            .unwrap();
+
+        assert!(team
+            .add_participant(practitioner, CareTeamRole::PrimaryCarePhysician, now + Duration::days(30), None)
+            .is_err());
+        assert!(team
+            .add_participant(practitioner, CareTeamRole::CareCoordinator, now, Some(now - Duration::days(1)))
+            .is_err());
+        assert!(team
+            .end_participation(practitioner, CareTeamRole::Nurse, now)
+            .is_err());
+        assert_eq!(CareTeamRole::parse("care_coordinator"), Some(CareTeamRole::CareCoordinator));
+    }
+
+    #[test]
+    fn test_emergency_access() {
+        let now = Utc::now();
+        assert!(EmergencyAccess::grant(Uuid::new_v4(), Uuid::new_v4(), "  ", now).is_err());
+
+        let grant = EmergencyAccess::grant(Uuid::new_v4(), Uuid::new_v4(), "Unresponsive in ED", now).unwrap();
+        assert!(grant.is_active_at(now + Duration::hours(1)));
+        assert!(!grant.is_active_at(now + Duration::hours(EMERGENCY_ACCESS_HOURS)));
+    }
+}
@@ -0,0 +1,220 @@
+//! Charge capture
+//!
+//! The clinician records what was done at an encounter as
+//! [`EncounterCharges`]: the diagnoses addressed, each linked to the
+//! recorded `Condition` it codes, and the CPT charge lines, each pointing at
+//! the diagnoses it treats. Captured charges are what the superbill prints
+//! and what the coder reviews before the encounter's codes are finalized.
+
+use crate::domain::traits::Validatable;
+use crate::domain::{CodeKind, MAX_CLAIM_DIAGNOSES, MAX_DIAGNOSIS_POINTERS};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// A diagnosis charges point at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChargeDiagnosis {
+    /// Position among the encounter's charge diagnoses, from 1
+    pub sequence: u32,
+    /// ICD-10-CM code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// FHIR `Condition` the code was taken from
+    pub condition_id: String,
+}
+
+/// A captured charge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChargeLine {
+    /// Position among the encounter's charge lines, from 1
+    pub sequence: u32,
+    /// CPT code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// Units of service
+    pub units: u32,
+    /// CPT modifiers
+    pub modifiers: Vec<String>,
+    /// Sequences of the diagnoses the service treats
+    pub diagnosis_pointers: Vec<u32>,
+}
+
+impl ChargeLine {
+    /// A charge as the clinician entered it, before it is sequenced
+    pub fn new(code: &str, units: u32, modifiers: Vec<String>, diagnosis_pointers: Vec<u32>) -> Self {
+        Self {
+            sequence: 0,
+            code: code.trim().to_uppercase(),
+            display: None,
+            units,
+            modifiers: modifiers.iter().map(|modifier| modifier.trim().to_uppercase()).collect(),
+            diagnosis_pointers,
+        }
+    }
+}
+
+/// The charges captured for an encounter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncounterCharges {
+    /// Encounter charged
+    pub encounter_id: Id,
+    /// The encounter's patient
+    pub patient_id: Id,
+    /// Diagnoses in sequence, the principal one first
+    pub diagnoses: Vec<ChargeDiagnosis>,
+    /// Charge lines in sequence
+    pub lines: Vec<ChargeLine>,
+    /// Who captured the charges
+    pub captured_by: Id,
+    /// When the charges were captured
+    pub captured_at: Timestamp,
+}
+
+impl EncounterCharges {
+    /// Capture charges in the order given; a line without diagnosis pointers
+    /// points at the principal diagnosis
+    pub fn new(
+        encounter_id: Id,
+        patient_id: Id,
+        mut diagnoses: Vec<ChargeDiagnosis>,
+        mut lines: Vec<ChargeLine>,
+        captured_by: Id,
+        at: Timestamp,
+    ) -> Result<Self> {
+        for (index, diagnosis) in diagnoses.iter_mut().enumerate() {
+            diagnosis.sequence = index as u32 + 1;
+        }
+        for (index, line) in lines.iter_mut().enumerate() {
+            line.sequence = index as u32 + 1;
+            if line.diagnosis_pointers.is_empty() {
+                line.diagnosis_pointers.push(1);
+            }
+        }
+
+        let charges = Self {
+            encounter_id,
+            patient_id,
+            diagnoses,
+            lines,
+            captured_by,
+            captured_at: at,
+        };
+        charges.validate()?;
+        Ok(charges)
+    }
+
+    /// The diagnoses a line points at, in pointer order
+    pub fn diagnoses_of<'a>(&'a self, line: &'a ChargeLine) -> impl Iterator<Item = &'a ChargeDiagnosis> {
+        line.diagnosis_pointers
+            .iter()
+            .filter_map(|&pointer| self.diagnoses.get((pointer as usize).checked_sub(1)?))
+    }
+}
+
+impl Validatable for EncounterCharges {
+    fn validate(&self) -> Result<()> {
+        if self.diagnoses.is_empty() {
+            return Err(Error::validation_error_with_field(
+                "Charges need at least one diagnosis",
+                "diagnoses",
+            ));
+        }
+        if self.diagnoses.len() > MAX_CLAIM_DIAGNOSES {
+            return Err(Error::validation_error_with_field(
+                &format!("Charges carry at most {} diagnoses", MAX_CLAIM_DIAGNOSES),
+                "diagnoses",
+            ));
+        }
+        for diagnosis in &self.diagnoses {
+            if !CodeKind::Diagnosis.is_well_formed(&diagnosis.code) {
+                return Err(Error::validation_error_with_field(
+                    &format!("'{}' is not a valid diagnosis code", diagnosis.code),
+                    "diagnoses",
+                ));
+            }
+        }
+        if self.lines.is_empty() {
+            return Err(Error::validation_error_with_field("Charges need at least one line", "lines"));
+        }
+
+        for line in &self.lines {
+            if !CodeKind::Procedure.is_well_formed(&line.code) {
+                return Err(Error::validation_error_with_field(
+                    &format!("'{}' is not a valid CPT code", line.code),
+                    "lines",
+                ));
+            }
+            if line.units == 0 {
+                return Err(Error::validation_error_with_field(
+                    &format!("Charge {} needs at least one unit", line.code),
+                    "units",
+                ));
+            }
+            if line.modifiers.iter().any(|m| m.len() != 2 || !m.chars().all(|c| c.is_ascii_alphanumeric())) {
+                return Err(Error::validation_error_with_field(
+                    &format!("Modifiers of charge {} must be two letters or digits", line.code),
+                    "modifiers",
+                ));
+            }
+            if line.diagnosis_pointers.len() > MAX_DIAGNOSIS_POINTERS
+                || line.diagnosis_pointers.iter().any(|&p| p == 0 || p as usize > self.diagnoses.len())
+            {
+                return Err(Error::validation_error_with_field(
+                    &format!(
+                        "Charge {} must point at one to {} of the encounter's diagnoses",
+                        line.code, MAX_DIAGNOSIS_POINTERS
+                    ),
+                    "diagnosis_pointers",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn diagnosis(code: &str) -> ChargeDiagnosis {
+        ChargeDiagnosis {
+            sequence: 0,
+            code: code.to_string(),
+            display: None,
+            condition_id: Uuid::new_v4().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_capture_charges() {
+        let capture = |lines: Vec<ChargeLine>| {
+            EncounterCharges::new(
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                vec![diagnosis("I10"), diagnosis("E11.9")],
+                lines,
+                Uuid::new_v4(),
+                Utc::now(),
+            )
+        };
+
+        let charges = capture(vec![
+            ChargeLine::new(" 99213 ", 1, vec!["25".to_string()], Vec::new()),
+            ChargeLine::new("82947", 1, Vec::new(), vec![2, 1]),
+        ])
+        .unwrap();
+        assert_eq!((charges.lines[0].sequence, charges.lines[0].diagnosis_pointers.clone()), (1, vec![1]));
+        let pointed: Vec<_> = charges.diagnoses_of(&charges.lines[1]).map(|d| d.code.as_str()).collect();
+        assert_eq!(pointed, vec!["E11.9", "I10"]);
+
+        assert!(capture(vec![ChargeLine::new("82947", 1, Vec::new(), vec![3])]).is_err());
+        assert!(capture(vec![ChargeLine::new("82947", 0, Vec::new(), Vec::new())]).is_err());
+        assert!(capture(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Clinical notes
+//!
+//! A [`ClinicalNote`] is written as a draft that its author may save as
+//! often as needed; every save is a new version. Signing makes the note
+//! final and its sections can no longer change. Corrections and late
+//! additions are appended as addenda, each with a reason, which mark the
+//! note amended. The signed body and each addendum are signed separately
+//! (see [`crate::signing`]). Notes map to FHIR `Composition` resources.
+
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Most sections per note
+pub const MAX_NOTE_SECTIONS: usize = 50;
+
+/// Longest section or addendum text, in characters
+pub const MAX_NOTE_TEXT_CHARS: usize = 100_000;
+
+/// Resource type named in note signatures
+pub const CLINICAL_NOTE_SIGNATURE_TARGET: &str = "ClinicalNote";
+
+/// Kind of note, coded in LOINC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteType {
+    /// Progress note
+    ProgressNote,
+    /// History and physical
+    HistoryAndPhysical,
+    /// Consultation note
+    ConsultNote,
+    /// Procedure note
+    ProcedureNote,
+    /// Discharge summary
+    DischargeSummary,
+    /// Psychiatry note; reading one needs a purpose of use
+    PsychiatricNote,
+}
+
+impl NoteType {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteType::ProgressNote => "progress_note",
+            NoteType::HistoryAndPhysical => "history_and_physical",
+            NoteType::ConsultNote => "consult_note",
+            NoteType::ProcedureNote => "procedure_note",
+            NoteType::DischargeSummary => "discharge_summary",
+            NoteType::PsychiatricNote => "psychiatric_note",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            NoteType::ProgressNote,
+            NoteType::HistoryAndPhysical,
+            NoteType::ConsultNote,
+            NoteType::ProcedureNote,
+            NoteType::DischargeSummary,
+            NoteType::PsychiatricNote,
+        ]
+        .into_iter()
+        .find(|note_type| note_type.as_str() == value)
+    }
+
+    /// LOINC code and display
+    pub fn loinc(&self) -> (&'static str, &'static str) {
+        match self {
+            NoteType::ProgressNote => ("11506-3", "Progress note"),
+            NoteType::HistoryAndPhysical => ("34117-2", "History and physical note"),
+            NoteType::ConsultNote => ("11488-4", "Consult note"),
+            NoteType::ProcedureNote => ("28570-0", "Procedure note"),
+            NoteType::DischargeSummary => ("18842-5", "Discharge summary"),
+            NoteType::PsychiatricNote => ("28627-8", "Psychiatry note"),
+        }
+    }
+}
+
+/// Note status (FHIR `Composition.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoteStatus {
+    /// Draft, still editable by its author
+    Preliminary,
+    /// Signed
+    Final,
+    /// Signed, with addenda
+    Amended,
+    /// Withdrawn as written in error
+    EnteredInError,
+}
+
+impl NoteStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteStatus::Preliminary => "preliminary",
+            NoteStatus::Final => "final",
+            NoteStatus::Amended => "amended",
+            NoteStatus::EnteredInError => "entered-in-error",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "preliminary" => Some(NoteStatus::Preliminary),
+            "final" => Some(NoteStatus::Final),
+            "amended" => Some(NoteStatus::Amended),
+            "entered-in-error" => Some(NoteStatus::EnteredInError),
+            _ => None,
+        }
+    }
+}
+
+/// One section of a note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteSection {
+    /// Heading, such as "Assessment"
+    pub title: String,
+    /// LOINC section code, if known
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Section text as Markdown
+    pub text: String,
+}
+
+/// Text appended to a signed note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteAddendum {
+    /// Addendum id
+    pub id: Id,
+    /// Practitioner who wrote it
+    pub author_id: Id,
+    /// Addendum text as Markdown
+    pub text: String,
+    /// Why the signed note needed amending
+    #[serde(default)]
+    pub reason: String,
+    /// When it was added
+    pub created_at: Timestamp,
+}
+
+impl NoteAddendum {
+    /// Content covered by the addendum's amendment signature
+    pub fn signed_content(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("addenda serialize to JSON")
+    }
+}
+
+/// Signed part of a note: everything but its status, version and addenda
+#[derive(Serialize)]
+struct AttestedContent<'a> {
+    id: Id,
+    patient_id: Id,
+    encounter_id: Option<Id>,
+    author_id: Id,
+    note_type: NoteType,
+    title: &'a str,
+    sections: &'a [NoteSection],
+}
+
+/// A clinical note about one patient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClinicalNote {
+    /// Id, version (one per saved draft or addendum) and timestamps
+    pub metadata: EntityMetadata,
+    /// Patient the note is about
+    pub patient_id: Id,
+    /// Encounter the note documents
+    pub encounter_id: Option<Id>,
+    /// Practitioner writing the note
+    pub author_id: Id,
+    /// Kind of note
+    pub note_type: NoteType,
+    /// Note title
+    pub title: String,
+    /// Note status
+    pub status: NoteStatus,
+    /// Note body
+    pub sections: Vec<NoteSection>,
+    /// When the note was signed
+    pub signed_at: Option<Timestamp>,
+    /// Addenda, oldest first
+    pub addenda: Vec<NoteAddendum>,
+}
+
+impl ClinicalNote {
+    /// New draft note
+    pub fn new(patient_id: Id, author_id: Id, note_type: NoteType, title: &str) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            patient_id,
+            encounter_id: None,
+            author_id,
+            note_type,
+            title: title.to_string(),
+            status: NoteStatus::Preliminary,
+            sections: Vec::new(),
+            signed_at: None,
+            addenda: Vec::new(),
+        }
+    }
+
+    /// Link the note to the encounter it documents
+    pub fn with_encounter(mut self, encounter_id: Id) -> Self {
+        self.encounter_id = Some(encounter_id);
+        self
+    }
+
+    /// Whether the note is a draft
+    pub fn is_draft(&self) -> bool {
+        self.status == NoteStatus::Preliminary
+    }
+
+    /// Save a new version of a draft
+    ///
+    /// `expected_version` is the version the author's edits are based on;
+    /// a save based on an older version is rejected rather than overwriting
+    /// newer text.
+    pub fn save_draft(
+        &mut self,
+        editor_id: Id,
+        expected_version: u64,
+        title: &str,
+        sections: Vec<NoteSection>,
+    ) -> Result<()> {
+        if !self.is_draft() {
+            return Err(Error::business_rule_violation(
+                "signed_note_immutable",
+                "Signed notes can only be changed with an addendum",
+            ));
+        }
+        if editor_id != self.author_id {
+            return Err(Error::authorization_error("Only the author can edit a draft note"));
+        }
+        if expected_version != self.metadata.version {
+            return Err(Error::data_integrity_error(&format!(
+                "The draft is at version {}, not {}",
+                self.metadata.version, expected_version
+            )));
+        }
+
+        self.title = title.to_string();
+        self.sections = sections;
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Sign a draft, making it final
+    pub fn sign(&mut self, signer_id: Id, at: Timestamp) -> Result<()> {
+        if !self.is_draft() {
+            return Err(Error::business_rule_violation("already_signed", "The note is already signed"));
+        }
+        if signer_id != self.author_id {
+            return Err(Error::authorization_error("Only the author can sign a note"));
+        }
+        if self.sections.iter().all(|section| section.text.trim().is_empty()) {
+            return Err(Error::validation_error_with_field("Notes need some text before signing", "sections"));
+        }
+
+        self.status = NoteStatus::Final;
+        self.signed_at = Some(at);
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Content covered by the author's attestation signature
+    pub fn attested_content(&self) -> Vec<u8> {
+        serde_json::to_vec(&AttestedContent {
+            id: self.metadata.id,
+            patient_id: self.patient_id,
+            encounter_id: self.encounter_id,
+            author_id: self.author_id,
+            note_type: self.note_type,
+            title: &self.title,
+            sections: &self.sections,
+        })
+        .expect("notes serialize to JSON")
+    }
+
+    /// Append an addendum to a signed note, marking it amended
+    pub fn add_addendum(&mut self, author_id: Id, text: &str, reason: &str) -> Result<&NoteAddendum> {
+        if !matches!(self.status, NoteStatus::Final | NoteStatus::Amended) {
+            return Err(Error::business_rule_violation(
+                "addendum_requires_signature",
+                "Addenda can only be added to signed notes; edit the draft instead",
+            ));
+        }
+        let length = text.trim().chars().count();
+        if length == 0 || length > MAX_NOTE_TEXT_CHARS {
+            return Err(Error::validation_error_with_field(
+                &format!("Addenda must have between 1 and {} characters", MAX_NOTE_TEXT_CHARS),
+                "text",
+            ));
+        }
+
+        if reason.trim().is_empty() {
+            return Err(Error::validation_error_with_field("Addenda need a reason", "reason"));
+        }
+
+        self.addenda.push(NoteAddendum {
+            id: Uuid::new_v4(),
+            author_id,
+            text: text.to_string(),
+            reason: reason.trim().to_string(),
+            created_at: Utc::now(),
+        });
+        self.status = NoteStatus::Amended;
+        self.metadata.update();
+        Ok(self.addenda.last().expect("addendum was just added"))
+    }
+}
+
+impl Validatable for ClinicalNote {
+    fn validate(&self) -> Result<()> {
+        if self.title.trim().is_empty() {
+            return Err(Error::validation_error_with_field("Notes need a title", "title"));
+        }
+        if self.sections.len() > MAX_NOTE_SECTIONS {
+            return Err(Error::validation_error_with_field(
+                &format!("Notes are limited to {} sections", MAX_NOTE_SECTIONS),
+                "sections",
+            ));
+        }
+        for section in &self.sections {
+            if section.title.trim().is_empty() {
+                return Err(Error::validation_error_with_field("Sections need a title", "sections"));
+            }
+            if section.text.chars().count() > MAX_NOTE_TEXT_CHARS {
+                return Err(Error::validation_error_with_field(
+                    &format!("Sections are limited to {} characters", MAX_NOTE_TEXT_CHARS),
+                    "sections",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(title: &str, text: &str) -> NoteSection {
+        NoteSection {
+            title: title.to_string(),
+            code: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_draft_versions_and_signing() {
+        let author = Uuid::new_v4();
+        let mut note = ClinicalNote::new(Uuid::new_v4(), author, NoteType::ProgressNote, "Follow-up");
+        assert!(note.sign(author, Utc::now()).is_err(), "empty notes cannot be signed");
+
+        note.save_draft(author, 1, "Follow-up", vec![section("Subjective", "Feeling better")]).unwrap();
+        assert_eq!(note.metadata.version, 2);
+        assert!(note.save_draft(author, 1, "Follow-up", vec![]).is_err(), "stale version");
+        assert!(note.save_draft(Uuid::new_v4(), 2, "Follow-up", vec![]).is_err(), "not the author");
+
+        note.sign(author, Utc::now()).unwrap();
+        assert_eq!(note.status, NoteStatus::Final);
+        assert!(note.signed_at.is_some());
+        assert!(note.save_draft(author, note.metadata.version, "Changed", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_addenda_after_signing() {
+        let author = Uuid::new_v4();
+        let mut note = ClinicalNote::new(Uuid::new_v4(), author, NoteType::ConsultNote, "Cardiology consult");
+        assert!(note.add_addendum(author, "Forgot to mention", "Omission").is_err(), "drafts are edited instead");
+
+        note.sections.push(section("Assessment", "Stable angina"));
+        note.sign(author, Utc::now()).unwrap();
+        let attested = note.attested_content();
+
+        let colleague = Uuid::new_v4();
+        assert!(note.add_addendum(colleague, "Echo reviewed, EF 55%", "").is_err(), "reason required");
+        note.add_addendum(colleague, "Echo reviewed, EF 55%", "Result arrived after signing").unwrap();
+        assert_eq!(note.status, NoteStatus::Amended);
+        assert_eq!(note.addenda.len(), 1);
+        assert_eq!(note.sections[0].text, "Stable angina");
+        assert_eq!(note.attested_content(), attested, "addenda leave the attested content alone");
+        assert!(note.add_addendum(colleague, "  ", "Typo").is_err());
+    }
+
+    #[test]
+    fn test_note_type_codes() {
+        assert_eq!(NoteType::DischargeSummary.loinc().0, "18842-5");
+        assert_eq!(NoteType::parse(NoteType::ConsultNote.as_str()), Some(NoteType::ConsultNote));
+        assert_eq!(NoteStatus::parse("entered-in-error"), Some(NoteStatus::EnteredInError));
+    }
+}
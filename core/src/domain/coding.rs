@@ -0,0 +1,358 @@
+//! Billing codes of an encounter
+//!
+//! After an encounter a coder reviews the codes the platform suggests (see
+//! [`crate::services::coding`]) and finalizes the encounter's
+//! [`EncounterCoding`]: ICD-10-CM diagnoses, the first being the principal
+//! one, and CPT procedures, each pointing at the diagnoses it treats. Each
+//! code records whether it was an accepted suggestion, an override of one or
+//! added by the coder. The finalized coding is what the claims export bills.
+
+use crate::domain::traits::Validatable;
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// ICD-10-CM, the system of diagnosis codes
+pub const ICD10CM_SYSTEM: &str = "http://hl7.org/fhir/sid/icd-10-cm";
+
+/// CPT, the system of procedure codes
+pub const CPT_SYSTEM: &str = "http://www.ama-assn.org/go/cpt";
+
+/// Most diagnoses a professional claim carries
+pub const MAX_CLAIM_DIAGNOSES: usize = 12;
+
+/// Most diagnoses a claim line points at
+pub const MAX_DIAGNOSIS_POINTERS: usize = 4;
+
+/// Kind of billing code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeKind {
+    /// ICD-10-CM diagnosis
+    Diagnosis,
+    /// CPT procedure or service
+    Procedure,
+}
+
+impl CodeKind {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CodeKind::Diagnosis => "diagnosis",
+            CodeKind::Procedure => "procedure",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "diagnosis" => Some(CodeKind::Diagnosis),
+            "procedure" => Some(CodeKind::Procedure),
+            _ => None,
+        }
+    }
+
+    /// Code system URI
+    pub fn system(&self) -> &'static str {
+        match self {
+            CodeKind::Diagnosis => ICD10CM_SYSTEM,
+            CodeKind::Procedure => CPT_SYSTEM,
+        }
+    }
+
+    /// Whether `code` has the shape of a code of this kind
+    pub fn is_well_formed(&self, code: &str) -> bool {
+        match self {
+            CodeKind::Diagnosis => is_icd10cm(code),
+            CodeKind::Procedure => is_cpt(code),
+        }
+    }
+}
+
+/// ICD-10-CM shape: letter, digit, digit or letter, then up to four more
+/// characters, optionally after a dot
+fn is_icd10cm(code: &str) -> bool {
+    let (category, subcategory) = match code.split_once('.') {
+        Some((category, subcategory)) if !subcategory.is_empty() => (category, subcategory),
+        Some(_) => return false,
+        None => (code.get(..3).unwrap_or(code), code.get(3..).unwrap_or("")),
+    };
+    let category: Vec<char> = category.chars().collect();
+    category.len() == 3
+        && category[0].is_ascii_uppercase()
+        && category[1].is_ascii_digit()
+        && category[2].is_ascii_alphanumeric()
+        && subcategory.len() <= 4
+        && subcategory.chars().all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+}
+
+/// CPT shape: five digits, or four digits and F (category II) or T
+/// (category III)
+fn is_cpt(code: &str) -> bool {
+    let chars: Vec<char> = code.chars().collect();
+    chars.len() == 5
+        && chars[..4].iter().all(char::is_ascii_digit)
+        && (chars[4].is_ascii_digit() || chars[4] == 'F' || chars[4] == 'T')
+}
+
+/// Where a finalized code came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeOrigin {
+    /// A suggestion the coder accepted as is
+    Accepted,
+    /// A suggestion the coder replaced with another code
+    Overridden,
+    /// Added by the coder without a suggestion
+    Added,
+}
+
+impl CodeOrigin {
+    /// Origin of `code` given the suggestion it was reviewed from, if any
+    pub fn of(suggested_code: Option<&str>, code: &str) -> Self {
+        match suggested_code {
+            Some(suggested) if suggested == code => CodeOrigin::Accepted,
+            Some(_) => CodeOrigin::Overridden,
+            None => CodeOrigin::Added,
+        }
+    }
+
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CodeOrigin::Accepted => "accepted",
+            CodeOrigin::Overridden => "overridden",
+            CodeOrigin::Added => "added",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "accepted" => Some(CodeOrigin::Accepted),
+            "overridden" => Some(CodeOrigin::Overridden),
+            "added" => Some(CodeOrigin::Added),
+            _ => None,
+        }
+    }
+}
+
+/// A finalized billing code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncounterCode {
+    /// Diagnosis or procedure
+    pub kind: CodeKind,
+    /// ICD-10-CM or CPT code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// Position among the codes of its kind, from 1; diagnosis 1 is the
+    /// principal diagnosis
+    pub sequence: u32,
+    /// Units of service (procedures)
+    pub units: u32,
+    /// CPT modifiers (procedures)
+    pub modifiers: Vec<String>,
+    /// Sequences of the diagnoses the procedure treats (procedures)
+    pub diagnosis_pointers: Vec<u32>,
+    /// Whether the code was suggested, overridden or added
+    pub origin: CodeOrigin,
+    /// The suggestion the code was reviewed from
+    pub suggested_code: Option<String>,
+    /// Why the coder replaced the suggestion
+    pub override_reason: Option<String>,
+}
+
+impl EncounterCode {
+    /// A code as the coder entered it, before it is sequenced
+    pub fn new(kind: CodeKind, code: &str, suggested_code: Option<&str>) -> Self {
+        let code = code.trim().to_uppercase();
+        Self {
+            kind,
+            origin: CodeOrigin::of(suggested_code, &code),
+            code,
+            display: None,
+            sequence: 0,
+            units: 1,
+            modifiers: Vec::new(),
+            diagnosis_pointers: Vec::new(),
+            suggested_code: suggested_code.map(str::to_string),
+            override_reason: None,
+        }
+    }
+}
+
+/// The finalized billing codes of an encounter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncounterCoding {
+    /// Encounter coded
+    pub encounter_id: Id,
+    /// The encounter's patient
+    pub patient_id: Id,
+    /// Diagnoses then procedures, each in sequence
+    pub codes: Vec<EncounterCode>,
+    /// Coder who finalized the codes
+    pub coded_by: Id,
+    /// When the codes were finalized
+    pub coded_at: Timestamp,
+}
+
+impl EncounterCoding {
+    /// Finalize codes in the order given: diagnoses are sequenced in the order
+    /// they appear, and a procedure without diagnosis pointers points at the
+    /// principal diagnosis
+    pub fn new(
+        encounter_id: Id,
+        patient_id: Id,
+        codes: Vec<EncounterCode>,
+        coded_by: Id,
+        at: Timestamp,
+    ) -> Result<Self> {
+        let (mut diagnoses, mut procedures): (Vec<_>, Vec<_>) =
+            codes.into_iter().partition(|code| code.kind == CodeKind::Diagnosis);
+        for (index, code) in diagnoses.iter_mut().enumerate() {
+            code.sequence = index as u32 + 1;
+            code.units = 1;
+            code.modifiers.clear();
+            code.diagnosis_pointers.clear();
+        }
+        for (index, code) in procedures.iter_mut().enumerate() {
+            code.sequence = index as u32 + 1;
+            if code.diagnosis_pointers.is_empty() {
+                code.diagnosis_pointers.push(1);
+            }
+            for modifier in &mut code.modifiers {
+                *modifier = modifier.trim().to_uppercase();
+            }
+        }
+        diagnoses.append(&mut procedures);
+
+        let coding = Self {
+            encounter_id,
+            patient_id,
+            codes: diagnoses,
+            coded_by,
+            coded_at: at,
+        };
+        coding.validate()?;
+        Ok(coding)
+    }
+
+    /// Codes of one kind, in sequence
+    pub fn of_kind(&self, kind: CodeKind) -> impl Iterator<Item = &EncounterCode> {
+        self.codes.iter().filter(move |code| code.kind == kind)
+    }
+}
+
+impl Validatable for EncounterCoding {
+    fn validate(&self) -> Result<()> {
+        let diagnoses = self.of_kind(CodeKind::Diagnosis).count();
+        if diagnoses == 0 {
+            return Err(Error::validation_error_with_field(
+                "An encounter's coding needs at least one diagnosis",
+                "codes",
+            ));
+        }
+        if diagnoses > MAX_CLAIM_DIAGNOSES {
+            return Err(Error::validation_error_with_field(
+                &format!("A claim carries at most {} diagnoses", MAX_CLAIM_DIAGNOSES),
+                "codes",
+            ));
+        }
+
+        for code in &self.codes {
+            if !code.kind.is_well_formed(&code.code) {
+                return Err(Error::validation_error_with_field(
+                    &format!("'{}' is not a valid {} code", code.code, code.kind.as_str()),
+                    "codes",
+                ));
+            }
+            if code.origin == CodeOrigin::Overridden
+                && code.override_reason.as_deref().map_or(true, |reason| reason.trim().is_empty())
+            {
+                let suggested = code.suggested_code.as_deref().unwrap_or_default();
+                return Err(Error::validation_error_with_field(
+                    &format!("Replacing the suggested {} with {} needs a reason", suggested, code.code),
+                    "override_reason",
+                ));
+            }
+            if code.kind == CodeKind::Procedure {
+                if code.units == 0 {
+                    return Err(Error::validation_error_with_field(
+                        &format!("Procedure {} needs at least one unit", code.code),
+                        "units",
+                    ));
+                }
+                if code.modifiers.iter().any(|m| m.len() != 2 || !m.chars().all(|c| c.is_ascii_alphanumeric())) {
+                    return Err(Error::validation_error_with_field(
+                        &format!("Modifiers of procedure {} must be two letters or digits", code.code),
+                        "modifiers",
+                    ));
+                }
+                if code.diagnosis_pointers.len() > MAX_DIAGNOSIS_POINTERS
+                    || code.diagnosis_pointers.iter().any(|&p| p == 0 || p as usize > diagnoses)
+                {
+                    return Err(Error::validation_error_with_field(
+                        &format!(
+                            "Procedure {} must point at one to {} of the encounter's diagnoses",
+                            code.code, MAX_DIAGNOSIS_POINTERS
+                        ),
+                        "diagnosis_pointers",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_code_shapes() {
+        for code in ["I10", "E11.9", "S72.001A", "E119", "U07.1"] {
+            assert!(CodeKind::Diagnosis.is_well_formed(code), "{}", code);
+        }
+        for code in ["i10", "I1", "E11.", "E11.12345", "99213"] {
+            assert!(!CodeKind::Diagnosis.is_well_formed(code), "{}", code);
+        }
+        for code in ["99213", "3074F", "0042T"] {
+            assert!(CodeKind::Procedure.is_well_formed(code), "{}", code);
+        }
+        for code in ["9921", "99213A", "G0438"] {
+            assert!(!CodeKind::Procedure.is_well_formed(code), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_finalize_coding() {
+        let finalize = |codes: Vec<EncounterCode>| {
+            EncounterCoding::new(Uuid::new_v4(), Uuid::new_v4(), codes, Uuid::new_v4(), Utc::now())
+        };
+        let mut visit = EncounterCode::new(CodeKind::Procedure, "99214", Some("99213"));
+        visit.override_reason = Some("Time documented in the note".to_string());
+        visit.modifiers = vec!["25".to_string()];
+        let hypertension = EncounterCode::new(CodeKind::Diagnosis, " i10 ", Some("I10"));
+        let diabetes = EncounterCode::new(CodeKind::Diagnosis, "E11.9", None);
+
+        let coding = finalize(vec![visit.clone(), hypertension.clone(), diabetes.clone()]).unwrap();
+        let diagnoses: Vec<_> = coding.of_kind(CodeKind::Diagnosis).collect();
+        assert_eq!((diagnoses[0].code.as_str(), diagnoses[0].origin), ("I10", CodeOrigin::Accepted));
+        assert_eq!((diagnoses[1].sequence, diagnoses[1].origin), (2, CodeOrigin::Added));
+        let procedure = coding.of_kind(CodeKind::Procedure).next().unwrap();
+        assert_eq!((procedure.origin, procedure.diagnosis_pointers.clone()), (CodeOrigin::Overridden, vec![1]));
+
+        assert!(finalize(vec![visit.clone()]).is_err());
+        let mut unexplained = visit.clone();
+        unexplained.override_reason = None;
+        assert!(finalize(vec![unexplained, hypertension.clone()]).is_err());
+        let mut dangling = visit;
+        dangling.diagnosis_pointers = vec![3];
+        assert!(finalize(vec![dangling, hypertension, diabetes]).is_err());
+    }
+}
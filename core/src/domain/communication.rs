@@ -0,0 +1,262 @@
+//! Secure messages between patients and practitioners
+//!
+//! A [`Communication`] is one message in a thread about one patient. The
+//! first message fixes the thread's participants; replies go to everyone in
+//! the thread except the sender. Attachments reference the patient's
+//! DocumentReference records rather than carrying content.
+
+use crate::domain::traits::Validatable;
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Longest message body, in characters
+pub const MAX_MESSAGE_CHARS: usize = 10_000;
+
+/// Most attachments per message
+pub const MAX_MESSAGE_ATTACHMENTS: usize = 10;
+
+/// Sender or recipient of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum CommunicationParty {
+    /// A patient, always the thread's subject
+    Patient(Id),
+    /// A practitioner
+    Practitioner(Id),
+}
+
+impl CommunicationParty {
+    /// Stored type name
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CommunicationParty::Patient(_) => "patient",
+            CommunicationParty::Practitioner(_) => "practitioner",
+        }
+    }
+
+    /// Party id
+    pub fn id(&self) -> Id {
+        match self {
+            CommunicationParty::Patient(id) | CommunicationParty::Practitioner(id) => *id,
+        }
+    }
+
+    /// Parse a stored type name and id
+    pub fn parse(type_name: &str, id: Id) -> Option<Self> {
+        match type_name {
+            "patient" => Some(CommunicationParty::Patient(id)),
+            "practitioner" => Some(CommunicationParty::Practitioner(id)),
+            _ => None,
+        }
+    }
+}
+
+/// Message status (FHIR `Communication.status`, restricted to what messages use)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommunicationStatus {
+    /// Sent
+    Completed,
+    /// Withdrawn as sent in error
+    EnteredInError,
+}
+
+impl CommunicationStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommunicationStatus::Completed => "completed",
+            CommunicationStatus::EnteredInError => "entered-in-error",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "completed" => Some(CommunicationStatus::Completed),
+            "entered-in-error" => Some(CommunicationStatus::EnteredInError),
+            _ => None,
+        }
+    }
+}
+
+/// One secure message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Communication {
+    /// Message id
+    pub id: Id,
+    /// Id of the thread's first message
+    pub thread_id: Id,
+    /// Message this one replies to
+    pub in_response_to: Option<Id>,
+    /// Patient the thread is about
+    pub subject: Id,
+    /// Who sent the message
+    pub sender: CommunicationParty,
+    /// Everyone else in the thread
+    pub recipients: Vec<CommunicationParty>,
+    /// Thread topic, set on the first message
+    pub topic: Option<String>,
+    /// Message text
+    pub payload: String,
+    /// DocumentReference ids of the subject's documents
+    pub attachments: Vec<Id>,
+    /// Message status
+    pub status: CommunicationStatus,
+    /// When the message was sent
+    pub sent: Timestamp,
+}
+
+impl Communication {
+    /// First message of a new thread
+    pub fn new_thread(
+        subject: Id,
+        sender: CommunicationParty,
+        recipients: Vec<CommunicationParty>,
+        topic: Option<String>,
+        payload: &str,
+    ) -> Self {
+        let id = Uuid::new_v4();
+        Self {
+            id,
+            thread_id: id,
+            in_response_to: None,
+            subject,
+            sender,
+            recipients,
+            topic,
+            payload: payload.to_string(),
+            attachments: Vec::new(),
+            status: CommunicationStatus::Completed,
+            sent: Utc::now(),
+        }
+    }
+
+    /// Everyone in the thread
+    pub fn participants(&self) -> Vec<CommunicationParty> {
+        let mut participants = vec![self.sender];
+        participants.extend(self.recipients.iter().copied());
+        participants
+    }
+
+    /// Reply to this message from one of the thread's participants
+    pub fn reply(&self, sender: CommunicationParty, payload: &str) -> Result<Self> {
+        let participants = self.participants();
+        if !participants.contains(&sender) {
+            return Err(Error::authorization_error("Only thread participants can reply"));
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            thread_id: self.thread_id,
+            in_response_to: Some(self.id),
+            subject: self.subject,
+            sender,
+            recipients: participants.into_iter().filter(|party| *party != sender).collect(),
+            topic: None,
+            payload: payload.to_string(),
+            attachments: Vec::new(),
+            status: CommunicationStatus::Completed,
+            sent: Utc::now(),
+        })
+    }
+
+    /// Attach documents of the subject
+    pub fn with_attachments(mut self, attachments: Vec<Id>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+}
+
+impl Validatable for Communication {
+    fn validate(&self) -> Result<()> {
+        let length = self.payload.trim().chars().count();
+        if length == 0 || length > MAX_MESSAGE_CHARS {
+            return Err(Error::validation_error_with_field(
+                &format!("Messages must have between 1 and {} characters", MAX_MESSAGE_CHARS),
+                "payload",
+            ));
+        }
+        if self.recipients.is_empty() || self.recipients.contains(&self.sender) {
+            return Err(Error::validation_error_with_field(
+                "Messages need recipients other than the sender",
+                "recipients",
+            ));
+        }
+        let outside_patient = self
+            .participants()
+            .iter()
+            .any(|party| matches!(party, CommunicationParty::Patient(id) if *id != self.subject));
+        if outside_patient {
+            return Err(Error::validation_error_with_field(
+                "The only patient in a thread is its subject",
+                "recipients",
+            ));
+        }
+        if self.attachments.len() > MAX_MESSAGE_ATTACHMENTS {
+            return Err(Error::validation_error_with_field(
+                &format!("Messages are limited to {} attachments", MAX_MESSAGE_ATTACHMENTS),
+                "attachments",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_goes_to_other_participants() {
+        let patient = CommunicationParty::Patient(Uuid::new_v4());
+        let doctor = CommunicationParty::Practitioner(Uuid::new_v4());
+        let nurse = CommunicationParty::Practitioner(Uuid::new_v4());
+
+        let first = Communication::new_thread(patient.id(), patient, vec![doctor, nurse], Some("Refill".to_string()), "Hello");
+        assert!(first.validate().is_ok());
+        assert_eq!(first.thread_id, first.id);
+
+        let reply = first.reply(doctor, "Sent to your pharmacy").unwrap();
+        assert_eq!(reply.thread_id, first.id);
+        assert_eq!(reply.in_response_to, Some(first.id));
+        assert_eq!(reply.recipients, vec![patient, nurse]);
+        assert!(reply.validate().is_ok());
+
+        let outsider = CommunicationParty::Practitioner(Uuid::new_v4());
+        assert!(first.reply(outsider, "Hi").is_err());
+    }
+
+    #[test]
+    fn test_communication_validation() {
+        let patient = CommunicationParty::Patient(Uuid::new_v4());
+        let doctor = CommunicationParty::Practitioner(Uuid::new_v4());
+
+        let blank = Communication::new_thread(patient.id(), doctor, vec![patient], None, "  ");
+        assert!(blank.validate().is_err());
+
+        let to_self = Communication::new_thread(patient.id(), doctor, vec![doctor], None, "Note");
+        assert!(to_self.validate().is_err());
+
+        let other_patient = CommunicationParty::Patient(Uuid::new_v4());
+        let wrong_patient = Communication::new_thread(patient.id(), doctor, vec![other_patient], None, "Results");
+        assert!(wrong_patient.validate().is_err());
+
+        let attachments = (0..=MAX_MESSAGE_ATTACHMENTS).map(|_| Uuid::new_v4()).collect();
+        let too_many = Communication::new_thread(patient.id(), doctor, vec![patient], None, "Files")
+            .with_attachments(attachments);
+        assert!(too_many.validate().is_err());
+    }
+
+    #[test]
+    fn test_party_serialization() {
+        let id = Uuid::new_v4();
+        let party = CommunicationParty::Practitioner(id);
+        let json = serde_json::to_value(party).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "practitioner", "id": id}));
+        assert_eq!(CommunicationParty::parse(party.type_name(), party.id()), Some(party));
+    }
+}
@@ -0,0 +1,114 @@
+//! Confidentiality labels
+//!
+//! Observations, conditions and documents carry an HL7 v3 confidentiality
+//! label. Users hold a clearance of the same scale and only see data
+//! labelled at or below it; the label is rendered in FHIR `meta.security`.
+
+use serde::{Deserialize, Serialize};
+
+/// HL7 v3 Confidentiality code system, used in FHIR `meta.security`
+pub const CONFIDENTIALITY_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/v3-Confidentiality";
+
+/// How confidential data is, least first; also a user's clearance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidentiality {
+    /// Typical health data
+    #[default]
+    Normal,
+    /// Sensitive data, e.g. mental health or substance use
+    Restricted,
+    /// Extremely sensitive data, e.g. about a VIP or kept from the patient
+    VeryRestricted,
+}
+
+impl Confidentiality {
+    /// Every label, least confidential first
+    pub const ALL: [Confidentiality; 3] = [
+        Confidentiality::Normal,
+        Confidentiality::Restricted,
+        Confidentiality::VeryRestricted,
+    ];
+
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confidentiality::Normal => "normal",
+            Confidentiality::Restricted => "restricted",
+            Confidentiality::VeryRestricted => "very_restricted",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|label| label.as_str() == value)
+    }
+
+    /// HL7 v3 Confidentiality code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Confidentiality::Normal => "N",
+            Confidentiality::Restricted => "R",
+            Confidentiality::VeryRestricted => "V",
+        }
+    }
+
+    /// HL7 v3 Confidentiality display
+    pub fn display(&self) -> &'static str {
+        match self {
+            Confidentiality::Normal => "normal",
+            Confidentiality::Restricted => "restricted",
+            Confidentiality::VeryRestricted => "very restricted",
+        }
+    }
+
+    /// Parse an HL7 v3 Confidentiality code; the codes below normal (`U`,
+    /// `L`, `M`) are treated as normal
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "U" | "L" | "M" | "N" => Some(Confidentiality::Normal),
+            "R" => Some(Confidentiality::Restricted),
+            "V" => Some(Confidentiality::VeryRestricted),
+            _ => None,
+        }
+    }
+
+    /// Whether a user with this clearance may see data labelled `label`
+    pub fn clears(&self, label: Confidentiality) -> bool {
+        label <= *self
+    }
+
+    /// Stored names of the labels this clearance may see
+    pub fn cleared_labels(&self) -> Vec<&'static str> {
+        Self::ALL
+            .into_iter()
+            .filter(|label| self.clears(*label))
+            .map(|label| label.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confidentiality_names() {
+        for label in Confidentiality::ALL {
+            assert_eq!(Confidentiality::parse(label.as_str()), Some(label));
+            assert_eq!(Confidentiality::from_code(label.code()), Some(label));
+        }
+        assert_eq!(Confidentiality::from_code("M"), Some(Confidentiality::Normal));
+        assert_eq!(Confidentiality::from_code("X"), None);
+        assert_eq!(Confidentiality::default(), Confidentiality::Normal);
+    }
+
+    #[test]
+    fn test_clearance() {
+        assert!(Confidentiality::Restricted.clears(Confidentiality::Normal));
+        assert!(Confidentiality::Restricted.clears(Confidentiality::Restricted));
+        assert!(!Confidentiality::Restricted.clears(Confidentiality::VeryRestricted));
+        assert_eq!(Confidentiality::Normal.cleared_labels(), vec!["normal"]);
+        assert_eq!(Confidentiality::VeryRestricted.cleared_labels().len(), 3);
+    }
+}
@@ -0,0 +1,269 @@
+//! Insurance coverage
+//!
+//! A [`Coverage`] (FHIR `Coverage`) records a patient's health plan: the
+//! payer, the member id on the card and, when the card is in someone else's
+//! name, who the subscriber is. Claims are billed to the patient's primary
+//! coverage in force on the date of service.
+
+use crate::domain::traits::Validatable;
+use crate::domain::values::AdministrativeGender;
+use crate::types::{EntityMetadata, Id};
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Coverage status (FHIR `Coverage.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoverageStatus {
+    /// In force
+    Active,
+    /// Ended by the payer or the patient
+    Cancelled,
+    /// Recorded in error
+    EnteredInError,
+}
+
+impl CoverageStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoverageStatus::Active => "active",
+            CoverageStatus::Cancelled => "cancelled",
+            CoverageStatus::EnteredInError => "entered-in-error",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(CoverageStatus::Active),
+            "cancelled" => Some(CoverageStatus::Cancelled),
+            "entered-in-error" => Some(CoverageStatus::EnteredInError),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of health plan, which decides how claims are filed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanType {
+    /// Commercial insurance
+    Commercial,
+    /// Medicare Part B
+    Medicare,
+    /// Medicaid
+    Medicaid,
+    /// Any other plan
+    Other,
+}
+
+impl PlanType {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanType::Commercial => "commercial",
+            PlanType::Medicare => "medicare",
+            PlanType::Medicaid => "medicaid",
+            PlanType::Other => "other",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "commercial" => Some(PlanType::Commercial),
+            "medicare" => Some(PlanType::Medicare),
+            "medicaid" => Some(PlanType::Medicaid),
+            "other" => Some(PlanType::Other),
+            _ => None,
+        }
+    }
+}
+
+/// The patient's relationship to the subscriber
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberRelationship {
+    /// The patient is the subscriber
+    #[serde(rename = "self")]
+    Own,
+    /// Spouse of the subscriber
+    Spouse,
+    /// Child of the subscriber
+    Child,
+    /// Any other dependent
+    Other,
+}
+
+impl SubscriberRelationship {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberRelationship::Own => "self",
+            SubscriberRelationship::Spouse => "spouse",
+            SubscriberRelationship::Child => "child",
+            SubscriberRelationship::Other => "other",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "self" => Some(SubscriberRelationship::Own),
+            "spouse" => Some(SubscriberRelationship::Spouse),
+            "child" => Some(SubscriberRelationship::Child),
+            "other" => Some(SubscriberRelationship::Other),
+            _ => None,
+        }
+    }
+}
+
+/// The policy holder, when it is not the patient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscriber {
+    /// Family name
+    pub family_name: String,
+    /// Given name
+    pub given_name: Option<String>,
+    /// Date of birth
+    pub birth_date: Option<NaiveDate>,
+    /// Administrative gender
+    pub gender: Option<AdministrativeGender>,
+}
+
+/// A patient's health plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coverage {
+    /// Entity metadata
+    #[serde(flatten)]
+    pub metadata: EntityMetadata,
+    /// Patient covered
+    pub patient_id: Id,
+    /// Status
+    pub status: CoverageStatus,
+    /// Order in which plans pay, 1 being primary
+    pub priority: u32,
+    /// Kind of plan
+    pub plan_type: PlanType,
+    /// Payer name
+    pub payer_name: String,
+    /// Payer id assigned by the clearinghouse
+    pub payer_id: String,
+    /// Member id on the insurance card
+    pub member_id: String,
+    /// Group number on the insurance card
+    pub group_number: Option<String>,
+    /// Plan name
+    pub plan_name: Option<String>,
+    /// The patient's relationship to the subscriber
+    pub relationship: SubscriberRelationship,
+    /// The subscriber, unless the patient is the subscriber
+    pub subscriber: Option<Subscriber>,
+    /// First day covered
+    pub start: Option<NaiveDate>,
+    /// Last day covered
+    pub end: Option<NaiveDate>,
+}
+
+impl Coverage {
+    /// Create an active coverage with the patient as the subscriber
+    pub fn new(patient_id: Id, plan_type: PlanType, payer_name: &str, payer_id: &str, member_id: &str) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            patient_id,
+            status: CoverageStatus::Active,
+            priority: 1,
+            plan_type,
+            payer_name: payer_name.trim().to_string(),
+            payer_id: payer_id.trim().to_string(),
+            member_id: member_id.trim().to_string(),
+            group_number: None,
+            plan_name: None,
+            relationship: SubscriberRelationship::Own,
+            subscriber: None,
+            start: None,
+            end: None,
+        }
+    }
+
+    /// Whether the coverage is active and covers `date`
+    pub fn is_in_force(&self, date: NaiveDate) -> bool {
+        self.status == CoverageStatus::Active
+            && self.start.map_or(true, |start| start <= date)
+            && self.end.map_or(true, |end| date <= end)
+    }
+}
+
+impl Validatable for Coverage {
+    fn validate(&self) -> Result<()> {
+        for (value, field) in [
+            (&self.payer_name, "payer_name"),
+            (&self.payer_id, "payer_id"),
+            (&self.member_id, "member_id"),
+        ] {
+            if value.trim().is_empty() {
+                return Err(Error::validation_error_with_field(&format!("{} is required", field), field));
+            }
+        }
+        if self.priority == 0 {
+            return Err(Error::validation_error_with_field("Priority starts at 1 (primary)", "priority"));
+        }
+        match (&self.relationship, &self.subscriber) {
+            (SubscriberRelationship::Own, Some(_)) => {
+                return Err(Error::validation_error_with_field(
+                    "The patient is the subscriber; leave the subscriber out",
+                    "subscriber",
+                ))
+            }
+            (relationship, None) if *relationship != SubscriberRelationship::Own => {
+                return Err(Error::validation_error_with_field(
+                    "Coverage through someone else needs the subscriber",
+                    "subscriber",
+                ))
+            }
+            (_, Some(subscriber)) if subscriber.family_name.trim().is_empty() => {
+                return Err(Error::validation_error_with_field(
+                    "The subscriber's family name is required",
+                    "subscriber",
+                ))
+            }
+            _ => {}
+        }
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if end < start {
+                return Err(Error::validation_error_with_field("Coverage cannot end before it starts", "end"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_coverage() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let mut coverage = Coverage::new(Uuid::new_v4(), PlanType::Commercial, "Acme Health", "60054", "W123456789");
+        coverage.start = Some(date(2026, 1, 1));
+        coverage.end = Some(date(2026, 12, 31));
+        assert!(coverage.validate().is_ok());
+        assert!(coverage.is_in_force(date(2026, 6, 1)));
+        assert!(!coverage.is_in_force(date(2027, 1, 1)));
+
+        coverage.relationship = SubscriberRelationship::Child;
+        assert!(coverage.validate().is_err());
+        coverage.subscriber = Some(Subscriber {
+            family_name: "Doe".to_string(),
+            given_name: Some("Jane".to_string()),
+            birth_date: Some(date(1985, 3, 2)),
+            gender: Some(AdministrativeGender::Female),
+        });
+        assert!(coverage.validate().is_ok());
+        assert_eq!(serde_json::to_value(SubscriberRelationship::Own).unwrap(), "self");
+    }
+}
@@ -1,15 +1,18 @@
 //! Encounter domain entity
 
 use crate::domain::traits::{Identifiable, Auditable, Validatable};
+use crate::domain::patient::Period;
 use crate::domain::values::*;
 use crate::types::{Id, Timestamp, EntityMetadata};
 use crate::{Result, Error};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 /// Encounter entity representing healthcare encounters
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct Encounter {
+    /// Identity, timestamps and version
     #[serde(flatten)]
     pub metadata: EntityMetadata,
     
@@ -57,32 +60,111 @@ pub struct Encounter {
 }
 
 /// Encounter status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EncounterStatus {
+    /// Planned but not yet started
     Planned,
+    /// Patient has arrived
     Arrived,
+    /// Patient has been triaged
     Triaged,
+    /// Encounter is underway
     InProgress,
+    /// Patient is on leave from the encounter
     Onleave,
+    /// Encounter has ended
     Finished,
+    /// Encounter was cancelled before it started
     Cancelled,
+    /// Recorded in error
     EnteredInError,
+    /// Status is not known
     Unknown,
 }
 
 /// Encounter class
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EncounterClass {
+    /// Inpatient admission
     Inpatient,
+    /// Outpatient visit
     Outpatient,
+    /// Ambulatory visit
     Ambulatory,
+    /// Emergency department visit
     Emergency,
+    /// Home health visit
     Home,
+    /// Care delivered in the field
     Field,
+    /// Day-case admission
     Daytime,
+    /// Virtual or telehealth visit
     Virtual,
 }
 
+impl EncounterStatus {
+    /// FHIR encounter-status code
+    pub fn code(&self) -> &'static str {
+        match self {
+            EncounterStatus::Planned => "planned",
+            EncounterStatus::Arrived => "arrived",
+            EncounterStatus::Triaged => "triaged",
+            EncounterStatus::InProgress => "in-progress",
+            EncounterStatus::Onleave => "onleave",
+            EncounterStatus::Finished => "finished",
+            EncounterStatus::Cancelled => "cancelled",
+            EncounterStatus::EnteredInError => "entered-in-error",
+            EncounterStatus::Unknown => "unknown",
+        }
+    }
+
+    /// Parse a FHIR encounter-status code
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_lowercase().as_str() {
+            "planned" => Some(EncounterStatus::Planned),
+            "arrived" => Some(EncounterStatus::Arrived),
+            "triaged" => Some(EncounterStatus::Triaged),
+            "in-progress" => Some(EncounterStatus::InProgress),
+            "onleave" => Some(EncounterStatus::Onleave),
+            "finished" => Some(EncounterStatus::Finished),
+            "cancelled" => Some(EncounterStatus::Cancelled),
+            "entered-in-error" => Some(EncounterStatus::EnteredInError),
+            "unknown" => Some(EncounterStatus::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl EncounterClass {
+    /// FHIR `v3-ActCode` of the class; outpatient and ambulatory visits share `AMB`
+    pub fn code(&self) -> &'static str {
+        match self {
+            EncounterClass::Inpatient => "IMP",
+            EncounterClass::Outpatient | EncounterClass::Ambulatory => "AMB",
+            EncounterClass::Emergency => "EMER",
+            EncounterClass::Home => "HH",
+            EncounterClass::Field => "FLD",
+            EncounterClass::Daytime => "SS",
+            EncounterClass::Virtual => "VR",
+        }
+    }
+
+    /// Parse a FHIR `v3-ActCode` encounter class
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_uppercase().as_str() {
+            "AMB" => Some(EncounterClass::Ambulatory),
+            "IMP" | "ACUTE" | "NONAC" => Some(EncounterClass::Inpatient),
+            "EMER" => Some(EncounterClass::Emergency),
+            "HH" => Some(EncounterClass::Home),
+            "FLD" => Some(EncounterClass::Field),
+            "SS" => Some(EncounterClass::Daytime),
+            "VR" => Some(EncounterClass::Virtual),
+            _ => None,
+        }
+    }
+}
+
 /// Encounter participant
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncounterParticipant {
@@ -123,21 +205,18 @@ pub struct EncounterLocation {
 }
 
 /// Encounter location status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EncounterLocationStatus {
+    /// Planned for this location
     Planned,
+    /// Patient is at this location
     Active,
+    /// Location is held for the patient
     Reserved,
+    /// Patient has left this location
     Completed,
 }
 
-/// Period of time
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Period {
-    pub start: Option<Timestamp>,
-    pub end: Option<Timestamp>,
-}
-
 impl Encounter {
     /// Create a new encounter with required fields
     pub fn new(status: EncounterStatus, class: EncounterClass, subject: Id) -> Self {
@@ -159,6 +238,144 @@ impl Encounter {
             service_provider: None,
         }
     }
+
+    /// Record the patient's arrival for a planned encounter
+    pub fn check_in(&mut self) -> Result<()> {
+        self.transition(&[EncounterStatus::Planned], EncounterStatus::Arrived)
+    }
+
+    /// Start the encounter, recording the period start
+    pub fn start(&mut self) -> Result<()> {
+        self.transition(
+            &[
+                EncounterStatus::Planned,
+                EncounterStatus::Arrived,
+                EncounterStatus::Triaged,
+            ],
+            EncounterStatus::InProgress,
+        )?;
+
+        let period = self.period.get_or_insert(Period { start: None, end: None });
+        period.start.get_or_insert_with(Utc::now);
+        Ok(())
+    }
+
+    /// End the encounter, recording the period end, length in minutes, and
+    /// closing any active location
+    pub fn end(&mut self) -> Result<()> {
+        self.transition(
+            &[EncounterStatus::InProgress, EncounterStatus::Onleave],
+            EncounterStatus::Finished,
+        )?;
+
+        let now = Utc::now();
+        let period = self.period.get_or_insert(Period { start: None, end: None });
+        period.end = Some(now);
+        self.length = period
+            .start
+            .map(|start| (now - start).num_minutes().max(0) as u32);
+
+        self.complete_active_locations(now);
+        Ok(())
+    }
+
+    /// Cancel an encounter that has not started
+    pub fn cancel(&mut self) -> Result<()> {
+        self.transition(
+            &[
+                EncounterStatus::Planned,
+                EncounterStatus::Arrived,
+                EncounterStatus::Triaged,
+            ],
+            EncounterStatus::Cancelled,
+        )
+    }
+
+    /// Add a participant to the encounter
+    pub fn add_participant(&mut self, participant: EncounterParticipant) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::business_rule_violation(
+                "encounter_closed",
+                "Participants cannot be added to a closed encounter",
+            ));
+        }
+
+        if let Some(individual) = participant.individual {
+            let already_assigned = self.participants.iter().any(|existing| {
+                existing.individual == Some(individual) && existing.type_ == participant.type_
+            });
+            if already_assigned {
+                return Ok(());
+            }
+        }
+
+        self.participants.push(participant);
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Move the encounter to a location, completing the current one
+    pub fn assign_location(&mut self, location: Id) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::business_rule_violation(
+                "encounter_closed",
+                "Locations cannot be assigned to a closed encounter",
+            ));
+        }
+
+        let now = Utc::now();
+        self.complete_active_locations(now);
+        self.location.push(EncounterLocation {
+            location,
+            status: Some(EncounterLocationStatus::Active),
+            period: Some(Period {
+                start: Some(now),
+                end: None,
+            }),
+        });
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Get the location the patient is currently at
+    pub fn current_location(&self) -> Option<&EncounterLocation> {
+        self.location
+            .iter()
+            .rev()
+            .find(|location| location.status == Some(EncounterLocationStatus::Active))
+    }
+
+    /// Check if the encounter has been finished, cancelled, or voided
+    pub fn is_closed(&self) -> bool {
+        matches!(
+            self.status,
+            EncounterStatus::Finished | EncounterStatus::Cancelled | EncounterStatus::EnteredInError
+        )
+    }
+
+    fn transition(&mut self, from: &[EncounterStatus], to: EncounterStatus) -> Result<()> {
+        if !from.contains(&self.status) {
+            return Err(Error::business_rule_violation(
+                "encounter_status_transition",
+                &format!("Cannot move encounter from {:?} to {:?}", self.status, to),
+            ));
+        }
+
+        self.status = to;
+        self.metadata.update();
+        Ok(())
+    }
+
+    fn complete_active_locations(&mut self, now: Timestamp) {
+        for location in &mut self.location {
+            if location.status == Some(EncounterLocationStatus::Active) {
+                location.status = Some(EncounterLocationStatus::Completed);
+                if let Some(period) = &mut location.period {
+                    period.end = Some(now);
+                }
+            }
+        }
+    }
 }
 
 impl Identifiable for Encounter {
@@ -184,10 +401,100 @@ impl Auditable for Encounter {
 impl Validatable for Encounter {
     fn validate(&self) -> Result<()> {
         use validator::Validate;
-        self.validate().map_err(|e| {
+        Validate::validate(self).map_err(|e| {
             Error::validation_error(&format!("Encounter validation failed: {}", e))
         })?;
 
+        if let Some(Period { start: Some(start), end: Some(end) }) = &self.period {
+            if end < start {
+                return Err(Error::validation_error_with_field(
+                    "Encounter period end cannot be before its start",
+                    "period",
+                ));
+            }
+        }
+
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planned_encounter() -> Encounter {
+        Encounter::new(EncounterStatus::Planned, EncounterClass::Ambulatory, uuid::Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_encounter_workflow() {
+        let mut encounter = planned_encounter();
+
+        encounter.check_in().unwrap();
+        assert_eq!(encounter.status, EncounterStatus::Arrived);
+
+        encounter.start().unwrap();
+        assert_eq!(encounter.status, EncounterStatus::InProgress);
+        assert!(encounter.period.as_ref().unwrap().start.is_some());
+
+        encounter.end().unwrap();
+        assert_eq!(encounter.status, EncounterStatus::Finished);
+        assert!(encounter.period.as_ref().unwrap().end.is_some());
+        assert_eq!(encounter.length, Some(0));
+        assert_eq!(encounter.metadata.version, 4);
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        let mut encounter = planned_encounter();
+        assert!(encounter.end().is_err());
+
+        encounter.start().unwrap();
+        assert!(encounter.check_in().is_err());
+        assert!(encounter.cancel().is_err());
+    }
+
+    #[test]
+    fn test_assign_location() {
+        let mut encounter = planned_encounter();
+        let waiting_room = uuid::Uuid::new_v4();
+        let exam_room = uuid::Uuid::new_v4();
+
+        encounter.assign_location(waiting_room).unwrap();
+        encounter.assign_location(exam_room).unwrap();
+
+        assert_eq!(encounter.location.len(), 2);
+        assert_eq!(encounter.location[0].status, Some(EncounterLocationStatus::Completed));
+        assert_eq!(encounter.current_location().unwrap().location, exam_room);
+
+        encounter.start().unwrap();
+        encounter.end().unwrap();
+        assert!(encounter.current_location().is_none());
+        assert!(encounter.assign_location(waiting_room).is_err());
+    }
+
+    #[test]
+    fn test_add_participant() {
+        let mut encounter = planned_encounter();
+        let participant = EncounterParticipant {
+            type_: Some("ATND".to_string()),
+            period: None,
+            individual: Some(uuid::Uuid::new_v4()),
+        };
+
+        encounter.add_participant(participant.clone()).unwrap();
+        encounter.add_participant(participant).unwrap();
+
+        assert_eq!(encounter.participants.len(), 1);
+    }
+
+    #[test]
+    fn test_status_and_class_codes() {
+        for status in [EncounterStatus::Planned, EncounterStatus::InProgress, EncounterStatus::EnteredInError] {
+            assert_eq!(EncounterStatus::from_code(status.code()), Some(status));
+        }
+        assert_eq!(EncounterClass::Outpatient.code(), "AMB");
+        assert!(matches!(EncounterClass::from_code("amb"), Some(EncounterClass::Ambulatory)));
+        assert!(EncounterClass::from_code("OUTP").is_none());
+    }
+}
\ No newline at end of file
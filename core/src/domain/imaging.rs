@@ -0,0 +1,382 @@
+//! Imaging studies
+//!
+//! Images stay in the PACS; the EMR only knows the study metadata the PACS
+//! reports over DICOMweb, as DICOM JSON (PS3.18 F.2). An [`ImagingStudy`] is
+//! read from a QIDO-RS study result and, for the series breakdown, from the
+//! instance metadata WADO-RS returns. Attributes are keyed by their tag as
+//! eight hex digits, e.g. `0020000D` for the Study Instance UID.
+
+use crate::domain::traits::Validatable;
+use crate::types::Id;
+use crate::{Error, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Attribute tags read from DICOM JSON
+pub mod tags {
+    /// Study Date (DA)
+    pub const STUDY_DATE: &str = "00080020";
+    /// Series Date (DA)
+    pub const SERIES_DATE: &str = "00080021";
+    /// Study Time (TM)
+    pub const STUDY_TIME: &str = "00080030";
+    /// Series Time (TM)
+    pub const SERIES_TIME: &str = "00080031";
+    /// Accession Number
+    pub const ACCESSION_NUMBER: &str = "00080050";
+    /// Modality of a series
+    pub const MODALITY: &str = "00080060";
+    /// Modalities in Study
+    pub const MODALITIES_IN_STUDY: &str = "00080061";
+    /// Referring Physician's Name (PN)
+    pub const REFERRING_PHYSICIAN_NAME: &str = "00080090";
+    /// Study Description
+    pub const STUDY_DESCRIPTION: &str = "00081030";
+    /// Series Description
+    pub const SERIES_DESCRIPTION: &str = "0008103E";
+    /// Patient ID
+    pub const PATIENT_ID: &str = "00100020";
+    /// Body Part Examined
+    pub const BODY_PART_EXAMINED: &str = "00180015";
+    /// Study Instance UID
+    pub const STUDY_INSTANCE_UID: &str = "0020000D";
+    /// Series Instance UID
+    pub const SERIES_INSTANCE_UID: &str = "0020000E";
+    /// Series Number
+    pub const SERIES_NUMBER: &str = "00200011";
+    /// Number of Study Related Series
+    pub const NUMBER_OF_STUDY_RELATED_SERIES: &str = "00201206";
+    /// Number of Study Related Instances
+    pub const NUMBER_OF_STUDY_RELATED_INSTANCES: &str = "00201208";
+    /// Number of Series Related Instances
+    pub const NUMBER_OF_SERIES_RELATED_INSTANCES: &str = "00201209";
+}
+
+/// Longest DICOM UID
+const MAX_UID_LENGTH: usize = 64;
+
+/// Whether `uid` is a DICOM UID: dot-separated numbers without leading zeros
+pub fn is_valid_uid(uid: &str) -> bool {
+    uid.len() <= MAX_UID_LENGTH
+        && uid.split('.').all(|component| {
+            !component.is_empty()
+                && component.chars().all(|c| c.is_ascii_digit())
+                && (component == "0" || !component.starts_with('0'))
+        })
+}
+
+/// A series of images within a study
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImagingSeries {
+    /// Series Instance UID
+    pub uid: String,
+    /// Series number within the study
+    pub number: Option<u32>,
+    /// DICOM modality code, e.g. `CT`
+    pub modality: Option<String>,
+    /// Series description
+    pub description: Option<String>,
+    /// Body part examined, as the modality recorded it
+    pub body_site: Option<String>,
+    /// When the series started, in the modality's local time
+    pub started: Option<NaiveDateTime>,
+    /// Number of instances (images) in the series
+    pub number_of_instances: u32,
+}
+
+/// A DICOM study held in the PACS
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImagingStudy {
+    /// Study Instance UID
+    pub study_instance_uid: String,
+    /// Patient the study was matched to
+    pub patient_id: Id,
+    /// Accession number of the imaging order
+    pub accession_number: Option<String>,
+    /// DICOM modality codes of the study's series
+    pub modalities: Vec<String>,
+    /// Study description
+    pub description: Option<String>,
+    /// When the study started, in the modality's local time; DICOM dates and
+    /// times carry no offset
+    pub started: Option<NaiveDateTime>,
+    /// Referring physician, as recorded by the modality
+    pub referring_physician: Option<String>,
+    /// Number of series, as reported by the PACS
+    pub number_of_series: u32,
+    /// Number of instances, as reported by the PACS
+    pub number_of_instances: u32,
+    /// Series in series number order; empty until read from the instance
+    /// metadata
+    #[serde(default)]
+    pub series: Vec<ImagingSeries>,
+}
+
+impl ImagingStudy {
+    /// A study from a QIDO-RS study result in DICOM JSON
+    pub fn from_dicom_json(patient_id: Id, dataset: &Value) -> Result<Self> {
+        let attributes = DicomAttributes::new(dataset)?;
+        let study = Self {
+            study_instance_uid: attributes
+                .string(tags::STUDY_INSTANCE_UID)
+                .ok_or_else(|| Error::validation_error_with_field("Study has no Study Instance UID", "0020000D"))?,
+            patient_id,
+            accession_number: attributes.string(tags::ACCESSION_NUMBER),
+            modalities: attributes.strings(tags::MODALITIES_IN_STUDY),
+            description: attributes.string(tags::STUDY_DESCRIPTION),
+            started: attributes.date_time(tags::STUDY_DATE, tags::STUDY_TIME),
+            referring_physician: attributes.person_name(tags::REFERRING_PHYSICIAN_NAME),
+            number_of_series: attributes.number(tags::NUMBER_OF_STUDY_RELATED_SERIES).unwrap_or(0),
+            number_of_instances: attributes.number(tags::NUMBER_OF_STUDY_RELATED_INSTANCES).unwrap_or(0),
+            series: Vec::new(),
+        };
+        study.validate()?;
+        Ok(study)
+    }
+
+    /// Fill in the series from WADO-RS instance metadata of the study
+    ///
+    /// Instances of other studies are ignored. The counts and modalities are
+    /// recomputed from the instances, which are authoritative over the
+    /// PACS's summary attributes.
+    pub fn with_instances(mut self, instances: &[Value]) -> Result<Self> {
+        let mut series: Vec<ImagingSeries> = Vec::new();
+        for instance in instances {
+            let attributes = DicomAttributes::new(instance)?;
+            if attributes.string(tags::STUDY_INSTANCE_UID).as_deref() != Some(self.study_instance_uid.as_str()) {
+                continue;
+            }
+            let Some(uid) = attributes.string(tags::SERIES_INSTANCE_UID) else {
+                continue;
+            };
+            match series.iter_mut().find(|known| known.uid == uid) {
+                Some(known) => known.number_of_instances += 1,
+                None => series.push(ImagingSeries {
+                    uid,
+                    number: attributes.number(tags::SERIES_NUMBER),
+                    modality: attributes.string(tags::MODALITY),
+                    description: attributes.string(tags::SERIES_DESCRIPTION),
+                    body_site: attributes.string(tags::BODY_PART_EXAMINED),
+                    started: attributes.date_time(tags::SERIES_DATE, tags::SERIES_TIME),
+                    number_of_instances: 1,
+                }),
+            }
+        }
+        if series.is_empty() {
+            return Ok(self);
+        }
+
+        series.sort_by_key(|series| series.number.unwrap_or(u32::MAX));
+        self.number_of_series = series.len() as u32;
+        self.number_of_instances = series.iter().map(|series| series.number_of_instances).sum();
+        for modality in series.iter().filter_map(|series| series.modality.as_ref()) {
+            if !self.modalities.contains(modality) {
+                self.modalities.push(modality.clone());
+            }
+        }
+        self.series = series;
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Link to the study in an image viewer
+    ///
+    /// `template` is the viewer's deep link with `{study_uid}`,
+    /// `{accession_number}` and `{patient_id}` placeholders, e.g.
+    /// `https://viewer.example.com/viewer?StudyInstanceUIDs={study_uid}`.
+    pub fn viewer_url(&self, template: &str, pacs_patient_id: &str) -> String {
+        template
+            .replace("{study_uid}", &self.study_instance_uid)
+            .replace(
+                "{accession_number}",
+                &query_escape(self.accession_number.as_deref().unwrap_or_default()),
+            )
+            .replace("{patient_id}", &query_escape(pacs_patient_id))
+    }
+}
+
+impl Validatable for ImagingStudy {
+    fn validate(&self) -> Result<()> {
+        if !is_valid_uid(&self.study_instance_uid) {
+            return Err(Error::validation_error_with_field(
+                &format!("'{}' is not a valid Study Instance UID", self.study_instance_uid),
+                "study_instance_uid",
+            ));
+        }
+        if let Some(series) = self.series.iter().find(|series| !is_valid_uid(&series.uid)) {
+            return Err(Error::validation_error_with_field(
+                &format!("'{}' is not a valid Series Instance UID", series.uid),
+                "series",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Percent-encode a value for a URL query
+fn query_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+/// Attributes of a DICOM JSON dataset
+///
+/// Each attribute is an object with a `vr` and, unless empty, a `Value`
+/// array; person names are objects with an `Alphabetic` representation.
+struct DicomAttributes<'a> {
+    attributes: &'a serde_json::Map<String, Value>,
+}
+
+impl<'a> DicomAttributes<'a> {
+    fn new(dataset: &'a Value) -> Result<Self> {
+        dataset
+            .as_object()
+            .map(|attributes| Self { attributes })
+            .ok_or_else(|| Error::validation_error("A DICOM JSON dataset must be an object"))
+    }
+
+    fn values(&self, tag: &str) -> &'a [Value] {
+        self.attributes
+            .get(tag)
+            .and_then(|attribute| attribute.get("Value"))
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn strings(&self, tag: &str) -> Vec<String> {
+        self.values(tag)
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn string(&self, tag: &str) -> Option<String> {
+        self.strings(tag).into_iter().next()
+    }
+
+    /// An IS or US value; integer strings arrive as numbers or strings
+    fn number(&self, tag: &str) -> Option<u32> {
+        match self.values(tag).first()? {
+            Value::Number(number) => number.as_u64().and_then(|value| u32::try_from(value).ok()),
+            Value::String(value) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn person_name(&self, tag: &str) -> Option<String> {
+        let alphabetic = self.values(tag).first()?.get("Alphabetic")?.as_str()?;
+        // Components are family^given^middle^prefix^suffix
+        let mut components = alphabetic.split('^').map(str::trim);
+        let family = components.next().filter(|family| !family.is_empty())?;
+        let given: Vec<&str> = components.take(2).filter(|part| !part.is_empty()).collect();
+        if given.is_empty() {
+            Some(family.to_string())
+        } else {
+            Some(format!("{}, {}", family, given.join(" ")))
+        }
+    }
+
+    /// A DA and TM pair; a time of day without a date is dropped
+    fn date_time(&self, date_tag: &str, time_tag: &str) -> Option<NaiveDateTime> {
+        let date = NaiveDate::parse_from_str(&self.string(date_tag)?, "%Y%m%d").ok()?;
+        let time = self.string(time_tag).and_then(|time| parse_time(&time)).unwrap_or(NaiveTime::MIN);
+        Some(date.and_time(time))
+    }
+}
+
+/// A TM value, `HH[MM[SS[.FFFFFF]]]`
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if whole.len() % 2 != 0 || whole.len() > 6 || !whole.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let field = |index: usize| whole.get(index * 2..index * 2 + 2).map_or(Some(0), |field| field.parse().ok());
+    let micros = match fraction {
+        "" => 0,
+        digits => format!("{:0<6}", digits).get(..6)?.parse().ok()?,
+    };
+    NaiveTime::from_hms_micro_opt(field(0)?, field(1)?, field(2)?, micros)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn instance(series_uid: &str, series_number: u32, modality: &str) -> Value {
+        json!({
+            "0020000D": {"vr": "UI", "Value": ["1.2.840.113619.2.55.3"]},
+            "0020000E": {"vr": "UI", "Value": [series_uid]},
+            "00200011": {"vr": "IS", "Value": [series_number]},
+            "00080060": {"vr": "CS", "Value": [modality]}
+        })
+    }
+
+    #[test]
+    fn test_study_from_dicom_json() {
+        let study = ImagingStudy::from_dicom_json(
+            Uuid::new_v4(),
+            &json!({
+                "0020000D": {"vr": "UI", "Value": ["1.2.840.113619.2.55.3"]},
+                "00080050": {"vr": "SH", "Value": ["ACC 42/7"]},
+                "00080061": {"vr": "CS", "Value": ["CT"]},
+                "00081030": {"vr": "LO", "Value": ["CT CHEST W/O CONTRAST"]},
+                "00080020": {"vr": "DA", "Value": ["20260304"]},
+                "00080030": {"vr": "TM", "Value": ["142501.5"]},
+                "00080090": {"vr": "PN", "Value": [{"Alphabetic": "Smith^Jane^Q"}]},
+                "00201206": {"vr": "IS", "Value": ["2"]},
+                "00201208": {"vr": "IS", "Value": [250]},
+                "00100020": {"vr": "LO"}
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(study.accession_number.as_deref(), Some("ACC 42/7"));
+        assert_eq!(study.started.unwrap().to_string(), "2026-03-04 14:25:01.500");
+        assert_eq!(study.referring_physician.as_deref(), Some("Smith, Jane Q"));
+        assert_eq!((study.number_of_series, study.number_of_instances), (2, 250));
+        assert_eq!(
+            study.viewer_url("https://viewer.example.com/?study={study_uid}&acc={accession_number}", "MRN1"),
+            "https://viewer.example.com/?study=1.2.840.113619.2.55.3&acc=ACC%2042%2F7"
+        );
+
+        let study = study
+            .with_instances(&[
+                instance("1.2.3.2", 2, "SR"),
+                instance("1.2.3.1", 1, "CT"),
+                instance("1.2.3.1", 1, "CT"),
+                json!({"0020000D": {"vr": "UI", "Value": ["9.9"]}, "0020000E": {"vr": "UI", "Value": ["9.9.1"]}}),
+            ])
+            .unwrap();
+        assert_eq!((study.number_of_series, study.number_of_instances), (2, 3));
+        assert_eq!((study.series[0].uid.as_str(), study.series[0].number_of_instances), ("1.2.3.1", 2));
+        assert_eq!(study.modalities, vec!["CT", "SR"]);
+
+        assert!(ImagingStudy::from_dicom_json(Uuid::new_v4(), &json!({})).is_err());
+        assert!(ImagingStudy::from_dicom_json(
+            Uuid::new_v4(),
+            &json!({"0020000D": {"vr": "UI", "Value": ["1.02.3"]}})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("0930"), NaiveTime::from_hms_opt(9, 30, 0));
+        assert_eq!(parse_time("235959.123456"), NaiveTime::from_hms_micro_opt(23, 59, 59, 123456));
+        assert_eq!(parse_time("093"), None);
+        assert_eq!(parse_time("2500"), None);
+    }
+}
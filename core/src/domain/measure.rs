@@ -0,0 +1,253 @@
+//! Clinical quality measures
+//!
+//! A [`QualityMeasure`] is a basic eCQM-style proportion measure built from
+//! the same criteria as patient panels: an initial population, the
+//! denominator within it, exclusions taken out of the denominator, and the
+//! numerator of denominator patients who met the measure. Measures are
+//! computed per reporting period by the jobs worker into a
+//! [`MeasureReport`], which keeps each patient's populations for drill-down.
+//! Criteria are evaluated as of the end of the reporting period.
+
+use crate::domain::panel::{PanelCriterion, MAX_CRITERIA};
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Population of a measure a patient falls in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasurePopulation {
+    /// Patients the measure is about
+    InitialPopulation,
+    /// Initial population patients eligible for the measure
+    Denominator,
+    /// Denominator patients taken out of the measure, e.g. in hospice
+    DenominatorExclusion,
+    /// Remaining denominator patients who met the measure
+    Numerator,
+}
+
+impl MeasurePopulation {
+    /// All populations, outermost first
+    pub const ALL: [MeasurePopulation; 4] = [
+        MeasurePopulation::InitialPopulation,
+        MeasurePopulation::Denominator,
+        MeasurePopulation::DenominatorExclusion,
+        MeasurePopulation::Numerator,
+    ];
+
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MeasurePopulation::InitialPopulation => "initial_population",
+            MeasurePopulation::Denominator => "denominator",
+            MeasurePopulation::DenominatorExclusion => "denominator_exclusion",
+            MeasurePopulation::Numerator => "numerator",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|population| population.as_str() == value)
+    }
+}
+
+/// Whether a higher or lower rate is better
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImprovementNotation {
+    /// More patients meeting the numerator is better, e.g. screenings done
+    Increase,
+    /// Fewer is better, e.g. poorly controlled HbA1c (CMS122)
+    Decrease,
+}
+
+impl ImprovementNotation {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImprovementNotation::Increase => "increase",
+            ImprovementNotation::Decrease => "decrease",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "increase" => Some(ImprovementNotation::Increase),
+            "decrease" => Some(ImprovementNotation::Decrease),
+            _ => None,
+        }
+    }
+}
+
+/// A proportion quality measure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityMeasure {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Published identifier, e.g. `CMS122v12`; unique
+    pub identifier: String,
+    /// Title shown on reports
+    pub title: String,
+    /// What the measure checks
+    pub description: Option<String>,
+    /// Whether a higher or lower rate is better
+    pub improvement: ImprovementNotation,
+    /// All of these hold for every patient the measure is about
+    pub initial_population: Vec<PanelCriterion>,
+    /// All of these further hold for denominator patients; none means the
+    /// whole initial population
+    pub denominator: Vec<PanelCriterion>,
+    /// Denominator patients meeting any of these are excluded
+    pub denominator_exclusions: Vec<PanelCriterion>,
+    /// All of these hold for numerator patients
+    pub numerator: Vec<PanelCriterion>,
+    /// Whether reports are computed for the measure
+    pub active: bool,
+}
+
+impl QualityMeasure {
+    /// Create an active measure
+    pub fn new(
+        identifier: &str,
+        title: &str,
+        initial_population: Vec<PanelCriterion>,
+        numerator: Vec<PanelCriterion>,
+    ) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            identifier: identifier.trim().to_string(),
+            title: title.trim().to_string(),
+            description: None,
+            improvement: ImprovementNotation::Increase,
+            initial_population,
+            denominator: Vec::new(),
+            denominator_exclusions: Vec::new(),
+            numerator,
+            active: true,
+        }
+    }
+}
+
+impl Validatable for QualityMeasure {
+    fn validate(&self) -> Result<()> {
+        if self.identifier.is_empty() || self.identifier.len() > 50 {
+            return Err(Error::validation_error_with_field("Measure identifiers need 1 to 50 characters", "identifier"));
+        }
+        if self.title.is_empty() {
+            return Err(Error::validation_error_with_field("Measures need a title", "title"));
+        }
+        let populations = [
+            ("initial_population", &self.initial_population),
+            ("denominator", &self.denominator),
+            ("denominator_exclusions", &self.denominator_exclusions),
+            ("numerator", &self.numerator),
+        ];
+        for (field, criteria) in populations {
+            if criteria.len() > MAX_CRITERIA {
+                return Err(Error::validation_error_with_field(
+                    &format!("Populations combine at most {} criteria", MAX_CRITERIA),
+                    field,
+                ));
+            }
+            criteria.iter().try_for_each(PanelCriterion::validate)?;
+        }
+        if self.initial_population.is_empty() {
+            return Err(Error::validation_error_with_field(
+                "Measures need initial population criteria",
+                "initial_population",
+            ));
+        }
+        if self.numerator.is_empty() {
+            return Err(Error::validation_error_with_field("Measures need numerator criteria", "numerator"));
+        }
+        Ok(())
+    }
+}
+
+/// A measure computed for a reporting period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeasureReport {
+    /// Report id
+    pub id: Id,
+    /// Measure computed
+    pub measure_id: Id,
+    /// Start of the reporting period
+    pub period_start: Timestamp,
+    /// End of the reporting period; criteria are evaluated as of this time
+    pub period_end: Timestamp,
+    /// Patients in the initial population
+    pub initial_population: i64,
+    /// Patients in the denominator, exclusions included
+    pub denominator: i64,
+    /// Denominator patients excluded
+    pub denominator_exclusions: i64,
+    /// Patients in the numerator
+    pub numerator: i64,
+    /// When the report was computed
+    pub computed_at: Timestamp,
+}
+
+impl MeasureReport {
+    /// Share of the denominator, less exclusions, in the numerator; `None`
+    /// when no patient is left to measure
+    pub fn performance_rate(&self) -> Option<f64> {
+        let eligible = self.denominator - self.denominator_exclusions;
+        (eligible > 0).then(|| self.numerator as f64 / eligible as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn diabetics() -> Vec<PanelCriterion> {
+        vec![PanelCriterion::Condition {
+            code: "E11".to_string(),
+            within_days: None,
+        }]
+    }
+
+    #[test]
+    fn test_measure_validation() {
+        let mut measure = QualityMeasure::new("CMS122v12", "Diabetes: HbA1c poor control", diabetics(), vec![]);
+        assert!(measure.validate().is_err(), "numerator criteria are required");
+
+        measure.numerator = vec![PanelCriterion::SeenWithin { days: 365 }];
+        assert!(measure.validate().is_ok());
+
+        measure.denominator_exclusions = vec![PanelCriterion::SeenWithin { days: 0 }];
+        assert!(measure.validate().is_err());
+    }
+
+    #[test]
+    fn test_performance_rate() {
+        let mut report = MeasureReport {
+            id: Uuid::new_v4(),
+            measure_id: Uuid::new_v4(),
+            period_start: Utc::now(),
+            period_end: Utc::now(),
+            initial_population: 120,
+            denominator: 100,
+            denominator_exclusions: 20,
+            numerator: 60,
+            computed_at: Utc::now(),
+        };
+        assert_eq!(report.performance_rate(), Some(0.75));
+
+        report.denominator_exclusions = 100;
+        assert_eq!(report.performance_rate(), None);
+    }
+
+    #[test]
+    fn test_population_names() {
+        for population in MeasurePopulation::ALL {
+            assert_eq!(MeasurePopulation::parse(population.as_str()), Some(population));
+        }
+        assert_eq!(MeasurePopulation::parse("gap"), None);
+    }
+}
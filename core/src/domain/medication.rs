@@ -0,0 +1,710 @@
+//! Medication orders and the medication administration record (MAR)
+//!
+//! A [`MedicationRequest`] orders a dose of a medication either on a fixed
+//! schedule (every `frequency_hours` from `start`) or as needed. Each dose a
+//! nurse gives, holds or the patient refuses is documented as a
+//! [`MedicationAdministration`] against the request. Scheduled doses must be
+//! given within the [`AdministrationPolicy`] window around their scheduled
+//! time, each scheduled dose is documented once, and as-needed doses respect
+//! the minimum interval. [`mar`] lays both out as the MAR grid for an
+//! encounter.
+
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// RxNorm, the system of medication codes
+pub const RXNORM_SYSTEM: &str = "http://www.nlm.nih.gov/research/umls/rxnorm";
+
+/// Medication order status (FHIR `MedicationRequest.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MedicationRequestStatus {
+    /// Being written, not yet actionable
+    Draft,
+    /// Placed and actionable
+    Active,
+    /// Paused; doses are not due
+    OnHold,
+    /// Withdrawn before any dose was given
+    Cancelled,
+    /// All doses given
+    Completed,
+    /// Discontinued after doses were given
+    Stopped,
+    /// Placed in error
+    EnteredInError,
+}
+
+impl MedicationRequestStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MedicationRequestStatus::Draft => "draft",
+            MedicationRequestStatus::Active => "active",
+            MedicationRequestStatus::OnHold => "on-hold",
+            MedicationRequestStatus::Cancelled => "cancelled",
+            MedicationRequestStatus::Completed => "completed",
+            MedicationRequestStatus::Stopped => "stopped",
+            MedicationRequestStatus::EnteredInError => "entered-in-error",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "draft" => Some(MedicationRequestStatus::Draft),
+            "active" => Some(MedicationRequestStatus::Active),
+            "on-hold" => Some(MedicationRequestStatus::OnHold),
+            "cancelled" => Some(MedicationRequestStatus::Cancelled),
+            "completed" => Some(MedicationRequestStatus::Completed),
+            "stopped" => Some(MedicationRequestStatus::Stopped),
+            "entered-in-error" => Some(MedicationRequestStatus::EnteredInError),
+            _ => None,
+        }
+    }
+
+    /// Whether an order may move from this status to `next`
+    pub fn can_transition_to(&self, next: MedicationRequestStatus) -> bool {
+        use MedicationRequestStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Active)
+                | (Draft, Cancelled)
+                | (Draft, EnteredInError)
+                | (Active, OnHold)
+                | (Active, Cancelled)
+                | (Active, Completed)
+                | (Active, Stopped)
+                | (Active, EnteredInError)
+                | (OnHold, Active)
+                | (OnHold, Stopped)
+                | (OnHold, EnteredInError)
+        )
+    }
+}
+
+/// An order to give a patient a medication
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicationRequest {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Patient the medication is for
+    pub patient_id: Id,
+    /// Encounter the order was placed in
+    pub encounter_id: Option<Id>,
+    /// RxNorm code of the medication
+    pub medication_code: String,
+    /// Human-readable medication name
+    pub medication_display: Option<String>,
+    /// Product identifiers (NDC or GTIN) that fill the order, as printed in
+    /// the product barcodes
+    pub product_identifiers: Vec<String>,
+    /// Amount per dose
+    pub dose_quantity: f64,
+    /// UCUM unit of `dose_quantity`
+    pub dose_unit: String,
+    /// Route of administration, e.g. `oral`
+    pub route: Option<String>,
+    /// Hours between scheduled doses; for as-needed orders, the minimum
+    /// hours between doses
+    pub frequency_hours: Option<u32>,
+    /// Given when needed rather than on a schedule
+    pub as_needed: bool,
+    /// First scheduled dose
+    pub start: Timestamp,
+    /// No doses are due from this time on
+    pub end: Option<Timestamp>,
+    /// Order status
+    pub status: MedicationRequestStatus,
+    /// Practitioner placing the order
+    pub requester_id: Id,
+    /// Clinical reason for the order
+    pub reason: Option<String>,
+    /// When the order was placed
+    pub authored_on: Timestamp,
+}
+
+impl MedicationRequest {
+    /// New active order for a scheduled dose every `frequency_hours` from `start`
+    pub fn new(
+        patient_id: Id,
+        requester_id: Id,
+        medication_code: &str,
+        dose_quantity: f64,
+        dose_unit: &str,
+        frequency_hours: u32,
+        start: Timestamp,
+    ) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            patient_id,
+            encounter_id: None,
+            medication_code: medication_code.to_string(),
+            medication_display: None,
+            product_identifiers: Vec::new(),
+            dose_quantity,
+            dose_unit: dose_unit.to_string(),
+            route: None,
+            frequency_hours: Some(frequency_hours),
+            as_needed: false,
+            start,
+            end: None,
+            status: MedicationRequestStatus::Active,
+            requester_id,
+            reason: None,
+            authored_on: Utc::now(),
+        }
+    }
+
+    /// Move the order to another status
+    pub fn transition(&mut self, next: MedicationRequestStatus) -> Result<()> {
+        if !self.status.can_transition_to(next) {
+            return Err(Error::business_rule_violation(
+                "invalid_medication_request_transition",
+                &format!(
+                    "Medication orders cannot move from {} to {}",
+                    self.status.as_str(),
+                    next.as_str()
+                ),
+            ));
+        }
+
+        self.status = next;
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Scheduled dose times from `from` to `to`, inclusive
+    ///
+    /// As-needed orders have no scheduled doses.
+    pub fn scheduled_times(&self, from: Timestamp, to: Timestamp) -> Vec<Timestamp> {
+        let Some(hours) = self.frequency_hours.filter(|hours| *hours > 0 && !self.as_needed) else {
+            return Vec::new();
+        };
+        let interval = Duration::hours(i64::from(hours));
+        let to = self.end.map_or(to, |end| to.min(end - Duration::seconds(1)));
+        if to < self.start {
+            return Vec::new();
+        }
+
+        // First dose at or after `from`
+        let skipped = if from > self.start {
+            ((from - self.start).num_seconds() + interval.num_seconds() - 1) / interval.num_seconds()
+        } else {
+            0
+        };
+        let mut times = Vec::new();
+        let mut time = self.start + interval * skipped as i32;
+        while time <= to {
+            times.push(time);
+            time += interval;
+        }
+        times
+    }
+
+    /// Whether a dose is scheduled at `time`
+    pub fn is_scheduled_at(&self, time: Timestamp) -> bool {
+        self.scheduled_times(time, time).first() == Some(&time)
+    }
+}
+
+impl Validatable for MedicationRequest {
+    fn validate(&self) -> Result<()> {
+        if self.medication_code.trim().is_empty() {
+            return Err(Error::validation_error_with_field(
+                "Medication orders need a medication code",
+                "medication_code",
+            ));
+        }
+        if !(self.dose_quantity.is_finite() && self.dose_quantity > 0.0) || self.dose_unit.trim().is_empty() {
+            return Err(Error::validation_error_with_field(
+                "Medication orders need a positive dose with a unit",
+                "dose_quantity",
+            ));
+        }
+        if self.frequency_hours == Some(0) || (!self.as_needed && self.frequency_hours.is_none()) {
+            return Err(Error::validation_error_with_field(
+                "Scheduled medication orders need a frequency of at least one hour",
+                "frequency_hours",
+            ));
+        }
+        if self.end.is_some_and(|end| end <= self.start) {
+            return Err(Error::validation_error_with_field("The order must end after it starts", "end"));
+        }
+        Ok(())
+    }
+}
+
+/// What happened to a dose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdministrationOutcome {
+    /// The dose was given
+    Given,
+    /// A clinician withheld the dose
+    Held,
+    /// The patient refused the dose
+    Refused,
+}
+
+impl AdministrationOutcome {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdministrationOutcome::Given => "given",
+            AdministrationOutcome::Held => "held",
+            AdministrationOutcome::Refused => "refused",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "given" => Some(AdministrationOutcome::Given),
+            "held" => Some(AdministrationOutcome::Held),
+            "refused" => Some(AdministrationOutcome::Refused),
+            _ => None,
+        }
+    }
+
+    /// FHIR `MedicationAdministration.status`
+    pub fn fhir_status(&self) -> &'static str {
+        match self {
+            AdministrationOutcome::Given => "completed",
+            AdministrationOutcome::Held | AdministrationOutcome::Refused => "not-done",
+        }
+    }
+}
+
+/// Barcodes scanned at the bedside before giving a dose
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BarcodeScan {
+    /// Contents of the patient's wristband barcode
+    pub patient: String,
+    /// Contents of the medication package barcode
+    pub medication: String,
+}
+
+/// A bedside scan that matched the patient and the order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BarcodeVerification {
+    /// The scanned barcodes
+    pub scan: BarcodeScan,
+    /// When the scan was checked
+    pub verified_at: Timestamp,
+}
+
+/// Rules for documenting doses
+#[derive(Debug, Clone)]
+pub struct AdministrationPolicy {
+    /// How far a scheduled dose may be given from its scheduled time
+    pub window: Duration,
+    /// Whether given doses need a barcode scan or a documented override
+    pub require_barcode: bool,
+}
+
+impl Default for AdministrationPolicy {
+    fn default() -> Self {
+        Self {
+            window: Duration::minutes(60),
+            require_barcode: true,
+        }
+    }
+}
+
+/// A dose given, held or refused (FHIR `MedicationAdministration`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicationAdministration {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Order the dose belongs to
+    pub request_id: Id,
+    /// Patient the dose is for
+    pub patient_id: Id,
+    /// Encounter the dose was documented in
+    pub encounter_id: Option<Id>,
+    /// What happened to the dose
+    pub outcome: AdministrationOutcome,
+    /// Scheduled time of the dose; `None` for as-needed doses
+    pub scheduled_for: Option<Timestamp>,
+    /// When the dose was given, held or refused
+    pub occurred_at: Timestamp,
+    /// Clinician documenting the dose
+    pub performer_id: Id,
+    /// Amount given, when it differs from the ordered dose
+    pub dose_quantity: Option<f64>,
+    /// UCUM unit of `dose_quantity`
+    pub dose_unit: Option<String>,
+    /// Why the dose was held or refused, or why an as-needed dose was given
+    pub reason: Option<String>,
+    /// Free-text note
+    pub note: Option<String>,
+    /// The bedside barcode scan, when one was done
+    pub barcode: Option<BarcodeVerification>,
+    /// Why a given dose was documented without a barcode scan
+    pub barcode_override: Option<String>,
+}
+
+impl MedicationAdministration {
+    /// New dose documentation against an order
+    pub fn new(
+        request: &MedicationRequest,
+        performer_id: Id,
+        outcome: AdministrationOutcome,
+        scheduled_for: Option<Timestamp>,
+        occurred_at: Timestamp,
+    ) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            request_id: request.metadata.id,
+            patient_id: request.patient_id,
+            encounter_id: request.encounter_id,
+            outcome,
+            scheduled_for,
+            occurred_at,
+            performer_id,
+            dose_quantity: None,
+            dose_unit: None,
+            reason: None,
+            note: None,
+            barcode: None,
+            barcode_override: None,
+        }
+    }
+
+    /// Check the dose against its order, the doses already documented for
+    /// the order, and the policy
+    pub fn check(
+        &self,
+        request: &MedicationRequest,
+        previous: &[MedicationAdministration],
+        policy: &AdministrationPolicy,
+        now: Timestamp,
+    ) -> Result<()> {
+        self.validate()?;
+        if self.request_id != request.metadata.id || self.patient_id != request.patient_id {
+            return Err(Error::validation_error_with_field(
+                "The dose does not belong to this order",
+                "request_id",
+            ));
+        }
+        if request.status != MedicationRequestStatus::Active {
+            return Err(Error::business_rule_violation(
+                "medication_request_not_active",
+                &format!("Doses cannot be documented on a {} order", request.status.as_str()),
+            ));
+        }
+        if self.occurred_at > now + Duration::minutes(1) {
+            return Err(Error::validation_error_with_field(
+                "Doses cannot be documented in the future",
+                "occurred_at",
+            ));
+        }
+        let after_end = request.end.is_some_and(|end| self.occurred_at > end + policy.window);
+        if self.occurred_at < request.start - policy.window || after_end {
+            return Err(Error::business_rule_violation(
+                "administration_outside_order",
+                "The dose falls outside the order's start and end",
+            ));
+        }
+
+        if request.as_needed {
+            self.check_as_needed(request, previous)?;
+        } else {
+            self.check_scheduled(request, previous, policy)?;
+        }
+
+        if self.outcome == AdministrationOutcome::Given
+            && policy.require_barcode
+            && self.barcode.is_none()
+            && self.barcode_override.as_deref().map_or(true, |reason| reason.trim().is_empty())
+        {
+            return Err(Error::business_rule_violation(
+                "barcode_verification_required",
+                "Scan the patient and medication barcodes, or document why the scan was skipped",
+            ));
+        }
+        Ok(())
+    }
+
+    /// A scheduled dose is due at a scheduled time, is documented once, and
+    /// is given within the window
+    fn check_scheduled(
+        &self,
+        request: &MedicationRequest,
+        previous: &[MedicationAdministration],
+        policy: &AdministrationPolicy,
+    ) -> Result<()> {
+        let scheduled_for = self.scheduled_for.ok_or_else(|| {
+            Error::validation_error_with_field("Scheduled orders need the dose's scheduled time", "scheduled_for")
+        })?;
+        if !request.is_scheduled_at(scheduled_for) {
+            return Err(Error::validation_error_with_field(
+                "No dose of this order is scheduled at that time",
+                "scheduled_for",
+            ));
+        }
+        if previous.iter().any(|dose| dose.scheduled_for == Some(scheduled_for)) {
+            return Err(Error::business_rule_violation(
+                "dose_already_documented",
+                "This dose has already been documented",
+            ));
+        }
+        if self.outcome == AdministrationOutcome::Given && (self.occurred_at - scheduled_for).abs() > policy.window {
+            return Err(Error::business_rule_violation(
+                "administration_outside_window",
+                &format!(
+                    "Scheduled doses must be given within {} minutes of their scheduled time",
+                    policy.window.num_minutes()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// As-needed doses have no scheduled time and keep the order's minimum
+    /// interval from the previous dose given
+    fn check_as_needed(&self, request: &MedicationRequest, previous: &[MedicationAdministration]) -> Result<()> {
+        if self.scheduled_for.is_some() {
+            return Err(Error::validation_error_with_field(
+                "As-needed doses have no scheduled time",
+                "scheduled_for",
+            ));
+        }
+        let (Some(hours), AdministrationOutcome::Given) = (request.frequency_hours, self.outcome) else {
+            return Ok(());
+        };
+        let last_given = previous
+            .iter()
+            .filter(|dose| dose.outcome == AdministrationOutcome::Given)
+            .map(|dose| dose.occurred_at)
+            .max();
+        if let Some(last_given) = last_given {
+            let next_allowed = last_given + Duration::hours(i64::from(hours));
+            if self.occurred_at < next_allowed {
+                return Err(Error::business_rule_violation(
+                    "as_needed_interval_not_elapsed",
+                    &format!("The next dose may be given from {}", next_allowed.to_rfc3339()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validatable for MedicationAdministration {
+    fn validate(&self) -> Result<()> {
+        let has_reason = self.reason.as_deref().is_some_and(|reason| !reason.trim().is_empty());
+        if self.outcome != AdministrationOutcome::Given && !has_reason {
+            return Err(Error::validation_error_with_field(
+                "Held and refused doses need a reason",
+                "reason",
+            ));
+        }
+        if self.dose_quantity.is_some_and(|dose| !(dose.is_finite() && dose > 0.0)) {
+            return Err(Error::validation_error_with_field("The dose given must be positive", "dose_quantity"));
+        }
+        if self.dose_quantity.is_some() && self.dose_unit.as_deref().map_or(true, |unit| unit.trim().is_empty()) {
+            return Err(Error::validation_error_with_field("The dose given needs a unit", "dose_unit"));
+        }
+        Ok(())
+    }
+}
+
+/// State of one cell of the MAR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DoseState {
+    /// Given
+    Given,
+    /// Held
+    Held,
+    /// Refused
+    Refused,
+    /// Scheduled later than the window from now
+    Upcoming,
+    /// Inside the window around its scheduled time
+    Due,
+    /// Past the window without documentation
+    Missed,
+}
+
+/// One dose on the MAR
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarDose {
+    /// Scheduled time; `None` for as-needed doses
+    pub scheduled_for: Option<Timestamp>,
+    /// Dose state
+    pub state: DoseState,
+    /// The documentation, once there is any
+    pub administration: Option<MedicationAdministration>,
+}
+
+/// One order's row on the MAR
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarRow {
+    /// The order
+    pub request: MedicationRequest,
+    /// Doses in the period, by time
+    pub doses: Vec<MarDose>,
+}
+
+/// The MAR for a period: a row per order with its scheduled doses and any
+/// documented dose in the period
+///
+/// Only active and on-hold orders get undocumented scheduled doses, and an
+/// on-hold order's doses are not due. Documented doses always show, so a
+/// stopped order keeps its history.
+pub fn mar(
+    requests: Vec<MedicationRequest>,
+    administrations: &[MedicationAdministration],
+    from: Timestamp,
+    to: Timestamp,
+    policy: &AdministrationPolicy,
+    now: Timestamp,
+) -> Vec<MarRow> {
+    requests
+        .into_iter()
+        .map(|request| {
+            let documented: Vec<&MedicationAdministration> = administrations
+                .iter()
+                .filter(|dose| dose.request_id == request.metadata.id)
+                .collect();
+            let in_period = |time: Timestamp| time >= from && time <= to;
+
+            let mut doses: Vec<MarDose> = documented
+                .iter()
+                .filter(|dose| in_period(dose.scheduled_for.unwrap_or(dose.occurred_at)))
+                .map(|dose| MarDose {
+                    scheduled_for: dose.scheduled_for,
+                    state: match dose.outcome {
+                        AdministrationOutcome::Given => DoseState::Given,
+                        AdministrationOutcome::Held => DoseState::Held,
+                        AdministrationOutcome::Refused => DoseState::Refused,
+                    },
+                    administration: Some((*dose).clone()),
+                })
+                .collect();
+
+            if matches!(request.status, MedicationRequestStatus::Active | MedicationRequestStatus::OnHold) {
+                for time in request.scheduled_times(from, to) {
+                    if documented.iter().any(|dose| dose.scheduled_for == Some(time)) {
+                        continue;
+                    }
+                    let state = if now > time + policy.window {
+                        DoseState::Missed
+                    } else if now < time - policy.window || request.status == MedicationRequestStatus::OnHold {
+                        DoseState::Upcoming
+                    } else {
+                        DoseState::Due
+                    };
+                    doses.push(MarDose {
+                        scheduled_for: Some(time),
+                        state,
+                        administration: None,
+                    });
+                }
+            }
+
+            doses.sort_by_key(|dose| {
+                dose.scheduled_for
+                    .or(dose.administration.as_ref().map(|administration| administration.occurred_at))
+            });
+            MarRow { request, doses }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn at(hour: u32, minute: u32) -> Timestamp {
+        Utc.with_ymd_and_hms(2024, 5, 1, hour, minute, 0).unwrap()
+    }
+
+    /// Acetaminophen 650 mg every 6 hours from 06:00
+    fn every_six_hours() -> MedicationRequest {
+        MedicationRequest::new(Uuid::new_v4(), Uuid::new_v4(), "313782", 650.0, "mg", 6, at(6, 0))
+    }
+
+    fn given(request: &MedicationRequest, scheduled_for: Option<Timestamp>, occurred_at: Timestamp) -> MedicationAdministration {
+        let mut dose = MedicationAdministration::new(
+            request,
+            Uuid::new_v4(),
+            AdministrationOutcome::Given,
+            scheduled_for,
+            occurred_at,
+        );
+        dose.barcode_override = Some("Scanner down".to_string());
+        dose
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut request = every_six_hours();
+        assert_eq!(request.scheduled_times(at(7, 0), at(18, 0)), vec![at(12, 0), at(18, 0)]);
+        assert!(request.is_scheduled_at(at(6, 0)));
+        assert!(!request.is_scheduled_at(at(9, 0)));
+
+        request.end = Some(at(18, 0));
+        assert_eq!(request.scheduled_times(at(0, 0), at(23, 0)), vec![at(6, 0), at(12, 0)]);
+        request.as_needed = true;
+        assert!(request.scheduled_times(at(0, 0), at(23, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_scheduled_dose_rules() {
+        let request = every_six_hours();
+        let policy = AdministrationPolicy::default();
+        let now = at(13, 0);
+
+        let on_time = given(&request, Some(at(12, 0)), at(12, 40));
+        on_time.check(&request, &[], &policy, now).unwrap();
+
+        let late = given(&request, Some(at(12, 0)), at(13, 1));
+        assert!(late.check(&request, &[], &policy, at(13, 5)).is_err());
+        assert!(on_time.check(&request, std::slice::from_ref(&on_time), &policy, now).is_err(), "documented twice");
+        assert!(given(&request, Some(at(9, 0)), at(9, 0)).check(&request, &[], &policy, now).is_err());
+
+        let mut unscanned = given(&request, Some(at(12, 0)), at(12, 0));
+        unscanned.barcode_override = None;
+        assert!(unscanned.check(&request, &[], &policy, now).is_err());
+
+        let held_outcome = AdministrationOutcome::Held;
+        let mut held = MedicationAdministration::new(&request, Uuid::new_v4(), held_outcome, Some(at(12, 0)), at(10, 0));
+        assert!(held.check(&request, &[], &policy, now).is_err(), "held doses need a reason");
+        held.reason = Some("NPO for surgery".to_string());
+        held.check(&request, &[], &policy, now).unwrap();
+    }
+
+    #[test]
+    fn test_as_needed_interval() {
+        let mut request = every_six_hours();
+        request.as_needed = true;
+        request.frequency_hours = Some(4);
+        let policy = AdministrationPolicy::default();
+
+        let first = given(&request, None, at(8, 0));
+        first.check(&request, &[], &policy, at(8, 0)).unwrap();
+        let early = given(&request, None, at(11, 0));
+        assert!(early.check(&request, std::slice::from_ref(&first), &policy, at(11, 0)).is_err());
+        given(&request, None, at(12, 0)).check(&request, &[first], &policy, at(12, 0)).unwrap();
+    }
+
+    #[test]
+    fn test_mar() {
+        let request = every_six_hours();
+        let morning = given(&request, Some(at(6, 0)), at(6, 10));
+        let policy = AdministrationPolicy::default();
+        let rows = mar(vec![request], &[morning], at(0, 0), at(23, 59), &policy, at(12, 30));
+
+        let states: Vec<DoseState> = rows[0].doses.iter().map(|dose| dose.state).collect();
+        assert_eq!(states, vec![DoseState::Given, DoseState::Due, DoseState::Upcoming]);
+    }
+}
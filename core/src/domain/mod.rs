@@ -5,13 +5,53 @@ pub mod organization;
 pub mod practitioner;
 pub mod encounter;
 pub mod observation;
+pub mod vitals;
+pub mod units;
+pub mod waveform;
+pub mod verification;
+pub mod provenance;
+pub mod communication;
+pub mod clinical_note;
+pub mod service_request;
+pub mod acknowledgment;
+pub mod care_team;
+pub mod referral;
+pub mod questionnaire;
+pub mod medication;
+pub mod coding;
+pub mod coverage;
+pub mod charge;
+pub mod imaging;
+pub mod panel;
+pub mod measure;
+pub mod report;
+pub mod confidentiality;
 
 pub use patient::*;
 pub use organization::*;
 pub use practitioner::*;
 pub use encounter::*;
 pub use observation::*;
-
+pub use vitals::*;
+pub use waveform::{SampledDataEncoding, Waveform};
+pub use verification::*;
+pub use provenance::*;
+pub use communication::*;
+pub use clinical_note::*;
+pub use service_request::*;
+pub use acknowledgment::*;
+pub use care_team::*;
+pub use referral::*;
+pub use questionnaire::*;
+pub use medication::*;
+pub use coding::*;
+pub use coverage::*;
+pub use charge::*;
+pub use imaging::{ImagingSeries, ImagingStudy};
+pub use panel::{Comparator, PanelCriterion, PanelRefresh, PatientPanel};
+pub use measure::{ImprovementNotation, MeasurePopulation, MeasureReport, QualityMeasure};
+pub use report::{ReportCadence, ReportFormat, ReportPeriod, ReportRun, ReportRunStatus, ReportSchedule};
+pub use confidentiality::{Confidentiality, CONFIDENTIALITY_SYSTEM};
 /// Common domain traits
 pub mod traits {
     use crate::types::{Id, Timestamp};
@@ -19,24 +59,31 @@ pub mod traits {
 
     /// Trait for entities that can be identified
     pub trait Identifiable {
+        /// Identifier of the entity
         fn id(&self) -> Id;
     }
 
     /// Trait for entities that have audit information
     pub trait Auditable {
+        /// When the entity was created
         fn created_at(&self) -> Timestamp;
+        /// When the entity was last updated
         fn updated_at(&self) -> Timestamp;
+        /// Version, incremented on every update
         fn version(&self) -> u64;
     }
 
     /// Trait for entities that can be validated
     pub trait Validatable {
+        /// Check the entity's invariants
         fn validate(&self) -> Result<()>;
     }
 
     /// Trait for entities that can be converted to FHIR resources
     pub trait FhirConvertible<T> {
+        /// Convert to the FHIR representation
         fn to_fhir(&self) -> Result<T>;
+        /// Build the entity from its FHIR representation
         fn from_fhir(resource: T) -> Result<Self>
         where
             Self: Sized;
@@ -45,81 +92,139 @@ pub mod traits {
 
 /// Value objects used across the domain
 pub mod values {
+    use crate::types::Timestamp;
     use serde::{Deserialize, Serialize};
     use validator::Validate;
 
     /// Human name representation
     #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
     pub struct HumanName {
+        /// Given names, in order
         #[validate(length(min = 1, max = 100))]
         pub given: Vec<String>,
+        /// Family name
         #[validate(length(min = 1, max = 100))]
         pub family: String,
+        /// Prefix, such as a title
         pub prefix: Option<String>,
+        /// Suffix, such as a qualification
         pub suffix: Option<String>,
+        /// Purpose of the name
         pub use_: Option<NameUse>,
     }
 
     /// Name use types
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum NameUse {
+        /// Name in everyday use
         Usual,
+        /// Formal or registered name
         Official,
+        /// Temporary name
         Temp,
+        /// Nickname
         Nickname,
+        /// Anonymous name
         Anonymous,
+        /// Name no longer in use
         Old,
+        /// Name used before marriage
         Maiden,
     }
 
     /// Contact information
     #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
     pub struct ContactPoint {
+        /// Kind of contact point
         pub system: ContactSystem,
+        /// Number, address or URL
         #[validate(length(min = 1, max = 100))]
         pub value: String,
+        /// Purpose of the contact point
         pub use_: Option<ContactUse>,
+        /// Preference order; 1 is most preferred
         pub rank: Option<u32>,
+        /// When the patient proved control of this channel
+        #[serde(default)]
+        pub verified_at: Option<Timestamp>,
+    }
+
+    impl ContactPoint {
+        /// Whether codes can be delivered to this contact point
+        pub fn is_verifiable(&self) -> bool {
+            matches!(self.system, ContactSystem::Phone | ContactSystem::Sms | ContactSystem::Email)
+        }
+
+        /// Whether the contact point has been verified
+        pub fn is_verified(&self) -> bool {
+            self.verified_at.is_some()
+        }
+
+        /// Record a successful verification
+        pub fn mark_verified(&mut self, at: Timestamp) {
+            self.verified_at = Some(at);
+        }
     }
 
     /// Contact system types
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub enum ContactSystem {
+        /// Telephone
         Phone,
+        /// Fax
         Fax,
+        /// Email
         Email,
+        /// Pager
         Pager,
+        /// URL
         Url,
+        /// SMS-capable number
         Sms,
+        /// Any other system
         Other,
     }
 
     /// Contact use types
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum ContactUse {
+        /// Home
         Home,
+        /// Work
         Work,
+        /// Temporary
         Temp,
+        /// No longer in use
         Old,
+        /// Mobile device
         Mobile,
     }
 
     /// Address representation
     #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
     pub struct Address {
+        /// Purpose of the address
         pub use_: Option<AddressUse>,
+        /// Postal, physical or both
         pub type_: Option<AddressType>,
+        /// Full address as displayed
         #[validate(length(min = 1, max = 200))]
         pub text: Option<String>,
+        /// Street lines
         pub line: Vec<String>,
+        /// City
         #[validate(length(min = 1, max = 100))]
         pub city: Option<String>,
+        /// District or county
         #[validate(length(min = 1, max = 100))]
         pub district: Option<String>,
+        /// State or province
         #[validate(length(min = 1, max = 100))]
         pub state: Option<String>,
+        /// Postal code
         #[validate(length(min = 1, max = 20))]
         pub postal_code: Option<String>,
+        /// Country
         #[validate(length(min = 1, max = 100))]
         pub country: Option<String>,
     }
@@ -127,26 +232,37 @@ pub mod values {
     /// Address use types
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum AddressUse {
+        /// Home
         Home,
+        /// Work
         Work,
+        /// Temporary
         Temp,
+        /// No longer in use
         Old,
+        /// Billing address
         Billing,
     }
 
     /// Address type
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum AddressType {
+        /// Mailing address
         Postal,
+        /// Physical location
         Physical,
+        /// Both mailing and physical
         Both,
     }
 
     /// Identifier for external systems
     #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
     pub struct Identifier {
+        /// Purpose of the identifier
         pub use_: Option<IdentifierUse>,
+        /// Namespace the value is unique in
         pub system: Option<String>,
+        /// Identifier value
         #[validate(length(min = 1, max = 100))]
         pub value: String,
     }
@@ -154,28 +270,41 @@ pub mod values {
     /// Identifier use types
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum IdentifierUse {
+        /// Identifier in everyday use
         Usual,
+        /// Formal identifier
         Official,
+        /// Temporary identifier
         Temp,
+        /// Secondary identifier
         Secondary,
+        /// Identifier no longer in use
         Old,
     }
 
     /// Gender representation
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum Gender {
+        /// Male
         Male,
+        /// Female
         Female,
+        /// Other
         Other,
+        /// Unknown
         Unknown,
     }
 
     /// Administrative gender as per FHIR
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum AdministrativeGender {
+        /// Male
         Male,
+        /// Female
         Female,
+        /// Other
         Other,
+        /// Unknown
         Unknown,
     }
 }
@@ -205,9 +334,12 @@ mod tests {
             value: "john.doe@example.com".to_string(),
             use_: Some(values::ContactUse::Work),
             rank: None,
+            verified_at: None,
         };
 
         assert_eq!(contact.value, "john.doe@example.com");
         assert!(matches!(contact.system, values::ContactSystem::Email));
+        assert!(contact.is_verifiable());
+        assert!(!contact.is_verified());
     }
 } 
\ No newline at end of file
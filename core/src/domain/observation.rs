@@ -1,6 +1,10 @@
 //! Observation domain entity
 
+use crate::domain::confidentiality::Confidentiality;
 use crate::domain::traits::{Identifiable, Auditable, Validatable};
+use crate::domain::units;
+use crate::domain::vitals::LOINC_SYSTEM;
+use crate::domain::waveform::{SampledDataEncoding, Waveform};
 use crate::domain::values::*;
 use crate::types::{Id, Timestamp, EntityMetadata};
 use crate::{Result, Error};
@@ -10,6 +14,7 @@ use validator::Validate;
 /// Observation entity representing clinical observations
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct Observation {
+    /// Identity, timestamps and version
     #[serde(flatten)]
     pub metadata: EntityMetadata,
     
@@ -66,56 +71,153 @@ pub struct Observation {
     
     /// Derived from observations
     pub derived_from: Vec<Id>,
+
+    /// Orders (ServiceRequests) the observation fulfils
+    #[serde(default)]
+    pub based_on: Vec<Id>,
+
+    /// Confidentiality label; only users cleared for it see the observation
+    #[serde(default)]
+    pub confidentiality: Confidentiality,
+}
+
+/// HL7 v3 ObservationInterpretation codes for critically abnormal results
+pub const CRITICAL_INTERPRETATIONS: [&str; 3] = ["AA", "HH", "LL"];
+
+/// HL7 v3 ObservationInterpretation codes for abnormal results
+pub const ABNORMAL_INTERPRETATIONS: [&str; 7] = ["A", "H", "L", "HU", "LU", "<", ">"];
+
+/// How far a result is from normal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultFlag {
+    /// Normal, or nothing says otherwise
+    Normal,
+    /// Abnormal, needs acknowledgment
+    Abnormal,
+    /// Critically abnormal, needs prompt acknowledgment
+    Critical,
 }
 
 /// Observation status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ObservationStatus {
+    /// Recorded but not yet available
     Registered,
+    /// Preliminary result
     Preliminary,
+    /// Final result
     Final,
+    /// Changed after being final
     Amended,
+    /// Corrected after being final
     Corrected,
+    /// Cancelled before a result was available
     Cancelled,
+    /// Recorded in error
     EnteredInError,
+    /// Status is not known
     Unknown,
 }
 
+impl ObservationStatus {
+    /// FHIR observation-status code
+    pub fn code(&self) -> &'static str {
+        match self {
+            ObservationStatus::Registered => "registered",
+            ObservationStatus::Preliminary => "preliminary",
+            ObservationStatus::Final => "final",
+            ObservationStatus::Amended => "amended",
+            ObservationStatus::Corrected => "corrected",
+            ObservationStatus::Cancelled => "cancelled",
+            ObservationStatus::EnteredInError => "entered-in-error",
+            ObservationStatus::Unknown => "unknown",
+        }
+    }
+
+    /// Parse a FHIR observation-status code
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_lowercase().as_str() {
+            "registered" => Some(ObservationStatus::Registered),
+            "preliminary" => Some(ObservationStatus::Preliminary),
+            "final" => Some(ObservationStatus::Final),
+            "amended" => Some(ObservationStatus::Amended),
+            "corrected" => Some(ObservationStatus::Corrected),
+            "cancelled" => Some(ObservationStatus::Cancelled),
+            "entered-in-error" => Some(ObservationStatus::EnteredInError),
+            "unknown" => Some(ObservationStatus::Unknown),
+            _ => None,
+        }
+    }
+}
+
 /// Observation value
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ObservationValue {
+    /// Measured amount with a unit
     Quantity {
+        /// Measured value
         value: f64,
+        /// Human-readable unit
         unit: String,
+        /// System the unit code comes from
         system: Option<String>,
+        /// Coded unit
         code: Option<String>,
     },
+    /// Free text
     String(String),
+    /// Yes or no
     Boolean(bool),
+    /// Whole number
     Integer(i64),
+    /// Range of values
     Range {
+        /// Lower bound
         low: Option<f64>,
+        /// Upper bound
         high: Option<f64>,
+        /// Unit of both bounds
         unit: String,
     },
+    /// Ratio of two values
     Ratio {
+        /// Numerator
         numerator: f64,
+        /// Denominator
         denominator: f64,
+        /// Unit of the ratio
         unit: String,
     },
+    /// Series of samples taken at a fixed interval
     SampledData {
+        /// Zero value, added to each scaled sample
         origin: f64,
+        /// Milliseconds between samples
         period: f64,
+        /// Multiplier applied to each sample
         factor: Option<f64>,
+        /// Lowest value the device can measure
         lower_limit: Option<f64>,
+        /// Highest value the device can measure
         upper_limit: Option<f64>,
+        /// Number of interleaved sample points
         dimensions: u32,
+        /// Encoded samples
         data: String,
+        /// How `data` is encoded
+        #[serde(default)]
+        encoding: SampledDataEncoding,
     },
+    /// Time of day
     Time(Timestamp),
+    /// Point in time
     DateTime(Timestamp),
+    /// Period of time
     Period {
+        /// Start of the period
         start: Option<Timestamp>,
+        /// End of the period
         end: Option<Timestamp>,
     },
 }
@@ -129,6 +231,9 @@ pub struct ObservationReferenceRange {
     /// High limit
     pub high: Option<f64>,
     
+    /// UCUM unit of the limits; the observation's unit when absent
+    pub unit: Option<String>,
+    
     /// Type of reference range
     pub type_: Option<String>,
     
@@ -165,6 +270,118 @@ impl Observation {
             reference_range: Vec::new(),
             has_member: Vec::new(),
             derived_from: Vec::new(),
+            based_on: Vec::new(),
+            confidentiality: Confidentiality::Normal,
+        }
+    }
+
+    /// Link the observation to an encounter
+    pub fn set_encounter(&mut self, encounter: Id) {
+        self.encounter = Some(encounter);
+        self.metadata.update();
+    }
+
+    /// Add a member observation (for panels)
+    pub fn add_member(&mut self, member: Id) {
+        if !self.has_member.contains(&member) {
+            self.has_member.push(member);
+            self.metadata.update();
+        }
+    }
+
+    /// Code system and code of the observation
+    ///
+    /// Codes are LOINC unless stored as a `system|code` token, as calculated
+    /// scores without a LOINC code are.
+    pub fn coding(&self) -> (&str, &str) {
+        self.code.split_once('|').unwrap_or((LOINC_SYSTEM, &self.code))
+    }
+
+    /// Check if the observation belongs to a category
+    pub fn has_category(&self, category: &str) -> bool {
+        self.category.iter().any(|c| c == category)
+    }
+
+    /// Rewrite quantity and reference range units to their UCUM codes
+    pub fn normalize_units(&mut self) -> Result<()> {
+        if let Some(ObservationValue::Quantity { unit, system, code, .. }) = &mut self.value {
+            let normalized = units::normalize(unit)?;
+            *unit = normalized.to_string();
+            *code = Some(normalized.to_string());
+            *system = Some(units::UCUM_SYSTEM.to_string());
+        }
+
+        for range in &mut self.reference_range {
+            if let Some(unit) = &mut range.unit {
+                *unit = units::normalize(unit)?.to_string();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Re-encode text SampledData with delta varint encoding when that is
+    /// lossless; other values are left untouched
+    pub fn compress_waveform(&mut self) -> Result<()> {
+        if let Some(value @ ObservationValue::SampledData { encoding: SampledDataEncoding::Text, .. }) = &self.value {
+            let waveform = Waveform::from_value(value)?;
+            if waveform.preferred_encoding() == SampledDataEncoding::DeltaVarint {
+                self.value = Some(waveform.to_value(SampledDataEncoding::DeltaVarint)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the quantity value converted to the given unit
+    pub fn quantity_in(&self, target_unit: &str) -> Result<Option<f64>> {
+        match &self.value {
+            Some(ObservationValue::Quantity { value, unit, .. }) => {
+                units::convert(*value, unit, target_unit).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Check the quantity value against the first reference range, converting
+    /// units where they differ. Returns `None` when there is nothing to compare.
+    pub fn is_within_reference_range(&self) -> Result<Option<bool>> {
+        let (value, unit) = match &self.value {
+            Some(ObservationValue::Quantity { value, unit, .. }) => (*value, unit.as_str()),
+            _ => return Ok(None),
+        };
+
+        let range = match self
+            .reference_range
+            .iter()
+            .find(|range| range.low.is_some() || range.high.is_some())
+        {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+
+        let value = match &range.unit {
+            Some(range_unit) => units::convert(value, unit, range_unit)?,
+            None => value,
+        };
+
+        let above_low = range.low.map(|low| value >= low).unwrap_or(true);
+        let below_high = range.high.map(|high| value <= high).unwrap_or(true);
+        Ok(Some(above_low && below_high))
+    }
+
+    /// Flag the result from its interpretation codes, or from its reference
+    /// range when it has no interpretation
+    pub fn result_flag(&self) -> ResultFlag {
+        let interpreted = |codes: &[&str]| self.interpretation.iter().any(|code| codes.contains(&code.as_str()));
+
+        if interpreted(&CRITICAL_INTERPRETATIONS) {
+            ResultFlag::Critical
+        } else if interpreted(&ABNORMAL_INTERPRETATIONS)
+            || (self.interpretation.is_empty() && self.is_within_reference_range().ok().flatten() == Some(false))
+        {
+            ResultFlag::Abnormal
+        } else {
+            ResultFlag::Normal
         }
     }
 }
@@ -192,7 +409,7 @@ impl Auditable for Observation {
 impl Validatable for Observation {
     fn validate(&self) -> Result<()> {
         use validator::Validate;
-        self.validate().map_err(|e| {
+        Validate::validate(self).map_err(|e| {
             Error::validation_error(&format!("Observation validation failed: {}", e))
         })?;
 
@@ -200,6 +417,141 @@ impl Validatable for Observation {
             return Err(Error::validation_error("Observation code cannot be empty"));
         }
 
+        if let Some(ObservationValue::Quantity { value, unit, .. }) = &self.value {
+            if !value.is_finite() {
+                return Err(Error::validation_error_with_field("Quantity value must be a finite number", "value"));
+            }
+            units::normalize(unit)?;
+        }
+
+        if let Some(value @ ObservationValue::SampledData { .. }) = &self.value {
+            Waveform::from_value(value)?;
+        }
+
+        for range in &self.reference_range {
+            if let Some(unit) = &range.unit {
+                units::normalize(unit)?;
+            }
+            if let (Some(low), Some(high)) = (range.low, range.high) {
+                if low > high {
+                    return Err(Error::validation_error_with_field(
+                        "Reference range low cannot exceed high",
+                        "reference_range",
+                    ));
+                }
+            }
+        }
+
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        for status in [ObservationStatus::Final, ObservationStatus::EnteredInError, ObservationStatus::Unknown] {
+            assert_eq!(ObservationStatus::from_code(status.code()), Some(status));
+        }
+        assert_eq!(ObservationStatus::from_code("FINAL"), Some(ObservationStatus::Final));
+        assert!(ObservationStatus::from_code("done").is_none());
+    }
+
+    fn quantity_observation(value: f64, unit: &str) -> Observation {
+        let mut observation = Observation::new(ObservationStatus::Final, "29463-7".to_string(), uuid::Uuid::new_v4());
+        observation.value = Some(ObservationValue::Quantity {
+            value,
+            unit: unit.to_string(),
+            system: None,
+            code: None,
+        });
+        observation
+    }
+
+    fn reference_range(low: f64, high: f64, unit: &str) -> ObservationReferenceRange {
+        ObservationReferenceRange {
+            low: Some(low),
+            high: Some(high),
+            unit: Some(unit.to_string()),
+            type_: None,
+            applies_to: Vec::new(),
+            age: None,
+            text: None,
+        }
+    }
+
+    #[test]
+    fn test_observation_unit_validation() {
+        assert!(Validatable::validate(&quantity_observation(70.0, "kg")).is_ok());
+        assert!(Validatable::validate(&quantity_observation(70.0, "lbs")).is_ok());
+        assert!(Validatable::validate(&quantity_observation(70.0, "stone")).is_err());
+    }
+
+    #[test]
+    fn test_normalize_units() {
+        let mut observation = quantity_observation(120.0, "mmHg");
+        observation.normalize_units().unwrap();
+
+        match observation.value {
+            Some(ObservationValue::Quantity { unit, system, .. }) => {
+                assert_eq!(unit, "mm[Hg]");
+                assert_eq!(system.as_deref(), Some(units::UCUM_SYSTEM));
+            }
+            other => panic!("Unexpected value: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compress_waveform() {
+        let mut observation = Observation::new(ObservationStatus::Final, "131328".to_string(), uuid::Uuid::new_v4());
+        observation.value = Some(ObservationValue::SampledData {
+            origin: 0.0,
+            period: 4.0,
+            factor: Some(1.0),
+            lower_limit: None,
+            upper_limit: None,
+            dimensions: 1,
+            data: "100 102 101 99".to_string(),
+            encoding: SampledDataEncoding::Text,
+        });
+
+        observation.compress_waveform().unwrap();
+
+        assert!(matches!(
+            observation.value,
+            Some(ObservationValue::SampledData { encoding: SampledDataEncoding::DeltaVarint, .. })
+        ));
+        assert!(Validatable::validate(&observation).is_ok());
+    }
+
+    #[test]
+    fn test_reference_range_across_units() {
+        let mut observation = quantity_observation(154.0, "[lb_av]");
+        observation.reference_range.push(reference_range(50.0, 80.0, "kg"));
+        assert_eq!(observation.is_within_reference_range().unwrap(), Some(true));
+
+        let mut observation = quantity_observation(101.3, "[degF]");
+        observation.reference_range.push(reference_range(36.1, 37.8, "Cel"));
+        assert_eq!(observation.is_within_reference_range().unwrap(), Some(false));
+
+        let observation = quantity_observation(70.0, "kg");
+        assert_eq!(observation.is_within_reference_range().unwrap(), None);
+    }
+
+    #[test]
+    fn test_result_flag() {
+        let mut potassium = quantity_observation(6.8, "mmol/L");
+        assert_eq!(potassium.result_flag(), ResultFlag::Normal);
+
+        potassium.reference_range.push(reference_range(3.5, 5.1, "mmol/L"));
+        assert_eq!(potassium.result_flag(), ResultFlag::Abnormal);
+
+        potassium.interpretation.push("HH".to_string());
+        assert_eq!(potassium.result_flag(), ResultFlag::Critical);
+
+        potassium.interpretation = vec!["N".to_string()];
+        assert_eq!(potassium.result_flag(), ResultFlag::Normal, "interpretation wins over the range");
+    }
 } 
\ No newline at end of file
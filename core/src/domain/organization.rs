@@ -10,6 +10,7 @@ use validator::Validate;
 /// Organization entity representing healthcare organizations
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct Organization {
+    /// Identity, timestamps and version
     #[serde(flatten)]
     pub metadata: EntityMetadata,
     
@@ -72,6 +73,43 @@ pub enum OrganizationType {
     Other,
 }
 
+impl OrganizationType {
+    /// FHIR organization-type code
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrganizationType::Prov => "prov",
+            OrganizationType::Dept => "dept",
+            OrganizationType::Team => "team",
+            OrganizationType::Govt => "govt",
+            OrganizationType::Ins => "ins",
+            OrganizationType::Edu => "edu",
+            OrganizationType::Reli => "reli",
+            OrganizationType::Crs => "crs",
+            OrganizationType::Cg => "cg",
+            OrganizationType::Bus => "bus",
+            OrganizationType::Other => "other",
+        }
+    }
+
+    /// Parse a FHIR organization-type code
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_lowercase().as_str() {
+            "prov" => Some(OrganizationType::Prov),
+            "dept" => Some(OrganizationType::Dept),
+            "team" => Some(OrganizationType::Team),
+            "govt" => Some(OrganizationType::Govt),
+            "ins" => Some(OrganizationType::Ins),
+            "edu" => Some(OrganizationType::Edu),
+            "reli" => Some(OrganizationType::Reli),
+            "crs" => Some(OrganizationType::Crs),
+            "cg" => Some(OrganizationType::Cg),
+            "bus" => Some(OrganizationType::Bus),
+            "other" => Some(OrganizationType::Other),
+            _ => None,
+        }
+    }
+}
+
 /// Organization contact person
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct OrganizationContact {
@@ -198,6 +236,73 @@ impl Organization {
     }
 }
 
+/// Organization with its descendants, as returned by hierarchy queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationNode {
+    /// The organization at this node
+    pub organization: Organization,
+    /// Organizations directly beneath it
+    pub children: Vec<OrganizationNode>,
+}
+
+impl OrganizationNode {
+    /// Build the tree rooted at `root_id` from a flat list of organizations.
+    ///
+    /// The list is typically the result of a recursive `part_of` query and may
+    /// contain unrelated organizations, which are ignored. Children are sorted
+    /// by name. Cycles in `part_of` are reported as data integrity errors.
+    pub fn build(root_id: Id, organizations: &[Organization]) -> Result<Self> {
+        let root = organizations
+            .iter()
+            .find(|org| org.metadata.id == root_id)
+            .ok_or_else(|| Error::entity_not_found("Organization", root_id))?;
+
+        let mut visited = std::collections::HashSet::new();
+        Self::build_node(root, organizations, &mut visited)
+    }
+
+    fn build_node(
+        organization: &Organization,
+        organizations: &[Organization],
+        visited: &mut std::collections::HashSet<Id>,
+    ) -> Result<Self> {
+        if !visited.insert(organization.metadata.id) {
+            return Err(Error::data_integrity_error(&format!(
+                "Organization hierarchy contains a cycle at {}",
+                organization.metadata.id
+            )));
+        }
+
+        let mut children: Vec<&Organization> = organizations
+            .iter()
+            .filter(|org| org.part_of == Some(organization.metadata.id))
+            .collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Self {
+            organization: organization.clone(),
+            children: children
+                .into_iter()
+                .map(|child| Self::build_node(child, organizations, visited))
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    /// Total number of organizations in the tree, including the root
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().map(OrganizationNode::size).sum::<usize>()
+    }
+
+    /// Flatten the tree in depth-first order, root first
+    pub fn flatten(&self) -> Vec<Organization> {
+        let mut organizations = vec![self.organization.clone()];
+        for child in &self.children {
+            organizations.extend(child.flatten());
+        }
+        organizations
+    }
+}
+
 impl Identifiable for Organization {
     fn id(&self) -> Id {
         self.metadata.id
@@ -222,7 +327,7 @@ impl Validatable for Organization {
     fn validate(&self) -> Result<()> {
         // Use validator crate for basic validation
         use validator::Validate;
-        self.validate().map_err(|e| {
+        Validate::validate(self).map_err(|e| {
             Error::validation_error(&format!("Organization validation failed: {}", e))
         })?;
 
@@ -307,7 +412,7 @@ mod tests {
     #[test]
     fn test_organization_validation() {
         let org = Organization::new("Test Hospital".to_string()).unwrap();
-        assert!(org.validate().is_ok());
+        assert!(Validatable::validate(&org).is_ok());
     }
 
     #[test]
@@ -315,7 +420,7 @@ mod tests {
         let mut org = Organization::new("Test Hospital".to_string()).unwrap();
         org.part_of = Some(org.metadata.id);
         
-        assert!(org.validate().is_err());
+        assert!(Validatable::validate(&org).is_err());
     }
 
     #[test]
@@ -343,4 +448,43 @@ mod tests {
         let primary = org.primary_identifier().unwrap();
         assert_eq!(primary.value, "1234567890");
     }
+
+    fn child_of(name: &str, parent: &Organization) -> Organization {
+        let mut org = Organization::new(name.to_string()).unwrap();
+        org.set_part_of(parent.metadata.id);
+        org
+    }
+
+    #[test]
+    fn test_organization_hierarchy() {
+        let root = Organization::new("Health System".to_string()).unwrap();
+        let west = child_of("West Hospital", &root);
+        let east = child_of("East Hospital", &root);
+        let cardiology = child_of("Cardiology", &west);
+        let unrelated = Organization::new("Other System".to_string()).unwrap();
+
+        let organizations = vec![cardiology, west, root.clone(), unrelated, east];
+        let tree = OrganizationNode::build(root.metadata.id, &organizations).unwrap();
+
+        assert_eq!(tree.size(), 4);
+        assert_eq!(tree.children[0].organization.name, "East Hospital");
+        assert_eq!(tree.children[1].children[0].organization.name, "Cardiology");
+        assert_eq!(tree.flatten()[0].name, "Health System");
+    }
+
+    #[test]
+    fn test_organization_hierarchy_missing_root() {
+        let org = Organization::new("Test Hospital".to_string()).unwrap();
+        assert!(OrganizationNode::build(uuid::Uuid::new_v4(), &[org]).is_err());
+    }
+
+    #[test]
+    fn test_organization_hierarchy_cycle() {
+        let mut a = Organization::new("A".to_string()).unwrap();
+        let mut b = Organization::new("B".to_string()).unwrap();
+        a.part_of = Some(b.metadata.id);
+        b.part_of = Some(a.metadata.id);
+
+        assert!(OrganizationNode::build(a.metadata.id, &[a.clone(), b]).is_err());
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,308 @@
+//! Saved patient panels
+//!
+//! A [`PatientPanel`] is a named, saved query over the patient population,
+//! e.g. "diabetics with an HbA1c over 9 in the last 6 months". It is defined
+//! by structured [`PanelCriterion`]s, all of which a patient must meet, and
+//! is evaluated on demand or nightly by the jobs worker; the patients found
+//! are kept as the panel's members until the next evaluation. Panels belong
+//! to the practitioner who saved them and can be shared with the rest of
+//! their organization.
+
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Most criteria a panel may combine
+pub const MAX_CRITERIA: usize = 20;
+
+/// Longest look-back window a criterion may use, in days
+pub const MAX_WINDOW_DAYS: u32 = 3650;
+
+/// How a numeric observation value is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    /// Greater than
+    Gt,
+    /// Greater than or equal
+    Ge,
+    /// Less than
+    Lt,
+    /// Less than or equal
+    Le,
+    /// Equal
+    Eq,
+}
+
+impl Comparator {
+    /// SQL operator
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Comparator::Gt => ">",
+            Comparator::Ge => ">=",
+            Comparator::Lt => "<",
+            Comparator::Le => "<=",
+            Comparator::Eq => "=",
+        }
+    }
+}
+
+/// A condition a panel member must meet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PanelCriterion {
+    /// Diagnosed with an ICD-10-CM code starting with `code`, e.g. `E11`
+    Condition {
+        /// Code or code prefix
+        code: String,
+        /// Only diagnoses coded in the last so many days
+        #[serde(default)]
+        within_days: Option<u32>,
+    },
+    /// Most recent numeric result for a LOINC code compares to `value`
+    Observation {
+        /// LOINC code, e.g. `4548-4` for HbA1c
+        code: String,
+        /// How the result is compared
+        comparator: Comparator,
+        /// Value the result is compared with
+        value: f64,
+        /// Only results from the last so many days
+        #[serde(default)]
+        within_days: Option<u32>,
+    },
+    /// Age in whole years within a range
+    Age {
+        /// Youngest age, inclusive
+        #[serde(default)]
+        min: Option<u32>,
+        /// Oldest age, inclusive
+        #[serde(default)]
+        max: Option<u32>,
+    },
+    /// Administrative gender, e.g. `female`
+    Gender {
+        /// Gender as stored
+        gender: String,
+    },
+    /// Has an active prescription for a medication code
+    Medication {
+        /// Medication code, e.g. an RxNorm code
+        code: String,
+    },
+    /// Had an encounter in the last so many days
+    SeenWithin {
+        /// Look-back window
+        days: u32,
+    },
+}
+
+impl PanelCriterion {
+    /// Check the criterion can be evaluated
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            PanelCriterion::Condition { code, within_days } => {
+                check_code(code, |c| c.is_ascii_alphanumeric() || c == '.')?;
+                check_window(*within_days)
+            }
+            PanelCriterion::Observation {
+                code,
+                value,
+                within_days,
+                ..
+            } => {
+                check_code(code, |c| c.is_ascii_alphanumeric() || c == '-')?;
+                if !value.is_finite() {
+                    return Err(Error::validation_error_with_field("Observation values must be numbers", "value"));
+                }
+                check_window(*within_days)
+            }
+            PanelCriterion::Age { min, max } => match (min, max) {
+                (None, None) => Err(Error::validation_error_with_field(
+                    "Age criteria need a minimum or maximum",
+                    "min",
+                )),
+                (Some(min), Some(max)) if min > max => Err(Error::validation_error_with_field(
+                    "Minimum age is above the maximum",
+                    "min",
+                )),
+                _ => Ok(()),
+            },
+            PanelCriterion::Gender { gender } => match gender.as_str() {
+                "male" | "female" | "other" | "unknown" => Ok(()),
+                _ => Err(Error::validation_error_with_field(&format!("Unknown gender '{}'", gender), "gender")),
+            },
+            PanelCriterion::Medication { code } => check_code(code, |c| c.is_ascii_alphanumeric() || c == '-'),
+            PanelCriterion::SeenWithin { days } => check_window(Some(*days)),
+        }
+    }
+}
+
+fn check_code(code: &str, allowed: impl Fn(char) -> bool) -> Result<()> {
+    if code.is_empty() || code.len() > 20 || !code.chars().all(allowed) {
+        return Err(Error::validation_error_with_field(&format!("Invalid code '{}'", code), "code"));
+    }
+    Ok(())
+}
+
+fn check_window(days: Option<u32>) -> Result<()> {
+    match days {
+        Some(days) if days == 0 || days > MAX_WINDOW_DAYS => Err(Error::validation_error_with_field(
+            &format!("Windows must be between 1 and {} days", MAX_WINDOW_DAYS),
+            "within_days",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// When a panel is evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanelRefresh {
+    /// Only when someone asks
+    OnDemand,
+    /// Every night by the jobs worker, and when someone asks
+    Nightly,
+}
+
+impl PanelRefresh {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PanelRefresh::OnDemand => "on_demand",
+            PanelRefresh::Nightly => "nightly",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "on_demand" => Some(PanelRefresh::OnDemand),
+            "nightly" => Some(PanelRefresh::Nightly),
+            _ => None,
+        }
+    }
+}
+
+/// A saved patient panel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientPanel {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Name shown on the list page
+    pub name: String,
+    /// What the panel is for
+    pub description: Option<String>,
+    /// Organization the panel belongs to
+    pub organization_id: Id,
+    /// Practitioner who saved the panel
+    pub owner_id: Id,
+    /// Whether the rest of the organization can see the panel
+    pub shared: bool,
+    /// Conditions every member meets
+    pub criteria: Vec<PanelCriterion>,
+    /// When the panel is evaluated
+    pub refresh: PanelRefresh,
+    /// When the members were last found
+    pub evaluated_at: Option<Timestamp>,
+    /// Number of members found then
+    pub member_count: Option<i64>,
+}
+
+impl PatientPanel {
+    /// Create a panel that has not been evaluated yet
+    pub fn new(name: &str, organization_id: Id, owner_id: Id, criteria: Vec<PanelCriterion>) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            name: name.trim().to_string(),
+            description: None,
+            organization_id,
+            owner_id,
+            shared: false,
+            criteria,
+            refresh: PanelRefresh::OnDemand,
+            evaluated_at: None,
+            member_count: None,
+        }
+    }
+
+    /// Whether a practitioner of the panel's organization can see it
+    pub fn visible_to(&self, practitioner_id: Id) -> bool {
+        self.shared || self.owner_id == practitioner_id
+    }
+}
+
+impl Validatable for PatientPanel {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.chars().count() > 100 {
+            return Err(Error::validation_error_with_field("Panel names need 1 to 100 characters", "name"));
+        }
+        if self.criteria.is_empty() {
+            return Err(Error::validation_error_with_field("Panels need at least one criterion", "criteria"));
+        }
+        if self.criteria.len() > MAX_CRITERIA {
+            return Err(Error::validation_error_with_field(
+                &format!("Panels combine at most {} criteria", MAX_CRITERIA),
+                "criteria",
+            ));
+        }
+        self.criteria.iter().try_for_each(PanelCriterion::validate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_criteria_deserialize() {
+        let criteria: Vec<PanelCriterion> = serde_json::from_str(
+            r#"[{"type": "condition", "code": "E11"},
+                {"type": "observation", "code": "4548-4", "comparator": "gt", "value": 9, "within_days": 180}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            criteria[1],
+            PanelCriterion::Observation {
+                code: "4548-4".to_string(),
+                comparator: Comparator::Gt,
+                value: 9.0,
+                within_days: Some(180),
+            }
+        );
+    }
+
+    #[test]
+    fn test_panel_validation() {
+        let diabetics = PanelCriterion::Condition {
+            code: "E11".to_string(),
+            within_days: None,
+        };
+        let mut panel = PatientPanel::new(" Diabetics ", Uuid::new_v4(), Uuid::new_v4(), vec![diabetics]);
+        assert_eq!(panel.name, "Diabetics");
+        assert!(panel.validate().is_ok());
+
+        panel.criteria.push(PanelCriterion::Age { min: Some(70), max: Some(60) });
+        assert!(panel.validate().is_err());
+        panel.criteria.pop();
+        panel.criteria.push(PanelCriterion::Condition {
+            code: "E11%".to_string(),
+            within_days: None,
+        });
+        assert!(panel.validate().is_err(), "codes are matched as prefixes and cannot hold wildcards");
+        panel.criteria.clear();
+        assert!(panel.validate().is_err());
+    }
+
+    #[test]
+    fn test_panel_visibility() {
+        let owner = Uuid::new_v4();
+        let mut panel = PatientPanel::new("Diabetics", Uuid::new_v4(), owner, vec![]);
+        assert!(panel.visible_to(owner));
+        assert!(!panel.visible_to(Uuid::new_v4()));
+        panel.shared = true;
+        assert!(panel.visible_to(Uuid::new_v4()));
+    }
+}
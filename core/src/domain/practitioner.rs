@@ -1,5 +1,6 @@
 //! Practitioner domain entity
 
+use crate::domain::patient::Period;
 use crate::domain::traits::{Identifiable, Auditable, Validatable};
 use crate::domain::values::*;
 use crate::types::{Id, Timestamp, EntityMetadata};
@@ -10,6 +11,7 @@ use validator::Validate;
 /// Practitioner entity representing healthcare practitioners
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct Practitioner {
+    /// Identity, timestamps and version
     #[serde(flatten)]
     pub metadata: EntityMetadata,
     
@@ -35,10 +37,42 @@ pub struct Practitioner {
     /// Communications (languages spoken)
     pub communications: Vec<String>,
     
+    /// Organizations the practitioner is affiliated with
+    pub organizations: Vec<Id>,
+    
     /// Whether this practitioner record is active
     pub active: bool,
 }
 
+/// Identifier systems that carry a US National Provider Identifier
+pub const NPI_SYSTEMS: [&str; 2] = ["NPI", "http://hl7.org/fhir/sid/us-npi"];
+
+/// Check a National Provider Identifier (10 digits, Luhn check digit with the
+/// `80840` card issuer prefix)
+pub fn is_valid_npi(npi: &str) -> bool {
+    if npi.len() != 10 || !npi.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let digits = format!("80840{}", npi);
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, digit)| {
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                digit
+            }
+        })
+        .sum();
+
+    sum % 10 == 0
+}
+
 /// Practitioner qualification
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PractitionerQualification {
@@ -55,13 +89,6 @@ pub struct PractitionerQualification {
     pub issuer: Option<Id>,
 }
 
-/// Period of time
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Period {
-    pub start: Option<Timestamp>,
-    pub end: Option<Timestamp>,
-}
-
 impl Practitioner {
     /// Create a new practitioner with required fields
     pub fn new(names: Vec<HumanName>) -> Result<Self> {
@@ -78,6 +105,7 @@ impl Practitioner {
             gender: None,
             qualifications: Vec::new(),
             communications: Vec::new(),
+            organizations: Vec::new(),
             active: true,
         })
     }
@@ -86,6 +114,63 @@ impl Practitioner {
     pub fn primary_name(&self) -> Option<&HumanName> {
         self.names.first()
     }
+
+    /// Get the practitioner's NPI, if recorded
+    pub fn npi(&self) -> Option<&str> {
+        self.identifiers
+            .iter()
+            .find(|identifier| {
+                identifier
+                    .system
+                    .as_deref()
+                    .map(|system| NPI_SYSTEMS.contains(&system))
+                    .unwrap_or(false)
+            })
+            .map(|identifier| identifier.value.as_str())
+    }
+
+    /// Add an identifier to the practitioner
+    pub fn add_identifier(&mut self, identifier: Identifier) {
+        self.identifiers.push(identifier);
+        self.metadata.update();
+    }
+
+    /// Add a qualification to the practitioner
+    pub fn add_qualification(&mut self, qualification: PractitionerQualification) {
+        self.qualifications.push(qualification);
+        self.metadata.update();
+    }
+
+    /// Remove qualifications with the given code, returning whether any were removed
+    pub fn remove_qualification(&mut self, code: &str) -> bool {
+        let before = self.qualifications.len();
+        self.qualifications.retain(|qualification| qualification.code != code);
+        let removed = self.qualifications.len() != before;
+        if removed {
+            self.metadata.update();
+        }
+        removed
+    }
+
+    /// Affiliate the practitioner with an organization
+    pub fn add_organization(&mut self, organization: Id) {
+        if !self.organizations.contains(&organization) {
+            self.organizations.push(organization);
+            self.metadata.update();
+        }
+    }
+
+    /// Remove an organization affiliation
+    pub fn remove_organization(&mut self, organization: Id) {
+        self.organizations.retain(|id| *id != organization);
+        self.metadata.update();
+    }
+
+    /// Deactivate the practitioner record
+    pub fn deactivate(&mut self) {
+        self.active = false;
+        self.metadata.update();
+    }
 }
 
 impl Identifiable for Practitioner {
@@ -111,7 +196,7 @@ impl Auditable for Practitioner {
 impl Validatable for Practitioner {
     fn validate(&self) -> Result<()> {
         use validator::Validate;
-        self.validate().map_err(|e| {
+        Validate::validate(self).map_err(|e| {
             Error::validation_error(&format!("Practitioner validation failed: {}", e))
         })?;
 
@@ -119,6 +204,99 @@ impl Validatable for Practitioner {
             return Err(Error::validation_error("Practitioner must have at least one name"));
         }
 
+        if let Some(npi) = self.npi() {
+            if !is_valid_npi(npi) {
+                return Err(Error::validation_error_with_field("Invalid NPI", "identifiers"));
+            }
+        }
+
+        for qualification in &self.qualifications {
+            if qualification.code.trim().is_empty() {
+                return Err(Error::validation_error_with_field(
+                    "Qualification code cannot be empty",
+                    "qualifications",
+                ));
+            }
+        }
+
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_practitioner() -> Practitioner {
+        Practitioner::new(vec![HumanName {
+            given: vec!["Emily".to_string()],
+            family: "Brown".to_string(),
+            prefix: Some("Dr.".to_string()),
+            suffix: None,
+            use_: Some(NameUse::Official),
+        }])
+        .unwrap()
+    }
+
+    fn npi_identifier(value: &str) -> Identifier {
+        Identifier {
+            use_: Some(IdentifierUse::Official),
+            system: Some("http://hl7.org/fhir/sid/us-npi".to_string()),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_npi_validation() {
+        assert!(is_valid_npi("1234567893"));
+        assert!(!is_valid_npi("1234567890"));
+        assert!(!is_valid_npi("123456789"));
+        assert!(!is_valid_npi("12345678a3"));
+    }
+
+    #[test]
+    fn test_practitioner_with_invalid_npi() {
+        let mut practitioner = create_test_practitioner();
+        practitioner.add_identifier(npi_identifier("1234567890"));
+
+        assert!(Validatable::validate(&practitioner).is_err());
+    }
+
+    #[test]
+    fn test_practitioner_with_valid_npi() {
+        let mut practitioner = create_test_practitioner();
+        practitioner.add_identifier(npi_identifier("1234567893"));
+
+        assert_eq!(practitioner.npi(), Some("1234567893"));
+        assert!(Validatable::validate(&practitioner).is_ok());
+    }
+
+    #[test]
+    fn test_practitioner_qualifications() {
+        let mut practitioner = create_test_practitioner();
+        practitioner.add_qualification(PractitionerQualification {
+            identifiers: Vec::new(),
+            code: "MD".to_string(),
+            period: None,
+            issuer: None,
+        });
+
+        assert_eq!(practitioner.qualifications.len(), 1);
+        assert!(practitioner.remove_qualification("MD"));
+        assert!(!practitioner.remove_qualification("MD"));
+        assert_eq!(practitioner.metadata.version, 3);
+    }
+
+    #[test]
+    fn test_practitioner_organizations() {
+        let mut practitioner = create_test_practitioner();
+        let organization = uuid::Uuid::new_v4();
+
+        practitioner.add_organization(organization);
+        practitioner.add_organization(organization);
+        assert_eq!(practitioner.organizations, vec![organization]);
+
+        practitioner.remove_organization(organization);
+        assert!(practitioner.organizations.is_empty());
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,169 @@
+//! Provenance of imported and synchronized data
+//!
+//! Every entity created by a batch import or a FHIR sync gets a
+//! [`Provenance`] record naming the system it came from, the job that
+//! brought it in, the identifiers it had there and the version of the
+//! mapping that transformed it. Clinicians use it to judge where a value
+//! came from; operators use it to find everything a bad import touched.
+
+use crate::domain::traits::Validatable;
+use crate::domain::values::Identifier;
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How an entity entered the system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvenanceActivity {
+    /// Batch file import
+    Import,
+    /// Synchronization from a FHIR server
+    FhirSync,
+}
+
+impl ProvenanceActivity {
+    /// Code stored and sent for the activity
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProvenanceActivity::Import => "import",
+            ProvenanceActivity::FhirSync => "fhir_sync",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "import" => Some(ProvenanceActivity::Import),
+            "fhir_sync" => Some(ProvenanceActivity::FhirSync),
+            _ => None,
+        }
+    }
+}
+
+/// Where an entity came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Identifier of the record
+    pub id: Id,
+    /// Entity type, e.g. `Patient` or `Observation`
+    pub target_type: String,
+    /// Entity the record is about
+    pub target_id: Id,
+    /// What was done to the entity
+    pub activity: ProvenanceActivity,
+    /// System that produced the data, e.g. a lab's LIS or a FHIR server
+    pub source_system: String,
+    /// Import or sync job that created the entity
+    pub job_id: Option<Id>,
+    /// Identifiers the entity had in the source system
+    pub original_identifiers: Vec<Identifier>,
+    /// Mapping or converter version that transformed the source data
+    pub transformation_version: String,
+    /// File name or URL the data was read from
+    pub source_reference: Option<String>,
+    /// When the activity was recorded
+    pub recorded_at: Timestamp,
+}
+
+impl Provenance {
+    /// Provenance for an entity brought in by `activity` from `source_system`
+    pub fn new(
+        target_type: &str,
+        target_id: Id,
+        activity: ProvenanceActivity,
+        source_system: &str,
+        transformation_version: &str,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            target_type: target_type.to_string(),
+            target_id,
+            activity,
+            source_system: source_system.to_string(),
+            job_id: None,
+            original_identifiers: Vec::new(),
+            transformation_version: transformation_version.to_string(),
+            source_reference: None,
+            recorded_at: Utc::now(),
+        }
+    }
+
+    /// Set the job that created the entity
+    pub fn with_job(mut self, job_id: Id) -> Self {
+        self.job_id = Some(job_id);
+        self
+    }
+
+    /// Set the identifiers the entity had in the source system
+    pub fn with_original_identifiers(mut self, identifiers: Vec<Identifier>) -> Self {
+        self.original_identifiers = identifiers;
+        self
+    }
+
+    /// Set the file name or URL the data was read from
+    pub fn with_source_reference(mut self, reference: &str) -> Self {
+        self.source_reference = Some(reference.to_string());
+        self
+    }
+}
+
+impl Validatable for Provenance {
+    fn validate(&self) -> Result<()> {
+        if self.target_type.trim().is_empty() {
+            return Err(Error::validation_error_with_field("Provenance target type is required", "target_type"));
+        }
+        if self.source_system.trim().is_empty() {
+            return Err(Error::validation_error_with_field("Provenance source system is required", "source_system"));
+        }
+        if self.transformation_version.trim().is_empty() {
+            return Err(Error::validation_error_with_field(
+                "Provenance transformation version is required",
+                "transformation_version",
+            ));
+        }
+        if self.original_identifiers.iter().any(|identifier| identifier.value.trim().is_empty()) {
+            return Err(Error::validation_error_with_field(
+                "Original identifiers must have a value",
+                "original_identifiers",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activity_names_round_trip() {
+        for activity in [ProvenanceActivity::Import, ProvenanceActivity::FhirSync] {
+            assert_eq!(ProvenanceActivity::parse(activity.as_str()), Some(activity));
+        }
+        assert_eq!(ProvenanceActivity::parse("manual"), None);
+    }
+
+    #[test]
+    fn test_provenance_validation() {
+        let provenance = Provenance::new("Observation", Uuid::new_v4(), ProvenanceActivity::Import, "acme-lis", "1.2.0")
+            .with_job(Uuid::new_v4())
+            .with_source_reference("results.csv")
+            .with_original_identifiers(vec![Identifier {
+                use_: None,
+                system: Some("urn:acme-lis:accession".to_string()),
+                value: "A-1001".to_string(),
+            }]);
+        assert!(provenance.validate().is_ok());
+
+        let mut missing_source = provenance.clone();
+        missing_source.source_system = " ".to_string();
+        assert!(missing_source.validate().is_err());
+
+        let mut blank_identifier = provenance;
+        blank_identifier.original_identifiers[0].value = String::new();
+        assert!(blank_identifier.validate().is_err());
+    }
+}
@@ -0,0 +1,795 @@
+//! Questionnaires and their responses
+//!
+//! A [`Questionnaire`] (FHIR `Questionnaire`) defines a form: questions,
+//! groups of questions and display text, with answer options for choice
+//! questions. An item's `enable_when` conditions show it only when earlier
+//! answers match. Conditions may only refer to earlier questions, so a form
+//! is evaluated top to bottom and a disabled item hides everything in it.
+//!
+//! A [`QuestionnaireResponse`] holds the answers keyed by `link_id`. Answers
+//! to disabled questions are dropped, and a completed response must answer
+//! every enabled required question. When answer options carry scores (FHIR
+//! `ordinalValue`) the response is scored as their sum, as for PHQ-9.
+
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Most items (at any depth) per questionnaire
+pub const MAX_QUESTIONNAIRE_ITEMS: usize = 500;
+
+/// Questionnaire status (subset of FHIR `PublicationStatus`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionnaireStatus {
+    /// Being designed, not yet used
+    Draft,
+    /// In use
+    Active,
+    /// No longer used for new responses
+    Retired,
+}
+
+impl QuestionnaireStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuestionnaireStatus::Draft => "draft",
+            QuestionnaireStatus::Active => "active",
+            QuestionnaireStatus::Retired => "retired",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "draft" => Some(QuestionnaireStatus::Draft),
+            "active" => Some(QuestionnaireStatus::Active),
+            "retired" => Some(QuestionnaireStatus::Retired),
+            _ => None,
+        }
+    }
+
+    /// Whether a questionnaire may move from this status to `next`
+    pub fn can_transition_to(&self, next: QuestionnaireStatus) -> bool {
+        use QuestionnaireStatus::*;
+        matches!((self, next), (Draft, Active) | (Draft, Retired) | (Active, Retired))
+    }
+}
+
+/// Kind of questionnaire item (subset of FHIR `item-type`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemType {
+    /// Groups other items
+    Group,
+    /// Text shown without asking anything
+    Display,
+    /// Yes/no question
+    Boolean,
+    /// Decimal number
+    Decimal,
+    /// Whole number
+    Integer,
+    /// Calendar date
+    Date,
+    /// Short free text
+    String,
+    /// Long free text
+    Text,
+    /// One (or, when repeating, several) of the answer options
+    Choice,
+}
+
+impl ItemType {
+    /// FHIR name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemType::Group => "group",
+            ItemType::Display => "display",
+            ItemType::Boolean => "boolean",
+            ItemType::Decimal => "decimal",
+            ItemType::Integer => "integer",
+            ItemType::Date => "date",
+            ItemType::String => "string",
+            ItemType::Text => "text",
+            ItemType::Choice => "choice",
+        }
+    }
+
+    /// Whether items of this type are answered
+    pub fn is_question(&self) -> bool {
+        !matches!(self, ItemType::Group | ItemType::Display)
+    }
+
+    /// Whether a value is a valid answer to items of this type
+    pub fn accepts(&self, value: &AnswerValue) -> bool {
+        matches!(
+            (self, value),
+            (ItemType::Boolean, AnswerValue::Boolean(_))
+                | (ItemType::Decimal, AnswerValue::Decimal(_) | AnswerValue::Integer(_))
+                | (ItemType::Integer, AnswerValue::Integer(_))
+                | (ItemType::Date, AnswerValue::Date(_))
+                | (ItemType::String | ItemType::Text, AnswerValue::String(_))
+                | (ItemType::Choice, AnswerValue::Coding(_))
+        )
+    }
+}
+
+/// An answer value; choice answers hold the option code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum AnswerValue {
+    /// Boolean answer
+    Boolean(bool),
+    /// Decimal answer
+    Decimal(f64),
+    /// Integer answer
+    Integer(i64),
+    /// Date answer
+    Date(NaiveDate),
+    /// Free text answer
+    String(String),
+    /// Code of the chosen answer option
+    Coding(String),
+}
+
+impl AnswerValue {
+    /// Order two values of comparable types; numbers compare across types
+    fn compare(&self, other: &AnswerValue) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (AnswerValue::Integer(a), AnswerValue::Integer(b)) => Some(a.cmp(b)),
+            (AnswerValue::Date(a), AnswerValue::Date(b)) => Some(a.cmp(b)),
+            (AnswerValue::String(a), AnswerValue::String(b)) => Some(a.cmp(b)),
+            (a, b) => a.as_number()?.partial_cmp(&b.as_number()?),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            AnswerValue::Decimal(value) => Some(*value),
+            AnswerValue::Integer(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Comparison of an `enableWhen` condition (FHIR `questionnaire-enable-operator`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnableWhenOperator {
+    /// The question is answered (answer `true`) or not (answer `false`)
+    #[serde(rename = "exists")]
+    Exists,
+    /// An answer equals the value
+    #[serde(rename = "=")]
+    Equals,
+    /// No answer equals the value
+    #[serde(rename = "!=")]
+    NotEquals,
+    /// An answer is greater than the value
+    #[serde(rename = ">")]
+    GreaterThan,
+    /// An answer is less than the value
+    #[serde(rename = "<")]
+    LessThan,
+    /// An answer is greater than or equal to the value
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+    /// An answer is less than or equal to the value
+    #[serde(rename = "<=")]
+    LessOrEqual,
+}
+
+impl EnableWhenOperator {
+    /// FHIR code
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EnableWhenOperator::Exists => "exists",
+            EnableWhenOperator::Equals => "=",
+            EnableWhenOperator::NotEquals => "!=",
+            EnableWhenOperator::GreaterThan => ">",
+            EnableWhenOperator::LessThan => "<",
+            EnableWhenOperator::GreaterOrEqual => ">=",
+            EnableWhenOperator::LessOrEqual => "<=",
+        }
+    }
+}
+
+/// Condition on an earlier answer for showing an item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnableWhen {
+    /// `link_id` of the earlier question
+    pub question: String,
+    /// How its answers are compared
+    pub operator: EnableWhenOperator,
+    /// Value compared against; a boolean for `exists`
+    pub answer: AnswerValue,
+}
+
+impl EnableWhen {
+    /// Whether the condition holds for the question's answers
+    pub fn is_met(&self, answers: &[AnswerValue]) -> bool {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+        let equals = |answer: &AnswerValue| *answer == self.answer || answer.compare(&self.answer) == Some(Equal);
+        let any_ordered = |accept: fn(std::cmp::Ordering) -> bool| {
+            answers
+                .iter()
+                .any(|answer| answer.compare(&self.answer).is_some_and(accept))
+        };
+
+        match self.operator {
+            EnableWhenOperator::Exists => {
+                matches!(self.answer, AnswerValue::Boolean(expected) if expected != answers.is_empty())
+            }
+            EnableWhenOperator::Equals => answers.iter().any(equals),
+            EnableWhenOperator::NotEquals => !answers.iter().any(equals),
+            EnableWhenOperator::GreaterThan => any_ordered(|ordering| ordering == Greater),
+            EnableWhenOperator::LessThan => any_ordered(|ordering| ordering == Less),
+            EnableWhenOperator::GreaterOrEqual => any_ordered(|ordering| ordering != Less),
+            EnableWhenOperator::LessOrEqual => any_ordered(|ordering| ordering != Greater),
+        }
+    }
+}
+
+/// How several `enable_when` conditions combine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnableBehavior {
+    /// Every condition must hold
+    #[default]
+    All,
+    /// One condition is enough
+    Any,
+}
+
+/// Answer option of a choice question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnswerOption {
+    /// Code stored in answers
+    pub code: String,
+    /// Text shown to the respondent
+    pub display: String,
+    /// Score added when chosen (FHIR `ordinalValue`)
+    #[serde(default)]
+    pub score: Option<i32>,
+}
+
+/// Question, group or display text of a questionnaire
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestionnaireItem {
+    /// Id unique within the questionnaire, linking answers to the item
+    pub link_id: String,
+    /// Question or display text
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Kind of item
+    #[serde(rename = "type")]
+    pub item_type: ItemType,
+    /// Whether a completed response must answer it (when enabled)
+    #[serde(default)]
+    pub required: bool,
+    /// Whether it takes several answers
+    #[serde(default)]
+    pub repeats: bool,
+    /// Options of a choice question
+    #[serde(default)]
+    pub answer_options: Vec<AnswerOption>,
+    /// Conditions for showing the item
+    #[serde(default)]
+    pub enable_when: Vec<EnableWhen>,
+    /// How the conditions combine
+    #[serde(default)]
+    pub enable_behavior: EnableBehavior,
+    /// Items of a group
+    #[serde(default)]
+    pub items: Vec<QuestionnaireItem>,
+}
+
+impl QuestionnaireItem {
+    /// Whether the item is shown, given the answers to earlier enabled questions
+    pub fn is_enabled(&self, answers: &HashMap<&str, &[AnswerValue]>) -> bool {
+        let met = |condition: &EnableWhen| {
+            let given = answers.get(condition.question.as_str()).copied().unwrap_or(&[]);
+            condition.is_met(given)
+        };
+        match self.enable_behavior {
+            EnableBehavior::All => self.enable_when.iter().all(met),
+            EnableBehavior::Any => self.enable_when.is_empty() || self.enable_when.iter().any(met),
+        }
+    }
+
+    fn option(&self, code: &str) -> Option<&AnswerOption> {
+        self.answer_options.iter().find(|option| option.code == code)
+    }
+}
+
+/// A form definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Questionnaire {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Canonical URL, when the form is published under one
+    pub url: Option<String>,
+    /// Form title
+    pub title: String,
+    /// Publication status
+    pub status: QuestionnaireStatus,
+    /// Top-level items
+    pub items: Vec<QuestionnaireItem>,
+}
+
+impl Questionnaire {
+    /// New draft questionnaire
+    pub fn new(title: &str, items: Vec<QuestionnaireItem>) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            url: None,
+            title: title.trim().to_string(),
+            status: QuestionnaireStatus::Draft,
+            items,
+        }
+    }
+
+    /// Move the questionnaire to another status
+    ///
+    /// Returns `false` when it already has the status.
+    pub fn transition(&mut self, next: QuestionnaireStatus) -> Result<bool> {
+        if self.status == next {
+            return Ok(false);
+        }
+        if !self.status.can_transition_to(next) {
+            return Err(Error::business_rule_violation(
+                "invalid_questionnaire_transition",
+                &format!("Questionnaires cannot move from {} to {}", self.status.as_str(), next.as_str()),
+            ));
+        }
+        self.status = next;
+        self.metadata.update();
+        Ok(true)
+    }
+
+    /// All items, depth first in form order
+    pub fn all_items(&self) -> Vec<&QuestionnaireItem> {
+        fn collect<'a>(items: &'a [QuestionnaireItem], into: &mut Vec<&'a QuestionnaireItem>) {
+            for item in items {
+                into.push(item);
+                collect(&item.items, into);
+            }
+        }
+
+        let mut all = Vec::new();
+        collect(&self.items, &mut all);
+        all
+    }
+
+    /// The item with a `link_id`
+    pub fn find_item(&self, link_id: &str) -> Option<&QuestionnaireItem> {
+        self.all_items().into_iter().find(|item| item.link_id == link_id)
+    }
+
+    /// `link_id`s of the items shown for the answers given so far, in form order
+    pub fn enabled_items(&self, answers: &[ResponseAnswer]) -> Vec<String> {
+        fn walk<'b>(
+            items: &[QuestionnaireItem],
+            given: &HashMap<&'b str, &'b [AnswerValue]>,
+            effective: &mut HashMap<&'b str, &'b [AnswerValue]>,
+            enabled: &mut Vec<String>,
+        ) {
+            for item in items {
+                if !item.is_enabled(effective) {
+                    continue;
+                }
+                enabled.push(item.link_id.clone());
+                if let Some((link_id, values)) = given.get_key_value(item.link_id.as_str()) {
+                    effective.insert(*link_id, *values);
+                }
+                walk(&item.items, given, effective, enabled);
+            }
+        }
+
+        let given: HashMap<&str, &[AnswerValue]> = answers
+            .iter()
+            .map(|answer| (answer.link_id.as_str(), answer.values.as_slice()))
+            .collect();
+        let mut enabled = Vec::new();
+        walk(&self.items, &given, &mut HashMap::new(), &mut enabled);
+        enabled
+    }
+
+    /// Check answers against the form and drop those to disabled questions
+    ///
+    /// With `complete`, every enabled required question must be answered.
+    pub fn check_answers(&self, answers: Vec<ResponseAnswer>, complete: bool) -> Result<Vec<ResponseAnswer>> {
+        let mut seen = HashSet::new();
+        let mut answers: Vec<ResponseAnswer> = answers.into_iter().filter(|answer| !answer.values.is_empty()).collect();
+        for answer in &answers {
+            let item = self
+                .find_item(&answer.link_id)
+                .filter(|item| item.item_type.is_question())
+                .ok_or_else(|| {
+                    Error::validation_error_with_field(&format!("Unknown question '{}'", answer.link_id), "answers")
+                })?;
+            if !seen.insert(answer.link_id.as_str()) {
+                return Err(Error::validation_error_with_field(
+                    &format!("Question '{}' is answered twice", answer.link_id),
+                    "answers",
+                ));
+            }
+            if !item.repeats && answer.values.len() > 1 {
+                return Err(Error::validation_error_with_field(
+                    &format!("Question '{}' takes one answer", answer.link_id),
+                    "answers",
+                ));
+            }
+            for value in &answer.values {
+                let valid = item.item_type.accepts(value)
+                    && match value {
+                        AnswerValue::Coding(code) => item.option(code).is_some(),
+                        _ => true,
+                    };
+                if !valid {
+                    return Err(Error::validation_error_with_field(
+                        &format!("Invalid answer to '{}' ({} question)", answer.link_id, item.item_type.as_str()),
+                        "answers",
+                    ));
+                }
+            }
+        }
+
+        let enabled: HashSet<String> = self.enabled_items(&answers).into_iter().collect();
+        answers.retain(|answer| enabled.contains(&answer.link_id));
+        if complete {
+            let missing = self.all_items().into_iter().find(|item| {
+                item.required
+                    && item.item_type.is_question()
+                    && enabled.contains(&item.link_id)
+                    && !answers.iter().any(|answer| answer.link_id == item.link_id)
+            });
+            if let Some(item) = missing {
+                return Err(Error::validation_error_with_field(
+                    &format!("Question '{}' is required", item.link_id),
+                    "answers",
+                ));
+            }
+        }
+        Ok(answers)
+    }
+
+    /// Sum of the scores of the chosen options, or `None` for unscored forms
+    pub fn score(&self, answers: &[ResponseAnswer]) -> Option<i32> {
+        let items = self.all_items();
+        let scored = items
+            .iter()
+            .any(|item| item.answer_options.iter().any(|option| option.score.is_some()));
+        if !scored {
+            return None;
+        }
+
+        let total = answers
+            .iter()
+            .filter_map(|answer| Some((items.iter().find(|item| item.link_id == answer.link_id)?, answer)))
+            .flat_map(|(item, answer)| {
+                answer.values.iter().filter_map(move |value| match value {
+                    AnswerValue::Coding(code) => item.option(code)?.score,
+                    _ => None,
+                })
+            })
+            .sum();
+        Some(total)
+    }
+}
+
+impl Validatable for Questionnaire {
+    fn validate(&self) -> Result<()> {
+        if self.title.is_empty() {
+            return Err(Error::validation_error_with_field("Questionnaires need a title", "title"));
+        }
+        let items = self.all_items();
+        if items.is_empty() || items.len() > MAX_QUESTIONNAIRE_ITEMS {
+            return Err(Error::validation_error_with_field(
+                &format!("Questionnaires have 1 to {} items", MAX_QUESTIONNAIRE_ITEMS),
+                "items",
+            ));
+        }
+
+        let mut link_ids = HashSet::new();
+        let mut earlier_questions = HashSet::new();
+        for item in items {
+            let invalid = |message: &str| {
+                Error::validation_error_with_field(&format!("Item '{}': {}", item.link_id, message), "items")
+            };
+            if item.link_id.trim().is_empty() {
+                return Err(Error::validation_error_with_field("Every item needs a link_id", "items"));
+            }
+            if !link_ids.insert(item.link_id.as_str()) {
+                return Err(invalid("link_id is used more than once"));
+            }
+            match item.item_type {
+                ItemType::Group if item.items.is_empty() => return Err(invalid("groups need items")),
+                ItemType::Group => {}
+                _ if !item.items.is_empty() => return Err(invalid("only groups contain items")),
+                ItemType::Display if item.required => return Err(invalid("display items cannot be required")),
+                ItemType::Choice if item.answer_options.is_empty() => {
+                    return Err(invalid("choice questions need answer options"))
+                }
+                _ => {}
+            }
+            let mut codes = HashSet::new();
+            if !item.answer_options.iter().all(|option| codes.insert(option.code.as_str())) {
+                return Err(invalid("answer option codes must be unique"));
+            }
+            for condition in &item.enable_when {
+                if !earlier_questions.contains(condition.question.as_str()) {
+                    return Err(invalid(&format!(
+                        "enable_when must refer to an earlier question, not '{}'",
+                        condition.question
+                    )));
+                }
+                if condition.operator == EnableWhenOperator::Exists
+                    && !matches!(condition.answer, AnswerValue::Boolean(_))
+                {
+                    return Err(invalid("'exists' conditions take a boolean answer"));
+                }
+            }
+            if item.item_type.is_question() {
+                earlier_questions.insert(item.link_id.as_str());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Response status (subset of FHIR `QuestionnaireResponse.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseStatus {
+    /// Partly filled in
+    InProgress,
+    /// Submitted
+    Completed,
+    /// Changed after submission
+    Amended,
+}
+
+impl ResponseStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseStatus::InProgress => "in-progress",
+            ResponseStatus::Completed => "completed",
+            ResponseStatus::Amended => "amended",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "in-progress" => Some(ResponseStatus::InProgress),
+            "completed" => Some(ResponseStatus::Completed),
+            "amended" => Some(ResponseStatus::Amended),
+            _ => None,
+        }
+    }
+}
+
+/// Answers to one question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseAnswer {
+    /// Question answered
+    pub link_id: String,
+    /// Its answers; one unless the question repeats
+    pub values: Vec<AnswerValue>,
+}
+
+/// A patient's answers to a questionnaire
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionnaireResponse {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Questionnaire answered
+    pub questionnaire_id: Id,
+    /// Patient the answers are about
+    pub patient_id: Id,
+    /// Practitioner who entered them; `None` when the patient did
+    pub author_id: Option<Id>,
+    /// Response status
+    pub status: ResponseStatus,
+    /// Answers to enabled questions
+    pub answers: Vec<ResponseAnswer>,
+    /// When the answers were last given
+    pub authored: Timestamp,
+    /// Total score of scored questionnaires
+    pub score: Option<i32>,
+}
+
+impl QuestionnaireResponse {
+    /// Record answers to an active questionnaire, completed or still in progress
+    pub fn record(
+        questionnaire: &Questionnaire,
+        patient_id: Id,
+        author_id: Option<Id>,
+        answers: Vec<ResponseAnswer>,
+        complete: bool,
+        now: Timestamp,
+    ) -> Result<Self> {
+        if questionnaire.status != QuestionnaireStatus::Active {
+            return Err(Error::business_rule_violation(
+                "questionnaire_not_active",
+                &format!("A {} questionnaire does not take responses", questionnaire.status.as_str()),
+            ));
+        }
+
+        let answers = questionnaire.check_answers(answers, complete)?;
+        Ok(Self {
+            metadata: EntityMetadata::new(),
+            questionnaire_id: questionnaire.metadata.id,
+            patient_id,
+            author_id,
+            status: if complete { ResponseStatus::Completed } else { ResponseStatus::InProgress },
+            score: questionnaire.score(&answers),
+            answers,
+            authored: now,
+        })
+    }
+
+    /// Replace the answers
+    ///
+    /// Responses in progress may stay in progress or be completed; changing a
+    /// submitted response amends it and must leave it complete.
+    pub fn revise(
+        &mut self,
+        questionnaire: &Questionnaire,
+        answers: Vec<ResponseAnswer>,
+        complete: bool,
+        now: Timestamp,
+    ) -> Result<()> {
+        let submitted = self.status != ResponseStatus::InProgress;
+        if submitted && !complete {
+            return Err(Error::business_rule_violation(
+                "response_already_submitted",
+                "A submitted response can only be amended with complete answers",
+            ));
+        }
+
+        self.answers = questionnaire.check_answers(answers, complete)?;
+        self.score = questionnaire.score(&self.answers);
+        self.status = match (submitted, complete) {
+            (true, _) => ResponseStatus::Amended,
+            (false, true) => ResponseStatus::Completed,
+            (false, false) => ResponseStatus::InProgress,
+        };
+        self.authored = now;
+        self.metadata.update();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn frequency(link_id: &str, text: &str) -> QuestionnaireItem {
+        let option = |code: &str, display: &str, score| AnswerOption {
+            code: code.to_string(),
+            display: display.to_string(),
+            score: Some(score),
+        };
+        QuestionnaireItem {
+            link_id: link_id.to_string(),
+            text: Some(text.to_string()),
+            item_type: ItemType::Choice,
+            required: true,
+            repeats: false,
+            answer_options: vec![
+                option("LA6568-5", "Not at all", 0),
+                option("LA6569-3", "Several days", 1),
+                option("LA6570-1", "More than half the days", 2),
+                option("LA6571-9", "Nearly every day", 3),
+            ],
+            enable_when: Vec::new(),
+            enable_behavior: EnableBehavior::All,
+            items: Vec::new(),
+        }
+    }
+
+    /// PHQ-2 with a follow-up asked only when the first answer is not "Not at all"
+    fn phq2() -> Questionnaire {
+        let mut follow_up = frequency("follow-up", "How difficult have these problems made things for you?");
+        follow_up.enable_when.push(EnableWhen {
+            question: "1".to_string(),
+            operator: EnableWhenOperator::NotEquals,
+            answer: AnswerValue::Coding("LA6568-5".to_string()),
+        });
+        let group = QuestionnaireItem {
+            link_id: "phq2".to_string(),
+            text: Some("Over the last 2 weeks, how often have you been bothered by".to_string()),
+            item_type: ItemType::Group,
+            required: false,
+            repeats: false,
+            answer_options: Vec::new(),
+            enable_when: Vec::new(),
+            enable_behavior: EnableBehavior::All,
+            items: vec![
+                frequency("1", "Little interest or pleasure in doing things"),
+                frequency("2", "Feeling down, depressed, or hopeless"),
+                follow_up,
+            ],
+        };
+
+        let mut questionnaire = Questionnaire::new("PHQ-2", vec![group]);
+        questionnaire.status = QuestionnaireStatus::Active;
+        questionnaire
+    }
+
+    fn answer(link_id: &str, code: &str) -> ResponseAnswer {
+        ResponseAnswer {
+            link_id: link_id.to_string(),
+            values: vec![AnswerValue::Coding(code.to_string())],
+        }
+    }
+
+    #[test]
+    fn test_enable_when_follows_answers() {
+        let questionnaire = phq2();
+        assert!(questionnaire.validate().is_ok());
+
+        assert_eq!(questionnaire.enabled_items(&[answer("1", "LA6568-5")]), vec!["phq2", "1", "2"]);
+        assert_eq!(
+            questionnaire.enabled_items(&[answer("1", "LA6570-1")]),
+            vec!["phq2", "1", "2", "follow-up"]
+        );
+    }
+
+    #[test]
+    fn test_responses_are_checked_and_scored() {
+        let questionnaire = phq2();
+        let patient = Uuid::new_v4();
+
+        let incomplete = vec![answer("1", "LA6571-9")];
+        assert!(QuestionnaireResponse::record(&questionnaire, patient, None, incomplete.clone(), true, Utc::now())
+            .is_err());
+        let mut response =
+            QuestionnaireResponse::record(&questionnaire, patient, None, incomplete, false, Utc::now()).unwrap();
+        assert_eq!(response.status, ResponseStatus::InProgress);
+
+        // The follow-up answer is dropped once question 1 disables it
+        let answers = vec![answer("1", "LA6568-5"), answer("2", "LA6569-3"), answer("follow-up", "LA6569-3")];
+        response.revise(&questionnaire, answers, true, Utc::now()).unwrap();
+        assert_eq!(response.status, ResponseStatus::Completed);
+        assert_eq!(response.answers.len(), 2);
+        assert_eq!(response.score, Some(1));
+
+        assert!(response.revise(&questionnaire, vec![answer("1", "LA6568-5")], false, Utc::now()).is_err());
+        assert!(response
+            .revise(&questionnaire, vec![answer("1", "nope"), answer("2", "LA6569-3")], true, Utc::now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_definitions_are_validated() {
+        let mut questionnaire = phq2();
+        questionnaire.items[0].items[0].enable_when.push(EnableWhen {
+            question: "2".to_string(),
+            operator: EnableWhenOperator::Exists,
+            answer: AnswerValue::Boolean(true),
+        });
+        assert!(questionnaire.validate().is_err(), "conditions must refer to earlier questions");
+
+        let mut questionnaire = phq2();
+        questionnaire.items[0].items[1].link_id = "1".to_string();
+        assert!(questionnaire.validate().is_err());
+
+        let condition = EnableWhen {
+            question: "weight".to_string(),
+            operator: EnableWhenOperator::GreaterOrEqual,
+            answer: AnswerValue::Decimal(100.0),
+        };
+        assert!(condition.is_met(&[AnswerValue::Integer(100)]));
+        assert!(!condition.is_met(&[]));
+    }
+}
@@ -0,0 +1,262 @@
+//! Referrals between organizations
+//!
+//! A [`Referral`] asks another organization to take over part of a patient's
+//! care. The referring practitioner drafts it with a reason and the patient's
+//! documents the target needs, then sends it. The target organization accepts
+//! or declines it and, once the patient has been seen, completes it. Only the
+//! transitions in [`ReferralStatus::can_transition_to`] are allowed.
+//!
+//! Sent referrals are exchanged as a FHIR `ServiceRequest` with a `Task`
+//! tracking the target's response, so responses can arrive either through
+//! this API or as status changes of that `Task`.
+
+use crate::domain::service_request::RequestPriority;
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Referral status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReferralStatus {
+    /// Being written, not yet sent
+    Draft,
+    /// Sent, waiting for the target organization
+    Sent,
+    /// Accepted by the target organization
+    Accepted,
+    /// Declined by the target organization
+    Declined,
+    /// The patient was seen and the referral closed
+    Completed,
+    /// Withdrawn by the referring side
+    Cancelled,
+}
+
+impl ReferralStatus {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferralStatus::Draft => "draft",
+            ReferralStatus::Sent => "sent",
+            ReferralStatus::Accepted => "accepted",
+            ReferralStatus::Declined => "declined",
+            ReferralStatus::Completed => "completed",
+            ReferralStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "draft" => Some(ReferralStatus::Draft),
+            "sent" => Some(ReferralStatus::Sent),
+            "accepted" => Some(ReferralStatus::Accepted),
+            "declined" => Some(ReferralStatus::Declined),
+            "completed" => Some(ReferralStatus::Completed),
+            "cancelled" => Some(ReferralStatus::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// Whether the referral still needs work (draft, sent or accepted)
+    pub fn is_open(&self) -> bool {
+        matches!(self, ReferralStatus::Draft | ReferralStatus::Sent | ReferralStatus::Accepted)
+    }
+
+    /// Whether a referral may move from this status to `next`
+    pub fn can_transition_to(&self, next: ReferralStatus) -> bool {
+        use ReferralStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Sent)
+                | (Draft, Cancelled)
+                | (Sent, Accepted)
+                | (Sent, Declined)
+                | (Sent, Cancelled)
+                | (Accepted, Completed)
+                | (Accepted, Cancelled)
+        )
+    }
+}
+
+/// A referral of a patient to another organization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Referral {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Patient referred
+    pub patient_id: Id,
+    /// Referring practitioner
+    pub requester_id: Id,
+    /// Organization the referral comes from
+    pub source_organization_id: Id,
+    /// Organization asked to take over care
+    pub target_organization_id: Id,
+    /// Why the patient is referred
+    pub reason: String,
+    /// Referral priority
+    pub priority: RequestPriority,
+    /// Referral status
+    pub status: ReferralStatus,
+    /// Patient documents sent along, as `DocumentReference` ids
+    pub document_ids: Vec<Id>,
+    /// When the referral was sent
+    pub sent_at: Option<Timestamp>,
+    /// When the target accepted or declined it
+    pub responded_at: Option<Timestamp>,
+    /// Why the target declined it
+    pub decline_reason: Option<String>,
+    /// When it was completed
+    pub completed_at: Option<Timestamp>,
+}
+
+impl Referral {
+    /// New draft referral
+    pub fn new(
+        patient_id: Id,
+        requester_id: Id,
+        source_organization_id: Id,
+        target_organization_id: Id,
+        reason: &str,
+        priority: RequestPriority,
+    ) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            patient_id,
+            requester_id,
+            source_organization_id,
+            target_organization_id,
+            reason: reason.trim().to_string(),
+            priority,
+            status: ReferralStatus::Draft,
+            document_ids: Vec::new(),
+            sent_at: None,
+            responded_at: None,
+            decline_reason: None,
+            completed_at: None,
+        }
+    }
+
+    /// Attach a patient document; documents can only change before sending
+    pub fn attach_document(&mut self, document_id: Id) -> Result<()> {
+        if self.status != ReferralStatus::Draft {
+            return Err(Error::business_rule_violation(
+                "referral_already_sent",
+                "Documents can only be attached to draft referrals",
+            ));
+        }
+        if !self.document_ids.contains(&document_id) {
+            self.document_ids.push(document_id);
+            self.metadata.update();
+        }
+        Ok(())
+    }
+
+    /// Move the referral to another status
+    ///
+    /// Declining needs a reason. Returns `false` when the referral already
+    /// has the status, so repeated responses are harmless.
+    pub fn transition(&mut self, next: ReferralStatus, reason: Option<&str>, at: Timestamp) -> Result<bool> {
+        if self.status == next {
+            return Ok(false);
+        }
+        if !self.status.can_transition_to(next) {
+            return Err(Error::business_rule_violation(
+                "invalid_referral_transition",
+                &format!("Referrals cannot move from {} to {}", self.status.as_str(), next.as_str()),
+            ));
+        }
+
+        match next {
+            ReferralStatus::Sent => self.sent_at = Some(at),
+            ReferralStatus::Accepted => self.responded_at = Some(at),
+            ReferralStatus::Declined => {
+                let reason = reason.map(str::trim).filter(|reason| !reason.is_empty()).ok_or_else(|| {
+                    Error::validation_error_with_field("Declining a referral needs a reason", "reason")
+                })?;
+                self.decline_reason = Some(reason.to_string());
+                self.responded_at = Some(at);
+            }
+            ReferralStatus::Completed => self.completed_at = Some(at),
+            ReferralStatus::Draft | ReferralStatus::Cancelled => {}
+        }
+        self.status = next;
+        self.metadata.update();
+        Ok(true)
+    }
+}
+
+impl Validatable for Referral {
+    fn validate(&self) -> Result<()> {
+        if self.reason.is_empty() {
+            return Err(Error::validation_error_with_field("Referrals need a reason", "reason"));
+        }
+        if self.source_organization_id == self.target_organization_id {
+            return Err(Error::validation_error_with_field(
+                "Referrals must go to another organization",
+                "target_organization_id",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn referral() -> Referral {
+        Referral::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "Suspected angina, please assess",
+            RequestPriority::Urgent,
+        )
+    }
+
+    #[test]
+    fn test_referral_workflow() {
+        let mut referral = referral();
+        let document = Uuid::new_v4();
+        referral.attach_document(document).unwrap();
+        referral.attach_document(document).unwrap();
+        assert_eq!(referral.document_ids, vec![document]);
+
+        assert!(referral.transition(ReferralStatus::Sent, None, Utc::now()).unwrap());
+        assert!(referral.attach_document(Uuid::new_v4()).is_err());
+        assert!(referral.transition(ReferralStatus::Completed, None, Utc::now()).is_err());
+
+        assert!(referral.transition(ReferralStatus::Accepted, None, Utc::now()).unwrap());
+        assert!(!referral.transition(ReferralStatus::Accepted, None, Utc::now()).unwrap(), "already accepted");
+        referral.transition(ReferralStatus::Completed, None, Utc::now()).unwrap();
+        assert!(!referral.status.is_open());
+        assert!(referral.completed_at.is_some());
+    }
+
+    #[test]
+    fn test_declining_needs_a_reason() {
+        let mut referral = referral();
+        referral.transition(ReferralStatus::Sent, None, Utc::now()).unwrap();
+
+        assert!(referral.transition(ReferralStatus::Declined, Some(" "), Utc::now()).is_err());
+        referral
+            .transition(ReferralStatus::Declined, Some("No capacity this quarter"), Utc::now())
+            .unwrap();
+        assert_eq!(referral.decline_reason.as_deref(), Some("No capacity this quarter"));
+        assert!(referral.transition(ReferralStatus::Cancelled, None, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_referrals_go_to_another_organization() {
+        let mut referral = referral();
+        assert!(referral.validate().is_ok());
+        referral.target_organization_id = referral.source_organization_id;
+        assert!(referral.validate().is_err());
+    }
+}
@@ -0,0 +1,351 @@
+//! Scheduled reports
+//!
+//! A [`ReportSchedule`] runs a saved [`ReportDefinition`] on a cadence:
+//! the jobs worker renders it as CSV or PDF, stores the file as a
+//! [`ReportRun`] and notifies the recipients that it is ready. A schedule
+//! may limit each run to the period before it, e.g. the encounters of the
+//! past week, by a date or timestamp field of the report's entity.
+
+use crate::domain::traits::Validatable;
+use crate::services::reporting::{compile, FieldKind, ReportDefinition};
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use chrono::{Datelike, Duration, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Most recipients a schedule notifies
+pub const MAX_RECIPIENTS: usize = 50;
+
+/// File format of a scheduled report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// Comma-separated values
+    Csv,
+    /// Printable table
+    Pdf,
+}
+
+impl ReportFormat {
+    /// Stored name, also the file extension
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "csv" => Some(ReportFormat::Csv),
+            "pdf" => Some(ReportFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    /// MIME type of the file
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "text/csv; charset=utf-8",
+            ReportFormat::Pdf => "application/pdf",
+        }
+    }
+}
+
+/// How often a report runs; runs start on the hour (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "every", rename_all = "snake_case")]
+pub enum ReportCadence {
+    /// Every day
+    Day {
+        /// Hour of the day, 0 to 23
+        hour: u32,
+    },
+    /// Every week
+    Week {
+        /// ISO day of the week, 1 (Monday) to 7 (Sunday)
+        weekday: u32,
+        /// Hour of the day, 0 to 23
+        hour: u32,
+    },
+    /// Every month
+    Month {
+        /// Day of the month, 1 to 28
+        day: u32,
+        /// Hour of the day, 0 to 23
+        hour: u32,
+    },
+}
+
+impl ReportCadence {
+    /// Check the cadence names a real time
+    pub fn validate(&self) -> Result<()> {
+        let (hour, valid) = match *self {
+            ReportCadence::Day { hour } => (hour, true),
+            ReportCadence::Week { weekday, hour } => (hour, (1..=7).contains(&weekday)),
+            ReportCadence::Month { day, hour } => (hour, (1..=28).contains(&day)),
+        };
+        if hour > 23 || !valid {
+            return Err(Error::validation_error_with_field(
+                "Cadences need an hour from 0 to 23, a weekday from 1 to 7 or a day of the month from 1 to 28",
+                "cadence",
+            ));
+        }
+        Ok(())
+    }
+
+    /// First run of a valid cadence after `after`
+    pub fn next_run(&self, after: Timestamp) -> Timestamp {
+        let hour = match *self {
+            ReportCadence::Day { hour } | ReportCadence::Week { hour, .. } | ReportCadence::Month { hour, .. } => hour,
+        };
+        let time = NaiveTime::from_hms_opt(hour.min(23), 0, 0).unwrap_or(NaiveTime::MIN);
+        // A month always holds a matching day within 31 days of any date
+        (0..=31)
+            .map(|days| after.date_naive() + Duration::days(days))
+            .filter(|date| match *self {
+                ReportCadence::Day { .. } => true,
+                ReportCadence::Week { weekday, .. } => date.weekday().number_from_monday() == weekday,
+                ReportCadence::Month { day, .. } => date.day() == day,
+            })
+            .map(|date| Utc.from_utc_datetime(&date.and_time(time)))
+            .find(|run| *run > after)
+            .unwrap_or(after + Duration::days(1))
+    }
+}
+
+/// Limits each run to the period before it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportPeriod {
+    /// Date or timestamp field of the report's entity
+    pub field: String,
+    /// Days before the run the period starts
+    pub days: u32,
+}
+
+/// A report run on a cadence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSchedule {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Name shown in notifications and the report history
+    pub name: String,
+    /// Practitioner who defined the schedule
+    pub owner_id: Id,
+    /// What the report shows
+    pub definition: ReportDefinition,
+    /// Period each run is limited to
+    pub period: Option<ReportPeriod>,
+    /// File format
+    pub format: ReportFormat,
+    /// When the report runs
+    pub cadence: ReportCadence,
+    /// Practitioners notified when a run is ready
+    pub recipients: Vec<Id>,
+    /// Whether the report still runs
+    pub active: bool,
+    /// When the report runs next
+    pub next_run_at: Timestamp,
+}
+
+impl ReportSchedule {
+    /// Create an active schedule first running at the cadence's next time
+    pub fn new(
+        name: &str,
+        owner_id: Id,
+        definition: ReportDefinition,
+        format: ReportFormat,
+        cadence: ReportCadence,
+    ) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            name: name.trim().to_string(),
+            owner_id,
+            definition,
+            period: None,
+            format,
+            cadence,
+            recipients: vec![owner_id],
+            active: true,
+            next_run_at: cadence.next_run(Utc::now()),
+        }
+    }
+
+    /// The definition a run at `run_at` executes
+    pub fn definition_at(&self, run_at: Timestamp) -> ReportDefinition {
+        match &self.period {
+            Some(period) => {
+                self.definition
+                    .within(&period.field, run_at - Duration::days(i64::from(period.days)), run_at)
+            }
+            None => self.definition.clone(),
+        }
+    }
+}
+
+impl Validatable for ReportSchedule {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.chars().count() > 100 {
+            return Err(Error::validation_error_with_field("Report names need 1 to 100 characters", "name"));
+        }
+        if self.recipients.is_empty() || self.recipients.len() > MAX_RECIPIENTS {
+            return Err(Error::validation_error_with_field(
+                &format!("Reports go to 1 to {} recipients", MAX_RECIPIENTS),
+                "recipients",
+            ));
+        }
+        if let Some(period) = &self.period {
+            if period.days == 0 || period.days > 366 {
+                return Err(Error::validation_error_with_field(
+                    "Report periods must be between 1 and 366 days",
+                    "period",
+                ));
+            }
+            let dated = self.definition.entity.fields().iter().any(|field| {
+                field.name == period.field && matches!(field.kind, FieldKind::Date | FieldKind::Timestamp)
+            });
+            if !dated {
+                return Err(Error::validation_error_with_field(
+                    &format!("Report periods need a date field, not '{}'", period.field),
+                    "period",
+                ));
+            }
+        }
+        self.cadence.validate()?;
+        compile(&self.definition_at(self.next_run_at)).map(|_| ())
+    }
+}
+
+/// Outcome of a scheduled report run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportRunStatus {
+    /// The file was generated
+    Completed,
+    /// The report could not be generated
+    Failed,
+}
+
+impl ReportRunStatus {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportRunStatus::Completed => "completed",
+            ReportRunStatus::Failed => "failed",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "completed" => Some(ReportRunStatus::Completed),
+            "failed" => Some(ReportRunStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A generated scheduled report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportRun {
+    /// Run id; the file is stored under it
+    pub id: Id,
+    /// Schedule run
+    pub schedule_id: Id,
+    /// When the run was due; periods end here
+    pub scheduled_for: Timestamp,
+    /// Outcome
+    pub status: ReportRunStatus,
+    /// File format
+    pub format: ReportFormat,
+    /// Rows in the report
+    pub row_count: Option<i64>,
+    /// File size in bytes
+    pub byte_count: Option<i64>,
+    /// Why the run failed
+    pub error: Option<String>,
+    /// When the run finished
+    pub generated_at: Timestamp,
+}
+
+impl ReportRun {
+    /// File name the run is downloaded as
+    pub fn file_name(&self, schedule_name: &str) -> String {
+        let slug: String = schedule_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+            .collect();
+        let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+        format!(
+            "{}-{}.{}",
+            if slug.is_empty() { "report" } else { &slug },
+            self.scheduled_for.format("%Y-%m-%d"),
+            self.format.as_str()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn at(day: u32, hour: u32) -> Timestamp {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_next_run() {
+        // 2024-03-06 is a Wednesday
+        let after = at(6, 9);
+        assert_eq!(ReportCadence::Day { hour: 6 }.next_run(after), at(7, 6));
+        assert_eq!(ReportCadence::Day { hour: 10 }.next_run(after), at(6, 10));
+        assert_eq!(ReportCadence::Week { weekday: 1, hour: 6 }.next_run(after), at(11, 6));
+        assert_eq!(ReportCadence::Week { weekday: 3, hour: 9 }.next_run(after), at(13, 9));
+        assert_eq!(
+            ReportCadence::Month { day: 1, hour: 6 }.next_run(after),
+            Utc.with_ymd_and_hms(2024, 4, 1, 6, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_schedule_validation() {
+        let definition: ReportDefinition =
+            serde_json::from_value(json!({"entity": "encounters", "metrics": [{"function": "count"}]})).unwrap();
+        let cadence = ReportCadence::Week { weekday: 1, hour: 6 };
+        let mut schedule = ReportSchedule::new("Weekly visits", Uuid::new_v4(), definition, ReportFormat::Pdf, cadence);
+        schedule.period = Some(ReportPeriod {
+            field: "start_date".to_string(),
+            days: 7,
+        });
+        assert!(schedule.validate().is_ok());
+
+        schedule.period = Some(ReportPeriod {
+            field: "class".to_string(),
+            days: 7,
+        });
+        assert!(schedule.validate().is_err(), "periods need a date field");
+
+        schedule.period = None;
+        schedule.cadence = ReportCadence::Month { day: 31, hour: 6 };
+        assert!(schedule.validate().is_err());
+    }
+
+    #[test]
+    fn test_run_file_name() {
+        let run = ReportRun {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::new_v4(),
+            scheduled_for: at(11, 6),
+            status: ReportRunStatus::Completed,
+            format: ReportFormat::Csv,
+            row_count: Some(3),
+            byte_count: Some(64),
+            error: None,
+            generated_at: at(11, 6),
+        };
+        assert_eq!(run.file_name("Weekly visits (AMB)"), "weekly-visits-amb-2024-03-11.csv");
+    }
+}
@@ -0,0 +1,345 @@
+//! Orders (service requests)
+//!
+//! A [`ServiceRequest`] is a lab, imaging or referral order placed by a
+//! requesting practitioner, optionally for a specific performer. Orders move
+//! through the FHIR `ServiceRequest.status` values; only the transitions in
+//! [`RequestStatus::can_transition_to`] are allowed. Incoming
+//! `DiagnosticReport`s that name an order in `basedOn` fulfil it, and a final
+//! report completes it.
+
+use crate::domain::traits::Validatable;
+use crate::types::{EntityMetadata, Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// SNOMED CT, the system of order category codes
+pub const SNOMED_SYSTEM: &str = "http://snomed.info/sct";
+
+/// Kind of order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestCategory {
+    /// Laboratory test
+    Laboratory,
+    /// Imaging study
+    Imaging,
+    /// Referral to another practitioner or organization
+    Referral,
+}
+
+impl RequestCategory {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestCategory::Laboratory => "laboratory",
+            RequestCategory::Imaging => "imaging",
+            RequestCategory::Referral => "referral",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "laboratory" => Some(RequestCategory::Laboratory),
+            "imaging" => Some(RequestCategory::Imaging),
+            "referral" => Some(RequestCategory::Referral),
+            _ => None,
+        }
+    }
+
+    /// SNOMED CT code and display of the category
+    pub fn snomed(&self) -> (&'static str, &'static str) {
+        match self {
+            RequestCategory::Laboratory => ("108252007", "Laboratory procedure"),
+            RequestCategory::Imaging => ("363679005", "Imaging"),
+            RequestCategory::Referral => ("3457005", "Patient referral"),
+        }
+    }
+}
+
+/// Order priority (FHIR `request-priority`), least urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    /// Normal priority
+    Routine,
+    /// Sooner than routine
+    Urgent,
+    /// As soon as possible, before urgent work
+    Asap,
+    /// Immediately, before all other work
+    Stat,
+}
+
+impl RequestPriority {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestPriority::Routine => "routine",
+            RequestPriority::Urgent => "urgent",
+            RequestPriority::Asap => "asap",
+            RequestPriority::Stat => "stat",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "routine" => Some(RequestPriority::Routine),
+            "urgent" => Some(RequestPriority::Urgent),
+            "asap" => Some(RequestPriority::Asap),
+            "stat" => Some(RequestPriority::Stat),
+            _ => None,
+        }
+    }
+}
+
+/// Order status (FHIR `ServiceRequest.status`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequestStatus {
+    /// Being written, not yet actionable
+    Draft,
+    /// Placed and actionable
+    Active,
+    /// Paused
+    OnHold,
+    /// Cancelled before completion
+    Revoked,
+    /// Fulfilled
+    Completed,
+    /// Placed in error
+    EnteredInError,
+}
+
+impl RequestStatus {
+    /// Stored (FHIR) name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestStatus::Draft => "draft",
+            RequestStatus::Active => "active",
+            RequestStatus::OnHold => "on-hold",
+            RequestStatus::Revoked => "revoked",
+            RequestStatus::Completed => "completed",
+            RequestStatus::EnteredInError => "entered-in-error",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "draft" => Some(RequestStatus::Draft),
+            "active" => Some(RequestStatus::Active),
+            "on-hold" => Some(RequestStatus::OnHold),
+            "revoked" => Some(RequestStatus::Revoked),
+            "completed" => Some(RequestStatus::Completed),
+            "entered-in-error" => Some(RequestStatus::EnteredInError),
+            _ => None,
+        }
+    }
+
+    /// Whether the order still waits for work (draft, active or on hold)
+    pub fn is_pending(&self) -> bool {
+        matches!(self, RequestStatus::Draft | RequestStatus::Active | RequestStatus::OnHold)
+    }
+
+    /// Whether an order may move from this status to `next`
+    pub fn can_transition_to(&self, next: RequestStatus) -> bool {
+        use RequestStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Active)
+                | (Draft, Revoked)
+                | (Draft, EnteredInError)
+                | (Active, OnHold)
+                | (Active, Revoked)
+                | (Active, Completed)
+                | (Active, EnteredInError)
+                | (OnHold, Active)
+                | (OnHold, Revoked)
+                | (OnHold, EnteredInError)
+        )
+    }
+}
+
+/// An order for a lab test, imaging study or referral
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceRequest {
+    /// Id, version and timestamps
+    pub metadata: EntityMetadata,
+    /// Patient the order is for
+    pub patient_id: Id,
+    /// Encounter the order was placed in
+    pub encounter_id: Option<Id>,
+    /// Kind of order
+    pub category: RequestCategory,
+    /// What is ordered, e.g. a LOINC test code
+    pub code: String,
+    /// Code system of `code`
+    pub code_system: String,
+    /// Human-readable name of what is ordered
+    pub display: Option<String>,
+    /// Order priority
+    pub priority: RequestPriority,
+    /// Order status
+    pub status: RequestStatus,
+    /// Practitioner placing the order
+    pub requester_id: Id,
+    /// Practitioner asked to perform it, if any
+    pub performer_id: Option<Id>,
+    /// Clinical reason for the order
+    pub reason: Option<String>,
+    /// When the order was placed
+    pub authored_on: Timestamp,
+    /// Reports fulfilling the order, as `DiagnosticReport/{id}` references
+    pub fulfilled_by: Vec<String>,
+    /// When the order was completed
+    pub completed_at: Option<Timestamp>,
+}
+
+impl ServiceRequest {
+    /// New active order
+    pub fn new(
+        patient_id: Id,
+        requester_id: Id,
+        category: RequestCategory,
+        code_system: &str,
+        code: &str,
+        priority: RequestPriority,
+    ) -> Self {
+        Self {
+            metadata: EntityMetadata::new(),
+            patient_id,
+            encounter_id: None,
+            category,
+            code: code.to_string(),
+            code_system: code_system.to_string(),
+            display: None,
+            priority,
+            status: RequestStatus::Active,
+            requester_id,
+            performer_id: None,
+            reason: None,
+            authored_on: Utc::now(),
+            fulfilled_by: Vec::new(),
+            completed_at: None,
+        }
+    }
+
+    /// Move the order to another status
+    pub fn transition(&mut self, next: RequestStatus) -> Result<()> {
+        if !self.status.can_transition_to(next) {
+            return Err(Error::business_rule_violation(
+                "invalid_order_transition",
+                &format!("Orders cannot move from {} to {}", self.status.as_str(), next.as_str()),
+            ));
+        }
+
+        self.status = next;
+        if next == RequestStatus::Completed {
+            self.completed_at = Some(Utc::now());
+        }
+        self.metadata.update();
+        Ok(())
+    }
+
+    /// Record a report fulfilling the order
+    ///
+    /// A `final` report completes an active order; other report statuses
+    /// only link the report. Linking a report twice changes nothing.
+    /// Returns whether the order changed.
+    pub fn fulfil(&mut self, report_reference: &str, report_status: &str) -> Result<bool> {
+        if !matches!(self.status, RequestStatus::Active | RequestStatus::OnHold | RequestStatus::Completed) {
+            return Err(Error::business_rule_violation(
+                "order_not_fulfillable",
+                &format!("A {} order cannot be fulfilled", self.status.as_str()),
+            ));
+        }
+
+        let mut changed = false;
+        if !self.fulfilled_by.iter().any(|reference| reference == report_reference) {
+            self.fulfilled_by.push(report_reference.to_string());
+            changed = true;
+        }
+        if matches!(report_status, "final" | "amended" | "corrected") && self.status != RequestStatus::Completed {
+            if self.status == RequestStatus::OnHold {
+                self.status = RequestStatus::Active;
+            }
+            self.transition(RequestStatus::Completed)?;
+            return Ok(true);
+        }
+        if changed {
+            self.metadata.update();
+        }
+        Ok(changed)
+    }
+}
+
+impl Validatable for ServiceRequest {
+    fn validate(&self) -> Result<()> {
+        if self.code.trim().is_empty() || self.code_system.trim().is_empty() {
+            return Err(Error::validation_error_with_field("Orders need a coded service", "code"));
+        }
+        if self.category == RequestCategory::Referral && self.performer_id.is_none() && self.reason.is_none() {
+            return Err(Error::validation_error_with_field(
+                "Referrals need a performer or a reason",
+                "performer_id",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::LOINC_SYSTEM;
+    use uuid::Uuid;
+
+    fn lipid_panel() -> ServiceRequest {
+        ServiceRequest::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            RequestCategory::Laboratory,
+            LOINC_SYSTEM,
+            "57698-3",
+            RequestPriority::Routine,
+        )
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        let mut order = lipid_panel();
+        order.transition(RequestStatus::OnHold).unwrap();
+        order.transition(RequestStatus::Active).unwrap();
+        order.transition(RequestStatus::Revoked).unwrap();
+        assert!(order.transition(RequestStatus::Active).is_err());
+        assert_eq!(order.metadata.version, 4);
+        assert!(!order.status.is_pending());
+    }
+
+    #[test]
+    fn test_reports_fulfil_orders() {
+        let mut order = lipid_panel();
+        assert!(order.fulfil("DiagnosticReport/r1", "preliminary").unwrap());
+        assert_eq!(order.status, RequestStatus::Active);
+        assert!(!order.fulfil("DiagnosticReport/r1", "preliminary").unwrap(), "already linked");
+
+        assert!(order.fulfil("DiagnosticReport/r1", "final").unwrap());
+        assert_eq!(order.status, RequestStatus::Completed);
+        assert!(order.completed_at.is_some());
+        assert_eq!(order.fulfilled_by, vec!["DiagnosticReport/r1".to_string()]);
+
+        let mut revoked = lipid_panel();
+        revoked.transition(RequestStatus::Revoked).unwrap();
+        assert!(revoked.fulfil("DiagnosticReport/r2", "final").is_err());
+    }
+
+    #[test]
+    fn test_priority_order() {
+        assert!(RequestPriority::Stat > RequestPriority::Asap);
+        assert!(RequestPriority::Urgent > RequestPriority::Routine);
+        assert_eq!(RequestStatus::parse("on-hold"), Some(RequestStatus::OnHold));
+    }
+}
@@ -0,0 +1,236 @@
+//! UCUM units of measure
+//!
+//! Validates quantity units against the subset of UCUM used for clinical
+//! observations, normalizes common aliases (e.g. `mmHg` to `mm[Hg]`), and
+//! converts between units of the same dimension so values recorded in
+//! different units can be compared.
+
+use crate::{Error, Result};
+
+/// UCUM code system URI
+pub const UCUM_SYSTEM: &str = "http://unitsofmeasure.org";
+
+/// Physical dimension of a unit; only units of the same dimension convert
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    /// Mass, e.g. kg or [lb_av]
+    Mass,
+    /// Length, e.g. cm or [in_i]
+    Length,
+    /// Temperature, e.g. Cel or [degF]
+    Temperature,
+    /// Pressure, e.g. mm[Hg]
+    Pressure,
+    /// Volume, e.g. mL or L
+    Volume,
+    /// Duration, e.g. s or min
+    Time,
+    /// Count per time, e.g. /min
+    Rate,
+    /// Mass per volume, e.g. mg/dL
+    MassConcentration,
+    /// Amount of substance per volume, e.g. mmol/L
+    SubstanceConcentration,
+    /// Body mass index, kg/m2
+    BodyMassIndex,
+    /// Dimensionless fraction, e.g. %
+    Fraction,
+}
+
+/// A UCUM unit known to the platform
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UcumUnit {
+    /// Case-sensitive UCUM code
+    pub code: &'static str,
+    /// Display name
+    pub display: &'static str,
+    /// Dimension of the unit
+    pub dimension: Dimension,
+    /// Factor to the dimension's base unit
+    factor: f64,
+    /// Offset to the dimension's base unit, applied after the factor
+    offset: f64,
+}
+
+const fn unit(code: &'static str, display: &'static str, dimension: Dimension, factor: f64) -> UcumUnit {
+    UcumUnit {
+        code,
+        display,
+        dimension,
+        factor,
+        offset: 0.0,
+    }
+}
+
+/// Supported UCUM units. Base units have a factor of 1.0.
+const UNITS: &[UcumUnit] = &[
+    unit("kg", "kilogram", Dimension::Mass, 1.0),
+    unit("g", "gram", Dimension::Mass, 0.001),
+    unit("mg", "milligram", Dimension::Mass, 1e-6),
+    unit("[lb_av]", "pound", Dimension::Mass, 0.453_592_37),
+    unit("[oz_av]", "ounce", Dimension::Mass, 0.028_349_523_125),
+    unit("m", "meter", Dimension::Length, 1.0),
+    unit("cm", "centimeter", Dimension::Length, 0.01),
+    unit("mm", "millimeter", Dimension::Length, 0.001),
+    unit("[in_i]", "inch", Dimension::Length, 0.0254),
+    unit("[ft_i]", "foot", Dimension::Length, 0.3048),
+    UcumUnit {
+        code: "Cel",
+        display: "degree Celsius",
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+        offset: 0.0,
+    },
+    UcumUnit {
+        code: "[degF]",
+        display: "degree Fahrenheit",
+        dimension: Dimension::Temperature,
+        factor: 5.0 / 9.0,
+        offset: -32.0 * 5.0 / 9.0,
+    },
+    UcumUnit {
+        code: "K",
+        display: "kelvin",
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+        offset: -273.15,
+    },
+    unit("mm[Hg]", "millimeter of mercury", Dimension::Pressure, 1.0),
+    unit("kPa", "kilopascal", Dimension::Pressure, 7.500_615_75),
+    unit("L", "liter", Dimension::Volume, 1.0),
+    unit("dL", "deciliter", Dimension::Volume, 0.1),
+    unit("mL", "milliliter", Dimension::Volume, 0.001),
+    unit("s", "second", Dimension::Time, 1.0),
+    unit("min", "minute", Dimension::Time, 60.0),
+    unit("h", "hour", Dimension::Time, 3600.0),
+    unit("d", "day", Dimension::Time, 86_400.0),
+    unit("wk", "week", Dimension::Time, 604_800.0),
+    unit("mo", "month", Dimension::Time, 2_629_800.0),
+    unit("a", "year", Dimension::Time, 31_557_600.0),
+    unit("/min", "per minute", Dimension::Rate, 1.0),
+    unit("/h", "per hour", Dimension::Rate, 1.0 / 60.0),
+    unit("mg/dL", "milligram per deciliter", Dimension::MassConcentration, 1.0),
+    unit("g/dL", "gram per deciliter", Dimension::MassConcentration, 1000.0),
+    unit("g/L", "gram per liter", Dimension::MassConcentration, 100.0),
+    unit("mmol/L", "millimole per liter", Dimension::SubstanceConcentration, 1.0),
+    unit("umol/L", "micromole per liter", Dimension::SubstanceConcentration, 0.001),
+    unit("kg/m2", "kilogram per square meter", Dimension::BodyMassIndex, 1.0),
+    unit("%", "percent", Dimension::Fraction, 1.0),
+];
+
+/// Common non-UCUM spellings and their UCUM codes
+const ALIASES: &[(&str, &str)] = &[
+    ("mmHg", "mm[Hg]"),
+    ("mm Hg", "mm[Hg]"),
+    ("bpm", "/min"),
+    ("beats/min", "/min"),
+    ("breaths/min", "/min"),
+    ("{beats}/min", "/min"),
+    ("{breaths}/min", "/min"),
+    ("C", "Cel"),
+    ("°C", "Cel"),
+    ("degC", "Cel"),
+    ("F", "[degF]"),
+    ("°F", "[degF]"),
+    ("degF", "[degF]"),
+    ("lb", "[lb_av]"),
+    ("lbs", "[lb_av]"),
+    ("oz", "[oz_av]"),
+    ("in", "[in_i]"),
+    ("ft", "[ft_i]"),
+    ("kg/m^2", "kg/m2"),
+    ("l", "L"),
+    ("ml", "mL"),
+    ("dl", "dL"),
+    ("hr", "h"),
+    ("yr", "a"),
+];
+
+/// Look up a unit by UCUM code or alias
+pub fn lookup(unit: &str) -> Option<&'static UcumUnit> {
+    let unit = unit.trim();
+    let code = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == unit)
+        .map(|(_, code)| *code)
+        .unwrap_or(unit);
+
+    UNITS.iter().find(|candidate| candidate.code == code)
+}
+
+/// Normalize a unit to its UCUM code
+pub fn normalize(unit: &str) -> Result<&'static str> {
+    lookup(unit)
+        .map(|unit| unit.code)
+        .ok_or_else(|| Error::validation_error_with_field(&format!("Unknown UCUM unit: {}", unit), "unit"))
+}
+
+/// Check if a unit is a supported UCUM code or alias
+pub fn is_valid(unit: &str) -> bool {
+    lookup(unit).is_some()
+}
+
+/// Convert a value between units of the same dimension
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64> {
+    let from_unit = lookup(from)
+        .ok_or_else(|| Error::validation_error_with_field(&format!("Unknown UCUM unit: {}", from), "unit"))?;
+    let to_unit = lookup(to)
+        .ok_or_else(|| Error::validation_error_with_field(&format!("Unknown UCUM unit: {}", to), "unit"))?;
+
+    if from_unit.code == to_unit.code {
+        return Ok(value);
+    }
+
+    if from_unit.dimension != to_unit.dimension {
+        return Err(Error::validation_error_with_field(
+            &format!("Cannot convert {} to {}", from_unit.code, to_unit.code),
+            "unit",
+        ));
+    }
+
+    let base = value * from_unit.factor + from_unit.offset;
+    Ok((base - to_unit.offset) / to_unit.factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_normalize_aliases() {
+        assert_eq!(normalize("mmHg").unwrap(), "mm[Hg]");
+        assert_eq!(normalize("mm[Hg]").unwrap(), "mm[Hg]");
+        assert_eq!(normalize("°F").unwrap(), "[degF]");
+        assert_eq!(normalize(" lbs ").unwrap(), "[lb_av]");
+        assert!(normalize("furlongs").is_err());
+    }
+
+    #[test]
+    fn test_ucum_codes_are_case_sensitive() {
+        assert!(is_valid("mg/dL"));
+        assert!(!is_valid("MG/DL"));
+    }
+
+    #[test]
+    fn test_mass_conversion() {
+        assert_close(convert(1.0, "kg", "[lb_av]").unwrap(), 2.204_622_62);
+        assert_close(convert(154.0, "lb", "kg").unwrap(), 69.853_225);
+    }
+
+    #[test]
+    fn test_temperature_conversion() {
+        assert_close(convert(98.6, "[degF]", "Cel").unwrap(), 37.0);
+        assert_close(convert(37.0, "Cel", "[degF]").unwrap(), 98.6);
+        assert_close(convert(0.0, "Cel", "K").unwrap(), 273.15);
+    }
+
+    #[test]
+    fn test_incompatible_conversion() {
+        assert!(convert(1.0, "kg", "cm").is_err());
+        assert!(convert(1.0, "kg", "stone").is_err());
+    }
+}
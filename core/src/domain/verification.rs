@@ -0,0 +1,261 @@
+//! Contact point verification codes
+//!
+//! A verification is issued for a single telecom value, delivered out of band
+//! (SMS or email) and confirmed by the patient entering the code. Codes expire
+//! and lock after too many wrong guesses.
+
+use crate::domain::values::ContactSystem;
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Number of digits in a verification code
+pub const VERIFICATION_CODE_LENGTH: usize = 6;
+
+/// Limits applied to verification codes
+#[derive(Debug, Clone)]
+pub struct VerificationPolicy {
+    /// How long an issued code stays valid
+    pub code_ttl: Duration,
+    /// Wrong guesses allowed before the code is locked
+    pub max_attempts: u32,
+    /// Minimum delay between two codes for the same contact point
+    pub resend_interval: Duration,
+    /// Codes allowed per contact point within `send_window`
+    pub max_sends: usize,
+    /// Window used for `max_sends`
+    pub send_window: Duration,
+}
+
+impl Default for VerificationPolicy {
+    fn default() -> Self {
+        Self {
+            code_ttl: Duration::minutes(10),
+            max_attempts: 5,
+            resend_interval: Duration::seconds(60),
+            max_sends: 5,
+            send_window: Duration::hours(1),
+        }
+    }
+}
+
+impl VerificationPolicy {
+    /// Check whether another code may be sent, given earlier send times
+    pub fn check_send_rate(&self, previous: &[Timestamp], now: Timestamp) -> Result<()> {
+        if let Some(last) = previous.iter().max() {
+            if now - *last < self.resend_interval {
+                return Err(Error::business_rule_violation(
+                    "verification_resend_interval",
+                    &format!("wait {} seconds before requesting another code", self.resend_interval.num_seconds()),
+                ));
+            }
+        }
+
+        let recent = previous.iter().filter(|sent| now - **sent < self.send_window).count();
+        if recent >= self.max_sends {
+            return Err(Error::business_rule_violation(
+                "verification_send_limit",
+                &format!("at most {} codes per {} minutes", self.max_sends, self.send_window.num_minutes()),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Outcome of a verification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VerificationStatus {
+    /// Code sent, awaiting confirmation
+    Pending,
+    /// Code confirmed
+    Verified,
+    /// Code expired before it was confirmed
+    Expired,
+    /// Too many wrong codes were entered
+    Locked,
+}
+
+/// A verification code issued for one patient contact point
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactVerification {
+    /// Identifier of the challenge
+    pub id: Id,
+    /// Patient the contact point belongs to
+    pub patient_id: Id,
+    /// Kind of contact point being verified
+    pub system: ContactSystem,
+    /// Contact point being verified
+    pub value: String,
+    /// Never serialized; only the delivery job sees the code
+    #[serde(skip)]
+    code: String,
+    /// When the code was sent
+    pub sent_at: Timestamp,
+    /// When the code stops being accepted
+    pub expires_at: Timestamp,
+    /// Wrong codes entered so far
+    pub attempts: u32,
+    /// Wrong codes allowed before the challenge locks
+    pub max_attempts: u32,
+    /// When the code was confirmed
+    pub verified_at: Option<Timestamp>,
+}
+
+impl ContactVerification {
+    /// Issue a new code, returning the verification and the plain code to deliver
+    pub fn issue(
+        patient_id: Id,
+        system: ContactSystem,
+        value: &str,
+        policy: &VerificationPolicy,
+        now: Timestamp,
+    ) -> Result<(Self, String)> {
+        if !matches!(system, ContactSystem::Phone | ContactSystem::Sms | ContactSystem::Email) {
+            return Err(Error::validation_error_with_field(
+                "Only phone, SMS and email contact points can be verified",
+                "system",
+            ));
+        }
+
+        let code = generate_code();
+        let verification = Self {
+            id: Uuid::new_v4(),
+            patient_id,
+            system,
+            value: value.to_string(),
+            code: code.clone(),
+            sent_at: now,
+            expires_at: now + policy.code_ttl,
+            attempts: 0,
+            max_attempts: policy.max_attempts,
+            verified_at: None,
+        };
+
+        Ok((verification, code))
+    }
+
+    /// Current status of the verification at `now`
+    pub fn status(&self, now: Timestamp) -> VerificationStatus {
+        if self.verified_at.is_some() {
+            VerificationStatus::Verified
+        } else if self.attempts >= self.max_attempts {
+            VerificationStatus::Locked
+        } else if now >= self.expires_at {
+            VerificationStatus::Expired
+        } else {
+            VerificationStatus::Pending
+        }
+    }
+
+    /// Check a code entered by the patient, counting wrong guesses
+    pub fn confirm(&mut self, code: &str, now: Timestamp) -> Result<()> {
+        match self.status(now) {
+            VerificationStatus::Pending => {}
+            VerificationStatus::Verified => return Ok(()),
+            VerificationStatus::Expired => {
+                return Err(Error::business_rule_violation(
+                    "verification_expired",
+                    "request a new verification code",
+                ))
+            }
+            VerificationStatus::Locked => {
+                return Err(Error::business_rule_violation(
+                    "verification_locked",
+                    "too many incorrect codes; request a new verification code",
+                ))
+            }
+        }
+
+        if code.trim() != self.code {
+            self.attempts += 1;
+            return Err(Error::validation_error_with_field("Incorrect verification code", "code"));
+        }
+
+        self.verified_at = Some(now);
+        Ok(())
+    }
+}
+
+/// Numeric code drawn from a v4 UUID's random bits
+fn generate_code() -> String {
+    let modulus = 10u128.pow(VERIFICATION_CODE_LENGTH as u32);
+    format!("{:0width$}", Uuid::new_v4().as_u128() % modulus, width = VERIFICATION_CODE_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn issue(now: Timestamp) -> (ContactVerification, String) {
+        ContactVerification::issue(
+            Uuid::new_v4(),
+            ContactSystem::Sms,
+            "+15555550100",
+            &VerificationPolicy::default(),
+            now,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_issue_generates_numeric_code() {
+        let (verification, code) = issue(Utc::now());
+        assert_eq!(code.len(), VERIFICATION_CODE_LENGTH);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(verification.status(verification.sent_at), VerificationStatus::Pending);
+
+        let result = ContactVerification::issue(
+            Uuid::new_v4(),
+            ContactSystem::Fax,
+            "+15555550100",
+            &VerificationPolicy::default(),
+            Utc::now(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_confirm() {
+        let now = Utc::now();
+        let (mut verification, code) = issue(now);
+
+        assert!(verification.confirm("not-the-code", now).is_err());
+        assert_eq!(verification.attempts, 1);
+
+        verification.confirm(&code, now).unwrap();
+        assert_eq!(verification.status(now), VerificationStatus::Verified);
+    }
+
+    #[test]
+    fn test_confirm_expired_and_locked() {
+        let now = Utc::now();
+        let (mut verification, code) = issue(now);
+        let later = now + Duration::minutes(11);
+        assert_eq!(verification.status(later), VerificationStatus::Expired);
+        assert!(verification.confirm(&code, later).is_err());
+
+        let (mut verification, code) = issue(now);
+        for _ in 0..5 {
+            let _ = verification.confirm("wrong", now);
+        }
+        assert_eq!(verification.status(now), VerificationStatus::Locked);
+        assert!(verification.confirm(&code, now).is_err());
+    }
+
+    #[test]
+    fn test_check_send_rate() {
+        let policy = VerificationPolicy::default();
+        let now = Utc::now();
+
+        assert!(policy.check_send_rate(&[], now).is_ok());
+        assert!(policy.check_send_rate(&[now - Duration::seconds(10)], now).is_err());
+
+        let sends: Vec<_> = (1..=5).map(|i| now - Duration::minutes(i * 5)).collect();
+        assert!(policy.check_send_rate(&sends, now).is_err());
+        assert!(policy.check_send_rate(&sends[..4], now).is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+//! LOINC-coded vital signs
+//!
+//! Builds vital sign observations following the FHIR vital signs profile:
+//! each measurement is an Observation in the `vital-signs` category with a
+//! LOINC code and UCUM unit, grouped under panel observations via `has_member`.
+
+use crate::domain::observation::{Observation, ObservationStatus, ObservationValue};
+use crate::domain::units::{self, UCUM_SYSTEM};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Observation category for vital signs
+pub const VITAL_SIGNS_CATEGORY: &str = "vital-signs";
+
+/// LOINC code for the vital signs panel
+pub const VITAL_SIGNS_PANEL_CODE: &str = "85353-1";
+
+/// LOINC code for the blood pressure panel
+pub const BLOOD_PRESSURE_PANEL_CODE: &str = "85354-9";
+
+/// LOINC code system URI
+pub const LOINC_SYSTEM: &str = "http://loinc.org";
+
+/// Individual vital sign measurements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VitalSign {
+    /// Systolic blood pressure
+    SystolicBloodPressure,
+    /// Diastolic blood pressure
+    DiastolicBloodPressure,
+    /// Heart rate
+    HeartRate,
+    /// Respiratory rate
+    RespiratoryRate,
+    /// Body temperature
+    BodyTemperature,
+    /// Oxygen saturation
+    OxygenSaturation,
+}
+
+impl VitalSign {
+    /// Every measurement
+    pub const ALL: [VitalSign; 6] = [
+        VitalSign::SystolicBloodPressure,
+        VitalSign::DiastolicBloodPressure,
+        VitalSign::HeartRate,
+        VitalSign::RespiratoryRate,
+        VitalSign::BodyTemperature,
+        VitalSign::OxygenSaturation,
+    ];
+
+    /// The measurement a LOINC code stands for, if it is a vital sign
+    pub fn from_loinc_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|vital_sign| vital_sign.loinc_code() == code)
+    }
+
+    /// LOINC code for the measurement
+    pub fn loinc_code(&self) -> &'static str {
+        match self {
+            VitalSign::SystolicBloodPressure => "8480-6",
+            VitalSign::DiastolicBloodPressure => "8462-4",
+            VitalSign::HeartRate => "8867-4",
+            VitalSign::RespiratoryRate => "9279-1",
+            VitalSign::BodyTemperature => "8310-5",
+            VitalSign::OxygenSaturation => "59408-5",
+        }
+    }
+
+    /// Human-readable name
+    pub fn display(&self) -> &'static str {
+        match self {
+            VitalSign::SystolicBloodPressure => "Systolic blood pressure",
+            VitalSign::DiastolicBloodPressure => "Diastolic blood pressure",
+            VitalSign::HeartRate => "Heart rate",
+            VitalSign::RespiratoryRate => "Respiratory rate",
+            VitalSign::BodyTemperature => "Body temperature",
+            VitalSign::OxygenSaturation => "Oxygen saturation",
+        }
+    }
+
+    /// UCUM unit required by the vital signs profile
+    pub fn ucum_unit(&self) -> &'static str {
+        match self {
+            VitalSign::SystolicBloodPressure | VitalSign::DiastolicBloodPressure => "mm[Hg]",
+            VitalSign::HeartRate | VitalSign::RespiratoryRate => "/min",
+            VitalSign::BodyTemperature => "Cel",
+            VitalSign::OxygenSaturation => "%",
+        }
+    }
+
+    /// Physiologically plausible range, used to reject data-entry errors
+    pub fn plausible_range(&self) -> (f64, f64) {
+        match self {
+            VitalSign::SystolicBloodPressure => (40.0, 300.0),
+            VitalSign::DiastolicBloodPressure => (20.0, 200.0),
+            VitalSign::HeartRate => (20.0, 300.0),
+            VitalSign::RespiratoryRate => (2.0, 80.0),
+            VitalSign::BodyTemperature => (25.0, 45.0),
+            VitalSign::OxygenSaturation => (50.0, 100.0),
+        }
+    }
+
+    /// Check a measurement, converting it to the profile unit.
+    ///
+    /// Values in other units of the same dimension (e.g. `[degF]`, `mmHg`)
+    /// are converted; implausible values are rejected.
+    pub fn measurement(&self, value: f64, unit: &str) -> Result<f64> {
+        let field = format!("{:?}", self);
+        let ucum_unit = self.ucum_unit();
+
+        let value = units::convert(value, unit, ucum_unit).map_err(|_| {
+            Error::validation_error_with_field(
+                &format!("{} cannot be recorded in {}; expected {}", self.display(), unit, ucum_unit),
+                &field,
+            )
+        })?;
+
+        let (low, high) = self.plausible_range();
+        if !value.is_finite() || value < low || value > high {
+            return Err(Error::validation_error_with_field(
+                &format!("{} of {} {} is outside the plausible range", self.display(), value, ucum_unit),
+                &field,
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Build a final vital sign observation for a patient, with the
+    /// measurement checked and converted by [`VitalSign::measurement`]
+    pub fn observation(&self, value: f64, unit: &str, subject: Id, effective: Timestamp) -> Result<Observation> {
+        let value = self.measurement(value, unit)?;
+        let ucum_unit = self.ucum_unit();
+
+        let mut observation = panel_observation(self.loinc_code(), subject, effective);
+        observation.value = Some(ObservationValue::Quantity {
+            value,
+            unit: ucum_unit.to_string(),
+            system: Some(UCUM_SYSTEM.to_string()),
+            code: Some(ucum_unit.to_string()),
+        });
+        Ok(observation)
+    }
+}
+
+/// Build an empty vital signs observation, used for panels that only group
+/// member observations
+pub fn panel_observation(code: &str, subject: Id, effective: Timestamp) -> Observation {
+    let mut observation = Observation::new(ObservationStatus::Final, code.to_string(), subject);
+    observation.category.push(VITAL_SIGNS_CATEGORY.to_string());
+    observation.effective = Some(effective);
+    observation.issued = Some(chrono::Utc::now());
+    observation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_vital_sign_observation() {
+        let observation = VitalSign::HeartRate
+            .observation(72.0, "/min", uuid::Uuid::new_v4(), Utc::now())
+            .unwrap();
+
+        assert_eq!(observation.code, "8867-4");
+        assert_eq!(observation.category, vec![VITAL_SIGNS_CATEGORY.to_string()]);
+        assert!(matches!(
+            observation.value,
+            Some(ObservationValue::Quantity { value, .. }) if value == 72.0
+        ));
+    }
+
+    #[test]
+    fn test_vital_sign_wrong_unit() {
+        let result = VitalSign::SystolicBloodPressure.observation(120.0, "kg", uuid::Uuid::new_v4(), Utc::now());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_vital_sign_unit_conversion() {
+        let observation = VitalSign::BodyTemperature
+            .observation(98.6, "[degF]", uuid::Uuid::new_v4(), Utc::now())
+            .unwrap();
+
+        match observation.value {
+            Some(ObservationValue::Quantity { value, unit, .. }) => {
+                assert!((value - 37.0).abs() < 1e-6);
+                assert_eq!(unit, "Cel");
+            }
+            other => panic!("Unexpected value: {:?}", other),
+        }
+
+        assert!(VitalSign::SystolicBloodPressure
+            .observation(120.0, "mmHg", uuid::Uuid::new_v4(), Utc::now())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_vital_sign_implausible_value() {
+        let result = VitalSign::OxygenSaturation.observation(120.0, "%", uuid::Uuid::new_v4(), Utc::now());
+        assert!(result.is_err());
+
+        let result = VitalSign::BodyTemperature.observation(f64::NAN, "Cel", uuid::Uuid::new_v4(), Utc::now());
+        assert!(result.is_err());
+    }
+}
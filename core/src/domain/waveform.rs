@@ -0,0 +1,407 @@
+//! SampledData waveform encoding
+//!
+//! FHIR `SampledData` carries samples as space-separated decimals, which is
+//! bulky for high-frequency waveforms (ECG, pleth). Waveforms can instead be
+//! stored in one of two compact binary encodings, both base64 wrapped:
+//!
+//! - [`SampledDataEncoding::DeltaVarint`]: samples quantized to integer
+//!   steps of `factor`, delta encoded, zigzag mapped and written as LEB128
+//!   varints. Lossless for device data, which is already digital.
+//! - [`SampledDataEncoding::Float32`]: little-endian packed `f32`. Used when
+//!   samples are not quantized or include error markers (`E`, stored as NaN).
+
+use crate::domain::observation::ObservationValue;
+use crate::{Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+/// Encoding of the `data` string in `ObservationValue::SampledData`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampledDataEncoding {
+    /// FHIR decimal text (`"1 2 3 E L U"`)
+    #[default]
+    Text,
+    /// Base64 of zigzag varint deltas of quantized samples
+    DeltaVarint,
+    /// Base64 of little-endian packed f32 samples
+    Float32,
+}
+
+/// Decoded waveform with samples in physical units
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waveform {
+    /// Zero value added to every sample
+    pub origin: f64,
+    /// Milliseconds between samples
+    pub period: f64,
+    /// Multiplier applied to raw samples
+    pub factor: f64,
+    /// Lower detection limit, substituted for `L` samples
+    pub lower_limit: Option<f64>,
+    /// Upper detection limit, substituted for `U` samples
+    pub upper_limit: Option<f64>,
+    /// Number of interleaved channels
+    pub dimensions: u32,
+    /// Physical sample values, interleaved by dimension; NaN marks errors
+    pub samples: Vec<f64>,
+}
+
+impl Waveform {
+    /// Decode an `ObservationValue::SampledData`
+    pub fn from_value(value: &ObservationValue) -> Result<Self> {
+        let (origin, period, factor, lower_limit, upper_limit, dimensions, data, encoding) = match value {
+            ObservationValue::SampledData {
+                origin,
+                period,
+                factor,
+                lower_limit,
+                upper_limit,
+                dimensions,
+                data,
+                encoding,
+            } => (*origin, *period, factor.unwrap_or(1.0), *lower_limit, *upper_limit, *dimensions, data, *encoding),
+            _ => return Err(Error::validation_error("Observation value is not SampledData")),
+        };
+
+        if dimensions == 0 || period <= 0.0 || factor == 0.0 {
+            return Err(Error::validation_error_with_field(
+                "SampledData requires positive dimensions and period and a non-zero factor",
+                "value",
+            ));
+        }
+
+        let raw = match encoding {
+            SampledDataEncoding::Text => decode_text(data, origin, factor, lower_limit, upper_limit)?,
+            SampledDataEncoding::DeltaVarint => decode_delta_varint(data)?
+                .into_iter()
+                .map(|step| step as f64)
+                .collect(),
+            SampledDataEncoding::Float32 => decode_float32(data)?,
+        };
+
+        // Text samples are already raw digital values; convert all encodings
+        // to physical units the same way.
+        let samples = raw.into_iter().map(|sample| origin + factor * sample).collect();
+
+        Ok(Self {
+            origin,
+            period,
+            factor,
+            lower_limit,
+            upper_limit,
+            dimensions,
+            samples,
+        })
+    }
+
+    /// Encode the waveform as an `ObservationValue::SampledData`
+    pub fn to_value(&self, encoding: SampledDataEncoding) -> Result<ObservationValue> {
+        let raw: Vec<f64> = self
+            .samples
+            .iter()
+            .map(|sample| (sample - self.origin) / self.factor)
+            .collect();
+
+        let data = match encoding {
+            SampledDataEncoding::Text => encode_text(&raw),
+            SampledDataEncoding::DeltaVarint => {
+                if raw.iter().any(|sample| !sample.is_finite()) {
+                    return Err(Error::validation_error(
+                        "Delta encoding cannot represent error samples; use Float32",
+                    ));
+                }
+                encode_delta_varint(&raw.iter().map(|sample| sample.round() as i64).collect::<Vec<_>>())
+            }
+            SampledDataEncoding::Float32 => encode_float32(&raw),
+        };
+
+        Ok(ObservationValue::SampledData {
+            origin: self.origin,
+            period: self.period,
+            factor: Some(self.factor),
+            lower_limit: self.lower_limit,
+            upper_limit: self.upper_limit,
+            dimensions: self.dimensions,
+            data,
+            encoding,
+        })
+    }
+
+    /// Encode with the most compact encoding for the samples
+    pub fn compress(&self) -> Result<ObservationValue> {
+        self.to_value(self.preferred_encoding())
+    }
+
+    /// DeltaVarint when every sample is a whole number of `factor` steps
+    /// from `origin` (lossless), otherwise Float32
+    pub fn preferred_encoding(&self) -> SampledDataEncoding {
+        let quantized = self.samples.iter().all(|sample| {
+            let raw = (sample - self.origin) / self.factor;
+            raw.is_finite() && (raw - raw.round()).abs() < 1e-6
+        });
+
+        if quantized {
+            SampledDataEncoding::DeltaVarint
+        } else {
+            SampledDataEncoding::Float32
+        }
+    }
+
+    /// Number of time points (samples per dimension)
+    pub fn len(&self) -> usize {
+        self.samples.len() / self.dimensions as usize
+    }
+
+    /// Check if the waveform has no samples
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Duration of the waveform in milliseconds
+    pub fn duration_ms(&self) -> f64 {
+        self.len() as f64 * self.period
+    }
+
+    /// Extract the samples in `[start_ms, end_ms)`, relative to the start of
+    /// the waveform
+    pub fn window(&self, start_ms: f64, end_ms: f64) -> Waveform {
+        let first = (start_ms.max(0.0) / self.period).ceil() as usize;
+        let last = ((end_ms / self.period).ceil().max(0.0) as usize).min(self.len());
+        let dimensions = self.dimensions as usize;
+
+        let samples = if first < last {
+            self.samples[first * dimensions..last * dimensions].to_vec()
+        } else {
+            Vec::new()
+        };
+
+        Waveform { samples, ..self.clone() }
+    }
+
+    /// Split the waveform into consecutive windows of `segment_ms`
+    pub fn segments(&self, segment_ms: f64) -> Vec<(f64, Waveform)> {
+        let segment_ms = segment_ms.max(self.period);
+        let mut segments = Vec::new();
+        let mut start = 0.0;
+
+        while start < self.duration_ms() {
+            let segment = self.window(start, start + segment_ms);
+            if !segment.is_empty() {
+                segments.push((start, segment));
+            }
+            start += segment_ms;
+        }
+
+        segments
+    }
+
+    /// Samples as `(offset_ms, values per dimension)` points for charting
+    pub fn points(&self) -> Vec<(f64, Vec<f64>)> {
+        self.samples
+            .chunks(self.dimensions as usize)
+            .enumerate()
+            .map(|(index, values)| (index as f64 * self.period, values.to_vec()))
+            .collect()
+    }
+}
+
+/// Parse FHIR decimal text into raw sample values
+fn decode_text(
+    data: &str,
+    origin: f64,
+    factor: f64,
+    lower_limit: Option<f64>,
+    upper_limit: Option<f64>,
+) -> Result<Vec<f64>> {
+    // Limits are physical values; convert back to raw so every sample goes
+    // through the same origin/factor transform.
+    let to_raw = |limit: Option<f64>| limit.map(|l| (l - origin) / factor).unwrap_or(f64::NAN);
+
+    data.split_whitespace()
+        .map(|token| match token {
+            "E" => Ok(f64::NAN),
+            "L" => Ok(to_raw(lower_limit)),
+            "U" => Ok(to_raw(upper_limit)),
+            _ => token.parse::<f64>().map_err(|_| {
+                Error::validation_error_with_field(&format!("Invalid SampledData value: {}", token), "value")
+            }),
+        })
+        .collect()
+}
+
+/// Format raw sample values as FHIR decimal text
+fn encode_text(raw: &[f64]) -> String {
+    raw.iter()
+        .map(|sample| if sample.is_finite() { sample.to_string() } else { "E".to_string() })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Encode integer samples as base64 zigzag varint deltas
+pub fn encode_delta_varint(samples: &[i64]) -> String {
+    let mut bytes = Vec::with_capacity(samples.len());
+    let mut previous = 0i64;
+
+    for &sample in samples {
+        let delta = sample.wrapping_sub(previous);
+        previous = sample;
+
+        let mut zigzag = ((delta << 1) ^ (delta >> 63)) as u64;
+        loop {
+            let byte = (zigzag & 0x7f) as u8;
+            zigzag >>= 7;
+            if zigzag == 0 {
+                bytes.push(byte);
+                break;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    STANDARD.encode(bytes)
+}
+
+/// Decode base64 zigzag varint deltas into integer samples
+pub fn decode_delta_varint(data: &str) -> Result<Vec<i64>> {
+    let bytes = STANDARD
+        .decode(data.trim())
+        .map_err(|e| Error::validation_error_with_field(&format!("Invalid base64 waveform: {}", e), "value"))?;
+
+    let mut samples = Vec::new();
+    let mut previous = 0i64;
+    let mut zigzag = 0u64;
+    let mut shift = 0u32;
+
+    for byte in bytes {
+        if shift >= 64 {
+            return Err(Error::validation_error_with_field("Varint overflow in waveform", "value"));
+        }
+        zigzag |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            let delta = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+            previous = previous.wrapping_add(delta);
+            samples.push(previous);
+            zigzag = 0;
+            shift = 0;
+        }
+    }
+
+    if shift != 0 {
+        return Err(Error::validation_error_with_field("Truncated varint in waveform", "value"));
+    }
+
+    Ok(samples)
+}
+
+/// Encode samples as base64 little-endian f32
+pub fn encode_float32(samples: &[f64]) -> String {
+    let bytes: Vec<u8> = samples
+        .iter()
+        .flat_map(|&sample| (sample as f32).to_le_bytes())
+        .collect();
+    STANDARD.encode(bytes)
+}
+
+/// Decode base64 little-endian f32 samples
+pub fn decode_float32(data: &str) -> Result<Vec<f64>> {
+    let bytes = STANDARD
+        .decode(data.trim())
+        .map_err(|e| Error::validation_error_with_field(&format!("Invalid base64 waveform: {}", e), "value"))?;
+
+    if bytes.len() % 4 != 0 {
+        return Err(Error::validation_error_with_field(
+            "Float32 waveform length must be a multiple of 4 bytes",
+            "value",
+        ));
+    }
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as f64)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_value(data: &str, dimensions: u32) -> ObservationValue {
+        ObservationValue::SampledData {
+            origin: 2048.0,
+            period: 4.0,
+            factor: Some(0.5),
+            lower_limit: Some(0.0),
+            upper_limit: Some(4096.0),
+            dimensions,
+            data: data.to_string(),
+            encoding: SampledDataEncoding::Text,
+        }
+    }
+
+    #[test]
+    fn test_delta_varint_round_trip() {
+        let samples = vec![0, 1, -1, 300, 299, i64::MAX, i64::MIN, 42];
+        let encoded = encode_delta_varint(&samples);
+        assert_eq!(decode_delta_varint(&encoded).unwrap(), samples);
+    }
+
+    #[test]
+    fn test_delta_varint_rejects_truncated_data() {
+        let encoded = STANDARD.encode([0x80u8]);
+        assert!(decode_delta_varint(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_text_decoding() {
+        let waveform = Waveform::from_value(&text_value("0 2 -2 E L U", 1)).unwrap();
+
+        assert_eq!(&waveform.samples[..3], &[2048.0, 2049.0, 2047.0]);
+        assert!(waveform.samples[3].is_nan());
+        assert_eq!(waveform.samples[4], 0.0);
+        assert_eq!(waveform.samples[5], 4096.0);
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let waveform = Waveform::from_value(&text_value("10 12 15 11 9 10", 2)).unwrap();
+        assert_eq!(waveform.preferred_encoding(), SampledDataEncoding::DeltaVarint);
+
+        let decoded = Waveform::from_value(&waveform.compress().unwrap()).unwrap();
+        assert_eq!(decoded.samples, waveform.samples);
+
+        let fractional = Waveform::from_value(&text_value("10 10.5 11", 1)).unwrap();
+        assert_eq!(fractional.preferred_encoding(), SampledDataEncoding::Float32);
+
+        let with_errors = Waveform::from_value(&text_value("10 E 12", 1)).unwrap();
+        assert_eq!(with_errors.preferred_encoding(), SampledDataEncoding::Float32);
+        assert!(with_errors.to_value(SampledDataEncoding::DeltaVarint).is_err());
+
+        let decoded = Waveform::from_value(&with_errors.compress().unwrap()).unwrap();
+        assert_eq!(decoded.samples[0], with_errors.samples[0]);
+        assert!(decoded.samples[1].is_nan());
+    }
+
+    #[test]
+    fn test_window_and_segments() {
+        // 2 dimensions, 4ms period, 5 time points = 20ms
+        let waveform = Waveform::from_value(&text_value("0 1 2 3 4 5 6 7 8 9", 2)).unwrap();
+        assert_eq!(waveform.len(), 5);
+        assert_eq!(waveform.duration_ms(), 20.0);
+
+        let window = waveform.window(4.0, 12.0);
+        assert_eq!(window.len(), 2);
+        assert_eq!(window.samples[0], 2048.0 + 0.5 * 2.0);
+
+        let segments = waveform.segments(8.0);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[2].0, 16.0);
+        assert_eq!(segments[2].1.len(), 1);
+
+        let points = window.points();
+        assert_eq!(points[1].0, 4.0);
+        assert_eq!(points[1].1.len(), 2);
+    }
+}
@@ -12,61 +12,77 @@ pub enum Error {
     /// Entity not found
     #[error("Entity not found: {entity_type} with id {id}")]
     EntityNotFound {
+        /// Type of the entity looked up
         entity_type: String,
+        /// Identifier looked up
         id: Uuid,
     },
 
     /// Validation error
     #[error("Validation error: {message}")]
     ValidationError {
+        /// What failed
         message: String,
+        /// Field that failed, if any
         field: Option<String>,
     },
 
     /// Business rule violation
     #[error("Business rule violation: {rule}")]
     BusinessRuleViolation {
+        /// Rule that was violated
         rule: String,
+        /// What the rule was applied to
         context: String,
     },
 
     /// Authorization error
     #[error("Authorization error: {message}")]
     AuthorizationError {
+        /// Why access was denied
         message: String,
+        /// Scope that would grant access
         required_scope: Option<String>,
     },
 
     /// FHIR-specific errors
     #[error("FHIR error: {message}")]
     FhirError {
+        /// What failed
         message: String,
+        /// Resource type involved
         resource_type: Option<String>,
     },
 
     /// Data integrity error
     #[error("Data integrity error: {message}")]
     DataIntegrityError {
+        /// What failed
         message: String,
+        /// Constraint that was violated
         constraint: Option<String>,
     },
 
     /// External service error
     #[error("External service error: {service} - {message}")]
     ExternalServiceError {
+        /// Service that failed
         service: String,
+        /// What failed
         message: String,
     },
 
     /// Configuration error
     #[error("Configuration error: {message}")]
     ConfigurationError {
+        /// What is misconfigured
         message: String,
     },
 
     /// Generic internal error
     #[error("Internal error: {message}")]
     InternalError {
+        /// What failed
         message: String,
     },
 }
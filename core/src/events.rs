@@ -0,0 +1,694 @@
+//! Versioned event envelopes
+//!
+//! Events published on NATS travel in an [`EventEnvelope`] carrying the event
+//! type, the version of the payload's schema, the tenant, the trace context
+//! of the request that raised it, and the payload. Consumers check the type
+//! and version before reading the payload: older versions are upgraded with
+//! [`Event::upgrade`], and versions newer than the consumer knows are refused
+//! instead of misread. Payloads published before envelopes existed are read
+//! as version 1.
+//!
+//! The payload schemas are registered in [`schemas`]. Within a version a
+//! schema may only change compatibly: new fields are optional and existing
+//! fields keep their kind and stay present. [`compare`] lists what changed
+//! between two schemas, and a test checks the registry against the published
+//! snapshot in `schemas/events.json`, so a breaking change has to come with a
+//! new version (and an upgrade for consumers).
+
+use crate::types::Id;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Why an event could not be read
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EventError {
+    /// The message is not JSON, or not an envelope
+    #[error("Malformed event: {0}")]
+    Malformed(String),
+
+    /// The envelope holds another event type
+    #[error("Expected a {expected} event, got {found}")]
+    WrongType {
+        /// Type the consumer reads
+        expected: String,
+        /// Type in the envelope
+        found: String,
+    },
+
+    /// The consumer cannot read this version of the payload
+    #[error("Version {version} of {event_type} events is not supported")]
+    UnsupportedVersion {
+        /// Event type
+        event_type: String,
+        /// Version in the envelope
+        version: u32,
+    },
+
+    /// The payload does not match its type
+    #[error("Invalid {event_type} v{version} payload: {message}")]
+    InvalidPayload {
+        /// Event type
+        event_type: String,
+        /// Version in the envelope
+        version: u32,
+        /// What serde reported
+        message: String,
+    },
+}
+
+/// Where an event was raised, for following it across services
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// `X-Request-ID` of the request that raised the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// W3C `traceparent` of the span that raised the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+}
+
+/// An event payload with its type and schema version
+pub trait Event: Serialize + DeserializeOwned {
+    /// Dotted event type, e.g. `job.event`
+    const EVENT_TYPE: &'static str;
+
+    /// Schema version this type reads and writes
+    const VERSION: u32;
+
+    /// Rewrite a payload of an older `version` as [`Event::VERSION`]
+    ///
+    /// Types that have only had one version support no other.
+    fn upgrade(version: u32, _payload: Value) -> Result<Value, EventError> {
+        Err(EventError::UnsupportedVersion {
+            event_type: Self::EVENT_TYPE.to_string(),
+            version,
+        })
+    }
+}
+
+/// An event as published
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope<T = Value> {
+    /// Unique per event; consumers use it to drop redeliveries
+    pub id: Uuid,
+    /// Dotted event type, e.g. `encounter.started`
+    pub event_type: String,
+    /// Schema version of the payload
+    pub version: u32,
+    /// Organization the event belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<Id>,
+    /// When the event happened
+    pub occurred_at: DateTime<Utc>,
+    /// Where the event was raised
+    #[serde(default)]
+    pub trace: TraceContext,
+    /// The event itself
+    pub payload: T,
+}
+
+impl<T> EventEnvelope<T> {
+    /// Envelope for a payload of `event_type` at `version`
+    pub fn with_type(event_type: &str, version: u32, payload: T) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type: event_type.to_string(),
+            version,
+            tenant_id: None,
+            occurred_at: Utc::now(),
+            trace: TraceContext::default(),
+            payload,
+        }
+    }
+
+    /// Attribute the event to a tenant
+    pub fn with_tenant(mut self, tenant_id: Id) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    /// Record where the event was raised
+    pub fn with_trace(mut self, trace: TraceContext) -> Self {
+        self.trace = trace;
+        self
+    }
+}
+
+impl<T: Serialize> EventEnvelope<T> {
+    /// The message body to publish
+    pub fn encode(&self) -> Result<Vec<u8>, EventError> {
+        serde_json::to_vec(self).map_err(|e| EventError::Malformed(e.to_string()))
+    }
+}
+
+impl<T: Event> EventEnvelope<T> {
+    /// Envelope for a typed event at its current version
+    pub fn new(payload: T) -> Self {
+        Self::with_type(T::EVENT_TYPE, T::VERSION, payload)
+    }
+
+    /// Read a typed event, upgrading older versions
+    pub fn decode(bytes: &[u8]) -> Result<Self, EventError> {
+        let envelope = EventEnvelope::read(bytes, T::EVENT_TYPE)?;
+        if envelope.event_type != T::EVENT_TYPE {
+            return Err(EventError::WrongType {
+                expected: T::EVENT_TYPE.to_string(),
+                found: envelope.event_type,
+            });
+        }
+
+        let version = envelope.version;
+        let payload = match version.cmp(&T::VERSION) {
+            std::cmp::Ordering::Equal => envelope.payload,
+            std::cmp::Ordering::Less => T::upgrade(version, envelope.payload)?,
+            std::cmp::Ordering::Greater => {
+                return Err(EventError::UnsupportedVersion {
+                    event_type: envelope.event_type,
+                    version,
+                })
+            }
+        };
+        let payload = serde_json::from_value(payload).map_err(|e| EventError::InvalidPayload {
+            event_type: T::EVENT_TYPE.to_string(),
+            version,
+            message: e.to_string(),
+        })?;
+
+        Ok(EventEnvelope {
+            id: envelope.id,
+            event_type: envelope.event_type,
+            version: T::VERSION,
+            tenant_id: envelope.tenant_id,
+            occurred_at: envelope.occurred_at,
+            trace: envelope.trace,
+            payload,
+        })
+    }
+}
+
+impl EventEnvelope<Value> {
+    /// Read an envelope without interpreting its payload
+    ///
+    /// A message without an envelope predates them and is taken as version 1
+    /// of `legacy_type`.
+    pub fn read(bytes: &[u8], legacy_type: &str) -> Result<Self, EventError> {
+        let value: Value = serde_json::from_slice(bytes).map_err(|e| EventError::Malformed(e.to_string()))?;
+        let enveloped = ["event_type", "version", "payload"]
+            .iter()
+            .all(|key| value.get(key).is_some());
+        if enveloped {
+            serde_json::from_value(value).map_err(|e| EventError::Malformed(e.to_string()))
+        } else if value.is_object() {
+            Ok(Self::with_type(legacy_type, 1, value))
+        } else {
+            Err(EventError::Malformed("expected a JSON object".to_string()))
+        }
+    }
+}
+
+/// What a payload field holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    /// Any string
+    String,
+    /// A UUID string
+    Uuid,
+    /// An RFC 3339 timestamp string
+    Timestamp,
+    /// Any number
+    Number,
+    /// `true` or `false`
+    Boolean,
+    /// A JSON object
+    Object,
+    /// A JSON array
+    Array,
+}
+
+impl FieldKind {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldKind::String => value.is_string(),
+            FieldKind::Uuid => value.as_str().is_some_and(|value| Uuid::parse_str(value).is_ok()),
+            FieldKind::Timestamp => value
+                .as_str()
+                .is_some_and(|value| DateTime::parse_from_rfc3339(value).is_ok()),
+            FieldKind::Number => value.is_number(),
+            FieldKind::Boolean => value.is_boolean(),
+            FieldKind::Object => value.is_object(),
+            FieldKind::Array => value.is_array(),
+        }
+    }
+}
+
+/// A top-level payload field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    /// JSON name
+    pub name: String,
+    /// What it holds
+    pub kind: FieldKind,
+    /// Whether every payload has it, not null
+    pub required: bool,
+}
+
+/// The payload of one version of an event type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventSchema {
+    /// Dotted event type
+    pub event_type: String,
+    /// Schema version
+    pub version: u32,
+    /// Top-level fields; payloads may carry others
+    pub fields: Vec<FieldSchema>,
+}
+
+impl EventSchema {
+    /// Schema without fields
+    pub fn new(event_type: &str, version: u32) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            version,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a field every payload has
+    pub fn required(self, name: &str, kind: FieldKind) -> Self {
+        self.field(name, kind, true)
+    }
+
+    /// Add a field payloads may leave out or set to null
+    pub fn optional(self, name: &str, kind: FieldKind) -> Self {
+        self.field(name, kind, false)
+    }
+
+    fn field(mut self, name: &str, kind: FieldKind, required: bool) -> Self {
+        self.fields.push(FieldSchema {
+            name: name.to_string(),
+            kind,
+            required,
+        });
+        self
+    }
+
+    /// Problems with a payload; empty when it matches
+    pub fn validate(&self, payload: &Value) -> Vec<String> {
+        let Some(object) = payload.as_object() else {
+            return vec!["payload is not an object".to_string()];
+        };
+        self.fields
+            .iter()
+            .filter_map(|field| match object.get(&field.name).filter(|value| !value.is_null()) {
+                None if field.required => Some(format!("{} is missing", field.name)),
+                Some(value) if !field.kind.matches(value) => {
+                    Some(format!("{} is not a {:?} value", field.name, field.kind))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A difference between two schemas of an event type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// A new field
+    Added {
+        /// Field name
+        field: String,
+        /// Whether payloads must have it
+        required: bool,
+    },
+    /// A field is gone
+    Removed {
+        /// Field name
+        field: String,
+    },
+    /// A field holds something else
+    KindChanged {
+        /// Field name
+        field: String,
+        /// What it held
+        from: FieldKind,
+        /// What it holds
+        to: FieldKind,
+    },
+    /// An optional field became required
+    MadeRequired {
+        /// Field name
+        field: String,
+    },
+    /// A required field became optional
+    MadeOptional {
+        /// Field name
+        field: String,
+    },
+}
+
+impl SchemaChange {
+    /// Whether a consumer or producer of the old schema breaks
+    ///
+    /// Consumers rely on required fields being present and holding what they
+    /// held, and old producers do not send fields that became required. Only
+    /// adding an optional field is compatible both ways.
+    pub fn is_breaking(&self) -> bool {
+        !matches!(self, SchemaChange::Added { required: false, .. })
+    }
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChange::Added { field, required: true } => write!(f, "required field {} added", field),
+            SchemaChange::Added { field, required: false } => write!(f, "optional field {} added", field),
+            SchemaChange::Removed { field } => write!(f, "field {} removed", field),
+            SchemaChange::KindChanged { field, from, to } => {
+                write!(f, "field {} changed from {:?} to {:?}", field, from, to)
+            }
+            SchemaChange::MadeRequired { field } => write!(f, "field {} made required", field),
+            SchemaChange::MadeOptional { field } => write!(f, "field {} made optional", field),
+        }
+    }
+}
+
+/// What changed from `old` to `new`
+pub fn compare(old: &EventSchema, new: &EventSchema) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    for field in &old.fields {
+        match new.fields.iter().find(|candidate| candidate.name == field.name) {
+            None => changes.push(SchemaChange::Removed {
+                field: field.name.clone(),
+            }),
+            Some(current) => {
+                if current.kind != field.kind {
+                    changes.push(SchemaChange::KindChanged {
+                        field: field.name.clone(),
+                        from: field.kind,
+                        to: current.kind,
+                    });
+                }
+                if current.required && !field.required {
+                    changes.push(SchemaChange::MadeRequired {
+                        field: field.name.clone(),
+                    });
+                }
+                if !current.required && field.required {
+                    changes.push(SchemaChange::MadeOptional {
+                        field: field.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for field in new.fields.iter().filter(|field| !old.fields.iter().any(|old| old.name == field.name)) {
+        changes.push(SchemaChange::Added {
+            field: field.name.clone(),
+            required: field.required,
+        });
+    }
+    changes
+}
+
+/// The breaking changes from `old` to `new` when both claim the same version
+pub fn check_compatibility(old: &EventSchema, new: &EventSchema) -> Result<(), Vec<SchemaChange>> {
+    if old.event_type != new.event_type || old.version != new.version {
+        return Ok(());
+    }
+    let breaking: Vec<_> = compare(old, new).into_iter().filter(SchemaChange::is_breaking).collect();
+    if breaking.is_empty() {
+        Ok(())
+    } else {
+        Err(breaking)
+    }
+}
+
+/// Encounter lifecycle events published for webhooks
+pub const ENCOUNTER_EVENTS: [&str; 4] = [
+    "encounter.created",
+    "encounter.arrived",
+    "encounter.started",
+    "encounter.finished",
+];
+
+/// Every published version of every event type
+///
+/// Versions stay registered while consumers may still receive them.
+pub fn schemas() -> Vec<EventSchema> {
+    let mut schemas = vec![
+        EventSchema::new("job.event", 1)
+            .required("job_id", FieldKind::Uuid)
+            .required("timestamp", FieldKind::Timestamp)
+            .required("event", FieldKind::String)
+            .optional("progress", FieldKind::Number)
+            .optional("status", FieldKind::String)
+            .optional("level", FieldKind::String)
+            .optional("message", FieldKind::String),
+        EventSchema::new("notification.in_app", 1)
+            .required("id", FieldKind::Uuid)
+            .required("recipient_id", FieldKind::Uuid)
+            .required("notification_type", FieldKind::String)
+            .required("priority", FieldKind::String)
+            .optional("subject", FieldKind::String)
+            .required("message", FieldKind::String)
+            .required("created_at", FieldKind::Timestamp),
+    ];
+    for event_type in ENCOUNTER_EVENTS {
+        schemas.push(
+            EventSchema::new(event_type, 1)
+                .required("id", FieldKind::Uuid)
+                .required("patient_id", FieldKind::Uuid)
+                .required("status", FieldKind::String)
+                .required("class", FieldKind::String)
+                .optional("type", FieldKind::String)
+                .required("reason", FieldKind::Array)
+                .required("participants", FieldKind::Array)
+                .required("locations", FieldKind::Array)
+                .optional("period", FieldKind::Object)
+                .optional("length", FieldKind::Number)
+                .optional("service_provider", FieldKind::Uuid),
+        );
+    }
+    schemas
+}
+
+/// A registered schema
+pub fn schema(event_type: &str, version: u32) -> Option<EventSchema> {
+    schemas()
+        .into_iter()
+        .find(|schema| schema.event_type == event_type && schema.version == version)
+}
+
+/// The newest registered version of an event type
+pub fn latest_version(event_type: &str) -> Option<u32> {
+    schemas()
+        .iter()
+        .filter(|schema| schema.event_type == event_type)
+        .map(|schema| schema.version)
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// `sample.event` as published in version 1: `name` only
+    #[derive(Debug, Serialize, Deserialize)]
+    struct SampleV1 {
+        name: String,
+    }
+
+    impl Event for SampleV1 {
+        const EVENT_TYPE: &'static str = "sample.event";
+        const VERSION: u32 = 1;
+    }
+
+    /// Version 2 split `name` into `given` and `family` and added an
+    /// optional `nickname`
+    #[derive(Debug, Serialize, Deserialize)]
+    struct SampleV2 {
+        given: String,
+        family: String,
+        #[serde(default)]
+        nickname: Option<String>,
+    }
+
+    impl Event for SampleV2 {
+        const EVENT_TYPE: &'static str = "sample.event";
+        const VERSION: u32 = 2;
+
+        fn upgrade(version: u32, payload: Value) -> Result<Value, EventError> {
+            match version {
+                1 => {
+                    let name = payload["name"].as_str().unwrap_or_default();
+                    let (given, family) = name.split_once(' ').unwrap_or((name, ""));
+                    Ok(json!({ "given": given, "family": family }))
+                }
+                _ => Err(EventError::UnsupportedVersion {
+                    event_type: Self::EVENT_TYPE.to_string(),
+                    version,
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let tenant_id = Uuid::new_v4();
+        let trace = TraceContext {
+            request_id: Some("req-1".to_string()),
+            traceparent: None,
+        };
+        let envelope = EventEnvelope::new(SampleV1 {
+            name: "Ada Lovelace".to_string(),
+        })
+        .with_tenant(tenant_id)
+        .with_trace(trace.clone());
+        let bytes = envelope.encode().unwrap();
+
+        let read = EventEnvelope::<SampleV1>::decode(&bytes).unwrap();
+        assert_eq!((read.event_type.as_str(), read.version), ("sample.event", 1));
+        assert_eq!((read.id, read.tenant_id, read.trace), (envelope.id, Some(tenant_id), trace));
+        assert_eq!(read.payload.name, "Ada Lovelace");
+
+        // A bare payload predates envelopes
+        let legacy = EventEnvelope::<SampleV1>::decode(br#"{"name": "Ada"}"#).unwrap();
+        assert_eq!((legacy.version, legacy.payload.name.as_str()), (1, "Ada"));
+        assert!(matches!(
+            EventEnvelope::<SampleV1>::decode(b"[1]"),
+            Err(EventError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_schema_evolution() {
+        let v1 = EventEnvelope::new(SampleV1 {
+            name: "Ada Lovelace".to_string(),
+        })
+        .encode()
+        .unwrap();
+        let v2 = EventEnvelope::new(SampleV2 {
+            given: "Grace".to_string(),
+            family: "Hopper".to_string(),
+            nickname: None,
+        })
+        .encode()
+        .unwrap();
+
+        // New consumers upgrade old payloads
+        let upgraded = EventEnvelope::<SampleV2>::decode(&v1).unwrap();
+        assert_eq!((upgraded.version, upgraded.payload.family.as_str()), (2, "Lovelace"));
+
+        // Old consumers refuse payloads they would misread
+        assert_eq!(
+            EventEnvelope::<SampleV1>::decode(&v2).unwrap_err(),
+            EventError::UnsupportedVersion {
+                event_type: "sample.event".to_string(),
+                version: 2
+            }
+        );
+
+        // Optional fields added within a version are ignored by older readers
+        // and defaulted by newer ones
+        let extended = EventEnvelope::with_type("sample.event", 1, json!({ "name": "Ada", "title": "Countess" }));
+        let read = EventEnvelope::<SampleV1>::decode(&extended.encode().unwrap()).unwrap();
+        assert_eq!(read.payload.name, "Ada");
+
+        let wrong = EventEnvelope::with_type("other.event", 1, json!({ "name": "Ada" }));
+        assert!(matches!(
+            EventEnvelope::<SampleV1>::decode(&wrong.encode().unwrap()),
+            Err(EventError::WrongType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compatibility_checker() {
+        let old = EventSchema::new("sample.event", 1)
+            .required("name", FieldKind::String)
+            .optional("age", FieldKind::Number);
+
+        let extended = old.clone().optional("nickname", FieldKind::String);
+        assert_eq!(check_compatibility(&old, &extended), Ok(()));
+
+        let mut changed = EventSchema::new("sample.event", 1)
+            .required("name", FieldKind::Object)
+            .required("age", FieldKind::Number)
+            .required("id", FieldKind::Uuid);
+        let breaking = check_compatibility(&old, &changed).unwrap_err();
+        assert_eq!(
+            breaking,
+            vec![
+                SchemaChange::KindChanged {
+                    field: "name".to_string(),
+                    from: FieldKind::String,
+                    to: FieldKind::Object
+                },
+                SchemaChange::MadeRequired { field: "age".to_string() },
+                SchemaChange::Added {
+                    field: "id".to_string(),
+                    required: true
+                },
+            ]
+        );
+
+        // Breaking changes are what new versions are for
+        changed.version = 2;
+        assert_eq!(check_compatibility(&old, &changed), Ok(()));
+        let removed = EventSchema::new("sample.event", 1).required("name", FieldKind::String);
+        assert_eq!(
+            check_compatibility(&old, &removed).unwrap_err()[0].to_string(),
+            "field age removed"
+        );
+    }
+
+    #[test]
+    fn test_validate_payload() {
+        let schema = schema("job.event", 1).unwrap();
+        let event = json!({
+            "job_id": Uuid::new_v4(),
+            "timestamp": Utc::now(),
+            "event": "progress",
+            "progress": 50.0,
+        });
+        assert!(schema.validate(&event).is_empty());
+        assert_eq!(
+            schema.validate(&json!({ "job_id": "job-1", "event": "progress" })),
+            vec!["job_id is not a Uuid value", "timestamp is missing"]
+        );
+    }
+
+    /// The registry may only change compatibly from the published snapshot.
+    /// After adding an optional field or a new version, update
+    /// `schemas/events.json` to match [`schemas`].
+    #[test]
+    fn test_registry_matches_snapshot() {
+        let published: Vec<EventSchema> = serde_json::from_str(include_str!("../schemas/events.json")).unwrap();
+        let registered = schemas();
+
+        for old in &published {
+            let current = registered
+                .iter()
+                .find(|schema| schema.event_type == old.event_type && schema.version == old.version)
+                .unwrap_or_else(|| {
+                    panic!("{} v{} was published and must stay registered", old.event_type, old.version)
+                });
+            if let Err(changes) = check_compatibility(old, current) {
+                let changes: Vec<_> = changes.iter().map(ToString::to_string).collect();
+                panic!(
+                    "{} v{} changed incompatibly ({}); publish a new version instead",
+                    old.event_type,
+                    old.version,
+                    changes.join(", ")
+                );
+            }
+        }
+        assert_eq!(published, registered, "schemas/events.json is out of date");
+    }
+}
@@ -0,0 +1,159 @@
+//! Feature flags
+//!
+//! A [`FeatureFlag`] turns a feature on or off at runtime without a deploy.
+//! A disabled flag is off everywhere. An enabled flag can be forced on or off
+//! per tenant (organization); for everyone else it is on for
+//! `rollout_percentage` percent of subjects, bucketed by a stable hash of the
+//! flag key and the subject so that each user keeps the same answer as the
+//! rollout grows.
+
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// A runtime feature toggle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureFlag {
+    /// Stable key code checks, e.g. `reports.scheduling`
+    pub key: String,
+    /// What the flag guards, for the admin UI
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Master switch; a disabled flag is off for every tenant
+    pub enabled: bool,
+    /// Percent of subjects the flag is on for, 0 to 100
+    pub rollout_percentage: u8,
+    /// Tenants the flag is forced on (`true`) or off (`false`) for
+    #[serde(default)]
+    pub tenants: BTreeMap<Id, bool>,
+    /// Incremented on every change
+    pub version: u64,
+    /// When the flag last changed
+    pub updated_at: Timestamp,
+    /// Who last changed the flag
+    #[serde(default)]
+    pub updated_by: Option<String>,
+}
+
+impl FeatureFlag {
+    /// Create an enabled flag that is on for everyone
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.trim().to_string(),
+            description: None,
+            enabled: true,
+            rollout_percentage: 100,
+            tenants: BTreeMap::new(),
+            version: 1,
+            updated_at: Utc::now(),
+            updated_by: None,
+        }
+    }
+
+    /// Check the key and rollout
+    pub fn validate(&self) -> Result<()> {
+        let valid_key = !self.key.is_empty()
+            && self.key.len() <= 100
+            && self
+                .key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'));
+        if !valid_key {
+            return Err(Error::validation_error_with_field(
+                "Flag keys are 1 to 100 lowercase letters, digits, '.', '_' or '-'",
+                "key",
+            ));
+        }
+        if self.rollout_percentage > 100 {
+            return Err(Error::validation_error_with_field(
+                "Rollout percentage must be between 0 and 100",
+                "rollout_percentage",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the flag is on for a subject (usually the user) of a tenant
+    pub fn is_enabled(&self, tenant_id: Option<Id>, subject: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if let Some(enabled) = tenant_id.and_then(|tenant_id| self.tenants.get(&tenant_id)) {
+            return *enabled;
+        }
+        match self.rollout_percentage {
+            0 => false,
+            100.. => true,
+            percentage => rollout_bucket(&self.key, subject) < percentage,
+        }
+    }
+
+    /// Record a change
+    pub fn touch(&mut self, updated_by: Option<String>) {
+        self.version += 1;
+        self.updated_at = Utc::now();
+        self.updated_by = updated_by;
+    }
+}
+
+/// Stable bucket from 0 to 99 of a subject for a flag; separate flags bucket
+/// the same subject independently
+pub fn rollout_bucket(key: &str, subject: &str) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(key.as_bytes())
+        .chain_update(b":")
+        .chain_update(subject.as_bytes())
+        .finalize();
+    let value = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    (value % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_flag_validation() {
+        assert!(FeatureFlag::new("reports.scheduling").validate().is_ok());
+        assert!(FeatureFlag::new("Reports Scheduling").validate().is_err());
+        let mut flag = FeatureFlag::new("reports.scheduling");
+        flag.rollout_percentage = 101;
+        assert!(flag.validate().is_err());
+    }
+
+    #[test]
+    fn test_tenant_rules() {
+        let (beta, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut flag = FeatureFlag::new("imaging.viewer");
+        flag.rollout_percentage = 0;
+        flag.tenants.insert(beta, true);
+
+        assert!(flag.is_enabled(Some(beta), "jane"));
+        assert!(!flag.is_enabled(Some(other), "jane"));
+        assert!(!flag.is_enabled(None, "jane"));
+
+        flag.enabled = false;
+        assert!(!flag.is_enabled(Some(beta), "jane"), "the master switch wins");
+    }
+
+    #[test]
+    fn test_percentage_rollout() {
+        let mut flag = FeatureFlag::new("search.v2");
+        flag.rollout_percentage = 25;
+        let subjects: Vec<String> = (0..1000).map(|i| format!("user-{}", i)).collect();
+        let on = subjects.iter().filter(|subject| flag.is_enabled(None, subject)).count();
+        assert!((150..350).contains(&on), "{} of 1000 subjects enabled", on);
+
+        // Subjects keep their answer as the rollout grows
+        let before: Vec<bool> = subjects.iter().map(|subject| flag.is_enabled(None, subject)).collect();
+        flag.rollout_percentage = 50;
+        assert!(subjects
+            .iter()
+            .zip(before)
+            .all(|(subject, was_on)| !was_on || flag.is_enabled(None, subject)));
+    }
+}
@@ -6,10 +6,22 @@
 //! This crate contains the pure domain logic without any external dependencies
 //! on web frameworks, databases, or other infrastructure concerns.
 
+pub mod billing;
+pub mod diagnostics;
 pub mod domain;
 pub mod error;
+pub mod events;
+pub mod flags;
+pub mod notifications;
+pub mod partitions;
 pub mod services;
 pub mod repositories;
+pub mod retention;
+pub mod secrets;
+pub mod signing;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod validation;
 
 pub use error::{Result, Error};
 
@@ -28,9 +40,13 @@ pub mod types {
     /// Common metadata for all entities
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct EntityMetadata {
+        /// Identifier of the entity
         pub id: Id,
+        /// When the entity was created
         pub created_at: Timestamp,
+        /// When the entity was last updated
         pub updated_at: Timestamp,
+        /// Version, incremented on every update
         pub version: u64,
     }
 
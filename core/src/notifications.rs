@@ -0,0 +1,707 @@
+//! Notification templates
+//!
+//! Notification jobs name a template and pass the structured data it needs
+//! instead of carrying preformatted text. A template has one variant per
+//! locale; a requested locale falls back from region (`es-MX`) to language
+//! (`es`) to [`DEFAULT_LOCALE`]. Templates use minijinja syntax and declare
+//! their variables: a template may only reference declared variables, and
+//! rendering fails when a required variable is missing or an undeclared one
+//! is passed.
+//!
+//! [`NotificationPreferences`] hold each user's channels per notification
+//! type, quiet hours and whether a type is sent immediately or in a daily
+//! digest. The notification handler asks them for a [`DeliveryDecision`]
+//! before sending.
+
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::{Duration, NaiveTime};
+use minijinja::{Environment, UndefinedBehavior};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::OnceLock;
+
+/// Locale every template has a variant for
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Verification code for a patient contact point
+pub const CONTACT_VERIFICATION_TEMPLATE: &str = "contact_verification";
+
+/// A secure message is waiting, without its content
+pub const SECURE_MESSAGE_TEMPLATE: &str = "secure_message";
+
+/// Upcoming appointment
+pub const APPOINTMENT_REMINDER_TEMPLATE: &str = "appointment_reminder";
+
+/// Critical result awaiting review
+pub const CRITICAL_RESULT_TEMPLATE: &str = "critical_result";
+
+/// Daily digest of held notifications
+pub const NOTIFICATION_DIGEST_TEMPLATE: &str = "notification_digest";
+
+/// Abnormal result waiting for the ordering practitioner's acknowledgment
+pub const ABNORMAL_RESULT_TEMPLATE: &str = "abnormal_result";
+
+/// Escalation of a critical result nobody acknowledged in time
+pub const UNACKNOWLEDGED_RESULT_TEMPLATE: &str = "unacknowledged_result";
+
+/// Suspicious login or access pattern, sent to security admins
+pub const SECURITY_EVENT_TEMPLATE: &str = "security_event";
+
+/// A variable a template accepts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateVariable {
+    /// Variable name as used in the template
+    pub name: String,
+    /// What the variable holds
+    pub description: String,
+    /// Whether rendering needs a value
+    pub required: bool,
+}
+
+/// One locale variant of a named template
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationTemplate {
+    /// Template name, shared by its locale variants
+    pub name: String,
+    /// BCP 47 language tag (`en`, `es`, `es-MX`)
+    pub locale: String,
+    /// Subject line for channels that have one (email, push)
+    pub subject: Option<String>,
+    /// Message text
+    pub body: String,
+    /// Variables the template accepts
+    pub variables: Vec<TemplateVariable>,
+}
+
+/// Template and data a notification is generated from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateRef {
+    /// Template name
+    pub name: String,
+    /// Recipient's locale; the default locale when absent
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Values of the template's variables
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+/// Text generated from a template
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderedNotification {
+    /// Template name
+    pub template: String,
+    /// Locale of the variant used, after fallback
+    pub locale: String,
+    /// Rendered subject line
+    pub subject: Option<String>,
+    /// Rendered message text
+    pub body: String,
+}
+
+/// Locales to try for a requested locale, most specific first
+fn locale_chain(locale: Option<&str>) -> Vec<String> {
+    let mut chain = Vec::new();
+    if let Some(locale) = locale {
+        let mut tag = locale.trim().replace('_', "-").to_ascii_lowercase();
+        while !tag.is_empty() {
+            chain.push(tag.clone());
+            tag = tag.rsplit_once('-').map(|(parent, _)| parent.to_string()).unwrap_or_default();
+        }
+    }
+    if !chain.iter().any(|tag| tag == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+    chain
+}
+
+/// Template environment that rejects use of undefined values
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env
+}
+
+/// Variables a template source references
+fn referenced_variables(env: &Environment<'_>, source: &str) -> Result<HashSet<String>> {
+    env.template_from_str(source)
+        .map(|template| template.undeclared_variables(false))
+        .map_err(|e| Error::validation_error(&format!("Invalid template: {}", e)))
+}
+
+/// Named templates and their locale variants
+#[derive(Debug, Clone, Default)]
+pub struct TemplateCatalog {
+    templates: Vec<NotificationTemplate>,
+}
+
+impl TemplateCatalog {
+    /// Empty catalog
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Catalog of the templates shipped with the platform
+    pub fn builtin() -> &'static TemplateCatalog {
+        static CATALOG: OnceLock<TemplateCatalog> = OnceLock::new();
+        CATALOG.get_or_init(|| {
+            let mut catalog = TemplateCatalog::new();
+            for template in builtin_templates() {
+                catalog.add(template).expect("built-in notification templates are valid");
+            }
+            catalog
+        })
+    }
+
+    /// Add a template variant after checking it compiles and references
+    /// only declared variables
+    pub fn add(&mut self, template: NotificationTemplate) -> Result<()> {
+        if template.name.trim().is_empty() || template.locale.trim().is_empty() {
+            return Err(Error::validation_error("Templates need a name and a locale"));
+        }
+        if self.variant(&template.name, &template.locale).is_some() {
+            return Err(Error::validation_error(&format!(
+                "Template {} already has a {} variant",
+                template.name, template.locale
+            )));
+        }
+
+        let declared: HashSet<&str> = template.variables.iter().map(|v| v.name.as_str()).collect();
+        if declared.len() != template.variables.len() {
+            return Err(Error::validation_error("Template variables must be unique"));
+        }
+        let env = environment();
+        let sources = std::iter::once(template.body.as_str()).chain(template.subject.as_deref());
+        for source in sources {
+            let mut undeclared: Vec<String> = referenced_variables(&env, source)?
+                .into_iter()
+                .filter(|name| !declared.contains(name.as_str()))
+                .collect();
+            if !undeclared.is_empty() {
+                undeclared.sort();
+                return Err(Error::validation_error(&format!(
+                    "Template {} ({}) uses undeclared variables: {}",
+                    template.name,
+                    template.locale,
+                    undeclared.join(", ")
+                )));
+            }
+        }
+
+        self.templates.push(template);
+        Ok(())
+    }
+
+    /// Every template variant
+    pub fn templates(&self) -> &[NotificationTemplate] {
+        &self.templates
+    }
+
+    /// Variant with exactly this locale
+    fn variant(&self, name: &str, locale: &str) -> Option<&NotificationTemplate> {
+        self.templates
+            .iter()
+            .find(|template| template.name == name && template.locale.eq_ignore_ascii_case(locale))
+    }
+
+    /// Best variant of a template for a locale
+    pub fn resolve(&self, name: &str, locale: Option<&str>) -> Option<&NotificationTemplate> {
+        locale_chain(locale)
+            .iter()
+            .find_map(|locale| self.variant(name, locale))
+    }
+
+    /// Render a template with the given variables
+    pub fn render(&self, reference: &TemplateRef) -> Result<RenderedNotification> {
+        let template = self
+            .resolve(&reference.name, reference.locale.as_deref())
+            .ok_or_else(|| {
+                Error::validation_error_with_field(
+                    &format!("Unknown notification template: {}", reference.name),
+                    "template",
+                )
+            })?;
+
+        for name in reference.variables.keys() {
+            if !template.variables.iter().any(|v| &v.name == name) {
+                return Err(Error::validation_error_with_field(
+                    &format!("Template {} has no variable {}", template.name, name),
+                    name,
+                ));
+            }
+        }
+        let mut context = reference.variables.clone();
+        for variable in &template.variables {
+            let missing = context.get(&variable.name).map_or(true, Value::is_null);
+            if missing && variable.required {
+                return Err(Error::validation_error_with_field(
+                    &format!("Template {} needs variable {}", template.name, variable.name),
+                    &variable.name,
+                ));
+            }
+            // Optional variables are null rather than undefined so templates
+            // can test them with `{% if %}`
+            context.entry(variable.name.clone()).or_insert(Value::Null);
+        }
+
+        let env = environment();
+        let render = |source: &str| {
+            env.render_str(source, &context).map_err(|e| {
+                Error::validation_error(&format!("Template {} failed to render: {}", template.name, e))
+            })
+        };
+
+        Ok(RenderedNotification {
+            template: template.name.clone(),
+            locale: template.locale.clone(),
+            subject: template.subject.as_deref().map(render).transpose()?,
+            body: render(&template.body)?,
+        })
+    }
+}
+
+/// One built-in variant; variables are `(name, required, description)`
+fn template(
+    name: &str,
+    locale: &str,
+    subject: &str,
+    body: &str,
+    variables: &[(&str, bool, &str)],
+) -> NotificationTemplate {
+    NotificationTemplate {
+        name: name.to_string(),
+        locale: locale.to_string(),
+        subject: Some(subject.to_string()),
+        body: body.to_string(),
+        variables: variables
+            .iter()
+            .map(|(name, required, description)| TemplateVariable {
+                name: name.to_string(),
+                description: description.to_string(),
+                required: *required,
+            })
+            .collect(),
+    }
+}
+
+/// Templates shipped with the platform
+fn builtin_templates() -> Vec<NotificationTemplate> {
+    let verification = [
+        ("code", true, "One-time verification code"),
+        ("expires_at", true, "Expiry time, HH:MM in UTC"),
+    ];
+    let appointment = [
+        ("start", true, "Appointment date and time as shown to the patient"),
+        ("practitioner_name", false, "Who the appointment is with"),
+        ("location", false, "Where the appointment is"),
+    ];
+    let critical = [("test_name", true, "Name of the test with the critical result")];
+    let abnormal = [("test_name", true, "Name of the test with the abnormal result")];
+    let unacknowledged = [
+        ("test_name", true, "Name of the test with the critical result"),
+        ("minutes", true, "Minutes since the result arrived"),
+    ];
+    let security = [
+        ("kind", true, "Kind of anomaly, e.g. impossible_travel"),
+        ("subject", true, "User or address the anomaly concerns"),
+        ("description", true, "What was detected"),
+    ];
+    let digest = [
+        ("count", true, "Number of notifications in the digest"),
+        ("items", true, "Text of each notification"),
+    ];
+
+    vec![
+        template(
+            CONTACT_VERIFICATION_TEMPLATE,
+            "en",
+            "Your verification code",
+            "Your verification code is {{ code }}. It expires at {{ expires_at }} UTC.",
+            &verification,
+        ),
+        template(
+            CONTACT_VERIFICATION_TEMPLATE,
+            "es",
+            "Su código de verificación",
+            "Su código de verificación es {{ code }}. Vence a las {{ expires_at }} UTC.",
+            &verification,
+        ),
+        template(
+            SECURE_MESSAGE_TEMPLATE,
+            "en",
+            "New secure message",
+            "You have a new secure message. Sign in to read it.",
+            &[],
+        ),
+        template(
+            SECURE_MESSAGE_TEMPLATE,
+            "es",
+            "Nuevo mensaje seguro",
+            "Tiene un nuevo mensaje seguro. Inicie sesión para leerlo.",
+            &[],
+        ),
+        template(
+            APPOINTMENT_REMINDER_TEMPLATE,
+            "en",
+            "Appointment reminder",
+            "Reminder: you have an appointment on {{ start }}\
+             {% if practitioner_name %} with {{ practitioner_name }}{% endif %}\
+             {% if location %} at {{ location }}{% endif %}.",
+            &appointment,
+        ),
+        template(
+            APPOINTMENT_REMINDER_TEMPLATE,
+            "es",
+            "Recordatorio de cita",
+            "Recordatorio: tiene una cita el {{ start }}\
+             {% if practitioner_name %} con {{ practitioner_name }}{% endif %}\
+             {% if location %} en {{ location }}{% endif %}.",
+            &appointment,
+        ),
+        template(
+            CRITICAL_RESULT_TEMPLATE,
+            "en",
+            "Critical result",
+            "A critical result for {{ test_name }} needs your review.",
+            &critical,
+        ),
+        template(
+            ABNORMAL_RESULT_TEMPLATE,
+            "en",
+            "Abnormal result",
+            "An abnormal result for {{ test_name }} is waiting for your acknowledgment.",
+            &abnormal,
+        ),
+        template(
+            UNACKNOWLEDGED_RESULT_TEMPLATE,
+            "en",
+            "Unacknowledged critical result",
+            "A critical result for {{ test_name }} has not been acknowledged for {{ minutes }} minutes.",
+            &unacknowledged,
+        ),
+        template(
+            SECURITY_EVENT_TEMPLATE,
+            "en",
+            "Security alert: {{ kind }}",
+            "Security alert for {{ subject }}: {{ description }}",
+            &security,
+        ),
+        template(
+            NOTIFICATION_DIGEST_TEMPLATE,
+            "en",
+            "Your notifications",
+            "You have {{ count }} notifications:{% for item in items %}\n- {{ item }}{% endfor %}",
+            &digest,
+        ),
+        template(
+            NOTIFICATION_DIGEST_TEMPLATE,
+            "es",
+            "Sus notificaciones",
+            "Tiene {{ count }} notificaciones:{% for item in items %}\n- {{ item }}{% endfor %}",
+            &digest,
+        ),
+    ]
+}
+
+/// Notification types, as named by notification jobs
+pub const NOTIFICATION_TYPES: [&str; 5] = ["Alert", "Reminder", "Update", "Warning", "Error"];
+
+/// Delivery channels, as named by notification jobs
+pub const NOTIFICATION_CHANNELS: [&str; 4] = ["Email", "Sms", "Push", "InApp"];
+
+/// Channel that quiet hours do not hold back
+pub const IN_APP_CHANNEL: &str = "InApp";
+
+/// Largest UTC offset, in minutes
+const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// When notifications of a type are sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryMode {
+    /// As soon as they are submitted
+    #[default]
+    Immediate,
+    /// Collected and sent once a day at the digest time
+    Digest,
+}
+
+/// A user's choices for one notification type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypePreference {
+    /// Channels the user accepts for the type
+    pub channels: Vec<String>,
+    /// Immediate or daily digest
+    #[serde(default)]
+    pub delivery: DeliveryMode,
+}
+
+/// Daily window, in the user's local time, in which nothing is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Start of the window
+    pub start: NaiveTime,
+    /// End of the window; before `start` for windows spanning midnight
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Whether a local time falls in the window
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// What to do with a notification for its recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryDecision {
+    /// Send it now
+    Send,
+    /// Drop it: the user does not accept the channel for the type
+    Suppress,
+    /// Hold it until quiet hours end
+    Defer(Timestamp),
+    /// Hold it for the digest sent at the given time
+    Digest(Timestamp),
+}
+
+/// A user's notification preferences
+///
+/// Types without an entry go to every channel immediately. Critical
+/// notifications ignore preferences.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    /// User (patient or practitioner) the preferences belong to
+    pub user_id: Id,
+    /// Choices per notification type
+    #[serde(default)]
+    pub types: BTreeMap<String, TypePreference>,
+    /// Quiet hours, if any; in-app notifications are not held back
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    /// Local time the daily digest is sent
+    #[serde(default = "default_digest_time")]
+    pub digest_time: NaiveTime,
+    /// User's offset from UTC in minutes, for quiet hours and the digest
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+fn default_digest_time() -> NaiveTime {
+    NaiveTime::from_hms_opt(8, 0, 0).expect("valid digest time")
+}
+
+impl NotificationPreferences {
+    /// Preferences of a user who has not set any
+    pub fn new(user_id: Id) -> Self {
+        Self {
+            user_id,
+            types: BTreeMap::new(),
+            quiet_hours: None,
+            digest_time: default_digest_time(),
+            utc_offset_minutes: 0,
+        }
+    }
+
+    /// Check type and channel names and the UTC offset
+    pub fn validate(&self) -> Result<()> {
+        for (notification_type, preference) in &self.types {
+            if !NOTIFICATION_TYPES.contains(&notification_type.as_str()) {
+                return Err(Error::validation_error_with_field(
+                    &format!("Unknown notification type: {}", notification_type),
+                    "types",
+                ));
+            }
+            if let Some(channel) = preference
+                .channels
+                .iter()
+                .find(|channel| !NOTIFICATION_CHANNELS.contains(&channel.as_str()))
+            {
+                return Err(Error::validation_error_with_field(
+                    &format!("Unknown notification channel: {}", channel),
+                    "channels",
+                ));
+            }
+        }
+        if self.utc_offset_minutes.abs() > MAX_UTC_OFFSET_MINUTES {
+            return Err(Error::validation_error_with_field(
+                "UTC offsets are limited to 14 hours",
+                "utc_offset_minutes",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Next time after `now` that the user's local clock reads `time`
+    fn next_local(&self, time: NaiveTime, now: Timestamp) -> Timestamp {
+        let offset = Duration::minutes(self.utc_offset_minutes as i64);
+        let local = now.naive_utc() + offset;
+        let mut next = local.date().and_time(time);
+        if next <= local {
+            next += Duration::days(1);
+        }
+        (next - offset).and_utc()
+    }
+
+    /// How to deliver a notification of a type on a channel
+    pub fn decide(&self, notification_type: &str, channel: &str, critical: bool, now: Timestamp) -> DeliveryDecision {
+        if critical {
+            return DeliveryDecision::Send;
+        }
+
+        let preference = self.types.get(notification_type);
+        if let Some(preference) = preference {
+            if !preference.channels.iter().any(|accepted| accepted == channel) {
+                return DeliveryDecision::Suppress;
+            }
+            if preference.delivery == DeliveryMode::Digest {
+                return DeliveryDecision::Digest(self.next_local(self.digest_time, now));
+            }
+        }
+
+        if channel != IN_APP_CHANNEL {
+            if let Some(quiet_hours) = self.quiet_hours {
+                let local = (now.naive_utc() + Duration::minutes(self.utc_offset_minutes as i64)).time();
+                if quiet_hours.contains(local) {
+                    return DeliveryDecision::Defer(self.next_local(quiet_hours.end, now));
+                }
+            }
+        }
+        DeliveryDecision::Send
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reference(name: &str, locale: Option<&str>, variables: Value) -> TemplateRef {
+        TemplateRef {
+            name: name.to_string(),
+            locale: locale.map(str::to_string),
+            variables: variables.as_object().cloned().unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_locale_fallback() {
+        assert_eq!(locale_chain(Some("es_MX")), vec!["es-mx", "es", "en"]);
+        assert_eq!(locale_chain(None), vec!["en"]);
+
+        let catalog = TemplateCatalog::builtin();
+        assert_eq!(catalog.resolve(SECURE_MESSAGE_TEMPLATE, Some("es-MX")).unwrap().locale, "es");
+        assert_eq!(catalog.resolve(CRITICAL_RESULT_TEMPLATE, Some("es")).unwrap().locale, "en");
+        assert!(catalog.resolve("unknown", None).is_none());
+    }
+
+    #[test]
+    fn test_render_appointment_reminder() {
+        let catalog = TemplateCatalog::builtin();
+
+        let rendered = catalog
+            .render(&reference(
+                APPOINTMENT_REMINDER_TEMPLATE,
+                Some("es"),
+                json!({"start": "3 de marzo, 10:00", "location": "Clínica Norte"}),
+            ))
+            .unwrap();
+        assert_eq!(rendered.locale, "es");
+        assert_eq!(rendered.body, "Recordatorio: tiene una cita el 3 de marzo, 10:00 en Clínica Norte.");
+
+        let rendered = catalog
+            .render(&reference(APPOINTMENT_REMINDER_TEMPLATE, None, json!({"start": "March 3, 10:00"})))
+            .unwrap();
+        assert_eq!(rendered.body, "Reminder: you have an appointment on March 3, 10:00.");
+        assert_eq!(rendered.subject.as_deref(), Some("Appointment reminder"));
+    }
+
+    #[test]
+    fn test_render_checks_variables() {
+        let catalog = TemplateCatalog::builtin();
+
+        let missing = reference(CONTACT_VERIFICATION_TEMPLATE, None, json!({"code": "123456"}));
+        assert!(catalog.render(&missing).is_err());
+
+        let unknown = reference(SECURE_MESSAGE_TEMPLATE, None, json!({"body": "Your biopsy results"}));
+        assert!(catalog.render(&unknown).is_err());
+
+        assert!(catalog.render(&reference("unknown", None, json!({}))).is_err());
+    }
+
+    #[test]
+    fn test_add_rejects_undeclared_variables() {
+        let mut catalog = TemplateCatalog::new();
+        let mut reminder = template("reminder", "en", "Reminder", "See you {{ when }}", &[]);
+        assert!(catalog.add(reminder.clone()).is_err());
+
+        reminder.variables.push(TemplateVariable {
+            name: "when".to_string(),
+            description: "Appointment time".to_string(),
+            required: true,
+        });
+        assert!(catalog.add(reminder.clone()).is_ok());
+        assert!(catalog.add(reminder).is_err(), "duplicate variant");
+
+        let broken = template("broken", "en", "Broken", "{% if %}", &[]);
+        assert!(catalog.add(broken).is_err());
+    }
+
+    fn at(hour: u32, minute: u32) -> Timestamp {
+        chrono::NaiveDate::from_ymd_opt(2026, 3, 2)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+            .and_utc()
+    }
+
+    fn time(hour: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_quiet_hours_defer_until_local_morning() {
+        let mut preferences = NotificationPreferences::new(uuid::Uuid::new_v4());
+        preferences.quiet_hours = Some(QuietHours { start: time(22), end: time(7) });
+        // UTC-5: 04:00 UTC is 23:00 local
+        preferences.utc_offset_minutes = -300;
+
+        assert_eq!(preferences.decide("Reminder", "Sms", false, at(4, 0)), DeliveryDecision::Defer(at(12, 0)));
+        assert_eq!(preferences.decide("Reminder", "InApp", false, at(4, 0)), DeliveryDecision::Send);
+        assert_eq!(preferences.decide("Alert", "Sms", true, at(4, 0)), DeliveryDecision::Send);
+        assert_eq!(preferences.decide("Reminder", "Sms", false, at(15, 0)), DeliveryDecision::Send);
+    }
+
+    #[test]
+    fn test_type_preferences() {
+        let mut preferences = NotificationPreferences::new(uuid::Uuid::new_v4());
+        preferences.types.insert(
+            "Update".to_string(),
+            TypePreference { channels: vec!["Email".to_string()], delivery: DeliveryMode::Digest },
+        );
+        preferences.types.insert(
+            "Reminder".to_string(),
+            TypePreference { channels: vec!["Sms".to_string()], delivery: DeliveryMode::Immediate },
+        );
+        assert!(preferences.validate().is_ok());
+
+        assert_eq!(preferences.decide("Reminder", "Email", false, at(9, 0)), DeliveryDecision::Suppress);
+        assert_eq!(preferences.decide("Reminder", "Sms", false, at(9, 0)), DeliveryDecision::Send);
+        // Digest at 08:00 UTC, already passed today
+        let tomorrow = at(8, 0) + Duration::days(1);
+        assert_eq!(preferences.decide("Update", "Email", false, at(9, 0)), DeliveryDecision::Digest(tomorrow));
+        assert_eq!(preferences.decide("Alert", "Push", false, at(9, 0)), DeliveryDecision::Send);
+
+        preferences.types.insert(
+            "Gossip".to_string(),
+            TypePreference { channels: vec![], delivery: DeliveryMode::Immediate },
+        );
+        assert!(preferences.validate().is_err());
+    }
+}
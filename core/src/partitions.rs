@@ -0,0 +1,478 @@
+//! Monthly partitions of time-series tables
+//!
+//! `emr.observations` is range-partitioned by the UTC month of
+//! `effective_date`. The PartitionMaintenance job [`plan`]s each table's
+//! partitions once a day: it creates them a few months ahead, gives months
+//! whose rows landed in the default partition a partition of their own, and
+//! archives or drops the partitions whose every row is past the retention
+//! period of the table's entity type.
+//!
+//! Queries on a partitioned table should bound its partition key, so
+//! Postgres only scans the partitions of the requested window;
+//! [`ObservationQuery`] always does.
+
+use crate::retention::{RetentionAction, RetentionPolicy};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::{Datelike, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Schema archived partitions are moved to
+pub const ARCHIVE_SCHEMA: &str = "emr_archive";
+
+/// A table range-partitioned by month of a timestamp column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionedTable {
+    /// Schema of the table and its partitions
+    pub schema: &'static str,
+    /// Table name; partitions are named after it
+    pub name: &'static str,
+    /// Partition key
+    pub column: &'static str,
+    /// Entity type whose retention policy applies to the rows
+    pub entity_type: &'static str,
+}
+
+/// Observations, partitioned by `effective_date`
+pub const OBSERVATIONS: PartitionedTable = PartitionedTable {
+    schema: "emr",
+    name: "observations",
+    column: "effective_date",
+    entity_type: "Observation",
+};
+
+/// Tables the PartitionMaintenance job looks after
+pub const PARTITIONED_TABLES: [PartitionedTable; 1] = [OBSERVATIONS];
+
+impl PartitionedTable {
+    /// The partitioned table for a qualified name, e.g. `emr.observations`
+    pub fn find(qualified: &str) -> Option<Self> {
+        PARTITIONED_TABLES.into_iter().find(|table| table.qualified() == qualified)
+    }
+
+    /// Qualified name of the table
+    pub fn qualified(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    /// Qualified name of the partition holding rows of months without one
+    pub fn default_partition(&self) -> String {
+        format!("{}.{}_default", self.schema, self.name)
+    }
+
+    /// Unqualified name of a month's partition, e.g. `observations_2026_10`
+    pub fn partition_name(&self, month: Month) -> String {
+        format!("{}_{:04}_{:02}", self.name, month.year, month.month)
+    }
+
+    /// Qualified name of a month's partition
+    pub fn partition(&self, month: Month) -> String {
+        format!("{}.{}", self.schema, self.partition_name(month))
+    }
+
+    /// Month of one of the table's partitions, from its unqualified name;
+    /// `None` for the default partition and tables named otherwise
+    pub fn month_of(&self, partition: &str) -> Option<Month> {
+        let suffix = partition.strip_prefix(self.name)?.strip_prefix('_')?;
+        let (year, month) = suffix.split_once('_')?;
+        if year.len() != 4 || month.len() != 2 {
+            return None;
+        }
+        Month::new(year.parse().ok()?, month.parse().ok()?)
+    }
+
+    /// Create a month's partition; for months without rows in the default
+    /// partition
+    pub fn create_sql(&self, month: Month) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} PARTITION OF {} {}",
+            self.partition(month),
+            self.qualified(),
+            month.bounds_sql()
+        )
+    }
+
+    /// Create a month's partition holding its rows from the default
+    /// partition, in one transaction
+    ///
+    /// Postgres refuses a new partition while the default partition holds
+    /// rows of its range, so the default partition is detached while its
+    /// rows move. Detached, it has no triggers: moved rows do not reach the
+    /// audit log.
+    pub fn adopt_sql(&self, month: Month) -> Vec<String> {
+        let partition = self.partition(month);
+        let default_partition = self.default_partition();
+        vec![
+            format!("ALTER TABLE {} DETACH PARTITION {}", self.qualified(), default_partition),
+            format!(
+                "CREATE TABLE {} (LIKE {} INCLUDING DEFAULTS INCLUDING CONSTRAINTS)",
+                partition,
+                self.qualified()
+            ),
+            format!(
+                "WITH moved AS (DELETE FROM {} WHERE {} RETURNING *) INSERT INTO {} SELECT * FROM moved",
+                default_partition,
+                month.range_condition(self.column),
+                partition
+            ),
+            format!("ALTER TABLE {} ATTACH PARTITION {} {}", self.qualified(), partition, month.bounds_sql()),
+            format!("ALTER TABLE {} ATTACH PARTITION {} DEFAULT", self.qualified(), default_partition),
+        ]
+    }
+
+    /// Detach a month's partition and archive or drop it
+    pub fn expire_sql(&self, month: Month, action: RetentionAction) -> Result<Vec<String>> {
+        let partition = self.partition(month);
+        let detach = format!("ALTER TABLE {} DETACH PARTITION {}", self.qualified(), partition);
+        match action {
+            RetentionAction::Archive => Ok(vec![
+                detach,
+                format!("ALTER TABLE {} SET SCHEMA {}", partition, ARCHIVE_SCHEMA),
+            ]),
+            RetentionAction::Purge => Ok(vec![detach, format!("DROP TABLE {}", partition)]),
+            RetentionAction::Anonymize => Err(Error::validation_error(&format!(
+                "Partitions of {} cannot be anonymized whole",
+                self.qualified()
+            ))),
+        }
+    }
+}
+
+/// Calendar month in UTC, the range of one partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Month {
+    year: i32,
+    month: u32,
+}
+
+impl Month {
+    /// The month, if `month` is 1 to 12 and the year has four digits
+    pub fn new(year: i32, month: u32) -> Option<Self> {
+        ((1..=9999).contains(&year) && (1..=12).contains(&month)).then_some(Self { year, month })
+    }
+
+    /// The month an instant falls in
+    pub fn containing(instant: Timestamp) -> Self {
+        Self {
+            year: instant.year(),
+            month: instant.month(),
+        }
+    }
+
+    /// Year
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    /// Month of the year, 1 to 12
+    pub fn month(&self) -> u32 {
+        self.month
+    }
+
+    /// The month `months` later
+    pub fn plus(self, months: u32) -> Self {
+        let index = self.year * 12 + self.month as i32 - 1 + months as i32;
+        Self {
+            year: index.div_euclid(12),
+            month: index.rem_euclid(12) as u32 + 1,
+        }
+    }
+
+    /// Midnight UTC on the first day of the month
+    pub fn start(self) -> Timestamp {
+        Utc.with_ymd_and_hms(self.year, self.month, 1, 0, 0, 0)
+            .single()
+            .expect("the first of a month is a valid UTC instant")
+    }
+
+    /// Start of the next month, the end of this month's range
+    pub fn end(self) -> Timestamp {
+        self.plus(1).start()
+    }
+
+    fn bounds_sql(self) -> String {
+        format!(
+            "FOR VALUES FROM ('{}') TO ('{}')",
+            self.start().to_rfc3339(),
+            self.end().to_rfc3339()
+        )
+    }
+
+    fn range_condition(self, column: &str) -> String {
+        format!(
+            "{column} >= '{}' AND {column} < '{}'",
+            self.start().to_rfc3339(),
+            self.end().to_rfc3339()
+        )
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// Maintenance due on one partitioned table
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PartitionPlan {
+    /// Months to give a partition, in order
+    pub create: Vec<Month>,
+    /// Partitions past retention and what to do with them, in order
+    pub expire: Vec<(Month, RetentionAction)>,
+}
+
+impl PartitionPlan {
+    /// Whether nothing is due
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.expire.is_empty()
+    }
+}
+
+/// Plan a table's maintenance as of `now`
+///
+/// `existing` are the months with a partition and `in_default` those with
+/// rows in the default partition. Partitions are created for this month,
+/// the `months_ahead` after it and the earlier months found in the default
+/// partition; later months stay there until they come within reach. A
+/// partition whose month ended more than the retention period ago is
+/// archived or dropped as the policy says; without a policy, or with one
+/// that anonymizes, partitions are kept.
+pub fn plan(
+    existing: &[Month],
+    in_default: &[Month],
+    now: Timestamp,
+    months_ahead: u32,
+    policy: Option<&RetentionPolicy>,
+) -> PartitionPlan {
+    let current = Month::containing(now);
+    let horizon = current.plus(months_ahead);
+
+    let mut create: Vec<Month> = (0..=months_ahead)
+        .map(|months| current.plus(months))
+        .chain(in_default.iter().copied().filter(|month| *month <= horizon))
+        .filter(|month| !existing.contains(month))
+        .collect();
+    create.sort();
+    create.dedup();
+
+    let expire = match policy {
+        Some(policy) if policy.action != RetentionAction::Anonymize => {
+            let mut expired: Vec<Month> = existing
+                .iter()
+                .chain(&create)
+                .copied()
+                .filter(|month| policy.due_at(month.end()) <= now)
+                .collect();
+            expired.sort();
+            expired.dedup();
+            expired.into_iter().map(|month| (month, policy.action)).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    PartitionPlan { create, expire }
+}
+
+/// Longest window an [`ObservationQuery`] may span
+pub const MAX_QUERY_DAYS: i64 = 400;
+
+/// Most observations an [`ObservationQuery`] returns
+pub const MAX_QUERY_LIMIT: u32 = 10_000;
+
+/// Observations of an [`ObservationQuery`], most recent first; `$1` and `$2`
+/// bound `effective_date`, `$3` to `$5` are the optional patient, category
+/// and code and `$6` the limit
+pub const OBSERVATION_QUERY: &str = "SELECT id, status, category, code, display, patient_id, encounter_id, \
+     device_id, effective_date, value_quantity_value::float8 AS value, value_quantity_unit AS unit \
+     FROM emr.observations \
+     WHERE effective_date >= $1 AND effective_date < $2 \
+     AND ($3::uuid IS NULL OR patient_id = $3) \
+     AND ($4::text IS NULL OR category = $4) \
+     AND ($5::text IS NULL OR code = $5) \
+     ORDER BY effective_date DESC LIMIT $6";
+
+/// Observations effective in a window, optionally of one patient, category
+/// or code
+///
+/// The window is required, so only the partitions it overlaps are scanned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObservationQuery {
+    /// Start of the window, inclusive
+    pub from: Timestamp,
+    /// End of the window, exclusive
+    pub to: Timestamp,
+    /// Only this patient's observations
+    #[serde(default)]
+    pub patient_id: Option<Id>,
+    /// Only observations in this category
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Only observations with this code
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Most observations returned
+    pub limit: u32,
+}
+
+impl ObservationQuery {
+    /// Observations effective in `[from, to)`, up to 1,000
+    pub fn between(from: Timestamp, to: Timestamp) -> Self {
+        Self {
+            from,
+            to,
+            patient_id: None,
+            category: None,
+            code: None,
+            limit: 1_000,
+        }
+    }
+
+    /// Observations effective in the `days` before `now`
+    pub fn recent(now: Timestamp, days: i64) -> Self {
+        Self::between(now - Duration::days(days), now)
+    }
+
+    /// Only the observations of one patient
+    pub fn for_patient(mut self, patient_id: Id) -> Self {
+        self.patient_id = Some(patient_id);
+        self
+    }
+
+    /// Only the observations in a category, e.g. `vital-signs`
+    pub fn in_category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    /// Only the observations with a code
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    /// Return at most `limit` observations
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Check the window and limit before the query runs
+    pub fn check(&self) -> Result<()> {
+        if self.from >= self.to {
+            return Err(Error::validation_error_with_field(
+                "The observation window must end after it starts",
+                "to",
+            ));
+        }
+        if self.to - self.from > Duration::days(MAX_QUERY_DAYS) {
+            return Err(Error::validation_error_with_field(
+                &format!("Observation windows span at most {} days", MAX_QUERY_DAYS),
+                "from",
+            ));
+        }
+        if self.limit == 0 || self.limit > MAX_QUERY_LIMIT {
+            return Err(Error::validation_error_with_field(
+                &format!("Observation queries return 1 to {} observations", MAX_QUERY_LIMIT),
+                "limit",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Months whose partitions the query scans
+    pub fn months(&self) -> Vec<Month> {
+        let last = Month::containing(self.to - Duration::microseconds(1));
+        let mut months = vec![Month::containing(self.from)];
+        while months[months.len() - 1] < last {
+            months.push(months[months.len() - 1].plus(1));
+        }
+        months
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn month(year: i32, month: u32) -> Month {
+        Month::new(year, month).unwrap()
+    }
+
+    #[test]
+    fn test_month() {
+        let now = Utc.with_ymd_and_hms(2026, 11, 30, 23, 59, 59).unwrap();
+        let current = Month::containing(now);
+        assert_eq!(current, month(2026, 11));
+        assert_eq!(current.plus(2), month(2027, 1));
+        assert_eq!(current.end(), Utc.with_ymd_and_hms(2026, 12, 1, 0, 0, 0).unwrap());
+        assert_eq!(month(2026, 1).to_string(), "2026-01");
+        assert!(Month::new(2026, 13).is_none());
+
+        assert_eq!(OBSERVATIONS.partition(month(2026, 3)), "emr.observations_2026_03");
+        assert_eq!(OBSERVATIONS.month_of("observations_2026_03"), Some(month(2026, 3)));
+        assert_eq!(OBSERVATIONS.month_of("observations_default"), None);
+        assert_eq!(OBSERVATIONS.month_of("observations_2026_3"), None);
+        assert_eq!(PartitionedTable::find("emr.observations"), Some(OBSERVATIONS));
+    }
+
+    #[test]
+    fn test_partition_sql() {
+        assert_eq!(
+            OBSERVATIONS.create_sql(month(2026, 12)),
+            "CREATE TABLE IF NOT EXISTS emr.observations_2026_12 PARTITION OF emr.observations \
+             FOR VALUES FROM ('2026-12-01T00:00:00+00:00') TO ('2027-01-01T00:00:00+00:00')"
+        );
+
+        let adopt = OBSERVATIONS.adopt_sql(month(2020, 1));
+        assert!(adopt[0].contains("DETACH PARTITION emr.observations_default"));
+        assert!(adopt[2].contains(
+            "effective_date >= '2020-01-01T00:00:00+00:00' AND effective_date < '2020-02-01T00:00:00+00:00'"
+        ));
+        assert!(adopt[4].ends_with("ATTACH PARTITION emr.observations_default DEFAULT"));
+
+        let archive = OBSERVATIONS.expire_sql(month(2015, 6), RetentionAction::Archive).unwrap();
+        assert_eq!(archive[1], "ALTER TABLE emr.observations_2015_06 SET SCHEMA emr_archive");
+        assert!(OBSERVATIONS.expire_sql(month(2015, 6), RetentionAction::Anonymize).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 3, 0, 0).unwrap();
+        let policy = RetentionPolicy::new("Observation", 365, RetentionAction::Archive);
+        let existing = [month(2025, 8), month(2025, 9), month(2025, 10), month(2026, 10)];
+        let in_default = [month(2024, 1), month(2026, 3), month(2031, 1)];
+
+        let plan = plan(&existing, &in_default, now, 2, Some(&policy));
+        assert_eq!(plan.create, vec![month(2024, 1), month(2026, 3), month(2026, 11), month(2026, 12)]);
+        // September 2025 ended more than a year ago; October 2025 has not
+        assert_eq!(
+            plan.expire,
+            vec![
+                (month(2024, 1), RetentionAction::Archive),
+                (month(2025, 8), RetentionAction::Archive),
+                (month(2025, 9), RetentionAction::Archive),
+            ]
+        );
+
+        let anonymize = RetentionPolicy::new("Observation", 365, RetentionAction::Anonymize);
+        assert!(super::plan(&existing, &[], now, 0, Some(&anonymize)).is_empty());
+        assert!(super::plan(&existing, &[], now, 0, None).expire.is_empty());
+    }
+
+    #[test]
+    fn test_observation_query() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 3, 0, 0).unwrap();
+        let query = ObservationQuery::recent(now, 30).in_category("vital-signs").limit(50);
+        assert!(query.check().is_ok());
+        assert_eq!(query.months(), vec![month(2026, 9), month(2026, 10)]);
+
+        let month_only = ObservationQuery::between(month(2026, 9).start(), month(2026, 10).start());
+        assert_eq!(month_only.months(), vec![month(2026, 9)]);
+
+        assert!(ObservationQuery::between(now, now).check().is_err());
+        assert!(ObservationQuery::recent(now, MAX_QUERY_DAYS + 1).check().is_err());
+        assert!(ObservationQuery::recent(now, 1).limit(0).check().is_err());
+    }
+}
@@ -54,6 +54,9 @@ pub trait OrganizationRepository: Repository<Organization> {
     
     /// Find child organizations
     async fn find_children(&self, parent_id: Id) -> Result<Vec<Organization>>;
+
+    /// Find an organization and all of its descendants through `part_of`
+    async fn find_hierarchy(&self, root_id: Id) -> Result<Vec<Organization>>;
     
     /// Find active organizations
     async fn find_active(&self) -> Result<Vec<Organization>>;
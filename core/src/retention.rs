@@ -0,0 +1,292 @@
+//! Data retention policies
+//!
+//! Policies say how long records of an entity type must be kept, optionally
+//! per jurisdiction, and what happens once that period has passed. The
+//! DataCleanup job and the compliance dry-run report both evaluate candidate
+//! records against the same [`RetentionPolicySet`].
+
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// What to do with a record once its retention period has passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    /// Move to cold storage, keeping the record intact
+    Archive,
+    /// Strip identifying data, keeping the clinical content
+    Anonymize,
+    /// Delete permanently
+    Purge,
+}
+
+/// Retention rule for one entity type, optionally scoped to a jurisdiction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Entity type the policy applies to, e.g. `Encounter`
+    pub entity_type: String,
+    /// Jurisdiction code (e.g. `US-CA`); `None` applies everywhere else
+    #[serde(default)]
+    pub jurisdiction: Option<String>,
+    /// Days to keep a record after its last activity
+    pub retention_days: u32,
+    /// What happens to records past the period
+    pub action: RetentionAction,
+}
+
+impl RetentionPolicy {
+    /// Create a policy that applies in every jurisdiction
+    pub fn new(entity_type: &str, retention_days: u32, action: RetentionAction) -> Self {
+        Self {
+            entity_type: entity_type.to_string(),
+            jurisdiction: None,
+            retention_days,
+            action,
+        }
+    }
+
+    /// Scope the policy to a jurisdiction
+    pub fn in_jurisdiction(mut self, jurisdiction: &str) -> Self {
+        self.jurisdiction = Some(jurisdiction.to_string());
+        self
+    }
+
+    /// When a record last active at `last_activity` becomes due
+    pub fn due_at(&self, last_activity: Timestamp) -> Timestamp {
+        last_activity + Duration::days(i64::from(self.retention_days))
+    }
+}
+
+/// Retention configuration section shared by the API and jobs worker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Policies, in evaluation order
+    pub policies: Vec<RetentionPolicy>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            policies: vec![
+                // HIPAA requires compliance documentation for six years
+                RetentionPolicy::new("AuditEvent", 6 * 365, RetentionAction::Archive),
+                RetentionPolicy::new("Patient", 10 * 365, RetentionAction::Archive),
+                RetentionPolicy::new("Encounter", 10 * 365, RetentionAction::Archive),
+                RetentionPolicy::new("Observation", 10 * 365, RetentionAction::Archive),
+                RetentionPolicy::new("Session", 90, RetentionAction::Purge),
+            ],
+        }
+    }
+}
+
+/// A record considered for retention processing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionCandidate {
+    /// Type of the record
+    pub entity_type: String,
+    /// Identifier of the record
+    pub entity_id: Id,
+    /// Jurisdiction the record is held under
+    pub jurisdiction: Option<String>,
+    /// Last activity on the record
+    pub last_activity: Timestamp,
+}
+
+/// Outcome for a candidate whose retention period has passed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionDecision {
+    /// Type of the record
+    pub entity_type: String,
+    /// Identifier of the record
+    pub entity_id: Id,
+    /// Jurisdiction the record is held under
+    pub jurisdiction: Option<String>,
+    /// Action that is due
+    pub action: RetentionAction,
+    /// When the action became due
+    pub due_at: Timestamp,
+}
+
+/// Result of evaluating candidates against the policy set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionReport {
+    /// When the evaluation ran
+    pub evaluated_at: Timestamp,
+    /// Number of candidates considered
+    pub evaluated: usize,
+    /// Candidates past their retention period
+    pub decisions: Vec<RetentionDecision>,
+    /// Count of decisions per action
+    pub summary: BTreeMap<RetentionAction, usize>,
+    /// Entity types with no applicable policy; these are always kept
+    pub unmatched_types: Vec<String>,
+}
+
+/// Validated collection of retention policies
+#[derive(Debug, Clone)]
+pub struct RetentionPolicySet {
+    policies: Vec<RetentionPolicy>,
+}
+
+impl RetentionPolicySet {
+    /// Build a policy set, rejecting ambiguous or empty policies
+    pub fn new(policies: Vec<RetentionPolicy>) -> Result<Self> {
+        let mut seen = HashSet::new();
+        for policy in &policies {
+            if policy.entity_type.trim().is_empty() {
+                return Err(Error::validation_error_with_field(
+                    "Retention policy requires an entity type",
+                    "entity_type",
+                ));
+            }
+            if policy.retention_days == 0 {
+                return Err(Error::validation_error_with_field(
+                    &format!("Retention period for {} must be at least one day", policy.entity_type),
+                    "retention_days",
+                ));
+            }
+            if !seen.insert((policy.entity_type.as_str(), policy.jurisdiction.as_deref())) {
+                return Err(Error::validation_error(&format!(
+                    "Duplicate retention policy for {} in {}",
+                    policy.entity_type,
+                    policy.jurisdiction.as_deref().unwrap_or("all jurisdictions")
+                )));
+            }
+        }
+
+        Ok(Self { policies })
+    }
+
+    /// Build a policy set from configuration
+    pub fn from_config(config: &RetentionConfig) -> Result<Self> {
+        Self::new(config.policies.clone())
+    }
+
+    /// All configured policies
+    pub fn policies(&self) -> &[RetentionPolicy] {
+        &self.policies
+    }
+
+    /// The policy for an entity type, preferring a jurisdiction-specific one
+    pub fn policy_for(&self, entity_type: &str, jurisdiction: Option<&str>) -> Option<&RetentionPolicy> {
+        let for_type = || self.policies.iter().filter(move |p| p.entity_type == entity_type);
+
+        jurisdiction
+            .and_then(|j| for_type().find(|p| p.jurisdiction.as_deref() == Some(j)))
+            .or_else(|| for_type().find(|p| p.jurisdiction.is_none()))
+    }
+
+    /// Decide what to do with each candidate as of `now`
+    pub fn evaluate(&self, candidates: &[RetentionCandidate], now: Timestamp) -> RetentionReport {
+        let mut decisions = Vec::new();
+        let mut summary = BTreeMap::new();
+        let mut unmatched_types = Vec::new();
+
+        for candidate in candidates {
+            let Some(policy) = self.policy_for(&candidate.entity_type, candidate.jurisdiction.as_deref()) else {
+                if !unmatched_types.contains(&candidate.entity_type) {
+                    unmatched_types.push(candidate.entity_type.clone());
+                }
+                continue;
+            };
+
+            let due_at = policy.due_at(candidate.last_activity);
+            if due_at > now {
+                continue;
+            }
+
+            *summary.entry(policy.action).or_insert(0) += 1;
+            decisions.push(RetentionDecision {
+                entity_type: candidate.entity_type.clone(),
+                entity_id: candidate.entity_id,
+                jurisdiction: candidate.jurisdiction.clone(),
+                action: policy.action,
+                due_at,
+            });
+        }
+
+        RetentionReport {
+            evaluated_at: now,
+            evaluated: candidates.len(),
+            decisions,
+            summary,
+            unmatched_types,
+        }
+    }
+}
+
+impl Default for RetentionPolicySet {
+    fn default() -> Self {
+        Self {
+            policies: RetentionConfig::default().policies,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn candidate(entity_type: &str, jurisdiction: Option<&str>, days_ago: i64) -> RetentionCandidate {
+        RetentionCandidate {
+            entity_type: entity_type.to_string(),
+            entity_id: Uuid::new_v4(),
+            jurisdiction: jurisdiction.map(str::to_string),
+            last_activity: Utc::now() - Duration::days(days_ago),
+        }
+    }
+
+    #[test]
+    fn test_policy_for_prefers_jurisdiction() {
+        let policies = RetentionPolicySet::new(vec![
+            RetentionPolicy::new("Encounter", 3650, RetentionAction::Archive),
+            RetentionPolicy::new("Encounter", 2555, RetentionAction::Anonymize).in_jurisdiction("US-CA"),
+        ])
+        .unwrap();
+
+        let policy = policies.policy_for("Encounter", Some("US-CA")).unwrap();
+        assert_eq!(policy.action, RetentionAction::Anonymize);
+        let policy = policies.policy_for("Encounter", Some("US-NY")).unwrap();
+        assert_eq!(policy.action, RetentionAction::Archive);
+        assert!(policies.policy_for("Patient", None).is_none());
+    }
+
+    #[test]
+    fn test_policy_set_rejects_duplicates() {
+        let result = RetentionPolicySet::new(vec![
+            RetentionPolicy::new("Session", 90, RetentionAction::Purge),
+            RetentionPolicy::new("Session", 30, RetentionAction::Purge),
+        ]);
+        assert!(result.is_err());
+        assert!(RetentionPolicySet::new(vec![RetentionPolicy::new("Session", 0, RetentionAction::Purge)]).is_err());
+        assert!(RetentionPolicySet::from_config(&RetentionConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let policies = RetentionPolicySet::new(vec![
+            RetentionPolicy::new("Session", 90, RetentionAction::Purge),
+            RetentionPolicy::new("Encounter", 3650, RetentionAction::Archive),
+        ])
+        .unwrap();
+
+        let candidates = vec![
+            candidate("Session", None, 120),
+            candidate("Session", None, 10),
+            candidate("Encounter", Some("US-CA"), 4000),
+            candidate("Practitioner", None, 9000),
+        ];
+        let report = policies.evaluate(&candidates, Utc::now());
+
+        assert_eq!(report.evaluated, 4);
+        assert_eq!(report.decisions.len(), 2);
+        assert_eq!(report.summary.get(&RetentionAction::Purge), Some(&1));
+        assert_eq!(report.summary.get(&RetentionAction::Archive), Some(&1));
+        assert_eq!(report.unmatched_types, vec!["Practitioner".to_string()]);
+    }
+}
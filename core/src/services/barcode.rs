@@ -0,0 +1,122 @@
+//! Bedside barcode verification for medication administration
+//!
+//! Before a dose is given, the nurse scans the patient's wristband and the
+//! medication package. A [`BarcodeVerifier`] checks the scan against the
+//! patient's identifiers and the products that fill the order; sites with
+//! their own scanning middleware plug in their own verifier.
+//! [`IdentifierBarcodeVerifier`] is the built-in one: the wristband must carry
+//! the patient id or one of the patient's identifiers, and the package one of
+//! the order's product identifiers, either plainly or as the GTIN of a GS1
+//! element string.
+
+use crate::domain::{BarcodeScan, BarcodeVerification, MedicationRequest};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+
+/// GS1 application identifier of a GTIN
+const GS1_GTIN_AI: &str = "01";
+
+/// Identifiers a patient's wristband may carry
+#[derive(Debug, Clone)]
+pub struct PatientIdentity {
+    /// The patient's id
+    pub patient_id: Id,
+    /// Identifier values, such as the MRN
+    pub identifiers: Vec<String>,
+}
+
+/// Checks bedside scans before a dose is documented
+pub trait BarcodeVerifier: Send + Sync {
+    /// Verify a scan, failing with a business rule violation on a mismatch
+    fn verify(
+        &self,
+        scan: &BarcodeScan,
+        patient: &PatientIdentity,
+        request: &MedicationRequest,
+        at: Timestamp,
+    ) -> Result<BarcodeVerification>;
+}
+
+/// Matches scans against identifiers exactly, ignoring case, spaces and dashes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentifierBarcodeVerifier;
+
+/// Identifier with case, spaces and dashes removed
+fn normalize(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// The GTIN in a GS1 element string starting with AI (01), if any
+fn gs1_gtin(scan: &str) -> Option<&str> {
+    let gtin = scan.strip_prefix(GS1_GTIN_AI)?.get(..14)?;
+    gtin.chars().all(|c| c.is_ascii_digit()).then_some(gtin)
+}
+
+impl BarcodeVerifier for IdentifierBarcodeVerifier {
+    fn verify(
+        &self,
+        scan: &BarcodeScan,
+        patient: &PatientIdentity,
+        request: &MedicationRequest,
+        at: Timestamp,
+    ) -> Result<BarcodeVerification> {
+        let wristband = normalize(&scan.patient);
+        let patient_matches = wristband == normalize(&patient.patient_id.to_string())
+            || patient.identifiers.iter().any(|identifier| normalize(identifier) == wristband);
+        if !patient_matches {
+            return Err(Error::business_rule_violation(
+                "barcode_patient_mismatch",
+                "The wristband scanned does not belong to this patient",
+            ));
+        }
+
+        let package = normalize(&scan.medication);
+        let scanned: Vec<&str> = std::iter::once(package.as_str()).chain(gs1_gtin(&package)).collect();
+        let medication_matches = request
+            .product_identifiers
+            .iter()
+            .map(|identifier| normalize(identifier))
+            .any(|identifier| scanned.contains(&identifier.as_str()));
+        if !medication_matches {
+            return Err(Error::business_rule_violation(
+                "barcode_medication_mismatch",
+                "The medication scanned does not fill this order",
+            ));
+        }
+
+        Ok(BarcodeVerification {
+            scan: scan.clone(),
+            verified_at: at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_identifier_verifier() {
+        let mut request = MedicationRequest::new(Uuid::new_v4(), Uuid::new_v4(), "313782", 650.0, "mg", 6, Utc::now());
+        request.product_identifiers = vec!["00350580413045".to_string()];
+        let patient = PatientIdentity {
+            patient_id: request.patient_id,
+            identifiers: vec!["MRN-0042".to_string()],
+        };
+        let scan = |patient: &str, medication: &str| BarcodeScan {
+            patient: patient.to_string(),
+            medication: medication.to_string(),
+        };
+        let verifier = IdentifierBarcodeVerifier;
+
+        assert!(verifier.verify(&scan("mrn 0042", "0100350580413045"), &patient, &request, Utc::now()).is_ok());
+        assert!(verifier.verify(&scan("MRN-0043", "00350580413045"), &patient, &request, Utc::now()).is_err());
+        assert!(verifier.verify(&scan("MRN-0042", "00350580999999"), &patient, &request, Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! Clinical calculators
+//!
+//! A [`Calculator`] derives a score or measurement from a patient's
+//! demographics, observations and active conditions. The result is recorded
+//! as a new Observation marked as calculated: its `method` is
+//! [`CALCULATED_METHOD`], `derived_from` lists the observations it used, and
+//! the notes show how it was reached. Calculators are looked up in a
+//! [`CalculatorRegistry`]; new ones implement the trait and are added with
+//! [`CalculatorRegistry::register`].
+//!
+//! Conditions are matched on exact SNOMED CT codes. There is no terminology
+//! server to expand descendants, so each calculator lists the concepts it
+//! recognises.
+
+use crate::domain::values::AdministrativeGender;
+use crate::domain::{Observation, ObservationStatus, ObservationValue, SNOMED_SYSTEM};
+use crate::services::growth::{self, LmsTable};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// `method` of calculated observations
+pub const CALCULATED_METHOD: &str = "calculated";
+
+/// Code system of calculator results that have no LOINC code
+pub const CALCULATOR_CODE_SYSTEM: &str = "urn:emr:calculator";
+
+/// Code of CHA₂DS₂-VASc results, which have no LOINC code
+pub const CHA2DS2_VASC_CODE: &str = "urn:emr:calculator|cha2ds2-vasc";
+
+/// LOINC code for body weight
+pub const BODY_WEIGHT_CODE: &str = "29463-7";
+
+/// LOINC code for body height
+pub const BODY_HEIGHT_CODE: &str = "8302-2";
+
+/// LOINC code for BMI percentile per age and sex
+pub const BMI_PERCENTILE_CODE: &str = "59576-9";
+
+/// A coded active condition of the patient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionCode {
+    /// Code system URI
+    pub system: String,
+    /// Code
+    pub code: String,
+}
+
+/// What a calculator may draw on
+#[derive(Debug, Clone)]
+pub struct CalculatorInput {
+    /// Patient calculated for
+    pub patient_id: Id,
+    /// Date of birth, when known
+    pub birth_date: Option<NaiveDate>,
+    /// Administrative gender, used as sex by the calculators
+    pub gender: Option<AdministrativeGender>,
+    /// The patient's observations, in any order
+    pub observations: Vec<Observation>,
+    /// The patient's active conditions
+    pub conditions: Vec<ConditionCode>,
+}
+
+impl CalculatorInput {
+    /// Age in whole years on a date
+    pub fn age_in_years(&self, on: NaiveDate) -> Option<u32> {
+        on.years_since(self.birth_date?)
+    }
+
+    /// Age in months on a date, with fractions
+    pub fn age_in_months(&self, on: NaiveDate) -> Option<f64> {
+        growth::age_in_months(self.birth_date?, on)
+    }
+
+    /// The most recent usable quantity with a LOINC code, converted to `unit`
+    ///
+    /// Cancelled and entered-in-error observations are skipped.
+    pub fn latest_quantity(&self, code: &str, unit: &str) -> Result<Option<(&Observation, f64)>> {
+        let latest = self
+            .observations
+            .iter()
+            .filter(|observation| observation.code == code)
+            .filter(|observation| {
+                !matches!(observation.status, ObservationStatus::Cancelled | ObservationStatus::EnteredInError)
+            })
+            .filter(|observation| matches!(observation.value, Some(ObservationValue::Quantity { .. })))
+            .max_by_key(|observation| observation.effective.or(observation.issued));
+        match latest {
+            Some(observation) => Ok(observation.quantity_in(unit)?.map(|value| (observation, value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the patient has an active condition with one of the SNOMED CT codes
+    pub fn has_condition(&self, snomed_codes: &[&str]) -> bool {
+        self.conditions
+            .iter()
+            .any(|condition| condition.system == SNOMED_SYSTEM && snomed_codes.contains(&condition.code.as_str()))
+    }
+
+    fn sex(&self, calculator: &str) -> Result<&AdministrativeGender> {
+        match &self.gender {
+            Some(gender @ (AdministrativeGender::Male | AdministrativeGender::Female)) => Ok(gender),
+            _ => Err(missing_input(calculator, "the patient's sex (male or female)")),
+        }
+    }
+}
+
+/// Result of a calculation, before it becomes an observation
+#[derive(Debug, Clone)]
+pub struct Calculation {
+    /// Calculated value
+    pub value: ObservationValue,
+    /// Interpretation of the value, e.g. a risk band
+    pub interpretation: Option<String>,
+    /// Observations the value was derived from
+    pub derived_from: Vec<Id>,
+    /// How the value was reached, one line per step
+    pub details: Vec<String>,
+}
+
+/// Description of a calculator
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CalculatorInfo {
+    /// Id used to run it
+    pub id: &'static str,
+    /// Display name
+    pub name: &'static str,
+    /// What it calculates and for whom
+    pub description: &'static str,
+    /// Code of the result: a LOINC code, or a `system|code` token
+    pub code: &'static str,
+    /// Observation category of the result
+    pub category: &'static str,
+}
+
+/// A clinical calculator
+pub trait Calculator: Send + Sync {
+    /// What the calculator is
+    fn info(&self) -> CalculatorInfo;
+
+    /// Calculate from the patient's data as of `at`
+    fn calculate(&self, input: &CalculatorInput, at: Timestamp) -> Result<Calculation>;
+}
+
+/// Error for an input a calculator needs but the patient's record lacks
+fn missing_input(calculator: &str, input: &str) -> Error {
+    Error::business_rule_violation("calculator_input_missing", &format!("{} needs {}", calculator, input))
+}
+
+/// The available calculators
+#[derive(Clone, Default)]
+pub struct CalculatorRegistry {
+    calculators: Vec<Arc<dyn Calculator>>,
+}
+
+impl CalculatorRegistry {
+    /// Registry without calculators
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a calculator, replacing any with the same id
+    pub fn register(&mut self, calculator: Arc<dyn Calculator>) {
+        let id = calculator.info().id;
+        self.calculators.retain(|existing| existing.info().id != id);
+        self.calculators.push(calculator);
+    }
+
+    /// Descriptions of the calculators, in registration order
+    pub fn list(&self) -> Vec<CalculatorInfo> {
+        self.calculators.iter().map(|calculator| calculator.info()).collect()
+    }
+
+    /// A calculator by id
+    pub fn find(&self, id: &str) -> Option<&Arc<dyn Calculator>> {
+        self.calculators.iter().find(|calculator| calculator.info().id == id)
+    }
+
+    /// Run a calculator and record its result as a final, calculated observation
+    pub fn run(&self, id: &str, input: &CalculatorInput, at: Timestamp) -> Result<Observation> {
+        let calculator = self
+            .find(id)
+            .ok_or_else(|| Error::validation_error_with_field(&format!("Unknown calculator '{}'", id), "calculator"))?;
+        let info = calculator.info();
+        let calculation = calculator.calculate(input, at)?;
+
+        let mut observation = Observation::new(ObservationStatus::Final, info.code.to_string(), input.patient_id);
+        observation.category.push(info.category.to_string());
+        observation.effective = Some(at);
+        observation.issued = Some(at);
+        observation.method = Some(CALCULATED_METHOD.to_string());
+        observation.value = Some(calculation.value);
+        observation.interpretation.extend(calculation.interpretation);
+        observation.derived_from = calculation.derived_from;
+        observation.note.push(format!("Calculated with {}", info.name));
+        observation.note.extend(calculation.details);
+        Ok(observation)
+    }
+}
+
+/// CHA₂DS₂-VASc stroke risk score in atrial fibrillation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cha2ds2VascCalculator;
+
+impl Cha2ds2VascCalculator {
+    const NAME: &'static str = "CHA₂DS₂-VASc";
+    const CONGESTIVE_HEART_FAILURE: &'static [&'static str] = &["42343007", "84114007"];
+    const HYPERTENSION: &'static [&'static str] = &["38341003", "59621000"];
+    const DIABETES: &'static [&'static str] = &["73211009", "44054006", "46635009"];
+    const STROKE_OR_THROMBOEMBOLISM: &'static [&'static str] = &["230690007", "422504002", "266257000", "371039008"];
+    const VASCULAR_DISEASE: &'static [&'static str] = &["22298006", "399211009", "400047006"];
+}
+
+impl Calculator for Cha2ds2VascCalculator {
+    fn info(&self) -> CalculatorInfo {
+        CalculatorInfo {
+            id: "cha2ds2-vasc",
+            name: Self::NAME,
+            description: "Stroke risk in atrial fibrillation from age, sex and the patient's active conditions",
+            code: CHA2DS2_VASC_CODE,
+            category: "survey",
+        }
+    }
+
+    fn calculate(&self, input: &CalculatorInput, at: Timestamp) -> Result<Calculation> {
+        let age = input
+            .age_in_years(at.date_naive())
+            .ok_or_else(|| missing_input(Self::NAME, "the patient's date of birth"))?;
+        let female = matches!(input.sex(Self::NAME)?, AdministrativeGender::Female);
+
+        let age_points = match age {
+            75.. => 2,
+            65..=74 => 1,
+            _ => 0,
+        };
+        let factors = [
+            ("Congestive heart failure", input.has_condition(Self::CONGESTIVE_HEART_FAILURE) as i64),
+            ("Hypertension", input.has_condition(Self::HYPERTENSION) as i64),
+            ("Age", age_points),
+            ("Diabetes mellitus", input.has_condition(Self::DIABETES) as i64),
+            ("Stroke, TIA or thromboembolism", 2 * input.has_condition(Self::STROKE_OR_THROMBOEMBOLISM) as i64),
+            ("Vascular disease", input.has_condition(Self::VASCULAR_DISEASE) as i64),
+            ("Female sex", female as i64),
+        ];
+        let score: i64 = factors.iter().map(|(_, points)| points).sum();
+
+        // Female sex alone does not raise the risk band
+        let risk = score - female as i64;
+        let interpretation = match risk {
+            0 => "Low risk; anticoagulation not recommended",
+            1 => "Intermediate risk; consider anticoagulation",
+            _ => "High risk; anticoagulation recommended",
+        };
+        Ok(Calculation {
+            value: ObservationValue::Integer(score),
+            interpretation: Some(interpretation.to_string()),
+            derived_from: Vec::new(),
+            details: factors
+                .iter()
+                .filter(|(_, points)| *points > 0)
+                .map(|(factor, points)| format!("{}: +{}", factor, points))
+                .collect(),
+        })
+    }
+}
+
+/// BMI-for-age percentile for children and teens (2 to 20 years), from the
+/// CDC BMI-for-age LMS reference
+#[derive(Debug, Clone)]
+pub struct BmiPercentileCalculator {
+    reference: Arc<LmsTable>,
+}
+
+impl BmiPercentileCalculator {
+    const NAME: &'static str = "BMI-for-age percentile";
+
+    /// Calculator over a loaded CDC BMI-for-age table (`bmiagerev.csv`)
+    pub fn new(reference: Arc<LmsTable>) -> Self {
+        Self { reference }
+    }
+}
+
+impl Calculator for BmiPercentileCalculator {
+    fn info(&self) -> CalculatorInfo {
+        CalculatorInfo {
+            id: "bmi-percentile",
+            name: Self::NAME,
+            description: "BMI percentile for age and sex (CDC, ages 2 to 20) from the latest weight and height",
+            code: BMI_PERCENTILE_CODE,
+            category: "vital-signs",
+        }
+    }
+
+    fn calculate(&self, input: &CalculatorInput, at: Timestamp) -> Result<Calculation> {
+        let sex = input.sex(Self::NAME)?;
+        let age_months = input
+            .age_in_months(at.date_naive())
+            .ok_or_else(|| missing_input(Self::NAME, "the patient's date of birth"))?;
+        let (weight, kg) = input
+            .latest_quantity(BODY_WEIGHT_CODE, "kg")?
+            .ok_or_else(|| missing_input(Self::NAME, "a body weight"))?;
+        let (height, cm) = input
+            .latest_quantity(BODY_HEIGHT_CODE, "cm")?
+            .ok_or_else(|| missing_input(Self::NAME, "a body height"))?;
+
+        let bmi = kg / (cm / 100.0).powi(2);
+        let assessment = self.reference.assess(sex, age_months, bmi).ok_or_else(|| {
+            let (first, last) = self.reference.age_range();
+            Error::business_rule_violation(
+                "calculator_not_applicable",
+                &format!("{} applies from {} to {} months of age", Self::NAME, first, last),
+            )
+        })?;
+
+        let percentile = (assessment.percentile * 10.0).round() / 10.0;
+        let interpretation = match percentile {
+            p if p < 5.0 => "Underweight",
+            p if p < 85.0 => "Healthy weight",
+            p if p < 95.0 => "Overweight",
+            _ => "Obesity",
+        };
+        Ok(Calculation {
+            value: ObservationValue::Quantity {
+                value: percentile,
+                unit: "%".to_string(),
+                system: Some(crate::domain::units::UCUM_SYSTEM.to_string()),
+                code: Some("%".to_string()),
+            },
+            interpretation: Some(interpretation.to_string()),
+            derived_from: vec![weight.metadata.id, height.metadata.id],
+            details: vec![
+                format!("BMI {:.1} kg/m2 from {:.1} kg and {:.1} cm", bmi, kg, cm),
+                format!("Age {:.1} months, z-score {:.2}", age_months, assessment.z_score),
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::units::UCUM_SYSTEM;
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    fn input(birth_date: NaiveDate, gender: AdministrativeGender) -> CalculatorInput {
+        CalculatorInput {
+            patient_id: Uuid::new_v4(),
+            birth_date: Some(birth_date),
+            gender: Some(gender),
+            observations: Vec::new(),
+            conditions: Vec::new(),
+        }
+    }
+
+    fn snomed(code: &str) -> ConditionCode {
+        ConditionCode {
+            system: SNOMED_SYSTEM.to_string(),
+            code: code.to_string(),
+        }
+    }
+
+    fn quantity(code: &str, value: f64, unit: &str, effective: Timestamp) -> Observation {
+        let mut observation = Observation::new(ObservationStatus::Final, code.to_string(), Uuid::new_v4());
+        observation.effective = Some(effective);
+        observation.value = Some(ObservationValue::Quantity {
+            value,
+            unit: unit.to_string(),
+            system: Some(UCUM_SYSTEM.to_string()),
+            code: Some(unit.to_string()),
+        });
+        observation
+    }
+
+    #[test]
+    fn test_cha2ds2_vasc() {
+        let at = Utc.with_ymd_and_hms(2026, 6, 1, 12, 0, 0).unwrap();
+        let mut patient = input(NaiveDate::from_ymd_opt(1955, 1, 1).unwrap(), AdministrativeGender::Female);
+        patient.conditions = vec![snomed("38341003"), snomed("266257000"), snomed("195967001")];
+
+        let mut registry = CalculatorRegistry::new();
+        registry.register(Arc::new(Cha2ds2VascCalculator));
+        let observation = registry.run("cha2ds2-vasc", &patient, at).unwrap();
+
+        // Hypertension 1, age 71 1, TIA 2, female 1
+        assert!(matches!(observation.value, Some(ObservationValue::Integer(5))));
+        assert_eq!(observation.method.as_deref(), Some(CALCULATED_METHOD));
+        assert_eq!(observation.interpretation, vec!["High risk; anticoagulation recommended"]);
+        assert_eq!(observation.subject, patient.patient_id);
+
+        patient.gender = Some(AdministrativeGender::Unknown);
+        assert!(registry.run("cha2ds2-vasc", &patient, at).is_err());
+        assert!(registry.run("unknown", &patient, at).is_err());
+    }
+
+    #[test]
+    fn test_bmi_percentile() {
+        // Made-up LMS parameters; L = 0 keeps the expected values exact
+        let reference = LmsTable::from_cdc_csv(
+            "Sex,Agemos,L,M,S\n1,24,0,16,0.1\n1,240,0,16,0.1\n2,24,0,16,0.1\n2,240,0,16,0.1\n",
+        )
+        .unwrap();
+        let calculator = BmiPercentileCalculator::new(Arc::new(reference));
+        let at = Utc.with_ymd_and_hms(2026, 6, 1, 12, 0, 0).unwrap();
+        let mut patient = input(NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), AdministrativeGender::Male);
+        assert!(calculator.calculate(&patient, at).is_err(), "no weight or height");
+
+        let earlier = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        patient.observations = vec![
+            quantity(BODY_WEIGHT_CODE, 10.0, "kg", earlier),
+            quantity(BODY_WEIGHT_CODE, 16000.0, "g", at),
+            quantity(BODY_HEIGHT_CODE, 100.0, "cm", at),
+        ];
+        let calculation = calculator.calculate(&patient, at).unwrap();
+
+        // BMI 16 is the median
+        assert!(matches!(calculation.value, ObservationValue::Quantity { value, .. } if value == 50.0));
+        assert_eq!(calculation.interpretation.as_deref(), Some("Healthy weight"));
+        assert_eq!(calculation.derived_from[0], patient.observations[1].metadata.id);
+    }
+}
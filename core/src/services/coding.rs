@@ -0,0 +1,345 @@
+//! Coding suggestions for encounters
+//!
+//! Diagnoses are suggested from the ICD-10-CM codings of the patient's
+//! recorded conditions, those addressed at the encounter first; conditions
+//! recorded only in other systems are listed for the coder to code by hand.
+//! The evaluation and management (E/M) visit is suggested from the
+//! encounter's class and total time under the CPT time thresholds, with
+//! prolonged service units past the highest level. Visits that are not
+//! levelled by time (emergency, inpatient) get no E/M suggestion.
+
+use crate::domain::{ChargeDiagnosis, CodeKind, Encounter, EncounterClass, EncounterStatus, ICD10CM_SYSTEM};
+use crate::types::Timestamp;
+use crate::{Error, Result};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+/// CPT code of each 15 minutes of prolonged service past the highest level
+pub const PROLONGED_SERVICE_CODE: &str = "99417";
+
+/// CPT modifier of a synchronous telemedicine service
+pub const TELEHEALTH_MODIFIER: &str = "95";
+
+/// Years after a visit during which the patient counts as established
+pub const ESTABLISHED_PATIENT_YEARS: i64 = 3;
+
+/// A coding of a recorded condition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConditionCoding {
+    /// Code system URI
+    pub system: String,
+    /// Code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+}
+
+/// A condition recorded for the patient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCondition {
+    /// FHIR `Condition` id
+    pub id: String,
+    /// The condition's codings
+    pub codings: Vec<ConditionCoding>,
+    /// Whether the condition was addressed at the encounter being coded
+    pub addressed: bool,
+}
+
+/// A code suggested to the coder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeSuggestion {
+    /// Diagnosis or procedure
+    pub kind: CodeKind,
+    /// Suggested code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// Suggested units of service
+    pub units: u32,
+    /// Suggested CPT modifiers
+    pub modifiers: Vec<String>,
+    /// Why the code is suggested
+    pub rationale: String,
+    /// Condition the diagnosis was taken from
+    pub condition_id: Option<String>,
+}
+
+/// Suggestions for coding an encounter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CodingSuggestions {
+    /// Diagnoses, addressed conditions first
+    pub diagnoses: Vec<CodeSuggestion>,
+    /// Procedures and services
+    pub procedures: Vec<CodeSuggestion>,
+    /// Ids of conditions without an ICD-10-CM coding
+    pub uncoded_conditions: Vec<String>,
+}
+
+/// ICD-10-CM suggestions from recorded conditions
+///
+/// Each code is suggested once; conditions addressed at the encounter come
+/// first, in the order given.
+pub fn suggest_diagnoses(conditions: &[RecordedCondition]) -> (Vec<CodeSuggestion>, Vec<String>) {
+    let mut suggestions: Vec<CodeSuggestion> = Vec::new();
+    let mut uncoded = Vec::new();
+
+    let ordered = conditions
+        .iter()
+        .filter(|condition| condition.addressed)
+        .chain(conditions.iter().filter(|condition| !condition.addressed));
+    for condition in ordered {
+        let icd10: Vec<&ConditionCoding> = condition
+            .codings
+            .iter()
+            .filter(|coding| coding.system == ICD10CM_SYSTEM)
+            .collect();
+        if icd10.is_empty() {
+            uncoded.push(condition.id.clone());
+        }
+        for coding in icd10 {
+            let code = coding.code.trim().to_uppercase();
+            if suggestions.iter().any(|suggestion| suggestion.code == code) {
+                continue;
+            }
+            suggestions.push(CodeSuggestion {
+                kind: CodeKind::Diagnosis,
+                code,
+                display: coding.display.clone(),
+                units: 1,
+                modifiers: Vec::new(),
+                rationale: if condition.addressed {
+                    "Condition addressed at this encounter".to_string()
+                } else {
+                    "Active condition not linked to this encounter".to_string()
+                },
+                condition_id: Some(condition.id.clone()),
+            });
+        }
+    }
+
+    (suggestions, uncoded)
+}
+
+/// Charge diagnoses for ICD-10-CM codes, each linked to the recorded
+/// condition it codes
+///
+/// A charge may only point at a condition on record for the patient; codes
+/// no condition carries are rejected together.
+pub fn charge_diagnoses(codes: &[String], conditions: &[RecordedCondition]) -> Result<Vec<ChargeDiagnosis>> {
+    let mut diagnoses = Vec::new();
+    let mut unrecorded = Vec::new();
+    for code in codes {
+        let code = code.trim().to_uppercase();
+        let coded = conditions.iter().find_map(|condition| {
+            condition
+                .codings
+                .iter()
+                .find(|coding| coding.system == ICD10CM_SYSTEM && coding.code.trim().eq_ignore_ascii_case(&code))
+                .map(|coding| (condition, coding))
+        });
+        match coded {
+            Some((condition, coding)) => diagnoses.push(ChargeDiagnosis {
+                sequence: 0,
+                code,
+                display: coding.display.clone(),
+                condition_id: condition.id.clone(),
+            }),
+            None => unrecorded.push(code),
+        }
+    }
+
+    if !unrecorded.is_empty() {
+        return Err(Error::validation_error_with_field(
+            &format!("No recorded condition is coded {}", unrecorded.join(", ")),
+            "diagnoses",
+        ));
+    }
+    Ok(diagnoses)
+}
+
+/// Time-levelled E/M codes of one setting
+struct EmLevels {
+    /// Setting, for the rationale
+    setting: &'static str,
+    /// (code, minimum minutes) for new patients, lowest level first
+    new_patient: &'static [(&'static str, u32)],
+    /// (code, minimum minutes) for established patients, lowest level first
+    established: &'static [(&'static str, u32)],
+    /// Minutes from which prolonged service is billed for new and established
+    /// patients
+    prolonged_from: (u32, u32),
+}
+
+const OFFICE_LEVELS: EmLevels = EmLevels {
+    setting: "office or outpatient visit",
+    new_patient: &[("99202", 15), ("99203", 30), ("99204", 45), ("99205", 60)],
+    established: &[("99212", 10), ("99213", 20), ("99214", 30), ("99215", 40)],
+    prolonged_from: (75, 55),
+};
+
+const HOME_LEVELS: EmLevels = EmLevels {
+    setting: "home or residence visit",
+    new_patient: &[("99341", 15), ("99342", 30), ("99344", 60), ("99345", 75)],
+    established: &[("99347", 20), ("99348", 30), ("99349", 40), ("99350", 60)],
+    prolonged_from: (90, 75),
+};
+
+/// Total minutes of the encounter, from its length or period
+pub fn encounter_minutes(encounter: &Encounter) -> Option<u32> {
+    encounter.length.or_else(|| {
+        let period = encounter.period.as_ref()?;
+        let minutes = (period.end? - period.start?).num_minutes();
+        u32::try_from(minutes).ok()
+    })
+}
+
+/// When the encounter took place: its start, or when it was recorded
+fn encounter_time(encounter: &Encounter) -> Timestamp {
+    encounter
+        .period
+        .as_ref()
+        .and_then(|period| period.start)
+        .unwrap_or(encounter.metadata.created_at)
+}
+
+/// Whether the patient had another visit in the three years before
+/// `encounter`, making them an established patient
+pub fn is_established_patient(encounter: &Encounter, history: &[Encounter]) -> bool {
+    let at = encounter_time(encounter);
+    let since = at - Duration::days(365 * ESTABLISHED_PATIENT_YEARS);
+    history.iter().any(|previous| {
+        let previous_at = encounter_time(previous);
+        previous.metadata.id != encounter.metadata.id
+            && matches!(previous.status, EncounterStatus::Finished | EncounterStatus::InProgress)
+            && previous_at < at
+            && previous_at >= since
+    })
+}
+
+/// E/M visit and prolonged service suggestions from the encounter's class
+/// and total time
+///
+/// `established` is whether the patient had a visit in the three years before
+/// this one.
+pub fn suggest_evaluation_and_management(encounter: &Encounter, established: bool) -> Vec<CodeSuggestion> {
+    let (levels, modifiers) = match encounter.class {
+        EncounterClass::Ambulatory | EncounterClass::Outpatient | EncounterClass::Daytime => (&OFFICE_LEVELS, vec![]),
+        EncounterClass::Virtual => (&OFFICE_LEVELS, vec![TELEHEALTH_MODIFIER.to_string()]),
+        EncounterClass::Home => (&HOME_LEVELS, vec![]),
+        EncounterClass::Emergency | EncounterClass::Inpatient | EncounterClass::Field => return Vec::new(),
+    };
+    let Some(minutes) = encounter_minutes(encounter) else {
+        return Vec::new();
+    };
+    let (table, prolonged_from, patient) = if established {
+        (levels.established, levels.prolonged_from.1, "established patient")
+    } else {
+        (levels.new_patient, levels.prolonged_from.0, "new patient")
+    };
+    let Some((code, threshold)) = table.iter().rev().find(|(_, threshold)| minutes >= *threshold) else {
+        return Vec::new();
+    };
+
+    let mut suggestions = vec![CodeSuggestion {
+        kind: CodeKind::Procedure,
+        code: code.to_string(),
+        display: None,
+        units: 1,
+        modifiers: modifiers.clone(),
+        rationale: format!(
+            "{} minutes, {}: {} of at least {} minutes",
+            minutes, patient, levels.setting, threshold
+        ),
+        condition_id: None,
+    }];
+    if minutes >= prolonged_from {
+        let units = (minutes - prolonged_from) / 15 + 1;
+        suggestions.push(CodeSuggestion {
+            kind: CodeKind::Procedure,
+            code: PROLONGED_SERVICE_CODE.to_string(),
+            display: None,
+            units,
+            modifiers,
+            rationale: format!(
+                "{} minutes: {} unit(s) of prolonged service from {} minutes",
+                minutes, units, prolonged_from
+            ),
+            condition_id: None,
+        });
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::EncounterBuilder;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_suggest_diagnoses() {
+        let condition = |id: &str, system: &str, code: &str, addressed: bool| RecordedCondition {
+            id: id.to_string(),
+            codings: vec![ConditionCoding {
+                system: system.to_string(),
+                code: code.to_string(),
+                display: None,
+            }],
+            addressed,
+        };
+        let conditions = [
+            condition("c1", ICD10CM_SYSTEM, "E11.9", false),
+            condition("c2", ICD10CM_SYSTEM, "i10", true),
+            condition("c3", "http://snomed.info/sct", "38341003", true),
+            condition("c4", ICD10CM_SYSTEM, "I10", false),
+        ];
+
+        let (diagnoses, uncoded) = suggest_diagnoses(&conditions);
+        let codes: Vec<&str> = diagnoses.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, vec!["I10", "E11.9"]);
+        assert_eq!(uncoded, vec!["c3".to_string()]);
+
+        let linked = charge_diagnoses(&["e11.9".to_string()], &conditions).unwrap();
+        assert_eq!((linked[0].code.as_str(), linked[0].condition_id.as_str()), ("E11.9", "c1"));
+        assert!(charge_diagnoses(&["I10".to_string(), "J45.909".to_string()], &conditions).is_err());
+    }
+
+    #[test]
+    fn test_suggest_evaluation_and_management() {
+        let visit = |class: EncounterClass, minutes: u32| {
+            let mut encounter = Encounter::new(EncounterStatus::Finished, class, Uuid::new_v4());
+            encounter.length = Some(minutes);
+            encounter
+        };
+        let codes = |suggestions: Vec<CodeSuggestion>| -> Vec<(String, u32)> {
+            suggestions.into_iter().map(|s| (s.code, s.units)).collect()
+        };
+
+        assert_eq!(
+            codes(suggest_evaluation_and_management(&visit(EncounterClass::Ambulatory, 25), true)),
+            vec![("99213".to_string(), 1)]
+        );
+        assert!(suggest_evaluation_and_management(&visit(EncounterClass::Ambulatory, 5), true).is_empty());
+        assert_eq!(
+            codes(suggest_evaluation_and_management(&visit(EncounterClass::Ambulatory, 92), false)),
+            vec![("99205".to_string(), 1), ("99417".to_string(), 2)]
+        );
+        let telehealth = suggest_evaluation_and_management(&visit(EncounterClass::Virtual, 30), false);
+        assert_eq!((telehealth[0].code.as_str(), telehealth[0].modifiers.clone()), ("99203", vec!["95".to_string()]));
+        assert!(suggest_evaluation_and_management(&visit(EncounterClass::Emergency, 60), true).is_empty());
+    }
+
+    #[test]
+    fn test_is_established_patient() {
+        let patient_id = Uuid::new_v4();
+        let visit = |status: EncounterStatus, days_ago: i64| {
+            EncounterBuilder::new(patient_id).with_status(status).started_days_ago(days_ago).build()
+        };
+        let today = visit(EncounterStatus::Finished, 0);
+
+        assert!(!is_established_patient(&today, std::slice::from_ref(&today)));
+        assert!(is_established_patient(&today, &[visit(EncounterStatus::Finished, 400)]));
+        assert!(!is_established_patient(&today, &[visit(EncounterStatus::Finished, 1200)]));
+        assert!(!is_established_patient(&today, &[visit(EncounterStatus::Cancelled, 30)]));
+    }
+}
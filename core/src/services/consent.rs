@@ -0,0 +1,246 @@
+//! Purpose of use and consent rules
+//!
+//! Some accesses to patient data are sensitive: psychiatric notes, HIV
+//! results, and the record of a patient outside the practitioner's panel,
+//! i.e. one they have no treating relationship with and opened by breaking
+//! the glass. For these the user must state a [`PurposeOfUse`], which is
+//! stored on the audit event, and [`ConsentRules`] decide whether the
+//! purpose permits the access.
+
+use serde::{Deserialize, Serialize};
+
+/// LOINC codes of HIV test results
+pub const HIV_RESULT_CODES: [&str; 5] = [
+    // HIV 1 Ab [Presence] in Serum
+    "7917-8",
+    // HIV 1 Ab [Presence] in Serum by Immunoblot
+    "5221-7",
+    // HIV 1 RNA [#/volume] (viral load) in Serum or Plasma by NAA with probe detection
+    "20447-9",
+    // HIV 1+2 Ab+HIV1 p24 Ag [Presence] in Serum or Plasma
+    "56888-1",
+    // HIV 1+2 Ab+HIV1 p24 Ag [Presence] in Serum or Plasma by Immunoassay
+    "75622-1",
+];
+
+/// Whether an observation code is an HIV test result
+pub fn is_hiv_result(code: &str) -> bool {
+    HIV_RESULT_CODES.contains(&code)
+}
+
+/// Why patient data is accessed (HL7 v3 `PurposeOfUse`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PurposeOfUse {
+    /// Providing care to the patient
+    Treatment,
+    /// Billing for care
+    Payment,
+    /// Running the practice: quality review, audits, training
+    Operations,
+    /// Emergency treatment
+    Emergency,
+}
+
+impl PurposeOfUse {
+    /// Every purpose, in the order offered to users
+    pub const ALL: [PurposeOfUse; 4] = [
+        PurposeOfUse::Treatment,
+        PurposeOfUse::Payment,
+        PurposeOfUse::Operations,
+        PurposeOfUse::Emergency,
+    ];
+
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PurposeOfUse::Treatment => "treatment",
+            PurposeOfUse::Payment => "payment",
+            PurposeOfUse::Operations => "operations",
+            PurposeOfUse::Emergency => "emergency",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|purpose| purpose.as_str() == value)
+    }
+
+    /// HL7 v3 ActReason code
+    pub fn code(&self) -> &'static str {
+        match self {
+            PurposeOfUse::Treatment => "TREAT",
+            PurposeOfUse::Payment => "HPAYMT",
+            PurposeOfUse::Operations => "HOPERAT",
+            PurposeOfUse::Emergency => "ETREAT",
+        }
+    }
+}
+
+/// Kind of sensitive data that needs a purpose of use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensitiveData {
+    /// Psychiatric notes
+    PsychiatricNotes,
+    /// HIV test results
+    HivResults,
+    /// The record of a patient outside the practitioner's panel
+    OffPanelPatient,
+}
+
+impl SensitiveData {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensitiveData::PsychiatricNotes => "psychiatric_notes",
+            SensitiveData::HivResults => "hiv_results",
+            SensitiveData::OffPanelPatient => "off_panel_patient",
+        }
+    }
+
+    /// Description for users
+    pub fn description(&self) -> &'static str {
+        match self {
+            SensitiveData::PsychiatricNotes => "psychiatric notes",
+            SensitiveData::HivResults => "HIV results",
+            SensitiveData::OffPanelPatient => "the record of a patient outside your panel",
+        }
+    }
+}
+
+/// Purposes a kind of sensitive data may be accessed for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsentRule {
+    /// Data the rule covers
+    pub data: SensitiveData,
+    /// Purposes that permit access
+    pub purposes: Vec<PurposeOfUse>,
+}
+
+/// Outcome of evaluating consent rules for an access
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsentDecision {
+    /// The access may go ahead
+    Permit,
+    /// No purpose was given; one of `allowed` must be
+    PurposeRequired {
+        /// Purposes that would permit the access
+        allowed: Vec<PurposeOfUse>,
+    },
+    /// The purpose given does not permit the access
+    Deny {
+        /// Purposes that would permit the access
+        allowed: Vec<PurposeOfUse>,
+    },
+}
+
+impl ConsentDecision {
+    /// Whether the access may go ahead
+    pub fn is_permitted(&self) -> bool {
+        matches!(self, ConsentDecision::Permit)
+    }
+}
+
+/// Consent rules for sensitive data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsentRules {
+    /// One rule per kind of data; data without a rule may be accessed for
+    /// any purpose, but one must still be given
+    pub rules: Vec<ConsentRule>,
+}
+
+impl Default for ConsentRules {
+    /// Psychiatric notes only for treatment; HIV results also for billing;
+    /// patients outside the panel for any purpose
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                ConsentRule {
+                    data: SensitiveData::PsychiatricNotes,
+                    purposes: vec![PurposeOfUse::Treatment, PurposeOfUse::Emergency],
+                },
+                ConsentRule {
+                    data: SensitiveData::HivResults,
+                    purposes: vec![PurposeOfUse::Treatment, PurposeOfUse::Payment, PurposeOfUse::Emergency],
+                },
+                ConsentRule {
+                    data: SensitiveData::OffPanelPatient,
+                    purposes: PurposeOfUse::ALL.to_vec(),
+                },
+            ],
+        }
+    }
+}
+
+impl ConsentRules {
+    /// Purposes that permit accessing all of `data`
+    pub fn allowed(&self, data: &[SensitiveData]) -> Vec<PurposeOfUse> {
+        PurposeOfUse::ALL
+            .into_iter()
+            .filter(|purpose| {
+                data.iter().all(|data| {
+                    self.rules
+                        .iter()
+                        .filter(|rule| rule.data == *data)
+                        .all(|rule| rule.purposes.contains(purpose))
+                })
+            })
+            .collect()
+    }
+
+    /// Decide whether an access to `data` for `purpose` may go ahead;
+    /// accesses to no sensitive data always may
+    pub fn evaluate(&self, data: &[SensitiveData], purpose: Option<PurposeOfUse>) -> ConsentDecision {
+        if data.is_empty() {
+            return ConsentDecision::Permit;
+        }
+        let allowed = self.allowed(data);
+        match purpose {
+            None => ConsentDecision::PurposeRequired { allowed },
+            Some(purpose) if allowed.contains(&purpose) => ConsentDecision::Permit,
+            Some(_) => ConsentDecision::Deny { allowed },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_purpose_of_use_names() {
+        for purpose in PurposeOfUse::ALL {
+            assert_eq!(PurposeOfUse::parse(purpose.as_str()), Some(purpose));
+        }
+        assert_eq!(PurposeOfUse::parse("marketing"), None);
+        assert_eq!(PurposeOfUse::Emergency.code(), "ETREAT");
+        assert!(is_hiv_result("75622-1"));
+        assert!(!is_hiv_result("8480-6"));
+    }
+
+    #[test]
+    fn test_consent_evaluation() {
+        let rules = ConsentRules::default();
+
+        assert_eq!(rules.evaluate(&[], None), ConsentDecision::Permit);
+        assert_eq!(
+            rules.evaluate(&[SensitiveData::OffPanelPatient], None),
+            ConsentDecision::PurposeRequired {
+                allowed: PurposeOfUse::ALL.to_vec()
+            }
+        );
+        assert!(rules
+            .evaluate(&[SensitiveData::HivResults], Some(PurposeOfUse::Payment))
+            .is_permitted());
+        assert_eq!(
+            rules.evaluate(
+                &[SensitiveData::PsychiatricNotes, SensitiveData::HivResults],
+                Some(PurposeOfUse::Payment)
+            ),
+            ConsentDecision::Deny {
+                allowed: vec![PurposeOfUse::Treatment, PurposeOfUse::Emergency]
+            }
+        );
+    }
+}
@@ -0,0 +1,534 @@
+//! Bulk ingestion of device observations
+//!
+//! Bedside monitors and other devices report readings far more often than
+//! anyone charts them, in batches of thousands. Each [`DeviceReading`] of a
+//! batch is checked on its own: a bad reading is reported by its index and
+//! does not hold back the rest. Readings of vital signs are checked and
+//! converted like charted vitals (see [`VitalSign::measurement`]), and
+//! readings of patients or encounters the caller cannot see are rejected
+//! (see [`ValidatedBatch::reject_unknown`]), as are readings a strict
+//! validation profile fails (see [`ValidatedBatch::reject`]).
+//!
+//! Accepted readings are stored with one multi-row insert per chunk, each
+//! column bound as an array ([`INSERT_DEVICE_OBSERVATIONS_QUERY`]), so a
+//! batch takes a few round trips rather than one per reading. A reading
+//! sent with its own id is stored once: a resent batch skips the readings
+//! already stored and counts them as duplicates.
+//!
+//! [`IngestLimits`] bound the readings per batch, the rows per insert and
+//! the batches stored at once.
+
+use crate::domain::units::UCUM_SYSTEM;
+use crate::domain::{Observation, ObservationStatus, ObservationValue, VitalSign, VITAL_SIGNS_CATEGORY};
+use crate::types::{Id, Timestamp};
+use crate::Error;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Patients of a batch the caller can see; `$1` is the batch's patient ids
+pub const KNOWN_PATIENTS_QUERY: &str = "SELECT id FROM emr.patients WHERE id = ANY($1)";
+
+/// Encounters of a batch the caller can see, with their patients and
+/// organizations (the tenant whose validation profile applies); `$1` is the
+/// batch's encounter ids
+pub const KNOWN_ENCOUNTERS_QUERY: &str =
+    "SELECT id, patient_id, organization_id FROM emr.encounters WHERE id = ANY($1)";
+
+/// Insert a chunk of observations, binding the arrays of
+/// [`ObservationColumns`] as `$1` to `$10` in field order; observations
+/// whose id is already stored with the same effective time (the partition
+/// key, see `crate::partitions`) are skipped
+pub const INSERT_DEVICE_OBSERVATIONS_QUERY: &str = "INSERT INTO emr.observations \
+     (id, status, category, code, display, patient_id, encounter_id, device_id, effective_date, \
+     value_quantity_value, value_quantity_unit) \
+     SELECT id, 'final', category, code, display, patient_id, encounter_id, device_id, effective_date, value, unit \
+     FROM unnest($1::uuid[], $2::text[], $3::text[], $4::text[], $5::uuid[], $6::uuid[], $7::text[], \
+     $8::timestamptz[], $9::float8[], $10::text[]) \
+     AS reading(id, category, code, display, patient_id, encounter_id, device_id, effective_date, value, unit) \
+     ON CONFLICT (id, effective_date) DO NOTHING";
+
+/// How far ahead of the receiver's clock a reading may be taken, for
+/// devices whose clocks drift
+const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+
+/// Limits on bulk ingestion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestLimits {
+    /// Readings accepted in one batch
+    pub max_readings: usize,
+    /// Rows stored by one insert statement
+    pub chunk_size: usize,
+    /// Batches stored at once; further batches wait or are refused
+    pub max_concurrent: usize,
+}
+
+impl Default for IngestLimits {
+    fn default() -> Self {
+        Self {
+            max_readings: 5_000,
+            chunk_size: 1_000,
+            max_concurrent: 4,
+        }
+    }
+}
+
+impl IngestLimits {
+    /// Problems with the limits, if any
+    pub fn check(&self) -> Result<(), String> {
+        if self.max_readings == 0 || self.chunk_size == 0 || self.max_concurrent == 0 {
+            return Err("max_readings, chunk_size and max_concurrent must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Refuse a batch with more readings than allowed
+    pub fn check_batch(&self, readings: usize) -> Result<(), String> {
+        if readings > self.max_readings {
+            return Err(format!("A batch holds at most {} readings, not {}", self.max_readings, readings));
+        }
+        Ok(())
+    }
+}
+
+/// A quantity measured by a device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceReading {
+    /// Id chosen by the sender, so a resent reading is stored once
+    #[serde(default)]
+    pub id: Option<Id>,
+    /// Patient measured
+    pub patient_id: Id,
+    /// Encounter the reading belongs to
+    #[serde(default)]
+    pub encounter_id: Option<Id>,
+    /// Identifier of the device, e.g. its serial number
+    #[serde(default)]
+    pub device_id: Option<String>,
+    /// LOINC code of the measurement
+    pub code: String,
+    /// Observation category; vital signs default to `vital-signs`
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Measured value
+    pub value: f64,
+    /// UCUM unit of the value
+    pub unit: String,
+    /// When the measurement was taken
+    pub effective: Timestamp,
+}
+
+/// A reading accepted for storage
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceObservation {
+    /// Observation id
+    pub id: Id,
+    /// Patient measured
+    pub patient_id: Id,
+    /// Encounter the reading belongs to
+    pub encounter_id: Option<Id>,
+    /// Reporting device
+    pub device_id: Option<String>,
+    /// LOINC code
+    pub code: String,
+    /// Name of the measurement, for vital signs
+    pub display: Option<String>,
+    /// Observation category
+    pub category: Option<String>,
+    /// Value, in the profile unit for vital signs
+    pub value: f64,
+    /// UCUM unit of the value
+    pub unit: String,
+    /// When the measurement was taken
+    pub effective: Timestamp,
+}
+
+/// The observation a stored reading reads back as, e.g. to check it
+/// against a validation profile
+impl From<&DeviceObservation> for Observation {
+    fn from(reading: &DeviceObservation) -> Self {
+        let mut observation = Observation::new(ObservationStatus::Final, reading.code.clone(), reading.patient_id);
+        observation.metadata.id = reading.id;
+        observation.encounter = reading.encounter_id;
+        observation.category = reading.category.iter().cloned().collect();
+        observation.effective = Some(reading.effective);
+        observation.value = Some(ObservationValue::Quantity {
+            value: reading.value,
+            unit: reading.unit.clone(),
+            system: Some(UCUM_SYSTEM.to_string()),
+            code: Some(reading.unit.clone()),
+        });
+        observation
+    }
+}
+
+/// Why a reading of a batch was not stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemError {
+    /// Position of the reading in the batch
+    pub index: usize,
+    /// What is wrong with it
+    pub message: String,
+}
+
+/// Outcome of a batch
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IngestResult {
+    /// Readings in the batch
+    pub received: usize,
+    /// Readings stored
+    pub inserted: usize,
+    /// Readings skipped because their id was already stored
+    pub duplicates: usize,
+    /// Readings rejected, by index
+    pub errors: Vec<ItemError>,
+}
+
+/// One insert's worth of observations, column by column
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObservationColumns {
+    /// Observation ids
+    pub ids: Vec<Id>,
+    /// Categories
+    pub categories: Vec<Option<String>>,
+    /// LOINC codes
+    pub codes: Vec<String>,
+    /// Displays
+    pub displays: Vec<Option<String>>,
+    /// Patients
+    pub patient_ids: Vec<Id>,
+    /// Encounters
+    pub encounter_ids: Vec<Option<Id>>,
+    /// Devices
+    pub device_ids: Vec<Option<String>>,
+    /// Effective times
+    pub effective: Vec<Timestamp>,
+    /// Values
+    pub values: Vec<f64>,
+    /// Units
+    pub units: Vec<String>,
+}
+
+impl ObservationColumns {
+    /// Number of observations
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether there are no observations
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl<'a> FromIterator<&'a DeviceObservation> for ObservationColumns {
+    fn from_iter<I: IntoIterator<Item = &'a DeviceObservation>>(observations: I) -> Self {
+        let mut columns = Self::default();
+        for observation in observations {
+            columns.ids.push(observation.id);
+            columns.categories.push(observation.category.clone());
+            columns.codes.push(observation.code.clone());
+            columns.displays.push(observation.display.clone());
+            columns.patient_ids.push(observation.patient_id);
+            columns.encounter_ids.push(observation.encounter_id);
+            columns.device_ids.push(observation.device_id.clone());
+            columns.effective.push(observation.effective);
+            columns.values.push(observation.value);
+            columns.units.push(observation.unit.clone());
+        }
+        columns
+    }
+}
+
+/// A batch split into accepted observations and rejected readings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidatedBatch {
+    received: usize,
+    accepted: Vec<(usize, DeviceObservation)>,
+    errors: Vec<ItemError>,
+}
+
+impl ValidatedBatch {
+    /// Check each reading of a batch received at `now`
+    pub fn validate(readings: Vec<DeviceReading>, now: Timestamp) -> Self {
+        Self::check_each(readings.into_iter().map(Ok), now)
+    }
+
+    /// Read and check each reading of a batch received at `now`; readings
+    /// that are not [`DeviceReading`]s are rejected like invalid ones
+    pub fn from_values(values: Vec<serde_json::Value>, now: Timestamp) -> Self {
+        Self::check_each(
+            values.into_iter().map(|value| serde_json::from_value(value).map_err(|e| e.to_string())),
+            now,
+        )
+    }
+
+    fn check_each(readings: impl ExactSizeIterator<Item = Result<DeviceReading, String>>, now: Timestamp) -> Self {
+        let mut batch = Self {
+            received: readings.len(),
+            ..Self::default()
+        };
+        for (index, reading) in readings.enumerate() {
+            match reading.and_then(|reading| accept(reading, now)) {
+                Ok(observation) => batch.accepted.push((index, observation)),
+                Err(message) => batch.errors.push(ItemError { index, message }),
+            }
+        }
+        batch
+    }
+
+    /// Accepted observations
+    pub fn accepted(&self) -> impl Iterator<Item = &DeviceObservation> {
+        self.accepted.iter().map(|(_, observation)| observation)
+    }
+
+    /// Distinct patients of the accepted observations
+    pub fn patient_ids(&self) -> Vec<Id> {
+        let ids: HashSet<Id> = self.accepted().map(|observation| observation.patient_id).collect();
+        ids.into_iter().collect()
+    }
+
+    /// Distinct encounters of the accepted observations
+    pub fn encounter_ids(&self) -> Vec<Id> {
+        let ids: HashSet<Id> = self.accepted().filter_map(|observation| observation.encounter_id).collect();
+        ids.into_iter().collect()
+    }
+
+    /// Reject observations of patients not in `patients`, or of encounters
+    /// not in `encounters` (encounter id to patient id) or of another patient
+    pub fn reject_unknown(&mut self, patients: &HashSet<Id>, encounters: &HashMap<Id, Option<Id>>) {
+        self.reject(|observation| {
+            if !patients.contains(&observation.patient_id) {
+                return Some(format!("Patient {} not found", observation.patient_id));
+            }
+            observation.encounter_id.and_then(|encounter_id| match encounters.get(&encounter_id) {
+                None => Some(format!("Encounter {} not found", encounter_id)),
+                Some(patient_id) if *patient_id != Some(observation.patient_id) => {
+                    Some(format!("Encounter {} is not the patient's", encounter_id))
+                }
+                Some(_) => None,
+            })
+        });
+    }
+
+    /// Reject the observations `problem` finds something wrong with
+    pub fn reject(&mut self, mut problem: impl FnMut(&DeviceObservation) -> Option<String>) {
+        let mut kept = Vec::with_capacity(self.accepted.len());
+        for (index, observation) in self.accepted.drain(..) {
+            match problem(&observation) {
+                Some(message) => self.errors.push(ItemError { index, message }),
+                None => kept.push((index, observation)),
+            }
+        }
+        self.accepted = kept;
+    }
+
+    /// The accepted observations in chunks of at most `size`
+    pub fn chunks(&self, size: usize) -> Vec<ObservationColumns> {
+        self.accepted
+            .chunks(size.max(1))
+            .map(|chunk| chunk.iter().map(|(_, observation)| observation).collect())
+            .collect()
+    }
+
+    /// Outcome once `inserted` of the accepted observations were stored
+    pub fn into_result(mut self, inserted: usize) -> IngestResult {
+        self.errors.sort_by_key(|error| error.index);
+        IngestResult {
+            received: self.received,
+            inserted,
+            duplicates: self.accepted.len().saturating_sub(inserted),
+            errors: self.errors,
+        }
+    }
+}
+
+/// Check one reading, returning what is wrong with it
+fn accept(reading: DeviceReading, now: Timestamp) -> Result<DeviceObservation, String> {
+    let code = reading.code.trim();
+    if code.is_empty() || code.len() > 100 {
+        return Err("code must be 1 to 100 characters".to_string());
+    }
+    if reading.unit.trim().is_empty() {
+        return Err("unit is required".to_string());
+    }
+    if !reading.value.is_finite() {
+        return Err("value must be a finite number".to_string());
+    }
+    if reading.device_id.as_ref().is_some_and(|device_id| device_id.len() > 255) {
+        return Err("device_id must be at most 255 characters".to_string());
+    }
+    if reading.effective > now + Duration::minutes(MAX_CLOCK_SKEW_MINUTES) {
+        return Err("effective is in the future".to_string());
+    }
+
+    let (value, unit, display, category) = match VitalSign::from_loinc_code(code) {
+        Some(vital_sign) => {
+            let value = vital_sign.measurement(reading.value, reading.unit.trim()).map_err(|e| match e {
+                Error::ValidationError { message, .. } => message,
+                other => other.to_string(),
+            })?;
+            let category = reading.category.unwrap_or_else(|| VITAL_SIGNS_CATEGORY.to_string());
+            (value, vital_sign.ucum_unit().to_string(), Some(vital_sign.display().to_string()), Some(category))
+        }
+        None => (reading.value, reading.unit.trim().to_string(), None, reading.category),
+    };
+
+    Ok(DeviceObservation {
+        id: reading.id.unwrap_or_else(uuid::Uuid::new_v4),
+        patient_id: reading.patient_id,
+        encounter_id: reading.encounter_id,
+        device_id: reading.device_id,
+        code: code.to_string(),
+        display,
+        category,
+        value,
+        unit,
+        effective: reading.effective,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn reading(patient_id: Id, code: &str, value: f64, unit: &str) -> DeviceReading {
+        DeviceReading {
+            id: None,
+            patient_id,
+            encounter_id: None,
+            device_id: Some("monitor-7".to_string()),
+            code: code.to_string(),
+            category: None,
+            value,
+            unit: unit.to_string(),
+            effective: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_each_reading() {
+        let patient_id = uuid::Uuid::new_v4();
+        let now = Utc::now();
+        let mut future = reading(patient_id, "8867-4", 72.0, "/min");
+        future.effective = now + Duration::hours(1);
+
+        let batch = ValidatedBatch::validate(
+            vec![
+                reading(patient_id, "8867-4", 72.0, "/min"),
+                reading(patient_id, "59408-5", 120.0, "%"),
+                reading(patient_id, "8310-5", 98.6, "[degF]"),
+                reading(patient_id, " ", 1.0, "1"),
+                future,
+                reading(patient_id, "2339-0", 5.4, "mmol/L"),
+            ],
+            now,
+        );
+
+        let accepted: Vec<&DeviceObservation> = batch.accepted().collect();
+        assert_eq!(accepted.len(), 3);
+        assert_eq!(accepted[0].category.as_deref(), Some(VITAL_SIGNS_CATEGORY));
+        assert_eq!(accepted[0].display.as_deref(), Some("Heart rate"));
+        assert!((accepted[1].value - 37.0).abs() < 1e-6);
+        assert_eq!(accepted[1].unit, "Cel");
+        assert_eq!(accepted[2].category, None);
+
+        let result = batch.into_result(3);
+        assert_eq!(result.received, 6);
+        assert_eq!(result.duplicates, 0);
+        let indexes: Vec<usize> = result.errors.iter().map(|error| error.index).collect();
+        assert_eq!(indexes, vec![1, 3, 4]);
+        assert!(result.errors[0].message.contains("plausible range"));
+    }
+
+    #[test]
+    fn test_from_values() {
+        let patient_id = uuid::Uuid::new_v4();
+        let values = vec![
+            serde_json::to_value(reading(patient_id, "8867-4", 72.0, "/min")).unwrap(),
+            serde_json::json!({"patient_id": patient_id, "code": "8867-4", "value": 72}),
+            serde_json::json!("72 bpm"),
+        ];
+
+        let result = ValidatedBatch::from_values(values, Utc::now()).into_result(1);
+        assert_eq!(result.received, 3);
+        let indexes: Vec<usize> = result.errors.iter().map(|error| error.index).collect();
+        assert_eq!(indexes, vec![1, 2]);
+        assert!(result.errors[0].message.starts_with("missing field `unit`"));
+    }
+
+    #[test]
+    fn test_reject() {
+        let patient_id = uuid::Uuid::new_v4();
+        let readings = vec![reading(patient_id, "8867-4", 72.0, "/min"), reading(patient_id, "8310-5", 37.0, "Cel")];
+        let mut batch = ValidatedBatch::validate(readings, Utc::now());
+
+        batch.reject(|observation| (observation.code == "8310-5").then(|| "no temperatures".to_string()));
+
+        let heart_rate = Observation::from(batch.accepted().next().unwrap());
+        assert_eq!(heart_rate.code, "8867-4");
+        assert!(matches!(heart_rate.value, Some(ObservationValue::Quantity { value, .. }) if value == 72.0));
+        let result = batch.into_result(1);
+        assert_eq!(result.errors, vec![ItemError { index: 1, message: "no temperatures".to_string() }]);
+    }
+
+    #[test]
+    fn test_reject_unknown() {
+        let patient_id = uuid::Uuid::new_v4();
+        let stranger = uuid::Uuid::new_v4();
+        let encounter_id = uuid::Uuid::new_v4();
+        let other_encounter = uuid::Uuid::new_v4();
+
+        let mut readings = vec![
+            reading(patient_id, "8867-4", 72.0, "/min"),
+            reading(stranger, "8867-4", 72.0, "/min"),
+            reading(patient_id, "8867-4", 72.0, "/min"),
+            reading(patient_id, "8867-4", 72.0, "/min"),
+        ];
+        readings[2].encounter_id = Some(encounter_id);
+        readings[3].encounter_id = Some(other_encounter);
+        let mut batch = ValidatedBatch::validate(readings, Utc::now());
+        assert_eq!(batch.patient_ids().len(), 2);
+        assert_eq!(batch.encounter_ids().len(), 2);
+
+        let patients = HashSet::from([patient_id]);
+        let encounters = HashMap::from([(encounter_id, Some(patient_id)), (other_encounter, Some(stranger))]);
+        batch.reject_unknown(&patients, &encounters);
+
+        assert_eq!(batch.accepted().count(), 2);
+        let result = batch.into_result(1);
+        assert_eq!(result.duplicates, 1);
+        assert_eq!(result.errors.len(), 2);
+        assert_eq!(result.errors[0].index, 1);
+        assert!(result.errors[1].message.ends_with("is not the patient's"));
+    }
+
+    #[test]
+    fn test_chunks() {
+        let patient_id = uuid::Uuid::new_v4();
+        let readings = (0..5).map(|_| reading(patient_id, "8867-4", 72.0, "/min")).collect();
+        let batch = ValidatedBatch::validate(readings, Utc::now());
+
+        let chunks = batch.chunks(2);
+        let sizes: Vec<usize> = chunks.iter().map(ObservationColumns::len).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(chunks[0].codes, vec!["8867-4", "8867-4"]);
+        assert_eq!(chunks[2].device_ids, vec![Some("monitor-7".to_string())]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = IngestLimits::default();
+        assert!(limits.check().is_ok());
+        assert!(limits.check_batch(5_000).is_ok());
+        assert!(limits.check_batch(5_001).is_err());
+
+        let limits = IngestLimits {
+            chunk_size: 0,
+            ..IngestLimits::default()
+        };
+        assert!(limits.check().is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Accounting of disclosures
+//!
+//! Patients may ask who accessed their record. Every access to a patient's
+//! data that passes the API's access check is written to `audit.audit_log`
+//! under the patient's id, with the user, what was accessed, the basis for
+//! access and the purpose of use given. [`DISCLOSURES_QUERY`] reads them back
+//! for a date range, and a [`DisclosureReport`] renders them as CSV or as a
+//! printable PDF to hand to the patient.
+
+use crate::domain::ReportFormat;
+use crate::services::reporting::ReportTable;
+use crate::types::{Id, Timestamp};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Accesses to a patient's data in `[$2, $3)`, oldest first, for patient
+/// `$1`; reads are audited as `READ`, changes as `WRITE`, emergency access
+/// grants as `BREAK_GLASS`
+pub const DISCLOSURES_QUERY: &str = "SELECT a.changed_at AS accessed_at, a.user_id, \
+     COALESCE(NULLIF(concat_ws(' ', array_to_string(p.given_names, ' '), p.family_name), ''), u.username, \
+     a.changed_by) AS user_name, a.operation AS action, COALESCE(a.new_values->>'path', a.table_name) AS resource, \
+     COALESCE(a.new_values->>'basis', CASE WHEN a.table_name = 'emergency_access' THEN 'emergency_access' END) \
+     AS basis, \
+     COALESCE(a.purpose_of_use, CASE WHEN a.table_name = 'emergency_access' THEN 'emergency' END) AS purpose_of_use, \
+     a.request_id \
+     FROM audit.audit_log a \
+     LEFT JOIN emr.practitioners p ON p.id = a.user_id \
+     LEFT JOIN emr.users u ON u.id = a.user_id \
+     WHERE a.patient_id = $1 AND a.changed_at >= $2 AND a.changed_at < $3 \
+     ORDER BY a.changed_at, a.id";
+
+/// One access to a patient's data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessRecord {
+    /// When the data was accessed
+    pub accessed_at: Timestamp,
+    /// Practitioner or user who accessed it, if known
+    pub user_id: Option<Id>,
+    /// Their name, or the database role for changes made outside the API
+    pub user_name: Option<String>,
+    /// `READ`, `WRITE` or `BREAK_GLASS`
+    pub action: String,
+    /// Path or table accessed
+    pub resource: String,
+    /// Why access was allowed, e.g. `treating_relationship`
+    pub basis: Option<String>,
+    /// Purpose of use given for the access
+    pub purpose_of_use: Option<String>,
+    /// Request the access was made in
+    pub request_id: Option<String>,
+}
+
+/// Accesses to one patient's data over a date range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisclosureReport {
+    /// Patient whose data was accessed
+    pub patient_id: Id,
+    /// Start of the range
+    pub start: Timestamp,
+    /// End of the range, exclusive
+    pub end: Timestamp,
+    /// Accesses, oldest first
+    pub records: Vec<AccessRecord>,
+}
+
+impl DisclosureReport {
+    /// Columns of the rendered report
+    pub const COLUMNS: [&'static str; 7] =
+        ["accessed_at", "user", "user_id", "action", "resource", "basis", "purpose_of_use"];
+
+    /// The accesses as a table
+    pub fn to_table(&self) -> ReportTable {
+        ReportTable {
+            columns: Self::COLUMNS.iter().map(|column| column.to_string()).collect(),
+            rows: self
+                .records
+                .iter()
+                .map(|record| {
+                    vec![
+                        json!(record.accessed_at.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+                        json!(record.user_name),
+                        json!(record.user_id),
+                        json!(record.action),
+                        json!(record.resource),
+                        json!(record.basis),
+                        json!(record.purpose_of_use),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// Render as CSV or PDF
+    pub fn render(&self, format: ReportFormat) -> Vec<u8> {
+        let table = self.to_table();
+        match format {
+            ReportFormat::Csv => table.to_csv().into_bytes(),
+            ReportFormat::Pdf => table.to_pdf(
+                &format!("Accounting of disclosures for patient {}", self.patient_id),
+                &format!(
+                    "{} - {}, {} accesses",
+                    self.start.format("%d %b %Y %H:%M UTC"),
+                    self.end.format("%d %b %Y %H:%M UTC"),
+                    self.records.len()
+                ),
+            ),
+        }
+    }
+
+    /// Download file name
+    pub fn file_name(&self, format: ReportFormat) -> String {
+        format!(
+            "disclosures-{}-{}-{}.{}",
+            self.patient_id,
+            self.start.format("%Y%m%d"),
+            self.end.format("%Y%m%d"),
+            format.as_str()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn report() -> DisclosureReport {
+        let practitioner_id = uuid::Uuid::new_v4();
+        DisclosureReport {
+            patient_id: uuid::Uuid::nil(),
+            start: Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2026, 7, 1, 0, 0, 0).unwrap(),
+            records: vec![
+                AccessRecord {
+                    accessed_at: Utc.with_ymd_and_hms(2026, 2, 3, 14, 5, 0).unwrap(),
+                    user_id: Some(practitioner_id),
+                    user_name: Some("Ana Ruiz".to_string()),
+                    action: "READ".to_string(),
+                    resource: "/api/v1/documents/d1".to_string(),
+                    basis: Some("treating_relationship".to_string()),
+                    purpose_of_use: Some("treatment".to_string()),
+                    request_id: Some("req-1".to_string()),
+                },
+                AccessRecord {
+                    accessed_at: Utc.with_ymd_and_hms(2026, 3, 9, 2, 40, 0).unwrap(),
+                    user_id: Some(practitioner_id),
+                    user_name: Some("Ana Ruiz".to_string()),
+                    action: "BREAK_GLASS".to_string(),
+                    resource: "emergency_access".to_string(),
+                    basis: Some("emergency_access".to_string()),
+                    purpose_of_use: Some("emergency".to_string()),
+                    request_id: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_disclosure_csv() {
+        let csv = String::from_utf8(report().render(ReportFormat::Csv)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], "accessed_at,user,user_id,action,resource,basis,purpose_of_use");
+        assert!(lines[1].starts_with("2026-02-03 14:05:00 UTC,Ana Ruiz,"));
+        assert!(lines[1].ends_with(",READ,/api/v1/documents/d1,treating_relationship,treatment"));
+        assert!(lines[2].ends_with(",BREAK_GLASS,emergency_access,emergency_access,emergency"));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_disclosure_pdf_and_file_name() {
+        let report = report();
+        assert!(report.render(ReportFormat::Pdf).starts_with(b"%PDF-"));
+        assert_eq!(
+            report.file_name(ReportFormat::Pdf),
+            "disclosures-00000000-0000-0000-0000-000000000000-20260101-20260701.pdf"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Pharmacy formulary lookups
+//!
+//! When a medication is prescribed, the prescriber is shown how the patient's
+//! drug plan covers it: the coverage tier, whether prior authorization is
+//! needed, and covered alternatives. A [`FormularyProvider`] answers those
+//! questions; the API uses an HTTP provider for the pharmacy benefit manager
+//! and [`MockFormularyProvider`] stands in for it in development and tests.
+//! Formularies change rarely, so lookups go through
+//! [`CachedFormularyProvider`].
+
+use crate::types::Timestamp;
+use crate::Result;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// A covered medication offered in place of the one prescribed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormularyAlternative {
+    /// RxNorm code
+    pub medication_code: String,
+    /// Display name
+    pub display: Option<String>,
+    /// Coverage tier, 1 being the cheapest for the patient
+    pub tier: Option<u8>,
+}
+
+/// How a drug plan covers a medication
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormularyStatus {
+    /// RxNorm code looked up
+    pub medication_code: String,
+    /// Whether the plan covers the medication at all
+    pub covered: bool,
+    /// Coverage tier, 1 being the cheapest for the patient
+    pub tier: Option<u8>,
+    /// Whether the plan needs prior authorization before it pays
+    #[serde(default)]
+    pub prior_authorization_required: bool,
+    /// Quantity limit, as the plan states it
+    pub quantity_limit: Option<String>,
+    /// Covered alternatives, preferred first
+    #[serde(default)]
+    pub alternatives: Vec<FormularyAlternative>,
+}
+
+/// Answers formulary questions for a drug plan
+#[async_trait]
+pub trait FormularyProvider: Send + Sync {
+    /// How `plan_id` (or the provider's default formulary, without one)
+    /// covers a medication; `None` when the formulary does not list it
+    async fn lookup(&self, plan_id: Option<&str>, medication_code: &str) -> Result<Option<FormularyStatus>>;
+}
+
+/// Plan and medication a lookup is for
+type FormularyKey = (Option<String>, String);
+
+/// Formulary held in memory
+#[derive(Debug, Clone, Default)]
+pub struct MockFormularyProvider {
+    entries: HashMap<FormularyKey, FormularyStatus>,
+}
+
+impl MockFormularyProvider {
+    /// Create an empty formulary
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List a medication for a plan, or for lookups without a plan
+    pub fn with_entry(mut self, plan_id: Option<&str>, status: FormularyStatus) -> Self {
+        self.entries
+            .insert((plan_id.map(str::to_string), status.medication_code.clone()), status);
+        self
+    }
+}
+
+#[async_trait]
+impl FormularyProvider for MockFormularyProvider {
+    async fn lookup(&self, plan_id: Option<&str>, medication_code: &str) -> Result<Option<FormularyStatus>> {
+        Ok(self
+            .entries
+            .get(&(plan_id.map(str::to_string), medication_code.to_string()))
+            .cloned())
+    }
+}
+
+/// Remembers another provider's answers for a while
+///
+/// Answers that a medication is not listed are remembered too; failures are
+/// not, so the next lookup tries again.
+pub struct CachedFormularyProvider {
+    inner: Arc<dyn FormularyProvider>,
+    ttl: Duration,
+    entries: RwLock<HashMap<FormularyKey, (Timestamp, Option<FormularyStatus>)>>,
+}
+
+impl CachedFormularyProvider {
+    /// Cache `inner`'s answers for `ttl`
+    pub fn new(inner: Arc<dyn FormularyProvider>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            entries: RwLock::default(),
+        }
+    }
+
+    fn cached(&self, key: &FormularyKey, now: Timestamp) -> Option<Option<FormularyStatus>> {
+        let entries = self.entries.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries
+            .get(key)
+            .filter(|(fetched_at, _)| now - *fetched_at < self.ttl)
+            .map(|(_, status)| status.clone())
+    }
+}
+
+#[async_trait]
+impl FormularyProvider for CachedFormularyProvider {
+    async fn lookup(&self, plan_id: Option<&str>, medication_code: &str) -> Result<Option<FormularyStatus>> {
+        let key = (plan_id.map(str::to_string), medication_code.to_string());
+        let now = Utc::now();
+        if let Some(status) = self.cached(&key, now) {
+            return Ok(status);
+        }
+
+        let status = self.inner.lookup(plan_id, medication_code).await?;
+        let mut entries = self.entries.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries.retain(|_, (fetched_at, _)| now - *fetched_at < self.ttl);
+        entries.insert(key, (now, status.clone()));
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the lookups that reach the formulary
+    struct CountingProvider {
+        formulary: MockFormularyProvider,
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl FormularyProvider for CountingProvider {
+        async fn lookup(&self, plan_id: Option<&str>, medication_code: &str) -> Result<Option<FormularyStatus>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.formulary.lookup(plan_id, medication_code).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_lookups() {
+        let status = FormularyStatus {
+            medication_code: "617314".to_string(),
+            covered: true,
+            tier: Some(3),
+            prior_authorization_required: true,
+            quantity_limit: None,
+            alternatives: vec![FormularyAlternative {
+                medication_code: "259255".to_string(),
+                display: Some("atorvastatin 80 MG Oral Tablet".to_string()),
+                tier: Some(1),
+            }],
+        };
+        let counting = Arc::new(CountingProvider {
+            formulary: MockFormularyProvider::new().with_entry(Some("PLAN-A"), status.clone()),
+            lookups: AtomicUsize::new(0),
+        });
+        let cached = CachedFormularyProvider::new(counting.clone(), Duration::minutes(5));
+
+        assert_eq!(cached.lookup(Some("PLAN-A"), "617314").await.unwrap(), Some(status.clone()));
+        assert_eq!(cached.lookup(Some("PLAN-A"), "617314").await.unwrap(), Some(status));
+        assert_eq!(cached.lookup(None, "617314").await.unwrap(), None);
+        assert_eq!(cached.lookup(None, "617314").await.unwrap(), None);
+        assert_eq!(counting.lookups.load(Ordering::SeqCst), 2);
+
+        let expired = CachedFormularyProvider::new(counting.clone(), Duration::zero());
+        expired.lookup(None, "617314").await.unwrap();
+        expired.lookup(None, "617314").await.unwrap();
+        assert_eq!(counting.lookups.load(Ordering::SeqCst), 4);
+    }
+}
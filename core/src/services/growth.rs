@@ -0,0 +1,739 @@
+//! Growth reference (LMS) tables
+//!
+//! Growth references such as the CDC BMI-for-age charts publish, per sex and
+//! age in months, the L (Box-Cox power), M (median) and S (coefficient of
+//! variation) of a measurement's distribution. A measurement `x` has z-score
+//! `((x / M)^L - 1) / (L * S)`, or `ln(x / M) / S` when L is 0, and its
+//! percentile is the standard normal distribution function of the z-score.
+//!
+//! The tables are loaded from the files the references publish rather than
+//! compiled in, so updated releases need no code change. Pediatric charts
+//! follow the CDC recommendation: the WHO standards below 24 months and the
+//! CDC references from 24 months on ([`GrowthReferences`]).
+
+use crate::domain::values::AdministrativeGender;
+use crate::domain::vitals::LOINC_SYSTEM;
+use crate::domain::{Observation, ObservationStatus};
+use crate::services::calculators::{BODY_HEIGHT_CODE, BODY_WEIGHT_CODE};
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Average days per month, as the references count ages
+pub const DAYS_PER_MONTH: f64 = 365.25 / 12.0;
+
+/// Age at which charts switch from the WHO standards to the CDC references
+pub const WHO_TO_CDC_AGE_MONTHS: f64 = 24.0;
+
+/// LOINC code for body length, measured lying down
+pub const BODY_LENGTH_CODE: &str = "8306-3";
+
+/// LOINC code for body mass index
+pub const BMI_CODE: &str = "39156-5";
+
+/// Percentiles drawn on growth charts by default
+pub const CHART_PERCENTILES: [f64; 9] = [3.0, 5.0, 10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 97.0];
+
+/// Age in months on a date, with fractions; `None` before birth
+pub fn age_in_months(birth_date: NaiveDate, on: NaiveDate) -> Option<f64> {
+    let days = (on - birth_date).num_days();
+    (days >= 0).then(|| days as f64 / DAYS_PER_MONTH)
+}
+
+/// LMS parameters at one age
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LmsPoint {
+    /// Age in months
+    pub age_months: f64,
+    /// Box-Cox power
+    pub l: f64,
+    /// Median
+    pub m: f64,
+    /// Coefficient of variation
+    pub s: f64,
+}
+
+impl LmsPoint {
+    /// z-score of a measurement
+    pub fn z_score(&self, value: f64) -> f64 {
+        if self.l.abs() < 1e-12 {
+            (value / self.m).ln() / self.s
+        } else {
+            ((value / self.m).powf(self.l) - 1.0) / (self.l * self.s)
+        }
+    }
+
+    /// Measurement at a z-score, the inverse of [`LmsPoint::z_score`]
+    pub fn value_at(&self, z: f64) -> f64 {
+        if self.l.abs() < 1e-12 {
+            self.m * (self.s * z).exp()
+        } else {
+            self.m * (1.0 + self.l * self.s * z).powf(1.0 / self.l)
+        }
+    }
+}
+
+/// Position of a measurement in a reference
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GrowthAssessment {
+    /// Standard deviations from the median
+    pub z_score: f64,
+    /// Percentile, 0 to 100
+    pub percentile: f64,
+}
+
+/// LMS parameters of one measurement for both sexes, by age
+#[derive(Debug, Clone, Default)]
+pub struct LmsTable {
+    male: Vec<LmsPoint>,
+    female: Vec<LmsPoint>,
+}
+
+impl LmsTable {
+    /// Build a table from points per sex, in any order
+    pub fn new(mut male: Vec<LmsPoint>, mut female: Vec<LmsPoint>) -> Result<Self> {
+        for points in [&mut male, &mut female] {
+            if points.is_empty() {
+                return Err(Error::configuration_error("Growth references need points for both sexes"));
+            }
+            if points
+                .iter()
+                .any(|point| !(point.age_months.is_finite() && point.m > 0.0 && point.s > 0.0 && point.l.is_finite()))
+            {
+                return Err(Error::configuration_error("Growth reference has invalid LMS parameters"));
+            }
+            points.sort_by(|a, b| a.age_months.total_cmp(&b.age_months));
+        }
+        Ok(Self { male, female })
+    }
+
+    /// Parse a CDC-format CSV (`Sex,Agemos,L,M,S,...`, sex 1 male and 2 female)
+    ///
+    /// Extra columns are ignored, and so are repeated header lines, which
+    /// some of the CDC files have between the sexes.
+    pub fn from_cdc_csv(csv: &str) -> Result<Self> {
+        let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<String> = lines
+            .next()
+            .ok_or_else(|| Error::configuration_error("Growth reference file is empty"))?
+            .split(',')
+            .map(|column| column.trim().trim_matches('"').to_ascii_lowercase())
+            .collect();
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|column| column == name)
+                .ok_or_else(|| Error::configuration_error(&format!("Growth reference has no '{}' column", name)))
+        };
+        let (sex, age, l, m, s) = (column("sex")?, column("agemos")?, column("l")?, column("m")?, column("s")?);
+
+        let (mut male, mut female) = (Vec::new(), Vec::new());
+        for (number, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split(',').map(|field| field.trim().trim_matches('"')).collect();
+            let Ok(sex_code) = fields.get(sex).copied().unwrap_or_default().parse::<u8>() else {
+                continue;
+            };
+            let number_at = |index: usize| {
+                fields.get(index).and_then(|field| field.parse::<f64>().ok()).ok_or_else(|| {
+                    Error::configuration_error(&format!("Growth reference line {} is malformed", number + 2))
+                })
+            };
+            let point = LmsPoint {
+                age_months: number_at(age)?,
+                l: number_at(l)?,
+                m: number_at(m)?,
+                s: number_at(s)?,
+            };
+            match sex_code {
+                1 => male.push(point),
+                2 => female.push(point),
+                _ => {
+                    return Err(Error::configuration_error(&format!(
+                        "Growth reference line {} has unknown sex {}",
+                        number + 2,
+                        sex_code
+                    )))
+                }
+            }
+        }
+        Self::new(male, female)
+    }
+
+    /// Parse a pair of WHO-format tables, one per sex
+    ///
+    /// The WHO publishes tab-separated files and the CDC republishes the
+    /// under-2 charts as CSV; both have a `Month` (or `Day`) column and `L`,
+    /// `M` and `S` columns, which is all this reads.
+    pub fn from_who_tables(boys: &str, girls: &str) -> Result<Self> {
+        Self::new(parse_who_table(boys)?, parse_who_table(girls)?)
+    }
+
+    /// Table points for a sex, by age; empty for `other` and `unknown`
+    pub fn points(&self, gender: &AdministrativeGender) -> &[LmsPoint] {
+        match gender {
+            AdministrativeGender::Male => &self.male,
+            AdministrativeGender::Female => &self.female,
+            AdministrativeGender::Other | AdministrativeGender::Unknown => &[],
+        }
+    }
+
+    /// Ages covered, in months
+    pub fn age_range(&self) -> (f64, f64) {
+        let first = self.male[0].age_months.max(self.female[0].age_months);
+        let last = self.male[self.male.len() - 1]
+            .age_months
+            .min(self.female[self.female.len() - 1].age_months);
+        (first, last)
+    }
+
+    /// LMS parameters at an age, interpolated linearly between table ages
+    ///
+    /// `None` outside the ages covered, or for sexes the reference has no
+    /// data for (`other`, `unknown`).
+    pub fn at(&self, gender: &AdministrativeGender, age_months: f64) -> Option<LmsPoint> {
+        let points = self.points(gender);
+        let after = points.iter().position(|point| point.age_months >= age_months)?;
+        let upper = points[after];
+        if upper.age_months == age_months {
+            return Some(upper);
+        }
+        let lower = points[after.checked_sub(1)?];
+
+        let fraction = (age_months - lower.age_months) / (upper.age_months - lower.age_months);
+        let between = |a: f64, b: f64| a + (b - a) * fraction;
+        Some(LmsPoint {
+            age_months,
+            l: between(lower.l, upper.l),
+            m: between(lower.m, upper.m),
+            s: between(lower.s, upper.s),
+        })
+    }
+
+    /// z-score and percentile of a measurement
+    pub fn assess(&self, gender: &AdministrativeGender, age_months: f64, value: f64) -> Option<GrowthAssessment> {
+        if !(value.is_finite() && value > 0.0) {
+            return None;
+        }
+        let z_score = self.at(gender, age_months)?.z_score(value);
+        Some(GrowthAssessment {
+            z_score,
+            percentile: normal_cdf(z_score) * 100.0,
+        })
+    }
+}
+
+/// Fields of a tab- or comma-separated line
+fn fields(line: &str) -> Vec<&str> {
+    line.split(['\t', ',']).map(|field| field.trim().trim_matches('"')).collect()
+}
+
+/// LMS points of a single-sex WHO-format table
+fn parse_who_table(text: &str) -> Result<Vec<LmsPoint>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = fields(
+        lines
+            .next()
+            .ok_or_else(|| Error::configuration_error("Growth reference file is empty"))?,
+    )
+    .into_iter()
+    .map(str::to_ascii_lowercase)
+    .collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let (age, months_per_unit) = match (column("month"), column("day")) {
+        (Some(index), _) => (index, 1.0),
+        (None, Some(index)) => (index, 1.0 / DAYS_PER_MONTH),
+        (None, None) => return Err(Error::configuration_error("Growth reference has no 'Month' or 'Day' column")),
+    };
+    let required = |name: &str| {
+        column(name).ok_or_else(|| Error::configuration_error(&format!("Growth reference has no '{}' column", name)))
+    };
+    let (l, m, s) = (required("l")?, required("m")?, required("s")?);
+
+    lines
+        .enumerate()
+        .map(|(number, line)| {
+            let fields = fields(line);
+            let number_at = |index: usize| {
+                fields.get(index).and_then(|field| field.parse::<f64>().ok()).ok_or_else(|| {
+                    Error::configuration_error(&format!("Growth reference line {} is malformed", number + 2))
+                })
+            };
+            Ok(LmsPoint {
+                age_months: number_at(age)? * months_per_unit,
+                l: number_at(l)?,
+                m: number_at(m)?,
+                s: number_at(s)?,
+            })
+        })
+        .collect()
+}
+
+/// Measurements plotted on growth charts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GrowthMeasure {
+    /// Body weight
+    Weight,
+    /// Length below 24 months, stature after
+    Height,
+    /// Body mass index
+    Bmi,
+}
+
+impl GrowthMeasure {
+    /// Wire name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GrowthMeasure::Weight => "weight",
+            GrowthMeasure::Height => "height",
+            GrowthMeasure::Bmi => "bmi",
+        }
+    }
+
+    /// Parse a wire name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "weight" => Some(GrowthMeasure::Weight),
+            "height" => Some(GrowthMeasure::Height),
+            "bmi" => Some(GrowthMeasure::Bmi),
+            _ => None,
+        }
+    }
+
+    /// LOINC codes the measure is recorded under
+    pub fn codes(&self) -> &'static [&'static str] {
+        match self {
+            GrowthMeasure::Weight => &[BODY_WEIGHT_CODE],
+            GrowthMeasure::Height => &[BODY_HEIGHT_CODE, BODY_LENGTH_CODE],
+            GrowthMeasure::Bmi => &[BMI_CODE],
+        }
+    }
+
+    /// UCUM unit of the reference tables
+    pub fn unit(&self) -> &'static str {
+        match self {
+            GrowthMeasure::Weight => "kg",
+            GrowthMeasure::Height => "cm",
+            GrowthMeasure::Bmi => "kg/m2",
+        }
+    }
+}
+
+/// Publisher of a growth reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GrowthReference {
+    /// WHO Child Growth Standards, 0 to 24 months
+    Who,
+    /// CDC growth charts, 2 to 20 years
+    Cdc,
+}
+
+impl GrowthReference {
+    /// Wire name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GrowthReference::Who => "who",
+            GrowthReference::Cdc => "cdc",
+        }
+    }
+
+    /// The reference charts use at an age
+    pub fn for_age(age_months: f64) -> Self {
+        if age_months < WHO_TO_CDC_AGE_MONTHS {
+            GrowthReference::Who
+        } else {
+            GrowthReference::Cdc
+        }
+    }
+}
+
+/// One point of a percentile curve
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CurvePoint {
+    /// Age in months
+    pub age_months: f64,
+    /// Measurement at the curve's percentile
+    pub value: f64,
+    /// Reference the point is from
+    pub reference: GrowthReference,
+}
+
+/// A percentile line on a growth chart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PercentileCurve {
+    /// Percentile, 0 to 100
+    pub percentile: f64,
+    /// Points by age
+    pub points: Vec<CurvePoint>,
+}
+
+/// The loaded growth references, by publisher and measure
+///
+/// Each age is assessed against one reference only ([`GrowthReference::for_age`]);
+/// there is no fallback to the other publisher when a table is missing, since
+/// mixing the two on one chart misplaces children around the switch.
+#[derive(Debug, Clone, Default)]
+pub struct GrowthReferences {
+    tables: HashMap<(GrowthReference, GrowthMeasure), Arc<LmsTable>>,
+}
+
+impl GrowthReferences {
+    /// No references loaded
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a table
+    pub fn insert(&mut self, reference: GrowthReference, measure: GrowthMeasure, table: Arc<LmsTable>) {
+        self.tables.insert((reference, measure), table);
+    }
+
+    /// A loaded table
+    pub fn table(&self, reference: GrowthReference, measure: GrowthMeasure) -> Option<&Arc<LmsTable>> {
+        self.tables.get(&(reference, measure))
+    }
+
+    /// z-score and percentile of a measurement, with the reference used
+    ///
+    /// `None` when the reference for the age is not loaded or does not cover
+    /// the age or sex.
+    pub fn assess(
+        &self,
+        measure: GrowthMeasure,
+        gender: &AdministrativeGender,
+        age_months: f64,
+        value: f64,
+    ) -> Option<(GrowthReference, GrowthAssessment)> {
+        let reference = GrowthReference::for_age(age_months);
+        let assessment = self.table(reference, measure)?.assess(gender, age_months, value)?;
+        Some((reference, assessment))
+    }
+
+    /// Percentile curves between two ages, at the ages the tables list
+    pub fn curves(
+        &self,
+        measure: GrowthMeasure,
+        gender: &AdministrativeGender,
+        percentiles: &[f64],
+        from_months: f64,
+        to_months: f64,
+    ) -> Vec<PercentileCurve> {
+        let points: Vec<(GrowthReference, LmsPoint)> = [GrowthReference::Who, GrowthReference::Cdc]
+            .into_iter()
+            .filter_map(|reference| Some((reference, self.table(reference, measure)?)))
+            .flat_map(|(reference, table)| table.points(gender).iter().map(move |point| (reference, *point)))
+            .filter(|(reference, point)| GrowthReference::for_age(point.age_months) == *reference)
+            .filter(|(_, point)| (from_months..=to_months).contains(&point.age_months))
+            .collect();
+
+        percentiles
+            .iter()
+            .map(|&percentile| {
+                let z = normal_quantile(percentile / 100.0);
+                PercentileCurve {
+                    percentile,
+                    points: points
+                        .iter()
+                        .map(|(reference, point)| CurvePoint {
+                            age_months: point.age_months,
+                            value: point.value_at(z),
+                            reference: *reference,
+                        })
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// One measurement placed on a growth chart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrowthPoint {
+    /// Observations the value comes from; weight and height for a derived BMI
+    pub observation_ids: Vec<Id>,
+    /// When the measurement was taken
+    pub effective: Timestamp,
+    /// Age in months when taken
+    pub age_months: f64,
+    /// Value in the measure's unit
+    pub value: f64,
+    /// The measure's UCUM unit
+    pub unit: String,
+    /// Reference the assessment is from
+    pub reference: Option<GrowthReference>,
+    /// z-score and percentile; `None` when no loaded reference covers the point
+    pub assessment: Option<GrowthAssessment>,
+}
+
+/// Usable quantities of a measure, oldest first
+///
+/// Cancelled and entered-in-error observations are skipped, and so are those
+/// without a time or with a unit that does not convert.
+fn measurements(observations: &[Observation], measure: GrowthMeasure) -> Vec<(&Observation, Timestamp, f64)> {
+    let mut measurements: Vec<_> = observations
+        .iter()
+        .filter(|observation| {
+            let (system, code) = observation.coding();
+            system == LOINC_SYSTEM && measure.codes().contains(&code)
+        })
+        .filter(|observation| {
+            !matches!(observation.status, ObservationStatus::Cancelled | ObservationStatus::EnteredInError)
+        })
+        .filter_map(|observation| {
+            let at = observation.effective.or(observation.issued)?;
+            let value = observation.quantity_in(measure.unit()).ok().flatten()?;
+            Some((observation, at, value))
+        })
+        .collect();
+    measurements.sort_by_key(|(_, at, _)| *at);
+    measurements
+}
+
+/// A patient's growth chart points for a measure, oldest first
+///
+/// BMI comes from recorded BMI observations and, on days without one, from
+/// that day's latest weight and height. Measurements from before birth are
+/// dropped.
+pub fn growth_points(
+    references: &GrowthReferences,
+    measure: GrowthMeasure,
+    birth_date: NaiveDate,
+    gender: &AdministrativeGender,
+    observations: &[Observation],
+) -> Vec<GrowthPoint> {
+    let mut values: Vec<(Vec<Id>, Timestamp, f64)> = measurements(observations, measure)
+        .into_iter()
+        .map(|(observation, at, value)| (vec![observation.metadata.id], at, value))
+        .collect();
+
+    if measure == GrowthMeasure::Bmi {
+        let recorded: HashSet<NaiveDate> = values.iter().map(|(_, at, _)| at.date_naive()).collect();
+        let latest_by_day = |measure| -> BTreeMap<NaiveDate, (&Observation, Timestamp, f64)> {
+            measurements(observations, measure)
+                .into_iter()
+                .map(|(observation, at, value)| (at.date_naive(), (observation, at, value)))
+                .collect()
+        };
+        let heights = latest_by_day(GrowthMeasure::Height);
+        for (day, (weight, at, kg)) in latest_by_day(GrowthMeasure::Weight) {
+            if recorded.contains(&day) {
+                continue;
+            }
+            if let Some((height, _, cm)) = heights.get(&day) {
+                values.push((vec![weight.metadata.id, height.metadata.id], at, kg / (cm / 100.0).powi(2)));
+            }
+        }
+        values.sort_by_key(|(_, at, _)| *at);
+    }
+
+    values
+        .into_iter()
+        .filter_map(|(observation_ids, effective, value)| {
+            let age_months = age_in_months(birth_date, effective.date_naive())?;
+            let assessed = references.assess(measure, gender, age_months, value);
+            Some(GrowthPoint {
+                observation_ids,
+                effective,
+                age_months,
+                value,
+                unit: measure.unit().to_string(),
+                reference: assessed.map(|(reference, _)| reference),
+                assessment: assessed.map(|(_, assessment)| assessment),
+            })
+        })
+        .collect()
+}
+
+/// Standard normal distribution function
+///
+/// Uses the Abramowitz and Stegun 7.1.26 approximation of erf, accurate to
+/// about 1e-7, which is far below the precision percentiles are read at.
+pub fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let polynomial = t
+        * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - polynomial * (-x * x).exp();
+    if z >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Inverse of [`normal_cdf`]: the z-score below which a fraction `p` falls
+///
+/// Uses Acklam's rational approximation, accurate to about 1e-9. Returns an
+/// infinity for `p` of 0 or 1 and below.
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const TAIL: f64 = 0.024_25;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < TAIL {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - TAIL {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::units::UCUM_SYSTEM;
+    use crate::domain::ObservationValue;
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    /// Made-up parameters in the CDC layout; not reference data
+    const SAMPLE: &str = "Sex,Agemos,L,M,S,P50\n\
+                          1,24,-2,16,0.08,16\n\
+                          1,36,-2,15.6,0.08,15.6\n\
+                          Sex,Agemos,L,M,S,P50\n\
+                          2,24,0,16,0.1,16\n\
+                          2,36,0,15.4,0.1,15.4\n";
+
+    #[test]
+    fn test_parse_and_interpolate() {
+        let table = LmsTable::from_cdc_csv(SAMPLE).unwrap();
+        assert_eq!(table.age_range(), (24.0, 36.0));
+
+        let point = table.at(&AdministrativeGender::Male, 30.0).unwrap();
+        assert!((point.m - 15.8).abs() < 1e-9);
+        assert!(table.at(&AdministrativeGender::Male, 40.0).is_none());
+        assert!(table.at(&AdministrativeGender::Unknown, 30.0).is_none());
+    }
+
+    #[test]
+    fn test_z_scores_and_percentiles() {
+        let table = LmsTable::from_cdc_csv(SAMPLE).unwrap();
+
+        let median = table.assess(&AdministrativeGender::Female, 24.0, 16.0).unwrap();
+        assert!(median.z_score.abs() < 1e-9);
+        assert!((median.percentile - 50.0).abs() < 1e-6);
+
+        // L = 0: z = ln(x / M) / S
+        let high = table.assess(&AdministrativeGender::Female, 24.0, 16.0 * (0.2f64).exp()).unwrap();
+        assert!((high.z_score - 2.0).abs() < 1e-9);
+        assert!((high.percentile - 97.725).abs() < 0.01);
+
+        assert!((normal_cdf(-1.0) - 0.158_655).abs() < 1e-6);
+    }
+
+    /// Made-up parameters in the WHO layouts; not reference data
+    const WHO_BOYS: &str = "Month\tL\tM\tS\n0\t1\t3.3\t0.14\n12\t1\t9.6\t0.11\n";
+    const WHO_GIRLS: &str = "Day,L,M,S\n0,1,3.2,0.14\n365.25,1,8.9,0.12\n";
+
+    fn quantity(code: &str, value: f64, unit: &str, effective: Timestamp) -> Observation {
+        let mut observation = Observation::new(ObservationStatus::Final, code.to_string(), Uuid::new_v4());
+        observation.effective = Some(effective);
+        observation.value = Some(ObservationValue::Quantity {
+            value,
+            unit: unit.to_string(),
+            system: Some(UCUM_SYSTEM.to_string()),
+            code: Some(unit.to_string()),
+        });
+        observation
+    }
+
+    #[test]
+    fn test_who_tables_and_curves() {
+        let who = LmsTable::from_who_tables(WHO_BOYS, WHO_GIRLS).unwrap();
+        assert_eq!(who.age_range(), (0.0, 12.0));
+        let point = who.at(&AdministrativeGender::Female, 12.0).unwrap();
+        assert!((point.value_at(point.z_score(10.0)) - 10.0).abs() < 1e-9);
+        assert!((normal_quantile(normal_cdf(1.5)) - 1.5).abs() < 1e-6);
+
+        let mut references = GrowthReferences::new();
+        references.insert(GrowthReference::Who, GrowthMeasure::Weight, Arc::new(who));
+        references.insert(
+            GrowthReference::Cdc,
+            GrowthMeasure::Weight,
+            Arc::new(LmsTable::from_cdc_csv(SAMPLE).unwrap()),
+        );
+
+        let curves = references.curves(GrowthMeasure::Weight, &AdministrativeGender::Male, &[50.0, 97.0], 0.0, 30.0);
+        let median: Vec<(f64, GrowthReference)> =
+            curves[0].points.iter().map(|point| (point.value, point.reference)).collect();
+        assert_eq!(
+            median,
+            vec![(3.3, GrowthReference::Who), (9.6, GrowthReference::Who), (16.0, GrowthReference::Cdc)]
+        );
+        assert!(curves[1].points[0].value > 3.3);
+
+        let (reference, _) = references.assess(GrowthMeasure::Weight, &AdministrativeGender::Male, 6.0, 7.0).unwrap();
+        assert_eq!(reference, GrowthReference::Who);
+    }
+
+    #[test]
+    fn test_growth_points_derive_bmi() {
+        let mut references = GrowthReferences::new();
+        references.insert(GrowthReference::Cdc, GrowthMeasure::Bmi, Arc::new(LmsTable::from_cdc_csv(SAMPLE).unwrap()));
+        let birth_date = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        let morning = Utc.with_ymd_and_hms(2024, 7, 1, 9, 0, 0).unwrap();
+        let observations = vec![
+            quantity(BODY_WEIGHT_CODE, 12.0, "kg", morning),
+            quantity(BODY_HEIGHT_CODE, 90.0, "cm", morning),
+            // A weight without a height that day gives no BMI
+            quantity(BODY_WEIGHT_CODE, 12.5, "kg", Utc.with_ymd_and_hms(2024, 8, 1, 9, 0, 0).unwrap()),
+        ];
+
+        let female = AdministrativeGender::Female;
+        let points = growth_points(&references, GrowthMeasure::Bmi, birth_date, &female, &observations);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].observation_ids.len(), 2);
+        assert!((points[0].value - 12.0 / 0.81).abs() < 1e-9);
+        assert_eq!(points[0].reference, Some(GrowthReference::Cdc));
+        assert!(points[0].assessment.unwrap().percentile < 50.0);
+
+        let weights = growth_points(&references, GrowthMeasure::Weight, birth_date, &female, &observations);
+        assert_eq!(weights.len(), 2);
+        assert!(weights.iter().all(|point| point.assessment.is_none()));
+    }
+}
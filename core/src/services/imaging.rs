@@ -0,0 +1,122 @@
+//! Imaging archive lookups
+//!
+//! Imaging studies are read from the PACS when a patient's chart is opened;
+//! nothing is copied into the EMR. An [`ImagingArchive`] finds a patient's
+//! studies by the patient ID the PACS knows them under, usually the MRN. The
+//! API queries the PACS over DICOMweb (QIDO-RS for searches, WADO-RS for a
+//! study's instance metadata); [`MockImagingArchive`] stands in for it in
+//! development and tests.
+
+use crate::domain::imaging::tags;
+use crate::domain::ImagingStudy;
+use crate::types::Id;
+use crate::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+
+/// Finds a patient's imaging studies
+#[async_trait]
+pub trait ImagingArchive: Send + Sync {
+    /// Studies held under `pacs_patient_id`, attributed to `patient_id`; the
+    /// series may be left out
+    async fn search_studies(&self, patient_id: Id, pacs_patient_id: &str) -> Result<Vec<ImagingStudy>>;
+
+    /// A study held under `pacs_patient_id` with its series; `None` when the
+    /// archive has no such study for the patient
+    async fn find_study(&self, patient_id: Id, pacs_patient_id: &str, study_uid: &str) -> Result<Option<ImagingStudy>>;
+}
+
+/// Archive of DICOM JSON instance metadata held in memory
+#[derive(Debug, Clone, Default)]
+pub struct MockImagingArchive {
+    instances: Vec<Value>,
+}
+
+impl MockImagingArchive {
+    /// Create an empty archive
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold an instance's metadata, which carries its patient, study and
+    /// series attributes
+    pub fn with_instance(mut self, instance: Value) -> Self {
+        self.instances.push(instance);
+        self
+    }
+
+    /// The patient's instances grouped by study, in the order first held
+    fn studies_of(&self, pacs_patient_id: &str) -> Vec<Vec<Value>> {
+        let mut studies: Vec<(String, Vec<Value>)> = Vec::new();
+        for instance in &self.instances {
+            if attribute(instance, tags::PATIENT_ID) != Some(pacs_patient_id) {
+                continue;
+            }
+            let Some(study_uid) = attribute(instance, tags::STUDY_INSTANCE_UID) else {
+                continue;
+            };
+            match studies.iter_mut().find(|(uid, _)| uid == study_uid) {
+                Some((_, instances)) => instances.push(instance.clone()),
+                None => studies.push((study_uid.to_string(), vec![instance.clone()])),
+            }
+        }
+        studies.into_iter().map(|(_, instances)| instances).collect()
+    }
+}
+
+/// First value of an attribute of a DICOM JSON dataset
+fn attribute<'a>(dataset: &'a Value, tag: &str) -> Option<&'a str> {
+    dataset.get(tag)?.get("Value")?.get(0)?.as_str()
+}
+
+#[async_trait]
+impl ImagingArchive for MockImagingArchive {
+    async fn search_studies(&self, patient_id: Id, pacs_patient_id: &str) -> Result<Vec<ImagingStudy>> {
+        self.studies_of(pacs_patient_id)
+            .iter()
+            .map(|instances| ImagingStudy::from_dicom_json(patient_id, &instances[0])?.with_instances(instances))
+            .collect()
+    }
+
+    async fn find_study(&self, patient_id: Id, pacs_patient_id: &str, study_uid: &str) -> Result<Option<ImagingStudy>> {
+        Ok(self
+            .search_studies(patient_id, pacs_patient_id)
+            .await?
+            .into_iter()
+            .find(|study| study.study_instance_uid == study_uid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn instance(patient: &str, study_uid: &str, series_uid: &str) -> Value {
+        json!({
+            "00100020": {"vr": "LO", "Value": [patient]},
+            "0020000D": {"vr": "UI", "Value": [study_uid]},
+            "0020000E": {"vr": "UI", "Value": [series_uid]},
+            "00080060": {"vr": "CS", "Value": ["MR"]}
+        })
+    }
+
+    #[tokio::test]
+    async fn test_mock_archive() {
+        let archive = MockImagingArchive::new()
+            .with_instance(instance("MRN-1", "1.2.1", "1.2.1.1"))
+            .with_instance(instance("MRN-1", "1.2.1", "1.2.1.1"))
+            .with_instance(instance("MRN-1", "1.2.2", "1.2.2.1"))
+            .with_instance(instance("MRN-2", "1.2.3", "1.2.3.1"));
+        let patient_id = Uuid::new_v4();
+
+        let studies = archive.search_studies(patient_id, "MRN-1").await.unwrap();
+        assert_eq!(studies.len(), 2);
+        assert_eq!((studies[0].number_of_instances, studies[0].patient_id), (2, patient_id));
+        assert_eq!(studies[0].modalities, vec!["MR"]);
+
+        assert!(archive.find_study(patient_id, "MRN-1", "1.2.2").await.unwrap().is_some());
+        assert!(archive.find_study(patient_id, "MRN-1", "1.2.3").await.unwrap().is_none());
+    }
+}
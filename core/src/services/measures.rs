@@ -0,0 +1,109 @@
+//! Quality measure evaluation
+//!
+//! A measure's populations are compiled into one parameterized query,
+//! reusing the panel criteria SQL (see [`crate::services::panels`]) with the
+//! end of the reporting period as the reference time. It returns one
+//! `patient_id`, `population` row per patient and population they fall in,
+//! which the jobs worker stores with the measure report for drill-down and
+//! counts for its totals. Each population is taken from within the one
+//! before it; exclusions are patients of the denominator meeting any
+//! exclusion criterion, and are left out of the numerator.
+
+use crate::domain::measure::{MeasurePopulation, QualityMeasure};
+use crate::services::panels::{PanelParam, PanelQuery};
+use crate::types::Timestamp;
+
+/// Compile a validated measure into its population query for the reporting
+/// period ending at `period_end`
+pub fn population_query(measure: &QualityMeasure, period_end: Timestamp) -> PanelQuery {
+    let mut query = PanelQuery::new();
+    let reference = query.bind(PanelParam::Timestamp(period_end));
+
+    let initial_population = query.conditions(&measure.initial_population, &reference);
+    let denominator = query.conditions(&measure.denominator, &reference);
+    let exclusions = if measure.denominator_exclusions.is_empty() {
+        "FALSE".to_string()
+    } else {
+        let any: Vec<String> = measure
+            .denominator_exclusions
+            .iter()
+            .map(|criterion| format!("({})", query.conditions(std::slice::from_ref(criterion), &reference)))
+            .collect();
+        any.join(" OR ")
+    };
+    let numerator = query.conditions(&measure.numerator, &reference);
+
+    let populations: Vec<String> = [
+        ("ip", MeasurePopulation::InitialPopulation),
+        ("denom", MeasurePopulation::Denominator),
+        ("excl", MeasurePopulation::DenominatorExclusion),
+        ("numer", MeasurePopulation::Numerator),
+    ]
+    .iter()
+    .map(|(table, population)| {
+        format!("SELECT id AS patient_id, '{}' AS population FROM {}", population.as_str(), table)
+    })
+    .collect();
+
+    query.sql = format!(
+        "WITH ip AS (SELECT p.id FROM emr.patients p WHERE p.active AND {}), \
+         denom AS (SELECT p.id FROM emr.patients p JOIN ip ON ip.id = p.id WHERE {}), \
+         excl AS (SELECT p.id FROM emr.patients p JOIN denom ON denom.id = p.id WHERE {}), \
+         numer AS (SELECT p.id FROM emr.patients p JOIN denom ON denom.id = p.id \
+         WHERE NOT EXISTS (SELECT 1 FROM excl WHERE excl.id = p.id) AND {}) \
+         {}",
+        initial_population,
+        denominator,
+        exclusions,
+        numerator,
+        populations.join(" UNION ALL ")
+    );
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::panel::{Comparator, PanelCriterion};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_population_query() {
+        let period_end = Utc.with_ymd_and_hms(2024, 12, 31, 23, 59, 59).unwrap();
+        let mut measure = QualityMeasure::new(
+            "CMS122v12",
+            "Diabetes: HbA1c poor control",
+            vec![
+                PanelCriterion::Condition {
+                    code: "E11".to_string(),
+                    within_days: None,
+                },
+                PanelCriterion::SeenWithin { days: 365 },
+            ],
+            vec![PanelCriterion::Observation {
+                code: "4548-4".to_string(),
+                comparator: Comparator::Gt,
+                value: 9.0,
+                within_days: Some(365),
+            }],
+        );
+        measure.denominator_exclusions = vec![
+            PanelCriterion::Condition {
+                code: "Z51.5".to_string(),
+                within_days: Some(365),
+            },
+            PanelCriterion::Age { min: Some(76), max: None },
+        ];
+
+        let query = population_query(&measure, period_end);
+
+        assert_eq!(query.params[0], PanelParam::Timestamp(period_end));
+        assert!(query.sql.starts_with("WITH ip AS (SELECT p.id FROM emr.patients p WHERE p.active AND EXISTS"));
+        assert!(query.sql.contains("c.code LIKE $2 AND c.coded_at <= $1)"));
+        assert!(query.sql.contains("JOIN ip ON ip.id = p.id WHERE TRUE)"));
+        assert!(query.sql.contains(") OR ((p.birth_date <= ($1)::date - make_interval(years => $6))))"));
+        assert!(query.sql.contains("o.effective_date >= $1 - make_interval(days => $8)"));
+        assert!(query.sql.ends_with("SELECT id AS patient_id, 'numerator' AS population FROM numer"));
+        assert_eq!(query.params.len(), 9);
+    }
+}
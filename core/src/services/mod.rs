@@ -1,5 +1,24 @@
 //! Domain services
 
+pub mod barcode;
+pub mod calculators;
+pub mod coding;
+pub mod consent;
+pub mod device_observations;
+pub mod disclosures;
+pub mod formulary;
+pub mod growth;
+pub mod imaging;
+pub mod malware;
+pub mod measures;
+pub mod ocr;
+pub mod panels;
+pub mod patient_summary;
+pub mod reporting;
+pub mod search;
+pub mod security_events;
+pub mod throttle;
+
 use crate::domain::*;
 use crate::types::Id;
 use crate::Result;
@@ -106,15 +125,25 @@ pub trait FhirService {
 /// Patient demographics summary
 #[derive(Debug, Clone)]
 pub struct PatientDemographics {
+    /// Patient identifier
     pub id: Id,
+    /// Display name
     pub name: String,
+    /// Administrative gender
     pub gender: Option<String>,
+    /// Date of birth
     pub birth_date: Option<chrono::NaiveDate>,
+    /// Age in whole years
     pub age: Option<u32>,
+    /// Primary address, formatted
     pub address: Option<String>,
+    /// Primary phone number
     pub phone: Option<String>,
+    /// Primary email address
     pub email: Option<String>,
+    /// Medical record number
     pub mrn: Option<String>,
+    /// Whether the record is active
     pub active: bool,
 }
 
@@ -140,14 +169,23 @@ pub trait AuditService {
 /// Audit event
 #[derive(Debug, Clone)]
 pub struct AuditEvent {
+    /// Identifier of the entry
     pub id: Id,
+    /// When the event happened
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Kind of event
     pub event_type: String,
+    /// Type of the entity affected
     pub entity_type: String,
+    /// Identifier of the entity affected
     pub entity_id: Id,
+    /// User who caused the event
     pub user_id: Id,
+    /// Description of the change
     pub changes: Option<String>,
+    /// Client IP address
     pub ip_address: Option<String>,
+    /// Client user agent
     pub user_agent: Option<String>,
 }
 
@@ -165,21 +203,57 @@ pub trait SecurityService {
     
     /// Validate SMART on FHIR scope
     async fn validate_smart_scope(&self, scope: &str, resource_type: &str, resource_id: Id) -> Result<bool>;
+
+    /// Check if a practitioner is on one of the patient's active care teams
+    async fn has_treating_relationship(&self, practitioner_id: Id, patient_id: Id) -> Result<bool>;
+
+    /// Decide how, if at all, a practitioner may access a patient's data
+    async fn check_patient_access(&self, practitioner_id: Id, patient_id: Id) -> Result<PatientAccess>;
+
+    /// Grant a practitioner without a treating relationship emergency access
+    async fn break_the_glass(&self, practitioner_id: Id, patient_id: Id, reason: &str) -> Result<EmergencyAccess>;
+}
+
+/// Outcome of a patient data access check
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "basis", rename_all = "snake_case")]
+pub enum PatientAccess {
+    /// The practitioner is on one of the patient's care teams
+    TreatingRelationship,
+    /// The practitioner broke the glass and the grant is still active
+    EmergencyAccess {
+        /// Emergency access grant relied on
+        grant_id: Id,
+        /// When the grant lapses
+        expires_at: chrono::DateTime<chrono::Utc>,
+    },
+    /// No basis for access
+    Denied,
+}
+
+impl PatientAccess {
+    /// Whether access is allowed
+    pub fn is_allowed(&self) -> bool {
+        !matches!(self, PatientAccess::Denied)
+    }
 }
 
 /// Permission definition
 #[derive(Debug, Clone)]
 pub struct Permission {
+    /// Resource type being accessed
     pub resource_type: String,
+    /// Specific resource, if any
     pub resource_id: Option<Id>,
+    /// Action being performed
     pub action: String,
+    /// Scope the request carries
     pub scope: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::values::*;
 
     #[test]
     fn test_patient_demographics_creation() {
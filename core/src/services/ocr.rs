@@ -0,0 +1,81 @@
+//! Text extraction from uploaded documents
+//!
+//! Once a document has been scanned clean, the jobs worker extracts its text
+//! so documents can be searched by content. A [`TextExtractor`] turns a
+//! document's bytes into plain text; scanned PDFs and photographed pages need
+//! OCR, which the worker runs through Apache Tika or the Tesseract command
+//! line. Plain-text uploads are read as they are by [`PlainTextExtractor`].
+//! Extracted text is tidied with [`normalize_extracted_text`] before it is
+//! stored and indexed.
+
+use crate::Result;
+use async_trait::async_trait;
+
+/// Turns a document's content into plain text
+#[async_trait]
+pub trait TextExtractor: Send + Sync {
+    /// Name recorded with the extracted text, e.g. `tesseract`
+    fn name(&self) -> &str;
+
+    /// Whether documents of this content type can be read
+    fn supports(&self, content_type: &str) -> bool;
+
+    /// The document's text; empty when it has none, e.g. a blank page
+    async fn extract(&self, data: &[u8], content_type: &str) -> Result<String>;
+}
+
+/// Reads `text/plain` documents as they are
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainTextExtractor;
+
+#[async_trait]
+impl TextExtractor for PlainTextExtractor {
+    fn name(&self) -> &str {
+        "plain"
+    }
+
+    fn supports(&self, content_type: &str) -> bool {
+        content_type == "text/plain"
+    }
+
+    async fn extract(&self, data: &[u8], _content_type: &str) -> Result<String> {
+        Ok(String::from_utf8_lossy(data).into_owned())
+    }
+}
+
+/// Tidy extracted text for storage
+///
+/// Runs of spaces and tabs become one space, lines are trimmed, blank lines
+/// and NULs (which Postgres text cannot hold) are dropped, and the text is
+/// cut to at most `max_chars` characters. OCR output is full of ragged
+/// whitespace that would otherwise bloat the stored text.
+pub fn normalize_extracted_text(text: &str, max_chars: usize) -> String {
+    let normalized = text
+        .lines()
+        .map(|line| line.replace('\0', "").split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    normalized.chars().take(max_chars).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_extracted_text() {
+        let raw = "  Discharge   summary\t\n\n\nPatient  was\0 seen \r\n   \n";
+        assert_eq!(normalize_extracted_text(raw, 100), "Discharge summary\nPatient was seen");
+        assert_eq!(normalize_extracted_text(raw, 20), "Discharge summary\nPa");
+        assert_eq!(normalize_extracted_text(" \n\t\n", 100), "");
+    }
+
+    #[tokio::test]
+    async fn test_plain_text_extractor() {
+        let extractor = PlainTextExtractor;
+        assert!(extractor.supports("text/plain"));
+        assert!(!extractor.supports("application/pdf"));
+        assert_eq!(extractor.extract(b"Referral letter", "text/plain").await.unwrap(), "Referral letter");
+    }
+}
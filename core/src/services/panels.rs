@@ -0,0 +1,186 @@
+//! Patient panel evaluation
+//!
+//! A panel's criteria are compiled into one parameterized query over the
+//! `emr` schema that returns the ids of the active patients meeting all of
+//! them. The API runs it when a panel is evaluated on demand and the jobs
+//! worker when it refreshes nightly panels, binding [`PanelQuery::params`]
+//! in order. Diagnoses are read from the codes recorded on encounters and
+//! observation criteria look at the most recent numeric result only.
+//! Criteria are evaluated as of a reference time: now for panels, the end of
+//! the reporting period for quality measures. Look-back windows count back
+//! from it and records dated after it are ignored.
+
+use crate::domain::panel::PanelCriterion;
+use crate::types::Timestamp;
+
+/// Value bound to a panel query placeholder
+#[derive(Debug, Clone, PartialEq)]
+pub enum PanelParam {
+    /// Bound as `text`
+    Text(String),
+    /// Bound as `integer`
+    Integer(i32),
+    /// Bound as `double precision`
+    Double(f64),
+    /// Bound as `timestamptz`
+    Timestamp(Timestamp),
+}
+
+/// Query returning the `id`s of a panel's members
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanelQuery {
+    /// SQL with `$1`, `$2`, ... placeholders
+    pub sql: String,
+    /// Values of the placeholders, in order
+    pub params: Vec<PanelParam>,
+}
+
+impl PanelQuery {
+    /// An empty query, to add bound conditions to
+    pub(crate) fn new() -> Self {
+        Self {
+            sql: String::new(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Add a parameter, returning its placeholder
+    pub(crate) fn bind(&mut self, param: PanelParam) -> String {
+        self.params.push(param);
+        format!("${}", self.params.len())
+    }
+
+    /// SQL condition on patient `p` meeting all of `criteria` as of
+    /// `reference`, a timestamp expression; `TRUE` when there are none
+    pub(crate) fn conditions(&mut self, criteria: &[PanelCriterion], reference: &str) -> String {
+        if criteria.is_empty() {
+            return "TRUE".to_string();
+        }
+        let conditions: Vec<String> = criteria.iter().map(|criterion| condition(criterion, self, reference)).collect();
+        conditions.join(" AND ")
+    }
+}
+
+/// Compile validated criteria into a member query
+pub fn member_query(criteria: &[PanelCriterion]) -> PanelQuery {
+    let mut query = PanelQuery::new();
+    let conditions = query.conditions(criteria, "NOW()");
+    query.sql = format!("SELECT p.id FROM emr.patients p WHERE p.active AND {}", conditions);
+    query
+}
+
+fn condition(criterion: &PanelCriterion, query: &mut PanelQuery, reference: &str) -> String {
+    match criterion {
+        PanelCriterion::Condition { code, within_days } => {
+            let prefix = query.bind(PanelParam::Text(format!("{}%", code.to_uppercase())));
+            let recent = within_days
+                .map(|days| window("c.coded_at", reference, query.bind(int_param(days))))
+                .unwrap_or_default();
+            format!(
+                "EXISTS (SELECT 1 FROM emr.encounter_codes c WHERE c.patient_id = p.id \
+                 AND c.kind = 'diagnosis' AND c.code LIKE {} AND c.coded_at <= {}{})",
+                prefix, reference, recent
+            )
+        }
+        PanelCriterion::Observation {
+            code,
+            comparator,
+            value,
+            within_days,
+        } => {
+            let code = query.bind(PanelParam::Text(code.clone()));
+            let recent = within_days
+                .map(|days| window("o.effective_date", reference, query.bind(int_param(days))))
+                .unwrap_or_default();
+            let value = query.bind(PanelParam::Double(*value));
+            format!(
+                "(SELECT o.value_quantity_value FROM emr.observations o WHERE o.patient_id = p.id \
+                 AND o.code = {} AND o.status IN ('final', 'amended', 'corrected') \
+                 AND o.value_quantity_value IS NOT NULL AND o.effective_date <= {}{} \
+                 ORDER BY o.effective_date DESC LIMIT 1) {} {}",
+                code,
+                reference,
+                recent,
+                comparator.as_sql(),
+                value
+            )
+        }
+        PanelCriterion::Age { min, max } => {
+            let mut bounds = Vec::new();
+            if let Some(min) = min {
+                let years = query.bind(int_param(*min));
+                bounds.push(format!("p.birth_date <= ({})::date - make_interval(years => {})", reference, years));
+            }
+            if let Some(max) = max {
+                let years = query.bind(int_param(max.saturating_add(1)));
+                bounds.push(format!("p.birth_date > ({})::date - make_interval(years => {})", reference, years));
+            }
+            format!("({})", bounds.join(" AND "))
+        }
+        PanelCriterion::Gender { gender } => {
+            format!("lower(p.gender) = {}", query.bind(PanelParam::Text(gender.to_lowercase())))
+        }
+        // Orders completed or stopped without an end date ended when last changed
+        PanelCriterion::Medication { code } => format!(
+            "EXISTS (SELECT 1 FROM emr.medication_requests m WHERE m.patient_id = p.id \
+             AND m.medication_code = {code} AND m.status IN ('active', 'completed', 'stopped') \
+             AND m.start_at <= {reference} AND COALESCE(m.end_at, \
+             CASE WHEN m.status = 'active' THEN 'infinity' ELSE m.updated_at END) > {reference})",
+            code = query.bind(PanelParam::Text(code.clone())),
+            reference = reference
+        ),
+        PanelCriterion::SeenWithin { days } => format!(
+            "EXISTS (SELECT 1 FROM emr.encounters e WHERE e.patient_id = p.id AND e.start_date <= {}{})",
+            reference,
+            window("e.start_date", reference, query.bind(int_param(*days)))
+        ),
+    }
+}
+
+/// Condition keeping `column` within `days` before `reference`
+fn window(column: &str, reference: &str, days: String) -> String {
+    format!(" AND {} >= {} - make_interval(days => {})", column, reference, days)
+}
+
+fn int_param(value: u32) -> PanelParam {
+    PanelParam::Integer(i32::try_from(value).unwrap_or(i32::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::panel::Comparator;
+
+    #[test]
+    fn test_member_query() {
+        let query = member_query(&[
+            PanelCriterion::Condition {
+                code: "e11".to_string(),
+                within_days: None,
+            },
+            PanelCriterion::Observation {
+                code: "4548-4".to_string(),
+                comparator: Comparator::Gt,
+                value: 9.0,
+                within_days: Some(180),
+            },
+            PanelCriterion::Age { min: Some(18), max: None },
+        ]);
+
+        assert!(query.sql.starts_with("SELECT p.id FROM emr.patients p WHERE p.active AND EXISTS"));
+        assert!(query.sql.contains("c.code LIKE $1 AND c.coded_at <= NOW())"));
+        assert!(query.sql.contains("o.effective_date >= NOW() - make_interval(days => $3)"));
+        assert!(query.sql.contains("LIMIT 1) > $4"));
+        assert!(query.sql.ends_with("(p.birth_date <= (NOW())::date - make_interval(years => $5))"));
+        assert_eq!(
+            query.params,
+            vec![
+                PanelParam::Text("E11%".to_string()),
+                PanelParam::Text("4548-4".to_string()),
+                PanelParam::Integer(180),
+                PanelParam::Double(9.0),
+                PanelParam::Integer(18),
+            ]
+        );
+    }
+}
@@ -0,0 +1,511 @@
+//! Patient summary projection
+//!
+//! The patient detail page opens with the latest vital signs, the active
+//! problems, the active medication orders and the next appointment. Rather
+//! than read them from four places on every view, each patient has a
+//! [`PatientSummary`] stored in `emr.patient_summaries`, updated as the
+//! records behind it change: handlers raise a [`SummaryEvent`] for each
+//! change and [`PatientSummary::apply`] folds it into the stored summary,
+//! which `GET /patients/{id}/summary` reads back in one query.
+//!
+//! Applying an event twice, or an older event after a newer one, leaves the
+//! same summary: vital signs keep the latest effective time per code,
+//! orders and appointments are replaced by id, and an encounter's coded
+//! diagnoses replace the ones coded for it before. The EMR keeps no problem
+//! list yet, so the diagnoses coded at the patient's encounters within
+//! [`PROBLEM_LOOKBACK_DAYS`] stand in for the active problems.
+//!
+//! A summary only reflects changes made since its table was created;
+//! patients whose records have not changed since have an empty one.
+
+use crate::domain::{
+    CodeKind, Encounter, EncounterClass, EncounterCoding, EncounterStatus, MedicationRequest,
+    MedicationRequestStatus, Observation, ObservationStatus, ObservationValue, VitalSign,
+};
+use crate::services::device_observations::DeviceObservation;
+use crate::types::{Id, Timestamp};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Days a coded diagnosis counts as an active problem
+pub const PROBLEM_LOOKBACK_DAYS: i64 = 365;
+
+/// Give `$1` an empty summary (`$2`) unless it has one
+pub const CREATE_SUMMARY_QUERY: &str = "INSERT INTO emr.patient_summaries (patient_id, summary, updated_at) \
+     VALUES ($1, $2::jsonb, NOW()) ON CONFLICT (patient_id) DO NOTHING";
+
+/// The summary of `$1`, locked until the transaction ends
+pub const LOCK_SUMMARY_QUERY: &str =
+    "SELECT summary::text AS summary FROM emr.patient_summaries WHERE patient_id = $1 FOR UPDATE";
+
+/// Replace the summary of `$1` with `$2`
+pub const SAVE_SUMMARY_QUERY: &str =
+    "UPDATE emr.patient_summaries SET summary = $2::jsonb, updated_at = NOW() WHERE patient_id = $1";
+
+/// The summary of `$1`
+pub const SUMMARY_QUERY: &str = "SELECT summary::text AS summary FROM emr.patient_summaries WHERE patient_id = $1";
+
+/// Latest measurement of a vital sign
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VitalReading {
+    /// Observation holding the measurement
+    pub observation_id: Id,
+    /// LOINC code
+    pub code: String,
+    /// Name of the measurement
+    pub display: String,
+    /// Value in `unit`
+    pub value: f64,
+    /// UCUM unit
+    pub unit: String,
+    /// When the measurement was taken
+    pub effective: Timestamp,
+}
+
+/// A diagnosis coded at one of the patient's encounters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProblemSummary {
+    /// ICD-10-CM code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// Encounter it was coded at
+    pub encounter_id: Id,
+    /// When the encounter was coded
+    pub recorded: Timestamp,
+}
+
+/// An active or paused medication order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MedicationSummary {
+    /// Medication order
+    pub request_id: Id,
+    /// RxNorm or local medication code
+    pub code: String,
+    /// Display text
+    pub display: Option<String>,
+    /// Dose per administration
+    pub dose_quantity: f64,
+    /// UCUM unit of the dose
+    pub dose_unit: String,
+    /// Hours between scheduled doses
+    pub frequency_hours: Option<u32>,
+    /// Given as needed rather than on schedule
+    pub as_needed: bool,
+    /// Order status
+    pub status: MedicationRequestStatus,
+    /// First dose
+    pub start: Timestamp,
+}
+
+/// A planned encounter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppointmentSummary {
+    /// Planned encounter
+    pub encounter_id: Id,
+    /// Scheduled start
+    pub start: Timestamp,
+    /// Encounter class
+    pub class: EncounterClass,
+    /// Encounter type
+    pub encounter_type: Option<String>,
+    /// Reasons given when it was scheduled
+    pub reason: Vec<String>,
+}
+
+/// A change to the records a summary is built from
+#[derive(Debug, Clone)]
+pub enum SummaryEvent {
+    /// A vital sign was measured, or its observation changed
+    VitalRecorded(VitalReading),
+    /// A vital sign observation was cancelled or entered in error
+    VitalWithdrawn {
+        /// Withdrawn observation
+        observation_id: Id,
+    },
+    /// A medication order was placed or changed status
+    MedicationChanged(MedicationSummary),
+    /// An encounter was coded; its diagnoses replace any coded before
+    EncounterCoded {
+        /// Coded encounter
+        encounter_id: Id,
+        /// Its diagnoses
+        diagnoses: Vec<ProblemSummary>,
+    },
+    /// An encounter was scheduled or changed; `None` once it is no longer
+    /// planned
+    EncounterChanged {
+        /// Changed encounter
+        encounter_id: Id,
+        /// The appointment it stands for
+        appointment: Option<AppointmentSummary>,
+    },
+}
+
+impl SummaryEvent {
+    /// Event for a stored observation, if it is a vital sign measurement
+    pub fn from_observation(observation: &Observation) -> Option<Self> {
+        let vital_sign = VitalSign::from_loinc_code(&observation.code)?;
+        if matches!(
+            observation.status,
+            ObservationStatus::Cancelled | ObservationStatus::EnteredInError
+        ) {
+            return Some(SummaryEvent::VitalWithdrawn {
+                observation_id: observation.metadata.id,
+            });
+        }
+        let Some(ObservationValue::Quantity { value, unit, .. }) = &observation.value else {
+            return None;
+        };
+
+        Some(SummaryEvent::VitalRecorded(VitalReading {
+            observation_id: observation.metadata.id,
+            code: observation.code.clone(),
+            display: vital_sign.display().to_string(),
+            value: *value,
+            unit: unit.clone(),
+            effective: observation.effective.unwrap_or(observation.metadata.created_at),
+        }))
+    }
+
+    /// Event for a stored device reading, if it is a vital sign
+    pub fn from_device_observation(observation: &DeviceObservation) -> Option<Self> {
+        let vital_sign = VitalSign::from_loinc_code(&observation.code)?;
+        Some(SummaryEvent::VitalRecorded(VitalReading {
+            observation_id: observation.id,
+            code: observation.code.clone(),
+            display: vital_sign.display().to_string(),
+            value: observation.value,
+            unit: observation.unit.clone(),
+            effective: observation.effective,
+        }))
+    }
+
+    /// Event for a stored medication order
+    pub fn from_medication_request(order: &MedicationRequest) -> Self {
+        SummaryEvent::MedicationChanged(MedicationSummary {
+            request_id: order.metadata.id,
+            code: order.medication_code.clone(),
+            display: order.medication_display.clone(),
+            dose_quantity: order.dose_quantity,
+            dose_unit: order.dose_unit.clone(),
+            frequency_hours: order.frequency_hours,
+            as_needed: order.as_needed,
+            status: order.status,
+            start: order.start,
+        })
+    }
+
+    /// Event for an encounter's finalized codes
+    pub fn from_coding(coding: &EncounterCoding) -> Self {
+        SummaryEvent::EncounterCoded {
+            encounter_id: coding.encounter_id,
+            diagnoses: coding
+                .of_kind(CodeKind::Diagnosis)
+                .map(|code| ProblemSummary {
+                    code: code.code.clone(),
+                    display: code.display.clone(),
+                    encounter_id: coding.encounter_id,
+                    recorded: coding.coded_at,
+                })
+                .collect(),
+        }
+    }
+
+    /// Event for a stored encounter; planned encounters with a start are
+    /// appointments
+    pub fn from_encounter(encounter: &Encounter) -> Self {
+        let start = encounter.period.as_ref().and_then(|period| period.start);
+        let appointment = match (&encounter.status, start) {
+            (EncounterStatus::Planned, Some(start)) => Some(AppointmentSummary {
+                encounter_id: encounter.metadata.id,
+                start,
+                class: encounter.class.clone(),
+                encounter_type: encounter.type_.clone(),
+                reason: encounter.reason.clone(),
+            }),
+            _ => None,
+        };
+
+        SummaryEvent::EncounterChanged {
+            encounter_id: encounter.metadata.id,
+            appointment,
+        }
+    }
+}
+
+/// What the patient detail page opens with
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PatientSummary {
+    /// Patient summarized
+    pub patient_id: Id,
+    /// Latest measurement of each vital sign
+    #[serde(default)]
+    pub vitals: Vec<VitalReading>,
+    /// Diagnoses coded within [`PROBLEM_LOOKBACK_DAYS`], per encounter
+    #[serde(default)]
+    pub problems: Vec<ProblemSummary>,
+    /// Active and paused medication orders, latest start first
+    #[serde(default)]
+    pub medications: Vec<MedicationSummary>,
+    /// Planned encounters, soonest first
+    #[serde(default)]
+    pub appointments: Vec<AppointmentSummary>,
+    /// When an event was last applied
+    #[serde(default)]
+    pub updated_at: Option<Timestamp>,
+}
+
+/// The summary as the detail page shows it at a point in time
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryView {
+    /// Patient summarized
+    pub patient_id: Id,
+    /// Latest measurement of each vital sign
+    pub latest_vitals: Vec<VitalReading>,
+    /// Distinct diagnoses coded within [`PROBLEM_LOOKBACK_DAYS`], most
+    /// recently coded first
+    pub active_problems: Vec<ProblemSummary>,
+    /// Active and paused medication orders
+    pub active_medications: Vec<MedicationSummary>,
+    /// The soonest planned encounter still ahead
+    pub next_appointment: Option<AppointmentSummary>,
+    /// When the summary last changed
+    pub updated_at: Option<Timestamp>,
+}
+
+impl PatientSummary {
+    /// An empty summary
+    pub fn new(patient_id: Id) -> Self {
+        Self {
+            patient_id,
+            ..Self::default()
+        }
+    }
+
+    /// Fold in a change made at `now`
+    pub fn apply(&mut self, event: SummaryEvent, now: Timestamp) {
+        match event {
+            SummaryEvent::VitalRecorded(reading) => {
+                self.vitals.retain(|vital| vital.observation_id != reading.observation_id);
+                match self.vitals.iter_mut().find(|vital| vital.code == reading.code) {
+                    Some(latest) if latest.effective > reading.effective => {}
+                    Some(latest) => *latest = reading,
+                    None => self.vitals.push(reading),
+                }
+                self.vitals.sort_by_key(|vital| vital_position(&vital.code));
+            }
+            SummaryEvent::VitalWithdrawn { observation_id } => {
+                self.vitals.retain(|vital| vital.observation_id != observation_id);
+            }
+            SummaryEvent::MedicationChanged(order) => {
+                self.medications.retain(|active| active.request_id != order.request_id);
+                if matches!(order.status, MedicationRequestStatus::Active | MedicationRequestStatus::OnHold) {
+                    self.medications.push(order);
+                }
+                self.medications.sort_by_key(|order| Reverse(order.start));
+            }
+            SummaryEvent::EncounterCoded { encounter_id, diagnoses } => {
+                self.problems.retain(|problem| problem.encounter_id != encounter_id);
+                self.problems.extend(diagnoses);
+                self.problems.sort_by_key(|problem| Reverse(problem.recorded));
+            }
+            SummaryEvent::EncounterChanged {
+                encounter_id,
+                appointment,
+            } => {
+                self.appointments.retain(|planned| planned.encounter_id != encounter_id);
+                self.appointments.extend(appointment);
+                self.appointments.sort_by_key(|planned| planned.start);
+            }
+        }
+
+        let lookback = now - Duration::days(PROBLEM_LOOKBACK_DAYS);
+        self.problems.retain(|problem| problem.recorded >= lookback);
+        self.appointments.retain(|planned| planned.start >= now);
+        self.updated_at = Some(now);
+    }
+
+    /// The summary as of `now`
+    pub fn view(&self, now: Timestamp) -> SummaryView {
+        let lookback = now - Duration::days(PROBLEM_LOOKBACK_DAYS);
+        let mut active_problems: Vec<ProblemSummary> = Vec::new();
+        for problem in self.problems.iter().filter(|problem| problem.recorded >= lookback) {
+            if !active_problems.iter().any(|active| active.code == problem.code) {
+                active_problems.push(problem.clone());
+            }
+        }
+
+        SummaryView {
+            patient_id: self.patient_id,
+            latest_vitals: self.vitals.clone(),
+            active_problems,
+            active_medications: self.medications.clone(),
+            next_appointment: self.appointments.iter().find(|planned| planned.start >= now).cloned(),
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+/// Vital sign events of stored device readings, by patient
+///
+/// Patients come in id order, the order their summaries are locked in so
+/// that concurrent batches do not deadlock.
+pub fn device_events<'a>(
+    observations: impl IntoIterator<Item = &'a DeviceObservation>,
+) -> BTreeMap<Id, Vec<SummaryEvent>> {
+    let mut events: BTreeMap<Id, Vec<SummaryEvent>> = BTreeMap::new();
+    for observation in observations {
+        if let Some(event) = SummaryEvent::from_device_observation(observation) {
+            events.entry(observation.patient_id).or_default().push(event);
+        }
+    }
+    events
+}
+
+/// Order vital signs are shown in
+fn vital_position(code: &str) -> usize {
+    VitalSign::ALL
+        .iter()
+        .position(|vital_sign| vital_sign.loinc_code() == code)
+        .unwrap_or(VitalSign::ALL.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Period;
+    use crate::domain::EncounterCode;
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    fn at(day: u32, hour: u32) -> Timestamp {
+        Utc.with_ymd_and_hms(2026, 3, day, hour, 0, 0).unwrap()
+    }
+
+    fn heart_rate(value: f64, effective: Timestamp) -> VitalReading {
+        VitalReading {
+            observation_id: Uuid::new_v4(),
+            code: "8867-4".to_string(),
+            display: "Heart rate".to_string(),
+            value,
+            unit: "/min".to_string(),
+            effective,
+        }
+    }
+
+    #[test]
+    fn test_vitals() {
+        let mut summary = PatientSummary::new(Uuid::new_v4());
+        let earlier = heart_rate(70.0, at(1, 8));
+        let later = heart_rate(82.0, at(1, 9));
+
+        summary.apply(SummaryEvent::VitalRecorded(later.clone()), at(1, 10));
+        summary.apply(SummaryEvent::VitalRecorded(earlier), at(1, 10));
+        summary.apply(SummaryEvent::VitalRecorded(later.clone()), at(1, 10));
+        assert_eq!(summary.vitals, vec![later.clone()]);
+
+        summary.apply(
+            SummaryEvent::VitalWithdrawn {
+                observation_id: later.observation_id,
+            },
+            at(1, 11),
+        );
+        assert!(summary.vitals.is_empty());
+
+        let mut observation = VitalSign::HeartRate.observation(72.0, "/min", summary.patient_id, at(2, 8)).unwrap();
+        let event = SummaryEvent::from_observation(&observation).unwrap();
+        summary.apply(event, at(2, 9));
+        assert_eq!(summary.view(at(2, 9)).latest_vitals[0].value, 72.0);
+        observation.status = ObservationStatus::EnteredInError;
+        assert!(matches!(
+            SummaryEvent::from_observation(&observation),
+            Some(SummaryEvent::VitalWithdrawn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_medications() {
+        let patient_id = Uuid::new_v4();
+        let mut order = MedicationRequest::new(patient_id, Uuid::new_v4(), "197361", 5.0, "mg", 24, at(1, 8));
+        order.status = MedicationRequestStatus::Draft;
+        let mut summary = PatientSummary::new(patient_id);
+
+        // Drafts are not active yet
+        summary.apply(SummaryEvent::from_medication_request(&order), at(1, 8));
+        assert!(summary.medications.is_empty());
+
+        order.status = MedicationRequestStatus::Active;
+        summary.apply(SummaryEvent::from_medication_request(&order), at(1, 9));
+        order.status = MedicationRequestStatus::OnHold;
+        summary.apply(SummaryEvent::from_medication_request(&order), at(1, 10));
+        assert_eq!(summary.medications.len(), 1);
+        assert_eq!(summary.medications[0].status, MedicationRequestStatus::OnHold);
+
+        order.status = MedicationRequestStatus::Stopped;
+        summary.apply(SummaryEvent::from_medication_request(&order), at(1, 11));
+        assert!(summary.view(at(1, 11)).active_medications.is_empty());
+    }
+
+    #[test]
+    fn test_problems() {
+        let patient_id = Uuid::new_v4();
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        let coding = |encounter_id: Id, codes: &[&str], coded_at: Timestamp| {
+            let codes = codes
+                .iter()
+                .map(|code| EncounterCode::new(CodeKind::Diagnosis, code, None))
+                .collect();
+            EncounterCoding::new(encounter_id, patient_id, codes, Uuid::new_v4(), coded_at).unwrap()
+        };
+        let mut summary = PatientSummary::new(patient_id);
+
+        summary.apply(SummaryEvent::from_coding(&coding(first, &["I10", "E11.9"], at(1, 8))), at(1, 8));
+        summary.apply(SummaryEvent::from_coding(&coding(second, &["I10"], at(5, 8))), at(5, 8));
+        let view = summary.view(at(5, 8));
+        let codes: Vec<&str> = view.active_problems.iter().map(|problem| problem.code.as_str()).collect();
+        assert_eq!(codes, vec!["I10", "E11.9"]);
+        assert_eq!(view.active_problems[0].encounter_id, second);
+
+        // Recoding an encounter replaces its diagnoses
+        summary.apply(SummaryEvent::from_coding(&coding(first, &["I10"], at(6, 8))), at(6, 8));
+        assert_eq!(summary.view(at(6, 8)).active_problems.len(), 1);
+
+        // Diagnoses older than the lookback no longer count
+        let next_year = at(6, 8) + Duration::days(PROBLEM_LOOKBACK_DAYS + 1);
+        assert!(summary.view(next_year).active_problems.is_empty());
+    }
+
+    #[test]
+    fn test_appointments() {
+        let patient_id = Uuid::new_v4();
+        let planned = |start: Timestamp| {
+            let mut encounter = Encounter::new(EncounterStatus::Planned, EncounterClass::Ambulatory, patient_id);
+            encounter.period = Some(Period {
+                start: Some(start),
+                end: None,
+            });
+            encounter
+        };
+        let mut follow_up = planned(at(20, 9));
+        let check_up = planned(at(10, 9));
+        let mut summary = PatientSummary::new(patient_id);
+
+        summary.apply(SummaryEvent::from_encounter(&follow_up), at(1, 8));
+        summary.apply(SummaryEvent::from_encounter(&check_up), at(1, 8));
+        let next = summary.view(at(1, 8)).next_appointment.unwrap();
+        assert_eq!(next.encounter_id, check_up.metadata.id);
+
+        // Once its start has passed the next one is shown
+        let next = summary.view(at(11, 8)).next_appointment.unwrap();
+        assert_eq!(next.encounter_id, follow_up.metadata.id);
+
+        follow_up.status = EncounterStatus::Cancelled;
+        summary.apply(SummaryEvent::from_encounter(&follow_up), at(11, 8));
+        assert!(summary.view(at(11, 8)).next_appointment.is_none());
+        assert!(summary.appointments.is_empty());
+    }
+}
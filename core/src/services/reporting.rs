@@ -0,0 +1,778 @@
+//! Ad-hoc aggregate reports
+//!
+//! Dashboard widgets ask for aggregates with a declarative
+//! [`ReportDefinition`]: an entity, filters, the dimensions to group by and
+//! the metrics to compute. Everything a definition names is checked against
+//! an allowlist of entities and fields before it is compiled into one
+//! parameterized query, so callers never supply SQL. The query returns each
+//! result row as a JSON array text, in the order of
+//! [`ReportQuery::columns`]; [`ReportTable`] holds the rows read back and
+//! renders them as CSV or as a printable PDF.
+
+use crate::billing::pdf::{self, PdfLine};
+use crate::domain::traits::Validatable;
+use crate::types::Timestamp;
+use crate::{Error, Result};
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Most dimensions a report groups by
+pub const MAX_DIMENSIONS: usize = 3;
+
+/// Most metrics a report computes
+pub const MAX_METRICS: usize = 5;
+
+/// Most filters a report applies
+pub const MAX_FILTERS: usize = 10;
+
+/// Rows returned when a report sets no limit
+pub const DEFAULT_ROW_LIMIT: u32 = 1000;
+
+/// Most rows a report returns
+pub const MAX_ROW_LIMIT: u32 = 10_000;
+
+/// What a report aggregates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportEntity {
+    /// Patients
+    Patients,
+    /// Encounters
+    Encounters,
+    /// Observations
+    Observations,
+    /// Medication orders
+    MedicationRequests,
+}
+
+/// Kind of value a field holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Text, compared as is
+    Text,
+    /// Number
+    Number,
+    /// True or false
+    Boolean,
+    /// Calendar date
+    Date,
+    /// Point in time
+    Timestamp,
+}
+
+/// A field reports may use
+#[derive(Debug, Clone, Copy)]
+pub struct ReportField {
+    /// Name used in definitions
+    pub name: &'static str,
+    /// SQL expression over the entity's table, aliased `t`
+    pub sql: &'static str,
+    /// Kind of value
+    pub kind: FieldKind,
+    /// Whether reports may group and filter by the field; other fields are
+    /// only counted distinct, e.g. patient ids
+    pub groupable: bool,
+}
+
+const fn field(name: &'static str, sql: &'static str, kind: FieldKind) -> ReportField {
+    ReportField {
+        name,
+        sql,
+        kind,
+        groupable: true,
+    }
+}
+
+const fn counted(name: &'static str, sql: &'static str) -> ReportField {
+    ReportField {
+        name,
+        sql,
+        kind: FieldKind::Text,
+        groupable: false,
+    }
+}
+
+const PATIENT_FIELDS: &[ReportField] = &[
+    field("gender", "t.gender", FieldKind::Text),
+    field("state", "t.state", FieldKind::Text),
+    field("country", "t.country", FieldKind::Text),
+    field("active", "t.active", FieldKind::Boolean),
+    field("birth_date", "t.birth_date", FieldKind::Date),
+    field("age", "date_part('year', age(t.birth_date))", FieldKind::Number),
+    field("created_at", "t.created_at", FieldKind::Timestamp),
+    counted("id", "t.id"),
+];
+
+const ENCOUNTER_FIELDS: &[ReportField] = &[
+    field("status", "t.status", FieldKind::Text),
+    field("class", "t.class", FieldKind::Text),
+    field("type", "t.type", FieldKind::Text),
+    field("reason_code", "t.reason_code", FieldKind::Text),
+    field("organization_id", "t.organization_id::text", FieldKind::Text),
+    field("practitioner_id", "t.practitioner_id::text", FieldKind::Text),
+    field("start_date", "t.start_date", FieldKind::Timestamp),
+    field("end_date", "t.end_date", FieldKind::Timestamp),
+    field(
+        "length_minutes",
+        "EXTRACT(EPOCH FROM (t.end_date - t.start_date)) / 60",
+        FieldKind::Number,
+    ),
+    counted("patient_id", "t.patient_id"),
+];
+
+const OBSERVATION_FIELDS: &[ReportField] = &[
+    field("status", "t.status", FieldKind::Text),
+    field("category", "t.category", FieldKind::Text),
+    field("code", "t.code", FieldKind::Text),
+    field("interpretation", "t.interpretation", FieldKind::Text),
+    field("unit", "t.value_quantity_unit", FieldKind::Text),
+    field("value", "t.value_quantity_value::double precision", FieldKind::Number),
+    field("effective_date", "t.effective_date", FieldKind::Timestamp),
+    counted("patient_id", "t.patient_id"),
+    counted("encounter_id", "t.encounter_id"),
+];
+
+const MEDICATION_REQUEST_FIELDS: &[ReportField] = &[
+    field("medication_code", "t.medication_code", FieldKind::Text),
+    field("status", "t.status", FieldKind::Text),
+    field("route", "t.route", FieldKind::Text),
+    field("as_needed", "t.as_needed", FieldKind::Boolean),
+    field("dose_quantity", "t.dose_quantity", FieldKind::Number),
+    field("start_at", "t.start_at", FieldKind::Timestamp),
+    field("authored_on", "t.authored_on", FieldKind::Timestamp),
+    counted("patient_id", "t.patient_id"),
+    counted("requester_id", "t.requester_id"),
+];
+
+impl ReportEntity {
+    /// Table the entity is read from
+    pub fn table(&self) -> &'static str {
+        match self {
+            ReportEntity::Patients => "emr.patients",
+            ReportEntity::Encounters => "emr.encounters",
+            ReportEntity::Observations => "emr.observations",
+            ReportEntity::MedicationRequests => "emr.medication_requests",
+        }
+    }
+
+    /// Fields reports on the entity may use
+    pub fn fields(&self) -> &'static [ReportField] {
+        match self {
+            ReportEntity::Patients => PATIENT_FIELDS,
+            ReportEntity::Encounters => ENCOUNTER_FIELDS,
+            ReportEntity::Observations => OBSERVATION_FIELDS,
+            ReportEntity::MedicationRequests => MEDICATION_REQUEST_FIELDS,
+        }
+    }
+
+    fn field(&self, name: &str, what: &str) -> Result<&'static ReportField> {
+        self.fields().iter().find(|field| field.name == name).ok_or_else(|| {
+            Error::validation_error_with_field(&format!("Reports cannot use '{}' in {}", name, what), what)
+        })
+    }
+}
+
+/// How a filter compares a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOperator {
+    /// Equal
+    Eq,
+    /// Not equal
+    Ne,
+    /// Greater than
+    Gt,
+    /// Greater than or equal
+    Ge,
+    /// Less than
+    Lt,
+    /// Less than or equal
+    Le,
+    /// One of a list of text values
+    In,
+    /// Has a value
+    Present,
+    /// Has no value
+    Missing,
+}
+
+/// A condition rows must meet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportFilter {
+    /// Field compared
+    pub field: String,
+    /// Comparison
+    #[serde(rename = "op")]
+    pub operator: FilterOperator,
+    /// Value compared with; dates and timestamps as ISO 8601 text, a list
+    /// for `in`, nothing for `present` and `missing`
+    #[serde(default)]
+    pub value: Value,
+}
+
+/// Aggregate a metric computes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricFunction {
+    /// Number of rows
+    Count,
+    /// Number of distinct values of a field
+    CountDistinct,
+    /// Sum of a number field
+    Sum,
+    /// Average of a number field
+    Avg,
+    /// Smallest value of a number field
+    Min,
+    /// Largest value of a number field
+    Max,
+}
+
+impl MetricFunction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricFunction::Count => "count",
+            MetricFunction::CountDistinct => "count_distinct",
+            MetricFunction::Sum => "sum",
+            MetricFunction::Avg => "avg",
+            MetricFunction::Min => "min",
+            MetricFunction::Max => "max",
+        }
+    }
+}
+
+/// A value computed per group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportMetric {
+    /// Aggregate
+    pub function: MetricFunction,
+    /// Field aggregated; none for `count`
+    #[serde(default)]
+    pub field: Option<String>,
+}
+
+impl ReportMetric {
+    /// Output column name, e.g. `count` or `avg_value`
+    pub fn column(&self) -> String {
+        match &self.field {
+            Some(field) => format!("{}_{}", self.function.as_str(), field),
+            None => self.function.as_str().to_string(),
+        }
+    }
+}
+
+/// How result rows are sorted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportOrder {
+    /// Output column, a dimension or a metric column name
+    pub column: String,
+    /// Largest first
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// A declarative aggregate report
+///
+/// Dimensions name a field, or a date or timestamp field truncated to a
+/// period, e.g. `start_date:month` (`day`, `week`, `month`, `quarter`,
+/// `year`). Rows are sorted by the dimensions unless `order_by` says
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportDefinition {
+    /// What is aggregated
+    pub entity: ReportEntity,
+    /// Conditions all rows must meet
+    #[serde(default)]
+    pub filters: Vec<ReportFilter>,
+    /// Dimensions rows are grouped by
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    /// Values computed per group
+    pub metrics: Vec<ReportMetric>,
+    /// Sort order
+    #[serde(default)]
+    pub order_by: Option<ReportOrder>,
+    /// Most rows returned
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl ReportDefinition {
+    /// The definition limited to rows whose `field` falls in
+    /// `[start, end)`, e.g. the week before a scheduled run
+    pub fn within(&self, field: &str, start: Timestamp, end: Timestamp) -> Self {
+        let mut definition = self.clone();
+        for (operator, instant) in [(FilterOperator::Ge, start), (FilterOperator::Lt, end)] {
+            definition.filters.push(ReportFilter {
+                field: field.to_string(),
+                operator,
+                value: Value::String(instant.to_rfc3339()),
+            });
+        }
+        definition
+    }
+}
+
+impl Validatable for ReportDefinition {
+    fn validate(&self) -> Result<()> {
+        compile(self).map(|_| ())
+    }
+}
+
+/// Value bound to a report query placeholder
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportParam {
+    /// Bound as `text`
+    Text(String),
+    /// Bound as `text[]`
+    TextList(Vec<String>),
+    /// Bound as `double precision`
+    Number(f64),
+    /// Bound as `boolean`
+    Boolean(bool),
+    /// Bound as `timestamptz`
+    Timestamp(Timestamp),
+    /// Bound as `bigint`
+    Limit(i64),
+}
+
+/// Compiled report
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportQuery {
+    /// SQL returning one `row` column of JSON array text per result row
+    pub sql: String,
+    /// Values of the placeholders, in order
+    pub params: Vec<ReportParam>,
+    /// Names of the values in each row
+    pub columns: Vec<String>,
+}
+
+impl ReportQuery {
+    fn bind(&mut self, param: ReportParam) -> String {
+        self.params.push(param);
+        format!("${}", self.params.len())
+    }
+}
+
+/// Check a definition against the allowlist and compile it
+pub fn compile(definition: &ReportDefinition) -> Result<ReportQuery> {
+    let entity = definition.entity;
+    if definition.metrics.is_empty() || definition.metrics.len() > MAX_METRICS {
+        return Err(Error::validation_error_with_field(
+            &format!("Reports compute 1 to {} metrics", MAX_METRICS),
+            "metrics",
+        ));
+    }
+    if definition.group_by.len() > MAX_DIMENSIONS {
+        return Err(Error::validation_error_with_field(
+            &format!("Reports group by at most {} dimensions", MAX_DIMENSIONS),
+            "group_by",
+        ));
+    }
+    if definition.filters.len() > MAX_FILTERS {
+        return Err(Error::validation_error_with_field(
+            &format!("Reports apply at most {} filters", MAX_FILTERS),
+            "filters",
+        ));
+    }
+    let limit = definition.limit.unwrap_or(DEFAULT_ROW_LIMIT);
+    if limit == 0 || limit > MAX_ROW_LIMIT {
+        return Err(Error::validation_error_with_field(
+            &format!("Reports return 1 to {} rows", MAX_ROW_LIMIT),
+            "limit",
+        ));
+    }
+
+    let mut query = ReportQuery {
+        sql: String::new(),
+        params: Vec::new(),
+        columns: Vec::new(),
+    };
+    let mut selected = Vec::new();
+    for dimension in &definition.group_by {
+        if query.columns.contains(dimension) {
+            return Err(Error::validation_error_with_field(
+                &format!("Dimension '{}' is listed twice", dimension),
+                "group_by",
+            ));
+        }
+        selected.push(dimension_sql(entity, dimension)?);
+        query.columns.push(dimension.clone());
+    }
+    for metric in &definition.metrics {
+        let column = metric.column();
+        if query.columns.contains(&column) {
+            return Err(Error::validation_error_with_field(
+                &format!("Metric '{}' is listed twice", column),
+                "metrics",
+            ));
+        }
+        selected.push(metric_sql(entity, metric)?);
+        query.columns.push(column);
+    }
+
+    let mut conditions = Vec::with_capacity(definition.filters.len());
+    for filter in &definition.filters {
+        conditions.push(filter_sql(entity, filter, &mut query)?);
+    }
+
+    let dimensions = definition.group_by.len();
+    let order = match &definition.order_by {
+        Some(order) => {
+            let position = query.columns.iter().position(|column| *column == order.column).ok_or_else(|| {
+                Error::validation_error_with_field(
+                    &format!("Reports can only be ordered by their columns, not '{}'", order.column),
+                    "order_by",
+                )
+            })?;
+            let direction = if order.descending { "DESC NULLS LAST" } else { "ASC NULLS LAST" };
+            let mut order = vec![format!("c{} {}", position, direction)];
+            order.extend((0..dimensions).filter(|i| *i != position).map(|i| format!("c{}", i)));
+            order
+        }
+        None => (0..dimensions).map(|i| format!("c{}", i)).collect(),
+    };
+
+    let limit = query.bind(ReportParam::Limit(i64::from(limit)));
+    let columns: Vec<String> = (0..selected.len()).map(|i| format!("c{}", i)).collect();
+    let selected: Vec<String> = selected
+        .iter()
+        .enumerate()
+        .map(|(i, sql)| format!("{} AS c{}", sql, i))
+        .collect();
+    query.sql = format!(
+        "SELECT json_build_array({})::text AS row FROM (SELECT {} FROM {} t{}{}{} LIMIT {}) r",
+        columns.join(", "),
+        selected.join(", "),
+        entity.table(),
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        },
+        if dimensions == 0 {
+            String::new()
+        } else {
+            format!(" GROUP BY {}", (1..=dimensions).map(|i| i.to_string()).collect::<Vec<_>>().join(", "))
+        },
+        if order.is_empty() {
+            String::new()
+        } else {
+            format!(" ORDER BY {}", order.join(", "))
+        },
+        limit
+    );
+    Ok(query)
+}
+
+fn dimension_sql(entity: ReportEntity, dimension: &str) -> Result<String> {
+    let (name, grain) = match dimension.split_once(':') {
+        Some((name, grain)) => (name, Some(grain)),
+        None => (dimension, None),
+    };
+    let field = entity.field(name, "group_by")?;
+    if !field.groupable {
+        return Err(Error::validation_error_with_field(
+            &format!("Reports cannot group by '{}'", name),
+            "group_by",
+        ));
+    }
+    match (grain, field.kind) {
+        (None, _) => Ok(field.sql.to_string()),
+        (Some(grain @ ("day" | "week" | "month" | "quarter" | "year")), FieldKind::Date | FieldKind::Timestamp) => {
+            Ok(format!("date_trunc('{}', {})::date", grain, field.sql))
+        }
+        (Some(grain), _) => Err(Error::validation_error_with_field(
+            &format!("'{}' cannot be grouped by '{}'", name, grain),
+            "group_by",
+        )),
+    }
+}
+
+fn metric_sql(entity: ReportEntity, metric: &ReportMetric) -> Result<String> {
+    let field = match (&metric.field, metric.function) {
+        (None, MetricFunction::Count) => return Ok("COUNT(*)".to_string()),
+        (Some(_), MetricFunction::Count) => {
+            return Err(Error::validation_error_with_field(
+                "Count takes no field; use count_distinct",
+                "metrics",
+            ))
+        }
+        (None, _) => {
+            return Err(Error::validation_error_with_field(
+                &format!("Metric {} needs a field", metric.function.as_str()),
+                "metrics",
+            ))
+        }
+        (Some(name), _) => entity.field(name, "metrics")?,
+    };
+    match metric.function {
+        MetricFunction::CountDistinct => Ok(format!("COUNT(DISTINCT {})", field.sql)),
+        function if field.kind == FieldKind::Number => {
+            Ok(format!("{}({})", function.as_str().to_uppercase(), field.sql))
+        }
+        function => Err(Error::validation_error_with_field(
+            &format!("Metric {} needs a number field, not '{}'", function.as_str(), field.name),
+            "metrics",
+        )),
+    }
+}
+
+fn filter_sql(entity: ReportEntity, filter: &ReportFilter, query: &mut ReportQuery) -> Result<String> {
+    let field = entity.field(&filter.field, "filters")?;
+    if !field.groupable {
+        return Err(Error::validation_error_with_field(
+            &format!("Reports cannot filter by '{}'", field.name),
+            "filters",
+        ));
+    }
+    let operator = match filter.operator {
+        FilterOperator::Present => return Ok(format!("{} IS NOT NULL", field.sql)),
+        FilterOperator::Missing => return Ok(format!("{} IS NULL", field.sql)),
+        FilterOperator::In => {
+            let values = filter
+                .value
+                .as_array()
+                .filter(|values| !values.is_empty())
+                .and_then(|values| values.iter().map(|v| v.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+                .filter(|_| field.kind == FieldKind::Text)
+                .ok_or_else(|| invalid_value(field))?;
+            return Ok(format!("{} = ANY({})", field.sql, query.bind(ReportParam::TextList(values))));
+        }
+        FilterOperator::Eq => "=",
+        FilterOperator::Ne => "<>",
+        FilterOperator::Gt => ">",
+        FilterOperator::Ge => ">=",
+        FilterOperator::Lt => "<",
+        FilterOperator::Le => "<=",
+    };
+    let param = match (field.kind, &filter.value) {
+        (FieldKind::Text, Value::String(value)) => ReportParam::Text(value.clone()),
+        (FieldKind::Number, Value::Number(value)) => {
+            ReportParam::Number(value.as_f64().ok_or_else(|| invalid_value(field))?)
+        }
+        (FieldKind::Boolean, Value::Bool(value)) => ReportParam::Boolean(*value),
+        (FieldKind::Date | FieldKind::Timestamp, Value::String(value)) => {
+            ReportParam::Timestamp(parse_instant(value).ok_or_else(|| invalid_value(field))?)
+        }
+        _ => return Err(invalid_value(field)),
+    };
+    let placeholder = query.bind(param);
+    Ok(match field.kind {
+        FieldKind::Date => format!("{} {} ({})::date", field.sql, operator, placeholder),
+        _ => format!("{} {} {}", field.sql, operator, placeholder),
+    })
+}
+
+fn invalid_value(field: &ReportField) -> Error {
+    Error::validation_error_with_field(&format!("Invalid value for filter on '{}'", field.name), "filters")
+}
+
+/// A date (as its UTC midnight) or RFC 3339 timestamp
+fn parse_instant(value: &str) -> Option<Timestamp> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?));
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|instant| instant.with_timezone(&Utc))
+}
+
+/// Report results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportTable {
+    /// Column names
+    pub columns: Vec<String>,
+    /// Rows of values, in column order
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl ReportTable {
+    /// Render as CSV with a header row (RFC 4180)
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let header: Vec<String> = self.columns.iter().map(|column| csv_field(column)).collect();
+        csv.push_str(&header.join(","));
+        csv.push_str("\r\n");
+        for row in &self.rows {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|value| match value {
+                    Value::Null => String::new(),
+                    Value::String(text) => csv_field(text),
+                    other => csv_field(&other.to_string()),
+                })
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    /// Render as a printable PDF table headed by `title` and `subtitle`;
+    /// cells too long for their column are cut short
+    pub fn to_pdf(&self, title: &str, subtitle: &str) -> Vec<u8> {
+        let width = PDF_TABLE_WIDTH / self.columns.len().max(1) as f32;
+        let chars = ((width - 6.0) / (PDF_TABLE_SIZE * 0.55)).max(1.0) as usize;
+        let row = |bold: bool, cells: Vec<String>| PdfLine {
+            size: PDF_TABLE_SIZE,
+            bold,
+            cells: cells
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| (i as f32 * width, clip(&text, chars)))
+                .collect(),
+        };
+
+        let mut lines = vec![
+            PdfLine::text(14.0, true, title),
+            PdfLine::text(9.0, false, subtitle),
+            PdfLine::blank(),
+            row(true, self.columns.clone()),
+        ];
+        for values in &self.rows {
+            let cells = values
+                .iter()
+                .map(|value| match value {
+                    Value::Null => String::new(),
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })
+                .collect();
+            lines.push(row(false, cells));
+        }
+        if self.rows.is_empty() {
+            lines.push(PdfLine::text(PDF_TABLE_SIZE, false, "No rows"));
+        }
+        pdf::render(&lines)
+    }
+}
+
+/// Width of a PDF report table in points (US Letter less margins)
+const PDF_TABLE_WIDTH: f32 = 504.0;
+
+/// Font size of PDF report tables
+const PDF_TABLE_SIZE: f32 = 8.0;
+
+fn clip(text: &str, chars: usize) -> String {
+    if text.chars().count() <= chars {
+        return text.to_string();
+    }
+    let mut clipped: String = text.chars().take(chars.saturating_sub(1)).collect();
+    clipped.push('~');
+    clipped
+}
+
+fn csv_field(text: &str) -> String {
+    // Leading formula characters are neutralized for spreadsheet users
+    let text = if text.starts_with(['=', '+', '-', '@']) && text.parse::<f64>().is_err() {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    };
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn definition(value: Value) -> ReportDefinition {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_compile_report() {
+        let query = compile(&definition(json!({
+            "entity": "encounters",
+            "filters": [
+                {"field": "start_date", "op": "ge", "value": "2024-01-01"},
+                {"field": "class", "op": "in", "value": ["AMB", "VR"]}
+            ],
+            "group_by": ["start_date:month", "class"],
+            "metrics": [
+                {"function": "count"},
+                {"function": "count_distinct", "field": "patient_id"},
+                {"function": "avg", "field": "length_minutes"}
+            ],
+            "order_by": {"column": "count", "descending": true}
+        })))
+        .unwrap();
+
+        assert_eq!(
+            query.columns,
+            vec!["start_date:month", "class", "count", "count_distinct_patient_id", "avg_length_minutes"]
+        );
+        assert!(query.sql.starts_with("SELECT json_build_array(c0, c1, c2, c3, c4)::text AS row FROM (SELECT"));
+        assert!(query.sql.contains("date_trunc('month', t.start_date)::date AS c0"));
+        assert!(query.sql.contains("COUNT(DISTINCT t.patient_id) AS c3"));
+        assert!(query.sql.contains(
+            "FROM emr.encounters t WHERE t.start_date >= $1 AND t.class = ANY($2) GROUP BY 1, 2 \
+             ORDER BY c2 DESC NULLS LAST, c0, c1 LIMIT $3) r"
+        ));
+        assert_eq!(query.params[2], ReportParam::Limit(1000));
+    }
+
+    #[test]
+    fn test_reports_only_use_allowed_fields() {
+        let rejected = [
+            json!({"entity": "patients", "group_by": ["family_name"], "metrics": [{"function": "count"}]}),
+            json!({"entity": "patients", "group_by": ["id"], "metrics": [{"function": "count"}]}),
+            json!({"entity": "patients", "group_by": ["gender:month"], "metrics": [{"function": "count"}]}),
+            json!({"entity": "patients", "metrics": [{"function": "avg", "field": "gender"}]}),
+            json!({"entity": "patients", "metrics": []}),
+            json!({"entity": "patients", "filters": [{"field": "age", "op": "gt", "value": "40"}],
+                   "metrics": [{"function": "count"}]}),
+            json!({"entity": "patients", "metrics": [{"function": "count"}], "order_by": {"column": "gender"}}),
+            json!({"entity": "patients", "metrics": [{"function": "count"}], "limit": 0}),
+        ];
+        for value in rejected {
+            assert!(compile(&definition(value.clone())).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_report_within_period() {
+        let base = definition(json!({"entity": "encounters", "metrics": [{"function": "count"}]}));
+        let end = Utc.with_ymd_and_hms(2024, 3, 11, 6, 0, 0).unwrap();
+        let weekly = base.within("start_date", end - chrono::Duration::days(7), end);
+        assert_eq!(weekly.filters[1].value, json!("2024-03-11T06:00:00+00:00"));
+        let query = compile(&weekly).unwrap();
+        assert!(query.sql.contains("WHERE t.start_date >= $1 AND t.start_date < $2"));
+    }
+
+    #[test]
+    fn test_report_pdf() {
+        let table = ReportTable {
+            columns: vec!["class".to_string(), "count".to_string()],
+            rows: vec![vec![json!("AMB"), json!(12)]],
+        };
+        let pdf = String::from_utf8(table.to_pdf("Weekly visits", "4 Mar - 11 Mar 2024")).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert!(pdf.contains("(Weekly visits) Tj"));
+        assert!(pdf.contains("(AMB) Tj"));
+        assert_eq!(clip("Follow-up visit", 6), "Follo~");
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let table = ReportTable {
+            columns: vec!["reason".to_string(), "count".to_string()],
+            rows: vec![
+                vec![json!("Follow-up, routine"), json!(12)],
+                vec![json!("=HYPERLINK(\"x\")"), json!(-1)],
+                vec![Value::Null, json!(3.5)],
+            ],
+        };
+        assert_eq!(
+            table.to_csv(),
+            "reason,count\r\n\"Follow-up, routine\",12\r\n\"'=HYPERLINK(\"\"x\"\")\",-1\r\n,3.5\r\n"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Global search
+//!
+//! One query searches patients, practitioners, organizations and documents.
+//! Each entity type is searched on its own and the candidates are scored
+//! here, so hits of different types can be ranked against each other: a
+//! name the query spells out exactly beats one that merely starts with it,
+//! which beats one containing it, and fuzzy (trigram) matches come last.
+//! Documents found only by their extracted text rank below name matches.
+//! Which hits a caller may see is decided before scoring, by the API.
+
+use crate::types::Id;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Shortest query searched; shorter ones match too much to be useful
+pub const MIN_QUERY_CHARS: usize = 2;
+
+/// Longest query searched
+pub const MAX_QUERY_CHARS: usize = 100;
+
+/// Kind of entity a hit is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchEntity {
+    /// Found by name or identifier, e.g. the MRN
+    Patient,
+    /// Found by name or specialty
+    Practitioner,
+    /// Found by name
+    Organization,
+    /// Found by title, type or extracted text
+    Document,
+}
+
+impl SearchEntity {
+    /// All entity types, in the order equally ranked hits are listed
+    pub const ALL: [SearchEntity; 4] = [
+        SearchEntity::Patient,
+        SearchEntity::Practitioner,
+        SearchEntity::Organization,
+        SearchEntity::Document,
+    ];
+
+    /// Value used in requests and responses
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchEntity::Patient => "patient",
+            SearchEntity::Practitioner => "practitioner",
+            SearchEntity::Organization => "organization",
+            SearchEntity::Document => "document",
+        }
+    }
+
+    /// Parse a request value
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|entity| entity.as_str() == value)
+    }
+
+    /// Label shown on the hit's badge
+    pub fn badge(&self) -> &'static str {
+        match self {
+            SearchEntity::Patient => "Patient",
+            SearchEntity::Practitioner => "Practitioner",
+            SearchEntity::Organization => "Organization",
+            SearchEntity::Document => "Document",
+        }
+    }
+
+    /// FHIR resource type of the entity, as named in access scopes
+    pub fn resource_type(&self) -> &'static str {
+        match self {
+            SearchEntity::Patient => "Patient",
+            SearchEntity::Practitioner => "Practitioner",
+            SearchEntity::Organization => "Organization",
+            SearchEntity::Document => "DocumentReference",
+        }
+    }
+}
+
+/// An entity found by a search
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    /// Kind of entity found
+    pub entity: SearchEntity,
+    /// Badge label, e.g. `Patient`
+    pub badge: String,
+    /// Id of the entity found
+    pub id: Id,
+    /// Name or title shown for the hit
+    pub title: String,
+    /// Second line, e.g. a birth date or specialty
+    pub subtitle: Option<String>,
+    /// Patient a document belongs to
+    pub patient_id: Option<Id>,
+    /// Relevance from 0 to 1
+    pub score: f64,
+}
+
+impl SearchHit {
+    /// A hit scored against the query
+    pub fn new(entity: SearchEntity, id: Id, title: String, subtitle: Option<String>, score: f64) -> Self {
+        Self {
+            entity,
+            badge: entity.badge().to_string(),
+            id,
+            title,
+            subtitle,
+            patient_id: None,
+            score,
+        }
+    }
+
+    /// Attribute the hit to a patient
+    pub fn for_patient(mut self, patient_id: Id) -> Self {
+        self.patient_id = Some(patient_id);
+        self
+    }
+}
+
+/// Trim a query and collapse its whitespace, checking its length
+pub fn normalize_query(query: &str) -> Result<String> {
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    let length = query.chars().count();
+    if length < MIN_QUERY_CHARS {
+        return Err(Error::validation_error_with_field(
+            &format!("Search needs at least {} characters", MIN_QUERY_CHARS),
+            "q",
+        ));
+    }
+    if length > MAX_QUERY_CHARS {
+        return Err(Error::validation_error_with_field(
+            &format!("Search is limited to {} characters", MAX_QUERY_CHARS),
+            "q",
+        ));
+    }
+    Ok(query)
+}
+
+/// Score a name or title against the query
+///
+/// `similarity` is the trigram similarity the database computed, used for
+/// names that only match fuzzily.
+pub fn score_name(query: &str, name: &str, similarity: f64) -> f64 {
+    let query = query.to_lowercase();
+    let name = name.to_lowercase();
+    if name == query {
+        1.0
+    } else if name.starts_with(&query) {
+        0.9
+    } else if name.split_whitespace().any(|word| word.starts_with(&query)) {
+        0.8
+    } else if name.contains(&query) {
+        0.6
+    } else {
+        0.5 * similarity.clamp(0.0, 1.0)
+    }
+}
+
+/// Score a document found by its title, its extracted text, or both
+///
+/// `text_rank` is the full-text rank of the extracted text; text matches
+/// score at most 0.5 so that they rank below name matches.
+pub fn score_document(query: &str, title: Option<&str>, text_rank: Option<f64>) -> f64 {
+    let title_score = title.map(|title| score_name(query, title, 0.0)).unwrap_or(0.0);
+    let text_score = text_rank
+        .map(|rank| 0.5 * rank.max(0.0) / (rank.max(0.0) + 0.1))
+        .unwrap_or(0.0);
+    title_score.max(text_score)
+}
+
+/// The best `limit` hits, best first; equal scores list patients first, then
+/// by title
+pub fn rank_hits(mut hits: Vec<SearchHit>, limit: usize) -> Vec<SearchHit> {
+    let order = |entity: SearchEntity| SearchEntity::ALL.iter().position(|e| *e == entity);
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| order(a.entity).cmp(&order(b.entity)))
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_normalize_query() {
+        assert_eq!(normalize_query("  jane \t doe ").unwrap(), "jane doe");
+        assert!(normalize_query(" j ").is_err());
+        assert!(normalize_query(&"x".repeat(MAX_QUERY_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_score_name() {
+        assert_eq!(score_name("jane doe", "Jane Doe", 1.0), 1.0);
+        assert_eq!(score_name("jan", "Jane Doe", 0.4), 0.9);
+        assert_eq!(score_name("doe", "Jane Doe", 0.4), 0.8);
+        assert_eq!(score_name("ane", "Jane Doe", 0.2), 0.6);
+        assert_eq!(score_name("jnae", "Jane Doe", 0.4), 0.2);
+        assert!(score_document("lipid", None, Some(0.3)) < 0.5);
+        assert_eq!(score_document("lipid", Some("Lipid panel"), Some(0.3)), 0.9);
+    }
+
+    #[test]
+    fn test_rank_hits() {
+        let hit = |entity, title: &str, score| SearchHit::new(entity, Uuid::new_v4(), title.to_string(), None, score);
+        let ranked = rank_hits(
+            vec![
+                hit(SearchEntity::Document, "Doe referral", 0.3),
+                hit(SearchEntity::Practitioner, "Jane Doe", 0.8),
+                hit(SearchEntity::Patient, "John Doe", 0.8),
+                hit(SearchEntity::Organization, "Doe Clinic", 0.9),
+            ],
+            3,
+        );
+        let titles: Vec<&str> = ranked.iter().map(|hit| hit.title.as_str()).collect();
+        assert_eq!(titles, vec!["Doe Clinic", "John Doe", "Jane Doe"]);
+        assert_eq!(ranked[1].badge, "Patient");
+    }
+}
@@ -0,0 +1,454 @@
+//! Security events
+//!
+//! The API writes every sign-in and every denied request to
+//! `audit.auth_events`, with the caller's address and, when the edge proxy
+//! provides it, their location. The jobs worker checks them for anomalies:
+//! a sign-in from a place the user has not signed in from before, a sign-in
+//! too far from the previous one for the time between them, repeated denied
+//! requests, and a user opening the records of many patients in a short
+//! time. The API itself records requests made with a role from outside the
+//! role's network zones and brute-force lockouts (see
+//! [`crate::services::throttle`]). Each anomaly is stored as a [`SecurityEvent`] in
+//! `audit.security_events`, alerted to the security admins and listed in the
+//! security events audit report ([`SecurityEventReport`]).
+
+use crate::domain::ReportFormat;
+use crate::services::reporting::ReportTable;
+use crate::types::Timestamp;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Security events detected in `[$1, $2)`, oldest first
+pub const SECURITY_EVENTS_QUERY: &str = "SELECT occurred_at, kind, subject, description, details::text AS details \
+     FROM audit.security_events WHERE occurred_at >= $1 AND occurred_at < $2 ORDER BY occurred_at, id";
+
+/// Distance under which sign-ins are never impossible travel; IP
+/// geolocation is not more precise than this
+pub const MIN_TRAVEL_KM: f64 = 100.0;
+
+/// What happened in an authentication event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventKind {
+    /// A user signed in
+    LoginSucceeded,
+    /// A sign-in was refused
+    LoginFailed,
+    /// A request was refused as unauthenticated or unauthorized
+    AccessDenied,
+}
+
+impl AuthEventKind {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthEventKind::LoginSucceeded => "login_succeeded",
+            AuthEventKind::LoginFailed => "login_failed",
+            AuthEventKind::AccessDenied => "access_denied",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        [AuthEventKind::LoginSucceeded, AuthEventKind::LoginFailed, AuthEventKind::AccessDenied]
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+    }
+
+    /// Whether the event counts towards repeated authorization failures
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, AuthEventKind::LoginSucceeded)
+    }
+}
+
+/// Where a request came from, as located by the edge proxy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 alpha-2 country code
+    pub country: String,
+    /// City, when the proxy could tell
+    pub city: Option<String>,
+    /// Degrees north
+    pub latitude: Option<f64>,
+    /// Degrees east
+    pub longitude: Option<f64>,
+}
+
+impl GeoLocation {
+    /// `City, CC`, or the country alone
+    pub fn label(&self) -> String {
+        match &self.city {
+            Some(city) => format!("{}, {}", city, self.country),
+            None => self.country.clone(),
+        }
+    }
+
+    /// Whether two locations are the same place, ignoring coordinates
+    pub fn same_place(&self, other: &GeoLocation) -> bool {
+        self.country.eq_ignore_ascii_case(&other.country)
+            && self.city.as_deref().map(str::to_lowercase) == other.city.as_deref().map(str::to_lowercase)
+    }
+
+    /// Great-circle distance in kilometres, if both have coordinates
+    pub fn distance_km(&self, other: &GeoLocation) -> Option<f64> {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+        let (lat1, lon1) = (self.latitude?.to_radians(), self.longitude?.to_radians());
+        let (lat2, lon2) = (other.latitude?.to_radians(), other.longitude?.to_radians());
+        let a = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+        Some(2.0 * EARTH_RADIUS_KM * a.sqrt().asin())
+    }
+}
+
+/// A sign-in or denied request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthEvent {
+    /// When it happened
+    pub occurred_at: Timestamp,
+    /// Token subject or username; `ip:<address>` when the caller is unknown
+    pub subject: String,
+    /// What happened
+    pub kind: AuthEventKind,
+    /// Caller's address
+    pub ip_address: Option<String>,
+    /// Caller's location
+    pub location: Option<GeoLocation>,
+    /// Path of a denied request
+    pub path: Option<String>,
+}
+
+/// Kind of anomaly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityEventKind {
+    /// Sign-in from a place the user has not signed in from before
+    NewLocation,
+    /// Sign-in too far from the previous one for the time between them
+    ImpossibleTravel,
+    /// Many refused sign-ins or requests in a short time
+    RepeatedAuthorizationFailures,
+    /// A user opened the records of many patients in a short time
+    MassRecordAccess,
+    /// A request with a role from outside the networks the role is limited to
+    NetworkZoneViolation,
+    /// Too many failed sign-ins or code checks locked a key out
+    ThrottleLockout,
+}
+
+impl SecurityEventKind {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityEventKind::NewLocation => "new_location",
+            SecurityEventKind::ImpossibleTravel => "impossible_travel",
+            SecurityEventKind::RepeatedAuthorizationFailures => "repeated_authorization_failures",
+            SecurityEventKind::MassRecordAccess => "mass_record_access",
+            SecurityEventKind::NetworkZoneViolation => "network_zone_violation",
+            SecurityEventKind::ThrottleLockout => "throttle_lockout",
+        }
+    }
+}
+
+/// A detected anomaly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityEvent {
+    /// When the anomalous activity happened
+    pub occurred_at: Timestamp,
+    /// Stored name of the [`SecurityEventKind`]
+    pub kind: String,
+    /// User or caller the activity is attributed to
+    pub subject: String,
+    /// One line for the alert and the report
+    pub description: String,
+    /// What the detection was based on
+    pub details: Value,
+}
+
+impl SecurityEvent {
+    /// Event for a request by `subject` with `role` from `ip_address`, which
+    /// is outside the role's network zones
+    pub fn network_zone_violation(
+        subject: &str,
+        role: &str,
+        ip_address: Option<&str>,
+        path: &str,
+        occurred_at: Timestamp,
+    ) -> Self {
+        SecurityEvent {
+            occurred_at,
+            kind: SecurityEventKind::NetworkZoneViolation.as_str().to_string(),
+            subject: subject.to_string(),
+            description: format!(
+                "{} used the {} role from {}, outside its network zones",
+                subject,
+                role,
+                ip_address.unwrap_or("an unknown address")
+            ),
+            details: json!({ "role": role, "ip_address": ip_address, "path": path }),
+        }
+    }
+}
+
+/// Thresholds for anomaly detection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyRules {
+    /// Fastest plausible travel between sign-ins, in km/h
+    pub max_travel_kmh: f64,
+    /// Refused sign-ins or requests by one subject that raise an event
+    pub failure_threshold: u32,
+    /// Minutes refused attempts are counted over
+    pub failure_window_minutes: i64,
+    /// Distinct patients opened by one user that raise an event
+    pub mass_access_threshold: u32,
+    /// Minutes record accesses are counted over
+    pub mass_access_window_minutes: i64,
+}
+
+impl Default for AnomalyRules {
+    fn default() -> Self {
+        Self {
+            max_travel_kmh: 900.0,
+            failure_threshold: 10,
+            failure_window_minutes: 15,
+            mass_access_threshold: 50,
+            mass_access_window_minutes: 60,
+        }
+    }
+}
+
+impl AnomalyRules {
+    /// Anomalies of a successful sign-in, given the subject's earlier
+    /// successful sign-ins, newest first
+    pub fn check_login(&self, login: &AuthEvent, history: &[AuthEvent]) -> Vec<SecurityEvent> {
+        let mut events = Vec::new();
+        let Some(location) = &login.location else {
+            return events;
+        };
+        let located: Vec<(&AuthEvent, &GeoLocation)> = history
+            .iter()
+            .filter(|earlier| earlier.occurred_at <= login.occurred_at)
+            .filter_map(|earlier| earlier.location.as_ref().map(|earlier_location| (earlier, earlier_location)))
+            .collect();
+        // A first sign-in is not anomalous
+        let Some((previous, previous_location)) = located.first() else {
+            return events;
+        };
+
+        if !located.iter().any(|(_, earlier)| earlier.same_place(location)) {
+            events.push(SecurityEvent {
+                occurred_at: login.occurred_at,
+                kind: SecurityEventKind::NewLocation.as_str().to_string(),
+                subject: login.subject.clone(),
+                description: format!("{} signed in from {} for the first time", login.subject, location.label()),
+                details: json!({ "ip_address": login.ip_address, "location": location }),
+            });
+        }
+
+        if let Some(km) = previous_location.distance_km(location).filter(|km| *km >= MIN_TRAVEL_KM) {
+            let hours = (login.occurred_at - previous.occurred_at).num_seconds().max(1) as f64 / 3600.0;
+            let kmh = km / hours;
+            if kmh > self.max_travel_kmh {
+                events.push(SecurityEvent {
+                    occurred_at: login.occurred_at,
+                    kind: SecurityEventKind::ImpossibleTravel.as_str().to_string(),
+                    subject: login.subject.clone(),
+                    description: format!(
+                        "{} signed in from {} {:.0} km and {:.1} hours after signing in from {}",
+                        login.subject,
+                        location.label(),
+                        km,
+                        hours,
+                        previous_location.label()
+                    ),
+                    details: json!({
+                        "ip_address": login.ip_address,
+                        "location": location,
+                        "previous_ip_address": previous.ip_address,
+                        "previous_location": previous_location,
+                        "previous_at": previous.occurred_at,
+                        "km": km.round(),
+                        "kmh": kmh.round(),
+                    }),
+                });
+            }
+        }
+        events
+    }
+
+    /// Event for a subject refused `failures` times within the failure window
+    /// ending at `now`, if that reaches the threshold
+    pub fn check_failures(&self, subject: &str, failures: u64, now: Timestamp) -> Option<SecurityEvent> {
+        (failures >= u64::from(self.failure_threshold)).then(|| SecurityEvent {
+            occurred_at: now,
+            kind: SecurityEventKind::RepeatedAuthorizationFailures.as_str().to_string(),
+            subject: subject.to_string(),
+            description: format!(
+                "{} was refused {} times in {} minutes",
+                subject, failures, self.failure_window_minutes
+            ),
+            details: json!({ "failures": failures, "window_minutes": self.failure_window_minutes }),
+        })
+    }
+
+    /// Event for a user who opened `patients` patients' records within the
+    /// access window ending at `now`, if that reaches the threshold
+    pub fn check_record_access(&self, subject: &str, patients: u64, now: Timestamp) -> Option<SecurityEvent> {
+        (patients >= u64::from(self.mass_access_threshold)).then(|| SecurityEvent {
+            occurred_at: now,
+            kind: SecurityEventKind::MassRecordAccess.as_str().to_string(),
+            subject: subject.to_string(),
+            description: format!(
+                "{} opened the records of {} patients in {} minutes",
+                subject, patients, self.mass_access_window_minutes
+            ),
+            details: json!({ "patients": patients, "window_minutes": self.mass_access_window_minutes }),
+        })
+    }
+}
+
+/// Security events detected over a date range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityEventReport {
+    /// Start of the range
+    pub start: Timestamp,
+    /// End of the range, exclusive
+    pub end: Timestamp,
+    /// Events, oldest first
+    pub events: Vec<SecurityEvent>,
+}
+
+impl SecurityEventReport {
+    /// Columns of the rendered report
+    pub const COLUMNS: [&'static str; 4] = ["occurred_at", "kind", "subject", "description"];
+
+    /// The events as a table
+    pub fn to_table(&self) -> ReportTable {
+        ReportTable {
+            columns: Self::COLUMNS.iter().map(|column| column.to_string()).collect(),
+            rows: self
+                .events
+                .iter()
+                .map(|event| {
+                    vec![
+                        json!(event.occurred_at.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+                        json!(event.kind),
+                        json!(event.subject),
+                        json!(event.description),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// Render as CSV or PDF
+    pub fn render(&self, format: ReportFormat) -> Vec<u8> {
+        let table = self.to_table();
+        match format {
+            ReportFormat::Csv => table.to_csv().into_bytes(),
+            ReportFormat::Pdf => table.to_pdf(
+                "Security events",
+                &format!(
+                    "{} - {}, {} events",
+                    self.start.format("%d %b %Y %H:%M UTC"),
+                    self.end.format("%d %b %Y %H:%M UTC"),
+                    self.events.len()
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn place(country: &str, city: &str, latitude: f64, longitude: f64) -> Option<GeoLocation> {
+        Some(GeoLocation {
+            country: country.to_string(),
+            city: Some(city.to_string()),
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+        })
+    }
+
+    fn login(hours: i64, location: Option<GeoLocation>) -> AuthEvent {
+        AuthEvent {
+            occurred_at: Utc.with_ymd_and_hms(2026, 5, 4, 8, 0, 0).unwrap() + Duration::hours(hours),
+            subject: "jdoe".to_string(),
+            kind: AuthEventKind::LoginSucceeded,
+            ip_address: Some("203.0.113.7".to_string()),
+            location,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        let portland = place("US", "Portland", 45.52, -122.68).unwrap();
+        let london = place("GB", "London", 51.51, -0.13).unwrap();
+        let km = portland.distance_km(&london).unwrap();
+        assert!((7800.0..8000.0).contains(&km), "{}", km);
+        assert_eq!(portland.distance_km(&GeoLocation { latitude: None, ..london }), None);
+    }
+
+    #[test]
+    fn test_login_anomalies() {
+        let rules = AnomalyRules::default();
+        let portland = || place("US", "Portland", 45.52, -122.68);
+        let history = vec![login(0, portland())];
+
+        // The same city again, and a first sign-in, are not anomalous
+        assert!(rules.check_login(&login(24, portland()), &history).is_empty());
+        assert!(rules.check_login(&login(24, place("GB", "London", 51.51, -0.13)), &[]).is_empty());
+
+        // Seattle is new but reachable in a day
+        let seattle = rules.check_login(&login(24, place("US", "Seattle", 47.61, -122.33)), &history);
+        assert_eq!(seattle.len(), 1);
+        assert_eq!(seattle[0].kind, "new_location");
+        assert_eq!(seattle[0].description, "jdoe signed in from Seattle, US for the first time");
+
+        // London two hours after Portland is not
+        let london = rules.check_login(&login(2, place("GB", "London", 51.51, -0.13)), &history);
+        let kinds: Vec<&str> = london.iter().map(|event| event.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["new_location", "impossible_travel"]);
+        assert_eq!(london[1].details["previous_location"]["city"], "Portland");
+    }
+
+    #[test]
+    fn test_threshold_events() {
+        let rules = AnomalyRules::default();
+        let now = Utc::now();
+        assert!(rules.check_failures("ip:203.0.113.7", 9, now).is_none());
+        let failures = rules.check_failures("ip:203.0.113.7", 10, now).unwrap();
+        assert_eq!(failures.description, "ip:203.0.113.7 was refused 10 times in 15 minutes");
+
+        assert!(rules.check_record_access("jdoe", 49, now).is_none());
+        assert_eq!(rules.check_record_access("jdoe", 80, now).unwrap().details["patients"], 80);
+        assert_eq!(AuthEventKind::parse("access_denied"), Some(AuthEventKind::AccessDenied));
+        assert!(AuthEventKind::LoginFailed.is_failure() && !AuthEventKind::LoginSucceeded.is_failure());
+
+        let violation = SecurityEvent::network_zone_violation("jdoe", "billing", Some("198.51.100.4"), "/api/claims", now);
+        assert_eq!(violation.description, "jdoe used the billing role from 198.51.100.4, outside its network zones");
+        assert_eq!(violation.details["ip_address"], "198.51.100.4");
+    }
+
+    #[test]
+    fn test_security_event_csv() {
+        let rules = AnomalyRules::default();
+        let now = Utc.with_ymd_and_hms(2026, 5, 4, 10, 0, 0).unwrap();
+        let report = SecurityEventReport {
+            start: now - Duration::days(1),
+            end: now,
+            events: vec![rules.check_record_access("jdoe", 80, now).unwrap()],
+        };
+        let csv = String::from_utf8(report.render(ReportFormat::Csv)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "occurred_at,kind,subject,description");
+        assert_eq!(
+            lines[1],
+            "2026-05-04 10:00:00 UTC,mass_record_access,jdoe,jdoe opened the records of 80 patients in 60 minutes"
+        );
+        assert!(report.render(ReportFormat::Pdf).starts_with(b"%PDF-"));
+    }
+}
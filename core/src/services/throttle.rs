@@ -0,0 +1,237 @@
+//! Brute-force throttling
+//!
+//! Global rate limits do not stop a slow guesser aiming at one account or
+//! one patient's verification codes from many addresses. Failed attempts at
+//! a [`ThrottledAction`] are counted per key, the principal or target under
+//! attack (a username, a patient). Once a [`ThrottleRule`]'s limit is reached
+//! within its window the key is locked out, and further attempts are refused
+//! without being checked until the lockout ends. Each lockout is recorded as
+//! a `throttle_lockout` security event.
+
+use crate::services::security_events::{SecurityEvent, SecurityEventKind};
+use crate::types::Timestamp;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// An action guarded against guessing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottledAction {
+    /// Portal sign-in, keyed by username
+    PortalLogin,
+    /// Contact point verification code check, keyed by patient
+    VerificationConfirm,
+}
+
+impl ThrottledAction {
+    /// Name used in security events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThrottledAction::PortalLogin => "portal_login",
+            ThrottledAction::VerificationConfirm => "verification_confirm",
+        }
+    }
+}
+
+/// Limit on failed attempts for one key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThrottleRule {
+    /// Failures within the window that lock the key out
+    pub max_failures: u32,
+    /// Minutes failures are counted over
+    pub window_minutes: i64,
+    /// Minutes the key stays locked out
+    pub lockout_minutes: i64,
+}
+
+impl ThrottleRule {
+    /// Problems with the rule, if any
+    pub fn check(&self) -> Result<(), String> {
+        if self.max_failures == 0 {
+            return Err("max_failures must be at least 1".to_string());
+        }
+        if self.window_minutes <= 0 || self.lockout_minutes <= 0 {
+            return Err("window_minutes and lockout_minutes must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Rules for each throttled action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleRules {
+    /// Wrong passwords per portal username
+    pub portal_login: ThrottleRule,
+    /// Wrong verification codes per patient, across reissued codes
+    pub verification_confirm: ThrottleRule,
+}
+
+impl Default for ThrottleRules {
+    fn default() -> Self {
+        Self {
+            portal_login: ThrottleRule {
+                max_failures: 5,
+                window_minutes: 15,
+                lockout_minutes: 15,
+            },
+            verification_confirm: ThrottleRule {
+                max_failures: 10,
+                window_minutes: 60,
+                lockout_minutes: 60,
+            },
+        }
+    }
+}
+
+impl ThrottleRules {
+    /// Rule for an action
+    pub fn rule(&self, action: ThrottledAction) -> &ThrottleRule {
+        match action {
+            ThrottledAction::PortalLogin => &self.portal_login,
+            ThrottledAction::VerificationConfirm => &self.verification_confirm,
+        }
+    }
+
+    /// Problems with the rules, if any
+    pub fn check(&self) -> Result<(), String> {
+        for action in [ThrottledAction::PortalLogin, ThrottledAction::VerificationConfirm] {
+            self.rule(action).check().map_err(|e| format!("{}: {}", action.as_str(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Recent failures for one key
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Attempts {
+    failures: Vec<Timestamp>,
+    locked_until: Option<Timestamp>,
+}
+
+impl Attempts {
+    /// End of the lockout in force at `now`
+    pub fn locked_until(&self, now: Timestamp) -> Option<Timestamp> {
+        self.locked_until.filter(|until| *until > now)
+    }
+
+    /// Count a failure; returns the end of the lockout when this failure
+    /// starts one
+    pub fn record_failure(&mut self, rule: &ThrottleRule, now: Timestamp) -> Option<Timestamp> {
+        self.failures.retain(|failed| now - *failed < Duration::minutes(rule.window_minutes));
+        self.failures.push(now);
+        if self.locked_until(now).is_some() || self.failures.len() < rule.max_failures as usize {
+            return None;
+        }
+
+        // The lockout starts a fresh count
+        self.failures.clear();
+        let until = now + Duration::minutes(rule.lockout_minutes);
+        self.locked_until = Some(until);
+        Some(until)
+    }
+
+    /// Whether nothing about the key is worth keeping at `now`
+    pub fn is_idle(&self, rule: &ThrottleRule, now: Timestamp) -> bool {
+        self.locked_until(now).is_none()
+            && self
+                .failures
+                .iter()
+                .all(|failed| now - *failed >= Duration::minutes(rule.window_minutes))
+    }
+}
+
+impl SecurityEvent {
+    /// Event for `key` being locked out of `action` after `rule.max_failures`
+    /// failures; the last one came from `ip_address`
+    pub fn throttle_lockout(
+        action: ThrottledAction,
+        key: &str,
+        rule: &ThrottleRule,
+        ip_address: Option<&str>,
+        locked_until: Timestamp,
+        occurred_at: Timestamp,
+    ) -> Self {
+        SecurityEvent {
+            occurred_at,
+            kind: SecurityEventKind::ThrottleLockout.as_str().to_string(),
+            subject: key.to_string(),
+            description: format!(
+                "{} failed {} {} times within {} minutes and is locked out until {}",
+                key,
+                action.as_str(),
+                rule.max_failures,
+                rule.window_minutes,
+                locked_until.format("%Y-%m-%d %H:%M UTC")
+            ),
+            details: json!({
+                "action": action.as_str(),
+                "failures": rule.max_failures,
+                "window_minutes": rule.window_minutes,
+                "locked_until": locked_until,
+                "ip_address": ip_address,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_lockout() {
+        let rule = ThrottleRules::default().portal_login;
+        let start = Utc::now();
+        let mut attempts = Attempts::default();
+
+        for minute in 0..4 {
+            assert_eq!(attempts.record_failure(&rule, start + Duration::minutes(minute)), None);
+        }
+        let locked_at = start + Duration::minutes(4);
+        let until = attempts.record_failure(&rule, locked_at).unwrap();
+        assert_eq!(until, locked_at + Duration::minutes(15));
+        assert_eq!(attempts.locked_until(locked_at + Duration::minutes(1)), Some(until));
+        assert!(!attempts.is_idle(&rule, locked_at));
+
+        // Failures during the lockout do not extend it
+        assert_eq!(attempts.record_failure(&rule, locked_at + Duration::minutes(1)), None);
+        assert_eq!(attempts.locked_until(until), None);
+        assert!(attempts.is_idle(&rule, until + Duration::minutes(15)));
+    }
+
+    #[test]
+    fn test_failures_outside_window_expire() {
+        let rule = ThrottleRules::default().portal_login;
+        let start = Utc::now();
+        let mut attempts = Attempts::default();
+
+        for minute in [0, 10, 20, 30, 40, 50] {
+            assert_eq!(attempts.record_failure(&rule, start + Duration::minutes(minute)), None);
+        }
+        assert_eq!(attempts.locked_until(start + Duration::minutes(50)), None);
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut rules = ThrottleRules::default();
+        assert!(rules.check().is_ok());
+        rules.verification_confirm.lockout_minutes = 0;
+        assert!(rules.check().unwrap_err().starts_with("verification_confirm"));
+
+        let now = Utc::now();
+        let event = SecurityEvent::throttle_lockout(
+            ThrottledAction::PortalLogin,
+            "jdoe",
+            &rules.portal_login,
+            Some("203.0.113.7"),
+            now + Duration::minutes(15),
+            now,
+        );
+        assert_eq!(event.kind, "throttle_lockout");
+        assert_eq!(event.details["action"], "portal_login");
+        assert_eq!(event.details["ip_address"], "203.0.113.7");
+    }
+}
@@ -0,0 +1,275 @@
+//! Electronic signatures
+//!
+//! A practitioner attests a note or order by signing it. The [`Signature`]
+//! records the SHA-256 hash of the signed content together with the signer
+//! and time, and seals all three with an HMAC-SHA256 under the server's
+//! signing key. Anyone holding the key can later check that the content
+//! still hashes the same and that the seal was made by this server.
+//!
+//! Signed content never changes. Later changes are amendments, signed on
+//! their own and always with a reason.
+
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use chrono::SubsecRound;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Longest amendment reason, in characters
+pub const MAX_REASON_CHARS: usize = 1_000;
+
+/// What a signature attests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureKind {
+    /// The signer wrote or reviewed the content and attests it is complete
+    Attestation,
+    /// The signer amended already signed content
+    Amendment,
+}
+
+impl SignatureKind {
+    /// Stored name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignatureKind::Attestation => "attestation",
+            SignatureKind::Amendment => "amendment",
+        }
+    }
+
+    /// Parse the stored name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "attestation" => Some(SignatureKind::Attestation),
+            "amendment" => Some(SignatureKind::Amendment),
+            _ => None,
+        }
+    }
+}
+
+/// A signature over some content of a resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Signature id
+    pub id: Id,
+    /// Signed resource type, e.g. `ClinicalNote`
+    pub target_type: String,
+    /// Signed resource id
+    pub target_id: Id,
+    /// What the signature attests
+    pub kind: SignatureKind,
+    /// Practitioner who signed
+    pub signer_id: Id,
+    /// When they signed, to the microsecond
+    pub signed_at: Timestamp,
+    /// Why signed content was amended; required for amendments
+    pub reason: Option<String>,
+    /// Hex SHA-256 of the signed content
+    pub content_hash: String,
+    /// Hex HMAC-SHA256 over the hash, signer, time and reason
+    pub seal: String,
+}
+
+/// Result of checking a signature against content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    /// The content and the seal are intact
+    Valid,
+    /// The content no longer hashes to the signed hash
+    ContentChanged,
+    /// The seal does not match; the signature record was altered or made with another key
+    InvalidSeal,
+}
+
+/// Hex SHA-256 of content
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Server key that seals signatures
+#[derive(Clone)]
+pub struct SigningKey {
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SigningKey(..)")
+    }
+}
+
+impl SigningKey {
+    /// Key from a configured secret
+    pub fn new(secret: &[u8]) -> Result<Self> {
+        if secret.len() < 32 {
+            return Err(Error::configuration_error("The signing key must be at least 32 bytes"));
+        }
+        Ok(Self { secret: secret.to_vec() })
+    }
+
+    /// Attest content of a resource
+    pub fn attest(&self, target_type: &str, target_id: Id, content: &[u8], signer_id: Id, at: Timestamp) -> Signature {
+        self.sign(target_type, target_id, SignatureKind::Attestation, content, signer_id, None, at)
+    }
+
+    /// Sign an amendment to signed content; the reason is required
+    pub fn amend(
+        &self,
+        target_type: &str,
+        target_id: Id,
+        content: &[u8],
+        signer_id: Id,
+        reason: &str,
+        at: Timestamp,
+    ) -> Result<Signature> {
+        let length = reason.trim().chars().count();
+        if length == 0 || length > MAX_REASON_CHARS {
+            return Err(Error::validation_error_with_field(
+                &format!("Amendments need a reason of at most {} characters", MAX_REASON_CHARS),
+                "reason",
+            ));
+        }
+        Ok(self.sign(
+            target_type,
+            target_id,
+            SignatureKind::Amendment,
+            content,
+            signer_id,
+            Some(reason.trim().to_string()),
+            at,
+        ))
+    }
+
+    /// Check a signature against the content it should cover
+    pub fn verify(&self, signature: &Signature, content: &[u8]) -> Verification {
+        let mut mac = self.mac();
+        mac.update(&sealed_message(signature));
+        let seal_valid = hex_decode(&signature.seal).is_some_and(|seal| mac.verify_slice(&seal).is_ok());
+
+        if !seal_valid {
+            Verification::InvalidSeal
+        } else if content_hash(content) != signature.content_hash {
+            Verification::ContentChanged
+        } else {
+            Verification::Valid
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn sign(
+        &self,
+        target_type: &str,
+        target_id: Id,
+        kind: SignatureKind,
+        content: &[u8],
+        signer_id: Id,
+        reason: Option<String>,
+        at: Timestamp,
+    ) -> Signature {
+        let mut signature = Signature {
+            id: Uuid::new_v4(),
+            target_type: target_type.to_string(),
+            target_id,
+            kind,
+            signer_id,
+            // Stored timestamps keep microseconds; the seal must survive a round trip
+            signed_at: at.trunc_subsecs(6),
+            reason,
+            content_hash: content_hash(content),
+            seal: String::new(),
+        };
+
+        let mut mac = self.mac();
+        mac.update(&sealed_message(&signature));
+        signature.seal = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        signature
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+}
+
+/// Everything a seal covers, one field per line
+fn sealed_message(signature: &Signature) -> Vec<u8> {
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+        signature.id,
+        signature.target_type,
+        signature.target_id,
+        signature.kind.as_str(),
+        signature.signer_id,
+        signature.signed_at.timestamp_micros(),
+        signature.content_hash,
+        signature.reason.as_deref().unwrap_or(""),
+    )
+    .into_bytes()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn key() -> SigningKey {
+        SigningKey::new(b"0123456789abcdef0123456789abcdef").unwrap()
+    }
+
+    #[test]
+    fn test_attestation_round_trip() {
+        let note = Uuid::new_v4();
+        let signature = key().attest("ClinicalNote", note, b"Assessment: stable", Uuid::new_v4(), Utc::now());
+
+        assert_eq!(signature.kind, SignatureKind::Attestation);
+        assert_eq!(signature.content_hash.len(), 64);
+        assert_eq!(key().verify(&signature, b"Assessment: stable"), Verification::Valid);
+        assert_eq!(key().verify(&signature, b"Assessment: unstable"), Verification::ContentChanged);
+
+        let other_key = SigningKey::new(&[7; 32]).unwrap();
+        assert_eq!(other_key.verify(&signature, b"Assessment: stable"), Verification::InvalidSeal);
+    }
+
+    #[test]
+    fn test_tampered_signature_fields() {
+        let signature = key().attest("ClinicalNote", Uuid::new_v4(), b"content", Uuid::new_v4(), Utc::now());
+
+        let mut other_signer = signature.clone();
+        other_signer.signer_id = Uuid::new_v4();
+        assert_eq!(key().verify(&other_signer, b"content"), Verification::InvalidSeal);
+
+        let mut backdated = signature.clone();
+        backdated.signed_at -= chrono::Duration::days(1);
+        assert_eq!(key().verify(&backdated, b"content"), Verification::InvalidSeal);
+    }
+
+    #[test]
+    fn test_amendments_need_a_reason() {
+        let note = Uuid::new_v4();
+        assert!(key().amend("ClinicalNote", note, b"addendum", Uuid::new_v4(), " ", Utc::now()).is_err());
+
+        let signature = key()
+            .amend("ClinicalNote", note, b"addendum", Uuid::new_v4(), "Late lab result", Utc::now())
+            .unwrap();
+        assert_eq!(signature.kind, SignatureKind::Amendment);
+        assert_eq!(signature.reason.as_deref(), Some("Late lab result"));
+        assert_eq!(key().verify(&signature, b"addendum"), Verification::Valid);
+    }
+
+    #[test]
+    fn test_short_keys_are_rejected() {
+        assert!(SigningKey::new(b"short").is_err());
+    }
+}
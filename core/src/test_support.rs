@@ -0,0 +1,872 @@
+//! Builders and fixtures for tests
+//!
+//! Compiled for this crate's tests and, with the `test-support` feature, for
+//! other crates' tests:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! emr-core = { path = "../core", features = ["test-support"] }
+//! ```
+//!
+//! Builders start from the entity's own constructor and only set what a test
+//! asks for, so they keep compiling and producing valid entities when fields
+//! are added:
+//!
+//! ```
+//! use emr_core::test_support::{EncounterBuilder, PatientBuilder};
+//!
+//! let patient = PatientBuilder::new().with_mrn("123").with_age(45).build();
+//! let encounter = EncounterBuilder::new(patient.metadata.id).build();
+//! assert_eq!(patient.age_in_years(), Some(45));
+//! assert_eq!(encounter.subject, patient.metadata.id);
+//! ```
+//!
+//! [`Fixtures`] generates realistic patients, encounters and vital signs
+//! from a seed, the same ones every run.
+
+use crate::domain::Period;
+use crate::domain::values::{
+    Address, AddressUse, AdministrativeGender, ContactPoint, ContactSystem, ContactUse, HumanName, Identifier,
+    IdentifierUse, NameUse,
+};
+use crate::domain::units::UCUM_SYSTEM;
+use crate::domain::{
+    Encounter, EncounterClass, EncounterStatus, Observation, ObservationStatus, ObservationValue, Patient,
+    VitalSign, VITAL_SIGNS_CATEGORY,
+};
+use crate::types::{Id, Timestamp};
+use chrono::{Duration, Months, NaiveDate, Utc};
+
+/// Identifier system fixture MRNs are issued under
+pub const MRN_SYSTEM: &str = "urn:emr:mrn";
+
+/// Builds a [`Patient`], by default an active "Jane Doe" with no other details
+#[derive(Debug, Clone)]
+pub struct PatientBuilder {
+    patient: Patient,
+}
+
+impl PatientBuilder {
+    /// Start from an active patient named Jane Doe
+    pub fn new() -> Self {
+        let patient = Patient::new(vec![name("Jane", "Doe")]).expect("a patient with a name is valid");
+        Self { patient }
+    }
+
+    /// Replace the official name
+    pub fn with_name(mut self, given: &str, family: &str) -> Self {
+        self.patient.names = vec![name(given, family)];
+        self
+    }
+
+    /// Add a medical record number under [`MRN_SYSTEM`]
+    pub fn with_mrn(mut self, mrn: &str) -> Self {
+        self.patient.identifiers.push(Identifier {
+            use_: Some(IdentifierUse::Usual),
+            system: Some(MRN_SYSTEM.to_string()),
+            value: mrn.to_string(),
+        });
+        self
+    }
+
+    /// Born this many years ago today
+    pub fn with_age(self, years: u32) -> Self {
+        let today = Utc::now().date_naive();
+        let birth_date = today.checked_sub_months(Months::new(years * 12)).unwrap_or(today);
+        self.with_birth_date(birth_date)
+    }
+
+    /// Set the date of birth
+    pub fn with_birth_date(mut self, birth_date: NaiveDate) -> Self {
+        self.patient.birth_date = Some(birth_date);
+        self
+    }
+
+    /// Set the administrative gender
+    pub fn with_gender(mut self, gender: AdministrativeGender) -> Self {
+        self.patient.gender = Some(gender);
+        self
+    }
+
+    /// Add a mobile number
+    pub fn with_phone(mut self, phone: &str) -> Self {
+        self.patient.telecom.push(contact(ContactSystem::Phone, phone, ContactUse::Mobile));
+        self
+    }
+
+    /// Add a home email address
+    pub fn with_email(mut self, email: &str) -> Self {
+        self.patient.telecom.push(contact(ContactSystem::Email, email, ContactUse::Home));
+        self
+    }
+
+    /// Add a home address
+    pub fn with_address(mut self, line: &str, city: &str, state: &str, postal_code: &str) -> Self {
+        self.patient.addresses.push(Address {
+            use_: Some(AddressUse::Home),
+            type_: None,
+            text: None,
+            line: vec![line.to_string()],
+            city: Some(city.to_string()),
+            district: None,
+            state: Some(state.to_string()),
+            postal_code: Some(postal_code.to_string()),
+            country: Some("US".to_string()),
+        });
+        self
+    }
+
+    /// Set the managing organization
+    pub fn with_managing_organization(mut self, organization: Id) -> Self {
+        self.patient.managing_organization = Some(organization);
+        self
+    }
+
+    /// Make the record inactive
+    pub fn inactive(mut self) -> Self {
+        self.patient.active = false;
+        self
+    }
+
+    /// The patient
+    pub fn build(self) -> Patient {
+        self.patient
+    }
+}
+
+impl Default for PatientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds an [`Encounter`], by default a planned ambulatory visit
+#[derive(Debug, Clone)]
+pub struct EncounterBuilder {
+    encounter: Encounter,
+}
+
+impl EncounterBuilder {
+    /// Start from a planned ambulatory visit of a patient
+    pub fn new(subject: Id) -> Self {
+        Self {
+            encounter: Encounter::new(EncounterStatus::Planned, EncounterClass::Ambulatory, subject),
+        }
+    }
+
+    /// Set the status, without going through the workflow
+    pub fn with_status(mut self, status: EncounterStatus) -> Self {
+        self.encounter.status = status;
+        self
+    }
+
+    /// Set the class
+    pub fn with_class(mut self, class: EncounterClass) -> Self {
+        self.encounter.class = class;
+        self
+    }
+
+    /// Started at `start`, and ended at `end` if given
+    pub fn with_period(mut self, start: Timestamp, end: Option<Timestamp>) -> Self {
+        self.encounter.period = Some(Period {
+            start: Some(start),
+            end,
+        });
+        self
+    }
+
+    /// Started this many days ago, and still open
+    pub fn started_days_ago(self, days: i64) -> Self {
+        self.with_period(Utc::now() - Duration::days(days), None)
+    }
+
+    /// Add a reason for the visit
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.encounter.reason.push(reason.to_string());
+        self
+    }
+
+    /// Set the organization providing the service
+    pub fn with_service_provider(mut self, organization: Id) -> Self {
+        self.encounter.service_provider = Some(organization);
+        self
+    }
+
+    /// The encounter
+    pub fn build(self) -> Encounter {
+        self.encounter
+    }
+}
+
+/// Builds an [`Observation`], by default a final heart rate of 72/min taken
+/// now
+#[derive(Debug, Clone)]
+pub struct ObservationBuilder {
+    observation: Observation,
+}
+
+impl ObservationBuilder {
+    /// Start from a final heart rate of 72/min for a patient, taken now
+    pub fn new(subject: Id) -> Self {
+        Self::vital_sign(subject, VitalSign::HeartRate, 72.0)
+    }
+
+    /// Start from a final vital sign in its profile unit, taken now
+    pub fn vital_sign(subject: Id, vital_sign: VitalSign, value: f64) -> Self {
+        let mut observation = Observation::new(ObservationStatus::Final, vital_sign.loinc_code().to_string(), subject);
+        observation.category.push(VITAL_SIGNS_CATEGORY.to_string());
+        observation.effective = Some(Utc::now());
+        Self { observation }.with_quantity(value, vital_sign.ucum_unit())
+    }
+
+    /// Set the LOINC code
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.observation.code = code.to_string();
+        self
+    }
+
+    /// Set the status
+    pub fn with_status(mut self, status: ObservationStatus) -> Self {
+        self.observation.status = status;
+        self
+    }
+
+    /// Set the value as a UCUM quantity
+    pub fn with_quantity(mut self, value: f64, unit: &str) -> Self {
+        self.observation.value = Some(ObservationValue::Quantity {
+            value,
+            unit: unit.to_string(),
+            system: Some(UCUM_SYSTEM.to_string()),
+            code: Some(unit.to_string()),
+        });
+        self
+    }
+
+    /// Set the value
+    pub fn with_value(mut self, value: ObservationValue) -> Self {
+        self.observation.value = Some(value);
+        self
+    }
+
+    /// Link the observation to an encounter
+    pub fn with_encounter(mut self, encounter: Id) -> Self {
+        self.observation.encounter = Some(encounter);
+        self
+    }
+
+    /// Set when the observation was taken
+    pub fn with_effective(mut self, effective: Timestamp) -> Self {
+        self.observation.effective = Some(effective);
+        self
+    }
+
+    /// The observation
+    pub fn build(self) -> Observation {
+        self.observation
+    }
+}
+
+const FEMALE_NAMES: [&str; 8] = ["Maria", "Aisha", "Emily", "Mei", "Olivia", "Fatima", "Grace", "Sofia"];
+const MALE_NAMES: [&str; 8] = ["James", "Wei", "Carlos", "Mohammed", "Daniel", "Kwame", "Liam", "Noah"];
+const FAMILY_NAMES: [&str; 10] = [
+    "Smith", "Garcia", "Chen", "Johnson", "Okafor", "Patel", "Nguyen", "Kowalski", "Haddad", "Rivera",
+];
+const CITIES: [(&str, &str, &str); 4] = [
+    ("Springfield", "IL", "62701"),
+    ("Portland", "OR", "97201"),
+    ("Austin", "TX", "73301"),
+    ("Columbus", "OH", "43004"),
+];
+
+/// Generates realistic test data from a seed
+///
+/// The same seed gives the same names, ages, MRNs and measurements; ids and
+/// timestamps are fresh on every call.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    state: u64,
+    next_mrn: u64,
+}
+
+impl Fixtures {
+    /// Generator for a seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+            next_mrn: 1_000_000,
+        }
+    }
+
+    /// Next pseudo-random number (SplitMix64)
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `low..=high`
+    fn between(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next() % (high - low + 1)
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.next() as usize % items.len()]
+    }
+
+    /// A measurement around `typical`, within `spread` either way, to one
+    /// decimal place
+    fn around(&mut self, typical: f64, spread: f64) -> f64 {
+        let offset = (self.next() % 2001) as f64 / 1000.0 - 1.0;
+        ((typical + offset * spread) * 10.0).round() / 10.0
+    }
+
+    /// An adult or child aged 0 to 95, with an MRN, a phone number and a
+    /// home address
+    pub fn patient(&mut self) -> Patient {
+        let female = self.next() & 1 == 0;
+        let (given, gender) = if female {
+            (self.pick(&FEMALE_NAMES), AdministrativeGender::Female)
+        } else {
+            (self.pick(&MALE_NAMES), AdministrativeGender::Male)
+        };
+        let family = self.pick(&FAMILY_NAMES);
+        let (city, state, postal_code) = self.pick(&CITIES);
+        let mrn = format!("MRN{}", self.next_mrn);
+        self.next_mrn += 1;
+
+        PatientBuilder::new()
+            .with_name(given, family)
+            .with_gender(gender)
+            .with_age(self.between(0, 95) as u32)
+            .with_mrn(&mrn)
+            .with_phone(&format!("+1555555{:04}", self.between(100, 199)))
+            .with_address(&format!("{} Main St", self.between(1, 9999)), city, state, postal_code)
+            .build()
+    }
+
+    /// `count` patients
+    pub fn patients(&mut self, count: usize) -> Vec<Patient> {
+        (0..count).map(|_| self.patient()).collect()
+    }
+
+    /// A finished ambulatory visit of the patient within the last year,
+    /// lasting 15 to 60 minutes
+    pub fn encounter(&mut self, patient: &Patient) -> Encounter {
+        let start = Utc::now() - Duration::hours(self.between(1, 365 * 24) as i64);
+        let end = start + Duration::minutes(self.between(15, 60) as i64);
+        EncounterBuilder::new(patient.metadata.id)
+            .with_status(EncounterStatus::Finished)
+            .with_period(start, Some(end))
+            .with_reason("Follow-up")
+            .build()
+    }
+
+    /// One of each vital sign for the patient, in normal adult ranges,
+    /// taken at `effective`
+    pub fn vital_signs(&mut self, patient: &Patient, effective: Timestamp) -> Vec<Observation> {
+        VitalSign::ALL
+            .into_iter()
+            .map(|vital_sign| {
+                let value = match vital_sign {
+                    VitalSign::SystolicBloodPressure => self.around(118.0, 12.0),
+                    VitalSign::DiastolicBloodPressure => self.around(76.0, 8.0),
+                    VitalSign::HeartRate => self.around(72.0, 12.0).round(),
+                    VitalSign::RespiratoryRate => self.around(15.0, 3.0).round(),
+                    VitalSign::BodyTemperature => self.around(36.9, 0.4),
+                    VitalSign::OxygenSaturation => self.around(97.5, 1.5).min(100.0),
+                };
+                ObservationBuilder::vital_sign(patient.metadata.id, vital_sign, value)
+                    .with_effective(effective)
+                    .build()
+            })
+            .collect()
+    }
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+fn name(given: &str, family: &str) -> HumanName {
+    HumanName {
+        given: vec![given.to_string()],
+        family: family.to_string(),
+        prefix: None,
+        suffix: None,
+        use_: Some(NameUse::Official),
+    }
+}
+
+fn contact(system: ContactSystem, value: &str, use_: ContactUse) -> ContactPoint {
+    ContactPoint {
+        system,
+        value: value.to_string(),
+        use_: Some(use_),
+        rank: None,
+        verified_at: None,
+    }
+}
+
+/// proptest strategies generating valid entities
+///
+/// Generated entities pass their domain validation, so properties can be
+/// stated over everything a user could store, such as converting to FHIR
+/// and back:
+///
+/// ```
+/// use emr_core::domain::traits::FhirConvertible;
+/// use emr_core::test_support::strategies;
+/// use proptest::prelude::*;
+///
+/// proptest!(|(patient in strategies::patient())| {
+///     prop_assert!(patient.to_fhir().is_ok());
+/// });
+/// ```
+pub mod strategies {
+    use crate::domain::values::{
+        Address, AddressType, AddressUse, AdministrativeGender, ContactPoint, ContactSystem, ContactUse, HumanName,
+        Identifier, IdentifierUse, NameUse,
+    };
+    use crate::domain::{
+        Attachment, ContactRelationship, DeceasedInfo, Encounter, EncounterClass, EncounterStatus, MaritalStatus,
+        MultipleBirth, Observation, ObservationStatus, Patient, PatientCommunication, PatientContact, PatientLink,
+        PatientLinkType, VitalSign,
+    };
+    use crate::domain::patient::Period;
+    use crate::types::{EntityMetadata, Id, Timestamp};
+    use chrono::{DateTime, NaiveDate};
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::*;
+    use uuid::Uuid;
+
+    /// Short non-blank text
+    pub fn text() -> impl Strategy<Value = String> {
+        "[A-Za-z][A-Za-z0-9 .'-]{0,23}"
+    }
+
+    /// Any id
+    pub fn id() -> impl Strategy<Value = Id> {
+        any::<u128>().prop_map(Uuid::from_u128)
+    }
+
+    /// Instants between 1970 and 2100, to the nanosecond
+    pub fn timestamp() -> impl Strategy<Value = Timestamp> {
+        (0i64..4_102_444_800, 0u32..1_000_000_000)
+            .prop_map(|(seconds, nanos)| DateTime::from_timestamp(seconds, nanos).expect("in range"))
+    }
+
+    /// Birth dates from 1900 to the start of 2024, none in the future
+    pub fn birth_date() -> impl Strategy<Value = NaiveDate> {
+        let first = NaiveDate::from_ymd_opt(1900, 1, 1).expect("valid date");
+        (0u64..45_290).prop_map(move |days| first + chrono::Days::new(days))
+    }
+
+    /// Metadata updated at or after its creation
+    pub fn metadata() -> impl Strategy<Value = EntityMetadata> {
+        (id(), timestamp(), timestamp(), 1u64..10_000).prop_map(|(id, a, b, version)| EntityMetadata {
+            id,
+            created_at: a.min(b),
+            updated_at: a.max(b),
+            version,
+        })
+    }
+
+    /// Any administrative gender
+    pub fn gender() -> impl Strategy<Value = AdministrativeGender> {
+        prop_oneof![
+            Just(AdministrativeGender::Male),
+            Just(AdministrativeGender::Female),
+            Just(AdministrativeGender::Other),
+            Just(AdministrativeGender::Unknown),
+        ]
+    }
+
+    /// A name with one or two given names
+    pub fn human_name() -> impl Strategy<Value = HumanName> {
+        let use_ = prop_oneof![
+            Just(NameUse::Usual),
+            Just(NameUse::Official),
+            Just(NameUse::Temp),
+            Just(NameUse::Nickname),
+            Just(NameUse::Anonymous),
+            Just(NameUse::Old),
+            Just(NameUse::Maiden),
+        ];
+        (vec(text(), 1..3), text(), option::of(text()), option::of(text()), option::of(use_)).prop_map(
+            |(given, family, prefix, suffix, use_)| HumanName {
+                given,
+                family,
+                prefix,
+                suffix,
+                use_,
+            },
+        )
+    }
+
+    /// An identifier, with or without use and system
+    pub fn identifier() -> impl Strategy<Value = Identifier> {
+        let use_ = prop_oneof![
+            Just(IdentifierUse::Usual),
+            Just(IdentifierUse::Official),
+            Just(IdentifierUse::Temp),
+            Just(IdentifierUse::Secondary),
+            Just(IdentifierUse::Old),
+        ];
+        (option::of(use_), option::of("urn:[a-z]{2,8}:[a-z]{2,8}"), "[A-Z0-9]{1,12}")
+            .prop_map(|(use_, system, value)| Identifier { use_, system, value })
+    }
+
+    /// A contact point of any system and use, some verified
+    pub fn contact_point() -> impl Strategy<Value = ContactPoint> {
+        let system = prop_oneof![
+            Just(ContactSystem::Phone),
+            Just(ContactSystem::Fax),
+            Just(ContactSystem::Email),
+            Just(ContactSystem::Pager),
+            Just(ContactSystem::Url),
+            Just(ContactSystem::Sms),
+            Just(ContactSystem::Other),
+        ];
+        let use_ = prop_oneof![
+            Just(ContactUse::Home),
+            Just(ContactUse::Work),
+            Just(ContactUse::Temp),
+            Just(ContactUse::Old),
+            Just(ContactUse::Mobile),
+        ];
+        (system, "[a-z0-9+@.]{1,30}", option::of(use_), option::of(1u32..10), option::of(timestamp())).prop_map(
+            |(system, value, use_, rank, verified_at)| ContactPoint {
+                system,
+                value,
+                use_,
+                rank,
+                verified_at,
+            },
+        )
+    }
+
+    /// An address with any of its parts
+    pub fn address() -> impl Strategy<Value = Address> {
+        let use_ = prop_oneof![
+            Just(AddressUse::Home),
+            Just(AddressUse::Work),
+            Just(AddressUse::Temp),
+            Just(AddressUse::Old),
+            Just(AddressUse::Billing),
+        ];
+        let type_ = prop_oneof![Just(AddressType::Postal), Just(AddressType::Physical), Just(AddressType::Both)];
+        (
+            option::of(use_),
+            option::of(type_),
+            option::of(text()),
+            vec(text(), 0..3),
+            option::of(text()),
+            option::of(text()),
+            option::of(text()),
+            option::of("[0-9]{5}"),
+            option::of(text()),
+        )
+            .prop_map(
+                |(use_, type_, text, line, city, district, state, postal_code, country)| Address {
+                    use_,
+                    type_,
+                    text,
+                    line,
+                    city,
+                    district,
+                    state,
+                    postal_code,
+                    country,
+                },
+            )
+    }
+
+    /// Either flag, or the time of death; patients store `Boolean(false)` as
+    /// `None` ([`DeceasedInfo::normalized`])
+    pub fn deceased() -> impl Strategy<Value = DeceasedInfo> {
+        prop_oneof![any::<bool>().prop_map(DeceasedInfo::Boolean), timestamp().prop_map(DeceasedInfo::DateTime)]
+    }
+
+    /// Either flag, or a birth order of 1 or more
+    pub fn multiple_birth() -> impl Strategy<Value = MultipleBirth> {
+        prop_oneof![any::<bool>().prop_map(MultipleBirth::Boolean), (1u32..=12).prop_map(MultipleBirth::Integer)]
+    }
+
+    /// Any marital status, unknown included
+    pub fn marital_status() -> impl Strategy<Value = MaritalStatus> {
+        prop_oneof![
+            Just(MaritalStatus::Annulled),
+            Just(MaritalStatus::Divorced),
+            Just(MaritalStatus::Interlocutory),
+            Just(MaritalStatus::LegallySerarated),
+            Just(MaritalStatus::Married),
+            Just(MaritalStatus::Polygamous),
+            Just(MaritalStatus::NeverMarried),
+            Just(MaritalStatus::DomesticPartner),
+            Just(MaritalStatus::Unmarried),
+            Just(MaritalStatus::Widowed),
+            Just(MaritalStatus::Unknown),
+        ]
+    }
+
+    /// A PNG or JPEG photo under the upload limit
+    pub fn photo() -> impl Strategy<Value = Attachment> {
+        (
+            prop_oneof![Just("image/png"), Just("image/jpeg")],
+            option::of("[a-z]{2}"),
+            option::of(vec(any::<u8>(), 0..64)),
+            option::of("https://[a-z]{3,10}\\.example/[a-z0-9]{1,10}"),
+            option::of(0u64..21_000_000),
+            option::of("[A-Za-z0-9+/]{27}="),
+            option::of(text()),
+            option::of(timestamp()),
+        )
+            .prop_map(|(content_type, language, data, url, size, hash, title, creation)| Attachment {
+                content_type: content_type.to_string(),
+                language,
+                data,
+                url,
+                size,
+                hash,
+                title,
+                creation,
+            })
+    }
+
+    fn period() -> impl Strategy<Value = Period> {
+        (option::of(timestamp()), option::of(timestamp())).prop_map(|(start, end)| match (start, end) {
+            (Some(start), Some(end)) => Period {
+                start: Some(start.min(end)),
+                end: Some(start.max(end)),
+            },
+            (start, end) => Period { start, end },
+        })
+    }
+
+    /// A contact with coded and free-text relationships
+    pub fn contact() -> impl Strategy<Value = PatientContact> {
+        let relationship = prop_oneof![
+            Just(ContactRelationship::EmergencyContact),
+            Just(ContactRelationship::Guardian),
+            Just(ContactRelationship::Parent),
+            Just(ContactRelationship::Spouse),
+            Just(ContactRelationship::Child),
+            Just(ContactRelationship::Sibling),
+            text().prop_map(ContactRelationship::Other),
+        ];
+        (
+            vec(relationship, 0..3),
+            option::of(human_name()),
+            vec(contact_point(), 0..2),
+            option::of(address()),
+            option::of(gender()),
+            option::of(id()),
+            option::of(period()),
+        )
+            .prop_map(|(relationships, name, telecom, address, gender, organization, period)| PatientContact {
+                relationships,
+                name,
+                telecom,
+                address,
+                gender,
+                organization,
+                period,
+            })
+    }
+
+    /// A link to another patient record
+    pub fn link() -> impl Strategy<Value = PatientLink> {
+        let type_ = prop_oneof![
+            Just(PatientLinkType::ReplacedBy),
+            Just(PatientLinkType::Replaces),
+            Just(PatientLinkType::Refer),
+            Just(PatientLinkType::Seealso),
+        ];
+        (id(), type_).prop_map(|(other, type_)| PatientLink { other, type_ })
+    }
+
+    prop_compose! {
+        /// Contacts, photos and the other details patients seldom have
+        fn patient_details()(
+            photos in vec(photo(), 0..2),
+            contacts in vec(contact(), 0..2),
+            communications in vec(("[a-z]{2}(-[A-Z]{2})?", any::<bool>()), 0..3),
+            managing_organization in option::of(id()),
+            links in vec(link(), 0..2),
+            active in any::<bool>(),
+        ) -> (Vec<Attachment>, Vec<PatientContact>, Vec<PatientCommunication>, Option<Id>, Vec<PatientLink>, bool) {
+            let communications = communications
+                .into_iter()
+                .map(|(language, preferred)| PatientCommunication { language, preferred })
+                .collect();
+            (photos, contacts, communications, managing_organization, links, active)
+        }
+    }
+
+    prop_compose! {
+        /// A valid patient, every field and choice-type variant included
+        pub fn patient()(
+            metadata in metadata(),
+            names in vec(human_name(), 1..3),
+            identifiers in vec(identifier(), 0..3),
+            telecom in vec(contact_point(), 0..3),
+            gender in option::of(gender()),
+            birth_date in option::of(birth_date()),
+            deceased in option::of(deceased()),
+            addresses in vec(address(), 0..2),
+            marital_status in option::of(marital_status()),
+            multiple_birth in option::of(multiple_birth()),
+            details in patient_details(),
+        ) -> Patient {
+            let (photos, contacts, communications, managing_organization, links, active) = details;
+            let mut patient = Patient::new(names).expect("names are generated");
+            patient.metadata = metadata;
+            patient.identifiers = identifiers;
+            patient.telecom = telecom;
+            patient.gender = gender;
+            patient.birth_date = birth_date;
+            patient.deceased = deceased.and_then(DeceasedInfo::normalized);
+            patient.addresses = addresses;
+            patient.marital_status = marital_status;
+            patient.multiple_birth = multiple_birth;
+            patient.photos = photos;
+            patient.contacts = contacts;
+            patient.communications = communications;
+            patient.managing_organization = managing_organization;
+            patient.links = links;
+            patient.active = active;
+            patient
+        }
+    }
+
+    /// Any encounter status
+    pub fn encounter_status() -> impl Strategy<Value = EncounterStatus> {
+        prop_oneof![
+            Just(EncounterStatus::Planned),
+            Just(EncounterStatus::Arrived),
+            Just(EncounterStatus::Triaged),
+            Just(EncounterStatus::InProgress),
+            Just(EncounterStatus::Onleave),
+            Just(EncounterStatus::Finished),
+            Just(EncounterStatus::Cancelled),
+            Just(EncounterStatus::EnteredInError),
+            Just(EncounterStatus::Unknown),
+        ]
+    }
+
+    /// Any encounter class
+    pub fn encounter_class() -> impl Strategy<Value = EncounterClass> {
+        prop_oneof![
+            Just(EncounterClass::Inpatient),
+            Just(EncounterClass::Outpatient),
+            Just(EncounterClass::Ambulatory),
+            Just(EncounterClass::Emergency),
+            Just(EncounterClass::Home),
+            Just(EncounterClass::Field),
+            Just(EncounterClass::Daytime),
+            Just(EncounterClass::Virtual),
+        ]
+    }
+
+    prop_compose! {
+        /// An encounter of a patient
+        pub fn encounter(subject: Id)(
+            status in encounter_status(),
+            class in encounter_class(),
+            period in option::of((timestamp(), option::of(timestamp()))),
+            reasons in vec(text(), 0..3),
+            service_provider in option::of(id()),
+        ) -> Encounter {
+            let mut builder = super::EncounterBuilder::new(subject).with_status(status).with_class(class);
+            if let Some((start, end)) = period {
+                builder = builder.with_period(start, end.map(|end| end.max(start)));
+            }
+            for reason in &reasons {
+                builder = builder.with_reason(reason);
+            }
+            if let Some(provider) = service_provider {
+                builder = builder.with_service_provider(provider);
+            }
+            builder.build()
+        }
+    }
+
+    /// Any observation status
+    pub fn observation_status() -> impl Strategy<Value = ObservationStatus> {
+        prop_oneof![
+            Just(ObservationStatus::Registered),
+            Just(ObservationStatus::Preliminary),
+            Just(ObservationStatus::Final),
+            Just(ObservationStatus::Amended),
+            Just(ObservationStatus::Corrected),
+            Just(ObservationStatus::Cancelled),
+            Just(ObservationStatus::EnteredInError),
+            Just(ObservationStatus::Unknown),
+        ]
+    }
+
+    /// A vital sign of a patient, within the sign's plausible range
+    pub fn vital_sign_observation(subject: Id) -> impl Strategy<Value = Observation> {
+        (proptest::sample::select(VitalSign::ALL.to_vec()), observation_status(), timestamp())
+            .prop_flat_map(move |(vital_sign, status, effective)| {
+                let (low, high) = vital_sign.plausible_range();
+                (low..=high).prop_map(move |value| {
+                    super::ObservationBuilder::vital_sign(subject, vital_sign, value)
+                        .with_status(status.clone())
+                        .with_effective(effective)
+                        .build()
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::traits::Validatable;
+
+    #[test]
+    fn test_builders_make_valid_entities() {
+        let patient = PatientBuilder::new().with_mrn("123").with_age(45).with_email("jane@example.com").build();
+        assert_eq!(patient.age_in_years(), Some(45));
+        assert_eq!(patient.primary_identifier().map(|id| id.value.as_str()), Some("123"));
+        Validatable::validate(&patient).unwrap();
+
+        let encounter = EncounterBuilder::new(patient.metadata.id).started_days_ago(3).build();
+        Validatable::validate(&encounter).unwrap();
+
+        let observation = ObservationBuilder::new(patient.metadata.id).with_encounter(encounter.metadata.id).build();
+        assert_eq!(observation.code, "8867-4");
+        Validatable::validate(&observation).unwrap();
+    }
+
+    #[test]
+    fn test_fixtures_are_realistic_and_repeatable() {
+        let patients = Fixtures::new(7).patients(20);
+        let again = Fixtures::new(7).patients(20);
+        for (patient, same) in patients.iter().zip(&again) {
+            assert_eq!(patient.names[0].family, same.names[0].family);
+            assert_eq!(patient.birth_date, same.birth_date);
+            assert!(patient.age_in_years().unwrap() <= 95);
+            Validatable::validate(patient).unwrap();
+        }
+        assert_eq!(patients[0].identifiers[0].value, "MRN1000000");
+        assert_eq!(patients[19].identifiers[0].value, "MRN1000019");
+
+        let mut fixtures = Fixtures::default();
+        let vitals = fixtures.vital_signs(&patients[0], Utc::now());
+        assert_eq!(vitals.len(), VitalSign::ALL.len());
+        for (vital_sign, observation) in VitalSign::ALL.iter().zip(&vitals) {
+            let Some(ObservationValue::Quantity { value, unit, .. }) = &observation.value else {
+                panic!("vital signs are quantities");
+            };
+            assert!(vital_sign.measurement(*value, unit).is_ok(), "{:?} of {}", vital_sign, value);
+            assert_eq!(observation.coding().1, vital_sign.loinc_code());
+        }
+    }
+}
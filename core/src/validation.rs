@@ -0,0 +1,372 @@
+//! Data validation profiles
+//!
+//! A profile is a versioned rule set for one entity type, either for one
+//! tenant (organization) or, without a tenant, for every tenant that has
+//! none of its own. Rules address fields of the entity's FHIR JSON by dotted
+//! path (`class.code`, `participant.individual.reference`), descending into
+//! arrays. The API evaluates the active profile when entities are created or
+//! updated and rejects them in strict mode; the DataValidation job evaluates
+//! stored entities in batch against the same rules.
+
+use crate::types::{Id, Timestamp};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Entity types profiles can be defined for
+pub const PROFILE_ENTITY_TYPES: [&str; 3] = ["Patient", "Encounter", "Observation"];
+
+/// How bad a rule violation is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSeverity {
+    /// Reported only
+    Info,
+    /// Reported only
+    Warning,
+    /// Blocks the write in strict mode
+    Error,
+    /// Blocks the write in strict mode
+    Critical,
+}
+
+/// What a rule checks about a field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum RuleCheck {
+    /// The field is present and not null, empty or blank
+    Required,
+    /// String values are one of `values`
+    OneOf {
+        /// Allowed values
+        values: Vec<String>,
+    },
+    /// Numeric values lie within the bounds, inclusive
+    Range {
+        /// Lower bound
+        #[serde(default)]
+        min: Option<f64>,
+        /// Upper bound
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    /// String lengths (in characters) or array lengths lie within the bounds
+    Length {
+        /// Shortest allowed
+        #[serde(default)]
+        min: Option<usize>,
+        /// Longest allowed
+        #[serde(default)]
+        max: Option<usize>,
+    },
+}
+
+/// A rule applied to one field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRule {
+    /// Unique within the profile
+    pub name: String,
+    /// Shown to users when the rule is violated
+    pub description: String,
+    /// Dotted path into the entity's FHIR JSON
+    pub field: String,
+    /// Check applied to the field
+    #[serde(flatten)]
+    pub check: RuleCheck,
+    /// Severity of a violation
+    pub severity: RuleSeverity,
+}
+
+/// A version of a tenant's rules for one entity type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationProfile {
+    /// Id of this version
+    pub id: Id,
+    /// Organization the profile belongs to; `None` is the default profile
+    pub tenant_id: Option<Id>,
+    /// Entity type, one of [`PROFILE_ENTITY_TYPES`]
+    pub entity_type: String,
+    /// Starts at 1 and grows with each edit
+    pub version: i32,
+    /// Reject creates and updates that violate an error or critical rule
+    pub strict: bool,
+    /// Rules, evaluated in order
+    pub rules: Vec<ProfileRule>,
+    /// When this version was saved
+    pub created_at: Timestamp,
+}
+
+/// A failed rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleViolation {
+    /// Rule name
+    pub rule: String,
+    /// Field path
+    pub field: String,
+    /// Rule severity
+    pub severity: RuleSeverity,
+    /// Rule description followed by what was found
+    pub message: String,
+}
+
+/// Outcome of evaluating a profile against one entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationReport {
+    /// Profile version evaluated
+    pub profile_id: Id,
+    /// Version number evaluated
+    pub profile_version: i32,
+    /// Violations in rule order
+    pub violations: Vec<RuleViolation>,
+}
+
+impl ValidationReport {
+    /// Whether an error or critical rule failed
+    pub fn has_errors(&self) -> bool {
+        self.violations.iter().any(|violation| violation.severity >= RuleSeverity::Error)
+    }
+
+    /// Why a strict profile rejects the entity, listing what failed
+    pub fn rejection(&self) -> String {
+        let messages: Vec<&str> = self.violations.iter().map(|violation| violation.message.as_str()).collect();
+        format!("Rejected by validation profile v{}: {}", self.profile_version, messages.join("; "))
+    }
+}
+
+/// Check a rule set before it is saved
+pub fn validate_rules(entity_type: &str, rules: &[ProfileRule]) -> Result<()> {
+    if !PROFILE_ENTITY_TYPES.contains(&entity_type) {
+        return Err(Error::validation_error_with_field(
+            &format!("Profiles are not supported for '{}'", entity_type),
+            "entity_type",
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for rule in rules {
+        if rule.name.trim().is_empty() || !names.insert(rule.name.as_str()) {
+            return Err(Error::validation_error_with_field(
+                &format!("Rule names must be unique and not blank ('{}')", rule.name),
+                "rules",
+            ));
+        }
+        if rule.field.split('.').any(|segment| segment.trim().is_empty()) {
+            return Err(Error::validation_error_with_field(
+                &format!("Rule '{}' has an invalid field path '{}'", rule.name, rule.field),
+                "rules",
+            ));
+        }
+        let inverted = match &rule.check {
+            RuleCheck::Range { min: Some(min), max: Some(max) } => min > max,
+            RuleCheck::Length { min: Some(min), max: Some(max) } => min > max,
+            RuleCheck::OneOf { values } => values.is_empty(),
+            _ => false,
+        };
+        if inverted {
+            return Err(Error::validation_error_with_field(
+                &format!("Rule '{}' can never pass", rule.name),
+                "rules",
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl ValidationProfile {
+    /// Evaluate every rule against an entity's FHIR JSON
+    pub fn evaluate(&self, entity: &Value) -> ValidationReport {
+        let violations = self
+            .rules
+            .iter()
+            .filter_map(|rule| check_rule(rule, entity))
+            .collect();
+
+        ValidationReport {
+            profile_id: self.id,
+            profile_version: self.version,
+            violations,
+        }
+    }
+}
+
+fn check_rule(rule: &ProfileRule, entity: &Value) -> Option<RuleViolation> {
+    let values = values_at(entity, &rule.field);
+    let problem = match &rule.check {
+        RuleCheck::Required => values.iter().all(|value| is_blank(value)).then(|| "missing".to_string()),
+        RuleCheck::OneOf { values: allowed } => values
+            .iter()
+            .filter_map(|value| value.as_str())
+            .find(|value| !allowed.iter().any(|allowed| allowed == value))
+            .map(|value| format!("'{}' is not allowed", value)),
+        RuleCheck::Range { min, max } => values
+            .iter()
+            .filter_map(|value| value.as_f64())
+            .find(|value| min.is_some_and(|min| *value < min) || max.is_some_and(|max| *value > max))
+            .map(|value| format!("{} is out of range", value)),
+        RuleCheck::Length { min, max } => values
+            .iter()
+            .filter_map(|value| match value {
+                Value::String(text) => Some(text.chars().count()),
+                Value::Array(items) => Some(items.len()),
+                _ => None,
+            })
+            .find(|len| min.is_some_and(|min| *len < min) || max.is_some_and(|max| *len > max))
+            .map(|len| format!("length {} is out of bounds", len)),
+    }?;
+
+    Some(RuleViolation {
+        rule: rule.name.clone(),
+        field: rule.field.clone(),
+        severity: rule.severity,
+        message: format!("{}: {}", rule.description, problem),
+    })
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// Values found by following a dotted path through objects and arrays.
+/// The last segment's arrays are kept whole so their length can be checked.
+fn values_at<'a>(entity: &'a Value, path: &str) -> Vec<&'a Value> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let mut nodes = vec![entity];
+
+    for (index, segment) in segments.iter().enumerate() {
+        let last = index + 1 == segments.len();
+        nodes = nodes
+            .into_iter()
+            .filter_map(|node| node.get(segment))
+            .flat_map(|node| match node {
+                Value::Array(items) if !last => items.iter().collect::<Vec<_>>(),
+                node => vec![node],
+            })
+            .collect();
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(name: &str, field: &str, check: RuleCheck, severity: RuleSeverity) -> ProfileRule {
+        ProfileRule {
+            name: name.to_string(),
+            description: format!("{} check", name),
+            field: field.to_string(),
+            check,
+            severity,
+        }
+    }
+
+    fn profile(rules: Vec<ProfileRule>) -> ValidationProfile {
+        ValidationProfile {
+            id: uuid::Uuid::new_v4(),
+            tenant_id: None,
+            entity_type: "Encounter".to_string(),
+            version: 3,
+            strict: true,
+            rules,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_rules() {
+        let profile = profile(vec![
+            rule("reason", "reasonCode", RuleCheck::Required, RuleSeverity::Error),
+            rule(
+                "class",
+                "class.code",
+                RuleCheck::OneOf { values: vec!["AMB".to_string(), "IMP".to_string()] },
+                RuleSeverity::Error,
+            ),
+            rule(
+                "participants",
+                "participant",
+                RuleCheck::Length { min: Some(1), max: Some(4) },
+                RuleSeverity::Warning,
+            ),
+            rule("practitioner", "participant.individual.reference", RuleCheck::Required, RuleSeverity::Info),
+        ]);
+
+        let encounter = json!({
+            "resourceType": "Encounter",
+            "class": {"code": "EMER"},
+            "participant": [{"individual": {"reference": "Practitioner/a"}}]
+        });
+        let report = profile.evaluate(&encounter);
+        let failed: Vec<&str> = report.violations.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(failed, vec!["reason", "class"]);
+        assert_eq!(report.violations[1].message, "class check: 'EMER' is not allowed");
+        assert_eq!(report.profile_version, 3);
+        assert!(report.has_errors());
+
+        let encounter = json!({
+            "class": {"code": "AMB"},
+            "reasonCode": [{"text": "Follow-up"}],
+            "participant": []
+        });
+        let report = profile.evaluate(&encounter);
+        let failed: Vec<&str> = report.violations.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(failed, vec!["participants", "practitioner"]);
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn test_range_skips_absent_values() {
+        let profile = profile(vec![rule(
+            "heart_rate",
+            "valueQuantity.value",
+            RuleCheck::Range { min: Some(20.0), max: Some(300.0) },
+            RuleSeverity::Critical,
+        )]);
+
+        assert!(profile.evaluate(&json!({"valueQuantity": {"value": 72}})).violations.is_empty());
+        assert!(profile.evaluate(&json!({"valueString": "n/a"})).violations.is_empty());
+        assert!(profile.evaluate(&json!({"valueQuantity": {"value": 720}})).has_errors());
+    }
+
+    #[test]
+    fn test_validate_rules() {
+        let required = rule("reason", "reasonCode", RuleCheck::Required, RuleSeverity::Error);
+        assert!(validate_rules("Encounter", std::slice::from_ref(&required)).is_ok());
+        assert!(validate_rules("Practitioner", std::slice::from_ref(&required)).is_err());
+        assert!(validate_rules("Encounter", &[required.clone(), required.clone()]).is_err());
+
+        let bad_path = rule("bad", "class..code", RuleCheck::Required, RuleSeverity::Error);
+        assert!(validate_rules("Encounter", &[bad_path]).is_err());
+
+        let inverted = rule(
+            "inverted",
+            "valueQuantity.value",
+            RuleCheck::Range { min: Some(10.0), max: Some(1.0) },
+            RuleSeverity::Error,
+        );
+        assert!(validate_rules("Observation", &[inverted]).is_err());
+    }
+
+    #[test]
+    fn test_rule_serialization() {
+        let rule: ProfileRule = serde_json::from_value(json!({
+            "name": "class",
+            "description": "Class must be ambulatory",
+            "field": "class.code",
+            "check": "one_of",
+            "values": ["AMB"],
+            "severity": "error"
+        }))
+        .unwrap();
+        assert_eq!(rule.check, RuleCheck::OneOf { values: vec!["AMB".to_string()] });
+        assert_eq!(rule.severity, RuleSeverity::Error);
+    }
+}
@@ -0,0 +1,498 @@
+//! End-to-end test harness
+//!
+//! [`TestStack`] starts the platform's infrastructure in containers and the
+//! services against it, the way `infra/docker-compose.yml` wires them:
+//!
+//! - Postgres 16, initialised from `infra/init-db.sql` (the schema the
+//!   compose file mounts as its init script), so tests run against the same
+//!   tables, partitions and row-level security policies as deployments
+//! - optionally NATS and Redis
+//! - the jobs worker, in this process, with its database-backed stores and
+//!   the NATS subscriptions that feed them
+//! - the api binary, as a child process listening on a free local port
+//!
+//! Patients, device readings and exports go through the api's HTTP routes,
+//! as clients use them. Containers and the api process are removed when the
+//! stack is dropped.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let stack = emr_e2e::TestStack::builder().with_jobs().with_api().start().await?;
+//! let result = stack.record_observations(Vec::new()).await?;
+//! assert_eq!(result.received, 0);
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{bail, Context, Result};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHasher, SaltString};
+use argon2::Argon2;
+use deadpool_diesel::postgres::Pool;
+use diesel::RunQueryDsl;
+use emr_core::domain::Patient;
+use emr_core::services::device_observations::IngestResult;
+use emr_jobs::config::DatabaseConfig;
+use emr_jobs::key_rotation::{generate_data_key, seal, DatabaseDataKeyStore};
+use emr_jobs::observations::DatabaseDeviceObservationStore;
+use emr_jobs::{DataKeyStore, JobsConfig, JobsWorker};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, ImageExt};
+use testcontainers_modules::nats::Nats;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::redis::Redis;
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Schema every test database starts from
+const INIT_DB_SQL: &str = include_str!("../../infra/init-db.sql");
+
+/// Database, user and image the compose file runs Postgres with
+const POSTGRES_TAG: &str = "16-alpine";
+const DATABASE_NAME: &str = "emr_platform";
+const DATABASE_USER: &str = "emr_user";
+const DATABASE_PASSWORD: &str = "emr_e2e_password";
+
+/// Secret the api signs and checks session tokens with
+const JWT_SECRET: &str = "emr-e2e-jwt-secret";
+
+/// Issuer the api requires of session tokens
+const TOKEN_ISSUER: &str = "nexus-api";
+
+/// How long a staff token from [`TestStack::staff_token`] lasts
+const STAFF_TOKEN_SECONDS: i64 = 900;
+
+/// How long the api process gets to answer `/healthz`
+const API_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a [`TestStack`] starts besides Postgres
+#[derive(Debug, Clone, Default)]
+pub struct TestStackBuilder {
+    nats: bool,
+    redis: bool,
+    jobs: bool,
+    api: bool,
+    fhir_url: Option<String>,
+}
+
+impl TestStackBuilder {
+    /// Start a NATS server
+    pub fn with_nats(mut self) -> Self {
+        self.nats = true;
+        self
+    }
+
+    /// Start a Redis server
+    pub fn with_redis(mut self) -> Self {
+        self.redis = true;
+        self
+    }
+
+    /// Run the jobs worker in this process. Implies NATS, which device
+    /// batches and submitted jobs reach the worker over.
+    pub fn with_jobs(mut self) -> Self {
+        self.jobs = true;
+        self.nats = true;
+        self
+    }
+
+    /// Run the api binary against the stack
+    pub fn with_api(mut self) -> Self {
+        self.api = true;
+        self
+    }
+
+    /// Point the api at a FHIR server, such as a stub the test serves
+    pub fn with_fhir(mut self, url: &str) -> Self {
+        self.fhir_url = Some(url.to_string());
+        self
+    }
+
+    /// Start the containers, then the services
+    pub async fn start(self) -> Result<TestStack> {
+        let postgres = Postgres::default()
+            .with_db_name(DATABASE_NAME)
+            .with_user(DATABASE_USER)
+            .with_password(DATABASE_PASSWORD)
+            .with_init_sql(INIT_DB_SQL.to_string().into_bytes())
+            .with_tag(POSTGRES_TAG)
+            .start()
+            .await
+            .context("Failed to start Postgres")?;
+        let database_url = format!(
+            "postgresql://{}:{}@127.0.0.1:{}/{}",
+            DATABASE_USER,
+            DATABASE_PASSWORD,
+            postgres.get_host_port_ipv4(5432).await?,
+            DATABASE_NAME
+        );
+        let pool = DatabaseConfig {
+            url: database_url.clone(),
+            ..DatabaseConfig::default()
+        }
+        .pool()
+        .map_err(|e| anyhow::anyhow!("Failed to create database pool: {}", e))?;
+
+        let (nats, nats_url) = if self.nats {
+            let container = Nats::default().start().await.context("Failed to start NATS")?;
+            let url = format!("nats://127.0.0.1:{}", container.get_host_port_ipv4(4222).await?);
+            (Some(container), Some(url))
+        } else {
+            (None, None)
+        };
+        let nats_client = match &nats_url {
+            Some(url) => Some(async_nats::connect(url.as_str()).await.context("Failed to connect to NATS")?),
+            None => None,
+        };
+
+        let (redis, redis_url) = if self.redis {
+            let container = Redis::default().start().await.context("Failed to start Redis")?;
+            let url = format!("redis://127.0.0.1:{}", container.get_host_port_ipv4(6379).await?);
+            (Some(container), Some(url))
+        } else {
+            (None, None)
+        };
+
+        let worker = match (&nats_client, self.jobs) {
+            (Some(client), true) => Some(start_worker(&database_url, pool.clone(), client.clone())),
+            _ => None,
+        };
+
+        let mut stack = TestStack {
+            database_url,
+            nats_url,
+            redis_url,
+            fhir_url: self.fhir_url,
+            api_url: None,
+            pool,
+            http: reqwest::Client::new(),
+            worker,
+            _api: None,
+            _postgres: postgres,
+            _nats: nats,
+            _redis: redis,
+        };
+        if self.api {
+            stack.start_api().await?;
+        }
+        Ok(stack)
+    }
+}
+
+/// Run the jobs worker with its database-backed device observation store
+fn start_worker(database_url: &str, pool: Pool, nats: async_nats::Client) -> JoinHandle<()> {
+    let mut config = JobsConfig::default();
+    config.database.url = database_url.to_string();
+    let worker = JobsWorker::new(config)
+        .with_device_observations(Arc::new(DatabaseDeviceObservationStore::new(pool)))
+        .with_nats(nats);
+    tokio::spawn(async move {
+        if let Err(e) = worker.start().await {
+            panic!("Jobs worker stopped: {}", e);
+        }
+    })
+}
+
+/// The api binary: `EMR_API_BIN`, or the workspace's debug build
+fn api_binary() -> PathBuf {
+    match std::env::var_os("EMR_API_BIN") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/debug/emr-api"),
+    }
+}
+
+/// A local port nothing is listening on
+fn free_port() -> Result<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Infrastructure and services for one test
+pub struct TestStack {
+    /// Postgres initialised from `infra/init-db.sql`
+    pub database_url: String,
+    pub nats_url: Option<String>,
+    pub redis_url: Option<String>,
+    /// FHIR server the api uses, when the test provides one
+    pub fhir_url: Option<String>,
+    /// Base URL of the api, once started
+    pub api_url: Option<String>,
+    pool: Pool,
+    http: reqwest::Client,
+    worker: Option<JoinHandle<()>>,
+    _api: Option<Child>,
+    _postgres: ContainerAsync<Postgres>,
+    _nats: Option<ContainerAsync<Nats>>,
+    _redis: Option<ContainerAsync<Redis>>,
+}
+
+impl TestStack {
+    /// Postgres only; add the rest with the builder
+    pub fn builder() -> TestStackBuilder {
+        TestStackBuilder::default()
+    }
+
+    /// The job database pool the worker's stores use
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    async fn start_api(&mut self) -> Result<()> {
+        let binary = api_binary();
+        if !binary.exists() {
+            bail!("{} not found; build it with `cargo build -p emr-api` or set EMR_API_BIN", binary.display());
+        }
+        let port = free_port()?;
+        let mut command = Command::new(&binary);
+        command
+            .env("PORT", port.to_string())
+            .env("DATABASE_URL", &self.database_url)
+            .env("JWT_SECRET", JWT_SECRET)
+            .kill_on_drop(true);
+        if let Some(url) = &self.nats_url {
+            command.env("NATS_URL", url);
+        }
+        if let Some(url) = &self.redis_url {
+            command.env("REDIS_URL", url);
+        }
+        if let Some(url) = &self.fhir_url {
+            command.env("FHIR_BASE_URL", url);
+        }
+        self._api = Some(command.spawn().with_context(|| format!("Failed to start {}", binary.display()))?);
+
+        let api_url = format!("http://127.0.0.1:{}", port);
+        let started = Instant::now();
+        loop {
+            match self.http.get(format!("{}/healthz", api_url)).send().await {
+                Ok(response) if response.status().is_success() => break,
+                _ if started.elapsed() > API_STARTUP_TIMEOUT => {
+                    bail!("api did not answer /healthz within {} seconds", API_STARTUP_TIMEOUT.as_secs())
+                }
+                _ => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+        self.api_url = Some(api_url);
+        Ok(())
+    }
+
+    /// Base URL of the api's `/api/v1` routes
+    fn api_v1(&self) -> Result<String> {
+        let api_url = self.api_url.as_deref().context("Stack was started without the api")?;
+        Ok(format!("{}/api/v1", api_url))
+    }
+
+    /// The `data` of an api response, or the error it answered with
+    async fn api_data(response: reqwest::Response) -> Result<Value> {
+        let status = response.status();
+        if !status.is_success() {
+            bail!("api answered {}: {}", status, response.text().await.unwrap_or_default());
+        }
+        let mut body: Value = response.json().await?;
+        Ok(body["data"].take())
+    }
+
+    /// Create a patient through `POST /patients`; returns the id the api
+    /// assigned
+    pub async fn create_patient(&self, patient: &Patient) -> Result<Uuid> {
+        let name = patient.names.first().context("Patient has no name")?;
+        let request = json!({
+            "name": name.given.iter().chain(std::iter::once(&name.family)).cloned().collect::<Vec<_>>().join(" "),
+            "gender": patient.gender.as_ref().map(|gender| format!("{:?}", gender).to_lowercase()),
+            "birth_date": patient.birth_date.map(|date| date.to_string()),
+        });
+
+        let response = self.http.post(format!("{}/patients", self.api_v1()?)).json(&request).send().await?;
+        let created = Self::api_data(response).await?;
+        let id = created["id"].as_str().context("Created patient has no id")?;
+        Ok(id.parse()?)
+    }
+
+    /// Give a patient a portal account. Accounts are provisioned by staff
+    /// tooling rather than the api, so this writes `emr.portal_accounts`.
+    pub async fn create_portal_account(&self, patient_id: Uuid, username: &str, password: &str) -> Result<()> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow::anyhow!("Failed to hash the password: {}", e))?
+            .to_string();
+        let username = username.to_string();
+
+        let conn = self.pool.get().await.map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        conn.interact(move |conn| {
+            diesel::sql_query("INSERT INTO emr.portal_accounts (patient_id, username, password_hash) VALUES ($1, $2, $3)")
+                .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                .bind::<diesel::sql_types::Text, _>(username)
+                .bind::<diesel::sql_types::Text, _>(password_hash)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!(e.to_string()))??;
+        Ok(())
+    }
+
+    /// Seal `value` as `field` of a resource under a new data key wrapped by
+    /// `master_key`, returning the data key's id. No api writer encrypts
+    /// fields yet, so this writes `emr.data_keys` and `emr.encrypted_fields`
+    /// the way one would.
+    pub async fn seal_field(
+        &self,
+        master_key_id: &str,
+        master_key: &[u8],
+        resource_type: &str,
+        resource_id: Uuid,
+        field: &str,
+        value: &str,
+    ) -> Result<Uuid> {
+        let data_key = generate_data_key();
+        let store = DatabaseDataKeyStore::new(self.pool.clone());
+        let data_key_id = store.create(master_key_id, &seal(master_key, &data_key)?).await?;
+        let ciphertext = seal(&data_key, value.as_bytes())?;
+        let (resource_type, field) = (resource_type.to_string(), field.to_string());
+
+        let conn = self.pool.get().await.map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.encrypted_fields (resource_type, resource_id, field, data_key_id, ciphertext) \
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind::<diesel::sql_types::Text, _>(resource_type)
+            .bind::<diesel::sql_types::Uuid, _>(resource_id)
+            .bind::<diesel::sql_types::Text, _>(field)
+            .bind::<diesel::sql_types::Uuid, _>(data_key_id)
+            .bind::<diesel::sql_types::Text, _>(ciphertext)
+            .execute(conn)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!(e.to_string()))??;
+        Ok(data_key_id)
+    }
+
+    /// Sign in to the portal through `POST /portal/login`; returns the
+    /// access token
+    pub async fn portal_login(&self, username: &str, password: &str) -> Result<String> {
+        let response = self
+            .http
+            .post(format!("{}/portal/login", self.api_v1()?))
+            .json(&json!({ "username": username, "password": password }))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("Portal login answered {}: {}", status, response.text().await.unwrap_or_default());
+        }
+        let body: Value = response.json().await?;
+        Ok(body["access_token"].as_str().context("Login answered without a token")?.to_string())
+    }
+
+    /// `GET` an api route as the holder of `token`, returning the response's
+    /// `data`
+    pub async fn get_as(&self, token: &str, route: &str) -> Result<Value> {
+        let response = self.http.get(format!("{}{}", self.api_v1()?, route)).bearer_auth(token).send().await?;
+        Self::api_data(response).await
+    }
+
+    /// A session token for a practitioner signed in to the api
+    pub fn staff_token(&self, practitioner_id: Uuid) -> Result<String> {
+        let now = chrono::Utc::now().timestamp();
+        let claims = json!({
+            "sub": practitioner_id.to_string(),
+            "iss": TOKEN_ISSUER,
+            "iat": now,
+            "exp": now + STAFF_TOKEN_SECONDS,
+            "scope": "user/*.*",
+        });
+        Ok(jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        )?)
+    }
+
+    /// `PUT` a JSON body to an api route as the holder of `token`, returning
+    /// the response's `data`
+    pub async fn put_as(&self, token: &str, route: &str, body: &Value) -> Result<Value> {
+        let response =
+            self.http.put(format!("{}{}", self.api_v1()?, route)).bearer_auth(token).json(body).send().await?;
+        Self::api_data(response).await
+    }
+
+    /// `GET` an api route, returning the response's `data`
+    pub async fn get(&self, route: &str) -> Result<Value> {
+        let response = self.http.get(format!("{}{}", self.api_v1()?, route)).send().await?;
+        Self::api_data(response).await
+    }
+
+    /// `POST` a JSON body to an api route, returning the response's `data`
+    pub async fn post(&self, route: &str, body: &Value) -> Result<Value> {
+        let response = self.http.post(format!("{}{}", self.api_v1()?, route)).json(body).send().await?;
+        Self::api_data(response).await
+    }
+
+    /// `PUT` a JSON body to an api route, returning the response's `data`
+    pub async fn put(&self, route: &str, body: &Value) -> Result<Value> {
+        let response = self.http.put(format!("{}{}", self.api_v1()?, route)).json(body).send().await?;
+        Self::api_data(response).await
+    }
+
+    /// `DELETE` an api route
+    pub async fn delete(&self, route: &str) -> Result<()> {
+        let response = self.http.delete(format!("{}{}", self.api_v1()?, route)).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("api answered {}: {}", status, response.text().await.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    /// Submit device readings through `POST /observations/_bulk`, as a
+    /// gateway does
+    pub async fn record_observations(&self, observations: Vec<Value>) -> Result<IngestResult> {
+        let response = self
+            .http
+            .post(format!("{}/observations/_bulk", self.api_v1()?))
+            .json(&json!({ "observations": observations }))
+            .send()
+            .await?;
+        Ok(serde_json::from_value(Self::api_data(response).await?)?)
+    }
+
+    /// A patient's record as the api exports it: their row of
+    /// `GET /patients/export`, their stored observations, most recent first,
+    /// and their summary
+    pub async fn export_patient(&self, patient_id: Uuid) -> Result<Value> {
+        let api = self.api_v1()?;
+
+        let export = self.http.get(format!("{}/patients/export", api)).send().await?.error_for_status()?;
+        let rows = export.text().await?;
+        let mut patient = None;
+        for line in rows.lines().filter(|line| !line.trim().is_empty()) {
+            let row: Value = serde_json::from_str(line)?;
+            if row["id"] == patient_id.to_string() {
+                patient = Some(row);
+                break;
+            }
+        }
+        let patient = patient.with_context(|| format!("Patient {} is not in the export", patient_id))?;
+
+        let observations = self.http.get(format!("{}/patients/{}/observations", api, patient_id)).send().await?;
+        let summary = self.http.get(format!("{}/patients/{}/summary", api, patient_id)).send().await?;
+        Ok(json!({
+            "patient": patient,
+            "observations": Self::api_data(observations).await?,
+            "summary": Self::api_data(summary).await?,
+        }))
+    }
+}
+
+impl Drop for TestStack {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            worker.abort();
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Golden paths through the running platform
+//!
+//! Each test starts its own [`TestStack`]: Postgres initialised from
+//! `infra/init-db.sql`, NATS, the jobs worker and the api binary. Docker
+//! must be running and the api built, so the tests are ignored by
+//! `cargo test --workspace`; `just e2e` builds the api and runs them. Tests
+//! that need a FHIR server stub the requests they expect.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Utc};
+use emr_core::domain::VitalSign;
+use emr_core::test_support::Fixtures;
+use emr_e2e::TestStack;
+use emr_jobs::config::{EncryptionConfig, ReportConfig};
+use emr_jobs::key_rotation::{generate_data_key, open, DatabaseDataKeyStore, KeyRotationHandler};
+use emr_jobs::{DataKeyStore, JobContext, JobHandler, KeyRotationJob};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn reading(patient_id: Uuid, vital_sign: VitalSign, value: f64, minutes_ago: i64) -> Value {
+    json!({
+        "id": Uuid::new_v4(),
+        "patient_id": patient_id,
+        "device_id": "monitor-7",
+        "code": vital_sign.loinc_code(),
+        "value": value,
+        "unit": vital_sign.ucum_unit(),
+        "effective": Utc::now() - Duration::minutes(minutes_ago),
+    })
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_create_patient_record_observation_export() {
+    let stack = TestStack::builder().with_jobs().with_api().start().await.unwrap();
+
+    let api_url = stack.api_url.as_deref().unwrap();
+    let health: Value = reqwest::get(format!("{}/healthz", api_url)).await.unwrap().json().await.unwrap();
+    assert_eq!(health["status"], "healthy");
+
+    let patient = Fixtures::new(7).patient();
+    let patient_id = stack.create_patient(&patient).await.unwrap();
+
+    let result = stack
+        .record_observations(vec![
+            reading(patient_id, VitalSign::HeartRate, 70.0, 10),
+            reading(patient_id, VitalSign::HeartRate, 84.0, 5),
+            reading(patient_id, VitalSign::OxygenSaturation, 98.0, 5),
+        ])
+        .await
+        .unwrap();
+    assert_eq!((result.received, result.inserted), (3, 3));
+    assert!(result.errors.is_empty());
+
+    let export = stack.export_patient(patient_id).await.unwrap();
+    assert_eq!(export["patient"]["id"], patient_id.to_string());
+    assert_eq!(export["patient"]["family_name"], patient.names[0].family);
+
+    let observations = export["observations"].as_array().unwrap();
+    assert_eq!(observations.len(), 3);
+    assert!(observations.iter().all(|o| o["category"] == "vital-signs" && o["device_id"] == "monitor-7"));
+
+    // The summary keeps the latest reading of each vital sign
+    let vitals = export["summary"]["latest_vitals"].as_array().unwrap();
+    let heart_rate = vitals.iter().find(|v| v["code"] == VitalSign::HeartRate.loinc_code()).unwrap();
+    assert_eq!(heart_rate["value"], 84.0);
+    assert_eq!(vitals.len(), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_resent_and_unknown_readings() {
+    let stack = TestStack::builder().with_jobs().with_api().start().await.unwrap();
+    let patient_id = stack.create_patient(&Fixtures::new(11).patient()).await.unwrap();
+
+    let batch = vec![
+        reading(patient_id, VitalSign::RespiratoryRate, 16.0, 1),
+        reading(Uuid::new_v4(), VitalSign::RespiratoryRate, 16.0, 1),
+    ];
+    let first = stack.record_observations(batch.clone()).await.unwrap();
+    assert_eq!(first.inserted, 1);
+    assert_eq!(first.errors.len(), 1);
+    assert_eq!(first.errors[0].index, 1);
+
+    // A gateway resending a batch that got no answer stores nothing twice
+    let resent = stack.record_observations(batch).await.unwrap();
+    assert_eq!((resent.inserted, resent.duplicates), (0, 1));
+
+    let export = stack.export_patient(patient_id).await.unwrap();
+    assert_eq!(export["observations"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_portal_login_and_demographics() {
+    let stack = TestStack::builder().with_nats().with_api().start().await.unwrap();
+    let patient = Fixtures::new(13).patient();
+    let patient_id = stack.create_patient(&patient).await.unwrap();
+    stack.create_portal_account(patient_id, "pat13", "correct horse battery").await.unwrap();
+
+    assert!(stack.portal_login("pat13", "wrong password").await.is_err());
+    let token = stack.portal_login("pat13", "correct horse battery").await.unwrap();
+
+    let me = stack.get_as(&token, "/portal/me").await.unwrap();
+    assert_eq!(me["id"], patient_id.to_string());
+    assert_eq!(me["family_name"], patient.names[0].family);
+
+    // A token this api did not sign is refused
+    assert!(stack.get_as("dummy.jwt.token", "/portal/me").await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_practitioner_directory() {
+    let stack = TestStack::builder().with_nats().with_api().start().await.unwrap();
+    let request = json!({
+        "given": ["Emily"],
+        "family": "Brown",
+        "prefix": "Dr.",
+        "npi": "1234567893",
+        "qualifications": [{"code": "MD"}],
+    });
+    let created = stack.post("/practitioners", &request).await.unwrap();
+    let route = format!("/practitioners/{}", created["id"].as_str().unwrap());
+
+    let stored = stack.get(&route).await.unwrap();
+    assert_eq!(stored["name"], "Dr. Emily Brown");
+    assert_eq!(stored["npi"], "1234567893");
+
+    let renamed = stack.put(&route, &json!({"given": ["Emily", "J"], "family": "Brown-Lee"})).await.unwrap();
+    assert_eq!(renamed["name"], "Emily J Brown-Lee");
+    stack.post(&format!("{}/qualifications", route), &json!({"code": "FACC"})).await.unwrap();
+    assert_eq!(stack.get(&route).await.unwrap()["qualifications"].as_array().unwrap().len(), 1);
+
+    stack.delete(&route).await.unwrap();
+    assert_eq!(stack.get(&route).await.unwrap()["active"], false);
+
+    let unknown = format!("/practitioners/{}", Uuid::new_v4());
+    assert!(stack.get(&unknown).await.unwrap_err().to_string().contains("404"));
+    assert!(stack.delete(&unknown).await.unwrap_err().to_string().contains("404"));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_organization_hierarchy() {
+    let stack = TestStack::builder().with_nats().with_api().start().await.unwrap();
+    let root = stack.post("/organizations", &json!({"name": "Health System"})).await.unwrap();
+    let root_id = root["id"].as_str().unwrap().to_string();
+    let hospital = stack.post("/organizations", &json!({"name": "Hospital", "part_of": root_id})).await.unwrap();
+    let hospital_id = hospital["id"].as_str().unwrap().to_string();
+    stack.post("/organizations", &json!({"name": "Cardiology", "part_of": hospital_id})).await.unwrap();
+    stack.post("/organizations", &json!({"name": "Unrelated"})).await.unwrap();
+
+    let tree = stack.get(&format!("/organizations/{}/hierarchy", root_id)).await.unwrap();
+    assert_eq!(tree["children"].as_array().unwrap().len(), 1);
+    assert_eq!(tree["children"][0]["children"][0]["organization"]["name"], "Cardiology");
+
+    let cycle = json!({"name": "Health System", "part_of": hospital_id});
+    let route = format!("/organizations/{}", root_id);
+    assert!(stack.put(&route, &cycle).await.unwrap_err().to_string().contains("400"));
+    assert!(stack.delete(&route).await.unwrap_err().to_string().contains("400"));
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_encounter_workflow() {
+    let stack = TestStack::builder().with_nats().with_api().start().await.unwrap();
+    let patient_id = stack.create_patient(&Fixtures::new(11).patient()).await.unwrap();
+
+    let created = stack
+        .post("/encounters", &json!({"patient_id": patient_id, "class": "Ambulatory", "reason": ["Chest pain"]}))
+        .await
+        .unwrap();
+    let route = format!("/encounters/{}", created["id"].as_str().unwrap());
+    assert_eq!(created["status"], "Planned");
+
+    assert_eq!(stack.post(&format!("{}/check-in", route), &json!({})).await.unwrap()["status"], "Arrived");
+    stack.put(&format!("{}/location", route), &json!({"location_id": Uuid::new_v4()})).await.unwrap();
+    stack.post(&format!("{}/start", route), &json!({})).await.unwrap();
+    let finished = stack.post(&format!("{}/end", route), &json!({})).await.unwrap();
+    assert_eq!(finished["status"], "Finished");
+    assert!(finished["period"]["end"].is_string());
+
+    // The stored encounter survives the request and its workflow is enforced
+    let stored = stack.get(&route).await.unwrap();
+    assert_eq!(stored["status"], "Finished");
+    assert_eq!(stored["reason"], json!(["Chest pain"]));
+    assert!(stack.post(&format!("{}/start", route), &json!({})).await.is_err());
+
+    let listed = stack.get(&format!("/encounters?patient_id={}", patient_id)).await.unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker and a built api; run with `just e2e`"]
+async fn test_code_and_charge_encounter() {
+    // The FHIR server records the patient's hypertension
+    let fhir = MockServer::start().await;
+    let conditions = json!({
+        "resourceType": "Bundle",
+        "type": "searchset",
+        "entry": [{"resource": {
+            "resourceType": "Condition",
+            "id": "hypertension",
+            "code": {"coding": [{"system": "http://hl7.org/fhir/sid/icd-10-cm", "code": "I10"}]},
+        }}],
+    });
+    Mock::given(method("GET"))
+        .and(path("/Condition"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(conditions))
+        .mount(&fhir)
+        .await;
+    let stack = TestStack::builder().with_nats().with_api().with_fhir(&fhir.uri()).start().await.unwrap();
+
+    let practitioner = stack.post("/practitioners", &json!({"given": ["Emily"], "family": "Brown"})).await.unwrap();
+    let practitioner_id: Uuid = practitioner["id"].as_str().unwrap().parse().unwrap();
+    let patient_id = stack.create_patient(&Fixtures::new(13).patient()).await.unwrap();
+    let team = json!({
+        "name": "Clinic team",
+        "participants": [{"practitioner_id": practitioner_id, "role": "attending_physician"}],
+    });
+    stack.post(&format!("/patients/{}/care-teams", patient_id), &team).await.unwrap();
+
+    let encounter = stack.post("/encounters", &json!({"patient_id": patient_id, "class": "Ambulatory"})).await.unwrap();
+    let route = format!("/encounters/{}", encounter["id"].as_str().unwrap());
+    stack.post(&format!("{}/start", route), &json!({})).await.unwrap();
+    stack.post(&format!("{}/end", route), &json!({})).await.unwrap();
+
+    let review = json!({"codes": [{"kind": "diagnosis", "code": "I10"}, {"kind": "procedure", "code": "99213"}]});
+    let anonymous = stack.put(&format!("{}/coding", route), &review).await.unwrap_err();
+    assert!(anonymous.to_string().contains("401"));
+
+    // Codes and charges name the signed-in practitioner
+    let token = stack.staff_token(practitioner_id).unwrap();
+    let coding = stack.put_as(&token, &format!("{}/coding", route), &review).await.unwrap();
+    assert_eq!(coding["coded_by"], practitioner_id.to_string());
+    let stored = stack.get_as(&token, &format!("{}/coding", route)).await.unwrap();
+    assert_eq!(stored["codes"].as_array().unwrap().len(), 2);
+
+    let capture = json!({"diagnoses": ["I10"], "lines": [{"code": "99213"}]});
+    let charges = stack.put_as(&token, &format!("{}/charges", route), &capture).await.unwrap();
+    assert_eq!(charges["captured_by"], practitioner_id.to_string());
+    assert_eq!(charges["diagnoses"][0]["condition_id"], "hypertension");
+    assert_eq!(stack.get_as(&token, &format!("{}/charges", route)).await.unwrap()["lines"][0]["code"], "99213");
+}
+
+#[tokio::test]
+#[ignore = "needs Docker; run with `just e2e`"]
+async fn test_key_rotation_reencrypts_seeded_fields() {
+    let stack = TestStack::builder().start().await.unwrap();
+    let (old_master, new_master) = (generate_data_key(), generate_data_key());
+    let resource_id = Uuid::new_v4();
+    let mut old_keys = Vec::new();
+    for (field, value) in [("ssn", "123-45-6789"), ("passport", "A1234567")] {
+        old_keys.push(stack.seal_field("old", &old_master, "Patient", resource_id, field, value).await.unwrap());
+    }
+
+    let output_dir = std::env::temp_dir().join(format!("key-rotation-{}", Uuid::new_v4()));
+    let encryption = EncryptionConfig {
+        master_keys: BTreeMap::from([
+            ("old".to_string(), STANDARD.encode(&old_master)),
+            ("new".to_string(), STANDARD.encode(&new_master)),
+        ]),
+    };
+    let reports = ReportConfig {
+        output_dir: output_dir.display().to_string(),
+    };
+    let store = Arc::new(DatabaseDataKeyStore::new(stack.pool().clone()));
+    let handler = KeyRotationHandler::new(encryption, reports, store.clone());
+    let job = KeyRotationJob {
+        rotation_id: Uuid::new_v4(),
+        master_key_id: "new".to_string(),
+        reencrypt_fields: true,
+        batch_size: 1,
+    };
+
+    let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+    assert!(result.success);
+    assert_eq!(result.metrics["fields_reencrypted"], 2.0);
+
+    // Both fields moved to new data keys under the new master key
+    let mut values = Vec::new();
+    for key in store.active_keys().await.unwrap() {
+        assert!(!old_keys.contains(&key.id) && key.master_key_id == "new");
+        let data_key = open(&new_master, &key.wrapped_key).unwrap();
+        for field in store.fields(key.id, 10).await.unwrap() {
+            values.push(String::from_utf8(open(&data_key, &field.ciphertext).unwrap()).unwrap());
+        }
+    }
+    values.sort();
+    assert_eq!(values, vec!["123-45-6789", "A1234567"]);
+    for key in old_keys {
+        assert_eq!(store.field_count(key).await.unwrap(), 0);
+    }
+    std::fs::remove_dir_all(output_dir).unwrap();
+}
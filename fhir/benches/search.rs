@@ -0,0 +1,45 @@
+//! Benchmarks for FHIR search query building and subscription matching
+//!
+//! Run with `cargo bench -p emr-fhir`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use emr_fhir::{SearchParameters, SubscriptionCriteria};
+use serde_json::json;
+
+fn search_parameters() -> SearchParameters {
+    let mut params = SearchParameters::new("Observation")
+        .add_parameter("patient", "Patient/7f1c2d3e-0000-4000-8000-000000000042")
+        .add_parameter("code", "http://loinc.org|8867-4,http://loinc.org|8480-6")
+        .add_parameter("date", "ge2024-01-01T00:00:00Z")
+        .add_parameter("status", "final")
+        .with_count(50)
+        .with_offset(100);
+    params.include.push("Observation:patient".to_string());
+    params.rev_include.push("Provenance:target".to_string());
+    params
+}
+
+fn bench_query_building(c: &mut Criterion) {
+    let params = search_parameters();
+    c.bench_function("search/to_query_string", |b| b.iter(|| black_box(&params).to_query_string()));
+}
+
+fn bench_subscription_criteria(c: &mut Criterion) {
+    let criteria = "Observation?status=final&code=http%3A%2F%2Floinc.org%7C8867-4&subject=Patient%2F42";
+    let parsed = SubscriptionCriteria::parse(criteria).unwrap();
+    let resource = json!({
+        "resourceType": "Observation",
+        "status": "final",
+        "code": {"coding": [{"system": "http://loinc.org", "code": "8867-4"}]},
+        "subject": {"reference": "Patient/42"},
+        "valueQuantity": {"value": 72, "unit": "/min"}
+    });
+
+    c.bench_function("subscription/parse", |b| {
+        b.iter(|| SubscriptionCriteria::parse(black_box(criteria)).unwrap())
+    });
+    c.bench_function("subscription/matches", |b| b.iter(|| parsed.matches(black_box(&resource))));
+}
+
+criterion_group!(benches, bench_query_building, bench_subscription_criteria);
+criterion_main!(benches);
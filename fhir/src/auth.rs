@@ -0,0 +1,168 @@
+//! OAuth2 client-credentials authentication for FHIR servers
+//!
+//! [`TokenProvider`] fetches bearer tokens from the authorization server,
+//! caches them and refreshes shortly before they expire. Concurrent callers
+//! share one refresh.
+
+use crate::gateway::{FhirClientError, FhirResult};
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Refresh tokens this long before they expire
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Lifetime assumed when the token response has no `expires_in`
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// OAuth2 client-credentials grant settings
+#[derive(Debug, Clone)]
+pub struct ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+impl CachedToken {
+    fn is_fresh(&self, now: Instant) -> bool {
+        now + REFRESH_MARGIN < self.expires_at
+    }
+}
+
+/// Fetches and caches bearer tokens
+#[derive(Debug)]
+pub struct TokenProvider {
+    credentials: ClientCredentials,
+    http: Client,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl TokenProvider {
+    /// Create a provider that requests tokens with `http`
+    pub fn new(credentials: ClientCredentials, http: Client) -> Self {
+        Self {
+            credentials,
+            http,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// A valid access token, fetching a new one if the cached token is
+    /// missing or about to expire
+    pub async fn token(&self) -> FhirResult<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref().filter(|token| token.is_fresh(Instant::now())) {
+            return Ok(token.access_token.clone());
+        }
+
+        let token = self.fetch().await?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    /// Drop the cached token, e.g. after the FHIR server answered 401
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    async fn fetch(&self) -> FhirResult<CachedToken> {
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.credentials.scope {
+            form.push(("scope", scope));
+        }
+
+        let requested_at = Instant::now();
+        let response = self
+            .http
+            .post(&self.credentials.token_url)
+            .basic_auth(&self.credentials.client_id, Some(&self.credentials.client_secret))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| FhirClientError::Authentication(format!("Token request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(FhirClientError::Authentication(format!(
+                "Token endpoint returned {}",
+                response.status()
+            )));
+        }
+
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| FhirClientError::Authentication(format!("Invalid token response: {}", e)))?;
+        let lifetime = token
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+        tracing::debug!(expires_in = lifetime.as_secs(), "Obtained FHIR access token");
+        Ok(CachedToken {
+            access_token: token.access_token,
+            expires_at: requested_at + lifetime,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_freshness() {
+        let now = Instant::now();
+        let token = CachedToken {
+            access_token: "abc".to_string(),
+            expires_at: now + Duration::from_secs(300),
+        };
+
+        assert!(token.is_fresh(now));
+        assert!(token.is_fresh(now + Duration::from_secs(200)));
+        assert!(!token.is_fresh(now + Duration::from_secs(241)));
+    }
+
+    #[test]
+    fn test_token_response_without_expiry() {
+        let token: TokenResponse = serde_json::from_str(r#"{"access_token": "abc", "token_type": "Bearer"}"#).unwrap();
+        assert_eq!(token.access_token, "abc");
+        assert!(token.expires_in.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cached_token_is_reused() {
+        let provider = TokenProvider::new(
+            ClientCredentials {
+                token_url: "http://127.0.0.1:9/token".to_string(),
+                client_id: "emr".to_string(),
+                client_secret: "secret".to_string(),
+                scope: None,
+            },
+            Client::new(),
+        );
+        *provider.cached.lock().await = Some(CachedToken {
+            access_token: "cached".to_string(),
+            expires_at: Instant::now() + Duration::from_secs(600),
+        });
+
+        assert_eq!(provider.token().await.unwrap(), "cached");
+
+        provider.invalidate().await;
+        assert!(provider.token().await.is_err());
+    }
+}
@@ -0,0 +1,354 @@
+//! Bundle assembly helpers
+//!
+//! [`BundleBuilder`] merges entries from several sources (server pages,
+//! locally stored resources) into one Bundle, keeping the first entry seen
+//! for each `resourceType/id`.
+//!
+//! Large Bundles stay a typed [`Bundle`] rather than one `Value`: entries
+//! are moved, never copied, between pages, the builder and the response,
+//! and [`Bundle::json_chunks`] writes the JSON entry by entry as the
+//! response is sent instead of rendering it in one buffer.
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+
+/// URL of the `next` page link of a searchset Bundle
+pub fn next_link(bundle: &Value) -> Option<&str> {
+    bundle["link"]
+        .as_array()?
+        .iter()
+        .find(|link| link["relation"] == "next")
+        .and_then(|link| link["url"].as_str())
+}
+
+/// `resourceType/id` of a resource, if it has both
+pub fn resource_key(resource: &Value) -> Option<String> {
+    let resource_type = resource["resourceType"].as_str()?;
+    let id = resource["id"].as_str()?;
+    Some(format!("{}/{}", resource_type, id))
+}
+
+/// A Bundle link, such as the `next` page of a searchset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleLink {
+    pub relation: String,
+    pub url: String,
+}
+
+/// A Bundle whose entries are kept as JSON
+///
+/// Read from a server page it is decoded straight from the response body;
+/// other elements of the page are dropped.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Bundle {
+    #[serde(rename = "type")]
+    pub bundle_type: String,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    #[serde(default)]
+    pub total: Option<usize>,
+    #[serde(default)]
+    pub link: Vec<BundleLink>,
+    #[serde(default)]
+    pub entry: Vec<Value>,
+}
+
+impl Bundle {
+    /// URL of the `next` page link
+    pub fn next_link(&self) -> Option<&str> {
+        self.link
+            .iter()
+            .find(|link| link.relation == "next")
+            .map(|link| link.url.as_str())
+    }
+
+    /// Keep the entries whose resource passes `keep`, lowering `total` to
+    /// match; returns how many were removed
+    pub fn retain_resources(&mut self, mut keep: impl FnMut(&Value) -> bool) -> usize {
+        let before = self.entry.len();
+        self.entry.retain(|entry| keep(&entry["resource"]));
+        let removed = before - self.entry.len();
+        if let Some(total) = self.total.as_mut() {
+            *total = total.saturating_sub(removed);
+        }
+        removed
+    }
+
+    /// The Bundle as a `Value`, moving the entries into it
+    pub fn into_value(self) -> Value {
+        let mut bundle = Map::new();
+        bundle.insert("resourceType".to_string(), json!("Bundle"));
+        bundle.insert("type".to_string(), Value::String(self.bundle_type));
+        if let Some(timestamp) = self.timestamp {
+            bundle.insert("timestamp".to_string(), Value::String(timestamp));
+        }
+        if let Some(total) = self.total {
+            bundle.insert("total".to_string(), json!(total));
+        }
+        if !self.link.is_empty() {
+            bundle.insert("link".to_string(), json!(self.link));
+        }
+        if !self.entry.is_empty() {
+            bundle.insert("entry".to_string(), Value::Array(self.entry));
+        }
+        Value::Object(bundle)
+    }
+
+    /// The Bundle's JSON in chunks of about `chunk_size` bytes, each
+    /// serialized when it is taken
+    pub fn json_chunks(self, chunk_size: usize) -> JsonChunks {
+        let Bundle {
+            bundle_type,
+            timestamp,
+            total,
+            link,
+            entry,
+        } = self;
+        let head = Bundle {
+            bundle_type,
+            timestamp,
+            total,
+            link,
+            entry: Vec::new(),
+        };
+
+        JsonChunks {
+            head: Some(head),
+            entries: entry.into_iter(),
+            written: 0,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+}
+
+// Written by hand so elements come out in FHIR order and empty ones are left out
+impl Serialize for Bundle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bundle = serializer.serialize_struct("Bundle", 6)?;
+        bundle.serialize_field("resourceType", "Bundle")?;
+        bundle.serialize_field("type", &self.bundle_type)?;
+        if let Some(timestamp) = &self.timestamp {
+            bundle.serialize_field("timestamp", timestamp)?;
+        }
+        if let Some(total) = self.total {
+            bundle.serialize_field("total", &total)?;
+        }
+        if !self.link.is_empty() {
+            bundle.serialize_field("link", &self.link)?;
+        }
+        if !self.entry.is_empty() {
+            bundle.serialize_field("entry", &self.entry)?;
+        }
+        bundle.end()
+    }
+}
+
+/// JSON of a [`Bundle`], chunk by chunk (see [`Bundle::json_chunks`])
+#[derive(Debug)]
+pub struct JsonChunks {
+    /// Elements before the entries, until they are written
+    head: Option<Bundle>,
+    entries: std::vec::IntoIter<Value>,
+    /// Entries written so far
+    written: usize,
+    chunk_size: usize,
+}
+
+impl Iterator for JsonChunks {
+    type Item = serde_json::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+
+        if let Some(head) = self.head.take() {
+            if let Err(e) = serde_json::to_writer(&mut chunk, &head) {
+                return Some(Err(e));
+            }
+            if self.entries.len() == 0 {
+                return Some(Ok(chunk));
+            }
+            // Reopen the object to append the entries
+            chunk.pop();
+            chunk.extend_from_slice(b",\"entry\":[");
+        } else if self.entries.len() == 0 {
+            return None;
+        }
+
+        while chunk.len() < self.chunk_size {
+            let Some(entry) = self.entries.next() else {
+                break;
+            };
+            if self.written > 0 {
+                chunk.push(b',');
+            }
+            if let Err(e) = serde_json::to_writer(&mut chunk, &entry) {
+                return Some(Err(e));
+            }
+            self.written += 1;
+        }
+        if self.entries.len() == 0 {
+            chunk.extend_from_slice(b"]}");
+        }
+        Some(Ok(chunk))
+    }
+}
+
+/// Builds a Bundle from de-duplicated entries
+#[derive(Debug)]
+pub struct BundleBuilder {
+    bundle_type: String,
+    entries: Vec<Value>,
+    seen: HashSet<String>,
+}
+
+impl BundleBuilder {
+    /// Start a Bundle of the given type, e.g. `collection`
+    pub fn new(bundle_type: &str) -> Self {
+        Self {
+            bundle_type: bundle_type.to_string(),
+            entries: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Add a resource; returns false if one with the same type and id was
+    /// already added
+    pub fn add_resource(&mut self, resource: Value) -> bool {
+        // Not `json!`, which would copy the resource
+        let mut entry = Map::new();
+        entry.insert("resource".to_string(), resource);
+        self.add_entry(Value::Object(entry))
+    }
+
+    /// Add a Bundle entry as-is (keeping `fullUrl` and `search`); returns
+    /// false if its resource was already added
+    pub fn add_entry(&mut self, entry: Value) -> bool {
+        if let Some(key) = resource_key(&entry["resource"]) {
+            if !self.seen.insert(key) {
+                return false;
+            }
+        }
+        self.entries.push(entry);
+        true
+    }
+
+    /// Add every entry of another Bundle
+    pub fn extend_from_bundle(&mut self, mut bundle: Value) {
+        if let Some(Value::Array(entries)) = bundle.get_mut("entry").map(Value::take) {
+            for entry in entries {
+                self.add_entry(entry);
+            }
+        }
+    }
+
+    /// Add every entry of a decoded Bundle
+    pub fn append(&mut self, bundle: Bundle) {
+        for entry in bundle.entry {
+            self.add_entry(entry);
+        }
+    }
+
+    /// Number of entries added so far
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The Bundle, timestamped now
+    pub fn finish(self) -> Bundle {
+        Bundle {
+            bundle_type: self.bundle_type,
+            timestamp: Some(chrono::Utc::now().to_rfc3339()),
+            total: Some(self.entries.len()),
+            link: Vec::new(),
+            entry: self.entries,
+        }
+    }
+
+    pub fn build(self) -> Value {
+        self.finish().into_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_link() {
+        let bundle = json!({
+            "resourceType": "Bundle",
+            "link": [
+                {"relation": "self", "url": "http://fhir.local/Patient/1/$everything"},
+                {"relation": "next", "url": "http://fhir.local/Patient/1/$everything?page=2"}
+            ]
+        });
+        assert_eq!(next_link(&bundle), Some("http://fhir.local/Patient/1/$everything?page=2"));
+        assert_eq!(next_link(&json!({"resourceType": "Bundle"})), None);
+    }
+
+    #[test]
+    fn test_builder_deduplicates() {
+        let mut builder = BundleBuilder::new("collection");
+        builder.extend_from_bundle(json!({
+            "entry": [
+                {"fullUrl": "http://fhir.local/Patient/1", "resource": {"resourceType": "Patient", "id": "1"}},
+                {"resource": {"resourceType": "Encounter", "id": "e1"}}
+            ]
+        }));
+        assert!(!builder.add_resource(json!({"resourceType": "Encounter", "id": "e1"})));
+        assert!(builder.add_resource(json!({"resourceType": "Observation", "id": "o1"})));
+
+        let bundle = builder.build();
+        assert_eq!(bundle["type"], "collection");
+        assert_eq!(bundle["total"], 3);
+        assert_eq!(bundle["entry"][0]["fullUrl"], "http://fhir.local/Patient/1");
+    }
+
+    #[test]
+    fn test_bundle_from_page() {
+        let page: Bundle = serde_json::from_value(json!({
+            "resourceType": "Bundle",
+            "type": "searchset",
+            "link": [{"relation": "next", "url": "http://fhir.local/Patient/1/$everything?page=2"}],
+            "entry": [
+                {"resource": {"resourceType": "Patient", "id": "1"}},
+                {"resource": {"resourceType": "Condition", "id": "c1"}}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(page.next_link(), Some("http://fhir.local/Patient/1/$everything?page=2"));
+
+        let mut builder = BundleBuilder::new("collection");
+        builder.append(page);
+        let mut bundle = builder.finish();
+        assert_eq!(bundle.retain_resources(|resource| resource["resourceType"] == "Patient"), 1);
+        assert_eq!(bundle.total, Some(1));
+        assert_eq!(bundle.into_value()["entry"][0]["resource"]["id"], "1");
+    }
+
+    #[test]
+    fn test_json_chunks() {
+        let mut builder = BundleBuilder::new("collection");
+        for id in 0..50 {
+            builder.add_resource(json!({"resourceType": "Observation", "id": id.to_string(), "status": "final"}));
+        }
+        let bundle = builder.finish();
+        let expected = serde_json::to_vec(&bundle).unwrap();
+
+        let chunks: Vec<Vec<u8>> = bundle.clone().json_chunks(256).collect::<Result<_, _>>().unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), expected);
+        assert_eq!(serde_json::from_slice::<Value>(&expected).unwrap(), bundle.into_value());
+
+        let empty = BundleBuilder::new("searchset").finish();
+        let expected = serde_json::to_vec(&empty).unwrap();
+        let chunks: Vec<Vec<u8>> = empty.json_chunks(256).collect::<Result<_, _>>().unwrap();
+        assert_eq!(chunks, vec![expected]);
+    }
+}
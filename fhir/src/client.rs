@@ -1,31 +1,68 @@
 //! FHIR client for Kodjin server integration
 
-use crate::{SearchParameters, OperationOutcome};
-use emr_core::{Result, Error};
-use reqwest::Client;
+use crate::auth::{ClientCredentials, TokenProvider};
+use crate::bundle::{Bundle, BundleBuilder};
+use crate::gateway::{FhirClientError, FhirGateway, FhirResult};
+use crate::xml::FhirFormat;
+use crate::{OperationOutcome, SearchParameters};
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Certificate, Client, Identity, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Upper bound on `$everything` result pages followed for one patient
+const MAX_EVERYTHING_PAGES: usize = 50;
+
+/// How requests are authenticated
+#[derive(Debug, Clone)]
+enum Authorization {
+    /// Fixed `Authorization` header value
+    Static(String),
+    /// Bearer tokens from an OAuth2 client-credentials grant
+    OAuth2(Arc<TokenProvider>),
+}
 
 /// FHIR client for interacting with Kodjin FHIR server
+///
+/// Every request goes through [`KodjinClient::send`], which applies the
+/// authorization header, the timeout and retries for idempotent methods.
+/// With OAuth2, a 401 drops the cached token and the request is sent once
+/// more with a fresh one. Resources are exchanged as JSON unless
+/// [`KodjinClient::with_format`] selects XML, for servers that only speak it.
+/// The capability statement is read once and shared by the client's clones.
 #[derive(Debug, Clone)]
 pub struct KodjinClient {
     base_url: String,
     client: Client,
     timeout: Duration,
+    max_retries: u32,
+    retry_delay: Duration,
+    authorization: Option<Authorization>,
+    format: FhirFormat,
+    capabilities: Arc<OnceCell<Value>>,
 }
 
 impl KodjinClient {
     /// Create a new Kodjin FHIR client
-    pub fn new(base_url: &str) -> Result<Self> {
+    pub fn new(base_url: &str) -> FhirResult<Self> {
         let client = Client::builder()
             .timeout(Duration::from_secs(30))
             .build()
-            .map_err(|e| Error::external_service_error("HTTP", &e.to_string()))?;
+            .map_err(|e| FhirClientError::Configuration(e.to_string()))?;
 
         Ok(Self {
             base_url: base_url.trim_end_matches('/').to_string(),
             client,
             timeout: Duration::from_secs(30),
+            max_retries: 0,
+            retry_delay: Duration::from_millis(500),
+            authorization: None,
+            format: FhirFormat::Json,
+            capabilities: Arc::default(),
         })
     }
 
@@ -35,155 +72,263 @@ impl KodjinClient {
         self
     }
 
-    /// Get capability statement
-    pub async fn get_capability_statement(&self) -> Result<Value> {
-        let url = format!("{}/metadata", self.base_url);
-        self.get_json(&url).await
+    /// Retry idempotent requests on 5xx, 429 and transport errors, doubling
+    /// the delay after each attempt
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Exchange resources with the server in `format`
+    pub fn with_format(mut self, format: FhirFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Send an `Authorization` header with every request
+    pub fn with_authorization(mut self, value: &str) -> Self {
+        self.authorization = Some(Authorization::Static(value.to_string()));
+        self
     }
 
-    /// Read a resource by type and ID
-    pub async fn read(&self, resource_type: &str, id: &str) -> Result<Value> {
-        let url = format!("{}/{}/{}", self.base_url, resource_type, id);
-        self.get_json(&url).await
+    /// Authenticate with bearer tokens from an OAuth2 client-credentials grant
+    pub fn with_client_credentials(mut self, credentials: ClientCredentials) -> Self {
+        let provider = TokenProvider::new(credentials, self.client.clone());
+        self.authorization = Some(Authorization::OAuth2(Arc::new(provider)));
+        self
     }
 
-    /// Search resources
-    pub async fn search(&self, params: &SearchParameters) -> Result<Value> {
-        let mut url = format!("{}/{}", self.base_url, params.resource_type);
-        
-        let query_string = params.to_query_string();
-        if !query_string.is_empty() {
-            url.push('?');
-            url.push_str(&query_string);
+    /// Present a client certificate (mTLS) and optionally trust a private CA.
+    ///
+    /// `identity_pem` holds the certificate chain followed by the PKCS#8
+    /// private key. Call before [`KodjinClient::with_client_credentials`] so
+    /// token requests use the same TLS settings.
+    pub fn with_mtls(mut self, identity_pem: &[u8], ca_pem: Option<&[u8]>) -> FhirResult<Self> {
+        let identity = Identity::from_pem(identity_pem)
+            .map_err(|e| FhirClientError::Configuration(format!("Invalid client certificate: {}", e)))?;
+
+        let mut builder = Client::builder().timeout(self.timeout).identity(identity);
+        if let Some(ca_pem) = ca_pem {
+            let ca = Certificate::from_pem(ca_pem)
+                .map_err(|e| FhirClientError::Configuration(format!("Invalid CA certificate: {}", e)))?;
+            builder = builder.add_root_certificate(ca);
         }
 
-        self.get_json(&url).await
+        self.client = builder
+            .build()
+            .map_err(|e| FhirClientError::Configuration(e.to_string()))?;
+        Ok(self)
     }
 
-    /// Create a new resource
-    pub async fn create(&self, resource_type: &str, resource: &Value) -> Result<Value> {
-        let url = format!("{}/{}", self.base_url, resource_type);
-        self.post_json(&url, resource).await
+    async fn authorization_header(&self) -> FhirResult<Option<String>> {
+        match &self.authorization {
+            None => Ok(None),
+            Some(Authorization::Static(value)) => Ok(Some(value.clone())),
+            Some(Authorization::OAuth2(provider)) => Ok(Some(format!("Bearer {}", provider.token().await?))),
+        }
     }
 
-    /// Update a resource
-    pub async fn update(&self, resource_type: &str, id: &str, resource: &Value) -> Result<Value> {
-        let url = format!("{}/{}/{}", self.base_url, resource_type, id);
-        self.put_json(&url, resource).await
+    /// Base URL of the FHIR server
+    pub fn base_url(&self) -> &str {
+        &self.base_url
     }
 
-    /// Delete a resource
-    pub async fn delete(&self, resource_type: &str, id: &str) -> Result<()> {
-        let url = format!("{}/{}/{}", self.base_url, resource_type, id);
-        
-        let response = self.client
-            .delete(&url)
-            .header("Accept", "application/fhir+json")
-            .timeout(self.timeout)
-            .send()
-            .await
-            .map_err(|e| Error::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            Err(Error::fhir_error(&format!("Delete failed: {} - {}", status, error_text), None))
-        }
+    /// Get capability statement
+    pub async fn get_capability_statement(&self) -> FhirResult<Value> {
+        self.capability_statement().await
     }
 
-    /// Validate a resource
-    pub async fn validate(&self, resource_type: &str, resource: &Value) -> Result<OperationOutcome> {
-        let url = format!("{}/$validate", self.base_url);
-        
-        let response = self.client
-            .post(&url)
-            .header("Content-Type", "application/fhir+json")
-            .header("Accept", "application/fhir+json")
-            .query(&[("profile", resource_type)])
-            .json(resource)
-            .timeout(self.timeout)
-            .send()
-            .await
-            .map_err(|e| Error::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            let outcome: OperationOutcome = response.json().await
-                .map_err(|e| Error::fhir_error(&format!("Failed to parse validation response: {}", e), None))?;
-            Ok(outcome)
-        } else {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            Err(Error::fhir_error(&format!("Validation failed: {} - {}", status, error_text), None))
+    fn resource_url(&self, resource_type: &str, id: Option<&str>) -> String {
+        match id {
+            Some(id) => format!("{}/{}/{}", self.base_url, resource_type, urlencoding::encode(id)),
+            None => format!("{}/{}", self.base_url, resource_type),
         }
     }
 
-    /// Perform a GET request and parse JSON response
-    async fn get_json(&self, url: &str) -> Result<Value> {
-        let response = self.client
-            .get(url)
-            .header("Accept", "application/fhir+json")
-            .timeout(self.timeout)
-            .send()
-            .await
-            .map_err(|e| Error::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            let json: Value = response.json().await
-                .map_err(|e| Error::fhir_error(&format!("Failed to parse JSON response: {}", e), None))?;
-            Ok(json)
-        } else {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            Err(Error::fhir_error(&format!("Request failed: {} - {}", status, error_text), None))
+    /// Send a request, retrying idempotent methods on transient failures
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        resource: &str,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> FhirResult<reqwest::Response> {
+        let idempotent = method != Method::POST;
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        let mut reauthenticated = false;
+
+        loop {
+            let mut request = self
+                .client
+                .request(method.clone(), url)
+                .header("Accept", self.format.content_type())
+                .timeout(self.timeout);
+            if let Some(authorization) = self.authorization_header().await? {
+                request = request.header("Authorization", authorization);
+            }
+
+            let result = match build(request).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                // The token may have been revoked or rotated early; the
+                // request was not processed, so it is safe to resend
+                Ok(response) if response.status() == StatusCode::UNAUTHORIZED && !reauthenticated => {
+                    if let Some(Authorization::OAuth2(provider)) = &self.authorization {
+                        provider.invalidate().await;
+                        reauthenticated = true;
+                        continue;
+                    }
+                    let body = response.text().await.unwrap_or_default();
+                    FhirClientError::from_response(401, resource, &body)
+                }
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    let body = response.text().await.unwrap_or_default();
+                    FhirClientError::from_response(status, resource, &body)
+                }
+                Err(e) => FhirClientError::Unavailable {
+                    status: None,
+                    message: e.to_string(),
+                },
+            };
+
+            if !idempotent || !result.is_retryable() || attempt >= self.max_retries {
+                return Err(result);
+            }
+
+            attempt += 1;
+            tracing::warn!(%method, url, attempt, error = %result, "Retrying FHIR request");
+            tokio::time::sleep(delay).await;
+            delay *= 2;
         }
     }
 
-    /// Perform a POST request with JSON body
-    async fn post_json(&self, url: &str, body: &Value) -> Result<Value> {
-        let response = self.client
-            .post(url)
-            .header("Content-Type", "application/fhir+json")
-            .header("Accept", "application/fhir+json")
-            .json(body)
-            .timeout(self.timeout)
-            .send()
+    /// Parse a response in the format it was sent in, straight into `T`
+    async fn decode<T: DeserializeOwned>(&self, response: reqwest::Response) -> FhirResult<T> {
+        let format = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(FhirFormat::from_media_type)
+            .unwrap_or(self.format);
+        let body = response
+            .bytes()
             .await
-            .map_err(|e| Error::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            let json: Value = response.json().await
-                .map_err(|e| Error::fhir_error(&format!("Failed to parse JSON response: {}", e), None))?;
-            Ok(json)
-        } else {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            Err(Error::fhir_error(&format!("POST request failed: {} - {}", status, error_text), None))
-        }
+            .map_err(|e| FhirClientError::InvalidResponse(e.to_string()))?;
+        format
+            .decode_as(&body)
+            .map_err(|e| FhirClientError::InvalidResponse(e.to_string()))
     }
 
-    /// Perform a PUT request with JSON body
-    async fn put_json(&self, url: &str, body: &Value) -> Result<Value> {
-        let response = self.client
-            .put(url)
-            .header("Content-Type", "application/fhir+json")
-            .header("Accept", "application/fhir+json")
-            .json(body)
-            .timeout(self.timeout)
-            .send()
-            .await
-            .map_err(|e| Error::external_service_error("FHIR", &e.to_string()))?;
-
-        if response.status().is_success() {
-            let json: Value = response.json().await
-                .map_err(|e| Error::fhir_error(&format!("Failed to parse JSON response: {}", e), None))?;
-            Ok(json)
-        } else {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            Err(Error::fhir_error(&format!("PUT request failed: {} - {}", status, error_text), None))
+    /// Serialize a request body
+    fn encode(&self, resource: &Value) -> FhirResult<Vec<u8>> {
+        self.format
+            .encode(resource)
+            .map_err(|e| FhirClientError::Configuration(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl FhirGateway for KodjinClient {
+    async fn capability_statement(&self) -> FhirResult<Value> {
+        // A failed read is not cached; the next call tries again
+        let statement = self
+            .capabilities
+            .get_or_try_init(|| async {
+                let url = format!("{}/metadata", self.base_url);
+                let response = self.send(Method::GET, &url, "metadata", |request| request).await?;
+                self.decode(response).await
+            })
+            .await?;
+        Ok(statement.clone())
+    }
+
+    async fn read(&self, resource_type: &str, id: &str) -> FhirResult<Value> {
+        let url = self.resource_url(resource_type, Some(id));
+        let resource = format!("{}/{}", resource_type, id);
+        let response = self.send(Method::GET, &url, &resource, |request| request).await?;
+        self.decode(response).await
+    }
+
+    async fn search(&self, params: &SearchParameters) -> FhirResult<Value> {
+        let url = params.to_url(&self.base_url);
+        let response = self
+            .send(Method::GET, &url, &params.resource_type, |request| request)
+            .await?;
+        self.decode(response).await
+    }
+
+    async fn create(&self, resource_type: &str, resource: &Value) -> FhirResult<Value> {
+        let url = self.resource_url(resource_type, None);
+        let body = self.encode(resource)?;
+        let response = self
+            .send(Method::POST, &url, resource_type, |request| {
+                request.header(CONTENT_TYPE, self.format.content_type()).body(body.clone())
+            })
+            .await?;
+        self.decode(response).await
+    }
+
+    async fn update(&self, resource_type: &str, id: &str, resource: &Value) -> FhirResult<Value> {
+        let url = self.resource_url(resource_type, Some(id));
+        let target = format!("{}/{}", resource_type, id);
+        let body = self.encode(resource)?;
+        let response = self
+            .send(Method::PUT, &url, &target, |request| {
+                request.header(CONTENT_TYPE, self.format.content_type()).body(body.clone())
+            })
+            .await?;
+        self.decode(response).await
+    }
+
+    async fn delete(&self, resource_type: &str, id: &str) -> FhirResult<()> {
+        let url = self.resource_url(resource_type, Some(id));
+        let target = format!("{}/{}", resource_type, id);
+        self.send(Method::DELETE, &url, &target, |request| request).await?;
+        Ok(())
+    }
+
+    async fn validate(&self, resource_type: &str, resource: &Value) -> FhirResult<OperationOutcome> {
+        let url = format!("{}/$validate", self.base_url);
+        let body = self.encode(resource)?;
+        let response = self
+            .send(Method::POST, &url, resource_type, |request| {
+                request
+                    .header(CONTENT_TYPE, self.format.content_type())
+                    .query(&[("profile", resource_type)])
+                    .body(body.clone())
+            })
+            .await?;
+        self.decode(response).await
+    }
+
+    async fn patient_everything(&self, id: &str) -> FhirResult<Bundle> {
+        let target = format!("Patient/{}", id);
+        let mut url = format!("{}/$everything", self.resource_url("Patient", Some(id)));
+        let mut builder = BundleBuilder::new("searchset");
+
+        for _ in 0..MAX_EVERYTHING_PAGES {
+            let response = self.send(Method::GET, &url, &target, |request| request).await?;
+            let page: Bundle = self.decode(response).await?;
+            let next = page.next_link().map(str::to_string);
+            builder.append(page);
+
+            match next {
+                // Never send credentials to a host other than the FHIR server
+                Some(next) if next.starts_with(&self.base_url) => url = next,
+                Some(next) => {
+                    return Err(FhirClientError::InvalidResponse(format!(
+                        "$everything next link points outside the FHIR server: {}",
+                        next
+                    )))
+                }
+                None => return Ok(builder.finish()),
+            }
         }
+
+        tracing::warn!(patient = id, pages = MAX_EVERYTHING_PAGES, "Truncated $everything result");
+        Ok(builder.finish())
     }
 }
 
@@ -204,4 +349,35 @@ mod tests {
             .with_timeout(Duration::from_secs(60));
         assert_eq!(client.timeout, Duration::from_secs(60));
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_kodjin_client_options() {
+        let client = KodjinClient::new("http://localhost:8080/fhir/")
+            .unwrap()
+            .with_retries(3, Duration::from_millis(100))
+            .with_authorization("Bearer token");
+        assert_eq!(client.base_url(), "http://localhost:8080/fhir");
+        assert_eq!(client.max_retries, 3);
+        assert!(matches!(client.authorization, Some(Authorization::Static(ref value)) if value == "Bearer token"));
+        assert_eq!(
+            client.resource_url("Patient", Some("a/b")),
+            "http://localhost:8080/fhir/Patient/a%2Fb"
+        );
+    }
+
+    #[test]
+    fn test_kodjin_client_rejects_invalid_identity() {
+        let result = KodjinClient::new("https://localhost:8443/fhir")
+            .unwrap()
+            .with_mtls(b"not a pem", None);
+        assert!(matches!(result, Err(FhirClientError::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_static_authorization_header() {
+        let client = KodjinClient::new("http://localhost:8080/fhir")
+            .unwrap()
+            .with_authorization("Basic abc");
+        assert_eq!(client.authorization_header().await.unwrap().as_deref(), Some("Basic abc"));
+    }
+}
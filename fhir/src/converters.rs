@@ -0,0 +1,1347 @@
+//! Domain → FHIR R4 JSON conversion
+//!
+//! Renders locally stored entities as FHIR resources so they can be merged
+//! with resources read from the FHIR server. Observation codes are LOINC, as
+//! recorded by the vitals workflow.
+
+use emr_core::domain::{
+    AcknowledgmentTask, AdministrationOutcome, AnswerValue, CareTeam, ClinicalNote, EnableBehavior, Encounter,
+    EncounterClass, EncounterStatus, ImagingStudy, ItemType, MedicationAdministration, MedicationRequest,
+    Observation, ObservationStatus, ObservationValue, Provenance, ProvenanceActivity, Questionnaire,
+    QuestionnaireItem, QuestionnaireResponse, Referral, ReferralStatus, RequestCategory, ServiceRequest,
+    LOINC_SYSTEM, RXNORM_SYSTEM, SNOMED_SYSTEM,
+};
+use emr_core::domain::units::UCUM_SYSTEM;
+use emr_core::types::{EntityMetadata, Id, Timestamp};
+use crate::security::label_resource;
+use serde_json::{json, Map, Value};
+
+const ACT_CODE_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/v3-ActCode";
+const OBSERVATION_CATEGORY_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+const DATA_OPERATION_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/v3-DataOperation";
+const PARTICIPANT_TYPE_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/provenance-participant-type";
+const TASK_CODE_SYSTEM: &str = "http://hl7.org/fhir/CodeSystem/task-code";
+const ORDINAL_VALUE_EXTENSION: &str = "http://hl7.org/fhir/StructureDefinition/ordinalValue";
+const DICOM_MODALITY_SYSTEM: &str = "http://dicom.nema.org/resources/ontology/DCM";
+const IDENTIFIER_TYPE_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/v2-0203";
+
+/// Render an encounter as a FHIR `Encounter`
+pub fn encounter_to_fhir(encounter: &Encounter) -> Value {
+    let mut resource = base_resource("Encounter", &encounter.metadata);
+    let (class_code, class_display) = encounter_class(&encounter.class);
+
+    resource.insert("status".into(), json!(encounter_status(&encounter.status)));
+    resource.insert(
+        "class".into(),
+        json!({"system": ACT_CODE_SYSTEM, "code": class_code, "display": class_display}),
+    );
+    if let Some(type_) = &encounter.type_ {
+        resource.insert("type".into(), json!([{"text": type_}]));
+    }
+    if let Some(priority) = &encounter.priority {
+        resource.insert("priority".into(), json!({"text": priority}));
+    }
+    resource.insert("subject".into(), reference("Patient", encounter.subject));
+
+    let participants: Vec<Value> = encounter
+        .participants
+        .iter()
+        .filter_map(|participant| participant.individual)
+        .map(|individual| json!({"individual": reference("Practitioner", individual)}))
+        .collect();
+    if !participants.is_empty() {
+        resource.insert("participant".into(), Value::Array(participants));
+    }
+
+    if let Some(period) = &encounter.period {
+        resource.insert("period".into(), period_json(period.start, period.end));
+    }
+    if !encounter.diagnosis.is_empty() {
+        let diagnoses: Vec<Value> = encounter
+            .diagnosis
+            .iter()
+            .map(|diagnosis| {
+                let mut entry = json!({"condition": reference("Condition", diagnosis.condition)});
+                if let Some(use_) = &diagnosis.use_ {
+                    entry["use"] = json!({"text": use_});
+                }
+                if let Some(rank) = diagnosis.rank {
+                    entry["rank"] = json!(rank);
+                }
+                entry
+            })
+            .collect();
+        resource.insert("diagnosis".into(), Value::Array(diagnoses));
+    }
+    if !encounter.location.is_empty() {
+        let locations: Vec<Value> = encounter
+            .location
+            .iter()
+            .map(|location| json!({"location": reference("Location", location.location)}))
+            .collect();
+        resource.insert("location".into(), Value::Array(locations));
+    }
+    if !encounter.reason.is_empty() {
+        let reasons: Vec<Value> = encounter.reason.iter().map(|reason| json!({"text": reason})).collect();
+        resource.insert("reasonCode".into(), Value::Array(reasons));
+    }
+    if let Some(provider) = encounter.service_provider {
+        resource.insert("serviceProvider".into(), reference("Organization", provider));
+    }
+
+    Value::Object(resource)
+}
+
+/// Render an observation as a FHIR `Observation`
+pub fn observation_to_fhir(observation: &Observation) -> Value {
+    let mut resource = base_resource("Observation", &observation.metadata);
+
+    if !observation.based_on.is_empty() {
+        let orders = observation.based_on.iter().map(|id| reference("ServiceRequest", *id)).collect();
+        resource.insert("basedOn".into(), Value::Array(orders));
+    }
+    resource.insert("status".into(), json!(observation_status(&observation.status)));
+    if !observation.category.is_empty() {
+        let categories: Vec<Value> = observation
+            .category
+            .iter()
+            .map(|category| json!({"coding": [{"system": OBSERVATION_CATEGORY_SYSTEM, "code": category}]}))
+            .collect();
+        resource.insert("category".into(), Value::Array(categories));
+    }
+    let (code_system, code) = observation.coding();
+    resource.insert("code".into(), json!({"coding": [{"system": code_system, "code": code}]}));
+    resource.insert("subject".into(), reference("Patient", observation.subject));
+    if let Some(encounter) = observation.encounter {
+        resource.insert("encounter".into(), reference("Encounter", encounter));
+    }
+    if let Some(effective) = observation.effective {
+        resource.insert("effectiveDateTime".into(), json!(effective.to_rfc3339()));
+    }
+    if let Some(issued) = observation.issued {
+        resource.insert("issued".into(), json!(issued.to_rfc3339()));
+    }
+    if let Some(value) = &observation.value {
+        let (key, value) = observation_value(value);
+        resource.insert(key.into(), value);
+    }
+    if !observation.interpretation.is_empty() {
+        let interpretations: Vec<Value> = observation
+            .interpretation
+            .iter()
+            .map(|interpretation| json!({"text": interpretation}))
+            .collect();
+        resource.insert("interpretation".into(), Value::Array(interpretations));
+    }
+    if !observation.note.is_empty() {
+        let notes: Vec<Value> = observation.note.iter().map(|note| json!({"text": note})).collect();
+        resource.insert("note".into(), Value::Array(notes));
+    }
+    if let Some(method) = &observation.method {
+        resource.insert("method".into(), json!({"text": method}));
+    }
+    if !observation.has_member.is_empty() {
+        let members = observation.has_member.iter().map(|id| reference("Observation", *id)).collect();
+        resource.insert("hasMember".into(), Value::Array(members));
+    }
+    if !observation.derived_from.is_empty() {
+        let sources = observation.derived_from.iter().map(|id| reference("Observation", *id)).collect();
+        resource.insert("derivedFrom".into(), Value::Array(sources));
+    }
+
+    let mut resource = Value::Object(resource);
+    label_resource(&mut resource, observation.confidentiality);
+    resource
+}
+
+/// Render a provenance record as a FHIR `Provenance`
+///
+/// The source system is the `author` agent and the import itself the
+/// `assembler`, named with its transformation version. Original identifiers
+/// and the source file or URL become `source` entities.
+pub fn provenance_to_fhir(provenance: &Provenance) -> Value {
+    let activity_display = match provenance.activity {
+        ProvenanceActivity::Import => "Imported from batch file",
+        ProvenanceActivity::FhirSync => "Synchronized from FHIR server",
+    };
+
+    let mut entities: Vec<Value> = provenance
+        .original_identifiers
+        .iter()
+        .map(|identifier| {
+            let mut fhir_identifier = Map::new();
+            if let Some(system) = &identifier.system {
+                fhir_identifier.insert("system".into(), json!(system));
+            }
+            fhir_identifier.insert("value".into(), json!(identifier.value));
+            json!({"role": "source", "what": {"identifier": fhir_identifier}})
+        })
+        .collect();
+    if let Some(source) = &provenance.source_reference {
+        entities.push(json!({"role": "source", "what": {"display": source}}));
+    }
+
+    let mut resource = Map::new();
+    resource.insert("resourceType".into(), json!("Provenance"));
+    resource.insert("id".into(), json!(provenance.id.to_string()));
+    resource.insert(
+        "target".into(),
+        json!([{"reference": format!("{}/{}", provenance.target_type, provenance.target_id)}]),
+    );
+    resource.insert("recorded".into(), json!(provenance.recorded_at.to_rfc3339()));
+    resource.insert(
+        "activity".into(),
+        json!({"coding": [{"system": DATA_OPERATION_SYSTEM, "code": "CREATE"}], "text": activity_display}),
+    );
+    resource.insert(
+        "agent".into(),
+        json!([
+            {
+                "type": {"coding": [{"system": PARTICIPANT_TYPE_SYSTEM, "code": "author"}]},
+                "who": {"display": provenance.source_system}
+            },
+            {
+                "type": {"coding": [{"system": PARTICIPANT_TYPE_SYSTEM, "code": "assembler"}]},
+                "who": {"display": format!("EMR {} {}", provenance.activity.as_str(), provenance.transformation_version)}
+            }
+        ]),
+    );
+    if !entities.is_empty() {
+        resource.insert("entity".into(), Value::Array(entities));
+    }
+
+    Value::Object(resource)
+}
+
+/// Render a clinical note as a FHIR `Composition`
+///
+/// Sections keep their Markdown source as escaped narrative. A signed note
+/// names its author as `legal` attester, and addenda follow the note's own
+/// sections as sections titled "Addendum".
+pub fn clinical_note_to_fhir(note: &ClinicalNote) -> Value {
+    let (type_code, type_display) = note.note_type.loinc();
+
+    let mut sections: Vec<Value> = note
+        .sections
+        .iter()
+        .map(|section| {
+            let mut fhir_section = Map::new();
+            fhir_section.insert("title".into(), json!(section.title));
+            if let Some(code) = &section.code {
+                fhir_section.insert("code".into(), json!({"coding": [{"system": LOINC_SYSTEM, "code": code}]}));
+            }
+            fhir_section.insert("text".into(), narrative(&section.text));
+            Value::Object(fhir_section)
+        })
+        .collect();
+    sections.extend(note.addenda.iter().map(|addendum| {
+        json!({
+            "title": "Addendum",
+            "author": [reference("Practitioner", addendum.author_id)],
+            "text": narrative(&format!(
+                "{}\nReason: {}\n\n{}",
+                addendum.created_at.to_rfc3339(),
+                addendum.reason,
+                addendum.text
+            )),
+        })
+    }));
+
+    let mut resource = base_resource("Composition", &note.metadata);
+    resource.insert("status".into(), json!(note.status.as_str()));
+    resource.insert(
+        "type".into(),
+        json!({"coding": [{"system": LOINC_SYSTEM, "code": type_code, "display": type_display}]}),
+    );
+    resource.insert("subject".into(), reference("Patient", note.patient_id));
+    if let Some(encounter_id) = note.encounter_id {
+        resource.insert("encounter".into(), reference("Encounter", encounter_id));
+    }
+    resource.insert(
+        "date".into(),
+        json!(note.signed_at.unwrap_or(note.metadata.updated_at).to_rfc3339()),
+    );
+    resource.insert("author".into(), json!([reference("Practitioner", note.author_id)]));
+    resource.insert("title".into(), json!(note.title));
+    if let Some(signed_at) = note.signed_at {
+        resource.insert(
+            "attester".into(),
+            json!([{
+                "mode": "legal",
+                "time": signed_at.to_rfc3339(),
+                "party": reference("Practitioner", note.author_id),
+            }]),
+        );
+    }
+    if !sections.is_empty() {
+        resource.insert("section".into(), Value::Array(sections));
+    }
+
+    Value::Object(resource)
+}
+
+/// Render an order as a FHIR `ServiceRequest`
+pub fn service_request_to_fhir(request: &ServiceRequest) -> Value {
+    let (category_code, category_display) = request.category.snomed();
+
+    let mut code = Map::new();
+    let mut coding = json!({"system": request.code_system, "code": request.code});
+    if let Some(display) = &request.display {
+        coding["display"] = json!(display);
+        code.insert("text".into(), json!(display));
+    }
+    code.insert("coding".into(), json!([coding]));
+
+    let mut resource = base_resource("ServiceRequest", &request.metadata);
+    resource.insert("status".into(), json!(request.status.as_str()));
+    resource.insert("intent".into(), json!("order"));
+    resource.insert(
+        "category".into(),
+        json!([{"coding": [{"system": SNOMED_SYSTEM, "code": category_code, "display": category_display}]}]),
+    );
+    resource.insert("priority".into(), json!(request.priority.as_str()));
+    resource.insert("code".into(), Value::Object(code));
+    resource.insert("subject".into(), reference("Patient", request.patient_id));
+    if let Some(encounter_id) = request.encounter_id {
+        resource.insert("encounter".into(), reference("Encounter", encounter_id));
+    }
+    resource.insert("authoredOn".into(), json!(request.authored_on.to_rfc3339()));
+    resource.insert("requester".into(), reference("Practitioner", request.requester_id));
+    if let Some(performer_id) = request.performer_id {
+        resource.insert("performer".into(), json!([reference("Practitioner", performer_id)]));
+    }
+    if let Some(reason) = &request.reason {
+        resource.insert("reasonCode".into(), json!([{"text": reason}]));
+    }
+
+    Value::Object(resource)
+}
+
+/// Local orders a `DiagnosticReport` is `basedOn`
+///
+/// Only `ServiceRequest/{uuid}` references count; other references, and
+/// resources that are not DiagnosticReports, yield nothing.
+pub fn report_based_on(report: &Value) -> Vec<Id> {
+    if report.get("resourceType").and_then(Value::as_str) != Some("DiagnosticReport") {
+        return Vec::new();
+    }
+
+    report
+        .get("basedOn")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|based_on| based_on.get("reference")?.as_str()?.strip_prefix("ServiceRequest/"))
+        .filter_map(|id| id.parse().ok())
+        .collect()
+}
+
+/// Convert a care team to a FHIR `CareTeam`
+pub fn care_team_to_fhir(team: &CareTeam) -> Value {
+    let participants: Vec<Value> = team
+        .participants
+        .iter()
+        .map(|participant| {
+            let (code, display) = participant.role.snomed();
+            json!({
+                "role": [{"coding": [{"system": SNOMED_SYSTEM, "code": code, "display": display}]}],
+                "member": reference("Practitioner", participant.practitioner_id),
+                "period": period_json(Some(participant.start), participant.end),
+            })
+        })
+        .collect();
+
+    let mut resource = base_resource("CareTeam", &team.metadata);
+    resource.insert("status".into(), json!(team.status.as_str()));
+    resource.insert("name".into(), json!(team.name));
+    resource.insert("subject".into(), reference("Patient", team.patient_id));
+    if !participants.is_empty() {
+        resource.insert("participant".into(), Value::Array(participants));
+    }
+
+    Value::Object(resource)
+}
+
+/// Convert a result acknowledgment task to a FHIR `Task`
+pub fn acknowledgment_task_to_fhir(task: &AcknowledgmentTask) -> Value {
+    let mut resource = Map::new();
+    resource.insert("resourceType".into(), json!("Task"));
+    resource.insert("id".into(), json!(task.id.to_string()));
+    resource.insert("status".into(), json!(task.status.as_str()));
+    resource.insert("intent".into(), json!("order"));
+    resource.insert("priority".into(), json!(if task.critical { "stat" } else { "routine" }));
+    resource.insert("description".into(), json!(format!("Acknowledge result: {}", task.test_name)));
+    if let Some(order_id) = task.order_id {
+        resource.insert("basedOn".into(), json!([reference("ServiceRequest", order_id)]));
+    }
+    resource.insert("focus".into(), reference("Observation", task.observation_id));
+    resource.insert("for".into(), reference("Patient", task.patient_id));
+    resource.insert("authoredOn".into(), json!(task.created_at.to_rfc3339()));
+    resource.insert("owner".into(), reference("Practitioner", task.owner_id));
+    if let Some(acknowledged_at) = task.acknowledged_at {
+        resource.insert("lastModified".into(), json!(acknowledged_at.to_rfc3339()));
+        resource.insert("executionPeriod".into(), period_json(Some(task.created_at), Some(acknowledged_at)));
+    }
+    if let Some(note) = &task.note {
+        resource.insert("note".into(), json!([{"text": note}]));
+    }
+
+    Value::Object(resource)
+}
+
+/// Convert a referral to the FHIR `ServiceRequest` sent to the target organization
+///
+/// The referral's documents go along as `supportingInfo` references.
+pub fn referral_to_fhir(referral: &Referral) -> Value {
+    let (category_code, category_display) = RequestCategory::Referral.snomed();
+
+    let mut resource = base_resource("ServiceRequest", &referral.metadata);
+    resource.insert("status".into(), json!(referral_request_status(referral.status)));
+    resource.insert("intent".into(), json!("order"));
+    resource.insert(
+        "category".into(),
+        json!([{"coding": [{"system": SNOMED_SYSTEM, "code": category_code, "display": category_display}]}]),
+    );
+    resource.insert("priority".into(), json!(referral.priority.as_str()));
+    resource.insert(
+        "code".into(),
+        json!({"coding": [{"system": SNOMED_SYSTEM, "code": category_code, "display": category_display}]}),
+    );
+    resource.insert("subject".into(), reference("Patient", referral.patient_id));
+    if let Some(sent_at) = referral.sent_at {
+        resource.insert("authoredOn".into(), json!(sent_at.to_rfc3339()));
+    }
+    resource.insert("requester".into(), reference("Practitioner", referral.requester_id));
+    resource.insert("performer".into(), json!([reference("Organization", referral.target_organization_id)]));
+    resource.insert("reasonCode".into(), json!([{"text": referral.reason}]));
+    if !referral.document_ids.is_empty() {
+        let documents: Vec<Value> = referral
+            .document_ids
+            .iter()
+            .map(|id| reference("DocumentReference", *id))
+            .collect();
+        resource.insert("supportingInfo".into(), Value::Array(documents));
+    }
+
+    Value::Object(resource)
+}
+
+/// Convert a referral to the FHIR `Task` the target organization updates to respond
+///
+/// The task has the referral's id and focuses on the `ServiceRequest` of
+/// [`referral_to_fhir`].
+pub fn referral_task_to_fhir(referral: &Referral) -> Value {
+    let mut resource = base_resource("Task", &referral.metadata);
+    resource.insert("status".into(), json!(referral_task_status(referral.status)));
+    if let Some(reason) = &referral.decline_reason {
+        resource.insert("statusReason".into(), json!({"text": reason}));
+    }
+    resource.insert("intent".into(), json!("order"));
+    resource.insert("priority".into(), json!(referral.priority.as_str()));
+    resource.insert(
+        "code".into(),
+        json!({"coding": [{"system": TASK_CODE_SYSTEM, "code": "fulfill", "display": "Fulfill the focal request"}]}),
+    );
+    resource.insert("focus".into(), reference("ServiceRequest", referral.metadata.id));
+    resource.insert("for".into(), reference("Patient", referral.patient_id));
+    if let Some(sent_at) = referral.sent_at {
+        resource.insert("authoredOn".into(), json!(sent_at.to_rfc3339()));
+    }
+    resource.insert("lastModified".into(), json!(referral.metadata.updated_at.to_rfc3339()));
+    resource.insert("requester".into(), reference("Organization", referral.source_organization_id));
+    resource.insert("owner".into(), reference("Organization", referral.target_organization_id));
+
+    Value::Object(resource)
+}
+
+/// Referral status a target organization set on its `Task`, with the
+/// `statusReason` text
+///
+/// Task statuses that mean work has not been picked up yet (`requested`,
+/// `received`, `ready`) read as sent, and `in-progress` as accepted.
+/// Returns `None` for anything that is not a Task with a known status.
+pub fn referral_status_from_task(task: &Value) -> Option<(ReferralStatus, Option<String>)> {
+    if task.get("resourceType").and_then(Value::as_str) != Some("Task") {
+        return None;
+    }
+
+    let status = match task.get("status")?.as_str()? {
+        "draft" => ReferralStatus::Draft,
+        "requested" | "received" | "ready" => ReferralStatus::Sent,
+        "accepted" | "in-progress" => ReferralStatus::Accepted,
+        "rejected" => ReferralStatus::Declined,
+        "completed" => ReferralStatus::Completed,
+        "cancelled" => ReferralStatus::Cancelled,
+        _ => return None,
+    };
+    let reason = task
+        .get("statusReason")
+        .and_then(|reason| reason.get("text"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Some((status, reason))
+}
+
+/// Convert a questionnaire to a FHIR `Questionnaire`
+///
+/// Option scores become `ordinalValue` extensions on the option codings.
+pub fn questionnaire_to_fhir(questionnaire: &Questionnaire) -> Value {
+    let mut resource = base_resource("Questionnaire", &questionnaire.metadata);
+    if let Some(url) = &questionnaire.url {
+        resource.insert("url".into(), json!(url));
+    }
+    resource.insert("title".into(), json!(questionnaire.title));
+    resource.insert("status".into(), json!(questionnaire.status.as_str()));
+    resource.insert("subjectType".into(), json!(["Patient"]));
+    resource.insert(
+        "item".into(),
+        Value::Array(questionnaire.items.iter().map(questionnaire_item).collect()),
+    );
+
+    Value::Object(resource)
+}
+
+/// Convert a response to a FHIR `QuestionnaireResponse`
+///
+/// Answers are nested in the groups of `questionnaire`, the questionnaire
+/// the response answers; groups without answers are left out.
+pub fn questionnaire_response_to_fhir(response: &QuestionnaireResponse, questionnaire: &Questionnaire) -> Value {
+    let mut resource = base_resource("QuestionnaireResponse", &response.metadata);
+    let canonical = questionnaire
+        .url
+        .clone()
+        .unwrap_or_else(|| format!("Questionnaire/{}", questionnaire.metadata.id));
+    resource.insert("questionnaire".into(), json!(canonical));
+    resource.insert("status".into(), json!(response.status.as_str()));
+    resource.insert("subject".into(), reference("Patient", response.patient_id));
+    resource.insert("authored".into(), json!(response.authored.to_rfc3339()));
+    if let Some(author_id) = response.author_id {
+        resource.insert("author".into(), reference("Practitioner", author_id));
+    }
+    resource.insert("item".into(), Value::Array(response_items(&questionnaire.items, response)));
+
+    Value::Object(resource)
+}
+
+/// Render a medication order as a FHIR `MedicationRequest`
+pub fn medication_request_to_fhir(request: &MedicationRequest) -> Value {
+    let mut resource = base_resource("MedicationRequest", &request.metadata);
+    resource.insert("status".into(), json!(request.status.as_str()));
+    resource.insert("intent".into(), json!("order"));
+    resource.insert("medicationCodeableConcept".into(), medication_concept(request));
+    resource.insert("subject".into(), reference("Patient", request.patient_id));
+    if let Some(encounter_id) = request.encounter_id {
+        resource.insert("encounter".into(), reference("Encounter", encounter_id));
+    }
+    resource.insert("authoredOn".into(), json!(request.authored_on.to_rfc3339()));
+    resource.insert("requester".into(), reference("Practitioner", request.requester_id));
+    if let Some(reason) = &request.reason {
+        resource.insert("reasonCode".into(), json!([{"text": reason}]));
+    }
+
+    let mut repeat = Map::new();
+    repeat.insert("boundsPeriod".into(), period_json(Some(request.start), request.end));
+    if let Some(hours) = request.frequency_hours {
+        repeat.insert("frequency".into(), json!(1));
+        repeat.insert("period".into(), json!(hours));
+        repeat.insert("periodUnit".into(), json!("h"));
+    }
+    let mut dosage = Map::new();
+    dosage.insert("timing".into(), json!({"repeat": repeat}));
+    dosage.insert("asNeededBoolean".into(), json!(request.as_needed));
+    if let Some(route) = &request.route {
+        dosage.insert("route".into(), json!({"text": route}));
+    }
+    dosage.insert(
+        "doseAndRate".into(),
+        json!([{"doseQuantity": dose_quantity(request.dose_quantity, &request.dose_unit)}]),
+    );
+    resource.insert("dosageInstruction".into(), json!([dosage]));
+
+    Value::Object(resource)
+}
+
+/// Render a documented dose as a FHIR `MedicationAdministration`
+///
+/// `request` is the order the dose belongs to, which names the medication.
+pub fn medication_administration_to_fhir(
+    administration: &MedicationAdministration,
+    request: &MedicationRequest,
+) -> Value {
+    let mut resource = base_resource("MedicationAdministration", &administration.metadata);
+    resource.insert("status".into(), json!(administration.outcome.fhir_status()));
+    if administration.outcome != AdministrationOutcome::Given {
+        if let Some(reason) = &administration.reason {
+            resource.insert("statusReason".into(), json!([{"text": reason}]));
+        }
+    } else if let Some(reason) = &administration.reason {
+        resource.insert("reasonCode".into(), json!([{"text": reason}]));
+    }
+    resource.insert("medicationCodeableConcept".into(), medication_concept(request));
+    resource.insert("subject".into(), reference("Patient", administration.patient_id));
+    if let Some(encounter_id) = administration.encounter_id {
+        resource.insert("context".into(), reference("Encounter", encounter_id));
+    }
+    resource.insert("effectiveDateTime".into(), json!(administration.occurred_at.to_rfc3339()));
+    resource.insert("performer".into(), json!([{"actor": reference("Practitioner", administration.performer_id)}]));
+    resource.insert("request".into(), reference("MedicationRequest", administration.request_id));
+    if let Some(note) = &administration.note {
+        resource.insert("note".into(), json!([{"text": note}]));
+    }
+    if administration.outcome == AdministrationOutcome::Given {
+        let dose = match (administration.dose_quantity, &administration.dose_unit) {
+            (Some(quantity), Some(unit)) => dose_quantity(quantity, unit),
+            _ => dose_quantity(request.dose_quantity, &request.dose_unit),
+        };
+        resource.insert("dosage".into(), json!({"dose": dose}));
+    }
+
+    Value::Object(resource)
+}
+
+/// Render a PACS study as a FHIR `ImagingStudy`
+///
+/// The resource id is the Study Instance UID. DICOM times carry no offset, so
+/// `started` is only the date.
+pub fn imaging_study_to_fhir(study: &ImagingStudy) -> Value {
+    let modality = |code: &str| json!({"system": DICOM_MODALITY_SYSTEM, "code": code});
+
+    let mut identifiers = vec![json!({
+        "system": "urn:dicom:uid",
+        "value": format!("urn:oid:{}", study.study_instance_uid),
+    })];
+    if let Some(accession_number) = &study.accession_number {
+        identifiers.push(json!({
+            "type": {"coding": [{"system": IDENTIFIER_TYPE_SYSTEM, "code": "ACSN"}]},
+            "value": accession_number,
+        }));
+    }
+
+    let mut resource = Map::new();
+    resource.insert("resourceType".into(), json!("ImagingStudy"));
+    resource.insert("id".into(), json!(study.study_instance_uid));
+    resource.insert("identifier".into(), Value::Array(identifiers));
+    resource.insert("status".into(), json!("available"));
+    if !study.modalities.is_empty() {
+        let modalities: Vec<Value> = study.modalities.iter().map(|code| modality(code)).collect();
+        resource.insert("modality".into(), Value::Array(modalities));
+    }
+    resource.insert("subject".into(), reference("Patient", study.patient_id));
+    if let Some(started) = study.started {
+        resource.insert("started".into(), json!(started.date().to_string()));
+    }
+    if let Some(referrer) = &study.referring_physician {
+        resource.insert("referrer".into(), json!({"display": referrer}));
+    }
+    resource.insert("numberOfSeries".into(), json!(study.number_of_series));
+    resource.insert("numberOfInstances".into(), json!(study.number_of_instances));
+    if let Some(description) = &study.description {
+        resource.insert("description".into(), json!(description));
+    }
+    if !study.series.is_empty() {
+        let series: Vec<Value> = study
+            .series
+            .iter()
+            .map(|series| {
+                let mut entry = Map::new();
+                entry.insert("uid".into(), json!(series.uid));
+                if let Some(number) = series.number {
+                    entry.insert("number".into(), json!(number));
+                }
+                // Modality is required; OT is DICOM's "other"
+                entry.insert("modality".into(), modality(series.modality.as_deref().unwrap_or("OT")));
+                if let Some(description) = &series.description {
+                    entry.insert("description".into(), json!(description));
+                }
+                entry.insert("numberOfInstances".into(), json!(series.number_of_instances));
+                if let Some(body_site) = &series.body_site {
+                    entry.insert("bodySite".into(), json!({"display": body_site}));
+                }
+                if let Some(started) = series.started {
+                    entry.insert("started".into(), json!(started.date().to_string()));
+                }
+                Value::Object(entry)
+            })
+            .collect();
+        resource.insert("series".into(), Value::Array(series));
+    }
+
+    Value::Object(resource)
+}
+
+fn medication_concept(request: &MedicationRequest) -> Value {
+    let mut coding = json!({"system": RXNORM_SYSTEM, "code": request.medication_code});
+    match &request.medication_display {
+        Some(display) => {
+            coding["display"] = json!(display);
+            json!({"coding": [coding], "text": display})
+        }
+        None => json!({"coding": [coding]}),
+    }
+}
+
+fn dose_quantity(value: f64, unit: &str) -> Value {
+    json!({"value": value, "unit": unit, "system": UCUM_SYSTEM, "code": unit})
+}
+
+fn questionnaire_item(item: &QuestionnaireItem) -> Value {
+    let mut fhir_item = Map::new();
+    fhir_item.insert("linkId".into(), json!(item.link_id));
+    if let Some(text) = &item.text {
+        fhir_item.insert("text".into(), json!(text));
+    }
+    fhir_item.insert("type".into(), json!(item.item_type.as_str()));
+    if item.item_type.is_question() {
+        fhir_item.insert("required".into(), json!(item.required));
+        fhir_item.insert("repeats".into(), json!(item.repeats));
+    }
+    if !item.answer_options.is_empty() {
+        let options: Vec<Value> = item
+            .answer_options
+            .iter()
+            .map(|option| {
+                let mut coding = json!({"code": option.code, "display": option.display});
+                if let Some(score) = option.score {
+                    coding["extension"] = json!([{"url": ORDINAL_VALUE_EXTENSION, "valueDecimal": score}]);
+                }
+                json!({"valueCoding": coding})
+            })
+            .collect();
+        fhir_item.insert("answerOption".into(), Value::Array(options));
+    }
+    if !item.enable_when.is_empty() {
+        let conditions: Vec<Value> = item
+            .enable_when
+            .iter()
+            .map(|condition| {
+                let (key, value) = answer_value("answer", &condition.answer, None);
+                json!({"question": condition.question, "operator": condition.operator.as_str(), key: value})
+            })
+            .collect();
+        fhir_item.insert("enableWhen".into(), Value::Array(conditions));
+        if item.enable_when.len() > 1 {
+            let behavior = match item.enable_behavior {
+                EnableBehavior::All => "all",
+                EnableBehavior::Any => "any",
+            };
+            fhir_item.insert("enableBehavior".into(), json!(behavior));
+        }
+    }
+    if !item.items.is_empty() {
+        fhir_item.insert("item".into(), Value::Array(item.items.iter().map(questionnaire_item).collect()));
+    }
+    Value::Object(fhir_item)
+}
+
+fn response_items(items: &[QuestionnaireItem], response: &QuestionnaireResponse) -> Vec<Value> {
+    items
+        .iter()
+        .filter_map(|item| {
+            let mut fhir_item = Map::new();
+            fhir_item.insert("linkId".into(), json!(item.link_id));
+            if let Some(text) = &item.text {
+                fhir_item.insert("text".into(), json!(text));
+            }
+            if item.item_type == ItemType::Group {
+                let children = response_items(&item.items, response);
+                if children.is_empty() {
+                    return None;
+                }
+                fhir_item.insert("item".into(), Value::Array(children));
+            } else {
+                let answer = response.answers.iter().find(|answer| answer.link_id == item.link_id)?;
+                let values: Vec<Value> = answer
+                    .values
+                    .iter()
+                    .map(|value| {
+                        let (key, value) = answer_value("value", value, Some(item));
+                        json!({ key: value })
+                    })
+                    .collect();
+                fhir_item.insert("answer".into(), Value::Array(values));
+            }
+            Some(Value::Object(fhir_item))
+        })
+        .collect()
+}
+
+/// An answer as a FHIR choice element, e.g. `valueInteger` for `prefix` "value";
+/// codings take their display from the item's options when there is an item
+fn answer_value(prefix: &str, value: &AnswerValue, item: Option<&QuestionnaireItem>) -> (String, Value) {
+    let (suffix, value) = match value {
+        AnswerValue::Boolean(value) => ("Boolean", json!(value)),
+        AnswerValue::Decimal(value) => ("Decimal", json!(value)),
+        AnswerValue::Integer(value) => ("Integer", json!(value)),
+        AnswerValue::Date(value) => ("Date", json!(value.to_string())),
+        AnswerValue::String(value) => ("String", json!(value)),
+        AnswerValue::Coding(code) => {
+            let display = item
+                .and_then(|item| item.answer_options.iter().find(|option| &option.code == code))
+                .map(|option| option.display.clone());
+            match display {
+                Some(display) => ("Coding", json!({"code": code, "display": display})),
+                None => ("Coding", json!({"code": code})),
+            }
+        }
+    };
+    (format!("{}{}", prefix, suffix), value)
+}
+
+fn base_resource(resource_type: &str, metadata: &EntityMetadata) -> Map<String, Value> {
+    let mut resource = Map::new();
+    resource.insert("resourceType".into(), json!(resource_type));
+    resource.insert("id".into(), json!(metadata.id.to_string()));
+    resource.insert(
+        "meta".into(),
+        json!({
+            "versionId": metadata.version.to_string(),
+            "lastUpdated": metadata.updated_at.to_rfc3339(),
+        }),
+    );
+    resource
+}
+
+fn reference(resource_type: &str, id: Id) -> Value {
+    json!({"reference": format!("{}/{}", resource_type, id)})
+}
+
+fn period_json(start: Option<Timestamp>, end: Option<Timestamp>) -> Value {
+    let mut period = Map::new();
+    if let Some(start) = start {
+        period.insert("start".into(), json!(start.to_rfc3339()));
+    }
+    if let Some(end) = end {
+        period.insert("end".into(), json!(end.to_rfc3339()));
+    }
+    Value::Object(period)
+}
+
+/// Narrative holding text verbatim in an XHTML `div`
+fn narrative(text: &str) -> Value {
+    let escaped = text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    json!({
+        "status": "additional",
+        "div": format!("<div xmlns=\"http://www.w3.org/1999/xhtml\"><pre>{}</pre></div>", escaped),
+    })
+}
+
+fn encounter_status(status: &EncounterStatus) -> &'static str {
+    match status {
+        EncounterStatus::Planned => "planned",
+        EncounterStatus::Arrived => "arrived",
+        EncounterStatus::Triaged => "triaged",
+        EncounterStatus::InProgress => "in-progress",
+        EncounterStatus::Onleave => "onleave",
+        EncounterStatus::Finished => "finished",
+        EncounterStatus::Cancelled => "cancelled",
+        EncounterStatus::EnteredInError => "entered-in-error",
+        EncounterStatus::Unknown => "unknown",
+    }
+}
+
+fn encounter_class(class: &EncounterClass) -> (&'static str, &'static str) {
+    match class {
+        EncounterClass::Inpatient => ("IMP", "inpatient encounter"),
+        EncounterClass::Outpatient | EncounterClass::Ambulatory => ("AMB", "ambulatory"),
+        EncounterClass::Emergency => ("EMER", "emergency"),
+        EncounterClass::Home => ("HH", "home health"),
+        EncounterClass::Field => ("FLD", "field"),
+        EncounterClass::Daytime => ("SS", "short stay"),
+        EncounterClass::Virtual => ("VR", "virtual"),
+    }
+}
+
+fn observation_status(status: &ObservationStatus) -> &'static str {
+    match status {
+        ObservationStatus::Registered => "registered",
+        ObservationStatus::Preliminary => "preliminary",
+        ObservationStatus::Final => "final",
+        ObservationStatus::Amended => "amended",
+        ObservationStatus::Corrected => "corrected",
+        ObservationStatus::Cancelled => "cancelled",
+        ObservationStatus::EnteredInError => "entered-in-error",
+        ObservationStatus::Unknown => "unknown",
+    }
+}
+
+/// `value[x]` element name and value
+/// `ServiceRequest.status` of a referral
+fn referral_request_status(status: ReferralStatus) -> &'static str {
+    match status {
+        ReferralStatus::Draft => "draft",
+        ReferralStatus::Sent | ReferralStatus::Accepted => "active",
+        ReferralStatus::Completed => "completed",
+        ReferralStatus::Declined | ReferralStatus::Cancelled => "revoked",
+    }
+}
+
+/// `Task.status` of a referral
+fn referral_task_status(status: ReferralStatus) -> &'static str {
+    match status {
+        ReferralStatus::Draft => "draft",
+        ReferralStatus::Sent => "requested",
+        ReferralStatus::Accepted => "accepted",
+        ReferralStatus::Declined => "rejected",
+        ReferralStatus::Completed => "completed",
+        ReferralStatus::Cancelled => "cancelled",
+    }
+}
+
+fn observation_value(value: &ObservationValue) -> (&'static str, Value) {
+    match value {
+        ObservationValue::Quantity { value, unit, system, code } => {
+            let mut quantity = json!({"value": value, "unit": unit});
+            if let Some(system) = system {
+                quantity["system"] = json!(system);
+            }
+            if let Some(code) = code {
+                quantity["code"] = json!(code);
+            }
+            ("valueQuantity", quantity)
+        }
+        ObservationValue::String(value) => ("valueString", json!(value)),
+        ObservationValue::Boolean(value) => ("valueBoolean", json!(value)),
+        ObservationValue::Integer(value) => ("valueInteger", json!(value)),
+        ObservationValue::Range { low, high, unit } => {
+            let mut range = Map::new();
+            if let Some(low) = low {
+                range.insert("low".into(), json!({"value": low, "unit": unit}));
+            }
+            if let Some(high) = high {
+                range.insert("high".into(), json!({"value": high, "unit": unit}));
+            }
+            ("valueRange", Value::Object(range))
+        }
+        ObservationValue::Ratio { numerator, denominator, unit } => (
+            "valueRatio",
+            json!({"numerator": {"value": numerator, "unit": unit}, "denominator": {"value": denominator}}),
+        ),
+        ObservationValue::SampledData {
+            origin,
+            period,
+            factor,
+            lower_limit,
+            upper_limit,
+            dimensions,
+            data,
+            ..
+        } => {
+            let mut sampled = json!({
+                "origin": {"value": origin},
+                "period": period,
+                "dimensions": dimensions,
+                "data": data,
+            });
+            if let Some(factor) = factor {
+                sampled["factor"] = json!(factor);
+            }
+            if let Some(lower_limit) = lower_limit {
+                sampled["lowerLimit"] = json!(lower_limit);
+            }
+            if let Some(upper_limit) = upper_limit {
+                sampled["upperLimit"] = json!(upper_limit);
+            }
+            ("valueSampledData", sampled)
+        }
+        ObservationValue::Time(time) => ("valueTime", json!(time.format("%H:%M:%S").to_string())),
+        ObservationValue::DateTime(time) => ("valueDateTime", json!(time.to_rfc3339())),
+        ObservationValue::Period { start, end } => ("valuePeriod", period_json(*start, *end)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::test_support::strategies;
+    use emr_core::validation::ValidationProfile;
+    use proptest::prelude::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_observation_to_fhir() {
+        let patient = Uuid::new_v4();
+        let mut observation = Observation::new(ObservationStatus::Final, "8867-4".to_string(), patient);
+        observation.category.push("vital-signs".to_string());
+        observation.value = Some(ObservationValue::Quantity {
+            value: 72.0,
+            unit: "/min".to_string(),
+            system: Some("http://unitsofmeasure.org".to_string()),
+            code: Some("/min".to_string()),
+        });
+
+        let resource = observation_to_fhir(&observation);
+        assert_eq!(resource["resourceType"], "Observation");
+        assert_eq!(resource["id"], observation.metadata.id.to_string());
+        assert_eq!(resource["status"], "final");
+        assert_eq!(resource["code"]["coding"][0]["code"], "8867-4");
+        assert_eq!(resource["category"][0]["coding"][0]["code"], "vital-signs");
+        assert_eq!(resource["subject"]["reference"], format!("Patient/{}", patient));
+        assert_eq!(resource["valueQuantity"]["value"], 72.0);
+        assert!(resource.get("encounter").is_none());
+        assert_eq!(resource["meta"]["security"][0]["code"], "N");
+
+        let code = "urn:emr:calculator|cha2ds2-vasc".to_string();
+        let mut score = Observation::new(ObservationStatus::Final, code, patient);
+        score.method = Some("calculated".to_string());
+        let resource = observation_to_fhir(&score);
+        assert_eq!(resource["code"]["coding"][0]["system"], "urn:emr:calculator");
+        assert_eq!(resource["code"]["coding"][0]["code"], "cha2ds2-vasc");
+        assert_eq!(resource["method"]["text"], "calculated");
+    }
+
+    #[test]
+    fn test_provenance_to_fhir() {
+        let observation = Uuid::new_v4();
+        let provenance = Provenance::new("Observation", observation, ProvenanceActivity::Import, "acme-lis", "1.2.0")
+            .with_source_reference("results.csv")
+            .with_original_identifiers(vec![emr_core::domain::values::Identifier {
+                use_: None,
+                system: Some("urn:acme-lis:accession".to_string()),
+                value: "A-1001".to_string(),
+            }]);
+
+        let resource = provenance_to_fhir(&provenance);
+        assert_eq!(resource["resourceType"], "Provenance");
+        assert_eq!(resource["target"][0]["reference"], format!("Observation/{}", observation));
+        assert_eq!(resource["agent"][0]["who"]["display"], "acme-lis");
+        assert_eq!(resource["agent"][1]["who"]["display"], "EMR import 1.2.0");
+        assert_eq!(resource["entity"][0]["what"]["identifier"]["value"], "A-1001");
+        assert_eq!(resource["entity"][1]["what"]["display"], "results.csv");
+    }
+
+    #[test]
+    fn test_clinical_note_to_fhir() {
+        use chrono::Utc;
+        use emr_core::domain::{NoteSection, NoteType};
+
+        let (patient, author) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut note = ClinicalNote::new(patient, author, NoteType::ProgressNote, "Follow-up");
+        note.sections.push(NoteSection {
+            title: "Plan".to_string(),
+            code: Some("18776-5".to_string()),
+            text: "Recheck BP in <2 weeks".to_string(),
+        });
+
+        let draft = clinical_note_to_fhir(&note);
+        assert_eq!(draft["resourceType"], "Composition");
+        assert_eq!(draft["status"], "preliminary");
+        assert_eq!(draft["type"]["coding"][0]["code"], "11506-3");
+        assert_eq!(draft["subject"]["reference"], format!("Patient/{}", patient));
+        assert!(draft.get("attester").is_none());
+        assert!(draft["section"][0]["text"]["div"].as_str().unwrap().contains("&lt;2 weeks"));
+
+        note.sign(author, Utc::now()).unwrap();
+        note.add_addendum(author, "Patient called, BP 128/82", "Home reading reported").unwrap();
+        let amended = clinical_note_to_fhir(&note);
+        assert_eq!(amended["status"], "amended");
+        assert_eq!(amended["attester"][0]["mode"], "legal");
+        assert_eq!(amended["attester"][0]["party"]["reference"], format!("Practitioner/{}", author));
+        assert_eq!(amended["section"][1]["title"], "Addendum");
+    }
+
+    #[test]
+    fn test_service_request_to_fhir() {
+        use emr_core::domain::{RequestCategory, RequestPriority};
+
+        let mut request = ServiceRequest::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            RequestCategory::Imaging,
+            LOINC_SYSTEM,
+            "36643-5",
+            RequestPriority::Stat,
+        );
+        request.display = Some("XR Chest 2 Views".to_string());
+
+        let resource = service_request_to_fhir(&request);
+        assert_eq!(resource["resourceType"], "ServiceRequest");
+        assert_eq!(resource["status"], "active");
+        assert_eq!(resource["intent"], "order");
+        assert_eq!(resource["priority"], "stat");
+        assert_eq!(resource["category"][0]["coding"][0]["code"], "363679005");
+        assert_eq!(resource["code"]["text"], "XR Chest 2 Views");
+        assert!(resource.get("performer").is_none());
+    }
+
+    #[test]
+    fn test_report_based_on() {
+        let order = Uuid::new_v4();
+        let report = json!({
+            "resourceType": "DiagnosticReport",
+            "status": "final",
+            "basedOn": [
+                {"reference": format!("ServiceRequest/{}", order)},
+                {"reference": "ServiceRequest/external-17"},
+                {"reference": "CarePlan/1"}
+            ]
+        });
+        assert_eq!(report_based_on(&report), vec![order]);
+        assert!(report_based_on(&json!({"resourceType": "Observation", "basedOn": report["basedOn"]})).is_empty());
+    }
+
+    #[test]
+    fn test_care_team_to_fhir() {
+        use emr_core::domain::CareTeamRole;
+
+        let mut team = CareTeam::new(Uuid::new_v4(), "Cardiology");
+        let start = chrono::Utc::now();
+        team.add_participant(Uuid::new_v4(), CareTeamRole::AttendingPhysician, start, None)
+            .unwrap();
+
+        let resource = care_team_to_fhir(&team);
+        assert_eq!(resource["resourceType"], "CareTeam");
+        assert_eq!(resource["status"], "active");
+        assert_eq!(resource["participant"][0]["role"][0]["coding"][0]["code"], "405279007");
+        assert!(resource["participant"][0]["period"].get("end").is_none());
+    }
+
+    #[test]
+    fn test_acknowledgment_task_to_fhir() {
+        let mut observation = Observation::new(ObservationStatus::Final, "2823-3".to_string(), Uuid::new_v4());
+        observation.interpretation.push("LL".to_string());
+        let order = Uuid::new_v4();
+        let mut task =
+            AcknowledgmentTask::for_result(&observation, Uuid::new_v4(), Some(order), "Potassium", chrono::Utc::now())
+                .unwrap();
+
+        let resource = acknowledgment_task_to_fhir(&task);
+        assert_eq!(resource["resourceType"], "Task");
+        assert_eq!(resource["status"], "requested");
+        assert_eq!(resource["priority"], "stat");
+        assert_eq!(resource["basedOn"][0]["reference"], format!("ServiceRequest/{}", order));
+        assert_eq!(resource["focus"]["reference"], format!("Observation/{}", observation.metadata.id));
+
+        task.acknowledge(task.owner_id, Some("Repeat ordered".to_string()), chrono::Utc::now()).unwrap();
+        let resource = acknowledgment_task_to_fhir(&task);
+        assert_eq!(resource["status"], "completed");
+        assert_eq!(resource["note"][0]["text"], "Repeat ordered");
+    }
+
+    #[test]
+    fn test_referral_exchange_resources() {
+        use emr_core::domain::RequestPriority;
+
+        let mut referral = Referral::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "Persistent AF, rate control advice",
+            RequestPriority::Routine,
+        );
+        referral.attach_document(Uuid::new_v4()).unwrap();
+        referral.transition(ReferralStatus::Sent, None, chrono::Utc::now()).unwrap();
+
+        let request = referral_to_fhir(&referral);
+        assert_eq!(request["resourceType"], "ServiceRequest");
+        assert_eq!(request["status"], "active");
+        assert_eq!(request["category"][0]["coding"][0]["code"], "3457005");
+        assert_eq!(
+            request["performer"][0]["reference"],
+            format!("Organization/{}", referral.target_organization_id)
+        );
+        assert_eq!(request["supportingInfo"].as_array().unwrap().len(), 1);
+
+        let mut task = referral_task_to_fhir(&referral);
+        assert_eq!(task["status"], "requested");
+        assert_eq!(task["focus"]["reference"], format!("ServiceRequest/{}", referral.metadata.id));
+        assert_eq!(referral_status_from_task(&task), Some((ReferralStatus::Sent, None)));
+
+        task["status"] = json!("rejected");
+        task["statusReason"] = json!({"text": "Out of area"});
+        assert_eq!(
+            referral_status_from_task(&task),
+            Some((ReferralStatus::Declined, Some("Out of area".to_string())))
+        );
+        assert_eq!(referral_status_from_task(&json!({"resourceType": "Task", "status": "on-hold"})), None);
+    }
+
+    #[test]
+    fn test_questionnaire_to_fhir() {
+        use emr_core::domain::{AnswerOption, EnableWhen, EnableWhenOperator, QuestionnaireStatus, ResponseAnswer};
+
+        let question = |link_id: &str, item_type: ItemType| QuestionnaireItem {
+            link_id: link_id.to_string(),
+            text: Some(format!("Question {}", link_id)),
+            item_type,
+            required: true,
+            repeats: false,
+            answer_options: Vec::new(),
+            enable_when: Vec::new(),
+            enable_behavior: EnableBehavior::All,
+            items: Vec::new(),
+        };
+        let mut smoker = question("smoker", ItemType::Choice);
+        smoker.answer_options = vec![
+            AnswerOption { code: "never".to_string(), display: "Never".to_string(), score: Some(0) },
+            AnswerOption { code: "current".to_string(), display: "Current".to_string(), score: Some(2) },
+        ];
+        let mut packs = question("packs", ItemType::Integer);
+        packs.enable_when.push(EnableWhen {
+            question: "smoker".to_string(),
+            operator: EnableWhenOperator::Equals,
+            answer: AnswerValue::Coding("current".to_string()),
+        });
+        let mut group = question("tobacco", ItemType::Group);
+        group.required = false;
+        group.items = vec![smoker, packs, question("notes", ItemType::Text)];
+        let mut questionnaire = Questionnaire::new("Tobacco use", vec![group]);
+        questionnaire.status = QuestionnaireStatus::Active;
+
+        let resource = questionnaire_to_fhir(&questionnaire);
+        assert_eq!(resource["resourceType"], "Questionnaire");
+        let smoker = &resource["item"][0]["item"][0];
+        assert_eq!(smoker["answerOption"][1]["valueCoding"]["extension"][0]["valueDecimal"], 2);
+        let packs = &resource["item"][0]["item"][1];
+        assert_eq!(packs["enableWhen"][0]["answerCoding"]["code"], "current");
+        assert_eq!(packs["enableWhen"][0]["operator"], "=");
+
+        let answers = vec![
+            ResponseAnswer { link_id: "smoker".to_string(), values: vec![AnswerValue::Coding("current".to_string())] },
+            ResponseAnswer { link_id: "packs".to_string(), values: vec![AnswerValue::Integer(10)] },
+        ];
+        let response =
+            QuestionnaireResponse::record(&questionnaire, Uuid::new_v4(), None, answers, false, chrono::Utc::now())
+                .unwrap();
+        let resource = questionnaire_response_to_fhir(&response, &questionnaire);
+        assert_eq!(resource["questionnaire"], format!("Questionnaire/{}", questionnaire.metadata.id));
+        assert_eq!(resource["status"], "in-progress");
+        let answered = &resource["item"][0]["item"];
+        assert_eq!(answered.as_array().unwrap().len(), 2, "unanswered questions are left out");
+        assert_eq!(answered[0]["answer"][0]["valueCoding"]["display"], "Current");
+        assert_eq!(answered[1]["answer"][0]["valueInteger"], 10);
+    }
+
+    #[test]
+    fn test_medication_resources() {
+        let start = chrono::Utc::now();
+        let mut request = MedicationRequest::new(Uuid::new_v4(), Uuid::new_v4(), "313782", 650.0, "mg", 6, start);
+        request.medication_display = Some("Acetaminophen 325 MG Oral Tablet".to_string());
+        let fhir = medication_request_to_fhir(&request);
+        assert_eq!(fhir["medicationCodeableConcept"]["coding"][0]["system"], RXNORM_SYSTEM);
+        assert_eq!(fhir["dosageInstruction"][0]["timing"]["repeat"]["period"], 6);
+        assert_eq!(fhir["dosageInstruction"][0]["doseAndRate"][0]["doseQuantity"]["value"], 650.0);
+
+        let mut held = MedicationAdministration::new(
+            &request,
+            Uuid::new_v4(),
+            AdministrationOutcome::Held,
+            Some(start),
+            start,
+        );
+        held.reason = Some("Patient NPO".to_string());
+        let fhir = medication_administration_to_fhir(&held, &request);
+        assert_eq!(fhir["status"], "not-done");
+        assert_eq!(fhir["statusReason"][0]["text"], "Patient NPO");
+        assert_eq!(fhir["request"]["reference"], format!("MedicationRequest/{}", request.metadata.id));
+        assert!(fhir.get("dosage").is_none());
+    }
+
+    #[test]
+    fn test_imaging_study_to_fhir() {
+        let study = ImagingStudy::from_dicom_json(
+            Uuid::new_v4(),
+            &json!({
+                "0020000D": {"vr": "UI", "Value": ["1.2.840.113619.2.55.3"]},
+                "00080050": {"vr": "SH", "Value": ["A1001"]},
+                "00080061": {"vr": "CS", "Value": ["CT"]},
+                "00080020": {"vr": "DA", "Value": ["20260304"]}
+            }),
+        )
+        .unwrap()
+        .with_instances(&[json!({
+            "0020000D": {"vr": "UI", "Value": ["1.2.840.113619.2.55.3"]},
+            "0020000E": {"vr": "UI", "Value": ["1.2.840.113619.2.55.3.1"]}
+        })])
+        .unwrap();
+
+        let resource = imaging_study_to_fhir(&study);
+        assert_eq!(resource["id"], "1.2.840.113619.2.55.3");
+        assert_eq!(resource["identifier"][0]["value"], "urn:oid:1.2.840.113619.2.55.3");
+        assert_eq!(resource["identifier"][1]["type"]["coding"][0]["code"], "ACSN");
+        assert_eq!(resource["started"], "2026-03-04");
+        assert_eq!(resource["series"][0]["modality"]["code"], "OT");
+        assert_eq!(resource["numberOfInstances"], 1);
+    }
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(encounter_status(&EncounterStatus::InProgress), "in-progress");
+        assert_eq!(encounter_class(&EncounterClass::Outpatient).0, "AMB");
+        assert_eq!(observation_status(&ObservationStatus::EnteredInError), "entered-in-error");
+    }
+
+    fn required_binding(field: &str, values: &[&str]) -> emr_core::validation::ProfileRule {
+        emr_core::validation::ProfileRule {
+            name: field.to_string(),
+            description: format!("{} is bound to its R4 value set", field),
+            field: field.to_string(),
+            check: emr_core::validation::RuleCheck::OneOf { values: values.iter().map(ToString::to_string).collect() },
+            severity: emr_core::validation::RuleSeverity::Error,
+        }
+    }
+
+    /// Required elements and bindings of an R4 resource, as a validation profile
+    fn r4_profile(entity_type: &str, mut rules: Vec<emr_core::validation::ProfileRule>) -> ValidationProfile {
+        for field in ["id", "status", "subject.reference"] {
+            rules.push(emr_core::validation::ProfileRule {
+                name: format!("{}-required", field),
+                description: format!("{} is required", field),
+                field: field.to_string(),
+                check: emr_core::validation::RuleCheck::Required,
+                severity: emr_core::validation::RuleSeverity::Error,
+            });
+        }
+        ValidationProfile {
+            id: Uuid::new_v4(),
+            tenant_id: None,
+            entity_type: entity_type.to_string(),
+            version: 1,
+            strict: true,
+            rules,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_generated_encounters_render_valid_fhir(encounter in strategies::encounter(Uuid::nil())) {
+            let profile = r4_profile(
+                "Encounter",
+                vec![
+                    required_binding(
+                        "status",
+                        &["planned", "arrived", "triaged", "in-progress", "onleave", "finished", "cancelled",
+                          "entered-in-error", "unknown"],
+                    ),
+                    required_binding("class.system", &[ACT_CODE_SYSTEM]),
+                ],
+            );
+            let resource = encounter_to_fhir(&encounter);
+            let report = profile.evaluate(&resource);
+            prop_assert!(report.violations.is_empty(), "{:?}", report.violations);
+        }
+
+        #[test]
+        fn test_generated_observations_render_valid_fhir(
+            observation in strategies::vital_sign_observation(Uuid::nil())
+        ) {
+            let profile = r4_profile(
+                "Observation",
+                vec![
+                    required_binding(
+                        "status",
+                        &["registered", "preliminary", "final", "amended", "corrected", "cancelled",
+                          "entered-in-error", "unknown"],
+                    ),
+                    required_binding("code.coding.system", &[LOINC_SYSTEM]),
+                    required_binding("valueQuantity.system", &[UCUM_SYSTEM]),
+                ],
+            );
+            let resource = observation_to_fhir(&observation);
+            let report = profile.evaluate(&resource);
+            prop_assert!(report.violations.is_empty(), "{:?}", report.violations);
+            prop_assert_eq!(&resource["category"][0]["coding"][0]["code"], "vital-signs");
+        }
+    }
+}
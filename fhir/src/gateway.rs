@@ -0,0 +1,159 @@
+//! FHIR server gateway abstraction
+//!
+//! [`FhirGateway`] is the interface the rest of the platform uses to talk to
+//! a FHIR server. [`crate::KodjinClient`] is the HTTP implementation; tests
+//! substitute their own implementations.
+
+use crate::bundle::Bundle;
+use crate::{OperationOutcome, SearchParameters};
+use async_trait::async_trait;
+use serde_json::Value;
+use thiserror::Error;
+
+/// Result type for FHIR gateway calls
+pub type FhirResult<T> = std::result::Result<T, FhirClientError>;
+
+/// Errors returned by FHIR gateway calls
+#[derive(Error, Debug)]
+pub enum FhirClientError {
+    /// The resource does not exist (404) or was deleted (410)
+    #[error("FHIR resource not found: {resource}")]
+    NotFound { resource: String },
+
+    /// The server rejected the request (4xx other than 404/410)
+    #[error("FHIR server rejected the request ({status}): {message}")]
+    Rejected {
+        status: u16,
+        message: String,
+        outcome: Option<OperationOutcome>,
+    },
+
+    /// The server could not be reached or failed (5xx, timeouts)
+    #[error("FHIR server unavailable: {message}")]
+    Unavailable { status: Option<u16>, message: String },
+
+    /// No access token could be obtained
+    #[error("FHIR authentication failed: {0}")]
+    Authentication(String),
+
+    /// The server answered with a body that could not be parsed
+    #[error("Invalid FHIR response: {0}")]
+    InvalidResponse(String),
+
+    /// The client is misconfigured
+    #[error("FHIR client configuration error: {0}")]
+    Configuration(String),
+}
+
+impl FhirClientError {
+    /// Classify an unsuccessful response
+    pub fn from_response(status: u16, resource: &str, body: &str) -> Self {
+        let outcome: Option<OperationOutcome> = serde_json::from_str(body).ok().or_else(|| {
+            // Servers answering in XML send their outcome as XML too
+            let outcome = crate::xml::from_xml(body).ok()?;
+            serde_json::from_value(outcome).ok()
+        });
+        let message = outcome
+            .as_ref()
+            .and_then(|outcome| outcome.issue.iter().find_map(|issue| issue.diagnostics.clone()))
+            .unwrap_or_else(|| body.chars().take(500).collect());
+
+        match status {
+            404 | 410 => Self::NotFound {
+                resource: resource.to_string(),
+            },
+            400..=499 => Self::Rejected {
+                status,
+                message,
+                outcome,
+            },
+            _ => Self::Unavailable {
+                status: Some(status),
+                message,
+            },
+        }
+    }
+
+    /// Whether the request may succeed if sent again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Unavailable { .. } => true,
+            Self::Rejected { status, .. } => *status == 429,
+            _ => false,
+        }
+    }
+}
+
+impl From<FhirClientError> for emr_core::Error {
+    fn from(err: FhirClientError) -> Self {
+        match err {
+            FhirClientError::Unavailable { message, .. } => emr_core::Error::external_service_error("FHIR", &message),
+            FhirClientError::Configuration(message) => emr_core::Error::configuration_error(&message),
+            err => emr_core::Error::fhir_error(&err.to_string(), None),
+        }
+    }
+}
+
+/// Operations on a FHIR server
+#[async_trait]
+pub trait FhirGateway: Send + Sync {
+    /// Get the server's CapabilityStatement
+    async fn capability_statement(&self) -> FhirResult<Value>;
+
+    /// Read a resource by type and ID
+    async fn read(&self, resource_type: &str, id: &str) -> FhirResult<Value>;
+
+    /// Search resources, returning a searchset Bundle
+    async fn search(&self, params: &SearchParameters) -> FhirResult<Value>;
+
+    /// Create a resource, returning the stored version
+    async fn create(&self, resource_type: &str, resource: &Value) -> FhirResult<Value>;
+
+    /// Update a resource, returning the stored version
+    async fn update(&self, resource_type: &str, id: &str, resource: &Value) -> FhirResult<Value>;
+
+    /// Delete a resource
+    async fn delete(&self, resource_type: &str, id: &str) -> FhirResult<()>;
+
+    /// Validate a resource with `$validate`
+    async fn validate(&self, resource_type: &str, resource: &Value) -> FhirResult<OperationOutcome>;
+
+    /// Everything the server holds for a patient (`Patient/{id}/$everything`),
+    /// with all result pages merged into one Bundle
+    async fn patient_everything(&self, id: &str) -> FhirResult<Bundle>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_from_response() {
+        assert!(matches!(
+            FhirClientError::from_response(404, "Patient/1", ""),
+            FhirClientError::NotFound { .. }
+        ));
+
+        let outcome = r#"{"resourceType": "OperationOutcome", "issue": [{"severity": "error", "code": "invalid", "diagnostics": "name is required"}]}"#;
+        match FhirClientError::from_response(422, "Patient", outcome) {
+            FhirClientError::Rejected { status, message, outcome } => {
+                assert_eq!(status, 422);
+                assert_eq!(message, "name is required");
+                assert!(outcome.is_some());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let outcome = r#"<OperationOutcome xmlns="http://hl7.org/fhir"><issue><severity value="error"/>
+            <code value="invalid"/><diagnostics value="name is required"/></issue></OperationOutcome>"#;
+        assert!(matches!(
+            FhirClientError::from_response(400, "Patient", outcome),
+            FhirClientError::Rejected { message, .. } if message == "name is required"
+        ));
+
+        let unavailable = FhirClientError::from_response(503, "Patient", "maintenance");
+        assert!(unavailable.is_retryable());
+        assert!(FhirClientError::from_response(429, "Patient", "").is_retryable());
+        assert!(!FhirClientError::from_response(400, "Patient", "").is_retryable());
+    }
+}
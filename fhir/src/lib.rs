@@ -5,15 +5,25 @@
 //! This crate provides utilities for working with FHIR R4 resources
 //! and integrating with Kodjin FHIR server.
 
+pub mod auth;
+pub mod bundle;
 pub mod client;
 pub mod converters;
-pub mod validators;
-
+pub mod gateway;
+pub mod search;
+pub mod security;
+pub mod subscription;
+pub mod xml;
+
+pub use auth::{ClientCredentials, TokenProvider};
+pub use bundle::{Bundle, BundleBuilder};
 pub use client::*;
 pub use converters::*;
-pub use validators::*;
-
-use emr_core::{Result, Error};
+pub use gateway::*;
+pub use search::*;
+pub use security::*;
+pub use subscription::*;
+pub use xml::{FhirFormat, FhirFormatError};
 
 /// FHIR resource types
 #[derive(Debug, Clone)]
@@ -55,6 +65,7 @@ impl std::fmt::Display for FhirResourceType {
 /// FHIR operation outcomes
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct OperationOutcome {
+    #[serde(rename = "resourceType")]
     pub resource_type: String,
     pub issue: Vec<OperationOutcomeIssue>,
 }
@@ -66,67 +77,9 @@ pub struct OperationOutcomeIssue {
     pub code: String,
     pub details: Option<serde_json::Value>,
     pub diagnostics: Option<String>,
-}
-
-/// FHIR search parameters
-#[derive(Debug, Default)]
-pub struct SearchParameters {
-    pub resource_type: String,
-    pub parameters: Vec<(String, String)>,
-    pub include: Vec<String>,
-    pub rev_include: Vec<String>,
-    pub count: Option<u32>,
-    pub offset: Option<u32>,
-}
-
-impl SearchParameters {
-    pub fn new(resource_type: &str) -> Self {
-        Self {
-            resource_type: resource_type.to_string(),
-            ..Default::default()
-        }
-    }
-
-    pub fn add_parameter(mut self, key: &str, value: &str) -> Self {
-        self.parameters.push((key.to_string(), value.to_string()));
-        self
-    }
-
-    pub fn with_count(mut self, count: u32) -> Self {
-        self.count = Some(count);
-        self
-    }
-
-    pub fn with_offset(mut self, offset: u32) -> Self {
-        self.offset = Some(offset);
-        self
-    }
-
-    pub fn to_query_string(&self) -> String {
-        let mut params = self.parameters.clone();
-        
-        if let Some(count) = self.count {
-            params.push(("_count".to_string(), count.to_string()));
-        }
-        
-        if let Some(offset) = self.offset {
-            params.push(("_offset".to_string(), offset.to_string()));
-        }
-        
-        for include in &self.include {
-            params.push(("_include".to_string(), include.clone()));
-        }
-        
-        for rev_include in &self.rev_include {
-            params.push(("_revinclude".to_string(), rev_include.clone()));
-        }
-        
-        params
-            .iter()
-            .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
-            .collect::<Vec<_>>()
-            .join("&")
-    }
+    /// FHIRPath of the element at fault, e.g. `Patient.name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<Vec<String>>,
 }
 
 #[cfg(test)]
@@ -170,6 +123,7 @@ mod tests {
                     code: "invalid".to_string(),
                     details: None,
                     diagnostics: Some("Invalid patient data".to_string()),
+                    expression: None,
                 }
             ],
         };
@@ -0,0 +1,362 @@
+//! Typed FHIR search builder
+//!
+//! Builds `[type]?[parameters]` queries with modifiers (`name:exact`),
+//! comparison prefixes (`date=ge2024-01-01`), chained parameters
+//! (`subject:Patient.name=peter`) and the `_sort`, `_elements`, `_include`,
+//! `_revinclude`, `_count` and `_offset` result parameters. Keys and values are
+//! percent-encoded; `:` and `.` are left readable in keys since they are part
+//! of the search syntax.
+
+use emr_core::{Error, Result};
+
+/// Search parameter modifier (`name:modifier=value`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchModifier {
+    Exact,
+    Contains,
+    Missing,
+    Not,
+    Text,
+    Above,
+    Below,
+    In,
+    NotIn,
+    Identifier,
+    /// Reference target type, e.g. `subject:Patient`
+    Type(String),
+}
+
+impl std::fmt::Display for SearchModifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact => write!(f, "exact"),
+            Self::Contains => write!(f, "contains"),
+            Self::Missing => write!(f, "missing"),
+            Self::Not => write!(f, "not"),
+            Self::Text => write!(f, "text"),
+            Self::Above => write!(f, "above"),
+            Self::Below => write!(f, "below"),
+            Self::In => write!(f, "in"),
+            Self::NotIn => write!(f, "not-in"),
+            Self::Identifier => write!(f, "identifier"),
+            Self::Type(resource_type) => write!(f, "{}", resource_type),
+        }
+    }
+}
+
+/// Comparison prefix for number, date and quantity parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchPrefix {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    /// Starts after
+    Sa,
+    /// Ends before
+    Eb,
+    /// Approximately
+    Ap,
+}
+
+impl SearchPrefix {
+    /// Prefix as written in the query value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Gt => "gt",
+            Self::Lt => "lt",
+            Self::Ge => "ge",
+            Self::Le => "le",
+            Self::Sa => "sa",
+            Self::Eb => "eb",
+            Self::Ap => "ap",
+        }
+    }
+}
+
+/// `_sort` direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// FHIR search parameters
+#[derive(Debug, Clone, Default)]
+pub struct SearchParameters {
+    pub resource_type: String,
+    pub parameters: Vec<(String, String)>,
+    pub include: Vec<String>,
+    pub rev_include: Vec<String>,
+    /// `_sort` keys, `-` prefixed for descending
+    pub sort: Vec<String>,
+    /// `_elements` to return
+    pub elements: Vec<String>,
+    pub count: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl SearchParameters {
+    pub fn new(resource_type: &str) -> Self {
+        Self {
+            resource_type: resource_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Build parameters from raw query pairs, e.g. a proxied request.
+    ///
+    /// Result parameters (`_count`, `_sort`, ...) are parsed into their typed
+    /// fields; everything else is kept as a search parameter in order.
+    pub fn from_pairs<K, V, I>(resource_type: &str, pairs: I) -> Result<Self>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        if !is_resource_type(resource_type) {
+            return Err(Error::validation_error_with_field(
+                &format!("Invalid resource type: {}", resource_type),
+                "resource_type",
+            ));
+        }
+
+        let mut params = Self::new(resource_type);
+        for (key, value) in pairs {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            if !is_parameter_name(key) {
+                return Err(Error::validation_error_with_field(
+                    &format!("Invalid search parameter: {}", key),
+                    key,
+                ));
+            }
+
+            match key {
+                "_count" => params.count = Some(parse_number(key, value)?),
+                "_offset" => params.offset = Some(parse_number(key, value)?),
+                "_include" => params.include.push(value.to_string()),
+                "_revinclude" => params.rev_include.push(value.to_string()),
+                "_sort" => params.sort.extend(split_list(value)),
+                "_elements" => params.elements.extend(split_list(value)),
+                _ => params.parameters.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        Ok(params)
+    }
+
+    pub fn add_parameter(mut self, key: &str, value: &str) -> Self {
+        self.parameters.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add `name:modifier=value`
+    pub fn with_modifier(self, name: &str, modifier: SearchModifier, value: &str) -> Self {
+        let key = format!("{}:{}", name, modifier);
+        self.add_parameter(&key, value)
+    }
+
+    /// Add `name=<prefix><value>`
+    pub fn with_prefix(self, name: &str, prefix: SearchPrefix, value: &str) -> Self {
+        let value = format!("{}{}", prefix.as_str(), value);
+        self.add_parameter(name, &value)
+    }
+
+    /// Add a chained parameter; `&["subject:Patient", "name"]` gives
+    /// `subject:Patient.name=value`
+    pub fn with_chain(self, chain: &[&str], value: &str) -> Self {
+        let key = chain.join(".");
+        self.add_parameter(&key, value)
+    }
+
+    /// Append a `_sort` key
+    pub fn sort_by(mut self, name: &str, order: SortOrder) -> Self {
+        self.sort.push(match order {
+            SortOrder::Ascending => name.to_string(),
+            SortOrder::Descending => format!("-{}", name),
+        });
+        self
+    }
+
+    /// Limit returned elements (`_elements`)
+    pub fn with_elements(mut self, elements: &[&str]) -> Self {
+        self.elements.extend(elements.iter().map(|element| element.to_string()));
+        self
+    }
+
+    /// Add an `_include`, e.g. `Observation:patient`
+    pub fn with_include(mut self, include: &str) -> Self {
+        self.include.push(include.to_string());
+        self
+    }
+
+    /// Add a `_revinclude`, e.g. `Provenance:target`
+    pub fn with_rev_include(mut self, rev_include: &str) -> Self {
+        self.rev_include.push(rev_include.to_string());
+        self
+    }
+
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn to_query_string(&self) -> String {
+        let mut params: Vec<(&str, String)> = self
+            .parameters
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect();
+
+        if !self.sort.is_empty() {
+            params.push(("_sort", self.sort.join(",")));
+        }
+
+        if !self.elements.is_empty() {
+            params.push(("_elements", self.elements.join(",")));
+        }
+
+        if let Some(count) = self.count {
+            params.push(("_count", count.to_string()));
+        }
+
+        if let Some(offset) = self.offset {
+            params.push(("_offset", offset.to_string()));
+        }
+
+        for include in &self.include {
+            params.push(("_include", include.clone()));
+        }
+
+        for rev_include in &self.rev_include {
+            params.push(("_revinclude", rev_include.clone()));
+        }
+
+        params
+            .iter()
+            .map(|(k, v)| format!("{}={}", encode_key(k), urlencoding::encode(v)))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Relative search URL, `Type?query` or just `Type`
+    pub fn to_url(&self, base_url: &str) -> String {
+        let mut url = format!("{}/{}", base_url.trim_end_matches('/'), self.resource_type);
+        let query = self.to_query_string();
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        url
+    }
+}
+
+/// Percent-encode a parameter name, keeping the `:` modifier separator
+fn encode_key(key: &str) -> String {
+    urlencoding::encode(key).replace("%3A", ":")
+}
+
+fn is_resource_type(resource_type: &str) -> bool {
+    resource_type.starts_with(|c: char| c.is_ascii_uppercase())
+        && resource_type.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_parameter_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.'))
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u32> {
+    value
+        .parse()
+        .map_err(|_| Error::validation_error_with_field(&format!("{} must be a non-negative integer", key), key))
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modifiers_prefixes_and_chains() {
+        let query = SearchParameters::new("Observation")
+            .with_modifier("code", SearchModifier::Not, "http://loinc.org|8867-4")
+            .with_prefix("date", SearchPrefix::Ge, "2024-01-01")
+            .with_prefix("date", SearchPrefix::Lt, "2024-02-01")
+            .with_chain(&["subject:Patient", "name"], "peter")
+            .to_query_string();
+
+        assert_eq!(
+            query,
+            "code:not=http%3A%2F%2Floinc.org%7C8867-4&date=ge2024-01-01&date=lt2024-02-01&subject:Patient.name=peter"
+        );
+    }
+
+    #[test]
+    fn test_values_are_encoded() {
+        let query = SearchParameters::new("Patient")
+            .with_modifier("name", SearchModifier::Contains, "O'Brien & Sons")
+            .to_query_string();
+        assert_eq!(query, "name:contains=O%27Brien%20%26%20Sons");
+    }
+
+    #[test]
+    fn test_result_parameters() {
+        let query = SearchParameters::new("Encounter")
+            .sort_by("date", SortOrder::Descending)
+            .sort_by("status", SortOrder::Ascending)
+            .with_elements(&["id", "status"])
+            .with_include("Encounter:patient")
+            .with_count(25)
+            .to_query_string();
+        assert_eq!(
+            query,
+            "_sort=-date%2Cstatus&_elements=id%2Cstatus&_count=25&_include=Encounter%3Apatient"
+        );
+    }
+
+    #[test]
+    fn test_from_pairs() {
+        let params = SearchParameters::from_pairs(
+            "Observation",
+            vec![
+                ("date", "ge2024-01-01"),
+                ("date", "le2024-12-31"),
+                ("_count", "10"),
+                ("_sort", "-date,code"),
+                ("code:text", "heart rate"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(params.parameters.len(), 3);
+        assert_eq!(params.count, Some(10));
+        assert_eq!(params.sort, vec!["-date", "code"]);
+        assert_eq!(
+            params.to_url("http://fhir.local/"),
+            "http://fhir.local/Observation?date=ge2024-01-01&date=le2024-12-31&code:text=heart%20rate&_sort=-date%2Ccode&_count=10"
+        );
+
+        assert!(SearchParameters::from_pairs("Patient", vec![("_count", "ten")]).is_err());
+        assert!(SearchParameters::from_pairs("Patient", vec![("name&x", "a")]).is_err());
+        assert!(SearchParameters::from_pairs("../admin", Vec::<(&str, &str)>::new()).is_err());
+    }
+}
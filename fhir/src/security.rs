@@ -0,0 +1,117 @@
+//! Confidentiality security labels
+//!
+//! A resource's confidentiality is the HL7 v3 Confidentiality coding in its
+//! `meta.security`; resources without one are normal. Resources a user is
+//! not cleared for are withheld from what they are sent.
+
+use crate::bundle::Bundle;
+use emr_core::domain::{Confidentiality, CONFIDENTIALITY_SYSTEM};
+use serde_json::{json, Value};
+
+/// `meta.security` coding of a confidentiality label
+pub fn security_label(label: Confidentiality) -> Value {
+    json!({
+        "system": CONFIDENTIALITY_SYSTEM,
+        "code": label.code(),
+        "display": label.display(),
+    })
+}
+
+/// Label a resource, replacing any confidentiality coding it had and
+/// keeping its other security labels
+pub fn label_resource(resource: &mut Value, label: Confidentiality) {
+    let Some(resource) = resource.as_object_mut() else {
+        return;
+    };
+    let meta = resource.entry("meta").or_insert_with(|| json!({}));
+    let Some(meta) = meta.as_object_mut() else {
+        return;
+    };
+    let security = meta.entry("security").or_insert_with(|| json!([]));
+    if let Value::Array(codings) = security {
+        codings.retain(|coding| coding["system"] != CONFIDENTIALITY_SYSTEM);
+        codings.push(security_label(label));
+    }
+}
+
+/// Confidentiality of a resource: its most confidential label, or normal
+pub fn resource_confidentiality(resource: &Value) -> Confidentiality {
+    resource["meta"]["security"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|coding| coding["system"] == CONFIDENTIALITY_SYSTEM)
+        .filter_map(|coding| coding["code"].as_str().and_then(Confidentiality::from_code))
+        .max()
+        .unwrap_or_default()
+}
+
+/// Remove the entries of a Bundle whose resource `clearance` does not
+/// clear, lowering its `total` to match; returns how many were removed
+pub fn withhold_uncleared(bundle: &mut Value, clearance: Confidentiality) -> usize {
+    let Some(entries) = bundle.get_mut("entry").and_then(Value::as_array_mut) else {
+        return 0;
+    };
+    let before = entries.len();
+    entries.retain(|entry| clearance.clears(resource_confidentiality(&entry["resource"])));
+    let withheld = before - entries.len();
+
+    if let Some(total) = bundle["total"].as_u64() {
+        bundle["total"] = json!(total.saturating_sub(withheld as u64));
+    }
+    withheld
+}
+
+/// [`withhold_uncleared`] for a typed [`Bundle`]
+pub fn withhold_uncleared_entries(bundle: &mut Bundle, clearance: Confidentiality) -> usize {
+    bundle.retain_resources(|resource| clearance.clears(resource_confidentiality(resource)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(code: &str) -> Value {
+        json!({
+            "resourceType": "Condition",
+            "id": code,
+            "meta": {"security": [
+                {"system": "http://terminology.hl7.org/CodeSystem/v3-ActCode", "code": "PSY"},
+                {"system": CONFIDENTIALITY_SYSTEM, "code": code},
+            ]},
+        })
+    }
+
+    #[test]
+    fn test_label_resource() {
+        let mut resource = condition("R");
+        label_resource(&mut resource, Confidentiality::VeryRestricted);
+        let security = resource["meta"]["security"].as_array().unwrap();
+        assert_eq!(security.len(), 2);
+        assert_eq!(security[1]["code"], "V");
+        assert_eq!(resource_confidentiality(&resource), Confidentiality::VeryRestricted);
+
+        let mut unlabelled = json!({"resourceType": "Observation"});
+        assert_eq!(resource_confidentiality(&unlabelled), Confidentiality::Normal);
+        label_resource(&mut unlabelled, Confidentiality::Normal);
+        assert_eq!(unlabelled["meta"]["security"][0]["code"], "N");
+    }
+
+    #[test]
+    fn test_withhold_uncleared() {
+        let mut bundle = json!({
+            "resourceType": "Bundle",
+            "total": 3,
+            "entry": [
+                {"resource": condition("N")},
+                {"resource": condition("R")},
+                {"resource": condition("V")},
+            ],
+        });
+
+        assert_eq!(withhold_uncleared(&mut bundle, Confidentiality::Restricted), 1);
+        assert_eq!(bundle["total"], 2);
+        assert_eq!(bundle["entry"][1]["resource"]["id"], "R");
+        assert_eq!(withhold_uncleared(&mut bundle, Confidentiality::VeryRestricted), 0);
+    }
+}
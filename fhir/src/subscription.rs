@@ -0,0 +1,379 @@
+//! FHIR R4 Subscription support
+//!
+//! Clients register a Subscription with search-style criteria and a rest-hook
+//! endpoint. The jobs crate evaluates resource changes against the registry and
+//! delivers notifications to matching endpoints.
+
+use chrono::{DateTime, Utc};
+use emr_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// FHIR Subscription resource (R4)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub resource_type: String,
+    pub id: Option<String>,
+    pub status: SubscriptionStatus,
+    pub reason: String,
+    pub criteria: String,
+    pub channel: SubscriptionChannel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Subscription status
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionStatus {
+    Requested,
+    Active,
+    Error,
+    Off,
+}
+
+/// Subscription notification channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionChannel {
+    #[serde(rename = "type")]
+    pub type_: ChannelType,
+    pub endpoint: Option<String>,
+    pub payload: Option<String>,
+    #[serde(default)]
+    pub header: Vec<String>,
+}
+
+/// Subscription channel types
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelType {
+    RestHook,
+    Websocket,
+    Email,
+    Sms,
+    Message,
+}
+
+impl Subscription {
+    /// Parse a Subscription from a FHIR JSON resource
+    pub fn from_resource(resource: &Value) -> Result<Self> {
+        serde_json::from_value(resource.clone()).map_err(|e| {
+            Error::fhir_error(&format!("Invalid Subscription resource: {}", e), Some("Subscription"))
+        })
+    }
+
+    /// Convert the Subscription into a FHIR JSON resource
+    pub fn to_resource(&self) -> Result<Value> {
+        serde_json::to_value(self).map_err(|e| {
+            Error::fhir_error(&format!("Failed to serialize Subscription: {}", e), Some("Subscription"))
+        })
+    }
+
+    /// Validate the subscription for registration
+    pub fn validate(&self) -> Result<()> {
+        if self.resource_type != "Subscription" {
+            return Err(Error::validation_error_with_field(
+                "Resource type must be Subscription",
+                "resourceType",
+            ));
+        }
+
+        SubscriptionCriteria::parse(&self.criteria)?;
+
+        if self.channel.type_ != ChannelType::RestHook {
+            return Err(Error::validation_error_with_field(
+                "Only rest-hook channels are supported",
+                "channel.type",
+            ));
+        }
+
+        match &self.channel.endpoint {
+            Some(endpoint) if endpoint.starts_with("https://") || endpoint.starts_with("http://") => {}
+            _ => {
+                return Err(Error::validation_error_with_field(
+                    "Rest-hook channels require an http(s) endpoint",
+                    "channel.endpoint",
+                ))
+            }
+        }
+
+        for header in &self.channel.header {
+            if !header.contains(':') {
+                return Err(Error::validation_error_with_field(
+                    "Channel headers must use the 'Name: value' format",
+                    "channel.header",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check if the subscription should receive notifications
+    pub fn is_active(&self) -> bool {
+        self.status == SubscriptionStatus::Active
+            && self.end.map(|end| end > Utc::now()).unwrap_or(true)
+    }
+
+    /// Channel headers split into name/value pairs
+    pub fn headers(&self) -> Vec<(String, String)> {
+        self.channel
+            .header
+            .iter()
+            .filter_map(|header| header.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect()
+    }
+
+    /// Check if the subscription criteria match a resource
+    pub fn matches(&self, resource: &Value) -> bool {
+        SubscriptionCriteria::parse(&self.criteria)
+            .map(|criteria| criteria.matches(resource))
+            .unwrap_or(false)
+    }
+}
+
+/// Parsed subscription criteria (`ResourceType?param=value&...`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionCriteria {
+    pub resource_type: String,
+    pub parameters: Vec<(String, String)>,
+}
+
+impl SubscriptionCriteria {
+    /// Parse criteria in FHIR search URL form
+    pub fn parse(criteria: &str) -> Result<Self> {
+        let (resource_type, query) = match criteria.split_once('?') {
+            Some((resource_type, query)) => (resource_type, query),
+            None => (criteria, ""),
+        };
+
+        if resource_type.is_empty() || !resource_type.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(Error::validation_error_with_field(
+                "Criteria must start with a resource type",
+                "criteria",
+            ));
+        }
+
+        let mut parameters = Vec::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| {
+                Error::validation_error_with_field("Criteria parameters must be key=value", "criteria")
+            })?;
+            let value = urlencoding::decode(value)
+                .map_err(|_| Error::validation_error_with_field("Criteria is not valid UTF-8", "criteria"))?;
+            parameters.push((key.to_string(), value.into_owned()));
+        }
+
+        Ok(Self {
+            resource_type: resource_type.to_string(),
+            parameters,
+        })
+    }
+
+    /// Check if a resource satisfies the criteria.
+    ///
+    /// Parameters are matched against the top-level element of the same name,
+    /// which covers simple tokens (`status`), codes (`code=system|code`) and
+    /// references (`subject=Patient/123`). Chained and modified parameters are
+    /// not supported and never match.
+    pub fn matches(&self, resource: &Value) -> bool {
+        if resource.get("resourceType").and_then(Value::as_str) != Some(self.resource_type.as_str()) {
+            return false;
+        }
+
+        self.parameters.iter().all(|(key, expected)| {
+            resource
+                .get(key.as_str())
+                .map(|element| element_matches(element, expected))
+                .unwrap_or(false)
+        })
+    }
+}
+
+/// Match a FHIR element against a search value
+fn element_matches(element: &Value, expected: &str) -> bool {
+    match element {
+        Value::String(value) => value == expected,
+        Value::Bool(value) => value.to_string() == expected,
+        Value::Number(value) => value.to_string() == expected,
+        Value::Array(values) => values.iter().any(|value| element_matches(value, expected)),
+        Value::Object(object) => {
+            if let Some(coding) = object.get("coding") {
+                return element_matches(coding, expected);
+            }
+            if let Some(reference) = object.get("reference").and_then(Value::as_str) {
+                return reference == expected;
+            }
+            if let Some(code) = object.get("code").and_then(Value::as_str) {
+                return match expected.split_once('|') {
+                    Some((system, expected_code)) => {
+                        code == expected_code
+                            && (system.is_empty()
+                                || object.get("system").and_then(Value::as_str) == Some(system))
+                    }
+                    None => code == expected,
+                };
+            }
+            false
+        }
+        Value::Null => false,
+    }
+}
+
+/// In-memory registry of active subscriptions
+///
+/// Current status: prototype storage; subscriptions are lost on restart.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionRegistry {
+    subscriptions: Arc<RwLock<HashMap<String, Subscription>>>,
+}
+
+impl SubscriptionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validate and register a subscription, returning the stored resource
+    pub async fn register(&self, mut subscription: Subscription) -> Result<Subscription> {
+        subscription.validate()?;
+
+        let id = subscription
+            .id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        subscription.id = Some(id.clone());
+        subscription.status = SubscriptionStatus::Active;
+        subscription.error = None;
+
+        self.subscriptions.write().await.insert(id, subscription.clone());
+        Ok(subscription)
+    }
+
+    /// Get a subscription by ID
+    pub async fn get(&self, id: &str) -> Option<Subscription> {
+        self.subscriptions.read().await.get(id).cloned()
+    }
+
+    /// Remove a subscription by ID
+    pub async fn remove(&self, id: &str) -> Option<Subscription> {
+        self.subscriptions.write().await.remove(id)
+    }
+
+    /// Find active subscriptions whose criteria match a resource
+    pub async fn matching(&self, resource: &Value) -> Vec<Subscription> {
+        self.subscriptions
+            .read()
+            .await
+            .values()
+            .filter(|subscription| subscription.is_active() && subscription.matches(resource))
+            .cloned()
+            .collect()
+    }
+
+    /// Record a delivery failure on a subscription
+    pub async fn mark_error(&self, id: &str, error: &str) {
+        if let Some(subscription) = self.subscriptions.write().await.get_mut(id) {
+            subscription.status = SubscriptionStatus::Error;
+            subscription.error = Some(error.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_subscription(criteria: &str) -> Subscription {
+        Subscription::from_resource(&json!({
+            "resourceType": "Subscription",
+            "status": "requested",
+            "reason": "Monitor lab results",
+            "criteria": criteria,
+            "channel": {
+                "type": "rest-hook",
+                "endpoint": "https://example.com/hook",
+                "payload": "application/fhir+json",
+                "header": ["Authorization: Bearer secret"]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn test_observation() -> Value {
+        json!({
+            "resourceType": "Observation",
+            "status": "final",
+            "code": {"coding": [{"system": "http://loinc.org", "code": "8867-4"}]},
+            "subject": {"reference": "Patient/123"}
+        })
+    }
+
+    #[test]
+    fn test_criteria_parsing() {
+        let criteria = SubscriptionCriteria::parse("Observation?status=final&code=http%3A%2F%2Floinc.org%7C8867-4").unwrap();
+        assert_eq!(criteria.resource_type, "Observation");
+        assert_eq!(criteria.parameters[1].1, "http://loinc.org|8867-4");
+
+        assert!(SubscriptionCriteria::parse("?status=final").is_err());
+        assert!(SubscriptionCriteria::parse("Observation?status").is_err());
+    }
+
+    #[test]
+    fn test_criteria_matching() {
+        let observation = test_observation();
+
+        assert!(test_subscription("Observation").matches(&observation));
+        assert!(test_subscription("Observation?status=final").matches(&observation));
+        assert!(test_subscription("Observation?code=http://loinc.org|8867-4").matches(&observation));
+        assert!(test_subscription("Observation?subject=Patient/123").matches(&observation));
+        assert!(!test_subscription("Observation?status=preliminary").matches(&observation));
+        assert!(!test_subscription("Patient").matches(&observation));
+    }
+
+    #[test]
+    fn test_subscription_validation() {
+        assert!(test_subscription("Observation?status=final").validate().is_ok());
+
+        let mut subscription = test_subscription("Observation");
+        subscription.channel.type_ = ChannelType::Email;
+        assert!(subscription.validate().is_err());
+
+        let mut subscription = test_subscription("Observation");
+        subscription.channel.endpoint = Some("ftp://example.com".to_string());
+        assert!(subscription.validate().is_err());
+    }
+
+    #[test]
+    fn test_subscription_headers() {
+        let subscription = test_subscription("Observation");
+        assert_eq!(
+            subscription.headers(),
+            vec![("Authorization".to_string(), "Bearer secret".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registry_matching() {
+        let registry = SubscriptionRegistry::new();
+        let registered = registry
+            .register(test_subscription("Observation?status=final"))
+            .await
+            .unwrap();
+
+        assert_eq!(registered.status, SubscriptionStatus::Active);
+        assert_eq!(registry.matching(&test_observation()).await.len(), 1);
+
+        let id = registered.id.unwrap();
+        registry.mark_error(&id, "endpoint unreachable").await;
+        assert!(registry.matching(&test_observation()).await.is_empty());
+    }
+}
@@ -0,0 +1,782 @@
+//! FHIR XML
+//!
+//! Converts resources between the JSON representation the rest of the crate
+//! works with and FHIR's XML representation (`application/fhir+xml`), for
+//! systems that only exchange XML. [`FhirFormat`] picks the representation
+//! from `Accept`, `Content-Type` and `_format` values.
+//!
+//! Writing follows the FHIR mapping rules: primitives become `value`
+//! attributes, `_name` siblings become the element's `id` and extensions,
+//! element `id` and extension `url` are attributes, contained resources are
+//! wrapped in an element named after their type, and narrative `div`s are
+//! written as XHTML. Elements are written in JSON order, which is schema
+//! order for the converters' output and for resources read from a server.
+//!
+//! Reading needs to know what XML leaves unsaid: which elements repeat and
+//! which primitives are numbers or booleans. Elements that occur more than
+//! once are arrays; single occurrences of repeating elements are recognised
+//! from [`REPEATING`] and [`REPEATING_IN`], which cover the data types and
+//! the resources the EMR exchanges. Numbers and booleans are recognised by
+//! choice-type suffix (`valueInteger`, `deceasedBoolean`) and by name.
+//! DOCTYPEs, and with them entity expansion, are rejected.
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+/// FHIR XML namespace
+pub const FHIR_NAMESPACE: &str = "http://hl7.org/fhir";
+
+/// XHTML namespace of narrative `div`s
+pub const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+
+/// Elements that repeat wherever they occur
+pub const REPEATING: &[&str] = &[
+    "extension", "modifierExtension", "contained", "identifier", "coding", "given", "prefix", "suffix", "line",
+    "telecom", "address", "contact", "entry", "link", "issue", "expression", "component", "note", "basedOn",
+    "partOf", "reasonCode", "reasonReference", "category", "participant", "performer", "qualification",
+    "communication", "generalPractitioner", "item", "answer", "answerOption", "enableWhen", "initial", "diagnosis",
+    "series", "instance", "target", "agent", "entity", "signature", "dosageInstruction", "content", "author",
+    "input", "output", "interpretation", "referenceRange", "hasMember", "derivedFrom", "header", "recipient",
+    "payload", "policy", "endpoint",
+];
+
+/// Elements that repeat only under a given parent, as `(parent, element)`;
+/// the parent is the resource type or the enclosing element's name
+pub const REPEATING_IN: &[(&str, &str)] = &[
+    ("Patient", "name"),
+    ("Practitioner", "name"),
+    ("RelatedPerson", "name"),
+    ("Person", "name"),
+    ("Encounter", "type"),
+    ("Encounter", "location"),
+    ("Organization", "type"),
+    ("Location", "type"),
+    ("participant", "type"),
+    ("ImagingStudy", "modality"),
+    ("ServiceRequest", "specimen"),
+    ("ServiceRequest", "bodySite"),
+    ("item", "code"),
+    ("issue", "location"),
+    ("repeat", "dayOfWeek"),
+    ("repeat", "timeOfDay"),
+    ("repeat", "when"),
+    ("Timing", "event"),
+    ("Bundle", "link"),
+    ("CareTeam", "managingOrganization"),
+];
+
+/// Elements of [`REPEATING`] that do not repeat under a given parent
+const SINGLE_IN: &[(&str, &str)] = &[
+    ("Bundle", "identifier"),
+    ("Location", "address"),
+    ("Encounter", "partOf"),
+    ("MedicationAdministration", "category"),
+    ("MedicationRequest", "performer"),
+    ("Provenance", "location"),
+    ("channel", "endpoint"),
+    ("channel", "payload"),
+];
+
+/// Primitive elements holding booleans, besides `...Boolean` choices
+const BOOLEAN_ELEMENTS: &[&str] = &[
+    "active", "userSelected", "required", "repeats", "readOnly", "experimental", "doNotPerform", "abstract",
+    "inactive", "preferred", "primarySource", "actual",
+];
+
+/// Primitive elements holding numbers, besides `...Integer` and `...Decimal`
+/// choices and quantity values
+const NUMBER_ELEMENTS: &[&str] = &[
+    "total", "rank", "sequence", "numberOfSeries", "numberOfInstances", "number", "count", "countMax", "duration",
+    "durationMax", "frequency", "frequencyMax", "period", "periodMax", "offset", "factor",
+];
+
+/// Choice-type suffixes of number and boolean primitives
+const NUMBER_SUFFIXES: &[&str] = &["Integer", "Integer64", "Decimal", "UnsignedInt", "PositiveInt"];
+
+/// Errors converting FHIR resources to or from their wire representation
+#[derive(Error, Debug)]
+pub enum FhirFormatError {
+    /// The body is not well-formed
+    #[error("Malformed FHIR {format}: {message}")]
+    Malformed { format: &'static str, message: String },
+
+    /// The body or value is not a FHIR resource
+    #[error("Not a FHIR resource: {0}")]
+    NotAResource(String),
+}
+
+/// Representation of FHIR resources on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FhirFormat {
+    /// `application/fhir+json`
+    #[default]
+    Json,
+    /// `application/fhir+xml`
+    Xml,
+}
+
+impl FhirFormat {
+    /// Media type of the representation
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FhirFormat::Json => "application/fhir+json",
+            FhirFormat::Xml => "application/fhir+xml",
+        }
+    }
+
+    /// The representation a media type or `_format` value names, if any
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        let media_type = media_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/fhir+json" | "application/json+fhir" | "application/json" | "json" => Some(FhirFormat::Json),
+            "application/fhir+xml" | "application/xml+fhir" | "application/xml" | "text/xml" | "xml" => {
+                Some(FhirFormat::Xml)
+            }
+            _ => None,
+        }
+    }
+
+    /// The representation an `Accept` header prefers; JSON unless XML is
+    /// the only, or the more preferred, FHIR representation it accepts
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let mut best: Option<(f32, FhirFormat)> = None;
+        for range in accept.unwrap_or_default().split(',') {
+            let Some(format) = Self::from_media_type(range) else {
+                continue;
+            };
+            let quality = range
+                .split(';')
+                .skip(1)
+                .find_map(|param| param.trim().strip_prefix("q=")?.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.map_or(true, |(best_quality, _)| quality > best_quality) {
+                best = Some((quality, format));
+            }
+        }
+        best.map_or(FhirFormat::Json, |(_, format)| format)
+    }
+
+    /// Serialize a resource
+    pub fn encode(self, resource: &Value) -> Result<Vec<u8>, FhirFormatError> {
+        match self {
+            FhirFormat::Json => serde_json::to_vec(resource).map_err(|e| FhirFormatError::NotAResource(e.to_string())),
+            FhirFormat::Xml => to_xml(resource).map(String::into_bytes),
+        }
+    }
+
+    /// Parse a resource
+    pub fn decode(self, body: &[u8]) -> Result<Value, FhirFormatError> {
+        match self {
+            FhirFormat::Json => serde_json::from_slice(body).map_err(|e| FhirFormatError::Malformed {
+                format: "JSON",
+                message: e.to_string(),
+            }),
+            FhirFormat::Xml => {
+                let xml = std::str::from_utf8(body).map_err(|e| FhirFormatError::Malformed {
+                    format: "XML",
+                    message: format!("not UTF-8 after byte {}", e.valid_up_to()),
+                })?;
+                from_xml(xml)
+            }
+        }
+    }
+
+    /// Parse a resource into `T`; JSON is read straight from the body
+    /// without building a `Value` first
+    pub fn decode_as<T: DeserializeOwned>(self, body: &[u8]) -> Result<T, FhirFormatError> {
+        let (format, parsed) = match self {
+            FhirFormat::Json => ("JSON", serde_json::from_slice(body)),
+            FhirFormat::Xml => ("XML", serde_json::from_value(self.decode(body)?)),
+        };
+        parsed.map_err(|e| FhirFormatError::Malformed {
+            format,
+            message: e.to_string(),
+        })
+    }
+}
+
+/// Render a JSON resource as FHIR XML
+pub fn to_xml(resource: &Value) -> Result<String, FhirFormatError> {
+    let object = resource
+        .as_object()
+        .ok_or_else(|| FhirFormatError::NotAResource("expected a JSON object".to_string()))?;
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    write_resource(object, &mut xml)?;
+    Ok(xml)
+}
+
+fn resource_type(object: &Map<String, Value>) -> Result<&str, FhirFormatError> {
+    object
+        .get("resourceType")
+        .and_then(Value::as_str)
+        .filter(|name| is_name(name))
+        .ok_or_else(|| FhirFormatError::NotAResource("missing or invalid resourceType".to_string()))
+}
+
+fn write_resource(object: &Map<String, Value>, xml: &mut String) -> Result<(), FhirFormatError> {
+    let name = resource_type(object)?;
+    xml.push_str(&format!("<{} xmlns=\"{}\">", name, FHIR_NAMESPACE));
+    write_children(name, object, true, xml)?;
+    xml.push_str(&format!("</{}>", name));
+    Ok(())
+}
+
+/// Write an object's properties as child elements, `id` and `extension`s first
+fn write_children(
+    parent: &str,
+    object: &Map<String, Value>,
+    resource: bool,
+    xml: &mut String,
+) -> Result<(), FhirFormatError> {
+    let first = ["id", "meta", "implicitRules", "language", "text", "contained", "extension", "modifierExtension"];
+    let leading: &[&str] = if resource { &first } else { &first[6..] };
+    let keys = leading
+        .iter()
+        .copied()
+        .filter(|key| object.contains_key(*key) || object.contains_key(&format!("_{}", key)))
+        .chain(object.keys().map(String::as_str).filter(|key| !leading.contains(key)));
+
+    let mut written = Vec::new();
+    for key in keys {
+        let name = key.strip_prefix('_').unwrap_or(key);
+        // Element attributes, written by the caller
+        if name == "resourceType" || written.contains(&name) || (!resource && is_attribute(parent, name)) {
+            continue;
+        }
+        written.push(name);
+        if !is_name(name) {
+            return Err(FhirFormatError::NotAResource(format!("{} is not an element name", name)));
+        }
+
+        let value = object.get(name).unwrap_or(&Value::Null);
+        let extra = object.get(&format!("_{}", name)).unwrap_or(&Value::Null);
+        match value {
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let extra = extra.as_array().and_then(|extras| extras.get(i)).unwrap_or(&Value::Null);
+                    write_element(parent, name, item, extra, xml)?;
+                }
+            }
+            Value::Null if extra.is_null() => {}
+            Value::Null => {
+                // A primitive with only an id or extensions
+                if let Value::Array(extras) = extra {
+                    for extra in extras {
+                        write_element(parent, name, &Value::Null, extra, xml)?;
+                    }
+                } else {
+                    write_element(parent, name, &Value::Null, extra, xml)?;
+                }
+            }
+            _ => write_element(parent, name, value, extra, xml)?,
+        }
+    }
+    Ok(())
+}
+
+/// Whether a property is written as an attribute of its parent element
+fn is_attribute(parent: &str, name: &str) -> bool {
+    name == "id" || (name == "url" && (parent == "extension" || parent == "modifierExtension"))
+}
+
+fn write_element(
+    parent: &str,
+    name: &str,
+    value: &Value,
+    extra: &Value,
+    xml: &mut String,
+) -> Result<(), FhirFormatError> {
+    match value {
+        Value::Object(object) if object.contains_key("resourceType") => {
+            xml.push_str(&format!("<{}>", name));
+            write_resource(object, xml)?;
+            xml.push_str(&format!("</{}>", name));
+        }
+        Value::Object(object) => {
+            xml.push_str(&format!("<{}", name));
+            for attribute in ["id", "url"].into_iter().filter(|attribute| is_attribute(name, attribute)) {
+                if let Some(value) = object.get(attribute).and_then(primitive_text) {
+                    xml.push_str(&format!(" {}=\"{}\"", attribute, escape(&value)));
+                }
+            }
+            xml.push('>');
+            write_children(name, object, false, xml)?;
+            xml.push_str(&format!("</{}>", name));
+        }
+        Value::String(div) if name == "div" && parent == "text" => xml.push_str(div),
+        Value::Array(_) => {
+            return Err(FhirFormatError::NotAResource(format!("{} holds nested arrays", name)));
+        }
+        _ => {
+            xml.push_str(&format!("<{}", name));
+            if let Some(id) = extra.get("id").and_then(primitive_text) {
+                xml.push_str(&format!(" id=\"{}\"", escape(&id)));
+            }
+            if let Some(text) = primitive_text(value) {
+                xml.push_str(&format!(" value=\"{}\"", escape(&text)));
+            }
+            match extra.get("extension") {
+                Some(Value::Array(extensions)) if !extensions.is_empty() => {
+                    xml.push('>');
+                    for extension in extensions {
+                        write_element(name, "extension", extension, &Value::Null, xml)?;
+                    }
+                    xml.push_str(&format!("</{}>", name));
+                }
+                _ => xml.push_str("/>"),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn primitive_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#xA;"),
+            '\r' => escaped.push_str("&#xD;"),
+            '\t' => escaped.push_str("&#x9;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parse a FHIR XML resource into its JSON representation
+pub fn from_xml(xml: &str) -> Result<Value, FhirFormatError> {
+    let root = Parser { xml, position: 0 }.document()?;
+    resource_json(&root, xml).map(Value::Object)
+}
+
+/// An element of a parsed document
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    /// Byte range of the whole element in the document
+    span: (usize, usize),
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// A reader for the subset of XML FHIR uses: elements, attributes, character
+/// data (ignored outside narrative), comments and processing instructions
+struct Parser<'a> {
+    xml: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: impl std::fmt::Display) -> FhirFormatError {
+        FhirFormatError::Malformed {
+            format: "XML",
+            message: format!("{} at byte {}", message, self.position),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.xml[self.position..]
+    }
+
+    fn skip_until(&mut self, end: &str) -> Result<(), FhirFormatError> {
+        match self.rest().find(end) {
+            Some(offset) => {
+                self.position += offset + end.len();
+                Ok(())
+            }
+            None => Err(self.error(format!("expected {}", end))),
+        }
+    }
+
+    /// Skip character data, comments and processing instructions
+    fn skip_misc(&mut self) -> Result<(), FhirFormatError> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_until("?>")?;
+            } else if rest.starts_with("<!") && !rest.starts_with("<![CDATA[") {
+                return Err(self.error("DOCTYPE declarations are not allowed"));
+            } else if rest.starts_with("<![CDATA[") {
+                self.skip_until("]]>")?;
+            } else if !rest.is_empty() && !rest.starts_with('<') {
+                self.position += rest.find('<').unwrap_or(rest.len());
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn document(mut self) -> Result<Element, FhirFormatError> {
+        self.skip_misc()?;
+        let root = self.element()?;
+        self.skip_misc()?;
+        if !self.rest().is_empty() {
+            return Err(self.error("content after the root element"));
+        }
+        Ok(root)
+    }
+
+    fn name(&mut self) -> Result<String, FhirFormatError> {
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')))
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.position += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn element(&mut self) -> Result<Element, FhirFormatError> {
+        let start = self.position;
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        self.position += 1;
+        let name = self.name()?;
+
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.position += 2;
+                return Ok(Element {
+                    name: local(&name),
+                    attributes,
+                    children: Vec::new(),
+                    span: (start, self.position),
+                });
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("expected ="));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            let quote = self.rest().chars().next().filter(|c| *c == '"' || *c == '\'');
+            let Some(quote) = quote else {
+                return Err(self.error("expected a quoted attribute value"));
+            };
+            self.position += 1;
+            let end = self.rest().find(quote).ok_or_else(|| self.error("unterminated attribute value"))?;
+            let raw = &self.rest()[..end];
+            let value = unescape(raw).map_err(|message| self.error(message))?;
+            self.position += end + 1;
+            attributes.push((key, value));
+        }
+
+        let mut children = Vec::new();
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("</") {
+                self.position += 2;
+                let closing = self.name()?;
+                if closing != name {
+                    return Err(self.error(format!("expected </{}>", name)));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error("expected >"));
+                }
+                self.position += 1;
+                break;
+            }
+            if self.rest().is_empty() {
+                return Err(self.error(format!("unclosed <{}>", name)));
+            }
+            children.push(self.element()?);
+        }
+        Ok(Element {
+            name: local(&name),
+            attributes,
+            children,
+            span: (start, self.position),
+        })
+    }
+}
+
+/// Name without its namespace prefix
+fn local(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_string()
+}
+
+fn unescape(raw: &str) -> Result<String, String> {
+    let mut text = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find('&') {
+        text.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or("unterminated entity")? + start;
+        let entity = &rest[start + 1..end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+                };
+                code.and_then(char::from_u32).ok_or_else(|| format!("unknown entity &{};", entity))?
+            }
+        };
+        text.push(c);
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+fn resource_json(element: &Element, xml: &str) -> Result<Map<String, Value>, FhirFormatError> {
+    if !element.name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err(FhirFormatError::NotAResource(format!("<{}> is not a resource", element.name)));
+    }
+    let mut object = Map::new();
+    object.insert("resourceType".to_string(), Value::String(element.name.clone()));
+    children_json(&element.name, element, true, xml, &mut object)?;
+    Ok(object)
+}
+
+/// Add an element's attributes and children to its JSON object
+fn children_json(
+    parent: &str,
+    element: &Element,
+    resource: bool,
+    xml: &str,
+    object: &mut Map<String, Value>,
+) -> Result<(), FhirFormatError> {
+    if !resource {
+        for (key, value) in &element.attributes {
+            if is_attribute(parent, key) {
+                object.insert(key.clone(), Value::String(value.clone()));
+            }
+        }
+    }
+
+    let mut names: Vec<&str> = Vec::new();
+    for child in &element.children {
+        if !names.contains(&child.name.as_str()) {
+            names.push(&child.name);
+        }
+    }
+    for name in names {
+        let group: Vec<&Element> = element.children.iter().filter(|child| child.name == name).collect();
+        let array = group.len() > 1 || repeats(parent, name);
+
+        if name == "div" && parent == "text" {
+            let (start, end) = group[0].span;
+            object.insert(name.to_string(), Value::String(xml[start..end].to_string()));
+            continue;
+        }
+
+        let mut values = Vec::new();
+        let mut extras = Vec::new();
+        for child in &group {
+            let (value, extra) = element_json(parent, child, xml)?;
+            values.push(value);
+            extras.push(extra);
+        }
+
+        if array {
+            if values.iter().any(|value| !value.is_null()) {
+                object.insert(name.to_string(), Value::Array(values));
+            }
+            if extras.iter().any(|extra| !extra.is_null()) {
+                object.insert(format!("_{}", name), Value::Array(extras));
+            }
+        } else {
+            let (value, extra) = (values.remove(0), extras.remove(0));
+            if !value.is_null() {
+                object.insert(name.to_string(), value);
+            }
+            if !extra.is_null() {
+                object.insert(format!("_{}", name), extra);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The JSON value of an element and, for primitives, its `_name` sibling
+fn element_json(parent: &str, element: &Element, xml: &str) -> Result<(Value, Value), FhirFormatError> {
+    let name = element.name.as_str();
+    // A wrapped resource, e.g. `<resource><Patient>...</Patient></resource>`
+    if let [only] = element.children.as_slice() {
+        if element.attribute("value").is_none() && only.name.starts_with(|c: char| c.is_ascii_uppercase()) {
+            return Ok((Value::Object(resource_json(only, xml)?), Value::Null));
+        }
+    }
+
+    match element.attribute("value") {
+        Some(text) => {
+            let value = primitive_json(parent, name, text);
+            let mut extra = Map::new();
+            if let Some(id) = element.attribute("id") {
+                extra.insert("id".to_string(), Value::String(id.to_string()));
+            }
+            let extensions = element.children.iter().filter(|child| child.name == "extension");
+            let mut converted = Vec::new();
+            for extension in extensions {
+                converted.push(element_json(name, extension, xml)?.0);
+            }
+            if !converted.is_empty() {
+                extra.insert("extension".to_string(), Value::Array(converted));
+            }
+            let extra = if extra.is_empty() { Value::Null } else { Value::Object(extra) };
+            Ok((value, extra))
+        }
+        None => {
+            let mut object = Map::new();
+            children_json(name, element, false, xml, &mut object)?;
+            Ok((Value::Object(object), Value::Null))
+        }
+    }
+}
+
+fn repeats(parent: &str, name: &str) -> bool {
+    if REPEATING_IN.contains(&(parent, name)) {
+        return true;
+    }
+    REPEATING.contains(&name) && !SINGLE_IN.contains(&(parent, name))
+}
+
+/// A primitive's JSON value: a number or boolean where FHIR has one
+fn primitive_json(parent: &str, name: &str, text: &str) -> Value {
+    let boolean = BOOLEAN_ELEMENTS.contains(&name) || name.ends_with("Boolean");
+    if boolean {
+        if let Ok(flag) = text.parse::<bool>() {
+            return Value::Bool(flag);
+        }
+    }
+
+    let quantity_value = name == "value"
+        && (parent.ends_with("Quantity")
+            || ["low", "high", "numerator", "denominator", "amount", "quantity", "valueAge", "valueDuration"]
+                .contains(&parent));
+    let number = quantity_value
+        || NUMBER_ELEMENTS.contains(&name)
+        || NUMBER_SUFFIXES.iter().any(|suffix| name.len() > suffix.len() && name.ends_with(suffix));
+    if number {
+        // Keep the decimal's precision, e.g. 1.50
+        if let Ok(number) = serde_json::from_str::<Number>(text) {
+            return Value::Number(number);
+        }
+    }
+    Value::String(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let observation = json!({
+            "resourceType": "Observation",
+            "id": "bp-1",
+            "text": {"status": "generated", "div": "<div xmlns=\"http://www.w3.org/1999/xhtml\">72 &amp; up</div>"},
+            "status": "final",
+            "category": [{"coding": [{"system": "http://example.org/category", "code": "vital-signs"}]}],
+            "code": {"coding": [{"system": "http://loinc.org", "code": "8867-4"}], "text": "Heart rate"},
+            "subject": {"reference": "Patient/123", "display": "Ada \"Countess\" Lovelace"},
+            "effectiveDateTime": "2026-10-17T09:30:00Z",
+            "_effectiveDateTime": {"extension": [{"url": "http://example.org/estimated", "valueBoolean": true}]},
+            "valueQuantity": {"value": 72, "unit": "beats/minute", "system": "http://unitsofmeasure.org"},
+            "note": [{"text": "Line one\nline two"}]
+        });
+
+        let xml = to_xml(&observation).unwrap();
+        assert!(xml.contains("<Observation xmlns=\"http://hl7.org/fhir\"><id value=\"bp-1\"/>"));
+        assert!(xml.contains(
+            "<effectiveDateTime value=\"2026-10-17T09:30:00Z\"><extension url=\"http://example.org/estimated\">\
+             <valueBoolean value=\"true\"/></extension></effectiveDateTime>"
+        ));
+        assert_eq!(from_xml(&xml).unwrap(), observation);
+    }
+
+    #[test]
+    fn test_read_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!-- from a legacy system -->
+            <Bundle xmlns="http://hl7.org/fhir">
+              <type value="searchset"/>
+              <total value="1"/>
+              <entry>
+                <fullUrl value="http://example.org/fhir/Patient/123"/>
+                <resource>
+                  <Patient>
+                    <id value="123"/>
+                    <active value="true"/>
+                    <name><family value="Lovelace"/><given value="Ada"/></name>
+                    <gender value="female"/>
+                    <birthDate value="1815-12-10"/>
+                  </Patient>
+                </resource>
+              </entry>
+            </Bundle>"#;
+
+        let bundle = from_xml(xml).unwrap();
+        assert_eq!(bundle["total"], 1);
+        let patient = &bundle["entry"][0]["resource"];
+        assert_eq!(patient["resourceType"], "Patient");
+        assert_eq!(patient["active"], true);
+        assert_eq!(patient["name"], json!([{"family": "Lovelace", "given": ["Ada"]}]));
+
+        assert!(from_xml("<!DOCTYPE x [<!ENTITY a \"b\">]><Patient/>").is_err());
+        assert!(from_xml("<Patient><id value=\"1\"></Patient>").is_err());
+    }
+
+    #[test]
+    fn test_format_negotiation() {
+        assert_eq!(FhirFormat::from_accept(None), FhirFormat::Json);
+        assert_eq!(FhirFormat::from_accept(Some("application/fhir+xml")), FhirFormat::Xml);
+        assert_eq!(
+            FhirFormat::from_accept(Some("application/fhir+json;q=0.5, application/fhir+xml")),
+            FhirFormat::Xml
+        );
+        assert_eq!(FhirFormat::from_accept(Some("text/html, */*")), FhirFormat::Json);
+        assert_eq!(FhirFormat::from_media_type("application/fhir+xml; charset=utf-8"), Some(FhirFormat::Xml));
+        assert_eq!(FhirFormat::from_media_type("xml"), Some(FhirFormat::Xml));
+    }
+}
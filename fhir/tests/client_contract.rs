@@ -0,0 +1,262 @@
+//! Contract tests for `KodjinClient` against a stubbed FHIR server
+//!
+//! Each test mounts the responses a FHIR server gives in one situation and
+//! checks what the client makes of them: resources on success, typed errors
+//! carrying the server's OperationOutcome on 4xx, retries on 5xx for
+//! idempotent requests only, and transport-level failures for timeouts and
+//! bodies that are not FHIR.
+
+use emr_fhir::{FhirClientError, FhirGateway, KodjinClient, SearchParameters};
+use serde_json::{json, Value};
+use std::time::Duration;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const FHIR_JSON: &str = "application/fhir+json";
+
+/// A response with a FHIR JSON body
+fn fhir_response(status: u16, body: &Value) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body.to_string(), FHIR_JSON)
+}
+
+fn operation_outcome(code: &str, diagnostics: &str) -> Value {
+    json!({
+        "resourceType": "OperationOutcome",
+        "issue": [{
+            "severity": "error",
+            "code": code,
+            "diagnostics": diagnostics,
+            "expression": ["Patient.birthDate"]
+        }]
+    })
+}
+
+fn patient(id: &str) -> Value {
+    json!({
+        "resourceType": "Patient",
+        "id": id,
+        "name": [{ "family": "Doe", "given": ["Jane"] }]
+    })
+}
+
+/// A client without retries, failing fast
+fn client(server: &MockServer) -> KodjinClient {
+    KodjinClient::new(&server.uri()).unwrap().with_timeout(Duration::from_secs(2))
+}
+
+#[tokio::test]
+async fn test_read_and_search_succeed() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/123"))
+        .and(header("Accept", FHIR_JSON))
+        .respond_with(fhir_response(200, &patient("123")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Patient"))
+        .and(query_param("family", "Doe"))
+        .respond_with(fhir_response(
+            200,
+            &json!({
+                "resourceType": "Bundle",
+                "type": "searchset",
+                "total": 1,
+                "entry": [{ "resource": patient("123") }]
+            }),
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let resource = client.read("Patient", "123").await.unwrap();
+    assert_eq!(resource["id"], "123");
+
+    let params = SearchParameters::new("Patient").add_parameter("family", "Doe");
+    let bundle = client.search(&params).await.unwrap();
+    assert_eq!(bundle["total"], 1);
+    assert_eq!(bundle["entry"][0]["resource"]["name"][0]["family"], "Doe");
+}
+
+#[tokio::test]
+async fn test_create_and_update_send_fhir_json() {
+    let server = MockServer::start().await;
+    let new_patient = json!({ "resourceType": "Patient", "name": [{ "family": "Doe" }] });
+    Mock::given(method("POST"))
+        .and(path("/Patient"))
+        .and(header("Content-Type", FHIR_JSON))
+        .and(body_json(&new_patient))
+        .respond_with(fhir_response(201, &patient("new-1")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/Patient/new-1"))
+        .and(header("Content-Type", FHIR_JSON))
+        .respond_with(fhir_response(200, &patient("new-1")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/Patient/new-1"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let created = client.create("Patient", &new_patient).await.unwrap();
+    assert_eq!(created["id"], "new-1");
+    client.update("Patient", "new-1", &created).await.unwrap();
+    client.delete("Patient", "new-1").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_capability_statement_is_read_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/metadata"))
+        .respond_with(fhir_response(200, &json!({ "resourceType": "CapabilityStatement", "fhirVersion": "4.0.1" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let first = client.capability_statement().await.unwrap();
+    let second = client.clone().capability_statement().await.unwrap();
+    assert_eq!(first, second);
+    assert_eq!(second["fhirVersion"], "4.0.1");
+}
+
+#[tokio::test]
+async fn test_4xx_returns_operation_outcome() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/Patient"))
+        .respond_with(fhir_response(422, &operation_outcome("invariant", "Birth date is in the future")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/missing"))
+        .respond_with(fhir_response(404, &operation_outcome("not-found", "Resource Patient/missing is not known")))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/removed"))
+        .respond_with(ResponseTemplate::new(410))
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    match client.create("Patient", &patient("p1")).await {
+        Err(FhirClientError::Rejected { status, message, outcome }) => {
+            assert_eq!(status, 422);
+            assert_eq!(message, "Birth date is in the future");
+            let issue = &outcome.expect("outcome is parsed").issue[0];
+            assert_eq!(issue.code, "invariant");
+            assert_eq!(issue.expression.as_deref(), Some(&["Patient.birthDate".to_string()][..]));
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    for id in ["missing", "removed"] {
+        match client.read("Patient", id).await {
+            Err(FhirClientError::NotFound { resource }) => assert_eq!(resource, format!("Patient/{}", id)),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_5xx_is_retried_for_idempotent_requests() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/123"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/123"))
+        .respond_with(fhir_response(200, &patient("123")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server).with_retries(2, Duration::from_millis(10));
+    let resource = client.read("Patient", "123").await.unwrap();
+    assert_eq!(resource["id"], "123");
+}
+
+#[tokio::test]
+async fn test_5xx_gives_up_after_retries_and_never_retries_create() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/123"))
+        .respond_with(fhir_response(500, &operation_outcome("exception", "Database unavailable")))
+        .expect(3)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Patient"))
+        .respond_with(ResponseTemplate::new(502))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server).with_retries(2, Duration::from_millis(10));
+    match client.read("Patient", "123").await {
+        Err(error @ FhirClientError::Unavailable { status: Some(500), .. }) => {
+            assert!(error.is_retryable());
+            assert!(error.to_string().contains("Database unavailable"));
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // A create may have been applied before the server failed
+    let created = client.create("Patient", &patient("p1")).await;
+    assert!(matches!(created, Err(FhirClientError::Unavailable { status: Some(502), .. })));
+}
+
+#[tokio::test]
+async fn test_timeout_is_unavailable() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/slow"))
+        .respond_with(fhir_response(200, &patient("slow")).set_delay(Duration::from_secs(2)))
+        .mount(&server)
+        .await;
+
+    let client = KodjinClient::new(&server.uri()).unwrap().with_timeout(Duration::from_millis(100));
+    match client.read("Patient", "slow").await {
+        Err(error @ FhirClientError::Unavailable { status: None, .. }) => assert!(error.is_retryable()),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_malformed_json_is_invalid_response() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/123"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"resourceType": "Patient", "id": "#, FHIR_JSON))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Patient/123/$everything"))
+        .respond_with(fhir_response(200, &json!({ "resourceType": "Bundle", "type": "searchset", "entry": {} })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server).with_retries(2, Duration::from_millis(10));
+    // Not retried: the server answered, just not with FHIR
+    assert!(matches!(client.read("Patient", "123").await, Err(FhirClientError::InvalidResponse(_))));
+    // Valid JSON that is not a valid Bundle
+    assert!(matches!(client.patient_everything("123").await, Err(FhirClientError::InvalidResponse(_))));
+}
@@ -11,6 +11,8 @@ CREATE SCHEMA IF NOT EXISTS emr;
 CREATE SCHEMA IF NOT EXISTS fhir;
 CREATE SCHEMA IF NOT EXISTS audit;
 CREATE SCHEMA IF NOT EXISTS jobs;
+-- Partitions detached past retention (see core::partitions)
+CREATE SCHEMA IF NOT EXISTS emr_archive;
 
 -- Set search path
 SET search_path TO emr, fhir, audit, jobs, public;
@@ -26,9 +28,56 @@ CREATE TABLE IF NOT EXISTS audit.audit_log (
     changed_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     request_id VARCHAR(255),
     user_id UUID,
-    session_id VARCHAR(255)
+    session_id VARCHAR(255),
+    -- Patient whose data was accessed, for the accounting of disclosures
+    patient_id UUID,
+    -- Purpose of use given for the access (treatment, payment, operations, emergency)
+    purpose_of_use VARCHAR(50)
 );
 
+CREATE INDEX IF NOT EXISTS idx_audit_log_patient ON audit.audit_log(patient_id, changed_at) WHERE patient_id IS NOT NULL;
+
+-- Logins and authorization failures, checked for anomalies by the jobs worker
+CREATE TABLE IF NOT EXISTS audit.auth_events (
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- User id, account email or `ip:<address>` when the caller is unknown
+    subject VARCHAR(255) NOT NULL,
+    -- login_succeeded, login_failed or access_denied
+    kind VARCHAR(50) NOT NULL,
+    ip_address VARCHAR(45),
+    country VARCHAR(2),
+    city VARCHAR(255),
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    path VARCHAR(255),
+    -- Set once the anomaly detector has looked at a successful login
+    checked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_events_subject ON audit.auth_events(subject, occurred_at DESC);
+CREATE INDEX IF NOT EXISTS idx_auth_events_unchecked ON audit.auth_events(occurred_at)
+    WHERE checked_at IS NULL AND kind = 'login_succeeded';
+
+-- Anomalies flagged from auth_events and the access log by the jobs worker,
+-- and network zone violations recorded by the API
+CREATE TABLE IF NOT EXISTS audit.security_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind VARCHAR(50) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    description TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    detected_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Set once the worker has queued alerts to the security admins
+    alerted_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_security_events_occurred ON audit.security_events(occurred_at);
+CREATE INDEX IF NOT EXISTS idx_security_events_subject ON audit.security_events(kind, subject, occurred_at);
+CREATE INDEX IF NOT EXISTS idx_security_events_unalerted ON audit.security_events(detected_at)
+    WHERE alerted_at IS NULL;
+
 -- Create jobs table for background processing
 CREATE TABLE IF NOT EXISTS jobs.job_queue (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -53,6 +102,161 @@ CREATE INDEX IF NOT EXISTS idx_job_queue_scheduled_at ON jobs.job_queue(schedule
 CREATE INDEX IF NOT EXISTS idx_job_queue_job_type ON jobs.job_queue(job_type);
 CREATE INDEX IF NOT EXISTS idx_job_queue_priority ON jobs.job_queue(priority DESC);
 
+-- Create dead-letter table for jobs that failed for good
+CREATE TABLE IF NOT EXISTS jobs.dead_letters (
+    job_id UUID PRIMARY KEY,
+    job_type VARCHAR(255) NOT NULL,
+    queue VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT NOT NULL,
+    failed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    replay_count INTEGER NOT NULL DEFAULT 0,
+    replayed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_dead_letters_failed_at ON jobs.dead_letters(failed_at DESC);
+CREATE INDEX IF NOT EXISTS idx_dead_letters_job_type ON jobs.dead_letters(job_type);
+
+-- Create job run history for windowed job statistics
+CREATE TABLE IF NOT EXISTS jobs.job_runs (
+    id BIGSERIAL PRIMARY KEY,
+    job_id UUID NOT NULL,
+    job_type VARCHAR(255) NOT NULL,
+    queue VARCHAR(50) NOT NULL,
+    status VARCHAR(50) NOT NULL,
+    attempts INTEGER NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    finished_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_runs_finished_at ON jobs.job_runs(finished_at);
+CREATE INDEX IF NOT EXISTS idx_job_runs_job_type_finished_at ON jobs.job_runs(job_type, finished_at);
+
+-- Create idempotency keys for deduplicated job submissions
+CREATE TABLE IF NOT EXISTS jobs.idempotency_keys (
+    key VARCHAR(255) PRIMARY KEY,
+    job_id UUID NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_expires_at ON jobs.idempotency_keys(expires_at);
+
+-- Create memoized results of completed job steps
+CREATE TABLE IF NOT EXISTS jobs.job_step_results (
+    job_id UUID NOT NULL,
+    step VARCHAR(255) NOT NULL,
+    result JSONB NOT NULL,
+    completed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (job_id, step)
+);
+
+-- Create daily maintenance tasks, each queued by one worker a day
+CREATE TABLE IF NOT EXISTS jobs.maintenance_tasks (
+    task VARCHAR(100) PRIMARY KEY,
+    queued_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Create webhook endpoints registered by tenants (organizations)
+CREATE TABLE IF NOT EXISTS emr.webhook_endpoints (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_endpoints_tenant_id ON emr.webhook_endpoints(tenant_id);
+
+-- Create webhook delivery log; kept when an endpoint is removed
+CREATE TABLE IF NOT EXISTS jobs.webhook_deliveries (
+    delivery_id UUID PRIMARY KEY,
+    endpoint_id UUID NOT NULL,
+    event_id UUID NOT NULL,
+    event_type VARCHAR(255) NOT NULL,
+    event JSONB NOT NULL,
+    status VARCHAR(50) NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_status_code INTEGER,
+    last_error TEXT,
+    attempt_log JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_endpoint_created ON jobs.webhook_deliveries(endpoint_id, created_at DESC);
+
+-- Create provenance of imported and synchronized entities
+CREATE TABLE IF NOT EXISTS emr.provenance (
+    id UUID PRIMARY KEY,
+    target_type VARCHAR(100) NOT NULL,
+    target_id UUID NOT NULL,
+    activity VARCHAR(50) NOT NULL,
+    source_system VARCHAR(255) NOT NULL,
+    job_id UUID,
+    original_identifiers JSONB NOT NULL DEFAULT '[]',
+    transformation_version VARCHAR(100) NOT NULL,
+    source_reference TEXT,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_provenance_target ON emr.provenance(target_type, target_id);
+CREATE INDEX IF NOT EXISTS idx_provenance_job_id ON emr.provenance(job_id);
+
+-- Create validation profiles, one row per version of a tenant's rules for an entity type
+CREATE TABLE IF NOT EXISTS emr.validation_profiles (
+    id UUID PRIMARY KEY,
+    tenant_id UUID,
+    entity_type VARCHAR(100) NOT NULL,
+    version INTEGER NOT NULL,
+    strict BOOLEAN NOT NULL DEFAULT false,
+    rules JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- The default profile (no tenant) is versioned like any other
+CREATE UNIQUE INDEX IF NOT EXISTS idx_validation_profiles_version ON emr.validation_profiles(
+    COALESCE(tenant_id, '00000000-0000-0000-0000-000000000000'), entity_type, version
+);
+
+-- Create notification preferences; users are patients or practitioners
+CREATE TABLE IF NOT EXISTS emr.notification_preferences (
+    user_id UUID PRIMARY KEY,
+    preferences JSONB NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create notifications held for quiet hours or the daily digest
+CREATE TABLE IF NOT EXISTS jobs.held_notifications (
+    id UUID PRIMARY KEY,
+    recipient_id UUID NOT NULL,
+    job JSONB NOT NULL,
+    message TEXT NOT NULL,
+    digest BOOLEAN NOT NULL,
+    release_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_held_notifications_release_at ON jobs.held_notifications(release_at);
+
+-- Create in-app notification inboxes, filled by the jobs worker
+CREATE TABLE IF NOT EXISTS emr.notifications (
+    id UUID PRIMARY KEY,
+    recipient_id UUID NOT NULL,
+    notification_type VARCHAR(50) NOT NULL,
+    priority VARCHAR(50) NOT NULL,
+    subject TEXT,
+    message TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    read_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_recipient_created ON emr.notifications(recipient_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_notifications_unread ON emr.notifications(recipient_id) WHERE read_at IS NULL;
+
 -- Create patients table
 CREATE TABLE IF NOT EXISTS emr.patients (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -72,7 +276,12 @@ CREATE TABLE IF NOT EXISTS emr.patients (
     country VARCHAR(100),
     identifiers JSONB,
     contact_points JSONB,
+    -- FHIR Attachment elements of the patient's photos and their thumbnails
+    photos JSONB,
     fhir_data JSONB,
+    -- Tenant: the emr.organizations row managing the patient; row-level security
+    -- limits patient data to it. New patients belong to the session's tenant.
+    managing_organization_id UUID DEFAULT NULLIF(current_setting('application.tenant_id', true), '')::uuid,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     created_by UUID,
@@ -88,6 +297,85 @@ CREATE INDEX IF NOT EXISTS idx_patients_active ON emr.patients(active);
 CREATE INDEX IF NOT EXISTS idx_patients_identifiers ON emr.patients USING GIN(identifiers);
 CREATE INDEX IF NOT EXISTS idx_patients_fhir_data ON emr.patients USING GIN(fhir_data);
 
+-- Create patient portal accounts for sign-in with local credentials
+CREATE TABLE IF NOT EXISTS emr.portal_accounts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    username VARCHAR(255) NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT true,
+    last_login_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_portal_accounts_patient_id ON emr.portal_accounts(patient_id);
+
+-- Create document references (clinical documents and files attached to a patient)
+CREATE TABLE IF NOT EXISTS emr.document_references (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    encounter_id UUID,
+    status VARCHAR(50) NOT NULL DEFAULT 'current',
+    type_code VARCHAR(100),
+    type_display VARCHAR(255),
+    title VARCHAR(255),
+    content_type VARCHAR(100) NOT NULL,
+    url TEXT NOT NULL,
+    size_bytes BIGINT,
+    -- Quarantined ('pending') until a malware scan comes back 'clean'; 'infected' content is discarded
+    scan_status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    scanned_at TIMESTAMP WITH TIME ZONE,
+    scanner VARCHAR(50),
+    scan_signature VARCHAR(255),
+    -- HL7 v3 confidentiality label; only users cleared for it see the document
+    confidentiality VARCHAR(20) NOT NULL DEFAULT 'normal',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_references_patient ON emr.document_references(patient_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_document_references_pending_scan ON emr.document_references(created_at)
+    WHERE scan_status = 'pending';
+
+-- Create extracted document text, written by the jobs worker's DocumentOcr job
+CREATE TABLE IF NOT EXISTS emr.document_texts (
+    document_id UUID PRIMARY KEY REFERENCES emr.document_references(id) ON DELETE CASCADE,
+    extractor VARCHAR(50) NOT NULL,
+    content TEXT NOT NULL,
+    search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('english', content)) STORED,
+    extracted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_texts_search ON emr.document_texts USING GIN(search_vector);
+
+-- Create secure messages; a thread is identified by its first message
+CREATE TABLE IF NOT EXISTS emr.communications (
+    id UUID PRIMARY KEY,
+    thread_id UUID NOT NULL,
+    in_response_to UUID REFERENCES emr.communications(id),
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    sender_type VARCHAR(20) NOT NULL,
+    sender_id UUID NOT NULL,
+    recipients JSONB NOT NULL,
+    topic VARCHAR(255),
+    payload TEXT NOT NULL,
+    attachments UUID[] NOT NULL DEFAULT '{}',
+    status VARCHAR(50) NOT NULL DEFAULT 'completed',
+    sent_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_communications_thread ON emr.communications(thread_id, sent_at);
+CREATE INDEX IF NOT EXISTS idx_communications_sender ON emr.communications(sender_type, sender_id);
+CREATE INDEX IF NOT EXISTS idx_communications_recipients ON emr.communications USING GIN(recipients);
+
+-- Create per-reader read marks for message threads
+CREATE TABLE IF NOT EXISTS emr.communication_reads (
+    thread_id UUID NOT NULL,
+    reader_type VARCHAR(20) NOT NULL,
+    reader_id UUID NOT NULL,
+    read_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (thread_id, reader_type, reader_id)
+);
+
 -- Create organizations table
 CREATE TABLE IF NOT EXISTS emr.organizations (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -95,6 +383,7 @@ CREATE TABLE IF NOT EXISTS emr.organizations (
     active BOOLEAN NOT NULL DEFAULT true,
     name VARCHAR(255) NOT NULL,
     type VARCHAR(100),
+    part_of UUID REFERENCES emr.organizations(id),
     phone VARCHAR(50),
     email VARCHAR(255),
     website VARCHAR(255),
@@ -106,6 +395,8 @@ CREATE TABLE IF NOT EXISTS emr.organizations (
     country VARCHAR(100),
     identifiers JSONB,
     contact_points JSONB,
+    aliases TEXT[] NOT NULL DEFAULT '{}',
+    version BIGINT NOT NULL DEFAULT 1,
     fhir_data JSONB,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
@@ -117,7 +408,10 @@ CREATE TABLE IF NOT EXISTS emr.organizations (
 CREATE INDEX IF NOT EXISTS idx_organizations_fhir_id ON emr.organizations(fhir_id);
 CREATE INDEX IF NOT EXISTS idx_organizations_name ON emr.organizations(name);
 CREATE INDEX IF NOT EXISTS idx_organizations_active ON emr.organizations(active);
+CREATE INDEX IF NOT EXISTS idx_organizations_part_of ON emr.organizations(part_of);
 CREATE INDEX IF NOT EXISTS idx_organizations_identifiers ON emr.organizations USING GIN(identifiers);
+-- Fuzzy name matching for global search
+CREATE INDEX IF NOT EXISTS idx_organizations_name_trgm ON emr.organizations USING GIN(name gin_trgm_ops);
 
 -- Create practitioners table
 CREATE TABLE IF NOT EXISTS emr.practitioners (
@@ -132,6 +426,12 @@ CREATE TABLE IF NOT EXISTS emr.practitioners (
     email VARCHAR(255),
     identifiers JSONB,
     contact_points JSONB,
+    -- Every name, with prefixes; family_name and given_names hold the first
+    names JSONB,
+    -- Every qualification; qualification holds the first one's code
+    qualifications JSONB,
+    organization_ids UUID[] NOT NULL DEFAULT '{}',
+    version BIGINT NOT NULL DEFAULT 1,
     fhir_data JSONB,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
@@ -161,6 +461,9 @@ CREATE TABLE IF NOT EXISTS emr.encounters (
     reason_code VARCHAR(255),
     reason_description TEXT,
     fhir_data JSONB,
+    -- Participants, locations, diagnoses and the rest of the domain encounter
+    details JSONB,
+    version BIGINT NOT NULL DEFAULT 1,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     created_by UUID,
@@ -175,17 +478,24 @@ CREATE INDEX IF NOT EXISTS idx_encounters_practitioner_id ON emr.encounters(prac
 CREATE INDEX IF NOT EXISTS idx_encounters_start_date ON emr.encounters(start_date);
 CREATE INDEX IF NOT EXISTS idx_encounters_status ON emr.encounters(status);
 
--- Create observations table
+-- Create observations table, partitioned by month of effective_date. The
+-- PartitionMaintenance job creates the monthly partitions ahead of time and
+-- archives those past retention; rows of months without a partition go to
+-- observations_default until the job gives them one. Keys of a partitioned
+-- table include the partition key, so id and fhir_id are unique per month.
 CREATE TABLE IF NOT EXISTS emr.observations (
-    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
-    fhir_id VARCHAR(255) UNIQUE,
+    id UUID NOT NULL DEFAULT uuid_generate_v4(),
+    fhir_id VARCHAR(255),
     status VARCHAR(50) NOT NULL,
     category VARCHAR(100),
     code VARCHAR(100),
     display VARCHAR(255),
     patient_id UUID REFERENCES emr.patients(id),
     encounter_id UUID REFERENCES emr.encounters(id),
-    effective_date TIMESTAMP WITH TIME ZONE,
+    -- Reporting device of readings stored in bulk
+    device_id VARCHAR(255),
+    -- Partition key; observations without an effective time are filed when recorded
+    effective_date TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     value_quantity_value DECIMAL,
     value_quantity_unit VARCHAR(50),
     value_string TEXT,
@@ -193,21 +503,562 @@ CREATE TABLE IF NOT EXISTS emr.observations (
     interpretation VARCHAR(100),
     reference_range_low DECIMAL,
     reference_range_high DECIMAL,
+    -- HL7 v3 confidentiality label; only users cleared for it see the row
+    confidentiality VARCHAR(20) NOT NULL DEFAULT 'normal',
     fhir_data JSONB,
+    -- Members, notes, full values and the rest of an API-recorded observation;
+    -- device readings have none
+    details JSONB,
+    version BIGINT NOT NULL DEFAULT 1,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     created_by UUID,
-    updated_by UUID
-);
+    updated_by UUID,
+    PRIMARY KEY (id, effective_date)
+) PARTITION BY RANGE (effective_date);
+
+CREATE TABLE IF NOT EXISTS emr.observations_default PARTITION OF emr.observations DEFAULT;
 
--- Create indexes for observations table
+-- Create indexes for observations table; each partition gets its own
 CREATE INDEX IF NOT EXISTS idx_observations_fhir_id ON emr.observations(fhir_id);
-CREATE INDEX IF NOT EXISTS idx_observations_patient_id ON emr.observations(patient_id);
+CREATE INDEX IF NOT EXISTS idx_observations_patient_id ON emr.observations(patient_id, effective_date DESC);
 CREATE INDEX IF NOT EXISTS idx_observations_encounter_id ON emr.observations(encounter_id);
 CREATE INDEX IF NOT EXISTS idx_observations_effective_date ON emr.observations(effective_date);
 CREATE INDEX IF NOT EXISTS idx_observations_code ON emr.observations(code);
 CREATE INDEX IF NOT EXISTS idx_observations_category ON emr.observations(category);
 
+-- Create clinical notes; sections and addenda are stored as JSONB
+CREATE TABLE IF NOT EXISTS emr.clinical_notes (
+    id UUID PRIMARY KEY,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    encounter_id UUID REFERENCES emr.encounters(id),
+    author_id UUID NOT NULL,
+    note_type VARCHAR(50) NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    status VARCHAR(50) NOT NULL,
+    sections JSONB NOT NULL DEFAULT '[]',
+    addenda JSONB NOT NULL DEFAULT '[]',
+    signed_at TIMESTAMP WITH TIME ZONE,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_clinical_notes_patient ON emr.clinical_notes(patient_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_clinical_notes_encounter ON emr.clinical_notes(encounter_id);
+CREATE INDEX IF NOT EXISTS idx_clinical_notes_author_drafts ON emr.clinical_notes(author_id) WHERE status = 'preliminary';
+
+-- Create saved versions of clinical note drafts
+CREATE TABLE IF NOT EXISTS emr.clinical_note_versions (
+    note_id UUID NOT NULL REFERENCES emr.clinical_notes(id),
+    version BIGINT NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    sections JSONB NOT NULL,
+    saved_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (note_id, version)
+);
+
+-- Create electronic signatures; rows are never updated or deleted
+CREATE TABLE IF NOT EXISTS emr.signatures (
+    id UUID PRIMARY KEY,
+    target_type VARCHAR(50) NOT NULL,
+    target_id UUID NOT NULL,
+    kind VARCHAR(20) NOT NULL,
+    signer_id UUID NOT NULL,
+    signed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    reason TEXT,
+    content_hash CHAR(64) NOT NULL,
+    seal CHAR(64) NOT NULL,
+    CHECK (kind <> 'amendment' OR reason IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS idx_signatures_target ON emr.signatures(target_type, target_id, signed_at);
+CREATE INDEX IF NOT EXISTS idx_signatures_signer ON emr.signatures(signer_id, signed_at DESC);
+
+-- Create orders (FHIR ServiceRequest)
+CREATE TABLE IF NOT EXISTS emr.service_requests (
+    id UUID PRIMARY KEY,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    encounter_id UUID REFERENCES emr.encounters(id),
+    category VARCHAR(20) NOT NULL,
+    code VARCHAR(100) NOT NULL,
+    code_system VARCHAR(255) NOT NULL,
+    display VARCHAR(255),
+    priority VARCHAR(10) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    requester_id UUID NOT NULL,
+    performer_id UUID,
+    reason TEXT,
+    authored_on TIMESTAMP WITH TIME ZONE NOT NULL,
+    fulfilled_by TEXT[] NOT NULL DEFAULT '{}',
+    completed_at TIMESTAMP WITH TIME ZONE,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_requests_patient ON emr.service_requests(patient_id, authored_on DESC);
+CREATE INDEX IF NOT EXISTS idx_service_requests_pending_requester ON emr.service_requests(requester_id)
+    WHERE status IN ('draft', 'active', 'on-hold');
+CREATE INDEX IF NOT EXISTS idx_service_requests_pending_performer ON emr.service_requests(performer_id)
+    WHERE status IN ('draft', 'active', 'on-hold');
+
+-- Acknowledgment tasks for abnormal results (FHIR Task), one per result
+CREATE TABLE IF NOT EXISTS emr.acknowledgment_tasks (
+    id UUID PRIMARY KEY,
+    observation_id UUID NOT NULL UNIQUE,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    order_id UUID REFERENCES emr.service_requests(id),
+    owner_id UUID NOT NULL,
+    test_name VARCHAR(255) NOT NULL,
+    critical BOOLEAN NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    acknowledged_at TIMESTAMP WITH TIME ZONE,
+    acknowledged_by UUID,
+    note TEXT,
+    escalation_count INTEGER NOT NULL DEFAULT 0,
+    last_escalated_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_acknowledgment_tasks_owner ON emr.acknowledgment_tasks(owner_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_acknowledgment_tasks_pending_critical ON emr.acknowledgment_tasks(created_at)
+    WHERE critical AND status = 'requested';
+
+-- Care teams (FHIR CareTeam); participation is the treating relationship
+CREATE TABLE IF NOT EXISTS emr.care_teams (
+    id UUID PRIMARY KEY,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    name VARCHAR(255) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS emr.care_team_participants (
+    care_team_id UUID NOT NULL REFERENCES emr.care_teams(id) ON DELETE CASCADE,
+    practitioner_id UUID NOT NULL,
+    role VARCHAR(30) NOT NULL,
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    period_end TIMESTAMP WITH TIME ZONE,
+    CHECK (period_end IS NULL OR period_end > period_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_care_teams_patient ON emr.care_teams(patient_id);
+CREATE INDEX IF NOT EXISTS idx_care_team_participants_team ON emr.care_team_participants(care_team_id);
+CREATE INDEX IF NOT EXISTS idx_care_team_participants_practitioner ON emr.care_team_participants(practitioner_id);
+
+-- Break-the-glass grants, reviewed after the fact
+CREATE TABLE IF NOT EXISTS emr.emergency_access (
+    id UUID PRIMARY KEY,
+    practitioner_id UUID NOT NULL,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    reason TEXT NOT NULL,
+    granted_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_emergency_access_active ON emr.emergency_access(practitioner_id, patient_id, expires_at);
+
+-- Referrals between organizations, exchanged as FHIR ServiceRequest + Task
+CREATE TABLE IF NOT EXISTS emr.referrals (
+    id UUID PRIMARY KEY,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    requester_id UUID NOT NULL,
+    source_organization_id UUID NOT NULL REFERENCES emr.organizations(id),
+    target_organization_id UUID NOT NULL REFERENCES emr.organizations(id),
+    reason TEXT NOT NULL,
+    priority VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    document_ids UUID[] NOT NULL DEFAULT '{}',
+    sent_at TIMESTAMP WITH TIME ZONE,
+    responded_at TIMESTAMP WITH TIME ZONE,
+    decline_reason TEXT,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CHECK (source_organization_id <> target_organization_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_referrals_patient ON emr.referrals(patient_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_referrals_source ON emr.referrals(source_organization_id, status);
+CREATE INDEX IF NOT EXISTS idx_referrals_target ON emr.referrals(target_organization_id, status);
+
+-- Questionnaires (FHIR Questionnaire); items hold the nested form definition
+CREATE TABLE IF NOT EXISTS emr.questionnaires (
+    id UUID PRIMARY KEY,
+    url TEXT UNIQUE,
+    title TEXT NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    items JSONB NOT NULL,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_questionnaires_status ON emr.questionnaires(status, title);
+
+-- Questionnaire responses with the answers to enabled questions and their score
+CREATE TABLE IF NOT EXISTS emr.questionnaire_responses (
+    id UUID PRIMARY KEY,
+    questionnaire_id UUID NOT NULL REFERENCES emr.questionnaires(id),
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    author_id UUID,
+    status VARCHAR(20) NOT NULL,
+    answers JSONB NOT NULL,
+    authored TIMESTAMP WITH TIME ZONE NOT NULL,
+    score INTEGER,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_questionnaire_responses_patient ON emr.questionnaire_responses(patient_id, authored DESC);
+
+-- Medication orders (FHIR MedicationRequest)
+CREATE TABLE IF NOT EXISTS emr.medication_requests (
+    id UUID PRIMARY KEY,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    encounter_id UUID REFERENCES emr.encounters(id),
+    medication_code VARCHAR(100) NOT NULL,
+    medication_display VARCHAR(255),
+    product_identifiers TEXT[] NOT NULL DEFAULT '{}',
+    dose_quantity DOUBLE PRECISION NOT NULL,
+    dose_unit VARCHAR(50) NOT NULL,
+    route VARCHAR(100),
+    frequency_hours INTEGER,
+    as_needed BOOLEAN NOT NULL DEFAULT false,
+    start_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    end_at TIMESTAMP WITH TIME ZONE,
+    status VARCHAR(20) NOT NULL,
+    requester_id UUID NOT NULL,
+    reason TEXT,
+    authored_on TIMESTAMP WITH TIME ZONE NOT NULL,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_medication_requests_patient ON emr.medication_requests(patient_id, authored_on DESC);
+CREATE INDEX IF NOT EXISTS idx_medication_requests_encounter ON emr.medication_requests(encounter_id);
+
+-- Medication administration record (FHIR MedicationAdministration); one row per documented dose
+CREATE TABLE IF NOT EXISTS emr.medication_administrations (
+    id UUID PRIMARY KEY,
+    request_id UUID NOT NULL REFERENCES emr.medication_requests(id),
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    encounter_id UUID REFERENCES emr.encounters(id),
+    outcome VARCHAR(10) NOT NULL,
+    scheduled_for TIMESTAMP WITH TIME ZONE,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    performer_id UUID NOT NULL,
+    dose_quantity DOUBLE PRECISION,
+    dose_unit VARCHAR(50),
+    reason TEXT,
+    note TEXT,
+    barcode_patient VARCHAR(255),
+    barcode_medication VARCHAR(255),
+    barcode_verified_at TIMESTAMP WITH TIME ZONE,
+    barcode_override TEXT,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_medication_administrations_request ON emr.medication_administrations(request_id, occurred_at);
+CREATE INDEX IF NOT EXISTS idx_medication_administrations_encounter ON emr.medication_administrations(encounter_id, occurred_at);
+-- Each scheduled dose is documented once
+CREATE UNIQUE INDEX IF NOT EXISTS idx_medication_administrations_scheduled
+    ON emr.medication_administrations(request_id, scheduled_for) WHERE scheduled_for IS NOT NULL;
+
+-- Finalized billing codes of encounters; replaced as a whole on each coder review
+CREATE TABLE IF NOT EXISTS emr.encounter_codes (
+    encounter_id UUID NOT NULL REFERENCES emr.encounters(id),
+    kind VARCHAR(10) NOT NULL,
+    sequence INTEGER NOT NULL,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    code VARCHAR(10) NOT NULL,
+    display VARCHAR(255),
+    units INTEGER NOT NULL DEFAULT 1,
+    modifiers TEXT[] NOT NULL DEFAULT '{}',
+    diagnosis_pointers INTEGER[] NOT NULL DEFAULT '{}',
+    origin VARCHAR(10) NOT NULL,
+    suggested_code VARCHAR(10),
+    override_reason TEXT,
+    coded_by UUID NOT NULL,
+    coded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (encounter_id, kind, sequence),
+    CHECK (origin <> 'overridden' OR override_reason IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS idx_encounter_codes_patient ON emr.encounter_codes(patient_id, coded_at DESC);
+
+-- Charges captured at the point of care; replaced as a whole on each capture
+CREATE TABLE IF NOT EXISTS emr.encounter_charges (
+    encounter_id UUID PRIMARY KEY REFERENCES emr.encounters(id),
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    diagnoses JSONB NOT NULL,
+    lines JSONB NOT NULL,
+    captured_by UUID NOT NULL,
+    captured_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Health plans of patients (FHIR Coverage); claims bill the primary coverage in force
+CREATE TABLE IF NOT EXISTS emr.coverages (
+    id UUID PRIMARY KEY,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    status VARCHAR(20) NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 1,
+    plan_type VARCHAR(20) NOT NULL,
+    payer_name VARCHAR(255) NOT NULL,
+    payer_id VARCHAR(80) NOT NULL,
+    member_id VARCHAR(80) NOT NULL,
+    group_number VARCHAR(50),
+    plan_name VARCHAR(255),
+    relationship VARCHAR(10) NOT NULL,
+    subscriber JSONB,
+    start_date DATE,
+    end_date DATE,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    CHECK (relationship = 'self' OR subscriber IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS idx_coverages_patient ON emr.coverages(patient_id, priority);
+
+-- Interchange control numbers of 837 files (ISA13), nine digits
+CREATE SEQUENCE IF NOT EXISTS emr.claim_control_numbers MINVALUE 1 MAXVALUE 999999999 CYCLE;
+
+-- Claims export runs: the 837P file and the encounters that could not be billed
+CREATE TABLE IF NOT EXISTS emr.claim_exports (
+    id UUID PRIMARY KEY,
+    control_number INTEGER NOT NULL,
+    service_from DATE NOT NULL,
+    service_to DATE NOT NULL,
+    claim_count INTEGER NOT NULL,
+    rejected_count INTEGER NOT NULL,
+    file_content TEXT,
+    rejections JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Claims sent to payers; an encounter is billed once
+CREATE TABLE IF NOT EXISTS emr.claims (
+    claim_id VARCHAR(38) PRIMARY KEY,
+    encounter_id UUID NOT NULL UNIQUE REFERENCES emr.encounters(id),
+    export_id UUID NOT NULL REFERENCES emr.claim_exports(id),
+    payer_id VARCHAR(80) NOT NULL,
+    total_charge_cents BIGINT NOT NULL,
+    claim JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_claims_export ON emr.claims(export_id);
+
+-- Remittance advice (835) received from payers, one row per payment
+CREATE TABLE IF NOT EXISTS emr.remittances (
+    id UUID PRIMARY KEY,
+    payer_id VARCHAR(80) NOT NULL,
+    payer_name VARCHAR(255) NOT NULL,
+    trace_number VARCHAR(50) NOT NULL,
+    payment_cents BIGINT NOT NULL,
+    payment_method VARCHAR(3) NOT NULL,
+    paid_on DATE,
+    source_location TEXT NOT NULL,
+    posted_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    UNIQUE (payer_id, trace_number)
+);
+
+-- Payments and adjustments posted against claims; claim_id is the payer's
+-- CLP01 and may not match an exported claim
+CREATE TABLE IF NOT EXISTS emr.claim_payments (
+    id UUID PRIMARY KEY,
+    remittance_id UUID NOT NULL REFERENCES emr.remittances(id),
+    claim_id VARCHAR(38) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    status_code VARCHAR(2) NOT NULL,
+    charge_cents BIGINT NOT NULL,
+    paid_cents BIGINT NOT NULL,
+    patient_responsibility_cents BIGINT NOT NULL,
+    written_off_cents BIGINT NOT NULL,
+    denied BOOLEAN NOT NULL,
+    payer_claim_number VARCHAR(50),
+    adjustments JSONB NOT NULL DEFAULT '[]',
+    lines JSONB NOT NULL DEFAULT '[]',
+    posted_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_claim_payments_claim ON emr.claim_payments(claim_id);
+
+-- Follow-up tasks for billing staff (FHIR Task): denials, reversals, unmatched payments
+CREATE TABLE IF NOT EXISTS emr.billing_tasks (
+    id UUID PRIMARY KEY,
+    remittance_id UUID NOT NULL REFERENCES emr.remittances(id),
+    claim_id VARCHAR(38) NOT NULL,
+    reason VARCHAR(20) NOT NULL,
+    detail TEXT NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    completed_by UUID
+);
+
+CREATE INDEX IF NOT EXISTS idx_billing_tasks_open ON emr.billing_tasks(created_at) WHERE status = 'requested';
+
+-- Saved patient panels: named criteria evaluated on demand or nightly
+CREATE TABLE IF NOT EXISTS emr.patient_panels (
+    id UUID PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    organization_id UUID NOT NULL REFERENCES emr.organizations(id),
+    owner_id UUID NOT NULL REFERENCES emr.practitioners(id),
+    shared BOOLEAN NOT NULL DEFAULT false,
+    criteria JSONB NOT NULL,
+    refresh VARCHAR(20) NOT NULL,
+    evaluated_at TIMESTAMP WITH TIME ZONE,
+    member_count BIGINT,
+    refresh_queued_at TIMESTAMP WITH TIME ZONE,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_patient_panels_organization ON emr.patient_panels(organization_id, name);
+CREATE INDEX IF NOT EXISTS idx_patient_panels_nightly ON emr.patient_panels(refresh_queued_at) WHERE refresh = 'nightly';
+
+-- Patients found by a panel's last evaluation; replaced as a whole
+CREATE TABLE IF NOT EXISTS emr.patient_panel_members (
+    panel_id UUID NOT NULL REFERENCES emr.patient_panels(id) ON DELETE CASCADE,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    PRIMARY KEY (panel_id, patient_id)
+);
+
+-- What patient detail opens with (core::services::patient_summary): latest vitals, active
+-- problems and medications, upcoming appointments; updated as the records behind it change
+CREATE TABLE IF NOT EXISTS emr.patient_summaries (
+    patient_id UUID PRIMARY KEY REFERENCES emr.patients(id),
+    summary JSONB NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Clinical quality measures; populations are panel criteria (JSONB arrays)
+CREATE TABLE IF NOT EXISTS emr.quality_measures (
+    id UUID PRIMARY KEY,
+    identifier VARCHAR(50) UNIQUE NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT,
+    improvement VARCHAR(20) NOT NULL,
+    initial_population JSONB NOT NULL,
+    denominator JSONB NOT NULL,
+    denominator_exclusions JSONB NOT NULL,
+    numerator JSONB NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT true,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- A measure computed for a reporting period; recomputing replaces it
+CREATE TABLE IF NOT EXISTS emr.measure_reports (
+    id UUID PRIMARY KEY,
+    measure_id UUID NOT NULL REFERENCES emr.quality_measures(id) ON DELETE CASCADE,
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    period_end TIMESTAMP WITH TIME ZONE NOT NULL,
+    initial_population BIGINT NOT NULL,
+    denominator BIGINT NOT NULL,
+    denominator_exclusions BIGINT NOT NULL,
+    numerator BIGINT NOT NULL,
+    computed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    UNIQUE (measure_id, period_start, period_end)
+);
+
+-- Populations each patient fell in, for drill-down
+CREATE TABLE IF NOT EXISTS emr.measure_report_patients (
+    report_id UUID NOT NULL REFERENCES emr.measure_reports(id) ON DELETE CASCADE,
+    patient_id UUID NOT NULL REFERENCES emr.patients(id),
+    population VARCHAR(30) NOT NULL,
+    PRIMARY KEY (report_id, population, patient_id)
+);
+
+-- Reports run on a cadence; definitions and cadences are JSONB
+CREATE TABLE IF NOT EXISTS emr.report_schedules (
+    id UUID PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    owner_id UUID NOT NULL REFERENCES emr.practitioners(id),
+    definition JSONB NOT NULL,
+    period JSONB,
+    format VARCHAR(10) NOT NULL,
+    cadence JSONB NOT NULL,
+    recipients UUID[] NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT true,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_report_schedules_owner ON emr.report_schedules(owner_id, name);
+CREATE INDEX IF NOT EXISTS idx_report_schedules_due ON emr.report_schedules(next_run_at) WHERE active;
+
+-- Generated scheduled reports; files are stored under the run id
+CREATE TABLE IF NOT EXISTS emr.report_runs (
+    id UUID PRIMARY KEY,
+    schedule_id UUID NOT NULL REFERENCES emr.report_schedules(id) ON DELETE CASCADE,
+    scheduled_for TIMESTAMP WITH TIME ZONE NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    format VARCHAR(10) NOT NULL,
+    row_count BIGINT,
+    byte_count BIGINT,
+    error TEXT,
+    generated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_report_runs_schedule ON emr.report_runs(schedule_id, scheduled_for DESC);
+
+-- Runtime feature flags; tenants maps organization ids to forced on/off
+CREATE TABLE IF NOT EXISTS emr.feature_flags (
+    key VARCHAR(100) PRIMARY KEY,
+    description TEXT,
+    enabled BOOLEAN NOT NULL,
+    rollout_percentage SMALLINT NOT NULL CHECK (rollout_percentage BETWEEN 0 AND 100),
+    tenants JSONB NOT NULL DEFAULT '{}',
+    version BIGINT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_by VARCHAR(255)
+);
+
+-- Data-encryption keys for field-level encryption, stored wrapped
+-- (base64 nonce || AES-256-GCM ciphertext) by a master key the database never
+-- sees; retired keys keep their row for the audit trail but lose the key
+CREATE TABLE IF NOT EXISTS emr.data_keys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    master_key_id VARCHAR(100) NOT NULL,
+    wrapped_key TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    rotated_at TIMESTAMP WITH TIME ZONE,
+    retired_at TIMESTAMP WITH TIME ZONE,
+    CHECK ((wrapped_key IS NULL) = (retired_at IS NOT NULL))
+);
+
+CREATE INDEX IF NOT EXISTS idx_data_keys_master ON emr.data_keys(master_key_id) WHERE retired_at IS NULL;
+
+-- Field values sealed with a data key, base64 nonce || ciphertext
+CREATE TABLE IF NOT EXISTS emr.encrypted_fields (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    resource_type VARCHAR(50) NOT NULL,
+    resource_id UUID NOT NULL,
+    field VARCHAR(100) NOT NULL,
+    data_key_id UUID NOT NULL REFERENCES emr.data_keys(id),
+    ciphertext TEXT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (resource_type, resource_id, field)
+);
+
+CREATE INDEX IF NOT EXISTS idx_encrypted_fields_key ON emr.encrypted_fields(data_key_id);
+
 -- Create users table for authentication
 CREATE TABLE IF NOT EXISTS emr.users (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -246,20 +1097,34 @@ CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON emr.sessions(user_id);
 CREATE INDEX IF NOT EXISTS idx_sessions_session_token ON emr.sessions(session_token);
 CREATE INDEX IF NOT EXISTS idx_sessions_expires_at ON emr.sessions(expires_at);
 
+-- The API user a change was made for (application.user_id), else the database user
+CREATE OR REPLACE FUNCTION audit.current_actor() RETURNS VARCHAR AS $$
+    SELECT COALESCE(NULLIF(current_setting('application.user_id', true), ''), current_user)
+$$ LANGUAGE sql STABLE;
+
+-- X-Request-ID of the API request a change was made for (application.request_id)
+CREATE OR REPLACE FUNCTION audit.current_request_id() RETURNS VARCHAR AS $$
+    SELECT NULLIF(current_setting('application.request_id', true), '')
+$$ LANGUAGE sql STABLE;
+
 -- Create audit triggers for all tables
+-- Triggers of partitioned tables pass the table's name, so changes are not logged under
+-- the partition's
 CREATE OR REPLACE FUNCTION audit.audit_trigger_function() RETURNS TRIGGER AS $$
+DECLARE
+    audited_table TEXT := COALESCE(TG_ARGV[0], TG_TABLE_NAME);
 BEGIN
     IF TG_OP = 'INSERT' THEN
         INSERT INTO audit.audit_log (table_name, operation, new_values, changed_by, request_id)
-        VALUES (TG_TABLE_NAME, TG_OP, to_jsonb(NEW), current_user, current_setting('application.request_id', true));
+        VALUES (audited_table, TG_OP, to_jsonb(NEW), audit.current_actor(), audit.current_request_id());
         RETURN NEW;
     ELSIF TG_OP = 'UPDATE' THEN
         INSERT INTO audit.audit_log (table_name, operation, old_values, new_values, changed_by, request_id)
-        VALUES (TG_TABLE_NAME, TG_OP, to_jsonb(OLD), to_jsonb(NEW), current_user, current_setting('application.request_id', true));
+        VALUES (audited_table, TG_OP, to_jsonb(OLD), to_jsonb(NEW), audit.current_actor(), audit.current_request_id());
         RETURN NEW;
     ELSIF TG_OP = 'DELETE' THEN
         INSERT INTO audit.audit_log (table_name, operation, old_values, changed_by, request_id)
-        VALUES (TG_TABLE_NAME, TG_OP, to_jsonb(OLD), current_user, current_setting('application.request_id', true));
+        VALUES (audited_table, TG_OP, to_jsonb(OLD), audit.current_actor(), audit.current_request_id());
         RETURN OLD;
     END IF;
     RETURN NULL;
@@ -271,7 +1136,30 @@ CREATE TRIGGER audit_patients AFTER INSERT OR UPDATE OR DELETE ON emr.patients F
 CREATE TRIGGER audit_organizations AFTER INSERT OR UPDATE OR DELETE ON emr.organizations FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
 CREATE TRIGGER audit_practitioners AFTER INSERT OR UPDATE OR DELETE ON emr.practitioners FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
 CREATE TRIGGER audit_encounters AFTER INSERT OR UPDATE OR DELETE ON emr.encounters FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
-CREATE TRIGGER audit_observations AFTER INSERT OR UPDATE OR DELETE ON emr.observations FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_observations AFTER INSERT OR UPDATE OR DELETE ON emr.observations FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function('observations');
+CREATE TRIGGER audit_clinical_notes AFTER INSERT OR UPDATE OR DELETE ON emr.clinical_notes FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_service_requests AFTER INSERT OR UPDATE OR DELETE ON emr.service_requests FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_acknowledgment_tasks AFTER INSERT OR UPDATE OR DELETE ON emr.acknowledgment_tasks FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_care_teams AFTER INSERT OR UPDATE OR DELETE ON emr.care_teams FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_care_team_participants AFTER INSERT OR UPDATE OR DELETE ON emr.care_team_participants FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_emergency_access AFTER INSERT OR UPDATE OR DELETE ON emr.emergency_access FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_referrals AFTER INSERT OR UPDATE OR DELETE ON emr.referrals FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_questionnaires AFTER INSERT OR UPDATE OR DELETE ON emr.questionnaires FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_questionnaire_responses AFTER INSERT OR UPDATE OR DELETE ON emr.questionnaire_responses FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_medication_requests AFTER INSERT OR UPDATE OR DELETE ON emr.medication_requests FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_medication_administrations AFTER INSERT OR UPDATE OR DELETE ON emr.medication_administrations FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_encounter_codes AFTER INSERT OR UPDATE OR DELETE ON emr.encounter_codes FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_encounter_charges AFTER INSERT OR UPDATE OR DELETE ON emr.encounter_charges FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_coverages AFTER INSERT OR UPDATE OR DELETE ON emr.coverages FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_claim_exports AFTER INSERT OR UPDATE OR DELETE ON emr.claim_exports FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_claims AFTER INSERT OR UPDATE OR DELETE ON emr.claims FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_remittances AFTER INSERT OR UPDATE OR DELETE ON emr.remittances FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_claim_payments AFTER INSERT OR UPDATE OR DELETE ON emr.claim_payments FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_billing_tasks AFTER INSERT OR UPDATE OR DELETE ON emr.billing_tasks FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_patient_panels AFTER INSERT OR UPDATE OR DELETE ON emr.patient_panels FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_quality_measures AFTER INSERT OR UPDATE OR DELETE ON emr.quality_measures FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_report_schedules AFTER INSERT OR UPDATE OR DELETE ON emr.report_schedules FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
+CREATE TRIGGER audit_feature_flags AFTER INSERT OR UPDATE OR DELETE ON emr.feature_flags FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
 CREATE TRIGGER audit_users AFTER INSERT OR UPDATE OR DELETE ON emr.users FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
 CREATE TRIGGER audit_sessions AFTER INSERT OR UPDATE OR DELETE ON emr.sessions FOR EACH ROW EXECUTE FUNCTION audit.audit_trigger_function();
 
@@ -333,4 +1221,127 @@ GRANT ALL PRIVILEGES ON ALL SEQUENCES IN SCHEMA jobs TO emr_user;
 GRANT USAGE ON SCHEMA emr TO emr_user;
 GRANT USAGE ON SCHEMA fhir TO emr_user;
 GRANT USAGE ON SCHEMA audit TO emr_user;
-GRANT USAGE ON SCHEMA jobs TO emr_user; 
\ No newline at end of file
+GRANT USAGE ON SCHEMA jobs TO emr_user; 
+
+-- Row-level security
+--
+-- The API switches its connections to emr_app (database.row_security_role) and sets
+-- application.tenant_id, application.user_id and application.patient_id for the request
+-- it serves. emr_app only sees the patients of the session's tenant, or the portal
+-- session's own patient, and only the rows of patient-scoped tables that belong to
+-- them, directly or through their parent row; other tenants' rows can be neither read
+-- nor written, whatever the query. With no tenant set nothing is visible. The audit log
+-- is append-only for emr_app, which reads back only its patients' entries, and tables
+-- the API does not use are not granted to it at all. The jobs worker, the CLI and
+-- migrations connect as the table owner and are not subject to the policies.
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'emr_app') THEN
+        CREATE ROLE emr_app NOLOGIN;
+    END IF;
+END
+$$;
+GRANT emr_app TO emr_user;
+GRANT USAGE ON SCHEMA emr, fhir, audit, jobs TO emr_app;
+GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA emr, fhir, audit, jobs TO emr_app;
+GRANT USAGE, SELECT ON ALL SEQUENCES IN SCHEMA emr, fhir, audit, jobs TO emr_app;
+-- Policies apply to partitions only when they are queried through their table
+REVOKE ALL ON emr.observations_default FROM emr_app;
+-- Tables only the jobs worker and the CLI use
+REVOKE ALL ON emr.sessions, emr.data_keys, emr.encrypted_fields, emr.claim_exports, emr.remittances,
+    jobs.job_queue, jobs.idempotency_keys, jobs.job_step_results, jobs.maintenance_tasks,
+    jobs.held_notifications FROM emr_app;
+-- The accounting of disclosures names staff users; their credentials stay out of reach
+REVOKE ALL ON emr.users FROM emr_app;
+GRANT SELECT (id, username) ON emr.users TO emr_app;
+REVOKE UPDATE, DELETE ON audit.audit_log FROM emr_app;
+
+CREATE OR REPLACE FUNCTION emr.current_tenant() RETURNS UUID AS $$
+    SELECT NULLIF(current_setting('application.tenant_id', true), '')::uuid
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION emr.current_patient() RETURNS UUID AS $$
+    SELECT NULLIF(current_setting('application.patient_id', true), '')::uuid
+$$ LANGUAGE sql STABLE;
+
+ALTER TABLE emr.patients ENABLE ROW LEVEL SECURITY;
+CREATE POLICY patients_tenant ON emr.patients TO emr_app
+    USING (managing_organization_id = emr.current_tenant() OR id = emr.current_patient());
+
+-- Rows of patient-scoped tables follow their patient: the subquery is itself limited
+-- by the patients policy
+DO $$
+DECLARE
+    patient_table TEXT;
+BEGIN
+    FOREACH patient_table IN ARRAY ARRAY[
+        'document_references', 'communications', 'encounters', 'observations', 'clinical_notes',
+        'service_requests', 'acknowledgment_tasks', 'care_teams', 'emergency_access', 'referrals',
+        'questionnaire_responses', 'medication_requests', 'medication_administrations', 'encounter_codes',
+        'encounter_charges', 'coverages', 'patient_panel_members', 'measure_report_patients',
+        'patient_summaries', 'portal_accounts'
+    ] LOOP
+        EXECUTE format('ALTER TABLE emr.%I ENABLE ROW LEVEL SECURITY', patient_table);
+        EXECUTE format(
+            'CREATE POLICY %I ON emr.%I TO emr_app USING (patient_id IN (SELECT id FROM emr.patients))',
+            patient_table || '_tenant',
+            patient_table
+        );
+    END LOOP;
+END
+$$;
+
+-- Rows that belong to a patient through a parent row follow the parent, whose own
+-- policy limits it to the session's patients
+ALTER TABLE emr.document_texts ENABLE ROW LEVEL SECURITY;
+CREATE POLICY document_texts_tenant ON emr.document_texts TO emr_app
+    USING (document_id IN (SELECT id FROM emr.document_references));
+
+ALTER TABLE emr.clinical_note_versions ENABLE ROW LEVEL SECURITY;
+CREATE POLICY clinical_note_versions_tenant ON emr.clinical_note_versions TO emr_app
+    USING (note_id IN (SELECT id FROM emr.clinical_notes));
+
+ALTER TABLE emr.signatures ENABLE ROW LEVEL SECURITY;
+CREATE POLICY signatures_tenant ON emr.signatures TO emr_app
+    USING (target_type = 'ClinicalNote' AND target_id IN (SELECT id FROM emr.clinical_notes));
+
+ALTER TABLE emr.care_team_participants ENABLE ROW LEVEL SECURITY;
+CREATE POLICY care_team_participants_tenant ON emr.care_team_participants TO emr_app
+    USING (care_team_id IN (SELECT id FROM emr.care_teams));
+
+ALTER TABLE emr.communication_reads ENABLE ROW LEVEL SECURITY;
+CREATE POLICY communication_reads_tenant ON emr.communication_reads TO emr_app
+    USING (thread_id IN (SELECT thread_id FROM emr.communications));
+
+ALTER TABLE emr.claims ENABLE ROW LEVEL SECURITY;
+CREATE POLICY claims_tenant ON emr.claims TO emr_app
+    USING (encounter_id IN (SELECT id FROM emr.encounters));
+
+ALTER TABLE emr.claim_payments ENABLE ROW LEVEL SECURITY;
+CREATE POLICY claim_payments_tenant ON emr.claim_payments TO emr_app
+    USING (claim_id IN (SELECT claim_id FROM emr.claims));
+
+ALTER TABLE emr.billing_tasks ENABLE ROW LEVEL SECURITY;
+CREATE POLICY billing_tasks_tenant ON emr.billing_tasks TO emr_app
+    USING (claim_id IN (SELECT claim_id FROM emr.claims));
+
+-- The audit log takes entries from every session, including the table triggers that
+-- run in it, and shows each only the entries about its own patients
+ALTER TABLE audit.audit_log ENABLE ROW LEVEL SECURITY;
+CREATE POLICY audit_log_append ON audit.audit_log FOR INSERT TO emr_app WITH CHECK (true);
+CREATE POLICY audit_log_tenant ON audit.audit_log FOR SELECT TO emr_app
+    USING (patient_id IN (SELECT id FROM emr.patients));
+
+-- Portal sign-in happens before there is a session, so it reaches accounts through
+-- these functions, which run as the owner and expose one account by its exact username
+CREATE OR REPLACE FUNCTION emr.portal_account(account_username TEXT)
+RETURNS TABLE (id UUID, patient_id UUID, password_hash TEXT, active BOOLEAN) AS $$
+    SELECT a.id, a.patient_id, a.password_hash, a.active FROM emr.portal_accounts a WHERE a.username = account_username
+$$ LANGUAGE sql STABLE SECURITY DEFINER SET search_path = pg_catalog, pg_temp;
+
+CREATE OR REPLACE FUNCTION emr.record_portal_login(account_id UUID) RETURNS VOID AS $$
+    UPDATE emr.portal_accounts SET last_login_at = NOW() WHERE id = account_id
+$$ LANGUAGE sql SECURITY DEFINER SET search_path = pg_catalog, pg_temp;
+
+REVOKE ALL ON FUNCTION emr.portal_account(TEXT), emr.record_portal_login(UUID) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION emr.portal_account(TEXT), emr.record_portal_login(UUID) TO emr_app;
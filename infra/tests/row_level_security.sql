@@ -0,0 +1,302 @@
+-- Row-level security tests
+--
+-- Proves the policies in init-db.sql keep tenants apart even when a query
+-- forgets to filter by tenant. Run against a database initialised from
+-- init-db.sql, as emr_user (`just test-rls`). Everything happens in one
+-- transaction that is rolled back; a failed check raises and stops the script.
+
+\set ON_ERROR_STOP on
+
+BEGIN;
+
+-- Two tenants with one patient, observation and document each
+INSERT INTO emr.organizations (id, name) VALUES
+    ('a0000000-0000-0000-0000-000000000001', 'Tenant A'),
+    ('b0000000-0000-0000-0000-000000000001', 'Tenant B');
+INSERT INTO emr.patients (id, family_name, managing_organization_id) VALUES
+    ('a0000000-0000-0000-0000-000000000002', 'Alpha', 'a0000000-0000-0000-0000-000000000001'),
+    ('b0000000-0000-0000-0000-000000000002', 'Beta', 'b0000000-0000-0000-0000-000000000001');
+INSERT INTO emr.observations (id, status, code, patient_id) VALUES
+    ('a0000000-0000-0000-0000-000000000003', 'final', '8867-4', 'a0000000-0000-0000-0000-000000000002'),
+    ('b0000000-0000-0000-0000-000000000003', 'final', '8867-4', 'b0000000-0000-0000-0000-000000000002');
+INSERT INTO emr.document_references (id, patient_id, content_type, url) VALUES
+    ('a0000000-0000-0000-0000-000000000004', 'a0000000-0000-0000-0000-000000000002', 'application/pdf', '/a'),
+    ('b0000000-0000-0000-0000-000000000004', 'b0000000-0000-0000-0000-000000000002', 'application/pdf', '/b');
+
+-- And one row each in the tables that reach their patient through a parent row
+INSERT INTO emr.document_texts (document_id, extractor, content) VALUES
+    ('a0000000-0000-0000-0000-000000000004', 'pdf', 'alpha text'),
+    ('b0000000-0000-0000-0000-000000000004', 'pdf', 'beta text');
+INSERT INTO emr.encounters (id, status, patient_id) VALUES
+    ('a0000000-0000-0000-0000-000000000006', 'finished', 'a0000000-0000-0000-0000-000000000002'),
+    ('b0000000-0000-0000-0000-000000000006', 'finished', 'b0000000-0000-0000-0000-000000000002');
+INSERT INTO emr.clinical_notes (id, patient_id, author_id, note_type, title, status, version, created_at, updated_at)
+VALUES
+    ('a0000000-0000-0000-0000-000000000007', 'a0000000-0000-0000-0000-000000000002',
+     'a0000000-0000-0000-0000-0000000000ff', 'progress', 'Alpha', 'signed', 1, NOW(), NOW()),
+    ('b0000000-0000-0000-0000-000000000007', 'b0000000-0000-0000-0000-000000000002',
+     'b0000000-0000-0000-0000-0000000000ff', 'progress', 'Beta', 'signed', 1, NOW(), NOW());
+INSERT INTO emr.clinical_note_versions (note_id, version, title, sections, saved_at) VALUES
+    ('a0000000-0000-0000-0000-000000000007', 1, 'Alpha', '[]', NOW()),
+    ('b0000000-0000-0000-0000-000000000007', 1, 'Beta', '[]', NOW());
+INSERT INTO emr.signatures (id, target_type, target_id, kind, signer_id, signed_at, content_hash, seal) VALUES
+    ('a0000000-0000-0000-0000-000000000008', 'ClinicalNote', 'a0000000-0000-0000-0000-000000000007', 'author',
+     'a0000000-0000-0000-0000-0000000000ff', NOW(), repeat('a', 64), repeat('a', 64)),
+    ('b0000000-0000-0000-0000-000000000008', 'ClinicalNote', 'b0000000-0000-0000-0000-000000000007', 'author',
+     'b0000000-0000-0000-0000-0000000000ff', NOW(), repeat('b', 64), repeat('b', 64));
+INSERT INTO emr.care_teams (id, patient_id, name, status, version, created_at, updated_at) VALUES
+    ('a0000000-0000-0000-0000-000000000009', 'a0000000-0000-0000-0000-000000000002', 'Alpha', 'active', 1,
+     NOW(), NOW()),
+    ('b0000000-0000-0000-0000-000000000009', 'b0000000-0000-0000-0000-000000000002', 'Beta', 'active', 1,
+     NOW(), NOW());
+INSERT INTO emr.care_team_participants (care_team_id, practitioner_id, role, period_start) VALUES
+    ('a0000000-0000-0000-0000-000000000009', 'a0000000-0000-0000-0000-0000000000ff', 'primary', NOW()),
+    ('b0000000-0000-0000-0000-000000000009', 'b0000000-0000-0000-0000-0000000000ff', 'primary', NOW());
+INSERT INTO emr.communications (id, thread_id, patient_id, sender_type, sender_id, recipients, payload, sent_at) VALUES
+    ('a0000000-0000-0000-0000-00000000000a', 'a0000000-0000-0000-0000-00000000000b',
+     'a0000000-0000-0000-0000-000000000002', 'patient', 'a0000000-0000-0000-0000-000000000002', '[]', 'hi', NOW()),
+    ('b0000000-0000-0000-0000-00000000000a', 'b0000000-0000-0000-0000-00000000000b',
+     'b0000000-0000-0000-0000-000000000002', 'patient', 'b0000000-0000-0000-0000-000000000002', '[]', 'hi', NOW());
+INSERT INTO emr.communication_reads (thread_id, reader_type, reader_id, read_at) VALUES
+    ('a0000000-0000-0000-0000-00000000000b', 'patient', 'a0000000-0000-0000-0000-000000000002', NOW()),
+    ('b0000000-0000-0000-0000-00000000000b', 'patient', 'b0000000-0000-0000-0000-000000000002', NOW());
+INSERT INTO emr.claim_exports (id, control_number, service_from, service_to, claim_count, rejected_count, created_at)
+VALUES ('c0000000-0000-0000-0000-000000000001', 1, CURRENT_DATE, CURRENT_DATE, 2, 0, NOW());
+INSERT INTO emr.claims (claim_id, encounter_id, export_id, payer_id, total_charge_cents, claim, created_at) VALUES
+    ('CLAIM-A', 'a0000000-0000-0000-0000-000000000006', 'c0000000-0000-0000-0000-000000000001', 'P1', 100, '{}', NOW()),
+    ('CLAIM-B', 'b0000000-0000-0000-0000-000000000006', 'c0000000-0000-0000-0000-000000000001', 'P1', 100, '{}', NOW());
+INSERT INTO emr.remittances (id, payer_id, payer_name, trace_number, payment_cents, payment_method, source_location,
+    posted_at)
+VALUES ('c0000000-0000-0000-0000-000000000002', 'P1', 'Payer', 'T1', 200, 'ACH', '/era/1', NOW());
+INSERT INTO emr.claim_payments (id, remittance_id, claim_id, status, status_code, charge_cents, paid_cents,
+    patient_responsibility_cents, written_off_cents, denied, posted_at)
+VALUES
+    ('a0000000-0000-0000-0000-00000000000c', 'c0000000-0000-0000-0000-000000000002', 'CLAIM-A', 'paid', '1',
+     100, 100, 0, 0, false, NOW()),
+    ('b0000000-0000-0000-0000-00000000000c', 'c0000000-0000-0000-0000-000000000002', 'CLAIM-B', 'paid', '1',
+     100, 100, 0, 0, false, NOW());
+INSERT INTO emr.billing_tasks (id, remittance_id, claim_id, reason, detail, status, created_at) VALUES
+    ('a0000000-0000-0000-0000-00000000000d', 'c0000000-0000-0000-0000-000000000002', 'CLAIM-A', 'review', 'a',
+     'requested', NOW()),
+    ('b0000000-0000-0000-0000-00000000000d', 'c0000000-0000-0000-0000-000000000002', 'CLAIM-B', 'review', 'b',
+     'requested', NOW());
+INSERT INTO emr.portal_accounts (id, patient_id, username, password_hash) VALUES
+    ('a0000000-0000-0000-0000-00000000000e', 'a0000000-0000-0000-0000-000000000002', 'alpha', 'hash-a'),
+    ('b0000000-0000-0000-0000-00000000000e', 'b0000000-0000-0000-0000-000000000002', 'beta', 'hash-b');
+INSERT INTO audit.audit_log (table_name, operation, patient_id) VALUES
+    ('patient_access', 'READ', 'a0000000-0000-0000-0000-000000000002'),
+    ('patient_access', 'READ', 'b0000000-0000-0000-0000-000000000002');
+INSERT INTO emr.users (id, username, email, password_hash) VALUES
+    ('a0000000-0000-0000-0000-00000000000f', 'staff-a', 'staff-a@example.org', 'hash-staff');
+
+-- The API's connections: emr_app with tenant A's session
+SET ROLE emr_app;
+SET application.tenant_id = 'a0000000-0000-0000-0000-000000000001';
+SET application.user_id = 'practitioner-a';
+
+DO $$
+BEGIN
+    -- Unfiltered queries, as buggy application code would write them
+    IF (SELECT count(*) FROM emr.patients) <> 1 THEN
+        RAISE EXCEPTION 'tenant A sees % patients', (SELECT count(*) FROM emr.patients);
+    END IF;
+    IF (SELECT count(*) FROM emr.observations) <> 1 THEN
+        RAISE EXCEPTION 'tenant A sees % observations', (SELECT count(*) FROM emr.observations);
+    END IF;
+    IF EXISTS (SELECT 1 FROM emr.patients WHERE family_name = 'Beta') THEN
+        RAISE EXCEPTION 'tenant A found tenant B''s patient by name';
+    END IF;
+    IF EXISTS (SELECT 1 FROM emr.observations WHERE id = 'b0000000-0000-0000-0000-000000000003') THEN
+        RAISE EXCEPTION 'tenant A read tenant B''s observation by id';
+    END IF;
+    IF EXISTS (
+        SELECT 1 FROM emr.document_references d
+        WHERE d.patient_id = 'b0000000-0000-0000-0000-000000000002'
+    ) THEN
+        RAISE EXCEPTION 'tenant A listed tenant B''s documents';
+    END IF;
+END
+$$;
+
+-- Every table holding patient data shows tenant A its own row only
+DO $$
+DECLARE
+    scoped TEXT;
+    visible BIGINT;
+BEGIN
+    FOREACH scoped IN ARRAY ARRAY[
+        'emr.document_texts', 'emr.encounters', 'emr.clinical_notes', 'emr.clinical_note_versions', 'emr.signatures',
+        'emr.care_teams', 'emr.care_team_participants', 'emr.communications', 'emr.communication_reads',
+        'emr.claims', 'emr.claim_payments', 'emr.billing_tasks', 'emr.portal_accounts'
+    ] LOOP
+        EXECUTE format('SELECT count(*) FROM %s', scoped) INTO visible;
+        IF visible <> 1 THEN
+            RAISE EXCEPTION 'tenant A sees % rows of %', visible, scoped;
+        END IF;
+    END LOOP;
+    IF EXISTS (SELECT 1 FROM emr.claims WHERE claim_id = 'CLAIM-B') THEN
+        RAISE EXCEPTION 'tenant A read tenant B''s claim by id';
+    END IF;
+    IF EXISTS (SELECT 1 FROM emr.portal_accounts WHERE username = 'beta') THEN
+        RAISE EXCEPTION 'tenant A read tenant B''s portal account';
+    END IF;
+    IF EXISTS (SELECT 1 FROM audit.audit_log WHERE patient_id = 'b0000000-0000-0000-0000-000000000002') THEN
+        RAISE EXCEPTION 'tenant A read the accounting of disclosures of tenant B''s patient';
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM audit.audit_log WHERE patient_id = 'a0000000-0000-0000-0000-000000000002') THEN
+        RAISE EXCEPTION 'tenant A cannot read the accounting of disclosures of its own patient';
+    END IF;
+    IF (SELECT username FROM emr.users WHERE id = 'a0000000-0000-0000-0000-00000000000f') <> 'staff-a' THEN
+        RAISE EXCEPTION 'the accounting of disclosures cannot name staff users';
+    END IF;
+END
+$$;
+
+-- The audit log only grows, staff credentials and worker tables are out of reach
+DO $$
+DECLARE
+    forbidden TEXT;
+BEGIN
+    FOREACH forbidden IN ARRAY ARRAY[
+        'UPDATE audit.audit_log SET operation = ''NONE''',
+        'DELETE FROM audit.audit_log',
+        'SELECT password_hash FROM emr.users',
+        'SELECT 1 FROM emr.sessions',
+        'SELECT 1 FROM emr.data_keys',
+        'SELECT 1 FROM emr.encrypted_fields',
+        'SELECT 1 FROM emr.claim_exports',
+        'SELECT 1 FROM emr.remittances',
+        'SELECT 1 FROM jobs.job_queue',
+        'SELECT 1 FROM jobs.held_notifications'
+    ] LOOP
+        BEGIN
+            EXECUTE forbidden;
+            RAISE EXCEPTION 'emr_app was allowed to run: %', forbidden;
+        EXCEPTION WHEN insufficient_privilege THEN
+            NULL;
+        END;
+    END LOOP;
+END
+$$;
+
+-- Writes to another tenant's rows touch nothing
+UPDATE emr.observations SET status = 'cancelled';
+UPDATE emr.portal_accounts SET active = false;
+DELETE FROM emr.care_team_participants;
+DELETE FROM emr.document_references WHERE id = 'b0000000-0000-0000-0000-000000000004';
+
+DO $$
+BEGIN
+    BEGIN
+        INSERT INTO emr.observations (status, code, patient_id)
+        VALUES ('final', '8867-4', 'b0000000-0000-0000-0000-000000000002');
+        RAISE EXCEPTION 'tenant A recorded an observation for tenant B''s patient';
+    EXCEPTION WHEN insufficient_privilege THEN
+        NULL;
+    END;
+    BEGIN
+        INSERT INTO emr.patients (family_name, managing_organization_id)
+        VALUES ('Gamma', 'b0000000-0000-0000-0000-000000000001');
+        RAISE EXCEPTION 'tenant A registered a patient for tenant B';
+    EXCEPTION WHEN insufficient_privilege THEN
+        NULL;
+    END;
+    -- Partitions have no policies of their own
+    BEGIN
+        PERFORM 1 FROM emr.observations_default;
+        RAISE EXCEPTION 'tenant A read a partition of observations directly';
+    EXCEPTION WHEN insufficient_privilege THEN
+        NULL;
+    END;
+END
+$$;
+
+-- New patients belong to the session's tenant
+INSERT INTO emr.patients (id, family_name) VALUES ('a0000000-0000-0000-0000-000000000005', 'Delta');
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM emr.patients WHERE id = 'a0000000-0000-0000-0000-000000000005') THEN
+        RAISE EXCEPTION 'a new patient did not join the session''s tenant';
+    END IF;
+END
+$$;
+
+-- A portal session sees its own patient only
+SET application.tenant_id = '';
+SET application.patient_id = 'b0000000-0000-0000-0000-000000000002';
+
+DO $$
+BEGIN
+    IF (SELECT array_agg(family_name) FROM emr.patients) <> ARRAY['Beta']::VARCHAR[] THEN
+        RAISE EXCEPTION 'portal session sees %', (SELECT array_agg(family_name) FROM emr.patients);
+    END IF;
+    IF (SELECT count(*) FROM emr.observations) <> 1 THEN
+        RAISE EXCEPTION 'portal session sees % observations', (SELECT count(*) FROM emr.observations);
+    END IF;
+END
+$$;
+
+-- Without a session nothing is visible
+SET application.patient_id = '';
+
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM emr.patients) OR EXISTS (SELECT 1 FROM emr.observations) THEN
+        RAISE EXCEPTION 'a connection without a session sees patient data';
+    END IF;
+    IF EXISTS (SELECT 1 FROM emr.portal_accounts) OR EXISTS (SELECT 1 FROM audit.audit_log) THEN
+        RAISE EXCEPTION 'a connection without a session sees portal accounts or the audit log';
+    END IF;
+    -- Portal sign-in still finds the one account it names
+    IF (SELECT patient_id FROM emr.portal_account('beta')) <> 'b0000000-0000-0000-0000-000000000002' THEN
+        RAISE EXCEPTION 'portal sign-in cannot find its account';
+    END IF;
+    PERFORM emr.record_portal_login('b0000000-0000-0000-0000-00000000000e');
+END
+$$;
+
+-- Back as the owner: tenant B's data is untouched and the audit trail names the user
+RESET ROLE;
+
+DO $$
+BEGIN
+    IF (SELECT status FROM emr.observations WHERE id = 'b0000000-0000-0000-0000-000000000003') <> 'final' THEN
+        RAISE EXCEPTION 'tenant A changed tenant B''s observation';
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM emr.document_references WHERE id = 'b0000000-0000-0000-0000-000000000004') THEN
+        RAISE EXCEPTION 'tenant A deleted tenant B''s document';
+    END IF;
+    IF (SELECT status FROM emr.observations WHERE id = 'a0000000-0000-0000-0000-000000000003') <> 'cancelled' THEN
+        RAISE EXCEPTION 'tenant A could not update its own observation';
+    END IF;
+    IF NOT (SELECT active FROM emr.portal_accounts WHERE username = 'beta') THEN
+        RAISE EXCEPTION 'tenant A deactivated tenant B''s portal account';
+    END IF;
+    IF (SELECT active FROM emr.portal_accounts WHERE username = 'alpha') THEN
+        RAISE EXCEPTION 'tenant A could not deactivate its own portal account';
+    END IF;
+    IF (SELECT count(*) FROM emr.care_team_participants) <> 1 THEN
+        RAISE EXCEPTION 'tenant A removed tenant B''s care team participants';
+    END IF;
+    IF (SELECT last_login_at FROM emr.portal_accounts WHERE username = 'beta') IS NULL THEN
+        RAISE EXCEPTION 'portal sign-in was not recorded';
+    END IF;
+    IF NOT EXISTS (
+        SELECT 1 FROM audit.audit_log
+        WHERE table_name = 'patients' AND operation = 'INSERT' AND changed_by = 'practitioner-a'
+    ) THEN
+        RAISE EXCEPTION 'the audit trail does not name the API user';
+    END IF;
+    IF NOT EXISTS (
+        SELECT 1 FROM audit.audit_log
+        WHERE table_name = 'observations' AND operation = 'UPDATE' AND changed_by = 'practitioner-a'
+    ) THEN
+        RAISE EXCEPTION 'changes to observations are not audited under their table';
+    END IF;
+END
+$$;
+
+ROLLBACK;
+
+\echo 'Row-level security tests passed'
@@ -0,0 +1,227 @@
+//! Escalation of unacknowledged critical results
+//!
+//! The API opens a task in `emr.acknowledgment_tasks` for every abnormal
+//! result of an order. On each poll the worker claims critical tasks that
+//! have waited longer than the configured SLA since they were opened or last
+//! escalated, and queues critical-priority SMS notifications to the ordering
+//! practitioner and, when configured, to an escalation contact. A task keeps
+//! escalating once per SLA period until it is acknowledged.
+
+use crate::types::{NotificationChannel, NotificationJob, NotificationType, Priority};
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::notifications::{TemplateRef, UNACKNOWLEDGED_RESULT_TEMPLATE};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{BigInt, Integer, Text, Timestamptz};
+use diesel::RunQueryDsl;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Overdue tasks escalated per poll
+pub const ESCALATION_BATCH_SIZE: i64 = 100;
+
+/// Claim critical tasks overdue at `$1` for an SLA of `$2` minutes, oldest
+/// first, recording the escalation
+pub const ESCALATE_OVERDUE_QUERY: &str = r#"
+UPDATE emr.acknowledgment_tasks
+SET escalation_count = escalation_count + 1, last_escalated_at = $1
+WHERE id IN (
+    SELECT id FROM emr.acknowledgment_tasks
+    WHERE critical AND status = 'requested'
+      AND COALESCE(last_escalated_at, created_at) <= $1 - make_interval(mins => $2)
+    ORDER BY created_at LIMIT $3 FOR UPDATE SKIP LOCKED)
+RETURNING id, owner_id, test_name, created_at, escalation_count
+"#;
+
+/// A critical result still waiting for acknowledgment past the SLA
+#[derive(Debug, Clone)]
+pub struct OverdueResult {
+    pub task_id: Uuid,
+    /// Ordering practitioner
+    pub owner_id: Uuid,
+    pub test_name: String,
+    /// When the result arrived
+    pub created_at: DateTime<Utc>,
+    /// Escalations including this one
+    pub escalation_count: u32,
+}
+
+/// Storage for acknowledgment tasks
+#[async_trait]
+pub trait AcknowledgmentStore: Send + Sync {
+    /// Claim up to `limit` critical tasks overdue at `now` and record their
+    /// escalation
+    async fn escalate_overdue(&self, now: DateTime<Utc>, sla_minutes: i64, limit: i64) -> JobResult<Vec<OverdueResult>>;
+}
+
+/// Tasks in `emr.acknowledgment_tasks`
+pub struct DatabaseAcknowledgmentStore {
+    pool: Pool,
+}
+
+impl DatabaseAcknowledgmentStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct OverdueRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    owner_id: Uuid,
+    #[diesel(sql_type = Text)]
+    test_name: String,
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+    #[diesel(sql_type = Integer)]
+    escalation_count: i32,
+}
+
+#[async_trait]
+impl AcknowledgmentStore for DatabaseAcknowledgmentStore {
+    async fn escalate_overdue(&self, now: DateTime<Utc>, sla_minutes: i64, limit: i64) -> JobResult<Vec<OverdueResult>> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let sla_minutes = i32::try_from(sla_minutes)
+            .map_err(|_| JobError::ConfigurationError(format!("SLA of {} minutes is too long", sla_minutes)))?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(ESCALATE_OVERDUE_QUERY)
+                    .bind::<Timestamptz, _>(now)
+                    .bind::<Integer, _>(sla_minutes)
+                    .bind::<BigInt, _>(limit)
+                    .load::<OverdueRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OverdueResult {
+                task_id: row.id,
+                owner_id: row.owner_id,
+                test_name: row.test_name,
+                created_at: row.created_at,
+                escalation_count: row.escalation_count.max(0) as u32,
+            })
+            .collect())
+    }
+}
+
+/// Critical notifications escalating overdue results to their orderers and
+/// the escalation contact
+pub fn escalation_jobs(
+    overdue: &[OverdueResult],
+    now: DateTime<Utc>,
+    escalation_recipient_id: Option<Uuid>,
+) -> Vec<NotificationJob> {
+    let mut jobs = Vec::new();
+    for result in overdue {
+        let minutes = (now - result.created_at).num_minutes().max(0);
+        let variables = json!({ "test_name": result.test_name, "minutes": minutes });
+        let recipients = std::iter::once(result.owner_id)
+            .chain(escalation_recipient_id.filter(|recipient| *recipient != result.owner_id));
+
+        for recipient_id in recipients {
+            jobs.push(NotificationJob {
+                recipient_id,
+                address: None,
+                notification_type: NotificationType::Alert,
+                message: String::new(),
+                template: Some(TemplateRef {
+                    name: UNACKNOWLEDGED_RESULT_TEMPLATE.to_string(),
+                    locale: None,
+                    variables: variables.as_object().cloned().unwrap_or_default(),
+                }),
+                channel: NotificationChannel::Sms,
+                priority: Priority::Critical,
+                scheduled_for: None,
+                digest: false,
+            });
+        }
+    }
+    jobs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::sync::Mutex;
+
+    /// Tasks kept in memory, escalated like the database query does
+    struct MemoryStore {
+        tasks: Mutex<Vec<(OverdueResult, Option<DateTime<Utc>>)>>,
+    }
+
+    #[async_trait]
+    impl AcknowledgmentStore for MemoryStore {
+        async fn escalate_overdue(&self, now: DateTime<Utc>, sla_minutes: i64, limit: i64) -> JobResult<Vec<OverdueResult>> {
+            let mut tasks = self.tasks.lock().unwrap();
+            let mut escalated = Vec::new();
+            for (task, last_escalated_at) in tasks.iter_mut() {
+                let since = last_escalated_at.unwrap_or(task.created_at);
+                if escalated.len() as i64 == limit || now - since < Duration::minutes(sla_minutes) {
+                    continue;
+                }
+                task.escalation_count += 1;
+                *last_escalated_at = Some(now);
+                escalated.push(task.clone());
+            }
+            Ok(escalated)
+        }
+    }
+
+    fn overdue(owner_id: Uuid, minutes_ago: i64) -> OverdueResult {
+        OverdueResult {
+            task_id: Uuid::new_v4(),
+            owner_id,
+            test_name: "Potassium".to_string(),
+            created_at: Utc::now() - Duration::minutes(minutes_ago),
+            escalation_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overdue_results_escalate_once_per_sla_period() {
+        let owner = Uuid::new_v4();
+        let store = MemoryStore {
+            tasks: Mutex::new(vec![(overdue(owner, 75), None), (overdue(owner, 20), None)]),
+        };
+        let now = Utc::now();
+
+        let escalated = store.escalate_overdue(now, 60, ESCALATION_BATCH_SIZE).await.unwrap();
+        assert_eq!(escalated.len(), 1);
+        assert_eq!(escalated[0].escalation_count, 1);
+        assert!(store.escalate_overdue(now + Duration::minutes(30), 60, ESCALATION_BATCH_SIZE).await.unwrap().is_empty());
+        assert_eq!(store.escalate_overdue(now + Duration::minutes(61), 60, ESCALATION_BATCH_SIZE).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_escalation_jobs() {
+        let owner = Uuid::new_v4();
+        let charge_nurse = Uuid::new_v4();
+        let results = [overdue(owner, 75)];
+
+        let jobs = escalation_jobs(&results, Utc::now(), Some(charge_nurse));
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].recipient_id, owner);
+        assert_eq!(jobs[1].recipient_id, charge_nurse);
+        assert!(jobs.iter().all(|job| job.priority == Priority::Critical && job.channel == NotificationChannel::Sms));
+
+        let template = jobs[0].template.as_ref().unwrap();
+        assert_eq!(template.name, UNACKNOWLEDGED_RESULT_TEMPLATE);
+        assert_eq!(template.variables["minutes"], 75);
+
+        assert_eq!(escalation_jobs(&results, Utc::now(), Some(owner)).len(), 1, "no duplicate for the orderer");
+    }
+}
@@ -0,0 +1,330 @@
+//! Encrypted logical backups
+//!
+//! Backups are plain-format `pg_dump` output, gzip-compressed, encrypted with
+//! AES-256-GCM and uploaded to an S3-compatible bucket next to a JSON
+//! manifest. The manifest records SHA-256 checksums of both the dump and the
+//! uploaded object so a restore can verify integrity before touching the
+//! database.
+
+use crate::config::BackupConfig;
+use crate::{JobError, JobResult};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path;
+use object_store::{Attribute, Attributes, ObjectStore, PutOptions, PutPayload};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use uuid::Uuid;
+
+/// Encryption algorithm recorded in manifests
+pub const BACKUP_CIPHER: &str = "AES-256-GCM";
+
+/// Describes a stored backup and how to verify it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub backup_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    /// Object key of the encrypted dump
+    pub object_key: String,
+    pub cipher: String,
+    /// Base64-encoded GCM nonce
+    pub nonce: String,
+    /// Size of the uncompressed dump
+    pub dump_bytes: u64,
+    /// Size of the uploaded object
+    pub object_bytes: u64,
+    /// SHA-256 (hex) of the uncompressed dump
+    pub dump_sha256: String,
+    /// SHA-256 (hex) of the uploaded object
+    pub object_sha256: String,
+}
+
+impl BackupManifest {
+    /// Object key of the manifest for a backup object
+    pub fn key_for(object_key: &str) -> String {
+        format!("{}.manifest.json", object_key)
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Check that a configured encryption key is base64 for 32 bytes
+pub fn check_key(key: &str) -> JobResult<()> {
+    cipher(key).map(|_| ())
+}
+
+fn cipher(key: &str) -> JobResult<Aes256Gcm> {
+    let key = STANDARD
+        .decode(key)
+        .map_err(|e| JobError::ValidationError(format!("Backup encryption key is not base64: {}", e)))?;
+    if key.len() != 32 {
+        return Err(JobError::ValidationError(
+            "Backup encryption key must be 32 bytes".to_string(),
+        ));
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+/// Compress and encrypt a dump, returning the object bytes and its manifest
+pub fn seal(dump: &[u8], key: &str, backup_id: Uuid, object_key: &str) -> JobResult<(Vec<u8>, BackupManifest)> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder
+        .write_all(dump)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| JobError::ProcessingError(format!("Backup compression failed: {}", e)))?;
+
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let object = cipher(key)?
+        .encrypt(&nonce, compressed.as_slice())
+        .map_err(|_| JobError::ProcessingError("Backup encryption failed".to_string()))?;
+
+    let manifest = BackupManifest {
+        backup_id,
+        created_at: Utc::now(),
+        object_key: object_key.to_string(),
+        cipher: BACKUP_CIPHER.to_string(),
+        nonce: STANDARD.encode(nonce),
+        dump_bytes: dump.len() as u64,
+        object_bytes: object.len() as u64,
+        dump_sha256: sha256_hex(dump),
+        object_sha256: sha256_hex(&object),
+    };
+
+    Ok((object, manifest))
+}
+
+/// Verify, decrypt and decompress a backup object
+///
+/// Checksums are checked before decryption and again on the restored dump,
+/// so a corrupted or substituted object is rejected.
+pub fn open(object: &[u8], manifest: &BackupManifest, key: &str) -> JobResult<Vec<u8>> {
+    if manifest.cipher != BACKUP_CIPHER {
+        return Err(JobError::ValidationError(format!("Unsupported backup cipher {}", manifest.cipher)));
+    }
+    if sha256_hex(object) != manifest.object_sha256 {
+        return Err(JobError::ValidationError(format!(
+            "Checksum mismatch for backup object {}",
+            manifest.object_key
+        )));
+    }
+
+    let nonce = STANDARD
+        .decode(&manifest.nonce)
+        .ok()
+        .filter(|nonce| nonce.len() == 12)
+        .ok_or_else(|| JobError::ValidationError("Backup manifest has an invalid nonce".to_string()))?;
+    let compressed = cipher(key)?
+        .decrypt(Nonce::from_slice(&nonce), object)
+        .map_err(|_| JobError::ValidationError("Backup decryption failed; wrong key?".to_string()))?;
+
+    let mut dump = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut dump)
+        .map_err(|e| JobError::ProcessingError(format!("Backup decompression failed: {}", e)))?;
+
+    if sha256_hex(&dump) != manifest.dump_sha256 {
+        return Err(JobError::ValidationError(format!(
+            "Checksum mismatch for restored dump of backup {}",
+            manifest.backup_id
+        )));
+    }
+
+    Ok(dump)
+}
+
+/// S3-compatible storage for backup objects and manifests
+pub struct BackupStore {
+    bucket: AmazonS3,
+}
+
+impl BackupStore {
+    /// Connect to the configured bucket
+    pub fn new(config: &BackupConfig) -> JobResult<Self> {
+        let bucket = AmazonS3Builder::new()
+            .with_endpoint(&config.endpoint)
+            .with_allow_http(config.endpoint.starts_with("http://"))
+            .with_region(&config.region)
+            .with_bucket_name(&config.bucket)
+            .with_access_key_id(&config.access_key)
+            .with_secret_access_key(&config.secret_key)
+            .build()
+            .map_err(|e| JobError::ValidationError(format!("Invalid backup bucket configuration: {}", e)))?;
+
+        Ok(Self { bucket })
+    }
+
+    async fn put(&self, key: &str, data: &[u8], content_type: &str) -> JobResult<()> {
+        let mut attributes = Attributes::new();
+        attributes.insert(Attribute::ContentType, content_type.to_string().into());
+        let options = PutOptions {
+            attributes,
+            ..PutOptions::default()
+        };
+        self.bucket
+            .put_opts(&Path::from(key), PutPayload::from(data.to_vec()), options)
+            .await
+            .map_err(|e| JobError::ExternalServiceError(format!("Upload of {} failed: {}", key, e)))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> JobResult<Vec<u8>> {
+        let response = self
+            .bucket
+            .get(&Path::from(key))
+            .await
+            .map_err(|e| JobError::ExternalServiceError(format!("Download of {} failed: {}", key, e)))?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| JobError::ExternalServiceError(format!("Download of {} failed: {}", key, e)))?;
+        Ok(bytes.to_vec())
+    }
+
+    /// Upload a sealed backup and its manifest
+    pub async fn upload(&self, object: &[u8], manifest: &BackupManifest) -> JobResult<()> {
+        self.put(&manifest.object_key, object, "application/octet-stream").await?;
+        let manifest_json =
+            serde_json::to_vec_pretty(manifest).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        self.put(&BackupManifest::key_for(&manifest.object_key), &manifest_json, "application/json")
+            .await
+    }
+
+    /// Download a backup object and its manifest
+    pub async fn download(&self, object_key: &str) -> JobResult<(Vec<u8>, BackupManifest)> {
+        let manifest_json = self.get(&BackupManifest::key_for(object_key)).await?;
+        let manifest: BackupManifest = serde_json::from_slice(&manifest_json)
+            .map_err(|e| JobError::SerializationError(format!("Invalid backup manifest: {}", e)))?;
+        let object = self.get(object_key).await?;
+        Ok((object, manifest))
+    }
+}
+
+/// Run `pg_dump` and capture the plain-format dump
+pub async fn dump_database(pg_dump: &str, database_url: &str, schemas: &[String]) -> JobResult<Vec<u8>> {
+    let mut command = Command::new(pg_dump);
+    command
+        .arg("--format=plain")
+        .arg("--no-owner")
+        .arg("--no-privileges")
+        .arg(format!("--dbname={}", database_url));
+    for schema in schemas {
+        command.arg(format!("--schema={}", schema));
+    }
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| JobError::ProcessingError(format!("Failed to run {}: {}", pg_dump, e)))?;
+    if !output.status.success() {
+        return Err(JobError::DatabaseError(format!(
+            "pg_dump exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Replay a plain-format dump through `psql`, stopping at the first error
+pub async fn restore_database(psql: &str, database_url: &str, dump: &[u8]) -> JobResult<()> {
+    let mut child = Command::new(psql)
+        .arg("--quiet")
+        .arg("--single-transaction")
+        .arg("--set=ON_ERROR_STOP=1")
+        .arg(format!("--dbname={}", database_url))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| JobError::ProcessingError(format!("Failed to run {}: {}", psql, e)))?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| JobError::ProcessingError("psql stdin unavailable".to_string()))?;
+    stdin
+        .write_all(dump)
+        .await
+        .map_err(|e| JobError::ProcessingError(format!("Failed to stream dump to psql: {}", e)))?;
+    drop(stdin);
+
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| JobError::ProcessingError(format!("psql did not finish: {}", e)))?;
+    if !output.status.success() {
+        return Err(JobError::DatabaseError(format!(
+            "psql exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Download and verify a backup, then restore it unless `verify_only` is set
+pub async fn restore(
+    config: &BackupConfig,
+    database_url: &str,
+    object_key: &str,
+    verify_only: bool,
+) -> JobResult<BackupManifest> {
+    let store = BackupStore::new(config)?;
+    let (object, manifest) = store.download(object_key).await?;
+    let dump = open(&object, &manifest, &config.encryption_key)?;
+
+    if !verify_only {
+        restore_database(&config.psql_path, database_url, &dump).await?;
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> String {
+        STANDARD.encode([7u8; 32])
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let dump = b"CREATE TABLE emr.patients (id uuid);\n".repeat(100);
+        let (object, manifest) = seal(&dump, &key(), Uuid::new_v4(), "backups/test.sql.gz.enc").unwrap();
+
+        assert!(object.len() < dump.len());
+        assert_eq!(manifest.dump_bytes, dump.len() as u64);
+        assert_eq!(manifest.object_sha256.len(), 64);
+        assert_eq!(open(&object, &manifest, &key()).unwrap(), dump);
+    }
+
+    #[test]
+    fn test_open_rejects_tampering() {
+        let (mut object, manifest) = seal(b"SELECT 1;", &key(), Uuid::new_v4(), "backups/test").unwrap();
+
+        let wrong_key = STANDARD.encode([9u8; 32]);
+        assert!(open(&object, &manifest, &wrong_key).is_err());
+
+        object[0] ^= 0xff;
+        assert!(open(&object, &manifest, &key()).is_err());
+    }
+
+    #[test]
+    fn test_cipher_requires_256_bit_key() {
+        assert!(seal(b"SELECT 1;", &STANDARD.encode([1u8; 16]), Uuid::new_v4(), "k").is_err());
+        assert!(seal(b"SELECT 1;", "not base64!", Uuid::new_v4(), "k").is_err());
+    }
+}
@@ -0,0 +1,725 @@
+//! Professional claims export
+//!
+//! A ClaimsExport job bills every finished, coded encounter with a date of
+//! service in the job's period that has not been billed yet. Each encounter
+//! is assembled into a claim against the patient's primary coverage in force
+//! on that date and priced from the configured fee schedule; encounters that
+//! cannot be billed are reported with their issues and left for the next
+//! run. The claims are written as one X12 837P file, and the export, the
+//! file and the billed claims are recorded together so an encounter is never
+//! billed twice.
+
+use crate::config::BillingConfig;
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::ClaimsExportJob;
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use emr_core::billing::{
+    assemble_claim, write_837p, ClaimIssue, ClaimPerson, ClaimSource, FeeSchedule, PostalAddress,
+    ProfessionalClaim, RenderingProvider,
+};
+use emr_core::domain::values::AdministrativeGender;
+use emr_core::domain::{
+    CodeKind, CodeOrigin, Coverage, CoverageStatus, EncounterClass, EncounterCode, EncounterCoding, PlanType,
+    SubscriberRelationship,
+};
+use emr_core::types::EntityMetadata;
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Array, BigInt, Date, Integer, Nullable, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// NPI identifier system
+pub const NPI_SYSTEM: &str = "http://hl7.org/fhir/sid/us-npi";
+
+/// Finished, coded encounters in a service period that have not been billed
+pub const BILLABLE_ENCOUNTERS_QUERY: &str = r#"
+SELECT e.id AS encounter_id, e.class, (e.start_date AT TIME ZONE 'UTC')::date AS service_date,
+       p.id AS patient_id, p.family_name, p.given_names[1] AS given_name, p.birth_date, p.gender,
+       p.address_line1, p.address_line2, p.city, p.state, p.postal_code,
+       pr.family_name AS practitioner_family_name, pr.given_names[1] AS practitioner_given_name,
+       (SELECT identifier->>'value' FROM jsonb_array_elements(COALESCE(pr.identifiers, '[]'::jsonb)) AS identifier
+        WHERE identifier->>'system' = $4 LIMIT 1) AS practitioner_npi
+FROM emr.encounters e
+JOIN emr.patients p ON p.id = e.patient_id
+LEFT JOIN emr.practitioners pr ON pr.id = e.practitioner_id
+WHERE e.status = 'finished'
+  AND (e.start_date AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+  AND ($3::uuid IS NULL OR e.organization_id = $3)
+  AND EXISTS (SELECT 1 FROM emr.encounter_codes c WHERE c.encounter_id = e.id)
+  AND NOT EXISTS (SELECT 1 FROM emr.claims cl WHERE cl.encounter_id = e.id)
+ORDER BY e.start_date, e.id
+"#;
+
+/// Finalized codes of encounters, diagnoses then procedures, in sequence
+pub const ENCOUNTER_CODES_QUERY: &str = r#"
+SELECT encounter_id, kind, sequence, patient_id, code, display, units, modifiers, diagnosis_pointers, origin,
+       suggested_code, override_reason, coded_by, coded_at
+FROM emr.encounter_codes WHERE encounter_id = ANY($1)
+ORDER BY encounter_id, kind, sequence
+"#;
+
+/// Active coverages of patients, primary first
+pub const PATIENT_COVERAGES_QUERY: &str = r#"
+SELECT id, patient_id, status, priority, plan_type, payer_name, payer_id, member_id, group_number, plan_name,
+       relationship, subscriber::text AS subscriber, start_date, end_date, version, created_at, updated_at
+FROM emr.coverages WHERE patient_id = ANY($1) AND status = 'active'
+ORDER BY priority, created_at DESC
+"#;
+
+/// An encounter that could not be billed, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimRejection {
+    pub encounter_id: Uuid,
+    pub issues: Vec<ClaimIssue>,
+}
+
+/// The outcome of a claims export run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimExport {
+    pub export_id: Uuid,
+    /// Interchange control number of the file (ISA13)
+    pub control_number: u32,
+    pub service_from: NaiveDate,
+    pub service_to: NaiveDate,
+    pub claims: Vec<ProfessionalClaim>,
+    pub rejections: Vec<ClaimRejection>,
+    /// The 837P file; `None` when nothing could be billed
+    pub file_content: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Billable encounters and the record of what was billed
+#[async_trait]
+pub trait ClaimStore: Send + Sync {
+    /// What each finished, coded and unbilled encounter of the job's period
+    /// is billed from
+    async fn claim_sources(&self, job: &ClaimsExportJob) -> JobResult<Vec<ClaimSource>>;
+
+    /// Next interchange control number
+    async fn next_control_number(&self) -> JobResult<u32>;
+
+    /// Record an export and the claims it billed, all or nothing
+    async fn save_export(&self, export: &ClaimExport) -> JobResult<()>;
+}
+
+/// Encounters, codes and coverages in the `emr` schema
+pub struct DatabaseClaimStore {
+    pool: Pool,
+}
+
+impl DatabaseClaimStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct BillableEncounterRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    encounter_id: Uuid,
+    #[diesel(sql_type = Nullable<Text>)]
+    class: Option<String>,
+    #[diesel(sql_type = Date)]
+    service_date: NaiveDate,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: Uuid,
+    #[diesel(sql_type = Nullable<Text>)]
+    family_name: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    given_name: Option<String>,
+    #[diesel(sql_type = Nullable<Date>)]
+    birth_date: Option<NaiveDate>,
+    #[diesel(sql_type = Nullable<Text>)]
+    gender: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    address_line1: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    address_line2: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    city: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    state: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    postal_code: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    practitioner_family_name: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    practitioner_given_name: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    practitioner_npi: Option<String>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct EncounterCodeRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    encounter_id: Uuid,
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Integer)]
+    sequence: i32,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: Uuid,
+    #[diesel(sql_type = Text)]
+    code: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    display: Option<String>,
+    #[diesel(sql_type = Integer)]
+    units: i32,
+    #[diesel(sql_type = Array<Text>)]
+    modifiers: Vec<String>,
+    #[diesel(sql_type = Array<Integer>)]
+    diagnosis_pointers: Vec<i32>,
+    #[diesel(sql_type = Text)]
+    origin: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    suggested_code: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    override_reason: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    coded_by: Uuid,
+    #[diesel(sql_type = Timestamptz)]
+    coded_at: DateTime<Utc>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct CoverageRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: Uuid,
+    #[diesel(sql_type = Text)]
+    status: String,
+    #[diesel(sql_type = Integer)]
+    priority: i32,
+    #[diesel(sql_type = Text)]
+    plan_type: String,
+    #[diesel(sql_type = Text)]
+    payer_name: String,
+    #[diesel(sql_type = Text)]
+    payer_id: String,
+    #[diesel(sql_type = Text)]
+    member_id: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    group_number: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    plan_name: Option<String>,
+    #[diesel(sql_type = Text)]
+    relationship: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    subscriber: Option<String>,
+    #[diesel(sql_type = Nullable<Date>)]
+    start_date: Option<NaiveDate>,
+    #[diesel(sql_type = Nullable<Date>)]
+    end_date: Option<NaiveDate>,
+    #[diesel(sql_type = BigInt)]
+    version: i64,
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+    #[diesel(sql_type = Timestamptz)]
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct ControlNumberRow {
+    #[diesel(sql_type = BigInt)]
+    value: i64,
+}
+
+fn stored_value_error(what: &str, value: &str) -> JobError {
+    JobError::SerializationError(format!("Unknown {} '{}'", what, value))
+}
+
+/// Encounter class from its stored FHIR `v3-ActCode`
+fn encounter_class(code: &str) -> Option<EncounterClass> {
+    match code {
+        "AMB" => Some(EncounterClass::Ambulatory),
+        "IMP" | "ACUTE" | "NONAC" => Some(EncounterClass::Inpatient),
+        "EMER" => Some(EncounterClass::Emergency),
+        "HH" => Some(EncounterClass::Home),
+        "FLD" => Some(EncounterClass::Field),
+        "SS" => Some(EncounterClass::Daytime),
+        "VR" => Some(EncounterClass::Virtual),
+        _ => None,
+    }
+}
+
+fn gender(value: &str) -> Option<AdministrativeGender> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "male" => Some(AdministrativeGender::Male),
+        "female" => Some(AdministrativeGender::Female),
+        "other" => Some(AdministrativeGender::Other),
+        "unknown" => Some(AdministrativeGender::Unknown),
+        _ => None,
+    }
+}
+
+impl EncounterCodeRow {
+    fn into_code(self) -> JobResult<EncounterCode> {
+        Ok(EncounterCode {
+            kind: CodeKind::parse(&self.kind).ok_or_else(|| stored_value_error("code kind", &self.kind))?,
+            origin: CodeOrigin::parse(&self.origin).ok_or_else(|| stored_value_error("code origin", &self.origin))?,
+            code: self.code,
+            display: self.display,
+            sequence: self.sequence as u32,
+            units: self.units as u32,
+            modifiers: self.modifiers,
+            diagnosis_pointers: self.diagnosis_pointers.into_iter().map(|pointer| pointer as u32).collect(),
+            suggested_code: self.suggested_code,
+            override_reason: self.override_reason,
+        })
+    }
+}
+
+impl CoverageRow {
+    fn into_coverage(self) -> JobResult<Coverage> {
+        let subscriber = self
+            .subscriber
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+        Ok(Coverage {
+            metadata: EntityMetadata {
+                id: self.id,
+                created_at: self.created_at,
+                updated_at: self.updated_at,
+                version: self.version as u64,
+            },
+            patient_id: self.patient_id,
+            status: CoverageStatus::parse(&self.status)
+                .ok_or_else(|| stored_value_error("coverage status", &self.status))?,
+            priority: self.priority as u32,
+            plan_type: PlanType::parse(&self.plan_type)
+                .ok_or_else(|| stored_value_error("plan type", &self.plan_type))?,
+            payer_name: self.payer_name,
+            payer_id: self.payer_id,
+            member_id: self.member_id,
+            group_number: self.group_number,
+            plan_name: self.plan_name,
+            relationship: SubscriberRelationship::parse(&self.relationship)
+                .ok_or_else(|| stored_value_error("subscriber relationship", &self.relationship))?,
+            subscriber,
+            start: self.start_date,
+            end: self.end_date,
+        })
+    }
+}
+
+impl BillableEncounterRow {
+    fn into_source(self, coding: EncounterCoding, coverages: &[Coverage]) -> JobResult<ClaimSource> {
+        let class = self.class.unwrap_or_default();
+        let address = match (self.address_line1, self.city, self.state, self.postal_code) {
+            (Some(line1), Some(city), Some(state), Some(postal_code)) => Some(PostalAddress {
+                line1,
+                line2: self.address_line2,
+                city,
+                state,
+                postal_code,
+            }),
+            _ => None,
+        };
+        // The primary coverage in force; otherwise the primary one, so the
+        // claim is rejected for the lapse rather than for missing coverage
+        let coverage = coverages
+            .iter()
+            .find(|coverage| coverage.is_in_force(self.service_date))
+            .or_else(|| coverages.first())
+            .cloned();
+        let rendering_provider = self.practitioner_npi.map(|npi| RenderingProvider {
+            npi,
+            family_name: self.practitioner_family_name.unwrap_or_default(),
+            given_name: self.practitioner_given_name,
+        });
+
+        Ok(ClaimSource {
+            encounter_id: self.encounter_id,
+            encounter_class: encounter_class(&class).ok_or_else(|| stored_value_error("encounter class", &class))?,
+            service_date: self.service_date,
+            coding,
+            patient: ClaimPerson {
+                family_name: self.family_name.unwrap_or_default(),
+                given_name: self.given_name,
+                birth_date: self.birth_date,
+                gender: self.gender.as_deref().and_then(gender),
+                address,
+            },
+            coverage,
+            rendering_provider,
+        })
+    }
+}
+
+#[async_trait]
+impl ClaimStore for DatabaseClaimStore {
+    async fn claim_sources(&self, job: &ClaimsExportJob) -> JobResult<Vec<ClaimSource>> {
+        let conn = self.connection().await?;
+        let (from, to, organization_id) = (job.service_from, job.service_to, job.organization_id);
+
+        let (encounters, codes, coverages) = conn
+            .interact(move |conn| {
+                let encounters = diesel::sql_query(BILLABLE_ENCOUNTERS_QUERY)
+                    .bind::<Date, _>(from)
+                    .bind::<Date, _>(to)
+                    .bind::<Nullable<diesel::sql_types::Uuid>, _>(organization_id)
+                    .bind::<Text, _>(NPI_SYSTEM)
+                    .load::<BillableEncounterRow>(conn)?;
+                let encounter_ids: Vec<Uuid> = encounters.iter().map(|row| row.encounter_id).collect();
+                let patient_ids: Vec<Uuid> = encounters.iter().map(|row| row.patient_id).collect();
+                let codes = diesel::sql_query(ENCOUNTER_CODES_QUERY)
+                    .bind::<Array<diesel::sql_types::Uuid>, _>(encounter_ids)
+                    .load::<EncounterCodeRow>(conn)?;
+                let coverages = diesel::sql_query(PATIENT_COVERAGES_QUERY)
+                    .bind::<Array<diesel::sql_types::Uuid>, _>(patient_ids)
+                    .load::<CoverageRow>(conn)?;
+                Ok::<_, diesel::result::Error>((encounters, codes, coverages))
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let mut codings: HashMap<Uuid, EncounterCoding> = HashMap::new();
+        for row in codes {
+            let (encounter_id, patient_id) = (row.encounter_id, row.patient_id);
+            let (coded_by, coded_at) = (row.coded_by, row.coded_at);
+            let code = row.into_code()?;
+            codings
+                .entry(encounter_id)
+                .or_insert_with(|| EncounterCoding {
+                    encounter_id,
+                    patient_id,
+                    codes: Vec::new(),
+                    coded_by,
+                    coded_at,
+                })
+                .codes
+                .push(code);
+        }
+        let mut patient_coverages: HashMap<Uuid, Vec<Coverage>> = HashMap::new();
+        for row in coverages {
+            let coverage = row.into_coverage()?;
+            patient_coverages.entry(coverage.patient_id).or_default().push(coverage);
+        }
+
+        encounters
+            .into_iter()
+            .filter_map(|row| {
+                let coding = codings.remove(&row.encounter_id)?;
+                let coverages = patient_coverages.get(&row.patient_id).map(Vec::as_slice).unwrap_or_default();
+                Some(row.into_source(coding, coverages))
+            })
+            .collect()
+    }
+
+    async fn next_control_number(&self) -> JobResult<u32> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(|conn| {
+                diesel::sql_query("SELECT nextval('emr.claim_control_numbers') AS value").load::<ControlNumberRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        rows.first()
+            .map(|row| row.value as u32)
+            .ok_or_else(|| JobError::DatabaseError("No claim control number returned".to_string()))
+    }
+
+    async fn save_export(&self, export: &ClaimExport) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let rejections =
+            serde_json::to_string(&export.rejections).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let claims = export
+            .claims
+            .iter()
+            .map(|claim| serde_json::to_string(claim).map(|json| (claim.clone(), json)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let export = export.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query(
+                    "INSERT INTO emr.claim_exports \
+                     (id, control_number, service_from, service_to, claim_count, rejected_count, file_content, \
+                     rejections, created_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8::jsonb, $9)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(export.export_id)
+                .bind::<Integer, _>(export.control_number as i32)
+                .bind::<Date, _>(export.service_from)
+                .bind::<Date, _>(export.service_to)
+                .bind::<Integer, _>(export.claims.len() as i32)
+                .bind::<Integer, _>(export.rejections.len() as i32)
+                .bind::<Nullable<Text>, _>(export.file_content.as_deref())
+                .bind::<Text, _>(&rejections)
+                .bind::<Timestamptz, _>(export.created_at)
+                .execute(conn)?;
+
+                for (claim, json) in &claims {
+                    diesel::sql_query(
+                        "INSERT INTO emr.claims \
+                         (claim_id, encounter_id, export_id, payer_id, total_charge_cents, claim, created_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7)",
+                    )
+                    .bind::<Text, _>(&claim.claim_id)
+                    .bind::<diesel::sql_types::Uuid, _>(claim.encounter_id)
+                    .bind::<diesel::sql_types::Uuid, _>(export.export_id)
+                    .bind::<Text, _>(&claim.payer_id)
+                    .bind::<BigInt, _>(claim.total_charge_cents as i64)
+                    .bind::<Text, _>(json)
+                    .bind::<Timestamptz, _>(export.created_at)
+                    .execute(conn)?;
+                }
+                Ok::<_, diesel::result::Error>(())
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Rejections listed in the job result
+const MAX_REPORTED_REJECTIONS: usize = 200;
+
+/// Bills coded encounters as an 837P file
+pub struct ClaimsExportHandler {
+    config: BillingConfig,
+    store: Arc<dyn ClaimStore>,
+}
+
+impl ClaimsExportHandler {
+    /// Create a handler reading billable encounters from a store
+    pub fn new(config: BillingConfig, store: Arc<dyn ClaimStore>) -> Self {
+        Self { config, store }
+    }
+
+    async fn fee_schedule(&self) -> JobResult<FeeSchedule> {
+        let text = tokio::fs::read_to_string(&self.config.fee_schedule_path).await.map_err(|e| {
+            JobError::ConfigurationError(format!("Cannot read fee schedule {}: {}", self.config.fee_schedule_path, e))
+        })?;
+        FeeSchedule::from_csv(&text).map_err(|e| JobError::ConfigurationError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl JobHandler<ClaimsExportJob> for ClaimsExportHandler {
+    async fn execute(&self, job: ClaimsExportJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            export_id = %job.export_id,
+            service_from = %job.service_from,
+            service_to = %job.service_to,
+            "Starting claims export job"
+        );
+
+        if !self.config.enabled {
+            return Err(JobError::ValidationError("Claims export is not enabled".to_string()));
+        }
+        if job.service_to < job.service_from {
+            return Err(JobError::ValidationError("The service period ends before it starts".to_string()));
+        }
+        let provider = self
+            .config
+            .billing_provider
+            .clone()
+            .ok_or_else(|| JobError::ConfigurationError("No billing provider is configured".to_string()))?;
+        let fees = self.fee_schedule().await?;
+
+        let sources = self.store.claim_sources(&job).await?;
+        context.progress.step(1, 3);
+        context.check_cancelled()?;
+
+        let mut claims = Vec::new();
+        let mut rejections = Vec::new();
+        for source in &sources {
+            let payer = source.coverage.as_ref().map(|coverage| coverage.payer_id.as_str());
+            if job.payer_id.is_some() && payer != job.payer_id.as_deref() {
+                continue;
+            }
+            match assemble_claim(source, &fees) {
+                Ok(claim) => claims.push(claim),
+                Err(issues) => rejections.push(ClaimRejection {
+                    encounter_id: source.encounter_id,
+                    issues,
+                }),
+            }
+        }
+
+        let created_at = Utc::now();
+        let (control_number, file_content) = if claims.is_empty() {
+            (0, None)
+        } else {
+            let control_number = self.store.next_control_number().await?;
+            let file = write_837p(&self.config.interchange(), &provider, &claims, control_number, created_at);
+            (control_number, Some(file))
+        };
+        context.progress.step(2, 3);
+        context.check_cancelled()?;
+
+        let export = ClaimExport {
+            export_id: job.export_id,
+            control_number,
+            service_from: job.service_from,
+            service_to: job.service_to,
+            claims,
+            rejections,
+            file_content,
+            created_at,
+        };
+        self.store.save_export(&export).await?;
+        context.progress.step(3, 3);
+
+        let total_charge_cents: u64 = export.claims.iter().map(|claim| claim.total_charge_cents).sum();
+        let data = serde_json::json!({
+            "export_id": export.export_id,
+            "control_number": export.control_number,
+            "claims_count": export.claims.len(),
+            "total_charge_cents": total_charge_cents,
+            "rejected_count": export.rejections.len(),
+            "rejections": export.rejections.iter().take(MAX_REPORTED_REJECTIONS).collect::<Vec<_>>(),
+        });
+
+        Ok(JobExecutionResult::success_with_data(
+            format!(
+                "Exported {} claims for {} to {}; {} encounters rejected",
+                export.claims.len(),
+                export.service_from,
+                export.service_to,
+                export.rejections.len()
+            ),
+            data,
+        )
+        .with_metric("claims_count".to_string(), export.claims.len() as f64)
+        .with_metric("rejected_count".to_string(), export.rejections.len() as f64)
+        .with_metric("total_charge".to_string(), total_charge_cents as f64 / 100.0))
+    }
+
+    fn name(&self) -> &'static str {
+        "claims_export"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::billing::BillingProvider;
+    use std::sync::Mutex;
+
+    /// Claim sources kept in memory, for tests
+    struct MemoryClaimStore {
+        sources: Vec<ClaimSource>,
+        exports: Mutex<Vec<ClaimExport>>,
+    }
+
+    #[async_trait]
+    impl ClaimStore for MemoryClaimStore {
+        async fn claim_sources(&self, _: &ClaimsExportJob) -> JobResult<Vec<ClaimSource>> {
+            Ok(self.sources.clone())
+        }
+
+        async fn next_control_number(&self) -> JobResult<u32> {
+            Ok(7)
+        }
+
+        async fn save_export(&self, export: &ClaimExport) -> JobResult<()> {
+            self.exports.lock().unwrap().push(export.clone());
+            Ok(())
+        }
+    }
+
+    fn source(service_date: NaiveDate, fee_scheduled_code: &str) -> ClaimSource {
+        let patient_id = Uuid::new_v4();
+        let encounter_id = Uuid::new_v4();
+        let codes = vec![
+            EncounterCode::new(CodeKind::Diagnosis, "I10", None),
+            EncounterCode::new(CodeKind::Procedure, fee_scheduled_code, None),
+        ];
+        ClaimSource {
+            encounter_id,
+            encounter_class: EncounterClass::Ambulatory,
+            service_date,
+            coding: EncounterCoding::new(encounter_id, patient_id, codes, Uuid::new_v4(), Utc::now()).unwrap(),
+            patient: ClaimPerson {
+                family_name: "Smith".to_string(),
+                given_name: Some("John".to_string()),
+                birth_date: NaiveDate::from_ymd_opt(1970, 5, 6),
+                gender: Some(AdministrativeGender::Male),
+                address: Some(address()),
+            },
+            coverage: Some(Coverage::new(patient_id, PlanType::Medicare, "Medicare Part B", "00882", "1EG4TE5MK73")),
+            rendering_provider: None,
+        }
+    }
+
+    fn address() -> PostalAddress {
+        PostalAddress {
+            line1: "1 Main St".to_string(),
+            line2: None,
+            city: "Springfield".to_string(),
+            state: "IL".to_string(),
+            postal_code: "62701".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claims_export_reports_rejections() {
+        let fee_schedule = std::env::temp_dir().join(format!("fees-{}.csv", Uuid::new_v4()));
+        std::fs::write(&fee_schedule, "code,charge\n99213,100.00\n").unwrap();
+        let config = BillingConfig {
+            enabled: true,
+            fee_schedule_path: fee_schedule.display().to_string(),
+            billing_provider: Some(BillingProvider {
+                name: "Springfield Clinic".to_string(),
+                npi: "1234567893".to_string(),
+                tax_id: "123456789".to_string(),
+                taxonomy: None,
+                address: address(),
+            }),
+            ..BillingConfig::default()
+        };
+        let date = NaiveDate::from_ymd_opt(2026, 3, 4).unwrap();
+        let store = Arc::new(MemoryClaimStore {
+            sources: vec![source(date, "99213"), source(date, "99214")],
+            exports: Mutex::new(Vec::new()),
+        });
+        let handler = ClaimsExportHandler::new(config, store.clone());
+        let job = ClaimsExportJob {
+            export_id: Uuid::new_v4(),
+            service_from: date,
+            service_to: date,
+            organization_id: None,
+            payer_id: None,
+        };
+
+        let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+        std::fs::remove_file(&fee_schedule).unwrap();
+        let data = result.data.unwrap();
+        assert_eq!(data["claims_count"], 1);
+        assert_eq!(data["rejected_count"], 1);
+        assert_eq!(data["rejections"][0]["issues"][0]["field"], "lines[0].charge");
+
+        let exports = store.exports.lock().unwrap();
+        let file = exports[0].file_content.as_deref().unwrap();
+        assert!(file.contains("SBR*P*18*******MB~"));
+        assert!(file.contains("CLM*"));
+    }
+}
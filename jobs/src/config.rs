@@ -1,16 +1,63 @@
 //! Configuration for the background job processing system
 
-use config::{Config, ConfigError, Environment, File};
+use config::{Config, ConfigError, File, FileFormat, FileSourceFile};
+use emr_core::billing::{BillingProvider, InterchangeSettings};
+use emr_core::domain::practitioner::is_valid_npi;
+use emr_core::domain::DEFAULT_CRITICAL_ACK_SLA_MINUTES;
+use emr_core::diagnostics::{endpoint, is_production, unknown_keys, ConfigReport, Severity};
+use emr_core::retention::{RetentionConfig, RetentionPolicySet};
+use emr_core::services::device_observations::IngestLimits;
+use emr_core::services::security_events::AnomalyRules;
+use emr_core::secrets::SecretResolver;
+use crate::backup;
+use crate::key_rotation;
+use crate::types::JobQueue;
+use crate::{JobError, JobResult};
+use deadpool_diesel::postgres::{Manager, Pool};
+use deadpool_diesel::Runtime;
+use emr_proto::GrpcConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
+use std::time::Duration;
+use tokio::net::TcpStream;
 
 /// Jobs configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct JobsConfig {
     pub database: DatabaseConfig,
     pub redis: RedisConfig,
     pub worker: WorkerConfig,
     pub monitoring: MonitoringConfig,
+    pub nats: NatsConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub ingestion: IngestionConfig,
+    #[serde(default)]
+    pub acknowledgments: AcknowledgmentConfig,
+    #[serde(default)]
+    pub billing: BillingConfig,
+    #[serde(default)]
+    pub ocr: OcrConfig,
+    #[serde(default)]
+    pub panels: PanelConfig,
+    #[serde(default)]
+    pub partitions: PartitionConfig,
+    #[serde(default)]
+    pub reports: ReportConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    /// Batches of device observations published over NATS
+    #[serde(default)]
+    pub device_ingest: IngestLimits,
+    /// Internal job API for the other services
+    #[serde(default)]
+    pub grpc: GrpcConfig,
 }
 
 /// Database configuration
@@ -33,11 +80,41 @@ pub struct RedisConfig {
 /// Worker configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkerConfig {
+    /// Workers for the default queue
     pub max_workers: u32,
     pub max_retries: u32,
     pub retry_delay: u64,
     pub job_timeout: u64,
     pub poll_interval: u64,
+    #[serde(default)]
+    pub queues: QueueWorkersConfig,
+    /// Seconds an idempotency key blocks duplicate submissions
+    #[serde(default = "default_idempotency_ttl")]
+    pub idempotency_ttl: u64,
+}
+
+fn default_idempotency_ttl() -> u64 {
+    24 * 3600
+}
+
+/// Worker pool sizes for the dedicated queues
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueWorkersConfig {
+    /// Notifications and subscription deliveries
+    pub latency_sensitive: u32,
+    /// Exports, imports, backups and reports
+    pub long_running: u32,
+}
+
+impl WorkerConfig {
+    /// Concurrent jobs allowed on a queue
+    pub fn workers_for(&self, queue: JobQueue) -> u32 {
+        match queue {
+            JobQueue::LatencySensitive => self.queues.latency_sensitive,
+            JobQueue::Default => self.max_workers,
+            JobQueue::LongRunning => self.queues.long_running,
+        }
+    }
 }
 
 /// Monitoring configuration
@@ -48,17 +125,185 @@ pub struct MonitoringConfig {
     pub health_check_interval: u64,
 }
 
-impl Default for JobsConfig {
-    fn default() -> Self {
-        Self {
-            database: DatabaseConfig::default(),
-            redis: RedisConfig::default(),
-            worker: WorkerConfig::default(),
-            monitoring: MonitoringConfig::default(),
+/// NATS configuration used to publish job events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatsConfig {
+    pub enabled: bool,
+    pub url: String,
+}
+
+/// Backup target and encryption configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    /// S3-compatible endpoint, e.g. `https://s3.us-west-2.amazonaws.com`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Key prefix for backup objects
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Base64-encoded 256-bit key; never stored alongside the backups
+    pub encryption_key: String,
+    pub pg_dump_path: String,
+    pub psql_path: String,
+}
+
+/// Batch file ingestion from SFTP and local directories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestionConfig {
+    pub enabled: bool,
+    /// Seconds between inbox scans
+    pub poll_interval: u64,
+    /// Files modified more recently than this many seconds are assumed to
+    /// still be uploading
+    pub settle_secs: u64,
+    #[serde(default)]
+    pub sources: Vec<IngestionSourceConfig>,
+}
+
+/// Escalation of unacknowledged critical results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcknowledgmentConfig {
+    /// Minutes a critical result may wait for acknowledgment before it is
+    /// escalated, and between repeated escalations
+    pub critical_sla_minutes: i64,
+    /// Also notified of every escalation, e.g. the department lead
+    #[serde(default)]
+    pub escalation_recipient_id: Option<uuid::Uuid>,
+}
+
+/// Professional claims submission to the clearinghouse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingConfig {
+    pub enabled: bool,
+    /// Interchange sender id assigned by the clearinghouse
+    pub sender_id: String,
+    /// Clearinghouse interchange receiver id
+    pub receiver_id: String,
+    pub receiver_name: String,
+    pub submitter_name: String,
+    /// Submitter id assigned by the clearinghouse
+    pub submitter_id: String,
+    pub contact_name: String,
+    pub contact_phone: String,
+    /// Send production (`P`) rather than test (`T`) files
+    pub production: bool,
+    /// The practice billing the claims
+    #[serde(default)]
+    pub billing_provider: Option<BillingProvider>,
+    /// CSV of `code,charge` lines, the charge in dollars per unit
+    pub fee_schedule_path: String,
+}
+
+impl BillingConfig {
+    /// Interchange envelope settings; ids are mutually defined (`ZZ`)
+    pub fn interchange(&self) -> InterchangeSettings {
+        InterchangeSettings {
+            sender_qualifier: "ZZ".to_string(),
+            sender_id: self.sender_id.clone(),
+            receiver_qualifier: "ZZ".to_string(),
+            receiver_id: self.receiver_id.clone(),
+            submitter_name: self.submitter_name.clone(),
+            submitter_id: self.submitter_id.clone(),
+            contact_name: self.contact_name.clone(),
+            contact_phone: self.contact_phone.clone(),
+            receiver_name: self.receiver_name.clone(),
+            production: self.production,
         }
     }
 }
 
+/// Text extraction from uploaded documents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrConfig {
+    pub enabled: bool,
+    /// Directory the API stores uploaded document content in
+    /// (`uploads.document_dir`)
+    pub document_dir: String,
+    /// `tika` or `tesseract`
+    pub backend: String,
+    /// Apache Tika server, e.g. `http://localhost:9998`
+    pub tika_url: String,
+    pub tesseract_path: String,
+    /// Poppler's `pdftotext`, for PDFs with a text layer
+    pub pdftotext_path: String,
+    /// Poppler's `pdftoppm`, to render scanned PDF pages for Tesseract
+    pub pdftoppm_path: String,
+    /// Tesseract language, e.g. `eng` or `eng+spa`
+    pub language: String,
+    /// Seconds allowed for extracting one document
+    pub timeout: u64,
+    /// Characters of text kept per document
+    pub max_text_chars: usize,
+}
+
+/// Nightly refresh of saved patient panels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanelConfig {
+    /// Hour of the day (UTC) from which nightly panels are refreshed
+    pub nightly_hour: u32,
+}
+
+/// Monthly partitions of time-series tables
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionConfig {
+    /// Months after the current one that get a partition ahead of time
+    pub months_ahead: u32,
+    /// Hour of the day (UTC) from which the daily partition maintenance is
+    /// queued
+    pub maintenance_hour: u32,
+}
+
+/// Scheduled reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportConfig {
+    /// Directory generated reports are stored in, which the API serves them
+    /// from (`uploads.report_dir`)
+    pub output_dir: String,
+}
+
+/// Master keys wrapping the data-encryption keys of field-level encryption
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Base64-encoded 256-bit keys by id; a key stays configured until a
+    /// KeyRotation job has moved every data key off it
+    #[serde(default)]
+    pub master_keys: BTreeMap<String, String>,
+}
+
+/// Detection of suspicious sign-ins and record access
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    /// Security admins alerted of every security event
+    #[serde(default)]
+    pub admin_recipient_ids: Vec<uuid::Uuid>,
+    #[serde(default)]
+    pub rules: AnomalyRules,
+}
+
+/// A watched inbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestionSourceConfig {
+    /// Unique name, recorded in import provenance
+    pub name: String,
+    /// `/var/emr/inbox/acme-lab` or `sftp://user@host:22/outbound`
+    pub location: String,
+    /// System producing the files, e.g. `acme-lis`
+    pub source_system: String,
+    /// SFTP password; ignored when a private key is set
+    #[serde(default)]
+    pub password: Option<String>,
+    /// SFTP private key file
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    #[serde(default)]
+    pub mapping_config: Option<String>,
+    #[serde(default)]
+    pub auto_merge: bool,
+}
+
 impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
@@ -70,6 +315,18 @@ impl Default for DatabaseConfig {
     }
 }
 
+impl DatabaseConfig {
+    /// Small pool for the worker's own bookkeeping (dead letters, run
+    /// history). Connections are opened on first use.
+    pub fn pool(&self) -> JobResult<Pool> {
+        let manager = Manager::new(self.url.as_str(), Runtime::Tokio1);
+        Pool::builder(manager)
+            .max_size(self.min_connections.max(1) as usize)
+            .build()
+            .map_err(|e| JobError::ConfigurationError(e.to_string()))
+    }
+}
+
 impl Default for RedisConfig {
     fn default() -> Self {
         Self {
@@ -88,6 +345,17 @@ impl Default for WorkerConfig {
             retry_delay: 30,
             job_timeout: 300,
             poll_interval: 5,
+            queues: QueueWorkersConfig::default(),
+            idempotency_ttl: default_idempotency_ttl(),
+        }
+    }
+}
+
+impl Default for QueueWorkersConfig {
+    fn default() -> Self {
+        Self {
+            latency_sensitive: 4,
+            long_running: 1,
         }
     }
 }
@@ -102,24 +370,163 @@ impl Default for MonitoringConfig {
     }
 }
 
+impl Default for NatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "nats://localhost:4222".to_string(),
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:9000".to_string(),
+            region: "us-west-2".to_string(),
+            bucket: "emr-backups".to_string(),
+            prefix: "backups".to_string(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            encryption_key: String::new(),
+            pg_dump_path: "pg_dump".to_string(),
+            psql_path: "psql".to_string(),
+        }
+    }
+}
+
+impl Default for IngestionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval: 60,
+            settle_secs: 30,
+            sources: Vec::new(),
+        }
+    }
+}
+
+impl Default for AcknowledgmentConfig {
+    fn default() -> Self {
+        Self {
+            critical_sla_minutes: DEFAULT_CRITICAL_ACK_SLA_MINUTES,
+            escalation_recipient_id: None,
+        }
+    }
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sender_id: String::new(),
+            receiver_id: String::new(),
+            receiver_name: String::new(),
+            submitter_name: String::new(),
+            submitter_id: String::new(),
+            contact_name: String::new(),
+            contact_phone: String::new(),
+            production: false,
+            billing_provider: None,
+            fee_schedule_path: "fee_schedule.csv".to_string(),
+        }
+    }
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            document_dir: "data/documents".to_string(),
+            backend: "tesseract".to_string(),
+            tika_url: "http://localhost:9998".to_string(),
+            tesseract_path: "tesseract".to_string(),
+            pdftotext_path: "pdftotext".to_string(),
+            pdftoppm_path: "pdftoppm".to_string(),
+            language: "eng".to_string(),
+            timeout: 300,
+            max_text_chars: 1_000_000,
+        }
+    }
+}
+
+impl Default for PanelConfig {
+    fn default() -> Self {
+        Self { nightly_hour: 2 }
+    }
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        Self {
+            months_ahead: 3,
+            maintenance_hour: 3,
+        }
+    }
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            output_dir: "data/reports".to_string(),
+        }
+    }
+}
+
+/// Top-level configuration sections, as named in `JOBS_<SECTION>_<KEY>`
+const SECTIONS: &[&str] = &[
+    "database",
+    "redis",
+    "worker",
+    "monitoring",
+    "nats",
+    "retention",
+    "backup",
+    "ingestion",
+    "acknowledgments",
+    "billing",
+    "ocr",
+    "panels",
+    "partitions",
+    "reports",
+    "encryption",
+    "security",
+    "device_ingest",
+    "grpc",
+];
+
 impl JobsConfig {
+    /// The `section.key` a `JOBS_<SECTION>_<KEY>` variable sets. Section and
+    /// key names contain underscores themselves, so the section is matched by
+    /// name: `JOBS_WORKER_MAX_WORKERS` sets `worker.max_workers`.
+    fn env_key(variable: &str) -> Option<String> {
+        let name = variable.strip_prefix("JOBS_")?.to_lowercase();
+        SECTIONS.iter().find_map(|section| {
+            let key = name.strip_prefix(section)?.strip_prefix('_')?;
+            (!key.is_empty()).then(|| format!("{}.{}", section, key))
+        })
+    }
+
+    /// The configuration file, `JOBS_CONFIG_PATH` or `jobs.toml`
+    fn file() -> File<FileSourceFile, FileFormat> {
+        let path = env::var("JOBS_CONFIG_PATH").unwrap_or_else(|_| "jobs.toml".to_string());
+        File::with_name(&path).required(false)
+    }
+
     /// Load configuration from environment variables and config files
     pub fn load() -> Result<Self, ConfigError> {
         let mut config = Config::builder();
 
         // Load from default config file if it exists
-        if let Ok(config_path) = env::var("JOBS_CONFIG_PATH") {
-            config = config.add_source(File::with_name(&config_path).required(false));
-        } else {
-            config = config.add_source(File::with_name("jobs.toml").required(false));
-        }
+        config = config.add_source(Self::file());
 
         // Load from environment variables
-        config = config.add_source(
-            Environment::with_prefix("JOBS")
-                .separator("_")
-                .prefix_separator("_")
-        );
+        for (variable, value) in env::vars() {
+            if let Some(key) = Self::env_key(&variable) {
+                config = config.set_override(key, value)?;
+            }
+        }
 
         // Set defaults
         config = config
@@ -135,35 +542,298 @@ impl JobsConfig {
             .set_default("worker.retry_delay", 30)?
             .set_default("worker.job_timeout", 300)?
             .set_default("worker.poll_interval", 5)?
+            .set_default("worker.queues.latency_sensitive", 4)?
+            .set_default("worker.queues.long_running", 1)?
+            .set_default("worker.idempotency_ttl", 86400)?
             .set_default("monitoring.enabled", true)?
             .set_default("monitoring.metrics_port", 9090)?
-            .set_default("monitoring.health_check_interval", 30)?;
+            .set_default("monitoring.health_check_interval", 30)?
+            .set_default("nats.enabled", false)?
+            .set_default("nats.url", "nats://localhost:4222")?
+            .set_default("backup.enabled", false)?
+            .set_default("backup.endpoint", "http://localhost:9000")?
+            .set_default("backup.region", "us-west-2")?
+            .set_default("backup.bucket", "emr-backups")?
+            .set_default("backup.prefix", "backups")?
+            .set_default("backup.access_key", "")?
+            .set_default("backup.secret_key", "")?
+            .set_default("backup.encryption_key", "")?
+            .set_default("backup.pg_dump_path", "pg_dump")?
+            .set_default("backup.psql_path", "psql")?
+            .set_default("ingestion.enabled", false)?
+            .set_default("ingestion.poll_interval", 60)?
+            .set_default("ingestion.settle_secs", 30)?
+            .set_default("acknowledgments.critical_sla_minutes", DEFAULT_CRITICAL_ACK_SLA_MINUTES)?
+            .set_default("billing.enabled", false)?
+            .set_default("billing.sender_id", "")?
+            .set_default("billing.receiver_id", "")?
+            .set_default("billing.receiver_name", "")?
+            .set_default("billing.submitter_name", "")?
+            .set_default("billing.submitter_id", "")?
+            .set_default("billing.contact_name", "")?
+            .set_default("billing.contact_phone", "")?
+            .set_default("billing.production", false)?
+            .set_default("billing.fee_schedule_path", "fee_schedule.csv")?
+            .set_default("ocr.enabled", false)?
+            .set_default("ocr.document_dir", "data/documents")?
+            .set_default("ocr.backend", "tesseract")?
+            .set_default("ocr.tika_url", "http://localhost:9998")?
+            .set_default("ocr.tesseract_path", "tesseract")?
+            .set_default("ocr.pdftotext_path", "pdftotext")?
+            .set_default("ocr.pdftoppm_path", "pdftoppm")?
+            .set_default("ocr.language", "eng")?
+            .set_default("ocr.timeout", 300)?
+            .set_default("ocr.max_text_chars", 1_000_000)?
+            .set_default("panels.nightly_hour", 2)?
+            .set_default("partitions.months_ahead", 3)?
+            .set_default("partitions.maintenance_hour", 3)?
+            .set_default("reports.output_dir", "data/reports")?;
 
         config.build()?.try_deserialize()
     }
 
-    /// Validate configuration
-    pub fn validate(&self) -> Result<(), String> {
+    /// Replace secret references (`env:`, `file:` and those of any other
+    /// registered backend) in the database and Redis URLs, the backup
+    /// credentials and encryption key and the master keys with the secrets
+    /// they name
+    pub async fn resolve_secrets(&mut self, resolver: &SecretResolver) -> emr_core::Result<()> {
+        resolver.resolve_in_place(&mut self.database.url).await?;
+        resolver.resolve_in_place(&mut self.redis.url).await?;
+        resolver.resolve_in_place(&mut self.backup.access_key).await?;
+        resolver.resolve_in_place(&mut self.backup.secret_key).await?;
+        resolver.resolve_in_place(&mut self.backup.encryption_key).await?;
+        for key in self.encryption.master_keys.values_mut() {
+            resolver.resolve_in_place(key).await?;
+        }
+        Ok(())
+    }
+
+    /// Validate configuration, reporting every problem found
+    pub fn validate(&self) -> Result<(), ConfigReport> {
+        self.check(is_production()).into_result()
+    }
+
+    /// Every problem with the settings' values; secrets are only judged in
+    /// production
+    pub fn check(&self, production: bool) -> ConfigReport {
+        let mut report = ConfigReport::new();
+
         if self.database.url.is_empty() {
-            return Err("Database URL cannot be empty".to_string());
+            report.error("database.url", "Database URL cannot be empty");
         }
 
         if self.redis.url.is_empty() {
-            return Err("Redis URL cannot be empty".to_string());
+            report.error("redis.url", "Redis URL cannot be empty");
         }
 
         if self.worker.max_workers == 0 {
-            return Err("Max workers must be greater than 0".to_string());
+            report.error("worker.max_workers", "Max workers must be greater than 0");
+        }
+
+        for queue in JobQueue::ALL.into_iter().filter(|queue| self.worker.workers_for(*queue) == 0) {
+            report.error(
+                "worker.queues",
+                format!("Workers for the {} queue must be greater than 0", queue.as_str()),
+            );
         }
 
         if self.worker.job_timeout == 0 {
-            return Err("Job timeout must be greater than 0".to_string());
+            report.error("worker.job_timeout", "Job timeout must be greater than 0");
         }
 
         if self.monitoring.enabled && self.monitoring.metrics_port == 0 {
-            return Err("Metrics port must be greater than 0 when monitoring is enabled".to_string());
+            report.error("monitoring.metrics_port", "Metrics port must be greater than 0 when monitoring is enabled");
+        }
+
+        if self.nats.enabled && self.nats.url.is_empty() {
+            report.error("nats.url", "NATS URL cannot be empty when NATS is enabled");
+        }
+
+        report.check("retention", RetentionPolicySet::from_config(&self.retention).map(|_| ()));
+
+        if self.backup.enabled && (self.backup.bucket.is_empty() || self.backup.encryption_key.is_empty()) {
+            report.error("backup", "Backup bucket and encryption key are required when backups are enabled");
+        }
+
+        if self.ingestion.enabled {
+            report.check("ingestion", self.validate_ingestion());
+        }
+
+        if self.acknowledgments.critical_sla_minutes <= 0 {
+            report.error(
+                "acknowledgments.critical_sla_minutes",
+                "Critical result acknowledgment SLA must be greater than 0",
+            );
+        }
+
+        if self.billing.enabled {
+            report.check("billing", self.validate_billing());
         }
 
+        if self.ocr.enabled {
+            report.check("ocr", self.validate_ocr());
+        }
+
+        if self.panels.nightly_hour > 23 {
+            report.error("panels.nightly_hour", "Nightly panel refresh hour must be between 0 and 23");
+        }
+
+        if self.partitions.months_ahead == 0 || self.partitions.months_ahead > 24 {
+            report.error("partitions.months_ahead", "Partitions must be created 1 to 24 months ahead");
+        }
+
+        if self.partitions.maintenance_hour > 23 {
+            report.error("partitions.maintenance_hour", "Partition maintenance hour must be between 0 and 23");
+        }
+
+        if self.reports.output_dir.is_empty() {
+            report.error("reports.output_dir", "Report output directory cannot be empty");
+        }
+
+        if self.backup.enabled && !self.backup.encryption_key.is_empty() {
+            report.check("backup.encryption_key", backup::check_key(&self.backup.encryption_key));
+        }
+
+        for (id, key) in &self.encryption.master_keys {
+            report.check(&format!("encryption.master_keys.{}", id), key_rotation::check_master_key(key));
+        }
+
+        report.check("security.rules", self.validate_security());
+        if production && self.security.admin_recipient_ids.is_empty() {
+            report.warning("security.admin_recipient_ids", "Security events are recorded but nobody is alerted");
+        }
+
+        report.check("device_ingest", self.device_ingest.check());
+
+        if production && self.redis.url.starts_with("redis://") && !self.redis.url.contains('@') {
+            report.warning("redis.url", "Redis in production should require a password");
+        }
+
+        report.extend(self.grpc.check("grpc", production));
+
+        report
+    }
+
+    /// Warn about settings in the configuration file the worker ignores
+    pub fn check_keys(&self) -> Result<ConfigReport, ConfigError> {
+        let values: serde_json::Value = Config::builder().add_source(Self::file()).build()?.try_deserialize()?;
+        let known = serde_json::to_value(self).unwrap_or_default();
+
+        let mut report = ConfigReport::new();
+        for (key, suggestion) in unknown_keys(&known, &values) {
+            match suggestion {
+                Some(suggestion) => report.warning(&key, format!("Unknown setting; did you mean {}?", suggestion)),
+                None => report.warning(&key, "Unknown setting; it is ignored"),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Check that the services the configuration names accept connections
+    pub async fn probe(&self, timeout: Duration) -> ConfigReport {
+        let mut targets = vec![
+            ("database.url", self.database.url.as_str(), Severity::Error),
+            ("redis.url", self.redis.url.as_str(), Severity::Error),
+        ];
+        if self.nats.enabled {
+            targets.push(("nats.url", self.nats.url.as_str(), Severity::Warning));
+        }
+        if self.backup.enabled {
+            targets.push(("backup.endpoint", self.backup.endpoint.as_str(), Severity::Error));
+        }
+        if self.ocr.enabled && self.ocr.backend == "tika" {
+            targets.push(("ocr.tika_url", self.ocr.tika_url.as_str(), Severity::Error));
+        }
+
+        let mut report = ConfigReport::new();
+        for (key, address, severity) in targets {
+            let Some((host, port)) = endpoint(address) else {
+                report.error(key, "Not a URL or host:port address");
+                continue;
+            };
+            let message = match tokio::time::timeout(timeout, TcpStream::connect((host.as_str(), port))).await {
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => format!("Cannot connect to {}:{}: {}", host, port, e),
+                Err(_) => format!("No answer from {}:{} within {}s", host, port, timeout.as_secs()),
+            };
+            match severity {
+                Severity::Error => report.error(key, message),
+                Severity::Warning => report.warning(key, message),
+            }
+        }
+        report
+    }
+
+    fn validate_billing(&self) -> Result<(), String> {
+        let billing = &self.billing;
+        if [&billing.sender_id, &billing.receiver_id, &billing.submitter_id].iter().any(|id| id.is_empty()) {
+            return Err("Claims export needs the sender, receiver and submitter ids".to_string());
+        }
+        if billing.sender_id.len() > 15 || billing.receiver_id.len() > 15 {
+            return Err("Interchange sender and receiver ids are at most 15 characters".to_string());
+        }
+        let Some(provider) = &billing.billing_provider else {
+            return Err("Claims export needs a billing provider".to_string());
+        };
+        if !is_valid_npi(&provider.npi) {
+            return Err(format!("Billing provider NPI {} is not valid", provider.npi));
+        }
+        if billing.fee_schedule_path.is_empty() {
+            return Err("Claims export needs a fee schedule".to_string());
+        }
+        Ok(())
+    }
+
+    fn validate_ocr(&self) -> Result<(), String> {
+        let ocr = &self.ocr;
+        if ocr.document_dir.is_empty() {
+            return Err("OCR needs the document directory".to_string());
+        }
+        match ocr.backend.as_str() {
+            "tika" if ocr.tika_url.is_empty() => Err("OCR with Tika needs the Tika server URL".to_string()),
+            "tika" | "tesseract" => Ok(()),
+            other => Err(format!("Unknown OCR backend '{}'; expected tika or tesseract", other)),
+        }?;
+        if ocr.timeout == 0 || ocr.max_text_chars == 0 {
+            return Err("OCR timeout and text limit must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    fn validate_security(&self) -> Result<(), String> {
+        let rules = &self.security.rules;
+        if rules.max_travel_kmh.is_nan() || rules.max_travel_kmh <= 0.0 {
+            return Err("Maximum travel speed must be greater than 0".to_string());
+        }
+        if rules.failure_threshold == 0 || rules.mass_access_threshold == 0 {
+            return Err("Failure and record access thresholds must be greater than 0".to_string());
+        }
+        if rules.failure_window_minutes <= 0 || rules.mass_access_window_minutes <= 0 {
+            return Err("Failure and record access windows must be greater than 0 minutes".to_string());
+        }
+        Ok(())
+    }
+
+    fn validate_ingestion(&self) -> Result<(), String> {
+        if self.ingestion.poll_interval == 0 {
+            return Err("Ingestion poll interval must be greater than 0".to_string());
+        }
+
+        let mut names = std::collections::HashSet::new();
+        for source in &self.ingestion.sources {
+            if source.name.is_empty() || source.location.is_empty() || source.source_system.is_empty() {
+                return Err("Ingestion sources need a name, location and source system".to_string());
+            }
+            if !names.insert(source.name.as_str()) {
+                return Err(format!("Duplicate ingestion source '{}'", source.name));
+            }
+            if source.location.starts_with("sftp://")
+                && source.password.is_none()
+                && source.private_key_path.is_none()
+            {
+                return Err(format!("Ingestion source '{}' needs an SFTP password or private key", source.name));
+            }
+        }
         Ok(())
     }
 }
@@ -191,6 +861,113 @@ mod tests {
         config.database.url = "postgresql://localhost/test".to_string();
         config.worker.max_workers = 0;
         assert!(config.validate().is_err());
+
+        // Test an empty dedicated queue
+        config.worker.max_workers = 4;
+        config.worker.queues.long_running = 0;
+        assert!(config.validate().is_err());
+
+        // Test ambiguous retention policies
+        config.worker.queues.long_running = 1;
+        config.retention.policies.push(config.retention.policies[0].clone());
+        assert!(config.validate().is_err());
+
+        // Test backups without an encryption key
+        config.retention = RetentionConfig::default();
+        config.backup.enabled = true;
+        assert!(config.validate().is_err());
+
+        // Test a master key that is not 256 bits
+        config.backup = BackupConfig::default();
+        config.encryption.master_keys.insert("2026-10".to_string(), "c2hvcnQ=".to_string());
+        assert!(config.validate().is_err());
+
+        // Test SFTP ingestion without credentials
+        config.encryption = EncryptionConfig::default();
+        config.ingestion.enabled = true;
+        config.ingestion.sources.push(IngestionSourceConfig {
+            name: "acme-lab".to_string(),
+            location: "sftp://emr@sftp.acme-lab.test/outbound".to_string(),
+            source_system: "acme-lis".to_string(),
+            password: None,
+            private_key_path: None,
+            mapping_config: None,
+            auto_merge: false,
+        });
+        assert!(config.validate().is_err());
+
+        config.ingestion.sources[0].private_key_path = Some("/etc/emr/acme-lab.key".to_string());
+        assert!(config.validate().is_ok());
+
+        // Test duplicate source names
+        config.ingestion.sources.push(config.ingestion.sources[0].clone());
+        assert!(config.validate().is_err());
+
+        // Test a zero failure threshold
+        config.ingestion = IngestionConfig::default();
+        config.security.rules.failure_threshold = 0;
+        assert!(config.validate().is_err());
+
+        // Test a zero acknowledgment SLA
+        config.security = SecurityConfig::default();
+        config.acknowledgments.critical_sla_minutes = 0;
+        assert!(config.validate().is_err());
+
+        // Test claims export without a billing provider
+        config.acknowledgments = AcknowledgmentConfig::default();
+        config.billing.enabled = true;
+        config.billing.sender_id = "EMRSENDER".to_string();
+        config.billing.receiver_id = "CLEARINGHOUSE".to_string();
+        config.billing.submitter_id = "S12345".to_string();
+        assert!(config.validate().is_err());
+
+        // Test an unknown OCR backend
+        config.billing = BillingConfig::default();
+        config.ocr.enabled = true;
+        config.ocr.backend = "cloud".to_string();
+        assert!(config.validate().is_err());
+        config.ocr.backend = "tika".to_string();
+        assert!(config.validate().is_ok());
+
+        // Test a nightly panel refresh hour past midnight
+        config.panels.nightly_hour = 24;
+        assert!(config.validate().is_err());
+
+        // Test a partition horizon beyond two years
+        config.panels = PanelConfig::default();
+        config.partitions.months_ahead = 25;
+        assert!(config.validate().is_err());
+
+        // Test scheduled reports without an output directory
+        config.partitions = PartitionConfig::default();
+        config.reports.output_dir = String::new();
+        assert!(config.validate().is_err());
+
+        // Test device ingestion that admits no batches
+        config.reports = ReportConfig::default();
+        config.device_ingest.max_concurrent = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let mut config = JobsConfig::default();
+        config.database.url = String::new();
+        config.worker.job_timeout = 0;
+        config.panels.nightly_hour = 24;
+
+        let report = config.validate().unwrap_err();
+        let keys: Vec<&str> = report.issues.iter().map(|issue| issue.key.as_str()).collect();
+        assert_eq!(keys, vec!["database.url", "worker.job_timeout", "panels.nightly_hour"]);
+    }
+
+    #[test]
+    fn test_env_key() {
+        assert_eq!(JobsConfig::env_key("JOBS_WORKER_MAX_WORKERS").as_deref(), Some("worker.max_workers"));
+        assert_eq!(JobsConfig::env_key("JOBS_DEVICE_INGEST_MAX_BATCH").as_deref(), Some("device_ingest.max_batch"));
+        assert_eq!(JobsConfig::env_key("JOBS_CONFIG_PATH"), None);
+        assert_eq!(JobsConfig::env_key("JOBS_WORKER"), None);
+        assert_eq!(JobsConfig::env_key("API_DATABASE_URL"), None);
     }
 
     #[test]
@@ -0,0 +1,132 @@
+//! Dead-letter store for jobs that failed for good
+//!
+//! A job is dead-lettered once it has used all of its attempts, or right
+//! away when its error is not retryable. Entries keep the job payload and
+//! the last error so an administrator can inspect them and replay the job
+//! after a fix. Replays reuse the job id, so a replay that fails again
+//! updates the same entry.
+
+use crate::types::{JobQueue, JobType};
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Integer, Text, Timestamptz};
+use diesel::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Upsert for a dead-lettered job
+pub const INSERT_DEAD_LETTER_QUERY: &str = r#"
+INSERT INTO jobs.dead_letters (job_id, job_type, queue, payload, attempts, last_error, failed_at)
+VALUES ($1, $2, $3, $4::jsonb, $5, $6, $7)
+ON CONFLICT (job_id) DO UPDATE SET
+    payload = EXCLUDED.payload,
+    attempts = EXCLUDED.attempts,
+    last_error = EXCLUDED.last_error,
+    failed_at = EXCLUDED.failed_at
+"#;
+
+/// A job that will not be retried automatically
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub job_id: Uuid,
+    pub job_type: String,
+    pub queue: JobQueue,
+    /// The job as submitted, including its `type` tag
+    pub payload: serde_json::Value,
+    pub attempts: u32,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DeadLetter {
+    /// Dead letter for a job that failed with `error` on attempt `attempts`
+    pub fn new(job_id: Uuid, job: &JobType, attempts: u32, error: &JobError) -> JobResult<Self> {
+        let payload = serde_json::to_value(job).map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        Ok(Self {
+            job_id,
+            job_type: job.name().to_string(),
+            queue: job.queue(),
+            payload,
+            attempts,
+            last_error: error.to_string(),
+            failed_at: Utc::now(),
+        })
+    }
+}
+
+/// Where dead-lettered jobs are kept
+#[async_trait]
+pub trait DeadLetterStore: Send + Sync {
+    /// Record a dead-lettered job, replacing an earlier entry for the same id
+    async fn record(&self, letter: &DeadLetter) -> JobResult<()>;
+}
+
+/// Dead letters in the `jobs.dead_letters` table
+pub struct DatabaseDeadLetterStore {
+    pool: Pool,
+}
+
+impl DatabaseDeadLetterStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl DeadLetterStore for DatabaseDeadLetterStore {
+    async fn record(&self, letter: &DeadLetter) -> JobResult<()> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let letter = letter.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_DEAD_LETTER_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(letter.job_id)
+                .bind::<Text, _>(&letter.job_type)
+                .bind::<Text, _>(letter.queue.as_str())
+                .bind::<Text, _>(letter.payload.to_string())
+                .bind::<Integer, _>(letter.attempts as i32)
+                .bind::<Text, _>(&letter.last_error)
+                .bind::<Timestamptz, _>(letter.failed_at)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn test_dead_letter_keeps_payload() {
+        let job = JobType::Backup(BackupJob {
+            backup_id: Uuid::new_v4(),
+            schemas: vec!["emr".to_string()],
+            label: Some("nightly".to_string()),
+        });
+        let error = JobError::ExternalServiceError("S3 unavailable".to_string());
+
+        let letter = DeadLetter::new(Uuid::new_v4(), &job, 4, &error).unwrap();
+        assert_eq!(letter.job_type, "Backup");
+        assert_eq!(letter.queue, JobQueue::LongRunning);
+        assert_eq!(letter.attempts, 4);
+        assert_eq!(letter.last_error, "External service error: S3 unavailable");
+
+        // The payload can be submitted again as-is
+        let replayed: JobType = serde_json::from_value(letter.payload).unwrap();
+        assert_eq!(replayed.name(), "Backup");
+    }
+}
@@ -0,0 +1,296 @@
+//! Accounting of disclosures
+//!
+//! AccessLog audit report jobs render each listed patient's accesses over
+//! the job's date range (see `emr_core::services::disclosures`) as CSV or PDF
+//! into `reports.output_dir`, at [`export_path`], where the API's
+//! `GET /patients/{id}/disclosures/exports/{job_id}` serves them from.
+
+use crate::config::ReportConfig;
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::{AuditReportJob, AuditReportType, OutputFormat};
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::domain::ReportFormat;
+use emr_core::services::disclosures::{AccessRecord, DisclosureReport, DISCLOSURES_QUERY};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Nullable, Text, Timestamptz};
+use diesel::RunQueryDsl;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Where a job stores a patient's report
+pub fn export_path(output_dir: &str, patient_id: Uuid, job_id: Uuid, format: ReportFormat) -> PathBuf {
+    Path::new(output_dir)
+        .join("disclosures")
+        .join(patient_id.to_string())
+        .join(format!("{}.{}", job_id, format.as_str()))
+}
+
+/// Accesses to patients' data
+#[async_trait]
+pub trait DisclosureStore: Send + Sync {
+    /// Accesses to a patient's data in `[start, end)`, oldest first
+    async fn disclosures(
+        &self,
+        patient_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> JobResult<Vec<AccessRecord>>;
+}
+
+/// Accesses in `audit.audit_log`
+pub struct DatabaseDisclosureStore {
+    pool: Pool,
+}
+
+impl DatabaseDisclosureStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct AccessRecordRow {
+    #[diesel(sql_type = Timestamptz)]
+    accessed_at: DateTime<Utc>,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    user_id: Option<Uuid>,
+    #[diesel(sql_type = Nullable<Text>)]
+    user_name: Option<String>,
+    #[diesel(sql_type = Text)]
+    action: String,
+    #[diesel(sql_type = Text)]
+    resource: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    basis: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    purpose_of_use: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    request_id: Option<String>,
+}
+
+#[async_trait]
+impl DisclosureStore for DatabaseDisclosureStore {
+    async fn disclosures(
+        &self,
+        patient_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> JobResult<Vec<AccessRecord>> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(DISCLOSURES_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(patient_id)
+                    .bind::<Timestamptz, _>(start)
+                    .bind::<Timestamptz, _>(end)
+                    .load::<AccessRecordRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| AccessRecord {
+                accessed_at: row.accessed_at,
+                user_id: row.user_id,
+                user_name: row.user_name,
+                action: row.action,
+                resource: row.resource,
+                basis: row.basis,
+                purpose_of_use: row.purpose_of_use,
+                request_id: row.request_id,
+            })
+            .collect())
+    }
+}
+
+/// Renders accounting of disclosures reports
+pub struct DisclosureReportHandler {
+    config: ReportConfig,
+    store: Arc<dyn DisclosureStore>,
+}
+
+impl DisclosureReportHandler {
+    /// Create a handler reading accesses from `store` into `config.output_dir`
+    pub fn new(config: ReportConfig, store: Arc<dyn DisclosureStore>) -> Self {
+        Self { config, store }
+    }
+}
+
+#[async_trait]
+impl JobHandler<AuditReportJob> for DisclosureReportHandler {
+    async fn execute(&self, job: AuditReportJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(job_id = ?context.job_id, "Starting disclosure report job");
+
+        if !matches!(job.report_type, AuditReportType::AccessLog) {
+            return Ok(JobExecutionResult::failure(format!(
+                "{:?} audit reports are not supported",
+                job.report_type
+            )));
+        }
+        let format = match job.output_format {
+            OutputFormat::Csv => ReportFormat::Csv,
+            OutputFormat::Pdf => ReportFormat::Pdf,
+            other => {
+                return Ok(JobExecutionResult::failure(format!(
+                    "Disclosure reports are rendered as CSV or PDF, not {:?}",
+                    other
+                )))
+            }
+        };
+        let patient_ids = job.patient_ids.unwrap_or_default();
+        if patient_ids.is_empty() {
+            return Ok(JobExecutionResult::failure("Disclosure reports need a patient".to_string()));
+        }
+
+        let mut accesses = 0;
+        for (done, patient_id) in patient_ids.iter().enumerate() {
+            context.check_cancelled()?;
+            let report = DisclosureReport {
+                patient_id: *patient_id,
+                start: job.date_range.start,
+                end: job.date_range.end,
+                records: self
+                    .store
+                    .disclosures(*patient_id, job.date_range.start, job.date_range.end)
+                    .await?,
+            };
+
+            let path = export_path(&self.config.output_dir, *patient_id, context.job_id, format);
+            if let Some(directory) = path.parent() {
+                tokio::fs::create_dir_all(directory)
+                    .await
+                    .map_err(|e| JobError::ProcessingError(format!("Failed to create report directory: {}", e)))?;
+            }
+            tokio::fs::write(&path, report.render(format))
+                .await
+                .map_err(|e| JobError::ProcessingError(format!("Failed to store report: {}", e)))?;
+
+            accesses += report.records.len();
+            context.progress.step(done + 1, patient_ids.len());
+        }
+
+        Ok(JobExecutionResult::success_with_data(
+            format!("Disclosure reports generated for {} patients", patient_ids.len()),
+            serde_json::json!({ "patient_ids": patient_ids, "accesses": accesses }),
+        )
+        .with_metric("accesses".to_string(), accesses as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "DisclosureReport"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DateRange;
+    use chrono::TimeZone;
+
+    /// The same accesses for every patient, for tests
+    struct MemoryDisclosureStore {
+        records: Vec<AccessRecord>,
+    }
+
+    #[async_trait]
+    impl DisclosureStore for MemoryDisclosureStore {
+        async fn disclosures(
+            &self,
+            _patient_id: Uuid,
+            start: DateTime<Utc>,
+            end: DateTime<Utc>,
+        ) -> JobResult<Vec<AccessRecord>> {
+            Ok(self
+                .records
+                .iter()
+                .filter(|record| record.accessed_at >= start && record.accessed_at < end)
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn access(day: u32) -> AccessRecord {
+        AccessRecord {
+            accessed_at: Utc.with_ymd_and_hms(2026, 3, day, 9, 0, 0).unwrap(),
+            user_id: Some(Uuid::new_v4()),
+            user_name: Some("Ana Ruiz".to_string()),
+            action: "READ".to_string(),
+            resource: "/api/v1/documents/d1".to_string(),
+            basis: Some("treating_relationship".to_string()),
+            purpose_of_use: None,
+            request_id: None,
+        }
+    }
+
+    fn job(output_format: OutputFormat, patient_ids: Vec<Uuid>) -> AuditReportJob {
+        AuditReportJob {
+            report_type: AuditReportType::AccessLog,
+            date_range: DateRange {
+                start: Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap(),
+                end: Utc.with_ymd_and_hms(2026, 3, 10, 0, 0, 0).unwrap(),
+            },
+            patient_ids: Some(patient_ids),
+            practitioner_ids: None,
+            output_format,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disclosure_report_written() {
+        let output_dir = std::env::temp_dir().join(format!("disclosures-{}", Uuid::new_v4()));
+        let config = ReportConfig {
+            output_dir: output_dir.display().to_string(),
+        };
+        let store = Arc::new(MemoryDisclosureStore {
+            records: vec![access(2), access(5), access(20)],
+        });
+        let handler = DisclosureReportHandler::new(config.clone(), store);
+        let patient_id = Uuid::new_v4();
+        let context = JobContext::new(Uuid::new_v4());
+
+        let result = handler
+            .execute(job(OutputFormat::Csv, vec![patient_id]), context.clone())
+            .await
+            .unwrap();
+        assert!(result.success);
+        assert_eq!(result.metrics["accesses"], 2.0);
+
+        let path = export_path(&config.output_dir, patient_id, context.job_id, ReportFormat::Csv);
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert!(csv.starts_with("accessed_at,user,user_id,action,resource,basis,purpose_of_use\r\n"));
+        assert_eq!(csv.lines().count(), 3);
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_reports_fail() {
+        let config = ReportConfig {
+            output_dir: std::env::temp_dir().display().to_string(),
+        };
+        let handler = DisclosureReportHandler::new(config, Arc::new(MemoryDisclosureStore { records: Vec::new() }));
+
+        let html = handler
+            .execute(job(OutputFormat::Html, vec![Uuid::new_v4()]), JobContext::new(Uuid::new_v4()))
+            .await
+            .unwrap();
+        assert!(!html.success);
+
+        let unscoped = handler
+            .execute(job(OutputFormat::Pdf, Vec::new()), JobContext::new(Uuid::new_v4()))
+            .await
+            .unwrap();
+        assert!(!unscoped.success);
+    }
+}
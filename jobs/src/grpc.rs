@@ -0,0 +1,165 @@
+//! Internal gRPC job service
+//!
+//! Services that need an answer submit jobs here instead of on NATS: the
+//! response carries the job id, or the earlier job when the idempotency key
+//! was already used, and the job can be followed with `GetJobStatus`.
+//! [`serve`] runs the service on `grpc.listen_addr` alongside the worker.
+
+use crate::history::{JobRun, JobRunStatus};
+use crate::types::{JobSubmission, JobType};
+use crate::worker::{JobState, JobsWorker, Submitted};
+use emr_proto::v1::job_service_server::{JobService, JobServiceServer};
+use emr_proto::v1::{self, GetJobStatusRequest, GetJobStatusResponse, SubmitJobRequest, SubmitJobResponse};
+use emr_proto::GrpcConfig;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tracing::info;
+use uuid::Uuid;
+
+/// [`JobService`] backed by the worker's queues and job history
+pub struct WorkerJobService {
+    worker: Arc<JobsWorker>,
+}
+
+impl WorkerJobService {
+    pub fn new(worker: Arc<JobsWorker>) -> Self {
+        Self { worker }
+    }
+}
+
+// `Status` is what tonic handlers return; boxing it here would only be undone
+#[allow(clippy::result_large_err)]
+fn parse_job_id(value: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(value).map_err(|_| Status::invalid_argument(format!("'{}' is not a job id", value)))
+}
+
+fn run_message(run: JobRun) -> v1::JobRun {
+    v1::JobRun {
+        job_type: run.job_type,
+        queue: run.queue.as_str().to_string(),
+        attempts: run.attempts,
+        duration_ms: run.duration_ms,
+        finished_at: run.finished_at.to_rfc3339(),
+    }
+}
+
+#[tonic::async_trait]
+impl JobService for WorkerJobService {
+    async fn submit_job(&self, request: Request<SubmitJobRequest>) -> Result<Response<SubmitJobResponse>, Status> {
+        let request = request.into_inner();
+        let job_id = match request.job_id.as_str() {
+            "" => None,
+            job_id => Some(parse_job_id(job_id)?),
+        };
+        let job: JobType = serde_json::from_slice(&request.job)
+            .map_err(|e| Status::invalid_argument(format!("Invalid job: {}", e)))?;
+
+        let submission = JobSubmission {
+            job_id,
+            idempotency_key: request.idempotency_key,
+            job,
+        };
+        let (job_id, duplicate) = match self.worker.submit(submission).await {
+            Submitted::Queued(job_id) => (job_id, false),
+            Submitted::Duplicate(job_id) => (job_id, true),
+        };
+
+        Ok(Response::new(SubmitJobResponse {
+            job_id: job_id.to_string(),
+            duplicate,
+        }))
+    }
+
+    async fn get_job_status(
+        &self,
+        request: Request<GetJobStatusRequest>,
+    ) -> Result<Response<GetJobStatusResponse>, Status> {
+        let job_id = parse_job_id(&request.get_ref().job_id)?;
+        let state = self
+            .worker
+            .status(job_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::not_found(format!("Job {} not found", job_id)))?;
+
+        let (state, run) = match state {
+            JobState::Queued => (v1::JobState::Queued, None),
+            JobState::Running => (v1::JobState::Running, None),
+            JobState::Finished(run) => {
+                let state = match run.status {
+                    JobRunStatus::Succeeded => v1::JobState::Succeeded,
+                    JobRunStatus::Failed => v1::JobState::Failed,
+                    JobRunStatus::Cancelled => v1::JobState::Cancelled,
+                };
+                (state, Some(run_message(run)))
+            }
+        };
+
+        Ok(Response::new(GetJobStatusResponse {
+            job_id: job_id.to_string(),
+            state: state.into(),
+            run,
+        }))
+    }
+}
+
+/// Serve the job service until it fails
+pub async fn serve(worker: Arc<JobsWorker>, config: &GrpcConfig) -> anyhow::Result<()> {
+    let addr = config.listen_addr.parse()?;
+    info!(%addr, mtls = config.mtls(), "Serving the internal job API");
+
+    emr_proto::server(config)?
+        .add_service(JobServiceServer::new(WorkerJobService::new(worker)))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::JobsConfig;
+
+    #[tokio::test]
+    async fn test_submit_and_follow_job() {
+        let service = WorkerJobService::new(Arc::new(JobsWorker::new(JobsConfig::default())));
+        let job = serde_json::json!({
+            "type": "DataValidation",
+            "patient_id": null,
+            "validation_type": "Completeness",
+            "rules": [],
+            "auto_fix": false,
+        });
+
+        let submitted = service
+            .submit_job(Request::new(SubmitJobRequest {
+                job_id: String::new(),
+                idempotency_key: None,
+                job: serde_json::to_vec(&job).unwrap(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!submitted.duplicate);
+
+        let status = service
+            .get_job_status(Request::new(GetJobStatusRequest {
+                job_id: submitted.job_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.state(), v1::JobState::Queued);
+        assert!(status.run.is_none());
+
+        let invalid = service
+            .submit_job(Request::new(SubmitJobRequest {
+                job_id: "not-a-uuid".to_string(),
+                idempotency_key: None,
+                job: serde_json::to_vec(&job).unwrap(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -1,14 +1,18 @@
 //! Job execution handlers
 
-use crate::{JobContext, JobError, JobResult, types::*};
+use crate::{backup, config::BackupConfig, JobContext, JobError, JobResult, types::*};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use core::prelude::*;
-use fhir::KodjinClient;
+use crate::notifications::{
+    inbox_subject, HeldNotification, InAppNotification, NotificationInbox, NotificationPreferenceStore,
+};
+use emr_core::events::EventEnvelope;
+use emr_core::notifications::{DeliveryDecision, TemplateCatalog};
+use emr_core::retention::{RetentionAction, RetentionCandidate, RetentionDecision, RetentionPolicySet};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tracing::{error, info, warn};
-use uuid::Uuid;
+use std::sync::Arc;
+use tracing::{info, warn};
 
 /// Job handler trait
 #[async_trait]
@@ -87,9 +91,14 @@ impl JobHandler<DataValidationJob> for DataValidationHandler {
         let mut errors_count = 0;
         let mut warnings_count = 0;
 
-        for rule in &job.rules {
+        let total_rules = job.rules.len();
+
+        for (index, rule) in job.rules.iter().enumerate() {
+            context.check_cancelled()?;
+
             // Simulate validation
             tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            context.progress.info(format!("Evaluated rule {}", rule.name));
             
             match rule.severity {
                 ValidationSeverity::Error | ValidationSeverity::Critical => {
@@ -104,6 +113,8 @@ impl JobHandler<DataValidationJob> for DataValidationHandler {
                     validation_results.push(format!("INFO: {}", rule.description));
                 }
             }
+
+            context.progress.step(index + 1, total_rules);
         }
 
         let result_data = serde_json::json!({
@@ -127,7 +138,85 @@ impl JobHandler<DataValidationJob> for DataValidationHandler {
 }
 
 /// Notification job handler
-pub struct NotificationHandler;
+///
+/// With a preference store, notifications follow the recipient's
+/// preferences: channels they turned off are skipped, and notifications for
+/// quiet hours or the daily digest are held until the worker releases them.
+/// With an inbox, in-app notifications are stored there and announced on
+/// NATS when a client is configured.
+#[derive(Default)]
+pub struct NotificationHandler {
+    preferences: Option<Arc<dyn NotificationPreferenceStore>>,
+    inbox: Option<Arc<dyn NotificationInbox>>,
+    nats: Option<async_nats::Client>,
+}
+
+impl NotificationHandler {
+    /// Handler that sends every notification
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply the preferences in a store
+    pub fn with_preferences(mut self, store: Arc<dyn NotificationPreferenceStore>) -> Self {
+        self.preferences = Some(store);
+        self
+    }
+
+    /// Deliver in-app notifications to an inbox
+    pub fn with_inbox(mut self, inbox: Arc<dyn NotificationInbox>) -> Self {
+        self.inbox = Some(inbox);
+        self
+    }
+
+    /// Announce new in-app notifications on NATS
+    pub fn with_nats(mut self, client: async_nats::Client) -> Self {
+        self.nats = Some(client);
+        self
+    }
+
+    /// Store an in-app notification and announce it to open sessions
+    async fn deliver_in_app(&self, notification: InAppNotification) -> JobResult<()> {
+        let Some(inbox) = &self.inbox else {
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            return Ok(());
+        };
+        inbox.deliver(&notification).await?;
+
+        // The notification is stored; clients that miss the announcement
+        // see it on their next refresh
+        if let Some(nats) = &self.nats {
+            let published = match EventEnvelope::new(notification.clone()).encode() {
+                Ok(payload) => nats
+                    .publish(inbox_subject(notification.recipient_id), payload.into())
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(error) = published {
+                warn!(notification_id = ?notification.id, %error, "Failed to announce in-app notification");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether to send a notification now, per its recipient's preferences
+    async fn decide(&self, job: &NotificationJob) -> JobResult<DeliveryDecision> {
+        let Some(store) = &self.preferences else {
+            return Ok(DeliveryDecision::Send);
+        };
+        let Some(preferences) = store.preferences(job.recipient_id).await? else {
+            return Ok(DeliveryDecision::Send);
+        };
+
+        let critical = job.priority == Priority::Critical;
+        match preferences.decide(job.notification_type.as_str(), job.channel.as_str(), critical, Utc::now()) {
+            // Digests go out at the time the recipient chose for them
+            DeliveryDecision::Digest(_) if job.digest => Ok(DeliveryDecision::Send),
+            decision => Ok(decision),
+        }
+    }
+}
 
 #[async_trait]
 impl JobHandler<NotificationJob> for NotificationHandler {
@@ -135,43 +224,115 @@ impl JobHandler<NotificationJob> for NotificationHandler {
         info!(
             job_id = ?context.job_id,
             recipient_id = ?job.recipient_id,
+            address = ?job.address,
             notification_type = ?job.notification_type,
             channel = ?job.channel,
             "Starting notification job"
         );
 
-        // TODO: Implement actual notification sending logic
-        // This is a stub implementation
-        
-        let delivery_result = match job.channel {
-            NotificationChannel::Email => {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                "Email sent successfully"
-            }
-            NotificationChannel::Sms => {
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-                "SMS sent successfully"
+        // Templated text is rendered at delivery so it follows the catalog
+        let (subject, message) = match &job.template {
+            Some(template) => {
+                let rendered = TemplateCatalog::builtin()
+                    .render(template)
+                    .map_err(|e| JobError::ValidationError(e.to_string()))?;
+                (rendered.subject, rendered.body)
             }
-            NotificationChannel::Push => {
-                tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-                "Push notification sent successfully"
+            None if job.message.trim().is_empty() => {
+                return Err(JobError::ValidationError(
+                    "Notifications need a message or a template".to_string(),
+                ));
             }
-            NotificationChannel::InApp => {
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                "In-app notification sent successfully"
+            None => (None, job.message.clone()),
+        };
+
+        let hold = match self.decide(&job).await? {
+            DeliveryDecision::Send => None,
+            DeliveryDecision::Suppress => {
+                info!(job_id = ?context.job_id, recipient_id = ?job.recipient_id, "Notification channel turned off by recipient");
+                return Ok(JobExecutionResult::success_with_data(
+                    "Notification suppressed by recipient preferences".to_string(),
+                    serde_json::json!({
+                        "recipient_id": job.recipient_id,
+                        "channel": job.channel,
+                        "suppressed": true
+                    }),
+                ));
             }
+            DeliveryDecision::Defer(release_at) => Some((release_at, false)),
+            DeliveryDecision::Digest(release_at) => Some((release_at, true)),
         };
+        if let (Some((release_at, digest)), Some(store)) = (hold, &self.preferences) {
+            let held = HeldNotification {
+                id: context.job_id,
+                job: job.clone(),
+                message: message.clone(),
+                digest,
+                release_at,
+            };
+            context.once("hold", || async { store.hold(&held).await }).await?;
+
+            return Ok(JobExecutionResult::success_with_data(
+                "Notification held by recipient preferences".to_string(),
+                serde_json::json!({
+                    "recipient_id": job.recipient_id,
+                    "channel": job.channel,
+                    "digest": digest,
+                    "release_at": release_at
+                }),
+            ));
+        }
+
+        // TODO: Implement actual notification sending logic
+        // This is a stub implementation
+        
+        // A retry of a job that already sent its message does not send again
+        let delivery: (String, DateTime<Utc>) = context
+            .once("deliver", || async {
+                let delivery_result = match job.channel {
+                    NotificationChannel::Email => {
+                        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                        "Email sent successfully"
+                    }
+                    NotificationChannel::Sms => {
+                        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                        "SMS sent successfully"
+                    }
+                    NotificationChannel::Push => {
+                        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                        "Push notification sent successfully"
+                    }
+                    NotificationChannel::InApp => {
+                        self.deliver_in_app(InAppNotification {
+                            id: context.job_id,
+                            recipient_id: job.recipient_id,
+                            notification_type: job.notification_type,
+                            priority: job.priority,
+                            subject: subject.clone(),
+                            message: message.clone(),
+                            created_at: Utc::now(),
+                        })
+                        .await?;
+                        "In-app notification sent successfully"
+                    }
+                };
+                Ok((delivery_result.to_string(), Utc::now()))
+            })
+            .await?;
+        let (delivery_result, delivered_at) = delivery;
 
         let result_data = serde_json::json!({
             "recipient_id": job.recipient_id,
-            "message": job.message,
+            "template": job.template.as_ref().map(|template| &template.name),
+            "subject": subject,
+            "message": message,
             "channel": job.channel,
             "priority": job.priority,
-            "delivered_at": Utc::now()
+            "delivered_at": delivered_at
         });
 
         Ok(JobExecutionResult::success_with_data(
-            delivery_result.to_string(),
+            delivery_result,
             result_data
         )
         .with_metric("delivery_time_ms".to_string(), 150.0))
@@ -182,9 +343,281 @@ impl JobHandler<NotificationJob> for NotificationHandler {
     }
 }
 
+/// Storage operations needed to apply retention policies
+#[async_trait]
+pub trait RetentionStore: Send + Sync {
+    /// Load records of the given entity types (all types when empty)
+    async fn candidates(&self, entity_types: &[String]) -> JobResult<Vec<RetentionCandidate>>;
+
+    /// Archive, anonymize or purge a record
+    async fn apply(&self, decision: &RetentionDecision) -> JobResult<()>;
+}
+
+/// Data cleanup job handler
+///
+/// Retention cleanups evaluate records against the configured
+/// [`RetentionPolicySet`]; dry runs only report what would happen.
+pub struct DataCleanupHandler {
+    policies: RetentionPolicySet,
+    store: Arc<dyn RetentionStore>,
+}
+
+impl DataCleanupHandler {
+    /// Create a cleanup handler over a retention store
+    pub fn new(policies: RetentionPolicySet, store: Arc<dyn RetentionStore>) -> Self {
+        Self { policies, store }
+    }
+
+    async fn apply_retention(&self, job: &DataCleanupJob, context: &JobContext) -> JobResult<JobExecutionResult> {
+        let candidates = self.store.candidates(&job.entity_types).await?;
+        let mut report = self.policies.evaluate(&candidates, Utc::now());
+
+        if job.preserve_audit {
+            report
+                .decisions
+                .retain(|decision| !(decision.entity_type == "AuditEvent" && decision.action == RetentionAction::Purge));
+        }
+
+        let total = report.decisions.len();
+        if !job.dry_run {
+            for (index, decision) in report.decisions.iter().enumerate() {
+                context.check_cancelled()?;
+                self.store.apply(decision).await?;
+                context.progress.step(index + 1, total);
+            }
+        }
+
+        let message = format!(
+            "Retention {}: {} of {} records due",
+            if job.dry_run { "dry run" } else { "applied" },
+            total,
+            report.evaluated
+        );
+        let data = serde_json::to_value(&report).map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        Ok(JobExecutionResult::success_with_data(message, data)
+            .with_metric("records_evaluated".to_string(), report.evaluated as f64)
+            .with_metric("records_due".to_string(), total as f64))
+    }
+}
+
+#[async_trait]
+impl JobHandler<DataCleanupJob> for DataCleanupHandler {
+    async fn execute(&self, job: DataCleanupJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            cleanup_type = ?job.cleanup_type,
+            dry_run = job.dry_run,
+            "Starting data cleanup job"
+        );
+
+        match job.cleanup_type {
+            CleanupType::Retention => self.apply_retention(&job, &context).await,
+            _ => Err(JobError::ProcessingError(format!(
+                "Cleanup type {:?} not yet implemented",
+                job.cleanup_type
+            ))),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "data_cleanup"
+    }
+}
+
+/// Backup job handler
+pub struct BackupHandler {
+    config: BackupConfig,
+    database_url: String,
+}
+
+impl BackupHandler {
+    /// Create a backup handler for a database
+    pub fn new(config: BackupConfig, database_url: String) -> Self {
+        Self { config, database_url }
+    }
+
+    /// Object key for a backup, grouped by day
+    fn object_key(&self, job: &BackupJob, at: DateTime<Utc>) -> String {
+        let name = match &job.label {
+            Some(label) => format!("{}-{}", label, job.backup_id),
+            None => job.backup_id.to_string(),
+        };
+        format!("{}/{}/{}.sql.gz.enc", self.config.prefix.trim_end_matches('/'), at.format("%Y/%m/%d"), name)
+    }
+}
+
+#[async_trait]
+impl JobHandler<BackupJob> for BackupHandler {
+    async fn execute(&self, job: BackupJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            backup_id = ?job.backup_id,
+            schemas = ?job.schemas,
+            "Starting backup job"
+        );
+
+        if !self.config.enabled {
+            return Err(JobError::ValidationError("Backups are not enabled".to_string()));
+        }
+
+        let dump = backup::dump_database(&self.config.pg_dump_path, &self.database_url, &job.schemas).await?;
+        context.progress.step(1, 3);
+        context.check_cancelled()?;
+
+        let object_key = self.object_key(&job, Utc::now());
+        let (object, manifest) = backup::seal(&dump, &self.config.encryption_key, job.backup_id, &object_key)?;
+        context.progress.step(2, 3);
+        context.check_cancelled()?;
+
+        backup::BackupStore::new(&self.config)?.upload(&object, &manifest).await?;
+        context.progress.step(3, 3);
+
+        let data = serde_json::to_value(&manifest).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        Ok(JobExecutionResult::success_with_data(format!("Backup stored at {}", object_key), data)
+            .with_metric("dump_bytes".to_string(), manifest.dump_bytes as f64)
+            .with_metric("object_bytes".to_string(), manifest.object_bytes as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "backup"
+    }
+}
+
+/// Default delay before the first delivery retry
+const DEFAULT_DELIVERY_BACKOFF: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// FHIR Subscription rest-hook delivery handler
+pub struct SubscriptionNotificationHandler {
+    client: reqwest::Client,
+    backoff_base: std::time::Duration,
+}
+
+impl SubscriptionNotificationHandler {
+    /// Create a new subscription notification handler
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            backoff_base: DEFAULT_DELIVERY_BACKOFF,
+        }
+    }
+
+    /// Set the delay before the first retry; later retries back off exponentially
+    pub fn with_backoff_base(mut self, backoff_base: std::time::Duration) -> Self {
+        self.backoff_base = backoff_base;
+        self
+    }
+
+    /// Perform a single delivery attempt, returning the response status
+    async fn deliver_once(&self, job: &SubscriptionNotificationJob) -> Result<u16, (Option<u16>, String)> {
+        let mut request = self.client.post(&job.endpoint);
+        for (name, value) in &job.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(payload) = &job.payload {
+            request = request
+                .header("Content-Type", "application/fhir+json")
+                .json(payload);
+        }
+
+        let response = request.send().await.map_err(|e| (None, e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(status.as_u16())
+        } else {
+            Err((Some(status.as_u16()), format!("Endpoint responded with {}", status)))
+        }
+    }
+}
+
+/// Check if a failed delivery should be retried
+pub(crate) fn is_retryable_delivery(status_code: Option<u16>) -> bool {
+    match status_code {
+        None => true,
+        Some(code) => code == 408 || code == 429 || code >= 500,
+    }
+}
+
+#[async_trait]
+impl JobHandler<SubscriptionNotificationJob> for SubscriptionNotificationHandler {
+    async fn execute(&self, job: SubscriptionNotificationJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            subscription_id = %job.subscription_id,
+            resource_type = %job.resource_type,
+            "Starting subscription notification job"
+        );
+
+        let max_attempts = job.max_attempts.max(1);
+        let mut attempts = Vec::new();
+        let mut status = DeliveryStatus::Pending;
+
+        for attempt in 1..=max_attempts {
+            context.check_cancelled()?;
+            let outcome = self.deliver_once(&job).await;
+            let (status_code, error) = match &outcome {
+                Ok(code) => (Some(*code), None),
+                Err((code, error)) => (*code, Some(error.clone())),
+            };
+
+            attempts.push(DeliveryAttempt {
+                attempt,
+                attempted_at: Utc::now(),
+                status_code,
+                error: error.clone(),
+            });
+
+            if outcome.is_ok() {
+                status = DeliveryStatus::Delivered;
+                break;
+            }
+
+            warn!(
+                job_id = ?context.job_id,
+                subscription_id = %job.subscription_id,
+                attempt,
+                error = ?error,
+                "Subscription notification delivery failed"
+            );
+            context.progress.warning(format!("Delivery attempt {} failed", attempt));
+
+            if attempt == max_attempts || !is_retryable_delivery(status_code) {
+                status = DeliveryStatus::Failed;
+                break;
+            }
+
+            let backoff = self.backoff_base * 2u32.saturating_pow(attempt - 1);
+            tokio::time::sleep(backoff).await;
+        }
+
+        let result_data = serde_json::json!({
+            "subscription_id": job.subscription_id,
+            "resource_type": job.resource_type,
+            "resource_id": job.resource_id,
+            "status": status,
+            "attempts": attempts,
+        });
+
+        let result = if status == DeliveryStatus::Delivered {
+            JobExecutionResult::success_with_data("Subscription notification delivered".to_string(), result_data)
+        } else {
+            let mut result = JobExecutionResult::failure("Subscription notification delivery failed".to_string());
+            result.data = Some(result_data);
+            result
+        };
+
+        Ok(result.with_metric("delivery_attempts".to_string(), attempts.len() as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "subscription_notification"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use uuid::Uuid;
 
     #[tokio::test]
     async fn test_data_validation_handler() {
@@ -202,6 +635,7 @@ mod tests {
                 },
             ],
             auto_fix: false,
+            profile: None,
         };
 
         let context = JobContext::new(Uuid::new_v4());
@@ -212,4 +646,189 @@ mod tests {
         assert!(result.success);
         assert!(result.data.is_some());
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_data_validation_handler_reports_progress() {
+        let handler = DataValidationHandler;
+        let job = DataValidationJob {
+            patient_id: None,
+            validation_type: ValidationType::Completeness,
+            rules: vec![
+                ValidationRule {
+                    name: "birth_date".to_string(),
+                    description: "Birth date is recommended".to_string(),
+                    rule_type: "required".to_string(),
+                    expression: "birth_date != null".to_string(),
+                    severity: ValidationSeverity::Warning,
+                },
+                ValidationRule {
+                    name: "gender".to_string(),
+                    description: "Gender is recommended".to_string(),
+                    rule_type: "required".to_string(),
+                    expression: "gender != null".to_string(),
+                    severity: ValidationSeverity::Info,
+                },
+            ],
+            auto_fix: false,
+            profile: None,
+        };
+
+        let context = JobContext::new(Uuid::new_v4());
+        let mut events = context.progress.subscribe();
+        handler.execute(job, context).await.unwrap();
+
+        let mut last_progress = 0.0;
+        while let Ok(event) = events.try_recv() {
+            if let crate::progress::JobEventKind::Progress { progress } = event.kind {
+                last_progress = progress;
+            }
+        }
+        assert_eq!(last_progress, 100.0);
+    }
+
+    #[test]
+    fn test_retryable_delivery_status() {
+        assert!(is_retryable_delivery(None));
+        assert!(is_retryable_delivery(Some(503)));
+        assert!(is_retryable_delivery(Some(429)));
+        assert!(!is_retryable_delivery(Some(404)));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_notification_handler_records_failed_attempts() {
+        let handler = SubscriptionNotificationHandler::new(reqwest::Client::new())
+            .with_backoff_base(std::time::Duration::from_millis(1));
+        let job = SubscriptionNotificationJob {
+            subscription_id: "sub-1".to_string(),
+            endpoint: "http://127.0.0.1:1/hook".to_string(),
+            headers: vec![],
+            resource_type: "Observation".to_string(),
+            resource_id: Some("obs-1".to_string()),
+            payload: None,
+            max_attempts: 3,
+        };
+
+        let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+
+        assert!(!result.success);
+        let data = result.data.unwrap();
+        assert_eq!(data["status"], "Failed");
+        assert_eq!(data["attempts"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_backup_object_key() {
+        let handler = BackupHandler::new(BackupConfig::default(), String::new());
+        let job = BackupJob {
+            backup_id: Uuid::nil(),
+            schemas: vec![],
+            label: Some("nightly".to_string()),
+        };
+        let at = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 3, 9, 2, 0, 0).unwrap();
+
+        assert_eq!(
+            handler.object_key(&job, at),
+            "backups/2024/03/09/nightly-00000000-0000-0000-0000-000000000000.sql.gz.enc"
+        );
+    }
+
+    /// Retention store holding candidates in memory and recording actions
+    struct MemoryRetentionStore {
+        candidates: Vec<RetentionCandidate>,
+        applied: tokio::sync::Mutex<Vec<RetentionDecision>>,
+    }
+
+    #[async_trait]
+    impl RetentionStore for MemoryRetentionStore {
+        async fn candidates(&self, _entity_types: &[String]) -> JobResult<Vec<RetentionCandidate>> {
+            Ok(self.candidates.clone())
+        }
+
+        async fn apply(&self, decision: &RetentionDecision) -> JobResult<()> {
+            self.applied.lock().await.push(decision.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_data_cleanup_handler_applies_retention() {
+        let expired = |entity_type: &str| RetentionCandidate {
+            entity_type: entity_type.to_string(),
+            entity_id: Uuid::new_v4(),
+            jurisdiction: None,
+            last_activity: Utc::now() - chrono::Duration::days(4000),
+        };
+        let store = Arc::new(MemoryRetentionStore {
+            candidates: vec![expired("Session"), expired("AuditEvent")],
+            applied: Default::default(),
+        });
+        let policies = RetentionPolicySet::new(vec![
+            emr_core::retention::RetentionPolicy::new("Session", 90, RetentionAction::Purge),
+            emr_core::retention::RetentionPolicy::new("AuditEvent", 2190, RetentionAction::Purge),
+        ])
+        .unwrap();
+        let handler = DataCleanupHandler::new(policies, store.clone());
+
+        let mut job = DataCleanupJob {
+            cleanup_type: CleanupType::Retention,
+            older_than: Utc::now(),
+            dry_run: true,
+            preserve_audit: true,
+            entity_types: vec![],
+        };
+
+        let result = handler.execute(job.clone(), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert_eq!(result.data.unwrap()["decisions"].as_array().unwrap().len(), 1);
+        assert!(store.applied.lock().await.is_empty());
+
+        job.dry_run = false;
+        handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+        let applied = store.applied.lock().await;
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].entity_type, "Session");
+    }
+
+    /// Inbox keeping delivered notifications in memory
+    #[derive(Default)]
+    struct MemoryInbox {
+        delivered: tokio::sync::Mutex<Vec<InAppNotification>>,
+    }
+
+    #[async_trait]
+    impl NotificationInbox for MemoryInbox {
+        async fn deliver(&self, notification: &InAppNotification) -> JobResult<()> {
+            self.delivered.lock().await.push(notification.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_app_notifications_go_to_inbox() {
+        let inbox = Arc::new(MemoryInbox::default());
+        let handler = NotificationHandler::new().with_inbox(inbox.clone());
+        let job = NotificationJob {
+            recipient_id: Uuid::new_v4(),
+            address: None,
+            notification_type: NotificationType::Update,
+            message: String::new(),
+            template: Some(emr_core::notifications::TemplateRef {
+                name: emr_core::notifications::SECURE_MESSAGE_TEMPLATE.to_string(),
+                locale: None,
+                variables: Default::default(),
+            }),
+            channel: NotificationChannel::InApp,
+            priority: Priority::Normal,
+            scheduled_for: None,
+            digest: false,
+        };
+        let job_id = Uuid::new_v4();
+
+        handler.execute(job.clone(), JobContext::new(job_id)).await.unwrap();
+
+        let delivered = inbox.delivered.lock().await;
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].id, job_id);
+        assert_eq!(delivered[0].recipient_id, job.recipient_id);
+        assert_eq!(delivered[0].message, "You have a new secure message. Sign in to read it.");
+    }
+}
@@ -0,0 +1,229 @@
+//! Job run history
+//!
+//! [`JobMonitor`](crate::JobMonitor) only covers the running worker. Every
+//! finished job is also recorded as a [`JobRun`] so throughput, failure rates
+//! and duration percentiles per job type survive restarts and can be queried
+//! over a time window (`GET /api/jobs/stats`). Retried attempts are folded
+//! into the run that finally succeeds or fails; `attempts` counts them.
+
+use crate::types::{JobQueue, JobType};
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{BigInt, Integer, Text, Timestamptz};
+use diesel::{OptionalExtension, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Insert for a finished job run
+pub const INSERT_JOB_RUN_QUERY: &str = r#"
+INSERT INTO jobs.job_runs (job_id, job_type, queue, status, attempts, duration_ms, finished_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+"#;
+
+/// Latest run of a job
+pub const FIND_JOB_RUN_QUERY: &str = r#"
+SELECT job_id, job_type, queue, status, attempts, duration_ms, finished_at
+FROM jobs.job_runs
+WHERE job_id = $1
+ORDER BY finished_at DESC
+LIMIT 1
+"#;
+
+/// How a job run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobRunStatus {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobRunStatus {
+    /// Name stored in `jobs.job_runs.status`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobRunStatus::Succeeded => "succeeded",
+            JobRunStatus::Failed => "failed",
+            JobRunStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Status stored under `name`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "succeeded" => Some(JobRunStatus::Succeeded),
+            "failed" => Some(JobRunStatus::Failed),
+            "cancelled" => Some(JobRunStatus::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// Status for a job result
+    pub fn from_result<T>(result: &JobResult<T>) -> Self {
+        match result {
+            Ok(_) => JobRunStatus::Succeeded,
+            Err(JobError::Cancelled(_)) => JobRunStatus::Cancelled,
+            Err(_) => JobRunStatus::Failed,
+        }
+    }
+}
+
+/// A finished job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRun {
+    pub job_id: Uuid,
+    pub job_type: String,
+    pub queue: JobQueue,
+    pub status: JobRunStatus,
+    /// Attempts made, including the last one
+    pub attempts: u32,
+    /// Duration of the last attempt
+    pub duration_ms: u64,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl JobRun {
+    /// Run of `job` that just finished
+    pub fn new(job_id: Uuid, job: &JobType, status: JobRunStatus, attempts: u32, duration_ms: u64) -> Self {
+        Self {
+            job_id,
+            job_type: job.name().to_string(),
+            queue: job.queue(),
+            status,
+            attempts,
+            duration_ms,
+            finished_at: Utc::now(),
+        }
+    }
+}
+
+/// Where finished job runs are kept
+#[async_trait]
+pub trait JobHistoryStore: Send + Sync {
+    /// Record a finished job run
+    async fn record(&self, run: &JobRun) -> JobResult<()>;
+
+    /// The latest run of a job; stores that keep no history find none
+    async fn find(&self, _job_id: Uuid) -> JobResult<Option<JobRun>> {
+        Ok(None)
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct JobRunRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    job_id: Uuid,
+    #[diesel(sql_type = Text)]
+    job_type: String,
+    #[diesel(sql_type = Text)]
+    queue: String,
+    #[diesel(sql_type = Text)]
+    status: String,
+    #[diesel(sql_type = Integer)]
+    attempts: i32,
+    #[diesel(sql_type = BigInt)]
+    duration_ms: i64,
+    #[diesel(sql_type = Timestamptz)]
+    finished_at: DateTime<Utc>,
+}
+
+impl JobRunRow {
+    fn into_run(self) -> JobResult<JobRun> {
+        let invalid = |field: &str, value: &str| {
+            JobError::DatabaseError(format!("Job run {} has an unknown {} '{}'", self.job_id, field, value))
+        };
+        Ok(JobRun {
+            job_id: self.job_id,
+            queue: JobQueue::parse(&self.queue).ok_or_else(|| invalid("queue", &self.queue))?,
+            status: JobRunStatus::parse(&self.status).ok_or_else(|| invalid("status", &self.status))?,
+            job_type: self.job_type,
+            attempts: self.attempts.max(0) as u32,
+            duration_ms: self.duration_ms.max(0) as u64,
+            finished_at: self.finished_at,
+        })
+    }
+}
+
+/// Job runs in the `jobs.job_runs` table
+pub struct DatabaseJobHistoryStore {
+    pool: Pool,
+}
+
+impl DatabaseJobHistoryStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobHistoryStore for DatabaseJobHistoryStore {
+    async fn record(&self, run: &JobRun) -> JobResult<()> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let run = run.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_JOB_RUN_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(run.job_id)
+                .bind::<Text, _>(&run.job_type)
+                .bind::<Text, _>(run.queue.as_str())
+                .bind::<Text, _>(run.status.as_str())
+                .bind::<Integer, _>(run.attempts as i32)
+                .bind::<BigInt, _>(run.duration_ms as i64)
+                .bind::<Timestamptz, _>(run.finished_at)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn find(&self, job_id: Uuid) -> JobResult<Option<JobRun>> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(FIND_JOB_RUN_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(job_id)
+                    .get_result::<JobRunRow>(conn)
+                    .optional()
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        row.map(JobRunRow::into_run).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_from_result() {
+        let ok: JobResult<()> = Ok(());
+        assert_eq!(JobRunStatus::from_result(&ok), JobRunStatus::Succeeded);
+
+        let cancelled: JobResult<()> = Err(JobError::Cancelled("stop".to_string()));
+        assert_eq!(JobRunStatus::from_result(&cancelled).as_str(), "cancelled");
+
+        let failed: JobResult<()> = Err(JobError::TimeoutError("slow".to_string()));
+        assert_eq!(JobRunStatus::from_result(&failed), JobRunStatus::Failed);
+
+        assert_eq!(JobRunStatus::parse("cancelled"), Some(JobRunStatus::Cancelled));
+        assert_eq!(JobRunStatus::parse("running"), None);
+    }
+}
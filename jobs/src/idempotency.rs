@@ -0,0 +1,298 @@
+//! Idempotent job submission and step memoization
+//!
+//! Two mechanisms keep side effects from happening twice:
+//!
+//! - A submission may carry an `idempotency_key` naming the business
+//!   operation (e.g. `contact-verification:{id}`). The first submission
+//!   claims the key; later ones are dropped until the claim expires.
+//! - Handlers wrap side effects in [`JobContext::once`](crate::JobContext::once).
+//!   The step's result is saved under the job id, so a retry or a
+//!   dead-letter replay (both keep the job id) returns the saved result
+//!   instead of sending the email or writing the file again.
+
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Double, Text};
+use diesel::{OptionalExtension, RunQueryDsl};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Claim a key unless an unexpired claim exists; returns the job id on success
+pub const CLAIM_KEY_QUERY: &str = r#"
+INSERT INTO jobs.idempotency_keys (key, job_id, created_at, expires_at)
+VALUES ($1, $2, NOW(), NOW() + make_interval(secs => $3))
+ON CONFLICT (key) DO UPDATE SET
+    job_id = EXCLUDED.job_id,
+    created_at = EXCLUDED.created_at,
+    expires_at = EXCLUDED.expires_at
+WHERE jobs.idempotency_keys.expires_at <= NOW()
+RETURNING job_id
+"#;
+
+/// Job holding an existing claim
+pub const CLAIMED_KEY_QUERY: &str = "SELECT job_id FROM jobs.idempotency_keys WHERE key = $1";
+
+/// Saved result of a completed step
+pub const STEP_RESULT_QUERY: &str =
+    "SELECT result::text AS result FROM jobs.job_step_results WHERE job_id = $1 AND step = $2";
+
+/// Save the result of a completed step
+pub const SAVE_STEP_RESULT_QUERY: &str = r#"
+INSERT INTO jobs.job_step_results (job_id, step, result, completed_at)
+VALUES ($1, $2, $3::jsonb, NOW())
+ON CONFLICT (job_id, step) DO NOTHING
+"#;
+
+/// Outcome of claiming an idempotency key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    /// The key is now held by the submitted job
+    Acquired,
+    /// Another submission holds the key
+    Duplicate { job_id: Uuid },
+}
+
+/// Storage for idempotency keys and step results
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim `key` for `job_id` for `ttl_secs`, unless it is already held.
+    /// Claiming a key again for the job that holds it is `Duplicate`.
+    async fn claim(&self, key: &str, job_id: Uuid, ttl_secs: u64) -> JobResult<Claim>;
+
+    /// Saved result of a step of a job
+    async fn step_result(&self, job_id: Uuid, step: &str) -> JobResult<Option<Value>>;
+
+    /// Save the result of a completed step; the first saved result wins
+    async fn save_step_result(&self, job_id: Uuid, step: &str, result: &Value) -> JobResult<()>;
+}
+
+/// Step results available to a running job
+///
+/// Without a store every step runs; handlers behave as before.
+#[derive(Clone, Default)]
+pub struct StepResults {
+    store: Option<Arc<dyn IdempotencyStore>>,
+}
+
+impl StepResults {
+    /// Step results kept in a store
+    pub fn new(store: Arc<dyn IdempotencyStore>) -> Self {
+        Self { store: Some(store) }
+    }
+
+    pub fn store(&self) -> Option<&Arc<dyn IdempotencyStore>> {
+        self.store.as_ref()
+    }
+}
+
+impl fmt::Debug for StepResults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StepResults")
+            .field("enabled", &self.store.is_some())
+            .finish()
+    }
+}
+
+/// Idempotency keys and step results in the `jobs` schema
+pub struct DatabaseIdempotencyStore {
+    pool: Pool,
+}
+
+impl DatabaseIdempotencyStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ClaimRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    job_id: Uuid,
+}
+
+#[derive(diesel::QueryableByName)]
+struct StepResultRow {
+    #[diesel(sql_type = Text)]
+    result: String,
+}
+
+#[async_trait]
+impl IdempotencyStore for DatabaseIdempotencyStore {
+    async fn claim(&self, key: &str, job_id: Uuid, ttl_secs: u64) -> JobResult<Claim> {
+        let conn = self.connection().await?;
+        let key = key.to_string();
+
+        // The job already holding the key, if it was not claimed
+        let holder: Option<Uuid> = conn
+            .interact(move |conn| {
+                let claimed = diesel::sql_query(CLAIM_KEY_QUERY)
+                    .bind::<Text, _>(&key)
+                    .bind::<diesel::sql_types::Uuid, _>(job_id)
+                    .bind::<Double, _>(ttl_secs as f64)
+                    .get_result::<ClaimRow>(conn)
+                    .optional()?;
+                match claimed {
+                    Some(_) => Ok(None),
+                    None => diesel::sql_query(CLAIMED_KEY_QUERY)
+                        .bind::<Text, _>(&key)
+                        .get_result::<ClaimRow>(conn)
+                        .map(|row| Some(row.job_id)),
+                }
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(match holder {
+            None => Claim::Acquired,
+            Some(job_id) => Claim::Duplicate { job_id },
+        })
+    }
+
+    async fn step_result(&self, job_id: Uuid, step: &str) -> JobResult<Option<Value>> {
+        let conn = self.connection().await?;
+        let step = step.to_string();
+
+        let row = conn
+            .interact(move |conn| {
+                diesel::sql_query(STEP_RESULT_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(job_id)
+                    .bind::<Text, _>(&step)
+                    .get_result::<StepResultRow>(conn)
+                    .optional()
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        row.map(|row| serde_json::from_str(&row.result))
+            .transpose()
+            .map_err(|e| JobError::SerializationError(e.to_string()))
+    }
+
+    async fn save_step_result(&self, job_id: Uuid, step: &str, result: &Value) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let step = step.to_string();
+        let result = result.to_string();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(SAVE_STEP_RESULT_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(job_id)
+                .bind::<Text, _>(&step)
+                .bind::<Text, _>(&result)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Keys and step results kept in memory, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryIdempotencyStore {
+    keys: std::sync::Mutex<std::collections::HashMap<String, Uuid>>,
+    steps: std::sync::Mutex<std::collections::HashMap<(Uuid, String), Value>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn claim(&self, key: &str, job_id: Uuid, _ttl_secs: u64) -> JobResult<Claim> {
+        let mut keys = self.keys.lock().unwrap();
+        match keys.get(key) {
+            Some(existing) => Ok(Claim::Duplicate { job_id: *existing }),
+            None => {
+                keys.insert(key.to_string(), job_id);
+                Ok(Claim::Acquired)
+            }
+        }
+    }
+
+    async fn step_result(&self, job_id: Uuid, step: &str) -> JobResult<Option<Value>> {
+        Ok(self.steps.lock().unwrap().get(&(job_id, step.to_string())).cloned())
+    }
+
+    async fn save_step_result(&self, job_id: Uuid, step: &str, result: &Value) -> JobResult<()> {
+        self.steps
+            .lock()
+            .unwrap()
+            .entry((job_id, step.to_string()))
+            .or_insert_with(|| result.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JobContext;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_once_skips_completed_steps() {
+        let store: Arc<dyn IdempotencyStore> = Arc::new(MemoryIdempotencyStore::default());
+        let job_id = Uuid::new_v4();
+        let sends = AtomicU32::new(0);
+        let send = || async {
+            sends.fetch_add(1, Ordering::SeqCst);
+            Ok("sent".to_string())
+        };
+
+        // Two attempts of the same job send once
+        for _ in 0..2 {
+            let context = JobContext::new(job_id).with_step_results(StepResults::new(store.clone()));
+            assert_eq!(context.once("deliver", send).await.unwrap(), "sent");
+        }
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+
+        // Another job runs the step itself
+        let context = JobContext::new(Uuid::new_v4()).with_step_results(StepResults::new(store));
+        context.once("deliver", send).await.unwrap();
+        assert_eq!(sends.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_steps_run_again() {
+        let store: Arc<dyn IdempotencyStore> = Arc::new(MemoryIdempotencyStore::default());
+        let context = JobContext::new(Uuid::new_v4()).with_step_results(StepResults::new(store));
+
+        let failed: JobResult<String> = context
+            .once("deliver", || async { Err(JobError::NetworkError("reset".to_string())) })
+            .await;
+        assert!(failed.is_err());
+
+        let retried = context.once("deliver", || async { Ok("sent".to_string()) }).await;
+        assert_eq!(retried.unwrap(), "sent");
+    }
+
+    #[tokio::test]
+    async fn test_once_without_store_always_runs() {
+        let context = JobContext::new(Uuid::new_v4());
+        let runs = AtomicU32::new(0);
+        for _ in 0..2 {
+            context
+                .once("deliver", || async {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
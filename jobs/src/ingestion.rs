@@ -0,0 +1,618 @@
+//! Batch file ingestion from SFTP and local directories
+//!
+//! Labs and other partners drop CSV, HL7, FHIR and XML files into an inbox.
+//! [`IngestionWatcher`] scans each configured inbox and, once a file has
+//! stopped changing, moves it into `staging/` and builds a
+//! [`DataImportJob`] carrying its [`ImportProvenance`]. When the import
+//! finishes for good the worker calls [`IngestionWatcher::finish`], which
+//! moves the file on to `processed/` or `error/`.
+//!
+//! ```text
+//! <inbox>/results.csv
+//! <inbox>/staging/20240105T101500Z_results.csv
+//! <inbox>/processed/20240105T101500Z_results.csv
+//! ```
+//!
+//! Staged names are prefixed with the time the file was picked up, so a
+//! partner can drop the same file name every day. Files still in `staging/`
+//! when the worker starts are picked up again by [`IngestionWatcher::recover`].
+
+use crate::config::{IngestionConfig, IngestionSourceConfig};
+use crate::types::{DataImportJob, ImportFormat, ImportProvenance, JobType};
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Default SFTP port
+const SFTP_PORT: u16 = 22;
+
+/// Suffixes of files a sender is still writing
+const PARTIAL_SUFFIXES: [&str; 3] = [".part", ".tmp", ".filepart"];
+
+/// Folder a file is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Inbox,
+    Staging,
+    Processed,
+    Error,
+}
+
+impl Stage {
+    /// Folder relative to the inbox
+    pub fn folder(&self) -> Option<&'static str> {
+        match self {
+            Stage::Inbox => None,
+            Stage::Staging => Some("staging"),
+            Stage::Processed => Some("processed"),
+            Stage::Error => Some("error"),
+        }
+    }
+
+    fn path(&self, root: &Path, name: &str) -> PathBuf {
+        match self.folder() {
+            Some(folder) => root.join(folder).join(name),
+            None => root.join(name),
+        }
+    }
+}
+
+/// A file found in a folder
+#[derive(Debug, Clone)]
+pub struct IngestionFile {
+    pub name: String,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/// Directory holding an inbox and its stage folders
+#[async_trait]
+pub trait IngestionDirectory: Send + Sync {
+    /// Regular files in a folder
+    async fn list(&self, stage: Stage) -> JobResult<Vec<IngestionFile>>;
+
+    /// Move a file between folders, creating the target folder if needed
+    async fn rename(&self, from: Stage, from_name: &str, to: Stage, to_name: &str) -> JobResult<()>;
+
+    /// Contents of a file
+    async fn read(&self, stage: Stage, name: &str) -> JobResult<Vec<u8>>;
+
+    /// Location of a file as given to the import job
+    fn location(&self, stage: Stage, name: &str) -> String;
+}
+
+/// Import format for a file name, from its extension
+pub fn import_format(name: &str) -> Option<ImportFormat> {
+    let extension = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "csv" => Some(ImportFormat::Csv),
+        "hl7" => Some(ImportFormat::Hl7),
+        "ndjson" => Some(ImportFormat::Fhir),
+        "json" => Some(ImportFormat::Json),
+        "xml" => Some(ImportFormat::Xml),
+        _ => None,
+    }
+}
+
+/// Files that are hidden or still being written are not picked up
+fn is_candidate(name: &str) -> bool {
+    !name.starts_with('.') && !PARTIAL_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Name of a file in the stage folders
+fn staged_name(name: &str, received_at: DateTime<Utc>) -> String {
+    format!("{}_{}", received_at.format("%Y%m%dT%H%M%SZ"), name)
+}
+
+/// Name a file was dropped under, from its staged name
+fn original_name(staged: &str) -> &str {
+    staged.split_once('_').map(|(_, name)| name).unwrap_or(staged)
+}
+
+/// A local directory
+pub struct LocalDirectory {
+    root: PathBuf,
+}
+
+impl LocalDirectory {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+fn io_error(e: std::io::Error) -> JobError {
+    JobError::ExternalServiceError(format!("Ingestion directory: {}", e))
+}
+
+#[async_trait]
+impl IngestionDirectory for LocalDirectory {
+    async fn list(&self, stage: Stage) -> JobResult<Vec<IngestionFile>> {
+        let dir = match stage.folder() {
+            Some(folder) => self.root.join(folder),
+            None => self.root.clone(),
+        };
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            // Stage folders are created on first use
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && stage != Stage::Inbox => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let metadata = entry.metadata().await.map_err(io_error)?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if metadata.is_file() {
+                files.push(IngestionFile {
+                    name,
+                    size_bytes: metadata.len(),
+                    modified: metadata.modified().map(DateTime::<Utc>::from).map_err(io_error)?,
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    async fn rename(&self, from: Stage, from_name: &str, to: Stage, to_name: &str) -> JobResult<()> {
+        let target = to.path(&self.root, to_name);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+        tokio::fs::rename(from.path(&self.root, from_name), target)
+            .await
+            .map_err(io_error)
+    }
+
+    async fn read(&self, stage: Stage, name: &str) -> JobResult<Vec<u8>> {
+        tokio::fs::read(stage.path(&self.root, name)).await.map_err(io_error)
+    }
+
+    fn location(&self, stage: Stage, name: &str) -> String {
+        stage.path(&self.root, name).display().to_string()
+    }
+}
+
+/// Connection details of an SFTP inbox
+#[derive(Clone)]
+struct SftpSettings {
+    host: String,
+    port: u16,
+    username: String,
+    password: Option<String>,
+    private_key_path: Option<String>,
+    root: PathBuf,
+}
+
+/// A directory on an SFTP server
+///
+/// Each operation opens its own session on the blocking thread pool; scans
+/// are infrequent and a session left open would outlive server timeouts.
+pub struct SftpDirectory {
+    settings: Arc<SftpSettings>,
+}
+
+impl SftpDirectory {
+    /// Directory at `sftp://user@host[:port]/path`
+    pub fn new(location: &str, password: Option<String>, private_key_path: Option<String>) -> JobResult<Self> {
+        let url = reqwest::Url::parse(location)
+            .map_err(|e| JobError::ConfigurationError(format!("Invalid SFTP location '{}': {}", location, e)))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| JobError::ConfigurationError(format!("SFTP location '{}' has no host", location)))?;
+        if url.username().is_empty() {
+            return Err(JobError::ConfigurationError(format!("SFTP location '{}' has no user", location)));
+        }
+
+        Ok(Self {
+            settings: Arc::new(SftpSettings {
+                host: host.to_string(),
+                port: url.port().unwrap_or(SFTP_PORT),
+                username: url.username().to_string(),
+                password,
+                private_key_path,
+                root: PathBuf::from(url.path()),
+            }),
+        })
+    }
+
+    /// Run an operation with a fresh SFTP session
+    async fn with_sftp<T, F>(&self, operation: F) -> JobResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ssh2::Sftp, &SftpSettings) -> JobResult<T> + Send + 'static,
+    {
+        let settings = self.settings.clone();
+        tokio::task::spawn_blocking(move || {
+            let sftp = connect(&settings)?;
+            operation(&sftp, &settings)
+        })
+        .await
+        .map_err(|e| JobError::UnknownError(e.to_string()))?
+    }
+}
+
+fn sftp_error(e: ssh2::Error) -> JobError {
+    JobError::ExternalServiceError(format!("SFTP: {}", e))
+}
+
+fn connect(settings: &SftpSettings) -> JobResult<ssh2::Sftp> {
+    let tcp = std::net::TcpStream::connect((settings.host.as_str(), settings.port))
+        .map_err(|e| JobError::NetworkError(format!("SFTP connect to {}: {}", settings.host, e)))?;
+
+    let mut session = ssh2::Session::new().map_err(sftp_error)?;
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(sftp_error)?;
+    match (&settings.private_key_path, &settings.password) {
+        (Some(key), _) => session.userauth_pubkey_file(&settings.username, None, Path::new(key), None),
+        (None, Some(password)) => session.userauth_password(&settings.username, password),
+        (None, None) => {
+            return Err(JobError::ConfigurationError(format!(
+                "No SFTP credentials for {}",
+                settings.host
+            )))
+        }
+    }
+    .map_err(sftp_error)?;
+
+    session.sftp().map_err(sftp_error)
+}
+
+#[async_trait]
+impl IngestionDirectory for SftpDirectory {
+    async fn list(&self, stage: Stage) -> JobResult<Vec<IngestionFile>> {
+        self.with_sftp(move |sftp, settings| {
+            let dir = match stage.folder() {
+                Some(folder) => settings.root.join(folder),
+                None => settings.root.clone(),
+            };
+            if stage != Stage::Inbox && sftp.stat(&dir).is_err() {
+                return Ok(Vec::new());
+            }
+
+            let files = sftp
+                .readdir(&dir)
+                .map_err(sftp_error)?
+                .into_iter()
+                .filter(|(_, stat)| stat.is_file())
+                .filter_map(|(path, stat)| {
+                    let name = path.file_name()?.to_str()?.to_string();
+                    Some(IngestionFile {
+                        name,
+                        size_bytes: stat.size.unwrap_or(0),
+                        modified: DateTime::<Utc>::from_timestamp(stat.mtime.unwrap_or(0) as i64, 0).unwrap_or_default(),
+                    })
+                })
+                .collect();
+            Ok(files)
+        })
+        .await
+    }
+
+    async fn rename(&self, from: Stage, from_name: &str, to: Stage, to_name: &str) -> JobResult<()> {
+        let (from_name, to_name) = (from_name.to_string(), to_name.to_string());
+        self.with_sftp(move |sftp, settings| {
+            let target = to.path(&settings.root, &to_name);
+            if let Some(parent) = target.parent() {
+                if sftp.stat(parent).is_err() {
+                    sftp.mkdir(parent, 0o750).map_err(sftp_error)?;
+                }
+            }
+            sftp.rename(&from.path(&settings.root, &from_name), &target, None)
+                .map_err(sftp_error)
+        })
+        .await
+    }
+
+    async fn read(&self, stage: Stage, name: &str) -> JobResult<Vec<u8>> {
+        let name = name.to_string();
+        self.with_sftp(move |sftp, settings| {
+            use std::io::Read;
+            let mut file = sftp.open(stage.path(&settings.root, &name)).map_err(sftp_error)?;
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).map_err(io_error)?;
+            Ok(contents)
+        })
+        .await
+    }
+
+    fn location(&self, stage: Stage, name: &str) -> String {
+        let settings = &self.settings;
+        format!(
+            "sftp://{}@{}:{}{}",
+            settings.username,
+            settings.host,
+            settings.port,
+            stage.path(&settings.root, name).display()
+        )
+    }
+}
+
+/// An inbox being watched
+struct WatchedSource {
+    config: IngestionSourceConfig,
+    directory: Arc<dyn IngestionDirectory>,
+}
+
+/// Scans inboxes and turns new files into import jobs
+pub struct IngestionWatcher {
+    sources: Vec<WatchedSource>,
+    settle: Duration,
+}
+
+impl IngestionWatcher {
+    /// Watcher for the configured sources
+    pub fn from_config(config: &IngestionConfig) -> JobResult<Self> {
+        let mut watcher = Self::new(Duration::from_secs(config.settle_secs));
+        for source in &config.sources {
+            let directory: Arc<dyn IngestionDirectory> = if source.location.starts_with("sftp://") {
+                Arc::new(SftpDirectory::new(
+                    &source.location,
+                    source.password.clone(),
+                    source.private_key_path.clone(),
+                )?)
+            } else {
+                Arc::new(LocalDirectory::new(&source.location))
+            };
+            watcher = watcher.with_source(source.clone(), directory);
+        }
+        Ok(watcher)
+    }
+
+    /// Watcher without sources; files modified within `settle` are skipped
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            sources: Vec::new(),
+            settle,
+        }
+    }
+
+    /// Watch a source in a directory
+    pub fn with_source(mut self, config: IngestionSourceConfig, directory: Arc<dyn IngestionDirectory>) -> Self {
+        self.sources.push(WatchedSource { config, directory });
+        self
+    }
+
+    /// Stage new inbox files and return their import jobs
+    ///
+    /// A source that cannot be scanned is skipped until the next poll.
+    pub async fn poll(&self) -> Vec<JobType> {
+        let settled_before = Utc::now() - chrono::Duration::from_std(self.settle).unwrap_or_default();
+        let mut jobs = Vec::new();
+
+        for source in &self.sources {
+            let files = match source.directory.list(Stage::Inbox).await {
+                Ok(files) => files,
+                Err(e) => {
+                    warn!(source = %source.config.name, error = %e, "Failed to scan ingestion inbox");
+                    continue;
+                }
+            };
+
+            for file in files {
+                if !is_candidate(&file.name) || file.modified > settled_before {
+                    continue;
+                }
+                let received_at = Utc::now();
+                let staged = staged_name(&file.name, received_at);
+
+                if import_format(&file.name).is_none() {
+                    warn!(source = %source.config.name, file = %file.name, "Unsupported ingestion file type");
+                    self.reject(source, Stage::Inbox, &file.name, &staged).await;
+                    continue;
+                }
+                if let Err(e) = source.directory.rename(Stage::Inbox, &file.name, Stage::Staging, &staged).await {
+                    warn!(source = %source.config.name, file = %file.name, error = %e, "Failed to stage ingestion file");
+                    continue;
+                }
+
+                if let Some(job) = self.import_job(source, &staged, received_at).await {
+                    info!(source = %source.config.name, file = %file.name, staged = %staged, "Ingestion file staged");
+                    jobs.push(job);
+                }
+            }
+        }
+
+        jobs
+    }
+
+    /// Import jobs for files left in staging by a previous run
+    pub async fn recover(&self) -> Vec<JobType> {
+        let mut jobs = Vec::new();
+        for source in &self.sources {
+            let files = match source.directory.list(Stage::Staging).await {
+                Ok(files) => files,
+                Err(e) => {
+                    warn!(source = %source.config.name, error = %e, "Failed to scan ingestion staging");
+                    continue;
+                }
+            };
+
+            for file in files {
+                info!(source = %source.config.name, staged = %file.name, "Resuming staged ingestion file");
+                if let Some(job) = self.import_job(source, &file.name, file.modified).await {
+                    jobs.push(job);
+                }
+            }
+        }
+        jobs
+    }
+
+    /// Move a staged file to `processed/` or `error/` once its import has
+    /// finished for good
+    pub async fn finish(&self, provenance: &ImportProvenance, succeeded: bool) {
+        let Some(source) = self.sources.iter().find(|s| s.config.name == provenance.source_name) else {
+            warn!(source = %provenance.source_name, "Import finished for an unknown ingestion source");
+            return;
+        };
+        let to = if succeeded { Stage::Processed } else { Stage::Error };
+
+        match source
+            .directory
+            .rename(Stage::Staging, &provenance.staged_name, to, &provenance.staged_name)
+            .await
+        {
+            Ok(()) => info!(source = %source.config.name, staged = %provenance.staged_name, folder = ?to.folder(), "Ingestion file finished"),
+            Err(e) => error!(source = %source.config.name, staged = %provenance.staged_name, error = %e, "Failed to move ingested file"),
+        }
+    }
+
+    /// Build the import job for a staged file; unreadable files go to `error/`
+    async fn import_job(&self, source: &WatchedSource, staged: &str, received_at: DateTime<Utc>) -> Option<JobType> {
+        let file_name = original_name(staged);
+        let Some(import_format) = import_format(file_name) else {
+            self.reject(source, Stage::Staging, staged, staged).await;
+            return None;
+        };
+
+        let contents = match source.directory.read(Stage::Staging, staged).await {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!(source = %source.config.name, staged = %staged, error = %e, "Failed to read staged file");
+                self.reject(source, Stage::Staging, staged, staged).await;
+                return None;
+            }
+        };
+
+        let provenance = ImportProvenance {
+            source_name: source.config.name.clone(),
+            source_system: source.config.source_system.clone(),
+            file_name: file_name.to_string(),
+            staged_name: staged.to_string(),
+            size_bytes: contents.len() as u64,
+            sha256: Sha256::digest(&contents).iter().map(|b| format!("{:02x}", b)).collect(),
+            received_at,
+        };
+
+        Some(JobType::DataImport(DataImportJob {
+            source_location: source.directory.location(Stage::Staging, staged),
+            import_format,
+            mapping_config: source.config.mapping_config.clone(),
+            validation_rules: Vec::new(),
+            auto_merge: source.config.auto_merge,
+            provenance: Some(provenance),
+        }))
+    }
+
+    async fn reject(&self, source: &WatchedSource, from: Stage, name: &str, staged: &str) {
+        if let Err(e) = source.directory.rename(from, name, Stage::Error, staged).await {
+            error!(source = %source.config.name, file = %name, error = %e, "Failed to move file to error folder");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, location: &Path) -> IngestionSourceConfig {
+        IngestionSourceConfig {
+            name: name.to_string(),
+            location: location.display().to_string(),
+            source_system: "acme-lis".to_string(),
+            password: None,
+            private_key_path: None,
+            mapping_config: None,
+            auto_merge: false,
+        }
+    }
+
+    fn inbox() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("emr-ingestion-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn folder(root: &Path, stage: Stage) -> Vec<String> {
+        let dir = stage.folder().map(|f| root.join(f)).unwrap_or_else(|| root.to_path_buf());
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().is_file())
+                    .map(|e| e.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_import_format() {
+        assert_eq!(import_format("results.CSV"), Some(ImportFormat::Csv));
+        assert_eq!(import_format("ORU_R01.hl7"), Some(ImportFormat::Hl7));
+        assert_eq!(import_format("Observation.ndjson"), Some(ImportFormat::Fhir));
+        assert_eq!(import_format("notes.pdf"), None);
+        assert_eq!(import_format("README"), None);
+    }
+
+    #[test]
+    fn test_staged_names() {
+        let received_at = DateTime::parse_from_rfc3339("2024-01-05T10:15:00Z").unwrap().with_timezone(&Utc);
+        let staged = staged_name("lab_results.csv", received_at);
+        assert_eq!(staged, "20240105T101500Z_lab_results.csv");
+        assert_eq!(original_name(&staged), "lab_results.csv");
+
+        assert!(is_candidate("results.csv"));
+        assert!(!is_candidate(".results.csv"));
+        assert!(!is_candidate("results.csv.part"));
+    }
+
+    #[tokio::test]
+    async fn test_files_move_through_stages() {
+        let root = inbox();
+        std::fs::write(root.join("results.csv"), "mrn,loinc,value\n123,2345-7,98\n").unwrap();
+        std::fs::write(root.join("scan.pdf"), "%PDF").unwrap();
+        std::fs::write(root.join("upload.hl7.part"), "MSH|").unwrap();
+
+        let watcher = IngestionWatcher::new(Duration::ZERO)
+            .with_source(source("acme-lab", &root), Arc::new(LocalDirectory::new(&root)));
+
+        let jobs = watcher.poll().await;
+        assert_eq!(jobs.len(), 1);
+        let JobType::DataImport(job) = &jobs[0] else {
+            panic!("expected an import job");
+        };
+        let provenance = job.provenance.clone().unwrap();
+        assert_eq!(job.import_format, ImportFormat::Csv);
+        assert_eq!(provenance.file_name, "results.csv");
+        assert_eq!(provenance.source_system, "acme-lis");
+        assert_eq!(provenance.size_bytes, 30);
+        assert_eq!(provenance.sha256.len(), 64);
+        assert!(job.source_location.ends_with(&format!("staging/{}", provenance.staged_name)));
+
+        assert_eq!(folder(&root, Stage::Inbox), vec!["upload.hl7.part"]);
+        assert_eq!(folder(&root, Stage::Staging), vec![provenance.staged_name.clone()]);
+        assert_eq!(folder(&root, Stage::Error).len(), 1);
+
+        // A restart picks the staged file up again
+        assert_eq!(watcher.recover().await.len(), 1);
+
+        watcher.finish(&provenance, true).await;
+        assert!(folder(&root, Stage::Staging).is_empty());
+        assert_eq!(folder(&root, Stage::Processed), vec![provenance.staged_name]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsettled_files_wait() {
+        let root = inbox();
+        std::fs::write(root.join("results.csv"), "mrn\n").unwrap();
+
+        let watcher = IngestionWatcher::new(Duration::from_secs(3600))
+            .with_source(source("acme-lab", &root), Arc::new(LocalDirectory::new(&root)));
+
+        assert!(watcher.poll().await.is_empty());
+        assert_eq!(folder(&root, Stage::Inbox), vec!["results.csv"]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
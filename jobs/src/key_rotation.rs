@@ -0,0 +1,865 @@
+//! Data-encryption key rotation
+//!
+//! Field-level encryption is two-tier: each value in `emr.encrypted_fields`
+//! is sealed with a data-encryption key, and each data key is kept in
+//! `emr.data_keys` wrapped by a master key from `encryption.master_keys`.
+//! A KeyRotation job re-wraps every active data key under the job's master
+//! key; with `reencrypt_fields` it also replaces each data key with a new one,
+//! re-encrypts its fields in batches and retires it, discarding the key.
+//!
+//! Every step can run again: keys already under the new master key are
+//! skipped, and each batch moves its fields to the new data key in one
+//! transaction, so a retried job carries on where the last attempt stopped.
+//! The job then checks that no active key is left under another master key
+//! and writes a signed [`RotationAttestation`] to [`attestation_path`].
+//!
+//! No writer seals fields yet: the api stores sensitive values in plain
+//! columns and cannot reach these tables, so until one does a rotation finds
+//! no data keys and attests to an empty store.
+
+use crate::config::{EncryptionConfig, ReportConfig};
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::KeyRotationJob;
+use crate::{JobContext, JobError, JobResult};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use deadpool_diesel::postgres::Pool;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Text};
+use diesel::{Connection, RunQueryDsl};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Cipher wrapping data keys and sealing fields
+pub const KEY_CIPHER: &str = "AES-256-GCM";
+
+const NONCE_LEN: usize = 12;
+
+/// Where a rotation's attestation is stored
+pub fn attestation_path(output_dir: &str, rotation_id: Uuid) -> PathBuf {
+    Path::new(output_dir)
+        .join("key-rotations")
+        .join(format!("{}.json", rotation_id))
+}
+
+fn cipher(key: &[u8]) -> JobResult<Aes256Gcm> {
+    if key.len() != 32 {
+        return Err(JobError::ValidationError("Encryption keys must be 32 bytes".to_string()));
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)))
+}
+
+/// Decode a configured master key, base64 for 32 bytes
+pub fn decode_master_key(key: &str) -> JobResult<Vec<u8>> {
+    let key = STANDARD
+        .decode(key)
+        .map_err(|e| JobError::ValidationError(format!("Master key is not base64: {}", e)))?;
+    cipher(&key)?;
+    Ok(key)
+}
+
+/// Check that a configured master key is base64 for 32 bytes
+pub fn check_master_key(key: &str) -> JobResult<()> {
+    decode_master_key(key).map(|_| ())
+}
+
+/// A new random data-encryption key
+pub fn generate_data_key() -> Vec<u8> {
+    Aes256Gcm::generate_key(&mut OsRng).to_vec()
+}
+
+/// Encrypt with `key`, returning base64 of the nonce and ciphertext; wraps
+/// data keys under master keys and seals fields under data keys
+pub fn seal(key: &[u8], plaintext: &[u8]) -> JobResult<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(key)?
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| JobError::ProcessingError("Encryption failed".to_string()))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(STANDARD.encode(sealed))
+}
+
+/// Decrypt what [`seal`] returned
+pub fn open(key: &[u8], sealed: &str) -> JobResult<Vec<u8>> {
+    let sealed = STANDARD
+        .decode(sealed)
+        .ok()
+        .filter(|sealed| sealed.len() > NONCE_LEN)
+        .ok_or_else(|| JobError::ValidationError("Ciphertext is malformed".to_string()))?;
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| JobError::ValidationError("Decryption failed; wrong key?".to_string()))
+}
+
+/// A data key, wrapped by a master key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataKey {
+    pub id: Uuid,
+    pub master_key_id: String,
+    pub wrapped_key: String,
+}
+
+/// A field value sealed with a data key
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncryptedField {
+    pub id: Uuid,
+    pub ciphertext: String,
+}
+
+/// Data keys and the fields sealed with them
+#[async_trait]
+pub trait DataKeyStore: Send + Sync {
+    /// Data keys that have not been retired
+    async fn active_keys(&self) -> JobResult<Vec<DataKey>>;
+
+    /// Store a new data key, returning its id
+    async fn create(&self, master_key_id: &str, wrapped_key: &str) -> JobResult<Uuid>;
+
+    /// Replace the wrapping of `key` unless it changed since it was read
+    async fn rewrap(&self, key: &DataKey, master_key_id: &str, wrapped_key: &str) -> JobResult<()>;
+
+    /// Up to `limit` fields sealed with a data key
+    async fn fields(&self, data_key_id: Uuid, limit: i64) -> JobResult<Vec<EncryptedField>>;
+
+    /// Number of fields sealed with a data key
+    async fn field_count(&self, data_key_id: Uuid) -> JobResult<u64>;
+
+    /// Store fields sealed with `to` that were sealed with `from`, in one
+    /// transaction
+    async fn reseal(&self, from: Uuid, to: Uuid, fields: Vec<EncryptedField>) -> JobResult<()>;
+
+    /// Retire a data key and discard it; returns false while fields still
+    /// use it
+    async fn retire(&self, data_key_id: Uuid) -> JobResult<bool>;
+}
+
+/// Data keys and fields in the `emr` schema
+pub struct DatabaseDataKeyStore {
+    pool: Pool,
+}
+
+impl DatabaseDataKeyStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct DataKeyRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    master_key_id: String,
+    #[diesel(sql_type = Text)]
+    wrapped_key: String,
+}
+
+#[derive(diesel::QueryableByName)]
+struct IdRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+}
+
+#[derive(diesel::QueryableByName)]
+struct EncryptedFieldRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    ciphertext: String,
+}
+
+#[derive(diesel::QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+#[async_trait]
+impl DataKeyStore for DatabaseDataKeyStore {
+    async fn active_keys(&self) -> JobResult<Vec<DataKey>> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(|conn| {
+                diesel::sql_query(
+                    "SELECT id, master_key_id, wrapped_key FROM emr.data_keys \
+                     WHERE retired_at IS NULL ORDER BY created_at, id",
+                )
+                .load::<DataKeyRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DataKey {
+                id: row.id,
+                master_key_id: row.master_key_id,
+                wrapped_key: row.wrapped_key,
+            })
+            .collect())
+    }
+
+    async fn create(&self, master_key_id: &str, wrapped_key: &str) -> JobResult<Uuid> {
+        let conn = self.connection().await?;
+        let (master_key_id, wrapped_key) = (master_key_id.to_string(), wrapped_key.to_string());
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query("INSERT INTO emr.data_keys (master_key_id, wrapped_key) VALUES ($1, $2) RETURNING id")
+                    .bind::<Text, _>(master_key_id)
+                    .bind::<Text, _>(wrapped_key)
+                    .load::<IdRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        rows.into_iter()
+            .next()
+            .map(|row| row.id)
+            .ok_or_else(|| JobError::DatabaseError("Data key was not stored".to_string()))
+    }
+
+    async fn rewrap(&self, key: &DataKey, master_key_id: &str, wrapped_key: &str) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let (id, previous_master_key_id) = (key.id, key.master_key_id.clone());
+        let (master_key_id, wrapped_key) = (master_key_id.to_string(), wrapped_key.to_string());
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "UPDATE emr.data_keys SET master_key_id = $2, wrapped_key = $3, rotated_at = NOW() \
+                 WHERE id = $1 AND master_key_id = $4 AND retired_at IS NULL",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(id)
+            .bind::<Text, _>(master_key_id)
+            .bind::<Text, _>(wrapped_key)
+            .bind::<Text, _>(previous_master_key_id)
+            .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn fields(&self, data_key_id: Uuid, limit: i64) -> JobResult<Vec<EncryptedField>> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, ciphertext FROM emr.encrypted_fields WHERE data_key_id = $1 ORDER BY id LIMIT $2",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(data_key_id)
+                .bind::<BigInt, _>(limit)
+                .load::<EncryptedFieldRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| EncryptedField {
+                id: row.id,
+                ciphertext: row.ciphertext,
+            })
+            .collect())
+    }
+
+    async fn field_count(&self, data_key_id: Uuid) -> JobResult<u64> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query("SELECT COUNT(*) AS count FROM emr.encrypted_fields WHERE data_key_id = $1")
+                    .bind::<diesel::sql_types::Uuid, _>(data_key_id)
+                    .load::<CountRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.first().map_or(0, |row| row.count as u64))
+    }
+
+    async fn reseal(&self, from: Uuid, to: Uuid, fields: Vec<EncryptedField>) -> JobResult<()> {
+        let conn = self.connection().await?;
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                for field in fields {
+                    diesel::sql_query(
+                        "UPDATE emr.encrypted_fields SET data_key_id = $3, ciphertext = $2, updated_at = NOW() \
+                         WHERE id = $1 AND data_key_id = $4",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(field.id)
+                    .bind::<Text, _>(field.ciphertext)
+                    .bind::<diesel::sql_types::Uuid, _>(to)
+                    .bind::<diesel::sql_types::Uuid, _>(from)
+                    .execute(conn)?;
+                }
+                Ok::<_, DieselError>(())
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn retire(&self, data_key_id: Uuid) -> JobResult<bool> {
+        let conn = self.connection().await?;
+        let retired = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.data_keys SET wrapped_key = NULL, retired_at = NOW() \
+                     WHERE id = $1 AND retired_at IS NULL \
+                     AND NOT EXISTS (SELECT 1 FROM emr.encrypted_fields WHERE data_key_id = $1)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(data_key_id)
+                .execute(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        Ok(retired == 1)
+    }
+}
+
+/// Record that a rotation completed, for compliance review
+///
+/// `signature` is the hex HMAC-SHA256 of the attestation serialized with an
+/// empty signature, under a key derived from the new master key, so anyone
+/// holding that key can check the file was not altered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RotationAttestation {
+    pub rotation_id: Uuid,
+    pub job_id: Uuid,
+    /// Master key every active data key is now wrapped under
+    pub master_key_id: String,
+    /// Master keys data keys were wrapped under when the rotation began
+    pub previous_master_key_ids: Vec<String>,
+    pub cipher: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+    /// Data keys moved to the new master key
+    pub keys_rewrapped: usize,
+    /// Data keys replaced by new ones and retired
+    pub keys_replaced: usize,
+    /// Fields re-encrypted under the replacement data keys
+    pub fields_reencrypted: u64,
+    /// Active data keys when the rotation completed
+    pub active_keys: usize,
+    pub signature: String,
+}
+
+impl RotationAttestation {
+    fn mac(&self, master_key: &[u8]) -> JobResult<Hmac<Sha256>> {
+        // A key of its own, so the master key is only used for wrapping
+        let mut derive = <Hmac<Sha256> as Mac>::new_from_slice(master_key).expect("HMAC accepts keys of any length");
+        derive.update(b"emr key rotation attestation");
+        let key = derive.finalize().into_bytes();
+
+        let unsigned = RotationAttestation {
+            signature: String::new(),
+            ..self.clone()
+        };
+        let body = serde_json::to_vec(&unsigned).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC accepts keys of any length");
+        mac.update(&body);
+        Ok(mac)
+    }
+
+    /// Sign the attestation with the new master key
+    pub fn sign(mut self, master_key: &[u8]) -> JobResult<Self> {
+        self.signature = self
+            .mac(master_key)?
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Ok(self)
+    }
+
+    /// Whether the signature matches the attestation under `master_key`
+    pub fn verify(&self, master_key: &[u8]) -> bool {
+        let Some(signature) = (0..self.signature.len())
+            .step_by(2)
+            .map(|i| self.signature.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+        else {
+            return false;
+        };
+        self.mac(master_key).is_ok_and(|mac| mac.verify_slice(&signature).is_ok())
+    }
+}
+
+/// A data key as the rotation found it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlannedKey {
+    id: Uuid,
+    master_key_id: String,
+}
+
+/// Rotates data keys to a new master key
+pub struct KeyRotationHandler {
+    encryption: EncryptionConfig,
+    reports: ReportConfig,
+    store: Arc<dyn DataKeyStore>,
+}
+
+impl KeyRotationHandler {
+    /// Create a handler rotating the keys in `store`, writing attestations to
+    /// `reports.output_dir`
+    pub fn new(encryption: EncryptionConfig, reports: ReportConfig, store: Arc<dyn DataKeyStore>) -> Self {
+        Self {
+            encryption,
+            reports,
+            store,
+        }
+    }
+
+    fn master_key(&self, id: &str) -> JobResult<Vec<u8>> {
+        let key = self
+            .encryption
+            .master_keys
+            .get(id)
+            .ok_or_else(|| JobError::ConfigurationError(format!("Master key {} is not configured", id)))?;
+        decode_master_key(key)
+    }
+
+    /// Unwrap a data key with the master key it is wrapped under
+    fn unwrap_key(&self, key: &DataKey) -> JobResult<Vec<u8>> {
+        open(&self.master_key(&key.master_key_id)?, &key.wrapped_key)
+    }
+
+    /// Re-wrap the active keys not yet under `master_key_id`
+    async fn rewrap_keys(&self, master_key_id: &str, master_key: &[u8]) -> JobResult<()> {
+        for key in self.store.active_keys().await? {
+            if key.master_key_id != master_key_id {
+                let wrapped_key = seal(master_key, &self.unwrap_key(&key)?)?;
+                self.store.rewrap(&key, master_key_id, &wrapped_key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the fields of data key `old` to a new data key and retire `old`,
+    /// returning the new key's id and how many fields it seals
+    async fn replace_key(
+        &self,
+        job: &KeyRotationJob,
+        master_key: &[u8],
+        old: Uuid,
+        context: &JobContext,
+    ) -> JobResult<(Uuid, u64)> {
+        // Saved so a retry keeps sealing with the same replacement
+        let new = context
+            .once(&format!("data-key:{}", old), || async {
+                let wrapped_key = seal(master_key, &generate_data_key())?;
+                self.store.create(&job.master_key_id, &wrapped_key).await
+            })
+            .await?;
+
+        let active = self.store.active_keys().await?;
+        let new_key = active
+            .iter()
+            .find(|key| key.id == new)
+            .ok_or_else(|| JobError::ProcessingError(format!("Replacement data key {} is missing", new)))?;
+        let Some(old_key) = active.iter().find(|key| key.id == old) else {
+            // Retired by an earlier attempt
+            return Ok((new, self.store.field_count(new).await?));
+        };
+        let old_data_key = self.unwrap_key(old_key)?;
+        let new_data_key = self.unwrap_key(new_key)?;
+
+        loop {
+            context.check_cancelled()?;
+            let batch = self.store.fields(old, i64::from(job.batch_size)).await?;
+            if batch.is_empty() {
+                break;
+            }
+            let resealed = batch
+                .into_iter()
+                .map(|field| {
+                    let plaintext = open(&old_data_key, &field.ciphertext)?;
+                    Ok(EncryptedField {
+                        id: field.id,
+                        ciphertext: seal(&new_data_key, &plaintext)?,
+                    })
+                })
+                .collect::<JobResult<Vec<_>>>()?;
+            self.store.reseal(old, new, resealed).await?;
+        }
+
+        if !self.store.retire(old).await? {
+            return Err(JobError::ProcessingError(format!(
+                "Data key {} gained fields during its replacement",
+                old
+            )));
+        }
+        Ok((new, self.store.field_count(new).await?))
+    }
+}
+
+#[async_trait]
+impl JobHandler<KeyRotationJob> for KeyRotationHandler {
+    async fn execute(&self, job: KeyRotationJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(job_id = ?context.job_id, rotation_id = ?job.rotation_id, "Starting key rotation job");
+
+        if job.batch_size == 0 {
+            return Ok(JobExecutionResult::failure("Key rotation batch size must be greater than 0".to_string()));
+        }
+        let master_key = match self.master_key(&job.master_key_id) {
+            Ok(master_key) => master_key,
+            Err(e) => return Ok(JobExecutionResult::failure(e.to_string())),
+        };
+
+        // The keys in use when the rotation began, kept across attempts so
+        // replacement keys are not replaced again
+        let started_at = context.once("started", || async { Ok(Utc::now()) }).await?;
+        let planned: Vec<PlannedKey> = context
+            .once("plan", || async {
+                Ok(self
+                    .store
+                    .active_keys()
+                    .await?
+                    .into_iter()
+                    .map(|key| PlannedKey {
+                        id: key.id,
+                        master_key_id: key.master_key_id,
+                    })
+                    .collect())
+            })
+            .await?;
+        let steps = if job.reencrypt_fields { planned.len() + 1 } else { 1 };
+
+        context.check_cancelled()?;
+        self.rewrap_keys(&job.master_key_id, &master_key).await?;
+        context.progress.step(1, steps);
+
+        let mut fields_reencrypted = 0;
+        if job.reencrypt_fields {
+            for (done, key) in planned.iter().enumerate() {
+                let (new, fields) = context
+                    .once(&format!("replace:{}", key.id), || {
+                        self.replace_key(&job, &master_key, key.id, &context)
+                    })
+                    .await?;
+                info!(job_id = ?context.job_id, old = ?key.id, new = ?new, fields, "Data key replaced");
+                fields_reencrypted += fields;
+                context.progress.step(done + 2, steps);
+            }
+        }
+
+        // Keys created under an old master key while the rotation ran
+        // are re-wrapped too; anything left means the rotation is incomplete
+        self.rewrap_keys(&job.master_key_id, &master_key).await?;
+        let active = self.store.active_keys().await?;
+        let remaining = active.iter().filter(|key| key.master_key_id != job.master_key_id).count();
+        if remaining > 0 {
+            return Ok(JobExecutionResult::failure(format!(
+                "{} data keys are still wrapped under another master key",
+                remaining
+            )));
+        }
+
+        let mut previous_master_key_ids: Vec<String> = planned
+            .iter()
+            .map(|key| key.master_key_id.clone())
+            .filter(|id| *id != job.master_key_id)
+            .collect();
+        previous_master_key_ids.sort();
+        previous_master_key_ids.dedup();
+
+        let attestation = RotationAttestation {
+            rotation_id: job.rotation_id,
+            job_id: context.job_id,
+            master_key_id: job.master_key_id.clone(),
+            previous_master_key_ids,
+            cipher: KEY_CIPHER.to_string(),
+            started_at,
+            completed_at: Utc::now(),
+            keys_rewrapped: planned.iter().filter(|key| key.master_key_id != job.master_key_id).count(),
+            keys_replaced: if job.reencrypt_fields { planned.len() } else { 0 },
+            fields_reencrypted,
+            active_keys: active.len(),
+            signature: String::new(),
+        }
+        .sign(&master_key)?;
+
+        let path = attestation_path(&self.reports.output_dir, job.rotation_id);
+        if let Some(directory) = path.parent() {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .map_err(|e| JobError::ProcessingError(format!("Failed to create attestation directory: {}", e)))?;
+        }
+        let body =
+            serde_json::to_vec_pretty(&attestation).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        tokio::fs::write(&path, body)
+            .await
+            .map_err(|e| JobError::ProcessingError(format!("Failed to store attestation: {}", e)))?;
+
+        Ok(JobExecutionResult::success_with_data(
+            format!("Data keys rotated to master key {}", job.master_key_id),
+            serde_json::to_value(&attestation).map_err(|e| JobError::SerializationError(e.to_string()))?,
+        )
+        .with_metric("keys_rewrapped".to_string(), attestation.keys_rewrapped as f64)
+        .with_metric("fields_reencrypted".to_string(), fields_reencrypted as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "KeyRotation"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idempotency::{IdempotencyStore, MemoryIdempotencyStore};
+    use crate::StepResults;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    /// Data keys and fields kept in memory, for tests
+    #[derive(Default)]
+    struct MemoryDataKeyStore {
+        /// `(key, retired)`
+        keys: Mutex<Vec<(DataKey, bool)>>,
+        /// `(field, data key)`
+        fields: Mutex<Vec<(EncryptedField, Uuid)>>,
+        /// Fail `reseal` after this many batches
+        fail_after: Mutex<Option<usize>>,
+    }
+
+    #[async_trait]
+    impl DataKeyStore for MemoryDataKeyStore {
+        async fn active_keys(&self) -> JobResult<Vec<DataKey>> {
+            let keys = self.keys.lock().unwrap();
+            Ok(keys.iter().filter(|(_, retired)| !retired).map(|(key, _)| key.clone()).collect())
+        }
+
+        async fn create(&self, master_key_id: &str, wrapped_key: &str) -> JobResult<Uuid> {
+            let key = DataKey {
+                id: Uuid::new_v4(),
+                master_key_id: master_key_id.to_string(),
+                wrapped_key: wrapped_key.to_string(),
+            };
+            let id = key.id;
+            self.keys.lock().unwrap().push((key, false));
+            Ok(id)
+        }
+
+        async fn rewrap(&self, key: &DataKey, master_key_id: &str, wrapped_key: &str) -> JobResult<()> {
+            let mut keys = self.keys.lock().unwrap();
+            if let Some((stored, _)) = keys.iter_mut().find(|(stored, _)| stored == key) {
+                stored.master_key_id = master_key_id.to_string();
+                stored.wrapped_key = wrapped_key.to_string();
+            }
+            Ok(())
+        }
+
+        async fn fields(&self, data_key_id: Uuid, limit: i64) -> JobResult<Vec<EncryptedField>> {
+            let fields = self.fields.lock().unwrap();
+            Ok(fields
+                .iter()
+                .filter(|(_, key)| *key == data_key_id)
+                .take(limit as usize)
+                .map(|(field, _)| field.clone())
+                .collect())
+        }
+
+        async fn field_count(&self, data_key_id: Uuid) -> JobResult<u64> {
+            Ok(self.fields.lock().unwrap().iter().filter(|(_, key)| *key == data_key_id).count() as u64)
+        }
+
+        async fn reseal(&self, from: Uuid, to: Uuid, resealed: Vec<EncryptedField>) -> JobResult<()> {
+            if let Some(remaining) = self.fail_after.lock().unwrap().as_mut() {
+                if *remaining == 0 {
+                    return Err(JobError::DatabaseError("connection reset".to_string()));
+                }
+                *remaining -= 1;
+            }
+            let mut fields = self.fields.lock().unwrap();
+            for field in resealed {
+                if let Some(stored) = fields.iter_mut().find(|(stored, key)| stored.id == field.id && *key == from) {
+                    *stored = (field, to);
+                }
+            }
+            Ok(())
+        }
+
+        async fn retire(&self, data_key_id: Uuid) -> JobResult<bool> {
+            if self.fields.lock().unwrap().iter().any(|(_, key)| *key == data_key_id) {
+                return Ok(false);
+            }
+            let mut keys = self.keys.lock().unwrap();
+            if let Some((key, retired)) = keys.iter_mut().find(|(key, _)| key.id == data_key_id) {
+                key.wrapped_key.clear();
+                *retired = true;
+            }
+            Ok(true)
+        }
+    }
+
+    fn master_key(byte: u8) -> String {
+        STANDARD.encode([byte; 32])
+    }
+
+    /// Handler with master keys `old` and `new`, and a store holding two data
+    /// keys under `old` sealing three fields
+    async fn setup(output_dir: &Path) -> (KeyRotationHandler, Arc<MemoryDataKeyStore>) {
+        let encryption = EncryptionConfig {
+            master_keys: BTreeMap::from([("old".to_string(), master_key(1)), ("new".to_string(), master_key(2))]),
+        };
+        let reports = ReportConfig {
+            output_dir: output_dir.display().to_string(),
+        };
+        let store = Arc::new(MemoryDataKeyStore::default());
+        let old_master = decode_master_key(&master_key(1)).unwrap();
+        for values in [vec!["123-45-6789", "555-0100"], vec!["A1234567"]] {
+            let data_key = generate_data_key();
+            let id = store.create("old", &seal(&old_master, &data_key).unwrap()).await.unwrap();
+            for value in values {
+                let field = EncryptedField {
+                    id: Uuid::new_v4(),
+                    ciphertext: seal(&data_key, value.as_bytes()).unwrap(),
+                };
+                store.fields.lock().unwrap().push((field, id));
+            }
+        }
+        (KeyRotationHandler::new(encryption, reports, store.clone()), store)
+    }
+
+    fn job(reencrypt_fields: bool) -> KeyRotationJob {
+        KeyRotationJob {
+            rotation_id: Uuid::new_v4(),
+            master_key_id: "new".to_string(),
+            reencrypt_fields,
+            batch_size: 1,
+        }
+    }
+
+    /// Every field's value, opened through its data key and the new master key
+    fn plaintexts(store: &MemoryDataKeyStore) -> Vec<String> {
+        let new_master = decode_master_key(&master_key(2)).unwrap();
+        let keys = store.keys.lock().unwrap();
+        let mut values: Vec<String> = store
+            .fields
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(field, key_id)| {
+                let (key, _) = keys.iter().find(|(key, _)| key.id == *key_id).unwrap();
+                let data_key = open(&new_master, &key.wrapped_key).unwrap();
+                String::from_utf8(open(&data_key, &field.ciphertext).unwrap()).unwrap()
+            })
+            .collect();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = generate_data_key();
+        let sealed = seal(&key, b"123-45-6789").unwrap();
+        assert_eq!(open(&key, &sealed).unwrap(), b"123-45-6789");
+        assert!(open(&generate_data_key(), &sealed).is_err());
+        assert!(open(&key, "bm9ub25jZQ==").is_err());
+        assert!(check_master_key(&master_key(1)).is_ok());
+        assert!(check_master_key(&STANDARD.encode([1u8; 16])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rewrap_keeps_fields() {
+        let output_dir = std::env::temp_dir().join(format!("key-rotation-{}", Uuid::new_v4()));
+        let (handler, store) = setup(&output_dir).await;
+        let ciphertexts: Vec<String> =
+            store.fields.lock().unwrap().iter().map(|(field, _)| field.ciphertext.clone()).collect();
+        let job = job(false);
+
+        let result = handler.execute(job.clone(), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.metrics["keys_rewrapped"], 2.0);
+        assert!(store.active_keys().await.unwrap().iter().all(|key| key.master_key_id == "new"));
+        let unchanged: Vec<String> =
+            store.fields.lock().unwrap().iter().map(|(field, _)| field.ciphertext.clone()).collect();
+        assert_eq!(unchanged, ciphertexts);
+        assert_eq!(plaintexts(&store), vec!["123-45-6789", "555-0100", "A1234567"]);
+
+        let path = attestation_path(&output_dir.display().to_string(), job.rotation_id);
+        let attestation: RotationAttestation = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(attestation.previous_master_key_ids, vec!["old"]);
+        assert_eq!(attestation.keys_replaced, 0);
+        let new_master = decode_master_key(&master_key(2)).unwrap();
+        assert!(attestation.verify(&new_master));
+        let tampered = RotationAttestation {
+            keys_rewrapped: 1,
+            ..attestation.clone()
+        };
+        assert!(!tampered.verify(&new_master));
+        assert!(!attestation.verify(&decode_master_key(&master_key(1)).unwrap()));
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reencryption_resumes() {
+        let output_dir = std::env::temp_dir().join(format!("key-rotation-{}", Uuid::new_v4()));
+        let (handler, store) = setup(&output_dir).await;
+        let old_keys: Vec<Uuid> = store.active_keys().await.unwrap().iter().map(|key| key.id).collect();
+        let steps: Arc<dyn IdempotencyStore> = Arc::new(MemoryIdempotencyStore::default());
+        let context = JobContext::new(Uuid::new_v4()).with_step_results(StepResults::new(steps));
+        let job = job(true);
+
+        // The first attempt fails after re-encrypting one field
+        *store.fail_after.lock().unwrap() = Some(1);
+        assert!(handler.execute(job.clone(), context.clone()).await.is_err());
+        *store.fail_after.lock().unwrap() = None;
+
+        let result = handler.execute(job.clone(), context).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.metrics["fields_reencrypted"], 3.0);
+
+        let active = store.active_keys().await.unwrap();
+        assert_eq!(active.len(), 2);
+        assert!(active.iter().all(|key| !old_keys.contains(&key.id) && key.master_key_id == "new"));
+        assert!(store.fields.lock().unwrap().iter().all(|(_, key)| !old_keys.contains(key)));
+        assert_eq!(plaintexts(&store), vec!["123-45-6789", "555-0100", "A1234567"]);
+
+        let path = attestation_path(&output_dir.display().to_string(), job.rotation_id);
+        let attestation: RotationAttestation = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(attestation.keys_replaced, 2);
+        assert_eq!(attestation.fields_reencrypted, 3);
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_master_key_fails() {
+        let output_dir = std::env::temp_dir().join(format!("key-rotation-{}", Uuid::new_v4()));
+        let (handler, store) = setup(&output_dir).await;
+        let job = KeyRotationJob {
+            master_key_id: "missing".to_string(),
+            ..job(false)
+        };
+
+        let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(!result.success);
+        assert!(store.active_keys().await.unwrap().iter().all(|key| key.master_key_id == "old"));
+    }
+}
@@ -7,23 +7,73 @@
 //! and audit logging.
 
 use std::collections::HashMap;
+use std::future::Future;
 
 use anyhow::Result;
-use apalis::prelude::*;
 use chrono::{DateTime, Utc};
-use core::prelude::*;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+pub mod acknowledgments;
+pub mod backup;
+pub mod claims;
 pub mod config;
+pub mod dead_letter;
+pub mod disclosures;
+pub mod grpc;
 pub mod handlers;
+pub mod history;
+pub mod idempotency;
+pub mod ingestion;
+pub mod key_rotation;
+pub mod measures;
+pub mod notifications;
+pub mod observations;
+pub mod ocr;
+pub mod panels;
+pub mod partitions;
+pub mod progress;
+pub mod provenance;
+pub mod queue;
+pub mod remittance;
+pub mod reports;
+pub mod retention;
+pub mod security;
+pub mod subscriptions;
+pub mod sync;
 pub mod types;
+pub mod validation;
+pub mod webhooks;
 pub mod worker;
 
+pub use acknowledgments::AcknowledgmentStore;
+pub use claims::ClaimStore;
 pub use config::JobsConfig;
+pub use dead_letter::{DeadLetter, DeadLetterStore};
 pub use handlers::*;
+pub use history::{JobHistoryStore, JobRun, JobRunStatus};
+pub use idempotency::{Claim, IdempotencyStore, StepResults};
+pub use ingestion::IngestionWatcher;
+pub use key_rotation::DataKeyStore;
+pub use measures::MeasureStore;
+pub use notifications::{NotificationInbox, NotificationPreferenceStore};
+pub use observations::DeviceObservationStore;
+pub use ocr::DocumentTextStore;
+pub use panels::PanelStore;
+pub use partitions::PartitionStore;
+pub use progress::{JobEvent, ProgressReporter};
+pub use remittance::RemittanceStore;
+pub use reports::ReportScheduleStore;
+pub use security::SecurityEventStore;
+pub use provenance::ProvenanceStore;
+pub use queue::JobQueues;
+pub use subscriptions::SubscriptionDispatcher;
 pub use types::*;
+pub use validation::ValidationEntityStore;
+pub use webhooks::{WebhookDispatcher, WebhookStore};
 pub use worker::JobsWorker;
 
 /// Re-export commonly used types
@@ -31,6 +81,7 @@ pub mod prelude {
     pub use super::{
         config::JobsConfig,
         handlers::*,
+        progress::{JobEvent, JobEventKind, ProgressReporter},
         types::*,
         worker::JobsWorker,
         JobContext,
@@ -40,11 +91,21 @@ pub mod prelude {
 }
 
 /// Job execution context
+///
+/// Cancellation is cooperative: long-running handlers call
+/// [`JobContext::check_cancelled`] between units of work and stop with
+/// [`JobError::Cancelled`] once the job has been cancelled.
+///
+/// Side effects that must not repeat when a job is retried (sending a
+/// message, writing a file) go through [`JobContext::once`].
 #[derive(Debug, Clone)]
 pub struct JobContext {
     pub job_id: Uuid,
     pub started_at: DateTime<Utc>,
     pub metadata: HashMap<String, String>,
+    pub progress: ProgressReporter,
+    pub cancellation: CancellationToken,
+    pub step_results: StepResults,
 }
 
 impl JobContext {
@@ -54,9 +115,77 @@ impl JobContext {
             job_id,
             started_at: Utc::now(),
             metadata: HashMap::new(),
+            progress: ProgressReporter::new(job_id),
+            cancellation: CancellationToken::new(),
+            step_results: StepResults::default(),
         }
     }
 
+    /// Use a cancellation token owned by the worker
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Check if the job has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Return [`JobError::Cancelled`] if the job has been cancelled
+    pub fn check_cancelled(&self) -> JobResult<()> {
+        if self.is_cancelled() {
+            return Err(JobError::Cancelled(format!("Job {} was cancelled", self.job_id)));
+        }
+        Ok(())
+    }
+
+    /// Memoize steps in a store shared across attempts
+    pub fn with_step_results(mut self, step_results: StepResults) -> Self {
+        self.step_results = step_results;
+        self
+    }
+
+    /// Run a step at most once per job.
+    ///
+    /// If an earlier attempt of this job completed `step`, its saved result
+    /// is returned without running `run` again. Failed steps are not saved
+    /// and run again on the next attempt.
+    pub async fn once<T, F, Fut>(&self, step: &str, run: F) -> JobResult<T>
+    where
+        T: Serialize + DeserializeOwned + Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = JobResult<T>> + Send,
+    {
+        let Some(store) = self.step_results.store() else {
+            return run().await;
+        };
+
+        if let Some(saved) = store.step_result(self.job_id, step).await? {
+            info!(job_id = ?self.job_id, step, "Step already completed, reusing its result");
+            return serde_json::from_value(saved).map_err(|e| JobError::SerializationError(e.to_string()));
+        }
+
+        let result = run().await?;
+        // The side effect already happened; failing the job now would only
+        // repeat it on the next attempt
+        match serde_json::to_value(&result) {
+            Ok(value) => {
+                if let Err(e) = store.save_step_result(self.job_id, step, &value).await {
+                    warn!(job_id = ?self.job_id, step, error = %e, "Failed to save step result");
+                }
+            }
+            Err(e) => warn!(job_id = ?self.job_id, step, error = %e, "Failed to serialize step result"),
+        }
+        Ok(result)
+    }
+
+    /// Attach an existing progress reporter to the job context
+    pub fn with_progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
     /// Add metadata to the job context
     pub fn with_metadata(mut self, key: String, value: String) -> Self {
         self.metadata.insert(key, value);
@@ -99,6 +228,9 @@ pub enum JobError {
     #[error("Configuration error: {0}")]
     ConfigurationError(String),
 
+    #[error("Job cancelled: {0}")]
+    Cancelled(String),
+
     #[error("Unknown error: {0}")]
     UnknownError(String),
 }
@@ -115,6 +247,7 @@ impl JobError {
             JobError::TimeoutError(_) => true,
             JobError::SerializationError(_) => false,
             JobError::ConfigurationError(_) => false,
+            JobError::Cancelled(_) => false,
             JobError::UnknownError(_) => false,
         }
     }
@@ -132,7 +265,7 @@ impl JobError {
 }
 
 /// Job execution statistics
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobStats {
     pub total_jobs: u64,
     pub successful_jobs: u64,
@@ -185,6 +318,12 @@ impl JobMonitor {
         self.stats.last_updated = Utc::now();
     }
 
+    /// Record a failed attempt that will be retried
+    pub fn record_retry(&mut self) {
+        self.stats.retried_jobs += 1;
+        self.stats.last_updated = Utc::now();
+    }
+
     /// Get current statistics
     pub fn get_stats(&self) -> &JobStats {
         &self.stats
@@ -227,6 +366,17 @@ mod tests {
         assert_eq!(context.get_metadata("key3"), None);
     }
 
+    #[test]
+    fn test_job_context_cancellation() {
+        let token = CancellationToken::new();
+        let context = JobContext::new(Uuid::new_v4()).with_cancellation(token.clone());
+        assert!(context.check_cancelled().is_ok());
+
+        token.cancel();
+        assert!(context.is_cancelled());
+        assert!(matches!(context.check_cancelled(), Err(JobError::Cancelled(_))));
+    }
+
     #[test]
     fn test_job_error_retryable() {
         assert!(!JobError::ValidationError("test".to_string()).is_retryable());
@@ -7,8 +7,12 @@
 //! audit reporting, and notifications.
 
 use anyhow::Result;
+use emr_core::diagnostics::{is_production, ConfigReport, Severity};
+use emr_core::secrets::SecretResolver;
 use dotenvy::dotenv;
-use emr_jobs::{config::JobsConfig, worker::JobsWorker};
+use emr_jobs::{backup, config::JobsConfig, grpc, worker::JobsWorker};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal;
 use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -30,7 +34,7 @@ async fn main() -> Result<()> {
     info!("Starting EMR Jobs Worker");
 
     // Load configuration
-    let config = match JobsConfig::load() {
+    let mut config = match JobsConfig::load() {
         Ok(config) => {
             info!("Configuration loaded successfully");
             config
@@ -41,14 +45,81 @@ async fn main() -> Result<()> {
         }
     };
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Read secrets named by reference instead of kept in the configuration
+    let secrets = config.resolve_secrets(&SecretResolver::new()).await;
+
+    // `jobs-worker --check-config [--probe]` reports every configuration
+    // problem, optionally checking that the services it names answer, and exits
+    if args.first().map(String::as_str) == Some("--check-config") {
+        let mut report = match secrets {
+            Ok(()) => config.check(is_production()),
+            Err(e) => {
+                let mut report = ConfigReport::new();
+                report.error("secrets", e.to_string());
+                report
+            }
+        };
+        match config.check_keys() {
+            Ok(keys) => report.extend(keys),
+            Err(e) => report.error("config", format!("Cannot read the configuration file: {}", e)),
+        }
+        if args.iter().any(|arg| arg == "--probe") {
+            report.extend(config.probe(Duration::from_secs(3)).await);
+        }
+        println!("{}", report);
+        std::process::exit(if report.has_errors() { 1 } else { 0 });
+    }
+
+    if let Err(e) = secrets {
+        error!("Failed to resolve configuration secrets: {}", e);
+        std::process::exit(1);
+    }
+
     // Validate configuration
-    if let Err(e) = config.validate() {
-        error!("Configuration validation failed: {}", e);
+    let report = config.check(is_production());
+    for issue in report.issues.iter().filter(|issue| issue.severity == Severity::Warning) {
+        warn!(setting = %issue.key, "{}", issue.message);
+    }
+    if report.has_errors() {
+        error!("Configuration validation failed: {}", report);
         std::process::exit(1);
     }
 
+    // `jobs-worker restore <object-key> [--verify-only]` restores a backup
+    // instead of starting the worker
+    if args.first().map(String::as_str) == Some("restore") {
+        return run_restore(&config, &args[1..]).await;
+    }
+
     // Create and start the worker
-    let worker = JobsWorker::new(config);
+    let nats_config = config.nats.clone();
+    let grpc_config = config.grpc.clone();
+    let mut worker = JobsWorker::new(config);
+
+    if nats_config.enabled {
+        match async_nats::connect(&nats_config.url).await {
+            Ok(client) => {
+                info!("Publishing job events to NATS");
+                worker = worker.with_nats(client);
+            }
+            Err(e) => {
+                warn!("Failed to connect to NATS, job events will not be published: {}", e);
+            }
+        }
+    }
+    let worker = Arc::new(worker);
+
+    // Other services submit and follow jobs over the internal gRPC API
+    if grpc_config.enabled {
+        let worker = worker.clone();
+        tokio::spawn(async move {
+            if let Err(e) = grpc::serve(worker, &grpc_config).await {
+                error!("Internal job API stopped: {}", e);
+            }
+        });
+    }
     
     // Set up graceful shutdown
     let shutdown_signal = setup_shutdown_signal();
@@ -79,6 +150,24 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Verify a stored backup and restore it into the configured database
+async fn run_restore(config: &JobsConfig, args: &[String]) -> Result<()> {
+    let verify_only = args.iter().any(|arg| arg == "--verify-only");
+    let Some(object_key) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        anyhow::bail!("Usage: jobs-worker restore <object-key> [--verify-only]");
+    };
+
+    info!(object_key = %object_key, verify_only, "Restoring backup");
+    let manifest = backup::restore(&config.backup, &config.database.url, object_key, verify_only).await?;
+
+    if verify_only {
+        info!(backup_id = ?manifest.backup_id, "Backup checksums verified");
+    } else {
+        info!(backup_id = ?manifest.backup_id, created_at = %manifest.created_at, "Backup restored");
+    }
+    Ok(())
+}
+
 /// Set up graceful shutdown signal handling
 async fn setup_shutdown_signal() {
     let ctrl_c = async {
@@ -0,0 +1,397 @@
+//! Clinical quality measure reports
+//!
+//! Quality analytics jobs compute measures for a reporting period: each
+//! measure's populations are found with its compiled query (see
+//! `emr_core::services::measures`) as of the end of the period, stored per
+//! patient for drill-down and counted into the measure's report. Computing a
+//! measure again for the same period replaces its report.
+
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::AnalyticsJob;
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::domain::{ImprovementNotation, MeasureReport, PanelCriterion, QualityMeasure};
+use emr_core::services::measures::population_query;
+use emr_core::services::panels::PanelParam;
+use emr_core::types::EntityMetadata;
+use deadpool_diesel::postgres::Pool;
+use diesel::pg::Pg;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::sql_types::{BigInt, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Quality measures and their reports
+#[async_trait]
+pub trait MeasureStore: Send + Sync {
+    /// Active measures with the given ids, or all active measures when
+    /// `ids` is empty
+    async fn measures(&self, ids: &[Uuid]) -> JobResult<Vec<QualityMeasure>>;
+
+    /// Compute a measure for a reporting period, replacing any earlier
+    /// report for the same period
+    async fn evaluate(
+        &self,
+        measure: &QualityMeasure,
+        period_start: DateTime<Utc>,
+        period_end: DateTime<Utc>,
+    ) -> JobResult<MeasureReport>;
+}
+
+/// Measures in the `emr` schema
+pub struct DatabaseMeasureStore {
+    pool: Pool,
+}
+
+impl DatabaseMeasureStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct MeasureRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    identifier: String,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<Text>)]
+    description: Option<String>,
+    #[diesel(sql_type = Text)]
+    improvement: String,
+    #[diesel(sql_type = Text)]
+    initial_population: String,
+    #[diesel(sql_type = Text)]
+    denominator: String,
+    #[diesel(sql_type = Text)]
+    denominator_exclusions: String,
+    #[diesel(sql_type = Text)]
+    numerator: String,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = BigInt)]
+    version: i64,
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+    #[diesel(sql_type = Timestamptz)]
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<MeasureRow> for QualityMeasure {
+    type Error = JobError;
+
+    fn try_from(row: MeasureRow) -> JobResult<Self> {
+        fn criteria(json: &str) -> JobResult<Vec<PanelCriterion>> {
+            serde_json::from_str(json).map_err(|e| JobError::SerializationError(e.to_string()))
+        }
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            identifier: row.identifier,
+            title: row.title,
+            description: row.description,
+            improvement: ImprovementNotation::parse(&row.improvement).ok_or_else(|| {
+                JobError::SerializationError(format!("Unknown improvement notation '{}'", row.improvement))
+            })?,
+            initial_population: criteria(&row.initial_population)?,
+            denominator: criteria(&row.denominator)?,
+            denominator_exclusions: criteria(&row.denominator_exclusions)?,
+            numerator: criteria(&row.numerator)?,
+            active: row.active,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ReportCountsRow {
+    #[diesel(sql_type = BigInt)]
+    initial_population: i64,
+    #[diesel(sql_type = BigInt)]
+    denominator: i64,
+    #[diesel(sql_type = BigInt)]
+    denominator_exclusions: i64,
+    #[diesel(sql_type = BigInt)]
+    numerator: i64,
+}
+
+/// Bind a compiled measure query's parameters, in order
+fn bind_params(
+    mut query: BoxedSqlQuery<'static, Pg, SqlQuery>,
+    params: Vec<PanelParam>,
+) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+    for param in params {
+        query = match param {
+            PanelParam::Text(value) => query.bind::<Text, _>(value),
+            PanelParam::Integer(value) => query.bind::<diesel::sql_types::Integer, _>(value),
+            PanelParam::Double(value) => query.bind::<diesel::sql_types::Double, _>(value),
+            PanelParam::Timestamp(value) => query.bind::<Timestamptz, _>(value),
+        };
+    }
+    query
+}
+
+#[async_trait]
+impl MeasureStore for DatabaseMeasureStore {
+    async fn measures(&self, ids: &[Uuid]) -> JobResult<Vec<QualityMeasure>> {
+        let conn = self.connection().await?;
+        let ids = ids.to_vec();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, identifier, title, description, improvement, \
+                     initial_population::text AS initial_population, denominator::text AS denominator, \
+                     denominator_exclusions::text AS denominator_exclusions, numerator::text AS numerator, \
+                     active, version, created_at, updated_at \
+                     FROM emr.quality_measures \
+                     WHERE active AND (cardinality($1) = 0 OR id = ANY($1)) ORDER BY identifier",
+                )
+                .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(ids)
+                .load::<MeasureRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        rows.into_iter().map(QualityMeasure::try_from).collect()
+    }
+
+    async fn evaluate(
+        &self,
+        measure: &QualityMeasure,
+        period_start: DateTime<Utc>,
+        period_end: DateTime<Utc>,
+    ) -> JobResult<MeasureReport> {
+        let conn = self.connection().await?;
+        let query = population_query(measure, period_end);
+        let measure_id = measure.metadata.id;
+        let report_id = Uuid::new_v4();
+        let computed_at = Utc::now();
+
+        let counts = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    diesel::sql_query(
+                        "DELETE FROM emr.measure_reports \
+                         WHERE measure_id = $1 AND period_start = $2 AND period_end = $3",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(measure_id)
+                    .bind::<Timestamptz, _>(period_start)
+                    .bind::<Timestamptz, _>(period_end)
+                    .execute(conn)?;
+                    diesel::sql_query(
+                        "INSERT INTO emr.measure_reports \
+                         (id, measure_id, period_start, period_end, initial_population, denominator, \
+                         denominator_exclusions, numerator, computed_at) \
+                         VALUES ($1, $2, $3, $4, 0, 0, 0, 0, $5)",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(report_id)
+                    .bind::<diesel::sql_types::Uuid, _>(measure_id)
+                    .bind::<Timestamptz, _>(period_start)
+                    .bind::<Timestamptz, _>(period_end)
+                    .bind::<Timestamptz, _>(computed_at)
+                    .execute(conn)?;
+                    let insert = diesel::sql_query(format!(
+                        "INSERT INTO emr.measure_report_patients (report_id, patient_id, population) \
+                         SELECT ${}, patient_id, population FROM ({}) populations",
+                        query.params.len() + 1,
+                        query.sql
+                    ))
+                    .into_boxed();
+                    bind_params(insert, query.params)
+                        .bind::<diesel::sql_types::Uuid, _>(report_id)
+                        .execute(conn)?;
+                    diesel::sql_query(
+                        "UPDATE emr.measure_reports r SET \
+                         initial_population = c.initial_population, denominator = c.denominator, \
+                         denominator_exclusions = c.denominator_exclusions, numerator = c.numerator \
+                         FROM (SELECT \
+                         COUNT(*) FILTER (WHERE population = 'initial_population') AS initial_population, \
+                         COUNT(*) FILTER (WHERE population = 'denominator') AS denominator, \
+                         COUNT(*) FILTER (WHERE population = 'denominator_exclusion') AS denominator_exclusions, \
+                         COUNT(*) FILTER (WHERE population = 'numerator') AS numerator \
+                         FROM emr.measure_report_patients WHERE report_id = $1) c \
+                         WHERE r.id = $1 \
+                         RETURNING r.initial_population, r.denominator, r.denominator_exclusions, r.numerator",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(report_id)
+                    .get_result::<ReportCountsRow>(conn)
+                })
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(MeasureReport {
+            id: report_id,
+            measure_id,
+            period_start,
+            period_end,
+            initial_population: counts.initial_population,
+            denominator: counts.denominator,
+            denominator_exclusions: counts.denominator_exclusions,
+            numerator: counts.numerator,
+            computed_at,
+        })
+    }
+}
+
+/// Quality analytics job handler
+pub struct QualityMeasureHandler {
+    store: Arc<dyn MeasureStore>,
+}
+
+impl QualityMeasureHandler {
+    /// Create a handler computing measures in `store`
+    pub fn new(store: Arc<dyn MeasureStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl JobHandler<AnalyticsJob> for QualityMeasureHandler {
+    async fn execute(&self, job: AnalyticsJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            period_start = %job.date_range.start,
+            period_end = %job.date_range.end,
+            "Starting quality measure job"
+        );
+        if job.date_range.start >= job.date_range.end {
+            return Err(JobError::ValidationError(
+                "Reporting periods must end after they start".to_string(),
+            ));
+        }
+
+        let measures = self.store.measures(&job.measure_ids).await?;
+        let mut reports = Vec::with_capacity(measures.len());
+        for (done, measure) in measures.iter().enumerate() {
+            context.check_cancelled()?;
+            let report = self
+                .store
+                .evaluate(measure, job.date_range.start, job.date_range.end)
+                .await?;
+            context.progress.step(done + 1, measures.len());
+            reports.push(serde_json::json!({
+                "measure_id": measure.metadata.id,
+                "identifier": measure.identifier,
+                "report_id": report.id,
+                "denominator": report.denominator,
+                "numerator": report.numerator,
+                "performance_rate": report.performance_rate(),
+            }));
+        }
+
+        Ok(JobExecutionResult::success_with_data(
+            format!("Computed {} quality measures", reports.len()),
+            serde_json::json!({ "reports": reports }),
+        )
+        .with_metric("measures".to_string(), reports.len() as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "QualityMeasures"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AnalyticsType, DateRange};
+    use chrono::{Duration, TimeZone};
+
+    /// Measures whose reports count every patient the same, for tests
+    struct MemoryMeasureStore {
+        measures: Vec<QualityMeasure>,
+    }
+
+    #[async_trait]
+    impl MeasureStore for MemoryMeasureStore {
+        async fn measures(&self, ids: &[Uuid]) -> JobResult<Vec<QualityMeasure>> {
+            Ok(self
+                .measures
+                .iter()
+                .filter(|measure| ids.is_empty() || ids.contains(&measure.metadata.id))
+                .cloned()
+                .collect())
+        }
+
+        async fn evaluate(
+            &self,
+            measure: &QualityMeasure,
+            period_start: DateTime<Utc>,
+            period_end: DateTime<Utc>,
+        ) -> JobResult<MeasureReport> {
+            Ok(MeasureReport {
+                id: Uuid::new_v4(),
+                measure_id: measure.metadata.id,
+                period_start,
+                period_end,
+                initial_population: 10,
+                denominator: 8,
+                denominator_exclusions: 0,
+                numerator: 2,
+                computed_at: Utc::now(),
+            })
+        }
+    }
+
+    fn job(measure_ids: Vec<Uuid>) -> AnalyticsJob {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        AnalyticsJob {
+            analytics_type: AnalyticsType::Quality,
+            date_range: DateRange {
+                start,
+                end: start + Duration::days(366),
+            },
+            dimensions: vec![],
+            metrics: vec![],
+            output_location: String::new(),
+            measure_ids,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quality_measure_job() {
+        let measure = |identifier: &str| {
+            QualityMeasure::new(
+                identifier,
+                identifier,
+                vec![PanelCriterion::SeenWithin { days: 365 }],
+                vec![PanelCriterion::Age { min: Some(50), max: None }],
+            )
+        };
+        let measures = vec![measure("CMS122v12"), measure("CMS165v12")];
+        let first = measures[0].metadata.id;
+        let handler = QualityMeasureHandler::new(Arc::new(MemoryMeasureStore { measures }));
+
+        let result = handler.execute(job(vec![]), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert_eq!(result.metrics.get("measures"), Some(&2.0));
+        assert_eq!(result.data.unwrap()["reports"][0]["performance_rate"], 0.25);
+
+        let result = handler.execute(job(vec![first]), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert_eq!(result.metrics.get("measures"), Some(&1.0));
+
+        let mut backwards = job(vec![]);
+        backwards.date_range.end = backwards.date_range.start;
+        assert!(handler.execute(backwards, JobContext::new(Uuid::new_v4())).await.is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! Notification preferences and held notifications
+//!
+//! The notification handler looks up the recipient's
+//! [`NotificationPreferences`] before sending. Notifications held for quiet
+//! hours or for the daily digest are kept in `jobs.held_notifications` with
+//! their rendered text. On each poll the worker releases the ones that are
+//! due: quiet-hour notifications are queued again as they were, and digest
+//! notifications are combined per recipient, type and channel into one
+//! digest notification.
+//!
+//! In-app notifications are delivered to the recipient's inbox in
+//! `emr.notifications`, which the API lists with unread counts, and
+//! announced on [`inbox_subject`] so open sessions update live.
+
+use crate::types::{NotificationJob, NotificationType, Priority};
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::events::Event;
+use emr_core::notifications::{NotificationPreferences, TemplateRef, NOTIFICATION_DIGEST_TEMPLATE};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{BigInt, Bool, Nullable, Text, Timestamptz};
+use diesel::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Held notifications released per poll
+pub const RELEASE_BATCH_SIZE: i64 = 500;
+
+/// A user's stored preferences
+pub const PREFERENCES_QUERY: &str = r#"
+SELECT preferences::text AS preferences FROM emr.notification_preferences WHERE user_id = $1
+"#;
+
+/// Insert for a held notification
+pub const HOLD_NOTIFICATION_QUERY: &str = r#"
+INSERT INTO jobs.held_notifications (id, recipient_id, job, message, digest, release_at)
+VALUES ($1, $2, $3::jsonb, $4, $5, $6)
+"#;
+
+/// Remove and return held notifications that are due, oldest first
+pub const RELEASE_DUE_QUERY: &str = r#"
+DELETE FROM jobs.held_notifications WHERE id IN (
+    SELECT id FROM jobs.held_notifications WHERE release_at <= $1
+    ORDER BY release_at LIMIT $2 FOR UPDATE SKIP LOCKED)
+RETURNING id, job::text AS job, message, digest, release_at
+"#;
+
+/// Insert for an in-app notification; a repeated delivery is stored once
+pub const INSERT_IN_APP_QUERY: &str = r#"
+INSERT INTO emr.notifications (id, recipient_id, notification_type, priority, subject, message, created_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+ON CONFLICT (id) DO NOTHING
+"#;
+
+/// NATS subject announcing a recipient's new in-app notifications, which
+/// the API relays to the recipient's open event streams
+pub fn inbox_subject(recipient_id: Uuid) -> String {
+    format!("notifications.{}", recipient_id)
+}
+
+/// A notification in a recipient's in-app inbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InAppNotification {
+    /// Id of the notification job
+    pub id: Uuid,
+    pub recipient_id: Uuid,
+    pub notification_type: NotificationType,
+    pub priority: Priority,
+    pub subject: Option<String>,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Announced on [`inbox_subject`] as `notification.in_app`; see
+/// [`emr_core::events::schemas`]
+impl Event for InAppNotification {
+    const EVENT_TYPE: &'static str = "notification.in_app";
+    const VERSION: u32 = 1;
+}
+
+/// Where in-app notifications are delivered
+#[async_trait]
+pub trait NotificationInbox: Send + Sync {
+    /// Add a notification to its recipient's inbox
+    async fn deliver(&self, notification: &InAppNotification) -> JobResult<()>;
+}
+
+/// Inboxes in the `emr.notifications` table
+pub struct DatabaseNotificationInbox {
+    pool: Pool,
+}
+
+impl DatabaseNotificationInbox {
+    /// Create an inbox using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl NotificationInbox for DatabaseNotificationInbox {
+    async fn deliver(&self, notification: &InAppNotification) -> JobResult<()> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let notification = notification.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_IN_APP_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(notification.id)
+                .bind::<diesel::sql_types::Uuid, _>(notification.recipient_id)
+                .bind::<Text, _>(notification.notification_type.as_str())
+                .bind::<Text, _>(notification.priority.as_str())
+                .bind::<Nullable<Text>, _>(notification.subject.as_deref())
+                .bind::<Text, _>(&notification.message)
+                .bind::<Timestamptz, _>(notification.created_at)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// A notification waiting for quiet hours to end or for the digest
+#[derive(Debug, Clone)]
+pub struct HeldNotification {
+    pub id: Uuid,
+    pub job: NotificationJob,
+    /// Text rendered when the notification was held
+    pub message: String,
+    /// Held for the digest rather than for quiet hours
+    pub digest: bool,
+    pub release_at: DateTime<Utc>,
+}
+
+/// Storage for preferences and held notifications
+#[async_trait]
+pub trait NotificationPreferenceStore: Send + Sync {
+    /// Preferences of a user, if they have set any
+    async fn preferences(&self, user_id: Uuid) -> JobResult<Option<NotificationPreferences>>;
+
+    /// Keep a notification until its release time
+    async fn hold(&self, held: &HeldNotification) -> JobResult<()>;
+
+    /// Remove and return up to `limit` held notifications due at `now`
+    async fn release_due(&self, now: DateTime<Utc>, limit: i64) -> JobResult<Vec<HeldNotification>>;
+}
+
+/// Preferences in `emr.notification_preferences`, held notifications in
+/// `jobs.held_notifications`
+pub struct DatabaseNotificationPreferenceStore {
+    pool: Pool,
+}
+
+impl DatabaseNotificationPreferenceStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PreferencesRow {
+    #[diesel(sql_type = Text)]
+    preferences: String,
+}
+
+#[derive(diesel::QueryableByName)]
+struct HeldRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    job: String,
+    #[diesel(sql_type = Text)]
+    message: String,
+    #[diesel(sql_type = Bool)]
+    digest: bool,
+    #[diesel(sql_type = Timestamptz)]
+    release_at: DateTime<Utc>,
+}
+
+#[async_trait]
+impl NotificationPreferenceStore for DatabaseNotificationPreferenceStore {
+    async fn preferences(&self, user_id: Uuid) -> JobResult<Option<NotificationPreferences>> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(PREFERENCES_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(user_id)
+                    .load::<PreferencesRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        rows.into_iter()
+            .next()
+            .map(|row| {
+                serde_json::from_str(&row.preferences).map_err(|e| JobError::SerializationError(e.to_string()))
+            })
+            .transpose()
+    }
+
+    async fn hold(&self, held: &HeldNotification) -> JobResult<()> {
+        let job = serde_json::to_string(&held.job).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let held = held.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(HOLD_NOTIFICATION_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(held.id)
+                .bind::<diesel::sql_types::Uuid, _>(held.job.recipient_id)
+                .bind::<Text, _>(&job)
+                .bind::<Text, _>(&held.message)
+                .bind::<Bool, _>(held.digest)
+                .bind::<Timestamptz, _>(held.release_at)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn release_due(&self, now: DateTime<Utc>, limit: i64) -> JobResult<Vec<HeldNotification>> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(RELEASE_DUE_QUERY)
+                    .bind::<Timestamptz, _>(now)
+                    .bind::<BigInt, _>(limit)
+                    .load::<HeldRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(HeldNotification {
+                    id: row.id,
+                    job: serde_json::from_str(&row.job).map_err(|e| JobError::SerializationError(e.to_string()))?,
+                    message: row.message,
+                    digest: row.digest,
+                    release_at: row.release_at,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Notification jobs to queue for released notifications
+///
+/// Quiet-hour notifications go out unchanged. Digest notifications for the
+/// same recipient, address, type and channel become one digest job in the
+/// locale of the first of them, at the highest priority among them.
+pub fn released_jobs(released: Vec<HeldNotification>) -> Vec<NotificationJob> {
+    let mut jobs = Vec::new();
+    let mut digests: Vec<(NotificationJob, Vec<String>)> = Vec::new();
+
+    for held in released {
+        if !held.digest {
+            jobs.push(held.job);
+            continue;
+        }
+        let job = held.job;
+        let existing = digests.iter_mut().find(|(digest, _)| {
+            digest.recipient_id == job.recipient_id
+                && digest.address == job.address
+                && digest.notification_type == job.notification_type
+                && digest.channel == job.channel
+        });
+        match existing {
+            Some((digest, items)) => {
+                digest.priority = digest.priority.max(job.priority);
+                items.push(held.message);
+            }
+            None => digests.push((job, vec![held.message])),
+        }
+    }
+
+    for (mut job, items) in digests {
+        let locale = job.template.as_ref().and_then(|template| template.locale.clone());
+        let variables = json!({ "count": items.len(), "items": items });
+        job.template = Some(TemplateRef {
+            name: NOTIFICATION_DIGEST_TEMPLATE.to_string(),
+            locale,
+            variables: variables.as_object().cloned().unwrap_or_default(),
+        });
+        job.message = String::new();
+        job.scheduled_for = None;
+        job.digest = true;
+        jobs.push(job);
+    }
+    jobs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::NotificationChannel;
+    use emr_core::notifications::TemplateCatalog;
+
+    fn held(recipient_id: Uuid, message: &str, priority: Priority, digest: bool) -> HeldNotification {
+        HeldNotification {
+            id: Uuid::new_v4(),
+            job: NotificationJob {
+                recipient_id,
+                address: None,
+                notification_type: NotificationType::Update,
+                message: message.to_string(),
+                template: None,
+                channel: NotificationChannel::Email,
+                priority,
+                scheduled_for: None,
+                digest: false,
+            },
+            message: message.to_string(),
+            digest,
+            release_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_released_digests_are_combined() {
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let jobs = released_jobs(vec![
+            held(alice, "Lab results are ready", Priority::Normal, true),
+            held(bob, "Quiet hours are over", Priority::Normal, false),
+            held(alice, "Your refill was sent", Priority::High, true),
+        ]);
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].message, "Quiet hours are over");
+        assert!(!jobs[0].digest);
+
+        let digest = &jobs[1];
+        assert!(digest.digest);
+        assert_eq!(digest.recipient_id, alice);
+        assert_eq!(digest.priority, Priority::High);
+        let rendered = TemplateCatalog::builtin().render(digest.template.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            rendered.body,
+            "You have 2 notifications:\n- Lab results are ready\n- Your refill was sent"
+        );
+    }
+
+    #[test]
+    fn test_inbox_subject() {
+        let recipient_id = Uuid::nil();
+        assert_eq!(inbox_subject(recipient_id), "notifications.00000000-0000-0000-0000-000000000000");
+    }
+
+    #[test]
+    fn test_in_app_notification_matches_published_schema() {
+        let schema = emr_core::events::schema(InAppNotification::EVENT_TYPE, InAppNotification::VERSION).unwrap();
+        let notification = InAppNotification {
+            id: Uuid::new_v4(),
+            recipient_id: Uuid::new_v4(),
+            notification_type: NotificationType::Update,
+            priority: Priority::Normal,
+            subject: None,
+            message: "Lab results are ready".to_string(),
+            created_at: Utc::now(),
+        };
+
+        let problems = schema.validate(&serde_json::to_value(&notification).unwrap());
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+}
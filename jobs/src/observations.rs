@@ -0,0 +1,394 @@
+//! Bulk ingestion of device observations over NATS
+//!
+//! Device gateways publish batches of readings to [`OBSERVATIONS_SUBJECT`]
+//! as `{"observations": [...]}`. Workers subscribe in the
+//! [`OBSERVATIONS_QUEUE_GROUP`] queue group, so each batch is stored by one
+//! worker, the same way `POST /observations/_bulk` stores it (see
+//! `emr_core::services::device_observations`). A batch published as a request
+//! gets its [`IngestResult`] back, with rejected readings by index, or
+//! `{"error": ...}` when the whole batch failed.
+//!
+//! A worker stores at most `device_ingest.max_concurrent` batches at once
+//! and stops reading the subscription while it is busy. Batches left
+//! waiting are buffered by the NATS client; a worker that falls too far
+//! behind is disconnected as a slow consumer and loses them, so gateways
+//! should publish as requests and resend batches that get no answer.
+//! Readings sent with ids are stored once however often they are resent.
+//! Readings a strict validation profile fails are rejected by index, with
+//! the profile of their encounter's organization or the default profile.
+//! Vital signs among them update the patients' summaries in the same
+//! transaction (see `emr_core::services::patient_summary`).
+
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::services::device_observations::{
+    IngestLimits, IngestResult, ValidatedBatch, INSERT_DEVICE_OBSERVATIONS_QUERY, KNOWN_ENCOUNTERS_QUERY,
+    KNOWN_PATIENTS_QUERY,
+};
+use emr_core::domain::Observation;
+use emr_core::services::patient_summary::{
+    device_events, PatientSummary, SummaryEvent, CREATE_SUMMARY_QUERY, LOCK_SUMMARY_QUERY, SAVE_SUMMARY_QUERY,
+};
+use emr_core::validation::ValidationProfile;
+use emr_fhir::observation_to_fhir;
+use deadpool_diesel::postgres::Pool;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{Array, Double, Nullable, Text, Timestamptz};
+use diesel::{Connection, PgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// NATS subject device gateways publish observation batches to
+pub const OBSERVATIONS_SUBJECT: &str = "observations.bulk";
+
+/// Queue group workers share [`OBSERVATIONS_SUBJECT`] in
+pub const OBSERVATIONS_QUEUE_GROUP: &str = "observation-ingest";
+
+/// Batch of device readings published to [`OBSERVATIONS_SUBJECT`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceBatch {
+    /// Device readings; malformed ones are reported by index
+    pub observations: Vec<serde_json::Value>,
+}
+
+/// Where device observations are stored
+#[async_trait]
+pub trait DeviceObservationStore: Send + Sync {
+    /// Store a validated batch in one transaction, `chunk_size` rows per
+    /// insert, rejecting observations of unknown patients and encounters
+    async fn insert_batch(&self, batch: ValidatedBatch, chunk_size: usize) -> JobResult<IngestResult>;
+}
+
+/// Observations in the `emr` schema
+pub struct DatabaseDeviceObservationStore {
+    pool: Pool,
+}
+
+impl DatabaseDeviceObservationStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct KnownPatientRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+}
+
+#[derive(diesel::QueryableByName)]
+struct KnownEncounterRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    patient_id: Option<Uuid>,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    organization_id: Option<Uuid>,
+}
+
+/// Latest `Observation` validation profile of each organization in `$1`,
+/// and the default profile
+const OBSERVATION_PROFILES_QUERY: &str = "SELECT DISTINCT ON (tenant_id) id, tenant_id, entity_type, version, strict, \
+     rules::text AS rules, created_at FROM emr.validation_profiles \
+     WHERE entity_type = 'Observation' AND (tenant_id = ANY($1) OR tenant_id IS NULL) \
+     ORDER BY tenant_id, version DESC";
+
+#[derive(diesel::QueryableByName)]
+struct ValidationProfileRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    tenant_id: Option<Uuid>,
+    #[diesel(sql_type = Text)]
+    entity_type: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    version: i32,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    strict: bool,
+    #[diesel(sql_type = Text)]
+    rules: String,
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<ValidationProfileRow> for ValidationProfile {
+    type Error = DieselError;
+
+    fn try_from(row: ValidationProfileRow) -> Result<Self, DieselError> {
+        Ok(Self {
+            id: row.id,
+            tenant_id: row.tenant_id,
+            entity_type: row.entity_type,
+            version: row.version,
+            strict: row.strict,
+            rules: serde_json::from_str(&row.rules).map_err(|e| DieselError::DeserializationError(Box::new(e)))?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// Reject readings the strict profile of their encounter's organization
+/// (`tenants`, by encounter id) fails, or the default profile for readings
+/// without one, as `POST /observations/_bulk` does
+fn reject_by_profiles(
+    batch: &mut ValidatedBatch,
+    tenants: &HashMap<Uuid, Option<Uuid>>,
+    profiles: &[ValidationProfile],
+) {
+    batch.reject(|reading| {
+        let tenant_id = reading.encounter_id.and_then(|encounter_id| tenants.get(&encounter_id).copied().flatten());
+        let profile = profiles
+            .iter()
+            .find(|profile| tenant_id.is_some() && profile.tenant_id == tenant_id)
+            .or_else(|| profiles.iter().find(|profile| profile.tenant_id.is_none()))?;
+        if !profile.strict {
+            return None;
+        }
+        let report = profile.evaluate(&observation_to_fhir(&Observation::from(reading)));
+        report.has_errors().then(|| report.rejection())
+    });
+}
+
+#[derive(diesel::QueryableByName)]
+struct PatientSummaryRow {
+    #[diesel(sql_type = Text)]
+    summary: String,
+}
+
+/// Fold events into the patients' stored summaries, locked in patient id
+/// order until the transaction ends
+fn apply_summary_events(
+    conn: &mut PgConnection,
+    events: BTreeMap<Uuid, Vec<SummaryEvent>>,
+) -> Result<(), DieselError> {
+    let now = Utc::now();
+    for (patient_id, events) in events {
+        let empty = serde_json::to_string(&PatientSummary::new(patient_id))
+            .map_err(|e| DieselError::SerializationError(Box::new(e)))?;
+        diesel::sql_query(CREATE_SUMMARY_QUERY)
+            .bind::<diesel::sql_types::Uuid, _>(patient_id)
+            .bind::<Text, _>(&empty)
+            .execute(conn)?;
+        let row = diesel::sql_query(LOCK_SUMMARY_QUERY)
+            .bind::<diesel::sql_types::Uuid, _>(patient_id)
+            .get_result::<PatientSummaryRow>(conn)?;
+
+        let mut summary: PatientSummary =
+            serde_json::from_str(&row.summary).map_err(|e| DieselError::DeserializationError(Box::new(e)))?;
+        for event in events {
+            summary.apply(event, now);
+        }
+        let summary = serde_json::to_string(&summary).map_err(|e| DieselError::SerializationError(Box::new(e)))?;
+        diesel::sql_query(SAVE_SUMMARY_QUERY)
+            .bind::<diesel::sql_types::Uuid, _>(patient_id)
+            .bind::<Text, _>(&summary)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl DeviceObservationStore for DatabaseDeviceObservationStore {
+    async fn insert_batch(&self, mut batch: ValidatedBatch, chunk_size: usize) -> JobResult<IngestResult> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let (batch, inserted) = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    let patients = diesel::sql_query(KNOWN_PATIENTS_QUERY)
+                        .bind::<Array<diesel::sql_types::Uuid>, _>(batch.patient_ids())
+                        .load::<KnownPatientRow>(conn)?;
+                    let encounters = diesel::sql_query(KNOWN_ENCOUNTERS_QUERY)
+                        .bind::<Array<diesel::sql_types::Uuid>, _>(batch.encounter_ids())
+                        .load::<KnownEncounterRow>(conn)?;
+                    batch.reject_unknown(
+                        &patients.into_iter().map(|row| row.id).collect(),
+                        &encounters.iter().map(|row| (row.id, row.patient_id)).collect(),
+                    );
+
+                    let tenants: HashMap<Uuid, Option<Uuid>> =
+                        encounters.iter().map(|row| (row.id, row.organization_id)).collect();
+                    let tenant_ids: Vec<Uuid> = tenants.values().flatten().copied().collect();
+                    let profiles = diesel::sql_query(OBSERVATION_PROFILES_QUERY)
+                        .bind::<Array<diesel::sql_types::Uuid>, _>(tenant_ids)
+                        .load::<ValidationProfileRow>(conn)?
+                        .into_iter()
+                        .map(ValidationProfile::try_from)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    reject_by_profiles(&mut batch, &tenants, &profiles);
+
+                    let mut inserted = 0;
+                    for columns in batch.chunks(chunk_size) {
+                        inserted += diesel::sql_query(INSERT_DEVICE_OBSERVATIONS_QUERY)
+                            .bind::<Array<diesel::sql_types::Uuid>, _>(columns.ids)
+                            .bind::<Array<Nullable<Text>>, _>(columns.categories)
+                            .bind::<Array<Text>, _>(columns.codes)
+                            .bind::<Array<Nullable<Text>>, _>(columns.displays)
+                            .bind::<Array<diesel::sql_types::Uuid>, _>(columns.patient_ids)
+                            .bind::<Array<Nullable<diesel::sql_types::Uuid>>, _>(columns.encounter_ids)
+                            .bind::<Array<Nullable<Text>>, _>(columns.device_ids)
+                            .bind::<Array<Timestamptz>, _>(columns.effective)
+                            .bind::<Array<Double>, _>(columns.values)
+                            .bind::<Array<Text>, _>(columns.units)
+                            .execute(conn)?;
+                    }
+                    apply_summary_events(conn, device_events(batch.accepted()))?;
+                    Ok::<_, DieselError>((batch, inserted))
+                })
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(batch.into_result(inserted))
+    }
+}
+
+/// Store a batch published at `now`
+pub async fn ingest(
+    store: &dyn DeviceObservationStore,
+    limits: &IngestLimits,
+    payload: &[u8],
+    now: DateTime<Utc>,
+) -> JobResult<IngestResult> {
+    let batch: DeviceBatch =
+        serde_json::from_slice(payload).map_err(|e| JobError::SerializationError(e.to_string()))?;
+    limits.check_batch(batch.observations.len()).map_err(JobError::ValidationError)?;
+
+    store
+        .insert_batch(ValidatedBatch::from_values(batch.observations, now), limits.chunk_size)
+        .await
+}
+
+/// Answer to a batch published as a request
+pub fn reply_body(result: &JobResult<IngestResult>) -> Vec<u8> {
+    let body = match result {
+        Ok(result) => json!(result),
+        Err(e) => json!({ "error": e.to_string() }),
+    };
+    body.to_string().into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Stores every accepted observation of known patients
+    struct MemoryStore {
+        patients: Vec<Uuid>,
+        stored: Mutex<Vec<Uuid>>,
+    }
+
+    #[async_trait]
+    impl DeviceObservationStore for MemoryStore {
+        async fn insert_batch(&self, mut batch: ValidatedBatch, _chunk_size: usize) -> JobResult<IngestResult> {
+            batch.reject_unknown(&self.patients.iter().copied().collect(), &Default::default());
+            let mut stored = self.stored.lock().unwrap();
+            let before = stored.len();
+            for observation in batch.accepted() {
+                if !stored.contains(&observation.id) {
+                    stored.push(observation.id);
+                }
+            }
+            let inserted = stored.len() - before;
+            drop(stored);
+            Ok(batch.into_result(inserted))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest() {
+        let patient_id = Uuid::new_v4();
+        let store = MemoryStore {
+            patients: vec![patient_id],
+            stored: Mutex::new(Vec::new()),
+        };
+        let reading = |value: f64| {
+            json!({
+                "id": Uuid::new_v4(),
+                "patient_id": patient_id,
+                "code": "8867-4",
+                "value": value,
+                "unit": "/min",
+                "effective": Utc::now(),
+            })
+        };
+        let batch = json!({ "observations": [reading(72.0), reading(0.0), reading(75.0), { "code": "8867-4" }] });
+        let payload = batch.to_string().into_bytes();
+        let limits = IngestLimits::default();
+
+        let result = ingest(&store, &limits, &payload, Utc::now()).await.unwrap();
+        assert_eq!((result.received, result.inserted, result.duplicates), (4, 2, 0));
+        let indexes: Vec<usize> = result.errors.iter().map(|error| error.index).collect();
+        assert_eq!(indexes, vec![1, 3]);
+
+        // A resent batch is not stored twice
+        let result = ingest(&store, &limits, &payload, Utc::now()).await.unwrap();
+        assert_eq!((result.inserted, result.duplicates), (0, 2));
+
+        let small = IngestLimits {
+            max_readings: 3,
+            ..IngestLimits::default()
+        };
+        let refused = ingest(&store, &small, &payload, Utc::now()).await;
+        assert!(matches!(refused, Err(JobError::ValidationError(_))));
+        assert!(String::from_utf8(reply_body(&refused)).unwrap().contains("at most 3 readings"));
+        assert!(ingest(&store, &limits, b"[]", Utc::now()).await.is_err());
+    }
+
+    #[test]
+    fn test_reject_by_profiles() {
+        use emr_core::validation::{ProfileRule, RuleCheck, RuleSeverity};
+
+        let profile = |tenant_id: Option<Uuid>, strict: bool| ValidationProfile {
+            id: Uuid::new_v4(),
+            tenant_id,
+            entity_type: "Observation".to_string(),
+            version: 3,
+            strict,
+            rules: vec![ProfileRule {
+                name: "plausible-rate".to_string(),
+                description: "Heart rate is at most 200".to_string(),
+                field: "valueQuantity.value".to_string(),
+                check: RuleCheck::Range { min: None, max: Some(200.0) },
+                severity: RuleSeverity::Error,
+            }],
+            created_at: Utc::now(),
+        };
+        let (lenient_encounter, lenient_tenant) = (Uuid::new_v4(), Uuid::new_v4());
+        let reading = |value: f64, encounter_id: Option<Uuid>| {
+            json!({
+                "patient_id": Uuid::new_v4(),
+                "encounter_id": encounter_id,
+                "code": "8867-4",
+                "value": value,
+                "unit": "/min",
+                "effective": Utc::now(),
+            })
+        };
+        let mut batch = ValidatedBatch::from_values(
+            vec![reading(72.0, None), reading(240.0, None), reading(240.0, Some(lenient_encounter))],
+            Utc::now(),
+        );
+
+        reject_by_profiles(
+            &mut batch,
+            &HashMap::from([(lenient_encounter, Some(lenient_tenant))]),
+            &[profile(None, true), profile(Some(lenient_tenant), false)],
+        );
+
+        let result = batch.into_result(2);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].index, 1);
+        assert!(result.errors[0].message.starts_with("Rejected by validation profile v3"));
+    }
+}
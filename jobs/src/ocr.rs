@@ -0,0 +1,528 @@
+//! Document OCR and text extraction
+//!
+//! A DocumentOcr job reads an uploaded document from the API's document
+//! directory and stores its text in `emr.document_texts`, where a full-text
+//! index makes documents searchable by content. Only documents that were
+//! scanned clean are read. `text/plain` documents are read as they are;
+//! everything else goes to the configured backend: an Apache Tika server, or
+//! the Tesseract command line, which reads a PDF's text layer with
+//! `pdftotext` and OCRs the rendered pages of scanned PDFs that have none.
+//! Extracting a document again replaces its text.
+
+use crate::config::OcrConfig;
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::DocumentOcrJob;
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::services::ocr::{normalize_extracted_text, PlainTextExtractor, TextExtractor};
+use emr_core::{Error as CoreError, Result as CoreResult};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Text, Timestamptz};
+use diesel::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::info;
+use uuid::Uuid;
+
+/// Resolution scanned PDF pages are rendered at for OCR
+const OCR_DPI: &str = "300";
+
+/// An uploaded document, as the OCR job needs it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrDocument {
+    pub id: Uuid,
+    pub patient_id: Uuid,
+    pub content_type: String,
+    /// DocumentReference status, e.g. `current`
+    pub status: String,
+    /// Malware scan status: `pending`, `clean` or `infected`
+    pub scan_status: String,
+}
+
+/// Text extracted from a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentText {
+    pub document_id: Uuid,
+    /// Extractor that read the document, e.g. `tesseract`
+    pub extractor: String,
+    pub content: String,
+    pub extracted_at: DateTime<Utc>,
+}
+
+/// Uploaded documents and their extracted text
+#[async_trait]
+pub trait DocumentTextStore: Send + Sync {
+    /// A document, whatever its scan status
+    async fn document(&self, document_id: Uuid) -> JobResult<Option<OcrDocument>>;
+
+    /// Store a document's text, replacing any extracted before
+    async fn save_text(&self, text: &DocumentText) -> JobResult<()>;
+}
+
+/// Documents in the `emr` schema
+pub struct DatabaseDocumentTextStore {
+    pool: Pool,
+}
+
+impl DatabaseDocumentTextStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct OcrDocumentRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    patient_id: Uuid,
+    #[diesel(sql_type = Text)]
+    content_type: String,
+    #[diesel(sql_type = Text)]
+    status: String,
+    #[diesel(sql_type = Text)]
+    scan_status: String,
+}
+
+#[async_trait]
+impl DocumentTextStore for DatabaseDocumentTextStore {
+    async fn document(&self, document_id: Uuid) -> JobResult<Option<OcrDocument>> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, patient_id, content_type, status, scan_status \
+                     FROM emr.document_references WHERE id = $1",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(document_id)
+                .load::<OcrDocumentRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().next().map(|row| OcrDocument {
+            id: row.id,
+            patient_id: row.patient_id,
+            content_type: row.content_type,
+            status: row.status,
+            scan_status: row.scan_status,
+        }))
+    }
+
+    async fn save_text(&self, text: &DocumentText) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let text = text.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.document_texts (document_id, extractor, content, extracted_at) \
+                 VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (document_id) DO UPDATE SET extractor = EXCLUDED.extractor, \
+                 content = EXCLUDED.content, extracted_at = EXCLUDED.extracted_at",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(text.document_id)
+            .bind::<Text, _>(&text.extractor)
+            .bind::<Text, _>(&text.content)
+            .bind::<Timestamptz, _>(text.extracted_at)
+            .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// The configured OCR backend; `JobsConfig::validate` rejects unknown ones
+pub fn text_extractor(config: &OcrConfig) -> Arc<dyn TextExtractor> {
+    match config.backend.as_str() {
+        "tika" => Arc::new(TikaExtractor::new(&config.tika_url, &config.language, reqwest::Client::new())),
+        _ => Arc::new(TesseractExtractor::from_config(config)),
+    }
+}
+
+/// Apache Tika server; runs Tesseract itself for images and scanned PDFs
+pub struct TikaExtractor {
+    url: String,
+    language: String,
+    client: reqwest::Client,
+}
+
+impl TikaExtractor {
+    /// Create an extractor for the Tika server at `url`
+    pub fn new(url: &str, language: &str, client: reqwest::Client) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            language: language.to_string(),
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl TextExtractor for TikaExtractor {
+    fn name(&self) -> &str {
+        "tika"
+    }
+
+    fn supports(&self, content_type: &str) -> bool {
+        content_type == "application/pdf" || content_type.starts_with("image/")
+    }
+
+    async fn extract(&self, data: &[u8], content_type: &str) -> CoreResult<String> {
+        let response = self
+            .client
+            .put(format!("{}/tika", self.url))
+            .header("Content-Type", content_type)
+            .header("Accept", "text/plain")
+            .header("X-Tika-OCRLanguage", &self.language)
+            .body(data.to_vec())
+            .send()
+            .await
+            .map_err(|e| CoreError::external_service_error("Tika", &e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(CoreError::external_service_error(
+                "Tika",
+                &format!("Extraction failed with status {}", response.status()),
+            ));
+        }
+        response
+            .text()
+            .await
+            .map_err(|e| CoreError::external_service_error("Tika", &e.to_string()))
+    }
+}
+
+/// Tesseract and Poppler command-line tools
+pub struct TesseractExtractor {
+    tesseract: String,
+    pdftotext: String,
+    pdftoppm: String,
+    language: String,
+}
+
+impl TesseractExtractor {
+    /// Create an extractor running the configured tools
+    pub fn from_config(config: &OcrConfig) -> Self {
+        Self {
+            tesseract: config.tesseract_path.clone(),
+            pdftotext: config.pdftotext_path.clone(),
+            pdftoppm: config.pdftoppm_path.clone(),
+            language: config.language.clone(),
+        }
+    }
+
+    /// OCR an image file, or standard input with `stdin`
+    async fn ocr(&self, image: &str, input: Option<&[u8]>) -> CoreResult<String> {
+        let output = run(&self.tesseract, &[image, "stdout", "-l", &self.language], input).await?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Text of a PDF: its text layer, or the OCR of its rendered pages when
+    /// it has none
+    async fn extract_pdf(&self, data: &[u8]) -> CoreResult<String> {
+        let text = run(&self.pdftotext, &["-layout", "-", "-"], Some(data)).await?;
+        let text = String::from_utf8_lossy(&text);
+        if !text.trim().is_empty() {
+            return Ok(text.into_owned());
+        }
+
+        let directory = std::env::temp_dir().join(format!("ocr-{}", Uuid::new_v4()));
+        let result = self.ocr_pages(&directory, data).await;
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        result
+    }
+
+    async fn ocr_pages(&self, directory: &Path, data: &[u8]) -> CoreResult<String> {
+        let io_error = |e: std::io::Error| CoreError::internal_error(&format!("OCR scratch space: {}", e));
+        tokio::fs::create_dir_all(directory).await.map_err(io_error)?;
+        let source = directory.join("document.pdf");
+        tokio::fs::write(&source, data).await.map_err(io_error)?;
+
+        let prefix = directory.join("page");
+        run(
+            &self.pdftoppm,
+            &["-r", OCR_DPI, "-png", &source.to_string_lossy(), &prefix.to_string_lossy()],
+            None,
+        )
+        .await?;
+
+        // pdftoppm zero-pads page numbers, so names sort in page order
+        let mut pages: Vec<PathBuf> = Vec::new();
+        let mut entries = tokio::fs::read_dir(directory).await.map_err(io_error)?;
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "png") {
+                pages.push(path);
+            }
+        }
+        pages.sort();
+
+        let mut text = Vec::with_capacity(pages.len());
+        for page in pages {
+            text.push(self.ocr(&page.to_string_lossy(), None).await?);
+        }
+        Ok(text.join("\n"))
+    }
+}
+
+#[async_trait]
+impl TextExtractor for TesseractExtractor {
+    fn name(&self) -> &str {
+        "tesseract"
+    }
+
+    fn supports(&self, content_type: &str) -> bool {
+        matches!(
+            content_type,
+            "application/pdf" | "image/jpeg" | "image/png" | "image/tiff" | "image/bmp" | "image/gif" | "image/webp"
+        )
+    }
+
+    async fn extract(&self, data: &[u8], content_type: &str) -> CoreResult<String> {
+        if content_type == "application/pdf" {
+            self.extract_pdf(data).await
+        } else {
+            self.ocr("stdin", Some(data)).await
+        }
+    }
+}
+
+/// Run a tool and capture its standard output
+async fn run(program: &str, args: &[&str], input: Option<&[u8]>) -> CoreResult<Vec<u8>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A timed-out extraction drops the future; take the tool with it
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| CoreError::external_service_error(program, &format!("Failed to run: {}", e)))?;
+
+    // Feed input while output is read so neither pipe fills up
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        let input = input.to_vec();
+        tokio::spawn(async move {
+            let _ = stdin.write_all(&input).await;
+        });
+    }
+
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| CoreError::external_service_error(program, &e.to_string()))?;
+    if !output.status.success() {
+        return Err(CoreError::external_service_error(
+            program,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Extracts and stores the text of uploaded documents
+pub struct DocumentOcrHandler {
+    config: OcrConfig,
+    store: Arc<dyn DocumentTextStore>,
+    extractors: Vec<Arc<dyn TextExtractor>>,
+}
+
+impl DocumentOcrHandler {
+    /// Create a handler reading documents with `extractor`, plain text aside
+    pub fn new(config: OcrConfig, store: Arc<dyn DocumentTextStore>, extractor: Arc<dyn TextExtractor>) -> Self {
+        Self {
+            config,
+            store,
+            extractors: vec![Arc::new(PlainTextExtractor), extractor],
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler<DocumentOcrJob> for DocumentOcrHandler {
+    async fn execute(&self, job: DocumentOcrJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(job_id = ?context.job_id, document_id = %job.document_id, "Starting document OCR job");
+
+        if !self.config.enabled {
+            return Err(JobError::ValidationError("OCR is not enabled".to_string()));
+        }
+        let document = self
+            .store
+            .document(job.document_id)
+            .await?
+            .ok_or_else(|| JobError::ValidationError(format!("Document {} not found", job.document_id)))?;
+        if document.scan_status != "clean" {
+            return Err(JobError::ValidationError(format!(
+                "Document {} has not been scanned clean",
+                document.id
+            )));
+        }
+        if document.status != "current" {
+            return Ok(JobExecutionResult::success(format!(
+                "Document {} is no longer current; skipped",
+                document.id
+            )));
+        }
+        let Some(extractor) = self
+            .extractors
+            .iter()
+            .find(|extractor| extractor.supports(&document.content_type))
+        else {
+            return Ok(JobExecutionResult::success(format!(
+                "No extractor reads {} documents; skipped",
+                document.content_type
+            )));
+        };
+
+        let path = Path::new(&self.config.document_dir).join(document.id.to_string());
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| JobError::ProcessingError(format!("Cannot read document {}: {}", path.display(), e)))?;
+        context.check_cancelled()?;
+
+        let raw = tokio::time::timeout(
+            Duration::from_secs(self.config.timeout),
+            extractor.extract(&data, &document.content_type),
+        )
+        .await
+        .map_err(|_| JobError::TimeoutError(format!("Text extraction of document {} timed out", document.id)))?
+        .map_err(|e| JobError::ExternalServiceError(e.to_string()))?;
+        let content = normalize_extracted_text(&raw, self.config.max_text_chars);
+        let characters = content.chars().count();
+
+        self.store
+            .save_text(&DocumentText {
+                document_id: document.id,
+                extractor: extractor.name().to_string(),
+                content,
+                extracted_at: Utc::now(),
+            })
+            .await?;
+
+        Ok(JobExecutionResult::success_with_data(
+            format!("Extracted {} characters from document {}", characters, document.id),
+            serde_json::json!({
+                "document_id": document.id,
+                "patient_id": document.patient_id,
+                "extractor": extractor.name(),
+                "characters": characters,
+            }),
+        )
+        .with_metric("characters".to_string(), characters as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "document_ocr"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Documents and extracted text kept in memory, for tests
+    #[derive(Default)]
+    struct MemoryDocumentTextStore {
+        documents: Vec<OcrDocument>,
+        texts: Mutex<Vec<DocumentText>>,
+    }
+
+    #[async_trait]
+    impl DocumentTextStore for MemoryDocumentTextStore {
+        async fn document(&self, document_id: Uuid) -> JobResult<Option<OcrDocument>> {
+            Ok(self.documents.iter().find(|document| document.id == document_id).cloned())
+        }
+
+        async fn save_text(&self, text: &DocumentText) -> JobResult<()> {
+            self.texts.lock().unwrap().push(text.clone());
+            Ok(())
+        }
+    }
+
+    /// Reads images as their bytes' text, for tests
+    struct FakeOcr;
+
+    #[async_trait]
+    impl TextExtractor for FakeOcr {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn supports(&self, content_type: &str) -> bool {
+            content_type.starts_with("image/")
+        }
+
+        async fn extract(&self, data: &[u8], _content_type: &str) -> CoreResult<String> {
+            Ok(String::from_utf8_lossy(data).into_owned())
+        }
+    }
+
+    fn document(content_type: &str, scan_status: &str) -> OcrDocument {
+        OcrDocument {
+            id: Uuid::new_v4(),
+            patient_id: Uuid::new_v4(),
+            content_type: content_type.to_string(),
+            status: "current".to_string(),
+            scan_status: scan_status.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_ocr_stores_text_of_clean_documents() {
+        let directory = std::env::temp_dir().join(format!("documents-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (scan, note, pending, video) = (
+            document("image/png", "clean"),
+            document("text/plain", "clean"),
+            document("image/png", "pending"),
+            document("video/mp4", "clean"),
+        );
+        for (document, content) in [(&scan, "  Lipid   panel\n\nLDL 130 "), (&note, "Referral letter")] {
+            std::fs::write(directory.join(document.id.to_string()), content).unwrap();
+        }
+        let store = Arc::new(MemoryDocumentTextStore {
+            documents: vec![scan.clone(), note.clone(), pending.clone(), video.clone()],
+            ..MemoryDocumentTextStore::default()
+        });
+        let config = OcrConfig {
+            enabled: true,
+            document_dir: directory.display().to_string(),
+            ..OcrConfig::default()
+        };
+        let handler = DocumentOcrHandler::new(config, store.clone(), Arc::new(FakeOcr));
+        let run = |document: &OcrDocument| {
+            handler.execute(DocumentOcrJob { document_id: document.id }, JobContext::new(Uuid::new_v4()))
+        };
+
+        let result = run(&scan).await.unwrap();
+        assert_eq!(result.data.unwrap()["extractor"], "fake");
+        run(&note).await.unwrap();
+        assert!(run(&pending).await.is_err());
+        assert!(run(&video).await.unwrap().data.is_none());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let texts = store.texts.lock().unwrap();
+        assert_eq!(texts.len(), 2);
+        assert_eq!((texts[0].content.as_str(), texts[0].document_id), ("Lipid panel\nLDL 130", scan.id));
+        assert_eq!((texts[1].extractor.as_str(), texts[1].content.as_str()), ("plain", "Referral letter"));
+    }
+}
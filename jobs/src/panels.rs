@@ -0,0 +1,263 @@
+//! Nightly refresh of saved patient panels
+//!
+//! Panels saved with the nightly refresh are found again once a day: from
+//! the configured hour on, each worker poll claims the nightly panels not yet
+//! queued since then and queues a PanelRefresh job for each. The job runs the
+//! panel's compiled criteria (see `emr_core::services::panels`) and replaces its
+//! members with the patients found, the same way the API does when a panel
+//! is evaluated on demand. A panel deleted in the meantime is skipped.
+
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::PanelRefreshJob;
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use emr_core::domain::PanelCriterion;
+use emr_core::services::panels::{member_query, PanelParam};
+use deadpool_diesel::postgres::Pool;
+use diesel::pg::Pg;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Most panels claimed for refresh per poll
+pub const CLAIM_BATCH_SIZE: i64 = 100;
+
+/// Start of the current nightly refresh period: today at `hour` (UTC), or
+/// yesterday at `hour` when that is still ahead
+pub fn nightly_due_since(now: DateTime<Utc>, hour: u32) -> DateTime<Utc> {
+    let time = NaiveTime::from_hms_opt(hour.min(23), 0, 0).unwrap_or(NaiveTime::MIN);
+    let today = now.date_naive().and_time(time).and_utc();
+    if today <= now {
+        today
+    } else {
+        today - Duration::days(1)
+    }
+}
+
+/// Saved patient panels and their members
+#[async_trait]
+pub trait PanelStore: Send + Sync {
+    /// Mark up to `limit` nightly panels not queued since `due_since` as
+    /// queued, returning their ids
+    async fn claim_due(&self, due_since: DateTime<Utc>, limit: i64) -> JobResult<Vec<Uuid>>;
+
+    /// Replace a panel's members with the patients its criteria find,
+    /// returning how many were found, or `None` if the panel is gone
+    async fn refresh(&self, panel_id: Uuid) -> JobResult<Option<i64>>;
+}
+
+/// Panels in the `emr` schema
+pub struct DatabasePanelStore {
+    pool: Pool,
+}
+
+impl DatabasePanelStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PanelIdRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+}
+
+#[derive(diesel::QueryableByName)]
+struct PanelCriteriaRow {
+    #[diesel(sql_type = Text)]
+    criteria: String,
+}
+
+/// Bind a compiled panel query's parameters, in order
+fn bind_params(
+    mut query: BoxedSqlQuery<'static, Pg, SqlQuery>,
+    params: Vec<PanelParam>,
+) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+    for param in params {
+        query = match param {
+            PanelParam::Text(value) => query.bind::<Text, _>(value),
+            PanelParam::Integer(value) => query.bind::<diesel::sql_types::Integer, _>(value),
+            PanelParam::Double(value) => query.bind::<diesel::sql_types::Double, _>(value),
+            PanelParam::Timestamp(value) => query.bind::<Timestamptz, _>(value),
+        };
+    }
+    query
+}
+
+#[async_trait]
+impl PanelStore for DatabasePanelStore {
+    async fn claim_due(&self, due_since: DateTime<Utc>, limit: i64) -> JobResult<Vec<Uuid>> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "UPDATE emr.patient_panels SET refresh_queued_at = NOW() WHERE id IN (\
+                     SELECT id FROM emr.patient_panels WHERE refresh = 'nightly' \
+                     AND (refresh_queued_at IS NULL OR refresh_queued_at < $1) \
+                     ORDER BY refresh_queued_at NULLS FIRST LIMIT $2 FOR UPDATE SKIP LOCKED) \
+                     RETURNING id",
+                )
+                .bind::<Timestamptz, _>(due_since)
+                .bind::<BigInt, _>(limit)
+                .load::<PanelIdRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().map(|row| row.id).collect())
+    }
+
+    async fn refresh(&self, panel_id: Uuid) -> JobResult<Option<i64>> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query("SELECT criteria::text AS criteria FROM emr.patient_panels WHERE id = $1")
+                    .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                    .load::<PanelCriteriaRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let Some(row) = rows.into_iter().next() else {
+            return Ok(None);
+        };
+        let criteria: Vec<PanelCriterion> =
+            serde_json::from_str(&row.criteria).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let query = member_query(&criteria);
+
+        let found = conn
+            .interact(move |conn| {
+                conn.transaction(|conn| {
+                    diesel::sql_query("DELETE FROM emr.patient_panel_members WHERE panel_id = $1")
+                        .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                        .execute(conn)?;
+                    let insert = diesel::sql_query(format!(
+                        "INSERT INTO emr.patient_panel_members (panel_id, patient_id) \
+                         SELECT ${}, id FROM ({}) members",
+                        query.params.len() + 1,
+                        query.sql
+                    ))
+                    .into_boxed();
+                    let found = bind_params(insert, query.params)
+                        .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                        .execute(conn)? as i64;
+                    diesel::sql_query(
+                        "UPDATE emr.patient_panels SET evaluated_at = NOW(), member_count = $2 WHERE id = $1",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(panel_id)
+                    .bind::<BigInt, _>(found)
+                    .execute(conn)?;
+                    Ok::<_, DieselError>(found)
+                })
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(Some(found))
+    }
+}
+
+/// Panel refresh job handler
+pub struct PanelRefreshHandler {
+    store: Arc<dyn PanelStore>,
+}
+
+impl PanelRefreshHandler {
+    /// Create a handler refreshing panels in `store`
+    pub fn new(store: Arc<dyn PanelStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl JobHandler<PanelRefreshJob> for PanelRefreshHandler {
+    async fn execute(&self, job: PanelRefreshJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(job_id = ?context.job_id, panel_id = %job.panel_id, "Starting panel refresh job");
+
+        let Some(members) = self.store.refresh(job.panel_id).await? else {
+            return Ok(JobExecutionResult::success(format!(
+                "Panel {} no longer exists; skipped",
+                job.panel_id
+            )));
+        };
+
+        Ok(JobExecutionResult::success_with_data(
+            format!("Panel {} has {} members", job.panel_id, members),
+            serde_json::json!({ "panel_id": job.panel_id, "members": members }),
+        )
+        .with_metric("members".to_string(), members as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "PanelRefresh"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    /// Panel member counts kept in memory, for tests
+    #[derive(Default)]
+    struct MemoryPanelStore {
+        members: HashMap<Uuid, i64>,
+    }
+
+    #[async_trait]
+    impl PanelStore for MemoryPanelStore {
+        async fn claim_due(&self, _due_since: DateTime<Utc>, _limit: i64) -> JobResult<Vec<Uuid>> {
+            Ok(self.members.keys().copied().collect())
+        }
+
+        async fn refresh(&self, panel_id: Uuid) -> JobResult<Option<i64>> {
+            Ok(self.members.get(&panel_id).copied())
+        }
+    }
+
+    #[test]
+    fn test_nightly_due_since() {
+        let evening = Utc.with_ymd_and_hms(2024, 3, 5, 22, 30, 0).unwrap();
+        assert_eq!(nightly_due_since(evening, 2), Utc.with_ymd_and_hms(2024, 3, 5, 2, 0, 0).unwrap());
+        let early = Utc.with_ymd_and_hms(2024, 3, 5, 1, 59, 0).unwrap();
+        assert_eq!(nightly_due_since(early, 2), Utc.with_ymd_and_hms(2024, 3, 4, 2, 0, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_panel_refresh_skips_deleted_panels() {
+        let panel_id = Uuid::new_v4();
+        let store = MemoryPanelStore {
+            members: HashMap::from([(panel_id, 42)]),
+        };
+        let handler = PanelRefreshHandler::new(Arc::new(store));
+
+        let result = handler
+            .execute(PanelRefreshJob { panel_id }, JobContext::new(Uuid::new_v4()))
+            .await
+            .unwrap();
+        assert_eq!(result.metrics.get("members"), Some(&42.0));
+
+        let deleted = PanelRefreshJob { panel_id: Uuid::new_v4() };
+        let result = handler.execute(deleted, JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(result.message.contains("no longer exists"));
+    }
+}
@@ -0,0 +1,392 @@
+//! Maintenance of monthly partitioned tables
+//!
+//! Once a day, from `partitions.maintenance_hour`, the first worker poll to
+//! claim the maintenance queues a PartitionMaintenance job for each
+//! partitioned table (see `emr_core::partitions`). The job creates the
+//! partitions of this month and the `partitions.months_ahead` months after
+//! it, gives months whose rows landed in the default partition a partition
+//! of their own, and expires partitions past the retention period of the
+//! table's entity type: `archive` detaches them into the `emr_archive`
+//! schema, `purge` drops them.
+//!
+//! Each change is its own transaction and waits at most [`LOCK_TIMEOUT`]
+//! for the table's locks, so on a busy table the job fails and is retried
+//! rather than queueing the table's queries behind it.
+
+use crate::config::PartitionConfig;
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::PartitionMaintenanceJob;
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::partitions::{self, Month, PartitionedTable, PARTITIONED_TABLES};
+use emr_core::retention::{RetentionAction, RetentionPolicySet};
+use deadpool_diesel::postgres::Pool;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{Integer, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use std::sync::Arc;
+use tracing::info;
+
+/// Name the daily maintenance is claimed under in `jobs.maintenance_tasks`
+pub const MAINTENANCE_TASK: &str = "partition_maintenance";
+
+/// How long a change waits for the table's locks
+pub const LOCK_TIMEOUT: &str = "10s";
+
+/// Jobs queued by a claimed maintenance, one per partitioned table
+pub fn maintenance_jobs() -> Vec<PartitionMaintenanceJob> {
+    PARTITIONED_TABLES
+        .iter()
+        .map(|table| PartitionMaintenanceJob {
+            table: table.qualified(),
+            dry_run: false,
+        })
+        .collect()
+}
+
+/// Partitions of the partitioned tables
+#[async_trait]
+pub trait PartitionStore: Send + Sync {
+    /// Mark the daily maintenance as queued unless it has been since
+    /// `due_since`; whether this call claimed it
+    async fn claim_maintenance(&self, due_since: DateTime<Utc>) -> JobResult<bool>;
+
+    /// Months with a partition of their own
+    async fn partitions(&self, table: PartitionedTable) -> JobResult<Vec<Month>>;
+
+    /// Months with rows in the default partition
+    async fn default_months(&self, table: PartitionedTable) -> JobResult<Vec<Month>>;
+
+    /// Give a month a partition, moving its rows out of the default
+    /// partition
+    async fn create(&self, table: PartitionedTable, month: Month) -> JobResult<()>;
+
+    /// Archive or drop a month's partition
+    async fn expire(&self, table: PartitionedTable, month: Month, action: RetentionAction) -> JobResult<()>;
+}
+
+/// Partitions in the database catalog
+pub struct DatabasePartitionStore {
+    pool: Pool,
+}
+
+impl DatabasePartitionStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    /// Run statements in one transaction, waiting at most [`LOCK_TIMEOUT`]
+    /// for locks
+    async fn alter(&self, statements: Vec<String>) -> JobResult<()> {
+        let conn = self.connection().await?;
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query(format!("SET LOCAL lock_timeout = '{}'", LOCK_TIMEOUT)).execute(conn)?;
+                for statement in &statements {
+                    diesel::sql_query(statement).execute(conn)?;
+                }
+                Ok::<_, DieselError>(())
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct PartitionRow {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(diesel::QueryableByName)]
+struct MonthRow {
+    #[diesel(sql_type = Integer)]
+    year: i32,
+    #[diesel(sql_type = Integer)]
+    month: i32,
+}
+
+#[derive(diesel::QueryableByName)]
+struct ExistsRow {
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    found: bool,
+}
+
+#[async_trait]
+impl PartitionStore for DatabasePartitionStore {
+    async fn claim_maintenance(&self, due_since: DateTime<Utc>) -> JobResult<bool> {
+        let conn = self.connection().await?;
+
+        let claimed = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "INSERT INTO jobs.maintenance_tasks (task, queued_at) VALUES ($1, NOW()) \
+                     ON CONFLICT (task) DO UPDATE SET queued_at = NOW() \
+                     WHERE jobs.maintenance_tasks.queued_at < $2",
+                )
+                .bind::<Text, _>(MAINTENANCE_TASK)
+                .bind::<Timestamptz, _>(due_since)
+                .execute(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(claimed > 0)
+    }
+
+    async fn partitions(&self, table: PartitionedTable) -> JobResult<Vec<Month>> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT c.relname::text AS name FROM pg_inherits i \
+                     JOIN pg_class c ON c.oid = i.inhrelid \
+                     WHERE i.inhparent = $1::regclass",
+                )
+                .bind::<Text, _>(table.qualified())
+                .load::<PartitionRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().filter_map(|row| table.month_of(&row.name)).collect())
+    }
+
+    async fn default_months(&self, table: PartitionedTable) -> JobResult<Vec<Month>> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(format!(
+                    "SELECT DISTINCT EXTRACT(YEAR FROM {column} AT TIME ZONE 'UTC')::int AS year, \
+                     EXTRACT(MONTH FROM {column} AT TIME ZONE 'UTC')::int AS month FROM {}",
+                    table.default_partition(),
+                    column = table.column
+                ))
+                .load::<MonthRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| Month::new(row.year, u32::try_from(row.month).ok()?))
+            .collect())
+    }
+
+    async fn create(&self, table: PartitionedTable, month: Month) -> JobResult<()> {
+        let conn = self.connection().await?;
+
+        let in_default = conn
+            .interact(move |conn| {
+                diesel::sql_query(format!(
+                    "SELECT EXISTS (SELECT 1 FROM {} WHERE {column} >= $1 AND {column} < $2) AS found",
+                    table.default_partition(),
+                    column = table.column
+                ))
+                .bind::<Timestamptz, _>(month.start())
+                .bind::<Timestamptz, _>(month.end())
+                .get_result::<ExistsRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        drop(conn);
+
+        if in_default.found {
+            self.alter(table.adopt_sql(month)).await
+        } else {
+            self.alter(vec![table.create_sql(month)]).await
+        }
+    }
+
+    async fn expire(&self, table: PartitionedTable, month: Month, action: RetentionAction) -> JobResult<()> {
+        let statements = table
+            .expire_sql(month, action)
+            .map_err(|e| JobError::ValidationError(e.to_string()))?;
+        self.alter(statements).await
+    }
+}
+
+/// Partition maintenance job handler
+pub struct PartitionMaintenanceHandler {
+    config: PartitionConfig,
+    policies: RetentionPolicySet,
+    store: Arc<dyn PartitionStore>,
+}
+
+impl PartitionMaintenanceHandler {
+    /// Create a handler maintaining the partitions in `store`
+    pub fn new(config: PartitionConfig, policies: RetentionPolicySet, store: Arc<dyn PartitionStore>) -> Self {
+        Self {
+            config,
+            policies,
+            store,
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler<PartitionMaintenanceJob> for PartitionMaintenanceHandler {
+    async fn execute(&self, job: PartitionMaintenanceJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            table = %job.table,
+            dry_run = job.dry_run,
+            "Starting partition maintenance job"
+        );
+
+        let table = PartitionedTable::find(&job.table)
+            .ok_or_else(|| JobError::ValidationError(format!("{} is not partitioned by month", job.table)))?;
+        let existing = self.store.partitions(table).await?;
+        let in_default = self.store.default_months(table).await?;
+        let plan = partitions::plan(
+            &existing,
+            &in_default,
+            Utc::now(),
+            self.config.months_ahead,
+            self.policies.policy_for(table.entity_type, None),
+        );
+
+        if !job.dry_run {
+            let total = plan.create.len() + plan.expire.len();
+            for (index, month) in plan.create.iter().enumerate() {
+                context.check_cancelled()?;
+                self.store.create(table, *month).await?;
+                info!(partition = %table.partition(*month), "Created partition");
+                context.progress.step(index + 1, total);
+            }
+            for (index, (month, action)) in plan.expire.iter().enumerate() {
+                context.check_cancelled()?;
+                self.store.expire(table, *month, *action).await?;
+                info!(partition = %table.partition(*month), ?action, "Expired partition");
+                context.progress.step(plan.create.len() + index + 1, total);
+            }
+        }
+
+        let message = format!(
+            "Partitions of {} {}: {} created, {} expired",
+            job.table,
+            if job.dry_run { "dry run" } else { "maintained" },
+            plan.create.len(),
+            plan.expire.len()
+        );
+        let data = serde_json::to_value(&plan).map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        Ok(JobExecutionResult::success_with_data(message, data)
+            .with_metric("partitions_created".to_string(), plan.create.len() as f64)
+            .with_metric("partitions_expired".to_string(), plan.expire.len() as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "PartitionMaintenance"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::retention::{RetentionConfig, RetentionPolicy};
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Partitions held in memory
+    #[derive(Default)]
+    struct MemoryPartitionStore {
+        months: Mutex<Vec<Month>>,
+        in_default: Mutex<Vec<Month>>,
+        expired: Mutex<Vec<(Month, RetentionAction)>>,
+    }
+
+    #[async_trait]
+    impl PartitionStore for MemoryPartitionStore {
+        async fn claim_maintenance(&self, _due_since: DateTime<Utc>) -> JobResult<bool> {
+            Ok(true)
+        }
+
+        async fn partitions(&self, _table: PartitionedTable) -> JobResult<Vec<Month>> {
+            Ok(self.months.lock().unwrap().clone())
+        }
+
+        async fn default_months(&self, _table: PartitionedTable) -> JobResult<Vec<Month>> {
+            Ok(self.in_default.lock().unwrap().clone())
+        }
+
+        async fn create(&self, _table: PartitionedTable, month: Month) -> JobResult<()> {
+            self.in_default.lock().unwrap().retain(|other| *other != month);
+            self.months.lock().unwrap().push(month);
+            Ok(())
+        }
+
+        async fn expire(&self, _table: PartitionedTable, month: Month, action: RetentionAction) -> JobResult<()> {
+            self.months.lock().unwrap().retain(|other| *other != month);
+            self.expired.lock().unwrap().push((month, action));
+            Ok(())
+        }
+    }
+
+    fn job(dry_run: bool) -> PartitionMaintenanceJob {
+        PartitionMaintenanceJob {
+            table: "emr.observations".to_string(),
+            dry_run,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partition_maintenance() {
+        let current = Month::containing(Utc::now());
+        let backfilled = Month::new(2001, 5).unwrap();
+        let store = Arc::new(MemoryPartitionStore::default());
+        store.months.lock().unwrap().push(current);
+        store.in_default.lock().unwrap().push(backfilled);
+        let policies = RetentionPolicySet::new(vec![RetentionPolicy::new(
+            "Observation",
+            365,
+            RetentionAction::Archive,
+        )])
+        .unwrap();
+        let handler = PartitionMaintenanceHandler::new(PartitionConfig::default(), policies, store.clone());
+
+        let result = handler.execute(job(true), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert_eq!(result.data.unwrap()["create"].as_array().unwrap().len(), 4);
+        assert_eq!(store.months.lock().unwrap().len(), 1);
+
+        handler.execute(job(false), JobContext::new(Uuid::new_v4())).await.unwrap();
+        let mut months = store.months.lock().unwrap().clone();
+        months.sort();
+        assert_eq!(months, vec![current, current.plus(1), current.plus(2), current.plus(3)]);
+        assert_eq!(*store.expired.lock().unwrap(), vec![(backfilled, RetentionAction::Archive)]);
+        assert!(store.in_default.lock().unwrap().is_empty());
+
+        // Nothing is left to do the same day
+        let result = handler.execute(job(false), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert_eq!(result.metrics.get("partitions_created"), Some(&0.0));
+
+        let unknown = PartitionMaintenanceJob {
+            table: "emr.patients".to_string(),
+            dry_run: false,
+        };
+        let handler = PartitionMaintenanceHandler::new(
+            PartitionConfig::default(),
+            RetentionPolicySet::from_config(&RetentionConfig::default()).unwrap(),
+            store,
+        );
+        assert!(handler.execute(unknown, JobContext::new(Uuid::new_v4())).await.is_err());
+    }
+}
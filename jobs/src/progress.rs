@@ -0,0 +1,239 @@
+//! Job progress reporting
+//!
+//! Handlers emit progress, status transitions, and log lines through the
+//! [`ProgressReporter`] carried on `JobContext`. The worker forwards these
+//! events to NATS so the API can relay them to clients as server-sent events.
+
+use crate::types::JobStatus;
+use chrono::{DateTime, Utc};
+use emr_core::events::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Number of buffered events per job before slow subscribers start lagging
+const EVENT_BUFFER_SIZE: usize = 64;
+
+/// NATS subject that carries progress events for a job, which the API
+/// relays to clients
+pub fn events_subject(job_id: Uuid) -> String {
+    format!("jobs.{}.events", job_id)
+}
+
+/// Event emitted while a job is running
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvent {
+    pub job_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: JobEventKind,
+}
+
+/// Published as `job.event`; see [`emr_core::events::schemas`]
+impl Event for JobEvent {
+    const EVENT_TYPE: &'static str = "job.event";
+    const VERSION: u32 = 1;
+}
+
+/// Job event payloads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEventKind {
+    /// Progress update in percent (0.0 - 100.0)
+    Progress { progress: f64 },
+
+    /// Status transition
+    Status { status: JobStatus },
+
+    /// Log line produced by the handler
+    Log { level: LogLevel, message: String },
+}
+
+/// Log levels for job log lines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl JobEvent {
+    /// SSE event name for this event
+    pub fn event_name(&self) -> &'static str {
+        match self.kind {
+            JobEventKind::Progress { .. } => "progress",
+            JobEventKind::Status { .. } => "status",
+            JobEventKind::Log { .. } => "log",
+        }
+    }
+
+    /// Check if this event ends the job's event stream
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self.kind,
+            JobEventKind::Status {
+                status: JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+            }
+        )
+    }
+}
+
+/// Handle used by job handlers to publish progress events
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    job_id: Uuid,
+    sender: broadcast::Sender<JobEvent>,
+}
+
+impl ProgressReporter {
+    /// Create a new progress reporter for a job
+    pub fn new(job_id: Uuid) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Self { job_id, sender }
+    }
+
+    /// Subscribe to the events emitted for this job
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Report progress in percent, clamped to 0.0 - 100.0
+    pub fn progress(&self, progress: f64) {
+        self.emit(JobEventKind::Progress {
+            progress: progress.clamp(0.0, 100.0),
+        });
+    }
+
+    /// Report progress as a fraction of completed steps
+    pub fn step(&self, completed: usize, total: usize) {
+        if total > 0 {
+            self.progress(completed as f64 / total as f64 * 100.0);
+        }
+    }
+
+    /// Report a status transition
+    pub fn status(&self, status: JobStatus) {
+        self.emit(JobEventKind::Status { status });
+    }
+
+    /// Emit an informational log line
+    pub fn info(&self, message: impl Into<String>) {
+        self.log(LogLevel::Info, message.into());
+    }
+
+    /// Emit a warning log line
+    pub fn warning(&self, message: impl Into<String>) {
+        self.log(LogLevel::Warning, message.into());
+    }
+
+    /// Emit an error log line
+    pub fn error(&self, message: impl Into<String>) {
+        self.log(LogLevel::Error, message.into());
+    }
+
+    fn log(&self, level: LogLevel, message: String) {
+        self.emit(JobEventKind::Log { level, message });
+    }
+
+    fn emit(&self, kind: JobEventKind) {
+        // Sending only fails when nobody is listening, which is fine for
+        // jobs that run without an attached subscriber.
+        let _ = self.sender.send(JobEvent {
+            job_id: self.job_id,
+            timestamp: Utc::now(),
+            kind,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_subject() {
+        assert_eq!(events_subject(Uuid::nil()), "jobs.00000000-0000-0000-0000-000000000000.events");
+    }
+
+    #[tokio::test]
+    async fn test_reporter_emits_events() {
+        let job_id = Uuid::new_v4();
+        let reporter = ProgressReporter::new(job_id);
+        let mut receiver = reporter.subscribe();
+
+        reporter.status(JobStatus::Running);
+        reporter.step(1, 4);
+        reporter.info("processing");
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.job_id, job_id);
+        assert_eq!(event.event_name(), "status");
+
+        let event = receiver.recv().await.unwrap();
+        assert!(matches!(event.kind, JobEventKind::Progress { progress } if progress == 25.0));
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.event_name(), "log");
+    }
+
+    #[test]
+    fn test_progress_is_clamped() {
+        let reporter = ProgressReporter::new(Uuid::new_v4());
+        let mut receiver = reporter.subscribe();
+
+        reporter.progress(150.0);
+
+        let event = receiver.try_recv().unwrap();
+        assert!(matches!(event.kind, JobEventKind::Progress { progress } if progress == 100.0));
+    }
+
+    #[test]
+    fn test_terminal_events() {
+        let reporter = ProgressReporter::new(Uuid::new_v4());
+        let mut receiver = reporter.subscribe();
+
+        reporter.status(JobStatus::Running);
+        reporter.status(JobStatus::Completed);
+
+        assert!(!receiver.try_recv().unwrap().is_terminal());
+        assert!(receiver.try_recv().unwrap().is_terminal());
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = JobEvent {
+            job_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            kind: JobEventKind::Progress { progress: 50.0 },
+        };
+
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"event\":\"progress\""));
+        assert!(json.contains("\"progress\":50.0"));
+    }
+
+    #[test]
+    fn test_events_match_published_schema() {
+        let schema = emr_core::events::schema(JobEvent::EVENT_TYPE, JobEvent::VERSION).unwrap();
+        let job_id = Uuid::new_v4();
+        let events = [
+            JobEventKind::Progress { progress: 50.0 },
+            JobEventKind::Status { status: JobStatus::Running },
+            JobEventKind::Log {
+                level: LogLevel::Info,
+                message: "processing".to_string(),
+            },
+        ];
+
+        for kind in events {
+            let event = JobEvent {
+                job_id,
+                timestamp: Utc::now(),
+                kind,
+            };
+            let problems = schema.validate(&serde_json::to_value(&event).unwrap());
+            assert!(problems.is_empty(), "{}: {:?}", event.event_name(), problems);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Provenance records for entities created by imports and FHIR sync
+//!
+//! Import and sync handlers record a [`Provenance`] for every entity they
+//! create, built with [`import_provenance`] or [`sync_provenance`] so the
+//! source system, job id and transformation version are filled in the same
+//! way everywhere. The API serves them on `GET /api/{type}/{id}/provenance`.
+
+use crate::types::{FhirSyncJob, ImportProvenance};
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use emr_core::domain::values::Identifier;
+use emr_core::domain::{Provenance, ProvenanceActivity};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Nullable, Text, Timestamptz};
+use diesel::RunQueryDsl;
+use uuid::Uuid;
+
+/// Version recorded as the transformation applied to imported data
+pub const TRANSFORMATION_VERSION: &str = concat!("emr-jobs/", env!("CARGO_PKG_VERSION"));
+
+/// Insert a provenance record
+pub const INSERT_PROVENANCE_QUERY: &str = r#"
+INSERT INTO emr.provenance
+    (id, target_type, target_id, activity, source_system, job_id, original_identifiers, transformation_version, source_reference, recorded_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, $8, $9, $10)
+"#;
+
+/// Provenance of an entity created from an ingested file
+pub fn import_provenance(
+    file: &ImportProvenance,
+    job_id: Uuid,
+    target_type: &str,
+    target_id: Uuid,
+    original_identifiers: Vec<Identifier>,
+) -> Provenance {
+    Provenance::new(
+        target_type,
+        target_id,
+        ProvenanceActivity::Import,
+        &file.source_system,
+        TRANSFORMATION_VERSION,
+    )
+    .with_job(job_id)
+    .with_source_reference(&format!("{} (sha256 {})", file.file_name, file.sha256))
+    .with_original_identifiers(original_identifiers)
+}
+
+/// Provenance of an entity created by a FHIR sync; the source resource's
+/// server id is kept as an original identifier
+pub fn sync_provenance(job: &FhirSyncJob, job_id: Uuid, target_id: Uuid, source_resource_id: &str) -> Provenance {
+    Provenance::new(
+        &job.resource_type,
+        target_id,
+        ProvenanceActivity::FhirSync,
+        &job.source_url,
+        TRANSFORMATION_VERSION,
+    )
+    .with_job(job_id)
+    .with_source_reference(&format!(
+        "{}/{}/{}",
+        job.source_url.trim_end_matches('/'),
+        job.resource_type,
+        source_resource_id
+    ))
+    .with_original_identifiers(vec![Identifier {
+        use_: None,
+        system: Some(job.source_url.clone()),
+        value: source_resource_id.to_string(),
+    }])
+}
+
+/// Where provenance records are kept
+#[async_trait]
+pub trait ProvenanceStore: Send + Sync {
+    /// Record the provenance of a created entity
+    async fn record(&self, provenance: &Provenance) -> JobResult<()>;
+}
+
+/// Provenance in the `emr.provenance` table
+pub struct DatabaseProvenanceStore {
+    pool: Pool,
+}
+
+impl DatabaseProvenanceStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ProvenanceStore for DatabaseProvenanceStore {
+    async fn record(&self, provenance: &Provenance) -> JobResult<()> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        let identifiers = serde_json::to_string(&provenance.original_identifiers)
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let provenance = provenance.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(INSERT_PROVENANCE_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(provenance.id)
+                .bind::<Text, _>(&provenance.target_type)
+                .bind::<diesel::sql_types::Uuid, _>(provenance.target_id)
+                .bind::<Text, _>(provenance.activity.as_str())
+                .bind::<Text, _>(&provenance.source_system)
+                .bind::<Nullable<diesel::sql_types::Uuid>, _>(provenance.job_id)
+                .bind::<Text, _>(&identifiers)
+                .bind::<Text, _>(&provenance.transformation_version)
+                .bind::<Nullable<Text>, _>(provenance.source_reference.as_deref())
+                .bind::<Timestamptz, _>(provenance.recorded_at)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SyncDirection;
+    use chrono::Utc;
+
+    #[test]
+    fn test_import_provenance() {
+        let file = ImportProvenance {
+            source_name: "acme-lab".to_string(),
+            source_system: "acme-lis".to_string(),
+            file_name: "results.csv".to_string(),
+            staged_name: "20240105T101500Z_results.csv".to_string(),
+            size_bytes: 30,
+            sha256: "ab".repeat(32),
+            received_at: Utc::now(),
+        };
+        let job_id = Uuid::new_v4();
+
+        let provenance = import_provenance(&file, job_id, "Observation", Uuid::new_v4(), vec![]);
+        assert_eq!(provenance.activity, ProvenanceActivity::Import);
+        assert_eq!(provenance.source_system, "acme-lis");
+        assert_eq!(provenance.job_id, Some(job_id));
+        assert!(provenance.transformation_version.starts_with("emr-jobs/"));
+        assert!(provenance.source_reference.unwrap().starts_with("results.csv (sha256 abab"));
+    }
+
+    #[test]
+    fn test_sync_provenance() {
+        let job = FhirSyncJob {
+            patient_id: Uuid::new_v4(),
+            resource_type: "Patient".to_string(),
+            source_url: "https://fhir.partner.example/r4/".to_string(),
+            target_url: "https://fhir.emr.example/r4".to_string(),
+            last_sync: None,
+            sync_direction: SyncDirection::Pull,
+        };
+
+        let provenance = sync_provenance(&job, Uuid::new_v4(), job.patient_id, "p-42");
+        assert_eq!(provenance.target_type, "Patient");
+        assert_eq!(
+            provenance.source_reference.as_deref(),
+            Some("https://fhir.partner.example/r4/Patient/p-42")
+        );
+        assert_eq!(provenance.original_identifiers[0].value, "p-42");
+    }
+}
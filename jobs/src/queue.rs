@@ -0,0 +1,281 @@
+//! Priority job queues
+//!
+//! Jobs are routed to a [`JobQueue`] by type and ordered within it by
+//! [`Priority`], oldest first among equals. Each queue has its own worker
+//! limit from [`WorkerConfig`], so a backlog of exports never delays a
+//! notification. Retries wait in a delayed list until their backoff has
+//! passed.
+
+use crate::config::WorkerConfig;
+use crate::types::{JobQueue, JobType, Priority};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+#[derive(Debug)]
+struct QueuedJob {
+    priority: Priority,
+    sequence: u64,
+    job_id: Uuid,
+    attempt: u32,
+    job: JobType,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    /// Higher priority first, then earlier submission
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    pending: BinaryHeap<QueuedJob>,
+    running: u32,
+    workers: u32,
+}
+
+/// A job taken from its queue to run
+#[derive(Debug)]
+pub struct ReadyJob {
+    pub queue: JobQueue,
+    pub job_id: Uuid,
+    /// 1 for the first run
+    pub attempt: u32,
+    pub job: JobType,
+}
+
+/// Pending jobs per queue and the number running on each
+#[derive(Debug)]
+pub struct JobQueues {
+    queues: HashMap<JobQueue, QueueState>,
+    delayed: Vec<(Instant, QueuedJob)>,
+    sequence: u64,
+}
+
+impl JobQueues {
+    /// Create empty queues sized from the worker configuration
+    pub fn new(config: &WorkerConfig) -> Self {
+        let queues = JobQueue::ALL
+            .into_iter()
+            .map(|queue| {
+                let state = QueueState {
+                    workers: config.workers_for(queue),
+                    ..Default::default()
+                };
+                (queue, state)
+            })
+            .collect();
+
+        Self {
+            queues,
+            delayed: Vec::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Add a job to its queue, returning the queue used
+    pub fn push(&mut self, job_id: Uuid, job: JobType) -> JobQueue {
+        let queued = self.queued(job_id, 1, job);
+        let queue = queued.job.queue();
+        self.state(queue).pending.push(queued);
+        queue
+    }
+
+    /// Queue another attempt of a job once `available_at` has passed
+    pub fn retry(&mut self, job_id: Uuid, job: JobType, attempt: u32, available_at: Instant) {
+        let queued = self.queued(job_id, attempt, job);
+        self.delayed.push((available_at, queued));
+    }
+
+    /// Take the next job from a queue that has a free worker, marking it
+    /// running. Call [`JobQueues::finished`] when it completes.
+    pub fn next_ready(&mut self) -> Option<ReadyJob> {
+        self.promote_due(Instant::now());
+
+        for queue in JobQueue::ALL {
+            let state = self.state(queue);
+            if state.running >= state.workers {
+                continue;
+            }
+            if let Some(queued) = state.pending.pop() {
+                state.running += 1;
+                return Some(ReadyJob {
+                    queue,
+                    job_id: queued.job_id,
+                    attempt: queued.attempt,
+                    job: queued.job,
+                });
+            }
+        }
+        None
+    }
+
+    /// Move retries whose backoff has passed onto their queues
+    fn promote_due(&mut self, now: Instant) {
+        let (due, waiting) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(available_at, _)| *available_at <= now);
+        self.delayed = waiting;
+
+        for (_, queued) in due {
+            let queue = queued.job.queue();
+            self.state(queue).pending.push(queued);
+        }
+    }
+
+    fn queued(&mut self, job_id: Uuid, attempt: u32, job: JobType) -> QueuedJob {
+        self.sequence += 1;
+        QueuedJob {
+            priority: job.priority(),
+            sequence: self.sequence,
+            job_id,
+            attempt,
+            job,
+        }
+    }
+
+    /// Free the worker of a finished job
+    pub fn finished(&mut self, queue: JobQueue) {
+        let state = self.state(queue);
+        state.running = state.running.saturating_sub(1);
+    }
+
+    /// Jobs waiting in a queue, not counting retries still backing off
+    pub fn pending(&self, queue: JobQueue) -> usize {
+        self.queues.get(&queue).map(|state| state.pending.len()).unwrap_or(0)
+    }
+
+    /// Retries still backing off
+    pub fn delayed(&self) -> usize {
+        self.delayed.len()
+    }
+
+    /// Whether a job is waiting for a worker, including a retry backing off
+    pub fn is_waiting(&self, job_id: Uuid) -> bool {
+        self.queues
+            .values()
+            .flat_map(|state| state.pending.iter())
+            .chain(self.delayed.iter().map(|(_, queued)| queued))
+            .any(|queued| queued.job_id == job_id)
+    }
+
+    /// Jobs running on a queue
+    pub fn running(&self, queue: JobQueue) -> u32 {
+        self.queues.get(&queue).map(|state| state.running).unwrap_or(0)
+    }
+
+    fn state(&mut self, queue: JobQueue) -> &mut QueueState {
+        self.queues.entry(queue).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    fn notification(priority: Priority, message: &str) -> JobType {
+        JobType::Notification(NotificationJob {
+            recipient_id: Uuid::new_v4(),
+            address: None,
+            notification_type: NotificationType::Reminder,
+            message: message.to_string(),
+            template: None,
+            channel: NotificationChannel::Email,
+            priority,
+            scheduled_for: None,
+            digest: false,
+        })
+    }
+
+    fn export() -> JobType {
+        JobType::DataExport(DataExportJob {
+            patient_ids: vec![],
+            export_format: ExportFormat::Fhir,
+            include_resources: vec![],
+            output_location: "s3://exports".to_string(),
+            encryption_key: None,
+        })
+    }
+
+    fn message(job: JobType) -> String {
+        match job {
+            JobType::Notification(job) => job.message,
+            other => panic!("unexpected job: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_priority_then_fifo() {
+        let mut queues = JobQueues::new(&WorkerConfig::default());
+        queues.push(Uuid::new_v4(), notification(Priority::Normal, "first"));
+        queues.push(Uuid::new_v4(), notification(Priority::Critical, "urgent"));
+        queues.push(Uuid::new_v4(), notification(Priority::Normal, "second"));
+
+        let order: Vec<String> = std::iter::from_fn(|| queues.next_ready())
+            .map(|ready| message(ready.job))
+            .collect();
+        assert_eq!(order, vec!["urgent", "first", "second"]);
+    }
+
+    #[test]
+    fn test_per_queue_worker_limits() {
+        let mut config = WorkerConfig::default();
+        config.queues.long_running = 1;
+        let mut queues = JobQueues::new(&config);
+
+        queues.push(Uuid::new_v4(), export());
+        queues.push(Uuid::new_v4(), export());
+        queues.push(Uuid::new_v4(), notification(Priority::Low, "reminder"));
+
+        assert_eq!(queues.next_ready().map(|ready| ready.queue), Some(JobQueue::LatencySensitive));
+        assert_eq!(queues.next_ready().map(|ready| ready.queue), Some(JobQueue::LongRunning));
+        // The second export waits for the long-running worker
+        assert!(queues.next_ready().is_none());
+        assert_eq!(queues.pending(JobQueue::LongRunning), 1);
+
+        queues.finished(JobQueue::LongRunning);
+        assert_eq!(queues.next_ready().map(|ready| ready.queue), Some(JobQueue::LongRunning));
+        assert_eq!(queues.running(JobQueue::LongRunning), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_waits_for_backoff() {
+        let mut queues = JobQueues::new(&WorkerConfig::default());
+        let job_id = Uuid::new_v4();
+        queues.retry(
+            job_id,
+            notification(Priority::High, "retry"),
+            2,
+            Instant::now() + std::time::Duration::from_secs(30),
+        );
+
+        assert!(queues.next_ready().is_none());
+        assert_eq!(queues.delayed(), 1);
+        assert!(queues.is_waiting(job_id));
+
+        tokio::time::advance(std::time::Duration::from_secs(30)).await;
+        let ready = queues.next_ready().unwrap();
+        assert_eq!((ready.job_id, ready.attempt), (job_id, 2));
+        assert_eq!(queues.delayed(), 0);
+        assert!(!queues.is_waiting(job_id));
+    }
+}
@@ -0,0 +1,406 @@
+//! Remittance (835) posting
+//!
+//! A RemittancePosting job reads an X12 835 file received from a payer and
+//! posts each payment it reports against the exported claims, matched on
+//! the claim's patient control number. Payments, adjustments and patient
+//! responsibility are recorded per claim; denials, reversals, payments for
+//! claims that were never exported and charges that differ from what was
+//! billed open a follow-up task for billing staff. A payment is posted once:
+//! transactions whose payer and trace number were already posted are skipped,
+//! so a file can safely be processed again.
+
+use crate::config::BillingConfig;
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::RemittancePostingJob;
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use emr_core::billing::{follow_up, parse_835, BillingTask, Remittance};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Array, BigInt, Bool, Date, Nullable, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// One posted 835 transaction and the follow-ups it opened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemittancePosting {
+    pub remittance_id: Uuid,
+    pub remittance: Remittance,
+    /// File the remittance was read from
+    pub source_location: String,
+    pub tasks: Vec<BillingTask>,
+    pub posted_at: DateTime<Utc>,
+}
+
+/// Exported claims and the record of posted payments
+#[async_trait]
+pub trait RemittanceStore: Send + Sync {
+    /// Billed charge, in cents, of each exported claim among `claim_ids`
+    async fn billed_charges(&self, claim_ids: &[String]) -> JobResult<HashMap<String, i64>>;
+
+    /// Whether the payer's payment with this trace number was already posted
+    async fn is_posted(&self, payer_id: &str, trace_number: &str) -> JobResult<bool>;
+
+    /// Record a remittance, its claim payments and follow-up tasks, all or
+    /// nothing
+    async fn save_posting(&self, posting: &RemittancePosting) -> JobResult<()>;
+}
+
+/// Claims and remittances in the `emr` schema
+pub struct DatabaseRemittanceStore {
+    pool: Pool,
+}
+
+impl DatabaseRemittanceStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct BilledChargeRow {
+    #[diesel(sql_type = Text)]
+    claim_id: String,
+    #[diesel(sql_type = BigInt)]
+    total_charge_cents: i64,
+}
+
+#[derive(diesel::QueryableByName)]
+struct PostedRow {
+    #[diesel(sql_type = Bool)]
+    posted: bool,
+}
+
+#[async_trait]
+impl RemittanceStore for DatabaseRemittanceStore {
+    async fn billed_charges(&self, claim_ids: &[String]) -> JobResult<HashMap<String, i64>> {
+        let conn = self.connection().await?;
+        let claim_ids = claim_ids.to_vec();
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query("SELECT claim_id, total_charge_cents FROM emr.claims WHERE claim_id = ANY($1)")
+                    .bind::<Array<Text>, _>(claim_ids)
+                    .load::<BilledChargeRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().map(|row| (row.claim_id, row.total_charge_cents)).collect())
+    }
+
+    async fn is_posted(&self, payer_id: &str, trace_number: &str) -> JobResult<bool> {
+        let conn = self.connection().await?;
+        let (payer_id, trace_number) = (payer_id.to_string(), trace_number.to_string());
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT EXISTS (SELECT 1 FROM emr.remittances WHERE payer_id = $1 AND trace_number = $2) AS posted",
+                )
+                .bind::<Text, _>(payer_id)
+                .bind::<Text, _>(trace_number)
+                .load::<PostedRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.first().map(|row| row.posted).unwrap_or(false))
+    }
+
+    async fn save_posting(&self, posting: &RemittancePosting) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let payments = posting
+            .remittance
+            .claims
+            .iter()
+            .map(|payment| {
+                let adjustments = serde_json::to_string(&payment.adjustments)?;
+                let lines = serde_json::to_string(&payment.lines)?;
+                Ok((payment.clone(), adjustments, lines))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let posting = posting.clone();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                let remittance = &posting.remittance;
+                diesel::sql_query(
+                    "INSERT INTO emr.remittances \
+                     (id, payer_id, payer_name, trace_number, payment_cents, payment_method, paid_on, \
+                     source_location, posted_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(posting.remittance_id)
+                .bind::<Text, _>(&remittance.payer_id)
+                .bind::<Text, _>(&remittance.payer_name)
+                .bind::<Text, _>(&remittance.trace_number)
+                .bind::<BigInt, _>(remittance.payment_cents)
+                .bind::<Text, _>(&remittance.payment_method)
+                .bind::<Nullable<Date>, _>(remittance.paid_on)
+                .bind::<Text, _>(&posting.source_location)
+                .bind::<Timestamptz, _>(posting.posted_at)
+                .execute(conn)?;
+
+                for (payment, adjustments, lines) in &payments {
+                    diesel::sql_query(
+                        "INSERT INTO emr.claim_payments \
+                         (id, remittance_id, claim_id, status, status_code, charge_cents, paid_cents, \
+                         patient_responsibility_cents, written_off_cents, denied, payer_claim_number, adjustments, \
+                         lines, posted_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::jsonb, $13::jsonb, $14)",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(Uuid::new_v4())
+                    .bind::<diesel::sql_types::Uuid, _>(posting.remittance_id)
+                    .bind::<Text, _>(&payment.claim_id)
+                    .bind::<Text, _>(payment.status.as_str())
+                    .bind::<Text, _>(&payment.status_code)
+                    .bind::<BigInt, _>(payment.charge_cents)
+                    .bind::<BigInt, _>(payment.paid_cents)
+                    .bind::<BigInt, _>(payment.patient_responsibility_cents)
+                    .bind::<BigInt, _>(payment.written_off_cents())
+                    .bind::<Bool, _>(payment.is_denied())
+                    .bind::<Nullable<Text>, _>(payment.payer_claim_number.as_deref())
+                    .bind::<Text, _>(adjustments)
+                    .bind::<Text, _>(lines)
+                    .bind::<Timestamptz, _>(posting.posted_at)
+                    .execute(conn)?;
+                }
+
+                for task in &posting.tasks {
+                    diesel::sql_query(
+                        "INSERT INTO emr.billing_tasks \
+                         (id, remittance_id, claim_id, reason, detail, status, created_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(task.id)
+                    .bind::<diesel::sql_types::Uuid, _>(task.remittance_id)
+                    .bind::<Text, _>(&task.claim_id)
+                    .bind::<Text, _>(task.reason.as_str())
+                    .bind::<Text, _>(&task.detail)
+                    .bind::<Text, _>(task.status.as_str())
+                    .bind::<Timestamptz, _>(task.created_at)
+                    .execute(conn)?;
+                }
+                Ok::<_, diesel::result::Error>(())
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Denied claims listed in the job result
+const MAX_REPORTED_DENIALS: usize = 200;
+
+/// Posts 835 payments against exported claims
+pub struct RemittancePostingHandler {
+    config: BillingConfig,
+    store: Arc<dyn RemittanceStore>,
+}
+
+impl RemittancePostingHandler {
+    /// Create a handler posting to a store
+    pub fn new(config: BillingConfig, store: Arc<dyn RemittanceStore>) -> Self {
+        Self { config, store }
+    }
+}
+
+#[async_trait]
+impl JobHandler<RemittancePostingJob> for RemittancePostingHandler {
+    async fn execute(&self, job: RemittancePostingJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(job_id = ?context.job_id, source = %job.source_location, "Starting remittance posting job");
+
+        if !self.config.enabled {
+            return Err(JobError::ValidationError("Billing is not enabled".to_string()));
+        }
+        let text = tokio::fs::read_to_string(&job.source_location).await.map_err(|e| {
+            JobError::ValidationError(format!("Cannot read remittance {}: {}", job.source_location, e))
+        })?;
+        let remittances = parse_835(&text).map_err(|e| JobError::ValidationError(e.to_string()))?;
+
+        let total = remittances.len();
+        let mut posted = 0usize;
+        let mut skipped = Vec::new();
+        let mut claims_count = 0usize;
+        let mut paid_cents = 0i64;
+        let mut denied = Vec::new();
+        let mut tasks_by_reason: HashMap<&'static str, usize> = HashMap::new();
+
+        for (index, remittance) in remittances.into_iter().enumerate() {
+            context.check_cancelled()?;
+            if self.store.is_posted(&remittance.payer_id, &remittance.trace_number).await? {
+                warn!(
+                    payer_id = %remittance.payer_id,
+                    trace_number = %remittance.trace_number,
+                    "Remittance already posted"
+                );
+                skipped.push(remittance.trace_number);
+                context.progress.step(index + 1, total);
+                continue;
+            }
+
+            let claim_ids: Vec<String> = remittance.claims.iter().map(|payment| payment.claim_id.clone()).collect();
+            let billed = self.store.billed_charges(&claim_ids).await?;
+            let remittance_id = Uuid::new_v4();
+            let posted_at = Utc::now();
+            let tasks: Vec<BillingTask> = remittance
+                .claims
+                .iter()
+                .filter_map(|payment| {
+                    follow_up(payment, billed.get(&payment.claim_id).copied(), remittance_id, posted_at)
+                })
+                .collect();
+
+            claims_count += remittance.claims.len();
+            paid_cents += remittance.payment_cents;
+            denied.extend(
+                remittance
+                    .claims
+                    .iter()
+                    .filter(|payment| payment.is_denied())
+                    .map(|payment| payment.claim_id.clone()),
+            );
+            for task in &tasks {
+                *tasks_by_reason.entry(task.reason.as_str()).or_default() += 1;
+            }
+
+            self.store
+                .save_posting(&RemittancePosting {
+                    remittance_id,
+                    remittance,
+                    source_location: job.source_location.clone(),
+                    tasks,
+                    posted_at,
+                })
+                .await?;
+            posted += 1;
+            context.progress.step(index + 1, total);
+        }
+
+        let tasks_count: usize = tasks_by_reason.values().sum();
+        let data = serde_json::json!({
+            "remittances_posted": posted,
+            "already_posted": skipped,
+            "claims_count": claims_count,
+            "paid_cents": paid_cents,
+            "denied_count": denied.len(),
+            "denied": denied.iter().take(MAX_REPORTED_DENIALS).collect::<Vec<_>>(),
+            "tasks": tasks_by_reason,
+        });
+
+        Ok(JobExecutionResult::success_with_data(
+            format!(
+                "Posted {} remittances covering {} claims; {} denied, {} follow-up tasks",
+                posted,
+                claims_count,
+                denied.len(),
+                tasks_count
+            ),
+            data,
+        )
+        .with_metric("claims_count".to_string(), claims_count as f64)
+        .with_metric("denied_count".to_string(), denied.len() as f64)
+        .with_metric("tasks_count".to_string(), tasks_count as f64)
+        .with_metric("paid".to_string(), paid_cents as f64 / 100.0))
+    }
+
+    fn name(&self) -> &'static str {
+        "remittance_posting"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Billed claims and postings kept in memory, for tests
+    struct MemoryRemittanceStore {
+        billed: HashMap<String, i64>,
+        postings: Mutex<Vec<RemittancePosting>>,
+    }
+
+    #[async_trait]
+    impl RemittanceStore for MemoryRemittanceStore {
+        async fn billed_charges(&self, claim_ids: &[String]) -> JobResult<HashMap<String, i64>> {
+            Ok(self.billed.iter().filter(|(id, _)| claim_ids.contains(id)).map(|(id, c)| (id.clone(), *c)).collect())
+        }
+
+        async fn is_posted(&self, payer_id: &str, trace_number: &str) -> JobResult<bool> {
+            Ok(self.postings.lock().unwrap().iter().any(|posting| {
+                posting.remittance.payer_id == payer_id && posting.remittance.trace_number == trace_number
+            }))
+        }
+
+        async fn save_posting(&self, posting: &RemittancePosting) -> JobResult<()> {
+            self.postings.lock().unwrap().push(posting.clone());
+            Ok(())
+        }
+    }
+
+    const REMITTANCE: &str = "ISA*00*          *00*          *ZZ*PAYER          *ZZ*EMRSENDER      \
+        *260320*1200*^*00501*000000101*0*P*:~\
+        GS*HP*PAYER*EMRSENDER*20260320*1200*101*X*005010X221A1~\
+        ST*835*0001~\
+        BPR*I*80*C*ACH*CCP*01*999999999*DA*123456*1512345678**01*999988880*DA*98765*20260320~\
+        TRN*1*EFT12345*1512345678~\
+        N1*PR*ACME HEALTH*XV*60054~\
+        CLP*ABC123*1*100*80*20*12*PCN1~\
+        CAS*PR*2*20~\
+        CLP*DEF456*4*90*0*0*12*PCN2~\
+        CAS*CO*50*90~\
+        CLP*XYZ789*1*40*0*40*12*PCN3~\
+        SE*10*0001~\
+        GE*1*101~\
+        IEA*1*000000101~";
+
+    #[tokio::test]
+    async fn test_remittance_posting_opens_follow_ups_once() {
+        let path = std::env::temp_dir().join(format!("remittance-{}.835", Uuid::new_v4()));
+        std::fs::write(&path, REMITTANCE).unwrap();
+        let store = Arc::new(MemoryRemittanceStore {
+            billed: HashMap::from([("ABC123".to_string(), 10000), ("DEF456".to_string(), 9000)]),
+            postings: Mutex::new(Vec::new()),
+        });
+        let config = BillingConfig {
+            enabled: true,
+            ..BillingConfig::default()
+        };
+        let handler = RemittancePostingHandler::new(config, store.clone());
+        let job = RemittancePostingJob {
+            source_location: path.display().to_string(),
+        };
+
+        let result = handler.execute(job.clone(), JobContext::new(Uuid::new_v4())).await.unwrap();
+        let data = result.data.unwrap();
+        assert_eq!(data["claims_count"], 3);
+        assert_eq!(data["paid_cents"], 8000);
+        assert_eq!(data["denied"], serde_json::json!(["DEF456"]));
+        assert_eq!(data["tasks"]["denied"], 1);
+        assert_eq!(data["tasks"]["unmatched"], 1);
+
+        let again = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(again.data.unwrap()["already_posted"], serde_json::json!(["EFT12345"]));
+        assert_eq!(store.postings.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,551 @@
+//! Scheduled reports
+//!
+//! Each worker poll claims the active report schedules that are due,
+//! advances them to their cadence's next run and queues a ScheduledReport
+//! job for each. The job runs the schedule's report (see
+//! `emr_core::services::reporting`) over its period, renders it as CSV or PDF
+//! into `reports.output_dir` under the run's id, records the run in the
+//! schedule's history and notifies the recipients in-app with a download
+//! link. Runs missed while the worker was down are not made up: a schedule
+//! runs once and moves on to its next time after now.
+
+use crate::config::ReportConfig;
+use crate::handlers::JobExecutionResult;
+use crate::types::{NotificationChannel, NotificationJob, NotificationType, Priority, ScheduledReportJob};
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use emr_core::domain::{ReportCadence, ReportFormat, ReportPeriod, ReportRun, ReportRunStatus, ReportSchedule};
+use emr_core::services::reporting::{compile, ReportDefinition, ReportParam, ReportQuery, ReportTable};
+use emr_core::types::EntityMetadata;
+use deadpool_diesel::postgres::Pool;
+use diesel::pg::Pg;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Nullable, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Most schedules claimed per poll
+pub const CLAIM_BATCH_SIZE: i64 = 50;
+
+/// API path a run is downloaded from
+pub fn download_path(run_id: Uuid) -> String {
+    format!("/api/report-runs/{}/download", run_id)
+}
+
+/// Report schedules and their runs
+#[async_trait]
+pub trait ReportScheduleStore: Send + Sync {
+    /// Advance up to `limit` active schedules due by `now` to their next
+    /// run, returning their ids and when each was due
+    async fn claim_due(&self, now: DateTime<Utc>, limit: i64) -> JobResult<Vec<(Uuid, DateTime<Utc>)>>;
+
+    /// A schedule, or `None` if it was deleted
+    async fn schedule(&self, id: Uuid) -> JobResult<Option<ReportSchedule>>;
+
+    /// Run a compiled report
+    async fn run(&self, query: ReportQuery) -> JobResult<ReportTable>;
+
+    /// Add a run to its schedule's history
+    async fn record_run(&self, run: &ReportRun) -> JobResult<()>;
+}
+
+/// Report schedules in the `emr` schema
+pub struct DatabaseReportScheduleStore {
+    pool: Pool,
+}
+
+impl DatabaseReportScheduleStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct DueScheduleRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    cadence: String,
+    #[diesel(sql_type = Timestamptz)]
+    next_run_at: DateTime<Utc>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct ScheduleRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    owner_id: Uuid,
+    #[diesel(sql_type = Text)]
+    definition: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    period: Option<String>,
+    #[diesel(sql_type = Text)]
+    format: String,
+    #[diesel(sql_type = Text)]
+    cadence: String,
+    #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Uuid>)]
+    recipients: Vec<Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    active: bool,
+    #[diesel(sql_type = Timestamptz)]
+    next_run_at: DateTime<Utc>,
+    #[diesel(sql_type = BigInt)]
+    version: i64,
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+    #[diesel(sql_type = Timestamptz)]
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(diesel::QueryableByName)]
+struct ReportRow {
+    #[diesel(sql_type = Text)]
+    row: String,
+}
+
+fn serialization_error(e: serde_json::Error) -> JobError {
+    JobError::SerializationError(e.to_string())
+}
+
+impl TryFrom<ScheduleRow> for ReportSchedule {
+    type Error = JobError;
+
+    fn try_from(row: ScheduleRow) -> JobResult<Self> {
+        let definition: ReportDefinition = serde_json::from_str(&row.definition).map_err(serialization_error)?;
+        let period: Option<ReportPeriod> = row
+            .period
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(serialization_error)?;
+        let cadence: ReportCadence = serde_json::from_str(&row.cadence).map_err(serialization_error)?;
+
+        Ok(Self {
+            metadata: EntityMetadata {
+                id: row.id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version as u64,
+            },
+            name: row.name,
+            owner_id: row.owner_id,
+            definition,
+            period,
+            format: ReportFormat::parse(&row.format)
+                .ok_or_else(|| JobError::SerializationError(format!("Unknown report format '{}'", row.format)))?,
+            cadence,
+            recipients: row.recipients,
+            active: row.active,
+            next_run_at: row.next_run_at,
+        })
+    }
+}
+
+/// Bind a compiled report's parameters, in order
+fn bind_params(
+    mut query: BoxedSqlQuery<'static, Pg, SqlQuery>,
+    params: Vec<ReportParam>,
+) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+    for param in params {
+        query = match param {
+            ReportParam::Text(value) => query.bind::<Text, _>(value),
+            ReportParam::TextList(values) => query.bind::<diesel::sql_types::Array<Text>, _>(values),
+            ReportParam::Number(value) => query.bind::<diesel::sql_types::Double, _>(value),
+            ReportParam::Boolean(value) => query.bind::<diesel::sql_types::Bool, _>(value),
+            ReportParam::Timestamp(value) => query.bind::<Timestamptz, _>(value),
+            ReportParam::Limit(value) => query.bind::<BigInt, _>(value),
+        };
+    }
+    query
+}
+
+#[async_trait]
+impl ReportScheduleStore for DatabaseReportScheduleStore {
+    async fn claim_due(&self, now: DateTime<Utc>, limit: i64) -> JobResult<Vec<(Uuid, DateTime<Utc>)>> {
+        let conn = self.connection().await?;
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                let due = diesel::sql_query(
+                    "SELECT id, cadence::text AS cadence, next_run_at FROM emr.report_schedules \
+                     WHERE active AND next_run_at <= $1 \
+                     ORDER BY next_run_at LIMIT $2 FOR UPDATE SKIP LOCKED",
+                )
+                .bind::<Timestamptz, _>(now)
+                .bind::<BigInt, _>(limit)
+                .load::<DueScheduleRow>(conn)?;
+
+                let mut claimed = Vec::with_capacity(due.len());
+                for row in due {
+                    // A cadence that no longer parses stops the schedule
+                    // rather than claiming it on every poll
+                    let next_run_at = serde_json::from_str::<ReportCadence>(&row.cadence)
+                        .ok()
+                        .map(|cadence| cadence.next_run(now));
+                    diesel::sql_query(
+                        "UPDATE emr.report_schedules \
+                         SET next_run_at = COALESCE($2, next_run_at), active = $2 IS NOT NULL WHERE id = $1",
+                    )
+                    .bind::<diesel::sql_types::Uuid, _>(row.id)
+                    .bind::<Nullable<Timestamptz>, _>(next_run_at)
+                    .execute(conn)?;
+                    match next_run_at {
+                        Some(_) => claimed.push((row.id, row.next_run_at)),
+                        None => warn!(schedule_id = %row.id, "Report schedule has an invalid cadence; deactivated"),
+                    }
+                }
+                Ok::<_, DieselError>(claimed)
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn schedule(&self, id: Uuid) -> JobResult<Option<ReportSchedule>> {
+        let conn = self.connection().await?;
+
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(
+                    "SELECT id, name, owner_id, definition::text AS definition, period::text AS period, format, \
+                     cadence::text AS cadence, recipients, active, next_run_at, version, created_at, updated_at \
+                     FROM emr.report_schedules WHERE id = $1",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(id)
+                .load::<ScheduleRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        rows.into_iter().next().map(ReportSchedule::try_from).transpose()
+    }
+
+    async fn run(&self, query: ReportQuery) -> JobResult<ReportTable> {
+        let conn = self.connection().await?;
+        let ReportQuery { sql, params, columns } = query;
+
+        let rows = conn
+            .interact(move |conn| {
+                conn.build_transaction()
+                    .read_only()
+                    .run(|conn| bind_params(diesel::sql_query(sql).into_boxed(), params).load::<ReportRow>(conn))
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let rows = rows
+            .into_iter()
+            .map(|row| serde_json::from_str(&row.row))
+            .collect::<Result<Vec<Vec<serde_json::Value>>, _>>()
+            .map_err(serialization_error)?;
+        Ok(ReportTable { columns, rows })
+    }
+
+    async fn record_run(&self, run: &ReportRun) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let run = run.clone();
+
+        conn.interact(move |conn| {
+            diesel::sql_query(
+                "INSERT INTO emr.report_runs \
+                 (id, schedule_id, scheduled_for, status, format, row_count, byte_count, error, generated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(run.id)
+            .bind::<diesel::sql_types::Uuid, _>(run.schedule_id)
+            .bind::<Timestamptz, _>(run.scheduled_for)
+            .bind::<Text, _>(run.status.as_str())
+            .bind::<Text, _>(run.format.as_str())
+            .bind::<Nullable<BigInt>, _>(run.row_count)
+            .bind::<Nullable<BigInt>, _>(run.byte_count)
+            .bind::<Nullable<Text>, _>(run.error)
+            .bind::<Timestamptz, _>(run.generated_at)
+            .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// In-app notifications telling a run's recipients it is ready; a failed
+/// run is only reported to the schedule's owner
+pub fn run_notifications(schedule: &ReportSchedule, run: &ReportRun) -> Vec<NotificationJob> {
+    let (recipients, notification_type, message) = match run.status {
+        ReportRunStatus::Completed => (
+            schedule.recipients.clone(),
+            NotificationType::Update,
+            format!(
+                "Report '{}' for {} is ready: {}",
+                schedule.name,
+                run.scheduled_for.format("%d %b %Y"),
+                download_path(run.id)
+            ),
+        ),
+        ReportRunStatus::Failed => (
+            vec![schedule.owner_id],
+            NotificationType::Error,
+            format!(
+                "Report '{}' for {} could not be generated: {}",
+                schedule.name,
+                run.scheduled_for.format("%d %b %Y"),
+                run.error.as_deref().unwrap_or("unknown error")
+            ),
+        ),
+    };
+
+    recipients
+        .into_iter()
+        .map(|recipient_id| NotificationJob {
+            recipient_id,
+            address: None,
+            notification_type,
+            message: message.clone(),
+            template: None,
+            channel: NotificationChannel::InApp,
+            priority: Priority::Normal,
+            scheduled_for: None,
+            digest: false,
+        })
+        .collect()
+}
+
+/// Scheduled report job handler
+///
+/// Unlike other handlers it hands back the notifications for the worker to
+/// queue, since delivering them is a job of their own.
+pub struct ScheduledReportHandler {
+    config: ReportConfig,
+    store: Arc<dyn ReportScheduleStore>,
+}
+
+impl ScheduledReportHandler {
+    /// Create a handler running schedules in `store` into `config.output_dir`
+    pub fn new(config: ReportConfig, store: Arc<dyn ReportScheduleStore>) -> Self {
+        Self { config, store }
+    }
+
+    /// Generate a schedule's report, returning the job result and the
+    /// notifications to queue
+    pub async fn generate(
+        &self,
+        job: ScheduledReportJob,
+        context: JobContext,
+    ) -> JobResult<(JobExecutionResult, Vec<NotificationJob>)> {
+        info!(job_id = ?context.job_id, schedule_id = %job.schedule_id, "Starting scheduled report job");
+
+        let schedule = match self.store.schedule(job.schedule_id).await? {
+            Some(schedule) if schedule.active => schedule,
+            _ => {
+                let message = format!("Report schedule {} is no longer active; skipped", job.schedule_id);
+                return Ok((JobExecutionResult::success(message), Vec::new()));
+            }
+        };
+        let mut run = ReportRun {
+            id: Uuid::new_v4(),
+            schedule_id: schedule.metadata.id,
+            scheduled_for: job.scheduled_for,
+            status: ReportRunStatus::Completed,
+            format: schedule.format,
+            row_count: None,
+            byte_count: None,
+            error: None,
+            generated_at: Utc::now(),
+        };
+
+        // A definition the reporting allowlist no longer accepts fails the
+        // run for good; database errors are retried
+        let query = match compile(&schedule.definition_at(job.scheduled_for)) {
+            Ok(query) => query,
+            Err(e) => {
+                run.status = ReportRunStatus::Failed;
+                run.error = Some(e.to_string());
+                self.store.record_run(&run).await?;
+                let result = JobExecutionResult::failure(format!("Report '{}' failed: {}", schedule.name, e));
+                return Ok((result, run_notifications(&schedule, &run)));
+            }
+        };
+        context.check_cancelled()?;
+        let table = self.store.run(query).await?;
+        context.progress.step(1, 2);
+
+        let content = match schedule.format {
+            ReportFormat::Csv => table.to_csv().into_bytes(),
+            ReportFormat::Pdf => table.to_pdf(&schedule.name, &period_label(&schedule, job.scheduled_for)),
+        };
+        let directory = Path::new(&self.config.output_dir);
+        tokio::fs::create_dir_all(directory)
+            .await
+            .map_err(|e| JobError::ProcessingError(format!("Failed to create report directory: {}", e)))?;
+        tokio::fs::write(directory.join(run.id.to_string()), &content)
+            .await
+            .map_err(|e| JobError::ProcessingError(format!("Failed to store report: {}", e)))?;
+
+        run.row_count = Some(table.rows.len() as i64);
+        run.byte_count = Some(content.len() as i64);
+        run.generated_at = Utc::now();
+        self.store.record_run(&run).await?;
+        context.progress.step(2, 2);
+
+        let notifications = run_notifications(&schedule, &run);
+        let result = JobExecutionResult::success_with_data(
+            format!("Report '{}' generated with {} rows", schedule.name, table.rows.len()),
+            serde_json::json!({ "run_id": run.id, "schedule_id": schedule.metadata.id, "rows": table.rows.len() }),
+        )
+        .with_metric("rows".to_string(), table.rows.len() as f64)
+        .with_metric("recipients".to_string(), notifications.len() as f64);
+        Ok((result, notifications))
+    }
+}
+
+/// Subtitle of a PDF report: its period, or when it was due
+fn period_label(schedule: &ReportSchedule, scheduled_for: DateTime<Utc>) -> String {
+    match &schedule.period {
+        Some(period) => format!(
+            "{} - {}",
+            (scheduled_for - Duration::days(i64::from(period.days))).format("%d %b %Y %H:%M UTC"),
+            scheduled_for.format("%d %b %Y %H:%M UTC")
+        ),
+        None => format!("As of {}", scheduled_for.format("%d %b %Y %H:%M UTC")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// One schedule whose report always has the same rows, for tests
+    struct MemoryReportScheduleStore {
+        schedule: ReportSchedule,
+        runs: Mutex<Vec<ReportRun>>,
+        queries: Mutex<Vec<ReportQuery>>,
+    }
+
+    #[async_trait]
+    impl ReportScheduleStore for MemoryReportScheduleStore {
+        async fn claim_due(&self, _now: DateTime<Utc>, _limit: i64) -> JobResult<Vec<(Uuid, DateTime<Utc>)>> {
+            Ok(vec![(self.schedule.metadata.id, self.schedule.next_run_at)])
+        }
+
+        async fn schedule(&self, id: Uuid) -> JobResult<Option<ReportSchedule>> {
+            Ok(Some(self.schedule.clone()).filter(|schedule| schedule.metadata.id == id))
+        }
+
+        async fn run(&self, query: ReportQuery) -> JobResult<ReportTable> {
+            let columns = query.columns.clone();
+            self.queries.lock().unwrap().push(query);
+            Ok(ReportTable {
+                columns,
+                rows: vec![vec![json!("AMB"), json!(12)], vec![json!("VR"), json!(3)]],
+            })
+        }
+
+        async fn record_run(&self, run: &ReportRun) -> JobResult<()> {
+            self.runs.lock().unwrap().push(run.clone());
+            Ok(())
+        }
+    }
+
+    fn store(format: ReportFormat, definition: serde_json::Value) -> Arc<MemoryReportScheduleStore> {
+        let definition: ReportDefinition = serde_json::from_value(definition).unwrap();
+        let cadence = ReportCadence::Week { weekday: 1, hour: 6 };
+        let mut schedule = ReportSchedule::new("Weekly visits", Uuid::new_v4(), definition, format, cadence);
+        schedule.recipients.push(Uuid::new_v4());
+        schedule.period = Some(ReportPeriod {
+            field: "start_date".to_string(),
+            days: 7,
+        });
+        Arc::new(MemoryReportScheduleStore {
+            schedule,
+            runs: Mutex::new(Vec::new()),
+            queries: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn job(store: &MemoryReportScheduleStore) -> ScheduledReportJob {
+        ScheduledReportJob {
+            schedule_id: store.schedule.metadata.id,
+            scheduled_for: Utc.with_ymd_and_hms(2024, 3, 11, 6, 0, 0).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_report_stores_file_and_notifies() {
+        let directory = std::env::temp_dir().join(format!("reports-{}", Uuid::new_v4()));
+        let config = ReportConfig {
+            output_dir: directory.display().to_string(),
+        };
+        let store = store(
+            ReportFormat::Csv,
+            json!({"entity": "encounters", "group_by": ["class"], "metrics": [{"function": "count"}]}),
+        );
+        let handler = ScheduledReportHandler::new(config, store.clone());
+
+        let (result, notifications) = handler.generate(job(&store), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.metrics.get("rows"), Some(&2.0));
+
+        let run = store.runs.lock().unwrap()[0].clone();
+        assert_eq!((run.status, run.row_count), (ReportRunStatus::Completed, Some(2)));
+        let content = std::fs::read_to_string(directory.join(run.id.to_string())).unwrap();
+        assert_eq!(content, "class,count\r\nAMB,12\r\nVR,3\r\n");
+        assert_eq!(run.byte_count, Some(content.len() as i64));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let query = store.queries.lock().unwrap()[0].clone();
+        assert_eq!(query.params[0], ReportParam::Timestamp(Utc.with_ymd_and_hms(2024, 3, 4, 6, 0, 0).unwrap()));
+
+        assert_eq!(notifications.len(), 2);
+        assert!(notifications.iter().all(|n| n.channel == NotificationChannel::InApp));
+        assert!(notifications[0].message.contains(&download_path(run.id)));
+        assert!(notifications[0].message.contains("11 Mar 2024"));
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_report_records_failed_definitions() {
+        // Grouping by a patient identifier is not allowed
+        let store = store(
+            ReportFormat::Pdf,
+            json!({"entity": "encounters", "group_by": ["patient_id"], "metrics": [{"function": "count"}]}),
+        );
+        let handler = ScheduledReportHandler::new(ReportConfig::default(), store.clone());
+
+        let (result, notifications) = handler.generate(job(&store), JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(store.runs.lock().unwrap()[0].status, ReportRunStatus::Failed);
+        assert!(store.queries.lock().unwrap().is_empty());
+        assert_eq!(notifications.len(), 1, "only the owner hears about failures");
+        assert_eq!(notifications[0].recipient_id, store.schedule.owner_id);
+
+        let missing = ScheduledReportJob {
+            schedule_id: Uuid::new_v4(),
+            ..job(&store)
+        };
+        let (result, notifications) = handler.generate(missing, JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(result.message.contains("no longer active"));
+        assert!(notifications.is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Retention records in the database
+//!
+//! The DataCleanup job and the API's dry-run report both load candidates
+//! through [`DatabaseRetentionStore`], so the report shows exactly what the
+//! job would do. Each entity type a policy may name maps to one table: the
+//! last activity is its newest timestamp (an encounter's end), and the
+//! jurisdiction is that of the patient's address, as `country-state`.
+//! Archiving moves a row into a copy of its table in the `emr_archive`
+//! schema, anonymizing scrubs the identifying columns in place, and purging
+//! deletes it.
+
+use crate::handlers::RetentionStore;
+use crate::{JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_diesel::postgres::Pool;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{Nullable, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use emr_core::retention::{RetentionAction, RetentionCandidate, RetentionDecision};
+use uuid::Uuid;
+
+/// Where the records of a retention entity type are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RetentionTable {
+    entity_type: &'static str,
+    table: &'static str,
+    /// Last activity of a row `r`
+    activity: &'static str,
+    /// Column of `r` holding the patient the row belongs to
+    patient_column: Option<&'static str>,
+    /// Scrubs the identifying columns of the row with id `$1`, for types
+    /// that hold any
+    anonymize: Option<&'static str>,
+}
+
+/// Tables of the entity types retention policies may name
+const RETENTION_TABLES: &[RetentionTable] = &[
+    RetentionTable {
+        entity_type: "AuditEvent",
+        table: "audit.audit_log",
+        activity: "r.changed_at",
+        patient_column: Some("patient_id"),
+        anonymize: Some(
+            "UPDATE audit.audit_log SET changed_by = NULL, user_id = NULL, session_id = NULL, patient_id = NULL, \
+             old_values = NULL, new_values = NULL WHERE id = $1",
+        ),
+    },
+    RetentionTable {
+        entity_type: "Patient",
+        table: "emr.patients",
+        activity: "COALESCE(r.updated_at, r.created_at)",
+        patient_column: Some("id"),
+        anonymize: Some(
+            "UPDATE emr.patients SET fhir_id = NULL, family_name = NULL, given_names = NULL, \
+             birth_date = date_trunc('year', birth_date)::date, phone = NULL, email = NULL, address_line1 = NULL, \
+             address_line2 = NULL, city = NULL, postal_code = NULL, identifiers = NULL, contact_points = NULL, \
+             photos = NULL, fhir_data = NULL, updated_at = NOW() WHERE id = $1",
+        ),
+    },
+    RetentionTable {
+        entity_type: "Encounter",
+        table: "emr.encounters",
+        activity: "COALESCE(r.end_date, r.updated_at, r.created_at)",
+        patient_column: Some("patient_id"),
+        anonymize: Some(
+            "UPDATE emr.encounters SET fhir_id = NULL, patient_id = NULL, practitioner_id = NULL, fhir_data = NULL, \
+             details = details - 'identifiers' - 'participants', updated_at = NOW(), version = version + 1 \
+             WHERE id = $1",
+        ),
+    },
+    RetentionTable {
+        entity_type: "Observation",
+        table: "emr.observations",
+        activity: "COALESCE(r.updated_at, r.created_at)",
+        patient_column: Some("patient_id"),
+        anonymize: Some(
+            "UPDATE emr.observations SET fhir_id = NULL, patient_id = NULL, device_id = NULL, fhir_data = NULL, \
+             details = details - 'identifiers' - 'performer' - 'specimen' - 'device', updated_at = NOW(), \
+             version = version + 1 WHERE id = $1",
+        ),
+    },
+    RetentionTable {
+        entity_type: "Organization",
+        table: "emr.organizations",
+        activity: "COALESCE(r.updated_at, r.created_at)",
+        patient_column: None,
+        anonymize: None,
+    },
+    RetentionTable {
+        entity_type: "Session",
+        table: "emr.sessions",
+        activity: "COALESCE(r.last_accessed, r.created_at)",
+        patient_column: None,
+        anonymize: Some("UPDATE emr.sessions SET ip_address = NULL, user_agent = NULL WHERE id = $1"),
+    },
+];
+
+impl RetentionTable {
+    /// The table of an entity type
+    fn of(entity_type: &str) -> Option<&'static RetentionTable> {
+        RETENTION_TABLES.iter().find(|table| table.entity_type == entity_type)
+    }
+
+    /// Tables of the given entity types; all of them when empty
+    fn selected(entity_types: &[String]) -> Vec<&'static RetentionTable> {
+        RETENTION_TABLES
+            .iter()
+            .filter(|table| entity_types.is_empty() || entity_types.iter().any(|t| t == table.entity_type))
+            .collect()
+    }
+
+    /// Query for the `entity_id`, `jurisdiction` and `last_activity` of
+    /// every row
+    fn candidates_query(&self) -> String {
+        let (jurisdiction, join) = match self.patient_column {
+            Some(column) => (
+                "CASE WHEN p.country IS NOT NULL AND p.state IS NOT NULL THEN p.country || '-' || p.state END",
+                format!(" LEFT JOIN emr.patients p ON p.id = r.{}", column),
+            ),
+            None => ("NULL::text", String::new()),
+        };
+        format!(
+            "SELECT r.id AS entity_id, {jurisdiction} AS jurisdiction, {activity} AS last_activity \
+             FROM {table} r{join} WHERE {activity} IS NOT NULL",
+            activity = self.activity,
+            table = self.table,
+        )
+    }
+
+    /// Copy of the table in the `emr_archive` schema
+    fn archive_table(&self) -> String {
+        let name = self.table.rsplit('.').next().unwrap_or(self.table);
+        format!("emr_archive.{}", name)
+    }
+
+    /// Statements carrying out `action` for the row with id `$1`
+    fn statements(&self, action: RetentionAction) -> JobResult<Vec<String>> {
+        Ok(match action {
+            RetentionAction::Archive => vec![
+                format!("CREATE TABLE IF NOT EXISTS {} (LIKE {})", self.archive_table(), self.table),
+                format!(
+                    "WITH moved AS (DELETE FROM {} WHERE id = $1 RETURNING *) INSERT INTO {} SELECT * FROM moved",
+                    self.table,
+                    self.archive_table()
+                ),
+            ],
+            RetentionAction::Anonymize => {
+                let anonymize = self.anonymize.ok_or_else(|| {
+                    JobError::ValidationError(format!("{} records hold no data to anonymize", self.entity_type))
+                })?;
+                vec![anonymize.to_string()]
+            }
+            RetentionAction::Purge => vec![format!("DELETE FROM {} WHERE id = $1", self.table)],
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct CandidateRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    entity_id: Uuid,
+    #[diesel(sql_type = Nullable<Text>)]
+    jurisdiction: Option<String>,
+    #[diesel(sql_type = Timestamptz)]
+    last_activity: DateTime<Utc>,
+}
+
+/// Retention candidates and actions in the `emr` and `audit` schemas
+pub struct DatabaseRetentionStore {
+    pool: Pool,
+}
+
+impl DatabaseRetentionStore {
+    /// Create a store over a database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl RetentionStore for DatabaseRetentionStore {
+    async fn candidates(&self, entity_types: &[String]) -> JobResult<Vec<RetentionCandidate>> {
+        let conn = self.connection().await?;
+        let tables = RetentionTable::selected(entity_types);
+
+        let candidates = conn
+            .interact(move |conn| {
+                let mut candidates = Vec::new();
+                for table in tables {
+                    let rows = diesel::sql_query(table.candidates_query()).load::<CandidateRow>(conn)?;
+                    candidates.extend(rows.into_iter().map(|row| RetentionCandidate {
+                        entity_type: table.entity_type.to_string(),
+                        entity_id: row.entity_id,
+                        jurisdiction: row.jurisdiction,
+                        last_activity: row.last_activity,
+                    }));
+                }
+                Ok::<_, DieselError>(candidates)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(candidates)
+    }
+
+    async fn apply(&self, decision: &RetentionDecision) -> JobResult<()> {
+        let table = RetentionTable::of(&decision.entity_type).ok_or_else(|| {
+            JobError::ValidationError(format!("No table holds {} records", decision.entity_type))
+        })?;
+        let statements = table.statements(decision.action)?;
+        let entity_id = decision.entity_id;
+        let conn = self.connection().await?;
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                for statement in &statements {
+                    if statement.contains("$1") {
+                        diesel::sql_query(statement)
+                            .bind::<diesel::sql_types::Uuid, _>(entity_id)
+                            .execute(conn)?;
+                    } else {
+                        diesel::sql_query(statement).execute(conn)?;
+                    }
+                }
+                Ok::<_, DieselError>(())
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emr_core::retention::RetentionConfig;
+
+    #[test]
+    fn test_default_policies_have_tables() {
+        for policy in RetentionConfig::default().policies {
+            assert!(RetentionTable::of(&policy.entity_type).is_some(), "{}", policy.entity_type);
+        }
+        assert_eq!(RetentionTable::selected(&[]).len(), RETENTION_TABLES.len());
+        assert_eq!(RetentionTable::selected(&["Encounter".to_string(), "Unknown".to_string()]).len(), 1);
+    }
+
+    #[test]
+    fn test_candidates_query() {
+        let encounters = RetentionTable::of("Encounter").unwrap().candidates_query();
+        assert!(encounters.contains("FROM emr.encounters r LEFT JOIN emr.patients p ON p.id = r.patient_id"));
+        assert!(encounters.contains("COALESCE(r.end_date, r.updated_at, r.created_at) AS last_activity"));
+
+        let sessions = RetentionTable::of("Session").unwrap().candidates_query();
+        assert!(sessions.contains("NULL::text AS jurisdiction"));
+        assert!(!sessions.contains("JOIN"));
+    }
+
+    #[test]
+    fn test_statements() {
+        let patients = RetentionTable::of("Patient").unwrap();
+        assert_eq!(
+            patients.statements(RetentionAction::Archive).unwrap(),
+            vec![
+                "CREATE TABLE IF NOT EXISTS emr_archive.patients (LIKE emr.patients)".to_string(),
+                "WITH moved AS (DELETE FROM emr.patients WHERE id = $1 RETURNING *) \
+                 INSERT INTO emr_archive.patients SELECT * FROM moved"
+                    .to_string(),
+            ]
+        );
+        assert_eq!(
+            patients.statements(RetentionAction::Purge).unwrap(),
+            vec!["DELETE FROM emr.patients WHERE id = $1".to_string()]
+        );
+        assert!(patients.statements(RetentionAction::Anonymize).unwrap()[0].contains("family_name = NULL"));
+
+        let organizations = RetentionTable::of("Organization").unwrap();
+        assert!(matches!(
+            organizations.statements(RetentionAction::Anonymize),
+            Err(JobError::ValidationError(_))
+        ));
+    }
+}
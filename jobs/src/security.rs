@@ -0,0 +1,760 @@
+//! Security event detection
+//!
+//! The API records portal sign-ins and refused requests in
+//! `audit.auth_events`. On each poll the worker claims the successful
+//! sign-ins it has not checked yet and compares each with the subject's
+//! earlier sign-ins for new locations and impossible travel, counts refused
+//! attempts and distinct patients opened per user over the configured
+//! windows, and stores what the [`AnomalyRules`] flag in
+//! `audit.security_events`, where the API also records network zone
+//! violations. Every event is then claimed once and sent as a high-priority
+//! alert to the configured security admins.
+//!
+//! SecurityEvents audit report jobs render the events of the job's date
+//! range as CSV or PDF into `reports.output_dir`, at [`export_path`].
+
+use crate::config::ReportConfig;
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::types::{
+    AuditReportJob, AuditReportType, NotificationChannel, NotificationJob, NotificationType, OutputFormat, Priority,
+};
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use emr_core::domain::ReportFormat;
+use emr_core::notifications::{TemplateRef, SECURITY_EVENT_TEMPLATE};
+use emr_core::services::security_events::{
+    AnomalyRules, AuthEvent, AuthEventKind, GeoLocation, SecurityEvent, SecurityEventKind, SecurityEventReport,
+    SECURITY_EVENTS_QUERY,
+};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{BigInt, Double, Nullable, Text, Timestamptz};
+use diesel::{Connection, RunQueryDsl};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Sign-ins checked per poll
+pub const LOGIN_BATCH_SIZE: i64 = 500;
+
+/// Earlier sign-ins a sign-in is compared with
+pub const LOGIN_HISTORY_LIMIT: i64 = 100;
+
+/// Security events alerted per poll
+pub const ALERT_BATCH_SIZE: i64 = 100;
+
+/// Claim up to `$1` unchecked successful sign-ins, marking them checked
+pub const CLAIM_LOGINS_QUERY: &str = r#"
+UPDATE audit.auth_events SET checked_at = NOW()
+WHERE id IN (
+    SELECT id FROM audit.auth_events
+    WHERE kind = 'login_succeeded' AND checked_at IS NULL
+    ORDER BY occurred_at LIMIT $1 FOR UPDATE SKIP LOCKED)
+RETURNING occurred_at, subject, kind, ip_address, country, city, latitude, longitude, path
+"#;
+
+/// Up to `$3` successful sign-ins of subject `$1` before `$2`, newest first
+pub const LOGIN_HISTORY_QUERY: &str = r#"
+SELECT occurred_at, subject, kind, ip_address, country, city, latitude, longitude, path
+FROM audit.auth_events
+WHERE subject = $1 AND kind = 'login_succeeded' AND occurred_at < $2
+ORDER BY occurred_at DESC LIMIT $3
+"#;
+
+/// Subjects refused at least `$2` times since `$1` and not flagged for it
+/// since, with the count
+pub const FAILURE_COUNTS_QUERY: &str = r#"
+SELECT e.subject, COUNT(*) AS events
+FROM audit.auth_events e
+WHERE e.kind IN ('login_failed', 'access_denied') AND e.occurred_at >= $1
+  AND NOT EXISTS (
+    SELECT 1 FROM audit.security_events s
+    WHERE s.kind = 'repeated_authorization_failures' AND s.subject = e.subject AND s.occurred_at >= $1)
+GROUP BY e.subject
+HAVING COUNT(*) >= $2
+"#;
+
+/// Users who opened at least `$2` distinct patients' records since `$1` and
+/// were not flagged for it since, with the count
+pub const PATIENT_ACCESS_COUNTS_QUERY: &str = r#"
+SELECT a.user_id::text AS subject, COUNT(DISTINCT a.patient_id) AS events
+FROM audit.audit_log a
+WHERE a.table_name = 'patient_access' AND a.changed_at >= $1 AND a.user_id IS NOT NULL
+  AND NOT EXISTS (
+    SELECT 1 FROM audit.security_events s
+    WHERE s.kind = 'mass_record_access' AND s.subject = a.user_id::text AND s.occurred_at >= $1)
+GROUP BY a.user_id
+HAVING COUNT(DISTINCT a.patient_id) >= $2
+"#;
+
+/// Claim up to `$1` events not alerted yet, oldest first, marking them
+/// alerted
+pub const CLAIM_ALERTS_QUERY: &str = r#"
+UPDATE audit.security_events SET alerted_at = NOW()
+WHERE id IN (
+    SELECT id FROM audit.security_events
+    WHERE alerted_at IS NULL
+    ORDER BY detected_at LIMIT $1 FOR UPDATE SKIP LOCKED)
+RETURNING occurred_at, kind, subject, description, details::text AS details
+"#;
+
+const INSERT_SECURITY_EVENT_QUERY: &str = "INSERT INTO audit.security_events \
+     (kind, subject, occurred_at, description, details) VALUES ($1, $2, $3, $4, $5::jsonb)";
+
+/// Where a job stores its report
+pub fn export_path(output_dir: &str, job_id: Uuid, format: ReportFormat) -> PathBuf {
+    Path::new(output_dir)
+        .join("security-events")
+        .join(format!("{}.{}", job_id, format.as_str()))
+}
+
+/// Authentication events and the security events detected from them
+#[async_trait]
+pub trait SecurityEventStore: Send + Sync {
+    /// Claim up to `limit` successful sign-ins not checked yet
+    async fn claim_logins(&self, limit: i64) -> JobResult<Vec<AuthEvent>>;
+
+    /// Up to `limit` successful sign-ins of `subject` before `before`,
+    /// newest first
+    async fn login_history(&self, subject: &str, before: DateTime<Utc>, limit: i64) -> JobResult<Vec<AuthEvent>>;
+
+    /// Subjects refused at least `threshold` times since `since`, unless
+    /// already flagged for it since
+    async fn failure_counts(&self, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>>;
+
+    /// Users who opened at least `threshold` patients' records since
+    /// `since`, unless already flagged for it since
+    async fn patient_access_counts(&self, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>>;
+
+    /// Store detected events
+    async fn record(&self, events: &[SecurityEvent]) -> JobResult<()>;
+
+    /// Claim up to `limit` events not alerted yet, wherever they were
+    /// recorded
+    async fn claim_alerts(&self, limit: i64) -> JobResult<Vec<SecurityEvent>>;
+
+    /// Events in `[start, end)`, oldest first
+    async fn security_events(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> JobResult<Vec<SecurityEvent>>;
+}
+
+/// Events in `audit.auth_events` and `audit.security_events`
+pub struct DatabaseSecurityEventStore {
+    pool: Pool,
+}
+
+impl DatabaseSecurityEventStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool.get().await.map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn counts(&self, query: &'static str, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(query)
+                    .bind::<Timestamptz, _>(since)
+                    .bind::<BigInt, _>(i64::from(threshold))
+                    .load::<CountRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.subject, row.events.max(0) as u64))
+            .collect())
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct AuthEventRow {
+    #[diesel(sql_type = Timestamptz)]
+    occurred_at: DateTime<Utc>,
+    #[diesel(sql_type = Text)]
+    subject: String,
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    ip_address: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    country: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    city: Option<String>,
+    #[diesel(sql_type = Nullable<Double>)]
+    latitude: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    longitude: Option<f64>,
+    #[diesel(sql_type = Nullable<Text>)]
+    path: Option<String>,
+}
+
+impl AuthEventRow {
+    fn into_event(self) -> Option<AuthEvent> {
+        let location = self.country.map(|country| GeoLocation {
+            country,
+            city: self.city,
+            latitude: self.latitude,
+            longitude: self.longitude,
+        });
+        Some(AuthEvent {
+            occurred_at: self.occurred_at,
+            subject: self.subject,
+            kind: AuthEventKind::parse(&self.kind)?,
+            ip_address: self.ip_address,
+            location,
+            path: self.path,
+        })
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = Text)]
+    subject: String,
+    #[diesel(sql_type = BigInt)]
+    events: i64,
+}
+
+#[derive(diesel::QueryableByName)]
+struct SecurityEventRow {
+    #[diesel(sql_type = Timestamptz)]
+    occurred_at: DateTime<Utc>,
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Text)]
+    subject: String,
+    #[diesel(sql_type = Text)]
+    description: String,
+    #[diesel(sql_type = Text)]
+    details: String,
+}
+
+impl SecurityEventRow {
+    fn into_event(self) -> SecurityEvent {
+        SecurityEvent {
+            occurred_at: self.occurred_at,
+            kind: self.kind,
+            subject: self.subject,
+            description: self.description,
+            details: serde_json::from_str(&self.details).unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecurityEventStore for DatabaseSecurityEventStore {
+    async fn claim_logins(&self, limit: i64) -> JobResult<Vec<AuthEvent>> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(CLAIM_LOGINS_QUERY)
+                    .bind::<BigInt, _>(limit)
+                    .load::<AuthEventRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().filter_map(AuthEventRow::into_event).collect())
+    }
+
+    async fn login_history(&self, subject: &str, before: DateTime<Utc>, limit: i64) -> JobResult<Vec<AuthEvent>> {
+        let conn = self.connection().await?;
+        let subject = subject.to_string();
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(LOGIN_HISTORY_QUERY)
+                    .bind::<Text, _>(&subject)
+                    .bind::<Timestamptz, _>(before)
+                    .bind::<BigInt, _>(limit)
+                    .load::<AuthEventRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().filter_map(AuthEventRow::into_event).collect())
+    }
+
+    async fn failure_counts(&self, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>> {
+        self.counts(FAILURE_COUNTS_QUERY, since, threshold).await
+    }
+
+    async fn patient_access_counts(&self, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>> {
+        self.counts(PATIENT_ACCESS_COUNTS_QUERY, since, threshold).await
+    }
+
+    async fn record(&self, events: &[SecurityEvent]) -> JobResult<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let conn = self.connection().await?;
+        let events = events.to_vec();
+
+        conn.interact(move |conn| {
+            conn.transaction(|conn| {
+                for event in &events {
+                    diesel::sql_query(INSERT_SECURITY_EVENT_QUERY)
+                        .bind::<Text, _>(&event.kind)
+                        .bind::<Text, _>(&event.subject)
+                        .bind::<Timestamptz, _>(event.occurred_at)
+                        .bind::<Text, _>(&event.description)
+                        .bind::<Text, _>(event.details.to_string())
+                        .execute(conn)?;
+                }
+                Ok::<_, diesel::result::Error>(())
+            })
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn claim_alerts(&self, limit: i64) -> JobResult<Vec<SecurityEvent>> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(CLAIM_ALERTS_QUERY)
+                    .bind::<BigInt, _>(limit)
+                    .load::<SecurityEventRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        let mut events: Vec<SecurityEvent> = rows.into_iter().map(SecurityEventRow::into_event).collect();
+        events.sort_by_key(|event| event.occurred_at);
+        Ok(events)
+    }
+
+    async fn security_events(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> JobResult<Vec<SecurityEvent>> {
+        let conn = self.connection().await?;
+        let rows = conn
+            .interact(move |conn| {
+                diesel::sql_query(SECURITY_EVENTS_QUERY)
+                    .bind::<Timestamptz, _>(start)
+                    .bind::<Timestamptz, _>(end)
+                    .load::<SecurityEventRow>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.into_iter().map(SecurityEventRow::into_event).collect())
+    }
+}
+
+/// Check new sign-ins, refused attempts and record access at `now` against
+/// the rules, and store the events found
+pub async fn detect(
+    store: &dyn SecurityEventStore,
+    rules: &AnomalyRules,
+    now: DateTime<Utc>,
+) -> JobResult<Vec<SecurityEvent>> {
+    let mut events = Vec::new();
+
+    let mut logins = store.claim_logins(LOGIN_BATCH_SIZE).await?;
+    logins.sort_by_key(|login| login.occurred_at);
+    for login in logins.iter().filter(|login| login.location.is_some()) {
+        let history = store
+            .login_history(&login.subject, login.occurred_at, LOGIN_HISTORY_LIMIT)
+            .await?;
+        events.extend(rules.check_login(login, &history));
+    }
+
+    let failures_since = now - Duration::minutes(rules.failure_window_minutes);
+    for (subject, failures) in store.failure_counts(failures_since, rules.failure_threshold).await? {
+        events.extend(rules.check_failures(&subject, failures, now));
+    }
+
+    let access_since = now - Duration::minutes(rules.mass_access_window_minutes);
+    for (subject, patients) in store
+        .patient_access_counts(access_since, rules.mass_access_threshold)
+        .await?
+    {
+        events.extend(rules.check_record_access(&subject, patients, now));
+    }
+
+    store.record(&events).await?;
+    Ok(events)
+}
+
+/// High-priority alerts of each event to each security admin
+pub fn alert_jobs(events: &[SecurityEvent], admin_recipient_ids: &[Uuid]) -> Vec<NotificationJob> {
+    let mut jobs = Vec::new();
+    for event in events {
+        let variables = json!({
+            "kind": event.kind,
+            "subject": event.subject,
+            "description": event.description,
+        });
+        for recipient_id in admin_recipient_ids {
+            jobs.push(NotificationJob {
+                recipient_id: *recipient_id,
+                address: None,
+                notification_type: NotificationType::Alert,
+                message: String::new(),
+                template: Some(TemplateRef {
+                    name: SECURITY_EVENT_TEMPLATE.to_string(),
+                    locale: None,
+                    variables: variables.as_object().cloned().unwrap_or_default(),
+                }),
+                channel: NotificationChannel::Email,
+                priority: Priority::High,
+                scheduled_for: None,
+                digest: false,
+            });
+        }
+    }
+    jobs
+}
+
+/// Renders security event audit reports
+pub struct SecurityEventReportHandler {
+    config: ReportConfig,
+    store: Arc<dyn SecurityEventStore>,
+}
+
+impl SecurityEventReportHandler {
+    /// Create a handler reading events from `store` into `config.output_dir`
+    pub fn new(config: ReportConfig, store: Arc<dyn SecurityEventStore>) -> Self {
+        Self { config, store }
+    }
+}
+
+#[async_trait]
+impl JobHandler<AuditReportJob> for SecurityEventReportHandler {
+    async fn execute(&self, job: AuditReportJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(job_id = ?context.job_id, "Starting security event report job");
+
+        if !matches!(job.report_type, AuditReportType::SecurityEvents) {
+            return Ok(JobExecutionResult::failure(format!(
+                "{:?} audit reports are not supported",
+                job.report_type
+            )));
+        }
+        let format = match job.output_format {
+            OutputFormat::Csv => ReportFormat::Csv,
+            OutputFormat::Pdf => ReportFormat::Pdf,
+            other => {
+                return Ok(JobExecutionResult::failure(format!(
+                    "Security event reports are rendered as CSV or PDF, not {:?}",
+                    other
+                )))
+            }
+        };
+
+        let report = SecurityEventReport {
+            start: job.date_range.start,
+            end: job.date_range.end,
+            events: self
+                .store
+                .security_events(job.date_range.start, job.date_range.end)
+                .await?,
+        };
+        context.check_cancelled()?;
+
+        let path = export_path(&self.config.output_dir, context.job_id, format);
+        if let Some(directory) = path.parent() {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .map_err(|e| JobError::ProcessingError(format!("Failed to create report directory: {}", e)))?;
+        }
+        tokio::fs::write(&path, report.render(format))
+            .await
+            .map_err(|e| JobError::ProcessingError(format!("Failed to store report: {}", e)))?;
+
+        let mut by_kind = serde_json::Map::new();
+        for kind in [
+            SecurityEventKind::NewLocation,
+            SecurityEventKind::ImpossibleTravel,
+            SecurityEventKind::RepeatedAuthorizationFailures,
+            SecurityEventKind::MassRecordAccess,
+            SecurityEventKind::NetworkZoneViolation,
+            SecurityEventKind::ThrottleLockout,
+        ] {
+            let count = report.events.iter().filter(|event| event.kind == kind.as_str()).count();
+            by_kind.insert(kind.as_str().to_string(), json!(count));
+        }
+
+        Ok(JobExecutionResult::success_with_data(
+            format!("Security event report generated with {} events", report.events.len()),
+            json!({ "path": path.display().to_string(), "events": by_kind }),
+        )
+        .with_metric("events".to_string(), report.events.len() as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "SecurityEventReport"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DateRange;
+    use chrono::TimeZone;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    /// Events kept in memory, filtered like the database queries do
+    #[derive(Default)]
+    struct MemorySecurityEventStore {
+        auth_events: Mutex<Vec<(AuthEvent, bool)>>,
+        /// `(user, patient, accessed_at)`
+        accesses: Vec<(String, Uuid, DateTime<Utc>)>,
+        /// Events and whether they were alerted
+        events: Mutex<Vec<(SecurityEvent, bool)>>,
+    }
+
+    impl MemorySecurityEventStore {
+        fn flagged(&self, kind: SecurityEventKind, subject: &str, since: DateTime<Utc>) -> bool {
+            self.events
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(event, _)| event)
+                .any(|event| event.kind == kind.as_str() && event.subject == subject && event.occurred_at >= since)
+        }
+    }
+
+    #[async_trait]
+    impl SecurityEventStore for MemorySecurityEventStore {
+        async fn claim_logins(&self, limit: i64) -> JobResult<Vec<AuthEvent>> {
+            let mut auth_events = self.auth_events.lock().unwrap();
+            let mut claimed = Vec::new();
+            for (event, checked) in auth_events.iter_mut() {
+                if event.kind == AuthEventKind::LoginSucceeded && !*checked && (claimed.len() as i64) < limit {
+                    *checked = true;
+                    claimed.push(event.clone());
+                }
+            }
+            Ok(claimed)
+        }
+
+        async fn login_history(&self, subject: &str, before: DateTime<Utc>, limit: i64) -> JobResult<Vec<AuthEvent>> {
+            let mut history: Vec<AuthEvent> = self
+                .auth_events
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(event, _)| event)
+                .filter(|event| event.subject == subject && event.kind == AuthEventKind::LoginSucceeded)
+                .filter(|event| event.occurred_at < before)
+                .cloned()
+                .collect();
+            history.sort_by_key(|event| std::cmp::Reverse(event.occurred_at));
+            history.truncate(limit as usize);
+            Ok(history)
+        }
+
+        async fn failure_counts(&self, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>> {
+            let mut counts: Vec<(String, u64)> = Vec::new();
+            for (event, _) in self.auth_events.lock().unwrap().iter() {
+                if !event.kind.is_failure() || event.occurred_at < since {
+                    continue;
+                }
+                match counts.iter_mut().find(|(subject, _)| *subject == event.subject) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((event.subject.clone(), 1)),
+                }
+            }
+            Ok(counts
+                .into_iter()
+                .filter(|(subject, count)| {
+                    *count >= u64::from(threshold)
+                        && !self.flagged(SecurityEventKind::RepeatedAuthorizationFailures, subject, since)
+                })
+                .collect())
+        }
+
+        async fn patient_access_counts(&self, since: DateTime<Utc>, threshold: u32) -> JobResult<Vec<(String, u64)>> {
+            let users: HashSet<&String> = self.accesses.iter().map(|(user, _, _)| user).collect();
+            Ok(users
+                .into_iter()
+                .map(|user| {
+                    let patients: HashSet<Uuid> = self
+                        .accesses
+                        .iter()
+                        .filter(|(accessor, _, accessed_at)| accessor == user && *accessed_at >= since)
+                        .map(|(_, patient, _)| *patient)
+                        .collect();
+                    (user.clone(), patients.len() as u64)
+                })
+                .filter(|(user, count)| {
+                    *count >= u64::from(threshold) && !self.flagged(SecurityEventKind::MassRecordAccess, user, since)
+                })
+                .collect())
+        }
+
+        async fn record(&self, events: &[SecurityEvent]) -> JobResult<()> {
+            self.events
+                .lock()
+                .unwrap()
+                .extend(events.iter().map(|event| (event.clone(), false)));
+            Ok(())
+        }
+
+        async fn claim_alerts(&self, limit: i64) -> JobResult<Vec<SecurityEvent>> {
+            let mut events = self.events.lock().unwrap();
+            let mut claimed = Vec::new();
+            for (event, alerted) in events.iter_mut() {
+                if !*alerted && (claimed.len() as i64) < limit {
+                    *alerted = true;
+                    claimed.push(event.clone());
+                }
+            }
+            Ok(claimed)
+        }
+
+        async fn security_events(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> JobResult<Vec<SecurityEvent>> {
+            Ok(self
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(event, _)| event)
+                .filter(|event| event.occurred_at >= start && event.occurred_at < end)
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn auth_event(
+        kind: AuthEventKind,
+        subject: &str,
+        occurred_at: DateTime<Utc>,
+        place: Option<(&str, f64, f64)>,
+    ) -> AuthEvent {
+        AuthEvent {
+            occurred_at,
+            subject: subject.to_string(),
+            kind,
+            ip_address: Some("203.0.113.7".to_string()),
+            location: place.map(|(city, latitude, longitude)| GeoLocation {
+                country: "US".to_string(),
+                city: Some(city.to_string()),
+                latitude: Some(latitude),
+                longitude: Some(longitude),
+            }),
+            path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect_anomalies() {
+        let now = Utc.with_ymd_and_hms(2026, 5, 4, 12, 0, 0).unwrap();
+        let portland = Some(("Portland", 45.52, -122.68));
+        let miami = Some(("Miami", 25.76, -80.19));
+        let mut auth_events = vec![
+            (auth_event(AuthEventKind::LoginSucceeded, "portal:a", now - Duration::days(3), portland), true),
+            (auth_event(AuthEventKind::LoginSucceeded, "portal:a", now - Duration::hours(2), portland), false),
+            (auth_event(AuthEventKind::LoginSucceeded, "portal:a", now - Duration::hours(1), miami), false),
+        ];
+        for minutes in 0..10 {
+            let at = now - Duration::minutes(minutes);
+            auth_events.push((auth_event(AuthEventKind::LoginFailed, "ip:198.51.100.4", at, None), false));
+        }
+        let nurse = Uuid::new_v4().to_string();
+        let store = MemorySecurityEventStore {
+            auth_events: Mutex::new(auth_events),
+            accesses: (0..50)
+                .map(|minutes| (nurse.clone(), Uuid::new_v4(), now - Duration::minutes(minutes)))
+                .collect(),
+            events: Mutex::new(Vec::new()),
+        };
+        let rules = AnomalyRules::default();
+
+        let events = detect(&store, &rules, now).await.unwrap();
+        let kinds: Vec<(&str, &str)> = events
+            .iter()
+            .map(|event| (event.kind.as_str(), event.subject.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("new_location", "portal:a"),
+                ("impossible_travel", "portal:a"),
+                ("repeated_authorization_failures", "ip:198.51.100.4"),
+                ("mass_record_access", nurse.as_str()),
+            ]
+        );
+
+        // Sign-ins are checked once and ongoing activity is flagged once per window
+        assert!(detect(&store, &rules, now + Duration::minutes(5)).await.unwrap().is_empty());
+        assert_eq!(store.security_events(now, now + Duration::days(1)).await.unwrap().len(), 2);
+
+        // Each event is alerted once, along with those the API recorded
+        let violation = SecurityEvent::network_zone_violation("jdoe", "billing", Some("198.51.100.4"), "/api/claims", now);
+        store.record(&[violation]).await.unwrap();
+        assert_eq!(store.claim_alerts(ALERT_BATCH_SIZE).await.unwrap().len(), 5);
+        assert!(store.claim_alerts(ALERT_BATCH_SIZE).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_alert_jobs() {
+        let rules = AnomalyRules::default();
+        let event = rules.check_failures("ip:198.51.100.4", 12, Utc::now()).unwrap();
+        let admins = [Uuid::new_v4(), Uuid::new_v4()];
+
+        let jobs = alert_jobs(&[event], &admins);
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[1].recipient_id, admins[1]);
+        assert!(jobs.iter().all(|job| job.priority == Priority::High));
+        let template = jobs[0].template.as_ref().unwrap();
+        assert_eq!(template.name, SECURITY_EVENT_TEMPLATE);
+        assert_eq!(template.variables["kind"], "repeated_authorization_failures");
+        assert!(alert_jobs(&[], &admins).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_security_event_report_written() {
+        let output_dir = std::env::temp_dir().join(format!("security-events-{}", Uuid::new_v4()));
+        let config = ReportConfig {
+            output_dir: output_dir.display().to_string(),
+        };
+        let start = Utc.with_ymd_and_hms(2026, 5, 1, 0, 0, 0).unwrap();
+        let rules = AnomalyRules::default();
+        let store = Arc::new(MemorySecurityEventStore::default());
+        store
+            .record(&[
+                rules.check_record_access("jdoe", 60, start + Duration::days(1)).unwrap(),
+                rules.check_failures("ip:198.51.100.4", 10, start + Duration::days(40)).unwrap(),
+            ])
+            .await
+            .unwrap();
+        let handler = SecurityEventReportHandler::new(config.clone(), store);
+        let context = JobContext::new(Uuid::new_v4());
+        let job = |output_format| AuditReportJob {
+            report_type: AuditReportType::SecurityEvents,
+            date_range: DateRange {
+                start,
+                end: start + Duration::days(31),
+            },
+            patient_ids: None,
+            practitioner_ids: None,
+            output_format,
+        };
+
+        let result = handler.execute(job(OutputFormat::Csv), context.clone()).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.metrics["events"], 1.0);
+        assert_eq!(result.data.unwrap()["events"]["mass_record_access"], 1);
+
+        let csv = std::fs::read_to_string(export_path(&config.output_dir, context.job_id, ReportFormat::Csv)).unwrap();
+        assert!(csv.starts_with("occurred_at,kind,subject,description"));
+        assert_eq!(csv.lines().count(), 2);
+
+        let html = handler.execute(job(OutputFormat::Html), context).await.unwrap();
+        assert!(!html.success);
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+//! FHIR Subscription dispatcher
+//!
+//! Evaluates resource changes against registered subscriptions and produces
+//! notification jobs for each matching rest-hook endpoint.
+
+use crate::types::{JobType, SubscriptionNotificationJob};
+use emr_fhir::{Subscription, SubscriptionRegistry};
+use serde_json::Value;
+use tracing::info;
+
+/// Default number of delivery attempts per notification
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Turns resource changes into subscription notification jobs
+#[derive(Debug, Clone)]
+pub struct SubscriptionDispatcher {
+    registry: SubscriptionRegistry,
+    max_attempts: u32,
+}
+
+impl SubscriptionDispatcher {
+    /// Create a new dispatcher over a subscription registry
+    pub fn new(registry: SubscriptionRegistry) -> Self {
+        Self {
+            registry,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Set the number of delivery attempts per notification
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Build notification jobs for a created or updated resource
+    pub async fn dispatch(&self, resource: &Value) -> Vec<JobType> {
+        let subscriptions = self.registry.matching(resource).await;
+
+        if !subscriptions.is_empty() {
+            info!(
+                resource_type = ?resource.get("resourceType"),
+                matches = subscriptions.len(),
+                "Dispatching subscription notifications"
+            );
+        }
+
+        subscriptions
+            .iter()
+            .filter_map(|subscription| self.notification_job(subscription, resource))
+            .map(JobType::SubscriptionNotification)
+            .collect()
+    }
+
+    fn notification_job(&self, subscription: &Subscription, resource: &Value) -> Option<SubscriptionNotificationJob> {
+        Some(SubscriptionNotificationJob {
+            subscription_id: subscription.id.clone()?,
+            endpoint: subscription.channel.endpoint.clone()?,
+            headers: subscription.headers(),
+            resource_type: resource.get("resourceType")?.as_str()?.to_string(),
+            resource_id: resource.get("id").and_then(Value::as_str).map(str::to_string),
+            // An empty payload means the endpoint is only notified that something changed
+            payload: subscription.channel.payload.as_ref().map(|_| resource.clone()),
+            max_attempts: self.max_attempts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn registry_with(criteria: &str, payload: Option<&str>) -> SubscriptionRegistry {
+        let registry = SubscriptionRegistry::new();
+        let subscription = Subscription::from_resource(&json!({
+            "resourceType": "Subscription",
+            "status": "requested",
+            "reason": "test",
+            "criteria": criteria,
+            "channel": {
+                "type": "rest-hook",
+                "endpoint": "https://example.com/hook",
+                "payload": payload,
+            }
+        }))
+        .unwrap();
+        registry.register(subscription).await.unwrap();
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_matching_resource() {
+        let dispatcher = SubscriptionDispatcher::new(
+            registry_with("Observation?status=final", Some("application/fhir+json")).await,
+        );
+        let resource = json!({"resourceType": "Observation", "id": "obs-1", "status": "final"});
+
+        let jobs = dispatcher.dispatch(&resource).await;
+        assert_eq!(jobs.len(), 1);
+        match &jobs[0] {
+            JobType::SubscriptionNotification(job) => {
+                assert_eq!(job.resource_id.as_deref(), Some("obs-1"));
+                assert!(job.payload.is_some());
+                assert_eq!(job.max_attempts, DEFAULT_MAX_ATTEMPTS);
+            }
+            other => panic!("Unexpected job type: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_empty_payload() {
+        let dispatcher = SubscriptionDispatcher::new(registry_with("Observation", None).await);
+        let resource = json!({"resourceType": "Observation", "status": "final"});
+
+        match &dispatcher.dispatch(&resource).await[0] {
+            JobType::SubscriptionNotification(job) => assert!(job.payload.is_none()),
+            other => panic!("Unexpected job type: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_non_matching_resource() {
+        let dispatcher = SubscriptionDispatcher::new(registry_with("Observation?status=final", None).await);
+        let resource = json!({"resourceType": "Observation", "status": "preliminary"});
+
+        assert!(dispatcher.dispatch(&resource).await.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! FHIR synchronization between two FHIR servers
+//!
+//! A FhirSync job copies one patient's resources of one type between
+//! `source_url` and `target_url`: `Pull` copies from the source to the
+//! target, `Push` the other way and `Bidirectional` does both, pulling
+//! first. Only resources changed since `last_sync` are copied. A copy keeps
+//! the resource's id and is written with an update, so syncing again does no
+//! harm. Every pulled resource whose id is a UUID gets a FhirSync provenance
+//! record.
+//!
+//! Server failures and timeouts fail the job with a retryable error; a
+//! request the server rejects fails it for good.
+
+use crate::handlers::{JobExecutionResult, JobHandler};
+use crate::provenance::{sync_provenance, ProvenanceStore};
+use crate::types::{FhirSyncJob, SyncDirection};
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use emr_fhir::{FhirClientError, FhirGateway, KodjinClient, SearchParameters, SearchPrefix};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Resources asked for per search page
+const SYNC_PAGE_SIZE: u32 = 100;
+
+/// Upper bound on search pages read for one job
+const MAX_SYNC_PAGES: u32 = 100;
+
+/// Default time allowed for one FHIR request
+const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Copies a patient's resources between FHIR servers
+pub struct FhirSyncHandler {
+    provenance: Arc<dyn ProvenanceStore>,
+    timeout: Duration,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl FhirSyncHandler {
+    /// Create a handler recording the provenance of pulled resources in
+    /// `provenance`
+    pub fn new(provenance: Arc<dyn ProvenanceStore>) -> Self {
+        Self {
+            provenance,
+            timeout: DEFAULT_SYNC_TIMEOUT,
+            max_retries: 2,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+
+    /// Set the time allowed for one FHIR request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how often idempotent requests are retried on server failures
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    fn client(&self, base_url: &str) -> JobResult<KodjinClient> {
+        Ok(KodjinClient::new(base_url)
+            .map_err(sync_error)?
+            .with_timeout(self.timeout)
+            .with_retries(self.max_retries, self.retry_delay))
+    }
+
+    /// Resources of the job's type and patient changed since its last sync
+    async fn changed_resources(&self, from: &KodjinClient, job: &FhirSyncJob) -> JobResult<Vec<Value>> {
+        let patient = if job.resource_type == "Patient" { "_id" } else { "patient" };
+        let mut resources = Vec::new();
+
+        for page in 0..MAX_SYNC_PAGES {
+            let mut params = SearchParameters::new(&job.resource_type)
+                .add_parameter(patient, &job.patient_id.to_string())
+                .with_count(SYNC_PAGE_SIZE)
+                .with_offset(page * SYNC_PAGE_SIZE);
+            if let Some(last_sync) = job.last_sync {
+                params = params.with_prefix("_lastUpdated", SearchPrefix::Gt, &last_sync.to_rfc3339());
+            }
+
+            let bundle = from.search(&params).await.map_err(sync_error)?;
+            let entries = bundle["entry"].as_array().map(Vec::as_slice).unwrap_or_default();
+            resources.extend(entries.iter().filter_map(|entry| entry.get("resource").cloned()));
+            if entries.len() < SYNC_PAGE_SIZE as usize {
+                return Ok(resources);
+            }
+        }
+
+        warn!(
+            patient_id = %job.patient_id,
+            resource_type = %job.resource_type,
+            "FHIR sync stopped after {} search pages",
+            MAX_SYNC_PAGES
+        );
+        Ok(resources)
+    }
+
+    /// Copy the changed resources from one server to the other; returns how
+    /// many were copied
+    async fn copy(
+        &self,
+        from: &KodjinClient,
+        to: &KodjinClient,
+        job: &FhirSyncJob,
+        context: &JobContext,
+        record_provenance: bool,
+    ) -> JobResult<usize> {
+        let resources = self.changed_resources(from, job).await?;
+        let mut copied = 0;
+
+        for resource in resources {
+            context.check_cancelled()?;
+            let Some(id) = resource["id"].as_str() else {
+                warn!(job_id = ?context.job_id, resource_type = %job.resource_type, "Skipped a resource without an id");
+                continue;
+            };
+
+            to.update(&job.resource_type, id, &resource).await.map_err(sync_error)?;
+            copied += 1;
+
+            if record_provenance {
+                match Uuid::parse_str(id) {
+                    Ok(target_id) => {
+                        self.provenance
+                            .record(&sync_provenance(job, context.job_id, target_id, id))
+                            .await?
+                    }
+                    Err(_) => warn!(resource_id = %id, "No provenance for a resource whose id is not a UUID"),
+                }
+            }
+        }
+
+        Ok(copied)
+    }
+}
+
+/// Job error for a failed FHIR request; retryable when the server may
+/// succeed later
+fn sync_error(err: FhirClientError) -> JobError {
+    match err {
+        err if err.is_retryable() => JobError::ExternalServiceError(err.to_string()),
+        FhirClientError::Configuration(message) => JobError::ConfigurationError(message),
+        err => JobError::ProcessingError(err.to_string()),
+    }
+}
+
+#[async_trait]
+impl JobHandler<FhirSyncJob> for FhirSyncHandler {
+    async fn execute(&self, job: FhirSyncJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            patient_id = %job.patient_id,
+            resource_type = %job.resource_type,
+            direction = ?job.sync_direction,
+            "Starting FHIR sync job"
+        );
+
+        let source = self.client(&job.source_url)?;
+        let target = self.client(&job.target_url)?;
+
+        let pulled = match job.sync_direction {
+            SyncDirection::Pull | SyncDirection::Bidirectional => {
+                self.copy(&source, &target, &job, &context, true).await?
+            }
+            SyncDirection::Push => 0,
+        };
+        let pushed = match job.sync_direction {
+            SyncDirection::Push | SyncDirection::Bidirectional => {
+                self.copy(&target, &source, &job, &context, false).await?
+            }
+            SyncDirection::Pull => 0,
+        };
+
+        let data = serde_json::json!({
+            "patient_id": job.patient_id,
+            "resource_type": job.resource_type,
+            "pulled": pulled,
+            "pushed": pushed,
+            "synced_at": context.started_at,
+        });
+        Ok(JobExecutionResult::success_with_data(
+            format!("Synchronized {} {} resources", pulled + pushed, job.resource_type),
+            data,
+        )
+        .with_metric("resources_pulled".to_string(), pulled as f64)
+        .with_metric("resources_pushed".to_string(), pushed as f64))
+    }
+
+    fn name(&self) -> &'static str {
+        "fhir_sync"
+    }
+}
@@ -1,7 +1,8 @@
 //! Job type definitions and payloads
 
-use chrono::{DateTime, Utc};
-use core::entities::*;
+use chrono::{DateTime, NaiveDate, Utc};
+use emr_core::notifications::TemplateRef;
+use emr_core::validation::ValidationProfile;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -32,6 +33,153 @@ pub enum JobType {
     
     /// Generate analytics reports
     Analytics(AnalyticsJob),
+
+    /// Deliver a FHIR Subscription notification
+    SubscriptionNotification(SubscriptionNotificationJob),
+
+    /// Encrypted logical database backup
+    Backup(BackupJob),
+
+    /// Deliver a domain event to a tenant's webhook endpoint
+    WebhookDelivery(WebhookDeliveryJob),
+
+    /// Bill coded encounters as an X12 837P claims file
+    ClaimsExport(ClaimsExportJob),
+
+    /// Post an X12 835 remittance against exported claims
+    RemittancePosting(RemittancePostingJob),
+
+    /// Extract the text of an uploaded document for search
+    DocumentOcr(DocumentOcrJob),
+
+    /// Find the members of a saved patient panel again
+    PanelRefresh(PanelRefreshJob),
+
+    /// Generate a scheduled report and notify its recipients
+    ScheduledReport(ScheduledReportJob),
+
+    /// Move data-encryption keys to a new master key
+    KeyRotation(KeyRotationJob),
+
+    /// Create monthly partitions ahead and expire those past retention
+    PartitionMaintenance(PartitionMaintenanceJob),
+}
+
+/// Worker queue a job runs on
+///
+/// Each queue has its own worker pool so long exports cannot starve
+/// notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobQueue {
+    /// Notifications, subscription and webhook deliveries
+    LatencySensitive,
+    /// Everything else
+    Default,
+    /// Exports, imports, backups and reports
+    LongRunning,
+}
+
+impl JobQueue {
+    pub const ALL: [JobQueue; 3] = [JobQueue::LatencySensitive, JobQueue::Default, JobQueue::LongRunning];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobQueue::LatencySensitive => "latency_sensitive",
+            JobQueue::Default => "default",
+            JobQueue::LongRunning => "long_running",
+        }
+    }
+
+    /// Queue named `name`, as returned by [`JobQueue::as_str`]
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|queue| queue.as_str() == name)
+    }
+}
+
+impl JobType {
+    /// Job type name, as used for the `type` tag
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobType::FhirSync(_) => "FhirSync",
+            JobType::DataValidation(_) => "DataValidation",
+            JobType::AuditReport(_) => "AuditReport",
+            JobType::Notification(_) => "Notification",
+            JobType::DataExport(_) => "DataExport",
+            JobType::DataImport(_) => "DataImport",
+            JobType::DataCleanup(_) => "DataCleanup",
+            JobType::Analytics(_) => "Analytics",
+            JobType::SubscriptionNotification(_) => "SubscriptionNotification",
+            JobType::Backup(_) => "Backup",
+            JobType::WebhookDelivery(_) => "WebhookDelivery",
+            JobType::ClaimsExport(_) => "ClaimsExport",
+            JobType::RemittancePosting(_) => "RemittancePosting",
+            JobType::DocumentOcr(_) => "DocumentOcr",
+            JobType::PanelRefresh(_) => "PanelRefresh",
+            JobType::ScheduledReport(_) => "ScheduledReport",
+            JobType::KeyRotation(_) => "KeyRotation",
+            JobType::PartitionMaintenance(_) => "PartitionMaintenance",
+        }
+    }
+
+    /// Queue the job runs on
+    pub fn queue(&self) -> JobQueue {
+        match self {
+            JobType::Notification(_) | JobType::SubscriptionNotification(_) | JobType::WebhookDelivery(_) => {
+                JobQueue::LatencySensitive
+            }
+            JobType::DataExport(_)
+            | JobType::DataImport(_)
+            | JobType::AuditReport(_)
+            | JobType::Analytics(_)
+            | JobType::Backup(_)
+            | JobType::ClaimsExport(_)
+            | JobType::RemittancePosting(_)
+            | JobType::DocumentOcr(_)
+            | JobType::PanelRefresh(_)
+            | JobType::ScheduledReport(_)
+            | JobType::KeyRotation(_) => JobQueue::LongRunning,
+            JobType::FhirSync(_)
+            | JobType::DataValidation(_)
+            | JobType::DataCleanup(_)
+            | JobType::PartitionMaintenance(_) => JobQueue::Default,
+        }
+    }
+
+    /// Priority within the queue; notifications carry their own
+    pub fn priority(&self) -> Priority {
+        match self {
+            JobType::Notification(job) => job.priority,
+            JobType::SubscriptionNotification(_) | JobType::FhirSync(_) => Priority::High,
+            JobType::DataCleanup(_)
+            | JobType::Analytics(_)
+            | JobType::PanelRefresh(_)
+            | JobType::ScheduledReport(_) => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// Job published on the submit subject
+///
+/// Submitters that want to follow or cancel the job choose its id; older
+/// payloads without one get an id assigned by the worker. An
+/// `idempotency_key` names the business operation so that submitting it
+/// twice runs it once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSubmission {
+    #[serde(default)]
+    pub job_id: Option<Uuid>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    #[serde(flatten)]
+    pub job: JobType,
+}
+
+/// Request to cancel a pending or running job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobCancellation {
+    pub job_id: Uuid,
 }
 
 /// FHIR synchronization job
@@ -52,6 +200,10 @@ pub struct DataValidationJob {
     pub validation_type: ValidationType,
     pub rules: Vec<ValidationRule>,
     pub auto_fix: bool,
+    /// Evaluate stored entities against this profile version instead of
+    /// `rules`; the tenant and entity type come from the profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ValidationProfile>,
 }
 
 /// Audit report generation job
@@ -68,11 +220,24 @@ pub struct AuditReportJob {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationJob {
     pub recipient_id: Uuid,
+    /// Explicit destination (phone number or email) instead of the
+    /// recipient's preferred contact point
+    #[serde(default)]
+    pub address: Option<String>,
     pub notification_type: NotificationType,
+    /// Preformatted text, used when no template is given
+    #[serde(default)]
     pub message: String,
+    /// Template the text is rendered from at delivery
+    #[serde(default)]
+    pub template: Option<TemplateRef>,
     pub channel: NotificationChannel,
     pub priority: Priority,
     pub scheduled_for: Option<DateTime<Utc>>,
+    /// Daily digest of held notifications, which is not held for the
+    /// digest again
+    #[serde(default)]
+    pub digest: bool,
 }
 
 /// Data export job
@@ -93,6 +258,26 @@ pub struct DataImportJob {
     pub mapping_config: Option<String>,
     pub validation_rules: Vec<ValidationRule>,
     pub auto_merge: bool,
+    /// Set for files picked up by the ingestion watcher
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ImportProvenance>,
+}
+
+/// Where an ingested batch file came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportProvenance {
+    /// Ingestion source the file was picked up from
+    pub source_name: String,
+    /// System that produced the file, e.g. a lab's LIS
+    pub source_system: String,
+    /// Name the file was dropped under
+    pub file_name: String,
+    /// Name in the staging, processed and error folders
+    pub staged_name: String,
+    pub size_bytes: u64,
+    /// Hex SHA-256 of the file contents
+    pub sha256: String,
+    pub received_at: DateTime<Utc>,
 }
 
 /// Data cleanup job
@@ -102,16 +287,165 @@ pub struct DataCleanupJob {
     pub older_than: DateTime<Utc>,
     pub dry_run: bool,
     pub preserve_audit: bool,
+    /// Entity types to consider for retention cleanup; empty means all
+    #[serde(default)]
+    pub entity_types: Vec<String>,
 }
 
 /// Analytics job
+///
+/// Quality analytics compute clinical quality measures for the reporting
+/// period `date_range`: those in `measure_ids`, or every active measure when
+/// it is empty.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalyticsJob {
     pub analytics_type: AnalyticsType,
     pub date_range: DateRange,
+    #[serde(default)]
     pub dimensions: Vec<String>,
+    #[serde(default)]
     pub metrics: Vec<String>,
+    #[serde(default)]
     pub output_location: String,
+    #[serde(default)]
+    pub measure_ids: Vec<Uuid>,
+}
+
+/// FHIR Subscription rest-hook notification job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionNotificationJob {
+    pub subscription_id: String,
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+    pub resource_type: String,
+    pub resource_id: Option<String>,
+    /// Resource body, omitted for empty-payload subscriptions
+    pub payload: Option<serde_json::Value>,
+    pub max_attempts: u32,
+}
+
+/// Database backup job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupJob {
+    pub backup_id: Uuid,
+    /// Schemas to dump; empty dumps the whole database
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// Label added to the object key, e.g. `nightly`
+    pub label: Option<String>,
+}
+
+/// Claims export job; bills the finished, coded encounters of the service
+/// period that have not been billed yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimsExportJob {
+    pub export_id: Uuid,
+    /// First date of service, inclusive
+    pub service_from: NaiveDate,
+    /// Last date of service, inclusive
+    pub service_to: NaiveDate,
+    /// Only encounters at this organization
+    #[serde(default)]
+    pub organization_id: Option<Uuid>,
+    /// Only claims to this payer
+    #[serde(default)]
+    pub payer_id: Option<String>,
+}
+
+/// Remittance posting job; posts the payments of an 835 file received from
+/// a payer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemittancePostingJob {
+    /// Path of the 835 file
+    pub source_location: String,
+}
+
+/// Document OCR job; extracts the text of a document that was scanned clean
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentOcrJob {
+    pub document_id: Uuid,
+}
+
+/// Panel refresh job; replaces a saved patient panel's members with the
+/// patients its criteria find now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanelRefreshJob {
+    pub panel_id: Uuid,
+}
+
+/// Scheduled report job; one run of a report schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledReportJob {
+    pub schedule_id: Uuid,
+    /// When the run was due; the report's period ends here
+    pub scheduled_for: DateTime<Utc>,
+}
+
+/// Key rotation job; re-wraps every active data-encryption key under a new
+/// master key and, with `reencrypt_fields`, replaces the data keys and
+/// re-encrypts the fields sealed with them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotationJob {
+    pub rotation_id: Uuid,
+    /// Master key from `encryption.master_keys` to wrap data keys under
+    pub master_key_id: String,
+    #[serde(default)]
+    pub reencrypt_fields: bool,
+    /// Fields re-encrypted per transaction
+    #[serde(default = "default_rotation_batch_size")]
+    pub batch_size: u32,
+}
+
+fn default_rotation_batch_size() -> u32 {
+    500
+}
+
+/// Partition maintenance job; queued daily for every partitioned table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionMaintenanceJob {
+    /// Qualified table name, e.g. `emr.observations`
+    pub table: String,
+    /// Only report what is due
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Domain event published to tenants' webhook endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    /// Organization whose endpoints receive the event
+    pub tenant_id: Uuid,
+    /// Dotted event name, e.g. `encounter.started`
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub data: serde_json::Value,
+}
+
+/// Webhook delivery job; the endpoint URL and secret are read at delivery
+/// time so they never appear in job payloads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryJob {
+    pub endpoint_id: Uuid,
+    pub event: WebhookEvent,
+    pub max_attempts: u32,
+}
+
+/// Subscription delivery status
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// A single delivery attempt for a notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    pub attempt: u32,
+    pub attempted_at: DateTime<Utc>,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
 }
 
 /// Synchronization direction
@@ -179,7 +513,7 @@ pub enum OutputFormat {
 }
 
 /// Notification types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NotificationType {
     Alert,
     Reminder,
@@ -188,8 +522,21 @@ pub enum NotificationType {
     Error,
 }
 
+impl NotificationType {
+    /// Serialized name, as used by notification preferences
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationType::Alert => "Alert",
+            NotificationType::Reminder => "Reminder",
+            NotificationType::Update => "Update",
+            NotificationType::Warning => "Warning",
+            NotificationType::Error => "Error",
+        }
+    }
+}
+
 /// Notification channels
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NotificationChannel {
     Email,
     Sms,
@@ -197,8 +544,20 @@ pub enum NotificationChannel {
     InApp,
 }
 
-/// Priority levels
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl NotificationChannel {
+    /// Serialized name, as used by notification preferences
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::Email => "Email",
+            NotificationChannel::Sms => "Sms",
+            NotificationChannel::Push => "Push",
+            NotificationChannel::InApp => "InApp",
+        }
+    }
+}
+
+/// Priority levels, lowest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Priority {
     Low,
     Normal,
@@ -206,6 +565,18 @@ pub enum Priority {
     Critical,
 }
 
+impl Priority {
+    /// Serialized name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+            Priority::Critical => "Critical",
+        }
+    }
+}
+
 /// Export formats
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExportFormat {
@@ -217,7 +588,7 @@ pub enum ExportFormat {
 }
 
 /// Import formats
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ImportFormat {
     Fhir,
     Hl7,
@@ -234,6 +605,8 @@ pub enum CleanupType {
     OldRecords,
     Duplicates,
     Orphaned,
+    /// Apply the configured retention policies
+    Retention,
 }
 
 /// Analytics types
@@ -263,8 +636,9 @@ pub struct JobMetadata {
 }
 
 /// Job status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub enum JobStatus {
+    #[default]
     Pending,
     Running,
     Completed,
@@ -273,11 +647,6 @@ pub enum JobStatus {
     Retrying,
 }
 
-impl Default for JobStatus {
-    fn default() -> Self {
-        JobStatus::Pending
-    }
-}
 
 impl JobMetadata {
     /// Create new job metadata
@@ -378,6 +747,31 @@ mod tests {
         assert!(!metadata.can_retry());
     }
 
+    #[test]
+    fn test_job_queue_and_priority() {
+        let notification = JobType::Notification(NotificationJob {
+            recipient_id: Uuid::new_v4(),
+            address: None,
+            notification_type: NotificationType::Alert,
+            message: "Critical potassium".to_string(),
+            template: None,
+            channel: NotificationChannel::Sms,
+            priority: Priority::Critical,
+            scheduled_for: None,
+            digest: false,
+        });
+        assert_eq!(notification.queue(), JobQueue::LatencySensitive);
+        assert_eq!(notification.priority(), Priority::Critical);
+
+        let backup = JobType::Backup(BackupJob {
+            backup_id: Uuid::new_v4(),
+            schemas: vec![],
+            label: None,
+        });
+        assert_eq!(backup.queue(), JobQueue::LongRunning);
+        assert!(Priority::Critical > Priority::High && Priority::Normal > Priority::Low);
+    }
+
     #[test]
     fn test_job_duration() {
         let mut metadata = JobMetadata::new("test_job".to_string());
@@ -0,0 +1,627 @@
+//! Webhook delivery
+//!
+//! Tenants (organizations) register endpoints with a secret and a list of
+//! event filters. Domain events published on [`EVENTS_SUBJECT`] are matched
+//! against the tenant's active endpoints by [`WebhookDispatcher`], and each
+//! match becomes a [`WebhookDeliveryJob`]. [`WebhookDeliveryHandler`] posts
+//! the event signed with HMAC-SHA256, retries transient failures with
+//! exponential backoff and logs every attempt in `jobs.webhook_deliveries`.
+//!
+//! Receivers verify [`SIGNATURE_HEADER`] (`t=<unix seconds>,v1=<hex>`) by
+//! computing HMAC-SHA256 over `"{t}.{body}"` with their secret.
+
+use crate::handlers::{is_retryable_delivery, JobExecutionResult, JobHandler};
+use crate::types::{DeliveryAttempt, DeliveryStatus, JobType, WebhookDeliveryJob, WebhookEvent};
+use crate::{JobContext, JobError, JobResult};
+use async_trait::async_trait;
+use chrono::Utc;
+use emr_core::events::{EventEnvelope, EventError};
+use deadpool_diesel::postgres::Pool;
+use diesel::sql_types::{Array, Bool, Integer, Nullable, Text};
+use diesel::RunQueryDsl;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// NATS subject the API publishes domain events to, in
+/// [`EventEnvelope`]s
+pub const EVENTS_SUBJECT: &str = "webhooks.events";
+
+/// Header carrying the payload signature
+pub const SIGNATURE_HEADER: &str = "X-EMR-Signature";
+
+/// Header carrying the event type
+pub const EVENT_HEADER: &str = "X-EMR-Event";
+
+/// Header carrying the delivery id; redeliveries get a new one
+pub const DELIVERY_HEADER: &str = "X-EMR-Delivery";
+
+/// Default number of delivery attempts per event
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Default delay before the first retry; 5 attempts wait 10+20+40+80s,
+/// inside the default job timeout
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Active endpoints of a tenant
+pub const TENANT_ENDPOINTS_QUERY: &str =
+    "SELECT id, tenant_id, url, secret, events, active FROM emr.webhook_endpoints WHERE tenant_id = $1 AND active";
+
+/// An endpoint by id, active or not
+pub const ENDPOINT_QUERY: &str =
+    "SELECT id, tenant_id, url, secret, events, active FROM emr.webhook_endpoints WHERE id = $1";
+
+/// Record a delivery attempt, creating the delivery on the first one
+pub const RECORD_ATTEMPT_QUERY: &str = r#"
+INSERT INTO jobs.webhook_deliveries
+    (delivery_id, endpoint_id, event_id, event_type, event, status, attempts, last_status_code, last_error, attempt_log, created_at, updated_at)
+VALUES ($1, $2, $3, $4, $5::jsonb, $6, 1, $7, $8, jsonb_build_array($9::jsonb), NOW(), NOW())
+ON CONFLICT (delivery_id) DO UPDATE SET
+    status = EXCLUDED.status,
+    attempts = jobs.webhook_deliveries.attempts + 1,
+    last_status_code = EXCLUDED.last_status_code,
+    last_error = EXCLUDED.last_error,
+    attempt_log = jobs.webhook_deliveries.attempt_log || EXCLUDED.attempt_log,
+    updated_at = NOW()
+"#;
+
+/// A tenant's registered endpoint
+#[derive(Debug, Clone, diesel::QueryableByName)]
+pub struct WebhookEndpoint {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub tenant_id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub url: String,
+    #[diesel(sql_type = Text)]
+    pub secret: String,
+    /// Event filters: exact names, `prefix.*` or `*`
+    #[diesel(sql_type = Array<Text>)]
+    pub events: Vec<String>,
+    #[diesel(sql_type = Bool)]
+    pub active: bool,
+}
+
+impl WebhookEndpoint {
+    /// Check if the endpoint subscribed to an event type
+    pub fn accepts(&self, event_type: &str) -> bool {
+        self.active && self.events.iter().any(|filter| event_matches(filter, event_type))
+    }
+}
+
+/// Match an event type against a filter such as `encounter.*`
+pub fn event_matches(filter: &str, event_type: &str) -> bool {
+    match filter.strip_suffix('*') {
+        Some("") => true,
+        Some(prefix) => prefix.ends_with('.') && event_type.starts_with(prefix),
+        None => filter == event_type,
+    }
+}
+
+/// Read a domain event published on [`EVENTS_SUBJECT`]
+///
+/// Events published before envelopes are bare [`WebhookEvent`]s.
+pub fn read_event(payload: &[u8]) -> Result<WebhookEvent, EventError> {
+    let enveloped = serde_json::from_slice::<serde_json::Value>(payload)
+        .map_err(|e| EventError::Malformed(e.to_string()))?
+        .get("payload")
+        .is_some();
+    if !enveloped {
+        return serde_json::from_slice(payload).map_err(|e| EventError::Malformed(e.to_string()));
+    }
+
+    let envelope: EventEnvelope =
+        serde_json::from_slice(payload).map_err(|e| EventError::Malformed(e.to_string()))?;
+    let tenant_id = envelope
+        .tenant_id
+        .ok_or_else(|| EventError::Malformed(format!("{} event without a tenant", envelope.event_type)))?;
+    Ok(WebhookEvent {
+        id: envelope.id,
+        tenant_id,
+        event_type: envelope.event_type,
+        occurred_at: envelope.occurred_at,
+        data: envelope.payload,
+    })
+}
+
+/// `t=<timestamp>,v1=<hex HMAC-SHA256 of "{timestamp}.{body}">`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("t={},v1={}", timestamp, signature)
+}
+
+/// Name stored in `jobs.webhook_deliveries.status`
+fn status_name(status: &DeliveryStatus) -> &'static str {
+    match status {
+        DeliveryStatus::Pending => "pending",
+        DeliveryStatus::Delivered => "delivered",
+        DeliveryStatus::Failed => "failed",
+    }
+}
+
+/// Endpoint lookup and delivery logging
+#[async_trait]
+pub trait WebhookStore: Send + Sync {
+    /// Active endpoints of a tenant
+    async fn tenant_endpoints(&self, tenant_id: Uuid) -> JobResult<Vec<WebhookEndpoint>>;
+
+    /// An endpoint by id
+    async fn endpoint(&self, id: Uuid) -> JobResult<Option<WebhookEndpoint>>;
+
+    /// Log an attempt of a delivery and its resulting status
+    async fn record_attempt(
+        &self,
+        delivery_id: Uuid,
+        job: &WebhookDeliveryJob,
+        attempt: &DeliveryAttempt,
+        status: DeliveryStatus,
+    ) -> JobResult<()>;
+}
+
+/// Endpoints in `emr.webhook_endpoints`, deliveries in `jobs.webhook_deliveries`
+pub struct DatabaseWebhookStore {
+    pool: Pool,
+}
+
+impl DatabaseWebhookStore {
+    /// Create a store using the worker's job database pool
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> JobResult<deadpool_diesel::postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl WebhookStore for DatabaseWebhookStore {
+    async fn tenant_endpoints(&self, tenant_id: Uuid) -> JobResult<Vec<WebhookEndpoint>> {
+        let conn = self.connection().await?;
+
+        conn.interact(move |conn| {
+            diesel::sql_query(TENANT_ENDPOINTS_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(tenant_id)
+                .load::<WebhookEndpoint>(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))
+    }
+
+    async fn endpoint(&self, id: Uuid) -> JobResult<Option<WebhookEndpoint>> {
+        let conn = self.connection().await?;
+
+        let endpoints = conn
+            .interact(move |conn| {
+                diesel::sql_query(ENDPOINT_QUERY)
+                    .bind::<diesel::sql_types::Uuid, _>(id)
+                    .load::<WebhookEndpoint>(conn)
+            })
+            .await
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?
+            .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(endpoints.into_iter().next())
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery_id: Uuid,
+        job: &WebhookDeliveryJob,
+        attempt: &DeliveryAttempt,
+        status: DeliveryStatus,
+    ) -> JobResult<()> {
+        let conn = self.connection().await?;
+        let event = serde_json::to_string(&job.event).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let attempt_json = serde_json::to_string(attempt).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let status = status_name(&status);
+        let (endpoint_id, event_id, event_type) = (job.endpoint_id, job.event.id, job.event.event_type.clone());
+        let (status_code, error) = (attempt.status_code.map(i32::from), attempt.error.clone());
+
+        conn.interact(move |conn| {
+            diesel::sql_query(RECORD_ATTEMPT_QUERY)
+                .bind::<diesel::sql_types::Uuid, _>(delivery_id)
+                .bind::<diesel::sql_types::Uuid, _>(endpoint_id)
+                .bind::<diesel::sql_types::Uuid, _>(event_id)
+                .bind::<Text, _>(&event_type)
+                .bind::<Text, _>(&event)
+                .bind::<Text, _>(status)
+                .bind::<Nullable<Integer>, _>(status_code)
+                .bind::<Nullable<Text>, _>(&error)
+                .bind::<Text, _>(&attempt_json)
+                .execute(conn)
+        })
+        .await
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?
+        .map_err(|e| JobError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Turns domain events into webhook delivery jobs
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    store: Arc<dyn WebhookStore>,
+    max_attempts: u32,
+}
+
+impl WebhookDispatcher {
+    /// Create a dispatcher over a webhook store
+    pub fn new(store: Arc<dyn WebhookStore>) -> Self {
+        Self {
+            store,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Set the number of delivery attempts per event
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Build delivery jobs for the tenant's endpoints that accept the event
+    pub async fn dispatch(&self, event: &WebhookEvent) -> JobResult<Vec<JobType>> {
+        let endpoints = self.store.tenant_endpoints(event.tenant_id).await?;
+
+        let jobs: Vec<JobType> = endpoints
+            .iter()
+            .filter(|endpoint| endpoint.accepts(&event.event_type))
+            .map(|endpoint| {
+                JobType::WebhookDelivery(WebhookDeliveryJob {
+                    endpoint_id: endpoint.id,
+                    event: event.clone(),
+                    max_attempts: self.max_attempts,
+                })
+            })
+            .collect();
+
+        if !jobs.is_empty() {
+            info!(
+                event_id = %event.id,
+                event_type = %event.event_type,
+                endpoints = jobs.len(),
+                "Dispatching webhook deliveries"
+            );
+        }
+        Ok(jobs)
+    }
+}
+
+/// Signed webhook delivery handler
+pub struct WebhookDeliveryHandler {
+    store: Arc<dyn WebhookStore>,
+    client: reqwest::Client,
+    backoff_base: Duration,
+}
+
+impl WebhookDeliveryHandler {
+    /// Create a handler reading endpoints from a store
+    pub fn new(store: Arc<dyn WebhookStore>, client: reqwest::Client) -> Self {
+        Self {
+            store,
+            client,
+            backoff_base: DEFAULT_BACKOFF,
+        }
+    }
+
+    /// Set the delay before the first retry; later retries back off exponentially
+    pub fn with_backoff_base(mut self, backoff_base: Duration) -> Self {
+        self.backoff_base = backoff_base;
+        self
+    }
+
+    /// Perform a single signed POST, returning the response status
+    async fn deliver_once(
+        &self,
+        endpoint: &WebhookEndpoint,
+        job: &WebhookDeliveryJob,
+        delivery_id: Uuid,
+        body: &[u8],
+    ) -> Result<u16, (Option<u16>, String)> {
+        let signature = sign(&endpoint.secret, Utc::now().timestamp(), body);
+
+        let response = self
+            .client
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .header(EVENT_HEADER, job.event.event_type.as_str())
+            .header(DELIVERY_HEADER, delivery_id.to_string())
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| (None, e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(status.as_u16())
+        } else {
+            Err((Some(status.as_u16()), format!("Endpoint responded with {}", status)))
+        }
+    }
+
+    /// Attempt delivery until it succeeds, fails permanently or runs out of attempts
+    async fn deliver(
+        &self,
+        endpoint: &WebhookEndpoint,
+        job: &WebhookDeliveryJob,
+        context: &JobContext,
+    ) -> JobResult<JobExecutionResult> {
+        let delivery_id = context.job_id;
+        let body = serde_json::to_vec(&job.event).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let max_attempts = job.max_attempts.max(1);
+
+        for attempt in 1..=max_attempts {
+            context.check_cancelled()?;
+            let outcome = self.deliver_once(endpoint, job, delivery_id, &body).await;
+            let (status_code, error) = match &outcome {
+                Ok(code) => (Some(*code), None),
+                Err((code, error)) => (*code, Some(error.clone())),
+            };
+
+            let delivered = outcome.is_ok();
+            let give_up = !delivered && (attempt == max_attempts || !is_retryable_delivery(status_code));
+            let status = match (delivered, give_up) {
+                (true, _) => DeliveryStatus::Delivered,
+                (false, true) => DeliveryStatus::Failed,
+                (false, false) => DeliveryStatus::Pending,
+            };
+
+            let logged = DeliveryAttempt {
+                attempt,
+                attempted_at: Utc::now(),
+                status_code,
+                error: error.clone(),
+            };
+            if let Err(e) = self.store.record_attempt(delivery_id, job, &logged, status.clone()).await {
+                warn!(delivery_id = %delivery_id, error = %e, "Failed to log webhook delivery attempt");
+            }
+
+            if delivered {
+                let data = serde_json::json!({
+                    "delivery_id": delivery_id,
+                    "endpoint_id": endpoint.id,
+                    "event_id": job.event.id,
+                    "attempts": attempt,
+                });
+                return Ok(JobExecutionResult::success_with_data("Webhook delivered".to_string(), data)
+                    .with_metric("delivery_attempts".to_string(), attempt as f64));
+            }
+            if give_up {
+                // Not retried by the worker; the dead letter can be replayed
+                return Err(JobError::ProcessingError(format!(
+                    "Webhook delivery to {} failed after {} attempt(s): {}",
+                    endpoint.url,
+                    attempt,
+                    error.unwrap_or_default()
+                )));
+            }
+
+            warn!(
+                delivery_id = %delivery_id,
+                endpoint_id = %endpoint.id,
+                attempt,
+                error = ?error,
+                "Webhook delivery failed"
+            );
+            context.progress.warning(format!("Delivery attempt {} failed", attempt));
+            tokio::time::sleep(self.backoff_base * 2u32.saturating_pow(attempt - 1)).await;
+        }
+
+        unreachable!("the last attempt either delivers or gives up")
+    }
+}
+
+#[async_trait]
+impl JobHandler<WebhookDeliveryJob> for WebhookDeliveryHandler {
+    async fn execute(&self, job: WebhookDeliveryJob, context: JobContext) -> JobResult<JobExecutionResult> {
+        info!(
+            job_id = ?context.job_id,
+            endpoint_id = %job.endpoint_id,
+            event_type = %job.event.event_type,
+            "Starting webhook delivery job"
+        );
+
+        let endpoint = match self.store.endpoint(job.endpoint_id).await? {
+            Some(endpoint) if endpoint.active => endpoint,
+            _ => {
+                return Ok(JobExecutionResult::success(format!(
+                    "Webhook endpoint {} is inactive or removed; delivery skipped",
+                    job.endpoint_id
+                )))
+            }
+        };
+
+        // A retry after a timeout does not post an event that was delivered
+        context.once("deliver", || self.deliver(&endpoint, &job, &context)).await
+    }
+
+    fn name(&self) -> &'static str {
+        "webhook_delivery"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Endpoints and attempts kept in memory
+    #[derive(Default)]
+    struct MemoryWebhookStore {
+        endpoints: Vec<WebhookEndpoint>,
+        attempts: Mutex<Vec<(Uuid, DeliveryAttempt, DeliveryStatus)>>,
+    }
+
+    #[async_trait]
+    impl WebhookStore for MemoryWebhookStore {
+        async fn tenant_endpoints(&self, tenant_id: Uuid) -> JobResult<Vec<WebhookEndpoint>> {
+            Ok(self
+                .endpoints
+                .iter()
+                .filter(|endpoint| endpoint.tenant_id == tenant_id && endpoint.active)
+                .cloned()
+                .collect())
+        }
+
+        async fn endpoint(&self, id: Uuid) -> JobResult<Option<WebhookEndpoint>> {
+            Ok(self.endpoints.iter().find(|endpoint| endpoint.id == id).cloned())
+        }
+
+        async fn record_attempt(
+            &self,
+            delivery_id: Uuid,
+            _job: &WebhookDeliveryJob,
+            attempt: &DeliveryAttempt,
+            status: DeliveryStatus,
+        ) -> JobResult<()> {
+            self.attempts.lock().unwrap().push((delivery_id, attempt.clone(), status));
+            Ok(())
+        }
+    }
+
+    fn endpoint(tenant_id: Uuid, url: &str, events: &[&str]) -> WebhookEndpoint {
+        WebhookEndpoint {
+            id: Uuid::new_v4(),
+            tenant_id,
+            url: url.to_string(),
+            secret: "whsec_test".to_string(),
+            events: events.iter().map(|event| event.to_string()).collect(),
+            active: true,
+        }
+    }
+
+    fn event(tenant_id: Uuid, event_type: &str) -> WebhookEvent {
+        WebhookEvent {
+            id: Uuid::new_v4(),
+            tenant_id,
+            event_type: event_type.to_string(),
+            occurred_at: Utc::now(),
+            data: json!({"id": "e1"}),
+        }
+    }
+
+    #[test]
+    fn test_event_matches() {
+        assert!(event_matches("*", "encounter.started"));
+        assert!(event_matches("encounter.*", "encounter.started"));
+        assert!(event_matches("encounter.started", "encounter.started"));
+        assert!(!event_matches("encounter.*", "encounters.started"));
+        assert!(!event_matches("encounter*", "encounter.started"));
+        assert!(!event_matches("patient.created", "encounter.started"));
+    }
+
+    #[test]
+    fn test_sign() {
+        let signature = sign("whsec_test", 1_700_000_000, br#"{"event_type":"encounter.started"}"#);
+        assert_eq!(
+            signature,
+            "t=1700000000,v1=1d5aa9dabc846c6de64e38cb9bc16af50df7335e7d9df859752f8277f468da90"
+        );
+    }
+
+    #[test]
+    fn test_read_event() {
+        let tenant = Uuid::new_v4();
+        let envelope = EventEnvelope::with_type("encounter.started", 1, json!({"id": "e1"})).with_tenant(tenant);
+        let read = read_event(&envelope.encode().unwrap()).unwrap();
+        assert_eq!((read.id, read.tenant_id), (envelope.id, tenant));
+        assert_eq!((read.event_type.as_str(), read.data), ("encounter.started", json!({"id": "e1"})));
+
+        // Published before envelopes
+        let legacy = event(tenant, "encounter.finished");
+        let read = read_event(&serde_json::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!((read.id, read.event_type), (legacy.id, legacy.event_type));
+
+        let untenanted = EventEnvelope::with_type("encounter.started", 1, json!({}));
+        assert!(read_event(&untenanted.encode().unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_filters_endpoints() {
+        let tenant = Uuid::new_v4();
+        let store = MemoryWebhookStore {
+            endpoints: vec![
+                endpoint(tenant, "https://a.example/hook", &["encounter.*"]),
+                endpoint(tenant, "https://b.example/hook", &["patient.created"]),
+                endpoint(Uuid::new_v4(), "https://c.example/hook", &["*"]),
+            ],
+            ..Default::default()
+        };
+        let wanted = store.endpoints[0].id;
+
+        let jobs = WebhookDispatcher::new(Arc::new(store))
+            .dispatch(&event(tenant, "encounter.started"))
+            .await
+            .unwrap();
+
+        assert_eq!(jobs.len(), 1);
+        match &jobs[0] {
+            JobType::WebhookDelivery(job) => {
+                assert_eq!(job.endpoint_id, wanted);
+                assert_eq!(job.max_attempts, DEFAULT_MAX_ATTEMPTS);
+            }
+            other => panic!("unexpected job: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_logged_and_dead_lettered() {
+        let tenant = Uuid::new_v4();
+        let target = endpoint(tenant, "http://127.0.0.1:1/hook", &["*"]);
+        let store = Arc::new(MemoryWebhookStore {
+            endpoints: vec![target.clone()],
+            ..Default::default()
+        });
+        let handler = WebhookDeliveryHandler::new(store.clone(), reqwest::Client::new())
+            .with_backoff_base(Duration::from_millis(1));
+        let job = WebhookDeliveryJob {
+            endpoint_id: target.id,
+            event: event(tenant, "encounter.started"),
+            max_attempts: 3,
+        };
+
+        let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await;
+        assert!(matches!(&result, Err(error) if !error.is_retryable()));
+
+        let attempts = store.attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(attempts[1].2, DeliveryStatus::Pending);
+        assert_eq!(attempts[2].2, DeliveryStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_removed_endpoint_is_skipped() {
+        let handler = WebhookDeliveryHandler::new(Arc::new(MemoryWebhookStore::default()), reqwest::Client::new());
+        let job = WebhookDeliveryJob {
+            endpoint_id: Uuid::new_v4(),
+            event: event(Uuid::new_v4(), "encounter.started"),
+            max_attempts: 3,
+        };
+
+        let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+        assert!(result.success);
+        assert!(result.message.contains("skipped"));
+    }
+}
@@ -1,43 +1,384 @@
 //! Job worker implementation using Apalis
 
 use crate::{
+    acknowledgments::{self, AcknowledgmentStore, DatabaseAcknowledgmentStore},
+    claims::{ClaimStore, ClaimsExportHandler, DatabaseClaimStore},
     config::JobsConfig,
+    dead_letter::{DatabaseDeadLetterStore, DeadLetter, DeadLetterStore},
+    disclosures::{DatabaseDisclosureStore, DisclosureReportHandler, DisclosureStore},
     handlers::*,
+    history::{DatabaseJobHistoryStore, JobHistoryStore, JobRun, JobRunStatus},
+    idempotency::{Claim, DatabaseIdempotencyStore, IdempotencyStore, StepResults},
+    ingestion::IngestionWatcher,
+    key_rotation::{DataKeyStore, DatabaseDataKeyStore, KeyRotationHandler},
+    measures::{DatabaseMeasureStore, MeasureStore, QualityMeasureHandler},
+    notifications::{
+        self, DatabaseNotificationInbox, DatabaseNotificationPreferenceStore, NotificationInbox,
+        NotificationPreferenceStore,
+    },
+    observations::{self, DatabaseDeviceObservationStore, DeviceObservationStore},
+    ocr::{self, DatabaseDocumentTextStore, DocumentOcrHandler, DocumentTextStore},
+    panels::{self, DatabasePanelStore, PanelRefreshHandler, PanelStore},
+    partitions::{self, DatabasePartitionStore, PartitionMaintenanceHandler, PartitionStore},
+    progress::{events_subject, ProgressReporter},
+    provenance::{DatabaseProvenanceStore, ProvenanceStore},
+    queue::{JobQueues, ReadyJob},
+    remittance::{DatabaseRemittanceStore, RemittancePostingHandler, RemittanceStore},
+    reports::{self, DatabaseReportScheduleStore, ReportScheduleStore, ScheduledReportHandler},
+    retention::DatabaseRetentionStore,
+    security::{self, DatabaseSecurityEventStore, SecurityEventReportHandler, SecurityEventStore},
+    sync::FhirSyncHandler,
     types::*,
+    validation::{DatabaseValidationEntityStore, ProfileValidationHandler, ValidationEntityStore},
+    webhooks::{self, DatabaseWebhookStore, WebhookDeliveryHandler, WebhookDispatcher, WebhookStore},
     JobContext,
     JobError,
     JobMonitor,
     JobResult,
 };
 use anyhow::Result;
-use apalis::prelude::*;
 use chrono::Utc;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use emr_core::events::EventEnvelope;
+use emr_core::retention::RetentionPolicySet;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast::error::RecvError, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// NATS subject the API publishes jobs to for immediate execution
+pub const SUBMIT_SUBJECT: &str = "jobs.submit";
+
+/// NATS subject the API publishes [`JobCancellation`] requests to
+pub const CANCEL_SUBJECT: &str = "jobs.cancel";
+
+/// What became of a submitted job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submitted {
+    Queued(Uuid),
+    /// Its idempotency key was already used by this job
+    Duplicate(Uuid),
+}
+
+/// Where a job is
+#[derive(Debug, Clone)]
+pub enum JobState {
+    /// Waiting for a worker, including a retry backing off
+    Queued,
+    Running,
+    Finished(JobRun),
+}
+
 /// Jobs worker that manages background job processing
+///
+/// Jobs are queued by type and priority (see [`JobQueues`]) and run
+/// concurrently up to each queue's worker limit. Every queued or running job
+/// has a cancellation token; jobs that exceed `WorkerConfig::job_timeout`
+/// are stopped and fail with a timeout error. Retryable failures are queued
+/// again with backoff up to `max_retries` times; jobs that still fail go to
+/// the dead-letter store. Finished runs are recorded in the job history
+/// alongside the in-memory [`JobMonitor`]. Submissions with an idempotency
+/// key are queued once per key, and handler steps run through
+/// [`JobContext::once`] are not repeated by retries. With ingestion enabled,
+/// files dropped in the watched inboxes are imported as they settle.
+/// Notifications held for quiet hours or the daily digest are released on
+/// each poll once due, critical results left unacknowledged past their
+/// SLA are escalated, nightly patient panels are queued for refresh once
+/// a day from `panels.nightly_hour`, report schedules are queued as they
+/// come due, and sign-ins and record access are checked for security events.
+/// Batches of device observations published over NATS are stored as they
+/// arrive, up to `device_ingest.max_concurrent` at once.
 pub struct JobsWorker {
     config: JobsConfig,
     monitor: Arc<RwLock<JobMonitor>>,
+    queues: Mutex<JobQueues>,
+    cancellations: Mutex<HashMap<Uuid, CancellationToken>>,
+    dead_letters: Arc<dyn DeadLetterStore>,
+    history: Arc<dyn JobHistoryStore>,
+    idempotency: Arc<dyn IdempotencyStore>,
     data_validation_handler: DataValidationHandler,
+    profile_validation_handler: ProfileValidationHandler,
+    notification_preferences: Arc<dyn NotificationPreferenceStore>,
     notification_handler: NotificationHandler,
+    acknowledgments: Arc<dyn AcknowledgmentStore>,
+    cleanup_handler: DataCleanupHandler,
+    backup_handler: BackupHandler,
+    claims_handler: ClaimsExportHandler,
+    remittance_handler: RemittancePostingHandler,
+    ocr_handler: DocumentOcrHandler,
+    panels: Arc<dyn PanelStore>,
+    panel_handler: PanelRefreshHandler,
+    partitions: Arc<dyn PartitionStore>,
+    partition_handler: PartitionMaintenanceHandler,
+    measure_handler: QualityMeasureHandler,
+    report_schedules: Arc<dyn ReportScheduleStore>,
+    report_handler: ScheduledReportHandler,
+    disclosure_handler: DisclosureReportHandler,
+    key_rotation_handler: KeyRotationHandler,
+    security_events: Arc<dyn SecurityEventStore>,
+    security_event_handler: SecurityEventReportHandler,
+    webhook_dispatcher: WebhookDispatcher,
+    webhook_handler: WebhookDeliveryHandler,
+    sync_handler: FhirSyncHandler,
+    device_observations: Arc<dyn DeviceObservationStore>,
+    device_batch_permits: Arc<Semaphore>,
+    ingestion: Option<IngestionWatcher>,
+    nats: Option<async_nats::Client>,
 }
 
 impl JobsWorker {
     /// Create a new jobs worker
     pub fn new(config: JobsConfig) -> Self {
+        // Policies are checked by `JobsConfig::validate` before the worker starts
+        let retention = RetentionPolicySet::from_config(&config.retention).unwrap_or_default();
+        let backup_handler = BackupHandler::new(config.backup.clone(), config.database.url.clone());
+
+        let queues = Mutex::new(JobQueues::new(&config.worker));
+        // Building a pool without timeouts cannot fail; connections are
+        // only opened when a run is recorded
+        let pool = config.database.pool().expect("job database pool configuration");
+        let retention_store = Arc::new(DatabaseRetentionStore::new(pool.clone()));
+        let dead_letters = Arc::new(DatabaseDeadLetterStore::new(pool.clone()));
+        let history = Arc::new(DatabaseJobHistoryStore::new(pool.clone()));
+        let idempotency = Arc::new(DatabaseIdempotencyStore::new(pool.clone()));
+        let validation_entities = Arc::new(DatabaseValidationEntityStore::new(pool.clone()));
+        let notification_preferences: Arc<dyn NotificationPreferenceStore> =
+            Arc::new(DatabaseNotificationPreferenceStore::new(pool.clone()));
+        let notification_inbox = Arc::new(DatabaseNotificationInbox::new(pool.clone()));
+        let acknowledgments = Arc::new(DatabaseAcknowledgmentStore::new(pool.clone()));
+        let claims_handler =
+            ClaimsExportHandler::new(config.billing.clone(), Arc::new(DatabaseClaimStore::new(pool.clone())));
+        let remittance_handler = RemittancePostingHandler::new(
+            config.billing.clone(),
+            Arc::new(DatabaseRemittanceStore::new(pool.clone())),
+        );
+        let ocr_handler = DocumentOcrHandler::new(
+            config.ocr.clone(),
+            Arc::new(DatabaseDocumentTextStore::new(pool.clone())),
+            ocr::text_extractor(&config.ocr),
+        );
+        let panels: Arc<dyn PanelStore> = Arc::new(DatabasePanelStore::new(pool.clone()));
+        let partitions: Arc<dyn PartitionStore> = Arc::new(DatabasePartitionStore::new(pool.clone()));
+        let partition_handler =
+            PartitionMaintenanceHandler::new(config.partitions.clone(), retention.clone(), partitions.clone());
+        let measures: Arc<dyn MeasureStore> = Arc::new(DatabaseMeasureStore::new(pool.clone()));
+        let report_schedules: Arc<dyn ReportScheduleStore> = Arc::new(DatabaseReportScheduleStore::new(pool.clone()));
+        let disclosures: Arc<dyn DisclosureStore> = Arc::new(DatabaseDisclosureStore::new(pool.clone()));
+        let disclosure_handler = DisclosureReportHandler::new(config.reports.clone(), disclosures);
+        let key_rotation_handler = KeyRotationHandler::new(
+            config.encryption.clone(),
+            config.reports.clone(),
+            Arc::new(DatabaseDataKeyStore::new(pool.clone())),
+        );
+        let security_events: Arc<dyn SecurityEventStore> = Arc::new(DatabaseSecurityEventStore::new(pool.clone()));
+        let device_observations: Arc<dyn DeviceObservationStore> =
+            Arc::new(DatabaseDeviceObservationStore::new(pool.clone()));
+        let device_batch_permits = Arc::new(Semaphore::new(config.device_ingest.max_concurrent));
+        let provenance: Arc<dyn ProvenanceStore> = Arc::new(DatabaseProvenanceStore::new(pool.clone()));
+        let webhooks: Arc<dyn WebhookStore> = Arc::new(DatabaseWebhookStore::new(pool));
+        let ingestion = if config.ingestion.enabled {
+            IngestionWatcher::from_config(&config.ingestion)
+                .map_err(|e| error!(error = %e, "Ingestion disabled: invalid source configuration"))
+                .ok()
+        } else {
+            None
+        };
+
+        let reports = config.reports.clone();
         Self {
             config,
             monitor: Arc::new(RwLock::new(JobMonitor::new())),
+            queues,
+            cancellations: Mutex::new(HashMap::new()),
+            dead_letters,
+            history,
+            idempotency,
             data_validation_handler: DataValidationHandler,
-            notification_handler: NotificationHandler,
+            profile_validation_handler: ProfileValidationHandler::new(validation_entities),
+            notification_handler: NotificationHandler::new()
+                .with_preferences(notification_preferences.clone())
+                .with_inbox(notification_inbox),
+            notification_preferences,
+            acknowledgments,
+            cleanup_handler: DataCleanupHandler::new(retention, retention_store),
+            backup_handler,
+            claims_handler,
+            remittance_handler,
+            ocr_handler,
+            panel_handler: PanelRefreshHandler::new(panels.clone()),
+            panels,
+            partitions,
+            partition_handler,
+            measure_handler: QualityMeasureHandler::new(measures),
+            report_handler: ScheduledReportHandler::new(reports.clone(), report_schedules.clone()),
+            report_schedules,
+            disclosure_handler,
+            key_rotation_handler,
+            security_event_handler: SecurityEventReportHandler::new(reports, security_events.clone()),
+            security_events,
+            webhook_dispatcher: WebhookDispatcher::new(webhooks.clone()),
+            webhook_handler: WebhookDeliveryHandler::new(webhooks, reqwest::Client::new()),
+            sync_handler: FhirSyncHandler::new(provenance),
+            device_observations,
+            device_batch_permits,
+            ingestion,
+            nats: None,
         }
     }
 
+    /// Keep dead-lettered jobs in another store
+    pub fn with_dead_letters(mut self, store: Arc<dyn DeadLetterStore>) -> Self {
+        self.dead_letters = store;
+        self
+    }
+
+    /// Keep job run history in another store
+    pub fn with_history(mut self, store: Arc<dyn JobHistoryStore>) -> Self {
+        self.history = store;
+        self
+    }
+
+    /// Keep idempotency keys and step results in another store
+    pub fn with_idempotency(mut self, store: Arc<dyn IdempotencyStore>) -> Self {
+        self.idempotency = store;
+        self
+    }
+
+    /// Read notification preferences from and hold notifications in another
+    /// store
+    pub fn with_notification_preferences(mut self, store: Arc<dyn NotificationPreferenceStore>) -> Self {
+        self.notification_handler = std::mem::take(&mut self.notification_handler).with_preferences(store.clone());
+        self.notification_preferences = store;
+        self
+    }
+
+    /// Deliver in-app notifications to another inbox
+    pub fn with_notification_inbox(mut self, inbox: Arc<dyn NotificationInbox>) -> Self {
+        self.notification_handler = std::mem::take(&mut self.notification_handler).with_inbox(inbox);
+        self
+    }
+
+    /// Escalate unacknowledged results from another store
+    pub fn with_acknowledgments(mut self, store: Arc<dyn AcknowledgmentStore>) -> Self {
+        self.acknowledgments = store;
+        self
+    }
+
+    /// Read webhook endpoints from and log deliveries to another store
+    pub fn with_webhooks(mut self, store: Arc<dyn WebhookStore>) -> Self {
+        self.webhook_dispatcher = WebhookDispatcher::new(store.clone());
+        self.webhook_handler = WebhookDeliveryHandler::new(store, reqwest::Client::new());
+        self
+    }
+
+    /// Read billable encounters from another store
+    pub fn with_claims(mut self, store: Arc<dyn ClaimStore>) -> Self {
+        self.claims_handler = ClaimsExportHandler::new(self.config.billing.clone(), store);
+        self
+    }
+
+    /// Post remittances to another store
+    pub fn with_remittances(mut self, store: Arc<dyn RemittanceStore>) -> Self {
+        self.remittance_handler = RemittancePostingHandler::new(self.config.billing.clone(), store);
+        self
+    }
+
+    /// Read documents from and store their text in another store
+    pub fn with_document_texts(mut self, store: Arc<dyn DocumentTextStore>) -> Self {
+        self.ocr_handler =
+            DocumentOcrHandler::new(self.config.ocr.clone(), store, ocr::text_extractor(&self.config.ocr));
+        self
+    }
+
+    /// Refresh saved patient panels in another store
+    pub fn with_panels(mut self, store: Arc<dyn PanelStore>) -> Self {
+        self.panel_handler = PanelRefreshHandler::new(store.clone());
+        self.panels = store;
+        self
+    }
+
+    /// Maintain the partitions of another store
+    pub fn with_partitions(mut self, store: Arc<dyn PartitionStore>) -> Self {
+        self.partition_handler = PartitionMaintenanceHandler::new(
+            self.config.partitions.clone(),
+            RetentionPolicySet::from_config(&self.config.retention).unwrap_or_default(),
+            store.clone(),
+        );
+        self.partitions = store;
+        self
+    }
+
+    /// Compute quality measures in another store
+    pub fn with_measures(mut self, store: Arc<dyn MeasureStore>) -> Self {
+        self.measure_handler = QualityMeasureHandler::new(store);
+        self
+    }
+
+    /// Run report schedules from another store
+    pub fn with_reports(mut self, store: Arc<dyn ReportScheduleStore>) -> Self {
+        self.report_handler = ScheduledReportHandler::new(self.config.reports.clone(), store.clone());
+        self.report_schedules = store;
+        self
+    }
+
+    /// Record the provenance of synchronized resources in another store
+    pub fn with_provenance(mut self, store: Arc<dyn ProvenanceStore>) -> Self {
+        self.sync_handler = FhirSyncHandler::new(store);
+        self
+    }
+
+    /// Report accesses to patients' data from another store
+    pub fn with_disclosures(mut self, store: Arc<dyn DisclosureStore>) -> Self {
+        self.disclosure_handler = DisclosureReportHandler::new(self.config.reports.clone(), store);
+        self
+    }
+
+    /// Detect and report security events from another store
+    pub fn with_security_events(mut self, store: Arc<dyn SecurityEventStore>) -> Self {
+        self.security_event_handler = SecurityEventReportHandler::new(self.config.reports.clone(), store.clone());
+        self.security_events = store;
+        self
+    }
+
+    /// Rotate data-encryption keys in another store
+    pub fn with_data_keys(mut self, store: Arc<dyn DataKeyStore>) -> Self {
+        self.key_rotation_handler =
+            KeyRotationHandler::new(self.config.encryption.clone(), self.config.reports.clone(), store);
+        self
+    }
+
+    /// Read entities for profile validation from another store
+    pub fn with_validation_entities(mut self, store: Arc<dyn ValidationEntityStore>) -> Self {
+        self.profile_validation_handler = ProfileValidationHandler::new(store);
+        self
+    }
+
+    /// Store device observations published over NATS in another store
+    pub fn with_device_observations(mut self, store: Arc<dyn DeviceObservationStore>) -> Self {
+        self.device_observations = store;
+        self
+    }
+
+    /// Watch inboxes with another watcher
+    pub fn with_ingestion(mut self, watcher: IngestionWatcher) -> Self {
+        self.ingestion = Some(watcher);
+        self
+    }
+
+    /// Publish job progress events and in-app notifications to NATS
+    pub fn with_nats(mut self, client: async_nats::Client) -> Self {
+        self.notification_handler = std::mem::take(&mut self.notification_handler).with_nats(client.clone());
+        self.nats = Some(client);
+        self
+    }
+
     /// Start the worker
-    pub async fn start(self) -> Result<()> {
+    pub async fn start(&self) -> Result<()> {
         info!("Starting jobs worker");
 
         // TODO: Set up Apalis workers here
@@ -46,17 +387,171 @@ impl JobsWorker {
         let worker_config = &self.config.worker;
         info!(
             max_workers = worker_config.max_workers,
+            latency_sensitive_workers = worker_config.queues.latency_sensitive,
+            long_running_workers = worker_config.queues.long_running,
             max_retries = worker_config.max_retries,
             "Jobs worker configuration loaded"
         );
 
-        // Simulate worker running
+        // Jobs submitted by the API over NATS run as soon as they arrive
+        let mut submissions = match &self.nats {
+            Some(nats) => Some(nats.subscribe(SUBMIT_SUBJECT).await?),
+            None => None,
+        };
+        let mut cancellations = match &self.nats {
+            Some(nats) => Some(nats.subscribe(CANCEL_SUBJECT).await?),
+            None => None,
+        };
+        let mut webhook_events = match &self.nats {
+            Some(nats) => Some(nats.subscribe(webhooks::EVENTS_SUBJECT).await?),
+            None => None,
+        };
+        // Each device batch goes to one worker of the queue group
+        let mut device_batches = match &self.nats {
+            Some(nats) => {
+                let group = observations::OBSERVATIONS_QUEUE_GROUP.to_string();
+                Some(nats.queue_subscribe(observations::OBSERVATIONS_SUBJECT, group).await?)
+            }
+            None => None,
+        };
+
+        // Imports interrupted by a restart start over from staging
+        if let Some(watcher) = &self.ingestion {
+            for job in watcher.recover().await {
+                self.enqueue(job);
+            }
+        }
+
+        let mut poll = tokio::time::interval(tokio::time::Duration::from_secs(worker_config.poll_interval));
+        let mut ingestion_poll =
+            tokio::time::interval(tokio::time::Duration::from_secs(self.config.ingestion.poll_interval.max(1)));
+        let mut running = FuturesUnordered::new();
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(worker_config.poll_interval)).await;
-            
-            // Check for pending jobs
-            self.process_pending_jobs().await?;
+            self.dispatch(&mut running);
+
+            tokio::select! {
+                _ = poll.tick() => {
+                    // Check for pending jobs
+                    self.process_pending_jobs().await?;
+                    self.release_held_notifications().await;
+                    self.escalate_unacknowledged_results().await;
+                    self.refresh_nightly_panels().await;
+                    self.queue_partition_maintenance().await;
+                    self.queue_scheduled_reports().await;
+                    self.detect_security_events().await;
+                }
+                _ = ingestion_poll.tick(), if self.ingestion.is_some() => {
+                    self.process_ingestion().await;
+                }
+                Some(message) = next_submission(&mut submissions) => {
+                    self.process_submitted_job(&message.payload).await;
+                }
+                Some(message) = next_submission(&mut cancellations) => {
+                    self.process_cancellation(&message.payload);
+                }
+                Some(message) = next_submission(&mut webhook_events) => {
+                    self.process_webhook_event(&message.payload).await;
+                }
+                Some((permit, message)) = next_device_batch(&self.device_batch_permits, &mut device_batches) => {
+                    self.process_device_batch(permit, message);
+                }
+                Some(queue) = running.next(), if !running.is_empty() => {
+                    self.lock_queues().finished(queue);
+                }
+            }
+        }
+    }
+
+    /// Queue a job for execution, returning its id
+    pub fn enqueue(&self, job: JobType) -> Uuid {
+        let job_id = Uuid::new_v4();
+        self.enqueue_with_id(job_id, job);
+        job_id
+    }
+
+    /// Queue a job under an id chosen by the submitter
+    pub fn enqueue_with_id(&self, job_id: Uuid, job: JobType) {
+        let priority = job.priority();
+        self.lock_cancellations().insert(job_id, CancellationToken::new());
+        let queue = self.lock_queues().push(job_id, job);
+        info!(job_id = ?job_id, queue = queue.as_str(), ?priority, "Job queued");
+    }
+
+    /// Queue a submitted job
+    ///
+    /// A submission whose idempotency key is already claimed is dropped. If
+    /// the key cannot be checked the job is queued anyway; steps run through
+    /// [`JobContext::once`] still guard its side effects.
+    pub async fn submit(&self, submission: JobSubmission) -> Submitted {
+        let job_id = submission.job_id.unwrap_or_else(Uuid::new_v4);
+
+        if let Some(key) = &submission.idempotency_key {
+            let ttl = self.config.worker.idempotency_ttl;
+            match self.idempotency.claim(key, job_id, ttl).await {
+                Ok(Claim::Acquired) => {}
+                Ok(Claim::Duplicate { job_id: existing }) => {
+                    info!(job_id = ?job_id, existing_job_id = ?existing, idempotency_key = %key, "Ignoring duplicate job submission");
+                    return Submitted::Duplicate(existing);
+                }
+                Err(e) => {
+                    warn!(job_id = ?job_id, idempotency_key = %key, error = %e, "Failed to check idempotency key");
+                }
+            }
+        }
+
+        self.enqueue_with_id(job_id, submission.job);
+        Submitted::Queued(job_id)
+    }
+
+    /// Where a job is: queued or running on this worker, or finished as
+    /// recorded in the job history. `None` when neither knows the job.
+    pub async fn status(&self, job_id: Uuid) -> JobResult<Option<JobState>> {
+        if self.lock_queues().is_waiting(job_id) {
+            return Ok(Some(JobState::Queued));
+        }
+        if self.lock_cancellations().contains_key(&job_id) {
+            return Ok(Some(JobState::Running));
         }
+        Ok(self.history.find(job_id).await?.map(JobState::Finished))
+    }
+
+    /// Cancel a queued or running job. Queued jobs are dropped when they
+    /// reach a worker; running jobs stop at their next cancellation check.
+    /// Returns false if the job is unknown or already finished.
+    pub fn cancel(&self, job_id: Uuid) -> bool {
+        match self.lock_cancellations().get(&job_id) {
+            Some(token) => {
+                token.cancel();
+                info!(job_id = ?job_id, "Job cancellation requested");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Start queued jobs while their queues have free workers
+    fn dispatch<'a>(&'a self, running: &mut FuturesUnordered<BoxFuture<'a, JobQueue>>) {
+        loop {
+            let Some(ready) = self.lock_queues().next_ready() else {
+                break;
+            };
+            let queue = ready.queue;
+            running.push(
+                async move {
+                    self.run_job(ready).await;
+                    queue
+                }
+                .boxed(),
+            );
+        }
+    }
+
+    fn lock_queues(&self) -> std::sync::MutexGuard<'_, JobQueues> {
+        self.queues.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_cancellations(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, CancellationToken>> {
+        self.cancellations.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
     /// Process pending jobs
@@ -76,13 +571,6 @@ impl JobsWorker {
 
     /// Process a sample job for demonstration
     async fn process_sample_job(&self) -> Result<()> {
-        let job_id = Uuid::new_v4();
-        let context = JobContext::new(job_id);
-        
-        info!(job_id = ?job_id, "Processing sample job");
-        
-        let start_time = std::time::Instant::now();
-        
         // Create a sample validation job
         let validation_job = DataValidationJob {
             patient_id: Some(Uuid::new_v4()),
@@ -97,12 +585,263 @@ impl JobsWorker {
                 },
             ],
             auto_fix: false,
+            profile: None,
         };
 
-        // Execute the job
-        let result = self.data_validation_handler.execute(validation_job, context).await;
-        
+        self.enqueue(JobType::DataValidation(validation_job));
+        Ok(())
+    }
+
+    /// Queue a job submitted on [`SUBMIT_SUBJECT`]
+    async fn process_submitted_job(&self, payload: &[u8]) {
+        match serde_json::from_slice::<JobSubmission>(payload) {
+            Ok(submission) => {
+                self.submit(submission).await;
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed job submission"),
+        }
+    }
+
+    /// Queue deliveries of a domain event published on
+    /// [`webhooks::EVENTS_SUBJECT`] to the tenant's matching endpoints
+    async fn process_webhook_event(&self, payload: &[u8]) {
+        let event = match webhooks::read_event(payload) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!(error = %e, "Ignoring malformed webhook event");
+                return;
+            }
+        };
+
+        match self.webhook_dispatcher.dispatch(&event).await {
+            Ok(jobs) => {
+                for job in jobs {
+                    self.enqueue(job);
+                }
+            }
+            Err(e) => error!(event_id = %event.id, error = %e, "Failed to dispatch webhook event"),
+        }
+    }
+
+    /// Store a batch of device observations in the background, answering
+    /// the publisher when it asked for a reply
+    fn process_device_batch(&self, permit: OwnedSemaphorePermit, message: async_nats::Message) {
+        let store = self.device_observations.clone();
+        let limits = self.config.device_ingest.clone();
+        let nats = self.nats.clone();
+
+        tokio::spawn(async move {
+            let result = observations::ingest(store.as_ref(), &limits, &message.payload, Utc::now()).await;
+            match &result {
+                Ok(result) => info!(
+                    received = result.received,
+                    inserted = result.inserted,
+                    duplicates = result.duplicates,
+                    rejected = result.errors.len(),
+                    "Stored device observations"
+                ),
+                Err(e) => warn!(error = %e, "Failed to store device observations"),
+            }
+            if let (Some(nats), Some(reply)) = (nats, message.reply) {
+                if let Err(e) = nats.publish(reply, observations::reply_body(&result).into()).await {
+                    warn!(error = %e, "Failed to answer device observation batch");
+                }
+            }
+            drop(permit);
+        });
+    }
+
+    /// Queue held notifications whose release time has come
+    async fn release_held_notifications(&self) {
+        match self
+            .notification_preferences
+            .release_due(Utc::now(), notifications::RELEASE_BATCH_SIZE)
+            .await
+        {
+            Ok(released) => {
+                for job in notifications::released_jobs(released) {
+                    self.enqueue(JobType::Notification(job));
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to release held notifications"),
+        }
+    }
+
+    /// Queue escalations for critical results unacknowledged past the SLA
+    async fn escalate_unacknowledged_results(&self) {
+        let config = &self.config.acknowledgments;
+        let now = Utc::now();
+        match self
+            .acknowledgments
+            .escalate_overdue(now, config.critical_sla_minutes, acknowledgments::ESCALATION_BATCH_SIZE)
+            .await
+        {
+            Ok(overdue) => {
+                for result in &overdue {
+                    warn!(
+                        task_id = %result.task_id,
+                        owner_id = %result.owner_id,
+                        escalation = result.escalation_count,
+                        "Escalating unacknowledged critical result"
+                    );
+                }
+                for job in acknowledgments::escalation_jobs(&overdue, now, config.escalation_recipient_id) {
+                    self.enqueue(JobType::Notification(job));
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to escalate unacknowledged results"),
+        }
+    }
+
+    /// Check for security events and queue alerts to the security admins
+    /// for them and for those the API recorded
+    async fn detect_security_events(&self) {
+        let config = &self.config.security;
+        match security::detect(self.security_events.as_ref(), &config.rules, Utc::now()).await {
+            Ok(events) => {
+                for event in &events {
+                    warn!(kind = %event.kind, subject = %event.subject, "{}", event.description);
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to detect security events"),
+        }
+        match self.security_events.claim_alerts(security::ALERT_BATCH_SIZE).await {
+            Ok(events) => {
+                for job in security::alert_jobs(&events, &config.admin_recipient_ids) {
+                    self.enqueue(JobType::Notification(job));
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to alert security events"),
+        }
+    }
+
+    /// Queue refreshes for nightly panels not refreshed since tonight's hour
+    async fn refresh_nightly_panels(&self) {
+        let due_since = panels::nightly_due_since(Utc::now(), self.config.panels.nightly_hour);
+        match self.panels.claim_due(due_since, panels::CLAIM_BATCH_SIZE).await {
+            Ok(panel_ids) => {
+                for panel_id in panel_ids {
+                    self.enqueue(JobType::PanelRefresh(PanelRefreshJob { panel_id }));
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to claim nightly panels"),
+        }
+    }
+
+    /// Queue the daily partition maintenance unless a worker already has
+    /// since today's hour
+    async fn queue_partition_maintenance(&self) {
+        let due_since = panels::nightly_due_since(Utc::now(), self.config.partitions.maintenance_hour);
+        match self.partitions.claim_maintenance(due_since).await {
+            Ok(true) => {
+                for job in partitions::maintenance_jobs() {
+                    self.enqueue(JobType::PartitionMaintenance(job));
+                }
+            }
+            Ok(false) => {}
+            Err(e) => warn!(error = %e, "Failed to claim partition maintenance"),
+        }
+    }
+
+    /// Queue the report schedules that have come due
+    async fn queue_scheduled_reports(&self) {
+        match self.report_schedules.claim_due(Utc::now(), reports::CLAIM_BATCH_SIZE).await {
+            Ok(due) => {
+                for (schedule_id, scheduled_for) in due {
+                    self.enqueue(JobType::ScheduledReport(ScheduledReportJob {
+                        schedule_id,
+                        scheduled_for,
+                    }));
+                }
+            }
+            Err(e) => warn!(error = %e, "Failed to claim due report schedules"),
+        }
+    }
+
+    /// Queue imports for files that have settled in the watched inboxes
+    async fn process_ingestion(&self) {
+        let Some(watcher) = &self.ingestion else {
+            return;
+        };
+        for job in watcher.poll().await {
+            self.enqueue(job);
+        }
+    }
+
+    /// Move an ingested file out of staging once its import has finished
+    /// for good
+    async fn finish_ingestion(&self, job: &JobType, succeeded: bool) {
+        let (Some(watcher), JobType::DataImport(DataImportJob { provenance: Some(provenance), .. })) =
+            (&self.ingestion, job)
+        else {
+            return;
+        };
+        watcher.finish(provenance, succeeded).await;
+    }
+
+    /// Cancel a job named on [`CANCEL_SUBJECT`]
+    fn process_cancellation(&self, payload: &[u8]) {
+        match serde_json::from_slice::<JobCancellation>(payload) {
+            Ok(request) => {
+                if !self.cancel(request.job_id) {
+                    warn!(job_id = ?request.job_id, "Cancellation for unknown or finished job");
+                }
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed job cancellation"),
+        }
+    }
+
+    /// Execute a job, reporting progress and recording statistics
+    async fn run_job(&self, ready: ReadyJob) {
+        let ReadyJob { job_id, attempt, job, .. } = ready;
+        let cancellation = self.lock_cancellations().entry(job_id).or_default().clone();
+        let context = JobContext::new(job_id)
+            .with_cancellation(cancellation.clone())
+            .with_step_results(StepResults::new(self.idempotency.clone()));
+        self.forward_events(&context.progress);
+        let progress = context.progress.clone();
+
+        let start_time = std::time::Instant::now();
+        let result = if cancellation.is_cancelled() {
+            Err(JobError::Cancelled(format!("Job {} was cancelled before it started", job_id)))
+        } else {
+            info!(job_id = ?job_id, attempt, "Processing job");
+            progress.status(JobStatus::Running);
+
+            let timeout = Duration::from_secs(self.config.worker.job_timeout);
+            match tokio::time::timeout(timeout, self.execute(job.clone(), context)).await {
+                Ok(result) => result,
+                Err(_) => {
+                    // Stop anything the handler spawned that watches the token
+                    cancellation.cancel();
+                    Err(JobError::TimeoutError(format!(
+                        "Job exceeded the {}s timeout",
+                        timeout.as_secs()
+                    )))
+                }
+            }
+        };
+        self.lock_cancellations().remove(&job_id);
+
         let duration = start_time.elapsed().as_millis() as u64;
+
+        if let Err(error @ JobError::Cancelled(_)) = &result {
+            info!(job_id = ?job_id, duration_ms = duration, reason = %error, "Job cancelled");
+            progress.status(JobStatus::Cancelled);
+            self.record_run(JobRun::new(job_id, &job, JobRunStatus::Cancelled, attempt, duration))
+                .await;
+            self.finish_ingestion(&job, false).await;
+            return;
+        }
+
+        if let Err(error) = &result {
+            if error.is_retryable() && attempt <= self.config.worker.max_retries {
+                self.schedule_retry(job_id, job, attempt, error, &progress).await;
+                return;
+            }
+            self.dead_letter(job_id, &job, attempt, error).await;
+        }
+
         let success = result.is_ok();
         
         // Update monitoring statistics
@@ -110,6 +849,17 @@ impl JobsWorker {
             let mut monitor = self.monitor.write().await;
             monitor.record_job(duration, success);
         }
+        let status = JobRunStatus::from_result(&result);
+        self.record_run(JobRun::new(job_id, &job, status, attempt, duration)).await;
+        self.finish_ingestion(&job, success).await;
+
+        match &result {
+            Ok(_) => progress.status(JobStatus::Completed),
+            Err(error) => {
+                progress.error(error.to_string());
+                progress.status(JobStatus::Failed);
+            }
+        }
 
         match result {
             Ok(job_result) => {
@@ -129,8 +879,144 @@ impl JobsWorker {
                 );
             }
         }
+    }
 
-        Ok(())
+    /// Queue the next attempt of a failed job after its backoff
+    async fn schedule_retry(
+        &self,
+        job_id: Uuid,
+        job: JobType,
+        attempt: u32,
+        error: &JobError,
+        progress: &ProgressReporter,
+    ) {
+        let delay = Duration::from_secs(error.retry_delay() << (attempt - 1).min(6));
+        warn!(
+            job_id = ?job_id,
+            attempt,
+            retry_in_secs = delay.as_secs(),
+            error = %error,
+            "Job failed, retrying"
+        );
+        progress.warning(format!("Attempt {} failed: {}", attempt, error));
+        progress.status(JobStatus::Retrying);
+        self.monitor.write().await.record_retry();
+
+        self.lock_cancellations().insert(job_id, CancellationToken::new());
+        self.lock_queues()
+            .retry(job_id, job, attempt + 1, tokio::time::Instant::now() + delay);
+    }
+
+    /// Keep a job that failed for good so it can be inspected and replayed
+    async fn dead_letter(&self, job_id: Uuid, job: &JobType, attempts: u32, error: &JobError) {
+        let letter = match DeadLetter::new(job_id, job, attempts, error) {
+            Ok(letter) => letter,
+            Err(e) => {
+                error!(job_id = ?job_id, error = %e, "Failed to build dead letter");
+                return;
+            }
+        };
+
+        match self.dead_letters.record(&letter).await {
+            Ok(()) => warn!(job_id = ?job_id, attempts, "Job moved to the dead-letter store"),
+            Err(e) => error!(job_id = ?job_id, error = %e, "Failed to record dead letter"),
+        }
+    }
+
+    /// Add a finished run to the job history
+    async fn record_run(&self, run: JobRun) {
+        if let Err(e) = self.history.record(&run).await {
+            warn!(job_id = ?run.job_id, error = %e, "Failed to record job run");
+        }
+    }
+
+    /// Run a job's handler; cleanups, backups, claims exports, remittance
+    /// postings, document OCR, panel refreshes, partition maintenance, quality
+    /// measures, scheduled reports, disclosure and security event reports, key
+    /// rotations, FHIR syncs, profile validations and notifications need
+    /// worker configuration
+    async fn execute(&self, job: JobType, context: JobContext) -> JobResult<JobExecutionResult> {
+        match job {
+            JobType::DataValidation(validation_job) if validation_job.profile.is_some() => {
+                self.profile_validation_handler.execute(validation_job, context).await
+            }
+            JobType::DataValidation(validation_job) => {
+                self.data_validation_handler.execute(validation_job, context).await
+            }
+            JobType::DataCleanup(cleanup_job) => self.cleanup_handler.execute(cleanup_job, context).await,
+            JobType::Backup(backup_job) => self.backup_handler.execute(backup_job, context).await,
+            JobType::ClaimsExport(claims_job) => self.claims_handler.execute(claims_job, context).await,
+            JobType::RemittancePosting(remittance_job) => {
+                self.remittance_handler.execute(remittance_job, context).await
+            }
+            JobType::DocumentOcr(ocr_job) => self.ocr_handler.execute(ocr_job, context).await,
+            JobType::PanelRefresh(panel_job) => self.panel_handler.execute(panel_job, context).await,
+            JobType::PartitionMaintenance(partition_job) => {
+                self.partition_handler.execute(partition_job, context).await
+            }
+            JobType::Analytics(analytics_job) if matches!(analytics_job.analytics_type, AnalyticsType::Quality) => {
+                self.measure_handler.execute(analytics_job, context).await
+            }
+            JobType::ScheduledReport(report_job) => {
+                let (result, notifications) = self.report_handler.generate(report_job, context).await?;
+                for notification in notifications {
+                    self.enqueue(JobType::Notification(notification));
+                }
+                Ok(result)
+            }
+            JobType::AuditReport(audit_job) if matches!(audit_job.report_type, AuditReportType::AccessLog) => {
+                self.disclosure_handler.execute(audit_job, context).await
+            }
+            JobType::AuditReport(audit_job) if matches!(audit_job.report_type, AuditReportType::SecurityEvents) => {
+                self.security_event_handler.execute(audit_job, context).await
+            }
+            JobType::KeyRotation(rotation_job) => self.key_rotation_handler.execute(rotation_job, context).await,
+            JobType::WebhookDelivery(webhook_job) => self.webhook_handler.execute(webhook_job, context).await,
+            JobType::FhirSync(sync_job) => self.sync_handler.execute(sync_job, context).await,
+            JobType::Notification(notification_job) => {
+                self.notification_handler.execute(notification_job, context).await
+            }
+            job => execute_job(job, context).await,
+        }
+    }
+
+    /// Forward a job's progress events to NATS until the job finishes
+    fn forward_events(&self, progress: &ProgressReporter) {
+        let Some(nats) = self.nats.clone() else {
+            return;
+        };
+        let mut events = progress.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let is_terminal = event.is_terminal();
+                        match EventEnvelope::new(event.clone()).encode() {
+                            Ok(payload) => {
+                                if let Err(e) = nats
+                                    .publish(events_subject(event.job_id), payload.into())
+                                    .await
+                                {
+                                    warn!(job_id = ?event.job_id, error = %e, "Failed to publish job event");
+                                }
+                            }
+                            Err(e) => {
+                                warn!(job_id = ?event.job_id, error = %e, "Failed to serialize job event");
+                            }
+                        }
+
+                        if is_terminal {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Job event forwarder lagged behind");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
     }
 
     /// Get worker statistics
@@ -199,7 +1085,25 @@ pub enum WorkerStatus {
     Error,
 }
 
-/// Job execution function for Apalis
+/// Wait for the next submitted job, or forever when NATS is not configured
+async fn next_submission(submissions: &mut Option<async_nats::Subscriber>) -> Option<async_nats::Message> {
+    match submissions {
+        Some(subscriber) => subscriber.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for a free batch permit, then for the next device observation
+/// batch; batches wait in the subscription while every permit is taken
+async fn next_device_batch(
+    permits: &Arc<Semaphore>,
+    batches: &mut Option<async_nats::Subscriber>,
+) -> Option<(OwnedSemaphorePermit, async_nats::Message)> {
+    let permit = permits.clone().acquire_owned().await.ok()?;
+    next_submission(batches).await.map(|message| (permit, message))
+}
+
+/// Run a job whose handler needs no worker configuration
 pub async fn execute_job(job: JobType, context: JobContext) -> JobResult<JobExecutionResult> {
     match job {
         JobType::DataValidation(validation_job) => {
@@ -207,9 +1111,13 @@ pub async fn execute_job(job: JobType, context: JobContext) -> JobResult<JobExec
             handler.execute(validation_job, context).await
         }
         JobType::Notification(notification_job) => {
-            let handler = NotificationHandler;
+            let handler = NotificationHandler::new();
             handler.execute(notification_job, context).await
         }
+        JobType::SubscriptionNotification(subscription_job) => {
+            let handler = SubscriptionNotificationHandler::new(reqwest::Client::new());
+            handler.execute(subscription_job, context).await
+        }
         _ => {
             // TODO: Implement other job types
             Err(JobError::ProcessingError(
@@ -223,6 +1131,7 @@ pub async fn execute_job(job: JobType, context: JobContext) -> JobResult<JobExec
 mod tests {
     use super::*;
     use crate::config::JobsConfig;
+    use crate::idempotency::MemoryIdempotencyStore;
 
     #[tokio::test]
     async fn test_worker_creation() {
@@ -253,6 +1162,7 @@ mod tests {
             validation_type: ValidationType::Schema,
             rules: vec![],
             auto_fix: false,
+            profile: None,
         });
         
         let context = JobContext::new(Uuid::new_v4());
@@ -263,6 +1173,170 @@ mod tests {
         assert!(result.success);
     }
 
+    #[tokio::test]
+    async fn test_submitted_jobs_are_queued() {
+        let worker = JobsWorker::new(JobsConfig::default());
+        let job = JobType::DataValidation(DataValidationJob {
+            patient_id: None,
+            validation_type: ValidationType::Completeness,
+            rules: vec![],
+            auto_fix: false,
+            profile: None,
+        });
+
+        worker.process_submitted_job(&serde_json::to_vec(&job).unwrap()).await;
+        worker.process_submitted_job(b"not json").await;
+        assert_eq!(worker.lock_queues().pending(JobQueue::Default), 1);
+
+        let mut running = FuturesUnordered::new();
+        worker.dispatch(&mut running);
+        assert_eq!(running.next().await, Some(JobQueue::Default));
+        assert_eq!(worker.get_stats().await.total_jobs, 1);
+    }
+
+    fn validation_job(rules: usize) -> JobType {
+        JobType::DataValidation(DataValidationJob {
+            patient_id: None,
+            validation_type: ValidationType::Schema,
+            rules: (0..rules)
+                .map(|index| ValidationRule {
+                    name: format!("rule_{}", index),
+                    description: "Sample validation rule".to_string(),
+                    rule_type: "required".to_string(),
+                    expression: "field != null".to_string(),
+                    severity: ValidationSeverity::Info,
+                })
+                .collect(),
+            auto_fix: false,
+            profile: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued_job() {
+        let worker = JobsWorker::new(JobsConfig::default());
+        let job_id = Uuid::new_v4();
+        let submission = JobSubmission {
+            job_id: Some(job_id),
+            idempotency_key: None,
+            job: validation_job(0),
+        };
+        worker.process_submitted_job(&serde_json::to_vec(&submission).unwrap()).await;
+        worker.process_cancellation(&serde_json::to_vec(&JobCancellation { job_id }).unwrap());
+
+        let mut running = FuturesUnordered::new();
+        worker.dispatch(&mut running);
+        assert_eq!(running.next().await, Some(JobQueue::Default));
+
+        // Cancelled jobs are neither successes nor failures
+        assert_eq!(worker.get_stats().await.total_jobs, 0);
+        assert!(!worker.cancel(job_id));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_submission_is_dropped() {
+        let worker = JobsWorker::new(JobsConfig::default())
+            .with_idempotency(Arc::new(MemoryIdempotencyStore::default()));
+        let submission = |job_id| JobSubmission {
+            job_id: Some(job_id),
+            idempotency_key: Some("contact-verification:1".to_string()),
+            job: validation_job(0),
+        };
+
+        let first = Uuid::new_v4();
+        worker.process_submitted_job(&serde_json::to_vec(&submission(first)).unwrap()).await;
+        worker.process_submitted_job(&serde_json::to_vec(&submission(Uuid::new_v4())).unwrap()).await;
+        // A redelivered message carries the same job id and is dropped too
+        worker.process_submitted_job(&serde_json::to_vec(&submission(first)).unwrap()).await;
+
+        assert_eq!(worker.lock_queues().pending(JobQueue::Default), 1);
+        assert!(matches!(worker.status(first).await, Ok(Some(JobState::Queued))));
+        assert_eq!(worker.submit(submission(Uuid::new_v4())).await, Submitted::Duplicate(first));
+    }
+
+    /// Dead letters kept in memory
+    #[derive(Default)]
+    struct MemoryDeadLetterStore {
+        letters: Mutex<Vec<DeadLetter>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DeadLetterStore for MemoryDeadLetterStore {
+        async fn record(&self, letter: &DeadLetter) -> JobResult<()> {
+            self.letters.lock().unwrap().push(letter.clone());
+            Ok(())
+        }
+    }
+
+    /// Job runs kept in memory
+    #[derive(Default)]
+    struct MemoryJobHistoryStore {
+        runs: Mutex<Vec<JobRun>>,
+    }
+
+    #[async_trait::async_trait]
+    impl JobHistoryStore for MemoryJobHistoryStore {
+        async fn record(&self, run: &JobRun) -> JobResult<()> {
+            self.runs.lock().unwrap().push(run.clone());
+            Ok(())
+        }
+    }
+
+    fn ready(job: JobType, attempt: u32) -> ReadyJob {
+        ReadyJob {
+            queue: job.queue(),
+            job_id: Uuid::new_v4(),
+            attempt,
+            job,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_job_timeout() {
+        let mut config = JobsConfig::default();
+        config.worker.job_timeout = 1;
+        config.worker.max_retries = 0;
+        let store = Arc::new(MemoryDeadLetterStore::default());
+        let history = Arc::new(MemoryJobHistoryStore::default());
+        let worker = JobsWorker::new(config)
+            .with_dead_letters(store.clone())
+            .with_history(history.clone());
+
+        // Each rule takes 100ms, so 20 rules outlast the 1s timeout
+        worker.run_job(ready(validation_job(20), 1)).await;
+
+        let stats = worker.get_stats().await;
+        assert_eq!(stats.total_jobs, 1);
+        assert_eq!(stats.failed_jobs, 1);
+
+        let letters = store.letters.lock().unwrap();
+        assert_eq!(letters.len(), 1);
+        assert!(letters[0].last_error.starts_with("Timeout error"));
+
+        let runs = history.runs.lock().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!((runs[0].status, runs[0].job_type.as_str()), (JobRunStatus::Failed, "DataValidation"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retryable_failure_is_retried() {
+        let mut config = JobsConfig::default();
+        config.worker.job_timeout = 1;
+        let store = Arc::new(MemoryDeadLetterStore::default());
+        let history = Arc::new(MemoryJobHistoryStore::default());
+        let worker = JobsWorker::new(config)
+            .with_dead_letters(store.clone())
+            .with_history(history.clone());
+
+        worker.run_job(ready(validation_job(20), 1)).await;
+
+        assert_eq!(worker.lock_queues().delayed(), 1);
+        assert_eq!(worker.get_stats().await.retried_jobs, 1);
+        assert!(store.letters.lock().unwrap().is_empty());
+        // The run is recorded once it finally succeeds or fails
+        assert!(history.runs.lock().unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_stats_recording() {
         let config = JobsConfig::default();
@@ -0,0 +1,249 @@
+//! Contract tests for `FhirSyncHandler` against stubbed FHIR servers
+//!
+//! Source and target are wiremock servers. The tests check which resources
+//! are read and written, the provenance of pulled resources, and how server
+//! responses map to job errors: OperationOutcome rejections fail for good,
+//! 5xx responses and timeouts are retryable, and bodies that are not FHIR are
+//! not retried.
+
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use emr_core::domain::{Provenance, ProvenanceActivity};
+use emr_jobs::handlers::JobHandler;
+use emr_jobs::sync::FhirSyncHandler;
+use emr_jobs::{FhirSyncJob, JobContext, JobError, JobResult, ProvenanceStore, SyncDirection};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{body_json, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const FHIR_JSON: &str = "application/fhir+json";
+
+/// Provenance kept in memory
+#[derive(Default)]
+struct MemoryProvenanceStore {
+    records: Mutex<Vec<Provenance>>,
+}
+
+#[async_trait]
+impl ProvenanceStore for MemoryProvenanceStore {
+    async fn record(&self, provenance: &Provenance) -> JobResult<()> {
+        self.records.lock().unwrap().push(provenance.clone());
+        Ok(())
+    }
+}
+
+/// A response with a FHIR JSON body
+fn fhir_response(status: u16, body: &Value) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body.to_string(), FHIR_JSON)
+}
+
+fn searchset(resources: Vec<Value>) -> Value {
+    let entries: Vec<Value> = resources.into_iter().map(|resource| json!({ "resource": resource })).collect();
+    json!({ "resourceType": "Bundle", "type": "searchset", "total": entries.len(), "entry": entries })
+}
+
+fn observation(id: &str, patient_id: Uuid) -> Value {
+    json!({
+        "resourceType": "Observation",
+        "id": id,
+        "status": "final",
+        "code": { "coding": [{ "system": "http://loinc.org", "code": "8867-4" }] },
+        "subject": { "reference": format!("Patient/{}", patient_id) }
+    })
+}
+
+fn job(source: &MockServer, target: &MockServer, direction: SyncDirection) -> FhirSyncJob {
+    FhirSyncJob {
+        patient_id: Uuid::new_v4(),
+        resource_type: "Observation".to_string(),
+        source_url: source.uri(),
+        target_url: target.uri(),
+        last_sync: None,
+        sync_direction: direction,
+    }
+}
+
+/// A handler without retries, failing fast
+fn handler(provenance: Arc<MemoryProvenanceStore>) -> FhirSyncHandler {
+    FhirSyncHandler::new(provenance)
+        .with_timeout(Duration::from_secs(2))
+        .with_retries(0, Duration::from_millis(10))
+}
+
+#[tokio::test]
+async fn test_pull_copies_changed_resources_and_records_provenance() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let mut job = job(&source, &target, SyncDirection::Pull);
+    job.last_sync = Some(Utc.with_ymd_and_hms(2026, 1, 5, 10, 0, 0).unwrap());
+    let id = Uuid::new_v4().to_string();
+    let resource = observation(&id, job.patient_id);
+
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .and(query_param("patient", job.patient_id.to_string()))
+        .and(query_param("_lastUpdated", "gt2026-01-05T10:00:00+00:00"))
+        .respond_with(fhir_response(200, &searchset(vec![resource.clone(), observation("lab-7", job.patient_id)])))
+        .expect(1)
+        .mount(&source)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(format!("/Observation/{}", id)))
+        .and(body_json(&resource))
+        .respond_with(fhir_response(200, &resource))
+        .expect(1)
+        .mount(&target)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/Observation/lab-7"))
+        .respond_with(fhir_response(200, &observation("lab-7", job.patient_id)))
+        .expect(1)
+        .mount(&target)
+        .await;
+
+    let provenance = Arc::new(MemoryProvenanceStore::default());
+    let context = JobContext::new(Uuid::new_v4());
+    let job_id = context.job_id;
+    let result = handler(provenance.clone()).execute(job.clone(), context).await.unwrap();
+    assert!(result.success);
+    assert_eq!(result.data.as_ref().unwrap()["pulled"], 2);
+    assert_eq!(result.metrics["resources_pushed"], 0.0);
+
+    // Only the resource with a UUID id maps to a local entity
+    let records = provenance.records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].activity, ProvenanceActivity::FhirSync);
+    assert_eq!(records[0].target_id.to_string(), id);
+    assert_eq!(records[0].job_id, Some(job_id));
+    assert_eq!(records[0].source_system, job.source_url);
+}
+
+#[tokio::test]
+async fn test_push_reads_the_target_and_writes_the_source() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let mut job = job(&source, &target, SyncDirection::Push);
+    job.resource_type = "Patient".to_string();
+    let patient = json!({ "resourceType": "Patient", "id": job.patient_id.to_string() });
+
+    Mock::given(method("GET"))
+        .and(path("/Patient"))
+        .and(query_param("_id", job.patient_id.to_string()))
+        .respond_with(fhir_response(200, &searchset(vec![patient.clone()])))
+        .expect(1)
+        .mount(&target)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(format!("/Patient/{}", job.patient_id)))
+        .respond_with(fhir_response(200, &patient))
+        .expect(1)
+        .mount(&source)
+        .await;
+
+    let provenance = Arc::new(MemoryProvenanceStore::default());
+    let result = handler(provenance.clone()).execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+    assert_eq!(result.data.unwrap()["pushed"], 1);
+    assert!(provenance.records.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_4xx_operation_outcome_fails_for_good() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let job = job(&source, &target, SyncDirection::Pull);
+
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .respond_with(fhir_response(200, &searchset(vec![observation("lab-7", job.patient_id)])))
+        .mount(&source)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/Observation/lab-7"))
+        .respond_with(fhir_response(
+            422,
+            &json!({
+                "resourceType": "OperationOutcome",
+                "issue": [{ "severity": "error", "code": "invalid", "diagnostics": "Observation.code is required" }]
+            }),
+        ))
+        .expect(1)
+        .mount(&target)
+        .await;
+
+    let error = handler(Arc::default()).execute(job, JobContext::new(Uuid::new_v4())).await.unwrap_err();
+    assert!(matches!(&error, JobError::ProcessingError(message) if message.contains("Observation.code is required")));
+    assert!(!error.is_retryable());
+}
+
+#[tokio::test]
+async fn test_5xx_is_retried_then_left_to_the_worker() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let job = job(&source, &target, SyncDirection::Pull);
+
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(3)
+        .mount(&source)
+        .await;
+
+    let handler = handler(Arc::default()).with_retries(2, Duration::from_millis(10));
+    let error = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap_err();
+    assert!(matches!(error, JobError::ExternalServiceError(_)));
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn test_5xx_then_success_completes() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let job = job(&source, &target, SyncDirection::Pull);
+
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .respond_with(ResponseTemplate::new(502))
+        .up_to_n_times(1)
+        .mount(&source)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .respond_with(fhir_response(200, &searchset(vec![])))
+        .mount(&source)
+        .await;
+
+    let handler = handler(Arc::default()).with_retries(2, Duration::from_millis(10));
+    let result = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap();
+    assert_eq!(result.data.unwrap()["pulled"], 0);
+}
+
+#[tokio::test]
+async fn test_timeout_is_retryable() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let job = job(&source, &target, SyncDirection::Pull);
+
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .respond_with(fhir_response(200, &searchset(vec![])).set_delay(Duration::from_secs(5)))
+        .mount(&source)
+        .await;
+
+    let handler = handler(Arc::default()).with_timeout(Duration::from_millis(200));
+    let error = handler.execute(job, JobContext::new(Uuid::new_v4())).await.unwrap_err();
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn test_malformed_json_is_not_retried() {
+    let (source, target) = (MockServer::start().await, MockServer::start().await);
+    let job = job(&source, &target, SyncDirection::Pull);
+
+    Mock::given(method("GET"))
+        .and(path("/Observation"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{\"resourceType\": \"Bundle\", ", FHIR_JSON))
+        .expect(1)
+        .mount(&source)
+        .await;
+
+    let error = handler(Arc::default()).execute(job, JobContext::new(Uuid::new_v4())).await.unwrap_err();
+    assert!(matches!(error, JobError::ProcessingError(_)));
+    assert!(!error.is_retryable());
+}
@@ -0,0 +1,304 @@
+#![deny(unsafe_code)]
+
+//! Load-testing harness for the EMR API
+//!
+//! Sends patient API requests from a fixed number of concurrent workers and
+//! reports throughput and latency percentiles.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: emr-loadtest [--base-url URL] [--concurrency N] [--requests N] \
+[--scenario list|get|mixed] [--timeout-ms N] [--json]";
+
+/// Request mix to send
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Scenario {
+    List,
+    Get,
+    Mixed,
+}
+
+impl Scenario {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "list" => Ok(Self::List),
+            "get" => Ok(Self::Get),
+            "mixed" => Ok(Self::Mixed),
+            other => bail!("Unknown scenario {}; expected list, get or mixed", other),
+        }
+    }
+
+    /// Whether the n-th request is a single-patient read
+    fn is_get(&self, n: usize) -> bool {
+        match self {
+            Self::List => false,
+            Self::Get => true,
+            Self::Mixed => n % 4 == 3,
+        }
+    }
+}
+
+/// Command line options
+#[derive(Debug, Clone)]
+struct Options {
+    base_url: String,
+    concurrency: usize,
+    requests: usize,
+    scenario: Scenario,
+    timeout: Duration,
+    json: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            base_url: "http://127.0.0.1:8090".to_string(),
+            concurrency: 16,
+            requests: 1000,
+            scenario: Scenario::Mixed,
+            timeout: Duration::from_secs(10),
+            json: false,
+        }
+    }
+}
+
+impl Options {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().with_context(|| format!("{} needs a value\n{}", name, USAGE));
+            match arg.as_str() {
+                "--base-url" => options.base_url = value("--base-url")?.trim_end_matches('/').to_string(),
+                "--concurrency" => options.concurrency = value("--concurrency")?.parse()?,
+                "--requests" => options.requests = value("--requests")?.parse()?,
+                "--scenario" => options.scenario = Scenario::parse(&value("--scenario")?)?,
+                "--timeout-ms" => options.timeout = Duration::from_millis(value("--timeout-ms")?.parse()?),
+                "--json" => options.json = true,
+                "--help" | "-h" => bail!("{}", USAGE),
+                other => bail!("Unknown argument {}\n{}", other, USAGE),
+            }
+        }
+
+        if options.concurrency == 0 || options.requests == 0 {
+            bail!("--concurrency and --requests must be greater than 0");
+        }
+        Ok(options)
+    }
+}
+
+/// Latency summary in milliseconds
+#[derive(Debug, Serialize)]
+struct Report {
+    scenario: Scenario,
+    concurrency: usize,
+    requests: usize,
+    errors: usize,
+    elapsed_ms: f64,
+    requests_per_second: f64,
+    p50_ms: f64,
+    p95_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Report {
+    fn new(options: &Options, mut latencies: Vec<Duration>, errors: usize, elapsed: Duration) -> Self {
+        latencies.sort();
+        Self {
+            scenario: options.scenario,
+            concurrency: options.concurrency,
+            requests: latencies.len(),
+            errors,
+            elapsed_ms: millis(elapsed),
+            requests_per_second: latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            p50_ms: millis(percentile(&latencies, 50.0)),
+            p95_ms: millis(percentile(&latencies, 95.0)),
+            p99_ms: millis(percentile(&latencies, 99.0)),
+            max_ms: millis(latencies.last().copied().unwrap_or_default()),
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "{:?} x{}: {} requests, {} errors in {:.0} ms ({:.1} req/s)",
+            self.scenario, self.concurrency, self.requests, self.errors, self.elapsed_ms, self.requests_per_second
+        );
+        println!(
+            "latency p50 {:.2} ms  p95 {:.2} ms  p99 {:.2} ms  max {:.2} ms",
+            self.p50_ms, self.p95_ms, self.p99_ms, self.max_ms
+        );
+    }
+}
+
+/// Patient ids from a list response (`patients` or paginated `data`)
+fn patient_ids(body: &Value) -> Vec<String> {
+    body.get("patients")
+        .or_else(|| body.get("data"))
+        .and_then(Value::as_array)
+        .map(|patients| {
+            patients
+                .iter()
+                .filter_map(|patient| patient.get("id").and_then(Value::as_str).map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn run(options: Options) -> Result<Report> {
+    let client = reqwest::Client::builder().timeout(options.timeout).build()?;
+    let list_url = format!("{}/api/v1/patients", options.base_url);
+
+    // Warm up and collect ids for single-patient reads
+    let listing: Value = client
+        .get(&list_url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", list_url))?
+        .error_for_status()?
+        .json()
+        .await?;
+    let ids = Arc::new(patient_ids(&listing));
+    if options.scenario != Scenario::List && ids.is_empty() {
+        bail!("The patient list is empty; seed data first (emr-admin seed)");
+    }
+
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..options.concurrency)
+        .map(|_| {
+            let client = client.clone();
+            let next = next.clone();
+            let ids = ids.clone();
+            let options = options.clone();
+            let list_url = list_url.clone();
+
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                let mut errors = 0;
+                loop {
+                    let n = next.fetch_add(1, Ordering::Relaxed);
+                    if n >= options.requests {
+                        break;
+                    }
+
+                    let url = if options.scenario.is_get(n) {
+                        format!("{}/{}", list_url, ids[n % ids.len()])
+                    } else {
+                        list_url.clone()
+                    };
+
+                    let sent = Instant::now();
+                    let result = client.get(&url).send().await.and_then(|r| r.error_for_status());
+                    let outcome = match result {
+                        Ok(response) => response.bytes().await.map(|_| ()),
+                        Err(e) => Err(e),
+                    };
+                    match outcome {
+                        Ok(()) => latencies.push(sent.elapsed()),
+                        Err(_) => errors += 1,
+                    }
+                }
+                (latencies, errors)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(options.requests);
+    let mut errors = 0;
+    for worker in workers {
+        let (worker_latencies, worker_errors) = worker.await?;
+        latencies.extend(worker_latencies);
+        errors += worker_errors;
+    }
+
+    Ok(Report::new(&options, latencies, errors, started.elapsed()))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = Options::parse(std::env::args().skip(1))?;
+    let json = options.json;
+    let report = run(options).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.print();
+    }
+
+    if report.errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(args(&["--concurrency", "8", "--scenario", "get", "--base-url", "http://h:1/"]))
+            .unwrap();
+        assert_eq!(options.concurrency, 8);
+        assert_eq!(options.scenario, Scenario::Get);
+        assert_eq!(options.base_url, "http://h:1");
+        assert_eq!(options.requests, 1000);
+
+        assert!(Options::parse(args(&["--scenario", "delete"])).is_err());
+        assert!(Options::parse(args(&["--concurrency", "0"])).is_err());
+        assert!(Options::parse(args(&["--requests"])).is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&samples, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&samples, 95.0), Duration::from_millis(95));
+        assert_eq!(percentile(&samples, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&[], 99.0), Duration::ZERO);
+        assert_eq!(percentile(&samples[..1], 99.0), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_mixed_scenario_ratio() {
+        let gets = (0..400).filter(|n| Scenario::Mixed.is_get(*n)).count();
+        assert_eq!(gets, 100);
+        assert!(!Scenario::List.is_get(3));
+    }
+
+    #[test]
+    fn test_patient_ids() {
+        let listing = serde_json::json!({"patients": [{"id": "patient-001"}, {"id": "patient-002"}]});
+        assert_eq!(patient_ids(&listing), vec!["patient-001", "patient-002"]);
+
+        let paginated = serde_json::json!({"data": [{"id": "a"}], "pagination": {}});
+        assert_eq!(patient_ids(&paginated), vec!["a"]);
+        assert!(patient_ids(&serde_json::json!({})).is_empty());
+    }
+}
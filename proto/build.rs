@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+
+    tonic_build::configure().compile_protos(
+        &[
+            "definitions/emr/internal/v1/jobs.proto",
+            "definitions/emr/internal/v1/patients.proto",
+        ],
+        &["definitions"],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! gRPC configuration shared by the services
+
+use emr_core::diagnostics::ConfigReport;
+use serde::{Deserialize, Serialize};
+
+/// Where a service serves the internal API and the certificates it uses
+///
+/// With all three paths set, the server only accepts clients presenting a
+/// certificate signed by `ca_cert_path`, and clients present `cert_path` and
+/// verify the server against the same CA. Production requires them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrpcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address the server listens on
+    #[serde(default = "default_listen_addr")]
+    pub listen_addr: String,
+    /// CA that signs every service's certificate
+    pub ca_cert_path: Option<String>,
+    /// This service's certificate, PEM
+    pub cert_path: Option<String>,
+    /// This service's private key, PEM
+    pub key_path: Option<String>,
+    /// Seconds to wait for a connection to another service
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+}
+
+fn default_listen_addr() -> String {
+    "0.0.0.0:50051".to_string()
+}
+
+fn default_connect_timeout() -> u64 {
+    5
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_addr: default_listen_addr(),
+            ca_cert_path: None,
+            cert_path: None,
+            key_path: None,
+            connect_timeout: default_connect_timeout(),
+        }
+    }
+}
+
+impl GrpcConfig {
+    /// Whether mutual TLS is configured
+    pub fn mtls(&self) -> bool {
+        self.ca_cert_path.is_some() && self.cert_path.is_some() && self.key_path.is_some()
+    }
+
+    /// Problems with the settings under `key`
+    pub fn check(&self, key: &str, production: bool) -> ConfigReport {
+        let mut report = ConfigReport::new();
+        if !self.enabled {
+            return report;
+        }
+
+        if self.listen_addr.parse::<std::net::SocketAddr>().is_err() {
+            report.error(
+                &format!("{}.listen_addr", key),
+                format!("'{}' is not a socket address such as 0.0.0.0:50051", self.listen_addr),
+            );
+        }
+
+        let paths = [&self.ca_cert_path, &self.cert_path, &self.key_path];
+        let set = paths.iter().filter(|path| path.is_some()).count();
+        if set != 0 && set != paths.len() {
+            report.error(key, "ca_cert_path, cert_path and key_path must be set together");
+        } else if set == 0 && production {
+            report.error(key, "Mutual TLS is required in production; set ca_cert_path, cert_path and key_path");
+        } else if set == 0 {
+            report.warning(key, "Internal gRPC traffic is not encrypted");
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_requires_mtls_in_production() {
+        let mut config = GrpcConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(!config.check("grpc", false).has_errors());
+        assert!(config.check("grpc", true).has_errors());
+
+        config.ca_cert_path = Some("ca.pem".to_string());
+        assert!(config.check("grpc", false).has_errors());
+
+        config.cert_path = Some("service.pem".to_string());
+        config.key_path = Some("service.key".to_string());
+        assert!(config.mtls());
+        assert!(config.check("grpc", true).issues.is_empty());
+
+        config.listen_addr = "localhost".to_string();
+        assert!(config.check("grpc", true).has_errors());
+    }
+}
@@ -0,0 +1,76 @@
+//! Tracing across gRPC calls
+//!
+//! Callers send the request id of the work they are doing in
+//! `x-request-id` metadata, the same header the HTTP API echoes, and servers
+//! open a span per call carrying it with the method name. A call made
+//! without one gets a fresh id from [`PropagateRequestId`].
+
+use tonic::codegen::http;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// Metadata key carrying the request id
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// A request carrying `request_id`, for a call made on behalf of it
+pub fn with_request_id<T>(message: T, request_id: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Ok(value) = MetadataValue::try_from(request_id) {
+        request.metadata_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    request
+}
+
+/// The request id a call carries
+pub fn request_id<T>(request: &Request<T>) -> Option<&str> {
+    request.metadata().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok())
+}
+
+/// Span for a call a server handles; pass to `Server::trace_fn`
+pub fn server_span(request: &http::Request<()>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!("grpc", method = %request.uri().path(), request_id = %request_id)
+}
+
+/// Client interceptor giving every call a request id
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PropagateRequestId;
+
+impl Interceptor for PropagateRequestId {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if !request.metadata().contains_key(REQUEST_ID_HEADER) {
+            let request_id = uuid::Uuid::new_v4().to_string();
+            if let Ok(value) = MetadataValue::try_from(request_id.as_str()) {
+                request.metadata_mut().insert(REQUEST_ID_HEADER, value);
+            }
+        }
+        tracing::debug!(request_id = request_id(&request).unwrap_or_default(), "Calling internal service");
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_propagation() {
+        let request = PropagateRequestId.call(with_request_id((), "req-123")).unwrap();
+        assert_eq!(request_id(&request), Some("req-123"));
+
+        let request = PropagateRequestId.call(Request::new(())).unwrap();
+        assert!(request_id(&request).is_some_and(|id| uuid::Uuid::parse_str(id).is_ok()));
+
+        let request = http::Request::builder()
+            .uri("/emr.internal.v1.JobService/SubmitJob")
+            .header(REQUEST_ID_HEADER, "req-123")
+            .body(())
+            .unwrap();
+        let _span = server_span(&request);
+    }
+}
@@ -0,0 +1,23 @@
+#![deny(unsafe_code)]
+
+//! Internal gRPC API between the EMR services
+//!
+//! The API and the jobs worker call each other over gRPC rather than HTTP and
+//! JSON: the worker serves job submission and status ([`v1::job_service_server`])
+//! and the API serves patient lookups ([`v1::patient_service_server`]). The
+//! definitions live in `definitions/` and are compiled at build time.
+//!
+//! Both ends use mutual TLS from [`GrpcConfig`] and the tracing hooks in
+//! [`interceptors`], so one request id follows a call across services.
+
+pub mod config;
+pub mod interceptors;
+pub mod transport;
+
+pub use config::GrpcConfig;
+pub use transport::{connect, server, TransportError};
+
+/// Version 1 of the internal API
+pub mod v1 {
+    tonic::include_proto!("emr.internal.v1");
+}